@@ -0,0 +1,208 @@
+//! What an agent can do, for hosts embedding Spacebot.
+//!
+//! A host asks for [`Capabilities`] and gets each agent's tools with their
+//! parameter schemas, the models behind each process type, and the limits
+//! the agent runs under, as plain serializable structs.
+//!
+//! These structs are versioned on their own, apart from the crate. Fields
+//! are only ever added, each with a default so documents from older
+//! versions still deserialize. Anything that would break a reader (a
+//! removed or renamed field, a changed meaning) bumps
+//! [`CAPABILITIES_VERSION`].
+
+use crate::ProcessType;
+use crate::llm::routing::RoutingConfig;
+
+use serde::{Deserialize, Serialize};
+
+/// Version of the capabilities format. Readers should refuse documents with
+/// a newer version than they know.
+pub const CAPABILITIES_VERSION: u32 = 1;
+
+/// Everything the agents of one instance can do.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct Capabilities {
+    /// [`CAPABILITIES_VERSION`] of the code that wrote this.
+    pub version: u32,
+    #[serde(default)]
+    pub agents: Vec<AgentCapabilities>,
+}
+
+impl Capabilities {
+    pub fn new(agents: Vec<AgentCapabilities>) -> Self {
+        Self {
+            version: CAPABILITIES_VERSION,
+            agents,
+        }
+    }
+
+    /// Whether a reader at this crate's version understands the document.
+    pub fn is_supported(&self) -> bool {
+        self.version <= CAPABILITIES_VERSION
+    }
+}
+
+/// What one agent can do.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct AgentCapabilities {
+    pub id: String,
+    /// Tools the agent's processes can call, sorted by name.
+    #[serde(default)]
+    pub tools: Vec<ToolCapability>,
+    /// Models answering for each process type, default tiers first.
+    #[serde(default)]
+    pub models: Vec<ModelTier>,
+    #[serde(default)]
+    pub budgets: Budgets,
+}
+
+/// A tool and the processes that can call it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct ToolCapability {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    /// JSON Schema of the tool's arguments.
+    #[serde(default)]
+    pub parameters: serde_json::Value,
+    #[serde(default)]
+    pub processes: Vec<ProcessType>,
+}
+
+/// The model a process type runs on.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct ModelTier {
+    pub process: ProcessType,
+    /// Set for task-type overrides, which only apply to branches and
+    /// workers spawned with that task type.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub task_type: Option<String>,
+    pub model: String,
+    /// Models tried in order when `model` fails.
+    #[serde(default)]
+    pub fallbacks: Vec<String>,
+}
+
+impl ModelTier {
+    /// One tier per process type, then one per task-type override for
+    /// branches and workers, sorted by task type.
+    pub fn from_routing(routing: &RoutingConfig) -> Vec<Self> {
+        let tier = |process, task_type: Option<&str>| {
+            let model = routing.resolve(process, task_type);
+            Self {
+                process,
+                task_type: task_type.map(str::to_string),
+                model: model.to_string(),
+                fallbacks: routing.get_fallbacks(model).to_vec(),
+            }
+        };
+
+        let mut tiers: Vec<Self> = [
+            ProcessType::Channel,
+            ProcessType::Branch,
+            ProcessType::Worker,
+            ProcessType::Compactor,
+            ProcessType::Cortex,
+        ]
+        .into_iter()
+        .map(|process| tier(process, None))
+        .collect();
+
+        let mut task_types: Vec<&String> = routing.task_overrides.keys().collect();
+        task_types.sort();
+        for task_type in task_types {
+            for process in [ProcessType::Branch, ProcessType::Worker] {
+                tiers.push(tier(process, Some(task_type)));
+            }
+        }
+        tiers
+    }
+}
+
+/// Limits an agent runs under.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct Budgets {
+    /// Most LLM turns a channel takes for one message.
+    #[serde(default)]
+    pub max_turns: usize,
+    #[serde(default)]
+    pub branch_max_turns: usize,
+    #[serde(default)]
+    pub max_concurrent_branches: usize,
+    #[serde(default)]
+    pub max_concurrent_workers: usize,
+    /// Context window in tokens.
+    #[serde(default)]
+    pub context_window: usize,
+    /// Estimated turn cost in USD above which a user has to confirm the
+    /// turn. `None` when turns are never held.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub confirm_above_usd: Option<f64>,
+    /// Inbound message quotas. `None` when messages are not rate limited.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rate_limit: Option<RateLimits>,
+}
+
+/// Inbound message quotas.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct RateLimits {
+    #[serde(default)]
+    pub user_per_minute: u32,
+    #[serde(default)]
+    pub user_burst: u32,
+    #[serde(default)]
+    pub user_per_hour: u32,
+    #[serde(default)]
+    pub channel_per_minute: u32,
+    #[serde(default)]
+    pub channel_burst: u32,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_model_tiers_include_task_overrides() {
+        let mut routing = RoutingConfig::default();
+        routing
+            .task_overrides
+            .insert("coding".into(), "anthropic/claude-opus-4".into());
+        routing.fallbacks.insert(
+            "anthropic/claude-opus-4".into(),
+            vec!["openai/gpt-4.1".into()],
+        );
+
+        let tiers = ModelTier::from_routing(&routing);
+        assert_eq!(tiers.len(), 7);
+        assert_eq!(tiers[0].process, ProcessType::Channel);
+        assert_eq!(tiers[0].task_type, None);
+
+        let coding = &tiers[6];
+        assert_eq!(coding.process, ProcessType::Worker);
+        assert_eq!(coding.task_type.as_deref(), Some("coding"));
+        assert_eq!(coding.fallbacks, vec!["openai/gpt-4.1".to_string()]);
+    }
+
+    #[test]
+    fn test_missing_and_unknown_fields_deserialize() {
+        let json = r#"{"version":1,"agents":[{"id":"main"}],"added_later":true}"#;
+        let capabilities: Capabilities = serde_json::from_str(json).unwrap();
+        assert!(capabilities.is_supported());
+        assert_eq!(capabilities.agents[0].id, "main");
+        assert!(capabilities.agents[0].tools.is_empty());
+        assert_eq!(capabilities.agents[0].budgets, Budgets::default());
+
+        let newer = Capabilities {
+            version: CAPABILITIES_VERSION + 1,
+            agents: Vec::new(),
+        };
+        assert!(!newer.is_supported());
+    }
+}
@@ -0,0 +1,149 @@
+//! Provider configuration consumed by [`LlmManager`](crate::llm::LlmManager).
+
+use crate::llm::credentials::aws::AwsSecretsConfig;
+use crate::llm::credentials::vault::VaultConfig;
+use crate::llm::faults::FaultRule;
+use crate::llm::health::HealthCheckConfig;
+use crate::llm::key_health::KeyCheckConfig;
+use crate::llm::outage::OutageConfig;
+use crate::llm::pricing::ModelPricing;
+use crate::llm::regions::RegionalEndpointsConfig;
+use crate::llm::signing::HmacSigningConfig;
+
+use std::collections::HashMap;
+
+/// LLM provider credentials (instance-level).
+#[derive(Debug, Clone, Default)]
+pub struct LlmConfig {
+    pub anthropic_key: Option<String>,
+    pub openai_key: Option<String>,
+    pub openrouter_key: Option<String>,
+    pub ollama_key: Option<String>,
+    pub zhipu_key: Option<String>,
+    pub groq_key: Option<String>,
+    pub together_key: Option<String>,
+    pub fireworks_key: Option<String>,
+    pub deepseek_key: Option<String>,
+    pub xai_key: Option<String>,
+    pub mistral_key: Option<String>,
+    pub opencode_zen_key: Option<String>,
+    /// OpenAI organization id, sent as `OpenAI-Organization` so usage is
+    /// billed to the right organization for keys that belong to several.
+    pub openai_organization: Option<String>,
+    /// OpenAI project id, sent as `OpenAI-Project`.
+    pub openai_project: Option<String>,
+    /// Cap on concurrent completion requests across all agents. When the cap
+    /// is reached, interactive requests are admitted before background ones.
+    /// `None` means unlimited.
+    pub max_concurrent_requests: Option<usize>,
+    /// Keep raw request and response bodies for recent provider attempts so
+    /// they can be inspected through the API. Off by default.
+    pub debug_recording: bool,
+    /// Upload images at least this many bytes through the provider's file
+    /// API instead of inlining them, where the provider has one. `None`
+    /// always inlines.
+    pub upload_threshold_bytes: Option<usize>,
+    /// Standby keys by provider id, used after the provider rejects the
+    /// primary key.
+    pub secondary_keys: HashMap<String, String>,
+    /// Server roots by provider id for self-hosted OpenAI-compatible servers
+    /// (Ollama, vLLM), replacing the provider's hosted endpoint. A key is
+    /// optional for these, and they are health-checked periodically.
+    pub base_urls: HashMap<String, String>,
+    /// Prioritized regional server roots by provider id. Requests go to the
+    /// active region and fail over to the next on retriable errors, before
+    /// any model fallback. Takes precedence over `base_urls`.
+    pub regions: HashMap<String, RegionalEndpointsConfig>,
+    /// How self-hosted servers in `base_urls` are probed.
+    pub health_check: HealthCheckConfig,
+    /// When a provider failing across models is treated as down.
+    pub outage: OutageConfig,
+    /// How hosted providers' keys are checked.
+    pub key_check: KeyCheckConfig,
+    /// Providers whose server is vLLM. Requests to them carry the routing's
+    /// per-model vLLM extras (guided decoding, LoRA adapters).
+    pub vllm_providers: Vec<String>,
+    /// Self-hosted providers (llama.cpp, vLLM) whose tool calls are
+    /// constrained by a grammar generated from the tools' schemas.
+    pub grammar_providers: Vec<String>,
+    /// Providers that get a request's tags in the body's `metadata` object.
+    /// Anthropic only takes a user id there, so it's never sent tags.
+    pub tag_metadata_providers: Vec<String>,
+    /// Shared-secret HMAC signing by provider id, for gateways that only
+    /// accept signed requests.
+    pub request_signing: HashMap<String, HmacSigningConfig>,
+    /// Token prices by full model name (`provider/model`), for cost
+    /// estimates. Models without an entry report no cost.
+    pub pricing: HashMap<String, ModelPricing>,
+    /// USD per call by tool name, for tools whose calls cost money (search
+    /// APIs, hosted browsers). Tools without an entry are free.
+    pub tool_pricing: HashMap<String, f64>,
+    /// Failures injected into requests to matching models, for testing
+    /// fallbacks and alerts. Empty outside of tests.
+    pub faults: Vec<FaultRule>,
+    /// Vault connection for `vault:` key references.
+    pub vault: Option<VaultConfig>,
+    /// AWS Secrets Manager settings for `aws:` key references.
+    pub aws_secrets: Option<AwsSecretsConfig>,
+}
+
+impl LlmConfig {
+    /// The configured key (or credential reference) for a provider id.
+    pub fn key(&self, provider: &str) -> Option<&str> {
+        let slot = match provider {
+            "anthropic" => &self.anthropic_key,
+            "openai" => &self.openai_key,
+            "openrouter" => &self.openrouter_key,
+            "ollama" => &self.ollama_key,
+            "zhipu" => &self.zhipu_key,
+            "groq" => &self.groq_key,
+            "together" => &self.together_key,
+            "fireworks" => &self.fireworks_key,
+            "deepseek" => &self.deepseek_key,
+            "xai" => &self.xai_key,
+            "mistral" => &self.mistral_key,
+            "opencode-zen" => &self.opencode_zen_key,
+            _ => return None,
+        };
+        slot.as_deref()
+    }
+
+    /// Mutable access to a provider's key field, or `None` for an unknown
+    /// provider id.
+    pub(crate) fn key_mut(&mut self, provider: &str) -> Option<&mut Option<String>> {
+        let slot = match provider {
+            "anthropic" => &mut self.anthropic_key,
+            "openai" => &mut self.openai_key,
+            "openrouter" => &mut self.openrouter_key,
+            "ollama" => &mut self.ollama_key,
+            "zhipu" => &mut self.zhipu_key,
+            "groq" => &mut self.groq_key,
+            "together" => &mut self.together_key,
+            "fireworks" => &mut self.fireworks_key,
+            "deepseek" => &mut self.deepseek_key,
+            "xai" => &mut self.xai_key,
+            "mistral" => &mut self.mistral_key,
+            "opencode-zen" => &mut self.opencode_zen_key,
+            _ => return None,
+        };
+        Some(slot)
+    }
+
+    /// Check if any provider key or self-hosted server is configured.
+    pub fn has_any_key(&self) -> bool {
+        !self.base_urls.is_empty()
+            || !self.regions.is_empty()
+            || self.anthropic_key.is_some()
+            || self.openai_key.is_some()
+            || self.openrouter_key.is_some()
+            || self.ollama_key.is_some()
+            || self.zhipu_key.is_some()
+            || self.groq_key.is_some()
+            || self.together_key.is_some()
+            || self.fireworks_key.is_some()
+            || self.deepseek_key.is_some()
+            || self.xai_key.is_some()
+            || self.mistral_key.is_some()
+            || self.opencode_zen_key.is_some()
+    }
+}
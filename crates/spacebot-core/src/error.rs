@@ -0,0 +1,150 @@
+//! Error types for the LLM engine.
+
+use serde::{Deserialize, Serialize};
+
+/// Result type for fallible engine operations.
+pub type Result<T> = std::result::Result<T, LlmError>;
+
+/// LLM provider and model errors.
+#[derive(Debug, thiserror::Error)]
+pub enum LlmError {
+    #[error("unknown provider: {0}")]
+    UnknownProvider(String),
+
+    #[error("unknown model: {0}")]
+    UnknownModel(String),
+
+    #[error("provider request failed: {0}")]
+    ProviderRequest(String),
+
+    #[error("missing API key for provider: {0}")]
+    MissingProviderKey(String),
+
+    #[error("credential unavailable: {0}")]
+    CredentialUnavailable(String),
+
+    #[error("embedding generation failed: {0}")]
+    EmbeddingFailed(String),
+
+    #[error("completion failed: {0}")]
+    CompletionFailed(String),
+
+    #[error(transparent)]
+    Other(#[from] anyhow::Error),
+}
+
+/// An error response from a provider's API, with the structured fields it
+/// sent so callers and the dashboard can show what to fix rather than a
+/// bare status.
+///
+/// Carried inside `CompletionError::RequestError`; use
+/// [`ProviderApiError::find`] to get it back out of a completion or prompt
+/// error. Its `Display` keeps the `"<Provider> API error (<status>): ..."`
+/// shape the string-based error classifiers in `llm::routing` match on.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProviderApiError {
+    /// Display name of the provider, e.g. `Anthropic`.
+    pub provider: String,
+    pub status: u16,
+    /// The provider's error type, e.g. `invalid_request_error`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_type: Option<String>,
+    /// The provider's error code, e.g. `context_length_exceeded`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code: Option<String>,
+    /// The request parameter the error is about, e.g. `tools[3].function`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub param: Option<String>,
+    pub message: String,
+    /// The provider's own id for the request, to quote to its support.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provider_request_id: Option<String>,
+    /// Request id to pull the recorded exchange up with, when
+    /// `llm.debug_recording` is on.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub debug_request_id: Option<String>,
+}
+
+impl ProviderApiError {
+    /// Read the error out of a provider's response body. Understands the
+    /// Anthropic (`{"error": {"type", "message"}}`) and OpenAI-style
+    /// (`{"error": {"message", "type", "param", "code"}}`) shapes, a bare
+    /// `{"error": "..."}`, and a top-level `message`. Anthropic's error
+    /// bodies also carry the provider's `request_id`.
+    pub fn from_response(provider: &str, status: u16, body: &serde_json::Value) -> Self {
+        let error = &body["error"];
+        let field = |name: &str| match &error[name] {
+            serde_json::Value::String(value) if !value.is_empty() => Some(value.clone()),
+            serde_json::Value::Number(value) => Some(value.to_string()),
+            _ => None,
+        };
+        let message = error
+            .as_str()
+            .or_else(|| error["message"].as_str())
+            .or_else(|| body["message"].as_str())
+            .unwrap_or("unknown error")
+            .to_string();
+        Self {
+            provider: provider.to_string(),
+            status,
+            error_type: field("type"),
+            code: field("code"),
+            param: field("param"),
+            message,
+            provider_request_id: body["request_id"].as_str().map(str::to_string),
+            debug_request_id: None,
+        }
+    }
+
+    /// The error in one line, e.g.
+    /// `invalid_request_error: tools.3.input_schema: Field required`.
+    pub fn detail(&self) -> String {
+        let mut parts: Vec<&str> = Vec::new();
+        if let Some(kind) = self.error_type.as_deref().or(self.code.as_deref()) {
+            parts.push(kind);
+        }
+        match self.param.as_deref() {
+            Some(param) if !self.message.contains(param) => parts.push(param),
+            _ => {}
+        }
+        parts.push(&self.message);
+        parts.join(": ")
+    }
+
+    /// The provider error behind `error`, if there is one anywhere in its
+    /// source chain.
+    pub fn find<'a>(error: &'a (dyn std::error::Error + 'static)) -> Option<&'a Self> {
+        std::iter::successors(Some(error), |error| error.source())
+            .find_map(|error| error.downcast_ref::<Self>())
+    }
+}
+
+impl std::fmt::Display for ProviderApiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let status = match reqwest::StatusCode::from_u16(self.status) {
+            Ok(status) => status.to_string(),
+            Err(_) => self.status.to_string(),
+        };
+        write!(
+            f,
+            "{} API error ({status}): {}",
+            self.provider,
+            self.detail()
+        )?;
+        if let Some(request_id) = &self.provider_request_id {
+            write!(f, " (provider request id: {request_id})")?;
+        }
+        if let Some(request_id) = &self.debug_request_id {
+            write!(f, " (debug request id: {request_id})")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ProviderApiError {}
+
+impl From<ProviderApiError> for rig::completion::CompletionError {
+    fn from(error: ProviderApiError) -> Self {
+        Self::RequestError(Box::new(error))
+    }
+}
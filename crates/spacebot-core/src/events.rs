@@ -0,0 +1,165 @@
+//! In-process event bus for cross-cutting notifications.
+//!
+//! The LLM engine and the processes built on it publish typed [`Event`]s to a
+//! shared [`EventBus`]; telemetry, alerting, the API event stream and plugins
+//! subscribe instead of each threading their own callback through the
+//! request path. Publishing never blocks, and subscribers that fall behind
+//! miss the oldest events rather than slowing down requests.
+
+use crate::ProcessType;
+use crate::llm::credentials::KeySlot;
+use crate::llm::key_health::KeyStatus;
+use crate::llm::race::RaceRecord;
+use crate::llm::shadow::ShadowRecord;
+
+use serde::Serialize;
+use tokio::sync::broadcast;
+
+/// Events buffered per subscriber before the slowest starts missing some.
+const DEFAULT_CAPACITY: usize = 1024;
+
+/// Something that happened that other modules may care about.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Event {
+    /// A routed completion request finished, successfully or not.
+    CompletionFinished {
+        request_id: String,
+        /// The model that was asked for; a fallback may have answered.
+        model: String,
+        success: bool,
+        input_tokens: u64,
+        output_tokens: u64,
+        latency_ms: u64,
+    },
+    /// A tool call returned.
+    ToolExecuted {
+        agent_id: String,
+        process_id: String,
+        process_type: ProcessType,
+        /// The conversation the process works for, if any.
+        channel_id: Option<String>,
+        tool_name: String,
+        result_bytes: usize,
+        /// Time from the call to its result.
+        duration_ms: u64,
+        /// False if the tool returned an error.
+        success: bool,
+    },
+    /// A process was stopped for looping without progress.
+    LoopDetected {
+        agent_id: String,
+        process_id: String,
+        process_type: ProcessType,
+        /// `repeated_tool_call`, `no_progress` or `oscillation`.
+        kind: String,
+        detail: String,
+    },
+    /// A model hit its provider's rate limit and entered cooldown.
+    RateLimited { model: String },
+    /// A self-hosted provider failed or passed a health probe after the
+    /// opposite outcome. Routing skips the provider while it is unhealthy.
+    ProviderHealthChanged {
+        provider: String,
+        healthy: bool,
+        detail: String,
+    },
+    /// A provider started or stopped failing across its models. Routing
+    /// skips the provider while the outage is active.
+    ProviderOutage {
+        provider: String,
+        active: bool,
+        detail: String,
+    },
+    /// A provider key's periodic check came out differently than the last
+    /// one, e.g. it was revoked or is nearing its spending limit.
+    KeyHealthChanged {
+        provider: String,
+        slot: KeySlot,
+        status: KeyStatus,
+        detail: String,
+    },
+    /// A best-of-N race finished, with every candidate's answer and cost.
+    RaceFinished { race: RaceRecord },
+    /// A request mirrored to a candidate model was answered and compared.
+    ShadowFinished { shadow: ShadowRecord },
+    /// Spend crossed a configured fraction of a budget. Nothing enforces
+    /// budgets yet; this is here so consumers can be written against it.
+    BudgetThreshold {
+        scope: String,
+        spent_usd: f64,
+        limit_usd: f64,
+    },
+    /// A credential source returned a different secret than before.
+    CredentialRefreshed { provider: String },
+    /// A provider rejected its primary key and requests moved to the
+    /// secondary.
+    CredentialFailover { provider: String },
+    /// A provider flagged a model as deprecated, in a response header or an
+    /// error. Published once per model.
+    ModelDeprecated { model: String, detail: String },
+    /// A request for a model was answered by a different snapshot than the
+    /// name asked for, and than answered last time.
+    ModelSnapshotChanged {
+        model: String,
+        served: String,
+        /// The snapshot that answered before, if one had been seen.
+        previous: Option<String>,
+    },
+    /// A circuit breaker opened and stopped something that kept failing.
+    CircuitBreakerTripped { scope: String, detail: String },
+}
+
+/// Cloneable handle for publishing and subscribing to [`Event`]s.
+#[derive(Debug, Clone)]
+pub struct EventBus {
+    tx: broadcast::Sender<Event>,
+}
+
+impl EventBus {
+    pub fn new(capacity: usize) -> Self {
+        let (tx, _) = broadcast::channel(capacity);
+        Self { tx }
+    }
+
+    /// Send an event to every current subscriber.
+    pub fn publish(&self, event: Event) {
+        // No subscribers is the common case and not an error.
+        self.tx.send(event).ok();
+    }
+
+    /// Receive every event published from now on.
+    pub fn subscribe(&self) -> broadcast::Receiver<Event> {
+        self.tx.subscribe()
+    }
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new(DEFAULT_CAPACITY)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_subscribers_receive_events_published_after_subscribing() {
+        let bus = EventBus::default();
+        bus.publish(Event::RateLimited {
+            model: "missed".into(),
+        });
+
+        let mut rx = bus.subscribe();
+        bus.publish(Event::CredentialRefreshed {
+            provider: "anthropic".into(),
+        });
+
+        let event = rx.recv().await.expect("event was published");
+        assert!(matches!(
+            event,
+            Event::CredentialRefreshed { provider } if provider == "anthropic"
+        ));
+    }
+}
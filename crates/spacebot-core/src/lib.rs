@@ -0,0 +1,66 @@
+//! Spacebot's LLM engine: provider clients, model routing, fallback chains,
+//! retries, and request limiting, without the agent runtime, messaging
+//! adapters, or daemon.
+//!
+//! The `spacebot` crate builds its agents on top of this one. Embedders can
+//! depend on it directly to get the same routing and fallback behavior.
+//! Tools stay in `spacebot`: they are built around its memory store,
+//! messaging adapters, and process events.
+//!
+//!
+//! ```no_run
+//! use rig::completion::CompletionModel as _;
+//! use spacebot_core::llm::{LlmManager, SpacebotModel};
+//! use std::sync::Arc;
+//!
+//! # fn run() -> spacebot_core::error::Result<()> {
+//! let manager = LlmManager::builder()
+//!     .provider_key("anthropic", std::env::var("ANTHROPIC_API_KEY").unwrap_or_default())
+//!     .build()?;
+//! let manager = Arc::new(manager);
+//! let model = SpacebotModel::make(&manager, "anthropic/claude-sonnet-4-20250514");
+//! # let _ = model;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! # Stability
+//!
+//! Everything reachable from this crate root is public API and follows
+//! semver: breaking changes to it only land in a new major version (or, while
+//! the version is `0.x`, a new minor version). Items marked `#[doc(hidden)]`
+//! are not covered.
+
+pub mod capabilities;
+pub mod config;
+pub mod error;
+pub mod events;
+pub mod llm;
+pub mod permissions;
+pub mod redact;
+
+use serde::{Deserialize, Serialize};
+
+/// Process types in the system.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum ProcessType {
+    Channel,
+    Branch,
+    Worker,
+    Compactor,
+    Cortex,
+}
+
+impl std::fmt::Display for ProcessType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ProcessType::Channel => write!(f, "channel"),
+            ProcessType::Branch => write!(f, "branch"),
+            ProcessType::Worker => write!(f, "worker"),
+            ProcessType::Compactor => write!(f, "compactor"),
+            ProcessType::Cortex => write!(f, "cortex"),
+        }
+    }
+}
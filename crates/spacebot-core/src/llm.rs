@@ -0,0 +1,45 @@
+//! LLM provider management and routing.
+
+pub mod candidates;
+pub mod compress;
+pub mod credentials;
+pub mod empty;
+pub mod faults;
+#[cfg(feature = "record")]
+pub mod fixtures;
+pub mod generation;
+pub mod grammar;
+pub mod health;
+pub mod key_health;
+pub mod latency_budget;
+pub mod limiter;
+pub mod manager;
+pub mod metrics;
+pub mod model;
+pub mod model_changes;
+pub mod outage;
+pub mod output_cap;
+pub mod pricing;
+pub mod prompt_tokens;
+pub mod providers;
+pub mod race;
+pub mod raw;
+pub mod recorder;
+pub mod regions;
+pub mod replay;
+pub mod routing;
+pub mod sampling;
+pub mod schema;
+pub mod shadow;
+pub mod signing;
+pub mod simulate;
+pub mod tags;
+pub mod tool_filter;
+pub mod tool_names;
+pub mod uploads;
+
+pub use credentials::{CredentialSource, CredentialSourceDyn};
+pub use limiter::Priority;
+pub use manager::{LlmManager, LlmManagerBuilder};
+pub use model::SpacebotModel;
+pub use routing::RoutingConfig;
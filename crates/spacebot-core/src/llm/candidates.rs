@@ -0,0 +1,84 @@
+//! Several candidate answers per request.
+//!
+//! Some callers want more than one answer to pick from: a reranker, an
+//! eval, a person choosing between drafts. With
+//! [`SpacebotModel::with_candidates`](crate::llm::model::SpacebotModel::with_candidates)
+//! above one, providers that draw several choices in one request (`n`) are
+//! asked for them that way, and the rest are sent the request once per
+//! missing candidate, in parallel. Every candidate ends up on
+//! [`RawResponse::candidates`](crate::llm::model::RawResponse::candidates),
+//! the response's own choice first, and the response's usage and cost
+//! cover all of them.
+//!
+//! Unlike [sampling](crate::llm::sampling), nothing is picked: selection is
+//! left to the caller.
+
+use rig::completion::Usage;
+use rig::message::AssistantContent;
+use rig::one_or_many::OneOrMany;
+use serde::{Deserialize, Serialize};
+
+/// Most candidates drawn for one request.
+pub const MAX_CANDIDATES: usize = 8;
+
+/// One answer to a request that asked for several.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Candidate {
+    pub choice: OneOrMany<AssistantContent>,
+    /// The model that drew it, when known. Fallbacks can answer some
+    /// candidates and not others.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+}
+
+impl Candidate {
+    pub fn new(choice: OneOrMany<AssistantContent>) -> Self {
+        Self {
+            choice,
+            model: None,
+        }
+    }
+}
+
+/// Whether a provider draws several choices per request when sent `n`.
+/// OpenAI and vLLM do; other OpenAI-compatible APIs tend to ignore it.
+pub(crate) fn supports_n(provider_id: &str, is_vllm: bool) -> bool {
+    provider_id == "openai" || is_vllm
+}
+
+/// Usage of the responses a set of candidates came from, added up. A
+/// response that drew several choices already reports them together.
+pub(crate) fn total_usage(usages: impl IntoIterator<Item = Usage>) -> Usage {
+    usages.into_iter().fold(Usage::new(), |mut total, usage| {
+        total += usage;
+        total
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_total_usage_adds_every_response() {
+        let usage = |input_tokens, output_tokens, cached_input_tokens| Usage {
+            input_tokens,
+            output_tokens,
+            total_tokens: input_tokens + output_tokens,
+            cached_input_tokens,
+        };
+        assert_eq!(
+            total_usage([usage(100, 20, 50), usage(100, 35, 0), usage(90, 5, 10)]),
+            usage(290, 60, 60)
+        );
+        assert_eq!(total_usage([]), Usage::new());
+    }
+
+    #[test]
+    fn test_supports_n() {
+        assert!(supports_n("openai", false));
+        assert!(supports_n("local", true));
+        assert!(!supports_n("openrouter", false));
+        assert!(!supports_n("anthropic", false));
+    }
+}
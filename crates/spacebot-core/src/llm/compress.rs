@@ -0,0 +1,305 @@
+//! Heuristic prompt compression.
+//!
+//! Tool output and pasted logs are mostly noise to the model: blank runs,
+//! repeated lines, progress spam. A [`PromptCompressor`] shrinks long text
+//! parts of a request toward a target ratio before it is sent, in the spirit
+//! of LLMLingua but without a scoring model: lines are ranked by how many
+//! words they add that the text hasn't already said, the head and tail are
+//! always kept, and dropped spans are replaced with a marker so the model
+//! knows something was left out.
+
+use rig::completion::CompletionRequest;
+use rig::message::{Message, ToolResultContent, UserContent};
+use rig::one_or_many::OneOrMany;
+
+use std::collections::HashSet;
+
+/// Lines at the start and end of a text that are never dropped. Commands
+/// and logs put their most useful output at either end.
+const PINNED_LINES: usize = 5;
+
+/// Budget reserved per kept line for the omission marker that may precede it.
+const MARKER_ALLOWANCE: usize = 32;
+
+/// Words that mark a line as worth keeping regardless of novelty.
+const SIGNAL_WORDS: &[&str] = &[
+    "error",
+    "failed",
+    "failure",
+    "panic",
+    "exception",
+    "warning",
+    "fatal",
+    "denied",
+    "traceback",
+];
+
+/// Characters before and after compressing one request.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CompressionStats {
+    pub chars_before: usize,
+    pub chars_after: usize,
+}
+
+impl CompressionStats {
+    /// Rough token savings, at ~4 chars/token.
+    pub fn tokens_saved(&self) -> usize {
+        self.chars_before.saturating_sub(self.chars_after) / 4
+    }
+}
+
+/// Shrinks tool results and long user messages toward a target ratio.
+#[derive(Debug, Clone, Copy)]
+pub struct PromptCompressor {
+    ratio: f32,
+    min_chars: usize,
+}
+
+impl PromptCompressor {
+    /// `ratio` is the target size as a fraction of the original, clamped to
+    /// 0.1–1.0. Text shorter than `min_chars` is left alone.
+    pub fn new(ratio: f32, min_chars: usize) -> Self {
+        Self {
+            ratio: ratio.clamp(0.1, 1.0),
+            min_chars,
+        }
+    }
+
+    /// Compress every tool result and user text part in the request history.
+    pub fn apply(&self, request: &mut CompletionRequest) -> CompressionStats {
+        let mut stats = CompressionStats::default();
+        let messages: Vec<Message> = request
+            .chat_history
+            .iter()
+            .map(|message| match message {
+                Message::User { content } => Message::User {
+                    content: map_one_or_many(content, |item| {
+                        self.compress_user_content(item, &mut stats)
+                    }),
+                },
+                other => other.clone(),
+            })
+            .collect();
+        if let Ok(history) = OneOrMany::many(messages) {
+            request.chat_history = history;
+        }
+        stats
+    }
+
+    fn compress_user_content(
+        &self,
+        item: &UserContent,
+        stats: &mut CompressionStats,
+    ) -> UserContent {
+        match item {
+            UserContent::Text(text) => {
+                let mut text = text.clone();
+                self.compress_in_place(&mut text.text, stats);
+                UserContent::Text(text)
+            }
+            UserContent::ToolResult(result) => {
+                let mut result = result.clone();
+                result.content = map_one_or_many(&result.content, |part| match part {
+                    ToolResultContent::Text(text) => {
+                        let mut text = text.clone();
+                        self.compress_in_place(&mut text.text, stats);
+                        ToolResultContent::Text(text)
+                    }
+                    other => other.clone(),
+                });
+                UserContent::ToolResult(result)
+            }
+            other => other.clone(),
+        }
+    }
+
+    fn compress_in_place(&self, text: &mut String, stats: &mut CompressionStats) {
+        if let Some(compressed) = self.compress(text) {
+            stats.chars_before += text.len();
+            stats.chars_after += compressed.len();
+            *text = compressed;
+        }
+    }
+
+    /// Compressed form of `text`, or None when it is too short to bother or
+    /// compression wouldn't make it smaller.
+    pub fn compress(&self, text: &str) -> Option<String> {
+        if text.len() < self.min_chars {
+            return None;
+        }
+        let budget = (text.len() as f32 * self.ratio) as usize;
+
+        let lines = collapse_repeats(text);
+        let mut compressed = if total_len(&lines) <= budget {
+            lines.join("\n")
+        } else {
+            select_lines(&lines, budget)
+        };
+        if compressed.len() > budget {
+            compressed = clip_middle(&compressed, budget);
+        }
+
+        (compressed.len() < text.len()).then_some(compressed)
+    }
+}
+
+/// Trim trailing whitespace, squeeze blank runs to one blank line, and fold
+/// consecutive identical lines into one with a count.
+fn collapse_repeats(text: &str) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    let mut previous: Option<&str> = None;
+    let mut repeats = 0;
+    for line in text.lines().map(str::trim_end) {
+        if previous == Some(line) {
+            if !line.is_empty() {
+                repeats += 1;
+            }
+            continue;
+        }
+        if let Some(last) = lines.last_mut().filter(|_| repeats > 0) {
+            last.push_str(&format!(" [repeated {repeats} more times]"));
+        }
+        repeats = 0;
+        previous = Some(line);
+        lines.push(line.to_string());
+    }
+    if let Some(last) = lines.last_mut().filter(|_| repeats > 0) {
+        last.push_str(&format!(" [repeated {repeats} more times]"));
+    }
+    lines
+}
+
+fn total_len(lines: &[String]) -> usize {
+    lines.iter().map(|line| line.len() + 1).sum()
+}
+
+/// Keep the pinned head and tail plus the most informative lines that fit in
+/// `budget`, in their original order, marking each dropped span.
+fn select_lines(lines: &[String], budget: usize) -> String {
+    let mut seen = HashSet::new();
+    let mut scored: Vec<(f32, usize)> = lines
+        .iter()
+        .enumerate()
+        .map(|(index, line)| (line_score(line, &mut seen), index))
+        .collect();
+    let is_pinned = |index: usize| index < PINNED_LINES || index + PINNED_LINES >= lines.len();
+
+    let mut keep = vec![false; lines.len()];
+    // One marker between head and tail even if nothing else is kept.
+    let mut used = MARKER_ALLOWANCE;
+    for index in (0..lines.len()).filter(|index| is_pinned(*index)) {
+        keep[index] = true;
+        used += lines[index].len() + 1;
+    }
+    scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+    for (score, index) in scored {
+        if keep[index] || score <= 0.0 {
+            continue;
+        }
+        let cost = lines[index].len() + 1 + MARKER_ALLOWANCE;
+        if used + cost <= budget {
+            keep[index] = true;
+            used += cost;
+        }
+    }
+
+    let mut output = Vec::new();
+    let mut dropped = 0;
+    for (line, kept) in lines.iter().zip(&keep) {
+        if *kept {
+            if dropped > 0 {
+                output.push(format!("[... {dropped} lines omitted]"));
+                dropped = 0;
+            }
+            output.push(line.clone());
+        } else {
+            dropped += 1;
+        }
+    }
+    if dropped > 0 {
+        output.push(format!("[... {dropped} lines omitted]"));
+    }
+    output.join("\n")
+}
+
+/// How much a line adds: words not seen earlier in the text, damped by
+/// length so long lines don't win on size alone, with a boost for lines
+/// that look like errors.
+fn line_score(line: &str, seen: &mut HashSet<String>) -> f32 {
+    let words: Vec<String> = line
+        .split(|c: char| !c.is_alphanumeric() && c != '_')
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect();
+    if words.is_empty() {
+        return 0.0;
+    }
+    let signal = words
+        .iter()
+        .any(|word| SIGNAL_WORDS.contains(&word.as_str()));
+    let novel = words
+        .iter()
+        .filter(|word| seen.insert((*word).clone()))
+        .count();
+
+    let score = novel as f32 / (words.len() as f32).sqrt();
+    if signal { score + 2.0 } else { score }
+}
+
+/// Cut the middle out of text that is still over budget, e.g. a single huge
+/// line of JSON. Keeps two thirds of the budget from the start and the rest
+/// from the end.
+fn clip_middle(text: &str, budget: usize) -> String {
+    let head_end = text.floor_char_boundary(budget * 2 / 3);
+    let tail_start = text.ceil_char_boundary(text.len() - (budget - budget * 2 / 3));
+    if tail_start <= head_end {
+        return text.to_string();
+    }
+    let omitted = tail_start - head_end;
+    format!(
+        "{}\n[... {omitted} characters omitted]\n{}",
+        &text[..head_end],
+        &text[tail_start..]
+    )
+}
+
+fn map_one_or_many<T: Clone>(items: &OneOrMany<T>, mut f: impl FnMut(&T) -> T) -> OneOrMany<T> {
+    let mapped: Vec<T> = items.iter().map(&mut f).collect();
+    OneOrMany::many(mapped).unwrap_or_else(|_| items.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_collapses_repeated_lines() {
+        let text = format!("start\n{}end", "progress 10%\n".repeat(50));
+        let compressed = PromptCompressor::new(0.5, 0).compress(&text).unwrap();
+        assert_eq!(
+            compressed,
+            "start\nprogress 10% [repeated 49 more times]\nend"
+        );
+    }
+
+    #[test]
+    fn test_keeps_errors_and_ends_within_budget() {
+        let mut lines: Vec<String> = (0..200).map(|i| format!("compiling crate {i}")).collect();
+        lines[100] = "error[E0308]: mismatched types".into();
+        let text = lines.join("\n");
+
+        let compressed = PromptCompressor::new(0.2, 100).compress(&text).unwrap();
+
+        assert!(compressed.len() <= text.len() / 5 + 1);
+        assert!(compressed.starts_with("compiling crate 0\n"));
+        assert!(compressed.ends_with("compiling crate 199"));
+        assert!(compressed.contains("error[E0308]: mismatched types"));
+        assert!(compressed.contains("lines omitted]"));
+    }
+
+    #[test]
+    fn test_short_text_untouched() {
+        let compressor = PromptCompressor::new(0.5, 1000);
+        assert_eq!(compressor.compress("short output"), None);
+    }
+}
@@ -0,0 +1,606 @@
+//! Pluggable credential sources for provider API keys.
+//!
+//! Each provider's key comes from a [`CredentialSource`]: a literal value,
+//! an environment variable, a file, or the OS keyring out of the box, or
+//! anything an embedder implements. Keys are fetched lazily on first use,
+//! cached per provider, and re-fetched when the cached value expires or is
+//! invalidated (for example after the provider rejects it).
+//!
+//! HashiCorp Vault and AWS Secrets Manager backends live in [`vault`] and
+//! [`aws`].
+
+pub mod aws;
+pub mod vault;
+
+use crate::error::{LlmError, Result};
+use crate::llm::credentials::aws::{AwsSecretCredential, AwsSecretsBackend};
+use crate::llm::credentials::vault::{VaultBackend, VaultCredential};
+
+use serde::Serialize;
+use tokio::sync::RwLock;
+
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// A fetched secret and how long it may be served from cache.
+#[derive(Clone)]
+pub struct Credential {
+    pub secret: String,
+    /// `None` caches the secret until it is invalidated.
+    pub ttl: Option<Duration>,
+}
+
+impl Credential {
+    pub fn new(secret: impl Into<String>) -> Self {
+        Self {
+            secret: secret.into(),
+            ttl: None,
+        }
+    }
+
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+}
+
+impl std::fmt::Debug for Credential {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Credential")
+            .field("secret", &"[REDACTED]")
+            .field("ttl", &self.ttl)
+            .finish()
+    }
+}
+
+/// Static trait for credential backends.
+/// Use this for type-safe implementations.
+pub trait CredentialSource: Send + Sync + 'static {
+    /// Where the credential comes from, for logs. Must not include the secret.
+    fn describe(&self) -> String;
+
+    /// Fetch the current secret.
+    fn fetch(&self) -> impl std::future::Future<Output = Result<Credential>> + Send;
+}
+
+/// Dynamic trait for runtime polymorphism.
+/// Use this when you need `Arc<dyn CredentialSourceDyn>` for storing different backends.
+pub trait CredentialSourceDyn: Send + Sync + 'static {
+    fn describe(&self) -> String;
+
+    fn fetch<'a>(
+        &'a self,
+    ) -> Pin<Box<dyn std::future::Future<Output = Result<Credential>> + Send + 'a>>;
+}
+
+/// Blanket implementation: any type implementing CredentialSource automatically implements CredentialSourceDyn.
+impl<T: CredentialSource> CredentialSourceDyn for T {
+    fn describe(&self) -> String {
+        CredentialSource::describe(self)
+    }
+
+    fn fetch<'a>(
+        &'a self,
+    ) -> Pin<Box<dyn std::future::Future<Output = Result<Credential>> + Send + 'a>> {
+        Box::pin(CredentialSource::fetch(self))
+    }
+}
+
+/// A key given directly in config or code.
+pub struct StaticCredential(String);
+
+impl StaticCredential {
+    pub fn new(secret: impl Into<String>) -> Self {
+        Self(secret.into())
+    }
+}
+
+impl CredentialSource for StaticCredential {
+    fn describe(&self) -> String {
+        "inline value".into()
+    }
+
+    async fn fetch(&self) -> Result<Credential> {
+        Ok(Credential::new(self.0.clone()))
+    }
+}
+
+/// A key read from an environment variable each time it is fetched.
+pub struct EnvCredential {
+    var: String,
+}
+
+impl EnvCredential {
+    pub fn new(var: impl Into<String>) -> Self {
+        Self { var: var.into() }
+    }
+}
+
+impl CredentialSource for EnvCredential {
+    fn describe(&self) -> String {
+        format!("env:{}", self.var)
+    }
+
+    async fn fetch(&self) -> Result<Credential> {
+        std::env::var(&self.var)
+            .map(Credential::new)
+            .map_err(|_| LlmError::CredentialUnavailable(format!("{} is not set", self.var)))
+    }
+}
+
+/// A key read from a file, e.g. a mounted Kubernetes or Docker secret.
+/// Surrounding whitespace is trimmed.
+pub struct FileCredential {
+    path: PathBuf,
+}
+
+impl FileCredential {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+}
+
+impl CredentialSource for FileCredential {
+    fn describe(&self) -> String {
+        format!("file:{}", self.path.display())
+    }
+
+    async fn fetch(&self) -> Result<Credential> {
+        let contents = tokio::fs::read_to_string(&self.path)
+            .await
+            .map_err(|error| {
+                LlmError::CredentialUnavailable(format!(
+                    "can't read {}: {error}",
+                    self.path.display()
+                ))
+            })?;
+        let secret = contents.trim();
+        if secret.is_empty() {
+            return Err(LlmError::CredentialUnavailable(format!(
+                "{} is empty",
+                self.path.display()
+            )));
+        }
+        Ok(Credential::new(secret))
+    }
+}
+
+/// A key stored in the OS keyring: the macOS Keychain via `security`, or the
+/// Secret Service (GNOME Keyring, KWallet) via `secret-tool` on other Unixes.
+/// Windows Credential Manager has no CLI that can print a secret, so it isn't
+/// supported; use a `file:` reference there instead.
+pub struct KeyringCredential {
+    service: String,
+    account: String,
+}
+
+impl KeyringCredential {
+    pub fn new(service: impl Into<String>, account: impl Into<String>) -> Self {
+        Self {
+            service: service.into(),
+            account: account.into(),
+        }
+    }
+}
+
+impl CredentialSource for KeyringCredential {
+    fn describe(&self) -> String {
+        format!("keyring:{}/{}", self.service, self.account)
+    }
+
+    async fn fetch(&self) -> Result<Credential> {
+        if cfg!(windows) {
+            return Err(LlmError::CredentialUnavailable(
+                "keyring references aren't supported on Windows; use file: or env: instead".into(),
+            ));
+        }
+
+        let mut command = if cfg!(target_os = "macos") {
+            let mut command = tokio::process::Command::new("security");
+            command.args([
+                "find-generic-password",
+                "-s",
+                &self.service,
+                "-a",
+                &self.account,
+                "-w",
+            ]);
+            command
+        } else {
+            let mut command = tokio::process::Command::new("secret-tool");
+            command.args(["lookup", "service", &self.service, "account", &self.account]);
+            command
+        };
+
+        let output = command.output().await.map_err(|error| {
+            LlmError::CredentialUnavailable(format!("can't query keyring: {error}"))
+        })?;
+        let secret = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        if !output.status.success() || secret.is_empty() {
+            return Err(LlmError::CredentialUnavailable(format!(
+                "no keyring entry for {}/{}",
+                self.service, self.account
+            )));
+        }
+        Ok(Credential::new(secret))
+    }
+}
+
+/// Shared connections to remote secret stores, set up once per manager.
+#[derive(Default)]
+pub struct CredentialBackends {
+    pub vault: Option<Arc<VaultBackend>>,
+    pub aws: Option<Arc<AwsSecretsBackend>>,
+}
+
+/// Build a source from a config value. `env:VAR`, `file:/path`,
+/// `keyring:service/account`, `vault:path#field`, and `aws:secret-id[#field]`
+/// select a backend; anything else is the key itself. Fails when a remote
+/// backend is referenced but not configured.
+pub fn source_from_reference(
+    value: &str,
+    backends: &CredentialBackends,
+) -> Result<Arc<dyn CredentialSourceDyn>> {
+    if let Some(var) = value.strip_prefix("env:") {
+        return Ok(Arc::new(EnvCredential::new(var)));
+    }
+    if let Some(path) = value.strip_prefix("file:") {
+        return Ok(Arc::new(FileCredential::new(path)));
+    }
+    if let Some(entry) = value.strip_prefix("keyring:") {
+        let (service, account) = entry.split_once('/').unwrap_or(("spacebot", entry));
+        return Ok(Arc::new(KeyringCredential::new(service, account)));
+    }
+    if let Some(entry) = value.strip_prefix("vault:") {
+        let backend = backends.vault.clone().ok_or_else(|| {
+            LlmError::CredentialUnavailable(format!("{value} needs an [llm.vault] section"))
+        })?;
+        let (path, field) = entry.split_once('#').unwrap_or((entry, "value"));
+        return Ok(Arc::new(VaultCredential::new(backend, path, field)));
+    }
+    if let Some(entry) = value.strip_prefix("aws:") {
+        let backend = backends.aws.clone().ok_or_else(|| {
+            LlmError::CredentialUnavailable(format!("{value} needs an [llm.aws_secrets] section"))
+        })?;
+        let (secret_id, field) = match entry.split_once('#') {
+            Some((secret_id, field)) => (secret_id, Some(field.to_string())),
+            None => (entry, None),
+        };
+        return Ok(Arc::new(AwsSecretCredential::new(
+            backend, secret_id, field,
+        )));
+    }
+    Ok(Arc::new(StaticCredential::new(value)))
+}
+
+/// Called with the provider id whenever a re-fetch returns a different
+/// secret than the one it replaces.
+pub type RefreshHook = Arc<dyn Fn(&str) + Send + Sync>;
+
+/// Called with the provider id when its primary key is rejected and requests
+/// fail over to the secondary key.
+pub type FailoverHook = Arc<dyn Fn(&str) + Send + Sync>;
+
+struct CachedCredential {
+    secret: String,
+    expires_at: Option<Instant>,
+}
+
+#[derive(Default)]
+struct FailoverState {
+    /// Providers whose primary key was rejected.
+    primary_bad: HashSet<String>,
+    /// Failover count per provider.
+    generations: HashMap<String, u64>,
+}
+
+/// Which of a provider's keys is in use.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum KeySlot {
+    Primary,
+    Secondary,
+}
+
+impl KeySlot {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Primary => "primary",
+            Self::Secondary => "secondary",
+        }
+    }
+
+    pub(crate) fn cache_key(self, provider: &str) -> String {
+        match self {
+            Self::Primary => provider.to_string(),
+            Self::Secondary => format!("{provider}:secondary"),
+        }
+    }
+}
+
+/// Per-provider credential sources with a shared cache.
+///
+/// A provider may have a secondary key. When the provider rejects the
+/// primary, the registry marks it bad and serves the secondary until
+/// [`restore_primary`](Self::restore_primary) is called, so a key can be
+/// rotated by installing the new one as secondary, revoking the old one, and
+/// then promoting it.
+pub struct CredentialRegistry {
+    sources: HashMap<String, Arc<dyn CredentialSourceDyn>>,
+    secondary_sources: HashMap<String, Arc<dyn CredentialSourceDyn>>,
+    cache: RwLock<HashMap<String, CachedCredential>>,
+    /// Last secret seen per key slot, kept across invalidation so rotation
+    /// can be detected.
+    last_seen: RwLock<HashMap<String, String>>,
+    failover: std::sync::Mutex<FailoverState>,
+    refresh_hooks: Vec<RefreshHook>,
+    failover_hooks: Vec<FailoverHook>,
+}
+
+impl CredentialRegistry {
+    pub fn new(
+        sources: HashMap<String, Arc<dyn CredentialSourceDyn>>,
+        secondary_sources: HashMap<String, Arc<dyn CredentialSourceDyn>>,
+        refresh_hooks: Vec<RefreshHook>,
+        failover_hooks: Vec<FailoverHook>,
+    ) -> Self {
+        Self {
+            sources,
+            secondary_sources,
+            cache: RwLock::new(HashMap::new()),
+            last_seen: RwLock::new(HashMap::new()),
+            failover: std::sync::Mutex::new(FailoverState::default()),
+            refresh_hooks,
+            failover_hooks,
+        }
+    }
+
+    /// Whether a source is registered for the provider. Doesn't fetch.
+    pub fn has_source(&self, provider: &str) -> bool {
+        self.sources.contains_key(provider)
+    }
+
+    /// Whether the provider has a secondary key to fail over to.
+    pub fn has_secondary(&self, provider: &str) -> bool {
+        self.secondary_sources.contains_key(provider)
+    }
+
+    /// Describe the provider's source, for logs and diagnostics.
+    pub fn describe(&self, provider: &str) -> Option<String> {
+        self.sources.get(provider).map(|source| source.describe())
+    }
+
+    /// The key slot requests to this provider currently use.
+    pub fn active_slot(&self, provider: &str) -> KeySlot {
+        if self.lock_failover().primary_bad.contains(provider) {
+            KeySlot::Secondary
+        } else {
+            KeySlot::Primary
+        }
+    }
+
+    /// Bumped every time the provider fails over. Callers compare values from
+    /// before and after a request to tell whether retrying with the new key
+    /// is worthwhile.
+    pub fn failover_generation(&self, provider: &str) -> u64 {
+        self.lock_failover()
+            .generations
+            .get(provider)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// The provider's current secret, from cache when still fresh.
+    pub async fn get(&self, provider: &str) -> Result<String> {
+        self.get_slot(provider, self.active_slot(provider)).await
+    }
+
+    /// The secret in one of the provider's key slots, whether or not it's
+    /// the one in use, e.g. to check a secondary key before it's needed.
+    pub async fn get_slot(&self, provider: &str, slot: KeySlot) -> Result<String> {
+        let cache_key = slot.cache_key(provider);
+        if let Some(cached) = self.cache.read().await.get(&cache_key) {
+            let fresh = cached
+                .expires_at
+                .is_none_or(|expires_at| Instant::now() < expires_at);
+            if fresh {
+                return Ok(cached.secret.clone());
+            }
+        }
+
+        let sources = match slot {
+            KeySlot::Primary => &self.sources,
+            KeySlot::Secondary => &self.secondary_sources,
+        };
+        let source = sources
+            .get(provider)
+            .ok_or_else(|| LlmError::MissingProviderKey(provider.into()))?;
+        let credential = source.fetch().await.map_err(|error| {
+            tracing::warn!(provider, source = %source.describe(), %error, "can't fetch credential");
+            error
+        })?;
+
+        let rotated = {
+            let mut last_seen = self.last_seen.write().await;
+            let previous = last_seen.insert(cache_key.clone(), credential.secret.clone());
+            previous.is_some_and(|previous| previous != credential.secret)
+        };
+        if rotated {
+            tracing::info!(provider, source = %source.describe(), "credential rotated");
+            for hook in &self.refresh_hooks {
+                hook(provider);
+            }
+        }
+
+        self.cache.write().await.insert(
+            cache_key,
+            CachedCredential {
+                secret: credential.secret.clone(),
+                expires_at: credential.ttl.map(|ttl| Instant::now() + ttl),
+            },
+        );
+        Ok(credential.secret)
+    }
+
+    /// Drop the cached secret so the next request fetches it again.
+    pub async fn invalidate(&self, provider: &str) {
+        let cache_key = self.active_slot(provider).cache_key(provider);
+        self.cache.write().await.remove(&cache_key);
+    }
+
+    /// The provider rejected the key in use. Fails over to the secondary key
+    /// if the primary was in use and a secondary exists; otherwise drops the
+    /// cached key so a rotated one is fetched next time.
+    pub async fn reject(&self, provider: &str) {
+        let failed_over = self.secondary_sources.contains_key(provider) && {
+            let mut failover = self.lock_failover();
+            let newly_bad = failover.primary_bad.insert(provider.to_string());
+            if newly_bad {
+                *failover
+                    .generations
+                    .entry(provider.to_string())
+                    .or_default() += 1;
+            }
+            newly_bad
+        };
+
+        if !failed_over {
+            self.invalidate(provider).await;
+            return;
+        }
+
+        tracing::error!(
+            provider,
+            "primary API key rejected, failing over to secondary key"
+        );
+        for hook in &self.failover_hooks {
+            hook(provider);
+        }
+    }
+
+    /// Go back to the primary key, e.g. after it has been replaced.
+    pub async fn restore_primary(&self, provider: &str) {
+        if self.lock_failover().primary_bad.remove(provider) {
+            self.cache.write().await.remove(provider);
+            tracing::info!(provider, "primary API key restored");
+        }
+    }
+
+    fn lock_failover(&self) -> std::sync::MutexGuard<'_, FailoverState> {
+        self.failover
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    struct CountingSource {
+        fetches: Arc<AtomicUsize>,
+    }
+
+    impl CredentialSource for CountingSource {
+        fn describe(&self) -> String {
+            "counting".into()
+        }
+
+        async fn fetch(&self) -> Result<Credential> {
+            let fetch = self.fetches.fetch_add(1, Ordering::SeqCst);
+            Ok(Credential::new(format!("key-{fetch}")))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_registry_caches_until_invalidated_and_fires_refresh_hook() {
+        let fetches = Arc::new(AtomicUsize::new(0));
+        let refreshed = Arc::new(AtomicUsize::new(0));
+        let hook_count = refreshed.clone();
+        let registry = CredentialRegistry::new(
+            HashMap::from([(
+                "anthropic".to_string(),
+                Arc::new(CountingSource {
+                    fetches: fetches.clone(),
+                }) as Arc<dyn CredentialSourceDyn>,
+            )]),
+            HashMap::new(),
+            vec![Arc::new(move |_: &str| {
+                hook_count.fetch_add(1, Ordering::SeqCst);
+            })],
+            Vec::new(),
+        );
+
+        assert_eq!(registry.get("anthropic").await.unwrap(), "key-0");
+        assert_eq!(registry.get("anthropic").await.unwrap(), "key-0");
+        assert_eq!(fetches.load(Ordering::SeqCst), 1);
+        assert_eq!(refreshed.load(Ordering::SeqCst), 0);
+
+        registry.invalidate("anthropic").await;
+        assert_eq!(registry.get("anthropic").await.unwrap(), "key-1");
+        assert_eq!(refreshed.load(Ordering::SeqCst), 1);
+
+        assert!(registry.get("openai").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_file_credential_trims_contents() {
+        let path = std::env::temp_dir().join(format!("spacebot-key-{}", std::process::id()));
+        std::fs::write(&path, "  sk-test\n").unwrap();
+
+        let source = source_from_reference(
+            &format!("file:{}", path.display()),
+            &CredentialBackends::default(),
+        )
+        .unwrap();
+        let credential = source.fetch().await.unwrap();
+        std::fs::remove_file(&path).ok();
+
+        assert_eq!(credential.secret, "sk-test");
+    }
+
+    #[test]
+    fn test_remote_reference_requires_backend() {
+        let backends = CredentialBackends::default();
+        assert!(source_from_reference("vault:spacebot/anthropic#key", &backends).is_err());
+        assert!(source_from_reference("aws:prod/openai", &backends).is_err());
+        assert!(source_from_reference("sk-plain", &backends).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_rejected_primary_fails_over_to_secondary_once() {
+        let failovers = Arc::new(AtomicUsize::new(0));
+        let hook_count = failovers.clone();
+        let registry = CredentialRegistry::new(
+            HashMap::from([(
+                "openai".to_string(),
+                Arc::new(StaticCredential::new("primary")) as Arc<dyn CredentialSourceDyn>,
+            )]),
+            HashMap::from([(
+                "openai".to_string(),
+                Arc::new(StaticCredential::new("secondary")) as Arc<dyn CredentialSourceDyn>,
+            )]),
+            Vec::new(),
+            vec![Arc::new(move |_: &str| {
+                hook_count.fetch_add(1, Ordering::SeqCst);
+            })],
+        );
+
+        assert_eq!(registry.get("openai").await.unwrap(), "primary");
+        registry.reject("openai").await;
+        assert_eq!(registry.active_slot("openai"), KeySlot::Secondary);
+        assert_eq!(registry.get("openai").await.unwrap(), "secondary");
+
+        // A rejected secondary doesn't flip back or alert again.
+        registry.reject("openai").await;
+        assert_eq!(registry.failover_generation("openai"), 1);
+        assert_eq!(failovers.load(Ordering::SeqCst), 1);
+
+        registry.restore_primary("openai").await;
+        assert_eq!(registry.get("openai").await.unwrap(), "primary");
+    }
+}
@@ -0,0 +1,231 @@
+//! HashiCorp Vault KV v2 credential backend.
+
+use crate::error::{LlmError, Result};
+use crate::llm::credentials::{Credential, CredentialSource};
+
+use serde::Deserialize;
+use tokio::sync::Mutex;
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Renew the AppRole token this long before Vault says it expires.
+const TOKEN_RENEW_MARGIN: Duration = Duration::from_secs(30);
+
+/// Connection settings for Vault.
+#[derive(Debug, Clone, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct VaultConfig {
+    /// Base URL, e.g. `https://vault.internal:8200`.
+    pub address: String,
+    #[serde(flatten)]
+    pub auth: VaultAuth,
+    /// KV v2 mount point.
+    #[serde(default = "default_mount")]
+    pub mount: String,
+    /// Vault Enterprise namespace, if any.
+    pub namespace: Option<String>,
+    /// How often cached keys are re-read to pick up rotations. `None` keeps
+    /// them until the provider rejects one.
+    pub refresh_interval_secs: Option<u64>,
+}
+
+fn default_mount() -> String {
+    "secret".into()
+}
+
+/// How to authenticate to Vault.
+#[derive(Clone, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(untagged)]
+pub enum VaultAuth {
+    Token { token: String },
+    AppRole { role_id: String, secret_id: String },
+}
+
+impl std::fmt::Debug for VaultAuth {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Token { .. } => f.write_str("Token"),
+            Self::AppRole { role_id, .. } => {
+                f.debug_struct("AppRole").field("role_id", role_id).finish()
+            }
+        }
+    }
+}
+
+struct CachedToken {
+    token: String,
+    expires_at: Option<Instant>,
+}
+
+/// A Vault connection shared by every provider that reads from it.
+pub struct VaultBackend {
+    config: VaultConfig,
+    http_client: reqwest::Client,
+    token: Mutex<Option<CachedToken>>,
+}
+
+impl VaultBackend {
+    pub fn new(config: VaultConfig, http_client: reqwest::Client) -> Self {
+        Self {
+            config,
+            http_client,
+            token: Mutex::new(None),
+        }
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!(
+            "{}/v1/{}",
+            self.config.address.trim_end_matches('/'),
+            path.trim_start_matches('/')
+        )
+    }
+
+    fn request(&self, method: reqwest::Method, path: &str) -> reqwest::RequestBuilder {
+        let request = self.http_client.request(method, self.url(path));
+        match &self.config.namespace {
+            Some(namespace) => request.header("X-Vault-Namespace", namespace),
+            None => request,
+        }
+    }
+
+    /// A client token, logging in with AppRole when the cached one is stale.
+    async fn client_token(&self) -> Result<String> {
+        let (role_id, secret_id) = match &self.config.auth {
+            VaultAuth::Token { token } => return Ok(token.clone()),
+            VaultAuth::AppRole { role_id, secret_id } => (role_id, secret_id),
+        };
+
+        let mut cached = self.token.lock().await;
+        let fresh = cached.as_ref().filter(|token| {
+            token
+                .expires_at
+                .is_none_or(|expires_at| Instant::now() < expires_at)
+        });
+        if let Some(token) = fresh {
+            return Ok(token.token.clone());
+        }
+
+        let response = self
+            .request(reqwest::Method::POST, "auth/approle/login")
+            .json(&serde_json::json!({"role_id": role_id, "secret_id": secret_id}))
+            .send()
+            .await
+            .map_err(|error| vault_error(format!("can't reach Vault: {error}")))?;
+        let body = read_json(response).await?;
+
+        let token = body["auth"]["client_token"]
+            .as_str()
+            .ok_or_else(|| vault_error("AppRole login returned no client token"))?
+            .to_string();
+        let expires_at = body["auth"]["lease_duration"]
+            .as_u64()
+            .filter(|lease| *lease > 0)
+            .map(|lease| {
+                Instant::now() + Duration::from_secs(lease).saturating_sub(TOKEN_RENEW_MARGIN)
+            });
+
+        *cached = Some(CachedToken {
+            token: token.clone(),
+            expires_at,
+        });
+        Ok(token)
+    }
+
+    /// Read one field of a KV v2 secret.
+    pub async fn read_field(&self, path: &str, field: &str) -> Result<String> {
+        let token = self.client_token().await?;
+        let response = self
+            .request(
+                reqwest::Method::GET,
+                &format!(
+                    "{}/data/{}",
+                    self.config.mount,
+                    path.trim_start_matches('/')
+                ),
+            )
+            .header("X-Vault-Token", token)
+            .send()
+            .await
+            .map_err(|error| vault_error(format!("can't reach Vault: {error}")))?;
+
+        if response.status() == reqwest::StatusCode::FORBIDDEN {
+            // The AppRole token may have been revoked early; log in again next time.
+            *self.token.lock().await = None;
+        }
+        let body = read_json(response).await?;
+
+        body["data"]["data"][field]
+            .as_str()
+            .map(ToOwned::to_owned)
+            .ok_or_else(|| vault_error(format!("secret {path} has no field {field:?}")))
+    }
+
+    fn refresh_interval(&self) -> Option<Duration> {
+        self.config.refresh_interval_secs.map(Duration::from_secs)
+    }
+}
+
+/// One provider's key stored in Vault.
+pub struct VaultCredential {
+    backend: Arc<VaultBackend>,
+    path: String,
+    field: String,
+}
+
+impl VaultCredential {
+    pub fn new(
+        backend: Arc<VaultBackend>,
+        path: impl Into<String>,
+        field: impl Into<String>,
+    ) -> Self {
+        Self {
+            backend,
+            path: path.into(),
+            field: field.into(),
+        }
+    }
+}
+
+impl CredentialSource for VaultCredential {
+    fn describe(&self) -> String {
+        format!("vault:{}#{}", self.path, self.field)
+    }
+
+    async fn fetch(&self) -> Result<Credential> {
+        let secret = self.backend.read_field(&self.path, &self.field).await?;
+        let credential = Credential::new(secret);
+        Ok(match self.backend.refresh_interval() {
+            Some(interval) => credential.with_ttl(interval),
+            None => credential,
+        })
+    }
+}
+
+async fn read_json(response: reqwest::Response) -> Result<serde_json::Value> {
+    let status = response.status();
+    let body: serde_json::Value = response
+        .json()
+        .await
+        .map_err(|error| vault_error(format!("invalid response ({status}): {error}")))?;
+    if !status.is_success() {
+        let errors = body["errors"]
+            .as_array()
+            .map(|errors| {
+                errors
+                    .iter()
+                    .filter_map(|error| error.as_str())
+                    .collect::<Vec<_>>()
+                    .join("; ")
+            })
+            .unwrap_or_default();
+        return Err(vault_error(format!("request failed ({status}): {errors}")));
+    }
+    Ok(body)
+}
+
+fn vault_error(message: impl Into<String>) -> LlmError {
+    LlmError::CredentialUnavailable(format!("vault: {}", message.into()))
+}
@@ -0,0 +1,75 @@
+//! Empty completions.
+//!
+//! Providers now and then answer with a success status and nothing in it:
+//! no text, or only whitespace, and no tool call. Responses with no content
+//! at all already fail to parse as an "empty response" error, which routing
+//! retries like any transient failure. Either way the turn would end with
+//! nothing to show, so a model with a nudge retries such a request once with
+//! the nudge appended as a user message, and only then lets the empty
+//! result through. Empty completions are counted per model in
+//! [`LlmMetrics`](crate::llm::metrics::LlmMetrics).
+
+use rig::completion::{self, CompletionError, CompletionRequest};
+use rig::message::{AssistantContent, Message};
+use rig::one_or_many::OneOrMany;
+
+/// What the model is told when its previous response was empty.
+pub const DEFAULT_NUDGE: &str = "Your previous response was empty. Please respond.";
+
+/// Whether a completion has nothing in it: no tool call and no text but
+/// whitespace. Reasoning doesn't count as an answer.
+pub fn is_empty(choice: &OneOrMany<AssistantContent>) -> bool {
+    choice.iter().all(|content| match content {
+        AssistantContent::Text(text) => text.text.trim().is_empty(),
+        AssistantContent::ToolCall(_) | AssistantContent::Image(_) => false,
+        AssistantContent::Reasoning(_) => true,
+    })
+}
+
+/// Whether a routed completion came back empty, as a response or as the
+/// error a response without content parses to.
+pub fn is_empty_result<R>(
+    result: &Result<completion::CompletionResponse<R>, CompletionError>,
+) -> bool {
+    match result {
+        Ok(response) => is_empty(&response.choice),
+        Err(error) => error.to_string().to_lowercase().contains("empty response"),
+    }
+}
+
+/// `request` with `nudge` added as the last user message.
+pub fn nudged(request: &CompletionRequest, nudge: &str) -> CompletionRequest {
+    let mut request = request.clone();
+    request.chat_history.push(Message::user(nudge));
+    request
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rig::message::{Reasoning, Text};
+
+    fn text(text: &str) -> AssistantContent {
+        AssistantContent::Text(Text { text: text.into() })
+    }
+
+    #[test]
+    fn test_is_empty() {
+        assert!(is_empty(&OneOrMany::one(text(" \n"))));
+        assert!(is_empty(&OneOrMany::one(AssistantContent::Reasoning(
+            Reasoning::new("thinking it over")
+        ))));
+        assert!(!is_empty(
+            &OneOrMany::many([text(""), text("Done.")]).unwrap()
+        ));
+
+        let error: Result<completion::CompletionResponse<()>, _> = Err(
+            CompletionError::ResponseError("empty response from Anthropic".into()),
+        );
+        assert!(is_empty_result(&error));
+        let error: Result<completion::CompletionResponse<()>, _> = Err(
+            CompletionError::ResponseError("missing content array".into()),
+        );
+        assert!(!is_empty_result(&error));
+    }
+}
@@ -0,0 +1,258 @@
+//! Fault injection for resilience testing.
+//!
+//! Fallback chains, outage detection, usage alerts and budgets only show
+//! whether they work when a provider misbehaves, which is a bad time to
+//! find out. A [`FaultRule`] makes requests to matching models fail the way
+//! a provider would: rate limited, timed out, with a body that isn't JSON,
+//! or just slow. Injected failures take the place of the provider call, so
+//! everything after it (retries, fallbacks, cooldowns, the outage detector
+//! and metrics) sees them as real.
+//!
+//! Rules come from `[[llm.faults]]` in config and can be replaced at runtime
+//! through the API. A rule with a `rate` below 1 fails that share of
+//! matching requests, spread evenly rather than at random, so a test run is
+//! repeatable.
+
+use crate::error::ProviderApiError;
+
+use chrono::{DateTime, Utc};
+use rig::completion::CompletionError;
+use serde::{Deserialize, Serialize};
+
+use std::sync::{Mutex, MutexGuard};
+use std::time::Duration;
+
+/// How an injected request fails.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum FaultKind {
+    /// A 429 with a rate limit error body.
+    RateLimit,
+    /// Hangs for the rule's delay, then fails as a timed-out request.
+    Timeout,
+    /// A 200 whose body isn't valid JSON.
+    MalformedJson,
+    /// Hangs for the rule's delay, then makes the real request.
+    SlowStream,
+}
+
+impl FaultKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::RateLimit => "rate_limit",
+            Self::Timeout => "timeout",
+            Self::MalformedJson => "malformed_json",
+            Self::SlowStream => "slow_stream",
+        }
+    }
+
+    /// The error a request to `provider` fails with, as the provider's
+    /// client would report it. None for faults that only slow a request.
+    pub fn error(self, provider: &str) -> Option<CompletionError> {
+        match self {
+            Self::RateLimit => {
+                let body = serde_json::json!({
+                    "error": {
+                        "type": "rate_limit_error",
+                        "message": "rate limit exceeded (injected fault)",
+                    }
+                });
+                Some(ProviderApiError::from_response(provider, 429, &body).into())
+            }
+            Self::Timeout => Some(CompletionError::ProviderError(format!(
+                "error sending request to {provider}: operation timeout (injected fault)"
+            ))),
+            Self::MalformedJson => Some(CompletionError::ProviderError(format!(
+                "{provider} response (200 OK) is not valid JSON: \
+                 EOF while parsing an object at line 1 column 27 (injected fault)\n\
+                 Body: {{\"id\": \"msg_injected\", \"con"
+            ))),
+            Self::SlowStream => None,
+        }
+    }
+}
+
+/// Which requests fail, and how.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct FaultRule {
+    /// Full model name (`provider/model`), `provider/*` for all of a
+    /// provider's models, or `*` for every model.
+    pub model: String,
+    pub kind: FaultKind,
+    /// Share of matching requests that fail, 0 to 1.
+    #[serde(default = "default_rate")]
+    pub rate: f64,
+    /// How long `timeout` and `slow_stream` faults hang before going on.
+    #[serde(default = "default_delay_ms")]
+    pub delay_ms: u64,
+    /// When the rule stops applying. None keeps it until it's removed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+fn default_rate() -> f64 {
+    1.0
+}
+
+fn default_delay_ms() -> u64 {
+    10_000
+}
+
+impl FaultRule {
+    pub fn new(model: impl Into<String>, kind: FaultKind) -> Self {
+        Self {
+            model: model.into(),
+            kind,
+            rate: default_rate(),
+            delay_ms: default_delay_ms(),
+            expires_at: None,
+        }
+    }
+
+    fn matches(&self, model: &str) -> bool {
+        if self.model == "*" {
+            return true;
+        }
+        match self.model.strip_suffix("/*") {
+            Some(provider) => model
+                .split_once('/')
+                .is_some_and(|(model_provider, _)| model_provider == provider),
+            None => self.model == model,
+        }
+    }
+
+    pub fn delay(&self) -> Duration {
+        Duration::from_millis(self.delay_ms)
+    }
+}
+
+/// A rule in effect, with how often it has fired.
+#[derive(Debug, Clone, Serialize)]
+pub struct ActiveFault {
+    #[serde(flatten)]
+    pub rule: FaultRule,
+    /// Matching requests seen since the rule was set.
+    pub matched: u64,
+    /// Of those, how many were made to fail or slowed.
+    pub injected: u64,
+}
+
+/// The fault rules in effect. Without rules, every request goes through
+/// untouched.
+#[derive(Debug, Default)]
+pub struct FaultInjector {
+    rules: Mutex<Vec<ActiveFault>>,
+}
+
+impl FaultInjector {
+    pub fn new(rules: Vec<FaultRule>) -> Self {
+        if !rules.is_empty() {
+            tracing::warn!(
+                rules = rules.len(),
+                "fault injection is on, matching LLM requests will fail on purpose"
+            );
+        }
+        let injector = Self::default();
+        injector.set_rules(rules);
+        injector
+    }
+
+    /// Replace every rule. Their counts start over.
+    pub fn set_rules(&self, rules: Vec<FaultRule>) {
+        *self.lock() = rules
+            .into_iter()
+            .map(|mut rule| {
+                rule.rate = rule.rate.clamp(0.0, 1.0);
+                ActiveFault {
+                    rule,
+                    matched: 0,
+                    injected: 0,
+                }
+            })
+            .collect();
+    }
+
+    /// The rules still in effect.
+    pub fn active(&self) -> Vec<ActiveFault> {
+        let mut rules = self.lock();
+        drop_expired(&mut rules);
+        rules.clone()
+    }
+
+    /// The fault to inject into a request to `model`, if any. The first
+    /// matching rule decides; a rule that doesn't fire this time lets the
+    /// request through.
+    pub fn draw(&self, model: &str) -> Option<FaultRule> {
+        let mut rules = self.lock();
+        drop_expired(&mut rules);
+        let fault = rules.iter_mut().find(|fault| fault.rule.matches(model))?;
+        let before = (fault.matched as f64 * fault.rule.rate).floor();
+        fault.matched += 1;
+        if (fault.matched as f64 * fault.rule.rate).floor() <= before {
+            return None;
+        }
+        fault.injected += 1;
+        Some(fault.rule.clone())
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Vec<ActiveFault>> {
+        self.rules.lock().unwrap_or_else(|error| error.into_inner())
+    }
+}
+
+fn drop_expired(rules: &mut Vec<ActiveFault>) {
+    let now = Utc::now();
+    rules.retain(|fault| {
+        fault
+            .rule
+            .expires_at
+            .is_none_or(|expires_at| expires_at > now)
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::routing::{is_rate_limit_error, is_retriable_error};
+
+    #[test]
+    fn test_rules_match_and_fire_at_their_rate() {
+        let injector = FaultInjector::new(vec![
+            FaultRule {
+                rate: 0.5,
+                ..FaultRule::new("anthropic/claude-sonnet-4", FaultKind::RateLimit)
+            },
+            FaultRule::new("openai/*", FaultKind::Timeout),
+            FaultRule {
+                expires_at: Some(Utc::now() - chrono::TimeDelta::minutes(1)),
+                ..FaultRule::new("*", FaultKind::MalformedJson)
+            },
+        ]);
+        assert_eq!(injector.active().len(), 2);
+
+        let fired: Vec<bool> = (0..4)
+            .map(|_| injector.draw("anthropic/claude-sonnet-4").is_some())
+            .collect();
+        assert_eq!(fired, [false, true, false, true]);
+        assert_eq!(
+            injector.draw("openai/gpt-4.1").map(|rule| rule.kind),
+            Some(FaultKind::Timeout)
+        );
+        assert!(injector.draw("anthropic/claude-haiku-4.5").is_none());
+        assert_eq!(injector.active()[0].injected, 2);
+
+        injector.set_rules(Vec::new());
+        assert!(injector.draw("openai/gpt-4.1").is_none());
+    }
+
+    #[test]
+    fn test_injected_errors_classify_like_real_ones() {
+        let rate_limit = FaultKind::RateLimit.error("Anthropic").unwrap().to_string();
+        assert!(is_rate_limit_error(&rate_limit));
+        let timeout = FaultKind::Timeout.error("OpenAI").unwrap().to_string();
+        assert!(is_retriable_error(&timeout) && !is_rate_limit_error(&timeout));
+        assert!(FaultKind::SlowStream.error("OpenAI").is_none());
+    }
+}
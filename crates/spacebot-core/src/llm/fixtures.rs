@@ -0,0 +1,165 @@
+//! Capture provider exchanges as regression fixtures.
+//!
+//! Only built with the `record` feature. When `SPACEBOT_RECORD_FIXTURES` is
+//! set to a case name, every provider exchange is written to
+//! `tests/fixtures/provider_responses/<case name>[-N]/exchange.json`, with
+//! credentials and personal data in every string replaced by placeholders.
+//! The model tests replay each recorded response through the provider's
+//! parser and compare the result with the case's `expected.json`; run them
+//! once with `UPDATE_GOLDEN=1` to write it, then review both files before
+//! committing.
+//!
+//! `SPACEBOT_FIXTURES_DIR` writes somewhere other than this crate's
+//! `tests/fixtures/provider_responses`.
+
+use crate::redact::redact_pii;
+
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Set to a case name to start recording.
+pub const RECORD_ENV: &str = "SPACEBOT_RECORD_FIXTURES";
+
+/// Overrides where cases are written.
+pub const DIR_ENV: &str = "SPACEBOT_FIXTURES_DIR";
+
+/// Serializes writers, so two exchanges finishing at once don't pick the
+/// same case directory.
+static WRITE_LOCK: Mutex<()> = Mutex::new(());
+
+/// Write one exchange as a new fixture case, if recording is on. Failures
+/// are logged; recording never gets in the way of the request.
+pub fn record(
+    provider: &str,
+    model: &str,
+    request_body: &serde_json::Value,
+    status: u16,
+    response_text: &str,
+) {
+    let Some(case_name) = std::env::var(RECORD_ENV)
+        .ok()
+        .filter(|name| !name.trim().is_empty())
+    else {
+        return;
+    };
+    let fixtures_dir = std::env::var_os(DIR_ENV)
+        .map(PathBuf::from)
+        .unwrap_or_else(|| {
+            Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/provider_responses")
+        });
+
+    let exchange = exchange_fixture(provider, model, request_body, status, response_text);
+    match write_case(&fixtures_dir, &case_name, &exchange) {
+        Ok(path) => tracing::info!(fixture = %path.display(), "recorded provider exchange"),
+        Err(error) => tracing::warn!(%error, "failed to record provider exchange"),
+    }
+}
+
+/// The fixture for one exchange, anonymized. A response that isn't JSON is
+/// kept as a string.
+pub fn exchange_fixture(
+    provider: &str,
+    model: &str,
+    request_body: &serde_json::Value,
+    status: u16,
+    response_text: &str,
+) -> serde_json::Value {
+    let mut request = request_body.clone();
+    anonymize(&mut request);
+    let mut response = serde_json::from_str(response_text)
+        .unwrap_or_else(|_| serde_json::Value::String(response_text.to_string()));
+    anonymize(&mut response);
+
+    serde_json::json!({
+        "provider": provider,
+        "model": model,
+        "status": status,
+        "request": request,
+        "response": response,
+    })
+}
+
+/// Redact every string in a JSON value, keys included. Working on values
+/// rather than the serialized text keeps the result valid JSON.
+fn anonymize(value: &mut serde_json::Value) {
+    match value {
+        serde_json::Value::String(text) => *text = redact_pii(text),
+        serde_json::Value::Array(items) => items.iter_mut().for_each(anonymize),
+        serde_json::Value::Object(fields) => {
+            let taken = std::mem::take(fields);
+            for (key, mut field) in taken {
+                anonymize(&mut field);
+                fields.insert(redact_pii(&key), field);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Write to the first free directory of `<case_name>`, `<case_name>-2`, ...
+fn write_case(
+    fixtures_dir: &Path,
+    case_name: &str,
+    exchange: &serde_json::Value,
+) -> std::io::Result<PathBuf> {
+    let case_name = case_name.trim().replace(['/', '\\'], "_");
+    let _guard = WRITE_LOCK
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    let case_dir = (1..)
+        .map(|index| match index {
+            1 => fixtures_dir.join(&case_name),
+            index => fixtures_dir.join(format!("{case_name}-{index}")),
+        })
+        .find(|path| !path.exists())
+        .expect("an unused case directory exists");
+    std::fs::create_dir_all(&case_dir)?;
+
+    let path = case_dir.join("exchange.json");
+    let rendered = serde_json::to_string_pretty(exchange).map_err(std::io::Error::other)?;
+    std::fs::write(&path, format!("{rendered}\n"))?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exchange_is_anonymized() {
+        let request = serde_json::json!({
+            "messages": [{"role": "user", "content": "mail me at jane@example.com"}],
+            "api_key": "sk-ant-REDACTED",
+        });
+        let exchange = exchange_fixture(
+            "anthropic",
+            "anthropic/claude-test",
+            &request,
+            200,
+            r#"{"content": [{"type": "text", "text": "call +1 415-555-0100"}]}"#,
+        );
+
+        assert_eq!(
+            exchange["request"]["messages"][0]["content"],
+            "mail me at [EMAIL]"
+        );
+        assert_eq!(exchange["request"]["api_key"], "[REDACTED]");
+        assert_eq!(exchange["response"]["content"][0]["text"], "call [PHONE]");
+
+        let exchange = exchange_fixture("openai", "openai/x", &request, 502, "Bad Gateway");
+        assert_eq!(exchange["response"], "Bad Gateway");
+    }
+
+    #[test]
+    fn test_cases_get_unique_directories() {
+        let dir = std::env::temp_dir().join(format!("spacebot-fixtures-{}", uuid::Uuid::new_v4()));
+        let exchange = serde_json::json!({"status": 200});
+
+        let first = write_case(&dir, "tool use", &exchange).unwrap();
+        let second = write_case(&dir, "tool use", &exchange).unwrap();
+
+        assert!(first.ends_with("tool use/exchange.json"));
+        assert!(second.ends_with("tool use-2/exchange.json"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
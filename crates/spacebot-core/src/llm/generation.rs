@@ -0,0 +1,113 @@
+//! Named generation profiles.
+//!
+//! Instead of temperatures scattered through config, an agent picks a
+//! profile by name: `creative`, `precise` and `deterministic` are built in,
+//! and config can redefine them or add more. A profile is a temperature and
+//! `top_p` sent to every provider, plus extra body fields per provider for
+//! the knobs only some of them have (`frequency_penalty`, `top_k`, ...).
+//! The profile's temperature and `top_p` don't replace ones the request
+//! already sets, such as the temperature [sampling](crate::llm::sampling)
+//! draws at.
+
+use serde::Deserialize;
+
+use std::collections::HashMap;
+
+/// Profiles every agent can use without configuring them.
+pub const BUILTIN_PROFILES: &[&str] = &["creative", "precise", "deterministic"];
+
+/// Sampling parameters sent with each request.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct GenerationProfile {
+    #[serde(default)]
+    pub temperature: Option<f64>,
+    #[serde(default)]
+    pub top_p: Option<f64>,
+    /// Extra body fields per provider ID, e.g. `top_k` for a vLLM server.
+    /// They override the fields above for that provider.
+    #[serde(default)]
+    pub providers: HashMap<String, serde_json::Map<String, serde_json::Value>>,
+}
+
+impl GenerationProfile {
+    /// A built-in profile by name.
+    pub fn builtin(name: &str) -> Option<Self> {
+        let temperature = match name {
+            "creative" => 1.0,
+            "precise" => 0.2,
+            "deterministic" => 0.0,
+            _ => return None,
+        };
+        Some(Self {
+            temperature: Some(temperature),
+            ..Self::default()
+        })
+    }
+
+    /// Add this profile to a request body for `provider_id`, without
+    /// replacing fields the request set itself.
+    pub fn apply(&self, provider_id: &str, body: &mut serde_json::Value) {
+        let Some(body) = body.as_object_mut() else {
+            return;
+        };
+        if let Some(temperature) = self.temperature {
+            body.entry("temperature")
+                .or_insert(serde_json::json!(temperature));
+        }
+        if let Some(top_p) = self.top_p {
+            body.entry("top_p").or_insert(serde_json::json!(top_p));
+        }
+        if let Some(params) = self.providers.get(provider_id) {
+            for (key, value) in params {
+                body.insert(key.clone(), value.clone());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_apply_keeps_request_fields() {
+        let mut profile = GenerationProfile::builtin("precise").unwrap();
+        profile.top_p = Some(0.9);
+        profile.providers.insert(
+            "openrouter".into(),
+            serde_json::json!({"top_k": 40, "top_p": 0.5})
+                .as_object()
+                .unwrap()
+                .clone(),
+        );
+
+        let mut body = serde_json::json!({"model": "m"});
+        profile.apply("openai", &mut body);
+        assert_eq!(
+            body,
+            serde_json::json!({"model": "m", "temperature": 0.2, "top_p": 0.9})
+        );
+
+        let mut body = serde_json::json!({"model": "m", "temperature": 0.8});
+        profile.apply("openrouter", &mut body);
+        assert_eq!(
+            body,
+            serde_json::json!({"model": "m", "temperature": 0.8, "top_p": 0.5, "top_k": 40})
+        );
+    }
+
+    #[test]
+    fn test_builtin_profiles() {
+        for name in BUILTIN_PROFILES {
+            assert!(GenerationProfile::builtin(name).is_some(), "{name}");
+        }
+        assert_eq!(
+            GenerationProfile::builtin("deterministic")
+                .unwrap()
+                .temperature,
+            Some(0.0)
+        );
+        assert!(GenerationProfile::builtin("wild").is_none());
+    }
+}
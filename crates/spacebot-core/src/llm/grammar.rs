@@ -0,0 +1,350 @@
+//! Grammar-constrained tool calls for self-hosted models.
+//!
+//! Small local models get tool calls almost right: a missing brace, an
+//! unquoted key, a number where the schema wants a string. llama.cpp and
+//! vLLM can constrain decoding to a GBNF grammar, so for providers listed in
+//! `llm.grammar_providers` a request with tools carries a grammar built from
+//! the tools' JSON schemas instead of the tools themselves. The model either
+//! answers in plain text or writes a single `{"name": ..., "arguments": ...}`
+//! object whose arguments match that tool's schema, which
+//! [`parse_tool_call`] turns back into a tool call.
+//!
+//! Schemas are covered as far as tool arguments use them: objects, arrays,
+//! the scalar types, `enum`, `const`, `anyOf`/`oneOf` and nullable type
+//! lists. Anything else (`$ref`, `allOf`) accepts any JSON value.
+
+use rig::completion::ToolDefinition;
+use serde_json::Value;
+
+/// Added to the system prompt, since the tools are described there rather
+/// than sent in the request.
+const TOOL_CALL_INSTRUCTIONS: &str = "To use a tool, reply with only a JSON object naming the tool and its arguments, like {\"name\": \"tool_name\", \"arguments\": {...}}, and nothing else. To answer without a tool, reply in plain text that doesn't start with '{'. The tools are:";
+
+/// Rules every grammar shares: JSON scalars, and any JSON value for schemas
+/// the generator doesn't constrain.
+const JSON_RULES: &str = r#"ws ::= [ \t\n]*
+string ::= "\"" ( [^"\\\x7F\x00-\x1F] | "\\" ( ["\\/bfnrt] | "u" [0-9a-fA-F] [0-9a-fA-F] [0-9a-fA-F] [0-9a-fA-F] ) )* "\""
+integer ::= "-"? ( [0-9] | [1-9] [0-9]* )
+number ::= integer ( "." [0-9]+ )? ( [eE] [-+]? [0-9]+ )?
+boolean ::= "true" | "false"
+null ::= "null"
+value ::= object | array | string | number | boolean | null
+object ::= "{" ws ( string ws ":" ws value ( ws "," ws string ws ":" ws value )* )? ws "}"
+array ::= "[" ws ( value ( ws "," ws value )* )? ws "]"
+"#;
+
+/// The request field a server reads the grammar from.
+pub(crate) fn grammar_field(vllm: bool) -> &'static str {
+    if vllm { "guided_grammar" } else { "grammar" }
+}
+
+/// Swap the tools in an OpenAI-style chat completions body for a grammar
+/// that only admits plain text or a valid call to one of them, and describe
+/// the tools in the system prompt instead. Servers reject requests that set
+/// both tools and a grammar.
+pub(crate) fn constrain(body: &mut Value, tools: &[ToolDefinition], field: &str) {
+    if tools.is_empty() {
+        return;
+    }
+    if let Some(body) = body.as_object_mut() {
+        body.remove("tools");
+        body.remove("tool_choice");
+    }
+    body[field] = Value::String(tool_grammar(tools));
+
+    let mut instructions = TOOL_CALL_INSTRUCTIONS.to_string();
+    for tool in tools {
+        instructions.push_str(&format!(
+            "\n\n{}: {}\nArguments schema: {}",
+            tool.name, tool.description, tool.parameters
+        ));
+    }
+    let Some(messages) = body["messages"].as_array_mut() else {
+        return;
+    };
+    match messages.first_mut() {
+        Some(system) if system["role"] == "system" => {
+            let preamble = system["content"].as_str().unwrap_or_default();
+            system["content"] = Value::String(format!("{preamble}\n\n{instructions}"));
+        }
+        _ => messages.insert(
+            0,
+            serde_json::json!({"role": "system", "content": instructions}),
+        ),
+    }
+}
+
+/// A GBNF grammar admitting plain text, or a call to one of `tools` with
+/// arguments matching its schema.
+pub(crate) fn tool_grammar(tools: &[ToolDefinition]) -> String {
+    let mut grammar = Grammar::default();
+    let calls: Vec<String> = tools
+        .iter()
+        .map(|tool| {
+            let arguments = grammar.schema(&format!("{}-arguments", tool.name), &tool.parameters);
+            grammar.add(
+                &format!("{}-call", tool.name),
+                format!(
+                    r#"{} ws "," ws "\"arguments\"" ws ":" ws {arguments}"#,
+                    literal(&Value::String(tool.name.clone()))
+                ),
+            )
+        })
+        .collect();
+
+    let mut out = format!(
+        "root ::= call | text\ncall ::= \"{{\" ws \"\\\"name\\\"\" ws \":\" ws ( {} ) ws \"}}\"\ntext ::= [^{{ \\t\\r\\n] [^\\x00]*\n",
+        calls.join(" | ")
+    );
+    for (name, definition) in &grammar.rules {
+        out.push_str(&format!("{name} ::= {definition}\n"));
+    }
+    out.push_str(JSON_RULES);
+    out
+}
+
+/// The tool call in a constrained reply: a JSON object naming one of
+/// `tools`, with its arguments.
+pub(crate) fn parse_tool_call(text: &str, tools: &[ToolDefinition]) -> Option<(String, Value)> {
+    let Value::Object(mut call) = serde_json::from_str(text.trim()).ok()? else {
+        return None;
+    };
+    let name = call.get("name")?.as_str()?.to_string();
+    if !tools.iter().any(|tool| tool.name == name) {
+        return None;
+    }
+    let arguments = call.remove("arguments").unwrap_or(Value::Null);
+    Some((name, arguments))
+}
+
+/// Rules generated from schemas, in the order they were added.
+#[derive(Default)]
+struct Grammar {
+    rules: Vec<(String, String)>,
+}
+
+impl Grammar {
+    /// Add a rule under a name derived from `hint`, returning the name.
+    fn add(&mut self, hint: &str, definition: String) -> String {
+        let base: String = hint
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+            .collect();
+        let mut name = base.clone();
+        let mut suffix = 1;
+        while self.rules.iter().any(|(existing, _)| *existing == name) {
+            suffix += 1;
+            name = format!("{base}-{suffix}");
+        }
+        self.rules.push((name.clone(), definition));
+        name
+    }
+
+    /// An expression matching JSON that satisfies `schema`.
+    fn schema(&mut self, hint: &str, schema: &Value) -> String {
+        if let Some(value) = schema.get("const") {
+            return literal(value);
+        }
+        if let Some(values) = schema.get("enum").and_then(Value::as_array) {
+            let choices: Vec<String> = values.iter().map(literal).collect();
+            return format!("( {} )", choices.join(" | "));
+        }
+        if let Some(schemas) = schema
+            .get("anyOf")
+            .or_else(|| schema.get("oneOf"))
+            .and_then(Value::as_array)
+        {
+            let choices: Vec<String> = schemas
+                .iter()
+                .enumerate()
+                .map(|(index, schema)| self.schema(&format!("{hint}-{index}"), schema))
+                .collect();
+            return format!("( {} )", choices.join(" | "));
+        }
+
+        match schema.get("type") {
+            Some(Value::String(kind)) => self.typed(hint, kind, schema),
+            Some(Value::Array(kinds)) => {
+                let choices: Vec<String> = kinds
+                    .iter()
+                    .filter_map(Value::as_str)
+                    .map(|kind| self.typed(hint, kind, schema))
+                    .collect();
+                format!("( {} )", choices.join(" | "))
+            }
+            _ if schema.get("properties").is_some() => self.typed(hint, "object", schema),
+            _ => "value".into(),
+        }
+    }
+
+    fn typed(&mut self, hint: &str, kind: &str, schema: &Value) -> String {
+        match kind {
+            "string" | "integer" | "number" | "boolean" | "null" => kind.into(),
+            "array" => match schema.get("items") {
+                Some(items) => {
+                    let item = self.schema(&format!("{hint}-item"), items);
+                    self.add(
+                        hint,
+                        format!(r#""[" ws ( {item} ( ws "," ws {item} )* )? ws "]""#),
+                    )
+                }
+                None => "array".into(),
+            },
+            "object" => self.object(hint, schema),
+            _ => "value".into(),
+        }
+    }
+
+    /// Required properties first, then optional ones, each in the schema's
+    /// key order. Optional ones may each be left out.
+    fn object(&mut self, hint: &str, schema: &Value) -> String {
+        let Some(properties) = schema.get("properties").and_then(Value::as_object) else {
+            return "object".into();
+        };
+        let required: Vec<&str> = schema
+            .get("required")
+            .and_then(Value::as_array)
+            .map(|names| names.iter().filter_map(Value::as_str).collect())
+            .unwrap_or_default();
+
+        let mut mandatory = Vec::new();
+        let mut optional = Vec::new();
+        for (name, property) in properties {
+            let value = self.schema(&format!("{hint}-{name}"), property);
+            let pair = format!(
+                r#"{} ws ":" ws {value}"#,
+                literal(&Value::String(name.clone()))
+            );
+            if required.contains(&name.as_str()) {
+                mandatory.push(pair);
+            } else {
+                optional.push(pair);
+            }
+        }
+
+        let rest = |pairs: &[String]| -> String {
+            pairs
+                .iter()
+                .map(|pair| format!(r#" ( ws "," ws {pair} )?"#))
+                .collect()
+        };
+        let body = if mandatory.is_empty() {
+            if optional.is_empty() {
+                String::new()
+            } else {
+                let starts: Vec<String> = (0..optional.len())
+                    .map(|first| format!("{}{}", optional[first], rest(&optional[first + 1..])))
+                    .collect();
+                format!("( {} )?", starts.join(" | "))
+            }
+        } else {
+            format!("{}{}", mandatory.join(r#" ws "," ws "#), rest(&optional))
+        };
+        self.add(hint, format!(r#""{{" ws {body} ws "}}""#))
+    }
+}
+
+/// A GBNF string literal matching `value` serialized as JSON.
+fn literal(value: &Value) -> String {
+    let json = value.to_string();
+    let mut out = String::with_capacity(json.len() + 2);
+    out.push('"');
+    for c in json.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            _ => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tool(name: &str, parameters: Value) -> ToolDefinition {
+        ToolDefinition {
+            name: name.into(),
+            description: format!("The {name} tool."),
+            parameters,
+        }
+    }
+
+    #[test]
+    fn test_tool_grammar() {
+        let tools = [
+            tool(
+                "memory_save",
+                serde_json::json!({
+                    "type": "object",
+                    "properties": {
+                        "content": {"type": "string"},
+                        "importance": {"type": "number"},
+                        "kind": {"enum": ["fact", "preference"]},
+                        "tags": {"type": "array", "items": {"type": "string"}}
+                    },
+                    "required": ["content"]
+                }),
+            ),
+            tool(
+                "skip",
+                serde_json::json!({"type": "object", "properties": {"reason": {"type": ["string", "null"]}}}),
+            ),
+        ];
+        let grammar = tool_grammar(&tools);
+
+        assert!(grammar.starts_with(
+            r#"root ::= call | text
+call ::= "{" ws "\"name\"" ws ":" ws ( memory-save-call | skip-call ) ws "}""#
+        ));
+        assert!(grammar.contains(
+            r#"memory-save-call ::= "\"memory_save\"" ws "," ws "\"arguments\"" ws ":" ws memory-save-arguments"#
+        ));
+        // Required first; optional properties may each be left out.
+        assert!(grammar.contains(
+            r#"memory-save-arguments ::= "{" ws "\"content\"" ws ":" ws string ( ws "," ws "\"importance\"" ws ":" ws number )? ( ws "," ws "\"kind\"" ws ":" ws ( "\"fact\"" | "\"preference\"" ) )? ( ws "," ws "\"tags\"" ws ":" ws memory-save-arguments-tags )? ws "}""#
+        ));
+        assert!(grammar.contains(
+            r#"memory-save-arguments-tags ::= "[" ws ( string ( ws "," ws string )* )? ws "]""#
+        ));
+        // With nothing required, the object may be empty.
+        assert!(grammar.contains(
+            r#"skip-arguments ::= "{" ws ( "\"reason\"" ws ":" ws ( string | null ) )? ws "}""#
+        ));
+    }
+
+    #[test]
+    fn test_constrain_moves_tools_into_the_prompt() {
+        let tools = [tool("reply", serde_json::json!({"type": "object"}))];
+        let mut body = serde_json::json!({
+            "model": "qwen",
+            "messages": [{"role": "user", "content": "hi"}],
+            "tools": [{"type": "function"}],
+        });
+        constrain(&mut body, &tools, grammar_field(false));
+
+        assert!(body.get("tools").is_none());
+        assert!(body["grammar"].as_str().unwrap().contains("reply-call"));
+        let system = body["messages"][0]["content"].as_str().unwrap();
+        assert!(system.starts_with(TOOL_CALL_INSTRUCTIONS));
+        assert!(system.contains("reply: The reply tool."));
+        assert_eq!(body["messages"][1]["content"], "hi");
+    }
+
+    #[test]
+    fn test_parse_tool_call() {
+        let tools = [tool("reply", serde_json::json!({"type": "object"}))];
+        assert_eq!(
+            parse_tool_call(
+                r#" {"name": "reply", "arguments": {"content": "hi"}} "#,
+                &tools
+            ),
+            Some(("reply".into(), serde_json::json!({"content": "hi"})))
+        );
+        assert_eq!(parse_tool_call(r#"{"name": "shell"}"#, &tools), None);
+        assert_eq!(parse_tool_call("Sure, replying now.", &tools), None);
+    }
+}
@@ -0,0 +1,223 @@
+//! Health probes for self-hosted inference servers.
+//!
+//! A provider with a base URL override points at a server someone runs
+//! themselves — Ollama on a workstation, vLLM on a GPU box — which can be
+//! asleep, restarting, or buried under a queue. The manager probes those
+//! servers periodically and routing skips a provider while its last probe
+//! failed, instead of spending the retry budget discovering the same thing.
+
+use crate::llm::signing::RequestSigner;
+
+use serde::Serialize;
+
+use std::time::Duration;
+
+/// Upper bound on a single probe request.
+const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Probe cadence and thresholds.
+#[derive(Debug, Clone)]
+pub struct HealthCheckConfig {
+    /// Seconds between probes.
+    pub interval_secs: u64,
+    /// Mark a server unhealthy when more requests than this are waiting in
+    /// its queue. Only servers that export queue metrics (vLLM) are checked.
+    pub max_queue_depth: Option<u64>,
+}
+
+impl Default for HealthCheckConfig {
+    fn default() -> Self {
+        Self {
+            interval_secs: 30,
+            max_queue_depth: None,
+        }
+    }
+}
+
+/// Result of the last probe of a self-hosted provider.
+#[derive(Debug, Clone, Serialize)]
+pub struct ProviderHealth {
+    pub healthy: bool,
+    /// Why the probe failed, or a short summary when it passed.
+    pub detail: String,
+    /// Models the server currently has in memory, when it reports them.
+    pub loaded_models: Vec<String>,
+    /// Requests waiting in the server's queue, when it reports them.
+    pub queue_depth: Option<u64>,
+    pub checked_at: chrono::DateTime<chrono::Utc>,
+}
+
+impl ProviderHealth {
+    fn unhealthy(detail: impl Into<String>) -> Self {
+        Self {
+            healthy: false,
+            detail: detail.into(),
+            loaded_models: Vec::new(),
+            queue_depth: None,
+            checked_at: chrono::Utc::now(),
+        }
+    }
+}
+
+/// Probe one server. Ollama is asked which models it has loaded; anything
+/// else is treated as a vLLM-style OpenAI-compatible server and must answer
+/// `/health`, with its queue read from `/metrics` if exposed.
+pub async fn probe(
+    client: &reqwest::Client,
+    signer: Option<&dyn RequestSigner>,
+    provider: &str,
+    base_url: &str,
+    config: &HealthCheckConfig,
+) -> ProviderHealth {
+    let base_url = base_url.trim_end_matches('/');
+    if provider == "ollama" {
+        probe_ollama(client, signer, base_url).await
+    } else {
+        probe_openai_compatible(client, signer, base_url, config.max_queue_depth).await
+    }
+}
+
+async fn probe_ollama(
+    client: &reqwest::Client,
+    signer: Option<&dyn RequestSigner>,
+    base_url: &str,
+) -> ProviderHealth {
+    let body = match get(client, signer, &format!("{base_url}/api/ps")).await {
+        Ok(body) => body,
+        Err(error) => return ProviderHealth::unhealthy(error),
+    };
+    let loaded_models = parse_ollama_loaded_models(&body);
+    let detail = if loaded_models.is_empty() {
+        // Ollama loads on demand, so an idle server is still usable.
+        "reachable, no model loaded".to_string()
+    } else {
+        format!("reachable, {} loaded", loaded_models.join(", "))
+    };
+
+    ProviderHealth {
+        healthy: true,
+        detail,
+        loaded_models,
+        queue_depth: None,
+        checked_at: chrono::Utc::now(),
+    }
+}
+
+async fn probe_openai_compatible(
+    client: &reqwest::Client,
+    signer: Option<&dyn RequestSigner>,
+    base_url: &str,
+    max_queue_depth: Option<u64>,
+) -> ProviderHealth {
+    if let Err(error) = get(client, signer, &format!("{base_url}/health")).await {
+        return ProviderHealth::unhealthy(error);
+    }
+
+    let queue_depth = get(client, signer, &format!("{base_url}/metrics"))
+        .await
+        .ok()
+        .and_then(|metrics| parse_queue_depth(&metrics));
+    let over_limit = queue_depth
+        .zip(max_queue_depth)
+        .filter(|(depth, max)| depth > max);
+    if let Some((depth, max)) = over_limit {
+        return ProviderHealth {
+            queue_depth,
+            ..ProviderHealth::unhealthy(format!("{depth} requests queued (limit {max})"))
+        };
+    }
+
+    ProviderHealth {
+        healthy: true,
+        detail: match queue_depth {
+            Some(depth) => format!("reachable, {depth} requests queued"),
+            None => "reachable".to_string(),
+        },
+        loaded_models: Vec::new(),
+        queue_depth,
+        checked_at: chrono::Utc::now(),
+    }
+}
+
+/// GET a URL and return the body, or a description of why it failed.
+async fn get(
+    client: &reqwest::Client,
+    signer: Option<&dyn RequestSigner>,
+    url: &str,
+) -> std::result::Result<String, String> {
+    let request = client.get(url).timeout(PROBE_TIMEOUT);
+    let response = crate::llm::signing::send(request, signer)
+        .await
+        .map_err(|error| format!("unreachable: {error}"))?;
+    let status = response.status();
+    if !status.is_success() {
+        return Err(format!("{url} returned {status}"));
+    }
+    response
+        .text()
+        .await
+        .map_err(|error| format!("failed to read {url}: {error}"))
+}
+
+/// Model names from an Ollama `/api/ps` response.
+fn parse_ollama_loaded_models(body: &str) -> Vec<String> {
+    serde_json::from_str::<serde_json::Value>(body)
+        .ok()
+        .and_then(|value| {
+            value["models"].as_array().map(|models| {
+                models
+                    .iter()
+                    .filter_map(|model| model["name"].as_str().map(String::from))
+                    .collect()
+            })
+        })
+        .unwrap_or_default()
+}
+
+/// Sum of `vllm:num_requests_waiting` across a Prometheus exposition.
+fn parse_queue_depth(metrics: &str) -> Option<u64> {
+    let mut total = None;
+    for line in metrics.lines() {
+        let Some(rest) = line.strip_prefix("vllm:num_requests_waiting") else {
+            continue;
+        };
+        // Skip label sets to reach the sample value.
+        let value = rest.rsplit_once('}').map_or(rest, |(_, value)| value);
+        if let Some(value) = value
+            .split_whitespace()
+            .next()
+            .and_then(|value| value.parse::<f64>().ok())
+        {
+            *total.get_or_insert(0) += value as u64;
+        }
+    }
+    total
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_queue_depth() {
+        let metrics = "\
+# HELP vllm:num_requests_waiting Number of requests waiting to be processed.
+# TYPE vllm:num_requests_waiting gauge
+vllm:num_requests_waiting{model_name=\"llama\"} 3.0
+vllm:num_requests_waiting{model_name=\"qwen\"} 2.0
+vllm:num_requests_running{model_name=\"llama\"} 8.0
+";
+        assert_eq!(parse_queue_depth(metrics), Some(5));
+        assert_eq!(parse_queue_depth("process_cpu_seconds_total 1.5"), None);
+    }
+
+    #[test]
+    fn test_parse_ollama_loaded_models() {
+        let body = r#"{"models":[{"name":"llama3.2:latest","size":1},{"name":"qwen2.5:7b"}]}"#;
+        assert_eq!(
+            parse_ollama_loaded_models(body),
+            vec!["llama3.2:latest", "qwen2.5:7b"]
+        );
+        assert!(parse_ollama_loaded_models(r#"{"models":[]}"#).is_empty());
+    }
+}
@@ -0,0 +1,308 @@
+//! Periodic checks of provider API keys.
+//!
+//! A revoked key, a spent credit balance or a key past its rotation date
+//! otherwise shows up as a burst of failed turns. The manager checks each
+//! hosted provider's keys, the secondary as well as the one in use, with a
+//! cheap authenticated call (listing models, or OpenRouter's key endpoint,
+//! which also reports the key's spending limit), and publishes
+//! [`Event::KeyHealthChanged`] whenever a key's status changes so it can be
+//! alerted on while requests still go through.
+//!
+//! [`Event::KeyHealthChanged`]: crate::events::Event::KeyHealthChanged
+
+use crate::llm::credentials::KeySlot;
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use std::collections::HashMap;
+use std::sync::{Mutex, MutexGuard};
+use std::time::Duration;
+
+/// How long a key check may take before the provider counts as unreachable.
+pub(crate) const CHECK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How often keys are checked and when they're flagged.
+#[derive(Debug, Clone)]
+pub struct KeyCheckConfig {
+    /// Seconds between checks. 0 turns them off.
+    pub interval_secs: u64,
+    /// Share of a key's spending limit used at which it's flagged.
+    pub quota_warn_at: f64,
+    /// Days before a key's expiry at which it's flagged.
+    pub expiry_warn_days: u32,
+    /// When keys stop working, by provider id, or `<provider>:secondary`
+    /// for a secondary key. Providers don't report this, so it comes from
+    /// whoever issued the key.
+    pub expires: HashMap<String, DateTime<Utc>>,
+    /// Where the application delivers status changes, as `adapter:target`.
+    /// The manager itself only publishes events.
+    pub delivery_targets: Vec<String>,
+}
+
+impl Default for KeyCheckConfig {
+    fn default() -> Self {
+        Self {
+            interval_secs: 3600,
+            quota_warn_at: 0.9,
+            expiry_warn_days: 14,
+            expires: HashMap::new(),
+            delivery_targets: Vec::new(),
+        }
+    }
+}
+
+/// How a key fared in its last check.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum KeyStatus {
+    Ok,
+    /// Works, but nearing its spending limit or expiry, or was rate limited.
+    Warning,
+    /// Rejected, out of credit, or expired. Requests with it will fail.
+    Failing,
+    /// The provider couldn't be reached or answered with a server error.
+    Unknown,
+}
+
+impl KeyStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Ok => "ok",
+            Self::Warning => "warning",
+            Self::Failing => "failing",
+            Self::Unknown => "unknown",
+        }
+    }
+}
+
+/// The last check of one key.
+#[derive(Debug, Clone, Serialize)]
+pub struct KeyHealth {
+    pub provider: String,
+    pub slot: KeySlot,
+    pub status: KeyStatus,
+    pub detail: String,
+    /// Share of the key's spending limit used, for providers that report
+    /// one.
+    pub quota_used: Option<f64>,
+    /// From `expires` in the config.
+    pub expires_at: Option<DateTime<Utc>>,
+    pub checked_at: DateTime<Utc>,
+}
+
+impl KeyHealth {
+    pub(crate) fn new(
+        provider: &str,
+        slot: KeySlot,
+        status: KeyStatus,
+        detail: impl Into<String>,
+    ) -> Self {
+        Self {
+            provider: provider.to_string(),
+            slot,
+            status,
+            detail: detail.into(),
+            quota_used: None,
+            expires_at: None,
+            checked_at: Utc::now(),
+        }
+    }
+
+    /// `provider`, or `provider:secondary` for a secondary key, as in the
+    /// config's `expires`.
+    pub fn key_name(&self) -> String {
+        self.slot.cache_key(&self.provider)
+    }
+}
+
+/// The latest round of checks, and the status last announced for each key.
+/// A key that couldn't be checked keeps its announced status, so a flaky
+/// connection doesn't alert twice.
+#[derive(Debug, Default)]
+pub(crate) struct KeyHealthLog {
+    inner: Mutex<KeyHealthState>,
+}
+
+#[derive(Debug, Default)]
+struct KeyHealthState {
+    latest: Vec<KeyHealth>,
+    announced: HashMap<String, KeyStatus>,
+}
+
+impl KeyHealthLog {
+    /// Replace the latest round with `results`, returning the keys whose
+    /// status changed since it was last announced. Keys start out `Ok`.
+    pub(crate) fn record(&self, results: Vec<KeyHealth>) -> Vec<KeyHealth> {
+        let mut state = self.lock();
+        let mut changed = Vec::new();
+        for health in &results {
+            if health.status == KeyStatus::Unknown {
+                continue;
+            }
+            let previous = state
+                .announced
+                .insert(health.key_name(), health.status)
+                .unwrap_or(KeyStatus::Ok);
+            if previous != health.status {
+                changed.push(health.clone());
+            }
+        }
+        state.latest = results;
+        changed
+    }
+
+    pub(crate) fn latest(&self) -> Vec<KeyHealth> {
+        self.lock().latest.clone()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, KeyHealthState> {
+        self.inner.lock().unwrap_or_else(|error| error.into_inner())
+    }
+}
+
+/// The path a provider's key is checked against, from its origin. None for
+/// providers without a known cheap authenticated endpoint.
+pub fn check_path(provider: &str) -> Option<&'static str> {
+    match provider {
+        "anthropic" | "openai" | "together" | "xai" | "mistral" => Some("/v1/models"),
+        "openrouter" => Some("/api/v1/key"),
+        "groq" => Some("/openai/v1/models"),
+        "fireworks" => Some("/inference/v1/models"),
+        "deepseek" => Some("/models"),
+        "opencode-zen" => Some("/zen/v1/models"),
+        _ => None,
+    }
+}
+
+/// Judge a key from the status and body its check got back, and its expiry.
+pub(crate) fn assess(
+    mut health: KeyHealth,
+    status: u16,
+    body: &serde_json::Value,
+    config: &KeyCheckConfig,
+) -> KeyHealth {
+    let (key_status, detail) = match status {
+        200..=299 => (KeyStatus::Ok, "valid".to_string()),
+        401 | 403 => (KeyStatus::Failing, format!("rejected ({status})")),
+        402 => (KeyStatus::Failing, "out of credit (402)".to_string()),
+        429 => (
+            KeyStatus::Warning,
+            "rate limited during the check".to_string(),
+        ),
+        _ => (KeyStatus::Unknown, format!("check failed ({status})")),
+    };
+    health.status = key_status;
+    health.detail = detail;
+    if key_status != KeyStatus::Ok {
+        return with_expiry(health, config);
+    }
+
+    // OpenRouter reports the key's spending limit, when it has one.
+    let data = &body["data"];
+    if let (Some(limit), Some(usage)) = (data["limit"].as_f64(), data["usage"].as_f64())
+        && limit > 0.0
+    {
+        let used = usage / limit;
+        health.quota_used = Some(used);
+        if used >= 1.0 {
+            health.status = KeyStatus::Failing;
+            health.detail = format!("spending limit of ${limit:.2} reached");
+        } else if used >= config.quota_warn_at {
+            health.status = KeyStatus::Warning;
+            health.detail = format!(
+                "{:.0}% of its ${limit:.2} spending limit used",
+                used * 100.0
+            );
+        }
+    }
+    with_expiry(health, config)
+}
+
+fn with_expiry(mut health: KeyHealth, config: &KeyCheckConfig) -> KeyHealth {
+    let Some(expires_at) = config.expires.get(&health.key_name()).copied() else {
+        return health;
+    };
+    health.expires_at = Some(expires_at);
+    let left = expires_at - health.checked_at;
+    if left <= chrono::TimeDelta::zero() {
+        health.status = KeyStatus::Failing;
+        health.detail = format!("expired on {}", expires_at.format("%Y-%m-%d"));
+    } else if left <= chrono::TimeDelta::days(i64::from(config.expiry_warn_days))
+        && health.status == KeyStatus::Ok
+    {
+        health.status = KeyStatus::Warning;
+        health.detail = format!(
+            "expires on {}, in {} days",
+            expires_at.format("%Y-%m-%d"),
+            left.num_days()
+        );
+    }
+    health
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(status: u16, body: serde_json::Value, config: &KeyCheckConfig) -> KeyHealth {
+        let health = KeyHealth::new("openrouter", KeySlot::Primary, KeyStatus::Unknown, "");
+        assess(health, status, &body, config)
+    }
+
+    #[test]
+    fn test_keys_are_flagged_before_they_fail() {
+        let config = KeyCheckConfig::default();
+        let quota = |usage: f64| serde_json::json!({"data": {"limit": 100.0, "usage": usage}});
+
+        assert_eq!(check(200, quota(10.0), &config).status, KeyStatus::Ok);
+        let nearly_spent = check(200, quota(95.0), &config);
+        assert_eq!(nearly_spent.status, KeyStatus::Warning);
+        assert_eq!(nearly_spent.quota_used, Some(0.95));
+        assert_eq!(check(200, quota(100.0), &config).status, KeyStatus::Failing);
+        let unlimited = serde_json::json!({"data": {"limit": null, "usage": 500.0}});
+        assert_eq!(check(200, unlimited, &config).status, KeyStatus::Ok);
+
+        assert_eq!(
+            check(401, serde_json::Value::Null, &config).status,
+            KeyStatus::Failing
+        );
+        assert_eq!(
+            check(503, serde_json::Value::Null, &config).status,
+            KeyStatus::Unknown
+        );
+
+        let expiring = KeyCheckConfig {
+            expires: HashMap::from([(
+                "openrouter".to_string(),
+                Utc::now() + chrono::TimeDelta::days(3),
+            )]),
+            ..KeyCheckConfig::default()
+        };
+        assert_eq!(
+            check(200, quota(10.0), &expiring).status,
+            KeyStatus::Warning
+        );
+        let expired = KeyCheckConfig {
+            expires: HashMap::from([(
+                "openrouter".to_string(),
+                Utc::now() - chrono::TimeDelta::days(1),
+            )]),
+            ..KeyCheckConfig::default()
+        };
+        assert_eq!(check(200, quota(10.0), &expired).status, KeyStatus::Failing);
+    }
+
+    #[test]
+    fn test_only_status_changes_are_announced() {
+        let log = KeyHealthLog::default();
+        let key = |status| KeyHealth::new("openai", KeySlot::Primary, status, "");
+
+        assert!(log.record(vec![key(KeyStatus::Ok)]).is_empty());
+        assert_eq!(log.record(vec![key(KeyStatus::Failing)]).len(), 1);
+        assert!(log.record(vec![key(KeyStatus::Unknown)]).is_empty());
+        assert_eq!(log.latest()[0].status, KeyStatus::Unknown);
+        assert!(log.record(vec![key(KeyStatus::Failing)]).is_empty());
+        assert_eq!(log.record(vec![key(KeyStatus::Ok)]).len(), 1);
+    }
+}
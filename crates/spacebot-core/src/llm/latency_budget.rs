@@ -0,0 +1,143 @@
+//! Turn latency budgets.
+//!
+//! Someone waiting in chat notices a slow answer long before they notice a
+//! thin one. A [`LatencyBudget`] is a soft time limit on one turn: the
+//! model is told about it up front, and once most of it has passed the
+//! turn hurries. Each completion may make only a few tool calls, and a
+//! model built [`with_latency_budget`](crate::llm::model::SpacebotModel::with_latency_budget)
+//! sends its follow-up calls to a faster model. Nothing is cut off when the
+//! budget runs out; it only stops the turn from fanning out further.
+
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// How a turn's budget is set.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BudgetLimits {
+    /// How long the turn should take.
+    pub target: Duration,
+    /// Share of `target` after which the turn hurries.
+    pub hurry_at: f64,
+    /// Full name of the model follow-up calls go to once hurrying, if any.
+    pub fast_model: Option<String>,
+    /// Tool calls each completion may make once hurrying.
+    pub max_tool_calls: usize,
+}
+
+/// A soft time limit on the turn in progress. Shared by the turn's model
+/// and its hook, and restarted at the start of every turn.
+#[derive(Debug, Default)]
+pub struct LatencyBudget {
+    turn: Mutex<Option<Turn>>,
+}
+
+#[derive(Debug)]
+struct Turn {
+    limits: BudgetLimits,
+    started: Instant,
+    /// Tool calls made since the last completion.
+    round_tool_calls: usize,
+}
+
+impl LatencyBudget {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start timing a new turn against `limits`, or stop timing with None.
+    pub fn restart(&self, limits: Option<BudgetLimits>) {
+        *self.lock() = limits.map(|limits| Turn {
+            limits,
+            started: Instant::now(),
+            round_tool_calls: 0,
+        });
+    }
+
+    /// Whether most of the turn's budget has passed.
+    pub fn is_hurrying(&self) -> bool {
+        self.lock().as_ref().is_some_and(Turn::is_hurrying)
+    }
+
+    /// The model follow-up calls should go to, once hurrying.
+    pub fn fast_model(&self) -> Option<String> {
+        self.lock()
+            .as_ref()
+            .filter(|turn| turn.is_hurrying())
+            .and_then(|turn| turn.limits.fast_model.clone())
+    }
+
+    /// Note that a completion is about to be requested, which starts a new
+    /// round of tool calls.
+    pub fn start_round(&self) {
+        if let Some(turn) = self.lock().as_mut() {
+            turn.round_tool_calls = 0;
+        }
+    }
+
+    /// Count a tool call. Returns false for a call past the round's limit
+    /// once hurrying, which should be skipped.
+    pub fn admit_tool_call(&self) -> bool {
+        let mut turn = self.lock();
+        let Some(turn) = turn.as_mut() else {
+            return true;
+        };
+        turn.round_tool_calls += 1;
+        !turn.is_hurrying() || turn.round_tool_calls <= turn.limits.max_tool_calls
+    }
+
+    /// Time spent on the turn so far.
+    pub fn elapsed(&self) -> Option<Duration> {
+        self.lock().as_ref().map(|turn| turn.started.elapsed())
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Option<Turn>> {
+        self.turn.lock().unwrap_or_else(|error| error.into_inner())
+    }
+}
+
+impl Turn {
+    fn is_hurrying(&self) -> bool {
+        self.started.elapsed() >= self.limits.target.mul_f64(self.limits.hurry_at)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limits(hurry_at: f64) -> BudgetLimits {
+        BudgetLimits {
+            target: Duration::from_secs(15),
+            hurry_at,
+            fast_model: Some("anthropic/claude-haiku-4.5".into()),
+            max_tool_calls: 1,
+        }
+    }
+
+    #[test]
+    fn test_fan_out_is_limited_only_when_hurrying() {
+        let budget = LatencyBudget::new();
+        assert!(budget.admit_tool_call());
+
+        budget.restart(Some(limits(1.0)));
+        assert!(!budget.is_hurrying());
+        assert_eq!(budget.fast_model(), None);
+        assert!(budget.admit_tool_call());
+        assert!(budget.admit_tool_call());
+
+        // A budget already spent hurries from the first call.
+        budget.restart(Some(limits(0.0)));
+        assert_eq!(
+            budget.fast_model().as_deref(),
+            Some("anthropic/claude-haiku-4.5")
+        );
+        assert!(budget.admit_tool_call());
+        assert!(!budget.admit_tool_call());
+        budget.start_round();
+        assert!(budget.admit_tool_call());
+
+        budget.restart(None);
+        assert!(!budget.is_hurrying());
+        assert_eq!(budget.elapsed(), None);
+    }
+}
@@ -0,0 +1,199 @@
+//! Priority-aware concurrency limiter for outbound completion requests.
+//!
+//! When the in-flight limit is reached, interactive requests (channel turns,
+//! branches) jump ahead of background work (workers, compaction, cortex) so a
+//! user never waits behind a batch of summarization jobs.
+
+use crate::ProcessType;
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::Notify;
+
+use std::sync::Mutex;
+
+/// Scheduling class for a completion request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Priority {
+    /// A user is waiting on the result.
+    #[default]
+    Interactive,
+    /// Scheduled or housekeeping work that can tolerate queueing.
+    Background,
+}
+
+impl Priority {
+    /// The default priority for requests made on behalf of a process.
+    pub fn for_process(process_type: ProcessType) -> Self {
+        match process_type {
+            ProcessType::Channel | ProcessType::Branch => Self::Interactive,
+            ProcessType::Worker | ProcessType::Compactor | ProcessType::Cortex => Self::Background,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Interactive => "interactive",
+            Self::Background => "background",
+        }
+    }
+}
+
+impl std::fmt::Display for Priority {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Debug, Default)]
+struct LimiterState {
+    in_flight: usize,
+    waiting_interactive: usize,
+}
+
+/// Caps concurrent completion requests, admitting interactive requests first.
+///
+/// A limit of `None` disables the limiter entirely.
+#[derive(Debug)]
+pub struct RequestLimiter {
+    max_in_flight: Option<usize>,
+    state: Mutex<LimiterState>,
+    released: Notify,
+}
+
+impl RequestLimiter {
+    pub fn new(max_in_flight: Option<usize>) -> Self {
+        Self {
+            max_in_flight: max_in_flight.filter(|limit| *limit > 0),
+            state: Mutex::new(LimiterState::default()),
+            released: Notify::new(),
+        }
+    }
+
+    /// Wait for a slot. Background requests are held back while any
+    /// interactive request is waiting.
+    pub async fn acquire(&self, priority: Priority) -> LimiterPermit<'_> {
+        let Some(max_in_flight) = self.max_in_flight else {
+            return LimiterPermit { limiter: None };
+        };
+
+        let _waiting = (priority == Priority::Interactive).then(|| WaitingInteractive::new(self));
+
+        loop {
+            let released = self.released.notified();
+            tokio::pin!(released);
+            released.as_mut().enable();
+
+            {
+                let mut state = self.lock_state();
+                let admitted = state.in_flight < max_in_flight
+                    && (priority == Priority::Interactive || state.waiting_interactive == 0);
+                if admitted {
+                    state.in_flight += 1;
+                    return LimiterPermit {
+                        limiter: Some(self),
+                    };
+                }
+            }
+
+            released.await;
+        }
+    }
+
+    /// Number of requests currently holding a slot.
+    pub fn in_flight(&self) -> usize {
+        self.lock_state().in_flight
+    }
+
+    fn lock_state(&self) -> std::sync::MutexGuard<'_, LimiterState> {
+        // The state is two counters; a panic mid-update can't leave it in a
+        // shape worth refusing to read.
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// Counts an interactive waiter for as long as it's queued, including when
+/// the acquiring future is dropped before admission.
+struct WaitingInteractive<'a> {
+    limiter: &'a RequestLimiter,
+}
+
+impl<'a> WaitingInteractive<'a> {
+    fn new(limiter: &'a RequestLimiter) -> Self {
+        limiter.lock_state().waiting_interactive += 1;
+        Self { limiter }
+    }
+}
+
+impl Drop for WaitingInteractive<'_> {
+    fn drop(&mut self) {
+        self.limiter.lock_state().waiting_interactive -= 1;
+        // Background requests may have been held back on our account.
+        self.limiter.released.notify_waiters();
+    }
+}
+
+/// Holds a limiter slot until dropped.
+#[must_use]
+pub struct LimiterPermit<'a> {
+    limiter: Option<&'a RequestLimiter>,
+}
+
+impl Drop for LimiterPermit<'_> {
+    fn drop(&mut self) {
+        if let Some(limiter) = self.limiter {
+            limiter.lock_state().in_flight -= 1;
+            limiter.released.notify_waiters();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_unlimited_never_blocks() {
+        let limiter = RequestLimiter::new(None);
+        let _first = limiter.acquire(Priority::Background).await;
+        let _second = limiter.acquire(Priority::Background).await;
+        assert_eq!(limiter.in_flight(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_interactive_admitted_before_background() {
+        let limiter = std::sync::Arc::new(RequestLimiter::new(Some(1)));
+        let held = limiter.acquire(Priority::Background).await;
+
+        let (order_tx, mut order_rx) = tokio::sync::mpsc::unbounded_channel();
+
+        let background = {
+            let limiter = limiter.clone();
+            let order_tx = order_tx.clone();
+            tokio::spawn(async move {
+                let _permit = limiter.acquire(Priority::Background).await;
+                order_tx.send(Priority::Background).ok();
+            })
+        };
+        tokio::time::sleep(Duration::from_millis(20)).await;
+
+        let interactive = {
+            let limiter = limiter.clone();
+            tokio::spawn(async move {
+                let _permit = limiter.acquire(Priority::Interactive).await;
+                order_tx.send(Priority::Interactive).ok();
+            })
+        };
+        tokio::time::sleep(Duration::from_millis(20)).await;
+
+        drop(held);
+        background.await.unwrap();
+        interactive.await.unwrap();
+
+        assert_eq!(order_rx.recv().await, Some(Priority::Interactive));
+        assert_eq!(order_rx.recv().await, Some(Priority::Background));
+    }
+}
@@ -0,0 +1,1309 @@
+//! LLM manager for provider credentials and HTTP client.
+//!
+//! The manager is intentionally simple — it holds API keys, an HTTP client,
+//! and shared rate limit state. Routing decisions (which model for which
+//! process) live on the agent's RoutingConfig, not here.
+
+use crate::config::LlmConfig;
+use crate::error::{LlmError, Result};
+use crate::events::{Event, EventBus};
+use crate::llm::credentials::aws::AwsSecretsBackend;
+use crate::llm::credentials::vault::VaultBackend;
+use crate::llm::credentials::{
+    CredentialBackends, CredentialRegistry, CredentialSource, CredentialSourceDyn, FailoverHook,
+    KeySlot, RefreshHook, source_from_reference,
+};
+use crate::llm::faults::FaultInjector;
+use crate::llm::health::{HealthCheckConfig, ProviderHealth};
+use crate::llm::key_health::{self, KeyCheckConfig, KeyHealth, KeyHealthLog, KeyStatus};
+use crate::llm::limiter::{LimiterPermit, Priority, RequestLimiter};
+use crate::llm::metrics::{LatencyKind, LlmMetrics};
+use crate::llm::model_changes::ModelChangeLog;
+use crate::llm::outage::{OutageChange, OutageDetector, ProviderOutage};
+use crate::llm::pricing::ModelPricing;
+use crate::llm::race::{RaceLog, RaceRecord};
+use crate::llm::raw::{self, RawProviderResponse};
+use crate::llm::recorder::DebugRecorder;
+use crate::llm::regions::{RegionalEndpoints, RegionalEndpointsConfig};
+use crate::llm::routing::{
+    MAX_RETRIES_PER_MODEL, RETRY_BASE_DELAY_MS, RoutingConfig, is_rate_limit_error,
+    is_retriable_error,
+};
+use crate::llm::schema::SchemaWarningLog;
+use crate::llm::shadow::{ShadowLog, ShadowRecord, ShadowSummary};
+use crate::llm::signing::{HmacSigner, RequestSigner};
+use crate::llm::simulate::RouteState;
+use crate::llm::tool_names::ToolNameCollisionLog;
+use crate::llm::uploads::FileUploads;
+use anyhow::Context as _;
+use std::collections::HashMap;
+use std::sync::{Arc, Weak};
+use std::time::{Duration, Instant};
+use tokio::sync::RwLock;
+
+/// Upper bound on a single warm-up probe so an unreachable provider can't
+/// hold up startup.
+const WARM_UP_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Manages LLM provider clients and tracks rate limit state.
+pub struct LlmManager {
+    credentials: CredentialRegistry,
+    http_client: reqwest::Client,
+    /// Models currently in rate limit cooldown, with the time they were limited.
+    rate_limited: Arc<RwLock<HashMap<String, Instant>>>,
+    /// Server roots of self-hosted providers, by provider id.
+    base_urls: HashMap<String, String>,
+    /// Regional server roots with failover state, by provider id.
+    regions: HashMap<String, RegionalEndpoints>,
+    health_check: HealthCheckConfig,
+    /// Providers served by vLLM, which accept vLLM request extras.
+    vllm_providers: Vec<String>,
+    /// Providers whose tool calls are grammar-constrained.
+    grammar_providers: Vec<String>,
+    /// Providers sent request tags as metadata.
+    tag_metadata_providers: Vec<String>,
+    /// Token prices by full model name.
+    pricing: HashMap<String, ModelPricing>,
+    /// Last probe result per self-hosted provider. Providers without an
+    /// entry are assumed healthy.
+    health: Arc<RwLock<HashMap<String, ProviderHealth>>>,
+    /// Providers failing across their models, which routing avoids.
+    outages: OutageDetector,
+    key_check: KeyCheckConfig,
+    /// Last check of each hosted provider's keys.
+    key_health: KeyHealthLog,
+    /// Failures injected into requests for resilience testing.
+    faults: FaultInjector,
+    /// Shared concurrency cap for outbound completion requests.
+    limiter: RequestLimiter,
+    /// Latency histograms for queueing, provider attempts, and whole requests.
+    metrics: LlmMetrics,
+    /// Raw request/response capture, populated only when debug recording is on.
+    debug_recorder: DebugRecorder,
+    /// Recent best-of-N races.
+    races: RaceLog,
+    /// Recent requests mirrored to candidate models.
+    shadows: ShadowLog,
+    /// File ids of images uploaded instead of inlined.
+    file_uploads: FileUploads,
+    /// Tool schema rewrites already warned about.
+    schema_warnings: SchemaWarningLog,
+    /// Tool name collisions already warned about.
+    tool_name_collisions: ToolNameCollisionLog,
+    /// Deprecations and served snapshots already reported.
+    model_changes: ModelChangeLog,
+    /// Signers for providers behind gateways that require signed requests.
+    signers: HashMap<String, Arc<dyn RequestSigner>>,
+    /// Billing organization and project for OpenAI requests.
+    openai_organization: Option<String>,
+    openai_project: Option<String>,
+    /// Routing applied to models that weren't given one explicitly.
+    default_routing: Option<RoutingConfig>,
+    events: EventBus,
+}
+
+impl LlmManager {
+    /// Create a new LLM manager with the given configuration.
+    pub async fn new(config: LlmConfig) -> Result<Self> {
+        Self::builder().config(config).build()
+    }
+
+    /// Start building a manager in code rather than from a config file.
+    pub fn builder() -> LlmManagerBuilder {
+        LlmManagerBuilder::default()
+    }
+
+    /// Get the API key for a provider, fetching it from its credential
+    /// source if it isn't cached.
+    pub async fn get_api_key(&self, provider: &str) -> Result<String> {
+        self.credentials.get(provider).await
+    }
+
+    /// Forget a provider's cached key, e.g. after the provider rejected it,
+    /// so the next request fetches it again.
+    pub async fn invalidate_api_key(&self, provider: &str) {
+        self.credentials.invalidate(provider).await;
+    }
+
+    /// Per-provider credential sources.
+    pub fn credentials(&self) -> &CredentialRegistry {
+        &self.credentials
+    }
+
+    /// Get the HTTP client.
+    pub fn http_client(&self) -> &reqwest::Client {
+        &self.http_client
+    }
+
+    /// The signer for a provider's requests, if it has one.
+    pub fn request_signer(&self, provider: &str) -> Option<&dyn RequestSigner> {
+        self.signers.get(provider).map(|signer| signer.as_ref())
+    }
+
+    /// OpenAI organization id to bill requests to, if configured.
+    pub fn openai_organization(&self) -> Option<&str> {
+        self.openai_organization.as_deref()
+    }
+
+    /// OpenAI project id to bill requests to, if configured.
+    pub fn openai_project(&self) -> Option<&str> {
+        self.openai_project.as_deref()
+    }
+
+    /// Send a request to a provider, signed if the provider has a signer.
+    /// Every request to a provider should go out through here.
+    pub async fn send(
+        &self,
+        provider: &str,
+        request: reqwest::RequestBuilder,
+    ) -> Result<reqwest::Response> {
+        super::signing::send(request, self.request_signer(provider)).await
+    }
+
+    /// Call any endpoint of a provider's API, e.g. `GET /v1/models`, with
+    /// its credentials, signing, retries and rate limit cooldown. `path` is
+    /// from the provider's origin, or from its server root when self-hosted.
+    ///
+    /// Responses the retries couldn't turn into a success are returned
+    /// rather than made into errors, so callers can read the provider's
+    /// error body. Only transport failures and a cooldown are errors.
+    pub async fn raw_request(
+        &self,
+        provider: &str,
+        method: reqwest::Method,
+        path: &str,
+        body: Option<serde_json::Value>,
+    ) -> Result<RawProviderResponse> {
+        let root = self
+            .base_url(provider)
+            .or_else(|| self.regions(provider).and_then(RegionalEndpoints::active))
+            .or_else(|| super::providers::provider_origin(provider))
+            .ok_or_else(|| LlmError::UnknownProvider(provider.to_string()))?;
+        let url = raw::url(root, path);
+        let series = raw::series_name(provider);
+        let cooldown_secs = self.default_routing().map_or(
+            RoutingConfig::default().rate_limit_cooldown_secs,
+            |routing| routing.rate_limit_cooldown_secs,
+        );
+        if self.is_rate_limited(&series, cooldown_secs).await {
+            return Err(LlmError::ProviderRequest(format!(
+                "{provider} API is rate limited, cooling down"
+            )));
+        }
+
+        let started = Instant::now();
+        let result = self
+            .raw_attempts(provider, &series, &method, &url, body.as_ref())
+            .await;
+        self.metrics.observe(
+            LatencyKind::TotalRequest,
+            &series,
+            Priority::Background,
+            started.elapsed(),
+        );
+        result
+    }
+
+    async fn raw_attempts(
+        &self,
+        provider: &str,
+        series: &str,
+        method: &reqwest::Method,
+        url: &str,
+        body: Option<&serde_json::Value>,
+    ) -> Result<RawProviderResponse> {
+        let mut last_error = String::new();
+        let mut last_response = None;
+        for attempt in 0..MAX_RETRIES_PER_MODEL {
+            if attempt > 0 {
+                let delay_ms = RETRY_BASE_DELAY_MS * 2u64.pow((attempt - 1) as u32);
+                tokio::time::sleep(Duration::from_millis(delay_ms)).await;
+            }
+
+            // Self-hosted servers usually don't check a key.
+            let api_key = match self.get_api_key(provider).await {
+                Ok(api_key) => Some(api_key),
+                Err(_) if self.base_url(provider).is_some() => None,
+                Err(error) => return Err(error),
+            };
+            let mut request = raw::authorize(
+                self,
+                provider,
+                self.http_client.request(method.clone(), url),
+                api_key.as_deref(),
+            );
+            if let Some(body) = body {
+                request = request.json(body);
+            }
+
+            let attempt_started = Instant::now();
+            let result = match self.send(provider, request).await {
+                Ok(response) => {
+                    let status = response.status();
+                    let provider_request_id = super::model::provider_request_id(response.headers());
+                    response
+                        .text()
+                        .await
+                        .map(|text| RawProviderResponse::new(status, provider_request_id, text))
+                        .map_err(|error| format!("failed to read response body: {error}"))
+                }
+                Err(LlmError::ProviderRequest(error)) => Err(error),
+                Err(error) => return Err(error),
+            };
+            self.metrics.observe(
+                LatencyKind::ProviderAttempt,
+                series,
+                Priority::Background,
+                attempt_started.elapsed(),
+            );
+
+            let error = match result {
+                Ok(response) if response.is_success() => {
+                    self.record_attempt(series, None);
+                    return Ok(response);
+                }
+                Ok(response) => {
+                    let status = reqwest::StatusCode::from_u16(response.status)
+                        .unwrap_or(reqwest::StatusCode::INTERNAL_SERVER_ERROR);
+                    if status == reqwest::StatusCode::UNAUTHORIZED
+                        || status == reqwest::StatusCode::FORBIDDEN
+                    {
+                        self.credentials.reject(provider).await;
+                    }
+                    if status == reqwest::StatusCode::TOO_MANY_REQUESTS {
+                        self.record_rate_limit(series).await;
+                    }
+                    let error = format!("{provider} API error ({status})");
+                    last_response = Some(response);
+                    error
+                }
+                Err(error) => error,
+            };
+            self.record_attempt(series, Some(&error));
+            // A rate limit starts the cooldown, so retrying can't help.
+            let retriable = is_retriable_error(&error) && !is_rate_limit_error(&error);
+            if retriable {
+                tracing::warn!(provider, url, attempt = attempt + 1, %error, "retriable raw request error");
+            }
+            last_error = error;
+            if !retriable {
+                break;
+            }
+        }
+
+        last_response.ok_or_else(|| LlmError::ProviderRequest(format!("{provider}: {last_error}")))
+    }
+
+    /// Providers that have a credential source or a self-hosted server
+    /// configured.
+    pub fn configured_providers(&self) -> Vec<&'static str> {
+        super::providers::PROVIDER_ORIGINS
+            .iter()
+            .map(|(provider, _)| *provider)
+            .filter(|provider| {
+                self.credentials.has_source(provider)
+                    || self.base_urls.contains_key(*provider)
+                    || self.regions.contains_key(*provider)
+            })
+            .collect()
+    }
+
+    /// Root URL of a provider's self-hosted server, if one is configured.
+    pub fn base_url(&self, provider: &str) -> Option<&str> {
+        self.base_urls.get(provider).map(String::as_str)
+    }
+
+    /// A provider's regional endpoints, if it has several.
+    pub fn regions(&self, provider: &str) -> Option<&RegionalEndpoints> {
+        self.regions.get(provider)
+    }
+
+    /// Open connections to every configured provider ahead of the first
+    /// completion so DNS resolution and the TLS handshake are already paid for.
+    ///
+    /// Any HTTP response counts as warm — only transport failures are
+    /// reported. Returns the providers that could not be reached.
+    pub async fn warm_up(&self) -> Vec<String> {
+        let started = Instant::now();
+        let probes = self
+            .configured_providers()
+            .into_iter()
+            .filter_map(|provider| {
+                let origin = self
+                    .base_url(provider)
+                    .or_else(|| super::providers::provider_origin(provider))?;
+                let request = self.http_client.head(origin).timeout(WARM_UP_TIMEOUT);
+                Some(async move { (provider, self.send(provider, request).await) })
+            });
+
+        let mut unreachable = Vec::new();
+        for (provider, result) in futures::future::join_all(probes).await {
+            match result {
+                Ok(response) => {
+                    tracing::debug!(provider, status = %response.status(), "provider connection warmed");
+                }
+                Err(error) => {
+                    tracing::warn!(provider, %error, "can't warm provider connection");
+                    unreachable.push(provider.to_string());
+                }
+            }
+        }
+
+        tracing::info!(
+            elapsed_ms = started.elapsed().as_millis() as u64,
+            unreachable = unreachable.len(),
+            "provider warm-up finished"
+        );
+        unreachable
+    }
+
+    /// Resolve a model name to provider and model components.
+    /// Format: "provider/model-name" or just "model-name" (defaults to anthropic).
+    pub fn resolve_model(&self, model_name: &str) -> Result<(String, String)> {
+        if let Some((provider, model)) = model_name.split_once('/') {
+            Ok((provider.to_string(), model.to_string()))
+        } else {
+            Ok(("anthropic".into(), model_name.into()))
+        }
+    }
+
+    /// Routing used by models that weren't given one with
+    /// [`SpacebotModel::with_routing`](crate::llm::SpacebotModel::with_routing).
+    pub fn default_routing(&self) -> Option<&RoutingConfig> {
+        self.default_routing.as_ref()
+    }
+
+    /// Wait for a request slot. Hold the returned permit for the duration of
+    /// the provider call.
+    pub async fn acquire_request_slot(&self, priority: Priority) -> LimiterPermit<'_> {
+        self.limiter.acquire(priority).await
+    }
+
+    /// Latency metrics shared by every model built from this manager.
+    pub fn metrics(&self) -> &LlmMetrics {
+        &self.metrics
+    }
+
+    /// Recorded provider exchanges, keyed by request id.
+    pub fn debug_recorder(&self) -> &DebugRecorder {
+        &self.debug_recorder
+    }
+
+    /// Keep a finished race and announce it.
+    pub fn record_race(&self, race: RaceRecord) {
+        self.races.record(race.clone());
+        self.events.publish(Event::RaceFinished { race });
+    }
+
+    /// Recent races, newest first.
+    pub fn recent_races(&self) -> Vec<RaceRecord> {
+        self.races.recent()
+    }
+
+    /// Keep a finished shadow request and announce it.
+    pub fn record_shadow(&self, shadow: ShadowRecord) {
+        self.shadows.record(shadow.clone());
+        self.events.publish(Event::ShadowFinished { shadow });
+    }
+
+    /// Recent shadow requests, newest first.
+    pub fn recent_shadows(&self) -> Vec<ShadowRecord> {
+        self.shadows.recent()
+    }
+
+    /// How each candidate compares to the model it shadows, over the recent
+    /// shadow requests.
+    pub fn shadow_summaries(&self) -> Vec<ShadowSummary> {
+        self.shadows.summaries()
+    }
+
+    /// Cache of images uploaded through provider file APIs.
+    pub fn file_uploads(&self) -> &FileUploads {
+        &self.file_uploads
+    }
+
+    /// Fault rules injected into provider requests, for testing fallbacks
+    /// and alerts.
+    pub fn faults(&self) -> &FaultInjector {
+        &self.faults
+    }
+
+    /// Log of lossy tool schema rewrites, each reported once.
+    pub fn schema_warnings(&self) -> &SchemaWarningLog {
+        &self.schema_warnings
+    }
+
+    /// Log of tool names that collide for a provider, each reported once.
+    pub fn tool_name_collisions(&self) -> &ToolNameCollisionLog {
+        &self.tool_name_collisions
+    }
+
+    /// Report that a provider flagged `model` as deprecated. Logged and
+    /// published once per model.
+    pub fn report_deprecation(&self, model: &str, detail: &str) {
+        if self.model_changes.deprecated(model) {
+            tracing::warn!(%model, %detail, "provider reports model is deprecated");
+            self.events.publish(Event::ModelDeprecated {
+                model: model.to_string(),
+                detail: detail.to_string(),
+            });
+        }
+    }
+
+    /// Report the model named in a provider's response to a request for
+    /// `model`, publishing when the served snapshot changes.
+    pub fn report_served_model(&self, model: &str, served: &str) {
+        if let Some(previous) = self.model_changes.served(model, served) {
+            tracing::info!(%model, %served, ?previous, "model served by a new snapshot");
+            self.events.publish(Event::ModelSnapshotChanged {
+                model: model.to_string(),
+                served: served.to_string(),
+                previous,
+            });
+        }
+    }
+
+    /// Bus that completion, rate-limit and credential events are published on.
+    pub fn events(&self) -> &EventBus {
+        &self.events
+    }
+
+    /// Record that a model hit a rate limit.
+    pub async fn record_rate_limit(&self, model_name: &str) {
+        self.rate_limited
+            .write()
+            .await
+            .insert(model_name.to_string(), Instant::now());
+        tracing::warn!(model = %model_name, "model rate limited, entering cooldown");
+        self.events.publish(Event::RateLimited {
+            model: model_name.to_string(),
+        });
+    }
+
+    /// Check if a model is currently in rate limit cooldown.
+    pub async fn is_rate_limited(&self, model_name: &str, cooldown_secs: u64) -> bool {
+        let map = self.rate_limited.read().await;
+        if let Some(limited_at) = map.get(model_name) {
+            limited_at.elapsed().as_secs() < cooldown_secs
+        } else {
+            false
+        }
+    }
+
+    /// Cooldown and health state as routing sees it, for
+    /// [`simulate_route`](crate::llm::simulate::simulate_route).
+    pub async fn route_state(&self) -> RouteState {
+        let rate_limited = self
+            .rate_limited
+            .read()
+            .await
+            .iter()
+            .map(|(model, limited_at)| (model.clone(), limited_at.elapsed().as_secs()))
+            .collect();
+        let unhealthy_providers = self
+            .health
+            .read()
+            .await
+            .iter()
+            .filter(|(_, health)| !health.healthy)
+            .map(|(provider, _)| provider.clone())
+            .chain(
+                self.outages
+                    .outages()
+                    .into_iter()
+                    .map(|outage| outage.provider),
+            )
+            .collect();
+        RouteState {
+            rate_limited,
+            unhealthy_providers,
+        }
+    }
+
+    /// Estimated cost in USD of a completion, if the model has a price.
+    pub fn cost_usd(&self, model_name: &str, input_tokens: u64, output_tokens: u64) -> Option<f64> {
+        self.pricing
+            .get(model_name)
+            .map(|pricing| pricing.cost_usd(input_tokens, output_tokens))
+    }
+
+    /// Whether a provider is flagged as a vLLM server.
+    pub fn is_vllm(&self, provider: &str) -> bool {
+        self.vllm_providers.iter().any(|vllm| vllm == provider)
+    }
+
+    /// Whether requests to a provider carry a tool call grammar instead of
+    /// tools.
+    pub fn uses_tool_grammar(&self, provider: &str) -> bool {
+        self.grammar_providers
+            .iter()
+            .any(|constrained| constrained == provider)
+    }
+
+    /// Whether requests to a provider carry their tags as metadata.
+    pub fn sends_tag_metadata(&self, provider: &str) -> bool {
+        self.tag_metadata_providers
+            .iter()
+            .any(|tagged| tagged == provider)
+    }
+
+    /// Whether the provider serving `model_name` passed its last health
+    /// probe and isn't in an outage. Hosted providers aren't probed, so only
+    /// an outage makes them unhealthy.
+    pub async fn is_model_healthy(&self, model_name: &str) -> bool {
+        let Ok((provider, _)) = self.resolve_model(model_name) else {
+            return true;
+        };
+        !self.outages.is_out(&provider)
+            && self
+                .health
+                .read()
+                .await
+                .get(&provider)
+                .is_none_or(|health| health.healthy)
+    }
+
+    /// Count a provider attempt toward outage detection, with its error if
+    /// it failed. Only server-side failures (5xx, overloaded, timeouts,
+    /// dropped connections) count against a provider; rate limits are
+    /// per-model and request errors are the caller's.
+    pub fn record_attempt(&self, model_name: &str, error: Option<&str>) {
+        if error.is_some_and(|error| !is_retriable_error(error) || is_rate_limit_error(error)) {
+            return;
+        }
+        match self.outages.record(model_name, error, Instant::now()) {
+            Some(OutageChange::Started(outage)) => {
+                tracing::warn!(provider = %outage.provider, detail = %outage.detail(), "provider outage detected, routing around it");
+                self.events.publish(Event::ProviderOutage {
+                    provider: outage.provider.clone(),
+                    active: true,
+                    detail: outage.detail(),
+                });
+            }
+            Some(OutageChange::Ended(outage)) => {
+                self.publish_recovery(&outage, "request succeeded");
+            }
+            None => {}
+        }
+    }
+
+    /// Providers currently in an outage.
+    pub fn provider_outages(&self) -> Vec<ProviderOutage> {
+        self.outages.outages()
+    }
+
+    /// Probe every provider in an outage once, ending the outage of any
+    /// that answer without a server error.
+    pub async fn probe_outages(&self) {
+        let probes = self.outages.outages().into_iter().filter_map(|outage| {
+            let origin = self
+                .base_url(&outage.provider)
+                .or_else(|| super::providers::provider_origin(&outage.provider))?;
+            let request = self.http_client.head(origin).timeout(WARM_UP_TIMEOUT);
+            Some(async move {
+                let result = self.send(&outage.provider, request).await;
+                (outage.provider, result)
+            })
+        });
+        for (provider, result) in futures::future::join_all(probes).await {
+            match result {
+                Ok(response) if !response.status().is_server_error() => {
+                    if let Some(outage) = self.outages.recover(&provider) {
+                        self.publish_recovery(&outage, "recovery probe passed");
+                    }
+                }
+                Ok(response) => {
+                    tracing::debug!(provider, status = %response.status(), "provider still out");
+                }
+                Err(error) => tracing::debug!(provider, %error, "provider still out"),
+            }
+        }
+    }
+
+    /// Probe providers in an outage on the configured interval until the
+    /// manager is dropped.
+    pub fn spawn_outage_probes(self: &Arc<Self>) -> tokio::task::JoinHandle<()> {
+        let manager: Weak<Self> = Arc::downgrade(self);
+        let period = Duration::from_secs(self.outages.config().probe_interval_secs.max(1));
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(period);
+            interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                interval.tick().await;
+                let Some(manager) = manager.upgrade() else {
+                    break;
+                };
+                manager.probe_outages().await;
+            }
+        })
+    }
+
+    fn publish_recovery(&self, outage: &ProviderOutage, reason: &str) {
+        tracing::info!(provider = %outage.provider, reason, "provider outage over, resuming");
+        self.events.publish(Event::ProviderOutage {
+            provider: outage.provider.clone(),
+            active: false,
+            detail: reason.to_string(),
+        });
+    }
+
+    /// Last probe result for each self-hosted provider.
+    pub async fn provider_health(&self) -> HashMap<String, ProviderHealth> {
+        self.health.read().await.clone()
+    }
+
+    /// Probe every self-hosted provider once and record the results.
+    /// Publishes [`Event::ProviderHealthChanged`] when a provider flips.
+    pub async fn check_health(&self) {
+        let probes = self
+            .base_urls
+            .iter()
+            .map(|(provider, base_url)| async move {
+                let health = super::health::probe(
+                    &self.http_client,
+                    self.request_signer(provider),
+                    provider,
+                    base_url,
+                    &self.health_check,
+                )
+                .await;
+                (provider.clone(), health)
+            });
+        let results = futures::future::join_all(probes).await;
+
+        let mut state = self.health.write().await;
+        for (provider, health) in results {
+            let was_healthy = state.get(&provider).is_none_or(|previous| previous.healthy);
+            if was_healthy != health.healthy {
+                if health.healthy {
+                    tracing::info!(provider, detail = %health.detail, "self-hosted provider recovered, resuming");
+                } else {
+                    tracing::warn!(provider, detail = %health.detail, "self-hosted provider unhealthy, pausing routing");
+                }
+                self.events.publish(Event::ProviderHealthChanged {
+                    provider: provider.clone(),
+                    healthy: health.healthy,
+                    detail: health.detail.clone(),
+                });
+            }
+            state.insert(provider, health);
+        }
+    }
+
+    /// Probe self-hosted providers on the configured interval until the
+    /// manager is dropped. Returns `None` when there is nothing to probe.
+    pub fn spawn_health_checks(self: &Arc<Self>) -> Option<tokio::task::JoinHandle<()>> {
+        if self.base_urls.is_empty() {
+            return None;
+        }
+        let manager: Weak<Self> = Arc::downgrade(self);
+        let period = Duration::from_secs(self.health_check.interval_secs.max(1));
+        Some(tokio::spawn(async move {
+            let mut interval = tokio::time::interval(period);
+            interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                interval.tick().await;
+                // A rebuilt manager starts its own loop; stop with the old one.
+                let Some(manager) = manager.upgrade() else {
+                    break;
+                };
+                manager.check_health().await;
+            }
+        }))
+    }
+
+    /// Last check of each hosted provider's keys.
+    pub fn key_health(&self) -> Vec<KeyHealth> {
+        self.key_health.latest()
+    }
+
+    /// Check every hosted provider's keys once, secondary keys included, and
+    /// record the results. Publishes [`Event::KeyHealthChanged`] when a key's
+    /// status changes. Self-hosted providers are left to the health probes.
+    pub async fn check_keys(&self) -> Vec<KeyHealth> {
+        let checks = self
+            .checkable_keys()
+            .into_iter()
+            .map(|(provider, slot, path)| self.check_key(provider, slot, path));
+        let mut results = futures::future::join_all(checks).await;
+        results.sort_by_key(|health| health.key_name());
+
+        for health in self.key_health.record(results.clone()) {
+            match health.status {
+                KeyStatus::Ok => {
+                    tracing::info!(key = %health.key_name(), detail = %health.detail, "provider key healthy again")
+                }
+                KeyStatus::Warning => {
+                    tracing::warn!(key = %health.key_name(), detail = %health.detail, "provider key needs attention")
+                }
+                _ => {
+                    tracing::error!(key = %health.key_name(), detail = %health.detail, "provider key failing")
+                }
+            }
+            self.events.publish(Event::KeyHealthChanged {
+                provider: health.provider,
+                slot: health.slot,
+                status: health.status,
+                detail: health.detail,
+            });
+        }
+        results
+    }
+
+    fn checkable_keys(&self) -> Vec<(&'static str, KeySlot, &'static str)> {
+        let mut keys = Vec::new();
+        for provider in self.configured_providers() {
+            if !self.credentials.has_source(provider) || self.base_url(provider).is_some() {
+                continue;
+            }
+            let Some(path) = key_health::check_path(provider) else {
+                continue;
+            };
+            keys.push((provider, KeySlot::Primary, path));
+            if self.credentials.has_secondary(provider) {
+                keys.push((provider, KeySlot::Secondary, path));
+            }
+        }
+        keys
+    }
+
+    async fn check_key(&self, provider: &str, slot: KeySlot, path: &str) -> KeyHealth {
+        let health = KeyHealth::new(provider, slot, KeyStatus::Unknown, "");
+        let api_key = match self.credentials.get_slot(provider, slot).await {
+            Ok(api_key) => api_key,
+            Err(error) => {
+                return KeyHealth {
+                    status: KeyStatus::Failing,
+                    detail: format!("can't fetch the key: {error}"),
+                    ..health
+                };
+            }
+        };
+        let Some(origin) = self
+            .regions(provider)
+            .and_then(RegionalEndpoints::active)
+            .or_else(|| super::providers::provider_origin(provider))
+        else {
+            return health;
+        };
+        let request = raw::authorize(
+            self,
+            provider,
+            self.http_client
+                .get(raw::url(origin, path))
+                .timeout(key_health::CHECK_TIMEOUT),
+            Some(&api_key),
+        );
+        let response = match self.send(provider, request).await {
+            Ok(response) => response,
+            Err(error) => {
+                return KeyHealth {
+                    detail: format!("unreachable: {error}"),
+                    ..health
+                };
+            }
+        };
+        let status = response.status().as_u16();
+        let body = response
+            .json::<serde_json::Value>()
+            .await
+            .unwrap_or_default();
+        key_health::assess(health, status, &body, &self.key_check)
+    }
+
+    /// Check keys on the configured interval until the manager is dropped.
+    /// Returns `None` when checks are off or there are no keys to check.
+    pub fn spawn_key_checks(self: &Arc<Self>) -> Option<tokio::task::JoinHandle<()>> {
+        if self.key_check.interval_secs == 0 || self.checkable_keys().is_empty() {
+            return None;
+        }
+        let manager: Weak<Self> = Arc::downgrade(self);
+        let period = Duration::from_secs(self.key_check.interval_secs);
+        Some(tokio::spawn(async move {
+            let mut interval = tokio::time::interval(period);
+            interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                interval.tick().await;
+                let Some(manager) = manager.upgrade() else {
+                    break;
+                };
+                manager.check_keys().await;
+            }
+        }))
+    }
+
+    /// Clean up expired rate limit entries.
+    pub async fn cleanup_rate_limits(&self, cooldown_secs: u64) {
+        self.rate_limited
+            .write()
+            .await
+            .retain(|_, limited_at| limited_at.elapsed().as_secs() < cooldown_secs);
+    }
+}
+
+/// Builds an [`LlmManager`] without a config file.
+///
+/// ```no_run
+/// # fn run() -> spacebot_core::error::Result<()> {
+/// use spacebot_core::llm::{LlmManager, routing::defaults_for_provider};
+///
+/// let manager = LlmManager::builder()
+///     .provider_key("anthropic", std::env::var("ANTHROPIC_API_KEY").unwrap_or_default())
+///     .provider_key("openrouter", std::env::var("OPENROUTER_API_KEY").unwrap_or_default())
+///     .routing(defaults_for_provider("anthropic"))
+///     .max_concurrent_requests(8)
+///     .build()?;
+/// # let _ = manager;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Default)]
+pub struct LlmManagerBuilder {
+    config: LlmConfig,
+    credential_sources: HashMap<String, Arc<dyn CredentialSourceDyn>>,
+    signers: HashMap<String, Arc<dyn RequestSigner>>,
+    refresh_hooks: Vec<RefreshHook>,
+    failover_hooks: Vec<FailoverHook>,
+    http_client: Option<reqwest::Client>,
+    routing: Option<RoutingConfig>,
+    events: Option<EventBus>,
+    unknown_providers: Vec<String>,
+}
+
+impl LlmManagerBuilder {
+    /// Start from an existing config. Replaces anything set so far.
+    pub fn config(mut self, config: LlmConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Register the API key for a provider, enabling it. The value may also
+    /// be a credential reference (`env:VAR`, `file:/path`,
+    /// `keyring:service/account`). Unknown provider ids are reported by
+    /// [`build`](Self::build).
+    pub fn provider_key(mut self, provider: &str, key: impl Into<String>) -> Self {
+        match self.config.key_mut(provider) {
+            Some(slot) => *slot = Some(key.into()),
+            None => self.unknown_providers.push(provider.to_string()),
+        }
+        self
+    }
+
+    /// Fetch a provider's key from a custom backend instead of config.
+    /// Takes precedence over a key set with [`provider_key`](Self::provider_key).
+    pub fn credential_source(mut self, provider: &str, source: impl CredentialSource) -> Self {
+        if self.config.key_mut(provider).is_none() {
+            self.unknown_providers.push(provider.to_string());
+            return self;
+        }
+        self.credential_sources
+            .insert(provider.to_string(), Arc::new(source));
+        self
+    }
+
+    /// Sign every request to a provider with a custom scheme. Takes
+    /// precedence over HMAC signing set up in config.
+    pub fn request_signer(mut self, provider: &str, signer: impl RequestSigner) -> Self {
+        if self.config.key_mut(provider).is_none() {
+            self.unknown_providers.push(provider.to_string());
+            return self;
+        }
+        self.signers.insert(provider.to_string(), Arc::new(signer));
+        self
+    }
+
+    /// Run `hook` with the provider id whenever a re-fetched key differs
+    /// from the previous one.
+    pub fn on_credential_refresh(mut self, hook: impl Fn(&str) + Send + Sync + 'static) -> Self {
+        self.refresh_hooks.push(Arc::new(hook));
+        self
+    }
+
+    /// Register a standby key for a provider. If the provider rejects the
+    /// primary key, requests switch to this one. Accepts the same credential
+    /// references as [`provider_key`](Self::provider_key).
+    pub fn secondary_key(mut self, provider: &str, key: impl Into<String>) -> Self {
+        if self.config.key_mut(provider).is_none() {
+            self.unknown_providers.push(provider.to_string());
+            return self;
+        }
+        self.config
+            .secondary_keys
+            .insert(provider.to_string(), key.into());
+        self
+    }
+
+    /// Run `hook` with the provider id when its primary key is rejected and
+    /// requests fail over to the secondary key.
+    pub fn on_credential_failover(mut self, hook: impl Fn(&str) + Send + Sync + 'static) -> Self {
+        self.failover_hooks.push(Arc::new(hook));
+        self
+    }
+
+    /// Use a caller-supplied HTTP client, e.g. one with a proxy or custom
+    /// root certificates, instead of the default pooled client.
+    pub fn http_client(mut self, client: reqwest::Client) -> Self {
+        self.http_client = Some(client);
+        self
+    }
+
+    /// Routing (model choice and fallback chains) for models built from this
+    /// manager that don't set their own.
+    pub fn routing(mut self, routing: RoutingConfig) -> Self {
+        self.routing = Some(routing);
+        self
+    }
+
+    /// Publish to an existing event bus instead of a new one, e.g. to keep
+    /// subscribers across a manager rebuild.
+    pub fn event_bus(mut self, events: EventBus) -> Self {
+        self.events = Some(events);
+        self
+    }
+
+    /// Send a provider's requests to a self-hosted server at `base_url`
+    /// (e.g. `http://localhost:11434` for Ollama) and health-check it.
+    pub fn base_url(mut self, provider: &str, base_url: impl Into<String>) -> Self {
+        if self.config.key_mut(provider).is_none() {
+            self.unknown_providers.push(provider.to_string());
+            return self;
+        }
+        self.config
+            .base_urls
+            .insert(provider.to_string(), base_url.into());
+        self
+    }
+
+    /// Serve a provider from several regions, failing over between them in
+    /// order before falling back to another model.
+    pub fn regions(mut self, provider: &str, regions: RegionalEndpointsConfig) -> Self {
+        if self.config.key_mut(provider).is_none() {
+            self.unknown_providers.push(provider.to_string());
+            return self;
+        }
+        self.config.regions.insert(provider.to_string(), regions);
+        self
+    }
+
+    /// Flag a provider as a vLLM server so requests to it carry vLLM extras
+    /// from the routing config.
+    pub fn vllm_provider(mut self, provider: &str) -> Self {
+        if self.config.key_mut(provider).is_none() {
+            self.unknown_providers.push(provider.to_string());
+            return self;
+        }
+        self.config.vllm_providers.push(provider.to_string());
+        self
+    }
+
+    /// Constrain a self-hosted provider's tool calls with a grammar built
+    /// from the tools' schemas.
+    pub fn grammar_provider(mut self, provider: &str) -> Self {
+        if self.config.key_mut(provider).is_none() {
+            self.unknown_providers.push(provider.to_string());
+            return self;
+        }
+        self.config.grammar_providers.push(provider.to_string());
+        self
+    }
+
+    /// Price a model's tokens so completions report an estimated cost.
+    pub fn model_pricing(mut self, model_name: impl Into<String>, pricing: ModelPricing) -> Self {
+        self.config.pricing.insert(model_name.into(), pricing);
+        self
+    }
+
+    /// Bill OpenAI requests to this organization.
+    pub fn openai_organization(mut self, organization: impl Into<String>) -> Self {
+        self.config.openai_organization = Some(organization.into());
+        self
+    }
+
+    /// Bill OpenAI requests to this project.
+    pub fn openai_project(mut self, project: impl Into<String>) -> Self {
+        self.config.openai_project = Some(project.into());
+        self
+    }
+
+    /// Cap on concurrent completion requests. Unlimited by default.
+    pub fn max_concurrent_requests(mut self, limit: usize) -> Self {
+        self.config.max_concurrent_requests = Some(limit);
+        self
+    }
+
+    /// Keep raw provider exchanges for inspection.
+    pub fn debug_recording(mut self, enabled: bool) -> Self {
+        self.config.debug_recording = enabled;
+        self
+    }
+
+    /// Upload images at least this large through provider file APIs.
+    pub fn upload_threshold_bytes(mut self, bytes: usize) -> Self {
+        self.config.upload_threshold_bytes = Some(bytes);
+        self
+    }
+
+    pub fn build(self) -> Result<LlmManager> {
+        if let Some(provider) = self.unknown_providers.into_iter().next() {
+            return Err(LlmError::UnknownProvider(provider));
+        }
+
+        let http_client = match self.http_client {
+            Some(client) => client,
+            // Keep pooled connections around long enough that the TLS
+            // sessions opened by `warm_up` are still alive for the first real
+            // request.
+            None => reqwest::Client::builder()
+                .timeout(Duration::from_secs(120))
+                .pool_idle_timeout(Duration::from_secs(300))
+                .tcp_keepalive(Duration::from_secs(60))
+                .build()
+                .with_context(|| "failed to build HTTP client")?,
+        };
+
+        let backends = CredentialBackends {
+            vault: self
+                .config
+                .vault
+                .clone()
+                .map(|config| Arc::new(VaultBackend::new(config, http_client.clone()))),
+            aws: self
+                .config
+                .aws_secrets
+                .clone()
+                .map(|config| Arc::new(AwsSecretsBackend::new(config, http_client.clone()))),
+        };
+        let mut credential_sources = self.credential_sources;
+        for (provider, _) in super::providers::PROVIDER_ORIGINS {
+            let Some(key) = self.config.key(provider) else {
+                continue;
+            };
+            if !credential_sources.contains_key(*provider) {
+                credential_sources
+                    .insert(provider.to_string(), source_from_reference(key, &backends)?);
+            }
+        }
+
+        let mut secondary_sources = HashMap::new();
+        for (provider, key) in &self.config.secondary_keys {
+            if self.config.key(provider).is_none() && !credential_sources.contains_key(provider) {
+                tracing::warn!(provider, "secondary key configured without a primary key");
+            }
+            secondary_sources.insert(provider.clone(), source_from_reference(key, &backends)?);
+        }
+
+        let mut base_urls = HashMap::new();
+        for (provider, base_url) in self.config.base_urls {
+            if super::providers::provider_origin(&provider).is_none() {
+                return Err(LlmError::UnknownProvider(provider));
+            }
+            if !super::providers::supports_base_url(&provider) {
+                tracing::warn!(provider, "provider can't be self-hosted, base URL ignored");
+                continue;
+            }
+            base_urls.insert(provider, base_url);
+        }
+
+        let mut regions = HashMap::new();
+        for (provider, config) in self.config.regions {
+            if super::providers::provider_origin(&provider).is_none() {
+                return Err(LlmError::UnknownProvider(provider));
+            }
+            if !super::providers::supports_base_url(&provider) {
+                tracing::warn!(provider, "provider has fixed endpoints, regions ignored");
+                continue;
+            }
+            if config.endpoints.is_empty() {
+                continue;
+            }
+            regions.insert(provider, RegionalEndpoints::new(config));
+        }
+
+        let mut signers = self.signers;
+        for (provider, config) in self.config.request_signing {
+            if super::providers::provider_origin(&provider).is_none() {
+                return Err(LlmError::UnknownProvider(provider));
+            }
+            signers
+                .entry(provider)
+                .or_insert_with(|| Arc::new(HmacSigner::new(config)));
+        }
+
+        for provider in &self.config.vllm_providers {
+            if super::providers::provider_origin(provider).is_none() {
+                return Err(LlmError::UnknownProvider(provider.clone()));
+            }
+            if !super::providers::supports_base_url(provider) {
+                tracing::warn!(provider, "provider can't be served by vLLM, flag ignored");
+            }
+        }
+        for provider in &self.config.grammar_providers {
+            if super::providers::provider_origin(provider).is_none() {
+                return Err(LlmError::UnknownProvider(provider.clone()));
+            }
+            if !super::providers::supports_base_url(provider) {
+                tracing::warn!(
+                    provider,
+                    "provider can't be self-hosted, tool grammar ignored"
+                );
+            }
+        }
+        for provider in &self.config.tag_metadata_providers {
+            if super::providers::provider_origin(provider).is_none() {
+                return Err(LlmError::UnknownProvider(provider.clone()));
+            }
+            if provider == "anthropic" {
+                tracing::warn!(
+                    provider,
+                    "provider only takes a user id as metadata, tags won't be sent"
+                );
+            }
+        }
+
+        let limiter = RequestLimiter::new(self.config.max_concurrent_requests);
+        let debug_recorder = DebugRecorder::new(self.config.debug_recording);
+
+        let events = self.events.unwrap_or_default();
+        let mut refresh_hooks = self.refresh_hooks;
+        let bus = events.clone();
+        refresh_hooks.push(Arc::new(move |provider: &str| {
+            bus.publish(Event::CredentialRefreshed {
+                provider: provider.to_string(),
+            })
+        }));
+        let mut failover_hooks = self.failover_hooks;
+        let bus = events.clone();
+        failover_hooks.push(Arc::new(move |provider: &str| {
+            bus.publish(Event::CredentialFailover {
+                provider: provider.to_string(),
+            })
+        }));
+
+        Ok(LlmManager {
+            credentials: CredentialRegistry::new(
+                credential_sources,
+                secondary_sources,
+                refresh_hooks,
+                failover_hooks,
+            ),
+            http_client,
+            rate_limited: Arc::new(RwLock::new(HashMap::new())),
+            base_urls,
+            regions,
+            health_check: self.config.health_check,
+            vllm_providers: self.config.vllm_providers,
+            grammar_providers: self.config.grammar_providers,
+            tag_metadata_providers: self.config.tag_metadata_providers,
+            pricing: self.config.pricing,
+            health: Arc::new(RwLock::new(HashMap::new())),
+            outages: OutageDetector::new(self.config.outage),
+            key_check: self.config.key_check,
+            key_health: KeyHealthLog::default(),
+            faults: FaultInjector::new(self.config.faults),
+            limiter,
+            metrics: LlmMetrics::new(),
+            debug_recorder,
+            races: RaceLog::default(),
+            shadows: ShadowLog::default(),
+            file_uploads: FileUploads::new(self.config.upload_threshold_bytes),
+            schema_warnings: SchemaWarningLog::default(),
+            tool_name_collisions: ToolNameCollisionLog::default(),
+            model_changes: ModelChangeLog::default(),
+            signers,
+            openai_organization: self.config.openai_organization,
+            openai_project: self.config.openai_project,
+            default_routing: self.routing,
+            events,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_builder_registers_provider_keys() {
+        let manager = LlmManager::builder()
+            .provider_key("openrouter", "sk-or-test")
+            .provider_key("opencode-zen", "zen-test")
+            .build()
+            .expect("builder should succeed");
+
+        assert_eq!(
+            manager.get_api_key("openrouter").await.unwrap(),
+            "sk-or-test"
+        );
+        assert_eq!(
+            manager.configured_providers(),
+            vec!["openrouter", "opencode-zen"]
+        );
+        assert!(manager.get_api_key("anthropic").await.is_err());
+        assert!(manager.default_routing().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_base_url_enables_self_hosted_provider() {
+        let manager = LlmManager::builder()
+            .base_url("ollama", "http://localhost:11434")
+            .build()
+            .expect("builder should succeed");
+
+        assert_eq!(manager.configured_providers(), vec!["ollama"]);
+        assert_eq!(manager.base_url("ollama"), Some("http://localhost:11434"));
+        // Unprobed servers are assumed healthy.
+        assert!(manager.is_model_healthy("ollama/llama3.2").await);
+    }
+
+    #[tokio::test]
+    async fn test_raw_request_needs_a_known_provider() {
+        let manager = LlmManager::builder()
+            .build()
+            .expect("builder should succeed");
+
+        let error = manager
+            .raw_request("acme", reqwest::Method::GET, "/v1/models", None)
+            .await
+            .unwrap_err();
+        assert!(matches!(error, LlmError::UnknownProvider(_)));
+
+        // Hosted providers need a key before anything goes out.
+        let error = manager
+            .raw_request("openai", reqwest::Method::GET, "/v1/models", None)
+            .await
+            .unwrap_err();
+        assert!(!matches!(error, LlmError::UnknownProvider(_)));
+    }
+
+    #[tokio::test]
+    async fn test_outage_routes_around_provider() {
+        let manager = LlmManager::builder()
+            .provider_key("openai", "sk-test")
+            .build()
+            .expect("builder should succeed");
+
+        // Request errors say nothing about the provider.
+        for model in ["openai/gpt-4.1", "openai/o3"].repeat(5) {
+            manager.record_attempt(model, Some("OpenAI API error (401 Unauthorized)"));
+        }
+        assert!(manager.is_model_healthy("openai/o3").await);
+
+        for model in ["openai/gpt-4.1", "openai/o3"].repeat(3) {
+            manager.record_attempt(model, Some("OpenAI API error (503 Service Unavailable)"));
+        }
+        assert!(!manager.is_model_healthy("openai/o3").await);
+        assert!(manager.is_model_healthy("anthropic/claude-sonnet-4").await);
+        let state = manager.route_state().await;
+        assert!(state.unhealthy_providers.contains("openai"));
+        assert_eq!(manager.provider_outages().len(), 1);
+
+        manager.record_attempt("openai/o3", None);
+        assert!(manager.is_model_healthy("openai/o3").await);
+    }
+
+    #[test]
+    fn test_builder_rejects_unknown_provider() {
+        let result = LlmManager::builder().provider_key("nope", "key").build();
+        assert!(matches!(result, Err(LlmError::UnknownProvider(provider)) if provider == "nope"));
+    }
+}
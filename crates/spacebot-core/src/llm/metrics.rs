@@ -0,0 +1,468 @@
+//! In-process latency histograms for LLM requests.
+//!
+//! Tracks where time goes on a completion: waiting for a limiter slot, each
+//! individual provider attempt, the whole routed request (retries and
+//! fallbacks included), and time-to-first-token for streamed responses.
+//! Series are labeled by model and priority class.
+//!
+//! Also counts, per model, requests with their tokens, cost and failures by
+//! [`ErrorClass`], how often response parsing had to work around an
+//! unexpected shape, how much prompt compression saved, and how often a
+//! completion came back empty. Requests carrying [`RequestTags`] are also
+//! counted per tag.
+
+use crate::error::ProviderApiError;
+use crate::llm::compress::CompressionStats;
+use crate::llm::limiter::Priority;
+use crate::llm::model::ParseWarning;
+use crate::llm::routing::{is_context_overflow_error, is_rate_limit_error};
+use crate::llm::tags::{MAX_TRACKED_VALUES, OTHER_VALUE, RequestTags};
+
+use rig::completion::CompletionError;
+use serde::Serialize;
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Upper bounds (inclusive, in milliseconds) of the histogram buckets. An
+/// implicit overflow bucket catches everything slower.
+pub const LATENCY_BUCKETS_MS: &[u64] = &[
+    10, 50, 100, 250, 500, 1_000, 2_500, 5_000, 10_000, 30_000, 60_000, 120_000,
+];
+
+/// What a latency sample measures.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LatencyKind {
+    /// Time spent waiting for a limiter slot before the request went out.
+    QueueWait,
+    /// A single provider call, excluding backoff and fallback.
+    ProviderAttempt,
+    /// The full routed request including retries and fallbacks.
+    TotalRequest,
+    /// Time until the first streamed token arrived. Nothing records this
+    /// until streaming completions are implemented.
+    TimeToFirstToken,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct SeriesKey {
+    kind: LatencyKind,
+    model: String,
+    priority: Priority,
+}
+
+#[derive(Debug, Clone)]
+struct Histogram {
+    /// One count per entry in `LATENCY_BUCKETS_MS`, plus the overflow bucket.
+    buckets: Vec<u64>,
+    count: u64,
+    sum_ms: u64,
+    max_ms: u64,
+}
+
+impl Histogram {
+    fn new() -> Self {
+        Self {
+            buckets: vec![0; LATENCY_BUCKETS_MS.len() + 1],
+            count: 0,
+            sum_ms: 0,
+            max_ms: 0,
+        }
+    }
+
+    fn observe(&mut self, elapsed_ms: u64) {
+        let index = LATENCY_BUCKETS_MS
+            .iter()
+            .position(|bound| elapsed_ms <= *bound)
+            .unwrap_or(LATENCY_BUCKETS_MS.len());
+        self.buckets[index] += 1;
+        self.count += 1;
+        self.sum_ms += elapsed_ms;
+        self.max_ms = self.max_ms.max(elapsed_ms);
+    }
+}
+
+/// Point-in-time view of one histogram series.
+#[derive(Debug, Clone, Serialize)]
+pub struct HistogramSnapshot {
+    pub kind: LatencyKind,
+    pub model: String,
+    pub priority: Priority,
+    pub count: u64,
+    pub sum_ms: u64,
+    pub max_ms: u64,
+    /// Bucket upper bounds in milliseconds, matching `counts` by index. The
+    /// final count has no bound and collects the overflow.
+    pub bucket_bounds_ms: &'static [u64],
+    pub counts: Vec<u64>,
+}
+
+/// How often a model's responses needed a particular parsing workaround.
+#[derive(Debug, Clone, Serialize)]
+pub struct ParseWarningCount {
+    pub model: String,
+    pub warning: &'static str,
+    pub count: u64,
+}
+
+/// Prompt compression totals for one model.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct CompressionCount {
+    pub model: String,
+    /// Requests where compression shrank at least one part.
+    pub requests: u64,
+    pub chars_before: u64,
+    pub chars_after: u64,
+    /// Estimated at ~4 chars/token.
+    pub tokens_saved: u64,
+}
+
+/// Empty completions from one model.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct EmptyResponseCount {
+    pub model: String,
+    /// Requests whose completion came back empty.
+    pub empty: u64,
+    /// Of those, the ones a retry with a nudge got an answer for.
+    pub recovered: u64,
+}
+
+/// What kind of failure a routed request ended in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorClass {
+    RateLimit,
+    /// The provider refused the credentials (401, 403).
+    Auth,
+    ContextOverflow,
+    /// Any other request the provider rejected (4xx).
+    InvalidRequest,
+    /// The provider failed (5xx).
+    Server,
+    Timeout,
+    /// The provider answered without content.
+    EmptyResponse,
+    Other,
+}
+
+impl ErrorClass {
+    /// Classify a routed request's error.
+    pub fn of(error: &CompletionError) -> Self {
+        let message = error.to_string().to_lowercase();
+        let status = ProviderApiError::find(error).map(|provider_error| provider_error.status);
+        if status == Some(429) || is_rate_limit_error(&message) {
+            Self::RateLimit
+        } else if matches!(status, Some(401 | 403)) {
+            Self::Auth
+        } else if is_context_overflow_error(&message) {
+            Self::ContextOverflow
+        } else if matches!(status, Some(500..=599)) {
+            Self::Server
+        } else if matches!(status, Some(400..=499)) {
+            Self::InvalidRequest
+        } else if message.contains("timeout") || message.contains("timed out") {
+            Self::Timeout
+        } else if message.contains("empty response") {
+            Self::EmptyResponse
+        } else {
+            Self::Other
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::RateLimit => "rate_limit",
+            Self::Auth => "auth",
+            Self::ContextOverflow => "context_overflow",
+            Self::InvalidRequest => "invalid_request",
+            Self::Server => "server",
+            Self::Timeout => "timeout",
+            Self::EmptyResponse => "empty_response",
+            Self::Other => "other",
+        }
+    }
+}
+
+/// Routed requests to one model since startup.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct UsageCount {
+    pub model: String,
+    /// Answered and failed requests.
+    pub requests: u64,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    /// Summed over responses whose model has a price.
+    pub cost_usd: f64,
+    /// Failed requests by [`ErrorClass`].
+    pub errors: BTreeMap<String, u64>,
+}
+
+/// Routed requests carrying one tag since startup, over every model.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct TagUsageCount {
+    pub key: String,
+    /// The tag's value, or `other` for values of a key seen after its first
+    /// [`MAX_TRACKED_VALUES`].
+    pub value: String,
+    /// Answered and failed requests.
+    pub requests: u64,
+    pub failed: u64,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    /// Summed over responses whose model has a price.
+    pub cost_usd: f64,
+}
+
+/// Request counters, latency histograms, parse warning counters,
+/// compression totals and empty response counts shared by every model built
+/// from the same manager.
+#[derive(Debug, Default)]
+pub struct LlmMetrics {
+    usage: Mutex<HashMap<String, UsageCount>>,
+    tags: Mutex<HashMap<(String, String), TagUsageCount>>,
+    series: Mutex<HashMap<SeriesKey, Histogram>>,
+    parse_warnings: Mutex<HashMap<(String, ParseWarning), u64>>,
+    compression: Mutex<HashMap<String, CompressionCount>>,
+    empty_responses: Mutex<HashMap<String, EmptyResponseCount>>,
+}
+
+impl LlmMetrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Count a routed request to `model` that was answered.
+    pub fn count_request(
+        &self,
+        model: &str,
+        input_tokens: u64,
+        output_tokens: u64,
+        cost_usd: Option<f64>,
+    ) {
+        let mut usage = self.lock_usage();
+        let count = usage
+            .entry(model.to_string())
+            .or_insert_with(|| UsageCount {
+                model: model.to_string(),
+                ..Default::default()
+            });
+        count.requests += 1;
+        count.input_tokens += input_tokens;
+        count.output_tokens += output_tokens;
+        count.cost_usd += cost_usd.unwrap_or_default();
+    }
+
+    /// Count a routed request to `model` that failed.
+    pub fn count_request_error(&self, model: &str, class: ErrorClass) {
+        let mut usage = self.lock_usage();
+        let count = usage
+            .entry(model.to_string())
+            .or_insert_with(|| UsageCount {
+                model: model.to_string(),
+                ..Default::default()
+            });
+        count.requests += 1;
+        *count.errors.entry(class.as_str().to_string()).or_default() += 1;
+    }
+
+    /// Request counters, sorted by model.
+    pub fn usage_counts(&self) -> Vec<UsageCount> {
+        let mut counts: Vec<UsageCount> = self.lock_usage().values().cloned().collect();
+        counts.sort_by(|a, b| a.model.cmp(&b.model));
+        counts
+    }
+
+    /// Count a routed request under each of its tags. `usage` is the
+    /// answered request's tokens and cost, None if it failed.
+    pub fn count_tagged(&self, tags: &RequestTags, usage: Option<(u64, u64, Option<f64>)>) {
+        if tags.is_empty() {
+            return;
+        }
+        let mut counts = self
+            .tags
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        for (key, value) in tags.iter() {
+            let tracked = counts.contains_key(&(key.to_string(), value.to_string()))
+                || counts.keys().filter(|(other, _)| other == key).count() < MAX_TRACKED_VALUES;
+            let value = if tracked { value } else { OTHER_VALUE };
+            let count = counts
+                .entry((key.to_string(), value.to_string()))
+                .or_insert_with(|| TagUsageCount {
+                    key: key.to_string(),
+                    value: value.to_string(),
+                    ..Default::default()
+                });
+            count.requests += 1;
+            match usage {
+                Some((input_tokens, output_tokens, cost_usd)) => {
+                    count.input_tokens += input_tokens;
+                    count.output_tokens += output_tokens;
+                    count.cost_usd += cost_usd.unwrap_or_default();
+                }
+                None => count.failed += 1,
+            }
+        }
+    }
+
+    /// Per-tag request counters, sorted by key then value.
+    pub fn tag_usage_counts(&self) -> Vec<TagUsageCount> {
+        let mut counts: Vec<TagUsageCount> = self
+            .tags
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .values()
+            .cloned()
+            .collect();
+        counts.sort_by(|a, b| (&a.key, &a.value).cmp(&(&b.key, &b.value)));
+        counts
+    }
+
+    /// Record one latency sample.
+    pub fn observe(&self, kind: LatencyKind, model: &str, priority: Priority, elapsed: Duration) {
+        let key = SeriesKey {
+            kind,
+            model: model.to_string(),
+            priority,
+        };
+        let elapsed_ms = elapsed.as_millis().min(u64::MAX as u128) as u64;
+        self.lock_series()
+            .entry(key)
+            .or_insert_with(Histogram::new)
+            .observe(elapsed_ms);
+    }
+
+    /// Copy out every series, sorted by kind, model, then priority.
+    pub fn snapshot(&self) -> Vec<HistogramSnapshot> {
+        let mut snapshots: Vec<HistogramSnapshot> = self
+            .lock_series()
+            .iter()
+            .map(|(key, histogram)| HistogramSnapshot {
+                kind: key.kind,
+                model: key.model.clone(),
+                priority: key.priority,
+                count: histogram.count,
+                sum_ms: histogram.sum_ms,
+                max_ms: histogram.max_ms,
+                bucket_bounds_ms: LATENCY_BUCKETS_MS,
+                counts: histogram.buckets.clone(),
+            })
+            .collect();
+        snapshots.sort_by(|a, b| {
+            (a.kind as u8, &a.model, a.priority as u8).cmp(&(
+                b.kind as u8,
+                &b.model,
+                b.priority as u8,
+            ))
+        });
+        snapshots
+    }
+
+    /// Count a response that needed a parsing workaround.
+    pub fn count_parse_warning(&self, model: &str, warning: ParseWarning) {
+        *self
+            .lock_parse_warnings()
+            .entry((model.to_string(), warning))
+            .or_default() += 1;
+    }
+
+    /// Parse warning counts, sorted by model then warning.
+    pub fn parse_warning_counts(&self) -> Vec<ParseWarningCount> {
+        let mut counts: Vec<ParseWarningCount> = self
+            .lock_parse_warnings()
+            .iter()
+            .map(|((model, warning), count)| ParseWarningCount {
+                model: model.clone(),
+                warning: warning.as_str(),
+                count: *count,
+            })
+            .collect();
+        counts.sort_by(|a, b| (&a.model, a.warning).cmp(&(&b.model, b.warning)));
+        counts
+    }
+
+    /// Add one compressed request to the model's totals.
+    pub fn record_compression(&self, model: &str, stats: CompressionStats) {
+        let mut compression = self
+            .compression
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let count = compression
+            .entry(model.to_string())
+            .or_insert_with(|| CompressionCount {
+                model: model.to_string(),
+                ..Default::default()
+            });
+        count.requests += 1;
+        count.chars_before += stats.chars_before as u64;
+        count.chars_after += stats.chars_after as u64;
+        count.tokens_saved += stats.tokens_saved() as u64;
+    }
+
+    /// Compression totals, sorted by model.
+    pub fn compression_counts(&self) -> Vec<CompressionCount> {
+        let mut counts: Vec<CompressionCount> = self
+            .compression
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .values()
+            .cloned()
+            .collect();
+        counts.sort_by(|a, b| a.model.cmp(&b.model));
+        counts
+    }
+
+    /// Count an empty completion from `model`, and whether a nudge then got
+    /// an answer out of it.
+    pub fn count_empty_response(&self, model: &str, recovered: bool) {
+        let mut counts = self
+            .empty_responses
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let count = counts
+            .entry(model.to_string())
+            .or_insert_with(|| EmptyResponseCount {
+                model: model.to_string(),
+                ..Default::default()
+            });
+        count.empty += 1;
+        if recovered {
+            count.recovered += 1;
+        }
+    }
+
+    /// Empty response counts, sorted by model.
+    pub fn empty_response_counts(&self) -> Vec<EmptyResponseCount> {
+        let mut counts: Vec<EmptyResponseCount> = self
+            .empty_responses
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .values()
+            .cloned()
+            .collect();
+        counts.sort_by(|a, b| a.model.cmp(&b.model));
+        counts
+    }
+
+    fn lock_parse_warnings(
+        &self,
+    ) -> std::sync::MutexGuard<'_, HashMap<(String, ParseWarning), u64>> {
+        self.parse_warnings
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn lock_usage(&self) -> std::sync::MutexGuard<'_, HashMap<String, UsageCount>> {
+        self.usage
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn lock_series(&self) -> std::sync::MutexGuard<'_, HashMap<SeriesKey, Histogram>> {
+        // Histograms are plain counters; a poisoned lock still holds usable data.
+        self.series
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
@@ -0,0 +1,3582 @@
+//! SpacebotModel: Custom CompletionModel implementation that routes through LlmManager.
+
+use crate::error::ProviderApiError;
+use crate::events::Event;
+use crate::llm::candidates::{self, Candidate, MAX_CANDIDATES};
+use crate::llm::compress::PromptCompressor;
+use crate::llm::empty;
+use crate::llm::faults::FaultKind;
+use crate::llm::generation::GenerationProfile;
+use crate::llm::grammar;
+use crate::llm::latency_budget::LatencyBudget;
+use crate::llm::limiter::Priority;
+use crate::llm::manager::LlmManager;
+use crate::llm::metrics::{ErrorClass, LatencyKind};
+use crate::llm::model_changes::{deprecation_notice, is_deprecation_error};
+use crate::llm::output_cap::OutputCap;
+use crate::llm::prompt_tokens::PromptTokens;
+use crate::llm::race::{
+    self, JUDGE_PREAMBLE, MAX_RACE_CANDIDATES, PICKED_BY_AGREEMENT, RaceCandidate, RaceRecord,
+};
+use crate::llm::recorder::{AttemptOutcome, render_output};
+use crate::llm::routing::{
+    self, MAX_FALLBACK_ATTEMPTS, MAX_RETRIES_PER_MODEL, RETRY_BASE_DELAY_MS, RaceConfig,
+    RoutingConfig, ShadowConfig, VllmOptions,
+};
+use crate::llm::sampling::{self, MAX_SAMPLES, SamplingConfig};
+use crate::llm::schema::{self, SchemaDialect};
+use crate::llm::shadow::{self, ShadowRecord};
+use crate::llm::tags::RequestTags;
+use crate::llm::tool_filter::ToolFilter;
+use crate::llm::tool_names::{ToolNameCodec, ToolNameRules};
+use crate::llm::uploads::ANTHROPIC_FILES_BETA;
+use crate::redact::truncate_redacted;
+
+use rig::completion::{self, CompletionError, CompletionModel, CompletionRequest, GetTokenUsage};
+use rig::message::{
+    AssistantContent, DocumentSourceKind, Image, Message, MimeType, Text, ToolCall, ToolFunction,
+    UserContent,
+};
+use rig::one_or_many::OneOrMany;
+use rig::streaming::StreamingCompletionResponse;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Most of a provider's error body or message kept in an error, so an HTML
+/// error page doesn't end up in logs whole.
+const MAX_ERROR_BODY_BYTES: usize = 500;
+
+/// Tool result images sent to Anthropic per request. Older ones become a
+/// placeholder, so a long computer-use session doesn't resend every
+/// screenshot it has taken.
+const MAX_TOOL_RESULT_IMAGES: usize = 3;
+
+/// Raw provider response. Wraps the JSON so Rig can carry it through.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RawResponse {
+    pub body: serde_json::Value,
+    /// Id of the routed request that produced this response.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+    /// The model that answered, after any fallback.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    /// The provider's own id for the request, for its support.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provider_request_id: Option<String>,
+    /// Estimated cost in USD, when the answering model has a price. For a
+    /// race, what every candidate and the judge cost together.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cost_usd: Option<f64>,
+    /// Sampling seed sent to the provider that answered. None when no seed
+    /// was set or the provider has no seed parameter.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u64>,
+    /// Whether a fallback answered because the routed model failed or was
+    /// unavailable.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub fallback: bool,
+    /// Estimated tokens of the request's preamble and tool schemas, as sent.
+    #[serde(default)]
+    pub prompt_tokens: PromptTokens,
+    /// Every answer drawn when several were asked for, `choice` first.
+    /// Empty for a single answer.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub candidates: Vec<Candidate>,
+}
+
+impl RawResponse {
+    pub fn new(body: serde_json::Value) -> Self {
+        Self {
+            body,
+            request_id: None,
+            model: None,
+            provider_request_id: None,
+            cost_usd: None,
+            seed: None,
+            fallback: false,
+            prompt_tokens: PromptTokens::default(),
+            candidates: Vec::new(),
+        }
+    }
+}
+
+/// Streaming response placeholder. Streaming will be implemented per-provider
+/// when we wire up SSE parsing.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RawStreamingResponse {
+    pub body: serde_json::Value,
+}
+
+impl GetTokenUsage for RawStreamingResponse {
+    fn token_usage(&self) -> Option<completion::Usage> {
+        None
+    }
+}
+
+/// Custom completion model that routes through LlmManager.
+///
+/// Optionally holds a RoutingConfig for fallback behavior. When present,
+/// completion() will try fallback models on retriable errors.
+#[derive(Clone)]
+pub struct SpacebotModel {
+    llm_manager: Arc<LlmManager>,
+    model_name: String,
+    provider: String,
+    full_model_name: String,
+    routing: Option<RoutingConfig>,
+    priority: Priority,
+    /// vLLM extras for this model, from the routing config.
+    vllm: Option<VllmOptions>,
+    /// `anthropic-beta` flags for this model, from the routing config.
+    anthropic_betas: Vec<String>,
+    /// The only tools sent with each request, when set.
+    allowed_tools: Option<HashSet<String>>,
+    /// Tools never sent, whatever `allowed_tools` says.
+    denied_tools: HashSet<String>,
+    /// Trims the tool set sent with each request to the relevant ones.
+    tool_filter: Option<ToolFilter>,
+    /// Shrinks tool results and long pastes before each request.
+    compressor: Option<PromptCompressor>,
+    /// Draws several completions per request and keeps the consensus.
+    sampling: Option<SamplingConfig>,
+    /// Answers drawn per request, all kept on the response.
+    candidates: usize,
+    /// Sampling seed sent to providers that take one.
+    seed: Option<u64>,
+    /// Temperature and provider parameters from the agent's profile.
+    generation: Option<GenerationProfile>,
+    /// Output token cap, below the provider's own limit.
+    output_cap: Option<OutputCap>,
+    /// Sent as a user message to retry a request whose completion came
+    /// back empty.
+    empty_nudge: Option<String>,
+    /// The turn's latency budget, which sends requests to a faster model
+    /// once the turn is short on time.
+    latency_budget: Option<Arc<LatencyBudget>>,
+    /// Business dimensions the request is counted under, and sent as
+    /// metadata to providers that take it.
+    tags: RequestTags,
+    /// Id of the routed request this model is serving, for debug recording.
+    request_id: Option<String>,
+    /// Regional endpoint this attempt is pinned to, by index.
+    region: Option<usize>,
+    /// Mark the preamble cacheable on providers that cache on request.
+    prompt_cache: bool,
+}
+
+impl SpacebotModel {
+    pub fn provider(&self) -> &str {
+        &self.provider
+    }
+    pub fn model_name(&self) -> &str {
+        &self.model_name
+    }
+    pub fn full_model_name(&self) -> &str {
+        &self.full_model_name
+    }
+
+    /// Attach routing config for fallback behavior.
+    pub fn with_routing(mut self, routing: RoutingConfig) -> Self {
+        self.vllm = routing.vllm_options(&self.full_model_name).cloned();
+        self.anthropic_betas = routing.anthropic_betas(&self.full_model_name).to_vec();
+        self.routing = Some(routing);
+        self
+    }
+
+    /// Never send tools outside `allowed`. None sends them all.
+    pub fn with_allowed_tools(mut self, allowed: Option<HashSet<String>>) -> Self {
+        self.allowed_tools = allowed;
+        self
+    }
+
+    /// Never send the tools in `denied`.
+    pub fn with_denied_tools(mut self, denied: HashSet<String>) -> Self {
+        self.denied_tools = denied;
+        self
+    }
+
+    /// Send only the tools `filter` picks as relevant to each turn.
+    pub fn with_tool_filter(mut self, filter: Option<ToolFilter>) -> Self {
+        self.tool_filter = filter;
+        self
+    }
+
+    /// Compress tool results and long user messages before sending.
+    pub fn with_compressor(mut self, compressor: Option<PromptCompressor>) -> Self {
+        self.compressor = compressor;
+        self
+    }
+
+    /// Sample each completion several times and keep the consensus answer.
+    pub fn with_sampling(mut self, sampling: Option<SamplingConfig>) -> Self {
+        self.sampling = sampling;
+        self
+    }
+
+    /// Draw `count` answers per request and keep every one on the
+    /// response's `candidates`. Ignored while sampling is on.
+    pub fn with_candidates(mut self, count: usize) -> Self {
+        self.candidates = count.clamp(1, MAX_CANDIDATES);
+        self
+    }
+
+    /// Ask providers that support it to sample deterministically with
+    /// `seed`. Fallback and race models get the same seed; consensus
+    /// samples get consecutive ones so they still differ.
+    pub fn with_seed(mut self, seed: Option<u64>) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Send a generation profile's parameters with each request.
+    pub fn with_generation(mut self, profile: Option<GenerationProfile>) -> Self {
+        self.generation = profile;
+        self
+    }
+
+    /// Clamp each request's `max_tokens` to `cap` and trim completions that
+    /// reach it. Fallback, race and shadow models get the clamped request.
+    pub fn with_output_cap(mut self, cap: Option<OutputCap>) -> Self {
+        self.output_cap = cap;
+        self
+    }
+
+    /// Retry a request once with `nudge` added when its completion comes
+    /// back empty. None lets empty completions through.
+    pub fn with_empty_retry(mut self, nudge: Option<String>) -> Self {
+        self.empty_nudge = nudge;
+        self
+    }
+
+    /// Send requests to the budget's faster model once the turn it times
+    /// is short on time.
+    pub fn with_latency_budget(mut self, budget: Option<Arc<LatencyBudget>>) -> Self {
+        self.latency_budget = budget;
+        self
+    }
+
+    /// Count requests under `tags` and send them as metadata to the
+    /// providers in `tag_metadata_providers`. Fallback models get the same
+    /// tags.
+    pub fn with_tags(mut self, tags: RequestTags) -> Self {
+        self.tags = tags;
+        self
+    }
+
+    /// Send vLLM extras (guided decoding, a LoRA adapter) with each request.
+    /// Ignored unless the provider is flagged as vLLM.
+    pub fn with_vllm_options(mut self, options: Option<VllmOptions>) -> Self {
+        self.vllm = options;
+        self
+    }
+
+    /// Send these `anthropic-beta` flags with each request. Ignored by other
+    /// providers.
+    pub fn with_anthropic_betas(mut self, betas: Vec<String>) -> Self {
+        self.anthropic_betas = betas;
+        self
+    }
+
+    /// Ask providers with explicit prompt caching (Anthropic) to cache the
+    /// preamble. Others cache repeated prefixes on their own, if at all.
+    pub fn with_prompt_cache(mut self, enabled: bool) -> Self {
+        self.prompt_cache = enabled;
+        self
+    }
+
+    /// Set the scheduling class used when requests queue for a slot.
+    pub fn with_priority(mut self, priority: Priority) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// Tag this model with the id of the routed request it is serving.
+    fn for_request(mut self, request_id: &str) -> Self {
+        self.request_id = Some(request_id.to_string());
+        self
+    }
+
+    /// The chat completions URL for a provider: the region this attempt is
+    /// pinned to, else its self-hosted server when one is configured,
+    /// otherwise the hosted `default` endpoint.
+    fn endpoint(&self, provider_id: &str, default: &str) -> String {
+        let region = self.region.and_then(|index| {
+            self.llm_manager
+                .regions(provider_id)
+                .and_then(|regions| regions.endpoint(index))
+        });
+        match region.or_else(|| self.llm_manager.base_url(provider_id)) {
+            Some(base_url) => format!("{}/v1/chat/completions", base_url.trim_end_matches('/')),
+            None => default.to_string(),
+        }
+    }
+
+    /// Add this model's vLLM extras to a chat completions body when its
+    /// provider is a vLLM server.
+    fn apply_vllm_options(&self, provider_id: &str, body: &mut serde_json::Value) {
+        let Some(options) = &self.vllm else {
+            return;
+        };
+        if self.llm_manager.is_vllm(provider_id) {
+            options.apply(body);
+        } else {
+            tracing::debug!(
+                model = %self.full_model_name,
+                "vLLM options configured but provider isn't flagged as vLLM, ignoring"
+            );
+        }
+    }
+
+    /// Add this model's seed to a chat completions body, under the name the
+    /// provider uses for it.
+    fn apply_seed(&self, provider_id: &str, body: &mut serde_json::Value) {
+        if let (Some(seed), Some(field)) = (self.seed, seed_field(provider_id)) {
+            body[field] = serde_json::json!(seed);
+        }
+    }
+
+    /// Add this model's tags to a chat completions body as `metadata`, for
+    /// providers configured to take them.
+    fn apply_tags(&self, provider_id: &str, body: &mut serde_json::Value) {
+        if !self.tags.is_empty() && self.llm_manager.sends_tag_metadata(provider_id) {
+            body["metadata"] = self.tags.to_json();
+        }
+    }
+
+    /// Add this model's generation profile to a chat completions body.
+    fn apply_generation(&self, provider_id: &str, body: &mut serde_json::Value) {
+        if let Some(profile) = &self.generation {
+            profile.apply(provider_id, body);
+        }
+    }
+
+    /// Ask for every candidate in one request when the provider takes `n`.
+    /// Grammar-constrained replies are read back one choice at a time, so
+    /// those providers get one candidate per request instead.
+    fn apply_candidates(
+        &self,
+        provider_id: &str,
+        request: &CompletionRequest,
+        body: &mut serde_json::Value,
+    ) {
+        let constrained =
+            !request.tools.is_empty() && self.llm_manager.uses_tool_grammar(provider_id);
+        if self.candidates > 1
+            && !constrained
+            && candidates::supports_n(provider_id, self.llm_manager.is_vllm(provider_id))
+        {
+            body["n"] = serde_json::json!(self.candidates);
+        }
+    }
+
+    /// Swap the request's tools for a grammar when the provider is flagged
+    /// for grammar-constrained tool calls. Returns whether it was, so the
+    /// reply can be read back into a tool call.
+    fn apply_tool_grammar(
+        &self,
+        provider_id: &str,
+        request: &CompletionRequest,
+        body: &mut serde_json::Value,
+    ) -> bool {
+        if request.tools.is_empty() || !self.llm_manager.uses_tool_grammar(provider_id) {
+            return false;
+        }
+        let field = grammar::grammar_field(self.llm_manager.is_vllm(provider_id));
+        grammar::constrain(body, &request.tools, field);
+        true
+    }
+
+    /// The provider's API key. Self-hosted servers usually don't check one,
+    /// so a missing key is only an error for hosted providers.
+    async fn optional_api_key(&self, provider_id: &str) -> Result<Option<String>, CompletionError> {
+        match self.llm_manager.get_api_key(provider_id).await {
+            Ok(api_key) => Ok(Some(api_key)),
+            Err(_) if self.llm_manager.base_url(provider_id).is_some() => Ok(None),
+            Err(error) => Err(CompletionError::ProviderError(error.to_string())),
+        }
+    }
+
+    /// Keep the raw exchange for this attempt when debug recording is on,
+    /// and as a test fixture when built with the `record` feature.
+    fn record_exchange(
+        &self,
+        body: &serde_json::Value,
+        status: reqwest::StatusCode,
+        provider_request_id: Option<&str>,
+        response_text: &str,
+    ) {
+        tracing::debug!(
+            model = %self.full_model_name,
+            request_id = self.request_id.as_deref(),
+            provider_request_id,
+            %status,
+            "provider responded"
+        );
+        #[cfg(feature = "record")]
+        crate::llm::fixtures::record(
+            &self.provider,
+            &self.full_model_name,
+            body,
+            status.as_u16(),
+            response_text,
+        );
+        if let Some(request_id) = &self.request_id {
+            self.llm_manager.debug_recorder().record_exchange(
+                request_id,
+                &self.full_model_name,
+                body,
+                status.as_u16(),
+                provider_request_id,
+                response_text,
+            );
+        }
+    }
+
+    /// Tell the credential registry when the provider rejects our key, so
+    /// it can fail over to the secondary key or re-fetch a rotated one.
+    async fn check_credential_rejected(&self, status: reqwest::StatusCode) {
+        if status == reqwest::StatusCode::UNAUTHORIZED || status == reqwest::StatusCode::FORBIDDEN {
+            self.llm_manager.credentials().reject(&self.provider).await;
+        }
+    }
+
+    /// Surface shape problems the parser worked around.
+    fn report_parse_warnings(&self, warnings: &[ParseWarning]) {
+        for warning in warnings {
+            tracing::warn!(
+                model = %self.full_model_name,
+                %warning,
+                "provider response deviated from the expected shape"
+            );
+            self.llm_manager
+                .metrics()
+                .count_parse_warning(&self.full_model_name, *warning);
+        }
+    }
+
+    /// A tool's parameter schema in the dialect this model accepts.
+    fn tool_parameters(&self, tool: &completion::ToolDefinition) -> serde_json::Value {
+        let dialect = SchemaDialect::for_model(&self.provider, &self.model_name);
+        let mut warnings = Vec::new();
+        let parameters = schema::normalize(dialect, &tool.parameters, &mut warnings);
+        self.llm_manager
+            .schema_warnings()
+            .report(&self.full_model_name, &tool.name, &warnings);
+        parameters
+    }
+
+    /// Direct call to the provider (no fallback logic).
+    async fn attempt_completion(
+        &self,
+        request: &CompletionRequest,
+    ) -> Result<completion::CompletionResponse<RawResponse>, CompletionError> {
+        let metrics = self.llm_manager.metrics();
+        let queued_at = Instant::now();
+        let _permit = self.llm_manager.acquire_request_slot(self.priority).await;
+        metrics.observe(
+            LatencyKind::QueueWait,
+            &self.full_model_name,
+            self.priority,
+            queued_at.elapsed(),
+        );
+
+        let started_at = Instant::now();
+        let credentials = self.llm_manager.credentials();
+        let failover_generation = credentials.failover_generation(&self.provider);
+        let mut result = self.call_with_faults(request).await;
+        if result.is_err() && credentials.failover_generation(&self.provider) != failover_generation
+        {
+            tracing::info!(model = %self.full_model_name, "retrying with secondary API key");
+            result = self.call_regions(request).await;
+        }
+        metrics.observe(
+            LatencyKind::ProviderAttempt,
+            &self.full_model_name,
+            self.priority,
+            started_at.elapsed(),
+        );
+        let error = result.as_ref().err().map(|error| error.to_string());
+        self.llm_manager
+            .record_attempt(&self.full_model_name, error.as_deref());
+        match (&result, &error) {
+            (Ok(response), _) => {
+                if let Some(served) = response.raw_response.body["model"].as_str() {
+                    self.llm_manager
+                        .report_served_model(&self.full_model_name, served);
+                }
+            }
+            (Err(_), Some(error)) if is_deprecation_error(error) => {
+                self.llm_manager
+                    .report_deprecation(&self.full_model_name, error);
+            }
+            _ => {}
+        }
+
+        if let Some(request_id) = &self.request_id {
+            let outcome = match &result {
+                Ok(response) => AttemptOutcome::Parsed {
+                    summary: format!(
+                        "{} content item(s), {} input / {} output tokens",
+                        response.choice.len(),
+                        response.usage.input_tokens,
+                        response.usage.output_tokens
+                    ),
+                },
+                Err(error) => AttemptOutcome::Failed {
+                    error: error.to_string(),
+                    provider_error: ProviderApiError::find(error).cloned(),
+                },
+            };
+            self.llm_manager
+                .debug_recorder()
+                .record_outcome(request_id, outcome);
+        }
+
+        result
+    }
+
+    /// [`call_regions`](Self::call_regions), unless a fault rule for this
+    /// model fires, in which case the request fails or is held up the way
+    /// the rule says.
+    async fn call_with_faults(
+        &self,
+        request: &CompletionRequest,
+    ) -> Result<completion::CompletionResponse<RawResponse>, CompletionError> {
+        let Some(fault) = self.llm_manager.faults().draw(&self.full_model_name) else {
+            return self.call_regions(request).await;
+        };
+        tracing::warn!(
+            model = %self.full_model_name,
+            fault = fault.kind.name(),
+            "injecting fault"
+        );
+        if matches!(fault.kind, FaultKind::Timeout | FaultKind::SlowStream) {
+            tokio::time::sleep(fault.delay()).await;
+        }
+        match fault.kind.error(&self.provider) {
+            Some(error) => Err(error),
+            None => self.call_regions(request).await,
+        }
+    }
+
+    /// Send one request to this model's provider, moving on through its
+    /// regions on retriable errors when it has several. Only the last
+    /// region's error is returned.
+    async fn call_regions(
+        &self,
+        request: &CompletionRequest,
+    ) -> Result<completion::CompletionResponse<RawResponse>, CompletionError> {
+        let Some(regions) = self.llm_manager.regions(&self.provider) else {
+            return self.call_provider(request).await;
+        };
+
+        let mut last_error = None;
+        for index in regions.candidates(Instant::now()) {
+            let pinned = Self {
+                region: Some(index),
+                ..self.clone()
+            };
+            match pinned.call_provider(request).await {
+                Ok(response) => {
+                    regions.report_success(index, Instant::now());
+                    return Ok(response);
+                }
+                Err(error) if routing::is_retriable_error(&error.to_string()) => {
+                    tracing::warn!(
+                        model = %self.full_model_name,
+                        endpoint = regions.endpoint(index).unwrap_or_default(),
+                        %error,
+                        "regional endpoint failed"
+                    );
+                    regions.report_failure(index, Instant::now());
+                    last_error = Some(error);
+                }
+                Err(error) => return Err(error),
+            }
+        }
+        Err(last_error.unwrap_or_else(|| {
+            CompletionError::ProviderError(format!("no regions for {}", self.provider))
+        }))
+    }
+
+    /// Send one request to this model's provider, under tool names it
+    /// accepts. The request is only copied when a name has to change.
+    async fn call_provider(
+        &self,
+        request: &CompletionRequest,
+    ) -> Result<completion::CompletionResponse<RawResponse>, CompletionError> {
+        let dialect = SchemaDialect::for_model(&self.provider, &self.model_name);
+        let codec = ToolNameCodec::new(
+            ToolNameRules::for_dialect(dialect),
+            &request.tools,
+            &request.chat_history,
+        );
+        if codec.is_identity() {
+            return self.dispatch(request).await;
+        }
+        self.llm_manager
+            .tool_name_collisions()
+            .report(&self.full_model_name, codec.collisions());
+        let mut response = self.dispatch(&codec.encode_request(request)).await?;
+        codec.decode_response(&mut response);
+        Ok(response)
+    }
+
+    /// Dispatch one request to this model's provider.
+    async fn dispatch(
+        &self,
+        request: &CompletionRequest,
+    ) -> Result<completion::CompletionResponse<RawResponse>, CompletionError> {
+        match self.provider.as_str() {
+            "anthropic" => self.call_anthropic(request).await,
+            "openai" => self.call_openai(request).await,
+            "openrouter" => self.call_openrouter(request).await,
+            "ollama" => self.call_ollama(request).await,
+            "zhipu" => self.call_zhipu(request).await,
+            "groq" => self.call_groq(request).await,
+            "together" => self.call_together(request).await,
+            "fireworks" => self.call_fireworks(request).await,
+            "deepseek" => self.call_deepseek(request).await,
+            "xai" => self.call_xai(request).await,
+            "mistral" => self.call_mistral(request).await,
+            "opencode-zen" => self.call_opencode_zen(request).await,
+            other => Err(CompletionError::ProviderError(format!(
+                "unknown provider: {other}"
+            ))),
+        }
+    }
+
+    /// Try a model with retries and exponential backoff on transient errors.
+    ///
+    /// Returns `Ok(response)` on success, or `Err((last_error, was_rate_limit))`
+    /// after exhausting retries. `was_rate_limit` indicates the final failure was
+    /// a 429/rate-limit (as opposed to a timeout or server error), so the caller
+    /// can decide whether to record cooldown.
+    ///
+    /// Every attempt borrows the same request. Only the provider body is
+    /// built per attempt, and it's dropped before the next one, so retries
+    /// and fallbacks don't hold extra copies of large (image-heavy) prompts.
+    async fn attempt_with_retries(
+        &self,
+        model_name: &str,
+        request: &CompletionRequest,
+        request_id: &str,
+    ) -> Result<completion::CompletionResponse<RawResponse>, (CompletionError, bool)> {
+        let model = if model_name == self.full_model_name {
+            self.clone()
+        } else {
+            SpacebotModel::make(&self.llm_manager, model_name)
+                .with_priority(self.priority)
+                .with_seed(self.seed)
+                .with_tags(self.tags.clone())
+                .with_generation(self.generation.clone())
+                .with_prompt_cache(self.prompt_cache)
+                .with_vllm_options(
+                    self.routing
+                        .as_ref()
+                        .and_then(|routing| routing.vllm_options(model_name))
+                        .cloned(),
+                )
+                .with_anthropic_betas(
+                    self.routing
+                        .as_ref()
+                        .map(|routing| routing.anthropic_betas(model_name).to_vec())
+                        .unwrap_or_default(),
+                )
+        }
+        .for_request(request_id);
+
+        let mut last_error = None;
+        for attempt in 0..MAX_RETRIES_PER_MODEL {
+            if attempt > 0 {
+                let delay_ms = RETRY_BASE_DELAY_MS * 2u64.pow((attempt - 1) as u32);
+                tracing::debug!(
+                    model = %model_name,
+                    attempt = attempt + 1,
+                    delay_ms,
+                    "retrying after backoff"
+                );
+                tokio::time::sleep(std::time::Duration::from_millis(delay_ms)).await;
+            }
+
+            match model.attempt_completion(request).await {
+                Ok(mut response) => {
+                    response.raw_response.model = Some(model_name.to_string());
+                    response.raw_response.seed =
+                        model.seed.filter(|_| seed_field(&model.provider).is_some());
+                    return Ok(response);
+                }
+                Err(error) => {
+                    let error_str = error.to_string();
+                    if !routing::is_retriable_error(&error_str) {
+                        // Non-retriable (auth error, bad request, etc) — bail immediately
+                        return Err((error, false));
+                    }
+                    tracing::warn!(
+                        model = %model_name,
+                        attempt = attempt + 1,
+                        %error,
+                        "retriable error"
+                    );
+                    last_error = Some(error_str);
+                }
+            }
+        }
+
+        let error_str = last_error.unwrap_or_default();
+        let was_rate_limit = routing::is_rate_limit_error(&error_str);
+        Err((
+            CompletionError::ProviderError(format!(
+                "{model_name} failed after {MAX_RETRIES_PER_MODEL} attempts: {error_str}"
+            )),
+            was_rate_limit,
+        ))
+    }
+}
+
+impl CompletionModel for SpacebotModel {
+    type Response = RawResponse;
+    type StreamingResponse = RawStreamingResponse;
+    type Client = Arc<LlmManager>;
+
+    fn make(client: &Self::Client, model: impl Into<String>) -> Self {
+        let full_name = model.into();
+
+        // OpenRouter model names have the form "openrouter/provider/model",
+        // so split on the first "/" only and keep the rest as the model name.
+        let (provider, model_name) = if let Some(rest) = full_name.strip_prefix("openrouter/") {
+            ("openrouter".to_string(), rest.to_string())
+        } else if let Some((p, m)) = full_name.split_once('/') {
+            (p.to_string(), m.to_string())
+        } else {
+            ("anthropic".to_string(), full_name.clone())
+        };
+
+        let full_model_name = format!("{provider}/{model_name}");
+        let routing = client.default_routing().cloned();
+
+        Self {
+            llm_manager: client.clone(),
+            model_name,
+            provider,
+            vllm: routing
+                .as_ref()
+                .and_then(|routing| routing.vllm_options(&full_model_name))
+                .cloned(),
+            anthropic_betas: routing
+                .as_ref()
+                .map(|routing| routing.anthropic_betas(&full_model_name).to_vec())
+                .unwrap_or_default(),
+            full_model_name,
+            routing,
+            priority: Priority::default(),
+            allowed_tools: None,
+            denied_tools: HashSet::new(),
+            tool_filter: None,
+            compressor: None,
+            sampling: None,
+            candidates: 1,
+            seed: None,
+            generation: None,
+            output_cap: None,
+            empty_nudge: None,
+            latency_budget: None,
+            tags: RequestTags::default(),
+            request_id: None,
+            region: None,
+            prompt_cache: false,
+        }
+    }
+
+    async fn completion(
+        &self,
+        mut request: CompletionRequest,
+    ) -> Result<completion::CompletionResponse<RawResponse>, CompletionError> {
+        if let Some(allowed) = &self.allowed_tools {
+            request.tools.retain(|tool| allowed.contains(&tool.name));
+        }
+        if !self.denied_tools.is_empty() {
+            request
+                .tools
+                .retain(|tool| !self.denied_tools.contains(&tool.name));
+        }
+        if let Some(filter) = &self.tool_filter {
+            filter.apply(&mut request).await;
+        }
+        if let Some(compressor) = &self.compressor {
+            let stats = compressor.apply(&mut request);
+            if stats.chars_before > 0 {
+                tracing::debug!(
+                    model = %self.full_model_name,
+                    tokens_saved = stats.tokens_saved(),
+                    "compressed prompt"
+                );
+                self.llm_manager
+                    .metrics()
+                    .record_compression(&self.full_model_name, stats);
+            }
+        }
+        if let Some(cap) = &self.output_cap {
+            cap.clamp(&mut request);
+        }
+        let prompt_tokens = PromptTokens::estimate(&request);
+        let request_id = uuid::Uuid::new_v4().to_string();
+        let recorder = self.llm_manager.debug_recorder();
+        recorder.record_request(&request_id, &self.full_model_name, &request);
+        let shadow = self
+            .routing
+            .as_ref()
+            .and_then(|routing| routing.shadow(&self.full_model_name))
+            .filter(|shadow| shadow::is_sampled(&request_id, shadow.sample_rate))
+            .map(|shadow| (shadow.clone(), request.clone()));
+        let started_at = Instant::now();
+        let retry = self
+            .empty_nudge
+            .as_deref()
+            .map(|nudge| empty::nudged(&request, nudge));
+        let mut routed = self.answer(request, &request_id).await;
+        if empty::is_empty_result(&routed) {
+            let mut recovered = false;
+            if let Some(retry) = retry {
+                tracing::warn!(model = %self.full_model_name, %request_id, "empty completion, retrying with a nudge");
+                let retried = self.answer(retry, &request_id).await;
+                recovered = !empty::is_empty_result(&retried);
+                routed = retried;
+            }
+            self.llm_manager
+                .metrics()
+                .count_empty_response(&self.full_model_name, recovered);
+        }
+        let result = routed.map(|mut response| {
+            recorder.record_output(&request_id, &response.choice);
+            if let Some(cap) = &self.output_cap
+                && cap.truncate(&mut response.choice, response.usage.output_tokens)
+            {
+                tracing::debug!(
+                    model = %self.full_model_name,
+                    max_tokens = cap.max_tokens,
+                    "completion reached its output cap, truncated"
+                );
+            }
+            let raw = &mut response.raw_response;
+            raw.request_id = Some(request_id.clone());
+            raw.prompt_tokens = prompt_tokens;
+            let model = raw
+                .model
+                .get_or_insert_with(|| self.full_model_name.clone());
+            if raw.cost_usd.is_none() {
+                raw.cost_usd = self.llm_manager.cost_usd(
+                    model,
+                    response.usage.input_tokens,
+                    response.usage.output_tokens,
+                );
+            }
+            response
+        });
+        let elapsed = started_at.elapsed();
+        if let (Some((config, request)), Ok(response)) = (shadow, &result) {
+            self.spawn_shadow(config, request, response, &request_id, elapsed);
+        }
+        self.llm_manager.metrics().observe(
+            LatencyKind::TotalRequest,
+            &self.full_model_name,
+            self.priority,
+            elapsed,
+        );
+
+        self.llm_manager.metrics().count_tagged(
+            &self.tags,
+            result.as_ref().ok().map(|response| {
+                (
+                    response.usage.input_tokens,
+                    response.usage.output_tokens,
+                    response.raw_response.cost_usd,
+                )
+            }),
+        );
+        match &result {
+            Ok(response) => self.llm_manager.metrics().count_request(
+                &self.full_model_name,
+                response.usage.input_tokens,
+                response.usage.output_tokens,
+                response.raw_response.cost_usd,
+            ),
+            Err(error) => self
+                .llm_manager
+                .metrics()
+                .count_request_error(&self.full_model_name, ErrorClass::of(error)),
+        }
+
+        let usage = result
+            .as_ref()
+            .map(|response| response.usage)
+            .unwrap_or_default();
+        self.llm_manager
+            .events()
+            .publish(Event::CompletionFinished {
+                request_id: request_id.clone(),
+                model: self.full_model_name.clone(),
+                success: result.is_ok(),
+                input_tokens: usage.input_tokens,
+                output_tokens: usage.output_tokens,
+                latency_ms: elapsed.as_millis() as u64,
+            });
+
+        result.map_err(|error| {
+            if !self.llm_manager.debug_recorder().is_enabled() {
+                return error;
+            }
+            // Point at the recorded exchange so the raw bodies can be pulled up.
+            tracing::warn!(%request_id, model = %self.full_model_name, "completion failed, exchange recorded");
+            match ProviderApiError::find(&error) {
+                Some(provider_error) => ProviderApiError {
+                    debug_request_id: Some(request_id.clone()),
+                    ..provider_error.clone()
+                }
+                .into(),
+                None => CompletionError::ProviderError(format!(
+                    "{error} (debug request id: {request_id})"
+                )),
+            }
+        })
+    }
+
+    async fn stream(
+        &self,
+        _request: CompletionRequest,
+    ) -> Result<StreamingCompletionResponse<RawStreamingResponse>, CompletionError> {
+        Err(CompletionError::ProviderError(
+            "streaming not yet implemented".into(),
+        ))
+    }
+}
+
+impl SpacebotModel {
+    /// Run the request the way this model answers: sampled, as several
+    /// candidates, or routed once.
+    async fn answer(
+        &self,
+        request: CompletionRequest,
+        request_id: &str,
+    ) -> Result<completion::CompletionResponse<RawResponse>, CompletionError> {
+        if let Some(model) = self.hurried() {
+            tracing::debug!(
+                model = %self.full_model_name,
+                fast_model = %model.full_model_name,
+                "turn short on time, using the faster model"
+            );
+            return model.route_completion(request, request_id).await;
+        }
+        match self
+            .sampling
+            .as_ref()
+            .filter(|sampling| sampling.samples > 1)
+        {
+            Some(sampling) => self.sample(sampling, request, request_id).await,
+            None if self.candidates > 1 => {
+                self.draw_candidates(self.candidates, request, request_id)
+                    .await
+            }
+            None => self.route_completion(request, request_id).await,
+        }
+    }
+
+    /// The latency budget's faster model, set up like this one, when the
+    /// turn is short on time. Requests to it are routed once, without
+    /// sampling or extra candidates.
+    fn hurried(&self) -> Option<Self> {
+        let fast_model = self.latency_budget.as_ref()?.fast_model()?;
+        if fast_model == self.full_model_name {
+            return None;
+        }
+        let model = SpacebotModel::make(&self.llm_manager, fast_model)
+            .with_priority(self.priority)
+            .with_seed(self.seed)
+            .with_tags(self.tags.clone())
+            .with_generation(self.generation.clone())
+            .with_prompt_cache(self.prompt_cache);
+        Some(match &self.routing {
+            Some(routing) => model.with_routing(routing.clone()),
+            None => model,
+        })
+    }
+
+    /// Run the request through the primary model and its fallback chain.
+    async fn route_completion(
+        &self,
+        request: CompletionRequest,
+        request_id: &str,
+    ) -> Result<completion::CompletionResponse<RawResponse>, CompletionError> {
+        let Some(routing) = &self.routing else {
+            // No routing config — just call the model directly, no fallback/retry
+            return self
+                .clone()
+                .for_request(request_id)
+                .attempt_completion(&request)
+                .await;
+        };
+
+        // `llm::simulate` replays these decisions for dry runs; keep the two
+        // in step.
+        let cooldown = routing.rate_limit_cooldown_secs;
+        let raced = match routing.race(&self.full_model_name) {
+            Some(race) => self.race(race, cooldown, &request, request_id).await,
+            None => None,
+        };
+        if let Some(response) = raced {
+            return Ok(response);
+        }
+        let fallbacks = routing.get_fallbacks(&self.full_model_name);
+        let mut last_error: Option<CompletionError> = None;
+
+        // Try the primary model (with retries) unless it's in rate-limit cooldown
+        // or its self-hosted server failed a health check, and we have
+        // fallbacks to try instead.
+        let primary_rate_limited = self
+            .llm_manager
+            .is_rate_limited(&self.full_model_name, cooldown)
+            .await;
+        let primary_healthy = self
+            .llm_manager
+            .is_model_healthy(&self.full_model_name)
+            .await;
+
+        let skip_primary = (primary_rate_limited || !primary_healthy) && !fallbacks.is_empty();
+
+        if skip_primary {
+            tracing::debug!(
+                model = %self.full_model_name,
+                rate_limited = primary_rate_limited,
+                healthy = primary_healthy,
+                "primary model unavailable, skipping to fallbacks"
+            );
+        } else if !primary_healthy {
+            // Nothing to fall back to. One attempt, in case the server came
+            // back since the last probe, but no retries against a server
+            // that is most likely still down.
+            return self
+                .clone()
+                .for_request(request_id)
+                .attempt_completion(&request)
+                .await;
+        } else {
+            match self
+                .attempt_with_retries(&self.full_model_name, &request, request_id)
+                .await
+            {
+                Ok(response) => return Ok(response),
+                Err((error, was_rate_limit)) => {
+                    if was_rate_limit {
+                        self.llm_manager
+                            .record_rate_limit(&self.full_model_name)
+                            .await;
+                    }
+                    if fallbacks.is_empty() {
+                        // No fallbacks — this is the final error
+                        return Err(error);
+                    }
+                    tracing::warn!(
+                        model = %self.full_model_name,
+                        "primary model exhausted retries, trying fallbacks"
+                    );
+                    last_error = Some(error);
+                }
+            }
+        }
+
+        // Try fallback chain, each with their own retry loop
+        for (index, fallback_name) in fallbacks.iter().take(MAX_FALLBACK_ATTEMPTS).enumerate() {
+            if self
+                .llm_manager
+                .is_rate_limited(fallback_name, cooldown)
+                .await
+            {
+                tracing::debug!(
+                    fallback = %fallback_name,
+                    "fallback model in cooldown, skipping"
+                );
+                continue;
+            }
+            if !self.llm_manager.is_model_healthy(fallback_name).await {
+                tracing::debug!(
+                    fallback = %fallback_name,
+                    "fallback model's server failed its health check, skipping"
+                );
+                continue;
+            }
+
+            match self
+                .attempt_with_retries(fallback_name, &request, request_id)
+                .await
+            {
+                Ok(mut response) => {
+                    tracing::info!(
+                        original = %self.full_model_name,
+                        fallback = %fallback_name,
+                        attempt = index + 1,
+                        "fallback model succeeded"
+                    );
+                    response.raw_response.fallback = true;
+                    return Ok(response);
+                }
+                Err((error, was_rate_limit)) => {
+                    if was_rate_limit {
+                        self.llm_manager.record_rate_limit(fallback_name).await;
+                    }
+                    tracing::warn!(
+                        fallback = %fallback_name,
+                        "fallback model exhausted retries, continuing chain"
+                    );
+                    last_error = Some(error);
+                }
+            }
+        }
+
+        Err(last_error.unwrap_or_else(|| {
+            CompletionError::ProviderError("all models in fallback chain failed".into())
+        }))
+    }
+
+    /// Send the request to this model and its race models at once and keep
+    /// the best answer. Returns None when fewer than two models are
+    /// available or every one failed, and the request is routed normally.
+    async fn race(
+        &self,
+        config: &RaceConfig,
+        cooldown: u64,
+        request: &CompletionRequest,
+        request_id: &str,
+    ) -> Option<completion::CompletionResponse<RawResponse>> {
+        let mut models: Vec<&str> = Vec::with_capacity(MAX_RACE_CANDIDATES);
+        let contenders = std::iter::once(self.full_model_name.as_str())
+            .chain(config.models.iter().map(String::as_str));
+        for model in contenders {
+            if models.len() == MAX_RACE_CANDIDATES {
+                break;
+            }
+            if models.contains(&model) {
+                continue;
+            }
+            if self.llm_manager.is_rate_limited(model, cooldown).await
+                || !self.llm_manager.is_model_healthy(model).await
+            {
+                tracing::debug!(%model, "race model unavailable, leaving it out");
+                continue;
+            }
+            models.push(model);
+        }
+        if models.len() < 2 {
+            tracing::debug!(model = %self.full_model_name, "too few race models available, routing normally");
+            return None;
+        }
+
+        let attempts = models.iter().map(|model| async move {
+            let started_at = Instant::now();
+            let result = self.attempt_with_retries(model, request, request_id).await;
+            (result, started_at.elapsed())
+        });
+        let results = futures::future::join_all(attempts).await;
+
+        let mut candidates = Vec::with_capacity(models.len());
+        let mut responses = Vec::new();
+        for (model, (result, elapsed)) in models.iter().zip(results) {
+            let mut candidate = RaceCandidate {
+                model: model.to_string(),
+                output: None,
+                error: None,
+                input_tokens: 0,
+                output_tokens: 0,
+                cost_usd: None,
+                latency_ms: elapsed.as_millis() as u64,
+            };
+            match result {
+                Ok(response) => {
+                    candidate.output = Some(render_output(&response.choice));
+                    candidate.input_tokens = response.usage.input_tokens;
+                    candidate.output_tokens = response.usage.output_tokens;
+                    candidate.cost_usd = self.llm_manager.cost_usd(
+                        model,
+                        response.usage.input_tokens,
+                        response.usage.output_tokens,
+                    );
+                    responses.push(response);
+                }
+                Err((error, was_rate_limit)) => {
+                    if was_rate_limit {
+                        self.llm_manager.record_rate_limit(model).await;
+                    }
+                    tracing::warn!(%model, %error, "race model failed");
+                    candidate.error = Some(error.to_string());
+                }
+            }
+            candidates.push(candidate);
+        }
+
+        let answers: Vec<&str> = candidates
+            .iter()
+            .filter_map(|candidate| candidate.output.as_deref())
+            .collect();
+        let mut picked_by = PICKED_BY_AGREEMENT.to_string();
+        let mut judge_cost_usd = None;
+        let mut pick = None;
+        if let Some(judge) = config.judge.as_deref().filter(|_| answers.len() > 1) {
+            let (verdict, cost_usd) = self.judge(judge, request, &answers, request_id).await;
+            judge_cost_usd = cost_usd;
+            if verdict.is_some() {
+                picked_by = judge.to_string();
+                pick = verdict;
+            }
+        }
+        let pick = match pick {
+            Some(pick) => Some(pick),
+            None if answers.is_empty() => None,
+            None => Some(race::pick_by_agreement(&answers)),
+        };
+
+        let answered: Vec<usize> = candidates
+            .iter()
+            .enumerate()
+            .filter(|(_, candidate)| candidate.output.is_some())
+            .map(|(index, _)| index)
+            .collect();
+        let record = RaceRecord {
+            request_id: request_id.to_string(),
+            finished_at: chrono::Utc::now(),
+            candidates,
+            winner: pick.map(|pick| answered[pick]),
+            picked_by,
+            judge_cost_usd,
+        };
+        let total_cost_usd = record.total_cost_usd();
+        if let Some(winner) = record.winner {
+            tracing::info!(
+                model = %self.full_model_name,
+                winner = %record.candidates[winner].model,
+                picked_by = %record.picked_by,
+                "race finished"
+            );
+        }
+        self.llm_manager.record_race(record);
+
+        let mut response = responses.into_iter().nth(pick?)?;
+        response.raw_response.cost_usd = total_cost_usd;
+        Some(response)
+    }
+
+    /// Draw several completions at the sampling temperature, each routed
+    /// like a normal request, and keep the consensus: the majority answer
+    /// when every sample is structured, otherwise the judge's pick. The
+    /// response's cost covers every sample and the judge.
+    async fn sample(
+        &self,
+        config: &SamplingConfig,
+        mut request: CompletionRequest,
+        request_id: &str,
+    ) -> Result<completion::CompletionResponse<RawResponse>, CompletionError> {
+        request.temperature = Some(config.temperature);
+        let samples = config.samples.min(MAX_SAMPLES);
+        let models: Vec<Self> = (0..samples as u64)
+            .map(|index| {
+                self.clone()
+                    .with_seed(self.seed.map(|seed| seed.wrapping_add(index)))
+            })
+            .collect();
+        let drawn = models
+            .iter()
+            .map(|model| model.route_completion(request.clone(), request_id));
+        let mut responses = Vec::with_capacity(samples);
+        let mut last_error = None;
+        for result in futures::future::join_all(drawn).await {
+            match result {
+                Ok(response) => responses.push(response),
+                Err(error) => {
+                    tracing::warn!(model = %self.full_model_name, %error, "sample failed");
+                    last_error = Some(error);
+                }
+            }
+        }
+        if responses.is_empty() {
+            return Err(last_error.unwrap_or_else(|| {
+                CompletionError::ProviderError("no samples were drawn".into())
+            }));
+        }
+
+        // Raced samples already carry their total cost.
+        let mut cost_usd: Option<f64> = responses
+            .iter()
+            .map(|response| {
+                response.raw_response.cost_usd.or_else(|| {
+                    self.llm_manager.cost_usd(
+                        response
+                            .raw_response
+                            .model
+                            .as_deref()
+                            .unwrap_or(&self.full_model_name),
+                        response.usage.input_tokens,
+                        response.usage.output_tokens,
+                    )
+                })
+            })
+            .sum();
+
+        let keys: Vec<Option<String>> = responses
+            .iter()
+            .map(|response| sampling::answer_key(&response.choice))
+            .collect();
+        let (pick, picked_by) = match sampling::majority(&keys) {
+            Some(pick) => (pick, "majority"),
+            None if responses.len() == 1 => (0, "only sample"),
+            None => {
+                let outputs: Vec<String> = responses
+                    .iter()
+                    .map(|response| render_output(&response.choice))
+                    .collect();
+                let answers: Vec<&str> = outputs.iter().map(String::as_str).collect();
+                let judge = config.judge.as_deref().unwrap_or(&self.full_model_name);
+                let (verdict, judge_cost_usd) =
+                    self.judge(judge, &request, &answers, request_id).await;
+                cost_usd = cost_usd.map(|cost| cost + judge_cost_usd.unwrap_or_default());
+                match verdict {
+                    Some(pick) => (pick, "judge"),
+                    None => (race::pick_by_agreement(&answers), PICKED_BY_AGREEMENT),
+                }
+            }
+        };
+        tracing::debug!(
+            model = %self.full_model_name,
+            samples = responses.len(),
+            picked = pick + 1,
+            picked_by,
+            ?cost_usd,
+            "sampled completion"
+        );
+
+        let mut response = responses.swap_remove(pick);
+        response.raw_response.cost_usd = cost_usd;
+        Ok(response)
+    }
+
+    /// Draw `count` answers to the request. A provider taking `n` is asked
+    /// for them all at once; whatever it (or a fallback that doesn't take
+    /// `n`) didn't draw is requested again, in parallel. The response keeps
+    /// every answer on `candidates`, and its usage and cost cover them all.
+    async fn draw_candidates(
+        &self,
+        count: usize,
+        request: CompletionRequest,
+        request_id: &str,
+    ) -> Result<completion::CompletionResponse<RawResponse>, CompletionError> {
+        let mut responses = Vec::with_capacity(count);
+        if candidates::supports_n(&self.provider, self.llm_manager.is_vllm(&self.provider)) {
+            responses.push(self.route_completion(request.clone(), request_id).await?);
+        }
+        let drawn: usize = responses
+            .iter()
+            .map(|response| response.raw_response.candidates.len().max(1))
+            .sum();
+
+        let models: Vec<Self> = (drawn..count)
+            .map(|index| {
+                self.clone()
+                    .with_candidates(1)
+                    .with_seed(self.seed.map(|seed| seed.wrapping_add(index as u64)))
+            })
+            .collect();
+        let extra = models
+            .iter()
+            .map(|model| model.route_completion(request.clone(), request_id));
+        let mut last_error = None;
+        for result in futures::future::join_all(extra).await {
+            match result {
+                Ok(response) => responses.push(response),
+                Err(error) => {
+                    tracing::warn!(model = %self.full_model_name, %error, "candidate failed");
+                    last_error = Some(error);
+                }
+            }
+        }
+        if responses.is_empty() {
+            return Err(last_error.unwrap_or_else(|| {
+                CompletionError::ProviderError("no candidates were drawn".into())
+            }));
+        }
+
+        // Raced responses already carry their total cost.
+        let cost_usd: Option<f64> = responses
+            .iter()
+            .map(|response| {
+                response.raw_response.cost_usd.or_else(|| {
+                    self.llm_manager.cost_usd(
+                        response
+                            .raw_response
+                            .model
+                            .as_deref()
+                            .unwrap_or(&self.full_model_name),
+                        response.usage.input_tokens,
+                        response.usage.output_tokens,
+                    )
+                })
+            })
+            .sum();
+        let usage = candidates::total_usage(responses.iter().map(|response| response.usage));
+        let drawn: Vec<Candidate> = responses
+            .iter()
+            .flat_map(|response| {
+                let model = response
+                    .raw_response
+                    .model
+                    .clone()
+                    .unwrap_or_else(|| self.full_model_name.clone());
+                let candidates = match response.raw_response.candidates.as_slice() {
+                    [] => vec![Candidate::new(response.choice.clone())],
+                    candidates => candidates.to_vec(),
+                };
+                candidates.into_iter().map(move |candidate| Candidate {
+                    model: candidate.model.or_else(|| Some(model.clone())),
+                    ..candidate
+                })
+            })
+            .take(count)
+            .collect();
+        tracing::debug!(
+            model = %self.full_model_name,
+            requested = count,
+            drawn = drawn.len(),
+            requests = responses.len(),
+            ?cost_usd,
+            "drew candidates"
+        );
+
+        let mut response = responses.swap_remove(0);
+        response.usage = usage;
+        response.raw_response.cost_usd = cost_usd;
+        response.raw_response.candidates = drawn;
+        Ok(response)
+    }
+
+    /// Send a one-token request with `preamble`, so the provider has it
+    /// cached before a batch of requests that share it. Only a preamble
+    /// above the provider's minimum cacheable length is cached. No retries
+    /// or fallbacks: a failed priming only costs the batch its discount.
+    pub async fn prime_cache(&self, preamble: &str) -> Result<completion::Usage, CompletionError> {
+        let request = self
+            .completion_request(Message::user("Reply with OK."))
+            .preamble(preamble.to_string())
+            .max_tokens(1)
+            .build();
+        let response = self.attempt_completion(&request).await?;
+        Ok(response.usage)
+    }
+
+    /// Mirror a request the primary answered to its shadow candidate in the
+    /// background. The candidate's answer is only compared, never used.
+    fn spawn_shadow(
+        &self,
+        config: ShadowConfig,
+        request: CompletionRequest,
+        primary: &completion::CompletionResponse<RawResponse>,
+        request_id: &str,
+        primary_latency: Duration,
+    ) {
+        // Mirrored traffic queues behind everything real.
+        let model = self.clone().with_priority(Priority::Background);
+        let primary_output = render_output(&primary.choice);
+        let primary_cost_usd = primary.raw_response.cost_usd;
+        let request_id = request_id.to_string();
+        tokio::spawn(async move {
+            let candidate = SpacebotModel::make(&model.llm_manager, &config.model)
+                .with_priority(Priority::Background)
+                .with_seed(model.seed)
+                .with_generation(model.generation.clone());
+            let started_at = Instant::now();
+            let result = candidate.attempt_completion(&request).await;
+            let mut record = ShadowRecord {
+                request_id: request_id.clone(),
+                finished_at: chrono::Utc::now(),
+                primary_model: model.full_model_name.clone(),
+                shadow_model: config.model.clone(),
+                primary_latency_ms: primary_latency.as_millis() as u64,
+                shadow_latency_ms: started_at.elapsed().as_millis() as u64,
+                primary_cost_usd,
+                shadow_cost_usd: None,
+                shadow_input_tokens: 0,
+                shadow_output_tokens: 0,
+                error: None,
+                agreement: None,
+                judge: None,
+                shadow_preferred: None,
+                judge_cost_usd: None,
+            };
+            match result {
+                Ok(response) => {
+                    let output = render_output(&response.choice);
+                    record.shadow_input_tokens = response.usage.input_tokens;
+                    record.shadow_output_tokens = response.usage.output_tokens;
+                    record.shadow_cost_usd = model.llm_manager.cost_usd(
+                        &config.model,
+                        response.usage.input_tokens,
+                        response.usage.output_tokens,
+                    );
+                    record.agreement = Some(race::similarity(&primary_output, &output));
+                    if let Some(judge) = &config.judge {
+                        let shadow_first = shadow::shadow_shown_first(&request_id);
+                        let answers = if shadow_first {
+                            [output.as_str(), primary_output.as_str()]
+                        } else {
+                            [primary_output.as_str(), output.as_str()]
+                        };
+                        let (verdict, cost_usd) =
+                            model.judge(judge, &request, &answers, &request_id).await;
+                        record.judge = Some(judge.clone());
+                        record.shadow_preferred = verdict.map(|pick| (pick == 0) == shadow_first);
+                        record.judge_cost_usd = cost_usd;
+                    }
+                }
+                Err(error) => {
+                    tracing::debug!(model = %config.model, %error, "shadow model failed");
+                    record.error = Some(error.to_string());
+                }
+            }
+            model.llm_manager.record_shadow(record);
+        });
+    }
+
+    /// Ask the judge model which answer is best. Returns the index it
+    /// picked, if it gave a usable one, and what asking it cost.
+    async fn judge(
+        &self,
+        judge: &str,
+        request: &CompletionRequest,
+        answers: &[&str],
+        request_id: &str,
+    ) -> (Option<usize>, Option<f64>) {
+        let prompt = race::judge_prompt(&race::last_user_text(&request.chat_history), answers);
+        let judge_request = self
+            .completion_request(Message::user(prompt))
+            .preamble(JUDGE_PREAMBLE.to_string())
+            .temperature(0.0)
+            .max_tokens(16)
+            .build();
+        match self
+            .attempt_with_retries(judge, &judge_request, request_id)
+            .await
+        {
+            Ok(response) => {
+                let reply = render_output(&response.choice);
+                let verdict = race::parse_verdict(&reply, answers.len());
+                if verdict.is_none() {
+                    tracing::warn!(%judge, %reply, "race judge gave no usable verdict");
+                }
+                let cost_usd = self.llm_manager.cost_usd(
+                    judge,
+                    response.usage.input_tokens,
+                    response.usage.output_tokens,
+                );
+                (verdict, cost_usd)
+            }
+            Err((error, _)) => {
+                tracing::warn!(%judge, %error, "race judge failed, picking by agreement");
+                (None, None)
+            }
+        }
+    }
+
+    async fn call_anthropic(
+        &self,
+        request: &CompletionRequest,
+    ) -> Result<completion::CompletionResponse<RawResponse>, CompletionError> {
+        let api_key = self
+            .llm_manager
+            .get_api_key("anthropic")
+            .await
+            .map_err(|e| CompletionError::ProviderError(e.to_string()))?;
+
+        let mut messages = convert_messages_to_anthropic(&request.chat_history);
+        let uses_files = self
+            .llm_manager
+            .file_uploads()
+            .upload_anthropic_images(
+                self.llm_manager.http_client(),
+                self.llm_manager.request_signer("anthropic"),
+                &api_key,
+                &mut messages,
+            )
+            .await;
+
+        let mut body = serde_json::json!({
+            "model": self.model_name,
+            "messages": messages,
+            "max_tokens": request.max_tokens.unwrap_or(4096),
+        });
+
+        if let Some(preamble) = &request.preamble {
+            body["system"] = if self.prompt_cache {
+                serde_json::json!([{
+                    "type": "text",
+                    "text": preamble,
+                    "cache_control": { "type": "ephemeral" },
+                }])
+            } else {
+                serde_json::json!(preamble)
+            };
+        }
+
+        if let Some(temperature) = request.temperature {
+            body["temperature"] = serde_json::json!(temperature);
+        }
+        self.apply_generation("anthropic", &mut body);
+
+        if !request.tools.is_empty() {
+            let tools: Vec<serde_json::Value> = request
+                .tools
+                .iter()
+                .map(|t| {
+                    serde_json::json!({
+                        "name": t.name,
+                        "description": t.description,
+                        "input_schema": self.tool_parameters(t),
+                    })
+                })
+                .collect();
+            body["tools"] = serde_json::json!(tools);
+        }
+
+        let mut request_builder = self
+            .llm_manager
+            .http_client()
+            .post("https://api.anthropic.com/v1/messages")
+            .header("x-api-key", &api_key)
+            .header("anthropic-version", "2023-06-01")
+            .header("content-type", "application/json");
+        let mut betas: Vec<&str> = self.anthropic_betas.iter().map(String::as_str).collect();
+        if uses_files && !betas.contains(&ANTHROPIC_FILES_BETA) {
+            betas.push(ANTHROPIC_FILES_BETA);
+        }
+        if !betas.is_empty() {
+            request_builder = request_builder.header("anthropic-beta", betas.join(","));
+        }
+        let response = self
+            .llm_manager
+            .send("anthropic", request_builder.json(&body))
+            .await
+            .map_err(|e| CompletionError::ProviderError(e.to_string()))?;
+
+        let status = response.status();
+        let provider_request_id = provider_request_id(response.headers());
+        if let Some(notice) = deprecation_notice(response.headers()) {
+            self.llm_manager
+                .report_deprecation(&self.full_model_name, &notice);
+        }
+        let response_text = response.text().await.map_err(|e| {
+            CompletionError::ProviderError(format!("failed to read response body: {e}"))
+        })?;
+        self.record_exchange(
+            &body,
+            status,
+            provider_request_id.as_deref(),
+            &response_text,
+        );
+        self.check_credential_rejected(status).await;
+
+        let response_body: serde_json::Value =
+            serde_json::from_str(&response_text).map_err(|e| {
+                CompletionError::ProviderError(format!(
+                    "Anthropic response ({status}) is not valid JSON: {e}{}\nBody: {}",
+                    provider_request_note(provider_request_id.as_deref()),
+                    truncate_redacted(&response_text, MAX_ERROR_BODY_BYTES)
+                ))
+            })?;
+
+        if !status.is_success() {
+            // An uploaded file may have been deleted; upload afresh next time.
+            if uses_files
+                && (status == reqwest::StatusCode::BAD_REQUEST
+                    || status == reqwest::StatusCode::NOT_FOUND)
+            {
+                self.llm_manager.file_uploads().forget_all();
+            }
+            return Err(provider_api_error(
+                "Anthropic",
+                status,
+                provider_request_id,
+                &response_body,
+            ));
+        }
+
+        let mut warnings = Vec::new();
+        let result = parse_anthropic_response(response_body, &mut warnings)
+            .map(|response| with_provider_request_id(response, provider_request_id));
+        self.report_parse_warnings(&warnings);
+        result
+    }
+
+    async fn call_openai(
+        &self,
+        request: &CompletionRequest,
+    ) -> Result<completion::CompletionResponse<RawResponse>, CompletionError> {
+        let api_key = self.optional_api_key("openai").await?;
+
+        let mut messages = Vec::new();
+
+        if let Some(preamble) = &request.preamble {
+            messages.push(serde_json::json!({
+                "role": "system",
+                "content": preamble,
+            }));
+        }
+
+        messages.extend(convert_messages_to_openai(&request.chat_history, false));
+
+        let mut body = serde_json::json!({
+            "model": self.model_name,
+            "messages": messages,
+        });
+
+        if let Some(max_tokens) = request.max_tokens {
+            body["max_tokens"] = serde_json::json!(max_tokens);
+        }
+
+        if let Some(temperature) = request.temperature {
+            body["temperature"] = serde_json::json!(temperature);
+        }
+        self.apply_generation("openai", &mut body);
+        self.apply_tags("openai", &mut body);
+
+        if !request.tools.is_empty() {
+            let tools: Vec<serde_json::Value> = request
+                .tools
+                .iter()
+                .map(|t| {
+                    serde_json::json!({
+                        "type": "function",
+                        "function": {
+                            "name": t.name,
+                            "description": t.description,
+                            "parameters": self.tool_parameters(t),
+                        }
+                    })
+                })
+                .collect();
+            body["tools"] = serde_json::json!(tools);
+        }
+
+        self.apply_vllm_options("openai", &mut body);
+        self.apply_seed("openai", &mut body);
+        self.apply_candidates("openai", request, &mut body);
+        let constrained = self.apply_tool_grammar("openai", request, &mut body);
+
+        let mut request_builder = self
+            .llm_manager
+            .http_client()
+            .post(self.endpoint("openai", "https://api.openai.com/v1/chat/completions"))
+            .header("content-type", "application/json");
+        if let Some(api_key) = &api_key {
+            request_builder = request_builder.header("authorization", format!("Bearer {api_key}"));
+        }
+        if let Some(organization) = self.llm_manager.openai_organization() {
+            request_builder = request_builder.header("openai-organization", organization);
+        }
+        if let Some(project) = self.llm_manager.openai_project() {
+            request_builder = request_builder.header("openai-project", project);
+        }
+
+        let response = self
+            .llm_manager
+            .send("openai", request_builder.json(&body))
+            .await
+            .map_err(|e| CompletionError::ProviderError(e.to_string()))?;
+
+        let status = response.status();
+        let provider_request_id = provider_request_id(response.headers());
+        if let Some(notice) = deprecation_notice(response.headers()) {
+            self.llm_manager
+                .report_deprecation(&self.full_model_name, &notice);
+        }
+        let response_text = response.text().await.map_err(|e| {
+            CompletionError::ProviderError(format!("failed to read response body: {e}"))
+        })?;
+        self.record_exchange(
+            &body,
+            status,
+            provider_request_id.as_deref(),
+            &response_text,
+        );
+        self.check_credential_rejected(status).await;
+
+        let response_body: serde_json::Value =
+            serde_json::from_str(&response_text).map_err(|e| {
+                CompletionError::ProviderError(format!(
+                    "OpenAI response ({status}) is not valid JSON: {e}{}\nBody: {}",
+                    provider_request_note(provider_request_id.as_deref()),
+                    truncate_redacted(&response_text, MAX_ERROR_BODY_BYTES)
+                ))
+            })?;
+
+        if !status.is_success() {
+            return Err(provider_api_error(
+                "OpenAI",
+                status,
+                provider_request_id,
+                &response_body,
+            ));
+        }
+
+        let mut warnings = Vec::new();
+        let result = parse_openai_response(response_body, "OpenAI", &mut warnings)
+            .map(|response| with_provider_request_id(response, provider_request_id));
+        self.report_parse_warnings(&warnings);
+        if constrained {
+            return result.map(|response| recover_tool_call(response, &request.tools));
+        }
+        result
+    }
+
+    async fn call_openrouter(
+        &self,
+        request: &CompletionRequest,
+    ) -> Result<completion::CompletionResponse<RawResponse>, CompletionError> {
+        let api_key = self
+            .llm_manager
+            .get_api_key("openrouter")
+            .await
+            .map_err(|e| CompletionError::ProviderError(e.to_string()))?;
+
+        // OpenRouter uses the OpenAI chat completions format.
+        // model_name is the full OpenRouter model ID (e.g. "anthropic/claude-sonnet-4-20250514").
+        let mut messages = Vec::new();
+
+        if let Some(preamble) = &request.preamble {
+            messages.push(serde_json::json!({
+                "role": "system",
+                "content": preamble,
+            }));
+        }
+
+        messages.extend(convert_messages_to_openai(&request.chat_history, false));
+
+        let mut body = serde_json::json!({
+            "model": self.model_name,
+            "messages": messages,
+        });
+
+        if let Some(max_tokens) = request.max_tokens {
+            body["max_tokens"] = serde_json::json!(max_tokens);
+        }
+
+        if let Some(temperature) = request.temperature {
+            body["temperature"] = serde_json::json!(temperature);
+        }
+        self.apply_generation("openrouter", &mut body);
+        self.apply_tags("openrouter", &mut body);
+
+        if !request.tools.is_empty() {
+            let tools: Vec<serde_json::Value> = request
+                .tools
+                .iter()
+                .map(|t| {
+                    serde_json::json!({
+                        "type": "function",
+                        "function": {
+                            "name": t.name,
+                            "description": t.description,
+                            "parameters": self.tool_parameters(t),
+                        }
+                    })
+                })
+                .collect();
+            body["tools"] = serde_json::json!(tools);
+        }
+
+        self.apply_seed("openrouter", &mut body);
+
+        let request = self
+            .llm_manager
+            .http_client()
+            .post("https://openrouter.ai/api/v1/chat/completions")
+            .header("authorization", format!("Bearer {api_key}"))
+            .header("content-type", "application/json")
+            .json(&body);
+        let response = self
+            .llm_manager
+            .send("openrouter", request)
+            .await
+            .map_err(|e| CompletionError::ProviderError(e.to_string()))?;
+
+        let status = response.status();
+        let provider_request_id = provider_request_id(response.headers());
+        if let Some(notice) = deprecation_notice(response.headers()) {
+            self.llm_manager
+                .report_deprecation(&self.full_model_name, &notice);
+        }
+        let response_text = response.text().await.map_err(|e| {
+            CompletionError::ProviderError(format!("failed to read response body: {e}"))
+        })?;
+        self.record_exchange(
+            &body,
+            status,
+            provider_request_id.as_deref(),
+            &response_text,
+        );
+        self.check_credential_rejected(status).await;
+
+        let response_body: serde_json::Value =
+            serde_json::from_str(&response_text).map_err(|e| {
+                CompletionError::ProviderError(format!(
+                    "OpenRouter response ({status}) is not valid JSON: {e}{}\nBody: {}",
+                    provider_request_note(provider_request_id.as_deref()),
+                    truncate_redacted(&response_text, MAX_ERROR_BODY_BYTES)
+                ))
+            })?;
+
+        if !status.is_success() {
+            return Err(provider_api_error(
+                "OpenRouter",
+                status,
+                provider_request_id,
+                &response_body,
+            ));
+        }
+
+        // OpenRouter returns OpenAI-format responses
+        let mut warnings = Vec::new();
+        let result = parse_openai_response(response_body, "OpenRouter", &mut warnings)
+            .map(|response| with_provider_request_id(response, provider_request_id));
+        self.report_parse_warnings(&warnings);
+        result
+    }
+
+    async fn call_zhipu(
+        &self,
+        request: &CompletionRequest,
+    ) -> Result<completion::CompletionResponse<RawResponse>, CompletionError> {
+        let api_key = self
+            .llm_manager
+            .get_api_key("zhipu")
+            .await
+            .map_err(|e| CompletionError::ProviderError(e.to_string()))?;
+
+        let mut messages = Vec::new();
+
+        if let Some(preamble) = &request.preamble {
+            messages.push(serde_json::json!({
+                "role": "system",
+                "content": preamble,
+            }));
+        }
+
+        messages.extend(convert_messages_to_openai(&request.chat_history, false));
+
+        let mut body = serde_json::json!({
+            "model": self.model_name,
+            "messages": messages,
+        });
+
+        if let Some(max_tokens) = request.max_tokens {
+            body["max_tokens"] = serde_json::json!(max_tokens);
+        }
+
+        if let Some(temperature) = request.temperature {
+            body["temperature"] = serde_json::json!(temperature);
+        }
+        self.apply_generation("zhipu", &mut body);
+        self.apply_tags("zhipu", &mut body);
+
+        if !request.tools.is_empty() {
+            let tools: Vec<serde_json::Value> = request
+                .tools
+                .iter()
+                .map(|t| {
+                    serde_json::json!({
+                        "type": "function",
+                        "function": {
+                            "name": t.name,
+                            "description": t.description,
+                            "parameters": self.tool_parameters(t),
+                        }
+                    })
+                })
+                .collect();
+            body["tools"] = serde_json::json!(tools);
+        }
+
+        let request = self
+            .llm_manager
+            .http_client()
+            .post("https://api.z.ai/api/paas/v4/chat/completions")
+            .header("authorization", format!("Bearer {api_key}"))
+            .header("content-type", "application/json")
+            .json(&body);
+        let response = self
+            .llm_manager
+            .send("zhipu", request)
+            .await
+            .map_err(|e| CompletionError::ProviderError(e.to_string()))?;
+
+        let status = response.status();
+        let provider_request_id = provider_request_id(response.headers());
+        if let Some(notice) = deprecation_notice(response.headers()) {
+            self.llm_manager
+                .report_deprecation(&self.full_model_name, &notice);
+        }
+        let response_text = response.text().await.map_err(|e| {
+            CompletionError::ProviderError(format!("failed to read response body: {e}"))
+        })?;
+        self.record_exchange(
+            &body,
+            status,
+            provider_request_id.as_deref(),
+            &response_text,
+        );
+        self.check_credential_rejected(status).await;
+
+        let response_body: serde_json::Value =
+            serde_json::from_str(&response_text).map_err(|e| {
+                CompletionError::ProviderError(format!(
+                    "Z.ai response ({status}) is not valid JSON: {e}{}\nBody: {}",
+                    provider_request_note(provider_request_id.as_deref()),
+                    truncate_redacted(&response_text, MAX_ERROR_BODY_BYTES)
+                ))
+            })?;
+
+        if !status.is_success() {
+            return Err(provider_api_error(
+                "Z.ai",
+                status,
+                provider_request_id,
+                &response_body,
+            ));
+        }
+
+        let mut warnings = Vec::new();
+        let result = parse_openai_response(response_body, "Z.ai", &mut warnings)
+            .map(|response| with_provider_request_id(response, provider_request_id));
+        self.report_parse_warnings(&warnings);
+        result
+    }
+
+    async fn call_ollama(
+        &self,
+        request: &CompletionRequest,
+    ) -> Result<completion::CompletionResponse<RawResponse>, CompletionError> {
+        self.call_openai_compatible(
+            request,
+            "ollama",
+            "Ollama",
+            "https://ollama.com/v1/chat/completions",
+        )
+        .await
+    }
+
+    /// Generic OpenAI-compatible API call.
+    /// Used by providers that implement the OpenAI chat completions format.
+    async fn call_openai_compatible(
+        &self,
+        request: &CompletionRequest,
+        provider_id: &str,
+        provider_display_name: &str,
+        endpoint: &str,
+    ) -> Result<completion::CompletionResponse<RawResponse>, CompletionError> {
+        let api_key = self.optional_api_key(provider_id).await?;
+
+        let mut messages = Vec::new();
+
+        if let Some(preamble) = &request.preamble {
+            messages.push(serde_json::json!({
+                "role": "system",
+                "content": preamble,
+            }));
+        }
+
+        messages.extend(convert_messages_to_openai(
+            &request.chat_history,
+            provider_id == "kimi-coding",
+        ));
+
+        let mut body = serde_json::json!({
+            "model": self.model_name,
+            "messages": messages,
+        });
+
+        if let Some(max_tokens) = request.max_tokens {
+            body["max_tokens"] = serde_json::json!(max_tokens);
+        }
+
+        if let Some(temperature) = request.temperature {
+            body["temperature"] = serde_json::json!(temperature);
+        }
+        self.apply_generation(provider_id, &mut body);
+        self.apply_tags(provider_id, &mut body);
+
+        if !request.tools.is_empty() {
+            let tools: Vec<serde_json::Value> = request
+                .tools
+                .iter()
+                .map(|t| {
+                    serde_json::json!({
+                        "type": "function",
+                        "function": {
+                            "name": t.name,
+                            "description": t.description,
+                            "parameters": self.tool_parameters(t),
+                        }
+                    })
+                })
+                .collect();
+            body["tools"] = serde_json::json!(tools);
+        }
+
+        self.apply_vllm_options(provider_id, &mut body);
+        self.apply_seed(provider_id, &mut body);
+        self.apply_candidates(provider_id, request, &mut body);
+        let constrained = self.apply_tool_grammar(provider_id, request, &mut body);
+
+        let mut request_builder = self
+            .llm_manager
+            .http_client()
+            .post(self.endpoint(provider_id, endpoint))
+            .header("content-type", "application/json");
+        if let Some(api_key) = &api_key {
+            request_builder = request_builder.header("authorization", format!("Bearer {api_key}"));
+        }
+
+        if provider_id == "kimi-coding" {
+            // Kimi Coding API checks for coding-agent traffic and rejects generic clients.
+            request_builder = request_builder.header("user-agent", "KimiCLI/1.3");
+
+            if let Some(messages) = body["messages"].as_array() {
+                let stats = collect_assistant_tool_call_reasoning_stats(messages);
+                tracing::debug!(
+                    provider = provider_id,
+                    total_messages = messages.len(),
+                    assistant_tool_call_messages = stats.assistant_tool_call_messages,
+                    messages_with_reasoning_content = stats.messages_with_reasoning_content,
+                    messages_with_empty_reasoning_content = stats.messages_with_empty_reasoning_content,
+                    missing_reasoning_content_indices = ?stats.missing_reasoning_content_indices,
+                    "sending kimi-coding request"
+                );
+            }
+        }
+
+        let response = self
+            .llm_manager
+            .send(provider_id, request_builder.json(&body))
+            .await
+            .map_err(|e| CompletionError::ProviderError(e.to_string()))?;
+
+        let status = response.status();
+        let provider_request_id = provider_request_id(response.headers());
+        if let Some(notice) = deprecation_notice(response.headers()) {
+            self.llm_manager
+                .report_deprecation(&self.full_model_name, &notice);
+        }
+        let response_text = response.text().await.map_err(|e| {
+            CompletionError::ProviderError(format!("failed to read response body: {e}"))
+        })?;
+        self.record_exchange(
+            &body,
+            status,
+            provider_request_id.as_deref(),
+            &response_text,
+        );
+        self.check_credential_rejected(status).await;
+
+        let response_body: serde_json::Value =
+            serde_json::from_str(&response_text).map_err(|e| {
+                CompletionError::ProviderError(format!(
+                    "{provider_display_name} response ({status}) is not valid JSON: {e}{}\nBody: {}",
+                    provider_request_note(provider_request_id.as_deref()),
+                    truncate_redacted(&response_text, MAX_ERROR_BODY_BYTES)
+                ))
+            })?;
+
+        if !status.is_success() {
+            return Err(provider_api_error(
+                provider_display_name,
+                status,
+                provider_request_id,
+                &response_body,
+            ));
+        }
+
+        let mut warnings = Vec::new();
+        let result = parse_openai_response(response_body, provider_display_name, &mut warnings)
+            .map(|response| with_provider_request_id(response, provider_request_id));
+        self.report_parse_warnings(&warnings);
+        if constrained {
+            return result.map(|response| recover_tool_call(response, &request.tools));
+        }
+        result
+    }
+
+    async fn call_groq(
+        &self,
+        request: &CompletionRequest,
+    ) -> Result<completion::CompletionResponse<RawResponse>, CompletionError> {
+        self.call_openai_compatible(
+            request,
+            "groq",
+            "Groq",
+            "https://api.groq.com/openai/v1/chat/completions",
+        )
+        .await
+    }
+
+    async fn call_together(
+        &self,
+        request: &CompletionRequest,
+    ) -> Result<completion::CompletionResponse<RawResponse>, CompletionError> {
+        self.call_openai_compatible(
+            request,
+            "together",
+            "Together AI",
+            "https://api.together.xyz/v1/chat/completions",
+        )
+        .await
+    }
+
+    async fn call_fireworks(
+        &self,
+        request: &CompletionRequest,
+    ) -> Result<completion::CompletionResponse<RawResponse>, CompletionError> {
+        self.call_openai_compatible(
+            request,
+            "fireworks",
+            "Fireworks AI",
+            "https://api.fireworks.ai/inference/v1/chat/completions",
+        )
+        .await
+    }
+
+    async fn call_deepseek(
+        &self,
+        request: &CompletionRequest,
+    ) -> Result<completion::CompletionResponse<RawResponse>, CompletionError> {
+        self.call_openai_compatible(
+            request,
+            "deepseek",
+            "DeepSeek",
+            "https://api.deepseek.com/v1/chat/completions",
+        )
+        .await
+    }
+
+    async fn call_xai(
+        &self,
+        request: &CompletionRequest,
+    ) -> Result<completion::CompletionResponse<RawResponse>, CompletionError> {
+        self.call_openai_compatible(
+            request,
+            "xai",
+            "xAI",
+            "https://api.x.ai/v1/chat/completions",
+        )
+        .await
+    }
+
+    async fn call_mistral(
+        &self,
+        request: &CompletionRequest,
+    ) -> Result<completion::CompletionResponse<RawResponse>, CompletionError> {
+        self.call_openai_compatible(
+            request,
+            "mistral",
+            "Mistral AI",
+            "https://api.mistral.ai/v1/chat/completions",
+        )
+        .await
+    }
+
+    async fn call_opencode_zen(
+        &self,
+        request: &CompletionRequest,
+    ) -> Result<completion::CompletionResponse<RawResponse>, CompletionError> {
+        self.call_openai_compatible(
+            request,
+            "opencode-zen",
+            "OpenCode Zen",
+            "https://opencode.ai/zen/v1/chat/completions",
+        )
+        .await
+    }
+}
+
+// --- Helpers ---
+
+/// The request field a provider reads its sampling seed from. Anthropic and
+/// Zhipu have none.
+fn seed_field(provider_id: &str) -> Option<&'static str> {
+    match provider_id {
+        "anthropic" | "zhipu" => None,
+        "mistral" => Some("random_seed"),
+        _ => Some("seed"),
+    }
+}
+
+/// The typed error for a provider's non-success response, with its message
+/// redacted and capped.
+fn provider_api_error(
+    provider: &str,
+    status: reqwest::StatusCode,
+    provider_request_id: Option<String>,
+    response_body: &serde_json::Value,
+) -> CompletionError {
+    let mut error = ProviderApiError::from_response(provider, status.as_u16(), response_body);
+    error.message = truncate_redacted(&error.message, MAX_ERROR_BODY_BYTES);
+    if provider_request_id.is_some() {
+        error.provider_request_id = provider_request_id;
+    }
+    error.into()
+}
+
+/// Response headers providers put their own request id in, which their
+/// support asks for.
+const PROVIDER_REQUEST_ID_HEADERS: &[&str] = &["request-id", "x-request-id"];
+
+/// The provider's id for a request, from its response headers.
+pub(crate) fn provider_request_id(headers: &reqwest::header::HeaderMap) -> Option<String> {
+    PROVIDER_REQUEST_ID_HEADERS
+        .iter()
+        .filter_map(|name| headers.get(*name)?.to_str().ok())
+        .find(|id| !id.is_empty())
+        .map(str::to_string)
+}
+
+/// ` (provider request id: ...)` for error messages, or nothing.
+fn provider_request_note(provider_request_id: Option<&str>) -> String {
+    provider_request_id
+        .map(|id| format!(" (provider request id: {id})"))
+        .unwrap_or_default()
+}
+
+fn with_provider_request_id(
+    mut response: completion::CompletionResponse<RawResponse>,
+    provider_request_id: Option<String>,
+) -> completion::CompletionResponse<RawResponse> {
+    response.raw_response.provider_request_id = provider_request_id;
+    response
+}
+
+fn tool_result_content_to_string(content: &OneOrMany<rig::message::ToolResultContent>) -> String {
+    content
+        .iter()
+        .map(|c| match c {
+            rig::message::ToolResultContent::Text(t) => t.text.clone(),
+            rig::message::ToolResultContent::Image(_) => {
+                "[image omitted: this provider doesn't accept images in tool results]".into()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Anthropic tool result content: a plain string, or blocks when the tool
+/// returned images. The oldest images are replaced with a placeholder while
+/// `images_to_drop` lasts.
+fn tool_result_content_anthropic(
+    content: &OneOrMany<rig::message::ToolResultContent>,
+    images_to_drop: &mut usize,
+) -> serde_json::Value {
+    let has_images = content
+        .iter()
+        .any(|c| matches!(c, rig::message::ToolResultContent::Image(_)));
+    if !has_images {
+        return serde_json::json!(tool_result_content_to_string(content));
+    }
+
+    let blocks = content
+        .iter()
+        .filter_map(|c| match c {
+            rig::message::ToolResultContent::Text(t) => {
+                Some(serde_json::json!({"type": "text", "text": t.text}))
+            }
+            rig::message::ToolResultContent::Image(_) if *images_to_drop > 0 => {
+                *images_to_drop -= 1;
+                Some(serde_json::json!({"type": "text", "text": "[earlier image omitted]"}))
+            }
+            rig::message::ToolResultContent::Image(image) => convert_image_anthropic(image),
+        })
+        .collect();
+    serde_json::Value::Array(blocks)
+}
+
+fn count_tool_result_images(messages: &OneOrMany<Message>) -> usize {
+    messages
+        .iter()
+        .filter_map(|message| match message {
+            Message::User { content } => Some(content),
+            Message::Assistant { .. } => None,
+        })
+        .flat_map(|content| content.iter())
+        .filter_map(|c| match c {
+            UserContent::ToolResult(result) => Some(&result.content),
+            _ => None,
+        })
+        .flat_map(|content| content.iter())
+        .filter(|c| matches!(c, rig::message::ToolResultContent::Image(_)))
+        .count()
+}
+
+#[derive(Debug)]
+struct AssistantToolCallReasoningStats {
+    assistant_tool_call_messages: usize,
+    messages_with_reasoning_content: usize,
+    messages_with_empty_reasoning_content: usize,
+    missing_reasoning_content_indices: Vec<usize>,
+}
+
+fn collect_assistant_tool_call_reasoning_stats(
+    messages: &[serde_json::Value],
+) -> AssistantToolCallReasoningStats {
+    let mut stats = AssistantToolCallReasoningStats {
+        assistant_tool_call_messages: 0,
+        messages_with_reasoning_content: 0,
+        messages_with_empty_reasoning_content: 0,
+        missing_reasoning_content_indices: Vec::new(),
+    };
+
+    for (index, message) in messages.iter().enumerate() {
+        let is_assistant = message["role"].as_str() == Some("assistant");
+        let has_tool_calls = message["tool_calls"]
+            .as_array()
+            .map(|tool_calls| !tool_calls.is_empty())
+            .unwrap_or(false);
+
+        if !(is_assistant && has_tool_calls) {
+            continue;
+        }
+
+        stats.assistant_tool_call_messages += 1;
+
+        match &message["reasoning_content"] {
+            serde_json::Value::String(value) => {
+                stats.messages_with_reasoning_content += 1;
+                if value.is_empty() {
+                    stats.messages_with_empty_reasoning_content += 1;
+                }
+            }
+            serde_json::Value::Array(values) => {
+                stats.messages_with_reasoning_content += 1;
+                if values.is_empty() {
+                    stats.messages_with_empty_reasoning_content += 1;
+                }
+            }
+            serde_json::Value::Null => {
+                stats.missing_reasoning_content_indices.push(index);
+            }
+            _ => {
+                stats.messages_with_reasoning_content += 1;
+            }
+        }
+    }
+
+    stats
+}
+
+// --- Message conversion ---
+
+fn convert_messages_to_anthropic(messages: &OneOrMany<Message>) -> Vec<serde_json::Value> {
+    let mut images_to_drop =
+        count_tool_result_images(messages).saturating_sub(MAX_TOOL_RESULT_IMAGES);
+    messages
+        .iter()
+        .map(|message| match message {
+            Message::User { content } => {
+                let parts: Vec<serde_json::Value> = content
+                    .iter()
+                    .filter_map(|c| match c {
+                        UserContent::Text(t) => {
+                            Some(serde_json::json!({"type": "text", "text": t.text}))
+                        }
+                        UserContent::Image(image) => convert_image_anthropic(image),
+                        UserContent::ToolResult(result) => Some(serde_json::json!({
+                            "type": "tool_result",
+                            "tool_use_id": result.id,
+                            "content": tool_result_content_anthropic(
+                                &result.content,
+                                &mut images_to_drop,
+                            ),
+                        })),
+                        _ => None,
+                    })
+                    .collect();
+                serde_json::json!({"role": "user", "content": parts})
+            }
+            Message::Assistant { content, .. } => {
+                let parts: Vec<serde_json::Value> = content
+                    .iter()
+                    .filter_map(|c| match c {
+                        AssistantContent::Text(t) => {
+                            Some(serde_json::json!({"type": "text", "text": t.text}))
+                        }
+                        AssistantContent::ToolCall(tc) => Some(serde_json::json!({
+                            "type": "tool_use",
+                            "id": tc.id,
+                            "name": tc.function.name,
+                            "input": tc.function.arguments,
+                        })),
+                        _ => None,
+                    })
+                    .collect();
+                serde_json::json!({"role": "assistant", "content": parts})
+            }
+        })
+        .collect()
+}
+
+fn convert_messages_to_openai(
+    messages: &OneOrMany<Message>,
+    include_reasoning_content: bool,
+) -> Vec<serde_json::Value> {
+    let mut result = Vec::new();
+
+    for message in messages.iter() {
+        match message {
+            Message::User { content } => {
+                // Separate tool results (they need their own messages) from content parts
+                let mut content_parts: Vec<serde_json::Value> = Vec::new();
+                let mut tool_results: Vec<serde_json::Value> = Vec::new();
+
+                for item in content.iter() {
+                    match item {
+                        UserContent::Text(t) => {
+                            content_parts.push(serde_json::json!({
+                                "type": "text",
+                                "text": t.text,
+                            }));
+                        }
+                        UserContent::Image(image) => {
+                            if let Some(part) = convert_image_openai(image) {
+                                content_parts.push(part);
+                            }
+                        }
+                        UserContent::ToolResult(tr) => {
+                            tool_results.push(serde_json::json!({
+                                "role": "tool",
+                                "tool_call_id": tr.id,
+                                "content": tool_result_content_to_string(&tr.content),
+                            }));
+                        }
+                        _ => {}
+                    }
+                }
+
+                if !content_parts.is_empty() {
+                    // If there's only one text part and no images, use simple string format
+                    if content_parts.len() == 1 && content_parts[0]["type"] == "text" {
+                        result.push(serde_json::json!({
+                            "role": "user",
+                            "content": content_parts[0]["text"],
+                        }));
+                    } else {
+                        // Mixed content (text + images): use array-of-parts format
+                        result.push(serde_json::json!({
+                            "role": "user",
+                            "content": content_parts,
+                        }));
+                    }
+                }
+
+                result.extend(tool_results);
+            }
+            Message::Assistant { content, .. } => {
+                let mut text_parts = Vec::new();
+                let mut tool_calls = Vec::new();
+                let mut reasoning_parts = Vec::new();
+
+                for item in content.iter() {
+                    match item {
+                        AssistantContent::Text(t) => {
+                            text_parts.push(t.text.clone());
+                        }
+                        AssistantContent::ToolCall(tc) => {
+                            // OpenAI expects arguments as a JSON string
+                            let args_string = serde_json::to_string(&tc.function.arguments)
+                                .unwrap_or_else(|_| "{}".to_string());
+                            tool_calls.push(serde_json::json!({
+                                "id": tc.id,
+                                "type": "function",
+                                "function": {
+                                    "name": tc.function.name,
+                                    "arguments": args_string,
+                                }
+                            }));
+                        }
+                        AssistantContent::Reasoning(reasoning) => {
+                            reasoning_parts.extend(reasoning.reasoning.iter().cloned());
+                        }
+                        _ => {}
+                    }
+                }
+
+                let mut msg = serde_json::json!({"role": "assistant"});
+                if !text_parts.is_empty() {
+                    msg["content"] = serde_json::json!(text_parts.join("\n"));
+                }
+                if !tool_calls.is_empty() {
+                    msg["tool_calls"] = serde_json::json!(tool_calls);
+                }
+                if include_reasoning_content && !tool_calls.is_empty() {
+                    msg["reasoning_content"] = serde_json::json!(reasoning_parts.join("\n"));
+                }
+                result.push(msg);
+            }
+        }
+    }
+
+    result
+}
+
+// --- Image conversion helpers ---
+
+/// Convert a rig Image to an Anthropic image content block.
+/// Anthropic format: {"type": "image", "source": {"type": "base64", "media_type": "image/jpeg", "data": "..."}}
+fn convert_image_anthropic(image: &Image) -> Option<serde_json::Value> {
+    let media_type = image
+        .media_type
+        .as_ref()
+        .map(|mt| mt.to_mime_type())
+        .unwrap_or("image/jpeg");
+
+    match &image.data {
+        DocumentSourceKind::Base64(data) => Some(serde_json::json!({
+            "type": "image",
+            "source": {
+                "type": "base64",
+                "media_type": media_type,
+                "data": data,
+            }
+        })),
+        DocumentSourceKind::Url(url) => Some(serde_json::json!({
+            "type": "image",
+            "source": {
+                "type": "url",
+                "url": url,
+            }
+        })),
+        _ => None,
+    }
+}
+
+/// Convert a rig Image to an OpenAI image_url content part.
+/// OpenAI/OpenRouter format: {"type": "image_url", "image_url": {"url": "data:image/jpeg;base64,..."}}
+fn convert_image_openai(image: &Image) -> Option<serde_json::Value> {
+    let media_type = image
+        .media_type
+        .as_ref()
+        .map(|mt| mt.to_mime_type())
+        .unwrap_or("image/jpeg");
+
+    match &image.data {
+        DocumentSourceKind::Base64(data) => {
+            let data_url = format!("data:{media_type};base64,{data}");
+            Some(serde_json::json!({
+                "type": "image_url",
+                "image_url": { "url": data_url }
+            }))
+        }
+        DocumentSourceKind::Url(url) => Some(serde_json::json!({
+            "type": "image_url",
+            "image_url": { "url": url }
+        })),
+        _ => None,
+    }
+}
+
+// --- Response parsing ---
+
+/// Read a grammar-constrained reply that names a tool as that tool call.
+/// Plain-text replies pass through.
+fn recover_tool_call(
+    mut response: completion::CompletionResponse<RawResponse>,
+    tools: &[rig::completion::ToolDefinition],
+) -> completion::CompletionResponse<RawResponse> {
+    let text: String = response
+        .choice
+        .iter()
+        .filter_map(|content| match content {
+            AssistantContent::Text(text) => Some(text.text.as_str()),
+            _ => None,
+        })
+        .collect();
+    if let Some((name, arguments)) = grammar::parse_tool_call(&text, tools) {
+        let id = format!("call_{}", uuid::Uuid::new_v4().simple());
+        response.choice = OneOrMany::one(AssistantContent::ToolCall(make_tool_call(
+            id, name, arguments,
+        )));
+    }
+    response
+}
+
+fn make_tool_call(id: String, name: String, arguments: serde_json::Value) -> ToolCall {
+    ToolCall {
+        id,
+        call_id: None,
+        function: ToolFunction {
+            name: name.trim().to_string(),
+            arguments,
+        },
+        signature: None,
+        additional_params: None,
+    }
+}
+
+/// A deviation from the documented response shape that parsing worked around.
+///
+/// Parsing is best effort: anything that can be salvaged without guessing at
+/// the model's intent is accepted, and each workaround is reported as a
+/// warning so provider quirks show up in logs and metrics instead of as
+/// silently dropped content. Only responses with nothing usable in them fail.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ParseWarning {
+    /// No `usage` object; token counts are reported as zero.
+    MissingUsage,
+    /// Message content arrived as an array of parts rather than a string.
+    ContentParts,
+    /// Message content was neither a string, an array, nor null.
+    UnexpectedContentType,
+    /// `reasoning_content` was neither a string nor an array of strings.
+    UnexpectedReasoningShape,
+    /// Tool call arguments were already a JSON object instead of a string.
+    ToolArgumentsNotString,
+    /// Tool call arguments were a string that didn't parse as JSON.
+    ToolArgumentsNotJson,
+    /// A tool call had no id; one was synthesized.
+    MissingToolCallId,
+    /// A content block of a type we don't know how to handle was skipped.
+    UnknownContentBlock,
+}
+
+impl ParseWarning {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::MissingUsage => "missing_usage",
+            Self::ContentParts => "content_parts",
+            Self::UnexpectedContentType => "unexpected_content_type",
+            Self::UnexpectedReasoningShape => "unexpected_reasoning_shape",
+            Self::ToolArgumentsNotString => "tool_arguments_not_string",
+            Self::ToolArgumentsNotJson => "tool_arguments_not_json",
+            Self::MissingToolCallId => "missing_tool_call_id",
+            Self::UnknownContentBlock => "unknown_content_block",
+        }
+    }
+}
+
+impl std::fmt::Display for ParseWarning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Read a token count that some providers send as a float or a string.
+fn read_token_count(value: &serde_json::Value) -> u64 {
+    value
+        .as_u64()
+        .or_else(|| value.as_f64().map(|count| count.max(0.0) as u64))
+        .or_else(|| value.as_str().and_then(|count| count.trim().parse().ok()))
+        .unwrap_or(0)
+}
+
+/// Pull the text out of a content value that may be a string or a list of
+/// `{"type": "text", "text": ...}` parts.
+fn read_text_content(content: &serde_json::Value, warnings: &mut Vec<ParseWarning>) -> String {
+    match content {
+        serde_json::Value::Null => String::new(),
+        serde_json::Value::String(text) => text.clone(),
+        serde_json::Value::Array(parts) => {
+            warnings.push(ParseWarning::ContentParts);
+            parts
+                .iter()
+                .filter_map(|part| part.as_str().or_else(|| part["text"].as_str()))
+                .collect::<Vec<_>>()
+                .join("")
+        }
+        _ => {
+            warnings.push(ParseWarning::UnexpectedContentType);
+            String::new()
+        }
+    }
+}
+
+/// Parse an Anthropic Messages API response. See [`ParseWarning`] for what
+/// best effort means here.
+fn parse_anthropic_response(
+    body: serde_json::Value,
+    warnings: &mut Vec<ParseWarning>,
+) -> Result<completion::CompletionResponse<RawResponse>, CompletionError> {
+    let mut assistant_content = Vec::new();
+
+    match &body["content"] {
+        serde_json::Value::Array(content_blocks) => {
+            for (index, block) in content_blocks.iter().enumerate() {
+                match block["type"].as_str() {
+                    Some("text") => {
+                        let text = block["text"].as_str().unwrap_or("").to_string();
+                        assistant_content.push(AssistantContent::Text(Text { text }));
+                    }
+                    Some("tool_use") => {
+                        let id = match block["id"].as_str() {
+                            Some(id) if !id.is_empty() => id.to_string(),
+                            _ => {
+                                warnings.push(ParseWarning::MissingToolCallId);
+                                format!("toolu_{index}")
+                            }
+                        };
+                        let name = block["name"].as_str().unwrap_or("").to_string();
+                        let arguments = if block["input"].is_null() {
+                            serde_json::json!({})
+                        } else {
+                            block["input"].clone()
+                        };
+                        assistant_content.push(AssistantContent::ToolCall(make_tool_call(
+                            id, name, arguments,
+                        )));
+                    }
+                    // Extended thinking blocks are expected and intentionally not replayed.
+                    Some("thinking") | Some("redacted_thinking") => {}
+                    _ => warnings.push(ParseWarning::UnknownContentBlock),
+                }
+            }
+        }
+        serde_json::Value::String(text) => {
+            warnings.push(ParseWarning::UnexpectedContentType);
+            assistant_content.push(AssistantContent::Text(Text { text: text.clone() }));
+        }
+        _ => {
+            return Err(CompletionError::ResponseError(
+                "missing content array".into(),
+            ));
+        }
+    }
+
+    let choice = OneOrMany::many(assistant_content)
+        .map_err(|_| CompletionError::ResponseError("empty response from Anthropic".into()))?;
+
+    let usage = &body["usage"];
+    if !usage.is_object() {
+        warnings.push(ParseWarning::MissingUsage);
+    }
+    let input_tokens = read_token_count(&usage["input_tokens"]);
+    let output_tokens = read_token_count(&usage["output_tokens"]);
+    let cached = read_token_count(&usage["cache_read_input_tokens"]);
+
+    Ok(completion::CompletionResponse {
+        choice,
+        usage: completion::Usage {
+            input_tokens,
+            output_tokens,
+            total_tokens: input_tokens + output_tokens,
+            cached_input_tokens: cached,
+        },
+        raw_response: RawResponse::new(body),
+    })
+}
+
+/// Parse an OpenAI-style chat completion response. Shared by every
+/// OpenAI-compatible provider; see [`ParseWarning`] for what best effort
+/// means here.
+fn parse_openai_response(
+    body: serde_json::Value,
+    provider_label: &str,
+    warnings: &mut Vec<ParseWarning>,
+) -> Result<completion::CompletionResponse<RawResponse>, CompletionError> {
+    let choice = &body["choices"][0]["message"];
+    if !choice.is_object() {
+        return Err(CompletionError::ResponseError(format!(
+            "{provider_label} response has no choices"
+        )));
+    }
+
+    let result_choice = OneOrMany::many(parse_openai_message(choice, warnings)).map_err(|_| {
+        CompletionError::ResponseError(format!("empty response from {provider_label}"))
+    })?;
+
+    // Asked for several choices with `n`, each one is a candidate.
+    let mut candidates = Vec::new();
+    if let Some(choices) = body["choices"]
+        .as_array()
+        .filter(|choices| choices.len() > 1)
+    {
+        candidates.push(Candidate::new(result_choice.clone()));
+        candidates.extend(
+            choices[1..]
+                .iter()
+                .filter_map(|choice| {
+                    OneOrMany::many(parse_openai_message(&choice["message"], warnings)).ok()
+                })
+                .map(Candidate::new),
+        );
+    }
+
+    let usage = &body["usage"];
+    if !usage.is_object() {
+        warnings.push(ParseWarning::MissingUsage);
+    }
+    let input_tokens = read_token_count(&usage["prompt_tokens"]);
+    let output_tokens = read_token_count(&usage["completion_tokens"]);
+    let cached = read_token_count(&usage["prompt_tokens_details"]["cached_tokens"]);
+
+    Ok(completion::CompletionResponse {
+        choice: result_choice,
+        usage: completion::Usage {
+            input_tokens,
+            output_tokens,
+            total_tokens: input_tokens + output_tokens,
+            cached_input_tokens: cached,
+        },
+        raw_response: RawResponse {
+            candidates,
+            ..RawResponse::new(body)
+        },
+    })
+}
+
+/// The text, reasoning and tool calls of one OpenAI-style choice's message.
+fn parse_openai_message(
+    choice: &serde_json::Value,
+    warnings: &mut Vec<ParseWarning>,
+) -> Vec<AssistantContent> {
+    let mut assistant_content = Vec::new();
+
+    let text = read_text_content(&choice["content"], warnings);
+    if !text.is_empty() {
+        assistant_content.push(AssistantContent::Text(Text { text }));
+    }
+
+    // Some OpenAI-compatible providers (OpenRouter, DeepSeek mirrors) name
+    // the field `reasoning` instead of `reasoning_content`.
+    let reasoning_value = if choice["reasoning_content"].is_null() {
+        &choice["reasoning"]
+    } else {
+        &choice["reasoning_content"]
+    };
+    match reasoning_value {
+        serde_json::Value::Null => {}
+        serde_json::Value::String(reasoning_content) => {
+            if !reasoning_content.is_empty() {
+                assistant_content.push(AssistantContent::Reasoning(rig::message::Reasoning::new(
+                    reasoning_content,
+                )));
+            }
+        }
+        serde_json::Value::Array(reasoning_parts) => {
+            let reasoning: Vec<String> = reasoning_parts
+                .iter()
+                .filter_map(|item| {
+                    item.as_str()
+                        .or_else(|| item["text"].as_str())
+                        .map(ToOwned::to_owned)
+                })
+                .collect();
+            if reasoning.len() != reasoning_parts.len() {
+                warnings.push(ParseWarning::UnexpectedReasoningShape);
+            }
+            if !reasoning.is_empty() {
+                assistant_content.push(AssistantContent::Reasoning(
+                    rig::message::Reasoning::multi(reasoning),
+                ));
+            }
+        }
+        _ => warnings.push(ParseWarning::UnexpectedReasoningShape),
+    }
+
+    if let Some(tool_calls) = choice["tool_calls"].as_array() {
+        for (index, tc) in tool_calls.iter().enumerate() {
+            let id = match tc["id"].as_str() {
+                Some(id) if !id.is_empty() => id.to_string(),
+                _ => {
+                    warnings.push(ParseWarning::MissingToolCallId);
+                    format!("call_{index}")
+                }
+            };
+            let name = tc["function"]["name"].as_str().unwrap_or("").to_string();
+            // OpenAI returns arguments as a JSON string, parse it back to Value
+            let arguments = match &tc["function"]["arguments"] {
+                serde_json::Value::String(raw) if raw.trim().is_empty() => serde_json::json!({}),
+                serde_json::Value::String(raw) => serde_json::from_str(raw).unwrap_or_else(|_| {
+                    warnings.push(ParseWarning::ToolArgumentsNotJson);
+                    serde_json::json!({})
+                }),
+                serde_json::Value::Null => serde_json::json!({}),
+                other => {
+                    warnings.push(ParseWarning::ToolArgumentsNotString);
+                    other.clone()
+                }
+            };
+            assistant_content.push(AssistantContent::ToolCall(make_tool_call(
+                id, name, arguments,
+            )));
+        }
+    }
+
+    assistant_content
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+    use rig::message::Reasoning;
+
+    #[test]
+    fn test_convert_messages_to_openai_adds_kimi_reasoning_content_for_tool_calls() {
+        let assistant_content = OneOrMany::many(vec![AssistantContent::ToolCall(make_tool_call(
+            "call_1".to_string(),
+            "shell".to_string(),
+            serde_json::json!({"command": "ls"}),
+        ))])
+        .unwrap();
+        let messages = OneOrMany::many(vec![Message::Assistant {
+            id: None,
+            content: assistant_content,
+        }])
+        .unwrap();
+
+        let converted = convert_messages_to_openai(&messages, true);
+
+        assert_eq!(converted.len(), 1);
+        assert_eq!(converted[0]["reasoning_content"], "");
+        assert!(converted[0]["tool_calls"].is_array());
+    }
+
+    #[test]
+    fn test_convert_messages_to_openai_keeps_reasoning_content_when_present() {
+        let assistant_content = OneOrMany::many(vec![
+            AssistantContent::Reasoning(Reasoning::new("first")),
+            AssistantContent::Reasoning(Reasoning::new("second")),
+            AssistantContent::ToolCall(make_tool_call(
+                "call_1".to_string(),
+                "shell".to_string(),
+                serde_json::json!({"command": "ls"}),
+            )),
+        ])
+        .unwrap();
+        let messages = OneOrMany::many(vec![Message::Assistant {
+            id: None,
+            content: assistant_content,
+        }])
+        .unwrap();
+
+        let converted = convert_messages_to_openai(&messages, true);
+
+        assert_eq!(converted.len(), 1);
+        assert_eq!(converted[0]["reasoning_content"], "first\nsecond");
+    }
+
+    #[test]
+    fn test_parse_openai_response_extracts_reasoning_content() {
+        let body = serde_json::json!({
+            "choices": [{
+                "message": {
+                    "content": "",
+                    "reasoning_content": "plan it",
+                    "tool_calls": [{
+                        "id": "call_1",
+                        "function": {
+                            "name": "shell",
+                            "arguments": "{\"command\":\"ls\"}"
+                        }
+                    }]
+                }
+            }],
+            "usage": {}
+        });
+
+        let parsed =
+            parse_openai_response(body, "Test", &mut Vec::new()).expect("response should parse");
+        let mut saw_reasoning = false;
+        let mut saw_tool_call = false;
+
+        for item in parsed.choice.iter() {
+            match item {
+                AssistantContent::Reasoning(reasoning) => {
+                    saw_reasoning = true;
+                    assert_eq!(reasoning.reasoning, vec!["plan it".to_string()]);
+                }
+                AssistantContent::ToolCall(tool_call) => {
+                    saw_tool_call = true;
+                    assert_eq!(tool_call.function.name, "shell");
+                }
+                _ => {}
+            }
+        }
+
+        assert!(saw_reasoning);
+        assert!(saw_tool_call);
+    }
+
+    #[test]
+    fn test_parse_openai_response_tolerates_content_parts_and_object_arguments() {
+        let body = serde_json::json!({
+            "choices": [{
+                "message": {
+                    "content": [{"type": "text", "text": "hello "}, {"type": "text", "text": "there"}],
+                    "reasoning": "thinking",
+                    "tool_calls": [{
+                        "function": {"name": "shell", "arguments": {"command": "ls"}}
+                    }]
+                }
+            }],
+            "usage": {"prompt_tokens": 12.0, "completion_tokens": "3"}
+        });
+
+        let mut warnings = Vec::new();
+        let parsed =
+            parse_openai_response(body, "Test", &mut warnings).expect("response should parse");
+
+        assert_eq!(parsed.usage.input_tokens, 12);
+        assert_eq!(parsed.usage.output_tokens, 3);
+        assert!(warnings.contains(&ParseWarning::ContentParts));
+        assert!(warnings.contains(&ParseWarning::ToolArgumentsNotString));
+        assert!(warnings.contains(&ParseWarning::MissingToolCallId));
+
+        let tool_call = parsed
+            .choice
+            .iter()
+            .find_map(|item| match item {
+                AssistantContent::ToolCall(tool_call) => Some(tool_call),
+                _ => None,
+            })
+            .expect("tool call should be parsed");
+        assert_eq!(tool_call.id, "call_0");
+        assert_eq!(tool_call.function.arguments["command"], "ls");
+        assert!(parsed.choice.iter().any(
+            |item| matches!(item, AssistantContent::Text(text) if text.text == "hello there")
+        ));
+        assert!(
+            parsed
+                .choice
+                .iter()
+                .any(|item| matches!(item, AssistantContent::Reasoning(_)))
+        );
+    }
+
+    #[test]
+    fn test_parse_openai_response_keeps_every_choice_as_a_candidate() {
+        let body = serde_json::json!({
+            "choices": [
+                {"message": {"content": "first"}},
+                {"message": {"content": "second"}},
+                {"message": {"content": ""}},
+                {"message": {"content": "fourth"}}
+            ],
+            "usage": {"prompt_tokens": 10, "completion_tokens": 9}
+        });
+
+        let parsed =
+            parse_openai_response(body, "Test", &mut Vec::new()).expect("response should parse");
+
+        let texts: Vec<String> = parsed
+            .raw_response
+            .candidates
+            .iter()
+            .map(|candidate| render_output(&candidate.choice))
+            .collect();
+        assert_eq!(texts, ["first", "second", "fourth"]);
+        assert_eq!(render_output(&parsed.choice), "first");
+        // The provider reports every choice's tokens together.
+        assert_eq!(parsed.usage.output_tokens, 9);
+
+        let single = serde_json::json!({"choices": [{"message": {"content": "only"}}]});
+        let parsed =
+            parse_openai_response(single, "Test", &mut Vec::new()).expect("response should parse");
+        assert!(parsed.raw_response.candidates.is_empty());
+    }
+
+    #[test]
+    fn test_parse_anthropic_response_skips_thinking_and_flags_unknown_blocks() {
+        let body = serde_json::json!({
+            "content": [
+                {"type": "thinking", "thinking": "hmm"},
+                {"type": "server_tool_result", "content": []},
+                {"type": "text", "text": "done"},
+                {"type": "tool_use", "id": "toolu_1", "name": "shell", "input": null}
+            ]
+        });
+
+        let mut warnings = Vec::new();
+        let parsed = parse_anthropic_response(body, &mut warnings).expect("response should parse");
+
+        assert_eq!(parsed.choice.len(), 2);
+        assert_eq!(
+            warnings,
+            vec![
+                ParseWarning::UnknownContentBlock,
+                ParseWarning::MissingUsage
+            ]
+        );
+    }
+
+    /// Malformed and unusual shapes must produce an error or a best-effort
+    /// parse, never a panic.
+    #[test]
+    fn test_parsers_never_panic_on_malformed_bodies() {
+        let bodies = [
+            serde_json::json!(null),
+            serde_json::json!([]),
+            serde_json::json!("text"),
+            serde_json::json!({}),
+            serde_json::json!({"choices": []}),
+            serde_json::json!({"choices": [null]}),
+            serde_json::json!({"choices": [{"message": {"content": 42}}]}),
+            serde_json::json!({"choices": [{"message": {"content": null, "tool_calls": "nope"}}]}),
+            serde_json::json!({"choices": [{"message": {"content": "", "reasoning_content": {"x": 1}}}]}),
+            serde_json::json!({"choices": [{"message": {"tool_calls": [null, {"function": null}]}}]}),
+            serde_json::json!({"choices": [{"message": {"tool_calls": [{"function": {"name": "x", "arguments": "{not json"}}]}}]}),
+            serde_json::json!({"choices": [{"message": {"content": "ok"}}], "usage": {"prompt_tokens": -1.5}}),
+            serde_json::json!({"content": null}),
+            serde_json::json!({"content": []}),
+            serde_json::json!({"content": "plain"}),
+            serde_json::json!({"content": [null, 1, {"type": null}, {"type": "tool_use"}]}),
+            serde_json::json!({"content": [{"type": "text"}], "usage": {"input_tokens": "many"}}),
+        ];
+
+        for body in bodies {
+            let _ = parse_openai_response(body.clone(), "Test", &mut Vec::new());
+            let _ = parse_anthropic_response(body, &mut Vec::new());
+        }
+
+        let mut warnings = Vec::new();
+        let parsed = parse_openai_response(
+            serde_json::json!({"choices": [{"message": {"tool_calls": [{"id": "c", "function": {"name": "x", "arguments": "{not json"}}]}}]}),
+            "Test",
+            &mut warnings,
+        )
+        .expect("tool call should survive bad arguments");
+        assert_eq!(parsed.choice.len(), 1);
+        assert!(warnings.contains(&ParseWarning::ToolArgumentsNotJson));
+    }
+
+    /// Field names and values the parsers look for, so generated bodies
+    /// reach past the first check.
+    const RESPONSE_WORDS: &[&str] = &[
+        "choices",
+        "message",
+        "content",
+        "reasoning_content",
+        "tool_calls",
+        "function",
+        "name",
+        "arguments",
+        "id",
+        "type",
+        "text",
+        "tool_use",
+        "input",
+        "thinking",
+        "usage",
+        "prompt_tokens",
+        "completion_tokens",
+        "input_tokens",
+        "output_tokens",
+    ];
+
+    fn response_word() -> impl Strategy<Value = String> {
+        prop_oneof![
+            prop::sample::select(RESPONSE_WORDS).prop_map(str::to_string),
+            "[a-z_{}]{0,10}",
+        ]
+    }
+
+    /// Arbitrary JSON, biased towards response field names.
+    fn json_value() -> impl Strategy<Value = serde_json::Value> {
+        let leaf = prop_oneof![
+            Just(serde_json::Value::Null),
+            any::<bool>().prop_map(serde_json::Value::from),
+            any::<i64>().prop_map(serde_json::Value::from),
+            any::<f64>().prop_map(serde_json::Value::from),
+            response_word().prop_map(serde_json::Value::from),
+        ];
+        leaf.prop_recursive(5, 96, 6, |inner| {
+            prop_oneof![
+                prop::collection::vec(inner.clone(), 0..6).prop_map(serde_json::Value::from),
+                prop::collection::vec((response_word(), inner), 0..6)
+                    .prop_map(|fields| serde_json::Value::Object(fields.into_iter().collect())),
+            ]
+        })
+    }
+
+    proptest! {
+        /// A body without the shape a parser needs is an error, and no body
+        /// makes either parser panic.
+        #[test]
+        fn test_parsers_reject_arbitrary_json(body in json_value()) {
+            let openai = parse_openai_response(body.clone(), "Test", &mut Vec::new());
+            if !body["choices"][0]["message"].is_object() {
+                prop_assert!(openai.is_err());
+            }
+            let anthropic = parse_anthropic_response(body.clone(), &mut Vec::new());
+            if !matches!(
+                body["content"],
+                serde_json::Value::Array(_) | serde_json::Value::String(_)
+            ) {
+                prop_assert!(anthropic.is_err());
+            }
+        }
+
+        #[test]
+        fn test_parsers_never_panic_inside_a_response(
+            message in json_value(),
+            content in json_value(),
+            usage in json_value(),
+        ) {
+            let openai = serde_json::json!({"choices": [{"message": message}], "usage": usage});
+            let result = parse_openai_response(openai, "Test", &mut Vec::new());
+            if !message.is_object() {
+                prop_assert!(result.is_err());
+            }
+            let anthropic = serde_json::json!({"content": content, "usage": usage});
+            let _ = parse_anthropic_response(anthropic, &mut Vec::new());
+        }
+    }
+
+    /// Golden-file harness for message conversion. Each directory under
+    /// `tests/fixtures/message_conversion` holds a `request.json` (rig messages)
+    /// and one expected body per provider dialect. Run with `UPDATE_GOLDEN=1`
+    /// to rewrite the expected files after an intentional change, then review
+    /// the fixture diff.
+    #[test]
+    fn test_message_conversion_golden_files() {
+        let fixtures_dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures/message_conversion");
+        let update = std::env::var_os("UPDATE_GOLDEN").is_some();
+
+        let mut case_dirs: Vec<_> = std::fs::read_dir(&fixtures_dir)
+            .expect("fixture directory should exist")
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.is_dir())
+            .collect();
+        case_dirs.sort();
+        assert!(!case_dirs.is_empty(), "no golden cases found");
+
+        let mut mismatches = Vec::new();
+        for case_dir in case_dirs {
+            let request = std::fs::read_to_string(case_dir.join("request.json"))
+                .expect("case should have request.json");
+            let messages: Vec<Message> =
+                serde_json::from_str(&request).expect("request.json should hold rig messages");
+            let messages = OneOrMany::many(messages).expect("request.json should not be empty");
+
+            let outputs = [
+                (
+                    "anthropic.json",
+                    serde_json::json!(convert_messages_to_anthropic(&messages)),
+                ),
+                (
+                    "openai.json",
+                    serde_json::json!(convert_messages_to_openai(&messages, false)),
+                ),
+                (
+                    "openai_reasoning_content.json",
+                    serde_json::json!(convert_messages_to_openai(&messages, true)),
+                ),
+            ];
+
+            for (file_name, actual) in outputs {
+                let path = case_dir.join(file_name);
+                let rendered = format!(
+                    "{}\n",
+                    serde_json::to_string_pretty(&actual).expect("json should serialize")
+                );
+                if update {
+                    std::fs::write(&path, &rendered).expect("golden file should be writable");
+                    continue;
+                }
+                let expected = std::fs::read_to_string(&path).unwrap_or_default();
+                if expected != rendered {
+                    mismatches.push(format!(
+                        "{}\n--- expected\n{expected}\n+++ actual\n{rendered}",
+                        path.display()
+                    ));
+                }
+            }
+        }
+
+        assert!(
+            mismatches.is_empty(),
+            "golden files out of date (rerun with UPDATE_GOLDEN=1 if intended):\n\n{}",
+            mismatches.join("\n")
+        );
+    }
+
+    /// Summarize a parsed response for the provider fixture harness.
+    fn summarize_parsed_response(
+        result: Result<completion::CompletionResponse<RawResponse>, CompletionError>,
+        warnings: &[ParseWarning],
+    ) -> serde_json::Value {
+        let warnings: Vec<_> = warnings.iter().map(ParseWarning::as_str).collect();
+        let response = match result {
+            Ok(response) => response,
+            Err(error) => {
+                return serde_json::json!({ "error": error.to_string(), "warnings": warnings });
+            }
+        };
+        let content: Vec<_> = response
+            .choice
+            .iter()
+            .map(|content| match content {
+                AssistantContent::Text(text) => {
+                    serde_json::json!({ "type": "text", "text": text.text })
+                }
+                AssistantContent::ToolCall(tool_call) => serde_json::json!({
+                    "type": "tool_call",
+                    "id": tool_call.id,
+                    "name": tool_call.function.name,
+                    "arguments": tool_call.function.arguments,
+                }),
+                AssistantContent::Reasoning(reasoning) => {
+                    serde_json::json!({ "type": "reasoning", "reasoning": reasoning.reasoning })
+                }
+                AssistantContent::Image(_) => serde_json::json!({ "type": "image" }),
+            })
+            .collect();
+        serde_json::json!({
+            "content": content,
+            "usage": {
+                "input_tokens": response.usage.input_tokens,
+                "output_tokens": response.usage.output_tokens,
+                "cached_input_tokens": response.usage.cached_input_tokens,
+            },
+            "warnings": warnings,
+        })
+    }
+
+    /// Replays recorded provider responses. Each directory under
+    /// `tests/fixtures/provider_responses` holds an `exchange.json` (recorded
+    /// with the `record` feature, see `llm::fixtures`) and an `expected.json`
+    /// with what the parser made of it. `UPDATE_GOLDEN=1` writes the expected
+    /// files, as for the message conversion harness.
+    #[test]
+    fn test_provider_response_fixtures() {
+        let fixtures_dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures/provider_responses");
+        let update = std::env::var_os("UPDATE_GOLDEN").is_some();
+
+        let mut case_dirs: Vec<_> = std::fs::read_dir(&fixtures_dir)
+            .expect("fixture directory should exist")
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.is_dir())
+            .collect();
+        case_dirs.sort();
+        assert!(!case_dirs.is_empty(), "no provider response cases found");
+
+        let mut mismatches = Vec::new();
+        for case_dir in case_dirs {
+            let exchange = std::fs::read_to_string(case_dir.join("exchange.json"))
+                .expect("case should have exchange.json");
+            let exchange: serde_json::Value =
+                serde_json::from_str(&exchange).expect("exchange.json should be valid json");
+            let provider = exchange["provider"].as_str().unwrap_or_default();
+            let body = exchange["response"].clone();
+
+            let mut warnings = Vec::new();
+            let result = match provider {
+                "anthropic" => parse_anthropic_response(body, &mut warnings),
+                _ => parse_openai_response(body, provider, &mut warnings),
+            };
+            let actual = summarize_parsed_response(result, &warnings);
+
+            let path = case_dir.join("expected.json");
+            let rendered = format!(
+                "{}\n",
+                serde_json::to_string_pretty(&actual).expect("json should serialize")
+            );
+            if update {
+                std::fs::write(&path, &rendered).expect("golden file should be writable");
+                continue;
+            }
+            let expected = std::fs::read_to_string(&path).unwrap_or_default();
+            if expected != rendered {
+                mismatches.push(format!(
+                    "{}\n--- expected\n{expected}\n+++ actual\n{rendered}",
+                    path.display()
+                ));
+            }
+        }
+
+        assert!(
+            mismatches.is_empty(),
+            "provider response fixtures out of date (rerun with UPDATE_GOLDEN=1 if intended):\n\n{}",
+            mismatches.join("\n")
+        );
+    }
+
+    #[test]
+    fn test_provider_api_error_keeps_structured_fields() {
+        let anthropic = serde_json::json!({
+            "type": "error",
+            "error": {
+                "type": "invalid_request_error",
+                "message": "tools.3.input_schema: Field required"
+            }
+        });
+        let error = provider_api_error(
+            "Anthropic",
+            reqwest::StatusCode::BAD_REQUEST,
+            Some("req_011".into()),
+            &anthropic,
+        );
+        assert!(error.to_string().ends_with(
+            "Anthropic API error (400 Bad Request): \
+             invalid_request_error: tools.3.input_schema: Field required \
+             (provider request id: req_011)"
+        ));
+
+        // The typed error survives being wrapped by the agentic loop.
+        let prompt_error = rig::completion::PromptError::CompletionError(error);
+        let found = ProviderApiError::find(&prompt_error).expect("provider error should be found");
+        assert_eq!(found.error_type.as_deref(), Some("invalid_request_error"));
+        assert_eq!(found.status, 400);
+
+        let openai = serde_json::json!({
+            "error": {
+                "message": "Invalid schema for function 'search'.",
+                "type": "invalid_request_error",
+                "param": "tools[3].function.parameters",
+                "code": "invalid_function_parameters"
+            }
+        });
+        let error = ProviderApiError::from_response("OpenAI", 400, &openai);
+        assert_eq!(error.code.as_deref(), Some("invalid_function_parameters"));
+        assert_eq!(
+            error.detail(),
+            "invalid_request_error: tools[3].function.parameters: Invalid schema for function 'search'."
+        );
+
+        let bare = ProviderApiError::from_response(
+            "Ollama",
+            404,
+            &serde_json::json!({"error": "model not found"}),
+        );
+        assert_eq!(bare.detail(), "model not found");
+        assert!(ProviderApiError::find(&CompletionError::ProviderError("x".into())).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_seed_goes_in_each_providers_field() {
+        let manager = Arc::new(
+            LlmManager::builder()
+                .provider_key("openai", "sk-test")
+                .build()
+                .unwrap(),
+        );
+        let body_for = |model: &str| {
+            let model = SpacebotModel::make(&manager, model).with_seed(Some(42));
+            let mut body = serde_json::json!({});
+            model.apply_seed(&model.provider, &mut body);
+            body
+        };
+
+        assert_eq!(body_for("openai/gpt-4.1"), serde_json::json!({"seed": 42}));
+        assert_eq!(
+            body_for("mistral/mistral-large-latest"),
+            serde_json::json!({"random_seed": 42})
+        );
+        assert_eq!(
+            body_for("anthropic/claude-sonnet-4-20250514"),
+            serde_json::json!({})
+        );
+    }
+}
@@ -0,0 +1,103 @@
+//! Changes in what providers serve behind a model name.
+//!
+//! Providers announce deprecations in response headers (`Deprecation`,
+//! `Sunset`) or, once a model is gone, in error messages, and a requested
+//! name is often an alias the provider resolves to a dated snapshot. Both
+//! change a model's behavior without any change on our side, so each is
+//! noted once when first seen, and a resolved snapshot again whenever it
+//! moves.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::{Mutex, MutexGuard};
+
+/// Headers providers use to announce that a model is going away.
+const DEPRECATION_HEADERS: &[&str] = &["deprecation", "sunset", "x-model-deprecation"];
+
+/// What has been seen so far, so each change is reported once.
+#[derive(Debug, Default)]
+pub struct ModelChangeLog {
+    deprecated: Mutex<HashSet<String>>,
+    /// Snapshot last served for each requested model.
+    served: Mutex<HashMap<String, String>>,
+}
+
+impl ModelChangeLog {
+    /// Note that `model` was flagged as deprecated. True the first time.
+    pub fn deprecated(&self, model: &str) -> bool {
+        lock(&self.deprecated).insert(model.to_string())
+    }
+
+    /// Note that a request for `model` was answered by `served`. Returns
+    /// the previous snapshot (None if this is the first) when the served
+    /// model differs from the requested one and from what served it last.
+    pub fn served(&self, model: &str, served: &str) -> Option<Option<String>> {
+        if served.is_empty() || model.ends_with(&format!("/{served}")) || model == served {
+            return None;
+        }
+        let mut snapshots = lock(&self.served);
+        match snapshots.get(model) {
+            Some(previous) if previous == served => None,
+            _ => Some(snapshots.insert(model.to_string(), served.to_string())),
+        }
+    }
+}
+
+/// The deprecation notice in a response's headers, if any.
+pub(crate) fn deprecation_notice(headers: &reqwest::header::HeaderMap) -> Option<String> {
+    let notice: Vec<String> = DEPRECATION_HEADERS
+        .iter()
+        .filter_map(|name| Some(format!("{name}: {}", headers.get(*name)?.to_str().ok()?)))
+        .collect();
+    (!notice.is_empty()).then(|| notice.join(", "))
+}
+
+/// Whether a provider error says the model has been retired.
+pub(crate) fn is_deprecation_error(error: &str) -> bool {
+    let error = error.to_lowercase();
+    [
+        "deprecated",
+        "decommissioned",
+        "has been retired",
+        "no longer supported",
+    ]
+    .iter()
+    .any(|phrase| error.contains(phrase))
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_served_snapshot_reported_on_change() {
+        let log = ModelChangeLog::default();
+        assert_eq!(log.served("openai/gpt-4o", "gpt-4o"), None);
+        assert_eq!(log.served("openai/gpt-4o", "gpt-4o-2024-08-06"), Some(None));
+        assert_eq!(log.served("openai/gpt-4o", "gpt-4o-2024-08-06"), None);
+        assert_eq!(
+            log.served("openai/gpt-4o", "gpt-4o-2024-11-20"),
+            Some(Some("gpt-4o-2024-08-06".into()))
+        );
+    }
+
+    #[test]
+    fn test_deprecation_notice() {
+        let mut headers = reqwest::header::HeaderMap::new();
+        assert_eq!(deprecation_notice(&headers), None);
+        headers.insert("sunset", "Wed, 01 Jul 2026 00:00:00 GMT".parse().unwrap());
+        assert_eq!(
+            deprecation_notice(&headers).as_deref(),
+            Some("sunset: Wed, 01 Jul 2026 00:00:00 GMT")
+        );
+        assert!(is_deprecation_error(
+            "The model `llama3-70b-8192` has been decommissioned"
+        ));
+        assert!(!is_deprecation_error("rate limit exceeded"));
+    }
+}
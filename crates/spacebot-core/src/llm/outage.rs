@@ -0,0 +1,277 @@
+//! Provider-wide outage detection.
+//!
+//! Cooldowns handle one model being rate limited. When a provider itself is
+//! down, every model it serves fails at once, and discovering that model by
+//! model burns a full retry budget on each. The detector watches server-side
+//! failures per provider over a sliding window; once enough requests fail,
+//! across more than one model, the provider is declared out and routing
+//! skips it until a recovery probe or a successful request shows it's back.
+
+use crate::llm::routing::provider_from_model;
+
+use serde::Serialize;
+
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+/// When a provider counts as out, and how often it's probed while it is.
+#[derive(Debug, Clone)]
+pub struct OutageConfig {
+    /// Seconds of attempts the error rate is computed over.
+    pub window_secs: u64,
+    /// Failed attempts in the window needed before an outage is declared.
+    pub min_failures: usize,
+    /// Fraction of the window's attempts that must have failed.
+    pub error_rate: f64,
+    /// Distinct models that must have failed, so one broken model isn't
+    /// taken for the whole provider.
+    pub min_models: usize,
+    /// Seconds between recovery probes while a provider is out.
+    pub probe_interval_secs: u64,
+}
+
+impl Default for OutageConfig {
+    fn default() -> Self {
+        Self {
+            window_secs: 120,
+            min_failures: 5,
+            error_rate: 0.5,
+            min_models: 2,
+            probe_interval_secs: 30,
+        }
+    }
+}
+
+/// A provider routing is currently avoiding.
+#[derive(Debug, Clone, Serialize)]
+pub struct ProviderOutage {
+    pub provider: String,
+    pub started_at: chrono::DateTime<chrono::Utc>,
+    /// Attempts and failures in the window when the outage was declared.
+    pub attempts: usize,
+    pub failures: usize,
+    /// Models that failed in that window.
+    pub models: Vec<String>,
+    pub last_error: String,
+}
+
+impl ProviderOutage {
+    /// One-line summary for logs and events.
+    pub fn detail(&self) -> String {
+        format!(
+            "{} of {} requests failed across {}: {}",
+            self.failures,
+            self.attempts,
+            self.models.join(", "),
+            self.last_error
+        )
+    }
+}
+
+/// An outage starting or ending.
+#[derive(Debug, Clone)]
+pub enum OutageChange {
+    Started(ProviderOutage),
+    Ended(ProviderOutage),
+}
+
+#[derive(Debug)]
+struct Attempt {
+    at: Instant,
+    model: String,
+    error: Option<String>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    attempts: HashMap<String, VecDeque<Attempt>>,
+    outages: HashMap<String, ProviderOutage>,
+}
+
+/// Per-provider attempt windows and the outages they've tripped.
+#[derive(Debug)]
+pub struct OutageDetector {
+    config: OutageConfig,
+    state: Mutex<State>,
+}
+
+impl OutageDetector {
+    pub fn new(config: OutageConfig) -> Self {
+        Self {
+            config,
+            state: Mutex::new(State::default()),
+        }
+    }
+
+    pub fn config(&self) -> &OutageConfig {
+        &self.config
+    }
+
+    /// Count an attempt on `model`, with its error if it failed. A success
+    /// on a provider that's out ends the outage.
+    pub fn record(&self, model: &str, error: Option<&str>, now: Instant) -> Option<OutageChange> {
+        let provider = provider_from_model(model);
+        let window = Duration::from_secs(self.config.window_secs);
+        let mut state = self.lock();
+        if error.is_none() && state.outages.contains_key(provider) {
+            return Self::end(&mut state, provider).map(OutageChange::Ended);
+        }
+
+        let State { attempts, outages } = &mut *state;
+        let attempts = attempts.entry(provider.to_string()).or_default();
+        attempts.push_back(Attempt {
+            at: now,
+            model: model.to_string(),
+            error: error.map(str::to_string),
+        });
+        while attempts
+            .front()
+            .is_some_and(|attempt| now.duration_since(attempt.at) > window)
+        {
+            attempts.pop_front();
+        }
+        let last_error = error.filter(|_| !outages.contains_key(provider))?;
+
+        let failed: Vec<&Attempt> = attempts
+            .iter()
+            .filter(|attempt| attempt.error.is_some())
+            .collect();
+        let models: BTreeSet<&str> = failed
+            .iter()
+            .map(|attempt| attempt.model.as_str())
+            .collect();
+        let rate = failed.len() as f64 / attempts.len() as f64;
+        if failed.len() < self.config.min_failures
+            || models.len() < self.config.min_models
+            || rate < self.config.error_rate
+        {
+            return None;
+        }
+
+        let outage = ProviderOutage {
+            provider: provider.to_string(),
+            started_at: chrono::Utc::now(),
+            attempts: attempts.len(),
+            failures: failed.len(),
+            models: models.into_iter().map(str::to_string).collect(),
+            last_error: last_error.to_string(),
+        };
+        outages.insert(provider.to_string(), outage.clone());
+        Some(OutageChange::Started(outage))
+    }
+
+    /// End a provider's outage after a passing probe.
+    pub fn recover(&self, provider: &str) -> Option<ProviderOutage> {
+        Self::end(&mut self.lock(), provider)
+    }
+
+    pub fn is_out(&self, provider: &str) -> bool {
+        self.lock().outages.contains_key(provider)
+    }
+
+    /// Providers currently out, oldest outage first.
+    pub fn outages(&self) -> Vec<ProviderOutage> {
+        let mut outages: Vec<_> = self.lock().outages.values().cloned().collect();
+        outages.sort_by_key(|outage| outage.started_at);
+        outages
+    }
+
+    /// Forget the window too, so the failures that caused the outage don't
+    /// trip it again straight away.
+    fn end(state: &mut State, provider: &str) -> Option<ProviderOutage> {
+        state.attempts.remove(provider);
+        state.outages.remove(provider)
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ERROR: &str = "Anthropic API error (529): overloaded";
+
+    #[test]
+    fn test_outage_needs_failures_across_models() {
+        let detector = OutageDetector::new(OutageConfig::default());
+        let start = Instant::now();
+
+        // One model failing on its own is a model problem.
+        for second in 0..6 {
+            let at = start + Duration::from_secs(second);
+            assert!(
+                detector
+                    .record("anthropic/claude-sonnet-4", Some(ERROR), at)
+                    .is_none()
+            );
+        }
+        assert!(!detector.is_out("anthropic"));
+
+        let at = start + Duration::from_secs(7);
+        let Some(OutageChange::Started(outage)) =
+            detector.record("anthropic/claude-haiku-4.5", Some(ERROR), at)
+        else {
+            panic!("expected an outage");
+        };
+        assert_eq!(outage.failures, 7);
+        assert_eq!(
+            outage.models,
+            ["anthropic/claude-haiku-4.5", "anthropic/claude-sonnet-4"]
+        );
+        assert!(detector.is_out("anthropic"));
+        assert!(!detector.is_out("openai"));
+        // Already out: further failures change nothing.
+        assert!(
+            detector
+                .record("anthropic/claude-opus-4", Some(ERROR), at)
+                .is_none()
+        );
+
+        let Some(OutageChange::Ended(_)) = detector.record("anthropic/claude-sonnet-4", None, at)
+        else {
+            panic!("a success should end the outage");
+        };
+        assert!(detector.outages().is_empty());
+        // The window was cleared with it.
+        assert!(
+            detector
+                .record("anthropic/claude-opus-4", Some(ERROR), at)
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_outage_window_and_error_rate() {
+        let detector = OutageDetector::new(OutageConfig::default());
+        let start = Instant::now();
+
+        // Failures spread over more than the window never add up.
+        for minute in 0..10 {
+            let at = start + Duration::from_secs(minute * 60);
+            let model = if minute % 2 == 0 {
+                "openai/gpt-4.1"
+            } else {
+                "openai/o3"
+            };
+            assert!(detector.record(model, Some(ERROR), at).is_none());
+        }
+
+        // Mostly successful traffic stays under the error rate.
+        let at = start + Duration::from_secs(3600);
+        for _ in 0..10 {
+            detector.record("openai/gpt-4.1", None, at);
+        }
+        for model in ["openai/gpt-4.1", "openai/o3"].repeat(3) {
+            assert!(detector.record(model, Some(ERROR), at).is_none());
+        }
+        assert!(!detector.is_out("openai"));
+
+        assert!(detector.recover("openai").is_none());
+    }
+}
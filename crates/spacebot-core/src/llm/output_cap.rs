@@ -0,0 +1,171 @@
+//! Output token caps.
+//!
+//! A provider's own limit on output tokens is a hard ceiling; a cap is a
+//! lower one chosen to keep replies concise and their cost predictable. The
+//! request's `max_tokens` is clamped to the cap, so the model stops there,
+//! and a completion that used the whole cap has its text trimmed according
+//! to a [`Truncation`] policy instead of ending mid-sentence.
+
+use rig::completion::CompletionRequest;
+use rig::message::AssistantContent;
+use rig::one_or_many::OneOrMany;
+use serde::Deserialize;
+
+/// Appended to text cut at a word rather than a sentence.
+const ELLIPSIS: &str = "…";
+
+/// What happens to text that stopped at the cap.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum Truncation {
+    /// Leave it as the model wrote it.
+    Keep,
+    /// Cut it back to its last complete sentence, or to its last word with
+    /// an ellipsis when it has none.
+    #[default]
+    Sentence,
+    /// Cut it back to its last complete word and add an ellipsis.
+    Ellipsis,
+}
+
+/// The most output tokens one completion may use.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OutputCap {
+    pub max_tokens: u64,
+    pub truncation: Truncation,
+}
+
+impl OutputCap {
+    /// Lower the request's `max_tokens` to the cap. A lower limit the
+    /// request already has is kept.
+    pub fn clamp(&self, request: &mut CompletionRequest) {
+        request.max_tokens = Some(request.max_tokens.map_or(self.max_tokens, |max_tokens| {
+            max_tokens.min(self.max_tokens)
+        }));
+    }
+
+    /// Trim a completion that used `output_tokens` of the cap. Returns
+    /// whether its text was cut. Completions with a tool call are left as
+    /// they are, since the call is what matters.
+    pub fn truncate(&self, choice: &mut OneOrMany<AssistantContent>, output_tokens: u64) -> bool {
+        if self.truncation == Truncation::Keep || output_tokens < self.max_tokens {
+            return false;
+        }
+        if choice
+            .iter()
+            .any(|content| matches!(content, AssistantContent::ToolCall(_)))
+        {
+            return false;
+        }
+        let Some(AssistantContent::Text(text)) = choice
+            .iter_mut()
+            .filter(|content| matches!(content, AssistantContent::Text(_)))
+            .last()
+        else {
+            return false;
+        };
+        let cut = self.truncate_text(&text.text);
+        if cut == text.text {
+            return false;
+        }
+        text.text = cut;
+        true
+    }
+
+    /// `text` cut back according to the policy.
+    pub fn truncate_text(&self, text: &str) -> String {
+        let text = text.trim_end();
+        match self.truncation {
+            Truncation::Keep => text.to_string(),
+            Truncation::Sentence => match last_sentence_end(text) {
+                Some(end) => text[..end].to_string(),
+                None => cut_at_word(text),
+            },
+            Truncation::Ellipsis => cut_at_word(text),
+        }
+    }
+}
+
+/// Where the last complete sentence in `text` ends, if there is one. A
+/// sentence ends at `.`, `!` or `?` followed by whitespace, or at a line
+/// break. The text's own final character doesn't count: the model stopped
+/// there because it ran out of tokens, not because the sentence was done.
+fn last_sentence_end(text: &str) -> Option<usize> {
+    let mut end = None;
+    let mut chars = text.char_indices().peekable();
+    while let Some((index, c)) = chars.next() {
+        let Some(&(next_index, next)) = chars.peek() else {
+            break;
+        };
+        if c == '\n' {
+            end = Some(index);
+        } else if matches!(c, '.' | '!' | '?') && next.is_whitespace() {
+            end = Some(next_index);
+        }
+    }
+    end.map(|end| text[..end].trim_end().len())
+        .filter(|end| *end > 0)
+}
+
+/// `text` without its last, probably unfinished, word, and an ellipsis.
+fn cut_at_word(text: &str) -> String {
+    let kept = match text.rfind(char::is_whitespace) {
+        Some(index) => text[..index].trim_end(),
+        None => text,
+    };
+    format!("{kept}{ELLIPSIS}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rig::message::Text;
+
+    fn cap(truncation: Truncation) -> OutputCap {
+        OutputCap {
+            max_tokens: 100,
+            truncation,
+        }
+    }
+
+    #[test]
+    fn test_truncate_text() {
+        let text =
+            "The deploy finished. Error rates are back to normal! Next we should look at the";
+        assert_eq!(
+            cap(Truncation::Sentence).truncate_text(text),
+            "The deploy finished. Error rates are back to normal!"
+        );
+        assert_eq!(
+            cap(Truncation::Ellipsis).truncate_text(text),
+            "The deploy finished. Error rates are back to normal! Next we should look at…"
+        );
+        assert_eq!(cap(Truncation::Keep).truncate_text(text), text);
+
+        // No complete sentence to fall back on.
+        assert_eq!(
+            cap(Truncation::Sentence).truncate_text("Steps to reproduce are"),
+            "Steps to reproduce…"
+        );
+        // A list item ends at its line break.
+        assert_eq!(
+            cap(Truncation::Sentence).truncate_text("Checks:\n- disk\n- mem"),
+            "Checks:\n- disk"
+        );
+    }
+
+    #[test]
+    fn test_truncate_only_at_the_cap() {
+        let cap = cap(Truncation::Sentence);
+        let mut choice = OneOrMany::one(AssistantContent::Text(Text {
+            text: "Done. And then".into(),
+        }));
+        assert!(!cap.truncate(&mut choice, 99));
+        assert!(cap.truncate(&mut choice, 100));
+        let AssistantContent::Text(text) = choice.first() else {
+            panic!("expected text");
+        };
+        assert_eq!(text.text, "Done.");
+    }
+}
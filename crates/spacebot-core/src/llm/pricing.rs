@@ -0,0 +1,38 @@
+//! Per-model token prices for cost estimates.
+//!
+//! Providers don't return prices with their responses, so costs are computed
+//! from operator-supplied rates. Models without a configured price have no
+//! cost rather than a guessed one.
+
+use serde::{Deserialize, Serialize};
+
+/// USD price per million tokens for one model.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct ModelPricing {
+    pub input_per_mtok: f64,
+    pub output_per_mtok: f64,
+}
+
+impl ModelPricing {
+    /// Cost in USD of a completion with the given token counts.
+    pub fn cost_usd(&self, input_tokens: u64, output_tokens: u64) -> f64 {
+        (input_tokens as f64 * self.input_per_mtok + output_tokens as f64 * self.output_per_mtok)
+            / 1_000_000.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cost_usd() {
+        let pricing = ModelPricing {
+            input_per_mtok: 3.0,
+            output_per_mtok: 15.0,
+        };
+        let cost = pricing.cost_usd(10_000, 2_000);
+        assert!((cost - 0.06).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,84 @@
+//! Where a request's input tokens go.
+//!
+//! Providers bill a request's input as one number. To show how much of it is
+//! the system prompt and how much the tool schemas sent with every
+//! completion, both are estimated from the request as it's sent, at ~4
+//! chars/token like the rest of the crate's estimates: no tokenizer is
+//! bundled, and each provider tokenizes differently anyway. The rest of the
+//! billed input is the conversation history.
+
+use rig::completion::CompletionRequest;
+use serde::{Deserialize, Serialize};
+
+/// Estimated tokens of a request's fixed parts.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PromptTokens {
+    /// The system prompt, with any documents attached to it.
+    pub preamble: u64,
+    /// Tool names, descriptions and parameter schemas.
+    pub tools: u64,
+}
+
+impl PromptTokens {
+    pub fn estimate(request: &CompletionRequest) -> Self {
+        let preamble_chars = request.preamble.as_ref().map_or(0, String::len)
+            + request
+                .documents
+                .iter()
+                .map(|document| document.text.len())
+                .sum::<usize>();
+        let tool_chars: usize = request
+            .tools
+            .iter()
+            .map(|tool| {
+                tool.name.len() + tool.description.len() + tool.parameters.to_string().len()
+            })
+            .sum();
+        Self {
+            preamble: (preamble_chars / 4) as u64,
+            tools: (tool_chars / 4) as u64,
+        }
+    }
+
+    /// Tokens of `input_tokens` left for the history once the preamble and
+    /// tools are taken out.
+    pub fn history(&self, input_tokens: u64) -> u64 {
+        input_tokens.saturating_sub(self.preamble + self.tools)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rig::completion::ToolDefinition;
+    use rig::message::Message;
+    use rig::one_or_many::OneOrMany;
+
+    #[test]
+    fn test_estimate_splits_preamble_and_tools() {
+        let request = CompletionRequest {
+            preamble: Some("p".repeat(400)),
+            chat_history: OneOrMany::one(Message::user("hello")),
+            documents: Vec::new(),
+            tools: vec![ToolDefinition {
+                name: "shell".into(),
+                description: "d".repeat(195),
+                parameters: serde_json::json!({}),
+            }],
+            temperature: None,
+            max_tokens: None,
+            tool_choice: None,
+            additional_params: None,
+        };
+        let tokens = PromptTokens::estimate(&request);
+        assert_eq!(
+            tokens,
+            PromptTokens {
+                preamble: 100,
+                tools: 50
+            }
+        );
+        assert_eq!(tokens.history(1_000), 850);
+        assert_eq!(tokens.history(120), 0);
+    }
+}
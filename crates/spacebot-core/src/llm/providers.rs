@@ -0,0 +1,64 @@
+//! Provider client initialization.
+
+use crate::config::LlmConfig;
+use crate::error::Result;
+
+/// Every provider the manager knows how to call, paired with the origin its
+/// completion endpoint lives on. Used to warm connections at startup.
+pub const PROVIDER_ORIGINS: &[(&str, &str)] = &[
+    ("anthropic", "https://api.anthropic.com"),
+    ("openai", "https://api.openai.com"),
+    ("openrouter", "https://openrouter.ai"),
+    ("zhipu", "https://api.z.ai"),
+    ("ollama", "https://ollama.com"),
+    ("groq", "https://api.groq.com"),
+    ("together", "https://api.together.xyz"),
+    ("fireworks", "https://api.fireworks.ai"),
+    ("deepseek", "https://api.deepseek.com"),
+    ("xai", "https://api.x.ai"),
+    ("mistral", "https://api.mistral.ai"),
+    ("opencode-zen", "https://opencode.ai"),
+];
+
+/// Look up the origin URL for a provider id.
+pub fn provider_origin(provider: &str) -> Option<&'static str> {
+    PROVIDER_ORIGINS
+        .iter()
+        .find(|(id, _)| *id == provider)
+        .map(|(_, origin)| *origin)
+}
+
+/// Whether a provider speaks the OpenAI chat completions API at
+/// `<root>/v1/chat/completions`, so it can be pointed at a self-hosted server.
+pub fn supports_base_url(provider: &str) -> bool {
+    !matches!(provider, "anthropic" | "openrouter" | "zhipu")
+}
+
+/// Initialize all configured provider clients.
+pub async fn init_providers(config: &LlmConfig) -> Result<()> {
+    // Provider clients are initialized lazily through LlmManager
+    // This module exists for any provider-specific setup that needs to happen
+    // during system startup
+
+    if config.anthropic_key.is_some() {
+        tracing::info!("Anthropic provider configured");
+    }
+
+    if config.openai_key.is_some() {
+        tracing::info!("OpenAI provider configured");
+    }
+
+    if config.openrouter_key.is_some() {
+        tracing::info!("OpenRouter provider configured");
+    }
+
+    if config.ollama_key.is_some() {
+        tracing::info!("Ollama provider configured");
+    }
+
+    if config.opencode_zen_key.is_some() {
+        tracing::info!("OpenCode Zen provider configured");
+    }
+
+    Ok(())
+}
@@ -0,0 +1,255 @@
+//! Best-of-N completions for quality-critical requests.
+//!
+//! With a race configured for a model, a request for it goes to up to
+//! [`MAX_RACE_CANDIDATES`] models at once and one answer is kept: a judge
+//! model picks it, or without a judge, the answer that agrees most with the
+//! others does. Every candidate is recorded with its tokens and cost, so
+//! whether a race pays for itself can be worked out later.
+
+use rig::message::{Message, UserContent};
+use rig::one_or_many::OneOrMany;
+use serde::Serialize;
+
+use std::collections::{HashSet, VecDeque};
+use std::sync::{Mutex, MutexGuard};
+
+/// Most models raced for one request, the primary included.
+pub const MAX_RACE_CANDIDATES: usize = 3;
+
+/// Races kept in memory for the API.
+const MAX_RECORDED_RACES: usize = 200;
+
+/// Instructions for the judge model.
+pub(crate) const JUDGE_PREAMBLE: &str = "You compare candidate answers to the same request and pick the best one: the most correct, complete and useful, following the request's instructions. Reply with the number of the best answer and nothing else.";
+
+/// What `picked_by` says when no judge was asked.
+pub const PICKED_BY_AGREEMENT: &str = "agreement";
+
+/// One model's answer in a race.
+#[derive(Debug, Clone, Serialize)]
+pub struct RaceCandidate {
+    pub model: String,
+    /// The answer, with tool calls rendered as `name(args)`. None if the
+    /// model failed.
+    pub output: Option<String>,
+    pub error: Option<String>,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    pub cost_usd: Option<f64>,
+    pub latency_ms: u64,
+}
+
+/// A finished race.
+#[derive(Debug, Clone, Serialize)]
+pub struct RaceRecord {
+    pub request_id: String,
+    pub finished_at: chrono::DateTime<chrono::Utc>,
+    pub candidates: Vec<RaceCandidate>,
+    /// Index into `candidates` of the answer that was kept. None if every
+    /// candidate failed and the request was routed normally instead.
+    pub winner: Option<usize>,
+    /// The judge model, or `agreement` when the heuristic picked.
+    pub picked_by: String,
+    /// What asking the judge cost. None without a judge, or when it failed
+    /// or has no price.
+    pub judge_cost_usd: Option<f64>,
+}
+
+impl RaceRecord {
+    /// What the race cost in total, if every model that answered is priced.
+    pub fn total_cost_usd(&self) -> Option<f64> {
+        let candidates: Option<f64> = self
+            .candidates
+            .iter()
+            .filter(|candidate| candidate.output.is_some())
+            .map(|candidate| candidate.cost_usd)
+            .sum();
+        Some(candidates? + self.judge_cost_usd.unwrap_or_default())
+    }
+}
+
+/// Recent races, newest last.
+#[derive(Debug, Default)]
+pub struct RaceLog {
+    races: Mutex<VecDeque<RaceRecord>>,
+}
+
+impl RaceLog {
+    pub fn record(&self, race: RaceRecord) {
+        let mut races = self.lock();
+        if races.len() == MAX_RECORDED_RACES {
+            races.pop_front();
+        }
+        races.push_back(race);
+    }
+
+    /// Recorded races, newest first.
+    pub fn recent(&self) -> Vec<RaceRecord> {
+        self.lock().iter().rev().cloned().collect()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, VecDeque<RaceRecord>> {
+        self.races
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// The judge's prompt: the request and the numbered answers.
+pub(crate) fn judge_prompt(request: &str, answers: &[&str]) -> String {
+    let mut prompt = format!("<request>\n{}\n</request>\n", request.trim());
+    for (index, answer) in answers.iter().enumerate() {
+        prompt.push_str(&format!(
+            "\n<answer number=\"{}\">\n{}\n</answer>\n",
+            index + 1,
+            answer.trim()
+        ));
+    }
+    prompt.push_str("\nWhich answer is best? Reply with its number only.");
+    prompt
+}
+
+/// The index of the answer the judge picked, from the first number in its
+/// reply.
+pub(crate) fn parse_verdict(reply: &str, answers: usize) -> Option<usize> {
+    reply
+        .split(|c: char| !c.is_ascii_digit())
+        .find(|part| !part.is_empty())
+        .and_then(|number| number.parse::<usize>().ok())
+        .filter(|number| (1..=answers).contains(number))
+        .map(|number| number - 1)
+}
+
+/// The answer sharing the most words with the others. Independent models
+/// that converge on an answer are more often right than the odd one out.
+/// Ties go to the earlier answer, so the primary model wins a two-way race.
+pub(crate) fn pick_by_agreement(answers: &[&str]) -> usize {
+    let words: Vec<HashSet<String>> = answers.iter().map(|answer| words(answer)).collect();
+
+    let mut best = (0, f64::MIN);
+    for (index, answer) in words.iter().enumerate() {
+        let agreement: f64 = words
+            .iter()
+            .enumerate()
+            .filter(|(other, _)| *other != index)
+            .map(|(_, other)| overlap(answer, other))
+            .sum();
+        if agreement > best.1 {
+            best = (index, agreement);
+        }
+    }
+    best.0
+}
+
+/// How much two answers agree, from 0 (no words in common) to 1 (the same
+/// words).
+pub(crate) fn similarity(a: &str, b: &str) -> f64 {
+    overlap(&words(a), &words(b))
+}
+
+fn words(answer: &str) -> HashSet<String> {
+    answer
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+fn overlap(a: &HashSet<String>, b: &HashSet<String>) -> f64 {
+    let union = a.union(b).count();
+    if union == 0 {
+        return 1.0;
+    }
+    a.intersection(b).count() as f64 / union as f64
+}
+
+/// The text of the last user message, which is what the answers respond to.
+pub(crate) fn last_user_text(history: &OneOrMany<Message>) -> String {
+    // `OneOrMany::iter` only goes forward, so keep the last user message seen.
+    history
+        .iter()
+        .filter_map(|message| match message {
+            Message::User { content } => Some(
+                content
+                    .iter()
+                    .filter_map(|content| match content {
+                        UserContent::Text(text) => Some(text.text.as_str()),
+                        _ => None,
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+            ),
+            _ => None,
+        })
+        .last()
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_verdict() {
+        assert_eq!(parse_verdict("2", 3), Some(1));
+        assert_eq!(parse_verdict("Answer 3 is best.", 3), Some(2));
+        assert_eq!(parse_verdict("4", 3), None);
+        assert_eq!(parse_verdict("0", 3), None);
+        assert_eq!(parse_verdict("the first one", 3), None);
+    }
+
+    #[test]
+    fn test_last_user_text() {
+        let history = OneOrMany::many(vec![
+            Message::user("What's the weather?"),
+            Message::assistant("Sunny."),
+            Message::user("And tomorrow?"),
+            Message::assistant("Rain."),
+        ])
+        .unwrap();
+        assert_eq!(last_user_text(&history), "And tomorrow?");
+        assert_eq!(last_user_text(&OneOrMany::one(Message::assistant("Hi."))), "");
+    }
+
+    #[test]
+    fn test_pick_by_agreement() {
+        let answers = [
+            "Rotate the key, then redeploy the service.",
+            "Delete the database.",
+            "Redeploy the service after you rotate the key.",
+        ];
+        assert_eq!(pick_by_agreement(&answers), 0);
+        assert_eq!(pick_by_agreement(&["yes", "no"]), 0);
+        assert_eq!(pick_by_agreement(&["no", "yes please", "yes"]), 1);
+    }
+
+    #[test]
+    fn test_total_cost() {
+        let candidate = |cost_usd, output: Option<&str>| RaceCandidate {
+            model: "openai/gpt-4.1".into(),
+            output: output.map(str::to_string),
+            error: None,
+            input_tokens: 0,
+            output_tokens: 0,
+            cost_usd,
+            latency_ms: 0,
+        };
+        let mut race = RaceRecord {
+            request_id: "req-1".into(),
+            finished_at: chrono::Utc::now(),
+            candidates: vec![candidate(Some(0.02), Some("a")), candidate(None, None)],
+            winner: Some(0),
+            picked_by: PICKED_BY_AGREEMENT.into(),
+            judge_cost_usd: None,
+        };
+        // The failed candidate cost nothing.
+        assert_eq!(race.total_cost_usd(), Some(0.02));
+
+        race.picked_by = "anthropic/claude-haiku-4.5".into();
+        race.judge_cost_usd = Some(0.01);
+        assert_eq!(race.total_cost_usd(), Some(0.03));
+
+        race.candidates.push(candidate(None, Some("b")));
+        assert_eq!(race.total_cost_usd(), None);
+    }
+}
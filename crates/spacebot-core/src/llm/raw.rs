@@ -0,0 +1,130 @@
+//! Raw calls to a provider's API.
+//!
+//! Completions go through [`SpacebotModel`](crate::llm::SpacebotModel), but
+//! integrations need the rest of a provider's API too: listing models,
+//! uploading files, checking on a batch. [`LlmManager::raw_request`] sends
+//! those with the same credentials, signing, retries, rate limit cooldown,
+//! outage tracking and latency metrics as completions, so nothing outside
+//! the crate has to know how each provider wants its key.
+
+use crate::llm::LlmManager;
+
+use serde::Serialize;
+
+/// A provider's answer to a raw request.
+#[derive(Debug, Clone, Serialize)]
+pub struct RawProviderResponse {
+    pub status: u16,
+    /// The provider's own id for the request, which their support asks for.
+    pub provider_request_id: Option<String>,
+    /// The body as JSON, as a JSON string when it isn't JSON, or null when
+    /// it's empty.
+    pub body: serde_json::Value,
+}
+
+impl RawProviderResponse {
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+
+    pub(crate) fn new(
+        status: reqwest::StatusCode,
+        provider_request_id: Option<String>,
+        text: String,
+    ) -> Self {
+        let body = if text.trim().is_empty() {
+            serde_json::Value::Null
+        } else {
+            serde_json::from_str(&text).unwrap_or(serde_json::Value::String(text))
+        };
+        Self {
+            status: status.as_u16(),
+            provider_request_id,
+            body,
+        }
+    }
+}
+
+/// Name raw requests to `provider` are tracked under for cooldowns, outages
+/// and metrics, apart from its completion models.
+pub fn series_name(provider: &str) -> String {
+    format!("{provider}/raw")
+}
+
+/// `path` joined onto a provider's origin or server root.
+pub(crate) fn url(root: &str, path: &str) -> String {
+    format!(
+        "{}/{}",
+        root.trim_end_matches('/'),
+        path.trim_start_matches('/')
+    )
+}
+
+/// Add the headers `provider` authenticates requests with.
+pub(crate) fn authorize(
+    manager: &LlmManager,
+    provider: &str,
+    mut request: reqwest::RequestBuilder,
+    api_key: Option<&str>,
+) -> reqwest::RequestBuilder {
+    if provider == "anthropic" {
+        request = request.header("anthropic-version", "2023-06-01");
+        if let Some(api_key) = api_key {
+            request = request.header("x-api-key", api_key);
+        }
+        return request;
+    }
+    if let Some(api_key) = api_key {
+        request = request.bearer_auth(api_key);
+    }
+    if provider == "openai" {
+        if let Some(organization) = manager.openai_organization() {
+            request = request.header("openai-organization", organization);
+        }
+        if let Some(project) = manager.openai_project() {
+            request = request.header("openai-project", project);
+        }
+    }
+    request
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_authorize_uses_each_providers_header() {
+        let manager = LlmManager::builder()
+            .build()
+            .expect("builder should succeed");
+        let request = |provider: &str| {
+            let builder = manager
+                .http_client()
+                .get(url("https://api.example.com/", "/v1/models"));
+            authorize(&manager, provider, builder, Some("k"))
+                .build()
+                .unwrap()
+        };
+
+        let anthropic = request("anthropic");
+        assert_eq!(
+            anthropic.url().as_str(),
+            "https://api.example.com/v1/models"
+        );
+        assert_eq!(anthropic.headers()["x-api-key"], "k");
+        assert!(anthropic.headers().get("authorization").is_none());
+
+        let groq = request("groq");
+        assert_eq!(groq.headers()["authorization"], "Bearer k");
+        assert!(groq.headers().get("x-api-key").is_none());
+    }
+
+    #[test]
+    fn test_response_body_falls_back_to_text() {
+        let ok = |text: &str| RawProviderResponse::new(reqwest::StatusCode::OK, None, text.into());
+        assert_eq!(ok(r#"{"data": []}"#).body, serde_json::json!({"data": []}));
+        assert_eq!(ok("not json").body, serde_json::json!("not json"));
+        assert!(ok("").body.is_null());
+        assert!(ok("").is_success());
+    }
+}
@@ -0,0 +1,352 @@
+//! Debug capture of raw provider exchanges.
+//!
+//! When enabled, every provider attempt keeps the exact serialized request
+//! body and the raw response text next to the parse outcome, keyed by the
+//! request id that `SpacebotModel::completion` assigns. Bodies are size-capped
+//! and scrubbed of credential-shaped strings before they are stored. Each
+//! request also keeps the provider-neutral [`RecordedCompletion`] it was built
+//! from, so it can be replayed against another model. Only the most recent
+//! requests are retained.
+
+use crate::error::ProviderApiError;
+use crate::redact::truncate_redacted;
+
+use rig::completion::{CompletionRequest, ToolDefinition};
+use rig::message::{AssistantContent, Message};
+use rig::one_or_many::OneOrMany;
+use serde::{Deserialize, Serialize};
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+
+/// Maximum bytes kept per request or response body.
+pub const MAX_RECORDED_BODY_BYTES: usize = 256 * 1024;
+
+/// How many requests to keep before evicting the oldest.
+pub const MAX_RECORDED_REQUESTS: usize = 200;
+
+/// One provider attempt as it went over the wire.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordedAttempt {
+    pub model: String,
+    pub attempt: usize,
+    pub started_at: chrono::DateTime<chrono::Utc>,
+    /// Serialized request body, redacted and truncated.
+    pub request_body: String,
+    pub status: u16,
+    /// The provider's own id for the request, from its response headers.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provider_request_id: Option<String>,
+    /// Raw response text, redacted and truncated.
+    pub response_body: String,
+    /// Set once the response has been parsed (or failed to).
+    pub outcome: Option<AttemptOutcome>,
+}
+
+/// Result of turning the raw response into a completion.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "result", rename_all = "snake_case")]
+pub enum AttemptOutcome {
+    Parsed {
+        summary: String,
+    },
+    Failed {
+        error: String,
+        /// The provider's structured error, when it sent one.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        provider_error: Option<ProviderApiError>,
+    },
+}
+
+/// A completion request as it left the agent, before any provider's
+/// dialect, and what came back. Held in memory unredacted so a replay sends
+/// the same thing; it's never served as is.
+#[derive(Debug, Clone)]
+pub struct RecordedCompletion {
+    pub model: String,
+    pub preamble: Option<String>,
+    pub chat_history: Vec<Message>,
+    pub tools: Vec<ToolDefinition>,
+    pub temperature: Option<f64>,
+    pub max_tokens: Option<u64>,
+    /// The response's text, with tool calls as `name(arguments)`. None
+    /// until the request succeeds.
+    pub output: Option<String>,
+}
+
+#[derive(Debug, Default)]
+struct RecorderState {
+    attempts: HashMap<String, Vec<RecordedAttempt>>,
+    completions: HashMap<String, RecordedCompletion>,
+    /// Request ids in insertion order, for eviction.
+    order: VecDeque<String>,
+}
+
+impl RecorderState {
+    /// Start tracking `request_id`, evicting the oldest requests past the cap.
+    fn track(&mut self, request_id: &str) {
+        if self.attempts.contains_key(request_id) || self.completions.contains_key(request_id) {
+            return;
+        }
+        self.order.push_back(request_id.to_string());
+        while self.order.len() > MAX_RECORDED_REQUESTS {
+            if let Some(evicted) = self.order.pop_front() {
+                self.attempts.remove(&evicted);
+                self.completions.remove(&evicted);
+            }
+        }
+    }
+}
+
+/// Bounded in-memory store of recorded provider exchanges.
+#[derive(Debug)]
+pub struct DebugRecorder {
+    enabled: bool,
+    state: Mutex<RecorderState>,
+}
+
+impl DebugRecorder {
+    pub fn new(enabled: bool) -> Self {
+        Self {
+            enabled,
+            state: Mutex::new(RecorderState::default()),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Record the request/response pair for one provider attempt.
+    pub fn record_exchange(
+        &self,
+        request_id: &str,
+        model: &str,
+        request_body: &serde_json::Value,
+        status: u16,
+        provider_request_id: Option<&str>,
+        response_text: &str,
+    ) {
+        if !self.enabled {
+            return;
+        }
+
+        let request_body = serde_json::to_string(request_body).unwrap_or_default();
+        let mut state = self.lock_state();
+        state.track(request_id);
+        let attempts = state.attempts.entry(request_id.to_string()).or_default();
+        let attempt = attempts.len() + 1;
+        attempts.push(RecordedAttempt {
+            model: model.to_string(),
+            attempt,
+            started_at: chrono::Utc::now(),
+            request_body: sanitize_body(&request_body),
+            status,
+            provider_request_id: provider_request_id.map(str::to_string),
+            response_body: sanitize_body(response_text),
+            outcome: None,
+        });
+    }
+
+    /// Attach the parse outcome to the latest attempt of a request.
+    pub fn record_outcome(&self, request_id: &str, outcome: AttemptOutcome) {
+        if !self.enabled {
+            return;
+        }
+        let mut state = self.lock_state();
+        let latest = state
+            .attempts
+            .get_mut(request_id)
+            .and_then(|attempts| attempts.last_mut())
+            .filter(|attempt| attempt.outcome.is_none());
+        if let Some(attempt) = latest {
+            attempt.outcome = Some(outcome);
+        }
+    }
+
+    /// Keep the provider-neutral request behind `request_id`.
+    pub fn record_request(&self, request_id: &str, model: &str, request: &CompletionRequest) {
+        if !self.enabled {
+            return;
+        }
+        let completion = RecordedCompletion {
+            model: model.to_string(),
+            preamble: request.preamble.clone(),
+            chat_history: request.chat_history.iter().cloned().collect(),
+            tools: request.tools.clone(),
+            temperature: request.temperature,
+            max_tokens: request.max_tokens,
+            output: None,
+        };
+        let mut state = self.lock_state();
+        state.track(request_id);
+        state.completions.insert(request_id.to_string(), completion);
+    }
+
+    /// Attach the response to a recorded request.
+    pub fn record_output(&self, request_id: &str, choice: &OneOrMany<AssistantContent>) {
+        if !self.enabled {
+            return;
+        }
+        if let Some(completion) = self.lock_state().completions.get_mut(request_id) {
+            completion.output = Some(render_output(choice));
+        }
+    }
+
+    /// The provider-neutral request behind `request_id`, if it's still kept.
+    pub fn completion(&self, request_id: &str) -> Option<RecordedCompletion> {
+        self.lock_state().completions.get(request_id).cloned()
+    }
+
+    /// All recorded attempts for a request, oldest first.
+    pub fn get(&self, request_id: &str) -> Option<Vec<RecordedAttempt>> {
+        self.lock_state().attempts.get(request_id).cloned()
+    }
+
+    /// Recorded request ids, most recent first.
+    pub fn recent_request_ids(&self) -> Vec<String> {
+        self.lock_state().order.iter().rev().cloned().collect()
+    }
+
+    fn lock_state(&self) -> std::sync::MutexGuard<'_, RecorderState> {
+        // A poisoned recorder only ever holds diagnostic copies.
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// A response's content as [`RecordedCompletion::output`] keeps it: text
+/// blocks, and tool calls as `name(arguments)`, one per paragraph.
+pub(crate) fn render_output(choice: &OneOrMany<AssistantContent>) -> String {
+    choice
+        .iter()
+        .filter_map(|content| match content {
+            AssistantContent::Text(text) => Some(text.text.trim().to_string()),
+            AssistantContent::ToolCall(call) => Some(format!(
+                "{}({})",
+                call.function.name, call.function.arguments
+            )),
+            _ => None,
+        })
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+/// Redact credential-shaped strings and cap the body size on a char boundary.
+fn sanitize_body(body: &str) -> String {
+    truncate_redacted(body, MAX_RECORDED_BODY_BYTES)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_redacts_and_truncates_bodies() {
+        let recorder = DebugRecorder::new(true);
+        let body =
+            serde_json::json!({"messages": [{"content": "key sk-ant-REDACTED"}]});
+        let response = "é".repeat(MAX_RECORDED_BODY_BYTES);
+
+        recorder.record_exchange(
+            "req-1",
+            "anthropic/test",
+            &body,
+            200,
+            Some("req_011"),
+            &response,
+        );
+        recorder.record_outcome(
+            "req-1",
+            AttemptOutcome::Failed {
+                error: "bad".into(),
+                provider_error: None,
+            },
+        );
+
+        let attempts = recorder.get("req-1").expect("request should be recorded");
+        assert_eq!(attempts.len(), 1);
+        assert_eq!(attempts[0].provider_request_id.as_deref(), Some("req_011"));
+        assert!(attempts[0].request_body.contains("[REDACTED]"));
+        assert!(
+            !attempts[0]
+                .request_body
+                .contains("abcdefghijklmnopqrstuvwxyz")
+        );
+        assert!(attempts[0].response_body.ends_with("bytes total]"));
+        assert!(matches!(
+            attempts[0].outcome,
+            Some(AttemptOutcome::Failed { .. })
+        ));
+    }
+
+    #[test]
+    fn test_disabled_recorder_keeps_nothing_and_evicts_oldest() {
+        let disabled = DebugRecorder::new(false);
+        disabled.record_exchange("req", "m", &serde_json::json!({}), 200, None, "{}");
+        assert!(disabled.get("req").is_none());
+
+        let recorder = DebugRecorder::new(true);
+        for index in 0..=MAX_RECORDED_REQUESTS {
+            recorder.record_exchange(
+                &format!("req-{index}"),
+                "m",
+                &serde_json::json!({}),
+                200,
+                None,
+                "{}",
+            );
+        }
+        assert!(recorder.get("req-0").is_none());
+        assert_eq!(recorder.recent_request_ids().len(), MAX_RECORDED_REQUESTS);
+        assert_eq!(
+            recorder.recent_request_ids().first().map(String::as_str),
+            Some(format!("req-{MAX_RECORDED_REQUESTS}").as_str())
+        );
+    }
+
+    #[test]
+    fn test_completion_output_and_eviction() {
+        let recorder = DebugRecorder::new(true);
+        recorder.record_exchange("req-0", "m", &serde_json::json!({}), 200, None, "{}");
+        recorder.lock_state().completions.insert(
+            "req-0".into(),
+            RecordedCompletion {
+                model: "anthropic/x".into(),
+                preamble: None,
+                chat_history: vec![Message::user("hi")],
+                tools: Vec::new(),
+                temperature: None,
+                max_tokens: None,
+                output: None,
+            },
+        );
+        let choice = OneOrMany::many(vec![
+            AssistantContent::text("hello "),
+            AssistantContent::text(""),
+            AssistantContent::text("there"),
+        ])
+        .unwrap();
+        recorder.record_output("req-0", &choice);
+        recorder.record_output("req-unknown", &choice);
+        let completion = recorder.completion("req-0").unwrap();
+        assert_eq!(completion.output.as_deref(), Some("hello\n\nthere"));
+        assert!(recorder.completion("req-unknown").is_none());
+
+        // The completion is evicted with the request's attempts.
+        for index in 1..=MAX_RECORDED_REQUESTS {
+            recorder.record_exchange(
+                &format!("req-{index}"),
+                "m",
+                &serde_json::json!({}),
+                200,
+                None,
+                "{}",
+            );
+        }
+        assert!(recorder.get("req-0").is_none());
+        assert!(recorder.completion("req-0").is_none());
+    }
+}
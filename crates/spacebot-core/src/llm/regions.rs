@@ -0,0 +1,197 @@
+//! Regional endpoint failover.
+//!
+//! Some deployments serve one provider from several regions (Azure OpenAI
+//! resources, Vertex or Bedrock behind an OpenAI-compatible gateway). A
+//! provider can be given a prioritized list of server roots; requests go to
+//! the active one, and a retriable failure moves the request on to the next
+//! healthy region before the error ever reaches the model fallback chain.
+//! So a regional outage costs a hop to another region, not a model
+//! downgrade.
+//!
+//! After a failover the new region stays active for `stickiness_secs`, so a
+//! flapping primary isn't retried on every request. Once the window passes,
+//! the highest-priority region that hasn't failed within it takes over again.
+
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+/// Regional server roots for one provider, in priority order.
+#[derive(Debug, Clone)]
+pub struct RegionalEndpointsConfig {
+    /// Server roots, like `base_urls` entries. The first is preferred.
+    pub endpoints: Vec<String>,
+    /// Seconds a region stays active after a failover, and how long a
+    /// failed region is skipped.
+    pub stickiness_secs: u64,
+}
+
+impl Default for RegionalEndpointsConfig {
+    fn default() -> Self {
+        Self {
+            endpoints: Vec::new(),
+            stickiness_secs: 300,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct State {
+    active: usize,
+    active_since: Instant,
+    /// Last failure per endpoint, by index.
+    failed_at: Vec<Option<Instant>>,
+}
+
+/// Which of a provider's regions requests go to.
+#[derive(Debug)]
+pub struct RegionalEndpoints {
+    config: RegionalEndpointsConfig,
+    state: Mutex<State>,
+}
+
+impl RegionalEndpoints {
+    pub fn new(config: RegionalEndpointsConfig) -> Self {
+        let failed_at = vec![None; config.endpoints.len()];
+        Self {
+            config,
+            state: Mutex::new(State {
+                active: 0,
+                active_since: Instant::now(),
+                failed_at,
+            }),
+        }
+    }
+
+    pub fn endpoint(&self, index: usize) -> Option<&str> {
+        self.config.endpoints.get(index).map(String::as_str)
+    }
+
+    /// The active region.
+    pub fn active(&self) -> Option<&str> {
+        self.endpoint(self.lock().active)
+    }
+
+    /// Regions to try for one request, by index: the active region first,
+    /// then the others in priority order, skipping those that failed within
+    /// the stickiness window. Past the window, the preferred healthy region
+    /// becomes active again.
+    pub fn candidates(&self, now: Instant) -> Vec<usize> {
+        let window = self.window();
+        let mut state = self.lock();
+        let healthy = |state: &State, index: usize| {
+            state.failed_at[index].is_none_or(|at| now.duration_since(at) >= window)
+        };
+
+        if state.active != 0
+            && now.duration_since(state.active_since) >= window
+            && let Some(preferred) = (0..state.active).find(|&index| healthy(&state, index))
+        {
+            tracing::info!(
+                endpoint = %self.config.endpoints[preferred],
+                "returning to preferred region"
+            );
+            state.active = preferred;
+            state.active_since = now;
+        }
+
+        let mut order = vec![state.active];
+        order.extend(
+            (0..self.config.endpoints.len())
+                .filter(|&index| index != state.active && healthy(&state, index)),
+        );
+        order
+    }
+
+    /// A request to region `index` failed with a retriable error. When it
+    /// was the active region, the next healthy one takes over.
+    pub fn report_failure(&self, index: usize, now: Instant) {
+        let window = self.window();
+        let mut state = self.lock();
+        state.failed_at[index] = Some(now);
+        if index != state.active {
+            return;
+        }
+        let count = self.config.endpoints.len();
+        let next = (1..count)
+            .map(|step| (index + step) % count)
+            .find(|&other| {
+                state.failed_at[other].is_none_or(|at| now.duration_since(at) >= window)
+            });
+        if let Some(next) = next {
+            tracing::warn!(
+                from = %self.config.endpoints[index],
+                to = %self.config.endpoints[next],
+                "regional endpoint failing, failing over"
+            );
+            state.active = next;
+            state.active_since = now;
+        }
+    }
+
+    /// A request to region `index` succeeded, so it is healthy and, if it
+    /// wasn't already, active.
+    pub fn report_success(&self, index: usize, now: Instant) {
+        let mut state = self.lock();
+        state.failed_at[index] = None;
+        if state.active != index {
+            state.active = index;
+            state.active_since = now;
+        }
+    }
+
+    fn window(&self) -> Duration {
+        Duration::from_secs(self.config.stickiness_secs)
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn regions() -> RegionalEndpoints {
+        RegionalEndpoints::new(RegionalEndpointsConfig {
+            endpoints: vec![
+                "https://eastus.example.com".into(),
+                "https://westeurope.example.com".into(),
+                "https://japaneast.example.com".into(),
+            ],
+            stickiness_secs: 60,
+        })
+    }
+
+    #[test]
+    fn test_failover_skips_failed_regions() {
+        let regions = regions();
+        let now = Instant::now();
+        assert_eq!(regions.candidates(now), vec![0, 1, 2]);
+
+        regions.report_failure(0, now);
+        assert_eq!(regions.active(), Some("https://westeurope.example.com"));
+        assert_eq!(regions.candidates(now), vec![1, 2]);
+
+        regions.report_failure(1, now);
+        assert_eq!(regions.candidates(now), vec![2]);
+    }
+
+    #[test]
+    fn test_returns_to_preferred_region_after_stickiness() {
+        let regions = regions();
+        let now = Instant::now();
+        regions.report_failure(0, now);
+        regions.report_success(1, now);
+
+        // Still sticky.
+        let soon = now + Duration::from_secs(30);
+        assert_eq!(regions.candidates(soon), vec![1, 2]);
+
+        let later = now + Duration::from_secs(61);
+        assert_eq!(regions.candidates(later), vec![0, 1, 2]);
+        assert_eq!(regions.active(), Some("https://eastus.example.com"));
+    }
+}
@@ -0,0 +1,127 @@
+//! Reissuing a recorded request against another model.
+//!
+//! [`replay`] takes a request the [`DebugRecorder`](super::recorder::DebugRecorder)
+//! kept, sends it to a chosen model with optionally different settings, and
+//! returns both outputs side by side, so "would model X have handled this
+//! better?" is one experiment. The replay goes through the manager like any
+//! other completion: it's routed, limited, priced and recorded in turn.
+
+use crate::error::{LlmError, Result};
+use crate::llm::SpacebotModel;
+use crate::llm::manager::LlmManager;
+use crate::llm::recorder::{MAX_RECORDED_REQUESTS, render_output};
+use crate::redact::redact_secrets;
+
+use rig::completion::CompletionModel as _;
+use serde::{Deserialize, Serialize};
+
+use std::sync::Arc;
+
+/// What to replay the request with. Unset settings keep the original's.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReplayOptions {
+    pub model: String,
+    #[serde(default)]
+    pub temperature: Option<f64>,
+    #[serde(default)]
+    pub max_tokens: Option<u64>,
+    /// Sampling seed, for providers that take one. Not kept from the
+    /// original, so set it on both sides of a comparison.
+    #[serde(default)]
+    pub seed: Option<u64>,
+}
+
+/// The original output next to the replay's. Both are redacted.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReplayOutcome {
+    pub request_id: String,
+    pub original_model: String,
+    /// None if the original request failed.
+    pub original_output: Option<String>,
+    /// The replay's own request id, for pulling up its exchange.
+    pub replay_request_id: Option<String>,
+    /// The model that answered the replay, after any fallback.
+    pub model: String,
+    pub output: String,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    pub cost_usd: Option<f64>,
+}
+
+/// Send the request recorded as `request_id` again with `options`.
+pub async fn replay(
+    manager: &Arc<LlmManager>,
+    request_id: &str,
+    options: &ReplayOptions,
+) -> Result<ReplayOutcome> {
+    let recorder = manager.debug_recorder();
+    if !recorder.is_enabled() {
+        return Err(anyhow::anyhow!("llm.debug_recording is off, so no requests were kept").into());
+    }
+    let recorded = recorder.completion(request_id).ok_or_else(|| {
+        anyhow::anyhow!(
+            "request {request_id} isn't recorded, only the last {MAX_RECORDED_REQUESTS} are kept"
+        )
+    })?;
+
+    let mut history = recorded.chat_history.clone();
+    let prompt = history
+        .pop()
+        .ok_or_else(|| anyhow::anyhow!("request {request_id} has no messages"))?;
+    let model = SpacebotModel::make(manager, options.model.as_str()).with_seed(options.seed);
+    let mut builder = model
+        .completion_request(prompt)
+        .messages(history)
+        .tools(recorded.tools.clone())
+        .temperature_opt(options.temperature.or(recorded.temperature))
+        .max_tokens_opt(options.max_tokens.or(recorded.max_tokens));
+    if let Some(preamble) = recorded.preamble.clone() {
+        builder = builder.preamble(preamble);
+    }
+
+    tracing::info!(%request_id, model = %options.model, "replaying recorded request");
+    let response = builder
+        .send()
+        .await
+        .map_err(|error| LlmError::CompletionFailed(error.to_string()))?;
+
+    Ok(ReplayOutcome {
+        request_id: request_id.to_string(),
+        original_model: recorded.model,
+        original_output: recorded.output.as_deref().map(redact_secrets),
+        replay_request_id: response.raw_response.request_id.clone(),
+        model: response
+            .raw_response
+            .model
+            .unwrap_or_else(|| options.model.clone()),
+        output: redact_secrets(&render_output(&response.choice)),
+        input_tokens: response.usage.input_tokens,
+        output_tokens: response.usage.output_tokens,
+        cost_usd: response.raw_response.cost_usd,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options() -> ReplayOptions {
+        ReplayOptions {
+            model: "openrouter/x".into(),
+            temperature: None,
+            max_tokens: None,
+            seed: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_replay_needs_a_recorded_request() {
+        let manager = Arc::new(LlmManager::builder().build().unwrap());
+        let error = replay(&manager, "req-1", &options()).await.unwrap_err();
+        assert!(error.to_string().contains("debug_recording is off"));
+
+        let manager = Arc::new(LlmManager::builder().debug_recording(true).build().unwrap());
+        let error = replay(&manager, "req-1", &options()).await.unwrap_err();
+        assert!(error.to_string().contains("request req-1 isn't recorded"));
+    }
+}
@@ -25,6 +25,76 @@ pub struct RoutingConfig {
 
     /// How long to deprioritize a rate-limited model (seconds).
     pub rate_limit_cooldown_secs: u64,
+
+    /// vLLM request extras per model, sent only when the model's provider is
+    /// flagged as vLLM (see `LlmConfig::vllm_providers`).
+    pub vllm: HashMap<String, VllmOptions>,
+
+    /// `anthropic-beta` header values per model, sent with each request to
+    /// Anthropic. Values must be in [`KNOWN_ANTHROPIC_BETAS`].
+    pub anthropic_betas: HashMap<String, Vec<String>>,
+
+    /// Best-of-N races per model. A request for a model with a race goes to
+    /// it and the race's models at once, and the best answer is kept (see
+    /// `llm::race`).
+    pub race: HashMap<String, RaceConfig>,
+
+    /// Candidate models mirrored per model. A sample of a model's successful
+    /// requests is sent again to its candidate in the background and how
+    /// the two compare is recorded (see `llm::shadow`).
+    pub shadow: HashMap<String, ShadowConfig>,
+}
+
+/// Models raced against one primary model, and who picks the winner.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RaceConfig {
+    /// Models answering alongside the primary. Up to
+    /// [`MAX_RACE_CANDIDATES`](crate::llm::race::MAX_RACE_CANDIDATES) run in
+    /// total.
+    pub models: Vec<String>,
+    /// Model comparing the answers. Without one, the answer agreeing most
+    /// with the others wins.
+    pub judge: Option<String>,
+}
+
+/// A candidate model shadowing one primary model.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ShadowConfig {
+    /// The candidate that gets a copy of the primary's requests.
+    pub model: String,
+    /// Fraction of the primary's successful requests mirrored, from 0 to 1.
+    pub sample_rate: f64,
+    /// Model comparing the two answers. Without one, only their word
+    /// overlap is recorded.
+    pub judge: Option<String>,
+}
+
+/// Extra request parameters understood by vLLM's OpenAI-compatible server.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct VllmOptions {
+    /// JSON schema the output must conform to (`guided_json`). Either a
+    /// schema object or a string holding one.
+    pub guided_json: Option<serde_json::Value>,
+    /// Regular expression the output must match (`guided_regex`).
+    pub guided_regex: Option<String>,
+    /// Name of a LoRA adapter loaded on the server. vLLM selects adapters
+    /// through the `model` field, so this replaces the base model name.
+    pub lora_adapter: Option<String>,
+}
+
+impl VllmOptions {
+    /// Add these options to an OpenAI-style chat completions body.
+    pub fn apply(&self, body: &mut serde_json::Value) {
+        if let Some(schema) = &self.guided_json {
+            body["guided_json"] = schema.clone();
+        }
+        if let Some(regex) = &self.guided_regex {
+            body["guided_regex"] = serde_json::json!(regex);
+        }
+        if let Some(adapter) = &self.lora_adapter {
+            body["model"] = serde_json::json!(adapter);
+        }
+    }
 }
 
 impl Default for RoutingConfig {
@@ -44,6 +114,10 @@ impl Default for RoutingConfig {
                 vec!["anthropic/claude-haiku-4.5-20250514".into()],
             )]),
             rate_limit_cooldown_secs: 60,
+            vllm: HashMap::new(),
+            anthropic_betas: HashMap::new(),
+            race: HashMap::new(),
+            shadow: HashMap::new(),
         }
     }
 }
@@ -52,12 +126,11 @@ impl RoutingConfig {
     /// Resolve the model name for a process type and optional task type.
     pub fn resolve(&self, process_type: ProcessType, task_type: Option<&str>) -> &str {
         // Check task-type override first (only for workers and branches)
-        if let Some(task) = task_type {
-            if matches!(process_type, ProcessType::Worker | ProcessType::Branch) {
-                if let Some(override_model) = self.task_overrides.get(task) {
-                    return override_model;
-                }
-            }
+        if let Some(task) = task_type
+            && matches!(process_type, ProcessType::Worker | ProcessType::Branch)
+            && let Some(override_model) = self.task_overrides.get(task)
+        {
+            return override_model;
         }
 
         match process_type {
@@ -69,6 +142,29 @@ impl RoutingConfig {
         }
     }
 
+    /// vLLM extras configured for a model, if any.
+    pub fn vllm_options(&self, model_name: &str) -> Option<&VllmOptions> {
+        self.vllm.get(model_name)
+    }
+
+    /// Anthropic beta flags configured for a model.
+    pub fn anthropic_betas(&self, model_name: &str) -> &[String] {
+        self.anthropic_betas
+            .get(model_name)
+            .map(Vec::as_slice)
+            .unwrap_or(&[])
+    }
+
+    /// The race configured for a model, if any.
+    pub fn race(&self, model_name: &str) -> Option<&RaceConfig> {
+        self.race.get(model_name)
+    }
+
+    /// The candidate shadowing a model, if any.
+    pub fn shadow(&self, model_name: &str) -> Option<&ShadowConfig> {
+        self.shadow.get(model_name)
+    }
+
     /// Get the fallback chain for a model, if any.
     pub fn get_fallbacks(&self, model_name: &str) -> &[String] {
         self.fallbacks
@@ -76,6 +172,21 @@ impl RoutingConfig {
             .map(|v| v.as_slice())
             .unwrap_or(&[])
     }
+
+    /// A copy with `model_name` answering channel turns, for a per-channel
+    /// override. Unless the override has a fallback chain of its own, it
+    /// falls back to the configured channel model.
+    pub fn with_channel_model(&self, model_name: &str) -> Self {
+        let mut routing = self.clone();
+        if model_name != self.channel {
+            routing
+                .fallbacks
+                .entry(model_name.to_string())
+                .or_insert_with(|| vec![self.channel.clone()]);
+            routing.channel = model_name.to_string();
+        }
+        routing
+    }
 }
 
 /// Whether an HTTP status code should trigger a fallback to the next model.
@@ -137,6 +248,10 @@ pub fn defaults_for_provider(provider: &str) -> RoutingConfig {
                 task_overrides: HashMap::from([("coding".into(), channel.clone())]),
                 fallbacks: HashMap::from([(channel, vec![worker])]),
                 rate_limit_cooldown_secs: 60,
+                vllm: HashMap::new(),
+                anthropic_betas: HashMap::new(),
+                race: HashMap::new(),
+                shadow: HashMap::new(),
             }
         }
         "openai" => {
@@ -151,6 +266,10 @@ pub fn defaults_for_provider(provider: &str) -> RoutingConfig {
                 task_overrides: HashMap::from([("coding".into(), channel.clone())]),
                 fallbacks: HashMap::from([(channel, vec![worker])]),
                 rate_limit_cooldown_secs: 60,
+                vllm: HashMap::new(),
+                anthropic_betas: HashMap::new(),
+                race: HashMap::new(),
+                shadow: HashMap::new(),
             }
         }
         "ollama" => {
@@ -165,6 +284,10 @@ pub fn defaults_for_provider(provider: &str) -> RoutingConfig {
                 task_overrides: HashMap::from([("coding".into(), channel.clone())]),
                 fallbacks: HashMap::from([(channel, vec![worker])]),
                 rate_limit_cooldown_secs: 60,
+                vllm: HashMap::new(),
+                anthropic_betas: HashMap::new(),
+                race: HashMap::new(),
+                shadow: HashMap::new(),
             }
         }
         "zhipu" => {
@@ -179,6 +302,10 @@ pub fn defaults_for_provider(provider: &str) -> RoutingConfig {
                 task_overrides: HashMap::from([("coding".into(), channel.clone())]),
                 fallbacks: HashMap::from([(channel, vec![worker])]),
                 rate_limit_cooldown_secs: 60,
+                vllm: HashMap::new(),
+                anthropic_betas: HashMap::new(),
+                race: HashMap::new(),
+                shadow: HashMap::new(),
             }
         }
         "groq" => {
@@ -193,6 +320,10 @@ pub fn defaults_for_provider(provider: &str) -> RoutingConfig {
                 task_overrides: HashMap::from([("coding".into(), channel.clone())]),
                 fallbacks: HashMap::from([(channel, vec![worker])]),
                 rate_limit_cooldown_secs: 60,
+                vllm: HashMap::new(),
+                anthropic_betas: HashMap::new(),
+                race: HashMap::new(),
+                shadow: HashMap::new(),
             }
         }
         "together" => {
@@ -207,6 +338,10 @@ pub fn defaults_for_provider(provider: &str) -> RoutingConfig {
                 task_overrides: HashMap::from([("coding".into(), channel.clone())]),
                 fallbacks: HashMap::from([(channel, vec![worker])]),
                 rate_limit_cooldown_secs: 60,
+                vllm: HashMap::new(),
+                anthropic_betas: HashMap::new(),
+                race: HashMap::new(),
+                shadow: HashMap::new(),
             }
         }
         "fireworks" => {
@@ -223,6 +358,10 @@ pub fn defaults_for_provider(provider: &str) -> RoutingConfig {
                 task_overrides: HashMap::from([("coding".into(), channel.clone())]),
                 fallbacks: HashMap::from([(channel, vec![worker])]),
                 rate_limit_cooldown_secs: 60,
+                vllm: HashMap::new(),
+                anthropic_betas: HashMap::new(),
+                race: HashMap::new(),
+                shadow: HashMap::new(),
             }
         }
         "deepseek" => {
@@ -237,6 +376,10 @@ pub fn defaults_for_provider(provider: &str) -> RoutingConfig {
                 task_overrides: HashMap::from([("coding".into(), channel.clone())]),
                 fallbacks: HashMap::new(),
                 rate_limit_cooldown_secs: 60,
+                vllm: HashMap::new(),
+                anthropic_betas: HashMap::new(),
+                race: HashMap::new(),
+                shadow: HashMap::new(),
             }
         }
         "xai" => {
@@ -251,6 +394,10 @@ pub fn defaults_for_provider(provider: &str) -> RoutingConfig {
                 task_overrides: HashMap::from([("coding".into(), channel.clone())]),
                 fallbacks: HashMap::new(),
                 rate_limit_cooldown_secs: 60,
+                vllm: HashMap::new(),
+                anthropic_betas: HashMap::new(),
+                race: HashMap::new(),
+                shadow: HashMap::new(),
             }
         }
         "mistral" => {
@@ -265,6 +412,10 @@ pub fn defaults_for_provider(provider: &str) -> RoutingConfig {
                 task_overrides: HashMap::from([("coding".into(), channel.clone())]),
                 fallbacks: HashMap::from([(channel, vec![worker])]),
                 rate_limit_cooldown_secs: 60,
+                vllm: HashMap::new(),
+                anthropic_betas: HashMap::new(),
+                race: HashMap::new(),
+                shadow: HashMap::new(),
             }
         }
         "opencode-zen" => {
@@ -279,6 +430,10 @@ pub fn defaults_for_provider(provider: &str) -> RoutingConfig {
                 task_overrides: HashMap::from([("coding".into(), channel.clone())]),
                 fallbacks: HashMap::new(),
                 rate_limit_cooldown_secs: 60,
+                vllm: HashMap::new(),
+                anthropic_betas: HashMap::new(),
+                race: HashMap::new(),
+                shadow: HashMap::new(),
             }
         }
         // Anthropic or unknown — use the standard defaults
@@ -314,6 +469,24 @@ pub fn provider_from_model(model: &str) -> &str {
     }
 }
 
+/// `anthropic-beta` values accepted in the routing config. Anthropic adds
+/// flags over time; add them here as they are released.
+pub const KNOWN_ANTHROPIC_BETAS: &[&str] = &[
+    "prompt-caching-2024-07-31",
+    "extended-cache-ttl-2025-04-11",
+    "context-1m-2025-08-07",
+    "computer-use-2024-10-22",
+    "computer-use-2025-01-24",
+    "interleaved-thinking-2025-05-14",
+    "output-128k-2025-02-19",
+    "token-efficient-tools-2025-02-19",
+    "fine-grained-tool-streaming-2025-05-14",
+    "pdfs-2024-09-25",
+    "files-api-2025-04-14",
+    "code-execution-2025-05-22",
+    "mcp-client-2025-04-04",
+];
+
 /// Max number of fallback models to try before giving up.
 pub const MAX_FALLBACK_ATTEMPTS: usize = 3;
 
@@ -330,3 +503,39 @@ pub fn is_rate_limit_error(error_message: &str) -> bool {
     let lower = error_message.to_lowercase();
     lower.contains("429") || lower.contains("rate limit")
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_vllm_options_apply() {
+        let mut body = serde_json::json!({ "model": "Qwen/Qwen2.5-7B-Instruct", "messages": [] });
+        VllmOptions {
+            guided_json: Some(serde_json::json!({ "type": "object" })),
+            guided_regex: None,
+            lora_adapter: Some("support-v2".into()),
+        }
+        .apply(&mut body);
+
+        assert_eq!(body["model"], "support-v2");
+        assert_eq!(body["guided_json"]["type"], "object");
+        assert!(body.get("guided_regex").is_none());
+    }
+
+    #[test]
+    fn test_channel_model_override_falls_back_to_default() {
+        let routing = RoutingConfig::default();
+        let overridden = routing.with_channel_model("openrouter/deepseek-chat");
+
+        assert_eq!(
+            overridden.resolve(ProcessType::Channel, None),
+            "openrouter/deepseek-chat"
+        );
+        assert_eq!(overridden.branch, routing.branch);
+        assert_eq!(
+            overridden.get_fallbacks("openrouter/deepseek-chat"),
+            std::slice::from_ref(&routing.channel)
+        );
+    }
+}
@@ -0,0 +1,138 @@
+//! Self-consistency sampling.
+//!
+//! A model asked the same thing several times at a higher temperature makes
+//! different mistakes each time but tends to land on the right answer most
+//! often. With sampling on, a completion is drawn [`SamplingConfig::samples`]
+//! times and one is kept: the majority answer when the samples are
+//! structured (tool calls or JSON), otherwise the one a judge model picks.
+
+use rig::message::AssistantContent;
+use rig::one_or_many::OneOrMany;
+
+use std::collections::HashMap;
+
+/// Most samples drawn for one completion.
+pub const MAX_SAMPLES: usize = 5;
+
+/// How many completions to sample and how to pick between them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SamplingConfig {
+    /// Completions drawn per request, capped at [`MAX_SAMPLES`].
+    pub samples: usize,
+    /// Temperature the samples are drawn at. High enough that they differ.
+    pub temperature: f64,
+    /// Model picking between free-text samples. Defaults to the sampled
+    /// model itself.
+    pub judge: Option<String>,
+}
+
+impl Default for SamplingConfig {
+    fn default() -> Self {
+        Self {
+            samples: 3,
+            temperature: 0.8,
+            judge: None,
+        }
+    }
+}
+
+impl SamplingConfig {
+    /// The default config drawing `samples` completions.
+    pub fn with_samples(samples: usize) -> Self {
+        Self {
+            samples: samples.clamp(1, MAX_SAMPLES),
+            ..Self::default()
+        }
+    }
+}
+
+/// What a structured answer is compared on: its tool calls without their
+/// ids, or the JSON it holds with formatting normalized. None for free text.
+pub(crate) fn answer_key(choice: &OneOrMany<AssistantContent>) -> Option<String> {
+    let calls: Vec<String> = choice
+        .iter()
+        .filter_map(|content| match content {
+            AssistantContent::ToolCall(call) => Some(format!(
+                "{}({})",
+                call.function.name, call.function.arguments
+            )),
+            _ => None,
+        })
+        .collect();
+    if !calls.is_empty() {
+        return Some(calls.join("\n"));
+    }
+
+    let text: String = choice
+        .iter()
+        .filter_map(|content| match content {
+            AssistantContent::Text(text) => Some(text.text.as_str()),
+            _ => None,
+        })
+        .collect();
+    let text = text.trim();
+    let json = text
+        .strip_prefix("```json")
+        .or_else(|| text.strip_prefix("```"))
+        .and_then(|fenced| fenced.strip_suffix("```"))
+        .unwrap_or(text);
+    serde_json::from_str::<serde_json::Value>(json)
+        .ok()
+        .filter(|value| value.is_object() || value.is_array())
+        .map(|value| value.to_string())
+}
+
+/// The first sample with the most common answer, when every sample is
+/// structured and one answer clearly leads. None otherwise, and the samples
+/// go to the judge.
+pub(crate) fn majority(keys: &[Option<String>]) -> Option<usize> {
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for key in keys {
+        *counts.entry(key.as_deref()?).or_default() += 1;
+    }
+    let top = counts.values().copied().max()?;
+    if top < 2 || counts.values().filter(|count| **count == top).count() > 1 {
+        return None;
+    }
+    keys.iter()
+        .position(|key| key.as_deref().is_some_and(|key| counts[key] == top))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(text: &str) -> OneOrMany<AssistantContent> {
+        OneOrMany::one(AssistantContent::text(text))
+    }
+
+    fn call(id: &str, name: &str, arguments: serde_json::Value) -> OneOrMany<AssistantContent> {
+        OneOrMany::one(AssistantContent::tool_call(id, name, arguments))
+    }
+
+    #[test]
+    fn test_answer_key() {
+        // Ids and JSON formatting don't make answers differ.
+        assert_eq!(
+            answer_key(&call("a", "reply", serde_json::json!({"content": "hi"}))),
+            answer_key(&call("b", "reply", serde_json::json!({"content": "hi"})))
+        );
+        assert_eq!(
+            answer_key(&text("```json\n{\"verdict\": \"spam\", \"score\": 2}\n```")),
+            answer_key(&text("{\"score\":2,\"verdict\":\"spam\"}"))
+        );
+        assert_eq!(answer_key(&text("The answer is 42.")), None);
+        assert_eq!(answer_key(&text("42")), None);
+    }
+
+    #[test]
+    fn test_majority() {
+        let key = |value: &str| Some(value.to_string());
+        assert_eq!(majority(&[key("b"), key("a"), key("a")]), Some(1));
+        // A tie, or every sample different, has no majority.
+        assert_eq!(majority(&[key("a"), key("b"), key("a"), key("b")]), None);
+        assert_eq!(majority(&[key("a"), key("b"), key("c")]), None);
+        // Free text anywhere means the judge decides.
+        assert_eq!(majority(&[key("a"), key("a"), None]), None);
+    }
+}
@@ -0,0 +1,585 @@
+//! Tool parameter schemas rewritten for each provider's JSON Schema dialect.
+//!
+//! Tools describe their parameters in plain JSON Schema, but providers only
+//! accept parts of it. Anthropic and OpenAI want an object at the top level
+//! without `anyOf`/`oneOf`/`allOf` there, and don't understand OpenAPI's
+//! `nullable`. Gemini takes an OpenAPI 3.0 subset: no `$ref`, `oneOf` or
+//! `additionalProperties`, `nullable` instead of `null` in a type list, and
+//! `enum` and `format` only on strings. [`normalize`] rewrites a schema for
+//! a [`SchemaDialect`] before it's sent, reporting each rewrite that accepts
+//! more than the original did as a [`SchemaWarning`].
+
+use serde_json::{Map, Value};
+
+use std::collections::HashSet;
+use std::sync::Mutex;
+
+/// Distinct warnings remembered before the log starts over.
+const MAX_REPORTED_WARNINGS: usize = 4096;
+
+/// Gemini keywords dropped without a warning: they don't constrain values.
+const GEMINI_IGNORED_KEYWORDS: &[&str] = &[
+    "$schema",
+    "$id",
+    "$comment",
+    "$defs",
+    "definitions",
+    "examples",
+    "readOnly",
+    "writeOnly",
+    "deprecated",
+];
+
+/// Gemini keywords dropped with a warning, since they constrain values.
+const GEMINI_UNSUPPORTED_KEYWORDS: &[&str] = &[
+    "additionalProperties",
+    "patternProperties",
+    "propertyNames",
+    "unevaluatedProperties",
+    "dependentRequired",
+    "dependentSchemas",
+    "not",
+    "if",
+    "then",
+    "else",
+    "prefixItems",
+    "contains",
+    "uniqueItems",
+    "multipleOf",
+    "exclusiveMinimum",
+    "exclusiveMaximum",
+];
+
+/// The JSON Schema subset a provider accepts for tool parameters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SchemaDialect {
+    Anthropic,
+    /// OpenAI and the OpenAI-compatible providers.
+    OpenAi,
+    /// Gemini models, wherever they're served from.
+    Gemini,
+}
+
+impl SchemaDialect {
+    /// The dialect for `model_name` on `provider`. Gemini models keep their
+    /// restrictions behind OpenRouter and other gateways.
+    pub fn for_model(provider: &str, model_name: &str) -> Self {
+        if model_name.to_ascii_lowercase().contains("gemini") {
+            Self::Gemini
+        } else if provider == "anthropic" {
+            Self::Anthropic
+        } else {
+            Self::OpenAi
+        }
+    }
+}
+
+/// A rewrite that made a schema accept more than it did.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SchemaWarning {
+    /// Where in the schema, e.g. `$.properties.filter`.
+    pub path: String,
+    pub detail: String,
+}
+
+impl std::fmt::Display for SchemaWarning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.path, self.detail)
+    }
+}
+
+/// Rewrite a tool's parameter schema for `dialect`, adding a warning for
+/// each lossy change.
+pub fn normalize(
+    dialect: SchemaDialect,
+    schema: &Value,
+    warnings: &mut Vec<SchemaWarning>,
+) -> Value {
+    let definitions = match dialect {
+        SchemaDialect::Gemini => definitions(schema),
+        _ => Map::new(),
+    };
+    let mut normalizer = Normalizer {
+        dialect,
+        definitions,
+        expanding: Vec::new(),
+        warnings,
+    };
+    let schema = normalizer.schema(schema.clone(), "$");
+    normalizer.root(schema)
+}
+
+/// Logs each distinct schema warning once per model and tool, since the same
+/// tools are sent with every request.
+#[derive(Debug, Default)]
+pub struct SchemaWarningLog {
+    reported: Mutex<HashSet<String>>,
+}
+
+impl SchemaWarningLog {
+    pub fn report(&self, model: &str, tool: &str, warnings: &[SchemaWarning]) {
+        if warnings.is_empty() {
+            return;
+        }
+        let mut reported = self.reported.lock().unwrap_or_else(|p| p.into_inner());
+        if reported.len() > MAX_REPORTED_WARNINGS {
+            reported.clear();
+        }
+        for warning in warnings {
+            if reported.insert(format!("{model}\n{tool}\n{warning}")) {
+                tracing::warn!(
+                    %model,
+                    %tool,
+                    %warning,
+                    "tool schema loosened for the provider"
+                );
+            }
+        }
+    }
+}
+
+/// The schema's `$defs` and `definitions`, by name.
+fn definitions(schema: &Value) -> Map<String, Value> {
+    let mut definitions = Map::new();
+    for keyword in ["definitions", "$defs"] {
+        if let Some(Value::Object(defs)) = schema.get(keyword) {
+            definitions.extend(defs.clone());
+        }
+    }
+    definitions
+}
+
+struct Normalizer<'a> {
+    dialect: SchemaDialect,
+    /// Definitions `$ref`s are inlined from, for Gemini.
+    definitions: Map<String, Value>,
+    /// Definitions being inlined, to cut off recursive references.
+    expanding: Vec<String>,
+    warnings: &'a mut Vec<SchemaWarning>,
+}
+
+impl Normalizer<'_> {
+    fn warn(&mut self, path: &str, detail: impl Into<String>) {
+        self.warnings.push(SchemaWarning {
+            path: path.to_string(),
+            detail: detail.into(),
+        });
+    }
+
+    /// Normalize a schema and its subschemas.
+    fn schema(&mut self, schema: Value, path: &str) -> Value {
+        let Value::Object(mut schema) = schema else {
+            return schema;
+        };
+        // Gemini has no references, so they're inlined.
+        let reference = match self.dialect {
+            SchemaDialect::Gemini => schema.remove("$ref"),
+            _ => None,
+        };
+        if let Some(Value::String(reference)) = reference {
+            return self.inline(&reference, schema, path);
+        }
+
+        if let Some(Value::Object(properties)) = schema.get_mut("properties") {
+            for (name, property) in properties.iter_mut() {
+                *property = self.schema(property.take(), &format!("{path}.properties.{name}"));
+            }
+        }
+        if let Some(items) = schema.get_mut("items") {
+            *items = self.schema(items.take(), &format!("{path}.items"));
+        }
+        for keyword in ["anyOf", "oneOf", "allOf"] {
+            if let Some(Value::Array(variants)) = schema.get_mut(keyword) {
+                for (index, variant) in variants.iter_mut().enumerate() {
+                    *variant = self.schema(variant.take(), &format!("{path}.{keyword}[{index}]"));
+                }
+            }
+        }
+
+        match self.dialect {
+            SchemaDialect::Gemini => self.gemini(&mut schema, path),
+            SchemaDialect::Anthropic | SchemaDialect::OpenAi => {
+                if let Some(additional @ Value::Object(_)) = schema.get_mut("additionalProperties")
+                {
+                    *additional =
+                        self.schema(additional.take(), &format!("{path}.additionalProperties"));
+                }
+                for keyword in ["$defs", "definitions"] {
+                    if let Some(Value::Object(defs)) = schema.get_mut(keyword) {
+                        for (name, def) in defs.iter_mut() {
+                            *def = self.schema(def.take(), &format!("{path}.{keyword}.{name}"));
+                        }
+                    }
+                }
+                self.json_schema(&mut schema, path);
+            }
+        }
+        Value::Object(schema)
+    }
+
+    /// Replace a `$ref` with the definition it points at, keeping the
+    /// referencing schema's own keywords, such as its description.
+    fn inline(&mut self, reference: &str, siblings: Map<String, Value>, path: &str) -> Value {
+        let name = reference
+            .strip_prefix("#/$defs/")
+            .or_else(|| reference.strip_prefix("#/definitions/"))
+            .filter(|name| self.definitions.contains_key(*name))
+            .map(str::to_string);
+        let Some(name) = name else {
+            self.warn(path, format!("unresolvable $ref {reference} dropped"));
+            return self.schema(Value::Object(siblings), path);
+        };
+        if self.expanding.contains(&name) {
+            self.warn(
+                path,
+                format!("recursive $ref {reference} replaced with an unconstrained object"),
+            );
+            let mut schema = siblings;
+            schema.insert("type".into(), "object".into());
+            return self.schema(Value::Object(schema), path);
+        }
+
+        let mut schema = match self.definitions.get(&name) {
+            Some(Value::Object(definition)) => definition.clone(),
+            _ => Map::new(),
+        };
+        schema.extend(siblings);
+        self.expanding.push(name);
+        let schema = self.schema(Value::Object(schema), path);
+        self.expanding.pop();
+        schema
+    }
+
+    /// Anthropic and OpenAI: plain JSON Schema, so OpenAPI's `nullable`
+    /// becomes `null` in the type list, and `required` may only name
+    /// properties the schema has.
+    fn json_schema(&mut self, schema: &mut Map<String, Value>, path: &str) {
+        if let Some(Value::Bool(true)) = schema.remove("nullable") {
+            match schema.get_mut("type") {
+                Some(Value::String(kind)) => {
+                    let kind = std::mem::take(kind);
+                    schema.insert("type".into(), serde_json::json!([kind, "null"]));
+                }
+                Some(Value::Array(kinds)) if !kinds.iter().any(|kind| kind == "null") => {
+                    kinds.push("null".into());
+                }
+                _ => {}
+            }
+        }
+
+        let Some(Value::Object(properties)) = schema.get("properties") else {
+            return;
+        };
+        let Some(Value::Array(required)) = schema.get("required") else {
+            return;
+        };
+        let (kept, dropped): (Vec<Value>, Vec<Value>) =
+            required.iter().cloned().partition(|name| {
+                name.as_str()
+                    .is_some_and(|name| properties.contains_key(name))
+            });
+        if dropped.is_empty() {
+            return;
+        }
+        let dropped: Vec<String> = dropped.iter().map(Value::to_string).collect();
+        self.warn(
+            path,
+            format!("required names missing properties: {}", dropped.join(", ")),
+        );
+        schema.insert("required".into(), Value::Array(kept));
+    }
+
+    /// Gemini: OpenAPI 3.0's subset of JSON Schema.
+    fn gemini(&mut self, schema: &mut Map<String, Value>, path: &str) {
+        for keyword in GEMINI_IGNORED_KEYWORDS {
+            schema.remove(*keyword);
+        }
+        for keyword in GEMINI_UNSUPPORTED_KEYWORDS {
+            match schema.remove(*keyword) {
+                // Allowing extra properties is what Gemini does anyway.
+                None | Some(Value::Bool(true)) if *keyword == "additionalProperties" => {}
+                None => {}
+                Some(_) => self.warn(path, format!("unsupported {keyword} dropped")),
+            }
+        }
+
+        if let Some(Value::Array(variants)) = schema.remove("oneOf") {
+            self.warn(path, "oneOf relaxed to anyOf");
+            match schema.get_mut("anyOf") {
+                Some(Value::Array(any_of)) => any_of.extend(variants),
+                _ => {
+                    schema.insert("anyOf".into(), Value::Array(variants));
+                }
+            }
+        }
+        if let Some(Value::Array(variants)) = schema.remove("allOf") {
+            if variants.len() > 1 {
+                self.warn(path, "allOf merged into one schema");
+            }
+            for variant in variants {
+                if let Value::Object(variant) = variant {
+                    merge(schema, variant);
+                }
+            }
+        }
+
+        if let Some(value) = schema.remove("const") {
+            match value {
+                Value::String(_) => {
+                    schema.insert("enum".into(), Value::Array(vec![value]));
+                }
+                _ => self.warn(path, "non-string const dropped"),
+            }
+        }
+
+        if let Some(Value::Array(kinds)) = schema.get("type") {
+            let mut kinds: Vec<Value> = kinds.clone();
+            let before = kinds.len();
+            kinds.retain(|kind| kind != "null");
+            if kinds.len() < before {
+                schema.insert("nullable".into(), true.into());
+            }
+            if kinds.len() > 1 {
+                self.warn(
+                    path,
+                    format!("type list narrowed to its first type, {}", kinds[0]),
+                );
+            }
+            match kinds.into_iter().next() {
+                Some(kind) => schema.insert("type".into(), kind),
+                None => schema.remove("type"),
+            };
+        }
+
+        let kind = schema
+            .get("type")
+            .and_then(Value::as_str)
+            .unwrap_or_default()
+            .to_string();
+        let enum_is_strings = match schema.get("enum") {
+            Some(Value::Array(values)) => values.iter().all(Value::is_string),
+            _ => true,
+        };
+        if !enum_is_strings || (schema.contains_key("enum") && kind != "string" && !kind.is_empty())
+        {
+            schema.remove("enum");
+            self.warn(path, "enum of non-strings dropped");
+        }
+
+        let format_allowed = match (kind.as_str(), schema.get("format").and_then(Value::as_str)) {
+            (_, None) => true,
+            ("string", Some(format)) => matches!(format, "enum" | "date-time"),
+            ("integer", Some(format)) => matches!(format, "int32" | "int64"),
+            ("number", Some(format)) => matches!(format, "float" | "double"),
+            _ => false,
+        };
+        if !format_allowed {
+            let format = schema.remove("format").unwrap_or_default();
+            self.warn(path, format!("unsupported format {format} dropped"));
+        }
+    }
+
+    /// Make the top level an object schema, as every provider requires.
+    /// Variants at the top level are merged into one object.
+    fn root(&mut self, schema: Value) -> Value {
+        let mut schema = match schema {
+            Value::Object(schema) => schema,
+            _ => Map::new(),
+        };
+        for keyword in ["anyOf", "oneOf", "allOf"] {
+            let Some(Value::Array(variants)) = schema.remove(keyword) else {
+                continue;
+            };
+            let keep_required = keyword == "allOf";
+            if !keep_required {
+                self.warn(
+                    "$",
+                    format!(
+                        "top-level {keyword} merged into one object, its variants' required \
+                         properties are optional"
+                    ),
+                );
+            }
+            for variant in variants {
+                let Value::Object(mut variant) = variant else {
+                    continue;
+                };
+                if !keep_required {
+                    variant.remove("required");
+                }
+                merge(&mut schema, variant);
+            }
+        }
+        match schema.get("type") {
+            None => {}
+            Some(Value::String(kind)) if kind == "object" => {}
+            Some(other) => {
+                let detail = format!("top-level type {other} replaced with object");
+                self.warn("$", detail);
+            }
+        }
+        schema.insert("type".into(), "object".into());
+        if !schema.contains_key("properties") {
+            schema.insert("properties".into(), Value::Object(Map::new()));
+        }
+        Value::Object(schema)
+    }
+}
+
+/// Merge `other` into `schema`: properties and required names are combined,
+/// other keywords `schema` already has win.
+fn merge(schema: &mut Map<String, Value>, other: Map<String, Value>) {
+    for (keyword, value) in other {
+        match (keyword.as_str(), schema.get_mut(&keyword), value) {
+            ("properties", Some(Value::Object(properties)), Value::Object(more)) => {
+                for (name, property) in more {
+                    properties.entry(name).or_insert(property);
+                }
+            }
+            ("required", Some(Value::Array(required)), Value::Array(more)) => {
+                for name in more {
+                    if !required.contains(&name) {
+                        required.push(name);
+                    }
+                }
+            }
+            (_, Some(_), _) => {}
+            (_, None, value) => {
+                schema.insert(keyword, value);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_gemini_inlines_refs_and_drops_unsupported_keywords() {
+        let schema = json!({
+            "$schema": "http://json-schema.org/draft-07/schema#",
+            "type": "object",
+            "additionalProperties": false,
+            "properties": {
+                "filter": { "$ref": "#/$defs/Filter", "description": "What to match" },
+                "limit": { "type": ["integer", "null"], "format": "uint32" },
+                "mode": { "oneOf": [{ "const": "fast" }, { "const": "thorough" }] }
+            },
+            "required": ["filter"],
+            "$defs": {
+                "Filter": {
+                    "type": "object",
+                    "properties": { "tag": { "type": "string" } }
+                }
+            }
+        });
+        let mut warnings = Vec::new();
+        let normalized = normalize(SchemaDialect::Gemini, &schema, &mut warnings);
+
+        assert_eq!(
+            normalized,
+            json!({
+                "type": "object",
+                "properties": {
+                    "filter": {
+                        "type": "object",
+                        "description": "What to match",
+                        "properties": { "tag": { "type": "string" } }
+                    },
+                    "limit": { "type": "integer", "nullable": true },
+                    "mode": { "anyOf": [{ "enum": ["fast"] }, { "enum": ["thorough"] }] }
+                },
+                "required": ["filter"]
+            })
+        );
+        let warnings: Vec<String> = warnings.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            warnings,
+            [
+                "$.properties.limit: unsupported format \"uint32\" dropped",
+                "$.properties.mode: oneOf relaxed to anyOf",
+                "$: unsupported additionalProperties dropped",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_gemini_cuts_off_recursive_refs() {
+        let schema = json!({
+            "type": "object",
+            "properties": { "tree": { "$ref": "#/definitions/Node" } },
+            "definitions": {
+                "Node": {
+                    "type": "object",
+                    "properties": { "children": { "type": "array", "items": { "$ref": "#/definitions/Node" } } }
+                }
+            }
+        });
+        let mut warnings = Vec::new();
+        let normalized = normalize(SchemaDialect::Gemini, &schema, &mut warnings);
+
+        assert_eq!(
+            normalized["properties"]["tree"]["properties"]["children"]["items"],
+            json!({ "type": "object" })
+        );
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].detail.starts_with("recursive $ref"));
+    }
+
+    #[test]
+    fn test_json_schema_nullable_required_and_top_level_variants() {
+        let schema = json!({
+            "oneOf": [
+                { "type": "object", "properties": { "path": { "type": "string", "nullable": true } }, "required": ["path"] },
+                { "type": "object", "properties": { "url": { "type": "string" } } }
+            ]
+        });
+        let mut warnings = Vec::new();
+        let normalized = normalize(SchemaDialect::OpenAi, &schema, &mut warnings);
+
+        assert_eq!(
+            normalized,
+            json!({
+                "type": "object",
+                "properties": {
+                    "path": { "type": ["string", "null"] },
+                    "url": { "type": "string" }
+                }
+            })
+        );
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].detail.starts_with("top-level oneOf merged"));
+
+        // Required names are only checked where the schema lists properties.
+        let schema = json!({
+            "type": "object",
+            "properties": { "query": { "type": "string" } },
+            "required": ["query", "page"]
+        });
+        let mut warnings = Vec::new();
+        let normalized = normalize(SchemaDialect::Anthropic, &schema, &mut warnings);
+        assert_eq!(normalized["required"], json!(["query"]));
+        assert_eq!(
+            warnings[0].to_string(),
+            "$: required names missing properties: \"page\""
+        );
+    }
+
+    #[test]
+    fn test_dialect_for_model() {
+        assert_eq!(
+            SchemaDialect::for_model("openrouter", "google/gemini-2.5-pro"),
+            SchemaDialect::Gemini
+        );
+        assert_eq!(
+            SchemaDialect::for_model("anthropic", "claude-sonnet-4-20250514"),
+            SchemaDialect::Anthropic
+        );
+        assert_eq!(
+            SchemaDialect::for_model("openrouter", "anthropic/claude-sonnet-4"),
+            SchemaDialect::OpenAi
+        );
+    }
+}
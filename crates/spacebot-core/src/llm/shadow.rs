@@ -0,0 +1,223 @@
+//! Shadow traffic for candidate models.
+//!
+//! With a shadow configured for a model, a sample of its successful requests
+//! is sent again to a candidate model in the background. The candidate's
+//! answer is thrown away; what's kept is how it compares: its latency, cost,
+//! how much its answer agrees with the primary's, and, with a judge, which
+//! of the two the judge preferred. That's enough to tell whether a model is
+//! fit for a fallback chain before any user sees it.
+
+use serde::Serialize;
+
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::{Mutex, MutexGuard};
+
+/// Shadow requests kept in memory for the API.
+const MAX_RECORDED_SHADOWS: usize = 500;
+
+/// One request mirrored to a candidate model.
+#[derive(Debug, Clone, Serialize)]
+pub struct ShadowRecord {
+    /// Id of the production request that was mirrored.
+    pub request_id: String,
+    pub finished_at: chrono::DateTime<chrono::Utc>,
+    pub primary_model: String,
+    pub shadow_model: String,
+    pub primary_latency_ms: u64,
+    pub shadow_latency_ms: u64,
+    pub primary_cost_usd: Option<f64>,
+    pub shadow_cost_usd: Option<f64>,
+    pub shadow_input_tokens: u64,
+    pub shadow_output_tokens: u64,
+    /// Why the candidate failed, if it did.
+    pub error: Option<String>,
+    /// Word overlap between the two answers, from 0 to 1.
+    pub agreement: Option<f64>,
+    /// The judge model, when one compared the answers.
+    pub judge: Option<String>,
+    /// Whether the judge preferred the candidate's answer. None without a
+    /// judge, or when it gave no usable verdict.
+    pub shadow_preferred: Option<bool>,
+    pub judge_cost_usd: Option<f64>,
+}
+
+/// How a candidate has done against one primary model so far.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ShadowSummary {
+    pub primary_model: String,
+    pub shadow_model: String,
+    pub requests: usize,
+    pub failures: usize,
+    /// Mean word overlap with the primary's answers.
+    pub mean_agreement: Option<f64>,
+    /// Requests a judge gave a verdict on, and how many of those it
+    /// preferred the candidate for.
+    pub judged: usize,
+    pub shadow_preferred: usize,
+    pub mean_primary_latency_ms: u64,
+    pub mean_shadow_latency_ms: u64,
+    /// What the mirrored requests cost on each side, over those that were
+    /// priced on both.
+    pub primary_cost_usd: f64,
+    pub shadow_cost_usd: f64,
+}
+
+/// Recent shadow requests, newest last.
+#[derive(Debug, Default)]
+pub struct ShadowLog {
+    records: Mutex<VecDeque<ShadowRecord>>,
+}
+
+impl ShadowLog {
+    pub fn record(&self, record: ShadowRecord) {
+        let mut records = self.lock();
+        if records.len() == MAX_RECORDED_SHADOWS {
+            records.pop_front();
+        }
+        records.push_back(record);
+    }
+
+    /// Recorded shadow requests, newest first.
+    pub fn recent(&self) -> Vec<ShadowRecord> {
+        self.lock().iter().rev().cloned().collect()
+    }
+
+    /// One summary per primary and candidate pair, over the recorded
+    /// requests.
+    pub fn summaries(&self) -> Vec<ShadowSummary> {
+        summarize(self.lock().iter())
+    }
+
+    fn lock(&self) -> MutexGuard<'_, VecDeque<ShadowRecord>> {
+        self.records
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+fn summarize<'a>(records: impl Iterator<Item = &'a ShadowRecord>) -> Vec<ShadowSummary> {
+    #[derive(Default)]
+    struct Totals {
+        summary: ShadowSummary,
+        agreement: Vec<f64>,
+        primary_latency_ms: u64,
+        shadow_latency_ms: u64,
+    }
+
+    let mut pairs: BTreeMap<(&str, &str), Totals> = BTreeMap::new();
+    for record in records {
+        let totals = pairs
+            .entry((record.primary_model.as_str(), record.shadow_model.as_str()))
+            .or_default();
+        let summary = &mut totals.summary;
+        summary.requests += 1;
+        totals.primary_latency_ms += record.primary_latency_ms;
+        if record.error.is_some() {
+            summary.failures += 1;
+            continue;
+        }
+        totals.shadow_latency_ms += record.shadow_latency_ms;
+        totals.agreement.extend(record.agreement);
+        if let Some(preferred) = record.shadow_preferred {
+            summary.judged += 1;
+            summary.shadow_preferred += usize::from(preferred);
+        }
+        if let (Some(primary), Some(shadow)) = (record.primary_cost_usd, record.shadow_cost_usd) {
+            summary.primary_cost_usd += primary;
+            summary.shadow_cost_usd += shadow;
+        }
+    }
+
+    pairs
+        .into_iter()
+        .map(|((primary, shadow), totals)| {
+            let mut summary = totals.summary;
+            summary.primary_model = primary.to_string();
+            summary.shadow_model = shadow.to_string();
+            summary.mean_primary_latency_ms = totals.primary_latency_ms / summary.requests as u64;
+            let answered = (summary.requests - summary.failures) as u64;
+            if let Some(mean) = totals.shadow_latency_ms.checked_div(answered) {
+                summary.mean_shadow_latency_ms = mean;
+            }
+            if !totals.agreement.is_empty() {
+                summary.mean_agreement =
+                    Some(totals.agreement.iter().sum::<f64>() / totals.agreement.len() as f64);
+            }
+            summary
+        })
+        .collect()
+}
+
+/// Whether the request with this id is in the sample mirrored at `rate`.
+/// Request ids are random UUIDs, so their leading bits are a fair draw.
+pub(crate) fn is_sampled(request_id: &str, rate: f64) -> bool {
+    if rate >= 1.0 {
+        return true;
+    }
+    let Ok(id) = uuid::Uuid::parse_str(request_id) else {
+        return false;
+    };
+    (id.as_u64_pair().0 as f64 / u64::MAX as f64) < rate
+}
+
+/// Whether the candidate's answer is shown to the judge first for this
+/// request, so a judge partial to one position doesn't favor either model.
+pub(crate) fn shadow_shown_first(request_id: &str) -> bool {
+    uuid::Uuid::parse_str(request_id).is_ok_and(|id| id.as_u64_pair().1 % 2 == 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(shadow_model: &str, error: Option<&str>, preferred: Option<bool>) -> ShadowRecord {
+        ShadowRecord {
+            request_id: uuid::Uuid::new_v4().to_string(),
+            finished_at: chrono::Utc::now(),
+            primary_model: "anthropic/claude-sonnet-4-20250514".into(),
+            shadow_model: shadow_model.into(),
+            primary_latency_ms: 1200,
+            shadow_latency_ms: 800,
+            primary_cost_usd: Some(0.02),
+            shadow_cost_usd: Some(0.01),
+            shadow_input_tokens: 900,
+            shadow_output_tokens: 300,
+            error: error.map(str::to_string),
+            agreement: error.is_none().then_some(0.5),
+            judge: preferred.map(|_| "anthropic/claude-haiku-4.5-20250514".into()),
+            shadow_preferred: preferred,
+            judge_cost_usd: None,
+        }
+    }
+
+    #[test]
+    fn test_summaries_per_pair() {
+        let log = ShadowLog::default();
+        log.record(record("openai/gpt-4.1", None, Some(true)));
+        log.record(record("openai/gpt-4.1", None, Some(false)));
+        log.record(record("openai/gpt-4.1", Some("503 overloaded"), None));
+        log.record(record("openai/gpt-4.1-mini", None, None));
+
+        let summaries = log.summaries();
+        assert_eq!(summaries.len(), 2);
+        let full = &summaries[0];
+        assert_eq!(full.shadow_model, "openai/gpt-4.1");
+        assert_eq!((full.requests, full.failures), (3, 1));
+        assert_eq!((full.judged, full.shadow_preferred), (2, 1));
+        assert_eq!(full.mean_agreement, Some(0.5));
+        assert_eq!(full.mean_shadow_latency_ms, 800);
+        assert!((full.shadow_cost_usd - 0.02).abs() < 1e-9);
+        assert_eq!(summaries[1].judged, 0);
+    }
+
+    #[test]
+    fn test_sampling_rate() {
+        let ids: Vec<String> = (0..2000)
+            .map(|_| uuid::Uuid::new_v4().to_string())
+            .collect();
+        let sampled = ids.iter().filter(|id| is_sampled(id, 0.25)).count();
+        assert!((300..700).contains(&sampled), "sampled {sampled}");
+        assert!(ids.iter().all(|id| is_sampled(id, 1.0)));
+        assert!(!ids.iter().any(|id| is_sampled(id, 0.0)));
+    }
+}
@@ -0,0 +1,170 @@
+//! Request signing for gateways in front of providers.
+//!
+//! Some corporate gateways only accept requests carrying a signature over
+//! the body. A provider can be given a [`RequestSigner`], which sees each
+//! request after it is fully built and right before it goes out — completion
+//! calls, file uploads, health probes and warm-up alike. [`HmacSigner`] covers
+//! the common shared-secret scheme; anything else can implement the trait and
+//! be registered with
+//! [`LlmManagerBuilder::request_signer`](crate::llm::LlmManagerBuilder::request_signer).
+
+use crate::error::{LlmError, Result};
+
+use hmac::{Hmac, Mac};
+use serde::Deserialize;
+use sha2::Sha256;
+
+/// Signs outgoing requests to a provider.
+pub trait RequestSigner: Send + Sync + 'static {
+    /// Add whatever the gateway expects, usually headers. The body is final
+    /// at this point. An error fails the request without sending it.
+    fn sign(&self, request: &mut reqwest::Request) -> Result<()>;
+}
+
+/// Shared-secret HMAC signing for one provider.
+#[derive(Clone, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct HmacSigningConfig {
+    /// Secret shared with the gateway.
+    pub secret: String,
+    /// Header carrying the hex HMAC-SHA256 signature.
+    #[serde(default = "default_signature_header")]
+    pub signature_header: String,
+    /// Header carrying the Unix timestamp that was signed.
+    #[serde(default = "default_timestamp_header")]
+    pub timestamp_header: String,
+}
+
+impl HmacSigningConfig {
+    pub fn new(secret: impl Into<String>) -> Self {
+        Self {
+            secret: secret.into(),
+            signature_header: default_signature_header(),
+            timestamp_header: default_timestamp_header(),
+        }
+    }
+}
+
+impl std::fmt::Debug for HmacSigningConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("HmacSigningConfig")
+            .field("secret", &"[REDACTED]")
+            .field("signature_header", &self.signature_header)
+            .field("timestamp_header", &self.timestamp_header)
+            .finish()
+    }
+}
+
+fn default_signature_header() -> String {
+    "x-signature".into()
+}
+
+fn default_timestamp_header() -> String {
+    "x-signature-timestamp".into()
+}
+
+/// Signs `{timestamp}.{body}` with HMAC-SHA256 and sends the timestamp and
+/// the hex signature in headers. Requests without a body sign an empty one.
+pub struct HmacSigner {
+    config: HmacSigningConfig,
+}
+
+impl HmacSigner {
+    pub fn new(config: HmacSigningConfig) -> Self {
+        Self { config }
+    }
+
+    fn signature(&self, timestamp: i64, body: &[u8]) -> String {
+        let mut mac = Hmac::<Sha256>::new_from_slice(self.config.secret.as_bytes())
+            .expect("HMAC accepts keys of any length");
+        mac.update(timestamp.to_string().as_bytes());
+        mac.update(b".");
+        mac.update(body);
+        hex(&mac.finalize().into_bytes())
+    }
+}
+
+impl RequestSigner for HmacSigner {
+    fn sign(&self, request: &mut reqwest::Request) -> Result<()> {
+        let body = match request.body() {
+            None => &[][..],
+            Some(body) => body.as_bytes().ok_or_else(|| {
+                LlmError::ProviderRequest("can't sign a streamed request body".into())
+            })?,
+        };
+        let timestamp = chrono::Utc::now().timestamp();
+        let signature = self.signature(timestamp, body);
+
+        let headers = request.headers_mut();
+        headers.insert(
+            header_name(&self.config.timestamp_header)?,
+            reqwest::header::HeaderValue::from(timestamp),
+        );
+        headers.insert(
+            header_name(&self.config.signature_header)?,
+            reqwest::header::HeaderValue::from_str(&signature)
+                .expect("hex is a valid header value"),
+        );
+        Ok(())
+    }
+}
+
+fn header_name(name: &str) -> Result<reqwest::header::HeaderName> {
+    reqwest::header::HeaderName::from_bytes(name.as_bytes()).map_err(|error| {
+        LlmError::ProviderRequest(format!("invalid signing header '{name}': {error}"))
+    })
+}
+
+/// Build `request`, sign it if there is a signer, and send it.
+pub async fn send(
+    request: reqwest::RequestBuilder,
+    signer: Option<&dyn RequestSigner>,
+) -> Result<reqwest::Response> {
+    let (client, request) = request.build_split();
+    let mut request = request.map_err(|error| LlmError::ProviderRequest(error.to_string()))?;
+    if let Some(signer) = signer {
+        signer.sign(&mut request)?;
+    }
+    client
+        .execute(request)
+        .await
+        .map_err(|error| LlmError::ProviderRequest(error.to_string()))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hmac_signer_signs_timestamp_and_body() {
+        let signer = HmacSigner::new(HmacSigningConfig {
+            signature_header: "x-gateway-signature".into(),
+            ..HmacSigningConfig::new("shared-secret")
+        });
+        let mut request = reqwest::Client::new()
+            .post("https://gateway.internal/v1/messages")
+            .body(r#"{"model":"claude"}"#)
+            .build()
+            .unwrap();
+
+        signer.sign(&mut request).unwrap();
+
+        let headers = request.headers();
+        let timestamp: i64 = headers["x-signature-timestamp"]
+            .to_str()
+            .unwrap()
+            .parse()
+            .unwrap();
+        let signature = headers["x-gateway-signature"].to_str().unwrap();
+        assert_eq!(signature.len(), 64);
+        assert_eq!(
+            signature,
+            signer.signature(timestamp, br#"{"model":"claude"}"#)
+        );
+        assert_ne!(signature, signer.signature(timestamp, b"{}"));
+    }
+}
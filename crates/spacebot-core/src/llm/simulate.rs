@@ -0,0 +1,346 @@
+//! Dry runs of model routing.
+//!
+//! [`simulate_route`] walks a request through the same decisions as
+//! `SpacebotModel::route_completion` — cooldown and health skips, retries,
+//! the fallback chain and its length limit — without calling any provider.
+//! Every model that is tried is assumed to fail with the given error, so the
+//! result is the full path a failing request would take. Errors are
+//! classified by the same checks real provider errors go through.
+
+use crate::llm::routing::{
+    MAX_FALLBACK_ATTEMPTS, MAX_RETRIES_PER_MODEL, RETRY_BASE_DELAY_MS, RoutingConfig,
+    is_rate_limit_error, is_retriable_error, provider_from_model,
+};
+
+use serde::{Deserialize, Serialize};
+
+use std::collections::{HashMap, HashSet};
+
+/// The failure every tried model is assumed to return.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SimulatedError {
+    RateLimit,
+    Overloaded,
+    ServerError,
+    Timeout,
+    Auth,
+    BadRequest,
+    ContextOverflow,
+}
+
+impl SimulatedError {
+    pub const ALL: [Self; 7] = [
+        Self::RateLimit,
+        Self::Overloaded,
+        Self::ServerError,
+        Self::Timeout,
+        Self::Auth,
+        Self::BadRequest,
+        Self::ContextOverflow,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::RateLimit => "rate_limit",
+            Self::Overloaded => "overloaded",
+            Self::ServerError => "server_error",
+            Self::Timeout => "timeout",
+            Self::Auth => "auth",
+            Self::BadRequest => "bad_request",
+            Self::ContextOverflow => "context_overflow",
+        }
+    }
+
+    /// An error message of this kind, as a provider would report it.
+    fn message(self) -> &'static str {
+        match self {
+            Self::RateLimit => "Anthropic API error (429 Too Many Requests): rate limit exceeded",
+            Self::Overloaded => "Anthropic API error (529): overloaded",
+            Self::ServerError => "OpenAI API error (503 Service Unavailable)",
+            Self::Timeout => "error sending request: operation timeout",
+            Self::Auth => "Anthropic API error (401 Unauthorized): invalid x-api-key",
+            Self::BadRequest => "OpenAI API error (400 Bad Request): invalid tool schema",
+            Self::ContextOverflow => "Anthropic API error (400 Bad Request): prompt is too long",
+        }
+    }
+}
+
+impl std::str::FromStr for SimulatedError {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        let name = name.trim().to_ascii_lowercase().replace('-', "_");
+        Self::ALL
+            .into_iter()
+            .find(|error| error.name() == name)
+            .ok_or_else(|| {
+                let names: Vec<_> = Self::ALL.iter().map(|error| error.name()).collect();
+                format!(
+                    "unknown error '{name}', expected one of: {}",
+                    names.join(", ")
+                )
+            })
+    }
+}
+
+/// What the router knows about models and providers when a request starts.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RouteState {
+    /// Seconds since each model last hit a rate limit.
+    pub rate_limited: HashMap<String, u64>,
+    /// Providers routing skips: self-hosted ones whose last health probe
+    /// failed, and any in an outage.
+    pub unhealthy_providers: HashSet<String>,
+}
+
+/// One decision the router makes about a model.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum RouteStep {
+    Tried {
+        model: String,
+        attempts: usize,
+        /// Time spent waiting between the attempts.
+        backoff_ms: u64,
+        /// Whether the failure puts the model into rate limit cooldown.
+        enters_cooldown: bool,
+    },
+    Skipped {
+        model: String,
+        reason: SkipReason,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SkipReason {
+    /// Rate limited less than `rate_limit_cooldown_secs` ago.
+    CoolingDown,
+    /// Its provider failed the last health probe.
+    Unhealthy,
+    /// Past the first `MAX_FALLBACK_ATTEMPTS` fallbacks, which is as far as
+    /// the chain goes.
+    PastFallbackLimit,
+}
+
+impl std::fmt::Display for SkipReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::CoolingDown => "cooling down after a rate limit",
+            Self::Unhealthy => "provider failed its health check",
+            Self::PastFallbackLimit => "beyond the fallback limit",
+        })
+    }
+}
+
+/// The steps a request for `model` goes through when every attempt fails
+/// with `error`, in order.
+pub fn simulate_route(
+    routing: &RoutingConfig,
+    model: &str,
+    error: SimulatedError,
+    state: &RouteState,
+) -> Vec<RouteStep> {
+    let cooldown = routing.rate_limit_cooldown_secs;
+    let mut cooling_down: HashSet<&str> = state
+        .rate_limited
+        .iter()
+        .filter(|(_, since)| **since < cooldown)
+        .map(|(model, _)| model.as_str())
+        .collect();
+    let healthy = |model: &str| {
+        !state
+            .unhealthy_providers
+            .contains(provider_from_model(model))
+    };
+    let fallbacks = routing.get_fallbacks(model);
+    let mut steps = Vec::new();
+
+    let primary_rate_limited = cooling_down.contains(model);
+    let primary_healthy = healthy(model);
+    if (primary_rate_limited || !primary_healthy) && !fallbacks.is_empty() {
+        let reason = if primary_rate_limited {
+            SkipReason::CoolingDown
+        } else {
+            SkipReason::Unhealthy
+        };
+        steps.push(skipped(model, reason));
+    } else if !primary_healthy {
+        // One attempt without retries, and no fallback to go on to.
+        steps.push(RouteStep::Tried {
+            model: model.to_string(),
+            attempts: 1,
+            backoff_ms: 0,
+            enters_cooldown: false,
+        });
+        return steps;
+    } else {
+        steps.push(attempt(model, error, &mut cooling_down));
+        if fallbacks.is_empty() {
+            return steps;
+        }
+    }
+
+    for (index, fallback) in fallbacks.iter().enumerate() {
+        let reason = if index >= MAX_FALLBACK_ATTEMPTS {
+            Some(SkipReason::PastFallbackLimit)
+        } else if cooling_down.contains(fallback.as_str()) {
+            Some(SkipReason::CoolingDown)
+        } else if !healthy(fallback) {
+            Some(SkipReason::Unhealthy)
+        } else {
+            None
+        };
+        steps.push(match reason {
+            Some(reason) => skipped(fallback, reason),
+            None => attempt(fallback, error, &mut cooling_down),
+        });
+    }
+    steps
+}
+
+/// A model tried with retries, as `attempt_with_retries` does it.
+fn attempt<'a>(
+    model: &'a str,
+    error: SimulatedError,
+    cooling_down: &mut HashSet<&'a str>,
+) -> RouteStep {
+    let message = error.message();
+    if !is_retriable_error(message) {
+        return RouteStep::Tried {
+            model: model.to_string(),
+            attempts: 1,
+            backoff_ms: 0,
+            enters_cooldown: false,
+        };
+    }
+    let backoff_ms = (1..MAX_RETRIES_PER_MODEL)
+        .map(|retry| RETRY_BASE_DELAY_MS * 2u64.pow(retry as u32 - 1))
+        .sum();
+    let enters_cooldown = is_rate_limit_error(message);
+    if enters_cooldown {
+        cooling_down.insert(model);
+    }
+    RouteStep::Tried {
+        model: model.to_string(),
+        attempts: MAX_RETRIES_PER_MODEL,
+        backoff_ms,
+        enters_cooldown,
+    }
+}
+
+fn skipped(model: &str, reason: SkipReason) -> RouteStep {
+    RouteStep::Skipped {
+        model: model.to_string(),
+        reason,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn routing(fallbacks: &[&str]) -> RoutingConfig {
+        RoutingConfig {
+            fallbacks: HashMap::from([(
+                "anthropic/sonnet".to_string(),
+                fallbacks.iter().map(|model| model.to_string()).collect(),
+            )]),
+            ..RoutingConfig::default()
+        }
+    }
+
+    fn tried(steps: &[RouteStep]) -> Vec<&str> {
+        steps
+            .iter()
+            .filter_map(|step| match step {
+                RouteStep::Tried { model, .. } => Some(model.as_str()),
+                RouteStep::Skipped { .. } => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_rate_limit_walks_the_chain_with_retries() {
+        let routing = routing(&["openai/gpt", "anthropic/sonnet", "ollama/llama", "groq/x"]);
+        let state = RouteState {
+            unhealthy_providers: HashSet::from(["ollama".to_string()]),
+            ..RouteState::default()
+        };
+
+        let steps = simulate_route(
+            &routing,
+            "anthropic/sonnet",
+            SimulatedError::RateLimit,
+            &state,
+        );
+
+        assert_eq!(tried(&steps), ["anthropic/sonnet", "openai/gpt"]);
+        assert_eq!(
+            steps[0],
+            RouteStep::Tried {
+                model: "anthropic/sonnet".into(),
+                attempts: 3,
+                backoff_ms: 1500,
+                enters_cooldown: true,
+            }
+        );
+        // The primary is in the chain again, but now cooling down.
+        assert_eq!(
+            steps[2],
+            skipped("anthropic/sonnet", SkipReason::CoolingDown)
+        );
+        assert_eq!(steps[3], skipped("ollama/llama", SkipReason::Unhealthy));
+        assert_eq!(steps[4], skipped("groq/x", SkipReason::PastFallbackLimit));
+    }
+
+    #[test]
+    fn test_cooldown_and_non_retriable_errors() {
+        let routing = routing(&["openai/gpt"]);
+        let state = RouteState {
+            rate_limited: HashMap::from([
+                ("anthropic/sonnet".to_string(), 10),
+                ("openai/gpt".to_string(), 600),
+            ]),
+            ..RouteState::default()
+        };
+
+        let steps = simulate_route(&routing, "anthropic/sonnet", SimulatedError::Auth, &state);
+
+        assert_eq!(
+            steps[0],
+            skipped("anthropic/sonnet", SkipReason::CoolingDown)
+        );
+        assert_eq!(
+            steps[1],
+            RouteStep::Tried {
+                model: "openai/gpt".into(),
+                attempts: 1,
+                backoff_ms: 0,
+                enters_cooldown: false,
+            }
+        );
+
+        // Without fallbacks, a model in cooldown is still tried.
+        let steps = simulate_route(&routing, "openai/other", SimulatedError::Timeout, &state);
+        assert_eq!(tried(&steps), ["openai/other"]);
+        assert!(matches!(
+            steps[0],
+            RouteStep::Tried {
+                attempts: 3,
+                enters_cooldown: false,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_parse_error_names() {
+        assert_eq!("rate-limit".parse(), Ok(SimulatedError::RateLimit));
+        assert!("nope".parse::<SimulatedError>().is_err());
+        for error in SimulatedError::ALL {
+            assert_eq!(error.name().parse(), Ok(error));
+        }
+    }
+}
@@ -0,0 +1,156 @@
+//! Request tags.
+//!
+//! A trigger can carry a few key-value tags naming the business dimension
+//! it belongs to, like `campaign=spring-promo` or `experiment=short-replies`.
+//! A model built [`with_tags`](crate::llm::model::SpacebotModel::with_tags)
+//! counts its requests under each tag in [`LlmMetrics`], and sends the tags
+//! as request metadata to the providers listed in
+//! `[llm] tag_metadata_providers`, so their own dashboards can slice by them
+//! too.
+//!
+//! Tags end up as metric labels and in every turn's ledger row, so both
+//! their number and their size are bounded: [`RequestTags::parse`] rejects
+//! tags past the limits, and [`LlmMetrics`] folds the values of a key past
+//! [`MAX_TRACKED_VALUES`] into one `other` series.
+//!
+//! [`LlmMetrics`]: crate::llm::metrics::LlmMetrics
+
+use serde::{Deserialize, Serialize};
+
+use std::collections::BTreeMap;
+
+/// Tags one request may carry.
+pub const MAX_TAGS: usize = 16;
+
+/// Longest tag key, in characters.
+pub const MAX_KEY_LEN: usize = 40;
+
+/// Longest tag value, in characters.
+pub const MAX_VALUE_LEN: usize = 128;
+
+/// Distinct values of one key counted in metrics before the rest are
+/// counted as [`OTHER_VALUE`].
+pub const MAX_TRACKED_VALUES: usize = 50;
+
+/// The value the metrics count a key's untracked values under.
+pub const OTHER_VALUE: &str = "other";
+
+/// Key-value tags on a request, sorted by key.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct RequestTags(BTreeMap<String, String>);
+
+impl RequestTags {
+    /// Check `tags` against the limits. Keys are lowercase ASCII letters,
+    /// digits, `_`, `.` and `-`; values are any non-empty text.
+    pub fn parse(tags: BTreeMap<String, String>) -> Result<Self, String> {
+        if tags.len() > MAX_TAGS {
+            return Err(format!("at most {MAX_TAGS} tags are allowed"));
+        }
+        for (key, value) in &tags {
+            if key.is_empty() || key.chars().count() > MAX_KEY_LEN {
+                return Err(format!(
+                    "tag key '{key}' must be 1 to {MAX_KEY_LEN} characters"
+                ));
+            }
+            if !key.chars().all(is_key_char) {
+                return Err(format!(
+                    "tag key '{key}' may only contain a-z, 0-9, '_', '.' and '-'"
+                ));
+            }
+            if value.is_empty() || value.chars().count() > MAX_VALUE_LEN {
+                return Err(format!(
+                    "tag '{key}' must have a value of 1 to {MAX_VALUE_LEN} characters"
+                ));
+            }
+        }
+        Ok(Self(tags))
+    }
+
+    /// Read tags back from message metadata, keeping only the entries
+    /// [`parse`](Self::parse) would accept.
+    pub fn from_json(value: &serde_json::Value) -> Self {
+        let Some(object) = value.as_object() else {
+            return Self::default();
+        };
+        let tags = object
+            .iter()
+            .filter_map(|(key, value)| Some((key.clone(), value.as_str()?.to_string())))
+            .filter(|(key, value)| {
+                Self::parse(BTreeMap::from([(key.clone(), value.clone())])).is_ok()
+            })
+            .take(MAX_TAGS)
+            .collect();
+        Self(tags)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.0
+            .iter()
+            .map(|(key, value)| (key.as_str(), value.as_str()))
+    }
+
+    pub fn into_inner(self) -> BTreeMap<String, String> {
+        self.0
+    }
+
+    /// The tags as a JSON object, for a provider's `metadata` field.
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::json!(self.0)
+    }
+}
+
+fn is_key_char(c: char) -> bool {
+    c.is_ascii_lowercase() || c.is_ascii_digit() || matches!(c, '_' | '.' | '-')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tags(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+        pairs
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_tags_are_bounded() {
+        let parsed = RequestTags::parse(tags(&[
+            ("campaign", "spring-promo"),
+            ("experiment", "short-replies"),
+        ]))
+        .unwrap();
+        assert_eq!(
+            parsed.iter().collect::<Vec<_>>(),
+            [
+                ("campaign", "spring-promo"),
+                ("experiment", "short-replies")
+            ]
+        );
+
+        assert!(RequestTags::parse(tags(&[("Campaign", "x")])).is_err());
+        assert!(RequestTags::parse(tags(&[("campaign", "")])).is_err());
+        assert!(RequestTags::parse(tags(&[("campaign", &"x".repeat(129))])).is_err());
+        let too_many = (0..=MAX_TAGS)
+            .map(|i| (format!("key{i}"), "x".to_string()))
+            .collect();
+        assert!(RequestTags::parse(too_many).is_err());
+
+        let lenient = RequestTags::from_json(&serde_json::json!({
+            "campaign": "spring-promo",
+            "Bad Key": "x",
+            "count": 3,
+        }));
+        assert_eq!(
+            lenient.to_json(),
+            serde_json::json!({"campaign": "spring-promo"})
+        );
+        assert!(RequestTags::from_json(&serde_json::json!("campaign")).is_empty());
+    }
+}
@@ -0,0 +1,207 @@
+//! Per-turn tool trimming.
+//!
+//! Every tool definition is resent with every completion request. For an
+//! agent with a large tool set that is thousands of prompt tokens per call,
+//! most of them for tools the turn has nothing to do with. A [`ToolFilter`]
+//! ranks the tools against the latest user message and sends only the most
+//! relevant ones, plus any that must always be available.
+
+use crate::error::Result;
+
+use rig::completion::{CompletionRequest, ToolDefinition};
+use rig::message::{AssistantContent, Message, UserContent};
+use rig::one_or_many::OneOrMany;
+
+use std::collections::HashSet;
+use std::pin::Pin;
+use std::sync::Arc;
+
+/// Scores tools by relevance to a piece of text.
+pub trait ToolRanker: Send + Sync + 'static {
+    /// One score per tool, in the order given. Higher is more relevant;
+    /// the scale is up to the ranker.
+    fn score(
+        &self,
+        query: &str,
+        tools: &[ToolDefinition],
+    ) -> impl std::future::Future<Output = Result<Vec<f32>>> + Send;
+}
+
+/// Dynamic trait for runtime polymorphism.
+/// Use this when you need `Arc<dyn ToolRankerDyn>`.
+pub trait ToolRankerDyn: Send + Sync + 'static {
+    fn score<'a>(
+        &'a self,
+        query: &'a str,
+        tools: &'a [ToolDefinition],
+    ) -> Pin<Box<dyn std::future::Future<Output = Result<Vec<f32>>> + Send + 'a>>;
+}
+
+/// Blanket implementation: any type implementing ToolRanker automatically implements ToolRankerDyn.
+impl<T: ToolRanker> ToolRankerDyn for T {
+    fn score<'a>(
+        &'a self,
+        query: &'a str,
+        tools: &'a [ToolDefinition],
+    ) -> Pin<Box<dyn std::future::Future<Output = Result<Vec<f32>>> + Send + 'a>> {
+        Box::pin(ToolRanker::score(self, query, tools))
+    }
+}
+
+/// Keeps the `top_k` most relevant tools in each request.
+#[derive(Clone)]
+pub struct ToolFilter {
+    ranker: Arc<dyn ToolRankerDyn>,
+    top_k: usize,
+    always_include: HashSet<String>,
+}
+
+impl ToolFilter {
+    pub fn new(
+        ranker: Arc<dyn ToolRankerDyn>,
+        top_k: usize,
+        always_include: impl IntoIterator<Item = String>,
+    ) -> Self {
+        Self {
+            ranker,
+            top_k,
+            always_include: always_include.into_iter().collect(),
+        }
+    }
+
+    /// Drop the less relevant tools from `request`. See [`select`](Self::select).
+    pub async fn apply(&self, request: &mut CompletionRequest) {
+        let tools = std::mem::take(&mut request.tools);
+        request.tools = self.select(&request.chat_history, tools).await;
+    }
+
+    /// The tools worth sending for the conversation in `history`.
+    ///
+    /// Tools listed in `always_include` and tools already called earlier in
+    /// the conversation are never dropped; providers reject histories that
+    /// reference tools the request doesn't define. Conversations without a
+    /// user text turn, or tool sets already small enough, are left alone, and
+    /// so is everything when ranking fails.
+    pub async fn select(
+        &self,
+        history: &OneOrMany<Message>,
+        tools: Vec<ToolDefinition>,
+    ) -> Vec<ToolDefinition> {
+        if tools.len() <= self.top_k {
+            return tools;
+        }
+        let Some(query) = latest_user_text(history) else {
+            return tools;
+        };
+
+        let mut pinned = called_tools(history);
+        pinned.extend(self.always_include.iter().cloned());
+        let (kept, candidates): (Vec<_>, Vec<_>) = tools
+            .into_iter()
+            .partition(|tool| pinned.contains(&tool.name));
+        if candidates.len() <= self.top_k {
+            return kept.into_iter().chain(candidates).collect();
+        }
+
+        let scores = match self.ranker.score(&query, &candidates).await {
+            Ok(scores) if scores.len() == candidates.len() => scores,
+            Ok(_) | Err(_) => {
+                tracing::warn!("tool ranking failed, sending every tool");
+                return kept.into_iter().chain(candidates).collect();
+            }
+        };
+
+        let mut ranked: Vec<(f32, ToolDefinition)> = scores.into_iter().zip(candidates).collect();
+        ranked.sort_by(|a, b| b.0.total_cmp(&a.0));
+        let dropped = ranked.len().saturating_sub(self.top_k);
+        ranked.truncate(self.top_k);
+        tracing::debug!(
+            kept = kept.len() + ranked.len(),
+            dropped,
+            "trimmed tools for this turn"
+        );
+
+        kept.into_iter()
+            .chain(ranked.into_iter().map(|(_, tool)| tool))
+            .collect()
+    }
+}
+
+/// Text of the most recent user message that has any, skipping turns that
+/// only carry tool results.
+fn latest_user_text(history: &OneOrMany<Message>) -> Option<String> {
+    history
+        .iter()
+        .filter_map(|message| {
+            let Message::User { content } = message else {
+                return None;
+            };
+            let text: Vec<&str> = content
+                .iter()
+                .filter_map(|item| match item {
+                    UserContent::Text(text) => Some(text.text.as_str()),
+                    _ => None,
+                })
+                .collect();
+            (!text.is_empty()).then(|| text.join("\n"))
+        })
+        .last()
+}
+
+/// Names of every tool the assistant has called in the history.
+fn called_tools(history: &OneOrMany<Message>) -> HashSet<String> {
+    history
+        .iter()
+        .filter_map(|message| match message {
+            Message::Assistant { content, .. } => Some(content.iter()),
+            _ => None,
+        })
+        .flatten()
+        .filter_map(|item| match item {
+            AssistantContent::ToolCall(call) => Some(call.function.name.clone()),
+            _ => None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Scores a tool by whether its name appears in the query.
+    struct NameMatch;
+
+    impl ToolRanker for NameMatch {
+        async fn score(&self, query: &str, tools: &[ToolDefinition]) -> Result<Vec<f32>> {
+            Ok(tools
+                .iter()
+                .map(|tool| if query.contains(&tool.name) { 1.0 } else { 0.0 })
+                .collect())
+        }
+    }
+
+    fn tool(name: &str) -> ToolDefinition {
+        ToolDefinition {
+            name: name.into(),
+            description: String::new(),
+            parameters: serde_json::json!({}),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_filter_keeps_top_k_and_pinned_tools() {
+        let filter = ToolFilter::new(Arc::new(NameMatch), 1, ["reply".to_string()]);
+        let history = OneOrMany::one(Message::User {
+            content: OneOrMany::one(UserContent::text("please run the shell")),
+        });
+        let tools = ["reply", "browser", "shell", "file"]
+            .into_iter()
+            .map(tool)
+            .collect();
+
+        let selected = filter.select(&history, tools).await;
+
+        let names: Vec<&str> = selected.iter().map(|tool| tool.name.as_str()).collect();
+        assert_eq!(names, vec!["reply", "shell"]);
+    }
+}
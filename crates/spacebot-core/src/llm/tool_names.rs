@@ -0,0 +1,414 @@
+//! Tool names rewritten for what each provider accepts.
+//!
+//! Providers restrict tool names: Anthropic and OpenAI take up to 64 of
+//! `[a-zA-Z0-9_-]`, Gemini also allows `.` and `:` but wants a letter or
+//! underscore first. Tools are named by whoever registers them (MCP servers
+//! use dots and slashes freely), so a [`ToolNameCodec`] is built per request:
+//! it maps every name the request uses, in its tools and in the tool calls
+//! in its history, to one the provider accepts, and maps the names of the
+//! tool calls that come back to the originals. Names that already fit are
+//! left alone, so the usual request goes through untouched.
+//!
+//! Two names can come out the same, such as `files.read` and `files_read`.
+//! Names that fit keep priority, later ones get a numbered suffix, and the
+//! collision is reported.
+
+use crate::llm::schema::SchemaDialect;
+
+use rig::completion::{self, CompletionRequest, ToolDefinition};
+use rig::message::{AssistantContent, Message};
+use rig::one_or_many::OneOrMany;
+use sha2::{Digest, Sha256};
+
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+
+/// Distinct collisions remembered before the log starts over.
+const MAX_REPORTED_COLLISIONS: usize = 1024;
+
+/// Hex digits of the original name's hash kept when a name is shortened.
+const HASH_SUFFIX_LEN: usize = 8;
+
+/// A provider's limits on tool names.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ToolNameRules {
+    pub max_len: usize,
+    /// Whether `.` and `:` are allowed besides `[a-zA-Z0-9_-]`.
+    pub allow_dots: bool,
+    /// Whether the name must start with a letter or underscore.
+    pub letter_first: bool,
+}
+
+impl ToolNameRules {
+    pub fn for_dialect(dialect: SchemaDialect) -> Self {
+        match dialect {
+            SchemaDialect::Gemini => Self {
+                max_len: 64,
+                allow_dots: true,
+                letter_first: true,
+            },
+            SchemaDialect::Anthropic | SchemaDialect::OpenAi => Self {
+                max_len: 64,
+                allow_dots: false,
+                letter_first: false,
+            },
+        }
+    }
+
+    fn allows(&self, c: char) -> bool {
+        c.is_ascii_alphanumeric()
+            || c == '_'
+            || c == '-'
+            || (self.allow_dots && matches!(c, '.' | ':'))
+    }
+
+    fn allows_first(&self, c: char) -> bool {
+        !self.letter_first || c.is_ascii_alphabetic() || c == '_'
+    }
+
+    /// Whether the provider takes `name` as it is.
+    pub fn accepts(&self, name: &str) -> bool {
+        !name.is_empty()
+            && name.len() <= self.max_len
+            && name.chars().all(|c| self.allows(c))
+            && name.chars().next().is_some_and(|c| self.allows_first(c))
+    }
+
+    /// `name` made acceptable: other characters become `_`, and a name too
+    /// long is cut short and ends in a hash of the original so names sharing
+    /// a prefix stay apart.
+    fn sanitize(&self, name: &str) -> String {
+        let mut sanitized: String = name
+            .chars()
+            .map(|c| if self.allows(c) { c } else { '_' })
+            .collect();
+        if !sanitized
+            .chars()
+            .next()
+            .is_some_and(|c| self.allows_first(c))
+        {
+            sanitized.insert(0, '_');
+        }
+        if sanitized.len() > self.max_len {
+            let hash = hex_hash(name);
+            sanitized.truncate(self.max_len - HASH_SUFFIX_LEN - 1);
+            sanitized.push('_');
+            sanitized.push_str(&hash);
+        }
+        sanitized
+    }
+}
+
+/// Two tool names the provider would see as one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ToolNameCollision {
+    /// The name that kept the provider name.
+    pub kept: String,
+    /// The name that was renamed.
+    pub renamed: String,
+    /// What the provider sees the renamed tool as.
+    pub encoded: String,
+}
+
+impl std::fmt::Display for ToolNameCollision {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} and {} both map to a name already taken, {} is sent as {}",
+            self.kept, self.renamed, self.renamed, self.encoded
+        )
+    }
+}
+
+/// Maps a request's tool names to and from the names a provider accepts.
+#[derive(Debug, Default)]
+pub struct ToolNameCodec {
+    to_provider: HashMap<String, String>,
+    from_provider: HashMap<String, String>,
+    collisions: Vec<ToolNameCollision>,
+}
+
+impl ToolNameCodec {
+    /// Build the codec for the names of `tools` and of the tool calls in
+    /// `history` under `rules`.
+    pub fn new(
+        rules: ToolNameRules,
+        tools: &[ToolDefinition],
+        history: &OneOrMany<Message>,
+    ) -> Self {
+        let mut names: Vec<&str> = tools.iter().map(|tool| tool.name.as_str()).collect();
+        names.extend(history_tool_names(history));
+
+        let mut codec = Self::default();
+        // Names the provider accepts go first, so they keep their names.
+        let (fitting, others): (Vec<&str>, Vec<&str>) =
+            names.into_iter().partition(|name| rules.accepts(name));
+        for name in fitting {
+            codec.insert(name, name.to_string());
+        }
+        for name in others {
+            if codec.to_provider.contains_key(name) {
+                continue;
+            }
+            let sanitized = rules.sanitize(name);
+            let Some(kept) = codec.from_provider.get(&sanitized).cloned() else {
+                codec.insert(name, sanitized);
+                continue;
+            };
+            let encoded = codec.unused_name(rules, &sanitized);
+            codec.collisions.push(ToolNameCollision {
+                kept,
+                renamed: name.to_string(),
+                encoded: encoded.clone(),
+            });
+            codec.insert(name, encoded);
+        }
+        codec
+    }
+
+    fn insert(&mut self, name: &str, encoded: String) {
+        if self.to_provider.contains_key(name) {
+            return;
+        }
+        self.from_provider.insert(encoded.clone(), name.to_string());
+        self.to_provider.insert(name.to_string(), encoded);
+    }
+
+    /// `base` with the lowest numbered suffix no other name has.
+    fn unused_name(&self, rules: ToolNameRules, base: &str) -> String {
+        (2..)
+            .map(|n| {
+                let suffix = format!("_{n}");
+                let end = base.len().min(rules.max_len - suffix.len());
+                format!("{}{suffix}", &base[..end])
+            })
+            .find(|candidate| !self.from_provider.contains_key(candidate))
+            .unwrap_or_default()
+    }
+
+    /// Whether every name is sent as it is.
+    pub fn is_identity(&self) -> bool {
+        self.to_provider
+            .iter()
+            .all(|(name, encoded)| name == encoded)
+    }
+
+    pub fn collisions(&self) -> &[ToolNameCollision] {
+        &self.collisions
+    }
+
+    /// The name the provider sees for `name`.
+    pub fn encode<'a>(&'a self, name: &'a str) -> &'a str {
+        self.to_provider.get(name).map_or(name, String::as_str)
+    }
+
+    /// The original name of a tool the provider called `encoded`.
+    pub fn decode<'a>(&'a self, encoded: &'a str) -> &'a str {
+        self.from_provider
+            .get(encoded)
+            .map_or(encoded, String::as_str)
+    }
+
+    /// A copy of `request` with the provider's names for its tools and for
+    /// the tool calls in its history.
+    pub fn encode_request(&self, request: &CompletionRequest) -> CompletionRequest {
+        let mut encoded = request.clone();
+        for tool in &mut encoded.tools {
+            tool.name = self.encode(&tool.name).to_string();
+        }
+        encoded.chat_history = self.encode_history(&request.chat_history);
+        encoded
+    }
+
+    /// `history` with the provider's names for its tool calls.
+    fn encode_history(&self, history: &OneOrMany<Message>) -> OneOrMany<Message> {
+        let messages: Vec<Message> = history
+            .iter()
+            .map(|message| match message {
+                Message::Assistant { id, content } => Message::Assistant {
+                    id: id.clone(),
+                    content: self.rename_calls(content, Self::encode),
+                },
+                other => other.clone(),
+            })
+            .collect();
+        OneOrMany::many(messages).unwrap_or_else(|_| history.clone())
+    }
+
+    /// Put the original names back on the tool calls in `response`.
+    pub fn decode_response<T>(&self, response: &mut completion::CompletionResponse<T>) {
+        response.choice = self.rename_calls(&response.choice, Self::decode);
+    }
+
+    fn rename_calls(
+        &self,
+        content: &OneOrMany<AssistantContent>,
+        rename: for<'a> fn(&'a Self, &'a str) -> &'a str,
+    ) -> OneOrMany<AssistantContent> {
+        let renamed: Vec<AssistantContent> = content
+            .iter()
+            .map(|item| match item {
+                AssistantContent::ToolCall(call) => {
+                    let mut call = call.clone();
+                    call.function.name = rename(self, &call.function.name).to_string();
+                    AssistantContent::ToolCall(call)
+                }
+                other => other.clone(),
+            })
+            .collect();
+        OneOrMany::many(renamed).unwrap_or_else(|_| content.clone())
+    }
+}
+
+/// Logs each distinct tool name collision once per model.
+#[derive(Debug, Default)]
+pub struct ToolNameCollisionLog {
+    reported: Mutex<HashSet<String>>,
+}
+
+impl ToolNameCollisionLog {
+    pub fn report(&self, model: &str, collisions: &[ToolNameCollision]) {
+        if collisions.is_empty() {
+            return;
+        }
+        let mut reported = self.reported.lock().unwrap_or_else(|p| p.into_inner());
+        if reported.len() > MAX_REPORTED_COLLISIONS {
+            reported.clear();
+        }
+        for collision in collisions {
+            if reported.insert(format!("{model}\n{collision}")) {
+                tracing::warn!(%model, %collision, "tool names collide for the provider");
+            }
+        }
+    }
+}
+
+/// Names of the tools called in `history`.
+fn history_tool_names(history: &OneOrMany<Message>) -> impl Iterator<Item = &str> {
+    history
+        .iter()
+        .filter_map(|message| match message {
+            Message::Assistant { content, .. } => Some(content.iter()),
+            _ => None,
+        })
+        .flatten()
+        .filter_map(|item| match item {
+            AssistantContent::ToolCall(call) => Some(call.function.name.as_str()),
+            _ => None,
+        })
+}
+
+fn hex_hash(text: &str) -> String {
+    Sha256::digest(text.as_bytes())
+        .iter()
+        .take(HASH_SUFFIX_LEN / 2)
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rig::message::{ToolCall, ToolFunction};
+
+    fn tool(name: &str) -> ToolDefinition {
+        ToolDefinition {
+            name: name.into(),
+            description: String::new(),
+            parameters: serde_json::json!({ "type": "object" }),
+        }
+    }
+
+    fn call(name: &str) -> AssistantContent {
+        AssistantContent::ToolCall(ToolCall {
+            id: "call_1".into(),
+            call_id: None,
+            function: ToolFunction {
+                name: name.into(),
+                arguments: serde_json::json!({}),
+            },
+            signature: None,
+            additional_params: None,
+        })
+    }
+
+    fn history(calls: &[&str]) -> OneOrMany<Message> {
+        let mut history = vec![Message::user("hi")];
+        for name in calls {
+            history.push(Message::Assistant {
+                id: None,
+                content: OneOrMany::one(call(name)),
+            });
+        }
+        OneOrMany::many(history).unwrap()
+    }
+
+    #[test]
+    fn test_fitting_names_are_untouched() {
+        let rules = ToolNameRules::for_dialect(SchemaDialect::OpenAi);
+        let codec =
+            ToolNameCodec::new(rules, &[tool("reply"), tool("shell")], &history(&["reply"]));
+        assert!(codec.is_identity());
+        assert!(codec.collisions().is_empty());
+    }
+
+    #[test]
+    fn test_names_round_trip_and_collisions_are_renamed() {
+        let rules = ToolNameRules::for_dialect(SchemaDialect::OpenAi);
+        let tools = [
+            tool("files.read"),
+            tool("files_read"),
+            tool("github/issues.list"),
+        ];
+        let history = history(&["files.read"]);
+        let codec = ToolNameCodec::new(rules, &tools, &history);
+
+        assert_eq!(codec.encode("files_read"), "files_read");
+        assert_eq!(codec.encode("files.read"), "files_read_2");
+        assert_eq!(codec.encode("github/issues.list"), "github_issues_list");
+        assert_eq!(codec.decode("files_read_2"), "files.read");
+        assert_eq!(
+            codec.collisions(),
+            [ToolNameCollision {
+                kept: "files_read".into(),
+                renamed: "files.read".into(),
+                encoded: "files_read_2".into(),
+            }]
+        );
+
+        let encoded = codec.encode_history(&history);
+        assert_eq!(
+            history_tool_names(&encoded).collect::<Vec<_>>(),
+            ["files_read_2"]
+        );
+
+        let mut response = completion::CompletionResponse {
+            choice: OneOrMany::one(call("github_issues_list")),
+            usage: completion::Usage {
+                input_tokens: 0,
+                output_tokens: 0,
+                total_tokens: 0,
+                cached_input_tokens: 0,
+            },
+            raw_response: (),
+        };
+        codec.decode_response(&mut response);
+        let AssistantContent::ToolCall(decoded) = response.choice.first() else {
+            panic!("expected a tool call");
+        };
+        assert_eq!(decoded.function.name, "github/issues.list");
+    }
+
+    #[test]
+    fn test_sanitize_limits() {
+        let gemini = ToolNameRules::for_dialect(SchemaDialect::Gemini);
+        assert_eq!(gemini.sanitize("1password.lookup"), "_1password.lookup");
+
+        let openai = ToolNameRules::for_dialect(SchemaDialect::OpenAi);
+        let long = format!("mcp_{}", "x".repeat(80));
+        let sanitized = openai.sanitize(&long);
+        assert_eq!(sanitized.len(), 64);
+        assert!(openai.accepts(&sanitized));
+        assert_ne!(sanitized, openai.sanitize(&format!("{long}y")));
+    }
+}
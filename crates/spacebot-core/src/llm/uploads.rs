@@ -0,0 +1,236 @@
+//! Provider file uploads for large image attachments.
+//!
+//! An inline base64 image is resent with every turn that still has it in
+//! context. With `upload_threshold_bytes` set, images at least that large are
+//! uploaded once through the provider's file API and referenced by id from
+//! then on. Uploads are cached by content hash and API key (file ids belong
+//! to the account that uploaded them), so later turns reuse the id instead of
+//! uploading again.
+//!
+//! Only Anthropic's Files API (beta) is used: OpenAI-style chat completions
+//! don't accept uploaded files as images, so those providers keep inlining.
+//! A failed upload also falls back to inlining, which includes every upload
+//! to a provider with HMAC request signing, since a streamed multipart body
+//! can't be signed.
+
+use crate::error::{LlmError, Result};
+use crate::llm::signing::RequestSigner;
+use crate::redact::truncate_redacted;
+
+use base64::Engine as _;
+use sha2::{Digest, Sha256};
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// Beta flag required both to upload and to reference uploaded files.
+pub const ANTHROPIC_FILES_BETA: &str = "files-api-2025-04-14";
+
+const ANTHROPIC_FILES_URL: &str = "https://api.anthropic.com/v1/files";
+
+/// Cached file ids beyond this are dropped, all at once.
+const MAX_CACHED_UPLOADS: usize = 1024;
+
+/// Uploads large images and remembers their file ids.
+#[derive(Debug, Default)]
+pub struct FileUploads {
+    /// Smallest decoded image size to upload. `None` never uploads.
+    threshold_bytes: Option<usize>,
+    /// File id by cache key (API key and content hashes).
+    uploaded: Mutex<HashMap<String, String>>,
+}
+
+impl FileUploads {
+    pub fn new(threshold_bytes: Option<usize>) -> Self {
+        Self {
+            threshold_bytes,
+            uploaded: Mutex::default(),
+        }
+    }
+
+    /// Replace large inline images in Anthropic `messages` with uploaded
+    /// files. Returns true if any image now references a file, in which case
+    /// the request must carry the [`ANTHROPIC_FILES_BETA`] header.
+    pub async fn upload_anthropic_images(
+        &self,
+        http: &reqwest::Client,
+        signer: Option<&dyn RequestSigner>,
+        api_key: &str,
+        messages: &mut [serde_json::Value],
+    ) -> bool {
+        let Some(threshold_bytes) = self.threshold_bytes else {
+            return false;
+        };
+        // Collected up front: a lazy iterator's closures held across the
+        // upload's await make the caller's future not `Send`.
+        let sources: Vec<&mut serde_json::Value> = messages
+            .iter_mut()
+            .filter_map(|message| message["content"].as_array_mut())
+            .flatten()
+            .filter(|block| block["type"] == "image")
+            .map(|block| &mut block["source"])
+            .filter(|source| source["type"] == "base64")
+            .collect();
+
+        let mut referenced = false;
+        for source in sources {
+            let Some(data) = source["data"].as_str() else {
+                continue;
+            };
+            if decoded_len(data) < threshold_bytes {
+                continue;
+            }
+            let media_type = source["media_type"].as_str().unwrap_or("image/jpeg");
+            let key = cache_key(api_key, data);
+
+            let file_id = match self.cached(&key) {
+                Some(file_id) => file_id,
+                None => {
+                    match upload_anthropic_file(http, signer, api_key, data, media_type).await {
+                        Ok(file_id) => {
+                            tracing::debug!(%file_id, bytes = decoded_len(data), "uploaded image");
+                            self.remember(key, file_id.clone());
+                            file_id
+                        }
+                        Err(error) => {
+                            tracing::warn!(%error, "image upload failed, sending it inline");
+                            continue;
+                        }
+                    }
+                }
+            };
+            *source = serde_json::json!({ "type": "file", "file_id": file_id });
+            referenced = true;
+        }
+        referenced
+    }
+
+    /// Drop every cached file id, e.g. after the provider stopped
+    /// recognizing one. The images are uploaded again on next use.
+    pub fn forget_all(&self) {
+        self.lock().clear();
+    }
+
+    fn cached(&self, key: &str) -> Option<String> {
+        self.lock().get(key).cloned()
+    }
+
+    fn remember(&self, key: String, file_id: String) {
+        let mut uploaded = self.lock();
+        if uploaded.len() >= MAX_CACHED_UPLOADS {
+            uploaded.clear();
+        }
+        uploaded.insert(key, file_id);
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, String>> {
+        self.uploaded
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+async fn upload_anthropic_file(
+    http: &reqwest::Client,
+    signer: Option<&dyn RequestSigner>,
+    api_key: &str,
+    data: &str,
+    media_type: &str,
+) -> Result<String> {
+    let bytes = base64::engine::general_purpose::STANDARD
+        .decode(data)
+        .map_err(|error| {
+            LlmError::ProviderRequest(format!("image is not valid base64: {error}"))
+        })?;
+    let extension = media_type.rsplit('/').next().unwrap_or("bin");
+    let part = reqwest::multipart::Part::bytes(bytes)
+        .file_name(format!("attachment.{extension}"))
+        .mime_str(media_type)
+        .map_err(|error| LlmError::ProviderRequest(error.to_string()))?;
+
+    let request = http
+        .post(ANTHROPIC_FILES_URL)
+        .header("x-api-key", api_key)
+        .header("anthropic-version", "2023-06-01")
+        .header("anthropic-beta", ANTHROPIC_FILES_BETA)
+        .multipart(reqwest::multipart::Form::new().part("file", part));
+    let response = crate::llm::signing::send(request, signer).await?;
+    let status = response.status();
+    let body: serde_json::Value = response
+        .json()
+        .await
+        .map_err(|error| LlmError::ProviderRequest(format!("file upload ({status}): {error}")))?;
+    if !status.is_success() {
+        let message = body["error"]["message"].as_str().unwrap_or("unknown error");
+        return Err(LlmError::ProviderRequest(format!(
+            "file upload failed ({status}): {}",
+            truncate_redacted(message, 500)
+        )));
+    }
+    body["id"]
+        .as_str()
+        .map(String::from)
+        .ok_or_else(|| LlmError::ProviderRequest("file upload response has no file id".into()))
+}
+
+/// Size of the decoded data, from the length of its base64 encoding.
+fn decoded_len(data: &str) -> usize {
+    data.len() / 4 * 3
+}
+
+/// File ids are only valid for the account that uploaded them, so the key
+/// covers the API key as well as the content.
+fn cache_key(api_key: &str, data: &str) -> String {
+    let account = Sha256::digest(api_key.as_bytes());
+    let content = Sha256::digest(data.as_bytes());
+    format!("{}:{}", hex(&account[..8]), hex(&content))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn image_message(data: &str) -> serde_json::Value {
+        serde_json::json!({
+            "role": "user",
+            "content": [
+                { "type": "text", "text": "what's this?" },
+                {
+                    "type": "image",
+                    "source": { "type": "base64", "media_type": "image/png", "data": data },
+                },
+            ],
+        })
+    }
+
+    #[tokio::test]
+    async fn test_cached_images_are_referenced_by_file_id() {
+        let uploads = FileUploads::new(Some(6));
+        let large = "QUJDREVGR0g="; // 8 bytes
+        uploads.remember(cache_key("key-a", large), "file_123".into());
+        let mut messages = vec![image_message(large), image_message("QUJD")];
+
+        let referenced = uploads
+            .upload_anthropic_images(&reqwest::Client::new(), None, "key-a", &mut messages)
+            .await;
+
+        assert!(referenced);
+        assert_eq!(
+            messages[0]["content"][1]["source"],
+            serde_json::json!({ "type": "file", "file_id": "file_123" })
+        );
+        // Below the threshold, so never uploaded.
+        assert_eq!(messages[1]["content"][1]["source"]["type"], "base64");
+    }
+
+    #[test]
+    fn test_cache_key_depends_on_account_and_content() {
+        assert_eq!(cache_key("key-a", "QUJD"), cache_key("key-a", "QUJD"));
+        assert_ne!(cache_key("key-a", "QUJD"), cache_key("key-b", "QUJD"));
+        assert_ne!(cache_key("key-a", "QUJD"), cache_key("key-a", "REVG"));
+    }
+}
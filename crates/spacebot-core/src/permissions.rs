@@ -0,0 +1,146 @@
+//! Owner-only access for files that hold secrets.
+//!
+//! On Unix this is the mode bits. On Windows, where mode bits don't exist,
+//! the file's ACL is replaced with one granting only the current user, using
+//! the built-in `icacls` tool. Other platforms are left alone.
+
+use std::io;
+use std::path::Path;
+
+/// Restrict a file or directory so only the current user can access it.
+pub fn restrict_to_owner(path: &Path) -> io::Result<()> {
+    imp::restrict_to_owner(path)
+}
+
+/// Whether anyone besides the current user (and, on Windows, SYSTEM and
+/// Administrators) can access the path.
+pub fn accessible_by_others(path: &Path) -> io::Result<bool> {
+    imp::accessible_by_others(path)
+}
+
+#[cfg(unix)]
+mod imp {
+    use std::io;
+    use std::os::unix::fs::PermissionsExt as _;
+    use std::path::Path;
+
+    pub fn restrict_to_owner(path: &Path) -> io::Result<()> {
+        let mode = if path.is_dir() { 0o700 } else { 0o600 };
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))
+    }
+
+    pub fn accessible_by_others(path: &Path) -> io::Result<bool> {
+        Ok(std::fs::metadata(path)?.permissions().mode() & 0o077 != 0)
+    }
+}
+
+#[cfg(windows)]
+mod imp {
+    use std::io;
+    use std::path::Path;
+    use std::process::Command;
+
+    /// Principals that always have access on Windows and aren't a leak.
+    const TRUSTED_PRINCIPALS: &[&str] = &[
+        "NT AUTHORITY\\SYSTEM",
+        "BUILTIN\\Administrators",
+        "CREATOR OWNER",
+    ];
+
+    fn current_user() -> io::Result<String> {
+        let user =
+            std::env::var("USERNAME").map_err(|_| io::Error::other("USERNAME is not set"))?;
+        Ok(match std::env::var("USERDOMAIN") {
+            Ok(domain) => format!("{domain}\\{user}"),
+            Err(_) => user,
+        })
+    }
+
+    fn icacls(path: &Path, args: &[&str]) -> io::Result<String> {
+        let output = Command::new("icacls").arg(path).args(args).output()?;
+        if !output.status.success() {
+            return Err(io::Error::other(format!(
+                "icacls failed: {}",
+                String::from_utf8_lossy(&output.stdout).trim()
+            )));
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+
+    pub fn restrict_to_owner(path: &Path) -> io::Result<()> {
+        // (OI)(CI) makes the grant inherit to files created in a directory.
+        let rights = if path.is_dir() { "(OI)(CI)F" } else { "F" };
+        let grant = format!("{}:{rights}", current_user()?);
+        icacls(path, &["/inheritance:r", "/grant:r", &grant]).map(|_| ())
+    }
+
+    pub fn accessible_by_others(path: &Path) -> io::Result<bool> {
+        let user = current_user()?;
+        let listing = icacls(path, &[])?;
+        let path_prefix = path.display().to_string();
+        Ok(
+            super::acl_principals(&listing, &path_prefix).any(|principal| {
+                !principal.eq_ignore_ascii_case(&user)
+                    && !TRUSTED_PRINCIPALS
+                        .iter()
+                        .any(|trusted| principal.eq_ignore_ascii_case(trusted))
+            }),
+        )
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+mod imp {
+    use std::io;
+    use std::path::Path;
+
+    pub fn restrict_to_owner(_path: &Path) -> io::Result<()> {
+        Ok(())
+    }
+
+    pub fn accessible_by_others(_path: &Path) -> io::Result<bool> {
+        Ok(false)
+    }
+}
+
+/// Principals named in `icacls` output. The first line is prefixed with the
+/// path; every ACE looks like `DOMAIN\name:(flags)`.
+#[cfg_attr(not(windows), allow(dead_code))]
+fn acl_principals<'a>(listing: &'a str, path_prefix: &'a str) -> impl Iterator<Item = &'a str> {
+    listing.lines().filter_map(move |line| {
+        let line = line.strip_prefix(path_prefix).unwrap_or(line).trim();
+        line.rfind(":(").map(|end| line[..end].trim())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_restrict_to_owner() {
+        let path = std::env::temp_dir().join(format!("spacebot-perm-{}", uuid::Uuid::new_v4()));
+        std::fs::write(&path, "secret").expect("temp dir is writable");
+
+        restrict_to_owner(&path).expect("can restrict own file");
+        let exposed = accessible_by_others(&path).expect("can read permissions");
+        std::fs::remove_file(&path).ok();
+
+        assert!(!exposed);
+    }
+
+    #[test]
+    fn test_acl_principals() {
+        let listing = "C:\\Users\\me\\.spacebot\\config.toml NT AUTHORITY\\SYSTEM:(F)\n\
+                       \x20                                 BUILTIN\\Users:(RX)\n\
+                       \x20                                 DESKTOP\\me:(I)(F)\n\
+                       \n\
+                       Successfully processed 1 files; Failed processing 0 files\n";
+        let principals: Vec<_> =
+            acl_principals(listing, "C:\\Users\\me\\.spacebot\\config.toml").collect();
+        assert_eq!(
+            principals,
+            ["NT AUTHORITY\\SYSTEM", "BUILTIN\\Users", "DESKTOP\\me"]
+        );
+    }
+}
@@ -0,0 +1,136 @@
+//! Detection of credential-shaped strings and personal data.
+
+use regex::Regex;
+
+use std::sync::LazyLock;
+
+/// Patterns for credential-shaped strings that must never leave the process.
+pub static LEAK_PATTERNS: LazyLock<Vec<Regex>> = LazyLock::new(|| {
+    vec![
+        // OpenAI keys
+        Regex::new(r"sk-[a-zA-Z0-9]{20,}").expect("hardcoded regex"),
+        // Anthropic keys
+        Regex::new(r"sk-ant-[a-zA-Z0-9_-]{20,}").expect("hardcoded regex"),
+        // OpenRouter keys
+        Regex::new(r"sk-or-[a-zA-Z0-9_-]{20,}").expect("hardcoded regex"),
+        // PEM private keys
+        Regex::new(r"-----BEGIN.*PRIVATE KEY-----").expect("hardcoded regex"),
+        // GitHub personal access tokens
+        Regex::new(r"ghp_[a-zA-Z0-9]{36}").expect("hardcoded regex"),
+        // Google API keys
+        Regex::new(r"AIza[0-9A-Za-z_-]{35}").expect("hardcoded regex"),
+        // Discord bot tokens (base64 user ID . timestamp . HMAC)
+        Regex::new(r"[MN][A-Za-z0-9]{23,}\.[A-Za-z0-9_-]{6}\.[A-Za-z0-9_-]{27,}")
+            .expect("hardcoded regex"),
+        // Slack bot tokens
+        Regex::new(r"xoxb-[0-9]{10,}-[0-9A-Za-z-]+").expect("hardcoded regex"),
+        // Slack app tokens
+        Regex::new(r"xapp-[0-9]-[A-Z0-9]+-[0-9]+-[a-f0-9]+").expect("hardcoded regex"),
+        // Telegram bot tokens
+        Regex::new(r"\d{8,}:[A-Za-z0-9_-]{35}").expect("hardcoded regex"),
+        // Brave Search API keys
+        Regex::new(r"BSA[a-zA-Z0-9]{20,}").expect("hardcoded regex"),
+    ]
+});
+
+/// Personal data patterns and the placeholder each is replaced with. Order
+/// matters: card numbers are matched before phone numbers would eat them.
+static PII_PATTERNS: LazyLock<Vec<(Regex, &'static str)>> = LazyLock::new(|| {
+    vec![
+        (
+            Regex::new(r"[A-Za-z0-9._%+-]+@[A-Za-z0-9.-]+\.[A-Za-z]{2,}").expect("hardcoded regex"),
+            "[EMAIL]",
+        ),
+        // Discord (<@123>, <@!123>, <@&123>) and Slack (<@U0123>) mentions
+        (
+            Regex::new(r"<@[!&]?[0-9A-Z]+(\|[^>]*)?>").expect("hardcoded regex"),
+            "[USER]",
+        ),
+        // Telegram-style @username mentions
+        (
+            Regex::new(r"\B@[A-Za-z][A-Za-z0-9_]{3,}").expect("hardcoded regex"),
+            "[USER]",
+        ),
+        (
+            Regex::new(r"\b\d{4}[ -]?\d{4}[ -]?\d{4}[ -]?\d{1,4}\b").expect("hardcoded regex"),
+            "[CARD]",
+        ),
+        (
+            Regex::new(r"(\+\d{1,3}[ .-]?)?\(?\b\d{3}\)?[ .-]?\d{3}[ .-]?\d{4}\b")
+                .expect("hardcoded regex"),
+            "[PHONE]",
+        ),
+        (
+            Regex::new(r"\b(\d{1,3}\.){3}\d{1,3}\b").expect("hardcoded regex"),
+            "[IP]",
+        ),
+    ]
+});
+
+/// Replace credentials and personal data (emails, mentions, card and phone
+/// numbers, IP addresses) with placeholders, for text leaving the instance.
+pub fn redact_pii(text: &str) -> String {
+    let mut redacted = redact_secrets(text);
+    for (pattern, placeholder) in PII_PATTERNS.iter() {
+        redacted = pattern.replace_all(&redacted, *placeholder).into_owned();
+    }
+    redacted
+}
+
+/// Replace credential-shaped strings with `[REDACTED]`.
+pub fn redact_secrets(text: &str) -> String {
+    let mut redacted = text.to_string();
+    for pattern in LEAK_PATTERNS.iter() {
+        redacted = pattern.replace_all(&redacted, "[REDACTED]").into_owned();
+    }
+    redacted
+}
+
+/// Redact credentials, then cut the text to at most `max_bytes` on a char
+/// boundary, noting how long it was. For provider bodies that end up in
+/// errors, logs or stored diagnostics: a provider can echo the key it was
+/// sent, and error pages can be megabytes of HTML.
+pub fn truncate_redacted(text: &str, max_bytes: usize) -> String {
+    let mut redacted = redact_secrets(text);
+    if redacted.len() > max_bytes {
+        let total = redacted.len();
+        redacted.truncate(redacted.floor_char_boundary(max_bytes));
+        redacted.push_str(&format!("... [truncated, {total} bytes total]"));
+    }
+    redacted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_redact_pii() {
+        let text = "mail jane.doe@example.com or call +1 415-555-0100, \
+                    card 4111 1111 1111 1111, host 10.0.0.12, cc <@123456> and @janedoe";
+        assert_eq!(
+            redact_pii(text),
+            "mail [EMAIL] or call [PHONE], card [CARD], host [IP], cc [USER] and [USER]"
+        );
+        assert_eq!(
+            redact_pii("meet at 10:30 on 2026-02-17"),
+            "meet at 10:30 on 2026-02-17"
+        );
+    }
+
+    #[test]
+    fn test_truncate_redacted() {
+        let body = r#"{"error": "bad key sk-abcdefghijklmnopqrstuvwxyz"}"#;
+        assert_eq!(
+            truncate_redacted(body, 500),
+            r#"{"error": "bad key [REDACTED]"}"#
+        );
+
+        // Cutting inside a multi-byte character backs off to its start.
+        assert_eq!(
+            truncate_redacted("ab\u{e9}cd", 3),
+            "ab... [truncated, 6 bytes total]"
+        );
+        assert_eq!(truncate_redacted("h\u{e9}llo", 6), "h\u{e9}llo");
+    }
+}
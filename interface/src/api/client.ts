@@ -151,11 +151,19 @@ export interface TimelineBranchRun {
 	completed_at: string | null;
 }
 
+export interface WorkerReport {
+	goals: string[];
+	actions: string[];
+	artifacts: string[];
+	unresolved: string[];
+}
+
 export interface TimelineWorkerRun {
 	type: "worker_run";
 	id: string;
 	task: string;
 	result: string | null;
+	report: WorkerReport | null;
 	status: string;
 	started_at: string;
 	completed_at: string | null;
@@ -233,6 +241,17 @@ export interface AgentOverviewResponse {
 	activity_daily: { date: string; branches: number; workers: number }[];
 	activity_heatmap: { day: number; hour: number; count: number }[];
 	latest_bulletin: string | null;
+	turn_usage: TurnUsageTotals;
+}
+
+export interface TurnUsageTotals {
+	turns: number;
+	input_tokens: number;
+	output_tokens: number;
+	cost_usd: number | null;
+	preamble_tokens: number;
+	tool_tokens: number;
+	history_tokens: number;
 }
 
 export interface AgentProfile {
@@ -281,6 +300,20 @@ export interface UpdateStatus {
 	error: string | null;
 }
 
+export interface ProviderOutage {
+	provider: string;
+	started_at: string;
+	attempts: number;
+	failures: number;
+	models: string[];
+	last_error: string;
+}
+
+export interface LlmHealthResponse {
+	providers: Record<string, unknown>;
+	outages: ProviderOutage[];
+}
+
 export interface UpdateApplyResponse {
 	status: "updating" | "error";
 	error?: string;
@@ -603,6 +636,25 @@ export interface AgentConfigUpdateRequest {
 
 // -- Cron Types --
 
+export interface ShareLink {
+	id: string;
+	channel_id: string;
+	created_by: string;
+	created_at: string;
+	expires_at: string;
+	revoked_at: string | null;
+}
+
+export interface ShareLinksResponse {
+	links: ShareLink[];
+}
+
+export interface CreateShareLinkResponse {
+	link: ShareLink;
+	token: string;
+	path: string;
+}
+
 export interface CronJobWithStats {
 	id: string;
 	prompt: string;
@@ -714,6 +766,32 @@ export interface IngestDeleteResponse {
 	success: boolean;
 }
 
+export interface KnowledgeSyncStatus {
+	source_id: string;
+	status: "syncing" | "ok" | "error";
+	last_sync_at: string | null;
+	last_success_at: string | null;
+	last_error: string | null;
+	documents: number;
+	chunks: number;
+	added: number;
+	removed: number;
+}
+
+export interface KnowledgeSourceInfo {
+	id: string;
+	kind: "git" | "notion" | "confluence" | "sitemap";
+	url: string;
+	store: string;
+	interval_secs: number;
+	enabled: boolean;
+	status: KnowledgeSyncStatus | null;
+}
+
+export interface KnowledgeSourcesResponse {
+	sources: KnowledgeSourceInfo[];
+}
+
 // -- Messaging / Bindings Types --
 
 export interface PlatformStatus {
@@ -948,6 +1026,40 @@ export const api = {
 		return response.json() as Promise<AgentConfigResponse>;
 	},
 
+	// Sharing links API
+	listShareLinks: (agentId: string, channelId: string) => {
+		const search = new URLSearchParams({ agent_id: agentId, channel_id: channelId });
+		return fetchJson<ShareLinksResponse>(`/channels/shares?${search}`);
+	},
+
+	createShareLink: async (agentId: string, channelId: string, expiresInDays: number) => {
+		const response = await fetch(`${API_BASE}/channels/shares`, {
+			method: "POST",
+			headers: { "Content-Type": "application/json" },
+			body: JSON.stringify({
+				agent_id: agentId,
+				channel_id: channelId,
+				expires_in_days: expiresInDays,
+				created_by: "dashboard",
+			}),
+		});
+		if (!response.ok) {
+			throw new Error(`API error: ${response.status}`);
+		}
+		return response.json() as Promise<CreateShareLinkResponse>;
+	},
+
+	revokeShareLink: async (agentId: string, linkId: string) => {
+		const search = new URLSearchParams({ agent_id: agentId, id: linkId });
+		const response = await fetch(`${API_BASE}/channels/shares?${search}`, {
+			method: "DELETE",
+		});
+		if (!response.ok) {
+			throw new Error(`API error: ${response.status}`);
+		}
+		return response.json() as Promise<{ success: boolean }>;
+	},
+
 	// Cron API
 	listCronJobs: (agentId: string) =>
 		fetchJson<CronListResponse>(`/agents/cron?agent_id=${encodeURIComponent(agentId)}`),
@@ -1083,6 +1195,9 @@ export const api = {
 		return response.json() as Promise<IngestDeleteResponse>;
 	},
 
+	knowledgeSources: (agentId: string) =>
+		fetchJson<KnowledgeSourcesResponse>(`/agents/knowledge?agent_id=${encodeURIComponent(agentId)}`),
+
 	// Messaging / Bindings API
 	messagingStatus: () => fetchJson<MessagingStatusResponse>("/messaging/status"),
 
@@ -1160,6 +1275,7 @@ export const api = {
 
 	// Update API
 	updateCheck: () => fetchJson<UpdateStatus>("/update/check"),
+	llmHealth: () => fetchJson<LlmHealthResponse>("/llm/health"),
 	updateCheckNow: async () => {
 		const response = await fetch(`${API_BASE}/update/check`, { method: "POST" });
 		if (!response.ok) {
@@ -0,0 +1,26 @@
+import { useQuery } from "@tanstack/react-query";
+import { api } from "@/api/client";
+import { Banner } from "@/ui";
+
+export function OutageBanner() {
+	const { data } = useQuery({
+		queryKey: ["llmHealth"],
+		queryFn: api.llmHealth,
+		refetchInterval: 15_000,
+	});
+
+	if (!data || data.outages.length === 0) return null;
+
+	return (
+		<Banner variant="error" dot="pulse">
+			{data.outages.map((outage) => (
+				<span key={outage.provider}>
+					<strong>{outage.provider}</strong> is failing across{" "}
+					{outage.models.length} models since{" "}
+					{new Date(outage.started_at).toLocaleTimeString()}; requests are routed to
+					fallbacks until it recovers.
+				</span>
+			))}
+		</Banner>
+	);
+}
@@ -255,6 +255,7 @@ export function useChannelLiveState(channels: ChannelInfo[]) {
 			id: event.worker_id,
 			task: event.task,
 			result: null,
+			report: null,
 			status: "running",
 			started_at: new Date().toISOString(),
 			completed_at: null,
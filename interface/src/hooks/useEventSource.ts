@@ -19,7 +19,8 @@ const BACKOFF_MULTIPLIER = 2;
 
 /**
  * SSE hook with exponential backoff, connection state tracking,
- * and reconnect notification for state recovery.
+ * and reconnect notification for state recovery. Reconnects resume from
+ * the last event seen, so the server replays anything missed meanwhile.
  */
 export function useEventSource(url: string, options: UseEventSourceOptions) {
 	const { handlers, enabled = true, onReconnect } = options;
@@ -35,6 +36,7 @@ export function useEventSource(url: string, options: UseEventSourceOptions) {
 	const eventSourceRef = useRef<EventSource>();
 	const retryDelayRef = useRef(INITIAL_RETRY_MS);
 	const hadConnectionRef = useRef(false);
+	const lastEventIdRef = useRef<string>();
 
 	const connect = useCallback(() => {
 		if (eventSourceRef.current) {
@@ -43,7 +45,12 @@ export function useEventSource(url: string, options: UseEventSourceOptions) {
 
 		setConnectionState(hadConnectionRef.current ? "reconnecting" : "connecting");
 
-		const source = new EventSource(url);
+		const lastEventId = lastEventIdRef.current;
+		const source = new EventSource(
+			lastEventId
+				? `${url}${url.includes("?") ? "&" : "?"}cursor=${encodeURIComponent(lastEventId)}`
+				: url,
+		);
 		eventSourceRef.current = source;
 
 		source.onopen = () => {
@@ -60,6 +67,9 @@ export function useEventSource(url: string, options: UseEventSourceOptions) {
 		// Register a listener for each event type in handlers
 		for (const eventType of Object.keys(handlersRef.current)) {
 			source.addEventListener(eventType, (event: MessageEvent) => {
+				if (event.lastEventId) {
+					lastEventIdRef.current = event.lastEventId;
+				}
 				try {
 					const data = JSON.parse(event.data);
 					handlersRef.current[eventType]?.(data);
@@ -91,6 +101,11 @@ export function useEventSource(url: string, options: UseEventSourceOptions) {
 		};
 	}, [url]);
 
+	// A different stream starts from its own beginning
+	useEffect(() => {
+		lastEventIdRef.current = undefined;
+	}, [url]);
+
 	useEffect(() => {
 		if (!enabled) {
 			setConnectionState("disconnected");
@@ -7,6 +7,7 @@ import {
 } from "@tanstack/react-router";
 import {ConnectionBanner} from "@/components/ConnectionBanner";
 import {SetupBanner} from "@/components/SetupBanner";
+import {OutageBanner} from "@/components/OutageBanner";
 import {UpdateBanner} from "@/components/UpdateBanner";
 import {Sidebar} from "@/components/Sidebar";
 import {Overview} from "@/routes/Overview";
@@ -38,6 +39,7 @@ function RootLayout() {
 				<ConnectionBanner state={connectionState} hasData={hasData} />
 				<UpdateBanner />
 				<SetupBanner />
+				<OutageBanner />
 				<div className="flex-1 overflow-hidden">
 					<Outlet />
 				</div>
@@ -1,7 +1,7 @@
 import { useMemo, useState } from "react";
 import { Link } from "@tanstack/react-router";
 import { useQuery } from "@tanstack/react-query";
-import { api, type CortexEvent, type CronJobInfo, MEMORY_TYPES } from "@/api/client";
+import { api, type CortexEvent, type CronJobInfo, type TurnUsageTotals, MEMORY_TYPES } from "@/api/client";
 import type { ChannelLiveState } from "@/hooks/useChannelLiveState";
 import { formatTimeAgo, formatDuration } from "@/lib/format";
 import {
@@ -178,6 +178,17 @@ export function AgentDetail({ agentId, liveStates }: AgentDetailProps) {
 					</div>
 				)}
 
+				{/* Token Breakdown */}
+				{overviewData && overviewData.turn_usage.turns > 0 && (
+					<div className="mt-6 rounded-xl bg-app-darkBox p-5">
+						<div className="mb-4 flex items-center justify-between">
+							<h3 className="font-plex text-sm font-medium text-ink-dull">Where Tokens Go</h3>
+							<span className="text-tiny text-ink-faint">Last 30 days</span>
+						</div>
+						<TokenBreakdown usage={overviewData.turn_usage} />
+					</div>
+				)}
+
 				{/* Identity Preview */}
 				{identityData && <IdentitySection agentId={agentId} identity={identityData} />}
 
@@ -586,6 +597,47 @@ function ModelRoutingList({ config }: { config: { routing: { channel: string; br
 	);
 }
 
+function TokenBreakdown({ usage }: { usage: TurnUsageTotals }) {
+	const parts = [
+		{ label: "Preamble", tokens: usage.preamble_tokens, color: "bg-violet-400" },
+		{ label: "Tool schemas", tokens: usage.tool_tokens, color: "bg-amber-400" },
+		{ label: "History", tokens: usage.history_tokens, color: "bg-blue-400" },
+		{ label: "Completion", tokens: usage.output_tokens, color: "bg-green-400" },
+	];
+	const total = parts.reduce((sum, part) => sum + part.tokens, 0);
+	const split = usage.preamble_tokens + usage.tool_tokens + usage.history_tokens;
+
+	if (split === 0 || total === 0) {
+		return <p className="text-sm text-ink-faint">No turns with a token breakdown yet</p>;
+	}
+
+	return (
+		<div className="flex flex-col gap-3">
+			<div className="flex h-2 overflow-hidden rounded-full bg-app-box">
+				{parts.map((part) => (
+					<div
+						key={part.label}
+						className={part.color}
+						style={{ width: `${(part.tokens / total) * 100}%` }}
+						title={`${part.label}: ${part.tokens.toLocaleString()} tokens`}
+					/>
+				))}
+			</div>
+			<div className="grid grid-cols-2 gap-2 lg:grid-cols-4">
+				{parts.map((part) => (
+					<div key={part.label} className="flex items-center gap-2">
+						<span className={`h-2 w-2 rounded-full ${part.color}`} />
+						<span className="text-tiny text-ink-faint">{part.label}</span>
+						<span className="ml-auto text-sm tabular-nums text-ink-dull">
+							{Math.round((part.tokens / total) * 100)}%
+						</span>
+					</div>
+				))}
+			</div>
+		</div>
+	);
+}
+
 function StatRow({ label, value, truncate }: { label: string; value: string; truncate?: boolean }) {
 	return (
 		<div className="flex items-center justify-between">
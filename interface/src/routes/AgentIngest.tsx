@@ -1,6 +1,6 @@
 import {useState, useRef, useCallback} from "react";
 import {useQuery, useMutation, useQueryClient} from "@tanstack/react-query";
-import {api, type IngestFileInfo} from "@/api/client";
+import {api, type IngestFileInfo, type KnowledgeSourceInfo} from "@/api/client";
 import {formatTimeAgo} from "@/lib/format";
 import {Button, Badge} from "@/ui";
 import {clsx} from "clsx";
@@ -237,6 +237,8 @@ export function AgentIngest({agentId}: AgentIngestProps) {
 						))}
 					</div>
 				)}
+
+				<KnowledgeSources agentId={agentId} />
 			</div>
 		</div>
 	);
@@ -317,3 +319,93 @@ function FileRow({
 		</div>
 	);
 }
+
+function KnowledgeSources({agentId}: {agentId: string}) {
+	const {data} = useQuery({
+		queryKey: ["knowledge-sources", agentId],
+		queryFn: () => api.knowledgeSources(agentId),
+		refetchInterval: 5_000,
+	});
+
+	const sources = data?.sources ?? [];
+	if (sources.length === 0) return null;
+
+	return (
+		<div className="mt-8">
+			<h3 className="mb-3 text-sm font-medium text-ink">Knowledge sources</h3>
+			<div className="flex flex-col gap-2">
+				{sources.map((source) => (
+					<KnowledgeSourceRow key={source.id} source={source} />
+				))}
+			</div>
+		</div>
+	);
+}
+
+function SyncBadge({source}: {source: KnowledgeSourceInfo}) {
+	const styles: Record<string, string> = {
+		syncing: "bg-blue-500/20 text-blue-400",
+		ok: "bg-green-500/20 text-green-400",
+		error: "bg-red-500/20 text-red-400",
+		pending: "bg-amber-500/20 text-amber-400",
+		disabled: "bg-app-box text-ink-faint",
+	};
+	const status = source.enabled ? (source.status?.status ?? "pending") : "disabled";
+	return (
+		<span
+			className={`inline-flex items-center rounded-md px-2 py-0.5 text-xs font-medium ${styles[status]}`}
+		>
+			{status === "syncing" && (
+				<span className="mr-1.5 h-1.5 w-1.5 animate-pulse rounded-full bg-blue-400" />
+			)}
+			{status}
+		</span>
+	);
+}
+
+function KnowledgeSourceRow({source}: {source: KnowledgeSourceInfo}) {
+	const status = source.status;
+
+	return (
+		<div className="flex items-center gap-4 rounded-lg border border-app-line bg-app-darkBox/30 px-4 py-3">
+			<div className="flex h-8 w-8 flex-shrink-0 items-center justify-center rounded-lg bg-app-box text-[10px] text-ink-faint">
+				{source.kind.toUpperCase()}
+			</div>
+
+			<div className="flex min-w-0 flex-1 flex-col gap-0.5">
+				<span className="truncate text-sm font-medium text-ink">
+					{source.id}
+					{source.url && (
+						<span className="ml-2 font-normal text-ink-faint">{source.url}</span>
+					)}
+				</span>
+				<div className="flex items-center gap-3 text-xs text-ink-faint">
+					<span>store: {source.store}</span>
+					{status && (
+						<>
+							<span>
+								{status.documents} doc{status.documents !== 1 ? "s" : ""},{" "}
+								{status.chunks} chunk{status.chunks !== 1 ? "s" : ""}
+							</span>
+							{(status.added > 0 || status.removed > 0) && (
+								<span>
+									+{status.added} / -{status.removed}
+								</span>
+							)}
+						</>
+					)}
+					{status?.last_sync_at && <span>synced {formatTimeAgo(status.last_sync_at)}</span>}
+				</div>
+				{status?.status === "error" && status.last_error && (
+					<span className="truncate text-xs text-red-400" title={status.last_error}>
+						{status.last_error}
+					</span>
+				)}
+			</div>
+
+			<div className="flex-shrink-0">
+				<SyncBadge source={source} />
+			</div>
+		</div>
+	);
+}
@@ -1,13 +1,14 @@
 import { useCallback, useEffect, useRef, useState } from "react";
 import { Link } from "@tanstack/react-router";
+import { useMutation, useQuery, useQueryClient } from "@tanstack/react-query";
 import { AnimatePresence, motion } from "framer-motion";
-import { api, type ChannelInfo, type TimelineItem, type TimelineBranchRun, type TimelineWorkerRun } from "@/api/client";
+import { api, type ChannelInfo, type TimelineItem, type TimelineBranchRun, type TimelineWorkerRun, type WorkerReport } from "@/api/client";
 import type { ChannelLiveState, ActiveWorker, ActiveBranch } from "@/hooks/useChannelLiveState";
 import { CortexChatPanel } from "@/components/CortexChatPanel";
 import { LiveDuration } from "@/components/LiveDuration";
 import { Markdown } from "@/components/Markdown";
-import { formatTimestamp, platformIcon, platformColor } from "@/lib/format";
-import { Button } from "@/ui";
+import { formatTimeAgo, formatTimestamp, platformIcon, platformColor } from "@/lib/format";
+import { Button, Popover, PopoverContent, PopoverTrigger } from "@/ui";
 import { Cancel01Icon, IdeaIcon } from "@hugeicons/core-free-icons";
 import { HugeiconsIcon } from "@hugeicons/react";
 
@@ -40,6 +41,123 @@ function CancelButton({ onClick }: { onClick: () => void }) {
 	);
 }
 
+function shareUrl(path: string): string {
+	const basePath = (window as any).__SPACEBOT_BASE_PATH || "";
+	return `${window.location.origin}${basePath}${path}`;
+}
+
+function ShareButton({ agentId, channelId }: { agentId: string; channelId: string }) {
+	const queryClient = useQueryClient();
+	const [open, setOpen] = useState(false);
+	const [expiresInDays, setExpiresInDays] = useState(7);
+	const [createdUrl, setCreatedUrl] = useState<string | null>(null);
+	const queryKey = ["share-links", agentId, channelId];
+
+	const linksQuery = useQuery({
+		queryKey,
+		queryFn: () => api.listShareLinks(agentId, channelId),
+		enabled: open,
+	});
+
+	const createMutation = useMutation({
+		mutationFn: () => api.createShareLink(agentId, channelId, expiresInDays),
+		onSuccess: (result) => {
+			const url = shareUrl(result.path);
+			setCreatedUrl(url);
+			navigator.clipboard?.writeText(url).catch(console.warn);
+			queryClient.invalidateQueries({ queryKey });
+		},
+	});
+
+	const revokeMutation = useMutation({
+		mutationFn: (linkId: string) => api.revokeShareLink(agentId, linkId),
+		onSuccess: () => queryClient.invalidateQueries({ queryKey }),
+	});
+
+	const now = Date.now();
+	const activeLinks = (linksQuery.data?.links ?? []).filter(
+		(link) => !link.revoked_at && new Date(link.expires_at).getTime() > now,
+	);
+
+	return (
+		<Popover
+			open={open}
+			onOpenChange={(next) => {
+				setOpen(next);
+				if (!next) setCreatedUrl(null);
+			}}
+		>
+			<PopoverTrigger asChild>
+				<Button variant="ghost" size="sm" className="h-8" title="Share a read-only link to this conversation">
+					Share
+				</Button>
+			</PopoverTrigger>
+			<PopoverContent align="end" className="w-80">
+				<p className="text-sm font-medium text-ink">Share conversation</p>
+				<p className="mt-1 text-tiny text-ink-faint">
+					Anyone with the link can read a redacted copy of this conversation until it expires.
+				</p>
+				<div className="mt-3 flex items-center gap-2">
+					<select
+						value={expiresInDays}
+						onChange={(e) => setExpiresInDays(Number(e.target.value))}
+						className="h-8 rounded-md border border-app-line bg-app-darkBox px-2 text-sm text-ink"
+					>
+						<option value={1}>1 day</option>
+						<option value={7}>7 days</option>
+						<option value={30}>30 days</option>
+					</select>
+					<Button
+						size="sm"
+						className="h-8"
+						disabled={createMutation.isPending}
+						onClick={() => createMutation.mutate()}
+					>
+						Create link
+					</Button>
+				</div>
+				{createMutation.isError && (
+					<p className="mt-2 text-tiny text-red-400">Couldn't create the link.</p>
+				)}
+				{createdUrl && (
+					<div className="mt-3">
+						<input
+							readOnly
+							value={createdUrl}
+							onFocus={(e) => e.target.select()}
+							className="w-full rounded-md border border-app-line bg-app-darkBox px-2 py-1 font-mono text-tiny text-ink"
+						/>
+						<p className="mt-1 text-tiny text-ink-faint">
+							Copied. This is the only time the link is shown.
+						</p>
+					</div>
+				)}
+				{activeLinks.length > 0 && (
+					<div className="mt-3 flex flex-col gap-1 border-t border-app-line/50 pt-3">
+						<span className="text-tiny text-ink-faint">Active links</span>
+						{activeLinks.map((link) => (
+							<div key={link.id} className="flex items-center gap-2">
+								<span className="text-tiny text-ink-dull">
+									Created {formatTimeAgo(link.created_at)}, expires {new Date(link.expires_at).toLocaleDateString()}
+								</span>
+								<Button
+									variant="ghost"
+									size="sm"
+									className="ml-auto h-6 text-tiny text-red-400 hover:bg-red-500/15"
+									disabled={revokeMutation.isPending}
+									onClick={() => revokeMutation.mutate(link.id)}
+								>
+									Revoke
+								</Button>
+							</div>
+						))}
+					</div>
+				)}
+			</PopoverContent>
+		</Popover>
+	);
+}
+
 function LiveBranchRunItem({ item, live, channelId }: { item: TimelineBranchRun; live: ActiveBranch; channelId: string }) {
 	const displayTool = live.currentTool ?? live.lastTool;
 	return (
@@ -137,8 +255,40 @@ function BranchRunItem({ item }: { item: TimelineBranchRun }) {
 	);
 }
 
+const REPORT_HEADING = "\n\n**Run report**";
+
+const REPORT_SECTIONS: [keyof WorkerReport, string][] = [
+	["goals", "Goals"],
+	["actions", "Actions"],
+	["artifacts", "Artifacts"],
+	["unresolved", "Unresolved"],
+];
+
+function WorkerReportSections({ report }: { report: WorkerReport }) {
+	return (
+		<div className="flex flex-col gap-2">
+			{REPORT_SECTIONS.filter(([key]) => report[key].length > 0).map(([key, title]) => (
+				<div key={key}>
+					<div className={`text-tiny font-medium ${key === "unresolved" ? "text-red-300" : "text-amber-300"}`}>
+						{title}
+					</div>
+					<ul className="list-disc pl-4 text-sm text-ink-dull">
+						{report[key].map((entry, index) => (
+							<li key={index}>{entry}</li>
+						))}
+					</ul>
+				</div>
+			))}
+		</div>
+	);
+}
+
 function WorkerRunItem({ item }: { item: TimelineWorkerRun }) {
 	const [expanded, setExpanded] = useState(false);
+	const expandable = Boolean(item.result || item.report);
+	// The result the channel got ends with the report rendered as Markdown,
+	// which is shown structured here instead.
+	const result = item.report ? item.result?.split(REPORT_HEADING)[0] : item.result;
 
 	return (
 		<div className="flex gap-3 px-3 py-2">
@@ -156,18 +306,26 @@ function WorkerRunItem({ item }: { item: TimelineWorkerRun }) {
 						<div className="h-2 w-2 rounded-full bg-amber-400/50" />
 						<span className="text-sm font-medium text-amber-300">Worker</span>
 						<span className="truncate text-sm text-ink-dull">{item.task}</span>
-						{item.result && (
-							<span className="ml-auto text-tiny text-ink-faint">
+						{item.report && (
+							<span className="ml-auto rounded bg-amber-500/15 px-1.5 text-tiny text-amber-300">
+								report
+							</span>
+						)}
+						{expandable && (
+							<span className={`${item.report ? "" : "ml-auto "}text-tiny text-ink-faint`}>
 								{expanded ? "▾" : "▸"}
 							</span>
 						)}
 					</div>
 				</Button>
-				{expanded && item.result && (
-					<div className="mt-1 rounded-md border border-amber-500/10 bg-amber-500/5 px-3 py-2">
-						<div className="text-sm text-ink-dull">
-							<Markdown>{item.result}</Markdown>
-						</div>
+				{expanded && expandable && (
+					<div className="mt-1 flex flex-col gap-3 rounded-md border border-amber-500/10 bg-amber-500/5 px-3 py-2">
+						{item.report && <WorkerReportSections report={item.report} />}
+						{result && (
+							<div className="text-sm text-ink-dull">
+								<Markdown>{result}</Markdown>
+							</div>
+						)}
 					</div>
 				)}
 			</div>
@@ -303,6 +461,7 @@ export function ChannelDetail({ agentId, channelId, channel, liveState, onLoadMo
 								<span className="ml-1 text-tiny text-ink-faint">typing</span>
 							</div>
 						)}
+					<ShareButton agentId={agentId} channelId={channelId} />
 					<Button
 						onClick={() => setCortexOpen(!cortexOpen)}
 						variant={cortexOpen ? "secondary" : "ghost"}
@@ -0,0 +1,25 @@
+-- Feedback on assistant replies: platform reactions and /feedback commands.
+-- Attribution (request, model, usage) is copied from the rated reply so the
+-- ledger can be exported and compared across models on its own.
+CREATE TABLE IF NOT EXISTS feedback (
+    id TEXT PRIMARY KEY,
+    channel_id TEXT NOT NULL,
+    message_id TEXT,                 -- conversation_messages.id of the rated reply
+    platform_message_id TEXT,        -- platform ID of the reacted-to message
+    rating INTEGER NOT NULL,         -- 1 (up) or -1 (down)
+    source TEXT NOT NULL,            -- 'reaction' or 'command'
+    user_id TEXT NOT NULL,
+    comment TEXT,
+    request_id TEXT,
+    model TEXT,
+    input_tokens INTEGER,
+    output_tokens INTEGER,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS idx_feedback_channel ON feedback(channel_id, created_at);
+CREATE INDEX IF NOT EXISTS idx_feedback_model ON feedback(model);
+
+-- One reaction per user per message; changing it replaces the rating.
+CREATE UNIQUE INDEX IF NOT EXISTS idx_feedback_reaction
+    ON feedback(channel_id, user_id, platform_message_id);
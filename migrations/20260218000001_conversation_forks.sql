@@ -0,0 +1,25 @@
+-- Conversation forks: editing an earlier message starts a new line of the
+-- conversation from that point while the original stays addressable.
+-- A fork holds only the messages sent on it; everything before its fork
+-- point is read from its parent. Messages with a NULL fork_id are on the
+-- root line.
+CREATE TABLE IF NOT EXISTS conversation_forks (
+    id TEXT PRIMARY KEY,
+    channel_id TEXT NOT NULL,
+    parent_fork_id TEXT,             -- NULL when forked from the root line
+    fork_point_message_id TEXT NOT NULL, -- the edited message, not part of the fork
+    fork_point_seq INTEGER NOT NULL, -- rowid of the edited message
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS idx_conversation_forks_channel ON conversation_forks(channel_id, created_at);
+
+-- The fork new messages in a channel are written to. No row means the root line.
+CREATE TABLE IF NOT EXISTS channel_active_forks (
+    channel_id TEXT PRIMARY KEY,
+    fork_id TEXT NOT NULL
+);
+
+ALTER TABLE conversation_messages ADD COLUMN fork_id TEXT;
+
+CREATE INDEX IF NOT EXISTS idx_conversation_messages_fork ON conversation_messages(channel_id, fork_id);
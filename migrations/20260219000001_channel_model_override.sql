@@ -0,0 +1,3 @@
+-- Model set for one conversation with `/model set`, replacing the agent's
+-- channel model. NULL means the routing default.
+ALTER TABLE channels ADD COLUMN model_override TEXT;
@@ -0,0 +1,8 @@
+-- Feed entries a feed watcher has already picked up, so each entry reaches
+-- the agent once.
+CREATE TABLE IF NOT EXISTS feed_items (
+    feed_id TEXT NOT NULL,           -- id of the feed watcher in config
+    item_id TEXT NOT NULL,           -- entry guid/id as parsed from the feed
+    seen_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (feed_id, item_id)
+);
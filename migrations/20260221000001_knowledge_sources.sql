@@ -0,0 +1,23 @@
+-- Chunks a knowledge source sync has stored as memories, so the next sync
+-- can tell which are new and which have gone.
+CREATE TABLE IF NOT EXISTS knowledge_chunks (
+    source_id TEXT NOT NULL,         -- id of the knowledge source in config
+    chunk_hash TEXT NOT NULL,        -- SHA-256 of the document id and chunk text
+    document TEXT NOT NULL,          -- file path, page URL or Notion page id
+    memory_id TEXT NOT NULL,         -- memory holding the chunk
+    synced_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (source_id, chunk_hash)
+);
+
+-- Outcome of each knowledge source's latest sync, for the dashboard.
+CREATE TABLE IF NOT EXISTS knowledge_sources (
+    source_id TEXT PRIMARY KEY,
+    status TEXT NOT NULL,            -- syncing, ok or error
+    last_sync_at TIMESTAMP,          -- when the latest sync started
+    last_success_at TIMESTAMP,       -- when the latest successful sync finished
+    last_error TEXT,
+    documents INTEGER NOT NULL DEFAULT 0,
+    chunks INTEGER NOT NULL DEFAULT 0,
+    added INTEGER NOT NULL DEFAULT 0,  -- chunks embedded by the latest sync
+    removed INTEGER NOT NULL DEFAULT 0 -- chunks deleted by the latest sync
+);
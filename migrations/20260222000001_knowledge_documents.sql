@@ -0,0 +1,8 @@
+-- When each knowledge source document was last edited as of the latest
+-- sync, so documents that haven't changed aren't fetched again.
+CREATE TABLE IF NOT EXISTS knowledge_documents (
+    source_id TEXT NOT NULL,         -- id of the knowledge source in config
+    document TEXT NOT NULL,          -- Notion or Confluence page id
+    edited_at TEXT NOT NULL,         -- last-edited time as reported by the source
+    PRIMARY KEY (source_id, document)
+);
@@ -0,0 +1,12 @@
+-- One row per finished channel turn, for `spacebot transcript`. The outcome
+-- is the turn's TurnOutcome as JSON: tool calls, usage, cost and routing.
+
+CREATE TABLE IF NOT EXISTS turn_runs (
+    id TEXT PRIMARY KEY,
+    channel_id TEXT NOT NULL,
+    outcome TEXT NOT NULL,
+    completed_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (channel_id) REFERENCES channels(id) ON DELETE CASCADE
+);
+
+CREATE INDEX idx_turn_runs_channel ON turn_runs(channel_id, completed_at);
@@ -0,0 +1,13 @@
+-- Facts pinned to a conversation with `/pin` or the pin tool. They're kept
+-- in the channel's context whatever gets evicted, across restarts too.
+
+CREATE TABLE IF NOT EXISTS channel_pins (
+    id TEXT PRIMARY KEY,
+    channel_id TEXT NOT NULL,
+    content TEXT NOT NULL,
+    pinned_by TEXT NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (channel_id) REFERENCES channels(id) ON DELETE CASCADE
+);
+
+CREATE INDEX idx_channel_pins_channel ON channel_pins(channel_id, created_at);
@@ -0,0 +1,18 @@
+-- One row per tool call, for per-tool spend and latency reports. channel_id
+-- is the conversation the calling process worked for, if any, and cost_usd
+-- the tool's configured price per call.
+
+CREATE TABLE IF NOT EXISTS tool_runs (
+    id TEXT PRIMARY KEY,
+    tool_name TEXT NOT NULL,
+    process_id TEXT NOT NULL,
+    process_type TEXT NOT NULL,
+    channel_id TEXT,
+    duration_ms INTEGER NOT NULL,
+    success INTEGER NOT NULL,
+    cost_usd REAL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX idx_tool_runs_created ON tool_runs(created_at);
+CREATE INDEX idx_tool_runs_channel ON tool_runs(channel_id, created_at);
@@ -0,0 +1,18 @@
+-- Outbound responses a messaging adapter failed to deliver, retried in order
+-- per conversation. id is the response's dedup key; message is the inbound
+-- message the response answers and response the OutboundResponse, both JSON.
+-- Rows are deleted once delivered and kept as 'failed' after the last attempt.
+
+CREATE TABLE IF NOT EXISTS outbox (
+    id TEXT PRIMARY KEY,
+    channel_id TEXT NOT NULL,
+    message TEXT NOT NULL,
+    response TEXT NOT NULL,
+    status TEXT NOT NULL DEFAULT 'pending',
+    attempts INTEGER NOT NULL DEFAULT 0,
+    last_error TEXT,
+    next_attempt_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX idx_outbox_pending ON outbox(status, channel_id);
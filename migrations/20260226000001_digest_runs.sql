@@ -0,0 +1,6 @@
+-- When each scheduled digest last ran, so a restart doesn't post it again
+-- before its interval is up.
+CREATE TABLE IF NOT EXISTS digest_runs (
+    digest_id TEXT PRIMARY KEY,      -- id of the digest in config
+    last_run_at TIMESTAMP NOT NULL
+);
@@ -0,0 +1,10 @@
+-- Per-user rate limits set by admins with `!quota set`, loaded into the
+-- limiter at startup. Null limits follow [defaults.rate_limit].
+CREATE TABLE IF NOT EXISTS user_quotas (
+    user_key TEXT PRIMARY KEY,       -- "platform:sender_id"
+    per_minute INTEGER,
+    burst INTEGER,
+    per_hour INTEGER,
+    updated_by TEXT NOT NULL,
+    updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+);
@@ -0,0 +1,3 @@
+-- Persona a conversation was switched to with `/persona`. NULL means the
+-- agent's own.
+ALTER TABLE channels ADD COLUMN persona TEXT;
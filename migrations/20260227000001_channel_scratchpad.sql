@@ -0,0 +1,11 @@
+-- Working state the channel keeps with the scratchpad tools, one value per
+-- key. Cleared with the rest of the transcript when the conversation expires.
+
+CREATE TABLE IF NOT EXISTS channel_scratchpad (
+    channel_id TEXT NOT NULL,
+    key TEXT NOT NULL,
+    value TEXT NOT NULL,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (channel_id, key),
+    FOREIGN KEY (channel_id) REFERENCES channels(id) ON DELETE CASCADE
+);
@@ -0,0 +1,15 @@
+-- Conversations linked with `/link`, so a user's context follows them from
+-- one surface to another. A link goes both ways and is stored once, with the
+-- lower channel ID first.
+
+CREATE TABLE IF NOT EXISTS conversation_links (
+    channel_id TEXT NOT NULL,
+    linked_channel_id TEXT NOT NULL,
+    linked_by TEXT NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (channel_id, linked_channel_id),
+    FOREIGN KEY (channel_id) REFERENCES channels(id) ON DELETE CASCADE,
+    FOREIGN KEY (linked_channel_id) REFERENCES channels(id) ON DELETE CASCADE
+);
+
+CREATE INDEX idx_conversation_links_linked ON conversation_links(linked_channel_id);
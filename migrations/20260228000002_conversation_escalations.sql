@@ -0,0 +1,19 @@
+-- Conversations flagged for human review, by the agent's `escalate` tool or
+-- an escalation rule. While one is open its conversation gets no automated
+-- replies. Moderators are notified once, then resume the conversation.
+
+CREATE TABLE IF NOT EXISTS conversation_escalations (
+    id TEXT PRIMARY KEY,
+    channel_id TEXT NOT NULL,
+    reason TEXT NOT NULL,
+    flagged_by TEXT NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    notified_at TIMESTAMP,
+    resolved_at TIMESTAMP,
+    resolved_by TEXT,
+    FOREIGN KEY (channel_id) REFERENCES channels(id) ON DELETE CASCADE
+);
+
+-- At most one open escalation per conversation.
+CREATE UNIQUE INDEX idx_conversation_escalations_open
+    ON conversation_escalations(channel_id) WHERE resolved_at IS NULL;
@@ -0,0 +1,4 @@
+-- Structured report a worker gave at the end of a long run, as JSON
+-- (see `agent::self_report`). NULL when none was asked for or it didn't
+-- conform.
+ALTER TABLE worker_runs ADD COLUMN report TEXT;
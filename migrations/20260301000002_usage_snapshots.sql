@@ -0,0 +1,18 @@
+-- What each model's request counters grew by between two snapshots, so
+-- usage history survives restarts without an external metrics store.
+-- Written to the default agent's database, since the counters are shared by
+-- every agent. errors is a JSON object of failed requests by error class.
+
+CREATE TABLE IF NOT EXISTS usage_snapshots (
+    id TEXT PRIMARY KEY,
+    taken_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    model TEXT NOT NULL,
+    requests INTEGER NOT NULL,
+    failed INTEGER NOT NULL,
+    input_tokens INTEGER NOT NULL,
+    output_tokens INTEGER NOT NULL,
+    cost_usd REAL NOT NULL,
+    errors TEXT NOT NULL
+);
+
+CREATE INDEX idx_usage_snapshots_taken ON usage_snapshots(taken_at);
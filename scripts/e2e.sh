@@ -0,0 +1,23 @@
+#!/bin/bash
+# Runs the end-to-end tests in tests/e2e against the docker-compose services.
+# Usage: ./scripts/e2e.sh [extra cargo test args]
+#
+# Set KEEP_SERVICES=1 to leave the services running afterwards.
+
+set -euo pipefail
+
+REPO_ROOT="$(cd "$(dirname "$0")/.." && pwd)"
+COMPOSE=(docker compose -f "$REPO_ROOT/tests/e2e/docker-compose.yml")
+
+cleanup() {
+  if [ "${KEEP_SERVICES:-0}" != "1" ]; then
+    "${COMPOSE[@]}" down --volumes --remove-orphans
+  fi
+}
+trap cleanup EXIT
+
+"${COMPOSE[@]}" up --detach --wait
+
+cd "$REPO_ROOT"
+# Instances share one embedding model cache, so run one test at a time.
+cargo test --features e2e --test e2e "$@" -- --test-threads=1
@@ -0,0 +1,276 @@
+//! Activation rules: which messages in a conversation the bot answers.
+//!
+//! A binding's `activation` policy is checked by the router before a message
+//! reaches a channel, so in a busy channel the bot isn't invoked on every
+//! line. A message activates it when it matches one of the policy's
+//! `respond_to` rules, outside quiet hours and the conversation's cooldown.
+//! Mentions and DMs get through both wherever the policy answers them.
+//! Adapters mark messages that address the bot with [`MENTIONS_BOT`].
+
+use crate::{InboundMessage, MessageContent};
+
+use serde::Deserialize;
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Inbound metadata key set to true when the message mentions or replies to
+/// the bot, or is a DM.
+pub const MENTIONS_BOT: &str = "mentions_bot";
+
+/// Past this many conversations, ones whose cooldown has run out are dropped.
+const PRUNE_THRESHOLD: usize = 10_000;
+
+/// Opening words that make a message a question without a question mark.
+const QUESTION_WORDS: &[&str] = &[
+    "who", "what", "when", "where", "why", "how", "which", "can", "could", "would", "should",
+    "does", "do", "is", "are", "will",
+];
+
+/// Something that makes a message activate the bot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, schemars::JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ActivationRule {
+    /// Every message.
+    Always,
+    /// No message, mentions included. Wins over every other rule.
+    Never,
+    /// Messages that mention or reply to the bot, and DMs.
+    Mention,
+    /// Messages containing one of the policy's `triggers` as a word.
+    Trigger,
+    /// Messages that look like a question.
+    Question,
+}
+
+/// When the bot answers in the conversations a binding matches.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, schemars::JsonSchema)]
+pub struct ActivationPolicy {
+    /// Any one match activates the bot. Empty means always.
+    #[serde(default)]
+    pub respond_to: Vec<ActivationRule>,
+    /// Words or phrases for the `trigger` rule, matched case-insensitively.
+    #[serde(default)]
+    pub triggers: Vec<String>,
+    /// Local hour quiet hours start at. Only mentions activate the bot
+    /// between the start and end hour; the window may wrap midnight.
+    pub quiet_start_hour: Option<u8>,
+    pub quiet_end_hour: Option<u8>,
+    /// After the bot is activated in a conversation, only mentions activate
+    /// it again for this many seconds.
+    #[serde(default)]
+    pub cooldown_secs: u64,
+}
+
+impl ActivationPolicy {
+    /// The rule `message` activates the bot by at local hour `hour`,
+    /// cooldown aside. None when it doesn't.
+    pub fn matching_rule(&self, message: &InboundMessage, hour: u8) -> Option<ActivationRule> {
+        if self.respond_to.contains(&ActivationRule::Never) {
+            return None;
+        }
+        let mentioned = message
+            .metadata
+            .get(MENTIONS_BOT)
+            .and_then(|value| value.as_bool())
+            .unwrap_or(false);
+        let answers_mentions = self.respond_to.is_empty()
+            || self.respond_to.contains(&ActivationRule::Mention)
+            || self.respond_to.contains(&ActivationRule::Always);
+        if mentioned && answers_mentions {
+            return Some(ActivationRule::Mention);
+        }
+        if self.in_quiet_hours(hour) {
+            return None;
+        }
+        if self.respond_to.is_empty() {
+            return Some(ActivationRule::Always);
+        }
+
+        let text = match &message.content {
+            MessageContent::Text(text) => text.as_str(),
+            MessageContent::Media { text, .. } => text.as_deref().unwrap_or_default(),
+        };
+        self.respond_to.iter().copied().find(|rule| match rule {
+            ActivationRule::Always => true,
+            ActivationRule::Trigger => self
+                .triggers
+                .iter()
+                .any(|trigger| contains_phrase(text, trigger)),
+            ActivationRule::Question => is_question(text),
+            ActivationRule::Mention | ActivationRule::Never => false,
+        })
+    }
+
+    fn in_quiet_hours(&self, hour: u8) -> bool {
+        let (Some(start), Some(end)) = (self.quiet_start_hour, self.quiet_end_hour) else {
+            return false;
+        };
+        if start <= end {
+            hour >= start && hour < end
+        } else {
+            // Wraps midnight (e.g. 22:00 - 07:00)
+            hour >= start || hour < end
+        }
+    }
+}
+
+/// When each conversation last activated the bot, for cooldowns. Clones
+/// share state.
+#[derive(Debug, Clone, Default)]
+pub struct Activations {
+    last: Arc<Mutex<HashMap<String, Instant>>>,
+}
+
+impl Activations {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether `message` activates the bot under `policy`, recording it if
+    /// so. `hour` is the current local hour.
+    pub fn admit(
+        &self,
+        policy: &ActivationPolicy,
+        message: &InboundMessage,
+        hour: u8,
+        now: Instant,
+    ) -> bool {
+        let Some(rule) = policy.matching_rule(message, hour) else {
+            return false;
+        };
+
+        let cooldown = Duration::from_secs(policy.cooldown_secs);
+        let mut last = self
+            .last
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let cooling = last
+            .get(&message.conversation_id)
+            .is_some_and(|at| now.duration_since(*at) < cooldown);
+        if cooling && rule != ActivationRule::Mention {
+            return false;
+        }
+
+        if last.len() >= PRUNE_THRESHOLD {
+            last.retain(|_, at| now.duration_since(*at) < cooldown);
+        }
+        last.insert(message.conversation_id.clone(), now);
+        true
+    }
+}
+
+/// Whether `phrase` occurs in `text` as whole words, ignoring case.
+fn contains_phrase(text: &str, phrase: &str) -> bool {
+    let phrase = phrase.trim().to_lowercase();
+    if phrase.is_empty() {
+        return false;
+    }
+    let text = text.to_lowercase();
+    text.match_indices(&phrase).any(|(start, _)| {
+        let before = text[..start].chars().next_back();
+        let after = text[start + phrase.len()..].chars().next();
+        !before.is_some_and(char::is_alphanumeric) && !after.is_some_and(char::is_alphanumeric)
+    })
+}
+
+/// Whether `text` looks like a question: it has a sentence ending in a
+/// question mark, or opens with a question word.
+fn is_question(text: &str) -> bool {
+    let text = text.trim();
+    if text.ends_with('?') || text.contains("? ") || text.contains("?\n") {
+        return true;
+    }
+    text.split_whitespace()
+        .next()
+        .map(|word| word.trim_matches(|c: char| !c.is_alphanumeric()))
+        .is_some_and(|word| {
+            QUESTION_WORDS
+                .iter()
+                .any(|question| word.eq_ignore_ascii_case(question))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(text: &str, mentioned: bool) -> InboundMessage {
+        let mut metadata = HashMap::new();
+        if mentioned {
+            metadata.insert(MENTIONS_BOT.into(), true.into());
+        }
+        InboundMessage {
+            id: "1".into(),
+            source: "discord".into(),
+            conversation_id: "discord:1:2".into(),
+            sender_id: "42".into(),
+            agent_id: None,
+            content: MessageContent::Text(text.into()),
+            timestamp: chrono::Utc::now(),
+            metadata,
+        }
+    }
+
+    #[test]
+    fn test_rules_and_quiet_hours() {
+        let policy = ActivationPolicy {
+            respond_to: vec![
+                ActivationRule::Mention,
+                ActivationRule::Trigger,
+                ActivationRule::Question,
+            ],
+            triggers: vec!["deploy".into()],
+            quiet_start_hour: Some(22),
+            quiet_end_hour: Some(7),
+            ..Default::default()
+        };
+        let rule = |text, mentioned, hour| policy.matching_rule(&message(text, mentioned), hour);
+
+        assert_eq!(rule("lunch?", false, 12), Some(ActivationRule::Question));
+        assert_eq!(rule("How do I", false, 12), Some(ActivationRule::Question));
+        assert_eq!(
+            rule("Deploy it now", false, 12),
+            Some(ActivationRule::Trigger)
+        );
+        assert_eq!(rule("redeployed it", false, 12), None);
+        assert_eq!(rule("nice weather", false, 12), None);
+        assert_eq!(
+            rule("nice weather", true, 12),
+            Some(ActivationRule::Mention)
+        );
+
+        // Quiet hours let only mentions through.
+        assert_eq!(rule("lunch?", false, 23), None);
+        assert_eq!(rule("lunch?", true, 3), Some(ActivationRule::Mention));
+
+        let never = ActivationPolicy {
+            respond_to: vec![ActivationRule::Never, ActivationRule::Mention],
+            ..Default::default()
+        };
+        assert_eq!(never.matching_rule(&message("hi", true), 12), None);
+        assert_eq!(
+            ActivationPolicy::default().matching_rule(&message("hi", false), 12),
+            Some(ActivationRule::Always)
+        );
+    }
+
+    #[test]
+    fn test_cooldown_holds_back_all_but_mentions() {
+        let policy = ActivationPolicy {
+            respond_to: vec![ActivationRule::Always],
+            cooldown_secs: 60,
+            ..Default::default()
+        };
+        let activations = Activations::new();
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+
+        assert!(activations.admit(&policy, &message("one", false), 12, at(0)));
+        assert!(!activations.admit(&policy, &message("two", false), 12, at(30)));
+        assert!(activations.admit(&policy, &message("three", true), 12, at(40)));
+        assert!(!activations.admit(&policy, &message("four", false), 12, at(90)));
+        assert!(activations.admit(&policy, &message("five", false), 12, at(101)));
+    }
+}
@@ -1,10 +1,27 @@
 //! Agent processes: channels, branches, workers, compactor, cortex.
 
 pub mod branch;
+pub mod capabilities;
 pub mod channel;
+pub mod commands;
 pub mod compactor;
 pub mod cortex;
 pub mod cortex_chat;
+pub mod escalation;
+pub mod eviction;
+pub mod fact_check;
+pub mod handoff;
 pub mod ingestion;
+pub mod link;
+pub mod managed;
+pub mod manifest;
+pub mod model_override;
+pub mod persona;
+pub mod provenance;
+pub mod retention;
+pub mod self_report;
 pub mod status;
+pub mod task;
+pub mod thread;
+pub mod turn;
 pub mod worker;
@@ -1,10 +1,13 @@
 //! Branch: Fork context for thinking and delegation.
 
 use crate::agent::compactor::estimate_history_tokens;
+use crate::agent::turn::{StopReason, TurnOutcome, TurnRecorder};
 use crate::error::Result;
 use crate::hooks::SpacebotHook;
-use crate::llm::SpacebotModel;
 use crate::llm::routing::is_context_overflow_error;
+use crate::llm::sampling::SamplingConfig;
+use crate::llm::tags::RequestTags;
+use crate::llm::{Priority, SpacebotModel};
 use crate::{AgentDeps, BranchId, ChannelId, ProcessEvent, ProcessId, ProcessType};
 use rig::agent::AgentBuilder;
 use rig::completion::{CompletionModel, Prompt};
@@ -21,6 +24,8 @@ pub struct Branch {
     pub description: String,
     pub deps: AgentDeps,
     pub hook: SpacebotHook,
+    /// Collects the branch's completions and tool calls.
+    pub turn: TurnRecorder,
     /// System prompt loaded from prompts/BRANCH.md.
     pub system_prompt: String,
     /// Clone of the channel's history at fork time (Rig message format).
@@ -29,6 +34,11 @@ pub struct Branch {
     pub tool_server: ToolServerHandle,
     /// Maximum LLM turns before the branch is forced to conclude.
     pub max_turns: usize,
+    /// Self-consistency sampling for each of the branch's completions.
+    pub sampling: Option<SamplingConfig>,
+    /// Tags of the turn that forked the branch, which its requests are
+    /// counted under.
+    pub tags: RequestTags,
 }
 
 impl Branch {
@@ -44,13 +54,17 @@ impl Branch {
     ) -> Self {
         let id = Uuid::new_v4();
         let process_id = ProcessId::Branch(id);
+        let turn = TurnRecorder::new();
         let hook = SpacebotHook::new(
             deps.agent_id.clone(),
             process_id,
             ProcessType::Branch,
             Some(channel_id.clone()),
             deps.event_tx.clone(),
-        );
+        )
+        .with_events(deps.llm_manager.events().clone())
+        .with_turn_recorder(turn.clone())
+        .with_loop_guard(deps.loop_guard());
 
         Self {
             id,
@@ -58,14 +72,30 @@ impl Branch {
             description: description.into(),
             deps,
             hook,
+            turn,
             system_prompt: system_prompt.into(),
             history,
             tool_server,
             max_turns,
+            sampling: None,
+            tags: RequestTags::default(),
         }
     }
 
-    /// Run the branch's LLM agent loop and return a conclusion.
+    /// Sample each completion several times and keep the consensus.
+    pub fn with_sampling(mut self, sampling: Option<SamplingConfig>) -> Self {
+        self.sampling = sampling;
+        self
+    }
+
+    /// Count the branch's requests under `tags`.
+    pub fn with_tags(mut self, tags: RequestTags) -> Self {
+        self.tags = tags;
+        self
+    }
+
+    /// Run the branch's LLM agent loop and return its conclusion with the
+    /// trace of how it got there.
     ///
     /// Each branch has its own isolated ToolServer with `memory_save` and
     /// `memory_recall` registered at creation. This keeps `memory_recall` off the
@@ -74,7 +104,7 @@ impl Branch {
     /// On context overflow, compacts history and retries up to `MAX_OVERFLOW_RETRIES`
     /// times. Branches inherit a full clone of channel history which may already
     /// be large, making them susceptible to overflow on the first LLM call.
-    pub async fn run(mut self, prompt: impl Into<String>) -> Result<String> {
+    pub async fn run(mut self, prompt: impl Into<String>) -> Result<TurnOutcome> {
         let prompt = prompt.into();
 
         tracing::info!(
@@ -91,7 +121,16 @@ impl Branch {
         let routing = self.deps.runtime_config.routing.load();
         let model_name = routing.resolve(ProcessType::Branch, None).to_string();
         let model = SpacebotModel::make(&self.deps.llm_manager, &model_name)
-            .with_routing((**routing).clone());
+            .with_routing((**routing).clone())
+            .with_priority(Priority::for_process(ProcessType::Branch))
+            .with_seed(**self.deps.runtime_config.seed.load())
+            .with_tags(self.tags.clone())
+            .with_generation(self.deps.runtime_config.generation.load().default_profile())
+            .with_output_cap(self.deps.output_cap(ProcessType::Branch, &model_name))
+            .with_empty_retry(self.deps.empty_nudge())
+            .with_tool_filter(self.deps.tool_filter())
+            .with_compressor(self.deps.compressor())
+            .with_sampling(self.sampling.clone());
 
         let agent = AgentBuilder::new(model)
             .preamble(&self.system_prompt)
@@ -102,24 +141,29 @@ impl Branch {
         let mut current_prompt = prompt;
         let mut overflow_retries = 0;
 
-        let conclusion = loop {
+        let (conclusion, stop_reason) = loop {
             match agent
                 .prompt(&current_prompt)
                 .with_history(&mut self.history)
                 .with_hook(self.hook.clone())
                 .await
             {
-                Ok(response) => break response,
+                Ok(response) => break (response, StopReason::Completed),
                 Err(rig::completion::PromptError::MaxTurnsError { .. }) => {
                     let partial = extract_last_assistant_text(&self.history).unwrap_or_else(|| {
                         "Branch exhausted its turns without a final conclusion.".into()
                     });
                     tracing::warn!(branch_id = %self.id, "branch hit max turns, returning partial result");
-                    break partial;
+                    break (partial, StopReason::MaxTurns);
                 }
                 Err(rig::completion::PromptError::PromptCancelled { reason, .. }) => {
                     tracing::info!(branch_id = %self.id, %reason, "branch cancelled");
-                    break format!("Branch was cancelled: {reason}");
+                    break (
+                        format!("Branch was cancelled: {reason}"),
+                        StopReason::Cancelled {
+                            reason: reason.to_string(),
+                        },
+                    );
                 }
                 Err(error) if is_context_overflow_error(&error.to_string()) => {
                     overflow_retries += 1;
@@ -130,8 +174,9 @@ impl Branch {
                             "branch context overflow unrecoverable after {MAX_OVERFLOW_RETRIES} attempts"
                         );
                         // Return partial conclusion if we have one rather than hard-failing
-                        break extract_last_assistant_text(&self.history)
+                        let partial = extract_last_assistant_text(&self.history)
                             .unwrap_or_else(|| format!("Branch failed: context overflow after {MAX_OVERFLOW_RETRIES} compaction attempts"));
+                        break (partial, StopReason::from_prompt_error(&error));
                     }
 
                     tracing::warn!(
@@ -161,7 +206,7 @@ impl Branch {
 
         tracing::info!(branch_id = %self.id, "branch completed");
 
-        Ok(conclusion)
+        Ok(self.turn.finish_with(conclusion, stop_reason))
     }
 
     /// Compact history if approaching context window limit.
@@ -0,0 +1,99 @@
+//! What a running agent can do, for hosts embedding Spacebot.
+//!
+//! Unlike the manifest behind `spacebot agents describe`, which explains a
+//! config to a person, this answers a program: the structs live in
+//! `spacebot_core::capabilities`, are versioned, and carry each tool's
+//! parameter schema. [`describe`] fills them in from the agent's live
+//! config. Tools come from the same ToolServers branches and workers get,
+//! so their schemas are exactly what the model sees. Channel tools are bound
+//! to a conversation and are not listed.
+
+pub use spacebot_core::capabilities::{
+    AgentCapabilities, Budgets, CAPABILITIES_VERSION, Capabilities, ModelTier, RateLimits,
+    ToolCapability,
+};
+
+use crate::conversation::ChannelStore;
+use crate::conversation::history::ConversationLogger;
+use crate::{AgentDeps, ProcessType};
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+/// Describe what the agent behind `deps` can do right now.
+///
+/// `screenshot_dir` is where the agent's workers keep browser screenshots;
+/// it only matters for building the browser tool.
+pub async fn describe(deps: &AgentDeps, screenshot_dir: PathBuf) -> AgentCapabilities {
+    let runtime_config = &deps.runtime_config;
+
+    let branch_tools = crate::tools::create_branch_tool_server(
+        deps.memory_search.clone(),
+        ConversationLogger::new(deps.sqlite_pool.clone()),
+        ChannelStore::new(deps.sqlite_pool.clone()),
+    );
+    let worker_tools = crate::agent::worker::task_tool_server(
+        deps,
+        uuid::Uuid::new_v4(),
+        None,
+        (**runtime_config.browser_config.load()).clone(),
+        screenshot_dir,
+        (**runtime_config.brave_search_key.load()).clone(),
+    )
+    .await;
+
+    let mut tools: BTreeMap<String, ToolCapability> = BTreeMap::new();
+    for (process, server) in [
+        (ProcessType::Branch, branch_tools),
+        (ProcessType::Worker, worker_tools),
+    ] {
+        let definitions = match server.get_tool_defs(None).await {
+            Ok(definitions) => definitions,
+            Err(error) => {
+                tracing::warn!(%error, agent_id = %deps.agent_id, %process, "failed to list tools");
+                continue;
+            }
+        };
+        for definition in definitions {
+            tools
+                .entry(definition.name.clone())
+                .or_insert_with(|| ToolCapability {
+                    name: definition.name,
+                    description: definition.description,
+                    parameters: definition.parameters,
+                    processes: Vec::new(),
+                })
+                .processes
+                .push(process);
+        }
+    }
+
+    AgentCapabilities {
+        id: deps.agent_id.to_string(),
+        tools: tools.into_values().collect(),
+        models: ModelTier::from_routing(&runtime_config.routing.load()),
+        budgets: budgets(deps),
+    }
+}
+
+fn budgets(deps: &AgentDeps) -> Budgets {
+    let runtime_config = &deps.runtime_config;
+    let cost_gate = runtime_config.cost_gate.load();
+    let rate_limit = runtime_config.rate_limit.load();
+
+    Budgets {
+        max_turns: **runtime_config.max_turns.load(),
+        branch_max_turns: **runtime_config.branch_max_turns.load(),
+        max_concurrent_branches: **runtime_config.max_concurrent_branches.load(),
+        max_concurrent_workers: **runtime_config.max_concurrent_workers.load(),
+        context_window: **runtime_config.context_window.load(),
+        confirm_above_usd: cost_gate.enabled.then_some(cost_gate.threshold_usd),
+        rate_limit: rate_limit.enabled.then(|| RateLimits {
+            user_per_minute: rate_limit.user_per_minute,
+            user_burst: rate_limit.user_burst,
+            user_per_hour: rate_limit.user_per_hour,
+            channel_per_minute: rate_limit.channel_per_minute,
+            channel_burst: rate_limit.channel_burst,
+        }),
+    }
+}
@@ -1,13 +1,41 @@
 //! Channel: User-facing conversation process.
 
 use crate::agent::branch::Branch;
-use crate::agent::compactor::Compactor;
+use crate::agent::commands::{self, Command};
+use crate::agent::compactor::{Compactor, estimate_history_tokens};
+use crate::agent::escalation::EscalationRules;
+use crate::agent::eviction::{self, PinCommand};
+use crate::agent::fact_check::FactChecker;
+use crate::agent::link::{self, LinkCommand};
+use crate::agent::managed::{AgentCommand, AgentContext, ManagedAgents};
+use crate::agent::model_override::ModelCommand;
+use crate::agent::persona::{self, PersonaCommand};
+use crate::agent::provenance::{Provenance, ToolSources};
 use crate::agent::status::StatusBlock;
+use crate::agent::task::BackgroundTasks;
+use crate::agent::turn::{StopReason, TurnEstimate, TurnRecorder, catch_panic};
 use crate::agent::worker::Worker;
-use crate::conversation::{ChannelStore, ConversationLogger, ProcessRunLogger};
+use crate::approval::Decision;
+use crate::budget::BudgetLevel;
+use crate::config::PersonaDef;
+use crate::conversation::escalations::{EscalationStore, FLAGGED_BY_RULE};
+use crate::conversation::history::{ConversationMessage, TurnUsageTotals, logged_metadata};
+use crate::conversation::{
+    ChannelStore, ConversationLogger, LinkStore, ProcessRunLogger, ReplyAttribution,
+};
+use crate::declined::{DeclineReason, Declined};
 use crate::error::{AgentError, Result};
+use crate::flags::FlagDef;
 use crate::hooks::SpacebotHook;
-use crate::llm::SpacebotModel;
+use crate::language::Language;
+use crate::llm::latency_budget::{BudgetLimits, LatencyBudget};
+use crate::llm::sampling::SamplingConfig;
+use crate::llm::tags::RequestTags;
+use crate::llm::{Priority, SpacebotModel};
+use crate::messaging::impersonation;
+use crate::messaging::rate_limit::{self, QuotaCommand, QuotaStore, UserQuota};
+use crate::prompt_library::{self, PendingPrompt, PromptCommand};
+use crate::prompts::RetrievedChunk;
 use crate::{
     AgentDeps, BranchId, ChannelId, InboundMessage, OutboundResponse, ProcessEvent, ProcessId,
     ProcessType, WorkerId,
@@ -21,7 +49,7 @@ use std::collections::HashMap;
 use std::collections::HashSet;
 use std::sync::Arc;
 use tokio::sync::broadcast;
-use tokio::sync::{RwLock, mpsc};
+use tokio::sync::{Mutex, RwLock, mpsc};
 
 /// Shared state that channel tools need to act on the channel.
 ///
@@ -38,7 +66,19 @@ pub struct ChannelState {
     /// Input senders for interactive workers, keyed by worker ID.
     /// Used by the route tool to deliver follow-up messages.
     pub worker_inputs: Arc<RwLock<HashMap<WorkerId, tokio::sync::mpsc::Sender<String>>>>,
+    /// Workers started with `start_task`, whose progress and results are
+    /// posted to the conversation directly.
+    pub tasks: BackgroundTasks,
     pub status_block: Arc<RwLock<StatusBlock>>,
+    /// The channel's most recent completion, recorded by its hook so replies
+    /// can be attributed to the request that produced them.
+    pub last_completion: Arc<RwLock<Option<ReplyAttribution>>>,
+    /// Tool results from the channel's branches and workers, which replies
+    /// are credited to when provenance is on.
+    pub tool_sources: ToolSources,
+    /// Tags of the message the current turn answers, passed on to the
+    /// branches and workers it starts.
+    pub turn_tags: Arc<RwLock<RequestTags>>,
     pub deps: AgentDeps,
     pub conversation_logger: ConversationLogger,
     pub process_run_logger: ProcessRunLogger,
@@ -59,6 +99,7 @@ impl ChannelState {
             .remove(&worker_id)
             .is_some();
         self.worker_inputs.write().await.remove(&worker_id);
+        self.tasks.remove(worker_id);
 
         if let Some(handle) = handle {
             handle.abort();
@@ -82,6 +123,27 @@ impl ChannelState {
             Err(format!("Branch {branch_id} not found"))
         }
     }
+
+    /// Replace the live history with persisted messages, e.g. after switching
+    /// conversation forks. A turn already running writes its own history back
+    /// when it finishes, so this should be called between turns.
+    pub async fn replace_history(&self, messages: &[ConversationMessage]) {
+        let history = messages
+            .iter()
+            .map(|message| match message.role.as_str() {
+                "assistant" => rig::message::Message::assistant(&message.content),
+                _ => {
+                    let sender = message
+                        .sender_name
+                        .as_deref()
+                        .or(message.sender_id.as_deref())
+                        .unwrap_or("user");
+                    rig::message::Message::user(format!("[{sender}]: {}", message.content))
+                }
+            })
+            .collect();
+        *self.history.write().await = history;
+    }
 }
 
 impl std::fmt::Debug for ChannelState {
@@ -98,6 +160,10 @@ pub struct Channel {
     pub title: Option<String>,
     pub deps: AgentDeps,
     pub hook: SpacebotHook,
+    /// Collects the current turn's completions and tool calls.
+    turn: TurnRecorder,
+    /// Times the current turn against `[defaults.latency_budget]`.
+    latency_budget: Arc<LatencyBudget>,
     pub state: ChannelState,
     /// Per-channel tool server (isolated from other channels).
     pub tool_server: rig::tool::server::ToolServerHandle,
@@ -119,6 +185,28 @@ pub struct Channel {
     message_count: usize,
     /// Branch IDs for silent memory persistence branches (results not injected into history).
     memory_persistence_branches: HashSet<BranchId>,
+    /// Model set with `/model set`, answering in place of the routing default.
+    model_override: Option<String>,
+    /// Persona set with `/persona`, if any.
+    persona: Option<String>,
+    /// The language the channel's users last wrote in, when it was detected.
+    language: Option<Language>,
+    /// Sender of the message the current turn answers, as `platform:id`,
+    /// for experiment flags.
+    flag_user: Option<String>,
+    /// Sampling seed the message the current turn answers asked for.
+    turn_seed: Option<u64>,
+    /// Generation profile the message the current turn answers asked for.
+    turn_profile: Option<String>,
+    /// Operator who sent the message the current turn answers through the
+    /// API, making the turn synthetic.
+    turn_impersonated_by: Option<String>,
+    /// The last message read from each linked conversation.
+    linked_seen: Mutex<HashMap<String, i64>>,
+    /// Compiled `[defaults.escalation]` patterns.
+    escalation_rules: EscalationRules,
+    /// Prompt from the library waiting for its slots to be filled in.
+    pending_prompt: Option<PendingPrompt>,
     /// Buffer for coalescing rapid-fire messages.
     coalesce_buffer: Vec<InboundMessage>,
     /// Deadline for flushing the coalesce buffer.
@@ -140,13 +228,21 @@ impl Channel {
         logs_dir: std::path::PathBuf,
     ) -> (Self, mpsc::Sender<InboundMessage>) {
         let process_id = ProcessId::Channel(id.clone());
+        let last_completion = Arc::new(RwLock::new(None));
+        let turn = TurnRecorder::new();
+        let latency_budget = Arc::new(LatencyBudget::new());
         let hook = SpacebotHook::new(
             deps.agent_id.clone(),
             process_id,
             ProcessType::Channel,
             Some(id.clone()),
             deps.event_tx.clone(),
-        );
+        )
+        .with_events(deps.llm_manager.events().clone())
+        .with_last_completion(last_completion.clone())
+        .with_turn_recorder(turn.clone())
+        .with_loop_guard(deps.loop_guard())
+        .with_latency_budget(Some(latency_budget.clone()));
         let status_block = Arc::new(RwLock::new(StatusBlock::new()));
         let history = Arc::new(RwLock::new(Vec::new()));
         let active_branches = Arc::new(RwLock::new(HashMap::new()));
@@ -166,7 +262,11 @@ impl Channel {
             active_workers: active_workers.clone(),
             worker_handles: Arc::new(RwLock::new(HashMap::new())),
             worker_inputs: Arc::new(RwLock::new(HashMap::new())),
+            tasks: BackgroundTasks::default(),
             status_block: status_block.clone(),
+            last_completion,
+            tool_sources: ToolSources::default(),
+            turn_tags: Arc::new(RwLock::new(RequestTags::default())),
             deps: deps.clone(),
             conversation_logger,
             process_run_logger,
@@ -185,6 +285,8 @@ impl Channel {
             title: None,
             deps,
             hook,
+            turn,
+            latency_budget,
             state,
             tool_server,
             message_rx,
@@ -196,6 +298,16 @@ impl Channel {
             compactor,
             message_count: 0,
             memory_persistence_branches: HashSet::new(),
+            model_override: None,
+            persona: None,
+            language: None,
+            flag_user: None,
+            turn_seed: None,
+            turn_profile: None,
+            turn_impersonated_by: None,
+            linked_seen: Mutex::new(HashMap::new()),
+            escalation_rules: EscalationRules::default(),
+            pending_prompt: None,
             coalesce_buffer: Vec::new(),
             coalesce_deadline: None,
         };
@@ -207,6 +319,21 @@ impl Channel {
     pub async fn run(mut self) -> Result<()> {
         tracing::info!(channel_id = %self.id, "channel started");
 
+        // A sandbox copy of a conversation starts with its settings.
+        let settings_id = impersonation::real_conversation_id(&self.id);
+        match self.state.channel_store.model_override(settings_id).await {
+            Ok(model_override) => self.model_override = model_override,
+            Err(error) => {
+                tracing::warn!(%error, channel_id = %self.id, "failed to load model override");
+            }
+        }
+        match self.state.channel_store.persona(settings_id).await {
+            Ok(persona) => self.persona = persona,
+            Err(error) => {
+                tracing::warn!(%error, channel_id = %self.id, "failed to load persona");
+            }
+        }
+
         loop {
             // Compute sleep duration based on coalesce deadline
             let sleep_duration = self
@@ -223,6 +350,64 @@ impl Channel {
 
             tokio::select! {
                 Some(message) = self.message_rx.recv() => {
+                    let commands = self.deps.runtime_config.commands.load_full();
+                    if let Some(command) = Command::from_message(&message, &commands) {
+                        if let Err(error) = self.handle_command(&message, command).await {
+                            tracing::error!(%error, channel_id = %self.id, "error handling command");
+                        }
+                        continue;
+                    }
+                    if let Some(command) = ModelCommand::from_message(&message) {
+                        if let Err(error) = self.handle_model_command(&message, command).await {
+                            tracing::error!(%error, channel_id = %self.id, "error handling model command");
+                        }
+                        continue;
+                    }
+                    if let Some(command) = PersonaCommand::from_message(&message) {
+                        if let Err(error) = self.handle_persona_command(&message, command).await {
+                            tracing::error!(%error, channel_id = %self.id, "error handling persona command");
+                        }
+                        continue;
+                    }
+                    if let Some(command) = PinCommand::from_message(&message) {
+                        if let Err(error) = self.handle_pin_command(&message, command).await {
+                            tracing::error!(%error, channel_id = %self.id, "error handling pin command");
+                        }
+                        continue;
+                    }
+                    if let Some(command) = LinkCommand::from_message(&message) {
+                        if let Err(error) = self.handle_link_command(&message, command).await {
+                            tracing::error!(%error, channel_id = %self.id, "error handling link command");
+                        }
+                        continue;
+                    }
+                    if let Some(command) = PromptCommand::from_message(&message) {
+                        if let Err(error) = self.handle_prompt_command(&message, command).await {
+                            tracing::error!(%error, channel_id = %self.id, "error handling prompt command");
+                        }
+                        continue;
+                    }
+                    if let Some(command) = AgentCommand::from_message(&message) {
+                        if let Err(error) = self.handle_agent_command(&message, command).await {
+                            tracing::error!(%error, channel_id = %self.id, "error handling agent command");
+                        }
+                        continue;
+                    }
+                    match self.hold_for_moderator(&message).await {
+                        Ok(true) => continue,
+                        Ok(false) => {}
+                        Err(error) => {
+                            tracing::error!(%error, channel_id = %self.id, "error checking escalation");
+                        }
+                    }
+                    let message = match self.fill_pending_prompt(message).await {
+                        Ok(Some(message)) => message,
+                        Ok(None) => continue,
+                        Err(error) => {
+                            tracing::error!(%error, channel_id = %self.id, "error filling in prompt");
+                            continue;
+                        }
+                    };
                     let config = self.deps.runtime_config.coalesce.load();
                     if self.should_coalesce(&message, &config) {
                         self.coalesce_buffer.push(message);
@@ -365,6 +550,16 @@ impl Channel {
     /// individually to conversation history, then presents them as one user turn
     /// with a coalesce hint telling the LLM this is a fast-moving conversation.
     async fn handle_message_batch(&mut self, messages: Vec<InboundMessage>) -> Result<()> {
+        match catch_panic(self.process_message_batch(messages)).await {
+            Ok(result) => result,
+            Err(panic) => {
+                self.recover_from_panic(panic).await;
+                Ok(())
+            }
+        }
+    }
+
+    async fn process_message_batch(&mut self, messages: Vec<InboundMessage>) -> Result<()> {
         let message_count = messages.len();
         let first_timestamp = messages
             .first()
@@ -418,7 +613,16 @@ impl Channel {
 
         // Persist each message to conversation log (individual audit trail)
         let mut user_contents: Vec<UserContent> = Vec::new();
+        let mut user_texts: Vec<String> = Vec::new();
         let mut conversation_id = String::new();
+        self.turn_seed = messages.iter().rev().find_map(requested_seed);
+        *self.state.turn_tags.write().await = messages
+            .iter()
+            .rev()
+            .find_map(requested_tags)
+            .unwrap_or_default();
+        self.turn_impersonated_by = messages.iter().find_map(impersonation::impersonated_by);
+        self.turn_profile = None;
 
         for message in &messages {
             if message.source != "system" {
@@ -434,19 +638,28 @@ impl Channel {
                         (text.clone().unwrap_or_default(), attachments.clone())
                     }
                 };
+                // The last `/profile` prefix in the batch picks the profile.
+                let raw_text = match requested_profile(&raw_text) {
+                    Some((name, text)) => {
+                        self.turn_profile = Some(name.to_string());
+                        text.to_string()
+                    }
+                    None => raw_text,
+                };
 
                 self.state.conversation_logger.log_user_message(
                     &self.state.channel_id,
                     sender_name,
                     &message.sender_id,
                     &raw_text,
-                    &message.metadata,
+                    &logged_metadata(message),
                 );
                 self.state
                     .channel_store
                     .upsert(&message.conversation_id, &message.metadata);
 
                 conversation_id = message.conversation_id.clone();
+                self.flag_user = Some(format!("{}:{}", message.source, message.sender_id));
 
                 // Format with relative timestamp
                 let relative_secs = message
@@ -469,6 +682,7 @@ impl Channel {
 
                 let formatted_text =
                     format!("[{}] ({}): {}", display_name, relative_text, raw_text);
+                user_texts.push(raw_text);
 
                 // Download attachments for this message
                 if !attachments.is_empty() {
@@ -482,6 +696,8 @@ impl Channel {
             }
         }
 
+        self.detect_language(&user_texts.join("\n"));
+
         // Combine all user content into a single text
         let combined_text = format!(
             "[{} messages arrived rapidly in this channel]\n\n{}",
@@ -502,7 +718,7 @@ impl Channel {
             .await;
 
         // Run agent turn
-        let (result, skip_flag) = self
+        let (result, skip_flag, declined) = self
             .run_agent_turn(
                 &combined_text,
                 &system_prompt,
@@ -511,7 +727,7 @@ impl Channel {
             )
             .await?;
 
-        self.handle_agent_result(result, &skip_flag).await;
+        self.handle_agent_result(result, &skip_flag, declined).await;
 
         // Check compaction
         if let Err(error) = self.compactor.check_and_compact().await {
@@ -525,6 +741,25 @@ impl Channel {
         Ok(())
     }
 
+    /// Remember the language of the users' latest messages. Messages too
+    /// short to tell keep the language detected before.
+    fn detect_language(&mut self, text: &str) {
+        if let Some(language) = crate::language::detect(text) {
+            self.language = Some(language);
+        }
+    }
+
+    /// The language the model is asked to reply in: the one configured for
+    /// the channel, else the one its users last wrote in.
+    fn reply_language(&self) -> Option<String> {
+        let config = self.deps.runtime_config.language.load();
+        match config.reply_language_for(&self.id) {
+            Some(language) => Some(language.to_string()),
+            None if config.detect => self.language.map(|language| language.name.to_string()),
+            None => None,
+        }
+    }
+
     /// Build system prompt with coalesce hint for batched messages.
     async fn build_system_prompt_with_coalesce(
         &self,
@@ -541,10 +776,25 @@ impl Channel {
         let skills_prompt = skills.render_channel_prompt(&prompt_engine);
 
         let browser_enabled = rc.browser_config.load().enabled;
+        let computer_use_enabled = rc.computer_use.load().available();
+        let ocr_enabled = rc.ocr.load().enabled;
+        let home_assistant_enabled = rc.home_assistant.load().enabled;
+        let issues_enabled = {
+            let issues = rc.issues.load();
+            issues.enabled && !issues.workspaces.is_empty()
+        };
         let web_search_enabled = rc.brave_search_key.load().is_some();
         let opencode_enabled = rc.opencode.load().enabled;
         let worker_capabilities = prompt_engine
-            .render_worker_capabilities(browser_enabled, web_search_enabled, opencode_enabled)
+            .render_worker_capabilities(
+                browser_enabled,
+                computer_use_enabled,
+                ocr_enabled,
+                home_assistant_enabled,
+                issues_enabled,
+                web_search_enabled,
+                opencode_enabled,
+            )
             .expect("failed to render worker capabilities");
 
         let status_text = {
@@ -558,17 +808,36 @@ impl Channel {
             .render_coalesce_hint(message_count, &elapsed_str, unique_senders)
             .ok();
 
+        let reply_language = self
+            .reply_language()
+            .and_then(|language| prompt_engine.render_reply_language(&language).ok());
+        let persona = self.active_persona().and_then(|persona| {
+            prompt_engine
+                .render_persona(&persona.name, &persona.preamble)
+                .ok()
+        });
+        let flags = self.active_flags();
+        let flag_names = flags.iter().map(|flag| flag.name.clone()).collect();
+        let experiments = flags
+            .iter()
+            .filter_map(|flag| flag.preamble.clone())
+            .collect();
+
         let empty_to_none = |s: String| if s.is_empty() { None } else { Some(s) };
 
         prompt_engine
             .render_channel_prompt(
                 empty_to_none(identity_context),
+                persona,
                 empty_to_none(memory_bulletin.to_string()),
                 empty_to_none(skills_prompt),
                 worker_capabilities,
                 self.conversation_context.clone(),
                 empty_to_none(status_text),
                 coalesce_hint,
+                reply_language,
+                flag_names,
+                experiments,
             )
             .expect("failed to render channel prompt")
     }
@@ -578,7 +847,20 @@ impl Channel {
     /// The LLM decides which tools to call: reply (to respond), branch (to think),
     /// spawn_worker (to delegate), route (to follow up with a worker), cancel, or
     /// memory_save. The tools act on the channel's shared state directly.
+    ///
+    /// The turn is supervised: a panic inside it becomes a failed turn and
+    /// the channel keeps running.
     async fn handle_message(&mut self, message: InboundMessage) -> Result<()> {
+        match catch_panic(self.process_message(message)).await {
+            Ok(result) => result,
+            Err(panic) => {
+                self.recover_from_panic(panic).await;
+                Ok(())
+            }
+        }
+    }
+
+    async fn process_message(&mut self, message: InboundMessage) -> Result<()> {
         tracing::info!(
             channel_id = %self.id,
             message_id = %message.id,
@@ -597,7 +879,41 @@ impl Channel {
             }
         };
 
+        // A `/profile <name>` prefix runs this turn with another generation
+        // profile. The rest of the message is what gets answered.
+        let (turn_profile, raw_text) = match requested_profile(&raw_text) {
+            Some((name, text)) => (Some(name.to_string()), text.to_string()),
+            None => (None, raw_text),
+        };
+        if let Some(name) = &turn_profile {
+            let generation = self.deps.runtime_config.generation.load();
+            let reply = if generation.resolve(name).is_none() {
+                Some(format!(
+                    "There's no generation profile `{name}`. Available: {}.",
+                    generation.names().join(", ")
+                ))
+            } else if raw_text.is_empty() && attachments.is_empty() {
+                Some("Usage: `/profile <name> <message>`.".to_string())
+            } else {
+                None
+            };
+            if let Some(reply) = reply {
+                self.response_tx
+                    .send(OutboundResponse::Text(reply))
+                    .await
+                    .map_err(|error| anyhow::anyhow!("failed to send profile reply: {error}"))?;
+                return Ok(());
+            }
+        }
+
         let user_text = format_user_message(&raw_text, &message);
+        self.turn_seed = requested_seed(&message);
+        *self.state.turn_tags.write().await = requested_tags(&message).unwrap_or_default();
+        self.turn_profile = turn_profile;
+        self.turn_impersonated_by = impersonation::impersonated_by(&message);
+        if message.source != "system" {
+            self.detect_language(&raw_text);
+        }
 
         let attachment_content = if !attachments.is_empty() {
             download_attachments(&self.deps, &attachments).await
@@ -617,15 +933,17 @@ impl Channel {
                 sender_name,
                 &message.sender_id,
                 &raw_text,
-                &message.metadata,
+                &logged_metadata(&message),
             );
             self.state
                 .channel_store
                 .upsert(&message.conversation_id, &message.metadata);
+            self.flag_user = Some(format!("{}:{}", message.source, message.sender_id));
         }
 
-        // Capture conversation context from the first message (platform, channel, server)
-        if self.conversation_context.is_none() {
+        // Capture conversation context from the first message (platform, channel, server).
+        // A handed-off conversation starts with a system message, which has neither.
+        if self.conversation_context.is_none() && message.source != "system" {
             let prompt_engine = self.deps.runtime_config.prompts.load();
             let server_name = message
                 .metadata
@@ -644,7 +962,7 @@ impl Channel {
 
         let system_prompt = self.build_system_prompt().await;
 
-        let (result, skip_flag) = self
+        let (result, skip_flag, declined) = self
             .run_agent_turn(
                 &user_text,
                 &system_prompt,
@@ -653,7 +971,7 @@ impl Channel {
             )
             .await?;
 
-        self.handle_agent_result(result, &skip_flag).await;
+        self.handle_agent_result(result, &skip_flag, declined).await;
 
         // Check context size and trigger compaction if needed
         if let Err(error) = self.compactor.check_and_compact().await {
@@ -680,10 +998,25 @@ impl Channel {
         let skills_prompt = skills.render_channel_prompt(&prompt_engine);
 
         let browser_enabled = rc.browser_config.load().enabled;
+        let computer_use_enabled = rc.computer_use.load().available();
+        let ocr_enabled = rc.ocr.load().enabled;
+        let home_assistant_enabled = rc.home_assistant.load().enabled;
+        let issues_enabled = {
+            let issues = rc.issues.load();
+            issues.enabled && !issues.workspaces.is_empty()
+        };
         let web_search_enabled = rc.brave_search_key.load().is_some();
         let opencode_enabled = rc.opencode.load().enabled;
         let worker_capabilities = prompt_engine
-            .render_worker_capabilities(browser_enabled, web_search_enabled, opencode_enabled)
+            .render_worker_capabilities(
+                browser_enabled,
+                computer_use_enabled,
+                ocr_enabled,
+                home_assistant_enabled,
+                issues_enabled,
+                web_search_enabled,
+                opencode_enabled,
+            )
             .expect("failed to render worker capabilities");
 
         let status_text = {
@@ -691,24 +1024,44 @@ impl Channel {
             status.render()
         };
 
+        let reply_language = self
+            .reply_language()
+            .and_then(|language| prompt_engine.render_reply_language(&language).ok());
+        let persona = self.active_persona().and_then(|persona| {
+            prompt_engine
+                .render_persona(&persona.name, &persona.preamble)
+                .ok()
+        });
+        let flags = self.active_flags();
+        let flag_names = flags.iter().map(|flag| flag.name.clone()).collect();
+        let experiments = flags
+            .iter()
+            .filter_map(|flag| flag.preamble.clone())
+            .collect();
+
         let empty_to_none = |s: String| if s.is_empty() { None } else { Some(s) };
 
         prompt_engine
             .render_channel_prompt(
                 empty_to_none(identity_context),
+                persona,
                 empty_to_none(memory_bulletin.to_string()),
                 empty_to_none(skills_prompt),
                 worker_capabilities,
                 self.conversation_context.clone(),
                 empty_to_none(status_text),
                 None, // coalesce_hint - only set for batched messages
+                reply_language,
+                flag_names,
+                experiments,
             )
             .expect("failed to render channel prompt")
     }
 
     /// Register per-turn tools, run the LLM agentic loop, and clean up.
     ///
-    /// Returns the prompt result and skip flag for the caller to dispatch.
+    /// Returns the prompt result, skip flag, and the decline that stopped
+    /// the turn before the model ran, if one did, for the caller to dispatch.
     async fn run_agent_turn(
         &self,
         user_text: &str,
@@ -718,6 +1071,7 @@ impl Channel {
     ) -> Result<(
         std::result::Result<String, rig::completion::PromptError>,
         crate::tools::SkipFlag,
+        Option<Declined>,
     )> {
         let skip_flag = crate::tools::new_skip_flag();
 
@@ -738,12 +1092,137 @@ impl Channel {
         let rc = &self.deps.runtime_config;
         let routing = rc.routing.load();
         let max_turns = **rc.max_turns.load();
-        let model_name = routing.resolve(ProcessType::Channel, None);
-        let model = SpacebotModel::make(&self.deps.llm_manager, model_name)
-            .with_routing((**routing).clone());
+        let budget = rc.budget.load_full();
+        let budget_level = self.deps.budget.level(&budget);
+        // Past its threshold the budget's cheaper model wins over everything.
+        // Otherwise a `/model` override wins over the persona's model, which
+        // wins over an experiment flag's.
+        let budget_model = (budget_level >= BudgetLevel::CheaperModel)
+            .then(|| persona::resolve_model(&budget.cheaper_model, &routing))
+            .flatten();
+        let persona = self.active_persona();
+        let flags = self.active_flags();
+        let persona_model = persona
+            .as_ref()
+            .and_then(|persona| persona::model(persona, &routing));
+        let flag_model = flags.iter().find_map(|flag| {
+            flag.model
+                .as_deref()
+                .and_then(|model| persona::resolve_model(model, &routing))
+        });
+        let routing = match budget_model
+            .as_ref()
+            .or(self.model_override.as_ref())
+            .or(persona_model.as_ref())
+            .or(flag_model.as_ref())
+        {
+            Some(model_name) => routing.with_channel_model(model_name),
+            None => (**routing).clone(),
+        };
+        // Flags with a tool list narrow the persona's tools further.
+        let allowed_tools = flags
+            .iter()
+            .filter_map(|flag| flag.tools.as_deref())
+            .map(persona::tool_allowlist)
+            .fold(
+                persona.as_ref().and_then(persona::allowed_tools),
+                |allowed, tools| match allowed {
+                    Some(allowed) => Some(allowed.intersection(&tools).cloned().collect()),
+                    None => Some(tools),
+                },
+            );
+        let denied_tools = if budget_level >= BudgetLevel::RestrictedTools {
+            budget.expensive_tools.iter().cloned().collect()
+        } else {
+            HashSet::new()
+        };
+        let model_name = routing.resolve(ProcessType::Channel, None).to_string();
+        // Cron jobs run through a channel too, but nobody is waiting on them.
+        let is_cron = self.id.starts_with("cron:");
+        let priority = if is_cron {
+            Priority::Background
+        } else {
+            Priority::for_process(ProcessType::Channel)
+        };
+        let latency = rc.latency_budget.load();
+        let latency_limits = (latency.enabled && !is_cron).then(|| BudgetLimits {
+            target: std::time::Duration::from_secs(latency.target_secs),
+            hurry_at: latency.hurry_at,
+            fast_model: persona::resolve_model(&latency.fast_model, &routing),
+            max_tool_calls: latency.max_tool_calls,
+        });
+        // A seed the message asked for wins over the configured one, and so
+        // does a generation profile.
+        let seed = self.turn_seed.or(**rc.seed.load());
+        let tags = self.state.turn_tags.read().await.clone();
+        let generation = rc.generation.load();
+        let profile = match self.turn_profile.as_deref() {
+            Some(name) => generation.resolve(name).or_else(|| {
+                tracing::warn!(channel_id = %self.id, profile = name, "unknown generation profile, using the agent's");
+                generation.default_profile()
+            }),
+            None => generation.default_profile(),
+        };
+        let model = SpacebotModel::make(&self.deps.llm_manager, model_name.as_str())
+            .with_routing(routing)
+            .with_priority(priority)
+            .with_seed(seed)
+            .with_tags(tags.clone())
+            .with_generation(profile)
+            .with_output_cap(self.deps.output_cap(ProcessType::Channel, &model_name))
+            .with_empty_retry(self.deps.empty_nudge())
+            .with_allowed_tools(allowed_tools)
+            .with_denied_tools(denied_tools)
+            .with_tool_filter(self.deps.tool_filter())
+            .with_compressor(self.deps.compressor())
+            .with_latency_budget(Some(self.latency_budget.clone()));
+
+        let (system_prompt, retrieval_tokens) =
+            self.with_retrieved_context(user_text, system_prompt).await;
+        let system_prompt = match &latency_limits {
+            Some(limits) => {
+                let hint = rc
+                    .prompts
+                    .load()
+                    .render_latency_budget(limits.target.as_secs())
+                    .expect("failed to render latency budget");
+                format!("{system_prompt}\n\n{hint}")
+            }
+            None => system_prompt,
+        };
+
+        self.turn.reset();
+        self.turn.record_prompt_version(self.prompt_version(&flags));
+        self.turn
+            .record_flags(flags.iter().map(|flag| flag.name.clone()).collect());
+        self.turn.record_retrieval(retrieval_tokens);
+        self.turn.record_seed(seed);
+        self.turn.record_tags(tags);
+        self.turn
+            .record_impersonation(self.turn_impersonated_by.clone());
+
+        // Over budget, the canned reply goes out instead of a model call and
+        // the turn ends declined. So does a turn the user declines to pay for.
+        let declined = if budget_level == BudgetLevel::OverBudget {
+            Some(
+                Declined::new(DeclineReason::OverBudget, "daily budget spent")
+                    .with_message(budget.over_budget_reply.clone()),
+            )
+        } else {
+            self.confirm_turn_cost(&model_name, &system_prompt, user_text)
+                .await
+        };
+        if let Some(declined) = declined {
+            self.send_decline(&declined).await;
+            if let Err(error) = crate::tools::remove_channel_tools(&self.tool_server).await {
+                tracing::warn!(%error, "failed to remove channel tools");
+            }
+            skip_flag.store(true, std::sync::atomic::Ordering::Relaxed);
+            return Ok((Ok(String::new()), skip_flag, Some(declined)));
+        }
 
         let agent = AgentBuilder::new(model)
-            .preamble(system_prompt)
+            .preamble(&system_prompt)
             .default_max_turns(max_turns)
             .tool_server_handle(self.tool_server.clone())
             .build();
@@ -753,6 +1232,9 @@ impl Channel {
             .send(OutboundResponse::Status(crate::StatusUpdate::Thinking))
             .await;
 
+        self.sync_pins().await;
+        self.sync_linked().await;
+
         // Inject attachments as a user message before the text prompt
         if !attachment_content.is_empty() {
             let mut history = self.state.history.write().await;
@@ -771,11 +1253,25 @@ impl Channel {
             guard.clone()
         };
 
+        self.hook.reset_loop_guard();
+        let target = latency_limits.as_ref().map(|limits| limits.target);
+        self.latency_budget.restart(latency_limits);
         let result = agent
             .prompt(user_text)
             .with_history(&mut history)
             .with_hook(self.hook.clone())
             .await;
+        if let (Some(target), Some(elapsed)) = (target, self.latency_budget.elapsed()) {
+            if elapsed > target {
+                tracing::info!(
+                    channel_id = %self.id,
+                    elapsed_ms = elapsed.as_millis() as u64,
+                    target_ms = target.as_millis() as u64,
+                    "turn went over its latency budget"
+                );
+            }
+        }
+        self.latency_budget.restart(None);
 
         // Write history back after the agentic loop completes
         {
@@ -787,7 +1283,979 @@ impl Channel {
             tracing::warn!(%error, "failed to remove channel tools");
         }
 
-        Ok((result, skip_flag))
+        Ok((result, skip_flag, None))
+    }
+
+    /// Estimate the turn's cost and, when the cost gate is on and the estimate
+    /// is over its threshold, ask the user to confirm it. Returns the decline
+    /// when the turn shouldn't run. Turns on models without a price always
+    /// run.
+    async fn confirm_turn_cost(
+        &self,
+        model_name: &str,
+        system_prompt: &str,
+        user_text: &str,
+    ) -> Option<Declined> {
+        let config = **self.deps.runtime_config.cost_gate.load();
+        // Nobody is around to confirm a cron job's turn.
+        if !config.enabled || self.id.starts_with("cron:") {
+            return None;
+        }
+
+        let history_tokens = estimate_history_tokens(&self.state.history.read().await);
+        let prompt_tokens = ((system_prompt.len() + user_text.len()) / 4 + history_tokens) as u64;
+        let price = |input, output| self.deps.llm_manager.cost_usd(model_name, input, output);
+        let Some(estimate) = TurnEstimate::new(model_name, prompt_tokens, &config, price) else {
+            return None;
+        };
+        self.turn.record_estimate(estimate.cost_usd);
+        if estimate.cost_usd <= config.threshold_usd {
+            return None;
+        }
+
+        let approvals = &self.deps.approvals;
+        let (action_id, decision) = approvals.request(&self.id, "turn");
+        let prompt = format!(
+            "{}\n\nReply `/confirm {action_id}` to go ahead or `/reject {action_id}` to drop it.",
+            estimate.preview()
+        );
+        if let Err(error) = self.response_tx.send(OutboundResponse::Text(prompt)).await {
+            tracing::warn!(%error, channel_id = %self.id, "failed to post turn cost estimate");
+            approvals.forget(&action_id);
+            return Some(Declined::new(
+                DeclineReason::CostNotConfirmed,
+                "couldn't ask for confirmation",
+            ));
+        }
+
+        let timeout = std::time::Duration::from_secs(config.timeout_secs);
+        let decision = tokio::time::timeout(timeout, decision).await;
+        tracing::info!(
+            channel_id = %self.id,
+            %action_id,
+            estimated_usd = estimate.cost_usd,
+            ?decision,
+            "turn cost decided"
+        );
+        let declined = match decision {
+            Ok(Ok(Decision::Approved)) => return None,
+            Ok(Ok(Decision::Rejected)) => Declined::new(
+                DeclineReason::CostNotConfirmed,
+                format!("estimate of ${:.2} rejected", estimate.cost_usd),
+            )
+            .with_message("Okay, I'll leave that one."),
+            Ok(Err(_)) | Err(_) => {
+                approvals.forget(&action_id);
+                Declined::new(
+                    DeclineReason::CostNotConfirmed,
+                    format!(
+                        "estimate of ${:.2} not confirmed in time",
+                        estimate.cost_usd
+                    ),
+                )
+                .with_message("That wasn't confirmed in time, so I've dropped it.")
+            }
+        };
+        Some(declined)
+    }
+
+    /// Append the passages retrieved for `query` to the system prompt, when
+    /// the agent has retrieval configured. Returns the prompt and the
+    /// estimated tokens added. A failed retrieval only costs the turn its
+    /// context.
+    async fn with_retrieved_context(&self, query: &str, system_prompt: &str) -> (String, u64) {
+        let retrieval = self.deps.runtime_config.retrieval.load();
+        let Some(retrieval) = retrieval.as_ref() else {
+            return (system_prompt.to_string(), 0);
+        };
+
+        let results = match self
+            .deps
+            .memory_search
+            .retrieve(
+                query,
+                retrieval.source(),
+                retrieval.top_k,
+                retrieval.min_score,
+            )
+            .await
+        {
+            Ok(results) if !results.is_empty() => results,
+            Ok(_) => return (system_prompt.to_string(), 0),
+            Err(error) => {
+                tracing::warn!(channel_id = %self.id, %error, "retrieval failed");
+                return (system_prompt.to_string(), 0);
+            }
+        };
+
+        let chunks = results
+            .into_iter()
+            .map(|result| RetrievedChunk {
+                memory_id: result.memory.id,
+                content: result.memory.content,
+                score: (result.score * 100.0).round() / 100.0,
+            })
+            .collect::<Vec<_>>();
+        let passages = chunks.len();
+        let context = self
+            .deps
+            .runtime_config
+            .prompts
+            .load()
+            .render_retrieved_context(&retrieval.store, chunks)
+            .expect("failed to render retrieved context");
+
+        // ~4 chars per token, the same estimate the compactor uses.
+        let tokens = (context.len() / 4) as u64;
+        tracing::debug!(
+            channel_id = %self.id,
+            store = %retrieval.store,
+            passages,
+            tokens,
+            "retrieved context for turn"
+        );
+        (format!("{system_prompt}\n\n{context}"), tokens)
+    }
+
+    /// Answer a `!` command without a model call. Model and pin commands are
+    /// handled the same as their `/` forms.
+    async fn handle_command(&mut self, message: &InboundMessage, command: Command) -> Result<()> {
+        let config = self.deps.runtime_config.commands.load_full();
+        let is_admin = self.deps.is_admin(message);
+        let reply = if command.needs_admin(&config) && !is_admin {
+            let name = command.spec().map_or("", |spec| spec.name);
+            format!("Only admins can run `{}{name}`.", commands::PREFIX)
+        } else {
+            match command {
+                Command::Model(command) => {
+                    return self.handle_model_command(message, command).await;
+                }
+                Command::Persona(command) => {
+                    return self.handle_persona_command(message, command).await;
+                }
+                Command::Pin(command) => return self.handle_pin_command(message, command).await,
+                Command::Link(command) => {
+                    return self.handle_link_command(message, command).await;
+                }
+                Command::Prompt(command) => {
+                    return self.handle_prompt_command(message, command).await;
+                }
+                Command::Agent(command) => {
+                    return self.handle_agent_command(message, command).await;
+                }
+                Command::Help(topic) => commands::help(topic.as_deref(), &config, is_admin),
+                Command::Usage => self.usage_report().await?,
+                Command::Quota(command) => self.quota_reply(message, command, is_admin).await?,
+                Command::New => {
+                    self.state.history.write().await.clear();
+                    self.message_count = 0;
+                    self.sync_pins().await;
+                    tracing::info!(channel_id = %self.id, cleared_by = %message.sender_id, "context cleared");
+                    "Starting fresh: earlier messages are out of context. Pins are kept."
+                        .to_string()
+                }
+                Command::TaskList => {
+                    let tasks = self.state.tasks.list(std::time::Instant::now());
+                    if tasks.is_empty() {
+                        "No background tasks are running.".to_string()
+                    } else {
+                        tasks.join("\n")
+                    }
+                }
+                Command::Escalations => self.escalations_report().await?,
+                Command::Resume(target) => self.resume_conversation(message, target).await?,
+                Command::Invalid(spec) => format!("Usage: {}", commands::usage_line(spec)),
+                Command::Unknown(name) => format!(
+                    "There's no command `{prefix}{name}`. See `{prefix}help`.",
+                    prefix = commands::PREFIX
+                ),
+            }
+        };
+
+        self.response_tx
+            .send(OutboundResponse::Text(reply))
+            .await
+            .map_err(|error| anyhow::anyhow!("failed to send command reply: {error}"))?;
+        Ok(())
+    }
+
+    /// What this channel's turns have used, for `!usage`.
+    async fn usage_report(&self) -> Result<String> {
+        let logger = &self.state.process_run_logger;
+        let day = logger.turn_usage(&self.id, Some(24)).await?;
+        let total = logger.turn_usage(&self.id, None).await?;
+        let line = |label: &str, usage: TurnUsageTotals| {
+            let cost = usage
+                .cost_usd
+                .map(|cost| format!(", ${cost:.4}"))
+                .unwrap_or_default();
+            let split = usage
+                .shares()
+                .map(|shares| {
+                    let shares: Vec<String> = shares
+                        .iter()
+                        .map(|(part, percent)| format!("{part} {percent}%"))
+                        .collect();
+                    format!("\n  tokens by part: {}", shares.join(", "))
+                })
+                .unwrap_or_default();
+            format!(
+                "{label}: {} turns, {} tokens in / {} out{cost}{split}",
+                usage.turns, usage.input_tokens, usage.output_tokens
+            )
+        };
+        Ok(format!(
+            "Usage in this conversation:\n{}\n{}",
+            line("Last 24 hours", day),
+            line("All time", total)
+        ))
+    }
+
+    /// Answer a `!quota` command. Overrides are stored before they're applied,
+    /// so they survive restarts.
+    async fn quota_reply(
+        &self,
+        message: &InboundMessage,
+        command: QuotaCommand,
+        is_admin: bool,
+    ) -> Result<String> {
+        let config = self.deps.runtime_config.rate_limit.load();
+        let limiter = &self.deps.rate_limiter;
+        let store = QuotaStore::new(self.deps.sqlite_pool.clone());
+        let key = |user: &str| rate_limit::user_key(&message.source, user);
+
+        let reply = match command {
+            QuotaCommand::Show(user) => {
+                if !config.enabled {
+                    return Ok("Rate limiting is off, so there's no quota to run out of.".into());
+                }
+                let own = user.is_none();
+                if own && is_admin {
+                    return Ok("Admins aren't rate limited.".into());
+                }
+                let user = match user {
+                    Some(user) => key(&user),
+                    None => format!("{}:{}", message.source, message.sender_id),
+                };
+                let status = limiter.status(&config, &user);
+                let limits = status.limits;
+                let minute = match status.minute_left {
+                    Some(left) => format!(
+                        "{left} of {} left, refilling {} a minute",
+                        limits.burst.unwrap_or_default().max(1),
+                        limits.per_minute.unwrap_or_default()
+                    ),
+                    None => "no limit".to_string(),
+                };
+                let hour = match status.hour_left {
+                    Some(left) => {
+                        format!("{left} of {} left", limits.per_hour.unwrap_or_default())
+                    }
+                    None => "no limit".to_string(),
+                };
+                let custom = if status.custom { " (custom)" } else { "" };
+                let mut reply = format!(
+                    "Quota for `{user}`{custom}:\nMessages per minute: {minute}\nMessages this hour: {hour}"
+                );
+                // Spend isn't tracked per user, so the sender gets this
+                // conversation's.
+                if own {
+                    let day = self
+                        .state
+                        .process_run_logger
+                        .turn_usage(&self.id, Some(24))
+                        .await?;
+                    if let Some(cost) = day.cost_usd {
+                        reply.push_str(&format!(
+                            "\nSpent in this conversation in the last 24 hours: ${cost:.4}"
+                        ));
+                    }
+                }
+                reply
+            }
+            QuotaCommand::Set(user, quota) => {
+                let user = key(&user);
+                let current = limiter.quota(&user).unwrap_or_default();
+                let quota = UserQuota {
+                    per_minute: quota.per_minute.or(current.per_minute),
+                    burst: quota.burst.or(current.burst),
+                    per_hour: quota.per_hour.or(current.per_hour),
+                };
+                store.save(&user, Some(&quota), &message.sender_id).await?;
+                limiter.set_quota(&user, Some(quota));
+                tracing::info!(%user, %quota, set_by = %message.sender_id, "user quota set");
+                format!(
+                    "`{user}` now has its own quota: {quota}. Unset limits follow the defaults."
+                )
+            }
+            QuotaCommand::Reset(user) => {
+                let user = key(&user);
+                store.save(&user, None, &message.sender_id).await?;
+                limiter.set_quota(&user, None);
+                tracing::info!(%user, reset_by = %message.sender_id, "user quota reset");
+                format!("`{user}` is back on the default quota.")
+            }
+            // Parsed as `Command::Invalid` instead.
+            QuotaCommand::Invalid => unreachable!("invalid !quota commands aren't dispatched"),
+        };
+        Ok(reply)
+    }
+
+    /// Answer a `/persona` command. Anyone can switch personas, except to or
+    /// away from an admin-only one.
+    async fn handle_persona_command(
+        &mut self,
+        message: &InboundMessage,
+        command: PersonaCommand,
+    ) -> Result<()> {
+        let personas = self.deps.runtime_config.personas.load_full();
+        let is_admin = self.deps.is_admin(message);
+        let reply = match command {
+            PersonaCommand::Show => persona::describe(&personas, self.persona.as_deref(), is_admin),
+            PersonaCommand::Switch(name) => match persona::find(&personas, &name) {
+                None => {
+                    format!("There's no persona `{name}`. See `/persona` for the ones available.")
+                }
+                Some(persona) if persona.admin_only && !is_admin => {
+                    format!("Only admins can switch to the `{}` persona.", persona.name)
+                }
+                Some(persona) if self.persona.as_deref() == Some(persona.name.as_str()) => {
+                    format!(
+                        "This conversation already uses the `{}` persona.",
+                        persona.name
+                    )
+                }
+                Some(persona) => {
+                    let name = persona.name.clone();
+                    self.switch_persona(Some(name.clone()), &message.sender_id)
+                        .await?;
+                    format!(
+                        "Switched to the `{name}` persona. Earlier messages are out of context; pins are kept."
+                    )
+                }
+            },
+            PersonaCommand::Reset => match self.active_persona() {
+                None if self.persona.is_none() => {
+                    "This conversation already uses the agent's own persona.".to_string()
+                }
+                Some(persona) if persona.admin_only && !is_admin => {
+                    format!(
+                        "Only admins can switch away from the `{}` persona.",
+                        persona.name
+                    )
+                }
+                _ => {
+                    self.switch_persona(None, &message.sender_id).await?;
+                    "Back to the agent's own persona. Earlier messages are out of context; pins are kept."
+                        .to_string()
+                }
+            },
+            PersonaCommand::Invalid => {
+                "Usage: `/persona`, `/persona <name>`, or `/persona reset`.".to_string()
+            }
+        };
+
+        self.response_tx
+            .send(OutboundResponse::Text(reply))
+            .await
+            .map_err(|error| anyhow::anyhow!("failed to send persona reply: {error}"))?;
+        Ok(())
+    }
+
+    /// Store the conversation's new persona and start its context over,
+    /// keeping pins, behind a marker saying the persona changed.
+    async fn switch_persona(&mut self, persona: Option<String>, switched_by: &str) -> Result<()> {
+        self.state
+            .channel_store
+            .set_persona(&self.id, persona.as_deref())
+            .await?;
+        let marker = self
+            .deps
+            .runtime_config
+            .prompts
+            .load()
+            .render_system_persona_switch(persona.as_deref())
+            .expect("failed to render persona switch marker");
+        {
+            let mut history = self.state.history.write().await;
+            history.clear();
+            history.push(rig::message::Message::from(marker));
+        }
+        self.message_count = 0;
+        self.sync_pins().await;
+        tracing::info!(channel_id = %self.id, ?persona, %switched_by, "persona switched");
+        self.persona = persona;
+        Ok(())
+    }
+
+    /// The configured persona this conversation uses. None when it has none,
+    /// or its persona was removed from config.
+    fn active_persona(&self) -> Option<PersonaDef> {
+        let name = self.persona.as_deref()?;
+        let personas = self.deps.runtime_config.personas.load();
+        let mut persona = persona::find(&personas, name).cloned()?;
+        persona.preamble = self
+            .deps
+            .runtime_config
+            .prompt_library
+            .load()
+            .expand(&persona.preamble);
+        Some(persona)
+    }
+
+    /// Experiment flags on for the sender the current turn answers.
+    fn active_flags(&self) -> Vec<FlagDef> {
+        let Some(user) = self.flag_user.as_deref() else {
+            return Vec::new();
+        };
+        let flags = self.deps.runtime_config.flags.load();
+        flags.active(user).into_iter().cloned().collect()
+    }
+
+    /// Version of the prompt this channel's turns currently run with, with
+    /// the experiment flags in `flags` on.
+    fn prompt_version(&self, flags: &[FlagDef]) -> String {
+        let identity_context = self.deps.runtime_config.identity.load().render();
+        let persona = self.active_persona();
+        let preambles = persona
+            .as_ref()
+            .map(|persona| persona.preamble.as_str())
+            .into_iter()
+            .chain(flags.iter().filter_map(|flag| flag.preamble.as_deref()));
+        crate::prompts::version::channel_version(&identity_context, preambles)
+    }
+
+    /// Answer a `/model` command. Changing the model is limited to admins;
+    /// the override is persisted on the channel row.
+    async fn handle_model_command(
+        &mut self,
+        message: &InboundMessage,
+        command: ModelCommand,
+    ) -> Result<()> {
+        let reply = if command.is_privileged() && !self.deps.is_admin(message) {
+            "Only admins can change this channel's model.".to_string()
+        } else {
+            match command {
+                ModelCommand::Show => {
+                    let default = self
+                        .deps
+                        .runtime_config
+                        .routing
+                        .load()
+                        .resolve(ProcessType::Channel, None)
+                        .to_string();
+                    match &self.model_override {
+                        Some(model_name) => format!(
+                            "This channel uses `{model_name}` (overriding the default `{default}`)."
+                        ),
+                        None => format!("This channel uses the default model `{default}`."),
+                    }
+                }
+                ModelCommand::Set(model_name) => {
+                    let provider = model_name.split_once('/').map(|(provider, _)| provider);
+                    let configured = self.deps.llm_manager.configured_providers();
+                    match provider {
+                        Some(provider) if configured.contains(&provider) => {
+                            self.state
+                                .channel_store
+                                .set_model_override(&self.id, Some(&model_name))
+                                .await?;
+                            tracing::info!(channel_id = %self.id, model = %model_name, "channel model overridden");
+                            let reply = format!("This channel now uses `{model_name}`.");
+                            self.model_override = Some(model_name);
+                            reply
+                        }
+                        Some(provider) => format!(
+                            "Provider `{provider}` isn't configured. Available: {}.",
+                            configured.join(", ")
+                        ),
+                        None => {
+                            "Models are named `provider/model`, e.g. `openrouter/deepseek-chat`."
+                                .to_string()
+                        }
+                    }
+                }
+                ModelCommand::Reset => {
+                    self.state
+                        .channel_store
+                        .set_model_override(&self.id, None)
+                        .await?;
+                    self.model_override = None;
+                    tracing::info!(channel_id = %self.id, "channel model override cleared");
+                    "This channel is back on the default model.".to_string()
+                }
+                ModelCommand::Invalid => {
+                    "Usage: `/model`, `/model set provider/model`, or `/model reset`.".to_string()
+                }
+            }
+        };
+
+        self.response_tx
+            .send(OutboundResponse::Text(reply))
+            .await
+            .map_err(|error| anyhow::anyhow!("failed to send model command reply: {error}"))?;
+        Ok(())
+    }
+
+    /// Answer an `/agent` command. Only admins may run it. Changes are
+    /// written to the managed agents file; the file watcher reloads it into
+    /// every agent's personas.
+    async fn handle_agent_command(
+        &mut self,
+        message: &InboundMessage,
+        command: AgentCommand,
+    ) -> Result<()> {
+        let reply = if !self.deps.is_admin(message) {
+            "Only admins can manage agents.".to_string()
+        } else {
+            let runtime_config = &self.deps.runtime_config;
+            let path = ManagedAgents::path(&runtime_config.instance_dir);
+            let _guard = ManagedAgents::lock().await;
+            let content = match tokio::fs::read_to_string(&path).await {
+                Ok(content) => content,
+                Err(error) if error.kind() == std::io::ErrorKind::NotFound => String::new(),
+                Err(error) => return Err(error.into()),
+            };
+            match ManagedAgents::parse(&content) {
+                Ok(mut managed) => {
+                    let personas = runtime_config.personas.load();
+                    let routing = runtime_config.routing.load();
+                    let providers = self.deps.llm_manager.configured_providers();
+                    let context = AgentContext {
+                        agent_id: &self.deps.agent_id,
+                        sender_id: &message.sender_id,
+                        personas: &personas,
+                        routing: &routing,
+                        providers: &providers,
+                    };
+                    let (reply, changed) = managed.run(command, &context);
+                    if changed {
+                        managed.save(&runtime_config.instance_dir).await?;
+                        tracing::info!(channel_id = %self.id, changed_by = %message.sender_id, "managed agents updated");
+                    }
+                    reply
+                }
+                Err(error) => {
+                    tracing::warn!(%error, path = %path.display(), "managed agents file is invalid");
+                    format!(
+                        "{} doesn't parse, so it can't be changed from chat until it's fixed.",
+                        crate::agent::managed::FILE_NAME
+                    )
+                }
+            }
+        };
+
+        self.response_tx
+            .send(OutboundResponse::Text(reply))
+            .await
+            .map_err(|error| anyhow::anyhow!("failed to send agent command reply: {error}"))?;
+        Ok(())
+    }
+
+    /// Answer a `/prompt` command. `use` runs the snippet right away when
+    /// every slot has a value, and otherwise asks the sender for the rest.
+    async fn handle_prompt_command(
+        &mut self,
+        message: &InboundMessage,
+        command: PromptCommand,
+    ) -> Result<()> {
+        let library = self.deps.runtime_config.prompt_library.load_full();
+        let reply = match command {
+            PromptCommand::List => prompt_library::describe(&library),
+            PromptCommand::Show(name) => match library.get(&name) {
+                Some(snippet) => format!("`{}`:\n```\n{}\n```", snippet.name, snippet.body),
+                None => format!("There's no prompt `{name}`. See `/prompt` for the library."),
+            },
+            PromptCommand::Use { name, values } => match library.get(&name) {
+                None => format!("There's no prompt `{name}`. See `/prompt` for the library."),
+                Some(snippet) => {
+                    let pending =
+                        PendingPrompt::new(snippet.clone(), message.sender_id.clone(), values);
+                    if let Some(text) = pending.finished() {
+                        self.pending_prompt = None;
+                        tracing::info!(channel_id = %self.id, prompt = %snippet.name, "running prompt from the library");
+                        return self.handle_message(with_text(message, text)).await;
+                    }
+                    let question = pending.question().unwrap_or_default();
+                    self.pending_prompt = Some(pending);
+                    question
+                }
+            },
+            PromptCommand::Cancel => match self.pending_prompt.take() {
+                Some(pending) => format!("Stopped filling in `{}`.", pending.snippet.name),
+                None => "No prompt is waiting for values.".to_string(),
+            },
+            PromptCommand::Invalid => "Usage: `/prompt`, `/prompt show <name>`, `/prompt use <name> [slot=value ...]`, or `/prompt cancel`.".to_string(),
+        };
+
+        self.response_tx
+            .send(OutboundResponse::Text(reply))
+            .await
+            .map_err(|error| anyhow::anyhow!("failed to send prompt reply: {error}"))?;
+        Ok(())
+    }
+
+    /// Take `message` as the value for the next slot of a pending prompt, if
+    /// it's from the user who ran the prompt. Returns the message to handle:
+    /// the filled-in prompt once the last slot is answered, `message` itself
+    /// when no prompt is waiting on its sender, or None while more slots are
+    /// left.
+    async fn fill_pending_prompt(
+        &mut self,
+        message: InboundMessage,
+    ) -> Result<Option<InboundMessage>> {
+        let Some(pending) = self.pending_prompt.as_mut() else {
+            return Ok(Some(message));
+        };
+        let MessageContent::Text(answer) = &message.content else {
+            return Ok(Some(message));
+        };
+        if pending.sender_id != message.sender_id {
+            return Ok(Some(message));
+        }
+
+        pending.answer(answer);
+        if let Some(text) = pending.finished() {
+            tracing::info!(channel_id = %self.id, prompt = %pending.snippet.name, "running prompt from the library");
+            self.pending_prompt = None;
+            return Ok(Some(with_text(&message, text)));
+        }
+        let question = pending.question().unwrap_or_default();
+        self.response_tx
+            .send(OutboundResponse::Text(question))
+            .await
+            .map_err(|error| anyhow::anyhow!("failed to send prompt question: {error}"))?;
+        Ok(None)
+    }
+
+    /// Answer a pin command. Pins are stored on the channel and mirrored
+    /// into its history, where compaction and eviction carry them over.
+    async fn handle_pin_command(
+        &mut self,
+        message: &InboundMessage,
+        command: PinCommand,
+    ) -> Result<()> {
+        let store = &self.state.channel_store;
+        let reply = match command {
+            PinCommand::Show => {
+                let pins = store.pins(&self.id).await?;
+                if pins.is_empty() {
+                    "Nothing is pinned. Pin a fact with `/pin <text>`.".to_string()
+                } else {
+                    let list: Vec<String> = pins
+                        .iter()
+                        .enumerate()
+                        .map(|(index, pin)| format!("{}. {}", index + 1, pin.content))
+                        .collect();
+                    format!(
+                        "Pinned in this conversation:\n{}\n\nUnpin one with `/unpin <number>`.",
+                        list.join("\n")
+                    )
+                }
+            }
+            PinCommand::Pin(text) => {
+                store.add_pin(&self.id, &text, &message.sender_id).await?;
+                tracing::info!(channel_id = %self.id, pinned_by = %message.sender_id, "fact pinned");
+                "Pinned. It stays in context however long the conversation gets.".to_string()
+            }
+            PinCommand::Unpin(number) => {
+                let pins = store.pins(&self.id).await?;
+                match pins.get(number - 1) {
+                    Some(pin) => {
+                        store.remove_pin(&self.id, &pin.id).await?;
+                        tracing::info!(channel_id = %self.id, "fact unpinned");
+                        format!("Unpinned: {}", pin.content)
+                    }
+                    None => format!("There's no pin {number}. See `/pins`."),
+                }
+            }
+            PinCommand::Clear => {
+                let cleared = store.clear_pins(&self.id).await?;
+                tracing::info!(channel_id = %self.id, cleared, "pins cleared");
+                format!("Unpinned {cleared} fact(s).")
+            }
+            PinCommand::Invalid => {
+                "Usage: `/pin <text>`, `/pins`, `/unpin <number>`, or `/pin clear`.".to_string()
+            }
+        };
+        self.sync_pins().await;
+
+        self.response_tx
+            .send(OutboundResponse::Text(reply))
+            .await
+            .map_err(|error| anyhow::anyhow!("failed to send pin command reply: {error}"))?;
+        Ok(())
+    }
+
+    /// Bring the pinned messages in history in line with the channel's
+    /// stored pins, which the pin tool and other processes also write to.
+    async fn sync_pins(&self) {
+        match self.state.channel_store.pins(&self.id).await {
+            Ok(pins) => {
+                let pinned: Vec<String> = pins.into_iter().map(|pin| pin.content).collect();
+                let mut history = self.state.history.write().await;
+                eviction::sync_pins(&mut history, &pinned);
+            }
+            Err(error) => {
+                tracing::warn!(%error, channel_id = %self.id, "failed to load pins");
+            }
+        }
+    }
+
+    /// Answer a link command. A link is stored once for both conversations,
+    /// and each channel reads the other's new messages before its turns.
+    async fn handle_link_command(
+        &mut self,
+        message: &InboundMessage,
+        command: LinkCommand,
+    ) -> Result<()> {
+        let store = LinkStore::new(self.deps.sqlite_pool.clone());
+        let reply = match command {
+            LinkCommand::Show => {
+                let links = store.linked(&self.id).await?;
+                if links.is_empty() {
+                    "This conversation isn't linked with any other. Link one of yours with `/link <conversation>`.".to_string()
+                } else {
+                    let mut list = Vec::new();
+                    for link in &links {
+                        let name = self.conversation_name(&link.channel_id).await;
+                        list.push(format!("- {name} (`{}`)", link.channel_id));
+                    }
+                    format!(
+                        "Linked with:\n{}\n\nUnlink one with `/unlink <conversation>`.",
+                        list.join("\n")
+                    )
+                }
+            }
+            LinkCommand::Link(target) => match self.find_conversation(&target).await? {
+                Some(other) => self.link_conversation(&store, message, &other).await?,
+                None => format!("There's no conversation called `{target}`."),
+            },
+            LinkCommand::Unlink(target) => match self.find_conversation(&target).await? {
+                Some(other) => {
+                    let name = self.conversation_name(&other).await;
+                    if store.unlink(&self.id, &other).await? {
+                        self.log_link_notices(&other, &message.sender_id, false)
+                            .await;
+                        tracing::info!(channel_id = %self.id, linked = %other, unlinked_by = %message.sender_id, "conversations unlinked");
+                        format!("Unlinked {name}. Messages from there no longer carry over.")
+                    } else {
+                        format!("This conversation isn't linked with {name}.")
+                    }
+                }
+                None => format!("There's no conversation called `{target}`."),
+            },
+            LinkCommand::Invalid => {
+                "Usage: `/link <conversation>`, `/links`, or `/unlink <conversation>`.".to_string()
+            }
+        };
+
+        self.response_tx
+            .send(OutboundResponse::Text(reply))
+            .await
+            .map_err(|error| anyhow::anyhow!("failed to send link command reply: {error}"))?;
+        Ok(())
+    }
+
+    /// Link this conversation with `other`, which the sender must have
+    /// written in unless they're an admin. Returns the reply.
+    async fn link_conversation(
+        &self,
+        store: &LinkStore,
+        message: &InboundMessage,
+        other: &str,
+    ) -> Result<String> {
+        let name = self.conversation_name(other).await;
+        if !self.deps.is_admin(message) && !store.has_spoken(other, &message.sender_id).await? {
+            return Ok(format!(
+                "You can only link conversations you've written in, and {name} isn't one of them."
+            ));
+        }
+        let reply = match store.link(&self.id, other, &message.sender_id).await {
+            Ok(true) => {
+                self.log_link_notices(other, &message.sender_id, true).await;
+                tracing::info!(channel_id = %self.id, linked = %other, linked_by = %message.sender_id, "conversations linked");
+                format!(
+                    "Linked with {name}. What's said there now carries over here, and the other way round."
+                )
+            }
+            Ok(false) => format!("Already linked with {name}."),
+            Err(error) => format!("Couldn't link with {name}: {error}"),
+        };
+        Ok(reply)
+    }
+
+    /// Record a link or unlink in both conversations' transcripts.
+    async fn log_link_notices(&self, other: &str, linked_by: &str, linked: bool) {
+        let here = self.conversation_name(&self.id).await;
+        let there = self.conversation_name(other).await;
+        let (verb, preposition) = if linked {
+            ("Linked", "with")
+        } else {
+            ("Unlinked", "from")
+        };
+        let logger = &self.state.conversation_logger;
+        logger.log_link(
+            &self.id,
+            &format!("{verb} {preposition} {there}."),
+            other,
+            linked_by,
+            linked,
+        );
+        logger.log_link(
+            &Arc::from(other),
+            &format!("{verb} {preposition} {here}."),
+            &self.id,
+            linked_by,
+            linked,
+        );
+    }
+
+    /// The conversation `target` names, by ID or by channel name.
+    async fn find_conversation(&self, target: &str) -> Result<Option<String>> {
+        let store = &self.state.channel_store;
+        if let Some(channel) = store.get(target).await? {
+            return Ok(Some(channel.id));
+        }
+        Ok(store.find_by_name(target).await?.map(|channel| channel.id))
+    }
+
+    /// A conversation's display name, or its ID when it has none.
+    async fn conversation_name(&self, channel_id: &str) -> String {
+        self.state
+            .channel_store
+            .resolve_name(channel_id)
+            .await
+            .unwrap_or_else(|| channel_id.to_string())
+    }
+
+    /// Whether to hold `message` for a moderator instead of answering: the
+    /// conversation has an open escalation, or the message matches an
+    /// escalation rule and opens one. Held messages are still logged, so
+    /// moderators and the agent see them once the conversation resumes.
+    /// Sandboxed conversations are never held, so they never page anyone.
+    async fn hold_for_moderator(&mut self, message: &InboundMessage) -> Result<bool> {
+        let config = self.deps.runtime_config.escalation.load_full();
+        if !config.enabled || message.source == "system" || impersonation::is_sandboxed(&self.id) {
+            return Ok(false);
+        }
+        let text = match &message.content {
+            crate::MessageContent::Text(text) => text.clone(),
+            crate::MessageContent::Media { text, .. } => text.clone().unwrap_or_default(),
+        };
+
+        let store = EscalationStore::new(self.deps.sqlite_pool.clone());
+        let opened = if store.open(&self.id).await?.is_some() {
+            false
+        } else {
+            let Some(pattern) = self
+                .escalation_rules
+                .matching(&config, &text)
+                .map(str::to_string)
+            else {
+                return Ok(false);
+            };
+            let reason = format!("message matched escalation rule `{pattern}`");
+            tracing::info!(channel_id = %self.id, %pattern, "escalation rule matched");
+            store
+                .flag(&self.id, &reason, FLAGGED_BY_RULE)
+                .await?
+                .is_some()
+        };
+
+        let sender_name = message
+            .metadata
+            .get("sender_display_name")
+            .and_then(|v| v.as_str())
+            .unwrap_or(&message.sender_id);
+        self.state.conversation_logger.log_user_message(
+            &self.state.channel_id,
+            sender_name,
+            &message.sender_id,
+            &text,
+            &logged_metadata(message),
+        );
+        self.state
+            .channel_store
+            .upsert(&message.conversation_id, &message.metadata);
+
+        let mut declined = Declined::new(DeclineReason::Escalated, "held for a moderator");
+        if opened {
+            let reply = config.paused_reply.clone();
+            self.state.conversation_logger.log_bot_message(
+                &self.state.channel_id,
+                &reply,
+                None,
+                &[],
+            );
+            declined = declined.with_message(reply);
+        }
+        self.send_decline(&declined).await;
+        Ok(true)
+    }
+
+    /// The conversations waiting for a moderator, for `!escalations`.
+    async fn escalations_report(&self) -> Result<String> {
+        let store = EscalationStore::new(self.deps.sqlite_pool.clone());
+        let escalations = store.list_open().await?;
+        if escalations.is_empty() {
+            return Ok("No conversations are waiting for a moderator.".to_string());
+        }
+        let config = self.deps.runtime_config.escalation.load_full();
+        let mut lines = Vec::new();
+        for escalation in &escalations {
+            let name = self.conversation_name(&escalation.channel_id).await;
+            lines.push(format!(
+                "- {name} (`{}`), {}: {}\n  {}",
+                escalation.channel_id,
+                escalation.created_at.format("%Y-%m-%d %H:%M UTC"),
+                escalation.reason,
+                crate::agent::escalation::transcript_link(
+                    &config,
+                    &self.deps.agent_id,
+                    &escalation.channel_id
+                ),
+            ));
+        }
+        Ok(format!(
+            "Waiting for a moderator:\n{}\n\nResume one with `!resume <conversation>`.",
+            lines.join("\n")
+        ))
+    }
+
+    /// Resolve the escalation of the conversation `target` names, or of
+    /// this one, for `!resume`.
+    async fn resume_conversation(
+        &self,
+        message: &InboundMessage,
+        target: Option<String>,
+    ) -> Result<String> {
+        let channel_id = match target {
+            Some(target) => match self.find_conversation(&target).await? {
+                Some(channel_id) => channel_id,
+                None => return Ok(format!("There's no conversation called `{target}`.")),
+            },
+            None => self.id.to_string(),
+        };
+        let name = self.conversation_name(&channel_id).await;
+        let store = EscalationStore::new(self.deps.sqlite_pool.clone());
+        if !store.resolve(&channel_id, &message.sender_id).await? {
+            return Ok(format!("{name} isn't waiting for a moderator."));
+        }
+        tracing::info!(channel_id = %channel_id, resolved_by = %message.sender_id, "escalation resolved");
+        Ok(format!("Resumed {name}: automated replies are back on."))
+    }
+
+    /// Add what's new in the conversations linked with this one to history,
+    /// marked with where it was said.
+    async fn sync_linked(&self) {
+        let mut seen = self.linked_seen.lock().await;
+        let contexts = link::new_context(&self.deps, &self.id, &mut seen).await;
+        if contexts.is_empty() {
+            return;
+        }
+        let mut history = self.state.history.write().await;
+        history.extend(contexts.into_iter().map(rig::message::Message::from));
     }
 
     /// Dispatch the LLM result: send fallback text, log errors, clean up typing.
@@ -795,7 +2263,28 @@ impl Channel {
         &self,
         result: std::result::Result<String, rig::completion::PromptError>,
         skip_flag: &crate::tools::SkipFlag,
+        declined: Option<Declined>,
     ) {
+        let mut outcome = self.turn.finish(&result, None);
+        outcome.model_override = self.model_override.clone();
+        outcome.persona = self.persona.clone();
+        outcome.language = self.language.map(|language| language.code.to_string());
+        if let Some(estimated_usd) = outcome.estimated_cost_usd {
+            tracing::info!(
+                channel_id = %self.id,
+                estimated_usd,
+                actual_usd = ?outcome.cost_usd,
+                completions = outcome.usage.completions,
+                "turn cost against estimate"
+            );
+        }
+        if result.is_ok() && skip_flag.load(std::sync::atomic::Ordering::Relaxed) {
+            outcome.stop_reason = match &declined {
+                Some(declined) => StopReason::declined(declined),
+                None => StopReason::Skipped,
+            };
+        }
+
         match result {
             Ok(response) => {
                 let skipped = skip_flag.load(std::sync::atomic::Ordering::Relaxed);
@@ -805,15 +2294,32 @@ impl Channel {
                 } else {
                     // If the LLM returned text without using the reply tool, send it
                     // directly. Some models respond with text instead of tool calls.
-                    let text = response.trim();
+                    let mut text = response.trim().to_string();
                     if !text.is_empty() {
-                        self.state
-                            .conversation_logger
-                            .log_bot_message(&self.state.channel_id, text);
-                        if let Err(error) = self
-                            .response_tx
-                            .send(OutboundResponse::Text(text.to_string()))
-                            .await
+                        if let Some(checker) = FactChecker::for_channel(&self.deps, &self.id) {
+                            text = checker.check(&text).await;
+                        }
+                        let attribution = self.state.last_completion.read().await.clone();
+                        let mut citations = attribution
+                            .as_ref()
+                            .map(|attribution| attribution.citations.clone())
+                            .unwrap_or_default();
+                        if let Some(provenance) = Provenance::for_channel(&self.state) {
+                            citations.extend(provenance.cite(&text, &[]));
+                        }
+                        self.state.conversation_logger.log_bot_message(
+                            &self.state.channel_id,
+                            &text,
+                            attribution.as_ref(),
+                            &citations,
+                        );
+                        let text = crate::messaging::format::render_citations(
+                            &text,
+                            &citations,
+                            crate::messaging::format::Platform::from_conversation_id(&self.id),
+                        );
+                        if let Err(error) =
+                            self.response_tx.send(OutboundResponse::Text(text)).await
                         {
                             tracing::error!(%error, channel_id = %self.id, "failed to send fallback reply");
                         }
@@ -838,6 +2344,76 @@ impl Channel {
             .response_tx
             .send(OutboundResponse::Status(crate::StatusUpdate::StopTyping))
             .await;
+
+        self.state
+            .process_run_logger
+            .log_turn(&self.state.channel_id, &outcome);
+        self.deps
+            .event_tx
+            .send(ProcessEvent::TurnCompleted {
+                agent_id: self.deps.agent_id.clone(),
+                channel_id: self.id.clone(),
+                outcome,
+            })
+            .ok();
+    }
+
+    /// Tell the user a policy declined their message, when the decline has
+    /// something to say, and count it.
+    async fn send_decline(&self, declined: &Declined) {
+        tracing::info!(
+            channel_id = %self.id,
+            reason = %declined.reason,
+            detail = %declined.detail,
+            "message declined"
+        );
+        self.deps.decline_metrics.record(declined);
+        let Some(response) = declined.response() else {
+            return;
+        };
+        if let Err(error) = self.response_tx.send(response).await {
+            tracing::warn!(%error, channel_id = %self.id, reason = %declined.reason, "failed to post decline");
+        }
+    }
+
+    /// Clean up after a turn that panicked: drop the turn's tools, tell the
+    /// user, and report the turn as failed. History is only written back
+    /// when a turn finishes, so it's left as it was before the turn.
+    async fn recover_from_panic(&self, message: String) {
+        tracing::error!(channel_id = %self.id, panic = %message, "channel turn panicked");
+
+        if let Err(error) = crate::tools::remove_channel_tools(&self.tool_server).await {
+            tracing::warn!(%error, "failed to remove channel tools");
+        }
+
+        let _ = self
+            .response_tx
+            .send(OutboundResponse::Text(
+                "Something went wrong while handling that. Please try again.".to_string(),
+            ))
+            .await;
+        let _ = self
+            .response_tx
+            .send(OutboundResponse::Status(crate::StatusUpdate::StopTyping))
+            .await;
+
+        let mut outcome = self
+            .turn
+            .finish_with(String::new(), StopReason::Panicked { message });
+        outcome.model_override = self.model_override.clone();
+        outcome.persona = self.persona.clone();
+        outcome.language = self.language.map(|language| language.code.to_string());
+        self.state
+            .process_run_logger
+            .log_turn(&self.state.channel_id, &outcome);
+        self.deps
+            .event_tx
+            .send(ProcessEvent::TurnCompleted {
+                agent_id: self.deps.agent_id.clone(),
+                channel_id: self.id.clone(),
+                outcome,
+            })
+            .ok();
     }
 
     /// Handle a process event (branch results, worker completions, status updates).
@@ -920,6 +2496,17 @@ impl Channel {
                 worker_id, status, ..
             } => {
                 run_logger.log_worker_status(*worker_id, status);
+
+                let now = std::time::Instant::now();
+                if let Some(progress) = self.state.tasks.progress(*worker_id, status, now) {
+                    let posted = self
+                        .response_tx
+                        .send(OutboundResponse::Text(progress))
+                        .await;
+                    if let Err(error) = posted {
+                        tracing::warn!(%error, worker_id = %worker_id, "failed to post task progress");
+                    }
+                }
             }
             ProcessEvent::WorkerComplete {
                 worker_id,
@@ -936,7 +2523,22 @@ impl Channel {
                 self.state.worker_handles.write().await.remove(worker_id);
                 self.state.worker_inputs.write().await.remove(worker_id);
 
-                if *notify {
+                let now = std::time::Instant::now();
+                if let Some(message) = self.state.tasks.finish(*worker_id, result, now) {
+                    // Tasks report straight to the user. The channel only
+                    // learns the result, so it can talk about it if asked.
+                    self.state
+                        .conversation_logger
+                        .log_bot_message(&self.id, &message, None, &[]);
+                    let posted = self.response_tx.send(OutboundResponse::Text(message)).await;
+                    if let Err(error) = posted {
+                        tracing::warn!(%error, worker_id = %worker_id, "failed to post task result");
+                    }
+                    let mut history = self.state.history.write().await;
+                    history.push(rig::message::Message::from(format!(
+                        "[Task completed, result already posted to the user]: {result}"
+                    )));
+                } else if *notify {
                     let mut history = self.state.history.write().await;
                     let worker_message = format!("[Worker completed]: {result}");
                     history.push(rig::message::Message::from(worker_message));
@@ -945,6 +2547,39 @@ impl Channel {
 
                 tracing::info!(worker_id = %worker_id, "worker completed");
             }
+            ProcessEvent::ActionPreview {
+                action_id,
+                tool_name,
+                preview,
+                ..
+            } => {
+                let prompt = format!(
+                    "{preview}\n\nReply `/confirm {action_id}` to run this or `/reject {action_id}` to cancel it."
+                );
+                if let Err(error) = self.response_tx.send(OutboundResponse::Text(prompt)).await {
+                    tracing::warn!(%error, %tool_name, "failed to post action preview");
+                }
+            }
+            ProcessEvent::ToolContent {
+                process_id,
+                content,
+                ..
+            } => {
+                if let Err(error) = self.response_tx.send(content.clone().into_response()).await {
+                    tracing::warn!(%error, %process_id, "failed to deliver tool content");
+                }
+            }
+            ProcessEvent::ToolCompleted {
+                process_id: ProcessId::Branch(_) | ProcessId::Worker(_),
+                channel_id: Some(event_channel),
+                source: Some(source),
+                result,
+                ..
+            } if *event_channel == self.id
+                && self.deps.runtime_config.provenance.load().enabled =>
+            {
+                self.state.tool_sources.record(source, result);
+            }
             _ => {}
         }
 
@@ -1023,6 +2658,7 @@ impl Channel {
 pub async fn spawn_branch_from_state(
     state: &ChannelState,
     description: impl Into<String>,
+    sampling: Option<SamplingConfig>,
 ) -> std::result::Result<BranchId, AgentError> {
     let description = description.into();
     let rc = &state.deps.runtime_config;
@@ -1040,6 +2676,7 @@ pub async fn spawn_branch_from_state(
         &description,
         &system_prompt,
         &description,
+        sampling,
     )
     .await
 }
@@ -1067,6 +2704,7 @@ async fn spawn_memory_persistence_branch(
         &prompt,
         &system_prompt,
         "persisting memories...",
+        None,
     )
     .await
 }
@@ -1081,6 +2719,7 @@ async fn spawn_branch(
     prompt: &str,
     system_prompt: &str,
     status_label: &str,
+    sampling: Option<SamplingConfig>,
 ) -> std::result::Result<BranchId, AgentError> {
     let max_branches = **state.deps.runtime_config.max_concurrent_branches.load();
     {
@@ -1113,7 +2752,9 @@ async fn spawn_branch(
         history,
         tool_server,
         branch_max_turns,
-    );
+    )
+    .with_sampling(sampling)
+    .with_tags(state.turn_tags.read().await.clone());
 
     let branch_id = branch.id;
     let prompt = prompt.to_owned();
@@ -1126,14 +2767,18 @@ async fn spawn_branch(
         // Ensure the branch is registered in channel state before it can emit
         // terminal events (success/failure), avoiding add/remove races.
         let _ = start_rx.await;
-        if let Err(error) = branch.run(&prompt).await {
-            let error_message = error.to_string();
+        let error = match catch_panic(branch.run(&prompt)).await {
+            Ok(Ok(_)) => None,
+            Ok(Err(error)) => Some(error.to_string()),
+            Err(panic) => Some(format!("branch panicked: {panic}")),
+        };
+        if let Some(error) = error {
             event_tx
                 .send(crate::ProcessEvent::BranchFailed {
                     agent_id,
                     branch_id,
                     channel_id,
-                    error: error_message.clone(),
+                    error: error.clone(),
                 })
                 .ok();
             tracing::error!(branch_id = %branch_id, %error, "branch failed");
@@ -1243,7 +2888,8 @@ pub async fn spawn_worker_from_state(
             brave_search_key,
             state.logs_dir.clone(),
         )
-    };
+    }
+    .with_tags(state.turn_tags.read().await.clone());
 
     let worker_id = worker.id;
 
@@ -1373,7 +3019,7 @@ pub async fn spawn_opencode_worker_from_state(
 
 /// Spawn a future as a tokio task that sends a `WorkerComplete` event on completion.
 ///
-/// Handles both success and error cases, logging failures and sending the
+/// Handles success, error and panic cases, logging failures and sending the
 /// appropriate event. Used by both builtin workers and OpenCode workers.
 /// Returns the JoinHandle so the caller can store it for cancellation.
 fn spawn_worker_task<F, E>(
@@ -1388,12 +3034,16 @@ where
     E: std::fmt::Display + Send + 'static,
 {
     tokio::spawn(async move {
-        let (result_text, notify) = match future.await {
-            Ok(text) => (text, true),
-            Err(error) => {
+        let (result_text, notify) = match catch_panic(future).await {
+            Ok(Ok(text)) => (text, true),
+            Ok(Err(error)) => {
                 tracing::error!(worker_id = %worker_id, %error, "worker failed");
                 (format!("Worker failed: {error}"), true)
             }
+            Err(panic) => {
+                tracing::error!(worker_id = %worker_id, %panic, "worker panicked");
+                (format!("Worker failed: it panicked: {panic}"), true)
+            }
         };
         let _ = event_tx.send(ProcessEvent::WorkerComplete {
             agent_id,
@@ -1405,6 +3055,42 @@ where
     })
 }
 
+/// Sampling seed a message asks its turn to run with, e.g. one an eval
+/// harness set through the webhook adapter.
+fn requested_seed(message: &InboundMessage) -> Option<u64> {
+    message
+        .metadata
+        .get("seed")
+        .and_then(serde_json::Value::as_u64)
+}
+
+/// Tags a message asks its turn's requests to be counted under, e.g. a
+/// campaign set through the webhook adapter.
+fn requested_tags(message: &InboundMessage) -> Option<RequestTags> {
+    message.metadata.get("tags").map(RequestTags::from_json)
+}
+
+/// A `/profile <name>` prefix asking for a turn to run with a generation
+/// profile: the name and the message after it.
+fn requested_profile(text: &str) -> Option<(&str, &str)> {
+    let rest = text.trim_start().strip_prefix("/profile")?;
+    if !rest.starts_with(char::is_whitespace) {
+        return None;
+    }
+    let rest = rest.trim_start();
+    let (name, text) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+    (!name.is_empty()).then(|| (name, text.trim_start()))
+}
+
+/// `message` with its content replaced by `text`, as if the sender had
+/// written it.
+fn with_text(message: &InboundMessage, text: String) -> InboundMessage {
+    InboundMessage {
+        content: MessageContent::Text(text),
+        ..message.clone()
+    }
+}
+
 /// Format a user message with sender attribution from message metadata.
 ///
 /// In multi-user channels, this lets the LLM distinguish who said what.
@@ -1475,6 +3161,18 @@ fn event_is_for_channel(event: &ProcessEvent, channel_id: &ChannelId) -> bool {
             channel_id: event_channel,
             ..
         } => event_channel.as_ref() == Some(channel_id),
+        ProcessEvent::TurnCompleted {
+            channel_id: event_channel,
+            ..
+        } => event_channel == channel_id,
+        ProcessEvent::ActionPreview {
+            channel_id: event_channel,
+            ..
+        } => event_channel == channel_id,
+        ProcessEvent::ToolContent {
+            channel_id: event_channel,
+            ..
+        } => event_channel == channel_id,
         // Status block updates, tool events, etc. — match on agent_id which
         // is already filtered by the event bus subscription. Let them through.
         _ => true,
@@ -1495,38 +3193,40 @@ const TEXT_MIME_PREFIXES: &[&str] = &[
     "application/yaml",
 ];
 
+/// Largest text attachment inlined before the rest is cut.
+const MAX_INLINE_TEXT_BYTES: usize = 50_000;
+
 /// Download attachments and convert them to LLM-ready UserContent parts.
 ///
 /// Images become `UserContent::Image` (base64). Text files get inlined.
-/// Other file types get a metadata-only description.
+/// Other file types get a metadata-only description. Attachments without a
+/// useful declared type are downloaded and classified by their content.
 async fn download_attachments(
     deps: &AgentDeps,
     attachments: &[crate::Attachment],
 ) -> Vec<UserContent> {
-    let http = deps.llm_manager.http_client();
     let mut parts = Vec::new();
 
     for attachment in attachments {
-        let is_image = IMAGE_MIME_PREFIXES
-            .iter()
-            .any(|p| attachment.mime_type.starts_with(p));
-        let is_text = TEXT_MIME_PREFIXES
-            .iter()
-            .any(|p| attachment.mime_type.starts_with(p));
-
-        let content = if is_image {
-            download_image_attachment(http, attachment).await
-        } else if is_text {
-            download_text_attachment(http, attachment).await
+        let declared = attachment.mime_type.as_str();
+        let worth_fetching = is_image_mime(declared)
+            || is_text_mime(declared)
+            || declared.is_empty()
+            || declared == "application/octet-stream";
+
+        let content = if worth_fetching {
+            match deps.downloads.fetch(attachment).await {
+                Ok(download) => attachment_content(attachment, download),
+                Err(error) => {
+                    tracing::warn!(%error, filename = %attachment.filename, "failed to download attachment");
+                    UserContent::text(format!(
+                        "[Failed to download {}: {error}]",
+                        attachment.filename
+                    ))
+                }
+            }
         } else {
-            let size_str = attachment
-                .size_bytes
-                .map(|s| format!("{:.1} KB", s as f64 / 1024.0))
-                .unwrap_or_else(|| "unknown size".into());
-            UserContent::text(format!(
-                "[Attachment: {} ({}, {})]",
-                attachment.filename, attachment.mime_type, size_str
-            ))
+            attachment_summary(attachment, declared, attachment.size_bytes)
         };
 
         parts.push(content);
@@ -1535,90 +3235,74 @@ async fn download_attachments(
     parts
 }
 
-/// Download an image attachment and encode it as base64 for the LLM.
-async fn download_image_attachment(
-    http: &reqwest::Client,
+/// The model's view of a downloaded attachment, by its resolved type.
+fn attachment_content(
     attachment: &crate::Attachment,
+    download: crate::messaging::download::Download,
 ) -> UserContent {
-    let response = match http.get(&attachment.url).send().await {
-        Ok(r) => r,
-        Err(error) => {
-            tracing::warn!(%error, filename = %attachment.filename, "failed to download image");
-            return UserContent::text(format!(
-                "[Failed to download image: {}]",
-                attachment.filename
-            ));
-        }
-    };
-
-    let bytes = match response.bytes().await {
-        Ok(b) => b,
-        Err(error) => {
-            tracing::warn!(%error, filename = %attachment.filename, "failed to read image bytes");
-            return UserContent::text(format!(
-                "[Failed to download image: {}]",
-                attachment.filename
-            ));
-        }
-    };
-
-    use base64::Engine as _;
-    let base64_data = base64::engine::general_purpose::STANDARD.encode(&bytes);
-    let media_type = ImageMediaType::from_mime_type(&attachment.mime_type);
-
     tracing::info!(
         filename = %attachment.filename,
-        mime = %attachment.mime_type,
-        size = bytes.len(),
-        "downloaded image attachment"
+        mime = %download.mime_type,
+        size = download.data.len(),
+        "downloaded attachment"
     );
 
-    UserContent::image_base64(base64_data, media_type, None)
-}
-
-/// Download a text attachment and inline its content for the LLM.
-async fn download_text_attachment(
-    http: &reqwest::Client,
-    attachment: &crate::Attachment,
-) -> UserContent {
-    let response = match http.get(&attachment.url).send().await {
-        Ok(r) => r,
-        Err(error) => {
-            tracing::warn!(%error, filename = %attachment.filename, "failed to download text file");
-            return UserContent::text(format!(
-                "[Failed to download file: {}]",
-                attachment.filename
-            ));
-        }
-    };
+    if is_image_mime(&download.mime_type) {
+        use base64::Engine as _;
+        let base64_data = base64::engine::general_purpose::STANDARD.encode(&download.data);
+        let media_type = ImageMediaType::from_mime_type(&download.mime_type);
+        return UserContent::image_base64(base64_data, media_type, None);
+    }
 
-    let content = match response.text().await {
-        Ok(c) => c,
-        Err(error) => {
-            tracing::warn!(%error, filename = %attachment.filename, "failed to read text file");
-            return UserContent::text(format!("[Failed to read file: {}]", attachment.filename));
-        }
-    };
+    if !is_text_mime(&download.mime_type) {
+        return attachment_summary(
+            attachment,
+            &download.mime_type,
+            Some(download.data.len() as u64),
+        );
+    }
 
+    let content = String::from_utf8_lossy(&download.data);
     // Truncate very large files to avoid blowing up context
-    let truncated = if content.len() > 50_000 {
+    let truncated = if content.len() > MAX_INLINE_TEXT_BYTES {
+        let mut end = MAX_INLINE_TEXT_BYTES;
+        while !content.is_char_boundary(end) {
+            end -= 1;
+        }
         format!(
             "{}...\n[truncated — {} bytes total]",
-            &content[..50_000],
+            &content[..end],
             content.len()
         )
     } else {
-        content
+        content.into_owned()
     };
 
-    tracing::info!(
-        filename = %attachment.filename,
-        mime = %attachment.mime_type,
-        "downloaded text attachment"
-    );
-
     UserContent::text(format!(
         "<file name=\"{}\" mime=\"{}\">\n{}\n</file>",
-        attachment.filename, attachment.mime_type, truncated
+        attachment.filename, download.mime_type, truncated
+    ))
+}
+
+/// A metadata-only description of an attachment the model can't read.
+fn attachment_summary(
+    attachment: &crate::Attachment,
+    mime_type: &str,
+    size_bytes: Option<u64>,
+) -> UserContent {
+    let size_str = size_bytes
+        .map(|s| format!("{:.1} KB", s as f64 / 1024.0))
+        .unwrap_or_else(|| "unknown size".into());
+    UserContent::text(format!(
+        "[Attachment: {} ({}, {})]",
+        attachment.filename, mime_type, size_str
     ))
 }
+
+fn is_image_mime(mime_type: &str) -> bool {
+    IMAGE_MIME_PREFIXES.iter().any(|p| mime_type.starts_with(p))
+}
+
+fn is_text_mime(mime_type: &str) -> bool {
+    TEXT_MIME_PREFIXES.iter().any(|p| mime_type.starts_with(p))
+}
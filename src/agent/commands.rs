@@ -0,0 +1,428 @@
+//! Inline `!` commands for power users.
+//!
+//! A message that starts with `!` and a command name is answered by the
+//! channel itself, without a model call: `!usage`, `!quota`, `!model`,
+//! `!persona`, `!new`, `!pin`, `!link`, `!prompt`, `!agent`, `!task list`,
+//! `!escalations`, `!resume` and `!help`.
+//! [`COMMANDS`] lists every command with its help text and whether it needs
+//! admin rights; `[defaults.commands]` can limit more of them to admins or
+//! turn them off. The `/model`, `/persona`, `/pin`, `/link`, `/prompt` and
+//! `/agent` forms keep working as before. Operators' own commands are
+//! scripts, answered by [`crate::messaging::scripts`] before a channel sees
+//! them.
+
+use crate::agent::eviction::PinCommand;
+use crate::agent::link::LinkCommand;
+use crate::agent::managed::AgentCommand;
+use crate::agent::model_override::ModelCommand;
+use crate::agent::persona::PersonaCommand;
+use crate::config::CommandsConfig;
+use crate::messaging::rate_limit::QuotaCommand;
+use crate::prompt_library::PromptCommand;
+use crate::{InboundMessage, MessageContent};
+
+pub const PREFIX: char = '!';
+
+/// A command as listed in `!help`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CommandSpec {
+    pub name: &'static str,
+    /// Arguments, as shown in help.
+    pub usage: &'static str,
+    pub summary: &'static str,
+    /// Only admins may run it, whatever the config says.
+    pub admin_only: bool,
+}
+
+pub const COMMANDS: &[CommandSpec] = &[
+    CommandSpec {
+        name: "help",
+        usage: "[command]",
+        summary: "List the commands, or explain one",
+        admin_only: false,
+    },
+    CommandSpec {
+        name: "usage",
+        usage: "",
+        summary: "Turns, tokens and cost of this conversation",
+        admin_only: false,
+    },
+    CommandSpec {
+        name: "quota",
+        usage: "[user | set <user> minute=N burst=N hour=N | reset <user>]",
+        summary: "What's left of your rate limit; admins can see and change anyone's",
+        admin_only: false,
+    },
+    CommandSpec {
+        name: "model",
+        usage: "[set provider/model | reset]",
+        summary: "Show this conversation's model; admins can change it",
+        admin_only: false,
+    },
+    CommandSpec {
+        name: "persona",
+        usage: "[name | reset]",
+        summary: "Show the personas, or switch this conversation to one",
+        admin_only: false,
+    },
+    CommandSpec {
+        name: "new",
+        usage: "",
+        summary: "Start over with an empty context, keeping pins",
+        admin_only: false,
+    },
+    CommandSpec {
+        name: "pin",
+        usage: "[text | clear]",
+        summary: "Pin a fact, list the pins, or clear them",
+        admin_only: false,
+    },
+    CommandSpec {
+        name: "unpin",
+        usage: "<number>",
+        summary: "Unpin a fact by its number in the list",
+        admin_only: false,
+    },
+    CommandSpec {
+        name: "link",
+        usage: "[conversation]",
+        summary: "Link another conversation of yours, or list the linked ones",
+        admin_only: false,
+    },
+    CommandSpec {
+        name: "unlink",
+        usage: "<conversation>",
+        summary: "Stop sharing context with a linked conversation",
+        admin_only: false,
+    },
+    CommandSpec {
+        name: "prompt",
+        usage: "[show <name> | use <name> [slot=value ...] | cancel]",
+        summary: "List the prompt library, or run a prompt from it",
+        admin_only: false,
+    },
+    CommandSpec {
+        name: "task",
+        usage: "list",
+        summary: "List the running background tasks",
+        admin_only: false,
+    },
+    CommandSpec {
+        name: "agent",
+        usage: "[create <name> ... | set <name> key=value ... | delete <name>]",
+        summary: "List, create or change the lightweight agents made from chat",
+        admin_only: true,
+    },
+    CommandSpec {
+        name: "escalations",
+        usage: "",
+        summary: "List the conversations waiting for a moderator",
+        admin_only: true,
+    },
+    CommandSpec {
+        name: "resume",
+        usage: "[conversation]",
+        summary: "Resolve a conversation's escalation and resume automated replies",
+        admin_only: true,
+    },
+];
+
+/// A parsed `!` command.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    /// `!help`, optionally about one command.
+    Help(Option<String>),
+    Usage,
+    Quota(QuotaCommand),
+    Model(ModelCommand),
+    Persona(PersonaCommand),
+    New,
+    Pin(PinCommand),
+    Link(LinkCommand),
+    Prompt(PromptCommand),
+    TaskList,
+    Agent(AgentCommand),
+    Escalations,
+    /// `!resume`, for the named conversation or this one.
+    Resume(Option<String>),
+    /// A command given arguments it doesn't take.
+    Invalid(&'static CommandSpec),
+    /// A name that isn't a command.
+    Unknown(String),
+}
+
+impl Command {
+    /// Parse a `!` command. Returns `None` for ordinary messages, and for
+    /// every message when commands are off or this one is disabled.
+    pub fn from_message(message: &InboundMessage, config: &CommandsConfig) -> Option<Self> {
+        if !config.enabled {
+            return None;
+        }
+        let MessageContent::Text(text) = &message.content else {
+            return None;
+        };
+        let rest = text.trim().strip_prefix(PREFIX)?;
+        let (name, args) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+        // "!!", "! wow" and the like are just emphasis.
+        if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric()) {
+            return None;
+        }
+        let name = name.to_ascii_lowercase();
+        let args = args.trim();
+
+        let command = match name.as_str() {
+            "help" => Self::Help(
+                args.split_whitespace()
+                    .next()
+                    .map(|topic| topic.trim_start_matches(PREFIX).to_ascii_lowercase()),
+            ),
+            "usage" if args.is_empty() => Self::Usage,
+            "quota" => match QuotaCommand::parse(args) {
+                QuotaCommand::Invalid => Self::Invalid(spec("quota")?),
+                command => Self::Quota(command),
+            },
+            "model" => Self::Model(ModelCommand::parse(args)),
+            "persona" => match PersonaCommand::parse(args) {
+                PersonaCommand::Invalid => Self::Invalid(spec("persona")?),
+                command => Self::Persona(command),
+            },
+            "new" if args.is_empty() => Self::New,
+            "pin" | "pins" | "unpin" => Self::Pin(PinCommand::parse(&name, args)?),
+            "link" | "links" | "unlink" => match LinkCommand::parse(&name, args)? {
+                LinkCommand::Invalid => Self::Invalid(spec("unlink")?),
+                command => Self::Link(command),
+            },
+            "prompt" | "prompts" => match PromptCommand::parse(args) {
+                PromptCommand::Invalid => Self::Invalid(spec("prompt")?),
+                command => Self::Prompt(command),
+            },
+            "task" | "tasks" if matches!(args, "" | "list") => Self::TaskList,
+            "agent" | "agents" => match AgentCommand::parse(args) {
+                AgentCommand::Invalid => Self::Invalid(spec("agent")?),
+                command => Self::Agent(command),
+            },
+            "escalations" if args.is_empty() => Self::Escalations,
+            "resume" if !args.contains(char::is_whitespace) => Self::Resume(
+                Some(args.trim_start_matches('#'))
+                    .filter(|target| !target.is_empty())
+                    .map(str::to_string),
+            ),
+            _ => match spec(&name) {
+                Some(spec) => Self::Invalid(spec),
+                None => Self::Unknown(name),
+            },
+        };
+        match command.spec() {
+            Some(spec) if config.disabled.iter().any(|name| name == spec.name) => None,
+            _ => Some(command),
+        }
+    }
+
+    /// The command's entry in [`COMMANDS`]. None for unknown commands.
+    pub fn spec(&self) -> Option<&'static CommandSpec> {
+        let name = match self {
+            Self::Help(_) => "help",
+            Self::Usage => "usage",
+            Self::Quota(_) => "quota",
+            Self::Model(_) => "model",
+            Self::Persona(_) => "persona",
+            Self::New => "new",
+            Self::Pin(PinCommand::Unpin(_)) => "unpin",
+            Self::Pin(_) => "pin",
+            Self::Link(LinkCommand::Unlink(_)) => "unlink",
+            Self::Link(_) => "link",
+            Self::Prompt(_) => "prompt",
+            Self::TaskList => "task",
+            Self::Agent(_) => "agent",
+            Self::Escalations => "escalations",
+            Self::Resume(_) => "resume",
+            Self::Invalid(spec) => return Some(*spec),
+            Self::Unknown(_) => return None,
+        };
+        spec(name)
+    }
+
+    /// Whether running this command needs admin rights.
+    pub fn needs_admin(&self, config: &CommandsConfig) -> bool {
+        if matches!(self, Self::Model(command) if command.is_privileged())
+            || matches!(self, Self::Quota(command) if command.is_privileged())
+        {
+            return true;
+        }
+        self.spec().is_some_and(|spec| restricted(spec, config))
+    }
+}
+
+fn spec(name: &str) -> Option<&'static CommandSpec> {
+    let name = match name {
+        "pins" => "pin",
+        "links" => "link",
+        "prompts" => "prompt",
+        "tasks" => "task",
+        "agents" => "agent",
+        other => other,
+    };
+    COMMANDS.iter().find(|spec| spec.name == name)
+}
+
+/// Whether `name`, or an alias of it, is a built-in command.
+pub fn is_builtin(name: &str) -> bool {
+    spec(name).is_some()
+}
+
+fn restricted(spec: &CommandSpec, config: &CommandsConfig) -> bool {
+    spec.admin_only || config.admin_only.iter().any(|name| name == spec.name)
+}
+
+/// `!help` text: the enabled commands the sender may run, or the one asked
+/// about.
+pub fn help(topic: Option<&str>, config: &CommandsConfig, is_admin: bool) -> String {
+    let enabled = |spec: &&CommandSpec| !config.disabled.iter().any(|name| name == spec.name);
+
+    if let Some(topic) = topic {
+        return match spec(topic).filter(enabled) {
+            Some(spec) => {
+                let mut line = usage_line(spec);
+                if restricted(spec, config) {
+                    line.push_str(" (admins only)");
+                }
+                line
+            }
+            None => format!("There's no command `{PREFIX}{topic}`. See `{PREFIX}help`."),
+        };
+    }
+
+    let lines: Vec<String> = COMMANDS
+        .iter()
+        .filter(enabled)
+        .filter(|spec| is_admin || !restricted(spec, config))
+        .map(usage_line)
+        .collect();
+    format!("Commands:\n{}", lines.join("\n"))
+}
+
+/// A command's usage and summary, as one line of help.
+pub fn usage_line(spec: &CommandSpec) -> String {
+    let usage = if spec.usage.is_empty() {
+        format!("`{PREFIX}{}`", spec.name)
+    } else {
+        format!("`{PREFIX}{} {}`", spec.name, spec.usage)
+    };
+    format!("{usage} — {}", spec.summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(text: &str) -> InboundMessage {
+        InboundMessage {
+            id: "1".into(),
+            source: "discord".into(),
+            conversation_id: "discord:1:2".into(),
+            sender_id: "42".into(),
+            agent_id: None,
+            content: MessageContent::Text(text.into()),
+            timestamp: chrono::Utc::now(),
+            metadata: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_parse_commands() {
+        let config = CommandsConfig::default();
+        let parse = |text| Command::from_message(&message(text), &config);
+
+        assert_eq!(parse("!usage"), Some(Command::Usage));
+        assert_eq!(parse("  !NEW "), Some(Command::New));
+        assert_eq!(parse("!task list"), Some(Command::TaskList));
+        assert_eq!(parse("!tasks"), Some(Command::TaskList));
+        assert_eq!(
+            parse("!model set openrouter/deepseek-chat"),
+            Some(Command::Model(ModelCommand::Set(
+                "openrouter/deepseek-chat".into()
+            )))
+        );
+        assert_eq!(
+            parse("!pin Reply in English."),
+            Some(Command::Pin(PinCommand::Pin("Reply in English.".into())))
+        );
+        assert_eq!(parse("!unpin 2"), Some(Command::Pin(PinCommand::Unpin(2))));
+        assert_eq!(
+            parse("!link #deploys"),
+            Some(Command::Link(LinkCommand::Link("deploys".into())))
+        );
+        assert_eq!(
+            parse("!unlink"),
+            Some(Command::Invalid(spec("unlink").unwrap()))
+        );
+        assert_eq!(
+            parse("!help !model"),
+            Some(Command::Help(Some("model".into())))
+        );
+        assert_eq!(
+            parse("!quota"),
+            Some(Command::Quota(QuotaCommand::Show(None)))
+        );
+        assert_eq!(parse("!quota set"), Some(Command::Invalid(&COMMANDS[2])));
+        assert_eq!(
+            parse("!persona Reviewer"),
+            Some(Command::Persona(PersonaCommand::Switch("reviewer".into())))
+        );
+        assert_eq!(parse("!new chat"), Some(Command::Invalid(&COMMANDS[5])));
+        assert_eq!(
+            parse("!prompt use triage service=checkout"),
+            Some(Command::Prompt(PromptCommand::Use {
+                name: "triage".into(),
+                values: vec![("service".into(), "checkout".into())],
+            }))
+        );
+        assert_eq!(parse("!agents"), Some(Command::Agent(AgentCommand::List)));
+        assert_eq!(parse("!escalations"), Some(Command::Escalations));
+        assert_eq!(parse("!resume"), Some(Command::Resume(None)));
+        assert_eq!(
+            parse("!resume #support"),
+            Some(Command::Resume(Some("support".into())))
+        );
+        assert_eq!(parse("!deploy"), Some(Command::Unknown("deploy".into())));
+
+        // Not commands.
+        assert_eq!(parse("!!"), None);
+        assert_eq!(parse("! wow"), None);
+        assert_eq!(parse("!?"), None);
+        assert_eq!(parse("usage"), None);
+    }
+
+    #[test]
+    fn test_admin_checks_and_help() {
+        let config = CommandsConfig {
+            enabled: true,
+            admin_only: vec!["new".into()],
+            disabled: vec!["usage".into()],
+        };
+        let parse = |text| Command::from_message(&message(text), &config).unwrap();
+
+        assert!(parse("!new").needs_admin(&config));
+        assert!(parse("!model reset").needs_admin(&config));
+        assert!(!parse("!model").needs_admin(&config));
+        assert!(!parse("!quota").needs_admin(&config));
+        assert!(parse("!quota reset <@7>").needs_admin(&config));
+        assert!(!parse("!pins").needs_admin(&config));
+        assert!(parse("!agent").needs_admin(&config));
+        assert!(parse("!resume").needs_admin(&config));
+        assert_eq!(Command::from_message(&message("!usage"), &config), None);
+
+        let help_for_user = help(None, &config, false);
+        assert!(help_for_user.contains("`!pin [text | clear]` — "));
+        assert!(!help_for_user.contains("`!new`"));
+        assert!(!help_for_user.contains("`!usage`"));
+        assert!(help(None, &config, true).contains("`!new`"));
+        assert!(help(Some("new"), &config, false).ends_with("(admins only)"));
+
+        let off = CommandsConfig {
+            enabled: false,
+            ..Default::default()
+        };
+        assert_eq!(Command::from_message(&message("!help"), &off), None);
+    }
+}
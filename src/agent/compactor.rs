@@ -4,8 +4,10 @@
 //! spawns compaction workers when thresholds are crossed. The LLM work (summarization
 //! + memory extraction) happens in the spawned worker, not here.
 
+use crate::agent::eviction::{elide_tool_results, evict_all_but_pinned, evict_oldest};
+use crate::config::EvictionPolicy;
 use crate::error::Result;
-use crate::llm::SpacebotModel;
+use crate::llm::{Priority, SpacebotModel};
 use crate::{AgentDeps, ChannelId, ProcessType};
 use rig::agent::AgentBuilder;
 use rig::completion::{CompletionModel as _, Prompt as _};
@@ -77,8 +79,7 @@ impl Compactor {
                     self.emergency_truncate().await?;
                 }
                 CompactionAction::Background | CompactionAction::Aggressive => {
-                    // Background/aggressive spawn a worker
-                    self.spawn_compaction_worker(action).await;
+                    self.evict(action, compaction_config.eviction).await?;
                 }
             }
 
@@ -88,21 +89,62 @@ impl Compactor {
         }
     }
 
+    /// Cut history down by the configured policy. Summarizing spawns a
+    /// worker; the other policies edit history in place.
+    async fn evict(&self, action: CompactionAction, policy: EvictionPolicy) -> Result<()> {
+        let fraction = match action {
+            CompactionAction::Background => 0.3,
+            CompactionAction::Aggressive => 0.5,
+            CompactionAction::EmergencyTruncate => unreachable!(),
+        };
+
+        match policy {
+            EvictionPolicy::SummarizeOldest => self.spawn_compaction_worker(fraction).await,
+            EvictionPolicy::DropOldest => {
+                let mut history = self.history.write().await;
+                let count = ((history.len() as f32 * fraction) as usize)
+                    .min(history.len().saturating_sub(2));
+                let removed = evict_oldest(&mut history, count).len();
+                self.insert_truncation_marker(&mut history, removed);
+            }
+            EvictionPolicy::DropToolResultsFirst => {
+                let mut history = self.history.write().await;
+                let tokens = estimate_history_tokens(&history);
+                let target = (tokens as f32 * (1.0 - fraction)) as usize;
+                let elided = elide_tool_results(&mut history, target);
+                let remaining = estimate_history_tokens(&history);
+                drop(history);
+
+                tracing::info!(
+                    channel_id = %self.channel_id,
+                    elided,
+                    tokens_before = tokens,
+                    tokens_after = remaining,
+                    "tool results elided"
+                );
+                if remaining > target {
+                    self.spawn_compaction_worker(fraction).await;
+                }
+            }
+            EvictionPolicy::KeepPinned => {
+                let mut history = self.history.write().await;
+                let removed = evict_all_but_pinned(&mut history).len();
+                self.insert_truncation_marker(&mut history, removed);
+            }
+        }
+
+        Ok(())
+    }
+
     /// Spawn a compaction worker in the background.
     ///
     /// The worker reads old messages, runs an LLM to produce a summary + extract
     /// memories, then swaps the summary into the channel's history.
-    async fn spawn_compaction_worker(&self, action: CompactionAction) {
+    async fn spawn_compaction_worker(&self, fraction: f32) {
         let mut is_compacting = self.is_compacting.write().await;
         *is_compacting = true;
         drop(is_compacting);
 
-        let fraction = match action {
-            CompactionAction::Background => 0.3,
-            CompactionAction::Aggressive => 0.5,
-            CompactionAction::EmergencyTruncate => unreachable!(),
-        };
-
         let history = self.history.clone();
         let is_compacting = self.is_compacting.clone();
         let channel_id = self.channel_id.clone();
@@ -139,8 +181,8 @@ impl Compactor {
 
     /// Emergency truncation: drop oldest messages without LLM summarization.
     ///
-    /// Only fires at 95%+ context usage. Removes the oldest half of messages and
-    /// inserts a marker. Fast and synchronous.
+    /// Only fires at 95%+ context usage. Removes the oldest half of messages,
+    /// keeping pinned ones, and inserts a marker. Fast and synchronous.
     async fn emergency_truncate(&self) -> Result<()> {
         let mut history = self.history.write().await;
         let total = history.len();
@@ -148,27 +190,30 @@ impl Compactor {
             return Ok(());
         }
 
-        let remove_count = total / 2;
-
-        let removed: Vec<Message> = history.drain(..remove_count).collect();
-        drop(removed);
-
-        // Insert a marker at the beginning
-        let prompt_engine = self.deps.runtime_config.prompts.load();
-        let marker = prompt_engine
-            .render_system_truncation(remove_count)
-            .expect("failed to render truncation message");
-        history.insert(0, Message::from(marker));
+        let removed = evict_oldest(&mut history, total / 2).len();
+        self.insert_truncation_marker(&mut history, removed);
 
         tracing::warn!(
             channel_id = %self.channel_id,
-            removed = remove_count,
+            removed,
             remaining = history.len(),
             "emergency truncation performed"
         );
 
         Ok(())
     }
+
+    /// Note at the start of history that `removed` messages were dropped.
+    fn insert_truncation_marker(&self, history: &mut Vec<Message>, removed: usize) {
+        if removed == 0 {
+            return;
+        }
+        let prompt_engine = self.deps.runtime_config.prompts.load();
+        let marker = prompt_engine
+            .render_system_truncation(removed)
+            .expect("failed to render truncation message");
+        history.insert(0, Message::from(marker));
+    }
 }
 
 /// Run the actual compaction: summarize via LLM, extract memories, swap summary into history.
@@ -178,17 +223,18 @@ async fn run_compaction(
     history: &Arc<RwLock<Vec<Message>>>,
     fraction: f32,
 ) -> Result<usize> {
-    // 1. Read and remove the oldest messages from history
+    // 1. Read and remove the oldest messages from history, keeping pinned ones
     let (removed_messages, remove_count) = {
         let mut hist = history.write().await;
         let total = hist.len();
         let remove_count = ((total as f32 * fraction) as usize)
             .max(1)
             .min(total.saturating_sub(2));
-        if remove_count == 0 {
+        let removed = evict_oldest(&mut hist, remove_count);
+        if removed.is_empty() {
             return Ok(0);
         }
-        let removed: Vec<Message> = hist.drain(..remove_count).collect();
+        let remove_count = removed.len();
         (removed, remove_count)
     };
 
@@ -196,12 +242,39 @@ async fn run_compaction(
     let transcript = render_messages_as_transcript(&removed_messages);
 
     // 3. Run the compaction LLM to produce summary + extracted memories
+    let summary = match summarize_transcript(deps, compactor_prompt, &transcript).await {
+        Ok(summary) => summary,
+        Err(error) => {
+            tracing::warn!(%error, "compaction LLM failed, using fallback summary");
+            format!("[Compaction summary of {remove_count} messages — LLM summarization failed]")
+        }
+    };
+
+    // 4. Insert the summary at the beginning of the channel's history
+    {
+        let mut hist = history.write().await;
+        let summary_message = format!("[Compaction Summary]: {summary}");
+        hist.insert(0, Message::from(summary_message));
+    }
+
+    Ok(remove_count)
+}
+
+/// Summarize a transcript with the compactor prompt. The model gets
+/// memory_save so it can persist memories it extracts along the way.
+pub(crate) async fn summarize_transcript(
+    deps: &AgentDeps,
+    compactor_prompt: &str,
+    transcript: &str,
+) -> std::result::Result<String, rig::completion::PromptError> {
     let routing = deps.runtime_config.routing.load();
     let model_name = routing.resolve(ProcessType::Worker, None).to_string();
-    let model =
-        SpacebotModel::make(&deps.llm_manager, &model_name).with_routing((**routing).clone());
+    let model = SpacebotModel::make(&deps.llm_manager, &model_name)
+        .with_routing((**routing).clone())
+        .with_priority(Priority::for_process(ProcessType::Compactor))
+        .with_seed(**deps.runtime_config.seed.load())
+        .with_output_cap(deps.output_cap(ProcessType::Compactor, &model_name));
 
-    // Give the compaction worker memory_save so it can directly persist memories
     let tool_server: ToolServerHandle = ToolServer::new()
         .tool(crate::tools::MemorySaveTool::new(
             deps.memory_search.clone(),
@@ -216,26 +289,11 @@ async fn run_compaction(
 
     let mut compaction_history = Vec::new();
     let response = agent
-        .prompt(&transcript)
+        .prompt(transcript)
         .with_history(&mut compaction_history)
-        .await;
-
-    let summary = match response {
-        Ok(text) => extract_summary_section(&text),
-        Err(error) => {
-            tracing::warn!(%error, "compaction LLM failed, using fallback summary");
-            format!("[Compaction summary of {remove_count} messages — LLM summarization failed]")
-        }
-    };
+        .await?;
 
-    // 4. Insert the summary at the beginning of the channel's history
-    {
-        let mut hist = history.write().await;
-        let summary_message = format!("[Compaction Summary]: {summary}");
-        hist.insert(0, Message::from(summary_message));
-    }
-
-    Ok(remove_count)
+    Ok(extract_summary_section(&response))
 }
 
 /// Estimate token count for a history using chars/4 heuristic.
@@ -11,7 +11,7 @@
 
 use crate::error::Result;
 use crate::hooks::CortexHook;
-use crate::llm::SpacebotModel;
+use crate::llm::{Priority, SpacebotModel};
 use crate::memory::search::{SearchConfig, SearchMode, SearchSort};
 use crate::memory::types::{Association, MemoryType, RelationType};
 use crate::{AgentDeps, ProcessEvent, ProcessType};
@@ -461,8 +461,10 @@ pub async fn generate_bulletin(deps: &AgentDeps, logger: &CortexLogger) -> bool
 
     let routing = deps.runtime_config.routing.load();
     let model_name = routing.resolve(ProcessType::Branch, None).to_string();
-    let model =
-        SpacebotModel::make(&deps.llm_manager, &model_name).with_routing((**routing).clone());
+    let model = SpacebotModel::make(&deps.llm_manager, &model_name)
+        .with_routing((**routing).clone())
+        .with_priority(Priority::for_process(ProcessType::Cortex))
+        .with_output_cap(deps.output_cap(ProcessType::Cortex, &model_name));
 
     // No tools needed — the LLM just synthesizes the pre-gathered data
     let agent = AgentBuilder::new(model).preamble(&bulletin_prompt).build();
@@ -619,8 +621,10 @@ async fn generate_profile(deps: &AgentDeps, logger: &CortexLogger) {
 
     let routing = deps.runtime_config.routing.load();
     let model_name = routing.resolve(ProcessType::Branch, None).to_string();
-    let model =
-        SpacebotModel::make(&deps.llm_manager, &model_name).with_routing((**routing).clone());
+    let model = SpacebotModel::make(&deps.llm_manager, &model_name)
+        .with_routing((**routing).clone())
+        .with_priority(Priority::for_process(ProcessType::Cortex))
+        .with_output_cap(deps.output_cap(ProcessType::Cortex, &model_name));
 
     let agent = AgentBuilder::new(model).preamble(&profile_prompt).build();
 
@@ -6,7 +6,7 @@
 //! into the system prompt as context.
 
 use crate::conversation::history::ProcessRunLogger;
-use crate::llm::SpacebotModel;
+use crate::llm::{Priority, SpacebotModel};
 use crate::{AgentDeps, ProcessType};
 
 use rig::agent::{AgentBuilder, HookAction, PromptHook, ToolCallHookAction};
@@ -263,7 +263,8 @@ impl CortexChatSession {
         let routing = self.deps.runtime_config.routing.load();
         let model_name = routing.resolve(ProcessType::Branch, None).to_string();
         let model = SpacebotModel::make(&self.deps.llm_manager, &model_name)
-            .with_routing((**routing).clone());
+            .with_routing((**routing).clone())
+            .with_priority(Priority::Interactive);
 
         let agent = AgentBuilder::new(model)
             .preamble(&system_prompt)
@@ -321,10 +322,25 @@ impl CortexChatSession {
         let memory_bulletin = runtime_config.memory_bulletin.load();
 
         let browser_enabled = runtime_config.browser_config.load().enabled;
+        let computer_use_enabled = runtime_config.computer_use.load().available();
+        let ocr_enabled = runtime_config.ocr.load().enabled;
+        let home_assistant_enabled = runtime_config.home_assistant.load().enabled;
+        let issues_enabled = {
+            let issues = runtime_config.issues.load();
+            issues.enabled && !issues.workspaces.is_empty()
+        };
         let web_search_enabled = runtime_config.brave_search_key.load().is_some();
         let opencode_enabled = runtime_config.opencode.load().enabled;
         let worker_capabilities = prompt_engine
-            .render_worker_capabilities(browser_enabled, web_search_enabled, opencode_enabled)
+            .render_worker_capabilities(
+                browser_enabled,
+                computer_use_enabled,
+                ocr_enabled,
+                home_assistant_enabled,
+                issues_enabled,
+                web_search_enabled,
+                opencode_enabled,
+            )
             .expect("failed to render worker capabilities");
 
         // Load channel transcript if a channel context is active
@@ -0,0 +1,189 @@
+//! Handing a conversation to human moderators.
+//!
+//! With `[defaults.escalation]` enabled, a conversation is flagged for review
+//! when the channel's `escalate` tool is called or a message matches one of
+//! the escalation rules. The flag is stored with the conversation (see
+//! [`EscalationStore`]), so it holds across restarts: the user is told once
+//! that a moderator will pick it up, and from then on the channel logs what's
+//! said without answering. A watcher per agent notifies the moderator
+//! targets with the reason and a transcript link. Moderators resume the
+//! conversation with `!resume <conversation>` or through the API.
+
+use crate::OutboundResponse;
+use crate::config::EscalationConfig;
+use crate::conversation::ChannelStore;
+use crate::conversation::escalations::{Escalation, EscalationStore, FLAGGED_BY_AGENT};
+use crate::cron::CronContext;
+use crate::cron::scheduler::DeliveryTarget;
+
+use regex::{Regex, RegexBuilder};
+
+use std::time::Duration;
+
+/// How often the watcher looks for escalations to notify moderators about.
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// An agent's escalation rules, compiled. Recompiled when the configured
+/// patterns change.
+#[derive(Debug, Default)]
+pub struct EscalationRules {
+    source: Vec<String>,
+    patterns: Vec<Regex>,
+}
+
+impl EscalationRules {
+    /// The first of `config`'s patterns that `text` matches, if any.
+    pub fn matching(&mut self, config: &EscalationConfig, text: &str) -> Option<&str> {
+        if self.source != config.patterns {
+            self.patterns = compile_patterns(&config.patterns);
+            self.source = config.patterns.clone();
+        }
+        self.patterns
+            .iter()
+            .find(|pattern| pattern.is_match(text))
+            .map(Regex::as_str)
+    }
+}
+
+fn compile_patterns(patterns: &[String]) -> Vec<Regex> {
+    patterns
+        .iter()
+        .filter_map(|pattern| {
+            RegexBuilder::new(pattern)
+                .case_insensitive(true)
+                .build()
+                .inspect_err(|error| tracing::warn!(%error, pattern, "invalid escalation pattern"))
+                .ok()
+        })
+        .collect()
+}
+
+/// Where a moderator can read the conversation: its page on the dashboard,
+/// or the command that prints it when there's no dashboard URL.
+pub fn transcript_link(config: &EscalationConfig, agent_id: &str, channel_id: &str) -> String {
+    match &config.dashboard_url {
+        Some(base) => format!(
+            "{}/agents/{agent_id}/channels/{channel_id}",
+            base.trim_end_matches('/')
+        ),
+        None => format!("`spacebot transcript show {channel_id} --agent {agent_id}`"),
+    }
+}
+
+/// What moderators are told about an escalation.
+pub fn notification(
+    config: &EscalationConfig,
+    agent_id: &str,
+    escalation: &Escalation,
+    name: Option<&str>,
+) -> String {
+    let channel_id = &escalation.channel_id;
+    let conversation = match name {
+        Some(name) => format!("{name} (`{channel_id}`)"),
+        None => format!("`{channel_id}`"),
+    };
+    let flagged_by = if escalation.flagged_by == FLAGGED_BY_AGENT {
+        "the agent"
+    } else {
+        "an escalation rule"
+    };
+    format!(
+        "Conversation {conversation} with {agent_id} was flagged for review by {flagged_by}: {}\n\
+         Transcript: {}\n\
+         Automated replies there are paused until a moderator runs `!resume {channel_id}`.",
+        escalation.reason,
+        transcript_link(config, agent_id, channel_id)
+    )
+}
+
+/// Notify moderators of new escalations, for as long as the agent runs.
+pub fn spawn_escalation_watcher(context: CronContext) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let store = EscalationStore::new(context.deps.sqlite_pool.clone());
+        loop {
+            let config = context.deps.runtime_config.escalation.load_full();
+            if config.enabled {
+                if let Err(error) = notify_moderators(&context, &store, &config).await {
+                    tracing::warn!(%error, "escalation notification failed");
+                }
+            }
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+    })
+}
+
+async fn notify_moderators(
+    context: &CronContext,
+    store: &EscalationStore,
+    config: &EscalationConfig,
+) -> crate::error::Result<()> {
+    let agent_id = &context.deps.agent_id;
+    let channel_store = ChannelStore::new(context.deps.sqlite_pool.clone());
+    for escalation in store.unnotified().await? {
+        tracing::warn!(
+            %agent_id,
+            channel_id = %escalation.channel_id,
+            flagged_by = %escalation.flagged_by,
+            reason = %escalation.reason,
+            "conversation escalated"
+        );
+        let name = channel_store.resolve_name(&escalation.channel_id).await;
+        let text = notification(config, agent_id, &escalation, name.as_deref());
+        for raw_target in &config.delivery_targets {
+            let Some(target) = DeliveryTarget::parse(raw_target) else {
+                tracing::warn!(%raw_target, "invalid escalation delivery target, expected 'adapter:target'");
+                continue;
+            };
+            if let Err(error) = context
+                .messaging_manager
+                .broadcast(
+                    &target.adapter,
+                    &target.target,
+                    OutboundResponse::Text(text.clone()),
+                )
+                .await
+            {
+                tracing::warn!(%error, %target, "failed to deliver escalation");
+            }
+        }
+        store.mark_notified(&escalation.id).await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rules_follow_the_config() {
+        let mut rules = EscalationRules::default();
+        let mut config = EscalationConfig {
+            patterns: vec![r"\bkill (myself|yourself)\b".into(), "(".into()],
+            ..Default::default()
+        };
+        assert_eq!(
+            rules.matching(&config, "I want to KILL MYSELF"),
+            Some(r"\bkill (myself|yourself)\b")
+        );
+        assert_eq!(rules.matching(&config, "kill the process"), None);
+
+        config.patterns = vec!["refund".into()];
+        assert_eq!(rules.matching(&config, "I want a refund"), Some("refund"));
+        assert_eq!(rules.matching(&config, "kill yourself"), None);
+    }
+
+    #[test]
+    fn test_transcript_link() {
+        let mut config = EscalationConfig::default();
+        assert_eq!(
+            transcript_link(&config, "main", "discord:1:2"),
+            "`spacebot transcript show discord:1:2 --agent main`"
+        );
+        config.dashboard_url = Some("https://spacebot.example.com/".into());
+        assert_eq!(
+            transcript_link(&config, "main", "discord:1:2"),
+            "https://spacebot.example.com/agents/main/channels/discord:1:2"
+        );
+    }
+}
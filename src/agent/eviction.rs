@@ -0,0 +1,318 @@
+//! History eviction: what leaves a channel's context as it nears the
+//! model's limit, and what never does.
+//!
+//! The compactor decides when; this module holds the history edits each
+//! [`EvictionPolicy`](crate::config::EvictionPolicy) makes. Pinned messages
+//! survive all of them. Pins are stored with the channel (see
+//! [`ChannelStore::pins`](crate::conversation::ChannelStore::pins)) and
+//! mirrored into history as messages marked by their prefix, like compaction
+//! summaries are, so they're read in order with everything else and carried
+//! over every eviction.
+
+use crate::agent::compactor::estimate_history_tokens;
+use crate::{InboundMessage, MessageContent};
+use rig::message::{Message, Text, ToolResultContent, UserContent};
+use rig::one_or_many::OneOrMany;
+
+/// Marks a pinned message.
+const PINNED_PREFIX: &str = "[Pinned]: ";
+
+/// What an elided tool result is replaced with.
+const ELIDED_TOOL_RESULT: &str = "[Tool result removed to save context]";
+
+/// Latest messages no policy touches, so the current exchange stays whole.
+pub const RECENT_MESSAGES_KEPT: usize = 10;
+
+/// A parsed `/pin`, `/pins` or `/unpin` command.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PinCommand {
+    /// List the channel's pins.
+    Show,
+    Pin(String),
+    /// Unpin the fact with this number in the `/pins` list.
+    Unpin(usize),
+    /// Unpin everything.
+    Clear,
+    /// An `/unpin` without a pin number.
+    Invalid,
+}
+
+impl PinCommand {
+    /// Parse a pin command. Returns `None` for ordinary messages.
+    pub fn from_message(message: &InboundMessage) -> Option<Self> {
+        let MessageContent::Text(text) = &message.content else {
+            return None;
+        };
+        let text = text.trim();
+        let (command, rest) = text.split_once(char::is_whitespace).unwrap_or((text, ""));
+        Self::parse(command.strip_prefix('/')?, rest)
+    }
+
+    /// Parse a `pin`, `pins` or `unpin` command from its name, without the
+    /// prefix, and arguments. Returns `None` for other names.
+    pub fn parse(name: &str, args: &str) -> Option<Self> {
+        Some(match (name, args.trim()) {
+            ("pins", _) | ("pin", "") => Self::Show,
+            ("pin", "clear") => Self::Clear,
+            ("pin", pinned) => Self::Pin(pinned.to_string()),
+            ("unpin", number) => number
+                .parse()
+                .ok()
+                .filter(|number| *number > 0)
+                .map_or(Self::Invalid, Self::Unpin),
+            _ => return None,
+        })
+    }
+}
+
+/// A history message holding a pinned instruction.
+pub fn pinned_message(text: &str) -> Message {
+    Message::from(format!("{PINNED_PREFIX}{}", text.trim()))
+}
+
+/// The instruction a pinned message holds.
+fn pinned_text(message: &Message) -> Option<&str> {
+    let Message::User { content } = message else {
+        return None;
+    };
+    content.iter().find_map(|item| match item {
+        UserContent::Text(text) => text.text.strip_prefix(PINNED_PREFIX),
+        _ => None,
+    })
+}
+
+pub fn is_pinned(message: &Message) -> bool {
+    pinned_text(message).is_some()
+}
+
+/// Pinned instructions in history, oldest first.
+fn pins(history: &[Message]) -> Vec<String> {
+    history
+        .iter()
+        .filter_map(pinned_text)
+        .map(str::to_string)
+        .collect()
+}
+
+/// Make the pinned messages in history match `pinned`, the channel's stored
+/// pins: ones since unpinned leave, and new ones are added at the end.
+pub fn sync_pins(history: &mut Vec<Message>, pinned: &[String]) {
+    history.retain(|message| {
+        pinned_text(message).is_none_or(|text| pinned.iter().any(|pin| pin == text))
+    });
+    let present = pins(history);
+    for text in pinned {
+        if !present.contains(text) {
+            history.push(pinned_message(text));
+        }
+    }
+}
+
+/// Take the unpinned messages among the oldest `count` out of history,
+/// in order. Pinned ones stay, ahead of what's left.
+pub fn evict_oldest(history: &mut Vec<Message>, count: usize) -> Vec<Message> {
+    let count = count.min(history.len());
+    let (pinned, evicted): (Vec<_>, Vec<_>) = history.drain(..count).partition(is_pinned);
+    history.splice(0..0, pinned);
+    evicted
+}
+
+/// Take every unpinned message out of history except the most recent
+/// [`RECENT_MESSAGES_KEPT`].
+pub fn evict_all_but_pinned(history: &mut Vec<Message>) -> Vec<Message> {
+    let count = history.len().saturating_sub(RECENT_MESSAGES_KEPT);
+    evict_oldest(history, count)
+}
+
+/// Replace tool results with a placeholder, oldest first, until history is
+/// estimated at `target_tokens` or less. The tool calls stay, so the model
+/// still sees what it did. Returns how many results were elided.
+pub fn elide_tool_results(history: &mut [Message], target_tokens: usize) -> usize {
+    let mut tokens = estimate_history_tokens(history);
+    let evictable = history.len().saturating_sub(RECENT_MESSAGES_KEPT);
+    let mut elided = 0;
+    for message in &mut history[..evictable] {
+        if tokens <= target_tokens {
+            break;
+        }
+        let Message::User { content } = message else {
+            continue;
+        };
+        let mut changed = false;
+        let items: Vec<UserContent> = content
+            .iter()
+            .map(|item| match item {
+                UserContent::ToolResult(result) if !is_elided(&result.content) => {
+                    changed = true;
+                    let mut result = result.clone();
+                    result.content = OneOrMany::one(ToolResultContent::Text(Text {
+                        text: ELIDED_TOOL_RESULT.into(),
+                    }));
+                    UserContent::ToolResult(result)
+                }
+                other => other.clone(),
+            })
+            .collect();
+        if !changed {
+            continue;
+        }
+        let before = estimate_history_tokens(std::slice::from_ref(message));
+        if let Ok(items) = OneOrMany::many(items) {
+            *content = items;
+            elided += 1;
+        }
+        let after = estimate_history_tokens(std::slice::from_ref(message));
+        tokens = tokens.saturating_sub(before.saturating_sub(after));
+    }
+    elided
+}
+
+fn is_elided(content: &OneOrMany<ToolResultContent>) -> bool {
+    content.iter().all(
+        |part| matches!(part, ToolResultContent::Text(text) if text.text == ELIDED_TOOL_RESULT),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tool_result(id: &str, text: &str) -> Message {
+        Message::User {
+            content: OneOrMany::one(UserContent::tool_result(
+                id,
+                OneOrMany::one(ToolResultContent::text(text)),
+            )),
+        }
+    }
+
+    fn text(message: &Message) -> String {
+        let Message::User { content } = message else {
+            return String::new();
+        };
+        content
+            .iter()
+            .map(|item| match item {
+                UserContent::Text(text) => text.text.clone(),
+                UserContent::ToolResult(result) => result
+                    .content
+                    .iter()
+                    .map(|part| match part {
+                        ToolResultContent::Text(text) => text.text.clone(),
+                        _ => String::new(),
+                    })
+                    .collect(),
+                _ => String::new(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_pinned_messages_survive_eviction() {
+        let mut history = vec![
+            Message::from("hello"),
+            pinned_message("Always answer in French."),
+            Message::from("how are you?"),
+            Message::from("what's new?"),
+        ];
+
+        let evicted = evict_oldest(&mut history, 3);
+        assert_eq!(
+            evicted.iter().map(text).collect::<Vec<_>>(),
+            ["hello", "how are you?"]
+        );
+        assert_eq!(pins(&history), ["Always answer in French."]);
+        assert_eq!(text(&history[1]), "what's new?");
+    }
+
+    #[test]
+    fn test_sync_pins() {
+        let mut history = vec![
+            pinned_message("The deploy freeze ends Friday."),
+            Message::from("hello"),
+            pinned_message("Always answer in French."),
+        ];
+
+        let stored = [
+            "Always answer in French.".to_string(),
+            "Staging is down.".to_string(),
+        ];
+        sync_pins(&mut history, &stored);
+        assert_eq!(pins(&history), stored);
+        assert_eq!(text(&history[0]), "hello");
+
+        sync_pins(&mut history, &[]);
+        assert_eq!(history.len(), 1);
+    }
+
+    #[test]
+    fn test_evict_all_but_pinned_keeps_recent() {
+        let mut history: Vec<Message> = (0..15)
+            .map(|index| Message::from(format!("message {index}")))
+            .collect();
+        history.insert(2, pinned_message("Use metric units."));
+
+        let evicted = evict_all_but_pinned(&mut history);
+        assert_eq!(evicted.len(), 5);
+        assert_eq!(history.len(), 1 + RECENT_MESSAGES_KEPT);
+        assert!(is_pinned(&history[0]));
+        assert_eq!(text(&history[1]), "message 5");
+    }
+
+    #[test]
+    fn test_elide_tool_results_oldest_first() {
+        let big = "x".repeat(4000);
+        let mut history = vec![tool_result("1", &big), tool_result("2", &big)];
+        history.extend((0..RECENT_MESSAGES_KEPT).map(|_| tool_result("recent", &big)));
+        let total = estimate_history_tokens(&history);
+
+        // One elision is enough to get under the target.
+        assert_eq!(elide_tool_results(&mut history, total - 500), 1);
+        assert_eq!(text(&history[0]), ELIDED_TOOL_RESULT);
+        assert_eq!(text(&history[1]), big);
+
+        // The recent messages are never touched, however far over it is.
+        assert_eq!(elide_tool_results(&mut history, 0), 1);
+        assert_eq!(elide_tool_results(&mut history, 0), 0);
+        assert_eq!(text(&history[2]), big);
+    }
+
+    #[test]
+    fn test_parse_pin_commands() {
+        let message = |text: &str| InboundMessage {
+            id: "1".into(),
+            source: "discord".into(),
+            conversation_id: "discord:1:2".into(),
+            sender_id: "42".into(),
+            agent_id: None,
+            content: MessageContent::Text(text.into()),
+            timestamp: chrono::Utc::now(),
+            metadata: Default::default(),
+        };
+        assert_eq!(
+            PinCommand::from_message(&message("/pin")),
+            Some(PinCommand::Show)
+        );
+        assert_eq!(
+            PinCommand::from_message(&message("/pin clear")),
+            Some(PinCommand::Clear)
+        );
+        assert_eq!(
+            PinCommand::from_message(&message("/pin  Reply in English. ")),
+            Some(PinCommand::Pin("Reply in English.".into()))
+        );
+        assert_eq!(
+            PinCommand::from_message(&message("/pins")),
+            Some(PinCommand::Show)
+        );
+        assert_eq!(
+            PinCommand::from_message(&message("/unpin 2")),
+            Some(PinCommand::Unpin(2))
+        );
+        assert_eq!(
+            PinCommand::from_message(&message("/unpin all")),
+            Some(PinCommand::Invalid)
+        );
+        assert_eq!(PinCommand::from_message(&message("/pinned")), None);
+    }
+}
@@ -0,0 +1,332 @@
+//! Post-hoc fact-checking of channel replies.
+//!
+//! For channels where being wrong is expensive, a reply is checked before
+//! it goes out: a model pulls the factual claims out of it, each claim is
+//! looked up in the agent's memory and on the web, and the model judges
+//! which claims the evidence supports. Claims it doesn't are listed under
+//! the reply, or the reply is rewritten to hedge them. Checking only ever
+//! adds caution: a check that fails or times out sends the reply as it was.
+
+use crate::AgentDeps;
+use crate::ProcessType;
+use crate::config::{FactCheckAction, FactCheckConfig};
+use crate::llm::{Priority, SpacebotModel};
+use crate::tools::web_search::{WebSearchArgs, WebSearchTool};
+
+use anyhow::Context as _;
+use rig::agent::AgentBuilder;
+use rig::completion::Prompt as _;
+use rig::tool::Tool as _;
+use serde::Deserialize;
+use std::time::Duration;
+
+/// Evidence gathered per claim from each source.
+const EVIDENCE_PER_SOURCE: usize = 3;
+
+/// Memories less similar to a claim than this aren't evidence for it, when
+/// `[retrieval]` doesn't set its own threshold.
+const MIN_MEMORY_SCORE: f32 = 0.5;
+
+/// Longest evidence snippet passed to the checking model.
+const MAX_SNIPPET_CHARS: usize = 600;
+
+/// What the evidence says about a claim.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Verdict {
+    Supported,
+    Contradicted,
+    Unverified,
+}
+
+/// One claim and its verdict.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct CheckedClaim {
+    pub claim: String,
+    pub verdict: Verdict,
+    /// What the evidence says instead, for a contradicted claim.
+    #[serde(default)]
+    pub note: String,
+}
+
+/// Checks replies for one channel against the agent's config at the time.
+#[derive(Clone)]
+pub struct FactChecker {
+    deps: AgentDeps,
+}
+
+impl FactChecker {
+    /// A checker when fact-checking is on for `channel_id`.
+    pub fn for_channel(deps: &AgentDeps, channel_id: &str) -> Option<Self> {
+        deps.runtime_config
+            .fact_check
+            .load()
+            .applies_to(channel_id)
+            .then(|| Self { deps: deps.clone() })
+    }
+
+    /// The reply to send in place of `reply`.
+    pub async fn check(&self, reply: &str) -> String {
+        let config = self.deps.runtime_config.fact_check.load_full();
+        let timeout = Duration::from_secs(config.timeout_secs);
+        match tokio::time::timeout(timeout, self.check_claims(&config, reply)).await {
+            Ok(Ok(checked)) => checked,
+            Ok(Err(error)) => {
+                tracing::warn!(%error, agent_id = %self.deps.agent_id, "fact check failed, reply sent unchecked");
+                reply.to_string()
+            }
+            Err(_) => {
+                tracing::warn!(agent_id = %self.deps.agent_id, "fact check timed out, reply sent unchecked");
+                reply.to_string()
+            }
+        }
+    }
+
+    async fn check_claims(&self, config: &FactCheckConfig, reply: &str) -> anyhow::Result<String> {
+        let claims: Vec<String> = parse_json(
+            &self
+                .ask(config, "fact_check_claims", reply.to_string())
+                .await?,
+        )?;
+        let claims: Vec<String> = claims
+            .into_iter()
+            .filter(|claim| !claim.trim().is_empty())
+            .take(config.max_claims)
+            .collect();
+        if claims.is_empty() {
+            return Ok(reply.to_string());
+        }
+
+        let evidence =
+            futures::future::join_all(claims.iter().map(|claim| self.evidence(config, claim)))
+                .await;
+        let verdicts: Vec<CheckedClaim> = parse_json(
+            &self
+                .ask(config, "fact_check", render_evidence(&claims, &evidence))
+                .await?,
+        )?;
+        let flagged: Vec<CheckedClaim> = verdicts
+            .into_iter()
+            .filter(|claim| claim.verdict != Verdict::Supported)
+            .collect();
+        tracing::info!(
+            agent_id = %self.deps.agent_id,
+            claims = claims.len(),
+            flagged = flagged.len(),
+            action = ?config.action,
+            "fact checked reply"
+        );
+        if flagged.is_empty() {
+            return Ok(reply.to_string());
+        }
+
+        match config.action {
+            FactCheckAction::Flag => Ok(flag(reply, &flagged)),
+            FactCheckAction::Soften => {
+                let prompt = format!("Reply:\n{reply}\n\n{}", render_flagged(&flagged));
+                match self.ask(config, "fact_check_soften", prompt).await {
+                    Ok(softened) if !softened.trim().is_empty() => Ok(softened.trim().to_string()),
+                    Ok(_) => Ok(flag(reply, &flagged)),
+                    Err(error) => {
+                        tracing::warn!(%error, "softening failed, flagging claims instead");
+                        Ok(flag(reply, &flagged))
+                    }
+                }
+            }
+        }
+    }
+
+    /// Snippets about `claim` from each configured source. A source that
+    /// fails only costs the claim its evidence.
+    async fn evidence(&self, config: &FactCheckConfig, claim: &str) -> Vec<String> {
+        let mut snippets = Vec::new();
+        if config.sources.iter().any(|source| source == "memory") {
+            let retrieval = self.deps.runtime_config.retrieval.load();
+            let (source, min_score) = match retrieval.as_ref() {
+                Some(retrieval) => (retrieval.source(), retrieval.min_score),
+                None => (None, MIN_MEMORY_SCORE),
+            };
+            match self
+                .deps
+                .memory_search
+                .retrieve(claim, source, EVIDENCE_PER_SOURCE, min_score)
+                .await
+            {
+                Ok(results) => snippets.extend(
+                    results
+                        .into_iter()
+                        .map(|result| format!("memory: {}", clip(&result.memory.content))),
+                ),
+                Err(error) => tracing::warn!(%error, "fact check memory lookup failed"),
+            }
+        }
+
+        let brave_key = (**self.deps.runtime_config.brave_search_key.load()).clone();
+        if let Some(key) = brave_key.filter(|_| config.sources.iter().any(|source| source == "web"))
+        {
+            let search = WebSearchTool::new(key)
+                .with_proxy(self.deps.network.proxy_for(WebSearchTool::NAME));
+            let args = WebSearchArgs {
+                query: claim.to_string(),
+                count: EVIDENCE_PER_SOURCE as u8,
+                country: None,
+                search_lang: None,
+                freshness: None,
+            };
+            match search.call(args).await {
+                Ok(output) => snippets.extend(output.results.into_iter().map(|result| {
+                    format!(
+                        "web ({}): {} {}",
+                        result.url,
+                        result.title,
+                        clip(&result.description)
+                    )
+                })),
+                Err(error) => tracing::warn!(%error, "fact check web search failed"),
+            }
+        }
+        snippets
+    }
+
+    async fn ask(
+        &self,
+        config: &FactCheckConfig,
+        prompt_key: &str,
+        prompt: String,
+    ) -> anyhow::Result<String> {
+        let routing = self.deps.runtime_config.routing.load();
+        let model_name = crate::agent::persona::resolve_model(&config.model, &routing)
+            .with_context(|| format!("unknown fact check model '{}'", config.model))?;
+        let model = SpacebotModel::make(&self.deps.llm_manager, &model_name)
+            .with_routing((**routing).clone())
+            .with_priority(Priority::for_process(ProcessType::Branch));
+        let agent = AgentBuilder::new(model)
+            .preamble(crate::prompts::text::get(prompt_key))
+            .build();
+        agent
+            .prompt(prompt)
+            .await
+            .context("fact check model call failed")
+    }
+}
+
+impl std::fmt::Debug for FactChecker {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FactChecker")
+            .field("agent_id", &self.deps.agent_id)
+            .finish()
+    }
+}
+
+fn clip(text: &str) -> String {
+    text.chars().take(MAX_SNIPPET_CHARS).collect()
+}
+
+fn render_evidence(claims: &[String], evidence: &[Vec<String>]) -> String {
+    let mut prompt = String::new();
+    for (index, (claim, snippets)) in claims.iter().zip(evidence).enumerate() {
+        prompt.push_str(&format!("Claim {}: {claim}\nEvidence:\n", index + 1));
+        if snippets.is_empty() {
+            prompt.push_str("- (none found)\n");
+        }
+        for snippet in snippets {
+            prompt.push_str(&format!("- {snippet}\n"));
+        }
+        prompt.push('\n');
+    }
+    prompt
+}
+
+fn render_flagged(flagged: &[CheckedClaim]) -> String {
+    let mut text = String::from("Claims the sources don't support:\n");
+    for claim in flagged {
+        match claim.verdict {
+            Verdict::Contradicted if !claim.note.is_empty() => text.push_str(&format!(
+                "- {} (contradicted: {})\n",
+                claim.claim, claim.note
+            )),
+            Verdict::Contradicted => text.push_str(&format!("- {} (contradicted)\n", claim.claim)),
+            _ => text.push_str(&format!("- {} (unverified)\n", claim.claim)),
+        }
+    }
+    text
+}
+
+/// `reply` with the claims the sources don't back listed under it.
+fn flag(reply: &str, flagged: &[CheckedClaim]) -> String {
+    let mut text = format!("{}\n\n", reply.trim_end());
+    let contradicted: Vec<&CheckedClaim> = flagged
+        .iter()
+        .filter(|claim| claim.verdict == Verdict::Contradicted)
+        .collect();
+    let unverified: Vec<&str> = flagged
+        .iter()
+        .filter(|claim| claim.verdict == Verdict::Unverified)
+        .map(|claim| claim.claim.as_str())
+        .collect();
+    for claim in contradicted {
+        if claim.note.is_empty() {
+            text.push_str(&format!("_Sources disagree with: {}_\n", claim.claim));
+        } else {
+            text.push_str(&format!(
+                "_Sources disagree with: {} ({})_\n",
+                claim.claim, claim.note
+            ));
+        }
+    }
+    if !unverified.is_empty() {
+        text.push_str(&format!("_Couldn't verify: {}_\n", unverified.join("; ")));
+    }
+    text.trim_end().to_string()
+}
+
+fn parse_json<T: serde::de::DeserializeOwned>(response: &str) -> anyhow::Result<T> {
+    let cleaned = response
+        .trim()
+        .trim_start_matches("```json")
+        .trim_start_matches("```")
+        .trim_end_matches("```")
+        .trim();
+    serde_json::from_str(cleaned)
+        .with_context(|| format!("fact check reply isn't the requested JSON: {cleaned}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_verdicts() {
+        let verdicts: Vec<CheckedClaim> = parse_json(
+            "```json\n[{\"claim\": \"Paris is in France\", \"verdict\": \"supported\"}, \
+             {\"claim\": \"The Seine is 50 km long\", \"verdict\": \"contradicted\", \"note\": \"it's 777 km\"}]\n```",
+        )
+        .unwrap();
+        assert_eq!(verdicts.len(), 2);
+        assert_eq!(verdicts[0].verdict, Verdict::Supported);
+        assert_eq!(verdicts[1].note, "it's 777 km");
+        assert!(parse_json::<Vec<CheckedClaim>>("All good.").is_err());
+    }
+
+    #[test]
+    fn test_flag_lists_claims_under_reply() {
+        let flagged = vec![
+            CheckedClaim {
+                claim: "The Seine is 50 km long".into(),
+                verdict: Verdict::Contradicted,
+                note: "it's 777 km".into(),
+            },
+            CheckedClaim {
+                claim: "It froze in 1954".into(),
+                verdict: Verdict::Unverified,
+                note: String::new(),
+            },
+        ];
+        assert_eq!(
+            flag("The Seine is 50 km long and froze in 1954.\n", &flagged),
+            "The Seine is 50 km long and froze in 1954.\n\n\
+             _Sources disagree with: The Seine is 50 km long (it's 777 km)_\n\
+             _Couldn't verify: It froze in 1954_"
+        );
+    }
+}
@@ -0,0 +1,254 @@
+//! Handing a conversation from one agent to another.
+//!
+//! A channel's `handoff` tool tells the user who is taking over, records the
+//! handoff in the transcript and queues a request for the router. The router
+//! closes the old channel, opens one for the new agent with the handing agent's
+//! summary as its first turn, and sends the conversation's later messages to
+//! the new agent instead of wherever bindings or intake would put them.
+
+use crate::AgentId;
+
+use tokio::sync::mpsc;
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// A conversation can't be handed off more often than this, so two agents
+/// can't bounce it between them indefinitely.
+pub const MAX_HANDOFFS_PER_CONVERSATION: u32 = 3;
+
+/// Past this many handed-off conversations, ones idle for `ROUTE_TTL` are
+/// dropped and go back to normal routing.
+const PRUNE_THRESHOLD: usize = 10_000;
+const ROUTE_TTL: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// Requests waiting for the router before the tool refuses new ones.
+const QUEUE_CAPACITY: usize = 64;
+
+/// An agent a conversation can be handed to.
+#[derive(Debug, Clone)]
+pub struct HandoffTarget {
+    pub agent_id: AgentId,
+    pub description: Option<String>,
+}
+
+/// A handoff queued by a channel, for the router.
+#[derive(Debug, Clone)]
+pub struct HandoffRequest {
+    pub conversation_id: String,
+    pub from: AgentId,
+    pub to: AgentId,
+    /// Why, as told to the user.
+    pub reason: String,
+    /// What the new agent needs to know, written by the one handing off.
+    pub summary: String,
+}
+
+impl HandoffRequest {
+    /// What the user is told, and what both transcripts record.
+    pub fn notice(&self) -> String {
+        format!(
+            "Handing this conversation over to {}: {}",
+            self.to, self.reason
+        )
+    }
+}
+
+#[derive(Debug)]
+struct Route {
+    agent_id: AgentId,
+    handoffs: u32,
+    last_seen: Instant,
+}
+
+#[derive(Debug, Default)]
+struct HandoffState {
+    targets: Vec<HandoffTarget>,
+    routes: HashMap<String, Route>,
+}
+
+/// Instance-wide handoff state. Clones share it.
+#[derive(Debug, Clone)]
+pub struct Handoffs {
+    state: Arc<Mutex<HandoffState>>,
+    request_tx: mpsc::Sender<HandoffRequest>,
+}
+
+impl Handoffs {
+    /// The shared state, and the receiver the router takes requests from.
+    pub fn new() -> (Self, mpsc::Receiver<HandoffRequest>) {
+        let (request_tx, request_rx) = mpsc::channel(QUEUE_CAPACITY);
+        let handoffs = Self {
+            state: Arc::new(Mutex::new(HandoffState::default())),
+            request_tx,
+        };
+        (handoffs, request_rx)
+    }
+
+    /// Set the agents conversations can be handed to, once they're running.
+    pub fn set_targets(&self, targets: Vec<HandoffTarget>) {
+        self.lock().targets = targets;
+    }
+
+    /// The agents `from` can hand a conversation to.
+    pub fn targets_for(&self, from: &str) -> Vec<HandoffTarget> {
+        self.lock()
+            .targets
+            .iter()
+            .filter(|target| &*target.agent_id != from)
+            .cloned()
+            .collect()
+    }
+
+    /// Why `request` can't go ahead, if it can't.
+    pub fn check(&self, request: &HandoffRequest) -> Result<(), String> {
+        let state = self.lock();
+        if request.to == request.from {
+            return Err("this conversation is already yours".into());
+        }
+        if !state
+            .targets
+            .iter()
+            .any(|target| target.agent_id == request.to)
+        {
+            let available: Vec<&str> = state
+                .targets
+                .iter()
+                .filter(|target| target.agent_id != request.from)
+                .map(|target| &*target.agent_id)
+                .collect();
+            return Err(format!(
+                "unknown agent '{}', available agents: {}",
+                request.to,
+                available.join(", ")
+            ));
+        }
+        let handoffs = state
+            .routes
+            .get(&request.conversation_id)
+            .map_or(0, |route| route.handoffs);
+        if handoffs >= MAX_HANDOFFS_PER_CONVERSATION {
+            return Err(format!(
+                "this conversation has already been handed off {handoffs} times, \
+                 answer it yourself"
+            ));
+        }
+        Ok(())
+    }
+
+    /// Queue a checked request for the router.
+    pub fn submit(&self, request: HandoffRequest) -> Result<(), String> {
+        self.request_tx
+            .try_send(request)
+            .map_err(|error| format!("failed to queue handoff: {error}"))
+    }
+
+    /// Route the conversation to `agent_id` from now on. Called by the router
+    /// once the new agent's channel is open.
+    pub fn assign(&self, conversation_id: &str, agent_id: AgentId) {
+        let now = Instant::now();
+        let mut state = self.lock();
+        if state.routes.len() >= PRUNE_THRESHOLD {
+            state
+                .routes
+                .retain(|_, route| now.duration_since(route.last_seen) < ROUTE_TTL);
+        }
+        let route = state
+            .routes
+            .entry(conversation_id.to_string())
+            .or_insert_with(|| Route {
+                agent_id: agent_id.clone(),
+                handoffs: 0,
+                last_seen: now,
+            });
+        route.agent_id = agent_id;
+        route.handoffs += 1;
+        route.last_seen = now;
+    }
+
+    /// The agent a conversation was handed to, if it was.
+    pub fn route(&self, conversation_id: &str) -> Option<AgentId> {
+        let mut state = self.lock();
+        let route = state.routes.get_mut(conversation_id)?;
+        route.last_seen = Instant::now();
+        Some(route.agent_id.clone())
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HandoffState> {
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(from: &str, to: &str) -> HandoffRequest {
+        HandoffRequest {
+            conversation_id: "discord:1".into(),
+            from: from.into(),
+            to: to.into(),
+            reason: "billing question".into(),
+            summary: "Charged twice for March.".into(),
+        }
+    }
+
+    #[test]
+    fn test_check_and_route() {
+        let (handoffs, _request_rx) = Handoffs::new();
+        handoffs.set_targets(vec![
+            HandoffTarget {
+                agent_id: "main".into(),
+                description: None,
+            },
+            HandoffTarget {
+                agent_id: "billing".into(),
+                description: Some("Invoices and refunds.".into()),
+            },
+        ]);
+
+        let targets = handoffs.targets_for("main");
+        assert_eq!(targets.len(), 1);
+        assert_eq!(&*targets[0].agent_id, "billing");
+
+        assert!(handoffs.check(&request("main", "billing")).is_ok());
+        assert!(handoffs.check(&request("main", "main")).is_err());
+        assert_eq!(
+            handoffs.check(&request("main", "sales")),
+            Err("unknown agent 'sales', available agents: billing".into())
+        );
+
+        assert_eq!(handoffs.route("discord:1"), None);
+        handoffs.assign("discord:1", "billing".into());
+        assert_eq!(handoffs.route("discord:1").as_deref(), Some("billing"));
+    }
+
+    #[test]
+    fn test_handoff_limit() {
+        let (handoffs, _request_rx) = Handoffs::new();
+        handoffs.set_targets(vec![
+            HandoffTarget {
+                agent_id: "main".into(),
+                description: None,
+            },
+            HandoffTarget {
+                agent_id: "billing".into(),
+                description: None,
+            },
+        ]);
+
+        for round in 0..MAX_HANDOFFS_PER_CONVERSATION {
+            let (from, to) = if round % 2 == 0 {
+                ("main", "billing")
+            } else {
+                ("billing", "main")
+            };
+            assert!(handoffs.check(&request(from, to)).is_ok());
+            handoffs.assign("discord:1", to.into());
+        }
+        assert!(handoffs.check(&request("billing", "main")).is_err());
+    }
+}
@@ -11,7 +11,7 @@
 use crate::AgentDeps;
 use crate::ProcessType;
 use crate::config::IngestionConfig;
-use crate::llm::SpacebotModel;
+use crate::llm::{Priority, SpacebotModel};
 
 use anyhow::Context as _;
 use rig::agent::AgentBuilder;
@@ -380,7 +380,7 @@ async fn complete_ingestion_file(
 ///
 /// Chunks target `chunk_size` characters but won't split mid-line. If a single
 /// line exceeds `chunk_size`, it gets its own chunk.
-fn chunk_text(text: &str, chunk_size: usize) -> Vec<String> {
+pub(crate) fn chunk_text(text: &str, chunk_size: usize) -> Vec<String> {
     if text.len() <= chunk_size {
         return vec![text.to_string()];
     }
@@ -427,8 +427,9 @@ async fn process_chunk(
 
     let routing = deps.runtime_config.routing.load();
     let model_name = routing.resolve(ProcessType::Branch, None).to_string();
-    let model =
-        SpacebotModel::make(&deps.llm_manager, &model_name).with_routing((**routing).clone());
+    let model = SpacebotModel::make(&deps.llm_manager, &model_name)
+        .with_routing((**routing).clone())
+        .with_priority(Priority::Background);
 
     let conversation_logger =
         crate::conversation::history::ConversationLogger::new(deps.sqlite_pool.clone());
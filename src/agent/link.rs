@@ -0,0 +1,166 @@
+//! Carrying a user's conversation across surfaces.
+//!
+//! `/link <conversation>` links the current conversation with another one
+//! the sender has written in (see [`LinkStore`]). Before each turn a channel
+//! reads what's new in its linked conversations and adds it to history as
+//! one marked message per conversation, so a question asked in a DM can be
+//! picked up in a thread and the other way round. The first turn after a
+//! link, or after the channel opens, brings in the latest
+//! [`BACKFILL_MESSAGES`].
+
+use crate::AgentDeps;
+use crate::conversation::{ChannelStore, LinkStore, LinkedMessage};
+use crate::{InboundMessage, MessageContent};
+
+use std::collections::HashMap;
+
+/// Messages read from a linked conversation the first time.
+pub const BACKFILL_MESSAGES: usize = 20;
+
+/// Most new messages read from a linked conversation in one turn.
+const MAX_NEW_MESSAGES: usize = 50;
+
+/// A parsed `/link` or `/unlink` command.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LinkCommand {
+    /// List the linked conversations.
+    Show,
+    /// Link the conversation with this name or ID.
+    Link(String),
+    /// Unlink the conversation with this name or ID.
+    Unlink(String),
+    /// An `/unlink` without a conversation.
+    Invalid,
+}
+
+impl LinkCommand {
+    /// Parse a link command. Returns `None` for ordinary messages.
+    pub fn from_message(message: &InboundMessage) -> Option<Self> {
+        let MessageContent::Text(text) = &message.content else {
+            return None;
+        };
+        let text = text.trim();
+        let (command, rest) = text.split_once(char::is_whitespace).unwrap_or((text, ""));
+        Self::parse(command.strip_prefix('/')?, rest)
+    }
+
+    /// Parse a `link`, `links` or `unlink` command from its name, without
+    /// the prefix, and arguments. Returns `None` for other names.
+    pub fn parse(name: &str, args: &str) -> Option<Self> {
+        let target = args.trim().trim_start_matches('#').to_string();
+        Some(match (name, target.is_empty()) {
+            ("links", _) | ("link", true) => Self::Show,
+            ("link", false) => Self::Link(target),
+            ("unlink", true) => Self::Invalid,
+            ("unlink", false) => Self::Unlink(target),
+            _ => return None,
+        })
+    }
+}
+
+/// A linked conversation's messages as a transcript, with whoever said each.
+pub fn transcript(messages: &[LinkedMessage]) -> String {
+    let mut transcript = String::new();
+    for linked in messages {
+        let message = &linked.message;
+        let speaker = match message.role.as_str() {
+            "assistant" => "(you)",
+            _ => message.sender_name.as_deref().unwrap_or("User"),
+        };
+        transcript.push_str(&format!("{speaker}: {}\n", message.content));
+    }
+    transcript.trim_end().to_string()
+}
+
+/// What's new in `channel_id`'s linked conversations since `seen`, the last
+/// message read from each, as one rendered context per conversation.
+/// Updates `seen`, and forgets conversations that were unlinked.
+pub async fn new_context(
+    deps: &AgentDeps,
+    channel_id: &str,
+    seen: &mut HashMap<String, i64>,
+) -> Vec<String> {
+    let store = LinkStore::new(deps.sqlite_pool.clone());
+    let links = match store.linked(channel_id).await {
+        Ok(links) => links,
+        Err(error) => {
+            tracing::warn!(%error, channel_id, "failed to load conversation links");
+            return Vec::new();
+        }
+    };
+    seen.retain(|linked_id, _| links.iter().any(|link| &link.channel_id == linked_id));
+
+    let channel_store = ChannelStore::new(deps.sqlite_pool.clone());
+    let prompts = deps.runtime_config.prompts.load_full();
+    let mut contexts = Vec::new();
+    for link in links {
+        let (after, limit) = match seen.get(&link.channel_id) {
+            Some(seq) => (*seq, MAX_NEW_MESSAGES),
+            None => (0, BACKFILL_MESSAGES),
+        };
+        let messages = match store.messages_after(&link.channel_id, after, limit).await {
+            Ok(messages) => messages,
+            Err(error) => {
+                tracing::warn!(%error, channel_id, linked = %link.channel_id, "failed to load linked messages");
+                continue;
+            }
+        };
+        let Some(last) = messages.last() else {
+            seen.entry(link.channel_id).or_insert(after);
+            continue;
+        };
+        seen.insert(link.channel_id.clone(), last.seq);
+
+        let name = channel_store
+            .resolve_name(&link.channel_id)
+            .await
+            .unwrap_or_else(|| link.channel_id.clone());
+        let transcript = transcript(&messages);
+        contexts.push(
+            prompts
+                .render_system_linked_conversation(&name, &transcript)
+                .unwrap_or(transcript),
+        );
+    }
+    contexts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_link_commands() {
+        let message = |text: &str| InboundMessage {
+            id: "1".into(),
+            source: "discord".into(),
+            conversation_id: "discord:1:2".into(),
+            sender_id: "42".into(),
+            agent_id: None,
+            content: MessageContent::Text(text.into()),
+            timestamp: chrono::Utc::now(),
+            metadata: Default::default(),
+        };
+        assert_eq!(
+            LinkCommand::from_message(&message("/link")),
+            Some(LinkCommand::Show)
+        );
+        assert_eq!(
+            LinkCommand::from_message(&message("/links")),
+            Some(LinkCommand::Show)
+        );
+        assert_eq!(
+            LinkCommand::from_message(&message("/link #deploys")),
+            Some(LinkCommand::Link("deploys".into()))
+        );
+        assert_eq!(
+            LinkCommand::from_message(&message("/unlink discord:dm:42")),
+            Some(LinkCommand::Unlink("discord:dm:42".into()))
+        );
+        assert_eq!(
+            LinkCommand::from_message(&message("/unlink")),
+            Some(LinkCommand::Invalid)
+        );
+        assert_eq!(LinkCommand::from_message(&message("/linked")), None);
+    }
+}
@@ -0,0 +1,562 @@
+//! `/agent` chat commands for lightweight agents managed from chat.
+//!
+//! Admins can create a lightweight agent without touching config: a name, a
+//! preamble, a model alias and a few channel tools from [`SAFE_TOOLS`]. It
+//! becomes a persona of the agent it was created in, so conversations switch
+//! to it with `/persona <name>`. Managed agents are validated before they're
+//! written to `agents.managed.toml` in the instance directory, which config
+//! loading merges into each agent's personas and the file watcher reloads.
+//! Changes hold [`ManagedAgents::lock`] from reading the file to writing it
+//! back, and are written through a temporary file renamed into place, so
+//! concurrent commands don't lose each other's edits and nothing ever reads
+//! a half-written file. Personas defined in config.toml can't be changed
+//! from chat.
+
+use crate::agent::persona;
+use crate::config::PersonaDef;
+use crate::llm::routing::RoutingConfig;
+use crate::prompt_library::parse_assignments;
+use crate::{InboundMessage, MessageContent};
+
+use anyhow::Context as _;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+const COMMAND: &str = "/agent";
+
+/// Managed agents file, in the instance directory.
+pub const FILE_NAME: &str = "agents.managed.toml";
+
+/// Held across each read, change and write of the managed agents file.
+static UPDATE_LOCK: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+/// Channel tools a managed agent may be given. Nothing that runs code,
+/// sends files or schedules work.
+pub const SAFE_TOOLS: &[&str] = &[
+    "reply",
+    "react",
+    "skip",
+    "branch",
+    "calc",
+    "pin",
+    "scratchpad_get",
+    "scratchpad_set",
+    "scratchpad_list",
+];
+
+/// Words `/persona` takes as commands rather than names.
+const RESERVED_NAMES: &[&str] = &["reset", "default", "list"];
+
+const MAX_NAME_CHARS: usize = 32;
+const MAX_DESCRIPTION_CHARS: usize = 200;
+const MAX_PREAMBLE_CHARS: usize = 4_000;
+
+/// A parsed `/agent` command.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AgentCommand {
+    /// List this agent's managed agents.
+    List,
+    /// Create one. The preamble is the text after the first line, or a
+    /// `preamble="..."` setting.
+    Create {
+        name: String,
+        settings: Vec<(String, String)>,
+    },
+    /// Change settings of an existing one.
+    Set {
+        name: String,
+        settings: Vec<(String, String)>,
+    },
+    Delete(String),
+    /// An `/agent` command with arguments we don't understand.
+    Invalid,
+}
+
+impl AgentCommand {
+    /// Parse an `/agent` command. Returns `None` for ordinary messages.
+    pub fn from_message(message: &InboundMessage) -> Option<Self> {
+        let MessageContent::Text(text) = &message.content else {
+            return None;
+        };
+        let rest = text.trim().strip_prefix(COMMAND)?;
+        if !rest.is_empty() && !rest.starts_with(char::is_whitespace) {
+            return None;
+        }
+        Some(Self::parse(rest))
+    }
+
+    /// Parse the arguments following the command name.
+    pub fn parse(args: &str) -> Self {
+        let args = args.trim();
+        let (line, body) = args.split_once('\n').unwrap_or((args, ""));
+        let (verb, rest) = line
+            .trim()
+            .split_once(char::is_whitespace)
+            .unwrap_or((line.trim(), ""));
+        let (name, assignments) = rest
+            .trim()
+            .split_once(char::is_whitespace)
+            .unwrap_or((rest.trim(), ""));
+        let name = name.to_ascii_lowercase();
+        let settings = || {
+            let mut settings = parse_assignments(assignments)?;
+            if !body.trim().is_empty() {
+                settings.push(("preamble".into(), body.trim().to_string()));
+            }
+            Some(settings)
+        };
+        match verb.to_ascii_lowercase().as_str() {
+            "" | "list" if rest.trim().is_empty() => Self::List,
+            "create" if !name.is_empty() => match settings() {
+                Some(settings) => Self::Create { name, settings },
+                None => Self::Invalid,
+            },
+            "set" if !name.is_empty() => match settings() {
+                Some(settings) if !settings.is_empty() => Self::Set { name, settings },
+                _ => Self::Invalid,
+            },
+            "delete" if !name.is_empty() && assignments.is_empty() => Self::Delete(name),
+            _ => Self::Invalid,
+        }
+    }
+}
+
+/// The contents of the managed agents file.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ManagedAgents {
+    #[serde(default, rename = "agent")]
+    pub agents: Vec<ManagedAgent>,
+}
+
+/// A lightweight agent created with `/agent create`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ManagedAgent {
+    /// The agent it was created in, and is a persona of.
+    pub agent: String,
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    pub preamble: String,
+    /// A model name or routing tier. None keeps the channel model.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    /// Channel tools, from [`SAFE_TOOLS`]. `reply` is always available.
+    #[serde(default)]
+    pub tools: Vec<String>,
+    pub updated_by: String,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl ManagedAgent {
+    pub fn persona(&self) -> PersonaDef {
+        PersonaDef {
+            name: self.name.clone(),
+            description: self.description.clone(),
+            preamble: self.preamble.clone(),
+            tools: Some(self.tools.clone()),
+            model: self.model.clone(),
+            admin_only: false,
+        }
+    }
+
+    /// Apply `key=value` settings from a command.
+    fn apply(&mut self, settings: &[(String, String)]) -> Result<(), String> {
+        for (key, value) in settings {
+            let value = value.trim();
+            match key.as_str() {
+                "description" => self.description = value.to_string(),
+                "preamble" => self.preamble = value.to_string(),
+                "model" => {
+                    self.model = match value {
+                        "" | "default" | "none" => None,
+                        model => Some(model.to_string()),
+                    }
+                }
+                "tools" => {
+                    self.tools = match value {
+                        "" | "none" => Vec::new(),
+                        "safe" => SAFE_TOOLS.iter().map(|tool| tool.to_string()).collect(),
+                        tools => tools
+                            .split(',')
+                            .map(|tool| tool.trim().to_ascii_lowercase())
+                            .filter(|tool| !tool.is_empty())
+                            .collect(),
+                    }
+                }
+                other => {
+                    return Err(format!(
+                        "There's no setting `{other}`. Use `description`, `preamble`, `model` or `tools`."
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Check the agent against what config would accept for a persona, and
+    /// against the limits on agents made from chat.
+    pub fn validate(&self, routing: &RoutingConfig, providers: &[&str]) -> Result<(), String> {
+        if self.name.is_empty()
+            || self.name.chars().count() > MAX_NAME_CHARS
+            || !self
+                .name
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_')
+        {
+            return Err(format!(
+                "Names are up to {MAX_NAME_CHARS} lowercase letters, digits, `-` and `_`."
+            ));
+        }
+        if RESERVED_NAMES.contains(&self.name.as_str()) {
+            return Err(format!("`{}` is taken by `/persona`.", self.name));
+        }
+        if self.preamble.trim().is_empty() {
+            return Err(
+                "A preamble is needed: put it on the lines after the command, or use `preamble=\"...\"`."
+                    .into(),
+            );
+        }
+        if self.preamble.chars().count() > MAX_PREAMBLE_CHARS {
+            return Err(format!(
+                "The preamble is too long (at most {MAX_PREAMBLE_CHARS} characters)."
+            ));
+        }
+        if self.description.chars().count() > MAX_DESCRIPTION_CHARS
+            || self.description.contains('\n')
+        {
+            return Err(format!(
+                "The description is one line of at most {MAX_DESCRIPTION_CHARS} characters."
+            ));
+        }
+        if let Some(model) = &self.model {
+            let Some(resolved) = persona::resolve_model(model, routing) else {
+                return Err(format!(
+                    "`{model}` isn't a model or routing tier. Use `provider/model`, or a tier like `worker` or `coding`."
+                ));
+            };
+            let provider = resolved
+                .split_once('/')
+                .map_or("", |(provider, _)| provider);
+            if !providers.contains(&provider) {
+                return Err(format!(
+                    "Provider `{provider}` isn't configured. Available: {}.",
+                    providers.join(", ")
+                ));
+            }
+        }
+        if let Some(tool) = self
+            .tools
+            .iter()
+            .find(|tool| !SAFE_TOOLS.contains(&tool.as_str()))
+        {
+            return Err(format!(
+                "`{tool}` can't be given to an agent made from chat. Allowed: {}.",
+                SAFE_TOOLS.join(", ")
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// What an `/agent` command needs to know about the agent it runs in.
+pub struct AgentContext<'a> {
+    pub agent_id: &'a str,
+    pub sender_id: &'a str,
+    /// The agent's personas, managed ones included.
+    pub personas: &'a [PersonaDef],
+    pub routing: &'a RoutingConfig,
+    pub providers: &'a [&'a str],
+}
+
+impl ManagedAgents {
+    /// Where an instance keeps its managed agents.
+    pub fn path(instance_dir: &Path) -> PathBuf {
+        instance_dir.join(FILE_NAME)
+    }
+
+    /// Load the managed agents file. A missing file has no agents.
+    pub fn load(instance_dir: &Path) -> anyhow::Result<Self> {
+        let path = Self::path(instance_dir);
+        match std::fs::read_to_string(&path) {
+            Ok(content) => {
+                Self::parse(&content).with_context(|| format!("failed to parse {}", path.display()))
+            }
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(error) => Err(error).with_context(|| format!("failed to read {}", path.display())),
+        }
+    }
+
+    pub fn parse(content: &str) -> anyhow::Result<Self> {
+        Ok(toml::from_str(content)?)
+    }
+
+    /// Wait for any other change to the managed agents file to finish. Hold
+    /// the guard from reading the file until the change is saved.
+    pub async fn lock() -> tokio::sync::MutexGuard<'static, ()> {
+        UPDATE_LOCK.lock().await
+    }
+
+    /// Write the managed agents file, replacing it whole.
+    pub async fn save(&self, instance_dir: &Path) -> anyhow::Result<()> {
+        let path = Self::path(instance_dir);
+        let temporary = path.with_extension("tmp");
+        tokio::fs::write(&temporary, self.to_toml()?)
+            .await
+            .with_context(|| format!("failed to write {}", temporary.display()))?;
+        tokio::fs::rename(&temporary, &path)
+            .await
+            .with_context(|| format!("failed to write {}", path.display()))
+    }
+
+    pub fn to_toml(&self) -> anyhow::Result<String> {
+        Ok(format!(
+            "# Lightweight agents managed with /agent in chat. Edits here are reloaded.\n\n{}",
+            toml::to_string(self)?
+        ))
+    }
+
+    /// `agent_id`'s managed agents, as personas.
+    pub fn personas(&self, agent_id: &str) -> Vec<PersonaDef> {
+        self.agents
+            .iter()
+            .filter(|managed| managed.agent == agent_id)
+            .map(ManagedAgent::persona)
+            .collect()
+    }
+
+    fn position(&self, agent_id: &str, name: &str) -> Option<usize> {
+        self.agents
+            .iter()
+            .position(|managed| managed.agent == agent_id && managed.name == name)
+    }
+
+    /// Run `command`, returning the reply and whether anything changed and
+    /// needs saving.
+    pub fn run(&mut self, command: AgentCommand, context: &AgentContext) -> (String, bool) {
+        let defined_in_config = |name: &str| {
+            persona::find(context.personas, name).is_some()
+                && self.position(context.agent_id, name).is_none()
+        };
+        match command {
+            AgentCommand::List => (self.describe(context.agent_id), false),
+            AgentCommand::Create { name, settings } => {
+                if persona::find(context.personas, &name).is_some()
+                    || self.position(context.agent_id, &name).is_some()
+                {
+                    return (
+                        format!("`{name}` already exists. Change it with `{COMMAND} set {name}`."),
+                        false,
+                    );
+                }
+                let mut managed = ManagedAgent {
+                    agent: context.agent_id.to_string(),
+                    name: name.clone(),
+                    description: String::new(),
+                    preamble: String::new(),
+                    model: None,
+                    tools: SAFE_TOOLS.iter().map(|tool| tool.to_string()).collect(),
+                    updated_by: context.sender_id.to_string(),
+                    updated_at: Utc::now(),
+                };
+                if let Err(reason) = managed
+                    .apply(&settings)
+                    .and_then(|()| managed.validate(context.routing, context.providers))
+                {
+                    return (reason, false);
+                }
+                self.agents.push(managed);
+                (
+                    format!(
+                        "Created `{name}`. Once the config reloads, switch to it with `/persona {name}`."
+                    ),
+                    true,
+                )
+            }
+            AgentCommand::Set { name, settings } => {
+                if defined_in_config(&name) {
+                    return (
+                        format!(
+                            "`{name}` is defined in config.toml and can't be changed from chat."
+                        ),
+                        false,
+                    );
+                }
+                let Some(index) = self.position(context.agent_id, &name) else {
+                    return (format!("There's no managed agent `{name}`."), false);
+                };
+                let mut managed = self.agents[index].clone();
+                managed.updated_by = context.sender_id.to_string();
+                managed.updated_at = Utc::now();
+                if let Err(reason) = managed
+                    .apply(&settings)
+                    .and_then(|()| managed.validate(context.routing, context.providers))
+                {
+                    return (reason, false);
+                }
+                self.agents[index] = managed;
+                (format!("Updated `{name}`."), true)
+            }
+            AgentCommand::Delete(name) => {
+                if defined_in_config(&name) {
+                    return (
+                        format!(
+                            "`{name}` is defined in config.toml and can't be deleted from chat."
+                        ),
+                        false,
+                    );
+                }
+                match self.position(context.agent_id, &name) {
+                    Some(index) => {
+                        self.agents.remove(index);
+                        (format!("Deleted `{name}`."), true)
+                    }
+                    None => (format!("There's no managed agent `{name}`."), false),
+                }
+            }
+            AgentCommand::Invalid => (
+                format!(
+                    "Usage: `{COMMAND}`, `{COMMAND} create <name> [model=...] [tools=a,b] [description=\"...\"]` with the preamble on the following lines, `{COMMAND} set <name> key=value ...`, or `{COMMAND} delete <name>`."
+                ),
+                false,
+            ),
+        }
+    }
+
+    /// `/agent` text: the agent's managed agents.
+    fn describe(&self, agent_id: &str) -> String {
+        let lines: Vec<String> = self
+            .agents
+            .iter()
+            .filter(|managed| managed.agent == agent_id)
+            .map(|managed| {
+                let model = managed.model.as_deref().unwrap_or("channel model");
+                let tools = if managed.tools.is_empty() {
+                    "reply only".to_string()
+                } else {
+                    managed.tools.join(", ")
+                };
+                let mut line = format!("`{}` — {model}; tools: {tools}", managed.name);
+                if !managed.description.is_empty() {
+                    line.push_str(&format!("\n  {}", managed.description));
+                }
+                line
+            })
+            .collect();
+        if lines.is_empty() {
+            format!(
+                "No agents have been created from chat. Create one with `{COMMAND} create <name>`."
+            )
+        } else {
+            format!("Managed agents:\n{}", lines.join("\n"))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn context<'a>(personas: &'a [PersonaDef], routing: &'a RoutingConfig) -> AgentContext<'a> {
+        AgentContext {
+            agent_id: "main",
+            sender_id: "42",
+            personas,
+            routing,
+            providers: &["anthropic", "openrouter"],
+        }
+    }
+
+    #[test]
+    fn test_parse_agent_commands() {
+        assert_eq!(AgentCommand::parse(""), AgentCommand::List);
+        assert_eq!(
+            AgentCommand::parse("create Helper model=worker tools=react,calc\nYou answer briefly."),
+            AgentCommand::Create {
+                name: "helper".into(),
+                settings: vec![
+                    ("model".into(), "worker".into()),
+                    ("tools".into(), "react,calc".into()),
+                    ("preamble".into(), "You answer briefly.".into()),
+                ],
+            }
+        );
+        assert_eq!(
+            AgentCommand::parse("set helper description=\"Short answers\""),
+            AgentCommand::Set {
+                name: "helper".into(),
+                settings: vec![("description".into(), "Short answers".into())],
+            }
+        );
+        assert_eq!(AgentCommand::parse("set helper"), AgentCommand::Invalid);
+        assert_eq!(
+            AgentCommand::parse("delete helper"),
+            AgentCommand::Delete("helper".into())
+        );
+        assert_eq!(AgentCommand::parse("create"), AgentCommand::Invalid);
+    }
+
+    #[test]
+    fn test_create_validates_and_round_trips() {
+        let routing = RoutingConfig::default();
+        let configured = [PersonaDef {
+            name: "reviewer".into(),
+            description: String::new(),
+            preamble: "Review.".into(),
+            tools: None,
+            model: None,
+            admin_only: false,
+        }];
+        let context = context(&configured, &routing);
+        let mut managed = ManagedAgents::default();
+
+        let (reply, changed) = managed.run(AgentCommand::parse("create helper"), &context);
+        assert!(!changed && reply.starts_with("A preamble is needed"));
+        let (reply, changed) = managed.run(
+            AgentCommand::parse("create helper tools=shell\nBe brief."),
+            &context,
+        );
+        assert!(!changed && reply.starts_with("`shell` can't be given"));
+        let (reply, changed) = managed.run(
+            AgentCommand::parse("create helper model=groq/llama\nBe brief."),
+            &context,
+        );
+        assert!(!changed && reply.starts_with("Provider `groq` isn't configured"));
+        let (_, changed) = managed.run(AgentCommand::parse("create reviewer\nBe brief."), &context);
+        assert!(!changed);
+        let (_, changed) = managed.run(AgentCommand::parse("delete reviewer"), &context);
+        assert!(!changed);
+
+        let (_, changed) = managed.run(
+            AgentCommand::parse(
+                "create helper model=openrouter/deepseek-chat tools=react\nBe brief.",
+            ),
+            &context,
+        );
+        assert!(changed);
+        let (_, changed) = managed.run(AgentCommand::parse("set helper tools=none"), &context);
+        assert!(changed);
+
+        let reloaded = ManagedAgents::parse(&managed.to_toml().unwrap()).unwrap();
+        assert_eq!(reloaded, managed);
+        let personas = reloaded.personas("main");
+        assert_eq!(personas.len(), 1);
+        assert_eq!(
+            personas[0].model.as_deref(),
+            Some("openrouter/deepseek-chat")
+        );
+        assert_eq!(personas[0].tools, Some(Vec::new()));
+        assert!(reloaded.personas("other").is_empty());
+
+        let dir = tempfile::tempdir().unwrap();
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        runtime.block_on(managed.save(dir.path())).unwrap();
+        assert_eq!(ManagedAgents::load(dir.path()).unwrap(), managed);
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+}
@@ -0,0 +1,427 @@
+//! Resolved capabilities of one agent, for `spacebot agents describe`.
+//!
+//! Everything an agent ends up with comes from several places: its own
+//! `[[agents]]` entry, `[defaults]`, provider keys, bindings, and for
+//! schedules also the agent's database. The manifest merges them the same
+//! way the daemon does, and says why a tool is missing when it is.
+
+use crate::config::{Config, CronDef, ResolvedAgentConfig};
+use crate::llm::routing::provider_from_model;
+use crate::tools::{
+    BranchTool, BrowserTool, CancelTool, ChannelRecallTool, CronTool, ExecTool, FileTool,
+    MemoryDeleteTool, MemoryRecallTool, MemorySaveTool, ReactTool, ReplyTool, RouteTool,
+    SendFileTool, SetStatusTool, ShellTool, SkipTool, SpawnWorkerTool, WebSearchTool,
+};
+
+use anyhow::Context as _;
+use rig::tool::Tool as _;
+use serde::Serialize;
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+/// An agent as the daemon would run it.
+#[derive(Debug, Clone, Serialize)]
+pub struct AgentManifest {
+    pub id: String,
+    /// Whether messages no binding matches go to this agent.
+    pub default: bool,
+    pub workspace: PathBuf,
+    /// Model per process type, in routing order.
+    pub models: Vec<ModelTier>,
+    /// Task type to model, for workers and branches spawned with one.
+    pub task_overrides: BTreeMap<String, String>,
+    pub tools: Vec<ProcessTools>,
+    /// Set when tools are trimmed per turn to the most relevant ones.
+    pub tool_filter: Option<ToolFilterSummary>,
+    pub memory: MemorySummary,
+    pub limits: Limits,
+    pub schedules: Vec<Schedule>,
+    /// Where the schedules came from: `config` or `database`.
+    pub schedules_source: &'static str,
+    pub bindings: Vec<BoundChannel>,
+}
+
+/// The model a process type uses and what it falls back to.
+#[derive(Debug, Clone, Serialize)]
+pub struct ModelTier {
+    pub process: &'static str,
+    pub model: String,
+    pub fallbacks: Vec<String>,
+    /// Whether `[llm]` has a key for the model's provider.
+    pub provider_configured: bool,
+}
+
+/// The tools one process type is given.
+#[derive(Debug, Clone, Serialize)]
+pub struct ProcessTools {
+    pub process: &'static str,
+    pub tools: Vec<ToolEntry>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ToolEntry {
+    pub name: &'static str,
+    pub available: bool,
+    /// Whether calls with side effects wait for `/confirm` (preview mode).
+    pub requires_approval: bool,
+    /// Why the tool is unavailable.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ToolFilterSummary {
+    pub top_k: usize,
+    pub always_include: Vec<String>,
+}
+
+/// Where the agent's memories live and what feeds them.
+#[derive(Debug, Clone, Serialize)]
+pub struct MemorySummary {
+    pub sqlite: PathBuf,
+    pub lancedb: PathBuf,
+    /// Messages between automatic memory persistence branches, if on.
+    pub persistence_interval: Option<usize>,
+    /// Directory watched for files to ingest, if on.
+    pub ingest_dir: Option<PathBuf>,
+    /// Automatic retrieval before each turn, if on.
+    pub retrieval: Option<RetrievalSummary>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RetrievalSummary {
+    pub store: String,
+    pub top_k: usize,
+    pub min_score: f32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Limits {
+    pub max_turns: usize,
+    pub branch_max_turns: usize,
+    pub max_concurrent_branches: usize,
+    pub max_concurrent_workers: usize,
+    pub context_window: usize,
+    /// Per-user messages per minute and per hour, if rate limiting is on.
+    pub user_rate_limit: Option<(u32, u32)>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Schedule {
+    pub id: String,
+    pub interval_secs: u64,
+    pub delivery_target: String,
+    pub active_hours: Option<(u8, u8)>,
+    pub enabled: bool,
+}
+
+/// A binding that routes conversations to the agent.
+#[derive(Debug, Clone, Serialize)]
+pub struct BoundChannel {
+    pub platform: String,
+    /// Guild, workspace or chat the binding is limited to.
+    pub scope: Option<String>,
+    /// Channels the binding is limited to. Empty means all.
+    pub channel_ids: Vec<String>,
+    pub dm_allowed_users: Vec<String>,
+    /// Whether the platform is configured and enabled under `[messaging]`.
+    pub platform_enabled: bool,
+}
+
+impl AgentManifest {
+    /// Resolve `agent_id` from the config alone. Schedules are the ones in
+    /// the config; see [`describe_agent`] for the ones the agent has stored.
+    pub fn resolve(config: &Config, agent_id: &str) -> Option<Self> {
+        let agent = config.agents.iter().find(|agent| agent.id == agent_id)?;
+        let resolved = agent.resolve(&config.instance_dir, &config.defaults);
+        let default = config.default_agent_id() == agent_id;
+
+        Some(Self {
+            id: resolved.id.clone(),
+            default,
+            workspace: resolved.workspace.clone(),
+            models: model_tiers(config, &resolved),
+            task_overrides: resolved
+                .routing
+                .task_overrides
+                .iter()
+                .map(|(task, model)| (task.clone(), model.clone()))
+                .collect(),
+            tools: tools(&resolved),
+            tool_filter: resolved.tool_filter.enabled.then(|| ToolFilterSummary {
+                top_k: resolved.tool_filter.top_k,
+                always_include: resolved.tool_filter.always_include.clone(),
+            }),
+            memory: MemorySummary {
+                sqlite: resolved.sqlite_path(),
+                lancedb: resolved.lancedb_path(),
+                persistence_interval: resolved
+                    .memory_persistence
+                    .enabled
+                    .then_some(resolved.memory_persistence.message_interval),
+                ingest_dir: resolved.ingestion.enabled.then(|| resolved.ingest_dir()),
+                retrieval: resolved
+                    .retrieval
+                    .as_ref()
+                    .map(|retrieval| RetrievalSummary {
+                        store: retrieval.store.clone(),
+                        top_k: retrieval.top_k,
+                        min_score: retrieval.min_score,
+                    }),
+            },
+            limits: Limits {
+                max_turns: resolved.max_turns,
+                branch_max_turns: resolved.branch_max_turns,
+                max_concurrent_branches: resolved.max_concurrent_branches,
+                max_concurrent_workers: resolved.max_concurrent_workers,
+                context_window: resolved.context_window,
+                user_rate_limit: resolved.rate_limit.enabled.then_some((
+                    resolved.rate_limit.user_per_minute,
+                    resolved.rate_limit.user_per_hour,
+                )),
+            },
+            schedules: resolved.cron.iter().map(Schedule::from).collect(),
+            schedules_source: "config",
+            bindings: bound_channels(config, agent_id),
+        })
+    }
+}
+
+/// Resolve an agent, with the schedules from its database when it has one.
+/// Jobs created through the `cron` tool only exist there.
+pub async fn describe_agent(config: &Config, agent_id: &str) -> anyhow::Result<AgentManifest> {
+    let mut manifest = AgentManifest::resolve(config, agent_id)
+        .with_context(|| format!("no agent named '{agent_id}'"))?;
+
+    let sqlite_path = &manifest.memory.sqlite;
+    if sqlite_path.exists() {
+        let pool = sqlx::SqlitePool::connect(&format!("sqlite:{}?mode=ro", sqlite_path.display()))
+            .await
+            .with_context(|| format!("failed to open {}", sqlite_path.display()))?;
+        let stored = crate::cron::CronStore::new(pool.clone())
+            .load_all_unfiltered()
+            .await;
+        pool.close().await;
+
+        manifest.schedules = stored?
+            .into_iter()
+            .map(|job| Schedule {
+                id: job.id,
+                interval_secs: job.interval_secs,
+                delivery_target: job.delivery_target,
+                active_hours: job.active_hours,
+                enabled: job.enabled,
+            })
+            .collect();
+        manifest.schedules_source = "database";
+    }
+    Ok(manifest)
+}
+
+impl From<&CronDef> for Schedule {
+    fn from(cron: &CronDef) -> Self {
+        Self {
+            id: cron.id.clone(),
+            interval_secs: cron.interval_secs,
+            delivery_target: cron.delivery_target.clone(),
+            active_hours: cron.active_hours,
+            enabled: cron.enabled,
+        }
+    }
+}
+
+fn model_tiers(config: &Config, resolved: &ResolvedAgentConfig) -> Vec<ModelTier> {
+    let routing = &resolved.routing;
+    [
+        ("channel", &routing.channel),
+        ("branch", &routing.branch),
+        ("worker", &routing.worker),
+        ("compactor", &routing.compactor),
+        ("cortex", &routing.cortex),
+    ]
+    .into_iter()
+    .map(|(process, model)| ModelTier {
+        process,
+        model: model.clone(),
+        fallbacks: routing.get_fallbacks(model).to_vec(),
+        provider_configured: config.llm.key(provider_from_model(model)).is_some(),
+    })
+    .collect()
+}
+
+/// Mirrors the tool server factories in [`crate::tools`].
+fn tools(resolved: &ResolvedAgentConfig) -> Vec<ProcessTools> {
+    let preview = &resolved.preview;
+    let gated = |name: &str| preview.enabled && preview.tools.iter().any(|tool| tool == name);
+    let tool = |name: &'static str| ToolEntry {
+        name,
+        available: true,
+        requires_approval: gated(name),
+        reason: None,
+    };
+    let unavailable = |name: &'static str, reason: &str| ToolEntry {
+        available: false,
+        reason: Some(reason.to_string()),
+        ..tool(name)
+    };
+
+    let browser = if resolved.browser.enabled {
+        tool(BrowserTool::NAME)
+    } else {
+        unavailable(BrowserTool::NAME, "browser.enabled is false")
+    };
+    let web_search = if resolved.brave_search_key.is_some() {
+        tool(WebSearchTool::NAME)
+    } else {
+        unavailable(WebSearchTool::NAME, "no brave_search_key is set")
+    };
+
+    vec![
+        ProcessTools {
+            process: "channel",
+            tools: vec![
+                tool(ReplyTool::NAME),
+                tool(BranchTool::NAME),
+                tool(SpawnWorkerTool::NAME),
+                tool(RouteTool::NAME),
+                tool(CancelTool::NAME),
+                tool(SkipTool::NAME),
+                tool(SendFileTool::NAME),
+                tool(ReactTool::NAME),
+                tool(CronTool::NAME),
+            ],
+        },
+        ProcessTools {
+            process: "branch",
+            tools: vec![
+                tool(MemorySaveTool::NAME),
+                tool(MemoryRecallTool::NAME),
+                tool(MemoryDeleteTool::NAME),
+                tool(ChannelRecallTool::NAME),
+            ],
+        },
+        ProcessTools {
+            process: "worker",
+            tools: vec![
+                tool(ShellTool::NAME),
+                tool(FileTool::NAME),
+                tool(ExecTool::NAME),
+                tool(SetStatusTool::NAME),
+                browser,
+                web_search,
+            ],
+        },
+        ProcessTools {
+            process: "cortex",
+            tools: vec![tool(MemorySaveTool::NAME)],
+        },
+    ]
+}
+
+fn bound_channels(config: &Config, agent_id: &str) -> Vec<BoundChannel> {
+    let messaging = &config.messaging;
+    config
+        .bindings
+        .iter()
+        .filter(|binding| binding.agent_id == agent_id)
+        .map(|binding| BoundChannel {
+            platform: binding.channel.clone(),
+            scope: binding
+                .guild_id
+                .clone()
+                .or_else(|| binding.workspace_id.clone())
+                .or_else(|| binding.chat_id.clone()),
+            channel_ids: binding.channel_ids.clone(),
+            dm_allowed_users: binding.dm_allowed_users.clone(),
+            platform_enabled: match binding.channel.as_str() {
+                "discord" => messaging.discord.as_ref().is_some_and(|c| c.enabled),
+                "slack" => messaging.slack.as_ref().is_some_and(|c| c.enabled),
+                "telegram" => messaging.telegram.as_ref().is_some_and(|c| c.enabled),
+                "webhook" => messaging.webhook.as_ref().is_some_and(|c| c.enabled),
+                _ => false,
+            },
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load(toml: &str) -> Config {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        std::fs::write(&path, toml).unwrap();
+        Config::load_from_path(&path).unwrap()
+    }
+
+    #[test]
+    fn test_manifest_resolves_overrides_and_explains_missing_tools() {
+        let config = load(
+            r#"
+[llm]
+anthropic_key = "sk-ant-test"
+
+[defaults.routing]
+channel = "anthropic/claude-sonnet-4-20250514"
+worker = "nowhere/small-model"
+
+[defaults.routing.fallbacks]
+"anthropic/claude-sonnet-4-20250514" = ["anthropic/claude-haiku-4.5-20250514"]
+
+[defaults.preview]
+enabled = true
+
+[[agents]]
+id = "main"
+default = true
+
+[[agents]]
+id = "ops"
+max_turns = 9
+
+[agents.browser]
+enabled = true
+
+[[bindings]]
+agent_id = "ops"
+channel = "discord"
+guild_id = "42"
+"#,
+        );
+
+        let manifest = AgentManifest::resolve(&config, "ops").unwrap();
+        assert!(!manifest.default);
+        assert_eq!(manifest.limits.max_turns, 9);
+
+        let channel = &manifest.models[0];
+        assert_eq!(channel.fallbacks, ["anthropic/claude-haiku-4.5-20250514"]);
+        assert!(channel.provider_configured);
+        let worker = &manifest.models[2];
+        assert_eq!(worker.model, "nowhere/small-model");
+        assert!(!worker.provider_configured);
+
+        let worker_tools = &manifest.tools[2].tools;
+        let find = |name: &str| worker_tools.iter().find(|tool| tool.name == name).unwrap();
+        assert!(find("shell").requires_approval);
+        assert!(find("browser").available);
+        assert!(!find("web_search").available);
+        assert!(find("web_search").reason.is_some());
+
+        assert_eq!(manifest.bindings.len(), 1);
+        assert_eq!(manifest.bindings[0].scope.as_deref(), Some("42"));
+        assert!(!manifest.bindings[0].platform_enabled);
+
+        let main = AgentManifest::resolve(&config, "main").unwrap();
+        assert!(main.default);
+        assert!(main.bindings.is_empty());
+        assert!(
+            !main.tools[2]
+                .tools
+                .iter()
+                .any(|tool| tool.name == "browser" && tool.available)
+        );
+        assert!(AgentManifest::resolve(&config, "nobody").is_none());
+    }
+}
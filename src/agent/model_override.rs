@@ -0,0 +1,111 @@
+//! `/model` chat commands for per-channel model overrides.
+//!
+//! `/model set provider/model` makes that model answer the channel's turns
+//! in place of the agent's routing default, and `/model reset` goes back to
+//! the default. The override is stored on the channel row so it survives
+//! restarts. `/model` on its own shows the current model; changing it is
+//! limited to the agent's admin users.
+
+use crate::{InboundMessage, MessageContent};
+
+const COMMAND: &str = "/model";
+
+/// A parsed `/model` command.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ModelCommand {
+    Show,
+    Set(String),
+    Reset,
+    /// A `/model` command with arguments we don't understand.
+    Invalid,
+}
+
+impl ModelCommand {
+    /// Parse a `/model` command. Returns `None` for ordinary messages.
+    pub fn from_message(message: &InboundMessage) -> Option<Self> {
+        let MessageContent::Text(text) = &message.content else {
+            return None;
+        };
+        let rest = text.trim().strip_prefix(COMMAND)?;
+        if !rest.is_empty() && !rest.starts_with(char::is_whitespace) {
+            return None;
+        }
+        Some(Self::parse(rest))
+    }
+
+    /// Parse the arguments following the command name.
+    pub fn parse(args: &str) -> Self {
+        let words: Vec<&str> = args.split_whitespace().collect();
+        match words.as_slice() {
+            [] | ["show"] => Self::Show,
+            ["set", model] => Self::Set(model.to_string()),
+            ["reset"] => Self::Reset,
+            _ => Self::Invalid,
+        }
+    }
+
+    /// Whether running this command needs admin rights.
+    pub fn is_privileged(&self) -> bool {
+        matches!(self, Self::Set(_) | Self::Reset)
+    }
+}
+
+/// Whether `sender_id` on `source` is in `admin_users`, listed either by
+/// bare sender ID or as `source:sender_id`.
+pub fn is_admin(admin_users: &[String], source: &str, sender_id: &str) -> bool {
+    admin_users.iter().any(|user| {
+        user == sender_id
+            || user
+                .strip_prefix(source)
+                .and_then(|rest| rest.strip_prefix(':'))
+                == Some(sender_id)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(text: &str) -> InboundMessage {
+        InboundMessage {
+            id: "1".into(),
+            source: "discord".into(),
+            conversation_id: "discord:1:2".into(),
+            sender_id: "42".into(),
+            agent_id: None,
+            content: MessageContent::Text(text.into()),
+            timestamp: chrono::Utc::now(),
+            metadata: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_parse_model_commands() {
+        assert_eq!(
+            ModelCommand::from_message(&message("/model set openrouter/deepseek-chat")),
+            Some(ModelCommand::Set("openrouter/deepseek-chat".into()))
+        );
+        assert_eq!(
+            ModelCommand::from_message(&message("/model reset")),
+            Some(ModelCommand::Reset)
+        );
+        assert_eq!(
+            ModelCommand::from_message(&message("/model")),
+            Some(ModelCommand::Show)
+        );
+        assert_eq!(
+            ModelCommand::from_message(&message("/model set")),
+            Some(ModelCommand::Invalid)
+        );
+        assert_eq!(ModelCommand::from_message(&message("/models")), None);
+        assert_eq!(ModelCommand::from_message(&message("which model?")), None);
+    }
+
+    #[test]
+    fn test_is_admin() {
+        let admins = vec!["7".to_string(), "discord:42".to_string()];
+        assert!(is_admin(&admins, "discord", "42"));
+        assert!(is_admin(&admins, "slack", "7"));
+        assert!(!is_admin(&admins, "slack", "42"));
+    }
+}
@@ -0,0 +1,187 @@
+//! `/persona` chat commands for switching a conversation's persona.
+//!
+//! Personas are configured per agent with `[[agents.personas]]`: a preamble
+//! added to the channel prompt, the channel tools the persona may use, and
+//! the model answering its turns. `/persona <name>` switches the conversation
+//! to one and `/persona reset` goes back to the agent's own. A switch clears
+//! the context, keeping pins, and leaves a marker so the model knows earlier
+//! messages were with someone else. The persona is stored on the channel row
+//! so it survives restarts.
+
+use crate::config::PersonaDef;
+use crate::llm::routing::RoutingConfig;
+use crate::{InboundMessage, MessageContent, ProcessType};
+
+use std::collections::HashSet;
+
+const COMMAND: &str = "/persona";
+
+/// Channel tool every persona keeps, so it can always answer.
+const ALWAYS_ALLOWED_TOOL: &str = "reply";
+
+/// A parsed `/persona` command.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PersonaCommand {
+    /// Show the current persona and the ones available.
+    Show,
+    Switch(String),
+    Reset,
+    /// A `/persona` command with arguments we don't understand.
+    Invalid,
+}
+
+impl PersonaCommand {
+    /// Parse a `/persona` command. Returns `None` for ordinary messages.
+    pub fn from_message(message: &InboundMessage) -> Option<Self> {
+        let MessageContent::Text(text) = &message.content else {
+            return None;
+        };
+        let rest = text.trim().strip_prefix(COMMAND)?;
+        if !rest.is_empty() && !rest.starts_with(char::is_whitespace) {
+            return None;
+        }
+        Some(Self::parse(rest))
+    }
+
+    /// Parse the arguments following the command name.
+    pub fn parse(args: &str) -> Self {
+        let words: Vec<&str> = args.split_whitespace().collect();
+        match words.as_slice() {
+            [] | ["list"] => Self::Show,
+            ["reset"] | ["default"] => Self::Reset,
+            [name] => Self::Switch(name.to_ascii_lowercase()),
+            _ => Self::Invalid,
+        }
+    }
+}
+
+/// The persona named `name` in `personas`, ignoring case.
+pub fn find<'a>(personas: &'a [PersonaDef], name: &str) -> Option<&'a PersonaDef> {
+    personas
+        .iter()
+        .find(|persona| persona.name.eq_ignore_ascii_case(name))
+}
+
+/// The model answering `persona`'s turns, or None to keep the channel model.
+/// A routing tier resolves to the model routing has for it; an unknown tier
+/// resolves to None.
+pub fn model(persona: &PersonaDef, routing: &RoutingConfig) -> Option<String> {
+    resolve_model(persona.model.as_deref()?, routing)
+}
+
+/// A model name as given, or the model routing has for a routing tier.
+/// Experiment flags name their models the same way.
+pub fn resolve_model(model: &str, routing: &RoutingConfig) -> Option<String> {
+    if model.contains('/') {
+        return Some(model.to_string());
+    }
+    let resolved = match model {
+        "channel" => routing.resolve(ProcessType::Channel, None),
+        "branch" => routing.resolve(ProcessType::Branch, None),
+        "worker" => routing.resolve(ProcessType::Worker, None),
+        "compactor" => routing.resolve(ProcessType::Compactor, None),
+        "cortex" => routing.resolve(ProcessType::Cortex, None),
+        task_type => routing.task_overrides.get(task_type).map(String::as_str)?,
+    };
+    Some(resolved.to_string())
+}
+
+/// The channel tools `persona` may use, or None for all of them.
+pub fn allowed_tools(persona: &PersonaDef) -> Option<HashSet<String>> {
+    persona.tools.as_deref().map(tool_allowlist)
+}
+
+/// `tools` plus the tool every allowlist keeps.
+pub fn tool_allowlist(tools: &[String]) -> HashSet<String> {
+    tools
+        .iter()
+        .cloned()
+        .chain(std::iter::once(ALWAYS_ALLOWED_TOOL.to_string()))
+        .collect()
+}
+
+/// `/persona` text: the current persona and the ones `is_admin` may switch to.
+pub fn describe(personas: &[PersonaDef], current: Option<&str>, is_admin: bool) -> String {
+    let mut text = match current {
+        Some(name) => format!("This conversation uses the `{name}` persona."),
+        None => "This conversation uses the agent's own persona.".to_string(),
+    };
+    let available: Vec<String> = personas
+        .iter()
+        .filter(|persona| is_admin || !persona.admin_only)
+        .map(|persona| {
+            if persona.description.is_empty() {
+                format!("`{}`", persona.name)
+            } else {
+                format!("`{}` — {}", persona.name, persona.description)
+            }
+        })
+        .collect();
+    if available.is_empty() {
+        text.push_str(" No other personas are configured.");
+    } else {
+        text.push_str(&format!(
+            "\nSwitch with `{COMMAND} <name>`, or go back with `{COMMAND} reset`:\n{}",
+            available.join("\n")
+        ));
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn persona(name: &str, model: Option<&str>, tools: Option<&[&str]>) -> PersonaDef {
+        PersonaDef {
+            name: name.into(),
+            description: String::new(),
+            preamble: String::new(),
+            tools: tools.map(|tools| tools.iter().map(|tool| tool.to_string()).collect()),
+            model: model.map(Into::into),
+            admin_only: false,
+        }
+    }
+
+    #[test]
+    fn test_parse_persona_commands() {
+        assert_eq!(PersonaCommand::parse(""), PersonaCommand::Show);
+        assert_eq!(
+            PersonaCommand::parse(" Reviewer "),
+            PersonaCommand::Switch("reviewer".into())
+        );
+        assert_eq!(PersonaCommand::parse("reset"), PersonaCommand::Reset);
+        assert_eq!(PersonaCommand::parse("a b"), PersonaCommand::Invalid);
+    }
+
+    #[test]
+    fn test_persona_model_and_tools() {
+        let routing = RoutingConfig::default();
+        let tier = persona("quick", Some("worker"), Some(&["react"]));
+        assert_eq!(
+            model(&tier, &routing).as_deref(),
+            Some(routing.worker.as_str())
+        );
+        let task = persona("coder", Some("coding"), None);
+        assert_eq!(
+            model(&task, &routing),
+            routing.task_overrides.get("coding").cloned()
+        );
+        let named = persona("named", Some("openrouter/deepseek-chat"), None);
+        assert_eq!(
+            model(&named, &routing).as_deref(),
+            Some("openrouter/deepseek-chat")
+        );
+        assert_eq!(model(&persona("x", Some("nope"), None), &routing), None);
+
+        let tools = allowed_tools(&tier).unwrap();
+        assert!(tools.contains("react") && tools.contains("reply"));
+        assert!(allowed_tools(&task).is_none());
+
+        let personas = [tier, task];
+        assert_eq!(
+            find(&personas, "QUICK").map(|p| p.name.as_str()),
+            Some("quick")
+        );
+    }
+}
@@ -0,0 +1,199 @@
+//! Tool provenance: which tool results a channel reply drew on.
+//!
+//! The channel never sees the tool calls its branches and workers make,
+//! only their conclusions, so the results are collected as they complete
+//! and each reply is compared with them. A result that shares enough
+//! three-word runs with the reply is credited, after anything the model
+//! named itself in the `reply` tool's `via` argument. Credits are stored as
+//! [`Tool`](crate::citations::CitationOrigin::Tool) citations and rendered
+//! as a "via" footer.
+
+use crate::AgentDeps;
+use crate::agent::channel::ChannelState;
+use crate::citations::{self, Citation, url_host};
+
+use std::collections::{HashSet, VecDeque};
+use std::sync::{Arc, Mutex};
+
+/// Tool results kept per channel. Older ones are dropped.
+const MAX_TOOL_SOURCES: usize = 32;
+
+/// Words per run compared between a reply and a tool result.
+const SHINGLE_WORDS: usize = 3;
+
+/// Runs a reply must share with a result to credit it.
+const MIN_SHARED_SHINGLES: usize = 2;
+
+/// Tools whose results aren't information the reply could draw on.
+const NON_SOURCE_TOOLS: &[&str] = &[
+    "set_status",
+    "memory_save",
+    "memory_delete",
+    "scratchpad_set",
+    "share",
+];
+
+/// What a tool call's result came from: the tool, plus the file or host it
+/// read when its arguments name one, e.g. `file:docs/deploy.md`. `None` for
+/// tools that don't fetch information.
+pub fn label(tool_name: &str, args: &str) -> Option<String> {
+    if NON_SOURCE_TOOLS.contains(&tool_name) {
+        return None;
+    }
+    let args: serde_json::Value = serde_json::from_str(args).unwrap_or_default();
+    if let Some(path) = args["path"].as_str() {
+        return Some(format!("{tool_name}:{}", path.trim_start_matches("./")));
+    }
+    if let Some(url) = args["url"].as_str() {
+        return Some(format!("{tool_name}:{}", url_host(url)));
+    }
+    Some(tool_name.to_string())
+}
+
+#[derive(Debug)]
+struct ToolSource {
+    label: String,
+    shingles: HashSet<String>,
+}
+
+/// Recent tool results from a channel's branches and workers. Clones share
+/// them.
+#[derive(Debug, Clone, Default)]
+pub struct ToolSources {
+    sources: Arc<Mutex<VecDeque<ToolSource>>>,
+}
+
+impl ToolSources {
+    /// Keep a completed tool call's result.
+    pub fn record(&self, label: &str, result: &str) {
+        let shingles = shingles(result);
+        if shingles.is_empty() {
+            return;
+        }
+        let mut sources = self.lock();
+        if sources.len() == MAX_TOOL_SOURCES {
+            sources.pop_front();
+        }
+        sources.push_back(ToolSource {
+            label: label.to_string(),
+            shingles,
+        });
+    }
+
+    /// Labels of the results `reply` draws on, most overlap first.
+    pub fn matching(&self, reply: &str) -> Vec<String> {
+        let reply = shingles(reply);
+        let sources = self.lock();
+        let mut matches: Vec<(usize, &str)> = Vec::new();
+        for source in sources.iter().rev() {
+            let shared = source.shingles.intersection(&reply).count();
+            if shared >= MIN_SHARED_SHINGLES {
+                matches.push((shared, &source.label));
+            }
+        }
+        matches.sort_by(|a, b| b.0.cmp(&a.0));
+        matches
+            .into_iter()
+            .map(|(_, label)| label.to_string())
+            .collect()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, VecDeque<ToolSource>> {
+        self.sources
+            .lock()
+            .unwrap_or_else(|error| error.into_inner())
+    }
+}
+
+fn shingles(text: &str) -> HashSet<String> {
+    let words: Vec<String> = text
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect();
+    words
+        .windows(SHINGLE_WORDS)
+        .map(|window| window.join(" "))
+        .collect()
+}
+
+/// Credits a channel's replies to its tool results, when provenance is on.
+#[derive(Clone)]
+pub struct Provenance {
+    deps: AgentDeps,
+    sources: ToolSources,
+}
+
+impl Provenance {
+    pub fn for_channel(state: &ChannelState) -> Option<Self> {
+        state
+            .deps
+            .runtime_config
+            .provenance
+            .load()
+            .enabled
+            .then(|| Self {
+                deps: state.deps.clone(),
+                sources: state.tool_sources.clone(),
+            })
+    }
+
+    /// Tool citations for `reply`: what the model named in `via`, then the
+    /// results the reply matches.
+    pub fn cite(&self, reply: &str, via: &[String]) -> Vec<Citation> {
+        let max_sources = self.deps.runtime_config.provenance.load().max_sources;
+        let named = via
+            .iter()
+            .map(|label| label.trim())
+            .filter(|label| !label.is_empty())
+            .map(str::to_string);
+        let cited = named
+            .chain(self.sources.matching(reply))
+            .map(Citation::tool)
+            .collect();
+        citations::dedup(cited)
+            .into_iter()
+            .take(max_sources)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_label_names_what_the_tool_read() {
+        assert_eq!(
+            label("file", r#"{"path": "./docs/deploy.md"}"#).as_deref(),
+            Some("file:docs/deploy.md")
+        );
+        assert_eq!(
+            label("browser", r#"{"url": "https://docs.rs/tokio/latest"}"#).as_deref(),
+            Some("browser:docs.rs")
+        );
+        assert_eq!(
+            label("web_search", r#"{"query": "tokio"}"#).as_deref(),
+            Some("web_search")
+        );
+        assert_eq!(label("set_status", "{}"), None);
+    }
+
+    #[test]
+    fn test_matching_credits_overlapping_results() {
+        let sources = ToolSources::default();
+        sources.record(
+            "file:docs/deploy.md",
+            "Deploys run from the release branch. Run make deploy after tagging.",
+        );
+        sources.record(
+            "web_search",
+            "Tokio 1.40 adds a new scheduler metric for task polls.",
+        );
+        sources.record("memory_recall", "Ana prefers async Rust.");
+
+        let reply = "According to the docs you should run make deploy after tagging, from the release branch.";
+        assert_eq!(sources.matching(reply), vec!["file:docs/deploy.md"]);
+        assert!(sources.matching("Sounds good!").is_empty());
+    }
+}
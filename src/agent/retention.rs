@@ -0,0 +1,432 @@
+//! Conversation retention: expire idle conversations.
+//!
+//! A conversation whose last message is older than its channel's
+//! `idle_days` has expired. Expiring it summarizes the transcript into an
+//! event memory (extracting other memories along the way, like compaction
+//! does), then either writes the transcript to a gzipped JSONL file in the
+//! agent's archives directory and deletes it, or just deletes it. Channels
+//! with the `keep` action never expire.
+//!
+//! [`find_expired`] only reads, so it also backs the `spacebot retention`
+//! dry-run report.
+
+use crate::AgentDeps;
+use crate::agent::cortex::CortexLogger;
+use crate::config::{RetentionAction, RetentionConfig, RetentionPolicy};
+use crate::memory::types::{Memory, MemoryType};
+
+use anyhow::Context as _;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::{Row as _, SqlitePool};
+
+use std::io::Write as _;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Transcripts longer than this are cut to their most recent part before
+/// summarization.
+const MAX_SUMMARY_INPUT_CHARS: usize = 100_000;
+
+/// A conversation that has expired under its channel's retention policy.
+#[derive(Debug, Clone, Serialize)]
+pub struct ExpiredConversation {
+    pub channel_id: String,
+    pub message_count: i64,
+    pub last_message_at: DateTime<Utc>,
+    /// Whole days since the last message.
+    pub idle_days: i64,
+    pub action: RetentionAction,
+    pub summarize: bool,
+}
+
+/// A message as written to an archive file.
+#[derive(Debug, Serialize)]
+struct ArchivedMessage {
+    id: String,
+    role: String,
+    sender_name: Option<String>,
+    sender_id: Option<String>,
+    content: String,
+    metadata: Option<String>,
+    fork_id: Option<String>,
+    created_at: DateTime<Utc>,
+}
+
+/// Spawn the retention loop for an agent.
+///
+/// Wakes every `check_interval_secs` and expires idle conversations while
+/// retention is enabled. Runs until the returned JoinHandle is aborted.
+pub fn spawn_retention_loop(archives_dir: PathBuf, deps: AgentDeps) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        loop {
+            let config = deps.runtime_config.retention.load_full();
+            if config.enabled {
+                match expire_conversations(&deps, &archives_dir).await {
+                    Ok(0) => {}
+                    Ok(count) => tracing::info!(count, "expired idle conversations"),
+                    Err(error) => tracing::error!(%error, "retention pass failed"),
+                }
+            }
+            tokio::time::sleep(Duration::from_secs(config.check_interval_secs.max(60))).await;
+        }
+    })
+}
+
+/// List the conversations that have expired as of `now`, longest idle first.
+pub async fn find_expired(
+    pool: &SqlitePool,
+    config: &RetentionConfig,
+    now: DateTime<Utc>,
+) -> anyhow::Result<Vec<ExpiredConversation>> {
+    let rows = sqlx::query(
+        "SELECT channel_id, COUNT(*) AS message_count, MAX(created_at) AS last_message_at \
+         FROM conversation_messages \
+         GROUP BY channel_id \
+         ORDER BY last_message_at",
+    )
+    .fetch_all(pool)
+    .await
+    .context("failed to list conversations")?;
+
+    let mut expired = Vec::new();
+    for row in rows {
+        let channel_id: String = row.try_get("channel_id").unwrap_or_default();
+        let Ok(last_message_at) = row.try_get::<DateTime<Utc>, _>("last_message_at") else {
+            continue;
+        };
+        let policy = config.policy_for(&channel_id);
+        if !is_expired(&policy, last_message_at, now) {
+            continue;
+        }
+        expired.push(ExpiredConversation {
+            message_count: row.try_get("message_count").unwrap_or_default(),
+            last_message_at,
+            idle_days: (now - last_message_at).num_days(),
+            action: policy.action,
+            summarize: policy.summarize,
+            channel_id,
+        });
+    }
+    Ok(expired)
+}
+
+/// Whether a conversation last active at `last_message_at` has expired.
+/// An `idle_days` of 0 turns expiry off.
+fn is_expired(
+    policy: &RetentionPolicy,
+    last_message_at: DateTime<Utc>,
+    now: DateTime<Utc>,
+) -> bool {
+    policy.action != RetentionAction::Keep
+        && policy.idle_days > 0
+        && now - last_message_at >= chrono::Duration::days(policy.idle_days.into())
+}
+
+/// Expire every conversation that is due. Returns how many were expired.
+/// A conversation that fails is logged and left for the next pass.
+pub async fn expire_conversations(deps: &AgentDeps, archives_dir: &Path) -> anyhow::Result<usize> {
+    let config = deps.runtime_config.retention.load_full();
+    let expired = find_expired(&deps.sqlite_pool, &config, Utc::now()).await?;
+
+    let mut count = 0;
+    for conversation in &expired {
+        match expire_conversation(deps, archives_dir, conversation).await {
+            Ok(true) => count += 1,
+            Ok(false) => {}
+            Err(error) => tracing::warn!(
+                channel_id = %conversation.channel_id,
+                %error,
+                "failed to expire conversation"
+            ),
+        }
+    }
+    Ok(count)
+}
+
+/// Summarize, archive and delete one conversation. Returns false if it got
+/// a new message in the meantime and was left alone.
+async fn expire_conversation(
+    deps: &AgentDeps,
+    archives_dir: &Path,
+    conversation: &ExpiredConversation,
+) -> anyhow::Result<bool> {
+    let channel_id = conversation.channel_id.as_str();
+    let messages = load_messages(&deps.sqlite_pool, channel_id).await?;
+    let Some(last) = messages.last() else {
+        return Ok(false);
+    };
+    let last_message_at = last.created_at;
+
+    if conversation.summarize {
+        summarize_into_memory(deps, channel_id, &messages).await?;
+    }
+
+    let archive = if conversation.action == RetentionAction::Archive {
+        Some(write_archive(archives_dir, channel_id, messages).await?)
+    } else {
+        None
+    };
+
+    let mut transaction = deps.sqlite_pool.begin().await?;
+    let latest: Option<DateTime<Utc>> = sqlx::query_scalar(
+        "SELECT MAX(created_at) FROM conversation_messages WHERE channel_id = ?",
+    )
+    .bind(channel_id)
+    .fetch_one(&mut *transaction)
+    .await?;
+    if latest != Some(last_message_at) {
+        // The conversation picked up again. The summary stays in memory; an
+        // archive written for it is superseded by the next one.
+        tracing::info!(%channel_id, "conversation resumed, not expiring it");
+        return Ok(false);
+    }
+    delete_transcript(&mut transaction, channel_id).await?;
+    transaction.commit().await?;
+
+    let action = match &archive {
+        Some(path) => format!("archived to {}", path.display()),
+        None => "deleted".to_string(),
+    };
+    CortexLogger::new(deps.sqlite_pool.clone()).log(
+        "conversation_expired",
+        &format!(
+            "Expired {channel_id} after {} idle days: {} messages {action}",
+            conversation.idle_days, conversation.message_count
+        ),
+        Some(serde_json::json!({
+            "channel_id": channel_id,
+            "message_count": conversation.message_count,
+            "idle_days": conversation.idle_days,
+            "action": conversation.action,
+            "summarized": conversation.summarize,
+            "archive": archive,
+        })),
+    );
+    Ok(true)
+}
+
+/// Delete a conversation's messages, forks, pins, scratchpad, queued replies,
+/// share links, escalations, links to other conversations, and branch,
+/// worker and turn runs. The channel row itself stays, so the tables'
+/// `ON DELETE CASCADE` never fires and each is cleared here.
+pub(crate) async fn delete_transcript(
+    connection: &mut sqlx::SqliteConnection,
+    channel_id: &str,
+) -> sqlx::Result<()> {
+    for table in [
+        "conversation_messages",
+        "conversation_forks",
+        "channel_active_forks",
+        "branch_runs",
+        "worker_runs",
+        "turn_runs",
+        "channel_scratchpad",
+        "channel_pins",
+        "outbox",
+        "share_links",
+        "conversation_escalations",
+    ] {
+        sqlx::query(&format!("DELETE FROM {table} WHERE channel_id = ?"))
+            .bind(channel_id)
+            .execute(&mut *connection)
+            .await?;
+    }
+    // A link is stored once, from either end.
+    sqlx::query("DELETE FROM conversation_links WHERE channel_id = ? OR linked_channel_id = ?")
+        .bind(channel_id)
+        .bind(channel_id)
+        .execute(&mut *connection)
+        .await?;
+    Ok(())
+}
+
+async fn load_messages(
+    pool: &SqlitePool,
+    channel_id: &str,
+) -> anyhow::Result<Vec<ArchivedMessage>> {
+    let rows = sqlx::query(
+        "SELECT id, role, sender_name, sender_id, content, metadata, fork_id, created_at \
+         FROM conversation_messages \
+         WHERE channel_id = ? \
+         ORDER BY created_at, rowid",
+    )
+    .bind(channel_id)
+    .fetch_all(pool)
+    .await
+    .context("failed to load conversation")?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| ArchivedMessage {
+            id: row.try_get("id").unwrap_or_default(),
+            role: row.try_get("role").unwrap_or_default(),
+            sender_name: row.try_get("sender_name").ok(),
+            sender_id: row.try_get("sender_id").ok(),
+            content: row.try_get("content").unwrap_or_default(),
+            metadata: row.try_get("metadata").ok(),
+            fork_id: row.try_get("fork_id").ok(),
+            created_at: row
+                .try_get("created_at")
+                .unwrap_or_else(|_| chrono::Utc::now()),
+        })
+        .collect())
+}
+
+/// Run the compactor over the transcript and save its summary as an event
+/// memory for the channel.
+async fn summarize_into_memory(
+    deps: &AgentDeps,
+    channel_id: &str,
+    messages: &[ArchivedMessage],
+) -> anyhow::Result<()> {
+    let mut transcript = String::new();
+    for message in messages {
+        let speaker = match message.role.as_str() {
+            "assistant" => "Assistant",
+            _ => message.sender_name.as_deref().unwrap_or("User"),
+        };
+        transcript.push_str(&format!("{speaker}: {}\n", message.content));
+    }
+    if transcript.len() > MAX_SUMMARY_INPUT_CHARS {
+        let mut start = transcript.len() - MAX_SUMMARY_INPUT_CHARS;
+        while !transcript.is_char_boundary(start) {
+            start += 1;
+        }
+        transcript.drain(..start);
+    }
+
+    let compactor_prompt = deps
+        .runtime_config
+        .prompts
+        .load()
+        .render_static("compactor")
+        .context("failed to render compactor prompt")?;
+    let summary =
+        crate::agent::compactor::summarize_transcript(deps, &compactor_prompt, &transcript)
+            .await
+            .context("failed to summarize conversation")?;
+
+    let first = messages
+        .first()
+        .map(|message| message.created_at.date_naive());
+    let last = messages
+        .last()
+        .map(|message| message.created_at.date_naive());
+    let content = match (first, last) {
+        (Some(first), Some(last)) => {
+            format!("Conversation in {channel_id} from {first} to {last}: {summary}")
+        }
+        _ => format!("Conversation in {channel_id}: {summary}"),
+    };
+    let memory = Memory::new(&content, MemoryType::Event)
+        .with_source("retention")
+        .with_channel_id(Arc::from(channel_id));
+
+    deps.memory_search
+        .store()
+        .save(&memory)
+        .await
+        .context("failed to save conversation summary")?;
+    let embedding = deps
+        .memory_search
+        .embedding_model_arc()
+        .embed_one(&content)
+        .await
+        .context("failed to embed conversation summary")?;
+    deps.memory_search
+        .embedding_table()
+        .store(&memory.id, &content, &embedding)
+        .await
+        .context("failed to store conversation summary embedding")?;
+    Ok(())
+}
+
+/// Write the transcript to `<archives_dir>/conversations/` as gzipped JSONL,
+/// one message per line. Returns the file's path.
+async fn write_archive(
+    archives_dir: &Path,
+    channel_id: &str,
+    messages: Vec<ArchivedMessage>,
+) -> anyhow::Result<PathBuf> {
+    let file_stem: String = channel_id
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    let path = archives_dir.join("conversations").join(format!(
+        "{file_stem}-{}.jsonl.gz",
+        Utc::now().format("%Y%m%d%H%M%S")
+    ));
+
+    let target = path.clone();
+    tokio::task::spawn_blocking(move || -> anyhow::Result<()> {
+        if let Some(parent) = target.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("failed to create {}", parent.display()))?;
+        }
+        let file = std::fs::File::create(&target)
+            .with_context(|| format!("failed to create {}", target.display()))?;
+        let mut encoder = flate2::write::GzEncoder::new(file, flate2::Compression::default());
+        for message in &messages {
+            serde_json::to_writer(&mut encoder, message)?;
+            encoder.write_all(b"\n")?;
+        }
+        encoder.finish()?.sync_all()?;
+        Ok(())
+    })
+    .await
+    .context("archive task panicked")??;
+
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ChannelRetentionConfig;
+
+    #[test]
+    fn test_channel_policies() {
+        let mut config = RetentionConfig {
+            idle_days: 30,
+            ..Default::default()
+        };
+        config.channels.insert(
+            "discord".into(),
+            ChannelRetentionConfig {
+                action: Some(RetentionAction::Delete),
+                ..Default::default()
+            },
+        );
+        config.channels.insert(
+            "discord:1:2".into(),
+            ChannelRetentionConfig {
+                action: Some(RetentionAction::Keep),
+                ..Default::default()
+            },
+        );
+
+        let now = Utc::now();
+        let last_message_at = now - chrono::Duration::days(45);
+
+        let slack = config.policy_for("slack:T1:C1");
+        assert_eq!(slack.action, RetentionAction::Archive);
+        assert!(is_expired(&slack, last_message_at, now));
+        assert!(!is_expired(&slack, now - chrono::Duration::days(29), now));
+
+        let discord = config.policy_for("discord:3:4");
+        assert_eq!(discord.action, RetentionAction::Delete);
+        assert_eq!(discord.idle_days, 30);
+        assert!(is_expired(&discord, last_message_at, now));
+
+        let kept = config.policy_for("discord:1:2");
+        assert!(!is_expired(&kept, last_message_at, now));
+    }
+}
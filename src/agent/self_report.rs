@@ -0,0 +1,128 @@
+//! Structured reports at the end of long worker runs.
+//!
+//! A worker's result is whatever prose the model ends with, which after a
+//! long run tends to describe the last few steps rather than the run. With
+//! `[defaults.self_report]` enabled, a worker that took at least `min_turns`
+//! turns is asked for a [`SelfReport`] before it finishes: its goals, the
+//! actions it took, the artifacts it produced and what it left unresolved,
+//! as JSON conforming to [`SelfReport::schema`]. A reply that doesn't
+//! conform is sent back once with the error. The report is stored with the
+//! worker run, shown in the dashboard's timeline and appended to the result
+//! the channel gets.
+
+use anyhow::Context as _;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// A worker's account of its run.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct SelfReport {
+    /// What the run set out to do.
+    pub goals: Vec<String>,
+    /// What was done, in order.
+    pub actions: Vec<String>,
+    /// Files, URLs, records and other things the run produced or changed.
+    pub artifacts: Vec<String>,
+    /// Anything left undone, failed, or needing a person's decision.
+    pub unresolved: Vec<String>,
+}
+
+impl SelfReport {
+    /// The JSON Schema a report must conform to, as sent to the model.
+    pub fn schema() -> String {
+        schemars::schema_for!(SelfReport).to_value().to_string()
+    }
+
+    /// Parse the model's reply. Fails on anything that doesn't conform to
+    /// the schema, or a report without a goal.
+    pub fn parse(response: &str) -> anyhow::Result<Self> {
+        let cleaned = response
+            .trim()
+            .trim_start_matches("```json")
+            .trim_start_matches("```")
+            .trim_end_matches("```")
+            .trim();
+        let report: Self = serde_json::from_str(cleaned)
+            .context("the reply isn't a report matching the schema")?;
+        anyhow::ensure!(
+            report.goals.iter().any(|goal| !goal.trim().is_empty()),
+            "the report has no goals"
+        );
+        Ok(report)
+    }
+
+    /// The report as Markdown, for the channel.
+    pub fn render(&self) -> String {
+        let mut text = String::from("**Run report**\n");
+        for (title, items) in [
+            ("Goals", &self.goals),
+            ("Actions", &self.actions),
+            ("Artifacts", &self.artifacts),
+            ("Unresolved", &self.unresolved),
+        ] {
+            let items: Vec<&str> = items
+                .iter()
+                .map(|item| item.trim())
+                .filter(|item| !item.is_empty())
+                .collect();
+            if items.is_empty() {
+                continue;
+            }
+            text.push_str(&format!("\n{title}:\n"));
+            for item in items {
+                text.push_str(&format!("- {item}\n"));
+            }
+        }
+        text.trim_end().to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_checks_the_schema() {
+        let report = SelfReport::parse(
+            "```json\n{\"goals\": [\"Upgrade the staging cluster\"], \
+             \"actions\": [\"Drained node 1\", \"Upgraded node 1\"], \
+             \"artifacts\": [\"PR #412\"], \"unresolved\": []}\n```",
+        )
+        .unwrap();
+        assert_eq!(report.actions.len(), 2);
+
+        // Missing field, unknown field, no goals.
+        assert!(SelfReport::parse(r#"{"goals": ["x"], "actions": [], "artifacts": []}"#).is_err());
+        assert!(
+            SelfReport::parse(
+                r#"{"goals": ["x"], "actions": [], "artifacts": [], "unresolved": [], "notes": ""}"#
+            )
+            .is_err()
+        );
+        assert!(
+            SelfReport::parse(
+                r#"{"goals": [" "], "actions": [], "artifacts": [], "unresolved": []}"#
+            )
+            .is_err()
+        );
+        assert!(SelfReport::parse("All done, the cluster is upgraded.").is_err());
+    }
+
+    #[test]
+    fn test_render_skips_empty_sections() {
+        let report = SelfReport {
+            goals: vec!["Upgrade the staging cluster".into()],
+            actions: vec!["Upgraded both nodes".into()],
+            artifacts: vec![],
+            unresolved: vec!["Node 2 logs a TLS warning".into(), "".into()],
+        };
+        assert_eq!(
+            report.render(),
+            "**Run report**\n\nGoals:\n- Upgrade the staging cluster\n\n\
+             Actions:\n- Upgraded both nodes\n\n\
+             Unresolved:\n- Node 2 logs a TLS warning"
+        );
+        assert!(SelfReport::schema().contains("unresolved"));
+    }
+}
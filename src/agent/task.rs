@@ -0,0 +1,170 @@
+//! Background tasks: workers that report to the user directly.
+//!
+//! A task is a fire-and-forget worker started with `start_task`. Instead of
+//! handing its result to the channel to relay, the channel posts the worker's
+//! progress and final result straight to the conversation, so a long job
+//! doesn't hold up the chat and doesn't need a turn to be reported.
+
+use crate::WorkerId;
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Progress is posted at most this often per task, so a busy worker doesn't
+/// flood the conversation.
+pub const PROGRESS_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Lifecycle statuses every worker reports, which say nothing to the user.
+const SILENT_STATUSES: &[&str] = &["running", "completed"];
+
+#[derive(Debug)]
+struct Task {
+    started_at: Instant,
+    last_posted: Option<Instant>,
+    last_status: Option<String>,
+}
+
+/// The channel's running tasks. Clones share them.
+#[derive(Debug, Clone, Default)]
+pub struct BackgroundTasks {
+    tasks: Arc<Mutex<HashMap<WorkerId, Task>>>,
+}
+
+impl BackgroundTasks {
+    /// Track a worker as a task.
+    pub fn register(&self, worker_id: WorkerId, now: Instant) {
+        self.lock().insert(
+            worker_id,
+            Task {
+                started_at: now,
+                last_posted: None,
+                last_status: None,
+            },
+        );
+    }
+
+    /// The progress message to post for a status update, if the worker is a
+    /// task and one is due.
+    pub fn progress(&self, worker_id: WorkerId, status: &str, now: Instant) -> Option<String> {
+        let status = status.trim();
+        if status.is_empty() || SILENT_STATUSES.contains(&status) {
+            return None;
+        }
+        let mut tasks = self.lock();
+        let task = tasks.get_mut(&worker_id)?;
+        if task.last_status.as_deref() == Some(status) {
+            return None;
+        }
+        if task
+            .last_posted
+            .is_some_and(|posted| now.duration_since(posted) < PROGRESS_INTERVAL)
+        {
+            return None;
+        }
+        task.last_posted = Some(now);
+        task.last_status = Some(status.to_string());
+        Some(format!(
+            "Task {} ({}): {status}",
+            short_id(worker_id),
+            format_elapsed(now.duration_since(task.started_at))
+        ))
+    }
+
+    /// Stop tracking a finished task, returning the result message to post.
+    /// `None` if the worker wasn't a task.
+    pub fn finish(&self, worker_id: WorkerId, result: &str, now: Instant) -> Option<String> {
+        let task = self.lock().remove(&worker_id)?;
+        Some(format!(
+            "Task {} finished after {}:\n\n{}",
+            short_id(worker_id),
+            format_elapsed(now.duration_since(task.started_at)),
+            result.trim()
+        ))
+    }
+
+    /// One line per running task, oldest first, for `!task list`.
+    pub fn list(&self, now: Instant) -> Vec<String> {
+        let tasks = self.lock();
+        let mut running: Vec<_> = tasks.iter().collect();
+        running.sort_by_key(|(_, task)| task.started_at);
+        running
+            .into_iter()
+            .map(|(worker_id, task)| {
+                format!(
+                    "Task {} ({}): {}",
+                    short_id(*worker_id),
+                    format_elapsed(now.duration_since(task.started_at)),
+                    task.last_status.as_deref().unwrap_or("started")
+                )
+            })
+            .collect()
+    }
+
+    /// Stop tracking a task without posting anything, e.g. when cancelled.
+    pub fn remove(&self, worker_id: WorkerId) {
+        self.lock().remove(&worker_id);
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<WorkerId, Task>> {
+        self.tasks
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// The first segment of the worker ID, enough to tell tasks apart in chat.
+fn short_id(worker_id: WorkerId) -> String {
+    worker_id.to_string().chars().take(8).collect()
+}
+
+fn format_elapsed(elapsed: Duration) -> String {
+    let minutes = elapsed.as_secs() / 60;
+    if minutes == 0 {
+        format!("{}s", elapsed.as_secs())
+    } else {
+        format!("{minutes}m")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_progress_throttled() {
+        let tasks = BackgroundTasks::default();
+        let worker_id = uuid::Uuid::new_v4();
+        let start = Instant::now();
+
+        assert_eq!(tasks.progress(worker_id, "searching", start), None);
+        tasks.register(worker_id, start);
+        assert_eq!(tasks.progress(worker_id, "running", start), None);
+
+        let first = tasks.progress(worker_id, "searching", start + Duration::from_secs(5));
+        assert_eq!(
+            first,
+            Some(format!("Task {} (5s): searching", short_id(worker_id)))
+        );
+        let too_soon = start + Duration::from_secs(30);
+        assert_eq!(tasks.progress(worker_id, "reading", too_soon), None);
+        let later = start + Duration::from_secs(125);
+        assert_eq!(tasks.progress(worker_id, "searching", later), None);
+        assert!(tasks.progress(worker_id, "reading", later).is_some());
+    }
+
+    #[test]
+    fn test_finish() {
+        let tasks = BackgroundTasks::default();
+        let worker_id = uuid::Uuid::new_v4();
+        let start = Instant::now();
+        tasks.register(worker_id, start);
+
+        let finished = start + Duration::from_secs(1200);
+        let message = tasks
+            .finish(worker_id, "Found 3 papers.\n", finished)
+            .unwrap();
+        assert!(message.ends_with("finished after 20m:\n\nFound 3 papers."));
+        assert_eq!(tasks.finish(worker_id, "again", start), None);
+    }
+}
@@ -0,0 +1,164 @@
+//! Context for conversations in platform threads.
+//!
+//! Adapters give each thread its own conversation and mark its messages with
+//! [`PARENT_CONVERSATION_ID`], the conversation the thread was started from.
+//! When a thread's channel opens, [`parent_context`] draws on the parent's
+//! recent transcript according to `[defaults.threads]`, so the thread picks
+//! up where the discussion that spawned it left off without sharing its
+//! history.
+
+use crate::config::ThreadParentContext;
+use crate::conversation::ConversationLogger;
+use crate::{AgentDeps, ChannelId, InboundMessage};
+
+use std::sync::Arc;
+
+/// Inbound metadata key holding the conversation a thread was started from.
+pub const PARENT_CONVERSATION_ID: &str = "parent_conversation_id";
+
+/// Longest parent transcript handed to the compactor.
+const MAX_SUMMARY_INPUT_CHARS: usize = 40_000;
+
+/// The conversation `message`'s thread was started from, if it's in one.
+pub fn parent_conversation_id(message: &InboundMessage) -> Option<&str> {
+    message
+        .metadata
+        .get(PARENT_CONVERSATION_ID)
+        .and_then(|value| value.as_str())
+        .filter(|parent| *parent != message.conversation_id)
+}
+
+/// What a thread's new channel is told about its parent conversation, or
+/// None when `message` isn't in a thread, the thread has been going for a
+/// while, the policy is `none` or the parent has no transcript.
+pub async fn parent_context(deps: &AgentDeps, message: &InboundMessage) -> Option<String> {
+    let parent = parent_conversation_id(message)?;
+    let config = deps.runtime_config.threads.load_full();
+    if config.parent_context == ThreadParentContext::None || config.parent_messages == 0 {
+        return None;
+    }
+
+    // Only a thread's first channel is seeded. One reopened later, after a
+    // restart or eviction, already has its own transcript to go on.
+    let logger = ConversationLogger::new(deps.sqlite_pool.clone());
+    let thread_id: ChannelId = Arc::from(message.conversation_id.as_str());
+    match logger.load_recent(&thread_id, 2).await {
+        // The message that opened the channel may be logged already.
+        Ok(messages) if messages.len() > 1 => return None,
+        Ok(_) => {}
+        Err(error) => {
+            tracing::warn!(%error, "failed to check thread transcript");
+            return None;
+        }
+    }
+
+    let parent_id: ChannelId = Arc::from(parent);
+    let messages = match logger
+        .load_recent(&parent_id, config.parent_messages as i64)
+        .await
+    {
+        Ok(messages) if !messages.is_empty() => messages,
+        Ok(_) => return None,
+        Err(error) => {
+            tracing::warn!(%error, parent, "failed to load thread parent transcript");
+            return None;
+        }
+    };
+
+    let mut transcript = String::new();
+    for message in &messages {
+        let speaker = match message.role.as_str() {
+            "assistant" => "(you)",
+            _ => message.sender_name.as_deref().unwrap_or("User"),
+        };
+        transcript.push_str(&format!("{speaker}: {}\n", message.content));
+    }
+
+    let summary = match config.parent_context {
+        ThreadParentContext::Summary => summarize(deps, &transcript).await,
+        _ => None,
+    };
+    let (context, summarized) = match &summary {
+        Some(summary) => (summary.as_str(), true),
+        None => (transcript.trim_end(), false),
+    };
+    Some(
+        deps.runtime_config
+            .prompts
+            .load()
+            .render_system_thread_parent(parent, context, summarized)
+            .unwrap_or_else(|_| context.to_string()),
+    )
+}
+
+/// The compactor's summary of `transcript`. None when it fails, in which
+/// case the thread gets the transcript instead.
+async fn summarize(deps: &AgentDeps, transcript: &str) -> Option<String> {
+    let mut start = transcript.len().saturating_sub(MAX_SUMMARY_INPUT_CHARS);
+    while !transcript.is_char_boundary(start) {
+        start += 1;
+    }
+
+    let compactor_prompt = match deps
+        .runtime_config
+        .prompts
+        .load()
+        .render_static("compactor")
+    {
+        Ok(prompt) => prompt,
+        Err(error) => {
+            tracing::warn!(%error, "failed to render compactor prompt");
+            return None;
+        }
+    };
+    match crate::agent::compactor::summarize_transcript(
+        deps,
+        &compactor_prompt,
+        &transcript[start..],
+    )
+    .await
+    {
+        Ok(summary) => Some(summary),
+        Err(error) => {
+            tracing::warn!(%error, "failed to summarize thread parent, using transcript");
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MessageContent;
+
+    fn message(conversation_id: &str, parent: Option<&str>) -> InboundMessage {
+        let mut metadata = std::collections::HashMap::new();
+        if let Some(parent) = parent {
+            metadata.insert(PARENT_CONVERSATION_ID.into(), parent.into());
+        }
+        InboundMessage {
+            id: "1".into(),
+            source: "slack".into(),
+            conversation_id: conversation_id.into(),
+            sender_id: "U1".into(),
+            agent_id: None,
+            content: MessageContent::Text("hi".into()),
+            timestamp: chrono::Utc::now(),
+            metadata,
+        }
+    }
+
+    #[test]
+    fn test_parent_conversation_id() {
+        assert_eq!(
+            parent_conversation_id(&message("slack:T1:C1:171.2", Some("slack:T1:C1"))),
+            Some("slack:T1:C1")
+        );
+        assert_eq!(parent_conversation_id(&message("slack:T1:C1", None)), None);
+        // A thread can't be its own parent.
+        assert_eq!(
+            parent_conversation_id(&message("slack:T1:C1", Some("slack:T1:C1"))),
+            None
+        );
+    }
+}
@@ -0,0 +1,544 @@
+//! Structured results of an agent turn.
+//!
+//! A [`TurnRecorder`] rides along on a process's hook and notes every
+//! completion and tool call while the agentic loop runs. When the loop
+//! returns, [`TurnRecorder::finish`] folds that trace and the loop's result
+//! into a [`TurnOutcome`], so the management API and adapters can show what
+//! a turn did without digging through logs. [`catch_panic`] isolates a turn
+//! so a panic inside it ends up as a [`StopReason::Panicked`] outcome.
+//! [`TurnEstimate`] is what a channel expects a turn to cost before it runs.
+//! [`TurnCompletion`] is how many of a set of recorded turns finished, for
+//! the reports built on the ledger.
+
+use crate::config::CostGateConfig;
+use crate::declined::{DeclineReason, Declined};
+use crate::error::ProviderApiError;
+use crate::llm::prompt_tokens::PromptTokens;
+use crate::llm::tags::RequestTags;
+use crate::tools::truncate_output;
+
+use futures::FutureExt as _;
+use rig::completion::PromptError;
+use serde::{Deserialize, Serialize};
+
+use std::any::Any;
+use std::collections::BTreeMap;
+use std::panic::AssertUnwindSafe;
+use std::sync::{Arc, Mutex};
+
+/// Bytes of each tool's arguments and result kept in the trace.
+const MAX_TRACE_BYTES: usize = 2_000;
+
+/// Everything a turn produced.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TurnOutcome {
+    /// The final text the model returned, or the partial result on early stop.
+    pub text: String,
+    /// Tool calls in the order they were made.
+    pub tool_trace: Vec<ToolTraceEntry>,
+    pub usage: TurnUsage,
+    /// Estimated cost in USD, summed over completions whose model has a price.
+    pub cost_usd: Option<f64>,
+    /// What the turn was expected to cost before it ran, when the channel's
+    /// cost gate estimated it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub estimated_cost_usd: Option<f64>,
+    /// One entry per completion request.
+    pub routing: Vec<RoutingDecision>,
+    pub stop_reason: StopReason,
+    /// The channel's `/model set` override, if one was in effect.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model_override: Option<String>,
+    /// The channel's `/persona`, if one was in effect.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub persona: Option<String>,
+    /// Version of the channel prompt the turn ran with, a hash of its
+    /// templates, identity files and persona.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prompt_version: Option<String>,
+    /// Experiment flags on for the turn, in config order.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub flags: Vec<String>,
+    /// ISO 639-3 code of the language the channel's users last wrote in,
+    /// if it was detected.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+    /// Sampling seed the turn's completions were sent with, if one was set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u64>,
+    /// Tags the message the turn answered asked its requests to be counted
+    /// under.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub tags: BTreeMap<String, String>,
+    /// Whether the turn answered a message an operator sent as someone else
+    /// through the API, rather than one from the platform.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub synthetic: bool,
+    /// Who sent the message, for a synthetic turn.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub impersonated_by: Option<String>,
+}
+
+/// One tool call and what it returned.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ToolTraceEntry {
+    pub tool_name: String,
+    /// Arguments as JSON, truncated.
+    pub args: String,
+    /// Result text, truncated. None if the call never returned.
+    pub result: Option<String>,
+}
+
+/// Token counts summed over the turn's completions.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct TurnUsage {
+    pub completions: u64,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    /// Estimated tokens of retrieved context added to the prompt. Counted
+    /// once per turn, though every completion in the turn carries it and
+    /// it's included in `input_tokens` each time.
+    #[serde(default)]
+    pub retrieval_tokens: u64,
+    /// `input_tokens` split into the system prompt, the tool schemas and
+    /// the history. The first two are estimated from the requests, and the
+    /// history is what's left.
+    #[serde(default)]
+    pub preamble_tokens: u64,
+    #[serde(default)]
+    pub tool_tokens: u64,
+    #[serde(default)]
+    pub history_tokens: u64,
+}
+
+/// Which model answered a completion request.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RoutingDecision {
+    pub request_id: Option<String>,
+    /// The model that answered, after any fallback.
+    pub model: Option<String>,
+    /// The provider's own id for the request, for its support.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provider_request_id: Option<String>,
+    /// Seed the answering provider was sent. None when it has no seed
+    /// parameter, so its answer wasn't seeded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u64>,
+    /// Whether a fallback answered in place of the routed model.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub fallback: bool,
+}
+
+/// Why the turn ended.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "reason", rename_all = "snake_case")]
+pub enum StopReason {
+    /// The model finished on its own.
+    #[default]
+    Completed,
+    /// The model chose not to respond.
+    Skipped,
+    /// The turn ran out of tool-calling rounds.
+    MaxTurns,
+    /// A hook or the user cancelled the turn.
+    Cancelled { reason: String },
+    /// The turn failed.
+    Failed {
+        error: String,
+        /// The provider's structured error, when a provider rejected the
+        /// request.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        provider_error: Option<ProviderApiError>,
+    },
+    /// Something panicked during the turn. The process kept running.
+    Panicked { message: String },
+    /// A policy stopped the turn before a model answered, like a spent
+    /// budget or an unconfirmed cost estimate.
+    Declined { code: DeclineReason, detail: String },
+}
+
+impl StopReason {
+    pub fn declined(declined: &Declined) -> Self {
+        Self::Declined {
+            code: declined.reason,
+            detail: declined.detail.clone(),
+        }
+    }
+
+    /// Classify the agentic loop's error.
+    pub fn from_prompt_error(error: &PromptError) -> Self {
+        match error {
+            PromptError::MaxTurnsError { .. } => Self::MaxTurns,
+            PromptError::PromptCancelled { reason, .. } => Self::Cancelled {
+                reason: reason.to_string(),
+            },
+            other => Self::Failed {
+                error: other.to_string(),
+                provider_error: ProviderApiError::find(other).cloned(),
+            },
+        }
+    }
+}
+
+/// How many of a set of turns finished.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct TurnCompletion {
+    pub turns: u64,
+    /// Turns that failed, panicked, were cancelled or ran out of rounds.
+    pub unfinished: u64,
+    /// Share of turns that finished, 0.0 to 1.0.
+    pub completion_rate: f64,
+}
+
+impl TurnCompletion {
+    pub fn new(turns: u64, unfinished: u64) -> Self {
+        let completion_rate = if turns > 0 {
+            (turns - unfinished) as f64 / turns as f64
+        } else {
+            0.0
+        };
+        Self {
+            turns,
+            unfinished,
+            completion_rate,
+        }
+    }
+
+    /// From a report row's `turns` and `unfinished` counts.
+    pub fn from_row(row: &sqlx::sqlite::SqliteRow) -> Self {
+        use sqlx::Row as _;
+
+        let count = |column: &str| row.try_get::<i64, _>(column).unwrap_or_default() as u64;
+        Self::new(count("turns"), count("unfinished"))
+    }
+}
+
+/// A turn's expected cost, worked out before it runs.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TurnEstimate {
+    pub model: String,
+    /// Estimated tokens in the first completion's prompt.
+    pub prompt_tokens: u64,
+    pub completions: u32,
+    pub cost_usd: f64,
+}
+
+impl TurnEstimate {
+    /// Estimate a turn on `model` whose prompt is about `prompt_tokens` long.
+    /// Every expected completion resends the prompt. `price` gives the cost
+    /// of input and output token counts, or None if the model has no price,
+    /// in which case there's no estimate.
+    pub fn new(
+        model: impl Into<String>,
+        prompt_tokens: u64,
+        config: &CostGateConfig,
+        price: impl FnOnce(u64, u64) -> Option<f64>,
+    ) -> Option<Self> {
+        let completions = config.expected_completions.max(1);
+        let input_tokens = prompt_tokens * u64::from(completions);
+        let output_tokens = config.expected_output_tokens * u64::from(completions);
+        Some(Self {
+            model: model.into(),
+            prompt_tokens,
+            completions,
+            cost_usd: price(input_tokens, output_tokens)?,
+        })
+    }
+
+    /// What the user is asked to confirm.
+    pub fn preview(&self) -> String {
+        format!(
+            "Answering this is estimated to cost about ${:.2}: {} completions on {} with a \
+             prompt of ~{}k tokens each.",
+            self.cost_usd,
+            self.completions,
+            self.model,
+            self.prompt_tokens.div_ceil(1000)
+        )
+    }
+}
+
+/// Run a turn, catching a panic anywhere inside it as an error carrying the
+/// panic message, so a bug in one tool or parser fails that turn instead of
+/// taking down the task it runs on.
+pub async fn catch_panic<F: Future>(future: F) -> std::result::Result<F::Output, String> {
+    AssertUnwindSafe(future)
+        .catch_unwind()
+        .await
+        .map_err(|payload| panic_message(payload.as_ref()))
+}
+
+/// The message a panic was raised with.
+pub fn panic_message(payload: &(dyn Any + Send)) -> String {
+    payload
+        .downcast_ref::<&str>()
+        .map(|message| message.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "Box<dyn Any>".into())
+}
+
+/// Collects a turn's completions and tool calls. Clones share one trace.
+#[derive(Debug, Clone, Default)]
+pub struct TurnRecorder {
+    trace: Arc<Mutex<TurnOutcome>>,
+}
+
+impl TurnRecorder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Drop anything recorded so far, at the start of a turn.
+    pub fn reset(&self) {
+        *self.lock() = TurnOutcome::default();
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn record_completion(
+        &self,
+        request_id: Option<String>,
+        model: Option<String>,
+        provider_request_id: Option<String>,
+        seed: Option<u64>,
+        fallback: bool,
+        input_tokens: u64,
+        output_tokens: u64,
+        cost_usd: Option<f64>,
+    ) {
+        let mut trace = self.lock();
+        trace.usage.completions += 1;
+        trace.usage.input_tokens += input_tokens;
+        trace.usage.output_tokens += output_tokens;
+        if let Some(cost) = cost_usd {
+            *trace.cost_usd.get_or_insert(0.0) += cost;
+        }
+        trace.routing.push(RoutingDecision {
+            request_id,
+            model,
+            provider_request_id,
+            seed,
+            fallback,
+        });
+    }
+
+    /// Split a completion's input tokens using the estimate of its
+    /// preamble and tool schemas.
+    pub fn record_prompt_tokens(&self, estimate: PromptTokens, input_tokens: u64) {
+        let mut trace = self.lock();
+        trace.usage.preamble_tokens += estimate.preamble.min(input_tokens);
+        trace.usage.tool_tokens += estimate
+            .tools
+            .min(input_tokens.saturating_sub(estimate.preamble));
+        trace.usage.history_tokens += estimate.history(input_tokens);
+    }
+
+    /// Note the retrieved context added to the turn's prompt.
+    pub fn record_retrieval(&self, tokens: u64) {
+        self.lock().usage.retrieval_tokens += tokens;
+    }
+
+    /// Note the version of the prompt the turn runs with.
+    pub fn record_prompt_version(&self, version: String) {
+        self.lock().prompt_version = Some(version);
+    }
+
+    /// Note the seed the turn's completions are sent with.
+    pub fn record_seed(&self, seed: Option<u64>) {
+        self.lock().seed = seed;
+    }
+
+    /// Note the tags the turn's requests are counted under.
+    pub fn record_tags(&self, tags: RequestTags) {
+        self.lock().tags = tags.into_inner();
+    }
+
+    /// Mark the turn synthetic when it answers a message `operator` sent
+    /// through the API.
+    pub fn record_impersonation(&self, operator: Option<String>) {
+        let mut trace = self.lock();
+        trace.synthetic = operator.is_some();
+        trace.impersonated_by = operator;
+    }
+
+    /// Note the experiment flags on for the turn.
+    pub fn record_flags(&self, flags: Vec<String>) {
+        self.lock().flags = flags;
+    }
+
+    /// Note what the turn was expected to cost.
+    pub fn record_estimate(&self, cost_usd: f64) {
+        self.lock().estimated_cost_usd = Some(cost_usd);
+    }
+
+    pub fn record_tool_call(&self, tool_name: &str, args: &str) {
+        self.lock().tool_trace.push(ToolTraceEntry {
+            tool_name: tool_name.to_string(),
+            args: truncate_output(args, MAX_TRACE_BYTES),
+            result: None,
+        });
+    }
+
+    /// Attach a result to the latest pending call of `tool_name`.
+    pub fn record_tool_result(&self, tool_name: &str, result: &str) {
+        let mut trace = self.lock();
+        let pending = trace
+            .tool_trace
+            .iter_mut()
+            .rev()
+            .find(|entry| entry.tool_name == tool_name && entry.result.is_none());
+        if let Some(entry) = pending {
+            entry.result = Some(truncate_output(result, MAX_TRACE_BYTES));
+        }
+    }
+
+    /// Take the recorded trace and close it out with the loop's result.
+    /// `fallback_text` is used when the loop returned an error, e.g. the
+    /// last partial answer.
+    pub fn finish(
+        &self,
+        result: &std::result::Result<String, PromptError>,
+        fallback_text: Option<String>,
+    ) -> TurnOutcome {
+        match result {
+            Ok(text) => self.finish_with(text.clone(), StopReason::Completed),
+            Err(error) => self.finish_with(
+                fallback_text.unwrap_or_default(),
+                StopReason::from_prompt_error(error),
+            ),
+        }
+    }
+
+    /// Take the recorded trace and close it out with the given text and
+    /// stop reason.
+    pub fn finish_with(&self, text: String, stop_reason: StopReason) -> TurnOutcome {
+        TurnOutcome {
+            text,
+            stop_reason,
+            ..std::mem::take(&mut *self.lock())
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, TurnOutcome> {
+        self.trace
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_recorder_builds_outcome() {
+        let recorder = TurnRecorder::new();
+        recorder.record_completion(
+            Some("req-1".into()),
+            Some("a/x".into()),
+            Some("req_011".into()),
+            None,
+            false,
+            100,
+            10,
+            Some(0.5),
+        );
+        recorder.record_tool_call("shell", r#"{"command":"ls"}"#);
+        recorder.record_completion(
+            Some("req-2".into()),
+            Some("a/y".into()),
+            None,
+            Some(7),
+            true,
+            200,
+            20,
+            None,
+        );
+        recorder.record_tool_result("shell", "Cargo.toml");
+        recorder.record_retrieval(40);
+        recorder.record_prompt_tokens(
+            PromptTokens {
+                preamble: 60,
+                tools: 30,
+            },
+            100,
+        );
+        recorder.record_prompt_tokens(
+            PromptTokens {
+                preamble: 60,
+                tools: 50,
+            },
+            100,
+        );
+
+        let outcome = recorder.finish(&Ok("done".into()), None);
+
+        assert_eq!(outcome.text, "done");
+        assert_eq!(outcome.stop_reason, StopReason::Completed);
+        assert_eq!(outcome.usage.completions, 2);
+        assert_eq!(outcome.usage.input_tokens, 300);
+        assert_eq!(outcome.usage.retrieval_tokens, 40);
+        assert_eq!(outcome.usage.preamble_tokens, 120);
+        assert_eq!(outcome.usage.tool_tokens, 70);
+        assert_eq!(outcome.usage.history_tokens, 10);
+        assert_eq!(outcome.cost_usd, Some(0.5));
+        assert_eq!(outcome.routing[1].model.as_deref(), Some("a/y"));
+        assert_eq!(
+            outcome.routing[0].provider_request_id.as_deref(),
+            Some("req_011")
+        );
+        assert_eq!(outcome.routing[1].seed, Some(7));
+        assert!(outcome.routing[1].fallback);
+        assert_eq!(outcome.tool_trace[0].result.as_deref(), Some("Cargo.toml"));
+
+        // The trace is consumed by finish.
+        let next = recorder.finish(&Ok(String::new()), None);
+        assert!(next.tool_trace.is_empty());
+    }
+
+    #[test]
+    fn test_turn_estimate() {
+        let config = CostGateConfig {
+            expected_completions: 3,
+            expected_output_tokens: 400,
+            ..CostGateConfig::default()
+        };
+        let price = |input: u64, output: u64| {
+            Some((input as f64 * 3.0 + output as f64 * 15.0) / 1_000_000.0)
+        };
+
+        let estimate = TurnEstimate::new("anthropic/x", 40_000, &config, price).unwrap();
+        assert_eq!(estimate.completions, 3);
+        // 120k input tokens at $3/M plus 1.2k output tokens at $15/M.
+        assert!((estimate.cost_usd - 0.378).abs() < 1e-9);
+        assert_eq!(
+            estimate.preview(),
+            "Answering this is estimated to cost about $0.38: 3 completions on anthropic/x \
+             with a prompt of ~40k tokens each."
+        );
+
+        assert_eq!(
+            TurnEstimate::new("local/y", 40_000, &config, |_, _| None),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn test_catch_panic() {
+        assert_eq!(catch_panic(async { 7 }).await, Ok(7));
+
+        let id = 3;
+        let result = catch_panic(async move {
+            if id == 3 {
+                panic!("bad tool call {id}");
+            }
+        })
+        .await;
+        assert_eq!(result, Err("bad tool call 3".to_string()));
+
+        let result = catch_panic(async {
+            std::panic::panic_any(42_u32);
+        })
+        .await;
+        assert_eq!(result, Err("Box<dyn Any>".to_string()));
+    }
+}
@@ -1,14 +1,18 @@
 //! Worker: Independent task execution process.
 
 use crate::agent::compactor::estimate_history_tokens;
+use crate::agent::self_report::SelfReport;
 use crate::config::BrowserConfig;
+use crate::conversation::history::ProcessRunLogger;
 use crate::error::Result;
 use crate::hooks::SpacebotHook;
-use crate::llm::SpacebotModel;
 use crate::llm::routing::is_context_overflow_error;
+use crate::llm::tags::RequestTags;
+use crate::llm::{Priority, SpacebotModel};
 use crate::{AgentDeps, ChannelId, ProcessId, ProcessType, WorkerId};
 use rig::agent::AgentBuilder;
 use rig::completion::{CompletionModel, Prompt};
+use rig::tool::Tool as _;
 use std::fmt::Write as _;
 use std::path::PathBuf;
 use tokio::sync::{mpsc, watch};
@@ -57,6 +61,9 @@ pub struct Worker {
     /// Status updates.
     pub status_tx: watch::Sender<String>,
     pub status_rx: watch::Receiver<String>,
+    /// Tags of the turn that spawned the worker, which its requests are
+    /// counted under.
+    pub tags: RequestTags,
 }
 
 impl Worker {
@@ -71,6 +78,7 @@ impl Worker {
         brave_search_key: Option<String>,
         logs_dir: PathBuf,
     ) -> Self {
+        let task = task.into();
         let id = Uuid::new_v4();
         let process_id = ProcessId::Worker(id);
         let hook = SpacebotHook::new(
@@ -79,13 +87,17 @@ impl Worker {
             ProcessType::Worker,
             channel_id.clone(),
             deps.event_tx.clone(),
-        );
+        )
+        .with_events(deps.llm_manager.events().clone())
+        .with_preview_gate(deps.preview_gate())
+        .with_tool_guard(deps.tool_guard(&task))
+        .with_loop_guard(deps.loop_guard());
         let (status_tx, status_rx) = watch::channel("starting".to_string());
 
         Self {
             id,
             channel_id,
-            task: task.into(),
+            task,
             state: WorkerState::Running,
             deps,
             hook,
@@ -97,6 +109,7 @@ impl Worker {
             logs_dir,
             status_tx,
             status_rx,
+            tags: RequestTags::default(),
         }
     }
 
@@ -111,6 +124,7 @@ impl Worker {
         brave_search_key: Option<String>,
         logs_dir: PathBuf,
     ) -> (Self, mpsc::Sender<String>) {
+        let task = task.into();
         let id = Uuid::new_v4();
         let process_id = ProcessId::Worker(id);
         let hook = SpacebotHook::new(
@@ -119,14 +133,18 @@ impl Worker {
             ProcessType::Worker,
             channel_id.clone(),
             deps.event_tx.clone(),
-        );
+        )
+        .with_events(deps.llm_manager.events().clone())
+        .with_preview_gate(deps.preview_gate())
+        .with_tool_guard(deps.tool_guard(&task))
+        .with_loop_guard(deps.loop_guard());
         let (status_tx, status_rx) = watch::channel("starting".to_string());
         let (input_tx, input_rx) = mpsc::channel(32);
 
         let worker = Self {
             id,
             channel_id,
-            task: task.into(),
+            task,
             state: WorkerState::Running,
             deps,
             hook,
@@ -138,11 +156,18 @@ impl Worker {
             logs_dir,
             status_tx,
             status_rx,
+            tags: RequestTags::default(),
         };
 
         (worker, input_tx)
     }
 
+    /// Count the worker's requests under `tags`.
+    pub fn with_tags(mut self, tags: RequestTags) -> Self {
+        self.tags = tags;
+        self
+    }
+
     /// Check if the worker can transition to a new state.
     pub fn can_transition_to(&self, target: WorkerState) -> bool {
         use WorkerState::*;
@@ -183,23 +208,28 @@ impl Worker {
 
         tracing::info!(worker_id = %self.id, task = %self.task, "worker starting");
 
-        // Create per-worker ToolServer with task tools
-        let worker_tool_server = crate::tools::create_worker_tool_server(
-            self.deps.agent_id.clone(),
+        let routing = self.deps.runtime_config.routing.load();
+        let worker_tool_server = task_tool_server(
+            &self.deps,
             self.id,
             self.channel_id.clone(),
-            self.deps.event_tx.clone(),
             self.browser_config.clone(),
             self.screenshot_dir.clone(),
             self.brave_search_key.clone(),
-            self.deps.runtime_config.workspace_dir.clone(),
-            self.deps.runtime_config.instance_dir.clone(),
-        );
+        )
+        .await;
 
-        let routing = self.deps.runtime_config.routing.load();
         let model_name = routing.resolve(ProcessType::Worker, None).to_string();
         let model = SpacebotModel::make(&self.deps.llm_manager, &model_name)
-            .with_routing((**routing).clone());
+            .with_routing((**routing).clone())
+            .with_priority(Priority::for_process(ProcessType::Worker))
+            .with_seed(**self.deps.runtime_config.seed.load())
+            .with_tags(self.tags.clone())
+            .with_generation(self.deps.runtime_config.generation.load().default_profile())
+            .with_output_cap(self.deps.output_cap(ProcessType::Worker, &model_name))
+            .with_empty_retry(self.deps.empty_nudge())
+            .with_tool_filter(self.deps.tool_filter())
+            .with_compressor(self.deps.compressor());
 
         let agent = AgentBuilder::new(model)
             .preamble(&self.system_prompt)
@@ -214,6 +244,8 @@ impl Worker {
         let mut prompt = self.task.clone();
         let mut segments_run = 0;
         let mut overflow_retries = 0;
+        // Turns compaction removed from history, for the self-report threshold
+        let mut compacted_turns = 0;
 
         let result = loop {
             segments_run += 1;
@@ -229,7 +261,7 @@ impl Worker {
                 }
                 Err(rig::completion::PromptError::MaxTurnsError { .. }) => {
                     overflow_retries = 0;
-                    self.maybe_compact_history(&mut history).await;
+                    compacted_turns += self.maybe_compact_history(&mut history).await;
                     prompt = "Continue where you left off. Do not repeat completed work.".into();
                     self.hook
                         .send_status(&format!("working (segment {segments_run})"));
@@ -265,7 +297,7 @@ impl Worker {
                         "context overflow, compacting and retrying"
                     );
                     self.hook.send_status("compacting (overflow recovery)");
-                    self.force_compact_history(&mut history).await;
+                    compacted_turns += self.force_compact_history(&mut history).await;
                     prompt = "Continue where you left off. Do not repeat completed work. \
                               Your previous attempt exceeded the context limit, so older history \
                               has been compacted."
@@ -289,9 +321,10 @@ impl Worker {
             while let Some(follow_up) = input_rx.recv().await {
                 self.state = WorkerState::Running;
                 self.hook.send_status("processing follow-up");
+                self.hook.reset_loop_guard();
 
                 // Compact before follow-up if needed
-                self.maybe_compact_history(&mut history).await;
+                compacted_turns += self.maybe_compact_history(&mut history).await;
 
                 let mut follow_up_prompt = follow_up.clone();
                 let mut follow_up_overflow_retries = 0;
@@ -318,7 +351,7 @@ impl Worker {
                                 "follow-up context overflow, compacting and retrying"
                             );
                             self.hook.send_status("compacting (overflow recovery)");
-                            self.force_compact_history(&mut history).await;
+                            compacted_turns += self.force_compact_history(&mut history).await;
                             let prompt_engine = self.deps.runtime_config.prompts.load();
                             let overflow_msg = prompt_engine
                                 .render_system_worker_overflow()
@@ -344,6 +377,17 @@ impl Worker {
             }
         }
 
+        // Ask a long run for a structured report to go with its result
+        let turns = compacted_turns + count_turns(&history);
+        let result = match self.request_self_report(&agent, &mut history, turns).await {
+            Some(report) => {
+                ProcessRunLogger::new(self.deps.sqlite_pool.clone())
+                    .log_worker_report(self.id, &report);
+                format!("{}\n\n{}", result.trim_end(), report.render())
+            }
+            None => result,
+        };
+
         self.state = WorkerState::Done;
         self.hook.send_status("completed");
 
@@ -357,22 +401,71 @@ impl Worker {
         Ok(result)
     }
 
+    /// Ask the model for a structured report of the run, when reports are
+    /// on and the run took at least `min_turns` turns.
+    ///
+    /// A reply that doesn't conform to the schema is sent back once with the
+    /// error. Returns None when no report was asked for or none conformed.
+    async fn request_self_report(
+        &self,
+        agent: &rig::agent::Agent<SpacebotModel>,
+        history: &mut Vec<rig::message::Message>,
+        turns: usize,
+    ) -> Option<SelfReport> {
+        let config = self.deps.runtime_config.self_report.load();
+        if !config.enabled || turns < config.min_turns {
+            return None;
+        }
+
+        self.hook.send_status("reporting");
+        let prompt_engine = self.deps.runtime_config.prompts.load();
+        let mut prompt = prompt_engine
+            .render_system_self_report(&SelfReport::schema())
+            .expect("failed to render self report request");
+
+        for attempt in 1..=2 {
+            let response = match agent
+                .prompt(&prompt)
+                .with_history(&mut *history)
+                .with_hook(self.hook.clone())
+                .await
+            {
+                Ok(response) => response,
+                Err(error) => {
+                    tracing::warn!(worker_id = %self.id, %error, "self report request failed");
+                    return None;
+                }
+            };
+            match SelfReport::parse(&response) {
+                Ok(report) => return Some(report),
+                Err(error) => {
+                    tracing::warn!(worker_id = %self.id, attempt, %error, "self report didn't conform");
+                    prompt = format!(
+                        "That report doesn't conform: {error:#}. Reply with the JSON object only."
+                    );
+                }
+            }
+        }
+        None
+    }
+
     /// Check context usage and compact history if approaching the limit.
+    /// Returns the number of assistant turns compacted away.
     ///
     /// Workers don't have a full Compactor instance — they do inline compaction
     /// by summarizing older tool calls and results into a condensed recap.
     /// No LLM call, just programmatic truncation with a summary marker.
-    async fn maybe_compact_history(&self, history: &mut Vec<rig::message::Message>) {
+    async fn maybe_compact_history(&self, history: &mut Vec<rig::message::Message>) -> usize {
         let context_window = **self.deps.runtime_config.context_window.load();
         let estimated = estimate_history_tokens(history);
         let usage = estimated as f32 / context_window as f32;
 
         if usage < 0.70 {
-            return;
+            return 0;
         }
 
         self.compact_history(history, 0.50, "worker history compacted")
-            .await;
+            .await
     }
 
     /// Aggressive compaction for context overflow recovery.
@@ -380,25 +473,26 @@ impl Worker {
     /// Unlike `maybe_compact_history`, this always fires regardless of current
     /// usage and removes 75% of messages. Used when the provider has already
     /// rejected the request for exceeding context limits.
-    async fn force_compact_history(&self, history: &mut Vec<rig::message::Message>) {
+    async fn force_compact_history(&self, history: &mut Vec<rig::message::Message>) -> usize {
         self.compact_history(
             history,
             0.75,
             "worker history force-compacted (overflow recovery)",
         )
-        .await;
+        .await
     }
 
     /// Compact worker history by removing a fraction of the oldest messages.
+    /// Returns the number of assistant turns removed.
     async fn compact_history(
         &self,
         history: &mut Vec<rig::message::Message>,
         fraction: f32,
         log_message: &str,
-    ) {
+    ) -> usize {
         let total = history.len();
         if total <= 4 {
-            return;
+            return 0;
         }
 
         let context_window = **self.deps.runtime_config.context_window.load();
@@ -424,6 +518,8 @@ impl Worker {
             usage = %format!("{:.0}%", usage * 100.0),
             "{log_message}"
         );
+
+        count_turns(&removed)
     }
 
     /// Check if worker is in a terminal state.
@@ -659,6 +755,14 @@ fn build_worker_recap(messages: &[rig::message::Message]) -> String {
     }
 }
 
+/// Assistant turns in a history.
+fn count_turns(history: &[rig::message::Message]) -> usize {
+    history
+        .iter()
+        .filter(|message| matches!(message, rig::message::Message::Assistant { .. }))
+        .count()
+}
+
 /// Extract the last assistant text message from a history.
 fn extract_last_assistant_text(history: &[rig::message::Message]) -> Option<String> {
     for message in history.iter().rev() {
@@ -680,3 +784,128 @@ fn extract_last_assistant_text(history: &[rig::message::Message]) -> Option<Stri
     }
     None
 }
+
+/// Build a worker's ToolServer with the task tools the agent's current
+/// config enables. Also used to describe the agent's tools to embedding
+/// hosts (see `agent::capabilities`).
+pub async fn task_tool_server(
+    deps: &AgentDeps,
+    worker_id: WorkerId,
+    channel_id: Option<ChannelId>,
+    browser_config: BrowserConfig,
+    screenshot_dir: PathBuf,
+    brave_search_key: Option<String>,
+) -> rig::tool::server::ToolServerHandle {
+    let routing = deps.runtime_config.routing.load();
+    let ocr_config = deps.runtime_config.ocr.load();
+    let ocr_tool = ocr_config.enabled.then(|| {
+        crate::tools::OcrTool::new(
+            (**ocr_config).clone(),
+            deps.llm_manager.clone(),
+            (**routing).clone(),
+            deps.runtime_config.workspace_dir.clone(),
+        )
+    });
+    let image_config = deps.runtime_config.image_generation.load();
+    let image_tool = image_config.enabled.then(|| {
+        crate::tools::GenerateImageTool::new(
+            (**image_config).clone(),
+            deps.llm_manager.clone(),
+            crate::storage::ArtifactStore::new(
+                &deps.runtime_config.storage.load(),
+                deps.runtime_config.workspace_dir.join("images"),
+                &format!("{}/images", deps.agent_id),
+            ),
+        )
+    });
+    let home_assistant_config = deps.runtime_config.home_assistant.load();
+    let home_assistant_tool = if home_assistant_config.enabled {
+        match crate::home_assistant::Client::new(&home_assistant_config) {
+            Ok(client) => Some(crate::tools::HomeAssistantTool::new(
+                client,
+                home_assistant_config.entities.clone(),
+            )),
+            Err(error) => {
+                tracing::warn!(%error, "home_assistant tool unavailable");
+                None
+            }
+        }
+    } else {
+        None
+    };
+    let issues_config = deps.runtime_config.issues.load();
+    let issues_tool = (issues_config.enabled && !issues_config.workspaces.is_empty())
+        .then(|| crate::tools::IssuesTool::new(issues_config.workspaces.clone()));
+    let sql_config = deps.runtime_config.sql.load();
+    let sql_tool = (sql_config.enabled && !sql_config.databases.is_empty())
+        .then(|| crate::tools::SqlQueryTool::new((**sql_config).clone()));
+    let http_config = deps.runtime_config.http.load();
+    let http_tool = (http_config.enabled && !http_config.allowed_domains.is_empty())
+        .then(|| crate::tools::HttpRequestTool::new(&http_config));
+    // API tools share http_request's network policy.
+    let api_tools = if http_config.enabled {
+        crate::tools::api_operation::load(
+            &http_config,
+            &deps.runtime_config.instance_dir,
+            deps.network.proxy_for(crate::tools::HttpRequestTool::NAME),
+        )
+        .await
+    } else {
+        Vec::new()
+    };
+    let kubernetes_config = deps.runtime_config.kubernetes.load();
+    let kubernetes_tool = (kubernetes_config.enabled && !kubernetes_config.clusters.is_empty())
+        .then(|| {
+            crate::tools::KubernetesTool::new(
+                (**kubernetes_config).clone(),
+                &deps.runtime_config.instance_dir,
+            )
+        });
+
+    let observability_config = deps.runtime_config.observability.load();
+    let has_source = |backend| {
+        observability_config.enabled
+            && observability_config
+                .sources
+                .iter()
+                .any(|source| source.backend == backend)
+    };
+    let promql_tool = has_source(crate::config::ObservabilityBackend::Prometheus)
+        .then(|| crate::tools::PromqlQueryTool::new((**observability_config).clone()));
+    let logql_tool = has_source(crate::config::ObservabilityBackend::Loki)
+        .then(|| crate::tools::LogqlQueryTool::new((**observability_config).clone()));
+    let incidents_config = deps.runtime_config.incidents.load();
+    let incident_tool = (incidents_config.enabled && !incidents_config.accounts.is_empty())
+        .then(|| crate::tools::IncidentTool::new(incidents_config.accounts.clone()));
+
+    let screenshots = crate::storage::ArtifactStore::new(
+        &deps.runtime_config.storage.load(),
+        screenshot_dir,
+        &format!("{}/screenshots", deps.agent_id),
+    );
+
+    crate::tools::create_worker_tool_server(
+        deps.agent_id.clone(),
+        worker_id,
+        channel_id,
+        deps.event_tx.clone(),
+        browser_config,
+        (**deps.runtime_config.computer_use.load()).clone(),
+        ocr_tool,
+        image_tool,
+        home_assistant_tool,
+        issues_tool,
+        sql_tool,
+        http_tool,
+        api_tools,
+        kubernetes_tool,
+        promql_tool,
+        logql_tool,
+        incident_tool,
+        screenshots,
+        brave_search_key,
+        deps.network.clone(),
+        deps.runtime_config.workspace_dir.clone(),
+        deps.runtime_config.instance_dir.clone(),
+    )
+}
@@ -2,8 +2,10 @@
 //!
 //! Serves the embedded frontend assets and provides a JSON API for
 //! managing agents, viewing status, and interacting with the system.
-//! Includes an SSE endpoint for realtime event streaming.
+//! Includes an SSE endpoint for realtime event streaming, which clients can
+//! resume from the last event they saw.
 
+mod journal;
 mod server;
 mod state;
 
@@ -0,0 +1,257 @@
+//! Numbered, persisted history of API events, so SSE clients can resume.
+//!
+//! Every event on the API stream is given a cursor that keeps increasing
+//! across restarts, and is appended to `logs/events.jsonl`. A client that
+//! reconnects with the last cursor it saw (the `Last-Event-ID` header, or
+//! `?cursor=`) gets everything after it replayed before the live stream, so
+//! a dashboard that drops its connection mid-run doesn't miss tool calls.
+//! Recent events are replayed from memory, older ones from the file. The
+//! file rotates to `events.jsonl.1` once it passes `MAX_LOG_BYTES`, so
+//! history reaches back one rotation.
+
+use super::state::ApiEvent;
+
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::io::BufRead as _;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard, OnceLock};
+use tokio::io::AsyncWriteExt as _;
+use tokio::sync::broadcast;
+
+/// Events kept in memory for replay.
+const RECENT_CAPACITY: usize = 2048;
+
+/// Size at which the event log is rotated.
+const MAX_LOG_BYTES: u64 = 16 * 1024 * 1024;
+
+/// An API event with its cursor, in the form it's sent over SSE.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JournaledEvent {
+    pub cursor: u64,
+    /// SSE event type, e.g. `tool_started`.
+    pub kind: String,
+    /// The event as JSON.
+    pub data: String,
+}
+
+/// Cursors and history for the API event stream.
+pub struct EventJournal {
+    tx: broadcast::Sender<JournaledEvent>,
+    recent: Mutex<VecDeque<JournaledEvent>>,
+    /// The event log, once the journal is started.
+    path: OnceLock<PathBuf>,
+}
+
+impl EventJournal {
+    pub fn new() -> Self {
+        let (tx, _) = broadcast::channel(512);
+        Self {
+            tx,
+            recent: Mutex::new(VecDeque::new()),
+            path: OnceLock::new(),
+        }
+    }
+
+    /// Numbered events as they're journaled.
+    pub fn subscribe(&self) -> broadcast::Receiver<JournaledEvent> {
+        self.tx.subscribe()
+    }
+
+    /// Start numbering and persisting `events` to the log at `path`,
+    /// continuing from the last cursor already in it. Does nothing if the
+    /// journal was already started.
+    pub fn start(
+        self: &std::sync::Arc<Self>,
+        mut events: broadcast::Receiver<ApiEvent>,
+        path: PathBuf,
+    ) {
+        if self.path.set(path.clone()).is_err() {
+            return;
+        }
+        let mut cursor = last_cursor(&path).unwrap_or(0);
+        let journal = self.clone();
+        tokio::spawn(async move {
+            let mut log = LogWriter::new(path);
+            loop {
+                let event = match events.recv().await {
+                    Ok(event) => event,
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        tracing::warn!(skipped, "API event journal fell behind");
+                        continue;
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                };
+                let Ok(data) = serde_json::to_string(&event) else {
+                    continue;
+                };
+                cursor += 1;
+                let event = JournaledEvent {
+                    cursor,
+                    kind: event.kind().to_string(),
+                    data,
+                };
+                log.append(&event).await;
+                {
+                    let mut recent = journal.lock();
+                    if recent.len() == RECENT_CAPACITY {
+                        recent.pop_front();
+                    }
+                    recent.push_back(event.clone());
+                }
+                journal.tx.send(event).ok();
+            }
+        });
+    }
+
+    /// Events after `cursor`, oldest first, from memory when it reaches
+    /// back far enough and from the event log otherwise.
+    pub async fn since(&self, cursor: u64) -> Vec<JournaledEvent> {
+        {
+            let recent = self.lock();
+            let in_memory = recent
+                .front()
+                .is_none_or(|first| first.cursor <= cursor + 1);
+            if in_memory || self.path.get().is_none() {
+                return recent
+                    .iter()
+                    .filter(|event| event.cursor > cursor)
+                    .cloned()
+                    .collect();
+            }
+        }
+        let Some(path) = self.path.get().cloned() else {
+            return Vec::new();
+        };
+        tokio::task::spawn_blocking(move || {
+            let mut events = read_after(&rotated(&path), cursor);
+            events.extend(read_after(&path, cursor));
+            events
+        })
+        .await
+        .unwrap_or_default()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, VecDeque<JournaledEvent>> {
+        self.recent
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl Default for EventJournal {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Appends to the event log, rotating it when it gets too big.
+struct LogWriter {
+    path: PathBuf,
+    file: Option<tokio::fs::File>,
+    size: u64,
+}
+
+impl LogWriter {
+    fn new(path: PathBuf) -> Self {
+        let size = std::fs::metadata(&path).map(|meta| meta.len()).unwrap_or(0);
+        Self {
+            path,
+            file: None,
+            size,
+        }
+    }
+
+    async fn append(&mut self, event: &JournaledEvent) {
+        if let Err(error) = self.try_append(event).await {
+            tracing::warn!(%error, path = %self.path.display(), "failed to append to API event log");
+            self.file = None;
+        }
+    }
+
+    async fn try_append(&mut self, event: &JournaledEvent) -> std::io::Result<()> {
+        let mut line = serde_json::to_string(event)?;
+        line.push('\n');
+        if self.size + line.len() as u64 > MAX_LOG_BYTES {
+            self.file = None;
+            tokio::fs::rename(&self.path, rotated(&self.path)).await?;
+            self.size = 0;
+        }
+        let file = match &mut self.file {
+            Some(file) => file,
+            None => {
+                if let Some(parent) = self.path.parent() {
+                    tokio::fs::create_dir_all(parent).await?;
+                }
+                let file = tokio::fs::OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(&self.path)
+                    .await?;
+                self.file.insert(file)
+            }
+        };
+        file.write_all(line.as_bytes()).await?;
+        self.size += line.len() as u64;
+        Ok(())
+    }
+}
+
+fn rotated(path: &Path) -> PathBuf {
+    let mut rotated = path.as_os_str().to_owned();
+    rotated.push(".1");
+    PathBuf::from(rotated)
+}
+
+/// Events in the log at `path` after `cursor`. Lines that don't parse are
+/// skipped, and a missing log has none.
+fn read_after(path: &Path, cursor: u64) -> Vec<JournaledEvent> {
+    let Ok(file) = std::fs::File::open(path) else {
+        return Vec::new();
+    };
+    std::io::BufReader::new(file)
+        .lines()
+        .map_while(Result::ok)
+        .filter_map(|line| serde_json::from_str::<JournaledEvent>(&line).ok())
+        .filter(|event| event.cursor > cursor)
+        .collect()
+}
+
+/// The last cursor written to the log at `path` or, if it's empty, to the
+/// rotated log before it.
+fn last_cursor(path: &Path) -> Option<u64> {
+    [path.to_path_buf(), rotated(path)]
+        .iter()
+        .find_map(|path| read_after(path, 0).last().map(|event| event.cursor))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(cursor: u64) -> JournaledEvent {
+        JournaledEvent {
+            cursor,
+            kind: "tool_started".into(),
+            data: "{}".into(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_log_resumes_across_rotation() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("logs").join("events.jsonl");
+        let mut log = LogWriter::new(path.clone());
+        for cursor in 1..=3 {
+            log.append(&event(cursor)).await;
+        }
+        // Force a rotation before the next write.
+        log.size = MAX_LOG_BYTES;
+        log.append(&event(4)).await;
+
+        assert_eq!(last_cursor(&path), Some(4));
+        let mut replayed = read_after(&rotated(&path), 2);
+        replayed.extend(read_after(&path, 2));
+        assert_eq!(replayed, vec![event(3), event(4)]);
+    }
+}
@@ -1,12 +1,19 @@
 //! HTTP server setup: router, static file serving, and API routes.
 
-use super::state::{AgentInfo, ApiEvent, ApiState};
+use super::journal::JournaledEvent;
+use super::state::{AgentInfo, ApiState};
 use crate::agent::cortex::{CortexEvent, CortexLogger};
 use crate::agent::cortex_chat::{CortexChatEvent, CortexChatMessage, CortexChatStore};
 use crate::conversation::channels::ChannelStore;
-use crate::conversation::history::{ProcessRunLogger, TimelineItem};
+use crate::conversation::forks::{ConversationFork, ForkStore};
+use crate::conversation::history::{ConversationMessage, ProcessRunLogger, TimelineItem};
+use crate::daemon::LogLevelChange;
+use crate::feedback::{FeedbackEntry, FeedbackStore, ModelFeedback};
+use crate::knowledge::{KnowledgeStatus, KnowledgeStore};
+use crate::maintenance::MaintenanceStatus;
 use crate::memory::search::{SearchConfig, SearchMode, SearchSort};
 use crate::memory::types::{Association, Memory, MemorySearchResult, MemoryType};
+use crate::messaging::impersonation::{self, ImpersonationRequest};
 
 use axum::Router;
 use axum::extract::{Query, State};
@@ -38,12 +45,87 @@ struct HealthResponse {
     status: &'static str,
 }
 
+#[derive(Serialize)]
+struct AdapterHealthResponse {
+    status: &'static str,
+    adapters: Vec<crate::messaging::manager::AdapterHealth>,
+}
+
+#[derive(Serialize)]
+struct LlmMetricsResponse {
+    usage: Vec<crate::llm::metrics::UsageCount>,
+    histograms: Vec<crate::llm::metrics::HistogramSnapshot>,
+    parse_warnings: Vec<crate::llm::metrics::ParseWarningCount>,
+    compression: Vec<crate::llm::metrics::CompressionCount>,
+    empty_responses: Vec<crate::llm::metrics::EmptyResponseCount>,
+    tags: Vec<crate::llm::metrics::TagUsageCount>,
+}
+
+#[derive(Serialize)]
+struct UsageSeriesResponse {
+    points: Vec<crate::usage_snapshots::UsagePoint>,
+}
+
+#[derive(Serialize)]
+struct LlmHealthResponse {
+    /// Last probe per self-hosted provider. Hosted providers aren't probed.
+    providers: HashMap<String, crate::llm::health::ProviderHealth>,
+    /// Providers currently failing across their models, oldest first.
+    outages: Vec<crate::llm::outage::ProviderOutage>,
+}
+
+#[derive(Serialize)]
+struct LlmKeysResponse {
+    /// Last check of each hosted provider's keys.
+    keys: Vec<crate::llm::key_health::KeyHealth>,
+}
+
+#[derive(Serialize)]
+struct LlmRacesResponse {
+    races: Vec<crate::llm::race::RaceRecord>,
+}
+
+#[derive(Serialize)]
+struct LlmShadowResponse {
+    /// One per primary and candidate pair.
+    summaries: Vec<crate::llm::shadow::ShadowSummary>,
+    /// Recent shadow requests, newest first.
+    requests: Vec<crate::llm::shadow::ShadowRecord>,
+}
+
+#[derive(Serialize)]
+struct LlmFaultsResponse {
+    /// Fault rules in effect, with how often each has fired.
+    faults: Vec<crate::llm::faults::ActiveFault>,
+}
+
+#[derive(Deserialize)]
+struct SetLlmFaultsRequest {
+    faults: Vec<crate::llm::faults::FaultRule>,
+    /// Expire rules without their own `expires_at` after this long, so a
+    /// forgotten test doesn't keep failing requests.
+    duration_secs: Option<u64>,
+}
+
+#[derive(Serialize)]
+struct LlmDebugRequestsResponse {
+    enabled: bool,
+    request_ids: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct LlmDebugRequestResponse {
+    request_id: String,
+    attempts: Vec<crate::llm::recorder::RecordedAttempt>,
+}
+
 #[derive(Serialize)]
 struct StatusResponse {
     status: &'static str,
     version: &'static str,
     pid: u32,
     uptime_seconds: u64,
+    maintenance: bool,
 }
 
 #[derive(Serialize)]
@@ -53,6 +135,10 @@ struct ChannelResponse {
     platform: String,
     display_name: Option<String>,
     is_active: bool,
+    /// Model set with `/model set`, if any.
+    model_override: Option<String>,
+    /// Persona set with `/persona`, if any.
+    persona: Option<String>,
     last_activity_at: String,
     created_at: String,
 }
@@ -95,6 +181,9 @@ struct AgentOverviewResponse {
     activity_heatmap: Vec<HeatmapCell>,
     /// Latest cortex bulletin text, if any.
     latest_bulletin: Option<String>,
+    /// Turn usage over the last 30 days, with input tokens split into
+    /// preamble, tool schemas and history.
+    turn_usage: crate::conversation::history::TurnUsageTotals,
 }
 
 #[derive(Serialize)]
@@ -186,6 +275,56 @@ struct CortexEventsResponse {
     total: i64,
 }
 
+#[derive(Serialize)]
+struct FeedbackResponse {
+    feedback: Vec<FeedbackEntry>,
+    total: i64,
+}
+
+#[derive(Serialize)]
+struct FeedbackModelsResponse {
+    models: Vec<ModelFeedback>,
+}
+
+#[derive(Serialize)]
+struct LanguagesResponse {
+    languages: Vec<crate::language::LanguageCount>,
+    /// Languages left out for coming from too few conversations.
+    suppressed_groups: usize,
+}
+
+#[derive(Serialize)]
+struct FlagsResponse {
+    flags: Vec<crate::flags::FlagStats>,
+    /// Flags left out for being on in too few conversations.
+    suppressed_groups: usize,
+}
+
+#[derive(Serialize)]
+struct TagsResponse {
+    tags: Vec<crate::usage_tags::TagStats>,
+    /// Tag values left out for being on too few conversations.
+    suppressed_groups: usize,
+}
+
+#[derive(Serialize)]
+struct PromptVersionsResponse {
+    versions: Vec<crate::prompts::version::PromptVersionStats>,
+    /// Versions left out for running in too few conversations.
+    suppressed_groups: usize,
+}
+
+#[derive(Serialize)]
+struct ToolUsageResponse {
+    /// Totals since startup, by tool.
+    totals: Vec<crate::tools::usage::ToolStats>,
+    /// Calls over the requested days, by tool and conversation, or only
+    /// by tool when the agent has a minimum group size.
+    spend: Vec<crate::tools::usage::ToolSpend>,
+    /// Tools left out for being used in too few conversations.
+    suppressed_groups: usize,
+}
+
 #[derive(Serialize)]
 struct CortexChatMessagesResponse {
     messages: Vec<CortexChatMessage>,
@@ -244,6 +383,23 @@ struct IngestUploadResponse {
     uploaded: Vec<String>,
 }
 
+#[derive(Serialize)]
+struct KnowledgeSourceInfo {
+    id: String,
+    kind: &'static str,
+    url: String,
+    store: String,
+    interval_secs: u64,
+    enabled: bool,
+    /// None until the source's first sync starts.
+    status: Option<KnowledgeStatus>,
+}
+
+#[derive(Serialize)]
+struct KnowledgeSourcesResponse {
+    sources: Vec<KnowledgeSourceInfo>,
+}
+
 #[derive(Serialize)]
 struct IngestDeleteResponse {
     success: bool,
@@ -436,14 +592,22 @@ pub async fn start_http_server(
 
     let api_routes = Router::new()
         .route("/health", get(health))
+        .route("/readyz", get(readyz))
+        .route("/healthz", get(healthz))
         .route("/status", get(status))
+        .route("/maintenance", get(get_maintenance).put(set_maintenance))
+        .route("/log-level", get(get_log_level).put(set_log_level))
         .route("/overview", get(instance_overview))
         .route("/events", get(events_sse))
         .route("/agents", get(list_agents))
         .route("/agents/overview", get(agent_overview))
+        .route("/capabilities", get(capabilities))
         .route("/channels", get(list_channels))
         .route("/channels/messages", get(channel_messages))
         .route("/channels/status", get(channel_status))
+        .route("/channels/forks", get(list_forks).post(edit_fork))
+        .route("/channels/forks/messages", get(fork_messages))
+        .route("/channels/forks/active", put(switch_fork))
         .route("/agents/memories", get(list_memories))
         .route("/agents/memories/search", get(search_memories))
         .route("/agents/memories/graph", get(memory_graph))
@@ -469,16 +633,56 @@ pub async fn start_http_server(
         .route("/agents/cron/executions", get(cron_executions))
         .route("/agents/cron/trigger", post(trigger_cron))
         .route("/agents/cron/toggle", put(toggle_cron))
+        .route("/incidents/{agent_id}/{account}", post(incident_webhook))
+        .route("/agents/feedback", get(list_feedback))
+        .route("/agents/feedback/export", get(export_feedback))
+        .route("/agents/feedback/models", get(feedback_models))
+        .route("/agents/languages", get(agent_languages))
+        .route("/agents/prompt-versions", get(agent_prompt_versions))
+        .route("/agents/flags", get(agent_flags))
+        .route("/agents/tags", get(agent_tags))
+        .route("/agents/tools/usage", get(agent_tool_usage))
+        .route("/agents/rate-limits", get(rate_limit_stats))
+        .route("/agents/tool-guard", get(tool_guard_stats))
+        .route("/agents/declines", get(decline_stats))
+        .route("/agents/escalations", get(list_escalations))
+        .route("/agents/escalations/resume", post(resume_escalation))
+        .route(
+            "/channels/shares",
+            get(list_share_links)
+                .post(create_share_link)
+                .delete(revoke_share_link),
+        )
+        .route("/intake", get(intake_stats))
         .route("/channels/cancel", post(cancel_process))
+        .route("/messages/impersonate", post(impersonate_message))
         .route(
             "/agents/ingest/files",
             get(list_ingest_files).delete(delete_ingest_file),
         )
         .route("/agents/ingest/upload", post(upload_ingest_file))
+        .route("/agents/knowledge", get(list_knowledge_sources))
         .route("/providers", get(get_providers).put(update_provider))
         .route("/providers/{provider}", delete(delete_provider))
         .route("/models", get(get_models))
         .route("/models/refresh", post(refresh_models))
+        .route("/llm/metrics", get(llm_metrics))
+        .route("/usage/series", get(usage_series))
+        .route("/llm/health", get(llm_health))
+        .route("/llm/races", get(llm_races))
+        .route("/llm/shadow", get(llm_shadow))
+        .route(
+            "/llm/faults",
+            get(llm_faults).put(set_llm_faults).delete(clear_llm_faults),
+        )
+        .route("/llm/debug", get(llm_debug_requests))
+        .route("/llm/debug/{request_id}", get(llm_debug_request))
+        .route("/llm/credentials", get(llm_keys))
+        .route("/llm/credentials/check", post(check_llm_keys))
+        .route(
+            "/llm/credentials/{provider}/restore-primary",
+            post(llm_restore_primary_key),
+        )
         .route("/messaging/status", get(messaging_status))
         .route(
             "/bindings",
@@ -497,6 +701,7 @@ pub async fn start_http_server(
 
     let app = Router::new()
         .nest("/api", api_routes)
+        .route("/share/{agent_id}/{token}", get(shared_transcript))
         .fallback(static_handler)
         .layer(cors)
         .with_state(state);
@@ -525,6 +730,49 @@ async fn health() -> Json<HealthResponse> {
     Json(HealthResponse { status: "ok" })
 }
 
+/// Readiness probe. Returns 503 until provider connections are warmed and
+/// agents are initialized, so load balancers don't route to a cold instance.
+async fn readyz(State(state): State<Arc<ApiState>>) -> (StatusCode, Json<HealthResponse>) {
+    if state.is_ready() {
+        (StatusCode::OK, Json(HealthResponse { status: "ready" }))
+    } else {
+        (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(HealthResponse { status: "starting" }),
+        )
+    }
+}
+
+/// Messaging adapter health. Returns 503 while any adapter is reconnecting or
+/// failing its health check, with each adapter's state and restart count.
+async fn healthz(State(state): State<Arc<ApiState>>) -> (StatusCode, Json<AdapterHealthResponse>) {
+    let manager = state.messaging_manager.read().await.clone();
+    let adapters = match manager {
+        Some(manager) => manager.health().await,
+        None => Vec::new(),
+    };
+    let healthy = adapters
+        .iter()
+        .all(|adapter| adapter.state == crate::messaging::manager::AdapterState::Connected);
+    if healthy {
+        (
+            StatusCode::OK,
+            Json(AdapterHealthResponse {
+                status: "ok",
+                adapters,
+            }),
+        )
+    } else {
+        (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(AdapterHealthResponse {
+                status: "degraded",
+                adapters,
+            }),
+        )
+    }
+}
+
 async fn status(State(state): State<Arc<ApiState>>) -> Json<StatusResponse> {
     let uptime = state.started_at.elapsed();
     Json(StatusResponse {
@@ -532,9 +780,58 @@ async fn status(State(state): State<Arc<ApiState>>) -> Json<StatusResponse> {
         version: env!("CARGO_PKG_VERSION"),
         pid: std::process::id(),
         uptime_seconds: uptime.as_secs(),
+        maintenance: state.maintenance.is_enabled(),
     })
 }
 
+#[derive(Serialize)]
+struct LogLevelResponse {
+    /// The filter in effect, as `EnvFilter` directives.
+    filter: String,
+}
+
+/// The log filter in effect. An empty change reads it without changing it.
+async fn get_log_level() -> Result<Json<LogLevelResponse>, StatusCode> {
+    set_log_level(Json(LogLevelChange::default())).await
+}
+
+/// Change the log level, for everything or one target, without restarting.
+async fn set_log_level(
+    Json(change): Json<LogLevelChange>,
+) -> Result<Json<LogLevelResponse>, StatusCode> {
+    crate::daemon::change_log_filter(&change)
+        .map(|filter| Json(LogLevelResponse { filter }))
+        .map_err(|error| {
+            tracing::warn!(error = %format!("{error:#}"), "failed to change log level");
+            StatusCode::BAD_REQUEST
+        })
+}
+
+#[derive(Deserialize)]
+struct MaintenanceRequest {
+    enabled: bool,
+    /// Notice sent to users instead of the default one.
+    #[serde(default)]
+    message: Option<String>,
+}
+
+async fn get_maintenance(State(state): State<Arc<ApiState>>) -> Json<MaintenanceStatus> {
+    Json(state.maintenance.status())
+}
+
+/// Turn maintenance mode on or off. Turns already running finish.
+async fn set_maintenance(
+    State(state): State<Arc<ApiState>>,
+    Json(request): Json<MaintenanceRequest>,
+) -> Json<MaintenanceStatus> {
+    if request.enabled {
+        state.maintenance.enable(request.message);
+    } else {
+        state.maintenance.disable();
+    }
+    Json(state.maintenance.status())
+}
+
 /// List all configured agents with their config summaries.
 async fn list_agents(State(state): State<Arc<ApiState>>) -> Json<AgentsResponse> {
     let agents = state.agent_configs.load();
@@ -543,6 +840,26 @@ async fn list_agents(State(state): State<Arc<ApiState>>) -> Json<AgentsResponse>
     })
 }
 
+/// Describe every running agent's tools, model tiers and budgets, for
+/// embedding hosts.
+async fn capabilities(
+    State(state): State<Arc<ApiState>>,
+) -> Json<crate::agent::capabilities::Capabilities> {
+    let schedulers = state.cron_schedulers.load();
+    let mut agent_ids: Vec<&String> = schedulers.keys().collect();
+    agent_ids.sort();
+
+    let mut agents = Vec::with_capacity(agent_ids.len());
+    for agent_id in agent_ids {
+        let context = schedulers[agent_id].context();
+        agents.push(
+            crate::agent::capabilities::describe(&context.deps, context.screenshot_dir.clone())
+                .await,
+        );
+    }
+    Json(crate::agent::capabilities::Capabilities::new(agents))
+}
+
 /// Get overview stats for an agent: memory breakdown, channels, cron, cortex.
 async fn agent_overview(
     State(state): State<Arc<ApiState>>,
@@ -706,6 +1023,10 @@ async fn agent_overview(
         })
         .collect();
 
+    let turn_usage = crate::conversation::history::agent_turn_usage(pool, 30 * 24)
+        .await
+        .unwrap_or_default();
+
     Ok(Json(AgentOverviewResponse {
         memory_counts,
         memory_total,
@@ -717,6 +1038,7 @@ async fn agent_overview(
         activity_daily,
         activity_heatmap,
         latest_bulletin,
+        turn_usage,
     }))
 }
 
@@ -824,33 +1146,51 @@ async fn instance_overview(
     }))
 }
 
-/// SSE endpoint streaming all agent events to connected clients.
+#[derive(Deserialize)]
+struct EventsQuery {
+    /// Last event cursor the client saw; events after it are replayed.
+    /// The `Last-Event-ID` header works too.
+    cursor: Option<u64>,
+}
+
+/// SSE endpoint streaming all agent events to connected clients. Each event
+/// carries its cursor as the SSE id; a client that reconnects with one gets
+/// the events it missed first.
 async fn events_sse(
     State(state): State<Arc<ApiState>>,
+    Query(query): Query<EventsQuery>,
+    headers: axum::http::HeaderMap,
 ) -> Sse<impl Stream<Item = Result<axum::response::sse::Event, Infallible>>> {
-    let mut rx = state.event_tx.subscribe();
+    let resume_from = query
+        .cursor
+        .or_else(|| headers.get("last-event-id")?.to_str().ok()?.parse().ok());
+    // Subscribe before reading the backlog so nothing falls between them.
+    let mut rx = state.event_journal.subscribe();
+    let backlog = match resume_from {
+        Some(cursor) => state.event_journal.since(cursor).await,
+        None => Vec::new(),
+    };
 
     let stream = async_stream::stream! {
+        let mut last_cursor = resume_from.unwrap_or(0);
+        // Older than the retained history: report the gap so the client
+        // re-syncs, as it would after lagging.
+        if let Some(first) = backlog.first().filter(|first| first.cursor > last_cursor + 1) {
+            let skipped = first.cursor - last_cursor - 1;
+            yield Ok(axum::response::sse::Event::default()
+                .event("lagged")
+                .data(format!("{{\"skipped\":{skipped}}}")));
+        }
+        for event in backlog {
+            last_cursor = event.cursor;
+            yield Ok(journaled_sse_event(event));
+        }
         loop {
             match rx.recv().await {
                 Ok(event) => {
-                    if let Ok(json) = serde_json::to_string(&event) {
-                        let event_type = match &event {
-                            ApiEvent::InboundMessage { .. } => "inbound_message",
-                            ApiEvent::OutboundMessage { .. } => "outbound_message",
-                            ApiEvent::TypingState { .. } => "typing_state",
-                            ApiEvent::WorkerStarted { .. } => "worker_started",
-                            ApiEvent::WorkerStatusUpdate { .. } => "worker_status",
-                            ApiEvent::WorkerCompleted { .. } => "worker_completed",
-                            ApiEvent::BranchStarted { .. } => "branch_started",
-                            ApiEvent::BranchCompleted { .. } => "branch_completed",
-                            ApiEvent::BranchFailed { .. } => "branch_failed",
-                            ApiEvent::ToolStarted { .. } => "tool_started",
-                            ApiEvent::ToolCompleted { .. } => "tool_completed",
-                        };
-                        yield Ok(axum::response::sse::Event::default()
-                            .event(event_type)
-                            .data(json));
+                    if event.cursor > last_cursor {
+                        last_cursor = event.cursor;
+                        yield Ok(journaled_sse_event(event));
                     }
                 }
                 Err(tokio::sync::broadcast::error::RecvError::Lagged(count)) => {
@@ -871,6 +1211,13 @@ async fn events_sse(
     )
 }
 
+fn journaled_sse_event(event: JournaledEvent) -> axum::response::sse::Event {
+    axum::response::sse::Event::default()
+        .id(event.cursor.to_string())
+        .event(event.kind)
+        .data(event.data)
+}
+
 /// List active channels across all agents.
 async fn list_channels(State(state): State<Arc<ApiState>>) -> Json<ChannelsResponse> {
     let pools = state.agent_pools.load();
@@ -887,6 +1234,8 @@ async fn list_channels(State(state): State<Arc<ApiState>>) -> Json<ChannelsRespo
                         platform: channel.platform,
                         display_name: channel.display_name,
                         is_active: channel.is_active,
+                        model_override: channel.model_override,
+                        persona: channel.persona,
                         last_activity_at: channel.last_activity_at.to_rfc3339(),
                         created_at: channel.created_at.to_rfc3339(),
                     });
@@ -979,6 +1328,182 @@ async fn channel_status(
     Json(result)
 }
 
+// -- Conversation forks --
+
+/// Messages loaded into a channel's live history after a fork switch.
+const FORK_HISTORY_LIMIT: usize = 200;
+
+#[derive(Deserialize)]
+struct ForksQuery {
+    agent_id: String,
+    channel_id: String,
+}
+
+#[derive(Serialize)]
+struct ForksResponse {
+    forks: Vec<ConversationFork>,
+    /// None when the channel is on its root line.
+    active_fork_id: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ForkMessagesQuery {
+    agent_id: String,
+    channel_id: String,
+    /// Omit for the root line.
+    fork_id: Option<String>,
+    #[serde(default = "default_message_limit")]
+    limit: i64,
+}
+
+#[derive(Serialize)]
+struct ForkMessagesResponse {
+    messages: Vec<ConversationMessage>,
+}
+
+#[derive(Deserialize)]
+struct EditForkRequest {
+    agent_id: String,
+    channel_id: String,
+    /// The user message being edited.
+    message_id: String,
+}
+
+#[derive(Deserialize)]
+struct SwitchForkRequest {
+    agent_id: String,
+    channel_id: String,
+    /// None switches back to the root line.
+    fork_id: Option<String>,
+}
+
+/// List a channel's conversation forks and which one is active.
+async fn list_forks(
+    State(state): State<Arc<ApiState>>,
+    Query(query): Query<ForksQuery>,
+) -> Result<Json<ForksResponse>, StatusCode> {
+    let pools = state.agent_pools.load();
+    let pool = pools.get(&query.agent_id).ok_or(StatusCode::NOT_FOUND)?;
+    let store = ForkStore::new(pool.clone());
+
+    let forks = store.list(&query.channel_id).await.map_err(|error| {
+        tracing::warn!(%error, channel_id = %query.channel_id, "failed to list forks");
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    let active_fork_id = store.active(&query.channel_id).await.map_err(|error| {
+        tracing::warn!(%error, channel_id = %query.channel_id, "failed to load active fork");
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(Json(ForksResponse {
+        forks,
+        active_fork_id,
+    }))
+}
+
+/// Messages as seen from one fork, oldest first.
+async fn fork_messages(
+    State(state): State<Arc<ApiState>>,
+    Query(query): Query<ForkMessagesQuery>,
+) -> Result<Json<ForkMessagesResponse>, StatusCode> {
+    let pools = state.agent_pools.load();
+    let pool = pools.get(&query.agent_id).ok_or(StatusCode::NOT_FOUND)?;
+
+    let messages = ForkStore::new(pool.clone())
+        .load_messages(
+            &query.channel_id,
+            query.fork_id.as_deref(),
+            query.limit.clamp(1, 200) as usize,
+        )
+        .await
+        .map_err(|error| {
+            tracing::warn!(%error, channel_id = %query.channel_id, "failed to load fork messages");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    Ok(Json(ForkMessagesResponse { messages }))
+}
+
+/// Fork the conversation at an edited user message.
+///
+/// The new fork is made active and the live channel is rewound to just
+/// before the edited message. The client then sends the edited text as a
+/// normal message, which lands on the fork and gets a fresh reply.
+async fn edit_fork(
+    State(state): State<Arc<ApiState>>,
+    Json(request): Json<EditForkRequest>,
+) -> Result<Json<ConversationFork>, StatusCode> {
+    let pools = state.agent_pools.load();
+    let pool = pools.get(&request.agent_id).ok_or(StatusCode::NOT_FOUND)?;
+    let store = ForkStore::new(pool.clone());
+
+    let fork = store
+        .fork_at(&request.channel_id, &request.message_id)
+        .await
+        .map_err(|error| {
+            tracing::warn!(%error, channel_id = %request.channel_id, "failed to fork conversation");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    reload_live_history(&state, &store, &request.channel_id, Some(&fork.id)).await?;
+    Ok(Json(fork))
+}
+
+/// Switch a channel to another fork, or back to its root line.
+async fn switch_fork(
+    State(state): State<Arc<ApiState>>,
+    Json(request): Json<SwitchForkRequest>,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    let pools = state.agent_pools.load();
+    let pool = pools.get(&request.agent_id).ok_or(StatusCode::NOT_FOUND)?;
+    let store = ForkStore::new(pool.clone());
+
+    let switched = store
+        .switch(&request.channel_id, request.fork_id.as_deref())
+        .await
+        .map_err(|error| {
+            tracing::warn!(%error, channel_id = %request.channel_id, "failed to switch fork");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    if !switched {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    reload_live_history(
+        &state,
+        &store,
+        &request.channel_id,
+        request.fork_id.as_deref(),
+    )
+    .await?;
+    Ok(Json(serde_json::json!({ "success": true })))
+}
+
+/// Point a running channel's history at `fork_id`. Channels that aren't
+/// running pick nothing up; they start with an empty history anyway.
+async fn reload_live_history(
+    state: &ApiState,
+    store: &ForkStore,
+    channel_id: &str,
+    fork_id: Option<&str>,
+) -> Result<(), StatusCode> {
+    let states = state.channel_states.read().await;
+    let Some(channel_state) = states.get(channel_id) else {
+        return Ok(());
+    };
+
+    let messages = store
+        .load_messages(channel_id, fork_id, FORK_HISTORY_LIMIT)
+        .await
+        .map_err(|error| {
+            tracing::warn!(%error, %channel_id, "failed to load fork history");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    channel_state.replace_history(&messages).await;
+    Ok(())
+}
+
 #[derive(Deserialize)]
 struct MemoriesListQuery {
     agent_id: String,
@@ -1262,6 +1787,9 @@ async fn cortex_chat_send(
     State(state): State<Arc<ApiState>>,
     axum::Json(request): axum::Json<CortexChatSendRequest>,
 ) -> Result<Sse<impl Stream<Item = Result<axum::response::sse::Event, Infallible>>>, StatusCode> {
+    if state.maintenance.is_enabled() {
+        return Err(StatusCode::SERVICE_UNAVAILABLE);
+    }
     let sessions = state.cortex_chat_sessions.load();
     let session = sessions
         .get(&request.agent_id)
@@ -2125,6 +2653,52 @@ async fn trigger_cron(
     }))
 }
 
+/// Inbound PagerDuty or Opsgenie webhook for one of an agent's incident
+/// accounts. New pages run the agent's incident trigger; other events are
+/// accepted and dropped.
+async fn incident_webhook(
+    State(state): State<Arc<ApiState>>,
+    axum::extract::Path((agent_id, account_name)): axum::extract::Path<(String, String)>,
+    headers: axum::http::HeaderMap,
+    body: axum::body::Bytes,
+) -> StatusCode {
+    let schedulers = state.cron_schedulers.load();
+    let Some(scheduler) = schedulers.get(&agent_id) else {
+        return StatusCode::NOT_FOUND;
+    };
+    let context = scheduler.context().clone();
+    let config = context.deps.runtime_config.incidents.load_full();
+    let Some(account) = config
+        .accounts
+        .iter()
+        .find(|account| config.enabled && account.name == account_name)
+    else {
+        return StatusCode::NOT_FOUND;
+    };
+
+    if !crate::incidents::webhook_authentic(account, &headers, &body) {
+        tracing::warn!(%agent_id, account = %account_name, "rejected incident webhook that failed verification");
+        return StatusCode::UNAUTHORIZED;
+    }
+    let Some(incident) = crate::incidents::parse_page(account.provider, &body) else {
+        return StatusCode::ACCEPTED;
+    };
+    match &config.trigger {
+        Some(trigger) if crate::incidents::trigger_matches(trigger, &incident) => {
+            tokio::spawn(crate::incidents::fire(
+                context,
+                account.name.clone(),
+                trigger.clone(),
+                incident,
+            ));
+        }
+        _ => {
+            tracing::debug!(%agent_id, account = %account_name, incident_id = %incident.id, "page matched no incident trigger");
+        }
+    }
+    StatusCode::ACCEPTED
+}
+
 /// Enable or disable a cron job.
 async fn toggle_cron(
     State(state): State<Arc<ApiState>>,
@@ -2161,26 +2735,721 @@ async fn toggle_cron(
     }))
 }
 
-// -- Process cancellation --
+// -- Feedback handlers --
 
 #[derive(Deserialize)]
-struct CancelProcessRequest {
-    channel_id: String,
-    process_type: String,
-    process_id: String,
+struct FeedbackQuery {
+    agent_id: String,
+    #[serde(default = "default_feedback_limit")]
+    limit: i64,
+    #[serde(default)]
+    offset: i64,
 }
 
-#[derive(Serialize)]
-struct CancelProcessResponse {
-    success: bool,
-    message: String,
+fn default_feedback_limit() -> i64 {
+    50
 }
 
-/// Cancel a running worker or branch via the API.
-async fn cancel_process(
+#[derive(Deserialize)]
+struct FeedbackAgentQuery {
+    agent_id: String,
+}
+
+/// List feedback on an agent's replies, newest first.
+async fn list_feedback(
     State(state): State<Arc<ApiState>>,
-    Json(request): Json<CancelProcessRequest>,
-) -> Result<Json<CancelProcessResponse>, StatusCode> {
+    Query(query): Query<FeedbackQuery>,
+) -> Result<Json<FeedbackResponse>, StatusCode> {
+    let pools = state.agent_pools.load();
+    let pool = pools.get(&query.agent_id).ok_or(StatusCode::NOT_FOUND)?;
+    let store = FeedbackStore::new(pool.clone());
+
+    let feedback = store
+        .load(query.limit.min(200), query.offset)
+        .await
+        .map_err(|error| {
+            tracing::warn!(%error, agent_id = %query.agent_id, "failed to load feedback");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    let total = store.count().await.map_err(|error| {
+        tracing::warn!(%error, agent_id = %query.agent_id, "failed to count feedback");
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(Json(FeedbackResponse { feedback, total }))
+}
+
+#[derive(Deserialize)]
+struct RateLimitQuery {
+    agent_id: String,
+}
+
+#[derive(Serialize)]
+struct RateLimitStatsResponse {
+    enabled: bool,
+    #[serde(flatten)]
+    stats: crate::messaging::rate_limit::RateLimitStats,
+}
+
+/// Counts of inbound messages let through and rate limited since startup.
+async fn rate_limit_stats(
+    State(state): State<Arc<ApiState>>,
+    Query(query): Query<RateLimitQuery>,
+) -> Result<Json<RateLimitStatsResponse>, StatusCode> {
+    let limiters = state.rate_limiters.load();
+    let limiter = limiters.get(&query.agent_id).ok_or(StatusCode::NOT_FOUND)?;
+    let configs = state.runtime_configs.load();
+    let enabled = configs
+        .get(&query.agent_id)
+        .is_some_and(|config| config.rate_limit.load().enabled);
+    Ok(Json(RateLimitStatsResponse {
+        enabled,
+        stats: limiter.stats(),
+    }))
+}
+
+#[derive(Serialize)]
+struct ToolGuardStatsResponse {
+    enabled: bool,
+    #[serde(flatten)]
+    stats: crate::hooks::tool_guard::GuardStats,
+}
+
+/// Counts of the tool guard's verdicts on high-risk tool calls since startup.
+async fn tool_guard_stats(
+    State(state): State<Arc<ApiState>>,
+    Query(query): Query<RateLimitQuery>,
+) -> Result<Json<ToolGuardStatsResponse>, StatusCode> {
+    let metrics = state.guard_metrics.load();
+    let metrics = metrics.get(&query.agent_id).ok_or(StatusCode::NOT_FOUND)?;
+    let configs = state.runtime_configs.load();
+    let enabled = configs
+        .get(&query.agent_id)
+        .is_some_and(|config| config.tool_guard.load().enabled);
+    Ok(Json(ToolGuardStatsResponse {
+        enabled,
+        stats: metrics.stats(),
+    }))
+}
+
+#[derive(Serialize)]
+struct DeclineStatsResponse {
+    /// Declined messages since startup, by reason. Reasons with none are
+    /// left out.
+    declined: std::collections::BTreeMap<crate::declined::DeclineReason, u64>,
+}
+
+/// Counts of messages the policy layers declined to answer since startup,
+/// kept apart from failed turns.
+async fn decline_stats(
+    State(state): State<Arc<ApiState>>,
+    Query(query): Query<RateLimitQuery>,
+) -> Result<Json<DeclineStatsResponse>, StatusCode> {
+    let metrics = state.decline_metrics.load();
+    let metrics = metrics.get(&query.agent_id).ok_or(StatusCode::NOT_FOUND)?;
+    Ok(Json(DeclineStatsResponse {
+        declined: metrics.counts(),
+    }))
+}
+
+#[derive(Serialize)]
+struct EscalationsResponse {
+    escalations: Vec<crate::conversation::Escalation>,
+}
+
+#[derive(Deserialize)]
+struct ResumeEscalationRequest {
+    agent_id: String,
+    channel_id: String,
+    /// Recorded as the moderator who resolved it.
+    #[serde(default = "default_resolved_by")]
+    resolved_by: String,
+}
+
+fn default_resolved_by() -> String {
+    "api".to_string()
+}
+
+/// Conversations waiting for a moderator, oldest first.
+async fn list_escalations(
+    State(state): State<Arc<ApiState>>,
+    Query(query): Query<RateLimitQuery>,
+) -> Result<Json<EscalationsResponse>, StatusCode> {
+    let pools = state.agent_pools.load();
+    let pool = pools.get(&query.agent_id).ok_or(StatusCode::NOT_FOUND)?;
+    let escalations = crate::conversation::EscalationStore::new(pool.clone())
+        .list_open()
+        .await
+        .map_err(|error| {
+            tracing::warn!(%error, agent_id = %query.agent_id, "failed to list escalations");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    Ok(Json(EscalationsResponse { escalations }))
+}
+
+/// Resolve a conversation's escalation, resuming automated replies there.
+async fn resume_escalation(
+    State(state): State<Arc<ApiState>>,
+    Json(request): Json<ResumeEscalationRequest>,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    let pools = state.agent_pools.load();
+    let pool = pools.get(&request.agent_id).ok_or(StatusCode::NOT_FOUND)?;
+    let resolved = crate::conversation::EscalationStore::new(pool.clone())
+        .resolve(&request.channel_id, &request.resolved_by)
+        .await
+        .map_err(|error| {
+            tracing::warn!(%error, channel_id = %request.channel_id, "failed to resolve escalation");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    if !resolved {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    tracing::info!(
+        agent_id = %request.agent_id,
+        channel_id = %request.channel_id,
+        resolved_by = %request.resolved_by,
+        "escalation resolved through the API"
+    );
+    Ok(Json(serde_json::json!({ "success": true })))
+}
+
+#[derive(Deserialize)]
+struct ShareLinksQuery {
+    agent_id: String,
+    channel_id: String,
+}
+
+#[derive(Serialize)]
+struct ShareLinksResponse {
+    links: Vec<crate::conversation::ShareLink>,
+}
+
+#[derive(Deserialize)]
+struct CreateShareLinkRequest {
+    agent_id: String,
+    channel_id: String,
+    /// Days until the link expires, at most 30.
+    #[serde(default = "default_share_days")]
+    expires_in_days: i64,
+    /// Recorded as who created the link.
+    #[serde(default = "default_created_by")]
+    created_by: String,
+}
+
+fn default_share_days() -> i64 {
+    crate::conversation::shares::DEFAULT_EXPIRY_DAYS
+}
+
+fn default_created_by() -> String {
+    "api".to_string()
+}
+
+#[derive(Serialize)]
+struct CreateShareLinkResponse {
+    link: crate::conversation::ShareLink,
+    /// Only returned here. Lost tokens can't be recovered, only revoked.
+    token: String,
+    /// Where the transcript is served, relative to the dashboard.
+    path: String,
+}
+
+#[derive(Deserialize)]
+struct RevokeShareLinkRequest {
+    agent_id: String,
+    id: String,
+}
+
+/// A conversation's sharing links, newest first.
+async fn list_share_links(
+    State(state): State<Arc<ApiState>>,
+    Query(query): Query<ShareLinksQuery>,
+) -> Result<Json<ShareLinksResponse>, StatusCode> {
+    let pools = state.agent_pools.load();
+    let pool = pools.get(&query.agent_id).ok_or(StatusCode::NOT_FOUND)?;
+    let links = crate::conversation::ShareLinkStore::new(pool.clone())
+        .list(&query.channel_id)
+        .await
+        .map_err(|error| {
+            tracing::warn!(%error, channel_id = %query.channel_id, "failed to list share links");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    Ok(Json(ShareLinksResponse { links }))
+}
+
+/// Create a read-only link to a conversation's redacted transcript.
+async fn create_share_link(
+    State(state): State<Arc<ApiState>>,
+    Json(request): Json<CreateShareLinkRequest>,
+) -> Result<Json<CreateShareLinkResponse>, StatusCode> {
+    // Checked before building the TimeDelta, which panics when out of range.
+    if !(1..=crate::conversation::shares::MAX_EXPIRY_DAYS).contains(&request.expires_in_days) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let pools = state.agent_pools.load();
+    let pool = pools.get(&request.agent_id).ok_or(StatusCode::NOT_FOUND)?;
+    let created = crate::conversation::ShareLinkStore::new(pool.clone())
+        .create(
+            &request.channel_id,
+            &request.created_by,
+            chrono::TimeDelta::days(request.expires_in_days),
+        )
+        .await
+        .map_err(|error| {
+            tracing::warn!(%error, channel_id = %request.channel_id, "failed to create share link");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    let (link, token) = created.ok_or(StatusCode::NOT_FOUND)?;
+    tracing::info!(
+        agent_id = %request.agent_id,
+        channel_id = %request.channel_id,
+        link_id = %link.id,
+        expires_at = %link.expires_at,
+        "share link created"
+    );
+    Ok(Json(CreateShareLinkResponse {
+        path: format!("/share/{}/{token}", request.agent_id),
+        link,
+        token,
+    }))
+}
+
+/// Revoke a sharing link. Its transcript stops being served right away.
+async fn revoke_share_link(
+    State(state): State<Arc<ApiState>>,
+    Query(request): Query<RevokeShareLinkRequest>,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    let pools = state.agent_pools.load();
+    let pool = pools.get(&request.agent_id).ok_or(StatusCode::NOT_FOUND)?;
+    let revoked = crate::conversation::ShareLinkStore::new(pool.clone())
+        .revoke(&request.id)
+        .await
+        .map_err(|error| {
+            tracing::warn!(%error, link_id = %request.id, "failed to revoke share link");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    if !revoked {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    tracing::info!(agent_id = %request.agent_id, link_id = %request.id, "share link revoked");
+    Ok(Json(serde_json::json!({ "success": true })))
+}
+
+/// The redacted transcript behind a sharing link, as a standalone page.
+/// Unknown, expired and revoked links all get the same 404.
+async fn shared_transcript(
+    State(state): State<Arc<ApiState>>,
+    axum::extract::Path((agent_id, token)): axum::extract::Path<(String, String)>,
+) -> Response {
+    let pool = state.agent_pools.load().get(&agent_id).cloned();
+    let link = match pool {
+        Some(pool) => match crate::conversation::ShareLinkStore::new(pool.clone())
+            .resolve(&token)
+            .await
+        {
+            Ok(Some(link)) => Some((pool, link)),
+            Ok(None) => None,
+            Err(error) => {
+                tracing::warn!(%error, %agent_id, "failed to look up share link");
+                return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+            }
+        },
+        None => None,
+    };
+    let Some((pool, link)) = link else {
+        return (
+            StatusCode::NOT_FOUND,
+            "This link has expired or doesn't exist.",
+        )
+            .into_response();
+    };
+
+    let transcript =
+        match crate::conversation::transcript::Transcript::load(&pool, &link.channel_id).await {
+            Ok(transcript) => transcript.shared(),
+            Err(error) => {
+                tracing::warn!(%error, link_id = %link.id, "failed to load shared transcript");
+                return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+            }
+        };
+    let page = transcript.export(crate::conversation::transcript::ExportFormat::Html);
+    (
+        [
+            (header::CACHE_CONTROL, "no-store"),
+            (header::REFERRER_POLICY, "no-referrer"),
+            (
+                header::HeaderName::from_static("x-robots-tag"),
+                "noindex, nofollow",
+            ),
+        ],
+        Html(page),
+    )
+        .into_response()
+}
+
+#[derive(Serialize)]
+struct IntakeStatsResponse {
+    enabled: bool,
+    method: Option<&'static str>,
+    min_confidence: Option<f32>,
+    default_agent: Option<String>,
+    stats: crate::messaging::intake::IntakeStats,
+}
+
+/// Where intake classification has routed unbound conversations since startup.
+async fn intake_stats(State(state): State<Arc<ApiState>>) -> Json<IntakeStatsResponse> {
+    let intake = state.intake.read().await;
+    let Some(intake) = intake.as_ref() else {
+        return Json(IntakeStatsResponse {
+            enabled: false,
+            method: None,
+            min_confidence: None,
+            default_agent: None,
+            stats: Default::default(),
+        });
+    };
+    Json(IntakeStatsResponse {
+        enabled: true,
+        method: Some(intake.config().method.as_str()),
+        min_confidence: Some(intake.config().min_confidence),
+        default_agent: Some(intake.default_agent().to_string()),
+        stats: intake.stats(),
+    })
+}
+
+/// Download the whole feedback ledger as JSON Lines.
+async fn export_feedback(
+    State(state): State<Arc<ApiState>>,
+    Query(query): Query<FeedbackAgentQuery>,
+) -> Result<Response, StatusCode> {
+    let pools = state.agent_pools.load();
+    let pool = pools.get(&query.agent_id).ok_or(StatusCode::NOT_FOUND)?;
+
+    let feedback = FeedbackStore::new(pool.clone())
+        .load(i64::MAX, 0)
+        .await
+        .map_err(|error| {
+            tracing::warn!(%error, agent_id = %query.agent_id, "failed to export feedback");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    let mut body = String::new();
+    for entry in &feedback {
+        let line = serde_json::to_string(entry).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        body.push_str(&line);
+        body.push('\n');
+    }
+
+    let filename = format!("attachment; filename=\"feedback-{}.jsonl\"", query.agent_id);
+    Ok((
+        StatusCode::OK,
+        [
+            (header::CONTENT_TYPE, "application/x-ndjson".to_string()),
+            (header::CONTENT_DISPOSITION, filename),
+        ],
+        body,
+    )
+        .into_response())
+}
+
+/// Ratings and average usage per model, for comparing models.
+async fn feedback_models(
+    State(state): State<Arc<ApiState>>,
+    Query(query): Query<FeedbackAgentQuery>,
+) -> Result<Json<FeedbackModelsResponse>, StatusCode> {
+    let pools = state.agent_pools.load();
+    let pool = pools.get(&query.agent_id).ok_or(StatusCode::NOT_FOUND)?;
+
+    let models = FeedbackStore::new(pool.clone())
+        .model_summary()
+        .await
+        .map_err(|error| {
+            tracing::warn!(%error, agent_id = %query.agent_id, "failed to summarize feedback");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    Ok(Json(FeedbackModelsResponse { models }))
+}
+
+#[derive(Deserialize)]
+struct LanguagesQuery {
+    agent_id: String,
+    #[serde(default = "default_languages_days")]
+    days: u32,
+}
+
+fn default_languages_days() -> u32 {
+    30
+}
+
+/// The languages an agent's users write in: turns per detected language
+/// over the last `days` days.
+async fn agent_languages(
+    State(state): State<Arc<ApiState>>,
+    Query(query): Query<LanguagesQuery>,
+) -> Result<Json<LanguagesResponse>, StatusCode> {
+    let pools = state.agent_pools.load();
+    let pool = pools.get(&query.agent_id).ok_or(StatusCode::NOT_FOUND)?;
+
+    let mut languages = crate::language::language_mix(pool, query.days)
+        .await
+        .map_err(|error| {
+            tracing::warn!(%error, agent_id = %query.agent_id, "failed to count languages");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    let suppressed_groups = crate::usage_privacy::suppress_small_groups(
+        &mut languages,
+        report_min_group_size(&state, &query.agent_id),
+    );
+
+    Ok(Json(LanguagesResponse {
+        languages,
+        suppressed_groups,
+    }))
+}
+
+#[derive(Deserialize)]
+struct PromptVersionsQuery {
+    agent_id: String,
+    #[serde(default = "default_prompt_versions_days")]
+    days: u32,
+}
+
+fn default_prompt_versions_days() -> u32 {
+    30
+}
+
+/// How an agent's prompt versions compare: turns, completion rate, cost and
+/// reply ratings per version over the last `days` days.
+async fn agent_prompt_versions(
+    State(state): State<Arc<ApiState>>,
+    Query(query): Query<PromptVersionsQuery>,
+) -> Result<Json<PromptVersionsResponse>, StatusCode> {
+    let pools = state.agent_pools.load();
+    let pool = pools.get(&query.agent_id).ok_or(StatusCode::NOT_FOUND)?;
+
+    let mut versions = crate::prompts::version::version_report(pool, query.days)
+        .await
+        .map_err(|error| {
+            tracing::warn!(%error, agent_id = %query.agent_id, "failed to compare prompt versions");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    let suppressed_groups = crate::usage_privacy::suppress_small_groups(
+        &mut versions,
+        report_min_group_size(&state, &query.agent_id),
+    );
+
+    Ok(Json(PromptVersionsResponse {
+        versions,
+        suppressed_groups,
+    }))
+}
+
+#[derive(Deserialize)]
+struct FlagsQuery {
+    agent_id: String,
+    #[serde(default = "default_flags_days")]
+    days: u32,
+}
+
+fn default_flags_days() -> u32 {
+    30
+}
+
+/// How an agent's turns with each experiment flag on compare with the rest
+/// over the last `days` days.
+async fn agent_flags(
+    State(state): State<Arc<ApiState>>,
+    Query(query): Query<FlagsQuery>,
+) -> Result<Json<FlagsResponse>, StatusCode> {
+    let pools = state.agent_pools.load();
+    let pool = pools.get(&query.agent_id).ok_or(StatusCode::NOT_FOUND)?;
+
+    let mut flags = crate::flags::flag_report(pool, query.days)
+        .await
+        .map_err(|error| {
+            tracing::warn!(%error, agent_id = %query.agent_id, "failed to compare flags");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    let suppressed_groups = crate::usage_privacy::suppress_small_groups(
+        &mut flags,
+        report_min_group_size(&state, &query.agent_id),
+    );
+
+    Ok(Json(FlagsResponse {
+        flags,
+        suppressed_groups,
+    }))
+}
+
+#[derive(Deserialize)]
+struct TagsQuery {
+    agent_id: String,
+    /// Only this tag's values. All tags when omitted.
+    key: Option<String>,
+    #[serde(default = "default_tags_days")]
+    days: u32,
+}
+
+fn default_tags_days() -> u32 {
+    30
+}
+
+/// Cost, tokens and completion of an agent's turns per request tag value
+/// over the last `days` days.
+async fn agent_tags(
+    State(state): State<Arc<ApiState>>,
+    Query(query): Query<TagsQuery>,
+) -> Result<Json<TagsResponse>, StatusCode> {
+    let pools = state.agent_pools.load();
+    let pool = pools.get(&query.agent_id).ok_or(StatusCode::NOT_FOUND)?;
+
+    let mut tags = crate::usage_tags::tag_report(pool, query.key.as_deref(), query.days)
+        .await
+        .map_err(|error| {
+            tracing::warn!(%error, agent_id = %query.agent_id, "failed to sum turns by tag");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    let suppressed_groups = crate::usage_privacy::suppress_small_groups(
+        &mut tags,
+        report_min_group_size(&state, &query.agent_id),
+    );
+
+    Ok(Json(TagsResponse {
+        tags,
+        suppressed_groups,
+    }))
+}
+
+#[derive(Deserialize)]
+struct ToolUsageQuery {
+    agent_id: String,
+    #[serde(default = "default_tool_usage_days")]
+    days: u32,
+}
+
+fn default_tool_usage_days() -> u32 {
+    30
+}
+
+/// What an agent's tools cost and how fast and reliably they ran: totals
+/// since startup, and the ledger's calls over the last `days` days by tool
+/// and conversation. With a minimum group size, calls are summed per tool
+/// and tools used in too few conversations are left out.
+async fn agent_tool_usage(
+    State(state): State<Arc<ApiState>>,
+    Query(query): Query<ToolUsageQuery>,
+) -> Result<Json<ToolUsageResponse>, StatusCode> {
+    let pools = state.agent_pools.load();
+    let pool = pools.get(&query.agent_id).ok_or(StatusCode::NOT_FOUND)?;
+
+    let spend = crate::tools::usage::tool_spend(pool, query.days)
+        .await
+        .map_err(|error| {
+            tracing::warn!(%error, agent_id = %query.agent_id, "failed to sum tool usage");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    let min_group_size = report_min_group_size(&state, &query.agent_id);
+    let mut spend = if min_group_size > 1 {
+        crate::usage_privacy::spend_per_tool(spend)
+    } else {
+        spend
+    };
+    let suppressed_groups = crate::usage_privacy::suppress_small_groups(&mut spend, min_group_size);
+
+    Ok(Json(ToolUsageResponse {
+        totals: state.tool_metrics.snapshot(&query.agent_id),
+        spend,
+        suppressed_groups,
+    }))
+}
+
+/// The agent's `[usage_reports] min_group_size`, 0 for unknown agents.
+fn report_min_group_size(state: &ApiState, agent_id: &str) -> u64 {
+    state
+        .runtime_configs
+        .load()
+        .get(agent_id)
+        .map(|rc| rc.usage_reports.load().min_group_size)
+        .unwrap_or_default()
+}
+
+// -- Process cancellation --
+
+#[derive(Deserialize)]
+struct CancelProcessRequest {
+    channel_id: String,
+    process_type: String,
+    process_id: String,
+}
+
+#[derive(Serialize)]
+struct CancelProcessResponse {
+    success: bool,
+    message: String,
+}
+
+#[derive(Serialize)]
+struct ImpersonationResponse {
+    message_id: String,
+    /// Conversation the message runs in, where its replies can be read.
+    conversation_id: String,
+    /// Whether the replies stay off the platform.
+    sandboxed: bool,
+}
+
+/// Send a message as a given user, to reproduce what they saw. It goes
+/// through the same routing, permissions, quotas and channel as the real
+/// sender's, and the turn answering it is marked synthetic. Replies show up
+/// on the event stream and in `/channels/messages` for the returned
+/// conversation.
+async fn impersonate_message(
+    State(state): State<Arc<ApiState>>,
+    Json(request): Json<ImpersonationRequest>,
+) -> Result<Json<ImpersonationResponse>, StatusCode> {
+    if !state.is_ready() {
+        return Err(StatusCode::SERVICE_UNAVAILABLE);
+    }
+    if request.text.trim().is_empty() || request.sender_id.is_empty() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let manager = state
+        .messaging_manager
+        .read()
+        .await
+        .clone()
+        .ok_or(StatusCode::SERVICE_UNAVAILABLE)?;
+
+    let operator = request.operator.clone();
+    let message = request.into_message();
+    let response = ImpersonationResponse {
+        message_id: message.id.clone(),
+        conversation_id: message.conversation_id.clone(),
+        sandboxed: impersonation::is_sandboxed(&message.conversation_id),
+    };
+    tracing::info!(
+        %operator,
+        source = %message.source,
+        conversation_id = %message.conversation_id,
+        sender_id = %message.sender_id,
+        "sending impersonated message"
+    );
+    manager.inject(message).await.map_err(|error| {
+        tracing::warn!(%error, "failed to send impersonated message");
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    Ok(Json(response))
+}
+
+/// Cancel a running worker or branch via the API.
+async fn cancel_process(
+    State(state): State<Arc<ApiState>>,
+    Json(request): Json<CancelProcessRequest>,
+) -> Result<Json<CancelProcessResponse>, StatusCode> {
     let states = state.channel_states.read().await;
     let channel_state = states
         .get(&request.channel_id)
@@ -2361,6 +3630,13 @@ async fn get_providers(
     Ok(Json(ProvidersResponse { providers, has_any }))
 }
 
+/// Keep a config file that holds provider keys readable only by its owner.
+fn restrict_config_file(config_path: &std::path::Path) {
+    if let Err(error) = crate::permissions::restrict_to_owner(config_path) {
+        tracing::warn!(%error, path = %config_path.display(), "can't restrict config file access");
+    }
+}
+
 async fn update_provider(
     State(state): State<Arc<ApiState>>,
     Json(request): Json<ProviderUpdateRequest>,
@@ -2494,6 +3770,7 @@ async fn update_provider(
     tokio::fs::write(&config_path, doc.to_string())
         .await
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    restrict_config_file(&config_path);
 
     // Signal the main loop that providers have been configured
     state
@@ -3050,6 +4327,230 @@ static DYNAMIC_MODELS: std::sync::LazyLock<
 
 const MODEL_CACHE_TTL: std::time::Duration = std::time::Duration::from_secs(3600);
 
+/// Request, token, cost and error counts by model, latency histograms for
+/// LLM requests (queue wait, per-attempt provider latency, and total routed
+/// latency, by model and priority) plus counts of
+/// responses that needed parsing workarounds, tokens saved by prompt
+/// compression, empty completions, and requests by tag.
+async fn llm_metrics(State(state): State<Arc<ApiState>>) -> Json<LlmMetricsResponse> {
+    let manager = state.llm_manager.read().await;
+    let Some(manager) = manager.as_ref() else {
+        return Json(LlmMetricsResponse {
+            usage: Vec::new(),
+            histograms: Vec::new(),
+            parse_warnings: Vec::new(),
+            compression: Vec::new(),
+            empty_responses: Vec::new(),
+            tags: Vec::new(),
+        });
+    };
+    Json(LlmMetricsResponse {
+        usage: manager.metrics().usage_counts(),
+        histograms: manager.metrics().snapshot(),
+        parse_warnings: manager.metrics().parse_warning_counts(),
+        compression: manager.metrics().compression_counts(),
+        empty_responses: manager.metrics().empty_response_counts(),
+        tags: manager.metrics().tag_usage_counts(),
+    })
+}
+
+#[derive(Deserialize)]
+struct UsageSeriesQuery {
+    /// The agent whose database holds the snapshots: the default agent.
+    agent_id: String,
+    #[serde(default = "default_usage_since")]
+    since: String,
+    #[serde(default = "default_usage_bucket")]
+    bucket: String,
+    model: Option<String>,
+}
+
+fn default_usage_since() -> String {
+    "24h".into()
+}
+
+fn default_usage_bucket() -> String {
+    "1h".into()
+}
+
+/// LLM requests, tokens, cost and errors from the usage snapshots over the
+/// last `since`, summed into buckets of `bucket`.
+async fn usage_series(
+    State(state): State<Arc<ApiState>>,
+    Query(query): Query<UsageSeriesQuery>,
+) -> Result<Json<UsageSeriesResponse>, StatusCode> {
+    let window =
+        crate::observability::parse_duration(&query.since).ok_or(StatusCode::BAD_REQUEST)?;
+    let bucket =
+        crate::observability::parse_duration(&query.bucket).ok_or(StatusCode::BAD_REQUEST)?;
+    let pools = state.agent_pools.load();
+    let pool = pools.get(&query.agent_id).ok_or(StatusCode::NOT_FOUND)?;
+
+    let points = crate::usage_snapshots::series(pool, window, bucket, query.model.as_deref())
+        .await
+        .map_err(|error| {
+            tracing::warn!(%error, agent_id = %query.agent_id, "failed to load usage series");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    Ok(Json(UsageSeriesResponse { points }))
+}
+
+/// Health of self-hosted providers and any provider outages. Routing skips
+/// a provider while its last probe failed or it's in an outage.
+async fn llm_health(State(state): State<Arc<ApiState>>) -> Json<LlmHealthResponse> {
+    let manager = state.llm_manager.read().await;
+    let (providers, outages) = match manager.as_ref() {
+        Some(manager) => (manager.provider_health().await, manager.provider_outages()),
+        None => (HashMap::new(), Vec::new()),
+    };
+    Json(LlmHealthResponse { providers, outages })
+}
+
+/// Recent best-of-N races with every candidate's answer and cost, newest
+/// first.
+async fn llm_races(State(state): State<Arc<ApiState>>) -> Json<LlmRacesResponse> {
+    let manager = state.llm_manager.read().await;
+    let races = manager
+        .as_ref()
+        .map(|manager| manager.recent_races())
+        .unwrap_or_default();
+    Json(LlmRacesResponse { races })
+}
+
+/// How each shadow candidate compares to the model it mirrors, and the
+/// recent shadow requests behind that.
+async fn llm_shadow(State(state): State<Arc<ApiState>>) -> Json<LlmShadowResponse> {
+    let manager = state.llm_manager.read().await;
+    let (summaries, requests) = manager
+        .as_ref()
+        .map(|manager| (manager.shadow_summaries(), manager.recent_shadows()))
+        .unwrap_or_default();
+    Json(LlmShadowResponse {
+        summaries,
+        requests,
+    })
+}
+
+/// Fault rules injected into provider requests, with how often each has
+/// fired.
+async fn llm_faults(State(state): State<Arc<ApiState>>) -> Json<LlmFaultsResponse> {
+    let manager = state.llm_manager.read().await;
+    let faults = manager
+        .as_ref()
+        .map(|manager| manager.faults().active())
+        .unwrap_or_default();
+    Json(LlmFaultsResponse { faults })
+}
+
+/// Replace the fault rules. They last until the next restart, or until
+/// `duration_secs` passes.
+async fn set_llm_faults(
+    State(state): State<Arc<ApiState>>,
+    Json(request): Json<SetLlmFaultsRequest>,
+) -> Result<Json<LlmFaultsResponse>, StatusCode> {
+    let manager = state.llm_manager.read().await;
+    let manager = manager.as_ref().ok_or(StatusCode::SERVICE_UNAVAILABLE)?;
+    let expires_at = request
+        .duration_secs
+        .map(|secs| chrono::Utc::now() + chrono::TimeDelta::seconds(secs as i64));
+    let faults: Vec<_> = request
+        .faults
+        .into_iter()
+        .map(|mut rule| {
+            rule.expires_at = rule.expires_at.or(expires_at);
+            rule
+        })
+        .collect();
+    tracing::warn!(count = faults.len(), "fault injection rules replaced");
+    manager.faults().set_rules(faults);
+    Ok(Json(LlmFaultsResponse {
+        faults: manager.faults().active(),
+    }))
+}
+
+/// Stop injecting faults.
+async fn clear_llm_faults(State(state): State<Arc<ApiState>>) -> StatusCode {
+    let manager = state.llm_manager.read().await;
+    let Some(manager) = manager.as_ref() else {
+        return StatusCode::SERVICE_UNAVAILABLE;
+    };
+    manager.faults().set_rules(Vec::new());
+    tracing::info!("fault injection rules cleared");
+    StatusCode::NO_CONTENT
+}
+
+/// Recently recorded request ids, newest first. Empty unless
+/// `llm.debug_recording` is enabled.
+async fn llm_debug_requests(State(state): State<Arc<ApiState>>) -> Json<LlmDebugRequestsResponse> {
+    let manager = state.llm_manager.read().await;
+    let Some(manager) = manager.as_ref() else {
+        return Json(LlmDebugRequestsResponse {
+            enabled: false,
+            request_ids: Vec::new(),
+        });
+    };
+    let recorder = manager.debug_recorder();
+    Json(LlmDebugRequestsResponse {
+        enabled: recorder.is_enabled(),
+        request_ids: recorder.recent_request_ids(),
+    })
+}
+
+/// Raw request/response bodies for every attempt of a recorded request.
+async fn llm_debug_request(
+    State(state): State<Arc<ApiState>>,
+    axum::extract::Path(request_id): axum::extract::Path<String>,
+) -> Result<Json<LlmDebugRequestResponse>, StatusCode> {
+    let manager = state.llm_manager.read().await;
+    let attempts = manager
+        .as_ref()
+        .and_then(|manager| manager.debug_recorder().get(&request_id))
+        .ok_or(StatusCode::NOT_FOUND)?;
+    Ok(Json(LlmDebugRequestResponse {
+        request_id,
+        attempts,
+    }))
+}
+
+/// How each hosted provider's keys fared in their last check.
+async fn llm_keys(State(state): State<Arc<ApiState>>) -> Json<LlmKeysResponse> {
+    let manager = state.llm_manager.read().await;
+    let keys = manager
+        .as_ref()
+        .map(|manager| manager.key_health())
+        .unwrap_or_default();
+    Json(LlmKeysResponse { keys })
+}
+
+/// Check every hosted provider's keys now, e.g. after replacing one, rather
+/// than waiting for the next scheduled check.
+async fn check_llm_keys(
+    State(state): State<Arc<ApiState>>,
+) -> Result<Json<LlmKeysResponse>, StatusCode> {
+    let manager = state.llm_manager.read().await.clone();
+    let manager = manager.ok_or(StatusCode::SERVICE_UNAVAILABLE)?;
+    let keys = manager.check_keys().await;
+    Ok(Json(LlmKeysResponse { keys }))
+}
+
+/// Switch a provider back to its primary key after a failover, once the
+/// primary has been replaced.
+async fn llm_restore_primary_key(
+    State(state): State<Arc<ApiState>>,
+    axum::extract::Path(provider): axum::extract::Path<String>,
+) -> StatusCode {
+    let manager = state.llm_manager.read().await;
+    let Some(manager) = manager.as_ref() else {
+        return StatusCode::SERVICE_UNAVAILABLE;
+    };
+    if !manager.credentials().has_source(&provider) {
+        return StatusCode::NOT_FOUND;
+    }
+    manager.credentials().restore_primary(&provider).await;
+    StatusCode::NO_CONTENT
+}
+
 async fn get_models(
     State(state): State<Arc<ApiState>>,
 ) -> Result<Json<ModelsResponse>, StatusCode> {
@@ -3289,6 +4790,45 @@ async fn list_ingest_files(
     Ok(Json(IngestFilesResponse { files }))
 }
 
+/// Configured knowledge sources with the outcome of their latest sync.
+async fn list_knowledge_sources(
+    State(state): State<Arc<ApiState>>,
+    Query(query): Query<IngestQuery>,
+) -> Result<Json<KnowledgeSourcesResponse>, StatusCode> {
+    let pools = state.agent_pools.load();
+    let pool = pools.get(&query.agent_id).ok_or(StatusCode::NOT_FOUND)?;
+    let configs = state.runtime_configs.load();
+    let config = configs.get(&query.agent_id).ok_or(StatusCode::NOT_FOUND)?;
+
+    let mut statuses: HashMap<String, KnowledgeStatus> = KnowledgeStore::new(pool.clone())
+        .statuses()
+        .await
+        .map_err(|error| {
+            tracing::warn!(%error, agent_id = %query.agent_id, "failed to load knowledge sources");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .into_iter()
+        .map(|status| (status.source_id.clone(), status))
+        .collect();
+
+    let sources = config
+        .knowledge
+        .load()
+        .iter()
+        .map(|source| KnowledgeSourceInfo {
+            id: source.id.clone(),
+            kind: source.kind.as_str(),
+            url: source.url.clone(),
+            store: source.store.clone(),
+            interval_secs: source.interval_secs,
+            enabled: source.enabled,
+            status: statuses.remove(&source.id),
+        })
+        .collect();
+
+    Ok(Json(KnowledgeSourcesResponse { sources }))
+}
+
 /// Upload one or more files to the agent's ingest directory.
 async fn upload_ingest_file(
     State(state): State<Arc<ApiState>>,
@@ -3,10 +3,16 @@
 use crate::agent::channel::ChannelState;
 use crate::agent::cortex_chat::CortexChatSession;
 use crate::agent::status::StatusBlock;
+use crate::api::journal::EventJournal;
 use crate::config::{Binding, DiscordPermissions, RuntimeConfig, SlackPermissions};
 use crate::cron::{CronStore, Scheduler};
+use crate::declined::DeclineMetrics;
+use crate::hooks::tool_guard::GuardMetrics;
+use crate::llm::LlmManager;
 use crate::memory::MemorySearch;
 use crate::messaging::MessagingManager;
+use crate::messaging::intake::IntakeRouter;
+use crate::messaging::rate_limit::RateLimiter;
 use crate::update::SharedUpdateStatus;
 use crate::{ProcessEvent, ProcessId};
 
@@ -16,6 +22,7 @@ use serde::Serialize;
 use std::collections::HashMap;
 use std::path::PathBuf;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::time::Instant;
 use tokio::sync::{RwLock, broadcast, mpsc};
 
@@ -33,8 +40,11 @@ pub struct AgentInfo {
 /// State shared across all API handlers.
 pub struct ApiState {
     pub started_at: Instant,
-    /// Aggregated event stream from all agents. SSE clients subscribe here.
+    /// Aggregated event stream from all agents. Producers send here.
     pub event_tx: broadcast::Sender<ApiEvent>,
+    /// The same events numbered and persisted. SSE clients subscribe here,
+    /// so they can resume after a reconnect.
+    pub event_journal: Arc<EventJournal>,
     /// Per-agent SQLite pools for querying channel/conversation data.
     pub agent_pools: arc_swap::ArcSwap<HashMap<String, sqlx::SqlitePool>>,
     /// Per-agent config summaries for the agents list endpoint.
@@ -58,6 +68,12 @@ pub struct ApiState {
     pub cron_schedulers: arc_swap::ArcSwap<HashMap<String, Arc<Scheduler>>>,
     /// Per-agent RuntimeConfig for reading live hot-reloaded configuration.
     pub runtime_configs: ArcSwap<HashMap<String, Arc<RuntimeConfig>>>,
+    /// Per-agent inbound rate limiters, for reading limit counts.
+    pub rate_limiters: ArcSwap<HashMap<String, RateLimiter>>,
+    /// Per-agent tool guard verdict counts.
+    pub guard_metrics: ArcSwap<HashMap<String, GuardMetrics>>,
+    /// Per-agent counts of declined messages.
+    pub decline_metrics: ArcSwap<HashMap<String, DeclineMetrics>>,
     /// Shared reference to the Discord permissions ArcSwap (same instance used by the adapter and file watcher).
     pub discord_permissions: RwLock<Option<Arc<ArcSwap<DiscordPermissions>>>>,
     /// Shared reference to the Slack permissions ArcSwap (same instance used by the adapter and file watcher).
@@ -66,10 +82,21 @@ pub struct ApiState {
     pub bindings: RwLock<Option<Arc<ArcSwap<Vec<Binding>>>>>,
     /// Shared messaging manager for runtime adapter addition.
     pub messaging_manager: RwLock<Option<Arc<MessagingManager>>>,
+    /// Shared LLM manager, for reading request metrics.
+    pub llm_manager: RwLock<Option<Arc<LlmManager>>>,
+    /// Intake classifier, for reading routing decisions. None when intake is off.
+    pub intake: RwLock<Option<Arc<IntakeRouter>>>,
+    /// Tool call totals per agent and tool since startup.
+    pub tool_metrics: crate::tools::usage::ToolMetrics,
     /// Sender to signal the main event loop that provider keys have been configured.
     pub provider_setup_tx: mpsc::Sender<crate::ProviderSetupEvent>,
     /// Shared update status, populated by the background update checker.
     pub update_status: SharedUpdateStatus,
+    /// Set once provider connections are warm and agents are initialized.
+    /// Backs the readiness probe.
+    pub ready: AtomicBool,
+    /// Instance-wide maintenance switch, shared with every agent's deps.
+    pub maintenance: crate::maintenance::Maintenance,
 }
 
 /// Events sent to SSE clients. Wraps ProcessEvents with agent context.
@@ -153,6 +180,78 @@ pub enum ApiEvent {
         process_id: String,
         tool_name: String,
     },
+    /// A channel turn finished, with its tool trace, usage, cost, and
+    /// routing.
+    TurnCompleted {
+        agent_id: String,
+        channel_id: String,
+        outcome: crate::agent::turn::TurnOutcome,
+    },
+    /// A worker is waiting for the user to confirm a side-effecting action.
+    ActionPreview {
+        agent_id: String,
+        channel_id: String,
+        process_id: String,
+        action_id: String,
+        tool_name: String,
+        preview: String,
+    },
+    /// A provider rejected its primary API key and requests moved to the
+    /// secondary key.
+    CredentialFailover { provider: String },
+    /// A self-hosted provider went down or came back.
+    ProviderHealthChanged {
+        provider: String,
+        healthy: bool,
+        detail: String,
+    },
+    /// A provider started or stopped failing across its models.
+    ProviderOutage {
+        provider: String,
+        active: bool,
+        detail: String,
+    },
+    /// A provider API key started or stopped failing, or needs attention
+    /// soon.
+    KeyHealthChanged {
+        provider: String,
+        slot: String,
+        status: String,
+        detail: String,
+    },
+    /// A process was stopped for looping without progress.
+    LoopDetected {
+        agent_id: String,
+        process_id: String,
+        kind: String,
+        detail: String,
+    },
+}
+
+impl ApiEvent {
+    /// The SSE event type this is sent as.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ApiEvent::InboundMessage { .. } => "inbound_message",
+            ApiEvent::OutboundMessage { .. } => "outbound_message",
+            ApiEvent::TypingState { .. } => "typing_state",
+            ApiEvent::WorkerStarted { .. } => "worker_started",
+            ApiEvent::WorkerStatusUpdate { .. } => "worker_status",
+            ApiEvent::WorkerCompleted { .. } => "worker_completed",
+            ApiEvent::BranchStarted { .. } => "branch_started",
+            ApiEvent::BranchCompleted { .. } => "branch_completed",
+            ApiEvent::BranchFailed { .. } => "branch_failed",
+            ApiEvent::ToolStarted { .. } => "tool_started",
+            ApiEvent::ToolCompleted { .. } => "tool_completed",
+            ApiEvent::TurnCompleted { .. } => "turn_completed",
+            ApiEvent::ActionPreview { .. } => "action_preview",
+            ApiEvent::CredentialFailover { .. } => "credential_failover",
+            ApiEvent::ProviderHealthChanged { .. } => "provider_health_changed",
+            ApiEvent::ProviderOutage { .. } => "provider_outage",
+            ApiEvent::KeyHealthChanged { .. } => "key_health_changed",
+            ApiEvent::LoopDetected { .. } => "loop_detected",
+        }
+    }
 }
 
 impl ApiState {
@@ -163,6 +262,7 @@ impl ApiState {
         Self {
             started_at: Instant::now(),
             event_tx,
+            event_journal: Arc::new(EventJournal::new()),
             agent_pools: arc_swap::ArcSwap::from_pointee(HashMap::new()),
             agent_configs: arc_swap::ArcSwap::from_pointee(Vec::new()),
             memory_searches: arc_swap::ArcSwap::from_pointee(HashMap::new()),
@@ -174,15 +274,39 @@ impl ApiState {
             cron_stores: arc_swap::ArcSwap::from_pointee(HashMap::new()),
             cron_schedulers: arc_swap::ArcSwap::from_pointee(HashMap::new()),
             runtime_configs: ArcSwap::from_pointee(HashMap::new()),
+            rate_limiters: ArcSwap::from_pointee(HashMap::new()),
+            guard_metrics: ArcSwap::from_pointee(HashMap::new()),
+            decline_metrics: ArcSwap::from_pointee(HashMap::new()),
             discord_permissions: RwLock::new(None),
             slack_permissions: RwLock::new(None),
             bindings: RwLock::new(None),
             messaging_manager: RwLock::new(None),
+            llm_manager: RwLock::new(None),
+            intake: RwLock::new(None),
+            tool_metrics: crate::tools::usage::ToolMetrics::new(),
             provider_setup_tx,
             update_status: crate::update::new_shared_status(),
+            ready: AtomicBool::new(false),
+            maintenance: crate::maintenance::Maintenance::new(),
         }
     }
 
+    /// Start numbering API events and persisting them to `path`, so SSE
+    /// clients can resume from a cursor.
+    pub fn start_event_journal(&self, path: PathBuf) {
+        self.event_journal.start(self.event_tx.subscribe(), path);
+    }
+
+    /// Mark the instance as ready (or not) to serve traffic.
+    pub fn set_ready(&self, ready: bool) {
+        self.ready.store(ready, Ordering::Release);
+    }
+
+    /// Whether the instance has finished starting up.
+    pub fn is_ready(&self) -> bool {
+        self.ready.load(Ordering::Acquire)
+    }
+
     /// Register a channel's status block so the API can read snapshots.
     pub async fn register_channel_status(
         &self,
@@ -348,6 +472,39 @@ impl ApiState {
                                     })
                                     .ok();
                             }
+                            ProcessEvent::TurnCompleted {
+                                channel_id,
+                                outcome,
+                                ..
+                            } => {
+                                api_tx
+                                    .send(ApiEvent::TurnCompleted {
+                                        agent_id: agent_id.clone(),
+                                        channel_id: channel_id.to_string(),
+                                        outcome: outcome.clone(),
+                                    })
+                                    .ok();
+                            }
+                            ProcessEvent::ActionPreview {
+                                process_id,
+                                channel_id,
+                                action_id,
+                                tool_name,
+                                preview,
+                                ..
+                            } => {
+                                let (_, id_str) = process_id_info(process_id);
+                                api_tx
+                                    .send(ApiEvent::ActionPreview {
+                                        agent_id: agent_id.clone(),
+                                        channel_id: channel_id.to_string(),
+                                        process_id: id_str,
+                                        action_id: action_id.clone(),
+                                        tool_name: tool_name.clone(),
+                                        preview: preview.clone(),
+                                    })
+                                    .ok();
+                            }
                             _ => {}
                         }
                     }
@@ -406,6 +563,21 @@ impl ApiState {
         self.runtime_configs.store(Arc::new(configs));
     }
 
+    /// Set the inbound rate limiters for all agents.
+    pub fn set_rate_limiters(&self, limiters: HashMap<String, RateLimiter>) {
+        self.rate_limiters.store(Arc::new(limiters));
+    }
+
+    /// Set the tool guard verdict counts for all agents.
+    pub fn set_guard_metrics(&self, metrics: HashMap<String, GuardMetrics>) {
+        self.guard_metrics.store(Arc::new(metrics));
+    }
+
+    /// Set the declined message counts for all agents.
+    pub fn set_decline_metrics(&self, metrics: HashMap<String, DeclineMetrics>) {
+        self.decline_metrics.store(Arc::new(metrics));
+    }
+
     /// Share the Discord permissions ArcSwap with the API so reads get hot-reloaded values.
     pub async fn set_discord_permissions(&self, permissions: Arc<ArcSwap<DiscordPermissions>>) {
         *self.discord_permissions.write().await = Some(permissions);
@@ -425,6 +597,16 @@ impl ApiState {
     pub async fn set_messaging_manager(&self, manager: Arc<MessagingManager>) {
         *self.messaging_manager.write().await = Some(manager);
     }
+
+    /// Share the LLM manager so API handlers can read request metrics.
+    pub async fn set_llm_manager(&self, manager: Arc<LlmManager>) {
+        *self.llm_manager.write().await = Some(manager);
+    }
+
+    /// Share the intake classifier with the API for its routing stats.
+    pub async fn set_intake(&self, intake: Option<Arc<IntakeRouter>>) {
+        *self.intake.write().await = intake;
+    }
 }
 
 /// Extract (process_type, id_string) from a ProcessId.
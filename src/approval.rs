@@ -0,0 +1,237 @@
+//! In-channel approval of side-effecting actions.
+//!
+//! With preview mode on, a worker about to run a gated tool renders what the
+//! call would do (a diff for file writes, the command line for shell and
+//! exec), posts it to its channel, and parks until a user replies
+//! `/confirm` or `/reject`. The router resolves those commands against
+//! [`ActionApprovals`] instead of handing them to the channel. Actions that
+//! aren't decided within the timeout are rejected. Some tools' actions can
+//! only be decided by certain users, like incident changes by responders.
+
+pub mod preview;
+
+use crate::{InboundMessage, MessageContent};
+
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::oneshot;
+
+const CONFIRM_COMMANDS: &[&str] = &["/confirm", "/approve"];
+const REJECT_COMMANDS: &[&str] = &["/reject", "/deny"];
+
+/// A user's answer to a pending action.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Decision {
+    Approved,
+    Rejected,
+}
+
+/// A `/confirm [id]` or `/reject [id]` command.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ApprovalCommand {
+    pub decision: Decision,
+    /// The action to decide. None means the channel's most recent one.
+    pub action_id: Option<String>,
+}
+
+impl ApprovalCommand {
+    /// Parse an approval command. Returns `None` for ordinary messages.
+    pub fn from_message(message: &InboundMessage) -> Option<Self> {
+        let MessageContent::Text(text) = &message.content else {
+            return None;
+        };
+        let mut words = text.split_whitespace();
+        let command = words.next()?.to_ascii_lowercase();
+        let decision = if CONFIRM_COMMANDS.contains(&command.as_str()) {
+            Decision::Approved
+        } else if REJECT_COMMANDS.contains(&command.as_str()) {
+            Decision::Rejected
+        } else {
+            return None;
+        };
+        Some(Self {
+            decision,
+            action_id: words.next().map(String::from),
+        })
+    }
+}
+
+struct PendingAction {
+    id: String,
+    channel_id: String,
+    tool_name: String,
+    responder: oneshot::Sender<Decision>,
+}
+
+/// Actions waiting on a user's decision, shared by an agent's workers and
+/// the message router.
+#[derive(Clone, Default)]
+pub struct ActionApprovals {
+    pending: Arc<Mutex<Vec<PendingAction>>>,
+}
+
+impl ActionApprovals {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register an action awaiting approval in `channel_id`. Returns its
+    /// short id and a receiver for the decision.
+    pub fn request(
+        &self,
+        channel_id: &str,
+        tool_name: &str,
+    ) -> (String, oneshot::Receiver<Decision>) {
+        let (responder, receiver) = oneshot::channel();
+        let id = uuid::Uuid::new_v4().simple().to_string()[..8].to_string();
+        self.lock().push(PendingAction {
+            id: id.clone(),
+            channel_id: channel_id.to_string(),
+            tool_name: tool_name.to_string(),
+            responder,
+        });
+        (id, receiver)
+    }
+
+    /// The tool name of the action a command in `channel_id` would decide,
+    /// so the router can check who may decide it first.
+    pub fn pending_tool(&self, channel_id: &str, action_id: Option<&str>) -> Option<String> {
+        let pending = self.lock();
+        pending
+            .iter()
+            .rev()
+            .find(|action| {
+                action.channel_id == channel_id && action_id.is_none_or(|id| action.id == id)
+            })
+            .map(|action| action.tool_name.clone())
+    }
+
+    /// Decide a pending action in `channel_id`: the one with `action_id`, or
+    /// the most recent one. Returns the action's tool name, or None if
+    /// nothing matched.
+    pub fn resolve(
+        &self,
+        channel_id: &str,
+        action_id: Option<&str>,
+        decision: Decision,
+    ) -> Option<String> {
+        let mut pending = self.lock();
+        let index = pending.iter().rposition(|action| {
+            action.channel_id == channel_id && action_id.is_none_or(|id| action.id == id)
+        })?;
+        let action = pending.remove(index);
+        // The worker may have timed out or been cancelled in the meantime.
+        let _ = action.responder.send(decision);
+        Some(action.tool_name)
+    }
+
+    /// Drop an action nobody decided on.
+    pub fn forget(&self, action_id: &str) {
+        self.lock().retain(|action| action.id != action_id);
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<PendingAction>> {
+        self.pending
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl std::fmt::Debug for ActionApprovals {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ActionApprovals")
+            .field("pending", &self.lock().len())
+            .finish()
+    }
+}
+
+/// Decides which tool calls need a preview and confirmation. Built per
+/// worker from the agent's preview config.
+#[derive(Debug, Clone)]
+pub struct PreviewGate {
+    pub approvals: ActionApprovals,
+    tools: HashSet<String>,
+    every_call: HashSet<String>,
+    pub timeout: Duration,
+    /// Relative file paths in previews resolve against this.
+    pub workspace: PathBuf,
+}
+
+impl PreviewGate {
+    pub fn new(
+        approvals: ActionApprovals,
+        tools: impl IntoIterator<Item = String>,
+        timeout: Duration,
+        workspace: PathBuf,
+    ) -> Self {
+        Self {
+            approvals,
+            tools: tools.into_iter().collect(),
+            every_call: HashSet::new(),
+            timeout,
+            workspace,
+        }
+    }
+
+    /// Also gate `tool_name`'s read-only calls.
+    pub fn with_every_call(mut self, tool_name: impl Into<String>) -> Self {
+        self.every_call.insert(tool_name.into());
+        self
+    }
+
+    /// Whether nothing is gated, so the gate can be skipped entirely.
+    pub fn is_empty(&self) -> bool {
+        self.tools.is_empty() && self.every_call.is_empty()
+    }
+
+    /// Whether this call must be confirmed first. Reads through gated tools,
+    /// like `file` with the `read` operation, pass straight through unless
+    /// the tool is gated for every call.
+    pub fn requires_confirmation(&self, tool_name: &str, args: &str) -> bool {
+        self.every_call.contains(tool_name)
+            || (self.tools.contains(tool_name) && preview::has_side_effects(tool_name, args))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_latest_or_by_id() {
+        let approvals = ActionApprovals::new();
+        let (first, mut first_rx) = approvals.request("chan", "shell");
+        let (_, mut second_rx) = approvals.request("chan", "file");
+        let (_, mut other_rx) = approvals.request("other", "exec");
+        assert_eq!(
+            approvals.pending_tool("chan", None).as_deref(),
+            Some("file")
+        );
+        assert_eq!(
+            approvals.pending_tool("chan", Some(&first)).as_deref(),
+            Some("shell")
+        );
+
+        assert_eq!(
+            approvals
+                .resolve("chan", None, Decision::Rejected)
+                .as_deref(),
+            Some("file")
+        );
+        assert_eq!(second_rx.try_recv(), Ok(Decision::Rejected));
+
+        assert_eq!(
+            approvals
+                .resolve("chan", Some(&first), Decision::Approved)
+                .as_deref(),
+            Some("shell")
+        );
+        assert_eq!(first_rx.try_recv(), Ok(Decision::Approved));
+
+        assert_eq!(approvals.pending_tool("chan", None), None);
+        assert_eq!(approvals.resolve("chan", None, Decision::Approved), None);
+        assert!(other_rx.try_recv().is_err());
+    }
+}
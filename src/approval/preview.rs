@@ -0,0 +1,521 @@
+//! Rendered previews of tool calls, per tool type.
+
+use crate::tools::truncate_output;
+
+use serde_json::Value;
+use spacebot_core::redact::LEAK_PATTERNS;
+
+use std::path::Path;
+
+/// Largest preview posted to a channel. Most platforms cap a message
+/// around this size.
+const MAX_PREVIEW_BYTES: usize = 3_000;
+
+/// Unchanged lines shown around each change in a diff.
+const DIFF_CONTEXT: usize = 2;
+
+/// Beyond this many line pairs a diff is too slow to compute; the preview
+/// only reports line counts.
+const MAX_DIFF_CELLS: usize = 4_000_000;
+
+/// Lines of a new file shown in its preview.
+const NEW_FILE_LINES: usize = 40;
+
+/// `computer` actions that only look at the screen.
+const COMPUTER_OBSERVATIONS: &[&str] = &["screenshot", "cursor_position", "wait"];
+
+/// `kubernetes` actions that only read.
+const KUBERNETES_READS: &[&str] = &["get", "describe", "logs", "events"];
+
+/// `incident` actions that only read.
+const INCIDENT_READS: &[&str] = &["list", "get", "timeline"];
+
+/// HTTP methods that only read.
+const READ_ONLY_METHODS: &[&str] = &["GET", "HEAD", "OPTIONS"];
+
+/// Whether a call to `tool_name` with `args` changes anything. `sql_query`,
+/// `promql_query` and `logql_query` never do, and only `file`, `computer`,
+/// `home_assistant`, `issues`, `http_request`, `kubernetes` and `incident`
+/// have read-only operations; every other tool is assumed to.
+pub fn has_side_effects(tool_name: &str, args: &str) -> bool {
+    match tool_name {
+        "file" => parse(args)
+            .get("operation")
+            .and_then(Value::as_str)
+            .is_none_or(|operation| operation == "write"),
+        "computer" => parse(args)
+            .get("action")
+            .and_then(Value::as_str)
+            .is_none_or(|action| !COMPUTER_OBSERVATIONS.contains(&action)),
+        "home_assistant" => parse(args)
+            .get("action")
+            .and_then(Value::as_str)
+            .is_none_or(|action| action == "call_service"),
+        "issues" => parse(args)
+            .get("action")
+            .and_then(Value::as_str)
+            .is_none_or(|action| action != "search"),
+        "http_request" => parse(args)
+            .get("method")
+            .and_then(Value::as_str)
+            .is_some_and(|method| {
+                !READ_ONLY_METHODS.contains(&method.to_ascii_uppercase().as_str())
+            }),
+        "kubernetes" => parse(args)
+            .get("action")
+            .and_then(Value::as_str)
+            .is_none_or(|action| !KUBERNETES_READS.contains(&action)),
+        "incident" => parse(args)
+            .get("action")
+            .and_then(Value::as_str)
+            .is_none_or(|action| !INCIDENT_READS.contains(&action)),
+        "sql_query" | "promql_query" | "logql_query" => false,
+        _ => true,
+    }
+}
+
+/// A markdown preview of what a tool call would do. Anything that looks like
+/// a secret is masked, since the preview is posted to the channel.
+pub async fn render(tool_name: &str, args: &str, workspace: &Path) -> String {
+    let args = parse(args);
+    let field = |name: &str| args.get(name).and_then(Value::as_str).unwrap_or_default();
+
+    let body = match tool_name {
+        "file" => {
+            let path = field("path");
+            let resolved = workspace.join(path);
+            let existing = tokio::fs::read_to_string(&resolved).await.ok();
+            render_file_write(path, existing.as_deref(), field("content"))
+        }
+        "shell" => {
+            let directory = args
+                .get("working_dir")
+                .and_then(Value::as_str)
+                .map(|dir| format!(" in `{dir}`"))
+                .unwrap_or_default();
+            format!(
+                "**shell** will run{directory}:\n```sh\n{}\n```",
+                field("command")
+            )
+        }
+        "exec" => {
+            let arguments: Vec<&str> = args
+                .get("args")
+                .and_then(Value::as_array)
+                .map(|values| values.iter().filter_map(Value::as_str).collect())
+                .unwrap_or_default();
+            // Only names: env values are where credentials usually go.
+            let env: Vec<&str> = args
+                .get("env")
+                .and_then(Value::as_array)
+                .map(|vars| {
+                    vars.iter()
+                        .filter_map(|var| var.get("key").and_then(Value::as_str))
+                        .collect()
+                })
+                .unwrap_or_default();
+            let env = if env.is_empty() {
+                String::new()
+            } else {
+                format!("\nwith environment: {}", env.join(", "))
+            };
+            format!(
+                "**exec** will run:\n```sh\n{} {}\n```{env}",
+                field("program"),
+                arguments.join(" ")
+            )
+        }
+        "send_file" => {
+            let caption = args
+                .get("caption")
+                .and_then(Value::as_str)
+                .map(|caption| format!("\n> {caption}"))
+                .unwrap_or_default();
+            format!(
+                "**send_file** will send `{}` to the channel{caption}",
+                field("file_path")
+            )
+        }
+        "computer" => render_computer_action(&args),
+        "http_request" => {
+            let method = args
+                .get("method")
+                .and_then(Value::as_str)
+                .unwrap_or("GET")
+                .to_ascii_uppercase();
+            let body = match args.get("body") {
+                Some(Value::String(text)) => format!(" with:\n```\n{text}\n```"),
+                Some(Value::Null) | None => String::new(),
+                Some(json) => format!(
+                    " with:\n```json\n{}\n```",
+                    serde_json::to_string_pretty(json).unwrap_or_default()
+                ),
+            };
+            format!(
+                "**http_request** will send `{method} {}`{body}",
+                field("url")
+            )
+        }
+        "home_assistant" => {
+            let data = args
+                .get("data")
+                .and_then(Value::as_object)
+                .filter(|data| !data.is_empty())
+                .map(|data| {
+                    let pretty = serde_json::to_string_pretty(data).unwrap_or_default();
+                    format!(" with:\n```json\n{pretty}\n```")
+                })
+                .unwrap_or_default();
+            format!(
+                "**home_assistant** will call `{}.{}` on `{}`{data}",
+                field("domain"),
+                field("service"),
+                field("entity_id")
+            )
+        }
+        "issues" => {
+            let workspace = args
+                .get("workspace")
+                .and_then(Value::as_str)
+                .map(|workspace| format!(" in `{workspace}`"))
+                .unwrap_or_default();
+            let quote = |text: &str| {
+                text.lines()
+                    .map(|line| format!("> {line}"))
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            };
+            if field("action") == "comment" {
+                format!(
+                    "**issues** will comment on `{}`{workspace}:\n{}",
+                    field("issue"),
+                    quote(field("body"))
+                )
+            } else {
+                format!(
+                    "**issues** will file an issue{workspace}: **{}**\n{}",
+                    field("title"),
+                    quote(field("description"))
+                )
+            }
+        }
+        "kubernetes" => {
+            let kind = args.get("kind").and_then(Value::as_str).unwrap_or("pods");
+            let target = match field("action") {
+                "delete_pod" => format!("delete pod `{}`", field("name")),
+                "scale" => format!(
+                    "scale {kind} `{}` to {} replicas",
+                    field("name"),
+                    args.get("replicas")
+                        .and_then(Value::as_i64)
+                        .unwrap_or_default()
+                ),
+                action => format!("{action} {kind} `{}`", field("name")),
+            };
+            let namespace = args
+                .get("namespace")
+                .and_then(Value::as_str)
+                .map(|namespace| format!(" in `{namespace}`"))
+                .unwrap_or_default();
+            let cluster = args
+                .get("cluster")
+                .and_then(Value::as_str)
+                .map(|cluster| format!(" on `{cluster}`"))
+                .unwrap_or_default();
+            format!("**kubernetes** will {target}{namespace}{cluster}")
+        }
+        "incident" => {
+            let id = field("incident_id");
+            let account = args
+                .get("account")
+                .and_then(Value::as_str)
+                .map(|account| format!(" in `{account}`"))
+                .unwrap_or_default();
+            match field("action") {
+                "note" => {
+                    let quoted: Vec<String> = field("note")
+                        .lines()
+                        .map(|line| format!("> {line}"))
+                        .collect();
+                    format!(
+                        "**incident** will add a note to `{id}`{account}:\n{}",
+                        quoted.join("\n")
+                    )
+                }
+                action => format!("**incident** will {action} `{id}`{account}"),
+            }
+        }
+        other => {
+            let pretty = serde_json::to_string_pretty(&args).unwrap_or_default();
+            format!("**{other}** will be called with:\n```json\n{pretty}\n```")
+        }
+    };
+
+    let masked = LEAK_PATTERNS.iter().fold(body, |text, pattern| {
+        pattern.replace_all(&text, "[REDACTED]").into_owned()
+    });
+    truncate_output(&masked, MAX_PREVIEW_BYTES)
+}
+
+fn parse(args: &str) -> Value {
+    serde_json::from_str(args).unwrap_or(Value::Null)
+}
+
+fn render_file_write(path: &str, existing: Option<&str>, content: &str) -> String {
+    match existing {
+        Some(existing) => format!(
+            "**file** will overwrite `{path}`:\n```diff\n{}\n```",
+            unified_diff(existing, content)
+        ),
+        None => {
+            let lines: Vec<&str> = content.lines().collect();
+            let shown = lines[..lines.len().min(NEW_FILE_LINES)].join("\n");
+            let more = lines.len().saturating_sub(NEW_FILE_LINES);
+            let more = if more > 0 {
+                format!("\n… {more} more lines")
+            } else {
+                String::new()
+            };
+            format!(
+                "**file** will create `{path}` ({} lines):\n```\n{shown}\n```{more}",
+                lines.len()
+            )
+        }
+    }
+}
+
+fn render_computer_action(args: &Value) -> String {
+    let field = |name: &str| args.get(name).and_then(Value::as_str).unwrap_or_default();
+    let point = |name: &str| {
+        let coordinate = args.get(name).and_then(Value::as_array)?;
+        match coordinate.as_slice() {
+            [x, y] => Some(format!("({x}, {y})")),
+            _ => None,
+        }
+    };
+
+    let action = args.get("action").and_then(Value::as_str).unwrap_or("act");
+    let verb = match action {
+        "left_click_drag" => "drag".to_string(),
+        "mouse_move" => "move the mouse".to_string(),
+        "key" => "press".to_string(),
+        other => other.replace('_', " "),
+    };
+    let mut summary = format!("**computer** will {verb}");
+    if let Some(start) = point("start_coordinate") {
+        summary.push_str(&format!(" from {start}"));
+    }
+    if let Some(target) = point("coordinate") {
+        let preposition = match action {
+            "mouse_move" | "left_click_drag" => "to",
+            _ => "at",
+        };
+        summary.push_str(&format!(" {preposition} {target}"));
+    }
+    match action {
+        "type" => format!("{summary}:\n```\n{}\n```", field("text")),
+        "key" => format!("{summary} `{}`", field("text")),
+        "scroll" => {
+            let amount = args
+                .get("scroll_amount")
+                .and_then(Value::as_u64)
+                .unwrap_or(1);
+            format!("{summary}, {} by {amount}", field("scroll_direction"))
+        }
+        _ => summary,
+    }
+}
+
+/// Line diff of `old` against `new`, with unchanged runs away from any change
+/// folded into `@@ N unchanged lines @@` markers.
+pub fn unified_diff(old: &str, new: &str) -> String {
+    let old: Vec<&str> = old.lines().collect();
+    let new: Vec<&str> = new.lines().collect();
+    if old == new {
+        return "(no changes)".into();
+    }
+    if old.len().saturating_mul(new.len()) > MAX_DIFF_CELLS {
+        return format!(
+            "-{} lines\n+{} lines (too large to diff)",
+            old.len(),
+            new.len()
+        );
+    }
+
+    // Longest common subsequence table, filled from the end.
+    let mut lcs = vec![vec![0u32; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = if old[i] == new[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut lines: Vec<(char, &str)> = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            lines.push((' ', old[i]));
+            i += 1;
+            j += 1;
+        } else if i < old.len() && (j == new.len() || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            lines.push(('-', old[i]));
+            i += 1;
+        } else {
+            lines.push(('+', new[j]));
+            j += 1;
+        }
+    }
+
+    let near_change = |index: usize| {
+        let start = index.saturating_sub(DIFF_CONTEXT);
+        let end = (index + DIFF_CONTEXT + 1).min(lines.len());
+        lines[start..end].iter().any(|(tag, _)| *tag != ' ')
+    };
+    let mut output = Vec::new();
+    let mut folded = 0;
+    for (index, (tag, line)) in lines.iter().enumerate() {
+        if *tag == ' ' && !near_change(index) {
+            folded += 1;
+            continue;
+        }
+        if folded > 0 {
+            output.push(format!("@@ {folded} unchanged lines @@"));
+            folded = 0;
+        }
+        output.push(format!("{tag}{line}"));
+    }
+    if folded > 0 {
+        output.push(format!("@@ {folded} unchanged lines @@"));
+    }
+    output.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unified_diff_folds_unchanged_lines() {
+        let old = "a\nb\nc\nd\ne\nf\ng\nh";
+        let new = "a\nb\nc\nd\ne\nf\nG\nh";
+        assert_eq!(
+            unified_diff(old, new),
+            "@@ 4 unchanged lines @@\n e\n f\n-g\n+G\n h"
+        );
+    }
+
+    #[test]
+    fn test_file_reads_have_no_side_effects() {
+        assert!(!has_side_effects(
+            "file",
+            r#"{"operation":"read","path":"x"}"#
+        ));
+        assert!(has_side_effects(
+            "file",
+            r#"{"operation":"write","path":"x","content":""}"#
+        ));
+        assert!(has_side_effects("shell", r#"{"command":"ls"}"#));
+    }
+
+    #[test]
+    fn test_computer_observations_have_no_side_effects() {
+        assert!(!has_side_effects("computer", r#"{"action":"screenshot"}"#));
+        assert!(has_side_effects(
+            "computer",
+            r#"{"action":"left_click","coordinate":[10,20]}"#
+        ));
+        assert_eq!(
+            render_computer_action(&parse(
+                r#"{"action":"left_click_drag","start_coordinate":[1,2],"coordinate":[3,4]}"#
+            )),
+            "**computer** will drag from (1, 2) to (3, 4)"
+        );
+    }
+
+    #[test]
+    fn test_home_assistant_reads_have_no_side_effects() {
+        assert!(!has_side_effects(
+            "home_assistant",
+            r#"{"action":"list_states","domain":"light"}"#
+        ));
+        assert!(!has_side_effects(
+            "home_assistant",
+            r#"{"action":"get_state","entity_id":"lock.front_door"}"#
+        ));
+        assert!(has_side_effects(
+            "home_assistant",
+            r#"{"action":"call_service","domain":"lock","service":"unlock","entity_id":"lock.front_door"}"#
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_issues_search_has_no_side_effects() {
+        assert!(!has_side_effects(
+            "issues",
+            r#"{"action":"search","query":"login"}"#
+        ));
+        assert!(has_side_effects(
+            "issues",
+            r#"{"action":"comment","issue":"SUP-1","body":"Fixed"}"#
+        ));
+        assert_eq!(
+            render(
+                "issues",
+                r#"{"action":"comment","workspace":"support","issue":"SUP-1","body":"Fixed in 1.2\nThanks"}"#,
+                Path::new("/tmp"),
+            )
+            .await,
+            "**issues** will comment on `SUP-1` in `support`:\n> Fixed in 1.2\n> Thanks"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_http_request_reads_have_no_side_effects() {
+        assert!(!has_side_effects(
+            "http_request",
+            r#"{"url":"https://api.example.com/items"}"#
+        ));
+        assert!(!has_side_effects(
+            "http_request",
+            r#"{"method":"head","url":"https://api.example.com/items"}"#
+        ));
+        let post = r#"{"method":"post","url":"https://api.example.com/items","body":{"name":"x"}}"#;
+        assert!(has_side_effects("http_request", post));
+        assert_eq!(
+            render("http_request", post, Path::new("/tmp")).await,
+            "**http_request** will send `POST https://api.example.com/items` with:\n```json\n{\n  \"name\": \"x\"\n}\n```"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_kubernetes_reads_have_no_side_effects() {
+        assert!(!has_side_effects(
+            "kubernetes",
+            r#"{"action":"logs","name":"checkout-7d9f","previous":true}"#
+        ));
+        let scale = r#"{"action":"scale","kind":"deployments","name":"checkout","namespace":"payments","replicas":3}"#;
+        assert!(has_side_effects("kubernetes", scale));
+        assert_eq!(
+            render("kubernetes", scale, Path::new("/tmp")).await,
+            "**kubernetes** will scale deployments `checkout` to 3 replicas in `payments`"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_incident_changes_have_side_effects() {
+        assert!(!has_side_effects(
+            "incident",
+            r#"{"action":"timeline","incident_id":"Q1"}"#
+        ));
+        let resolve = r#"{"action":"resolve","account":"pagerduty","incident_id":"Q1"}"#;
+        assert!(has_side_effects("incident", resolve));
+        assert_eq!(
+            render("incident", resolve, Path::new("/tmp")).await,
+            "**incident** will resolve `Q1` in `pagerduty`"
+        );
+    }
+}
@@ -0,0 +1,379 @@
+//! Load testing for `spacebot bench`.
+//!
+//! Runs synthetic conversations against a mock provider: an in-process
+//! server that speaks the OpenAI chat completions API and answers after a
+//! fixed delay. Each simulated agent gets its own database in a scratch
+//! instance directory, like a real agent. Each turn goes through the same
+//! path a channel turn takes: a routed [`SpacebotModel`] behind the request
+//! limiter, the provider's HTTP client and response parser, and the
+//! conversation log. The memory system and tools are not involved, since
+//! the mock never calls a tool.
+
+use crate::ProcessType;
+use crate::conversation::ConversationLogger;
+use crate::db::Db;
+use crate::llm::metrics::LatencyKind;
+use crate::llm::{LlmManager, Priority, RoutingConfig, SpacebotModel};
+
+use anyhow::Context as _;
+use axum::Json;
+use axum::extract::State;
+use axum::routing::{get, post};
+use rig::agent::AgentBuilder;
+use rig::completion::{CompletionModel, Prompt};
+use serde::Serialize;
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// Model every simulated turn is routed to.
+const MOCK_MODEL: &str = "openai/spacebot-bench-mock";
+
+/// How long to wait for fire-and-forget message writes to land.
+const PERSISTENCE_TIMEOUT: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone)]
+pub struct BenchOptions {
+    pub agents: usize,
+    pub turns: usize,
+    /// How long the mock provider takes to answer.
+    pub mock_latency: Duration,
+    /// Request limiter size. `None` is the limiter's default.
+    pub max_concurrent_requests: Option<usize>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BenchReport {
+    pub agents: usize,
+    pub turns_per_agent: usize,
+    pub completed_turns: usize,
+    pub failed_turns: usize,
+    pub elapsed_ms: u64,
+    pub turns_per_second: f64,
+    /// Whole turn as a channel sees it: queueing, the provider call and
+    /// response parsing.
+    pub turn_latency: LatencySummary,
+    /// Mean and worst time spent waiting for a limiter slot.
+    pub queue_wait_mean_ms: u64,
+    pub queue_wait_max_ms: u64,
+    pub provider_requests: u64,
+    pub persisted_messages: u64,
+    /// Time after the last turn until every message was written.
+    pub persistence_drain_ms: u64,
+    /// Peak resident memory of the process, where the OS reports it.
+    pub peak_rss_bytes: Option<u64>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct LatencySummary {
+    pub mean_ms: u64,
+    pub p50_ms: u64,
+    pub p90_ms: u64,
+    pub p99_ms: u64,
+    pub max_ms: u64,
+}
+
+impl LatencySummary {
+    fn from_samples(mut samples: Vec<Duration>) -> Self {
+        if samples.is_empty() {
+            return Self::default();
+        }
+        samples.sort();
+        let millis = |duration: Duration| duration.as_millis() as u64;
+        let percentile = |fraction: f64| {
+            let index = ((samples.len() as f64 * fraction).ceil() as usize).saturating_sub(1);
+            millis(samples[index.min(samples.len() - 1)])
+        };
+        let total: Duration = samples.iter().sum();
+        Self {
+            mean_ms: millis(total / samples.len() as u32),
+            p50_ms: percentile(0.50),
+            p90_ms: percentile(0.90),
+            p99_ms: percentile(0.99),
+            max_ms: millis(samples[samples.len() - 1]),
+        }
+    }
+}
+
+/// Run the benchmark in a scratch instance directory, removed afterwards.
+pub async fn run(options: &BenchOptions) -> anyhow::Result<BenchReport> {
+    let scratch_dir =
+        std::env::temp_dir().join(format!("spacebot-bench-{}", uuid::Uuid::new_v4().simple()));
+    let report = run_in(options, &scratch_dir).await;
+    if let Err(error) = std::fs::remove_dir_all(&scratch_dir) {
+        tracing::warn!(%error, dir = %scratch_dir.display(), "failed to remove bench directory");
+    }
+    report
+}
+
+async fn run_in(options: &BenchOptions, scratch_dir: &Path) -> anyhow::Result<BenchReport> {
+    let mock = MockProvider::start(options.mock_latency).await?;
+
+    let mut builder = LlmManager::builder().base_url("openai", mock.base_url());
+    if let Some(limit) = options.max_concurrent_requests {
+        builder = builder.max_concurrent_requests(limit);
+    }
+    let manager = Arc::new(builder.build().context("failed to build LLM manager")?);
+    let routing = RoutingConfig {
+        channel: MOCK_MODEL.into(),
+        task_overrides: HashMap::new(),
+        fallbacks: HashMap::new(),
+        ..RoutingConfig::default()
+    };
+
+    let mut databases = Vec::with_capacity(options.agents);
+    for index in 0..options.agents {
+        let data_dir = scratch_dir.join("agents").join(format!("bench-{index}"));
+        std::fs::create_dir_all(&data_dir)
+            .with_context(|| format!("failed to create {}", data_dir.display()))?;
+        databases.push(Db::connect(&data_dir).await?);
+    }
+
+    let started = Instant::now();
+    let conversations = databases.iter().enumerate().map(|(index, db)| {
+        converse(
+            &manager,
+            &routing,
+            ConversationLogger::new(db.sqlite.clone()),
+            index,
+            options.turns,
+        )
+    });
+    let results = futures::future::join_all(conversations).await;
+    let elapsed = started.elapsed();
+
+    let mut latencies = Vec::new();
+    let mut failed_turns = 0;
+    for (turn_latencies, failures) in results {
+        latencies.extend(turn_latencies);
+        failed_turns += failures;
+    }
+    let completed_turns = latencies.len();
+
+    // Each completed turn logs two messages, each failed one only the user's.
+    let expected_messages = (completed_turns * 2 + failed_turns) as u64;
+    let drain_started = Instant::now();
+    let persisted_messages = wait_for_messages(&databases, expected_messages).await?;
+    let persistence_drain = drain_started.elapsed();
+
+    let (queue_wait_sum, queue_wait_count, queue_wait_max) = manager
+        .metrics()
+        .snapshot()
+        .into_iter()
+        .filter(|series| series.kind == LatencyKind::QueueWait)
+        .fold((0, 0, 0), |(sum, count, max), series| {
+            (
+                sum + series.sum_ms,
+                count + series.count,
+                max.max(series.max_ms),
+            )
+        });
+
+    for db in databases {
+        db.close().await;
+    }
+
+    Ok(BenchReport {
+        agents: options.agents,
+        turns_per_agent: options.turns,
+        completed_turns,
+        failed_turns,
+        elapsed_ms: elapsed.as_millis() as u64,
+        turns_per_second: completed_turns as f64 / elapsed.as_secs_f64().max(f64::EPSILON),
+        turn_latency: LatencySummary::from_samples(latencies),
+        queue_wait_mean_ms: queue_wait_sum.checked_div(queue_wait_count).unwrap_or(0),
+        queue_wait_max_ms: queue_wait_max,
+        provider_requests: mock.requests(),
+        persisted_messages,
+        persistence_drain_ms: persistence_drain.as_millis() as u64,
+        peak_rss_bytes: peak_rss_bytes(),
+    })
+}
+
+/// One agent's channel: `turns` turns in a row, with growing history.
+/// Returns the latency of each completed turn and the number that failed.
+async fn converse(
+    manager: &Arc<LlmManager>,
+    routing: &RoutingConfig,
+    logger: ConversationLogger,
+    index: usize,
+    turns: usize,
+) -> (Vec<Duration>, usize) {
+    let channel_id: crate::ChannelId = Arc::from(format!("bench:{index}").as_str());
+    let metadata = HashMap::new();
+    let mut history = Vec::new();
+    let mut latencies = Vec::with_capacity(turns);
+    let mut failures = 0;
+
+    for turn in 0..turns {
+        let user_text = format!("Message {turn} from bench user {index}. What should I do next?");
+        logger.log_user_message(
+            &channel_id,
+            "bench user",
+            "bench-user",
+            &user_text,
+            &metadata,
+        );
+
+        let model = SpacebotModel::make(manager, MOCK_MODEL)
+            .with_routing(routing.clone())
+            .with_priority(Priority::for_process(ProcessType::Channel));
+        let agent = AgentBuilder::new(model)
+            .preamble("You are a load test agent. Answer briefly.")
+            .build();
+
+        let started = Instant::now();
+        match agent.prompt(&user_text).with_history(&mut history).await {
+            Ok(reply) => {
+                latencies.push(started.elapsed());
+                logger.log_bot_message(&channel_id, &reply, None, &[]);
+            }
+            Err(error) => {
+                tracing::warn!(%error, agent = index, turn, "bench turn failed");
+                failures += 1;
+            }
+        }
+    }
+    (latencies, failures)
+}
+
+/// Poll until `expected` messages are stored across all databases, or the
+/// timeout passes. Returns the number stored.
+async fn wait_for_messages(databases: &[Db], expected: u64) -> anyhow::Result<u64> {
+    let deadline = Instant::now() + PERSISTENCE_TIMEOUT;
+    loop {
+        let mut stored = 0;
+        for db in databases {
+            let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM conversation_messages")
+                .fetch_one(&db.sqlite)
+                .await
+                .context("failed to count stored messages")?;
+            stored += count as u64;
+        }
+        if stored >= expected || Instant::now() >= deadline {
+            return Ok(stored);
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+}
+
+/// Peak resident set size, from `/proc/self/status`.
+fn peak_rss_bytes() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmHWM:"))?;
+    let kilobytes: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kilobytes * 1024)
+}
+
+/// OpenAI-compatible chat completions server that answers every request
+/// with a canned reply after a fixed delay.
+struct MockProvider {
+    address: std::net::SocketAddr,
+    requests: Arc<AtomicU64>,
+    handle: tokio::task::JoinHandle<()>,
+}
+
+#[derive(Clone)]
+struct MockState {
+    latency: Duration,
+    requests: Arc<AtomicU64>,
+}
+
+impl MockProvider {
+    async fn start(latency: Duration) -> anyhow::Result<Self> {
+        let requests = Arc::new(AtomicU64::new(0));
+        let app = axum::Router::new()
+            .route("/v1/chat/completions", post(mock_completion))
+            // Providers with a base URL override get health probes.
+            .route("/health", get(|| async { "ok" }))
+            .with_state(MockState {
+                latency,
+                requests: requests.clone(),
+            });
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+            .await
+            .context("failed to bind mock provider")?;
+        let address = listener.local_addr()?;
+        let handle = tokio::spawn(async move {
+            if let Err(error) = axum::serve(listener, app).await {
+                tracing::warn!(%error, "mock provider stopped");
+            }
+        });
+        Ok(Self {
+            address,
+            requests,
+            handle,
+        })
+    }
+
+    fn base_url(&self) -> String {
+        format!("http://{}", self.address)
+    }
+
+    fn requests(&self) -> u64 {
+        self.requests.load(Ordering::Relaxed)
+    }
+}
+
+impl Drop for MockProvider {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
+async fn mock_completion(
+    State(state): State<MockState>,
+    Json(body): Json<serde_json::Value>,
+) -> Json<serde_json::Value> {
+    let number = state.requests.fetch_add(1, Ordering::Relaxed);
+    tokio::time::sleep(state.latency).await;
+
+    let prompt_chars: usize = body["messages"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|message| message["content"].as_str())
+        .map(str::len)
+        .sum();
+    let reply = "Noted. Here is a short, plausible answer so the conversation can continue.";
+    let prompt_tokens = prompt_chars / 4;
+    let completion_tokens = reply.len() / 4;
+    Json(serde_json::json!({
+        "id": format!("bench-{number}"),
+        "object": "chat.completion",
+        "created": 0,
+        "model": body["model"],
+        "choices": [{
+            "index": 0,
+            "message": { "role": "assistant", "content": reply },
+            "finish_reason": "stop",
+        }],
+        "usage": {
+            "prompt_tokens": prompt_tokens,
+            "completion_tokens": completion_tokens,
+            "total_tokens": prompt_tokens + completion_tokens,
+        },
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_latency_percentiles() {
+        let samples = (1..=100).map(Duration::from_millis).collect();
+        let summary = LatencySummary::from_samples(samples);
+
+        assert_eq!(summary.p50_ms, 50);
+        assert_eq!(summary.p90_ms, 90);
+        assert_eq!(summary.p99_ms, 99);
+        assert_eq!(summary.max_ms, 100);
+        assert_eq!(summary.mean_ms, 50);
+
+        assert_eq!(LatencySummary::from_samples(Vec::new()).max_ms, 0);
+    }
+}
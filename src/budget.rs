@@ -0,0 +1,237 @@
+//! Graceful degradation as an agent spends its daily budget.
+//!
+//! One watcher runs per agent. Every `check_interval_secs` it sums the cost
+//! of the agent's `turn_runs` over the last 24 hours and moves the agent down
+//! the ladder:
+//! - at `cheaper_model_at` of `daily_usd`, channels answer with the cheaper
+//!   model,
+//! - at `restrict_tools_at`, the expensive tools are withheld as well,
+//! - once the budget is spent, every message gets the canned over-budget
+//!   reply and no model is called.
+//!
+//! As older turns age out of the window the agent climbs back up. Each step
+//! is logged and delivered to the configured targets, the same way usage
+//! anomalies are, instead of the agent failing once the money runs out.
+
+use crate::OutboundResponse;
+use crate::config::BudgetConfig;
+use crate::cron::CronContext;
+use crate::cron::scheduler::DeliveryTarget;
+
+use anyhow::Context as _;
+use serde::Serialize;
+use sqlx::SqlitePool;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Checks never run more often than this, whatever the interval.
+const MIN_INTERVAL_SECS: u64 = 10;
+
+/// How far down the ladder an agent is. Each level keeps the ones before.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BudgetLevel {
+    #[default]
+    Normal,
+    CheaperModel,
+    RestrictedTools,
+    OverBudget,
+}
+
+/// The level `spent_usd` over the last 24 hours puts the agent at.
+pub fn level(config: &BudgetConfig, spent_usd: f64) -> BudgetLevel {
+    if config.daily_usd <= 0.0 {
+        return BudgetLevel::Normal;
+    }
+    let share = spent_usd / config.daily_usd;
+    if share >= 1.0 {
+        BudgetLevel::OverBudget
+    } else if share >= config.restrict_tools_at {
+        BudgetLevel::RestrictedTools
+    } else if share >= config.cheaper_model_at {
+        BudgetLevel::CheaperModel
+    } else {
+        BudgetLevel::Normal
+    }
+}
+
+/// The alert text for `agent_id` moving to `level`.
+pub fn describe(
+    config: &BudgetConfig,
+    agent_id: &str,
+    level: BudgetLevel,
+    spent_usd: f64,
+) -> String {
+    let what = match level {
+        BudgetLevel::Normal => "is back to its usual model and tools".to_string(),
+        BudgetLevel::CheaperModel => {
+            format!(
+                "now answers with the cheaper `{}` model",
+                config.cheaper_model
+            )
+        }
+        BudgetLevel::RestrictedTools => format!(
+            "now answers with the cheaper `{}` model and without {}",
+            config.cheaper_model,
+            config.expensive_tools.join(", ")
+        ),
+        BudgetLevel::OverBudget => {
+            "is over budget and only sends its over-budget reply".to_string()
+        }
+    };
+    format!(
+        "Budget: agent `{agent_id}` spent ${spent_usd:.2} of its ${:.2} daily budget in the last 24 hours and {what}.",
+        config.daily_usd
+    )
+}
+
+/// Where an agent is on the ladder, as last checked.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct BudgetStatus {
+    pub level: BudgetLevel,
+    /// Spend over the last 24 hours, in USD.
+    pub spent_usd: f64,
+}
+
+/// An agent's budget status, updated by its watcher and read by its
+/// channels. Clones share state.
+#[derive(Debug, Clone, Default)]
+pub struct BudgetTracker {
+    status: Arc<Mutex<BudgetStatus>>,
+}
+
+impl BudgetTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn status(&self) -> BudgetStatus {
+        *self.lock()
+    }
+
+    /// The level channels should act on. Normal while the budget is off.
+    pub fn level(&self, config: &BudgetConfig) -> BudgetLevel {
+        if config.enabled {
+            self.lock().level
+        } else {
+            BudgetLevel::Normal
+        }
+    }
+
+    /// Store a new status and return the level it replaced.
+    fn update(&self, status: BudgetStatus) -> BudgetLevel {
+        std::mem::replace(&mut *self.lock(), status).level
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, BudgetStatus> {
+        self.status
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// Sum the cost of the turns completed in the last 24 hours.
+async fn daily_spend(pool: &SqlitePool) -> anyhow::Result<f64> {
+    sqlx::query_scalar(
+        "SELECT CAST(COALESCE(SUM(json_extract(outcome, '$.cost_usd')), 0) AS REAL) \
+         FROM turn_runs WHERE completed_at >= datetime('now', '-24 hours')",
+    )
+    .fetch_one(pool)
+    .await
+    .context("failed to sum daily spend")
+}
+
+/// Spawn the budget watcher for an agent.
+///
+/// Reads `deps.runtime_config.budget` on every check, so changes on config
+/// reload are picked up without a restart.
+pub fn spawn_budget_watcher(context: CronContext) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        loop {
+            let config = context.deps.runtime_config.budget.load_full();
+            if config.enabled {
+                if let Err(error) = check(&context, &config).await {
+                    tracing::warn!(%error, "budget check failed");
+                }
+            } else {
+                context.deps.budget.update(BudgetStatus::default());
+            }
+
+            let interval = config.check_interval_secs.max(MIN_INTERVAL_SECS);
+            tokio::time::sleep(Duration::from_secs(interval)).await;
+        }
+    })
+}
+
+async fn check(context: &CronContext, config: &BudgetConfig) -> anyhow::Result<()> {
+    let spent_usd = daily_spend(&context.deps.sqlite_pool).await?;
+    let level = level(config, spent_usd);
+    let previous = context
+        .deps
+        .budget
+        .update(BudgetStatus { level, spent_usd });
+    if level == previous {
+        return Ok(());
+    }
+
+    let agent_id = &context.deps.agent_id;
+    tracing::warn!(%agent_id, ?previous, ?level, spent_usd, daily_usd = config.daily_usd, "budget level changed");
+    let text = describe(config, agent_id, level, spent_usd);
+    for raw_target in &config.delivery_targets {
+        let Some(target) = DeliveryTarget::parse(raw_target) else {
+            tracing::warn!(%raw_target, "invalid budget delivery target, expected 'adapter:target'");
+            continue;
+        };
+        if let Err(error) = context
+            .messaging_manager
+            .broadcast(
+                &target.adapter,
+                &target.target,
+                OutboundResponse::Text(text.clone()),
+            )
+            .await
+        {
+            tracing::warn!(%error, %target, "failed to deliver budget alert");
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_level_steps_down_the_ladder() {
+        let config = BudgetConfig {
+            enabled: true,
+            ..BudgetConfig::default()
+        };
+        assert_eq!(level(&config, 0.0), BudgetLevel::Normal);
+        assert_eq!(level(&config, 7.99), BudgetLevel::Normal);
+        assert_eq!(level(&config, 8.0), BudgetLevel::CheaperModel);
+        assert_eq!(level(&config, 9.5), BudgetLevel::RestrictedTools);
+        assert_eq!(level(&config, 10.0), BudgetLevel::OverBudget);
+
+        let unlimited = BudgetConfig {
+            daily_usd: 0.0,
+            ..config
+        };
+        assert_eq!(level(&unlimited, 100.0), BudgetLevel::Normal);
+    }
+
+    #[test]
+    fn test_tracker_ignores_level_while_disabled() {
+        let tracker = BudgetTracker::new();
+        let previous = tracker.update(BudgetStatus {
+            level: BudgetLevel::OverBudget,
+            spent_usd: 12.0,
+        });
+        assert_eq!(previous, BudgetLevel::Normal);
+
+        let mut config = BudgetConfig::default();
+        assert_eq!(tracker.level(&config), BudgetLevel::Normal);
+        config.enabled = true;
+        assert_eq!(tracker.level(&config), BudgetLevel::OverBudget);
+    }
+}
@@ -0,0 +1,1167 @@
+//! Deterministic calculator behind the `calc` tool.
+//!
+//! Models are unreliable at arithmetic, so the tool evaluates expressions
+//! here instead: decimal arithmetic with 28 significant digits and no binary
+//! floating point, quantities with units (`60 km/h * 90 min`), conversions
+//! (`5 mi to km`, `100 degF to degC`) and dates (`2026-10-17 + 30 days`,
+//! `2026-12-25 - today`).
+//!
+//! A number followed by units is a quantity: `10 MB/s` reads as one value,
+//! so `1 GB / 10 MB/s` is a time. `%` after a value is a percentage
+//! (`20% of 150`) and between two values is the remainder (`7 % 3`, also
+//! `7 mod 3`). `to`, `in` or `as` at the end converts the result. Results
+//! are shown in the units of the leftmost quantity they came from, or in SI
+//! units when those cancel out. Temperatures convert absolutely, so add and
+//! subtract them in kelvin.
+
+pub mod units;
+
+use units::{DIMENSIONLESS, Dims, Unit};
+
+use chrono::NaiveDate;
+use rust_decimal::prelude::ToPrimitive as _;
+use rust_decimal::{Decimal, MathematicalOps as _};
+
+/// Decimal places results are rounded to.
+const RESULT_DP: u32 = 20;
+
+/// Seconds in a day, for date arithmetic.
+const DAY_SECS: i64 = 86_400;
+
+/// Why an expression couldn't be evaluated.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("{0}")]
+pub struct CalcError(String);
+
+type Result<T> = std::result::Result<T, CalcError>;
+
+fn fail<T>(message: impl Into<String>) -> Result<T> {
+    Err(CalcError(message.into()))
+}
+
+/// The result of an expression.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Answer {
+    Number {
+        value: Decimal,
+        /// None for plain numbers.
+        unit: Option<String>,
+    },
+    Date(NaiveDate),
+}
+
+impl std::fmt::Display for Answer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Number { value, unit: None } => write!(f, "{value}"),
+            Self::Number {
+                value,
+                unit: Some(unit),
+            } => write!(f, "{value} {unit}"),
+            Self::Date(date) => write!(f, "{} ({})", date.format("%Y-%m-%d"), date.format("%A")),
+        }
+    }
+}
+
+/// Evaluate `expression`, with `today` as the current UTC date.
+pub fn evaluate(expression: &str) -> Result<Answer> {
+    evaluate_on(expression, chrono::Utc::now().date_naive())
+}
+
+/// Evaluate `expression` with `today` as the current date.
+pub fn evaluate_on(expression: &str, today: NaiveDate) -> Result<Answer> {
+    let tokens = tokenize(expression)?;
+    if tokens.is_empty() {
+        return fail("the expression is empty");
+    }
+    let mut parser = Parser {
+        tokens,
+        pos: 0,
+        today,
+    };
+    let value = parser.expression()?;
+    if let Some(token) = parser.peek() {
+        return fail(format!("unexpected {token}"));
+    }
+    Ok(match value {
+        Value::Date(date) => Answer::Date(date),
+        Value::Quantity(quantity) => quantity.answer()?,
+    })
+}
+
+/// A number with dimensions, held in SI units.
+#[derive(Debug, Clone, PartialEq)]
+struct Quantity {
+    value: Decimal,
+    dims: Dims,
+    /// Units to show the value in, with their powers.
+    display: Vec<(&'static Unit, i8)>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    Quantity(Quantity),
+    Date(NaiveDate),
+}
+
+impl Quantity {
+    fn number(value: Decimal) -> Self {
+        Self {
+            value,
+            dims: DIMENSIONLESS,
+            display: Vec::new(),
+        }
+    }
+
+    fn unit(unit: &'static Unit) -> Self {
+        Self {
+            value: unit.factor(),
+            dims: unit.dims,
+            display: vec![(unit, 1)],
+        }
+    }
+
+    fn is_dimensionless(&self) -> bool {
+        self.dims == DIMENSIONLESS
+    }
+
+    /// The units the value is shown in: the ones it was written in, or SI
+    /// base units.
+    fn display_units(&self) -> Vec<(&'static Unit, i8)> {
+        if !self.display.is_empty() || self.is_dimensionless() {
+            return self.display.clone();
+        }
+        self.dims
+            .iter()
+            .enumerate()
+            .filter(|(_, power)| **power != 0)
+            .map(|(index, power)| (units::base(index), *power))
+            .collect()
+    }
+
+    /// The value in `display` units.
+    fn to_display(&self, display: &[(&'static Unit, i8)]) -> Result<Decimal> {
+        match display {
+            [(unit, 1)] if unit.is_affine() => checked(
+                self.value
+                    .checked_div(unit.factor())
+                    .and_then(|value| value.checked_sub(unit.offset())),
+            ),
+            _ => checked(self.value.checked_div(scale(display)?)),
+        }
+    }
+
+    /// The SI value of `value` in `display` units.
+    fn from_display(value: Decimal, display: &[(&'static Unit, i8)]) -> Result<Decimal> {
+        match display {
+            [(unit, 1)] if unit.is_affine() => checked(
+                value
+                    .checked_add(unit.offset())
+                    .and_then(|value| value.checked_mul(unit.factor())),
+            ),
+            _ => checked(value.checked_mul(scale(display)?)),
+        }
+    }
+
+    /// Apply `op` to the value as shown, keeping its units.
+    fn map_shown(self, op: impl FnOnce(Decimal) -> Option<Decimal>) -> Result<Self> {
+        let display = self.display_units();
+        let shown = checked(op(self.to_display(&display)?))?;
+        Ok(Self {
+            value: Self::from_display(shown, &display)?,
+            ..self
+        })
+    }
+
+    fn answer(self) -> Result<Answer> {
+        let display = self.display_units();
+        let value = self.to_display(&display)?.round_dp(RESULT_DP).normalize();
+        Ok(Answer::Number {
+            value,
+            unit: (!display.is_empty()).then(|| format_units(&display)),
+        })
+    }
+}
+
+/// Size of one of `display`'s composite unit in SI units.
+fn scale(display: &[(&'static Unit, i8)]) -> Result<Decimal> {
+    let mut scale = Decimal::ONE;
+    for (unit, power) in display {
+        let factor = checked(unit.factor().checked_powi(i64::from(*power)))?;
+        scale = checked(scale.checked_mul(factor))?;
+    }
+    Ok(scale)
+}
+
+fn checked(value: Option<Decimal>) -> Result<Decimal> {
+    match value {
+        Some(value) => Ok(value),
+        None => fail("the result is out of range"),
+    }
+}
+
+/// `km`, `m/s^2`, `kg*m/s^2`.
+fn format_units(display: &[(&'static Unit, i8)]) -> String {
+    let part = |unit: &Unit, power: i16| match power {
+        1 => unit.symbol.to_string(),
+        power => format!("{}^{power}", unit.symbol),
+    };
+    let numerator: Vec<String> = display
+        .iter()
+        .filter(|(_, power)| *power > 0)
+        .map(|(unit, power)| part(*unit, i16::from(*power)))
+        .collect();
+    let denominator: Vec<String> = display
+        .iter()
+        .filter(|(_, power)| *power < 0)
+        .map(|(unit, power)| part(*unit, -i16::from(*power)))
+        .collect();
+    let mut text = if numerator.is_empty() {
+        "1".to_string()
+    } else {
+        numerator.join("*")
+    };
+    for unit in denominator {
+        text.push('/');
+        text.push_str(&unit);
+    }
+    text
+}
+
+/// `display` with units of the same dimension combined into the first of
+/// them, so `h * km/h` shows in `km`.
+fn merge_units(display: Vec<(&'static Unit, i8)>) -> Vec<(&'static Unit, i8)> {
+    let mut merged: Vec<(&'static Unit, i8)> = Vec::new();
+    for (unit, power) in display {
+        match merged.iter_mut().find(|(kept, _)| kept.dims == unit.dims) {
+            Some((_, kept_power)) => *kept_power = kept_power.saturating_add(power),
+            None => merged.push((unit, power)),
+        }
+    }
+    merged.retain(|(_, power)| *power != 0);
+    merged
+}
+
+fn add_dims(left: Dims, right: Dims, sign: i8) -> Result<Dims> {
+    let mut dims = left;
+    for (dim, other) in dims.iter_mut().zip(right) {
+        *dim = match other
+            .checked_mul(sign)
+            .and_then(|other| dim.checked_add(other))
+        {
+            Some(dim) => dim,
+            None => return fail("the units' powers are out of range"),
+        };
+    }
+    Ok(dims)
+}
+
+fn describe(quantity: &Quantity) -> String {
+    let display = quantity.display_units();
+    if display.is_empty() {
+        "a plain number".to_string()
+    } else {
+        format_units(&display)
+    }
+}
+
+fn quantity(value: Value, what: &str) -> Result<Quantity> {
+    match value {
+        Value::Quantity(quantity) => Ok(quantity),
+        Value::Date(_) => fail(format!("{what} doesn't work on dates")),
+    }
+}
+
+fn number(value: Value, what: &str) -> Result<Decimal> {
+    let quantity = quantity(value, what)?;
+    if !quantity.is_dimensionless() {
+        return fail(format!(
+            "{what} needs a plain number, not {}",
+            describe(&quantity)
+        ));
+    }
+    Ok(quantity.value)
+}
+
+/// `date` moved by `duration`. Whole months and years move by calendar
+/// months; anything else has to be whole days.
+fn move_date(date: NaiveDate, duration: &Quantity, sign: i8) -> Result<NaiveDate> {
+    if duration.dims != units::TIME {
+        return fail(format!(
+            "dates can only move by durations, not {}",
+            describe(duration)
+        ));
+    }
+
+    let moved = match calendar_months(duration)? {
+        Some(months) => {
+            let months = months * i64::from(sign);
+            let count =
+                chrono::Months::new(u32::try_from(months.unsigned_abs()).unwrap_or(u32::MAX));
+            if months < 0 {
+                date.checked_sub_months(count)
+            } else {
+                date.checked_add_months(count)
+            }
+        }
+        None => {
+            let days = checked(duration.value.checked_div(Decimal::from(DAY_SECS)))?;
+            if !days.fract().is_zero() {
+                return fail("dates only move by whole days, months or years");
+            }
+            days.to_i64()
+                .and_then(|days| days.checked_mul(i64::from(sign)))
+                .and_then(chrono::TimeDelta::try_days)
+                .and_then(|delta| date.checked_add_signed(delta))
+        }
+    };
+    match moved {
+        Some(date) => Ok(date),
+        None => fail("the date is out of range"),
+    }
+}
+
+/// `duration` in calendar months, if it's a whole number of months or years.
+fn calendar_months(duration: &Quantity) -> Result<Option<i64>> {
+    let per_unit = match duration.display.as_slice() {
+        [(unit, 1)] if unit.symbol == "month" => 1,
+        [(unit, 1)] if unit.symbol == "year" => 12,
+        _ => return Ok(None),
+    };
+    let shown = duration.to_display(&duration.display)?;
+    if !shown.fract().is_zero() {
+        return Ok(None);
+    }
+    Ok(shown
+        .to_i64()
+        .and_then(|count| count.checked_mul(per_unit))
+        .filter(|months| months.unsigned_abs() <= u64::from(u32::MAX)))
+}
+
+fn add(left: Value, right: Value, sign: i8) -> Result<Value> {
+    match (left, right) {
+        (Value::Date(date), Value::Quantity(duration)) => {
+            Ok(Value::Date(move_date(date, &duration, sign)?))
+        }
+        (Value::Quantity(duration), Value::Date(date)) if sign > 0 => {
+            add(Value::Date(date), Value::Quantity(duration), sign)
+        }
+        (Value::Date(left), Value::Date(right)) if sign < 0 => {
+            let days = (left - right).num_days();
+            let day = units::lookup("day").expect("day is in the unit table");
+            Ok(Value::Quantity(Quantity {
+                value: Decimal::from(days) * Decimal::from(DAY_SECS),
+                dims: day.dims,
+                display: vec![(day, 1)],
+            }))
+        }
+        (Value::Date(_), Value::Date(_)) | (Value::Quantity(_), Value::Date(_)) => {
+            fail("dates can only be subtracted from each other or moved by a duration")
+        }
+        (Value::Quantity(left), Value::Quantity(right)) => {
+            if left.dims != right.dims {
+                return fail(format!(
+                    "can't combine {} with {}",
+                    describe(&left),
+                    describe(&right)
+                ));
+            }
+            let value = match sign {
+                1 => left.value.checked_add(right.value),
+                _ => left.value.checked_sub(right.value),
+            };
+            Ok(Value::Quantity(Quantity {
+                value: checked(value)?,
+                dims: left.dims,
+                display: if left.display.is_empty() {
+                    right.display
+                } else {
+                    left.display
+                },
+            }))
+        }
+    }
+}
+
+fn multiply(left: Quantity, right: Quantity, sign: i8) -> Result<Quantity> {
+    let value = match sign {
+        1 => left.value.checked_mul(right.value),
+        _ => {
+            if right.value.is_zero() {
+                return fail("division by zero");
+            }
+            left.value.checked_div(right.value)
+        }
+    };
+    let dims = add_dims(left.dims, right.dims, sign)?;
+    let display = if dims == DIMENSIONLESS {
+        Vec::new()
+    } else {
+        merge_units(
+            left.display_units()
+                .into_iter()
+                .chain(
+                    right
+                        .display_units()
+                        .into_iter()
+                        .map(|(unit, power)| (unit, power.saturating_mul(sign))),
+                )
+                .collect(),
+        )
+    };
+    Ok(Quantity {
+        value: checked(value)?,
+        dims,
+        display,
+    })
+}
+
+fn remainder(left: Quantity, right: Quantity) -> Result<Quantity> {
+    if left.dims != right.dims {
+        return fail(format!(
+            "can't take the remainder of {} by {}",
+            describe(&left),
+            describe(&right)
+        ));
+    }
+    if right.value.is_zero() {
+        return fail("division by zero");
+    }
+    Ok(Quantity {
+        value: checked(left.value.checked_rem(right.value))?,
+        ..left
+    })
+}
+
+fn power(base: Quantity, exponent: Decimal) -> Result<Quantity> {
+    if exponent.fract().is_zero() {
+        let Some(exponent) = exponent.to_i64() else {
+            return fail("the exponent is out of range");
+        };
+        if base.value.is_zero() && exponent < 0 {
+            return fail("division by zero");
+        }
+        let Ok(small) = i8::try_from(exponent) else {
+            if base.is_dimensionless() {
+                return Ok(Quantity::number(checked(
+                    base.value.checked_powi(exponent),
+                )?));
+            }
+            return fail("the exponent is out of range");
+        };
+        let mut dims = DIMENSIONLESS;
+        for _ in 0..small.unsigned_abs() {
+            dims = add_dims(dims, base.dims, small.signum())?;
+        }
+        return Ok(Quantity {
+            value: checked(base.value.checked_powi(exponent))?,
+            dims,
+            display: base
+                .display_units()
+                .into_iter()
+                .map(|(unit, power)| (unit, power.saturating_mul(small)))
+                .filter(|(_, power)| *power != 0)
+                .collect(),
+        });
+    }
+    if !base.is_dimensionless() {
+        return fail(format!(
+            "{} can only be raised to whole powers",
+            describe(&base)
+        ));
+    }
+    if base.value.is_sign_negative() {
+        return fail("negative numbers can't be raised to fractional powers");
+    }
+    Ok(Quantity::number(checked(
+        base.value.checked_powd(exponent),
+    )?))
+}
+
+fn square_root(value: Quantity) -> Result<Quantity> {
+    if value.value.is_sign_negative() && !value.value.is_zero() {
+        return fail("square root of a negative number");
+    }
+    if value.dims.iter().any(|dim| dim % 2 != 0) {
+        return fail(format!(
+            "can't take the square root of {}",
+            describe(&value)
+        ));
+    }
+    let display = value.display_units();
+    let display = if display.iter().all(|(_, power)| power % 2 == 0) {
+        display
+            .into_iter()
+            .map(|(unit, power)| (unit, power / 2))
+            .collect()
+    } else {
+        Vec::new()
+    };
+    Ok(Quantity {
+        value: checked(value.value.sqrt())?,
+        dims: value.dims.map(|dim| dim / 2),
+        display,
+    })
+}
+
+fn convert(value: Value, target: Quantity) -> Result<Value> {
+    let value = quantity(value, "Converting")?;
+    if value.dims != target.dims {
+        return fail(format!(
+            "can't convert {} to {}",
+            describe(&value),
+            describe(&target)
+        ));
+    }
+    Ok(Value::Quantity(Quantity {
+        display: target.display,
+        ..value
+    }))
+}
+
+fn call(name: &str, args: Vec<Value>) -> Result<Value> {
+    let arity = |count: usize| {
+        if args.len() == count {
+            Ok(())
+        } else {
+            fail(format!("{name} takes {count} argument(s)"))
+        }
+    };
+    let single = |args: Vec<Value>| args.into_iter().next().expect("arity checked");
+    let plain = |value: Value, op: fn(Decimal) -> Option<Decimal>| -> Result<Value> {
+        let value = number(value, name)?;
+        Ok(Value::Quantity(Quantity::number(checked(op(value))?)))
+    };
+
+    match name {
+        "sqrt" => {
+            arity(1)?;
+            Ok(Value::Quantity(square_root(quantity(single(args), name)?)?))
+        }
+        "abs" => {
+            arity(1)?;
+            let value = quantity(single(args), name)?;
+            Ok(Value::Quantity(value.map_shown(|value| Some(value.abs()))?))
+        }
+        "floor" | "ceil" => {
+            arity(1)?;
+            let value = quantity(single(args), name)?;
+            let up = name == "ceil";
+            Ok(Value::Quantity(value.map_shown(|value| {
+                Some(if up { value.ceil() } else { value.floor() })
+            })?))
+        }
+        "round" => {
+            if args.is_empty() || args.len() > 2 {
+                return fail("round takes a value and optionally a number of decimal places");
+            }
+            let mut args = args.into_iter();
+            let value = quantity(args.next().expect("checked"), name)?;
+            let places = match args.next() {
+                Some(places) => number(places, name)?,
+                None => Decimal::ZERO,
+            };
+            let Some(places) = places.to_u32().filter(|places| *places <= 28) else {
+                return fail("round takes 0 to 28 decimal places");
+            };
+            Ok(Value::Quantity(
+                value.map_shown(|value| Some(value.round_dp(places)))?,
+            ))
+        }
+        "min" | "max" => {
+            let mut values = args
+                .into_iter()
+                .map(|value| quantity(value, name))
+                .collect::<Result<Vec<_>>>()?
+                .into_iter();
+            let Some(mut best) = values.next() else {
+                return fail(format!("{name} needs at least one value"));
+            };
+            for value in values {
+                if value.dims != best.dims {
+                    return fail(format!(
+                        "can't compare {} with {}",
+                        describe(&best),
+                        describe(&value)
+                    ));
+                }
+                let better = if name == "min" {
+                    value.value < best.value
+                } else {
+                    value.value > best.value
+                };
+                if better {
+                    best = value;
+                }
+            }
+            Ok(Value::Quantity(best))
+        }
+        "ln" => {
+            arity(1)?;
+            plain(single(args), |value| value.checked_ln())
+        }
+        "log" => {
+            arity(1)?;
+            plain(single(args), |value| value.checked_log10())
+        }
+        "exp" => {
+            arity(1)?;
+            plain(single(args), |value| value.checked_exp())
+        }
+        "sin" => {
+            arity(1)?;
+            plain(single(args), |value| value.checked_sin())
+        }
+        "cos" => {
+            arity(1)?;
+            plain(single(args), |value| value.checked_cos())
+        }
+        "tan" => {
+            arity(1)?;
+            plain(single(args), |value| value.checked_tan())
+        }
+        _ => fail(format!("unknown function {name}")),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(Decimal),
+    Date(NaiveDate),
+    Ident(String),
+    Op(char),
+    LParen,
+    RParen,
+    Comma,
+}
+
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Number(number) => write!(f, "'{number}'"),
+            Self::Date(date) => write!(f, "'{date}'"),
+            Self::Ident(name) => write!(f, "'{name}'"),
+            Self::Op(op) => write!(f, "'{op}'"),
+            Self::LParen => write!(f, "'('"),
+            Self::RParen => write!(f, "')'"),
+            Self::Comma => write!(f, "','"),
+        }
+    }
+}
+
+/// Words with a meaning of their own, which can't be units.
+const KEYWORDS: &[&str] = &["to", "in", "as", "mod", "of"];
+
+fn is_conversion(word: &str) -> bool {
+    matches!(word, "to" | "in" | "as")
+}
+
+fn tokenize(expression: &str) -> Result<Vec<Token>> {
+    let chars: Vec<char> = expression.chars().collect();
+    let mut tokens = Vec::new();
+    let mut pos = 0;
+    while pos < chars.len() {
+        let c = chars[pos];
+        if c.is_whitespace() {
+            pos += 1;
+        } else if let Some((date, len)) = date_at(&chars[pos..])? {
+            tokens.push(Token::Date(date));
+            pos += len;
+        } else if c.is_ascii_digit()
+            || (c == '.' && chars.get(pos + 1).is_some_and(char::is_ascii_digit))
+        {
+            let (number, len) = number_at(&chars[pos..])?;
+            tokens.push(Token::Number(number));
+            pos += len;
+        } else if c.is_alphabetic() || c == '_' {
+            let len = chars[pos..]
+                .iter()
+                .take_while(|c| c.is_alphanumeric() || **c == '_')
+                .count();
+            tokens.push(Token::Ident(chars[pos..pos + len].iter().collect()));
+            pos += len;
+        } else {
+            let token = match c {
+                '*' if chars.get(pos + 1) == Some(&'*') => {
+                    pos += 1;
+                    Token::Op('^')
+                }
+                '+' | '-' | '*' | '/' | '^' | '%' => Token::Op(c),
+                '×' => Token::Op('*'),
+                '÷' => Token::Op('/'),
+                '−' => Token::Op('-'),
+                '(' => Token::LParen,
+                ')' => Token::RParen,
+                ',' => Token::Comma,
+                _ => return fail(format!("unexpected character '{c}'")),
+            };
+            tokens.push(token);
+            pos += 1;
+        }
+    }
+    Ok(tokens)
+}
+
+/// A `YYYY-MM-DD` date at the start of `chars`, with its length.
+fn date_at(chars: &[char]) -> Result<Option<(NaiveDate, usize)>> {
+    const PATTERN: &str = "dddd-dd-dd";
+    let matches = chars.len() >= PATTERN.len()
+        && PATTERN
+            .chars()
+            .zip(chars)
+            .all(|(pattern, c)| match pattern {
+                'd' => c.is_ascii_digit(),
+                _ => *c == pattern,
+            })
+        && !chars
+            .get(PATTERN.len())
+            .is_some_and(|c| c.is_ascii_digit() || *c == '.');
+    if !matches {
+        return Ok(None);
+    }
+    let text: String = chars[..PATTERN.len()].iter().collect();
+    match NaiveDate::parse_from_str(&text, "%Y-%m-%d") {
+        Ok(date) => Ok(Some((date, PATTERN.len()))),
+        Err(_) => fail(format!("{text} isn't a valid date")),
+    }
+}
+
+/// A number at the start of `chars`, with its length. Digits can be grouped
+/// with `_`, and an exponent of up to two digits follows `e`.
+fn number_at(chars: &[char]) -> Result<(Decimal, usize)> {
+    let digits = |from: usize| {
+        chars[from..]
+            .iter()
+            .take_while(|c| c.is_ascii_digit() || **c == '_')
+            .count()
+    };
+    let mut len = digits(0);
+    if chars.get(len) == Some(&'.') {
+        len += 1 + digits(len + 1);
+    }
+    let mantissa_len = len;
+    if matches!(chars.get(len), Some('e' | 'E')) {
+        let sign = usize::from(matches!(chars.get(len + 1), Some('+' | '-')));
+        if chars.get(len + 1 + sign).is_some_and(char::is_ascii_digit) {
+            let exponent_digits = digits(len + 1 + sign);
+            len += 1 + sign + exponent_digits;
+            if exponent_digits > 2 {
+                let text: String = chars[..len].iter().collect();
+                return fail(format!("{text} isn't a number calc can hold"));
+            }
+        }
+    }
+
+    let mut text: String = chars[..len].iter().filter(|c| **c != '_').collect();
+    if text.starts_with('.') {
+        text.insert(0, '0');
+    }
+    let parsed = if len > mantissa_len {
+        Decimal::from_scientific(&text)
+    } else {
+        Decimal::from_str_exact(&text)
+    };
+    match parsed {
+        Ok(number) => Ok((number, len)),
+        Err(_) => fail(format!("{text} isn't a number calc can hold")),
+    }
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    today: NaiveDate,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<&Token> {
+        self.tokens.get(self.pos + offset)
+    }
+
+    fn advance(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn eat(&mut self, token: &Token) -> bool {
+        if self.peek() == Some(token) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn peek_word(&self) -> Option<&str> {
+        match self.peek() {
+            Some(Token::Ident(word)) => Some(word.as_str()),
+            _ => None,
+        }
+    }
+
+    /// The unit at `offset`, unless the name there is a keyword or a call.
+    fn unit_at(&self, offset: usize) -> Option<&'static Unit> {
+        match (self.peek_at(offset), self.peek_at(offset + 1)) {
+            (Some(Token::Ident(name)), next) if next != Some(&Token::LParen) => {
+                if KEYWORDS.contains(&name.as_str()) {
+                    None
+                } else {
+                    units::lookup(name)
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// Whether the token at `offset` can start a value.
+    fn starts_value(&self, offset: usize) -> bool {
+        match self.peek_at(offset) {
+            Some(Token::Number(_) | Token::Date(_) | Token::LParen) => true,
+            Some(Token::Ident(word)) => !KEYWORDS.contains(&word.as_str()),
+            _ => false,
+        }
+    }
+
+    fn expression(&mut self) -> Result<Value> {
+        let value = self.sum()?;
+        match self.peek_word() {
+            Some(word) if is_conversion(word) => {
+                self.pos += 1;
+                let Some(unit) = self.unit_at(0) else {
+                    return fail("expected a unit to convert to");
+                };
+                self.pos += 1;
+                let target = self.attached_units(Quantity::unit(unit))?;
+                convert(value, target)
+            }
+            _ => Ok(value),
+        }
+    }
+
+    fn sum(&mut self) -> Result<Value> {
+        let mut value = self.product()?;
+        loop {
+            let sign = match self.peek() {
+                Some(Token::Op('+')) => 1,
+                Some(Token::Op('-')) => -1,
+                _ => return Ok(value),
+            };
+            self.pos += 1;
+            let right = self.product()?;
+            value = add(value, right, sign)?;
+        }
+    }
+
+    fn product(&mut self) -> Result<Value> {
+        let mut value = self.unary()?;
+        loop {
+            let op = match self.peek() {
+                Some(Token::Op(op @ ('*' | '/' | '%'))) => *op,
+                Some(Token::Ident(word)) if word == "of" => '*',
+                Some(Token::Ident(word)) if word == "mod" => '%',
+                _ => return Ok(value),
+            };
+            self.pos += 1;
+            let right = quantity(self.unary()?, "Arithmetic other than + and -")?;
+            let left = quantity(value, "Arithmetic other than + and -")?;
+            value = Value::Quantity(match op {
+                '*' => multiply(left, right, 1)?,
+                '/' => multiply(left, right, -1)?,
+                _ => remainder(left, right)?,
+            });
+        }
+    }
+
+    fn unary(&mut self) -> Result<Value> {
+        if self.eat(&Token::Op('-')) {
+            let value = quantity(self.unary()?, "Negation")?;
+            return Ok(Value::Quantity(Quantity {
+                value: -value.value,
+                ..value
+            }));
+        }
+        if self.eat(&Token::Op('+')) {
+            return self.unary();
+        }
+        self.power()
+    }
+
+    fn power(&mut self) -> Result<Value> {
+        let base = self.percent()?;
+        if !self.eat(&Token::Op('^')) {
+            return Ok(base);
+        }
+        let exponent = number(self.unary()?, "An exponent")?;
+        Ok(Value::Quantity(power(quantity(base, "Powers")?, exponent)?))
+    }
+
+    /// A value and any `%` signs after it that mean percent rather than
+    /// remainder.
+    fn percent(&mut self) -> Result<Value> {
+        let mut value = self.primary()?;
+        while self.peek() == Some(&Token::Op('%')) && !self.starts_value(1) {
+            self.pos += 1;
+            let quantity = quantity(value, "Percent")?;
+            value = Value::Quantity(Quantity {
+                value: quantity.value / Decimal::ONE_HUNDRED,
+                ..quantity
+            });
+        }
+        Ok(value)
+    }
+
+    fn primary(&mut self) -> Result<Value> {
+        let Some(token) = self.advance() else {
+            return fail("the expression ends too early");
+        };
+        match token {
+            Token::Number(number) => Ok(Value::Quantity(
+                self.attached_units(Quantity::number(number))?,
+            )),
+            Token::Date(date) => Ok(Value::Date(date)),
+            Token::LParen => {
+                let value = self.expression()?;
+                if !self.eat(&Token::RParen) {
+                    return fail("missing ')'");
+                }
+                Ok(value)
+            }
+            Token::Ident(name) => {
+                if self.eat(&Token::LParen) {
+                    let mut args = Vec::new();
+                    if !self.eat(&Token::RParen) {
+                        loop {
+                            args.push(self.expression()?);
+                            if self.eat(&Token::RParen) {
+                                break;
+                            }
+                            if !self.eat(&Token::Comma) {
+                                return fail(format!("missing ')' after the arguments to {name}"));
+                            }
+                        }
+                    }
+                    return call(&name, args);
+                }
+                match name.as_str() {
+                    "pi" => return Ok(Value::Quantity(Quantity::number(Decimal::PI))),
+                    "e" => return Ok(Value::Quantity(Quantity::number(Decimal::E))),
+                    "today" => return Ok(Value::Date(self.today)),
+                    _ => {}
+                }
+                // A bare unit is one of it: `km to mi`.
+                self.pos -= 1;
+                if self.unit_at(0).is_none() {
+                    return fail(format!("unknown name '{name}'"));
+                }
+                Ok(Value::Quantity(
+                    self.attached_units(Quantity::number(Decimal::ONE))?,
+                ))
+            }
+            token => fail(format!("unexpected {token}")),
+        }
+    }
+
+    /// `value` times the units written right after it: `km`, `m/s^2`,
+    /// `kW*h`, `N m`.
+    fn attached_units(&mut self, mut value: Quantity) -> Result<Quantity> {
+        // Temperatures don't scale, so `100 degF` stands alone.
+        let affine = self
+            .unit_at(0)
+            .filter(|unit| unit.is_affine() && value.display.is_empty());
+        if let Some(unit) = affine {
+            self.pos += 1;
+            let display = vec![(unit, 1)];
+            return Ok(Quantity {
+                value: Quantity::from_display(value.value, &display)?,
+                dims: unit.dims,
+                display,
+            });
+        }
+        loop {
+            let (sign, skip) = match self.peek() {
+                Some(Token::Op('/')) if self.unit_at(1).is_some() => (-1, 1),
+                Some(Token::Op('*')) if self.unit_at(1).is_some() => (1, 1),
+                _ if self.unit_at(0).is_some() => (1, 0),
+                _ => return Ok(value),
+            };
+            self.pos += skip;
+            let unit = self.unit_at(0).expect("checked above");
+            self.pos += 1;
+            let mut factor = Quantity::unit(unit);
+            if self.peek() == Some(&Token::Op('^')) {
+                let negative = self.peek_at(1) == Some(&Token::Op('-'));
+                let index = 1 + usize::from(negative);
+                if let Some(Token::Number(exponent)) = self.peek_at(index).cloned() {
+                    self.pos += index + 1;
+                    let exponent = if negative { -exponent } else { exponent };
+                    factor = power(factor, exponent)?;
+                }
+            }
+            value = multiply(value, factor, sign)?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    fn calc(expression: &str) -> String {
+        let today = NaiveDate::from_ymd_opt(2026, 10, 17).unwrap();
+        match evaluate_on(expression, today) {
+            Ok(answer) => answer.to_string(),
+            Err(error) => format!("error: {error}"),
+        }
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        assert_eq!(calc("0.1 + 0.2"), "0.3");
+        assert_eq!(calc("2 + 3 * 4"), "14");
+        assert_eq!(calc("(2 + 3) * 4"), "20");
+        assert_eq!(calc("-2^2"), "-4");
+        assert_eq!(calc("2^3^2"), "512");
+        assert_eq!(calc("2^-1"), "0.5");
+        assert_eq!(calc("7 % 3"), "1");
+        assert_eq!(calc("7 mod 3"), "1");
+        assert_eq!(calc("20% of 150"), "30");
+        assert_eq!(calc("200 * (1 + 5%)"), "210");
+        assert_eq!(calc("1_000_000 / 3"), "333333.33333333333333333333");
+        assert_eq!(calc("1.5e3"), "1500");
+        assert_eq!(calc("sqrt(2) ^ 2"), "2");
+        assert_eq!(calc("round(2 / 3, 2)"), "0.67");
+        assert_eq!(calc("max(3, 9, 4)"), "9");
+        assert_eq!(calc("1 / 0"), "error: division by zero");
+        assert_eq!(calc("2 +"), "error: the expression ends too early");
+        assert_eq!(calc("2 3"), "error: unexpected '3'");
+    }
+
+    #[test]
+    fn test_units() {
+        assert_eq!(calc("5 mi to km"), "8.04672 km");
+        assert_eq!(calc("3 ft + 2 inch to cm"), "96.52 cm");
+        assert_eq!(calc("60 km/h * 90 min"), "90 km");
+        assert_eq!(calc("1 GB / 10 MB/s"), "100 s");
+        assert_eq!(calc("100 km/h to m/s"), "27.77777777777777777778 m/s");
+        assert_eq!(calc("212 degF to degC"), "100 degC");
+        assert_eq!(calc("0 C in F"), "32 degF");
+        assert_eq!(calc("2 m * 3 m"), "6 m^2");
+        assert_eq!(calc("sqrt(9 m^2)"), "3 m");
+        assert_eq!(calc("1 kWh to J"), "3600000 J");
+        assert_eq!(calc("10 kg / 2 kg"), "5");
+        assert_eq!(calc("5 km + 3 kg"), "error: can't combine km with kg");
+        assert_eq!(calc("5 km to s"), "error: can't convert km to s");
+    }
+
+    #[test]
+    fn test_dates() {
+        assert_eq!(calc("2026-10-17 + 30 days"), "2026-11-16 (Monday)");
+        assert_eq!(calc("today - 2 weeks"), "2026-10-03 (Saturday)");
+        assert_eq!(calc("2026-12-25 - today"), "69 day");
+        assert_eq!(
+            calc("2026-12-25 - today to weeks"),
+            "9.85714285714285714286 week"
+        );
+        assert_eq!(calc("2026-02-30"), "error: 2026-02-30 isn't a valid date");
+        assert_eq!(calc("2026-01-31 + 1 month"), "2026-02-28 (Saturday)");
+        assert_eq!(calc("2024-02-29 - 1 year"), "2023-02-28 (Tuesday)");
+        assert_eq!(
+            calc("today + 1.5 days"),
+            "error: dates only move by whole days, months or years"
+        );
+    }
+
+    /// An integer expression, for checking the parser against an evaluator
+    /// that doesn't share its code.
+    #[derive(Debug, Clone)]
+    enum Expr {
+        Number(i64),
+        Neg(Box<Expr>),
+        Binary(Box<Expr>, char, Box<Expr>),
+    }
+
+    impl Expr {
+        fn render(&self) -> String {
+            match self {
+                Self::Number(number) if *number < 0 => format!("({number})"),
+                Self::Number(number) => number.to_string(),
+                Self::Neg(inner) => format!("-({})", inner.render()),
+                Self::Binary(left, op, right) => {
+                    format!("({} {op} {})", left.render(), right.render())
+                }
+            }
+        }
+
+        /// The value, or None if any step leaves the range a decimal holds.
+        fn eval(&self) -> Option<i128> {
+            let max = Decimal::MAX.mantissa();
+            let value = match self {
+                Self::Number(number) => i128::from(*number),
+                Self::Neg(inner) => -inner.eval()?,
+                Self::Binary(left, op, right) => {
+                    let (left, right) = (left.eval()?, right.eval()?);
+                    match op {
+                        '+' => left.checked_add(right)?,
+                        '-' => left.checked_sub(right)?,
+                        _ => left.checked_mul(right)?,
+                    }
+                }
+            };
+            (value.abs() <= max).then_some(value)
+        }
+    }
+
+    fn expr() -> impl Strategy<Value = Expr> {
+        let leaf = (-1000i64..1000).prop_map(Expr::Number);
+        leaf.prop_recursive(4, 32, 2, |inner| {
+            prop_oneof![
+                inner.clone().prop_map(|inner| Expr::Neg(Box::new(inner))),
+                (
+                    inner.clone(),
+                    prop_oneof![Just('+'), Just('-'), Just('*')],
+                    inner
+                )
+                    .prop_map(|(left, op, right)| Expr::Binary(
+                        Box::new(left),
+                        op,
+                        Box::new(right)
+                    )),
+            ]
+        })
+    }
+
+    proptest! {
+        #[test]
+        fn test_integer_expressions_match_reference(tree in expr()) {
+            let result = calc(&tree.render());
+            match tree.eval() {
+                Some(expected) => prop_assert_eq!(result, expected.to_string()),
+                None => prop_assert_eq!(result, "error: the result is out of range"),
+            }
+        }
+
+        #[test]
+        fn test_numbers_round_trip(mantissa in any::<i64>(), scale in 0u32..12) {
+            let number = Decimal::new(mantissa, scale).normalize();
+            prop_assert_eq!(calc(&number.to_string().replace('-', "- ")), number.to_string());
+        }
+
+        #[test]
+        fn test_conversions_round_trip(value in -1_000_000i64..1_000_000, unit in 0usize..4) {
+            let (from, to) = [("km", "mi"), ("lb", "kg"), ("degC", "degF"), ("GiB", "MB")][unit];
+            let back = calc(&format!("({value} {from} to {to}) to {from}"));
+            prop_assert_eq!(back, format!("{value} {from}"));
+        }
+
+        #[test]
+        fn test_garbage_is_an_error_not_a_panic(text in "[0-9a-z+*/^%().,_ -]{0,40}") {
+            let _ = calc(&text);
+        }
+    }
+}
@@ -0,0 +1,211 @@
+//! Units the calculator knows, with their dimensions and size in SI units.
+
+use rust_decimal::Decimal;
+
+/// Exponents of length, mass, time, temperature and data.
+pub type Dims = [i8; 5];
+
+pub const DIMENSIONLESS: Dims = [0; 5];
+
+const LENGTH: Dims = [1, 0, 0, 0, 0];
+const AREA: Dims = [2, 0, 0, 0, 0];
+const VOLUME: Dims = [3, 0, 0, 0, 0];
+const MASS: Dims = [0, 1, 0, 0, 0];
+pub const TIME: Dims = [0, 0, 1, 0, 0];
+const TEMPERATURE: Dims = [0, 0, 0, 1, 0];
+const DATA: Dims = [0, 0, 0, 0, 1];
+const SPEED: Dims = [1, 0, -1, 0, 0];
+const FORCE: Dims = [1, 1, -2, 0, 0];
+const ENERGY: Dims = [2, 1, -2, 0, 0];
+const POWER: Dims = [2, 1, -3, 0, 0];
+const PRESSURE: Dims = [-1, 1, -2, 0, 0];
+
+/// A unit of measure.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Unit {
+    /// How results in this unit are shown.
+    pub symbol: &'static str,
+    /// Other names the unit goes by.
+    pub aliases: &'static [&'static str],
+    /// The unit's size in SI base units, as a decimal or a fraction.
+    factor: &'static str,
+    /// Added before scaling, for temperatures: kelvin = (value + offset) * factor.
+    offset: &'static str,
+    pub dims: Dims,
+}
+
+impl Unit {
+    const fn new(
+        symbol: &'static str,
+        aliases: &'static [&'static str],
+        factor: &'static str,
+        dims: Dims,
+    ) -> Self {
+        Self {
+            symbol,
+            aliases,
+            factor,
+            offset: "0",
+            dims,
+        }
+    }
+
+    const fn with_offset(mut self, offset: &'static str) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    pub fn factor(&self) -> Decimal {
+        match self.factor.split_once('/') {
+            Some((numerator, denominator)) => parse(numerator) / parse(denominator),
+            None => parse(self.factor),
+        }
+    }
+
+    pub fn offset(&self) -> Decimal {
+        parse(self.offset)
+    }
+
+    pub fn is_affine(&self) -> bool {
+        self.offset != "0"
+    }
+}
+
+fn parse(text: &str) -> Decimal {
+    Decimal::from_str_exact(text).expect("unit table holds valid decimals")
+}
+
+pub static UNITS: &[Unit] = &[
+    // Length
+    Unit::new("m", &["meter", "meters", "metre", "metres"], "1", LENGTH),
+    Unit::new(
+        "km",
+        &["kilometer", "kilometers", "kilometre", "kilometres"],
+        "1000",
+        LENGTH,
+    ),
+    Unit::new("cm", &["centimeter", "centimeters"], "0.01", LENGTH),
+    Unit::new("mm", &["millimeter", "millimeters"], "0.001", LENGTH),
+    Unit::new(
+        "um",
+        &["micrometer", "micrometers", "micron", "microns"],
+        "0.000001",
+        LENGTH,
+    ),
+    Unit::new("nm", &["nanometer", "nanometers"], "0.000000001", LENGTH),
+    Unit::new("mi", &["mile", "miles"], "1609.344", LENGTH),
+    Unit::new("yd", &["yard", "yards"], "0.9144", LENGTH),
+    Unit::new("ft", &["foot", "feet"], "0.3048", LENGTH),
+    Unit::new("inch", &["inches"], "0.0254", LENGTH),
+    Unit::new("nmi", &["nautical_mile", "nautical_miles"], "1852", LENGTH),
+    // Area and volume
+    Unit::new("ha", &["hectare", "hectares"], "10000", AREA),
+    Unit::new("acre", &["acres"], "4046.8564224", AREA),
+    Unit::new(
+        "L",
+        &["l", "liter", "liters", "litre", "litres"],
+        "0.001",
+        VOLUME,
+    ),
+    Unit::new(
+        "mL",
+        &["ml", "milliliter", "milliliters"],
+        "0.000001",
+        VOLUME,
+    ),
+    Unit::new("gal", &["gallon", "gallons"], "0.003785411784", VOLUME),
+    // Mass
+    Unit::new("kg", &["kilogram", "kilograms"], "1", MASS),
+    Unit::new("g", &["gram", "grams"], "0.001", MASS),
+    Unit::new("mg", &["milligram", "milligrams"], "0.000001", MASS),
+    Unit::new("t", &["tonne", "tonnes"], "1000", MASS),
+    Unit::new("lb", &["lbs", "pound", "pounds"], "0.45359237", MASS),
+    Unit::new("oz", &["ounce", "ounces"], "0.028349523125", MASS),
+    Unit::new("st", &["stone", "stones"], "6.35029318", MASS),
+    // Time
+    Unit::new("s", &["sec", "secs", "second", "seconds"], "1", TIME),
+    Unit::new("ms", &["millisecond", "milliseconds"], "0.001", TIME),
+    Unit::new("min", &["mins", "minute", "minutes"], "60", TIME),
+    Unit::new("h", &["hr", "hrs", "hour", "hours"], "3600", TIME),
+    Unit::new("day", &["days"], "86400", TIME),
+    Unit::new("week", &["weeks", "wk"], "604800", TIME),
+    Unit::new("month", &["months"], "2629746", TIME),
+    Unit::new("year", &["years", "yr", "yrs"], "31556952", TIME),
+    // Temperature
+    Unit::new("K", &["kelvin"], "1", TEMPERATURE),
+    Unit::new("degC", &["C", "celsius"], "1", TEMPERATURE).with_offset("273.15"),
+    Unit::new("degF", &["F", "fahrenheit"], "5/9", TEMPERATURE).with_offset("459.67"),
+    // Data
+    Unit::new("B", &["byte", "bytes"], "1", DATA),
+    Unit::new("bit", &["bits"], "0.125", DATA),
+    Unit::new("KB", &["kB"], "1000", DATA),
+    Unit::new("MB", &[], "1000000", DATA),
+    Unit::new("GB", &[], "1000000000", DATA),
+    Unit::new("TB", &[], "1000000000000", DATA),
+    Unit::new("KiB", &[], "1024", DATA),
+    Unit::new("MiB", &[], "1048576", DATA),
+    Unit::new("GiB", &[], "1073741824", DATA),
+    Unit::new("TiB", &[], "1099511627776", DATA),
+    // Speed
+    Unit::new("mph", &[], "0.44704", SPEED),
+    Unit::new("kn", &["knot", "knots"], "463/900", SPEED),
+    // Force, energy, power and pressure
+    Unit::new("N", &["newton", "newtons"], "1", FORCE),
+    Unit::new("J", &["joule", "joules"], "1", ENERGY),
+    Unit::new("kJ", &[], "1000", ENERGY),
+    Unit::new("cal", &["calorie", "calories"], "4.184", ENERGY),
+    Unit::new("kcal", &[], "4184", ENERGY),
+    Unit::new("Wh", &[], "3600", ENERGY),
+    Unit::new("kWh", &[], "3600000", ENERGY),
+    Unit::new("W", &["watt", "watts"], "1", POWER),
+    Unit::new("kW", &[], "1000", POWER),
+    Unit::new("Pa", &["pascal", "pascals"], "1", PRESSURE),
+    Unit::new("kPa", &[], "1000", PRESSURE),
+    Unit::new("bar", &[], "100000", PRESSURE),
+    Unit::new("psi", &[], "6894.757293168361", PRESSURE),
+];
+
+/// The SI unit shown for each base dimension when nothing else was asked for.
+const BASE_SYMBOLS: [&str; 5] = ["m", "kg", "s", "K", "B"];
+
+/// The unit called `name`. Symbols are case-sensitive (`MB` isn't `mb`);
+/// longer names aren't.
+pub fn lookup(name: &str) -> Option<&'static Unit> {
+    UNITS
+        .iter()
+        .find(|unit| unit.symbol == name || unit.aliases.contains(&name))
+        .or_else(|| {
+            if name.len() <= 3 {
+                return None;
+            }
+            UNITS.iter().find(|unit| {
+                unit.aliases
+                    .iter()
+                    .any(|alias| alias.len() > 3 && alias.eq_ignore_ascii_case(name))
+            })
+        })
+}
+
+/// The SI base unit for dimension `index`.
+pub fn base(index: usize) -> &'static Unit {
+    lookup(BASE_SYMBOLS[index]).expect("base units are in the table")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_table_is_consistent() {
+        let mut names = std::collections::HashSet::new();
+        for unit in UNITS {
+            assert!(unit.factor() > Decimal::ZERO, "{}", unit.symbol);
+            let _ = unit.offset();
+            for name in std::iter::once(&unit.symbol).chain(unit.aliases) {
+                assert!(names.insert(*name), "{name} is defined twice");
+            }
+        }
+        assert_eq!(lookup("Miles").map(|unit| unit.symbol), Some("mi"));
+        assert_eq!(lookup("mb"), None);
+    }
+}
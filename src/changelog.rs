@@ -0,0 +1,179 @@
+//! Append-only changelog of routing and model behavior events.
+//!
+//! Deprecation notices, snapshot changes behind model names, provider
+//! outages, key failovers and failing keys, circuit breaker trips and config
+//! reloads are appended to `logs/changelog.jsonl` as they happen, one JSON
+//! object per line. `spacebot events --since 24h` reads them back, so a change in how
+//! the bot behaves can be lined up with what changed underneath it.
+
+use crate::events::Event;
+
+use anyhow::Context as _;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::io::{BufRead as _, Write as _};
+use std::path::{Path, PathBuf};
+
+/// One thing that changed.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChangelogEntry {
+    pub at: DateTime<Utc>,
+    /// `model_deprecated`, `model_snapshot_changed`, `provider_outage`,
+    /// `provider_recovered`, `provider_health`, `credential_failover`,
+    /// `key_health`, `circuit_breaker` or `config_reload`.
+    pub kind: String,
+    /// The model, provider or job it concerns.
+    pub subject: String,
+    pub detail: String,
+}
+
+impl ChangelogEntry {
+    pub fn new(kind: &str, subject: impl Into<String>, detail: impl Into<String>) -> Self {
+        Self {
+            at: Utc::now(),
+            kind: kind.to_string(),
+            subject: subject.into(),
+            detail: detail.into(),
+        }
+    }
+}
+
+/// Where an instance keeps its changelog.
+pub fn path(instance_dir: &Path) -> PathBuf {
+    instance_dir.join("logs").join("changelog.jsonl")
+}
+
+/// The changelog entry for a bus event, if it's one worth keeping.
+pub fn from_event(event: Event) -> Option<ChangelogEntry> {
+    let entry = match event {
+        Event::ModelDeprecated { model, detail } => {
+            ChangelogEntry::new("model_deprecated", model, detail)
+        }
+        Event::ModelSnapshotChanged {
+            model,
+            served,
+            previous,
+        } => {
+            let detail = match previous {
+                Some(previous) => format!("served by {served}, was {previous}"),
+                None => format!("served by {served}"),
+            };
+            ChangelogEntry::new("model_snapshot_changed", model, detail)
+        }
+        Event::ProviderOutage {
+            provider,
+            active,
+            detail,
+        } => {
+            let kind = if active {
+                "provider_outage"
+            } else {
+                "provider_recovered"
+            };
+            ChangelogEntry::new(kind, provider, detail)
+        }
+        Event::ProviderHealthChanged {
+            provider,
+            healthy,
+            detail,
+        } => {
+            let status = if healthy { "healthy" } else { "unhealthy" };
+            ChangelogEntry::new("provider_health", provider, format!("{status}: {detail}"))
+        }
+        Event::CredentialFailover { provider } => ChangelogEntry::new(
+            "credential_failover",
+            provider,
+            "primary key rejected, using the secondary",
+        ),
+        Event::KeyHealthChanged {
+            provider,
+            slot,
+            status,
+            detail,
+        } => ChangelogEntry::new(
+            "key_health",
+            provider,
+            format!("{} key {}: {detail}", slot.as_str(), status.as_str()),
+        ),
+        Event::CircuitBreakerTripped { scope, detail } => {
+            ChangelogEntry::new("circuit_breaker", scope, detail)
+        }
+        _ => return None,
+    };
+    Some(entry)
+}
+
+/// Append one entry, for writers outside the event bus (the config
+/// watcher).
+pub fn append(path: &Path, entry: &ChangelogEntry) -> std::io::Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let mut line = serde_json::to_string(entry)?;
+    line.push('\n');
+    std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?
+        .write_all(line.as_bytes())
+}
+
+/// Entries at or after `since`, oldest first. A missing changelog has no
+/// entries, and lines that don't parse are skipped.
+pub fn read_since(path: &Path, since: DateTime<Utc>) -> anyhow::Result<Vec<ChangelogEntry>> {
+    let file = match std::fs::File::open(path) {
+        Ok(file) => file,
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(error) => {
+            return Err(error).with_context(|| format!("failed to open {}", path.display()));
+        }
+    };
+    let mut entries = Vec::new();
+    for line in std::io::BufReader::new(file).lines() {
+        let line = line.with_context(|| format!("failed to read {}", path.display()))?;
+        match serde_json::from_str::<ChangelogEntry>(&line) {
+            Ok(entry) if entry.at >= since => entries.push(entry),
+            _ => {}
+        }
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_since_filters_by_time() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = path(dir.path());
+        let mut old = ChangelogEntry::new("config_reload", "config", "config");
+        old.at = Utc::now() - chrono::TimeDelta::days(2);
+        append(&path, &old).unwrap();
+        let event = Event::ModelSnapshotChanged {
+            model: "openai/gpt-4o".into(),
+            served: "gpt-4o-2024-11-20".into(),
+            previous: Some("gpt-4o-2024-08-06".into()),
+        };
+        append(&path, &from_event(event).unwrap()).unwrap();
+        std::fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap()
+            .write_all(b"not json\n")
+            .unwrap();
+
+        let entries = read_since(&path, Utc::now() - chrono::TimeDelta::hours(24)).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].kind, "model_snapshot_changed");
+        assert_eq!(
+            entries[0].detail,
+            "served by gpt-4o-2024-11-20, was gpt-4o-2024-08-06"
+        );
+        assert!(
+            read_since(&dir.path().join("missing.jsonl"), old.at)
+                .unwrap()
+                .is_empty()
+        );
+    }
+}
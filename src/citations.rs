@@ -0,0 +1,221 @@
+//! Citations: the sources an assistant reply draws on.
+//!
+//! A reply's citations come from two places. Providers with built-in search
+//! (Anthropic web search, OpenAI and OpenRouter URL annotations, Perplexity)
+//! attach them to the completion, and they're picked up automatically. The
+//! channel also passes the web results and memories it relied on to the
+//! `reply` tool, and with [provenance](crate::agent::provenance) on, the
+//! tools its branches and workers ran are credited too. Citations are
+//! stored in the message's metadata, rendered
+//! per platform by [`render_citations`](crate::messaging::format::render_citations),
+//! and included in exports.
+
+use serde::{Deserialize, Serialize};
+
+/// Where a citation came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CitationOrigin {
+    /// Attached to the completion by the model provider.
+    Provider,
+    /// A web page, from a `web_search` result.
+    WebSearch,
+    /// A memory, from a `memory_recall` result.
+    Memory,
+    /// A tool a branch or worker ran, credited in a "via" footer.
+    Tool,
+}
+
+/// One source behind an assistant reply.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Citation {
+    pub title: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    /// The passage the reply relies on, when the source says.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub snippet: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memory_id: Option<String>,
+    pub origin: CitationOrigin,
+}
+
+impl Citation {
+    /// A web page. Without a title, the page's host stands in.
+    pub fn web(title: Option<&str>, url: &str, origin: CitationOrigin) -> Self {
+        let title = title
+            .map(str::trim)
+            .filter(|title| !title.is_empty())
+            .map(ToOwned::to_owned)
+            .unwrap_or_else(|| url_host(url).to_string());
+        Self {
+            title,
+            url: Some(url.to_string()),
+            snippet: None,
+            memory_id: None,
+            origin,
+        }
+    }
+
+    /// A memory, titled with the start of its content.
+    pub fn memory(memory_id: &str, title: &str) -> Self {
+        Self {
+            title: title.trim().to_string(),
+            url: None,
+            snippet: None,
+            memory_id: Some(memory_id.to_string()),
+            origin: CitationOrigin::Memory,
+        }
+    }
+
+    /// A tool result, labeled with the tool and what it read.
+    pub fn tool(label: String) -> Self {
+        Self {
+            title: label,
+            url: None,
+            snippet: None,
+            memory_id: None,
+            origin: CitationOrigin::Tool,
+        }
+    }
+
+    /// What makes two citations the same source.
+    fn key(&self) -> &str {
+        self.url
+            .as_deref()
+            .or(self.memory_id.as_deref())
+            .unwrap_or(&self.title)
+    }
+}
+
+pub(crate) fn url_host(url: &str) -> &str {
+    let rest = url.split_once("://").map_or(url, |(_, rest)| rest);
+    rest.split(['/', '?', '#']).next().unwrap_or(rest)
+}
+
+/// Drop repeated sources, keeping the first of each.
+pub fn dedup(citations: Vec<Citation>) -> Vec<Citation> {
+    let mut seen = std::collections::HashSet::new();
+    citations
+        .into_iter()
+        .filter(|citation| seen.insert(citation.key().to_string()))
+        .collect()
+}
+
+/// Citations a provider attached to a raw completion response.
+///
+/// Understands Anthropic text block `citations`, OpenAI-style message
+/// `annotations` (also used by OpenRouter), and Perplexity's top-level
+/// `search_results` and `citations`.
+pub fn from_provider_response(body: &serde_json::Value) -> Vec<Citation> {
+    let mut citations = Vec::new();
+
+    // Anthropic: web search results and document passages per text block
+    for block in body["content"].as_array().into_iter().flatten() {
+        for citation in block["citations"].as_array().into_iter().flatten() {
+            let snippet = citation["cited_text"].as_str().map(ToOwned::to_owned);
+            let parsed = match citation["url"].as_str() {
+                Some(url) => {
+                    Citation::web(citation["title"].as_str(), url, CitationOrigin::Provider)
+                }
+                None => match citation["document_title"].as_str() {
+                    Some(title) => Citation {
+                        title: title.to_string(),
+                        url: None,
+                        snippet: None,
+                        memory_id: None,
+                        origin: CitationOrigin::Provider,
+                    },
+                    None => continue,
+                },
+            };
+            citations.push(Citation { snippet, ..parsed });
+        }
+    }
+
+    // OpenAI and OpenRouter: URL annotations on the message
+    let message = &body["choices"][0]["message"];
+    for annotation in message["annotations"].as_array().into_iter().flatten() {
+        if annotation["type"].as_str() != Some("url_citation") {
+            continue;
+        }
+        let citation = &annotation["url_citation"];
+        if let Some(url) = citation["url"].as_str() {
+            citations.push(Citation::web(
+                citation["title"].as_str(),
+                url,
+                CitationOrigin::Provider,
+            ));
+        }
+    }
+
+    // Perplexity: titled search results, or a bare list of URLs
+    for result in body["search_results"].as_array().into_iter().flatten() {
+        if let Some(url) = result["url"].as_str() {
+            let mut citation =
+                Citation::web(result["title"].as_str(), url, CitationOrigin::Provider);
+            citation.snippet = result["snippet"].as_str().map(ToOwned::to_owned);
+            citations.push(citation);
+        }
+    }
+    for url in body["citations"].as_array().into_iter().flatten() {
+        if let Some(url) = url.as_str() {
+            citations.push(Citation::web(None, url, CitationOrigin::Provider));
+        }
+    }
+
+    dedup(citations)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_anthropic_citations() {
+        let body = serde_json::json!({
+            "content": [
+                {"type": "text", "text": "Intro. "},
+                {
+                    "type": "text",
+                    "text": "Rust 1.85 shipped edition 2024.",
+                    "citations": [{
+                        "type": "web_search_result_location",
+                        "url": "https://blog.rust-lang.org/2025/02/20/Rust-1.85.0.html",
+                        "title": "Announcing Rust 1.85.0",
+                        "cited_text": "Rust 1.85.0 stabilizes the 2024 edition"
+                    }]
+                }
+            ]
+        });
+
+        let citations = from_provider_response(&body);
+        assert_eq!(citations.len(), 1);
+        assert_eq!(citations[0].title, "Announcing Rust 1.85.0");
+        assert_eq!(
+            citations[0].snippet.as_deref(),
+            Some("Rust 1.85.0 stabilizes the 2024 edition")
+        );
+        assert_eq!(citations[0].origin, CitationOrigin::Provider);
+    }
+
+    #[test]
+    fn test_openai_and_perplexity_citations() {
+        let body = serde_json::json!({
+            "choices": [{"message": {
+                "content": "See the docs.",
+                "annotations": [
+                    {"type": "url_citation", "url_citation": {"url": "https://docs.rs/tokio", "title": "tokio"}},
+                    {"type": "url_citation", "url_citation": {"url": "https://docs.rs/tokio"}}
+                ]
+            }}],
+            "citations": ["https://example.com/post?id=1"]
+        });
+
+        let citations = from_provider_response(&body);
+        assert_eq!(citations.len(), 2);
+        assert_eq!(citations[0].title, "tokio");
+        // Untitled sources are named after their host
+        assert_eq!(citations[1].title, "example.com");
+    }
+}
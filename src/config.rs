@@ -1,14 +1,35 @@
 //! Configuration loading and validation.
 
+pub mod rollout;
+
+use crate::activation::ActivationPolicy;
 use crate::error::{ConfigError, Result};
-use crate::llm::routing::RoutingConfig;
+use crate::flags::{FlagDef, FlagSet};
+use crate::llm::routing::{
+    KNOWN_ANTHROPIC_BETAS, RaceConfig, RoutingConfig, ShadowConfig, VllmOptions,
+};
+use crate::messaging::postprocess::{Disclosure, PostProcessor};
 use anyhow::Context as _;
 use arc_swap::ArcSwap;
 use serde::Deserialize;
+use spacebot_core::llm::credentials::aws::AwsSecretsConfig;
+use spacebot_core::llm::credentials::vault::{VaultAuth, VaultConfig};
+use spacebot_core::llm::empty::DEFAULT_NUDGE;
+use spacebot_core::llm::faults::FaultRule;
+use spacebot_core::llm::generation::{BUILTIN_PROFILES, GenerationProfile};
+use spacebot_core::llm::health::HealthCheckConfig;
+use spacebot_core::llm::key_health::KeyCheckConfig;
+use spacebot_core::llm::outage::OutageConfig;
+use spacebot_core::llm::output_cap::{OutputCap, Truncation};
+use spacebot_core::llm::pricing::ModelPricing;
+use spacebot_core::llm::regions::RegionalEndpointsConfig;
+use spacebot_core::llm::signing::HmacSigningConfig;
 use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
+pub use spacebot_core::config::LlmConfig;
+
 /// Top-level Spacebot configuration.
 #[derive(Debug, Clone)]
 pub struct Config {
@@ -26,6 +47,14 @@ pub struct Config {
     pub bindings: Vec<Binding>,
     /// HTTP API server configuration.
     pub api: ApiConfig,
+    /// Opt-in crash reporting.
+    pub telemetry: TelemetryConfig,
+    /// Classification of messages no binding matches.
+    pub intake: IntakeConfig,
+    /// Routing of sensitive topics to a dedicated agent, or declining them.
+    pub topics: TopicsConfig,
+    /// Operator-written hook scripts.
+    pub scripting: ScriptingConfig,
 }
 
 /// HTTP API server configuration.
@@ -49,38 +78,192 @@ impl Default for ApiConfig {
     }
 }
 
-/// LLM provider credentials (instance-level).
+/// Crash reporting to an endpoint the operator runs, and usage snapshots.
+///
+/// Crash reporting is off unless `crash_reporting` is set and `dsn` points at
+/// a Sentry-compatible server (Sentry, GlitchTip, ...). Nothing is sent
+/// anywhere else. Usage snapshots stay in the default agent's database (see
+/// [`crate::usage_snapshots`]).
+#[derive(Debug, Clone)]
+pub struct TelemetryConfig {
+    /// Whether panics are reported.
+    pub crash_reporting: bool,
+    /// Sentry DSN of the operator's endpoint. Supports "env:VAR_NAME" references.
+    pub dsn: Option<String>,
+    /// Environment name attached to reports, e.g. "production".
+    pub environment: Option<String>,
+    /// Seconds between snapshots of the LLM request counters. 0 turns them
+    /// off.
+    pub snapshot_interval_secs: u64,
+}
+
+impl Default for TelemetryConfig {
+    fn default() -> Self {
+        Self {
+            crash_reporting: false,
+            dsn: None,
+            environment: None,
+            snapshot_interval_secs: 300,
+        }
+    }
+}
+
+/// Intake classification: picks an agent for messages no binding matches.
+///
+/// Off unless `enabled` is set, in which case the first message of an unbound
+/// conversation is scored against each agent's `description` and the whole
+/// conversation goes to the best match. Low-confidence and failed
+/// classifications go to `default_agent`.
+#[derive(Debug, Clone)]
+pub struct IntakeConfig {
+    pub enabled: bool,
+    /// How messages are scored against agent descriptions.
+    pub method: IntakeMethod,
+    /// Model for the `llm` method. None uses the default agent's worker model.
+    pub model: Option<String>,
+    /// Score below which a classification isn't trusted.
+    pub min_confidence: f32,
+    /// Agent for messages that can't be classified. None uses the instance's
+    /// default agent.
+    pub default_agent: Option<String>,
+}
+
+impl Default for IntakeConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            method: IntakeMethod::default(),
+            model: None,
+            min_confidence: IntakeMethod::default().default_min_confidence(),
+            default_agent: None,
+        }
+    }
+}
+
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, serde::Serialize, schemars::JsonSchema,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum IntakeMethod {
+    /// Cosine similarity between the message and each description, using
+    /// the local embedding model.
+    #[default]
+    Embedding,
+    /// One model call that picks an agent and says how sure it is.
+    Llm,
+}
+
+impl IntakeMethod {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Embedding => "embedding",
+            Self::Llm => "llm",
+        }
+    }
+
+    /// Similarity scores run lower than a model's stated confidence, so the
+    /// two methods get different thresholds unless one is configured.
+    pub fn default_min_confidence(self) -> f32 {
+        match self {
+            Self::Embedding => 0.35,
+            Self::Llm => 0.6,
+        }
+    }
+}
+
+/// Sensitive-topic policy: routes or declines conversations on topics the
+/// operator wants handled with more care (medical, legal, self-harm, ...).
+///
+/// Off unless `enabled` is set. Every inbound message is matched against each
+/// category's `patterns`, and with the `llm` method a message no pattern
+/// matches is also classified against the categories' descriptions. A
+/// conversation that hits a category stays in it: its messages go to the
+/// category's agent, or are declined.
+#[derive(Debug, Clone, Default)]
+pub struct TopicsConfig {
+    pub enabled: bool,
+    /// How messages are classified.
+    pub method: TopicMethod,
+    /// Model for the `llm` method. None uses the default agent's worker model.
+    pub model: Option<String>,
+    /// Checked in order; the first category a pattern matches wins.
+    pub categories: Vec<TopicCategory>,
+}
+
+/// How the topic policy classifies messages.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, serde::Serialize, schemars::JsonSchema,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum TopicMethod {
+    /// Only the categories' regex patterns. No model calls.
+    #[default]
+    Regex,
+    /// Patterns first, then one model call for messages none match.
+    Llm,
+}
+
+/// One sensitive topic and what to do with conversations about it.
+#[derive(Debug, Clone, Deserialize, serde::Serialize, schemars::JsonSchema)]
+pub struct TopicCategory {
+    /// Short name, e.g. "medical". Used in logs and by the classifier.
+    pub name: String,
+    /// What the category covers, for the `llm` method. Categories without
+    /// one are matched by their patterns only.
+    #[serde(default)]
+    pub description: Option<String>,
+    /// Case-insensitive regexes over the message text.
+    #[serde(default)]
+    pub patterns: Vec<String>,
+    pub action: TopicAction,
+    /// Agent that handles the category, for `action = "route"`.
+    #[serde(default)]
+    pub agent: Option<String>,
+    /// Sent once to the conversation before the routed agent answers.
+    #[serde(default)]
+    pub disclaimer: Option<String>,
+    /// Reply to declined messages. None uses a generic refusal.
+    #[serde(default)]
+    pub decline_message: Option<String>,
+}
+
+/// What happens to a conversation in a sensitive category.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Deserialize, serde::Serialize, schemars::JsonSchema,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum TopicAction {
+    /// Hand the conversation to the category's agent.
+    Route,
+    /// Answer every message with the decline message, without calling a model.
+    Decline,
+}
+
+/// Rhai hook scripts for customizations too small for a plugin: rewriting or
+/// dropping inbound messages, picking the agent for a message, and custom `!`
+/// commands (see [`crate::messaging::scripts`]).
+///
+/// Off unless `enabled` is set. Paths are relative to the instance directory.
 #[derive(Debug, Clone)]
-pub struct LlmConfig {
-    pub anthropic_key: Option<String>,
-    pub openai_key: Option<String>,
-    pub openrouter_key: Option<String>,
-    pub ollama_key: Option<String>,
-    pub zhipu_key: Option<String>,
-    pub groq_key: Option<String>,
-    pub together_key: Option<String>,
-    pub fireworks_key: Option<String>,
-    pub deepseek_key: Option<String>,
-    pub xai_key: Option<String>,
-    pub mistral_key: Option<String>,
-    pub opencode_zen_key: Option<String>,
-}
-
-impl LlmConfig {
-    /// Check if any provider key is configured.
-    pub fn has_any_key(&self) -> bool {
-        self.anthropic_key.is_some()
-            || self.openai_key.is_some()
-            || self.openrouter_key.is_some()
-            || self.ollama_key.is_some()
-            || self.zhipu_key.is_some()
-            || self.groq_key.is_some()
-            || self.together_key.is_some()
-            || self.fireworks_key.is_some()
-            || self.deepseek_key.is_some()
-            || self.xai_key.is_some()
-            || self.mistral_key.is_some()
-            || self.opencode_zen_key.is_some()
+pub struct ScriptingConfig {
+    pub enabled: bool,
+    /// Hook scripts, run in order. Each may define `on_message(msg)` and
+    /// `route(msg)`.
+    pub hooks: Vec<PathBuf>,
+    /// Command name to the script whose `run(args, msg)` answers `!name`.
+    pub commands: HashMap<String, PathBuf>,
+    /// Operations one hook call may run before it's stopped.
+    pub max_operations: u64,
+}
+
+impl Default for ScriptingConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            hooks: Vec::new(),
+            commands: HashMap::new(),
+            max_operations: 100_000,
+        }
     }
 }
 
@@ -99,8 +282,51 @@ pub struct DefaultsConfig {
     pub ingestion: IngestionConfig,
     pub cortex: CortexConfig,
     pub browser: BrowserConfig,
+    pub tool_filter: ToolFilterConfig,
+    pub compression: CompressionConfig,
+    pub preview: PreviewConfig,
+    pub tool_guard: ToolGuardConfig,
+    pub cost_gate: CostGateConfig,
+    pub computer_use: ComputerUseConfig,
+    pub ocr: OcrConfig,
+    pub image_generation: ImageGenerationConfig,
+    pub home_assistant: HomeAssistantConfig,
+    pub issues: IssuesConfig,
+    pub sql: SqlConfig,
+    pub http: HttpConfig,
+    pub kubernetes: KubernetesConfig,
+    pub observability: ObservabilityConfig,
+    pub incidents: IncidentsConfig,
+    pub storage: StorageConfig,
+    pub rate_limit: RateLimitConfig,
+    pub dedup: DedupConfig,
+    /// Experiment flags every agent has unless it defines one by the same name.
+    pub flags: Vec<FlagDef>,
+    pub loop_detection: LoopDetectionConfig,
+    pub usage_anomalies: UsageAnomalyConfig,
+    pub budget: BudgetConfig,
+    pub fact_check: FactCheckConfig,
+    pub provenance: ProvenanceConfig,
+    pub escalation: EscalationConfig,
+    pub generation: GenerationConfig,
+    pub output_caps: OutputCapsConfig,
+    pub empty_response: EmptyResponseConfig,
+    pub self_report: SelfReportConfig,
+    pub latency_budget: LatencyBudgetConfig,
+    pub usage_reports: UsageReportConfig,
+    pub retention: RetentionConfig,
+    pub language: LanguageConfig,
+    pub network: NetworkConfig,
+    pub threads: ThreadConfig,
+    pub commands: CommandsConfig,
+    /// Users allowed to run admin chat commands such as `/model set`, by
+    /// sender ID or `platform:sender_id`.
+    pub admin_users: Vec<String>,
     /// Brave Search API key for web search tool. Supports "env:VAR_NAME" references.
     pub brave_search_key: Option<String>,
+    /// Sampling seed sent with every completion to providers that take one,
+    /// for eval and test runs. None leaves sampling random.
+    pub seed: Option<u64>,
     pub history_backfill_count: usize,
     pub cron: Vec<CronDef>,
     pub opencode: OpenCodeConfig,
@@ -114,6 +340,28 @@ pub struct CompactionConfig {
     pub background_threshold: f32,
     pub aggressive_threshold: f32,
     pub emergency_threshold: f32,
+    /// What leaves history when the background or aggressive threshold is
+    /// crossed. Emergency truncation always drops the oldest messages.
+    pub eviction: EvictionPolicy,
+}
+
+/// How history is cut down as it nears the context window. Messages pinned
+/// with `/pin` survive every policy.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, serde::Serialize, schemars::JsonSchema,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum EvictionPolicy {
+    /// A compaction worker summarizes the oldest messages into one.
+    #[default]
+    SummarizeOldest,
+    /// The oldest messages are dropped, without an LLM call.
+    DropOldest,
+    /// Old tool results are replaced with a placeholder first. If that
+    /// isn't enough, the oldest messages are summarized.
+    DropToolResultsFirst,
+    /// Everything but pinned and recent messages is dropped.
+    KeepPinned,
 }
 
 /// Auto-branching memory persistence configuration.
@@ -144,6 +392,7 @@ impl Default for CompactionConfig {
             background_threshold: 0.80,
             aggressive_threshold: 0.85,
             emergency_threshold: 0.95,
+            eviction: EvictionPolicy::default(),
         }
     }
 }
@@ -232,663 +481,2911 @@ impl Default for BrowserConfig {
     }
 }
 
-/// OpenCode subprocess worker configuration.
+/// Per-turn tool trimming configuration.
+///
+/// When enabled, each completion request only carries the `top_k` tools most
+/// relevant to the latest user message, ranked by embedding similarity, plus
+/// the tools in `always_include`. Saves prompt tokens for agents with large
+/// tool sets.
 #[derive(Debug, Clone)]
-pub struct OpenCodeConfig {
-    /// Whether OpenCode workers are available.
+pub struct ToolFilterConfig {
+    /// Whether tool trimming is enabled.
     pub enabled: bool,
-    /// Path to the OpenCode binary. Supports "env:VAR_NAME" references.
-    /// Falls back to "opencode" on PATH.
-    pub path: String,
-    /// Maximum concurrent OpenCode server processes.
-    pub max_servers: usize,
-    /// Timeout in seconds waiting for a server to become healthy.
-    pub server_startup_timeout_secs: u64,
-    /// Maximum restart attempts before giving up on a server.
-    pub max_restart_retries: u32,
-    /// Permission settings passed to OpenCode's config.
-    pub permissions: crate::opencode::OpenCodePermissions,
+    /// Number of ranked tools to send each turn.
+    pub top_k: usize,
+    /// Tools that are always sent, regardless of rank.
+    pub always_include: Vec<String>,
 }
 
-impl Default for OpenCodeConfig {
+impl Default for ToolFilterConfig {
     fn default() -> Self {
         Self {
             enabled: false,
-            path: "opencode".to_string(),
-            max_servers: 5,
-            server_startup_timeout_secs: 30,
-            max_restart_retries: 5,
-            permissions: crate::opencode::OpenCodePermissions::default(),
+            top_k: 8,
+            always_include: Vec::new(),
         }
     }
 }
 
-/// Cortex configuration.
+/// Automatic retrieval before each channel turn.
+///
+/// The memories most similar to the incoming message are added to the
+/// channel prompt, so the model doesn't have to search for them itself.
+#[derive(Debug, Clone)]
+pub struct RetrievalConfig {
+    /// Where to retrieve from: `"memory"` for all memories, or a memory
+    /// source (such as `"docs"`) to only use memories saved with it.
+    pub store: String,
+    /// Most passages added per turn.
+    pub top_k: usize,
+    /// Minimum similarity (one minus the vector distance) for a passage to
+    /// be added.
+    pub min_score: f32,
+}
+
+impl RetrievalConfig {
+    /// The memory source to filter on, if the store isn't all of memory.
+    pub fn source(&self) -> Option<&str> {
+        (self.store != "memory").then_some(self.store.as_str())
+    }
+}
+
+/// Prompt compression configuration.
+///
+/// When enabled, tool results and user messages longer than `min_chars` are
+/// heuristically trimmed toward `ratio` of their original size before each
+/// LLM request.
 #[derive(Debug, Clone, Copy)]
-pub struct CortexConfig {
-    pub tick_interval_secs: u64,
-    pub worker_timeout_secs: u64,
-    pub branch_timeout_secs: u64,
-    pub circuit_breaker_threshold: u8,
-    /// Interval in seconds between memory bulletin refreshes.
-    pub bulletin_interval_secs: u64,
-    /// Target word count for the memory bulletin.
-    pub bulletin_max_words: usize,
-    /// Max LLM turns for bulletin generation.
-    pub bulletin_max_turns: usize,
-    /// Interval in seconds between association passes.
-    pub association_interval_secs: u64,
-    /// Minimum cosine similarity to create a RelatedTo edge.
-    pub association_similarity_threshold: f32,
-    /// Minimum cosine similarity to create an Updates edge (near-duplicate).
-    pub association_updates_threshold: f32,
-    /// Max associations to create per pass (rate limit).
-    pub association_max_per_pass: usize,
+pub struct CompressionConfig {
+    /// Whether prompt compression is enabled.
+    pub enabled: bool,
+    /// Target size as a fraction of the original (0.1–1.0).
+    pub ratio: f32,
+    /// Texts shorter than this many characters are sent as-is.
+    pub min_chars: usize,
 }
 
-impl Default for CortexConfig {
+impl Default for CompressionConfig {
     fn default() -> Self {
         Self {
-            tick_interval_secs: 30,
-            worker_timeout_secs: 300,
-            branch_timeout_secs: 60,
-            circuit_breaker_threshold: 3,
-            bulletin_interval_secs: 3600,
-            bulletin_max_words: 1500,
-            bulletin_max_turns: 15,
-            association_interval_secs: 300,
-            association_similarity_threshold: 0.85,
-            association_updates_threshold: 0.95,
-            association_max_per_pass: 100,
+            enabled: false,
+            ratio: 0.5,
+            min_chars: 2000,
         }
     }
 }
 
-/// Per-agent configuration (raw, before resolution with defaults).
+/// Draft/preview mode for side-effecting tools.
+///
+/// When enabled, a worker calling one of `tools` posts a rendered preview of
+/// the action to its channel and waits for the user to `/confirm` or
+/// `/reject` it before the tool runs.
 #[derive(Debug, Clone)]
-pub struct AgentConfig {
-    pub id: String,
-    pub default: bool,
-    /// Custom workspace path. If None, resolved to instance_dir/agents/{id}/workspace.
-    pub workspace: Option<PathBuf>,
-    /// Per-agent routing overrides. None inherits from defaults.
-    pub routing: Option<RoutingConfig>,
-    pub max_concurrent_branches: Option<usize>,
-    pub max_concurrent_workers: Option<usize>,
-    pub max_turns: Option<usize>,
-    pub branch_max_turns: Option<usize>,
-    pub context_window: Option<usize>,
-    pub compaction: Option<CompactionConfig>,
-    pub memory_persistence: Option<MemoryPersistenceConfig>,
-    pub coalesce: Option<CoalesceConfig>,
-    pub ingestion: Option<IngestionConfig>,
-    pub cortex: Option<CortexConfig>,
-    pub browser: Option<BrowserConfig>,
-    /// Per-agent Brave Search API key override. None inherits from defaults.
-    pub brave_search_key: Option<String>,
-    /// Cron job definitions for this agent.
-    pub cron: Vec<CronDef>,
+pub struct PreviewConfig {
+    /// Whether preview mode is enabled.
+    pub enabled: bool,
+    /// Tools that need confirmation before running.
+    pub tools: Vec<String>,
+    /// How long to wait for a decision before rejecting the action.
+    pub timeout_secs: u64,
 }
 
-/// A cron job definition from config.
-#[derive(Debug, Clone)]
-pub struct CronDef {
-    pub id: String,
-    pub prompt: String,
-    pub interval_secs: u64,
-    /// Delivery target in "adapter:target" format (e.g. "discord:123456789").
-    pub delivery_target: String,
-    /// Optional active hours window (start_hour, end_hour) in 24h format.
-    pub active_hours: Option<(u8, u8)>,
-    pub enabled: bool,
+impl Default for PreviewConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            tools: ["shell", "exec", "file", "send_file"]
+                .into_iter()
+                .map(String::from)
+                .collect(),
+            timeout_secs: 600,
+        }
+    }
 }
 
-/// Fully resolved agent config (merged with defaults, paths resolved).
+/// Guard model checks on high-risk tool calls.
+///
+/// When enabled, a worker calling one of `tools` in a way that changes
+/// something first has a cheap model score how consistent the call is with
+/// the task the worker was given. Calls scoring below `block_below` are
+/// refused, and calls scoring below `confirm_below` wait for the user to
+/// `/confirm` or `/reject` them as in preview mode.
 #[derive(Debug, Clone)]
-pub struct ResolvedAgentConfig {
-    pub id: String,
-    pub workspace: PathBuf,
-    pub data_dir: PathBuf,
-    pub archives_dir: PathBuf,
-    pub routing: RoutingConfig,
-    pub max_concurrent_branches: usize,
-    pub max_concurrent_workers: usize,
-    pub max_turns: usize,
-    pub branch_max_turns: usize,
-    pub context_window: usize,
-    pub compaction: CompactionConfig,
-    pub memory_persistence: MemoryPersistenceConfig,
-    pub coalesce: CoalesceConfig,
-    pub ingestion: IngestionConfig,
-    pub cortex: CortexConfig,
-    pub browser: BrowserConfig,
-    pub brave_search_key: Option<String>,
-    /// Number of messages to fetch from the platform when a new channel is created.
-    pub history_backfill_count: usize,
-    pub cron: Vec<CronDef>,
+pub struct ToolGuardConfig {
+    /// Whether the guard is enabled.
+    pub enabled: bool,
+    /// High-risk tools whose calls are checked.
+    pub tools: Vec<String>,
+    /// Model scoring the calls: a model name or a routing tier.
+    pub model: String,
+    /// Calls scoring below this are refused.
+    pub block_below: f32,
+    /// Calls scoring below this need the user's confirmation.
+    pub confirm_below: f32,
+    /// How long to wait for the guard model. A call the guard couldn't score
+    /// needs confirmation.
+    pub timeout_secs: u64,
 }
 
-impl Default for DefaultsConfig {
+impl Default for ToolGuardConfig {
     fn default() -> Self {
         Self {
-            routing: RoutingConfig::default(),
-            max_concurrent_branches: 5,
-            max_concurrent_workers: 5,
-            max_turns: 5,
-            branch_max_turns: 50,
-            context_window: 128_000,
-            compaction: CompactionConfig::default(),
-            memory_persistence: MemoryPersistenceConfig::default(),
-            coalesce: CoalesceConfig::default(),
-            ingestion: IngestionConfig::default(),
-            cortex: CortexConfig::default(),
-            browser: BrowserConfig::default(),
-            brave_search_key: None,
-            history_backfill_count: 50,
-            cron: Vec::new(),
-            opencode: OpenCodeConfig::default(),
-            worker_log_mode: crate::settings::WorkerLogMode::default(),
+            enabled: false,
+            tools: ["shell", "exec", "file", "send_file", "browser"]
+                .into_iter()
+                .map(String::from)
+                .collect(),
+            model: "compactor".into(),
+            block_below: 0.3,
+            confirm_below: 0.7,
+            timeout_secs: 20,
         }
     }
 }
 
-impl AgentConfig {
-    /// Resolve this agent config against instance defaults and base paths.
-    pub fn resolve(&self, instance_dir: &Path, defaults: &DefaultsConfig) -> ResolvedAgentConfig {
-        let agent_root = instance_dir.join("agents").join(&self.id);
+/// Confirmation of expensive channel turns.
+///
+/// When enabled, a channel estimates each turn's cost before running it, from
+/// the prompt size, the channel model's price and the completions a turn is
+/// expected to make. A turn estimated above `threshold_usd` is posted for the
+/// user to `/confirm` or `/reject` first. Models without a configured price
+/// are never gated.
+#[derive(Debug, Clone, Copy)]
+pub struct CostGateConfig {
+    /// Whether expensive turns need confirmation.
+    pub enabled: bool,
+    /// Estimated cost in USD above which a turn needs confirmation.
+    pub threshold_usd: f64,
+    /// Completions a turn is expected to make: one, plus one per round of
+    /// tool calls. Each resends the whole prompt.
+    pub expected_completions: u32,
+    /// Output tokens expected from each completion.
+    pub expected_output_tokens: u64,
+    /// How long to wait for a decision before dropping the turn.
+    pub timeout_secs: u64,
+}
 
-        ResolvedAgentConfig {
-            id: self.id.clone(),
-            workspace: self
-                .workspace
-                .clone()
-                .unwrap_or_else(|| agent_root.join("workspace")),
-            data_dir: agent_root.join("data"),
-            archives_dir: agent_root.join("archives"),
-            routing: self
-                .routing
-                .clone()
-                .unwrap_or_else(|| defaults.routing.clone()),
-            max_concurrent_branches: self
-                .max_concurrent_branches
-                .unwrap_or(defaults.max_concurrent_branches),
-            max_concurrent_workers: self
-                .max_concurrent_workers
-                .unwrap_or(defaults.max_concurrent_workers),
-            max_turns: self.max_turns.unwrap_or(defaults.max_turns),
-            branch_max_turns: self.branch_max_turns.unwrap_or(defaults.branch_max_turns),
-            context_window: self.context_window.unwrap_or(defaults.context_window),
-            compaction: self.compaction.unwrap_or(defaults.compaction),
-            memory_persistence: self
-                .memory_persistence
-                .unwrap_or(defaults.memory_persistence),
-            coalesce: self.coalesce.unwrap_or(defaults.coalesce),
-            ingestion: self.ingestion.unwrap_or(defaults.ingestion),
-            cortex: self.cortex.unwrap_or(defaults.cortex),
-            browser: self
-                .browser
-                .clone()
-                .unwrap_or_else(|| defaults.browser.clone()),
-            brave_search_key: self
-                .brave_search_key
-                .clone()
-                .or_else(|| defaults.brave_search_key.clone()),
-            history_backfill_count: defaults.history_backfill_count,
-            cron: self.cron.clone(),
+impl Default for CostGateConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            threshold_usd: 0.5,
+            expected_completions: 3,
+            expected_output_tokens: 500,
+            timeout_secs: 300,
         }
     }
 }
 
-impl ResolvedAgentConfig {
-    pub fn sqlite_path(&self) -> PathBuf {
-        self.data_dir.join("spacebot.db")
-    }
-    pub fn lancedb_path(&self) -> PathBuf {
-        self.data_dir.join("lancedb")
-    }
-    pub fn redb_path(&self) -> PathBuf {
-        self.data_dir.join("config.redb")
-    }
-    pub fn history_backfill_count(&self) -> usize {
-        self.history_backfill_count
-    }
-    /// Resolved screenshot directory, falling back to data_dir/screenshots.
-    pub fn screenshot_dir(&self) -> PathBuf {
-        self.browser
-            .screenshot_dir
-            .clone()
-            .unwrap_or_else(|| self.data_dir.join("screenshots"))
-    }
+/// Desktop control through the `computer` tool, built with the
+/// `computer-use` feature.
+///
+/// Workers drive an X11 display with screenshots, the mouse and the keyboard.
+/// Calls are confirmed in the worker's channel according to `confirm`, and
+/// nothing is clicked, typed into or captured while a window whose class or
+/// title contains one of `excluded_applications` is the target.
+#[derive(Debug, Clone)]
+pub struct ComputerUseConfig {
+    /// Whether workers get the `computer` tool.
+    pub enabled: bool,
+    /// Which calls need the user's confirmation.
+    pub confirm: ComputerConfirm,
+    /// Window classes or titles the tool refuses to touch, matched
+    /// case-insensitively as substrings.
+    pub excluded_applications: Vec<String>,
+    /// X display to drive. Defaults to the process's `DISPLAY`.
+    pub display: Option<String>,
+}
 
-    /// Directory for worker execution logs written on failure.
-    pub fn logs_dir(&self) -> PathBuf {
-        self.data_dir.join("logs")
+impl Default for ComputerUseConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            confirm: ComputerConfirm::Actions,
+            excluded_applications: [
+                "1password",
+                "bitwarden",
+                "keepassxc",
+                "lastpass",
+                "seahorse",
+            ]
+            .into_iter()
+            .map(String::from)
+            .collect(),
+            display: None,
+        }
     }
+}
 
-    /// Path to agent workspace skills directory.
-    pub fn skills_dir(&self) -> PathBuf {
-        self.workspace.join("skills")
+impl ComputerUseConfig {
+    /// Whether the tool is both compiled in and enabled.
+    pub fn available(&self) -> bool {
+        cfg!(feature = "computer-use") && self.enabled
     }
+}
 
-    /// Path to the memory ingestion directory where users drop files.
-    pub fn ingest_dir(&self) -> PathBuf {
-        self.workspace.join("ingest")
+/// Which `computer` calls need confirmation.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Deserialize, serde::Serialize, schemars::JsonSchema,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum ComputerConfirm {
+    /// Every call, screenshots included.
+    Always,
+    /// Mouse and keyboard input. Screenshots, cursor lookups and waits run
+    /// unconfirmed.
+    Actions,
+    /// Nothing. Only for displays nobody else uses.
+    Never,
+}
+
+impl ComputerConfirm {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Always => "always",
+            Self::Actions => "actions",
+            Self::Never => "never",
+        }
     }
 }
 
-/// Routes a messaging platform conversation to a specific agent.
+/// The `ocr` tool, which reads text and its position out of images.
+///
+/// The vision backend sends the image to a model; the tesseract backend runs
+/// locally and is built with the `ocr-tesseract` feature. With `fallback`
+/// set, a failure of `backend` is retried on the other one.
 #[derive(Debug, Clone)]
-pub struct Binding {
-    pub agent_id: String,
-    pub channel: String,
-    pub guild_id: Option<String>,
-    pub workspace_id: Option<String>, // Slack workspace (team) ID
-    pub chat_id: Option<String>,
-    /// Channel IDs this binding applies to. If empty, all channels in the guild/workspace are allowed.
-    pub channel_ids: Vec<String>,
-    /// User IDs allowed to DM the bot through this binding.
-    pub dm_allowed_users: Vec<String>,
+pub struct OcrConfig {
+    /// Whether workers get the `ocr` tool.
+    pub enabled: bool,
+    /// Which backend to try first.
+    pub backend: OcrBackend,
+    /// Whether to try the other backend when the first one fails.
+    pub fallback: bool,
+    /// Model for the vision backend. Defaults to the worker model.
+    pub model: Option<String>,
+    /// Tesseract language codes, joined with `+` (e.g. "eng+deu").
+    pub languages: String,
 }
 
-impl Binding {
-    /// Check if this binding matches an inbound message.
-    fn matches(&self, message: &crate::InboundMessage) -> bool {
-        if self.channel != message.source {
-            return false;
-        }
-
-        // DM messages have no guild_id — match if the sender is in dm_allowed_users
-        let is_dm =
-            message.metadata.get("discord_guild_id").is_none() && message.source == "discord";
-        if is_dm {
-            return !self.dm_allowed_users.is_empty()
-                && self.dm_allowed_users.contains(&message.sender_id);
+impl Default for OcrConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            backend: OcrBackend::Vision,
+            fallback: true,
+            model: None,
+            languages: "eng".into(),
         }
+    }
+}
 
-        if let Some(guild_id) = &self.guild_id {
-            let message_guild = message
-                .metadata
-                .get("discord_guild_id")
-                .and_then(|v| v.as_u64())
-                .map(|v| v.to_string());
-            if message_guild.as_deref() != Some(guild_id) {
-                return false;
-            }
-        }
+/// The `generate_image` tool, which draws images from a prompt.
+///
+/// Images come from `provider`'s OpenAI-compatible images endpoint
+/// (`/v1/images/generations`), with its key and base URL. Each one is saved
+/// as an artifact and, for a worker in a conversation, uploaded there with
+/// its caption, so users get the image rather than a link.
+#[derive(Debug, Clone)]
+pub struct ImageGenerationConfig {
+    /// Whether workers get the `generate_image` tool.
+    pub enabled: bool,
+    pub provider: String,
+    pub model: String,
+    /// Image dimensions, e.g. "1024x1024".
+    pub size: String,
+    /// Most images one call may ask for.
+    pub max_images: u32,
+}
 
-        if let Some(workspace_id) = &self.workspace_id {
-            let message_workspace = message
-                .metadata
-                .get("slack_workspace_id")
-                .and_then(|v| v.as_str());
-            if message_workspace != Some(workspace_id) {
-                return false;
-            }
+impl Default for ImageGenerationConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            provider: "openai".into(),
+            model: "gpt-image-1".into(),
+            size: "1024x1024".into(),
+            max_images: 4,
         }
+    }
+}
 
-        if !self.channel_ids.is_empty() {
-            let message_channel = message
-                .metadata
-                .get("discord_channel_id")
-                .and_then(|v| v.as_u64())
-                .map(|v| v.to_string());
-            let parent_channel = message
-                .metadata
-                .get("discord_parent_channel_id")
-                .and_then(|v| v.as_u64())
-                .map(|v| v.to_string());
-
-            // Also check Slack channel IDs
-            let slack_channel = message
-                .metadata
-                .get("slack_channel_id")
-                .and_then(|v| v.as_str());
-
-            let direct_match = message_channel
-                .as_ref()
-                .is_some_and(|id| self.channel_ids.contains(id))
-                || slack_channel.is_some_and(|id| self.channel_ids.contains(&id.to_string()));
-            let parent_match = parent_channel
-                .as_ref()
-                .is_some_and(|id| self.channel_ids.contains(id));
-
-            if !direct_match && !parent_match {
-                return false;
-            }
-        }
+/// Where the `ocr` tool reads text.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Deserialize, serde::Serialize, schemars::JsonSchema,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum OcrBackend {
+    /// A vision model call.
+    Vision,
+    /// The local `tesseract` binary.
+    Tesseract,
+}
 
-        if let Some(chat_id) = &self.chat_id {
-            let message_chat = message
-                .metadata
-                .get("telegram_chat_id")
-                .and_then(|v| v.as_str());
-            if message_chat != Some(chat_id) {
-                return false;
-            }
+impl OcrBackend {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Vision => "vision",
+            Self::Tesseract => "tesseract",
         }
-
-        true
     }
 }
 
-/// Resolve which agent should handle an inbound message.
+/// Home Assistant access: the `home_assistant` tool and state-change triggers.
 ///
-/// Checks bindings in order. First match wins. Falls back to the default
-/// agent if no binding matches.
-pub fn resolve_agent_for_message(
-    bindings: &[Binding],
-    message: &crate::InboundMessage,
-    default_agent_id: &str,
-) -> crate::AgentId {
-    for binding in bindings {
-        if binding.matches(message) {
-            return std::sync::Arc::from(binding.agent_id.as_str());
+/// Both only see entities matching `entities`, so an agent can be handed the
+/// lights without also getting the front door lock.
+#[derive(Debug, Clone)]
+pub struct HomeAssistantConfig {
+    /// Whether workers get the `home_assistant` tool and triggers run.
+    pub enabled: bool,
+    /// Base URL of the instance (e.g. "http://homeassistant.local:8123").
+    pub url: String,
+    /// Long-lived access token. Supports `env:VAR` references.
+    pub token: Option<String>,
+    /// Entity ID patterns the agent may read and control, with `*` as a
+    /// wildcard (e.g. "light.*"). Empty allows nothing.
+    pub entities: Vec<String>,
+    /// Prompts to run when entities change state.
+    pub triggers: Vec<HomeAssistantTrigger>,
+}
+
+impl Default for HomeAssistantConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            url: "http://homeassistant.local:8123".into(),
+            token: None,
+            entities: Vec::new(),
+            triggers: Vec::new(),
         }
     }
-    std::sync::Arc::from(default_agent_id)
 }
 
-/// Messaging platform credentials (instance-level).
+/// Runs a prompt when a matching entity changes state, like a cron job that
+/// is woken by the house instead of the clock.
+#[derive(Debug, Clone)]
+pub struct HomeAssistantTrigger {
+    pub id: String,
+    /// Entity ID patterns to watch. Entities outside the allowlist never fire.
+    pub entities: Vec<String>,
+    /// Only fire when leaving this state.
+    pub from: Option<String>,
+    /// Only fire when entering this state.
+    pub to: Option<String>,
+    pub prompt: String,
+    /// Delivery target in "adapter:target" format.
+    pub delivery_target: String,
+    /// Minimum seconds between two runs of this trigger.
+    pub cooldown_secs: u64,
+    pub enabled: bool,
+}
+
+/// The `issues` tool, which files, finds and comments on issues in Jira and
+/// Linear workspaces.
 #[derive(Debug, Clone, Default)]
-pub struct MessagingConfig {
-    pub discord: Option<DiscordConfig>,
-    pub slack: Option<SlackConfig>,
-    pub telegram: Option<TelegramConfig>,
-    pub webhook: Option<WebhookConfig>,
+pub struct IssuesConfig {
+    /// Whether workers get the `issues` tool.
+    pub enabled: bool,
+    pub workspaces: Vec<IssueWorkspace>,
 }
 
+/// One Jira project or Linear team the agent can file issues in.
 #[derive(Debug, Clone)]
-pub struct DiscordConfig {
-    pub enabled: bool,
+pub struct IssueWorkspace {
+    /// Name the agent picks the workspace by.
+    pub name: String,
+    pub provider: IssueProvider,
+    /// Jira site URL (e.g. "https://acme.atlassian.net"). Unused for Linear.
+    pub url: Option<String>,
+    /// Jira Cloud account email, paired with `token` for basic auth. Without
+    /// it the token is sent as a bearer token (Jira Data Center).
+    pub email: Option<String>,
+    /// API token. Supports `env:VAR` references.
     pub token: String,
-    /// User IDs allowed to DM the bot. If empty, DMs are ignored entirely.
-    pub dm_allowed_users: Vec<String>,
-    /// Whether to process messages from other bots (self-messages are always ignored).
-    pub allow_bot_messages: bool,
+    /// Jira project key or Linear team ID that issues are created in and
+    /// searches are limited to.
+    pub project: String,
+    /// Extra fields set on every created issue. String values are minijinja
+    /// templates over `title`, `description` and the variables the agent
+    /// passes in `fields`.
+    pub fields: serde_json::Map<String, serde_json::Value>,
 }
 
-#[derive(Debug, Clone)]
-pub struct SlackConfig {
-    pub enabled: bool,
-    pub bot_token: String,
-    pub app_token: String,
-    /// User IDs allowed to DM the bot. If empty, DMs are ignored entirely.
-    pub dm_allowed_users: Vec<String>,
+/// Issue tracker behind an [`IssueWorkspace`].
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Deserialize, serde::Serialize, schemars::JsonSchema,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum IssueProvider {
+    Jira,
+    Linear,
 }
 
-/// Hot-reloadable Discord permission filters.
-///
-/// Derived from bindings + discord config. Shared with the Discord adapter
-/// via `Arc<ArcSwap<..>>` so the file watcher can swap in new values without
-/// restarting the gateway connection.
-#[derive(Debug, Clone, Default)]
-pub struct DiscordPermissions {
-    pub guild_filter: Option<Vec<u64>>,
-    pub channel_filter: std::collections::HashMap<u64, Vec<u64>>,
-    pub dm_allowed_users: Vec<u64>,
-    pub allow_bot_messages: bool,
+impl IssueProvider {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Jira => "jira",
+            Self::Linear => "linear",
+        }
+    }
 }
 
-/// Hot-reloadable Slack permission filters.
-///
-/// Shared with the Slack adapter via `Arc<ArcSwap<..>>` for hot-reloading.
-#[derive(Debug, Clone, Default)]
-pub struct SlackPermissions {
-    pub workspace_filter: Option<Vec<String>>, // team IDs
-    pub channel_filter: std::collections::HashMap<String, Vec<String>>, // team_id -> allowed channel_ids
-    pub dm_allowed_users: Vec<String>,                                  // user IDs
+/// The `sql_query` tool, which runs read-only queries against configured
+/// Postgres, MySQL and SQLite databases.
+#[derive(Debug, Clone)]
+pub struct SqlConfig {
+    /// Whether workers get the `sql_query` tool.
+    pub enabled: bool,
+    /// Most rows a query returns.
+    pub max_rows: usize,
+    /// Most bytes of cell data a query returns.
+    pub max_bytes: usize,
+    /// How long a query may run before it's cancelled.
+    pub timeout_secs: u64,
+    pub databases: Vec<SqlDatabase>,
 }
 
-impl SlackPermissions {
-    /// Build from the current config's slack settings and bindings.
-    pub fn from_config(slack: &SlackConfig, bindings: &[Binding]) -> Self {
-        let slack_bindings: Vec<&Binding> =
-            bindings.iter().filter(|b| b.channel == "slack").collect();
+impl Default for SqlConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_rows: 100,
+            max_bytes: 20_000,
+            timeout_secs: 30,
+            databases: Vec::new(),
+        }
+    }
+}
 
-        let workspace_filter = {
-            let workspace_ids: Vec<String> = slack_bindings
-                .iter()
-                .filter_map(|b| b.workspace_id.clone())
-                .collect();
-            if workspace_ids.is_empty() {
-                None
-            } else {
-                Some(workspace_ids)
-            }
-        };
+/// Statements `sql_query` accepts unless a database lists its own.
+pub const DEFAULT_SQL_STATEMENTS: &[&str] =
+    &["select", "with", "explain", "show", "describe", "values"];
 
-        let channel_filter = {
-            let mut filter: std::collections::HashMap<String, Vec<String>> =
-                std::collections::HashMap::new();
-            for binding in &slack_bindings {
-                if let Some(workspace_id) = &binding.workspace_id {
-                    if !binding.channel_ids.is_empty() {
-                        filter
-                            .entry(workspace_id.clone())
-                            .or_default()
-                            .extend(binding.channel_ids.clone());
-                    }
-                }
-            }
-            filter
-        };
+/// One database the agent can query.
+#[derive(Debug, Clone)]
+pub struct SqlDatabase {
+    /// Name the agent picks the database by.
+    pub name: String,
+    /// Connection URL (`postgres://`, `mysql://` or `sqlite:`). Supports
+    /// `env:VAR` references.
+    pub url: String,
+    /// What's in the database, shown to the agent in the tool description.
+    pub description: Option<String>,
+    /// Leading keywords of the statements the tool runs, lowercase.
+    pub statements: Vec<String>,
+}
 
-        let dm_allowed_users = slack.dm_allowed_users.clone();
+/// Database behind an [`SqlDatabase`], from its URL scheme.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SqlBackend {
+    Postgres,
+    Mysql,
+    Sqlite,
+}
 
-        Self {
-            workspace_filter,
-            channel_filter,
-            dm_allowed_users,
+impl SqlBackend {
+    pub fn from_url(url: &str) -> Option<Self> {
+        let scheme = url.split_once(':')?.0.to_ascii_lowercase();
+        match scheme.as_str() {
+            "postgres" | "postgresql" => Some(Self::Postgres),
+            "mysql" | "mariadb" => Some(Self::Mysql),
+            "sqlite" => Some(Self::Sqlite),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Postgres => "postgres",
+            Self::Mysql => "mysql",
+            Self::Sqlite => "sqlite",
         }
     }
 }
 
-impl DiscordPermissions {
-    /// Build from the current config's discord settings and bindings.
-    pub fn from_config(discord: &DiscordConfig, bindings: &[Binding]) -> Self {
-        let discord_bindings: Vec<&Binding> =
-            bindings.iter().filter(|b| b.channel == "discord").collect();
+/// The `http_request` tool, and tools generated from the operations of
+/// APIs described by OpenAPI specs.
+#[derive(Debug, Clone)]
+pub struct HttpConfig {
+    /// Whether workers get the `http_request` tool and the API tools.
+    pub enabled: bool,
+    /// Domains `http_request` may call, subdomains included. Empty leaves
+    /// out `http_request`.
+    pub allowed_domains: Vec<String>,
+    /// How long a request may take, redirects included.
+    pub timeout_secs: u64,
+    /// Most bytes of a response body returned to the agent.
+    pub max_response_bytes: usize,
+    pub apis: Vec<HttpApi>,
+}
 
-        let guild_filter = {
-            let guild_ids: Vec<u64> = discord_bindings
-                .iter()
-                .filter_map(|b| b.guild_id.as_ref()?.parse::<u64>().ok())
-                .collect();
-            if guild_ids.is_empty() {
-                None
-            } else {
-                Some(guild_ids)
-            }
-        };
+impl Default for HttpConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            allowed_domains: Vec::new(),
+            timeout_secs: 30,
+            max_response_bytes: 50_000,
+            apis: Vec::new(),
+        }
+    }
+}
 
-        let channel_filter = {
-            let mut filter: std::collections::HashMap<u64, Vec<u64>> =
-                std::collections::HashMap::new();
-            for binding in &discord_bindings {
-                if let Some(guild_id) = binding
-                    .guild_id
-                    .as_ref()
-                    .and_then(|g| g.parse::<u64>().ok())
-                {
-                    if !binding.channel_ids.is_empty() {
-                        let channel_ids: Vec<u64> = binding
-                            .channel_ids
-                            .iter()
-                            .filter_map(|id| id.parse::<u64>().ok())
-                            .collect();
-                        filter.entry(guild_id).or_default().extend(channel_ids);
-                    }
-                }
-            }
-            filter
-        };
+/// An API described by an OpenAPI 3 spec. Each of its operations becomes a
+/// worker tool named `{name}_{operationId}`.
+#[derive(Debug, Clone)]
+pub struct HttpApi {
+    /// Prefix of the API's tool names.
+    pub name: String,
+    /// The spec, a JSON or YAML file. Relative paths are resolved against
+    /// the instance directory.
+    pub spec: PathBuf,
+    /// Where requests go. Defaults to the spec's first server.
+    pub base_url: Option<String>,
+    /// Domains the API's requests may go to, subdomains included. Defaults
+    /// to the base URL's host.
+    pub allowed_domains: Vec<String>,
+    /// operationIds to expose. Empty exposes every operation.
+    pub operations: Vec<String>,
+    /// Credentials sent with every request to the API, and only to it.
+    pub auth: Option<HttpAuth>,
+}
 
-        let mut dm_allowed_users: Vec<u64> = discord
-            .dm_allowed_users
-            .iter()
-            .filter_map(|id| id.parse::<u64>().ok())
-            .collect();
+/// How requests to an [`HttpApi`] authenticate. Values support `env:VAR`
+/// references.
+#[derive(Debug, Clone, Deserialize, schemars::JsonSchema)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum HttpAuth {
+    /// `Authorization: Bearer {token}`.
+    Bearer { token: String },
+    /// HTTP basic auth.
+    Basic { username: String, password: String },
+    /// A header of the API's choosing, such as `X-API-Key`.
+    Header { name: String, value: String },
+    /// A query parameter, such as `?api_key=`.
+    Query { name: String, value: String },
+}
 
-        // Also collect dm_allowed_users from bindings
-        for binding in &discord_bindings {
-            for id in &binding.dm_allowed_users {
-                if let Ok(uid) = id.parse::<u64>() {
-                    if !dm_allowed_users.contains(&uid) {
-                        dm_allowed_users.push(uid);
-                    }
-                }
-            }
+impl HttpAuth {
+    fn resolve_env(self) -> Self {
+        let env = |value: String| resolve_env_value(&value).unwrap_or_default();
+        match self {
+            Self::Bearer { token } => Self::Bearer { token: env(token) },
+            Self::Basic { username, password } => Self::Basic {
+                username: env(username),
+                password: env(password),
+            },
+            Self::Header { name, value } => Self::Header {
+                name,
+                value: env(value),
+            },
+            Self::Query { name, value } => Self::Query {
+                name,
+                value: env(value),
+            },
         }
+    }
+}
+
+/// The `kubernetes` tool, which reads pods, logs, events and other resources
+/// from configured clusters, and changes workloads only when writes are
+/// turned on.
+#[derive(Debug, Clone)]
+pub struct KubernetesConfig {
+    /// Whether workers get the `kubernetes` tool.
+    pub enabled: bool,
+    /// Whether the tool may restart and scale workloads and delete pods.
+    pub allow_writes: bool,
+    /// Most objects a `get` or `events` call returns.
+    pub max_objects: usize,
+    /// Most log lines a `logs` call returns.
+    pub max_log_lines: usize,
+    /// How long a call may take before it's cancelled.
+    pub timeout_secs: u64,
+    pub clusters: Vec<KubernetesCluster>,
+}
 
+impl Default for KubernetesConfig {
+    fn default() -> Self {
         Self {
-            guild_filter,
-            channel_filter,
-            dm_allowed_users,
-            allow_bot_messages: discord.allow_bot_messages,
+            enabled: false,
+            allow_writes: false,
+            max_objects: 100,
+            max_log_lines: 200,
+            timeout_secs: 30,
+            clusters: Vec::new(),
         }
     }
 }
 
+/// One cluster the agent can look at.
 #[derive(Debug, Clone)]
-pub struct TelegramConfig {
+pub struct KubernetesCluster {
+    /// Name the agent picks the cluster by.
+    pub name: String,
+    /// Kubeconfig file. Relative paths are resolved against the instance
+    /// directory. Without one, `$KUBECONFIG`, `~/.kube/config` or the
+    /// in-cluster service account is used.
+    pub kubeconfig: Option<PathBuf>,
+    /// Kubeconfig context. Defaults to the current context.
+    pub context: Option<String>,
+    /// Namespaces the agent may look at. Empty allows every namespace.
+    pub namespaces: Vec<String>,
+    /// What runs in the cluster, shown to the agent in the tool description.
+    pub description: Option<String>,
+}
+
+impl KubernetesCluster {
+    pub fn namespace_allowed(&self, namespace: &str) -> bool {
+        self.namespaces.is_empty() || self.namespaces.iter().any(|n| n == namespace)
+    }
+}
+
+/// The `promql_query` and `logql_query` tools, which query configured
+/// Prometheus and Loki endpoints.
+#[derive(Debug, Clone)]
+pub struct ObservabilityConfig {
+    /// Whether workers get the query tools.
     pub enabled: bool,
-    pub token: String,
-    /// User IDs allowed to DM the bot. If empty, DMs are ignored entirely.
-    pub dm_allowed_users: Vec<String>,
+    /// Longest time range a query may cover.
+    pub max_range_hours: u64,
+    /// Most points returned per series. Longer series are downsampled.
+    pub max_points: usize,
+    /// Most series a metrics query returns.
+    pub max_series: usize,
+    /// Most log lines a `logql_query` returns.
+    pub max_log_lines: usize,
+    /// How long a query may run before it's cancelled.
+    pub timeout_secs: u64,
+    pub sources: Vec<ObservabilitySource>,
 }
 
-/// Hot-reloadable Telegram permission filters.
+impl Default for ObservabilityConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_range_hours: 24,
+            max_points: 120,
+            max_series: 20,
+            max_log_lines: 100,
+            timeout_secs: 30,
+            sources: Vec::new(),
+        }
+    }
+}
+
+/// A Prometheus-compatible or Loki endpoint the agent can query.
+#[derive(Debug, Clone)]
+pub struct ObservabilitySource {
+    /// Name the agent picks the source by.
+    pub name: String,
+    pub backend: ObservabilityBackend,
+    /// Base URL of the HTTP API, without `/api/v1`. A Grafana data source
+    /// proxy URL works too.
+    pub url: String,
+    /// What the source holds, shown to the agent in the tool description.
+    pub description: Option<String>,
+    pub auth: Option<HttpAuth>,
+}
+
+/// Query language an [`ObservabilitySource`] speaks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, schemars::JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ObservabilityBackend {
+    /// PromQL, for Prometheus, Thanos, Mimir, VictoriaMetrics and the like.
+    Prometheus,
+    /// LogQL.
+    Loki,
+}
+
+/// Incident response: the `incident` tool for PagerDuty and Opsgenie, and a
+/// webhook trigger that runs a prompt when a page fires.
 ///
-/// Shared with the Telegram adapter via `Arc<ArcSwap<..>>` for hot-reloading.
+/// Acknowledging, resolving and annotating incidents always need a
+/// confirmation in the channel, and only `responders` may give it.
 #[derive(Debug, Clone, Default)]
-pub struct TelegramPermissions {
-    /// Allowed chat IDs (None = all chats accepted).
-    pub chat_filter: Option<Vec<i64>>,
-    /// User IDs allowed in private chats.
-    pub dm_allowed_users: Vec<i64>,
+pub struct IncidentsConfig {
+    /// Whether workers get the `incident` tool and webhooks are accepted.
+    pub enabled: bool,
+    /// Users who may confirm incident changes, listed like `admin_users`.
+    /// Empty leaves it to the admin users.
+    pub responders: Vec<String>,
+    pub accounts: Vec<IncidentAccount>,
+    /// Prompt to run when a page fires.
+    pub trigger: Option<IncidentTrigger>,
 }
 
-impl TelegramPermissions {
-    /// Build from the current config's telegram settings and bindings.
-    pub fn from_config(telegram: &TelegramConfig, bindings: &[Binding]) -> Self {
-        let telegram_bindings: Vec<&Binding> = bindings
-            .iter()
-            .filter(|b| b.channel == "telegram")
-            .collect();
-
-        let chat_filter = {
-            let chat_ids: Vec<i64> = telegram_bindings
-                .iter()
-                .filter_map(|b| b.chat_id.as_ref()?.parse::<i64>().ok())
-                .collect();
-            if chat_ids.is_empty() {
-                None
-            } else {
-                Some(chat_ids)
-            }
-        };
+/// One PagerDuty or Opsgenie account the agent can work incidents in.
+#[derive(Debug, Clone)]
+pub struct IncidentAccount {
+    /// Name the agent picks the account by, and the last segment of its
+    /// webhook URL.
+    pub name: String,
+    pub provider: IncidentProvider,
+    /// REST API key. Supports `env:VAR` references.
+    pub token: String,
+    /// PagerDuty user email that changes are made as. Required by PagerDuty
+    /// for acknowledging, resolving and adding notes.
+    pub email: Option<String>,
+    /// API base URL. Defaults to the provider's; EU Opsgenie accounts use
+    /// "https://api.eu.opsgenie.com".
+    pub url: Option<String>,
+    /// Secret inbound webhooks are checked against: PagerDuty's signing
+    /// secret, or the value Opsgenie sends in the `X-Spacebot-Token` header.
+    /// Supports `env:VAR` references. Without it, webhooks are refused.
+    pub webhook_secret: Option<String>,
+}
 
-        let mut dm_allowed_users: Vec<i64> = telegram
-            .dm_allowed_users
-            .iter()
-            .filter_map(|id| id.parse::<i64>().ok())
-            .collect();
+/// Incident management service behind an [`IncidentAccount`].
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Deserialize, serde::Serialize, schemars::JsonSchema,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum IncidentProvider {
+    Pagerduty,
+    Opsgenie,
+}
 
-        for binding in &telegram_bindings {
-            for id in &binding.dm_allowed_users {
-                if let Ok(uid) = id.parse::<i64>() {
-                    if !dm_allowed_users.contains(&uid) {
-                        dm_allowed_users.push(uid);
-                    }
-                }
-            }
+impl IncidentProvider {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Pagerduty => "pagerduty",
+            Self::Opsgenie => "opsgenie",
         }
+    }
+}
+
+/// Runs a prompt when a page fires, like a cron job that is woken by the
+/// pager instead of the clock.
+#[derive(Debug, Clone)]
+pub struct IncidentTrigger {
+    pub prompt: String,
+    /// Delivery target in "adapter:target" format.
+    pub delivery_target: String,
+    /// Only fire for these PagerDuty services or Opsgenie alert entities.
+    /// Empty fires for every page.
+    pub services: Vec<String>,
+    pub enabled: bool,
+}
+
+/// Where artifacts such as browser screenshots are kept.
+///
+/// The local backend writes them to the agent's data directory. The S3
+/// backend uploads them to any S3-compatible service (AWS, MinIO, R2) under
+/// `{prefix}{agent_id}/`, so they outlive the node that produced them.
+#[derive(Debug, Clone)]
+pub struct StorageConfig {
+    pub backend: StorageBackend,
+    /// S3 endpoint URL (e.g. "https://s3.us-east-1.amazonaws.com" or
+    /// "http://minio:9000"). Buckets are addressed path-style.
+    pub endpoint: Option<String>,
+    pub bucket: Option<String>,
+    /// Signing region. R2 uses "auto".
+    pub region: String,
+    /// Supports `env:VAR` references.
+    pub access_key_id: Option<String>,
+    /// Supports `env:VAR` references.
+    pub secret_access_key: Option<String>,
+    /// Key prefix for everything this instance stores (e.g. "prod/").
+    pub prefix: String,
+    /// Public base URL of the bucket, used to link stored objects. Without
+    /// it links are `s3://bucket/key`.
+    pub public_url: Option<String>,
+}
 
+impl Default for StorageConfig {
+    fn default() -> Self {
         Self {
-            chat_filter,
-            dm_allowed_users,
+            backend: StorageBackend::Local,
+            endpoint: None,
+            bucket: None,
+            region: "us-east-1".into(),
+            access_key_id: None,
+            secret_access_key: None,
+            prefix: String::new(),
+            public_url: None,
         }
     }
 }
 
-#[derive(Debug, Clone)]
-pub struct WebhookConfig {
+/// Artifact storage backend.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Deserialize, serde::Serialize, schemars::JsonSchema,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum StorageBackend {
+    /// The agent's data directory.
+    Local,
+    /// An S3-compatible bucket.
+    S3,
+}
+
+impl StorageBackend {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Local => "local",
+            Self::S3 => "s3",
+        }
+    }
+}
+
+/// Inbound message rate limits, applied before messages reach a channel.
+///
+/// Each user gets a per-minute rate with a burst allowance and an hourly
+/// quota; each conversation gets a combined per-minute rate. A limit of 0
+/// turns that limit off.
+#[derive(Debug, Clone, Copy)]
+pub struct RateLimitConfig {
+    /// Whether rate limiting is enabled.
     pub enabled: bool,
-    pub port: u16,
-    pub bind: String,
+    /// Sustained messages per minute from one user.
+    pub user_per_minute: u32,
+    /// Messages a user can send in quick succession before the per-minute
+    /// rate kicks in.
+    pub user_burst: u32,
+    /// Messages per hour from one user.
+    pub user_per_hour: u32,
+    /// Sustained messages per minute in one conversation, across all users.
+    pub channel_per_minute: u32,
+    /// Burst allowance for a conversation.
+    pub channel_burst: u32,
 }
 
-// -- TOML deserialization types --
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            user_per_minute: 10,
+            user_burst: 5,
+            user_per_hour: 120,
+            channel_per_minute: 30,
+            channel_burst: 15,
+        }
+    }
+}
 
-#[derive(Deserialize)]
-struct TomlConfig {
-    #[serde(default)]
-    llm: TomlLlmConfig,
-    #[serde(default)]
-    defaults: TomlDefaultsConfig,
-    #[serde(default)]
-    agents: Vec<TomlAgentConfig>,
-    #[serde(default)]
-    messaging: TomlMessagingConfig,
-    #[serde(default)]
-    bindings: Vec<TomlBinding>,
-    #[serde(default)]
-    api: TomlApiConfig,
+/// Inbound deduplication, applied before anything else in the router.
+///
+/// A message is dropped when its platform event id was already seen in the
+/// conversation within `event_window_secs`, or when its sender sent the same
+/// content there within `content_window_secs`. A window of 0 turns that
+/// check off.
+#[derive(Debug, Clone, Copy)]
+pub struct DedupConfig {
+    /// Whether deduplication is enabled.
+    pub enabled: bool,
+    /// How long a platform event id is remembered, for redelivered webhooks.
+    pub event_window_secs: u64,
+    /// How long identical content from one sender counts as a double send.
+    pub content_window_secs: u64,
 }
 
-#[derive(Deserialize)]
-struct TomlApiConfig {
-    #[serde(default = "default_api_enabled")]
-    enabled: bool,
-    #[serde(default = "default_api_port")]
-    port: u16,
-    #[serde(default = "default_api_bind")]
-    bind: String,
+impl Default for DedupConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            event_window_secs: 600,
+            content_window_secs: 10,
+        }
+    }
 }
 
-impl Default for TomlApiConfig {
+/// Runaway loop detection in the agentic loop.
+///
+/// A turn is terminated when the same tool is called with the same
+/// arguments `max_repeated_calls` times in a row, when `max_stalled_rounds`
+/// tool rounds in a row return nothing new, or when completions alternate
+/// between two outputs. A threshold of 0 turns that check off.
+#[derive(Debug, Clone, Copy)]
+pub struct LoopDetectionConfig {
+    /// Whether loop detection is enabled.
+    pub enabled: bool,
+    /// Identical tool calls in a row before the turn is stopped.
+    pub max_repeated_calls: u32,
+    /// Tool rounds in a row without a new result before the turn is stopped.
+    pub max_stalled_rounds: u32,
+}
+
+impl Default for LoopDetectionConfig {
     fn default() -> Self {
         Self {
-            enabled: default_api_enabled(),
-            port: default_api_port(),
-            bind: default_api_bind(),
+            enabled: true,
+            max_repeated_calls: 4,
+            max_stalled_rounds: 3,
         }
     }
 }
 
-fn default_api_enabled() -> bool {
-    true
+/// Usage anomaly detection over the agent's turn ledger.
+///
+/// Every `check_interval_secs` the last hour of turns is compared against
+/// the `baseline_days` before it. Spend, tokens per turn and the share of
+/// completions answered by a fallback model are each flagged when they
+/// reach their factor times the baseline, and the alert is delivered to
+/// `delivery_targets`. A kind of anomaly is alerted at most once per
+/// `cooldown_secs`.
+#[derive(Debug, Clone)]
+pub struct UsageAnomalyConfig {
+    pub enabled: bool,
+    pub check_interval_secs: u64,
+    pub baseline_days: u32,
+    /// Hourly spend this many times the baseline hourly spend is flagged.
+    pub spend_factor: f64,
+    /// Hours costing less than this are never flagged.
+    pub min_spend_usd: f64,
+    /// Tokens per turn this many times the baseline are flagged.
+    pub token_factor: f64,
+    /// Fallback share this many times the baseline share is flagged.
+    pub fallback_factor: f64,
+    /// Fallback shares below this are never flagged.
+    pub min_fallback_share: f64,
+    /// Turns the last hour and the baseline each need before anything is
+    /// compared.
+    pub min_turns: u64,
+    pub cooldown_secs: u64,
+    /// Where alerts go, as `adapter:target`. Anomalies are only logged
+    /// when empty.
+    pub delivery_targets: Vec<String>,
 }
-fn default_api_port() -> u16 {
-    19898
+
+impl Default for UsageAnomalyConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            check_interval_secs: 900,
+            baseline_days: 7,
+            spend_factor: 5.0,
+            min_spend_usd: 1.0,
+            token_factor: 2.0,
+            fallback_factor: 3.0,
+            min_fallback_share: 0.2,
+            min_turns: 10,
+            cooldown_secs: 21_600,
+            delivery_targets: Vec::new(),
+        }
+    }
 }
-fn default_api_bind() -> String {
-    "127.0.0.1".into()
+
+/// Graceful degradation as an agent spends its daily budget.
+///
+/// Every `check_interval_secs` the agent's spend over the last 24 hours is
+/// compared against `daily_usd`. At `cheaper_model_at` of the budget the
+/// channel answers with `cheaper_model`; at `restrict_tools_at` the
+/// `expensive_tools` are withheld as well; once the budget is spent every
+/// message gets `over_budget_reply` and no model is called. Each step,
+/// down or back up, is logged and delivered to `delivery_targets`.
+#[derive(Debug, Clone)]
+pub struct BudgetConfig {
+    pub enabled: bool,
+    /// Spend allowed over any 24 hours.
+    pub daily_usd: f64,
+    pub check_interval_secs: u64,
+    /// Share of `daily_usd` at which the channel drops to `cheaper_model`.
+    pub cheaper_model_at: f64,
+    /// A routing tier (`channel`, `branch`, `worker`, ...) or model name.
+    pub cheaper_model: String,
+    /// Share of `daily_usd` at which `expensive_tools` are withheld.
+    pub restrict_tools_at: f64,
+    pub expensive_tools: Vec<String>,
+    /// Sent instead of calling a model once the budget is spent.
+    pub over_budget_reply: String,
+    /// Where step changes go, as `adapter:target`. They are only logged
+    /// when empty.
+    pub delivery_targets: Vec<String>,
 }
 
-#[derive(Deserialize, Default)]
-struct TomlLlmConfig {
-    anthropic_key: Option<String>,
-    openai_key: Option<String>,
-    openrouter_key: Option<String>,
-    ollama_key: Option<String>,
-    zhipu_key: Option<String>,
-    groq_key: Option<String>,
-    together_key: Option<String>,
-    fireworks_key: Option<String>,
-    deepseek_key: Option<String>,
-    xai_key: Option<String>,
-    mistral_key: Option<String>,
-    opencode_zen_key: Option<String>,
+impl Default for BudgetConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            daily_usd: 10.0,
+            check_interval_secs: 60,
+            cheaper_model_at: 0.8,
+            cheaper_model: "worker".into(),
+            restrict_tools_at: 0.9,
+            expensive_tools: ["spawn_worker", "start_task", "branch", "route"]
+                .into_iter()
+                .map(String::from)
+                .collect(),
+            over_budget_reply: "I've used up my budget for today and can't take new requests right now. Please try again later.".into(),
+            delivery_targets: Vec::new(),
+        }
+    }
 }
 
-#[derive(Deserialize, Default)]
-struct TomlDefaultsConfig {
-    routing: Option<TomlRoutingConfig>,
-    max_concurrent_branches: Option<usize>,
-    max_concurrent_workers: Option<usize>,
-    max_turns: Option<usize>,
-    branch_max_turns: Option<usize>,
+/// Post-hoc fact-checking of channel replies.
+///
+/// When enabled, each reply a channel listed in `channels` is about to send
+/// has up to `max_claims` factual claims extracted by `model`, each claim is
+/// looked up in `sources` (`"memory"`, honoring `[retrieval]`'s store, and
+/// `"web"` through Brave Search), and claims the sources don't back are
+/// flagged under the reply or softened in it, per `action`. A check that
+/// fails or takes longer than `timeout_secs` sends the reply unchanged.
+#[derive(Debug, Clone)]
+pub struct FactCheckConfig {
+    pub enabled: bool,
+    /// Channel IDs (e.g. "discord:123:456") or platforms (e.g. "slack")
+    /// whose replies are checked. Empty checks every channel.
+    pub channels: Vec<String>,
+    /// Model extracting and checking claims: a model name or a routing tier.
+    pub model: String,
+    /// Where claims are looked up: `"memory"` and/or `"web"`.
+    pub sources: Vec<String>,
+    pub action: FactCheckAction,
+    pub max_claims: usize,
+    pub timeout_secs: u64,
+}
+
+impl Default for FactCheckConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            channels: Vec::new(),
+            model: "branch".into(),
+            sources: vec!["memory".into(), "web".into()],
+            action: FactCheckAction::Flag,
+            max_claims: 5,
+            timeout_secs: 45,
+        }
+    }
+}
+
+impl FactCheckConfig {
+    /// Whether replies in `channel_id` are checked.
+    pub fn applies_to(&self, channel_id: &str) -> bool {
+        let platform = channel_id.split(':').next().unwrap_or(channel_id);
+        self.enabled
+            && (self.channels.is_empty()
+                || self
+                    .channels
+                    .iter()
+                    .any(|channel| channel == channel_id || channel == platform))
+    }
+}
+
+/// "via" footers naming the tools a channel reply drew on.
+///
+/// When enabled, the results of tools run by the channel's branches and
+/// workers are kept, and each reply is matched against them: a result that
+/// shares enough wording with the reply is credited, as is anything the
+/// model lists in the `reply` tool's `via` argument. Up to `max_sources` are
+/// shown under the reply, e.g. "via web_search, file:docs/deploy.md".
+#[derive(Debug, Clone)]
+pub struct ProvenanceConfig {
+    pub enabled: bool,
+    pub max_sources: usize,
+}
+
+impl Default for ProvenanceConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_sources: 3,
+        }
+    }
+}
+
+/// Flagging conversations for human review.
+///
+/// When enabled, the channel gets an `escalate` tool, and messages matching
+/// any of `patterns` (case-insensitive regexes) are flagged before they reach
+/// a model. A flagged conversation gets `paused_reply` once and no automated
+/// replies after it until a moderator resumes it. Moderators are notified
+/// at `delivery_targets` with the reason and a link to the transcript on
+/// the dashboard at `dashboard_url`, or the command that prints it when
+/// that isn't set.
+#[derive(Debug, Clone)]
+pub struct EscalationConfig {
+    pub enabled: bool,
+    /// Where moderators are notified, as `adapter:target`. Escalations are
+    /// only logged when empty.
+    pub delivery_targets: Vec<String>,
+    pub patterns: Vec<String>,
+    /// Sent to the conversation when it's flagged.
+    pub paused_reply: String,
+    /// Base URL of the dashboard, for transcript links.
+    pub dashboard_url: Option<String>,
+}
+
+impl Default for EscalationConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            delivery_targets: Vec::new(),
+            patterns: Vec::new(),
+            paused_reply: "I've asked a moderator to look at this conversation. Someone will pick it up from here.".into(),
+            dashboard_url: None,
+        }
+    }
+}
+
+/// Named generation profiles and the one an agent's completions use.
+///
+/// `profile` names one of `profiles` or a built-in (`creative`, `precise`,
+/// `deterministic`); without one, no sampling parameters are sent and each
+/// provider uses its own defaults. A channel message starting with
+/// `/profile <name>` runs its turn with that profile instead.
+#[derive(Debug, Clone, Default)]
+pub struct GenerationConfig {
+    pub profile: Option<String>,
+    /// Profiles by name. One named like a built-in replaces it.
+    pub profiles: HashMap<String, GenerationProfile>,
+}
+
+impl GenerationConfig {
+    /// The profile called `name`, configured or built in.
+    pub fn resolve(&self, name: &str) -> Option<GenerationProfile> {
+        self.profiles
+            .get(name)
+            .cloned()
+            .or_else(|| GenerationProfile::builtin(name))
+    }
+
+    /// The profile completions use unless a turn asks for another.
+    pub fn default_profile(&self) -> Option<GenerationProfile> {
+        self.profile.as_deref().and_then(|name| self.resolve(name))
+    }
+
+    /// Every profile name, built-ins first, the rest sorted.
+    pub fn names(&self) -> Vec<String> {
+        let mut configured: Vec<&String> = self
+            .profiles
+            .keys()
+            .filter(|name| !BUILTIN_PROFILES.contains(&name.as_str()))
+            .collect();
+        configured.sort();
+        BUILTIN_PROFILES
+            .iter()
+            .map(|name| name.to_string())
+            .chain(configured.into_iter().cloned())
+            .collect()
+    }
+}
+
+/// Output token caps per process type and per model.
+///
+/// Separate from a provider's own limit, a cap keeps replies concise and
+/// their cost predictable. A completion's cap is the lower of its process
+/// type's and its model's; with neither, `max_tokens` is left as the request
+/// set it. A completion that uses its whole cap is trimmed per `truncation`.
+#[derive(Debug, Clone, Default)]
+pub struct OutputCapsConfig {
+    pub channel: Option<u64>,
+    pub branch: Option<u64>,
+    pub worker: Option<u64>,
+    pub compactor: Option<u64>,
+    pub cortex: Option<u64>,
+    /// Caps per model, as `provider/model`.
+    pub models: HashMap<String, u64>,
+    pub truncation: Truncation,
+}
+
+impl OutputCapsConfig {
+    /// The cap for a completion by `process_type` on `model_name`, if any.
+    pub fn resolve(&self, process_type: crate::ProcessType, model_name: &str) -> Option<OutputCap> {
+        let tier = match process_type {
+            crate::ProcessType::Channel => self.channel,
+            crate::ProcessType::Branch => self.branch,
+            crate::ProcessType::Worker => self.worker,
+            crate::ProcessType::Compactor => self.compactor,
+            crate::ProcessType::Cortex => self.cortex,
+        };
+        let max_tokens = match (tier, self.models.get(model_name).copied()) {
+            (Some(tier), Some(model)) => tier.min(model),
+            (tier, model) => tier.or(model)?,
+        };
+        Some(OutputCap {
+            max_tokens,
+            truncation: self.truncation,
+        })
+    }
+}
+
+/// Retrying completions that come back empty.
+///
+/// With `retry` on, a request whose completion has no text and no tool call
+/// is sent once more with `nudge` added as a user message before the empty
+/// result is let through.
+#[derive(Debug, Clone)]
+pub struct EmptyResponseConfig {
+    pub retry: bool,
+    pub nudge: String,
+}
+
+impl Default for EmptyResponseConfig {
+    fn default() -> Self {
+        Self {
+            retry: true,
+            nudge: DEFAULT_NUDGE.to_string(),
+        }
+    }
+}
+
+/// Structured reports at the end of long worker runs.
+///
+/// With `enabled` on, a worker that took at least `min_turns` turns is asked
+/// for a report of its goals, actions, artifacts and unresolved items before
+/// it finishes. See [`crate::agent::self_report`].
+#[derive(Debug, Clone)]
+pub struct SelfReportConfig {
+    pub enabled: bool,
+    pub min_turns: usize,
+}
+
+impl Default for SelfReportConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            min_turns: 10,
+        }
+    }
+}
+
+/// A soft time limit on channel turns.
+///
+/// With `enabled` on, the channel prompt asks the model to answer within
+/// about `target_secs` of work. Once `hurry_at` of that has passed, each
+/// completion may make at most `max_tool_calls` tool calls besides replying,
+/// and follow-up calls go to `fast_model`. See
+/// [`crate::llm::latency_budget`].
+#[derive(Debug, Clone)]
+pub struct LatencyBudgetConfig {
+    pub enabled: bool,
+    pub target_secs: u64,
+    /// Share of `target_secs` after which the turn hurries.
+    pub hurry_at: f64,
+    /// A routing tier (`channel`, `branch`, `worker`, ...) or model name.
+    pub fast_model: String,
+    pub max_tool_calls: usize,
+}
+
+impl Default for LatencyBudgetConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            target_secs: 15,
+            hurry_at: 0.7,
+            fast_model: "worker".into(),
+            max_tool_calls: 1,
+        }
+    }
+}
+
+/// Privacy of the aggregate reports the API serves: turns by language,
+/// flag and prompt version, and tool spend.
+///
+/// With `min_group_size` above 1, a report row is only shown when it's drawn
+/// from at least that many conversations, so a report shared beyond admins
+/// doesn't give away one user's messages or spend. Tool spend is then
+/// summed per tool instead of per conversation. See
+/// [`crate::usage_privacy`].
+#[derive(Debug, Clone, Default)]
+pub struct UsageReportConfig {
+    /// 0 or 1 shows every row.
+    pub min_group_size: u64,
+}
+
+/// What happens to a reply's unverified claims.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Deserialize, serde::Serialize, schemars::JsonSchema,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum FactCheckAction {
+    /// Send the reply as written, with the unverified claims listed under it.
+    Flag,
+    /// Rewrite the reply to hedge unverified claims and correct ones the
+    /// sources contradict.
+    Soften,
+}
+
+/// Conversation retention.
+///
+/// A conversation with no messages for `idle_days` is summarized into
+/// memory (when `summarize` is set) and its transcript is then archived,
+/// deleted, or kept according to `action`. `channels` overrides the policy
+/// for single conversations by channel ID, or for a whole platform by name.
+#[derive(Debug, Clone)]
+pub struct RetentionConfig {
+    /// Whether expired conversations are processed.
+    pub enabled: bool,
+    /// Days without a message before a conversation expires.
+    pub idle_days: u32,
+    /// What happens to an expired conversation's transcript.
+    pub action: RetentionAction,
+    /// Whether an expired conversation is summarized into memory first.
+    pub summarize: bool,
+    /// How often to look for expired conversations.
+    pub check_interval_secs: u64,
+    /// Overrides keyed by channel ID (e.g. "discord:123:456") or platform
+    /// (e.g. "discord").
+    pub channels: HashMap<String, ChannelRetentionConfig>,
+}
+
+impl Default for RetentionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            idle_days: 90,
+            action: RetentionAction::Archive,
+            summarize: true,
+            check_interval_secs: 86400,
+            channels: HashMap::new(),
+        }
+    }
+}
+
+impl RetentionConfig {
+    /// The policy for a channel: its own override, else its platform's,
+    /// else the agent-wide settings.
+    pub fn policy_for(&self, channel_id: &str) -> RetentionPolicy {
+        let platform = channel_id.split(':').next().unwrap_or(channel_id);
+        let overrides = self
+            .channels
+            .get(channel_id)
+            .or_else(|| self.channels.get(platform))
+            .copied()
+            .unwrap_or_default();
+        RetentionPolicy {
+            idle_days: overrides.idle_days.unwrap_or(self.idle_days),
+            action: overrides.action.unwrap_or(self.action),
+            summarize: overrides.summarize.unwrap_or(self.summarize),
+        }
+    }
+}
+
+/// What happens to an expired conversation's transcript.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Deserialize, serde::Serialize, schemars::JsonSchema,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum RetentionAction {
+    /// Write the transcript to a gzipped JSONL file in the agent's archives
+    /// directory, then delete it from the database.
+    Archive,
+    /// Delete the transcript.
+    Delete,
+    /// Never expire the conversation.
+    Keep,
+}
+
+impl RetentionAction {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Archive => "archive",
+            Self::Delete => "delete",
+            Self::Keep => "keep",
+        }
+    }
+}
+
+/// Per-channel or per-platform retention overrides. Unset fields fall back
+/// to the agent's retention settings.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ChannelRetentionConfig {
+    pub idle_days: Option<u32>,
+    pub action: Option<RetentionAction>,
+    pub summarize: Option<bool>,
+}
+
+/// The effective retention policy for one channel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetentionPolicy {
+    pub idle_days: u32,
+    pub action: RetentionAction,
+    pub summarize: bool,
+}
+
+/// Reply-language matching.
+///
+/// The language of each inbound message is detected, and the model is asked
+/// to reply in it. `channels` sets a fixed reply language for single
+/// conversations by channel ID, or for a whole platform by name, with
+/// `"auto"` going back to detection.
+#[derive(Debug, Clone)]
+pub struct LanguageConfig {
+    /// Whether the model is asked to reply in the detected language.
+    /// Messages are labeled with their language for metrics either way.
+    pub detect: bool,
+    /// Reply languages keyed by channel ID or platform, e.g. "German".
+    pub channels: HashMap<String, String>,
+}
+
+impl Default for LanguageConfig {
+    fn default() -> Self {
+        Self {
+            detect: true,
+            channels: HashMap::new(),
+        }
+    }
+}
+
+impl LanguageConfig {
+    /// The language a channel always replies in: its own override, else its
+    /// platform's. None when replies follow the detected language.
+    pub fn reply_language_for(&self, channel_id: &str) -> Option<&str> {
+        let platform = channel_id.split(':').next().unwrap_or(channel_id);
+        self.channels
+            .get(channel_id)
+            .or_else(|| self.channels.get(platform))
+            .map(String::as_str)
+            .filter(|language| !language.eq_ignore_ascii_case("auto"))
+    }
+}
+
+/// Network access for worker tools.
+///
+/// A tool with a policy in `tools`, keyed by tool name, reaches the network
+/// only through an internal proxy that enforces it. Tools without one aren't
+/// proxied.
+#[derive(Debug, Clone, Default)]
+pub struct NetworkConfig {
+    pub tools: HashMap<String, ToolNetworkPolicy>,
+}
+
+/// What one tool may reach over the network.
+#[derive(Debug, Clone, Default, Deserialize, schemars::JsonSchema)]
+pub struct ToolNetworkPolicy {
+    #[serde(default)]
+    pub access: NetworkAccess,
+    /// Domains reachable under `access = "allowlist"`, subdomains included.
+    #[serde(default)]
+    pub allow: Vec<String>,
+    /// Most bytes one connection may send, headers included. Larger
+    /// requests are refused or cut off.
+    pub max_request_bytes: Option<u64>,
+}
+
+/// Which hosts a tool may connect to.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, schemars::JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum NetworkAccess {
+    /// Any host.
+    #[default]
+    Any,
+    /// No host.
+    None,
+    /// Hosts in `allow` and their subdomains.
+    Allowlist,
+}
+
+impl ToolNetworkPolicy {
+    /// Whether the tool may connect to `host`. Hosts are matched by name,
+    /// so an IP address is only reachable when allowed verbatim.
+    pub fn allows_host(&self, host: &str) -> bool {
+        let host = host.trim_end_matches('.').to_ascii_lowercase();
+        match self.access {
+            NetworkAccess::Any => true,
+            NetworkAccess::None => false,
+            NetworkAccess::Allowlist => domain_allowed(&host, &self.allow),
+        }
+    }
+}
+
+/// Whether `host` is one of `domains` or a subdomain of one. A leading `*.`
+/// on a domain is accepted and means the same thing.
+pub fn domain_allowed(host: &str, domains: &[String]) -> bool {
+    let host = host.trim_end_matches('.').to_ascii_lowercase();
+    domains.iter().any(|domain| {
+        let domain = domain.trim_start_matches("*.").to_ascii_lowercase();
+        host == domain
+            || host
+                .strip_suffix(domain.as_str())
+                .is_some_and(|prefix| prefix.ends_with('.'))
+    })
+}
+
+/// How conversations in platform threads start.
+///
+/// A Slack or Discord thread gets its own conversation, separate from the
+/// channel it was started in. `parent_context` sets what a thread's channel
+/// is told about that parent conversation when it opens.
+#[derive(Debug, Clone)]
+pub struct ThreadConfig {
+    pub parent_context: ThreadParentContext,
+    /// Most recent parent messages the context is drawn from.
+    pub parent_messages: usize,
+}
+
+impl Default for ThreadConfig {
+    fn default() -> Self {
+        Self {
+            parent_context: ThreadParentContext::Summary,
+            parent_messages: 30,
+        }
+    }
+}
+
+/// What a new thread's channel gets from its parent conversation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, schemars::JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ThreadParentContext {
+    /// Nothing; the thread starts fresh.
+    None,
+    /// The parent's recent messages, verbatim.
+    Transcript,
+    /// A summary of the parent's recent messages, written by the compactor.
+    Summary,
+}
+
+/// Inline `!` commands, answered by the runtime without a model call.
+///
+/// Commands that always need admin rights, such as `!model set`, stay
+/// limited to admins whatever `admin_only` says.
+#[derive(Debug, Clone)]
+pub struct CommandsConfig {
+    pub enabled: bool,
+    /// Commands only admin users may run, by name without the `!`.
+    pub admin_only: Vec<String>,
+    /// Commands turned off, whose messages go to the model like any other.
+    pub disabled: Vec<String>,
+}
+
+impl Default for CommandsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            admin_only: Vec::new(),
+            disabled: Vec::new(),
+        }
+    }
+}
+
+/// OpenCode subprocess worker configuration.
+#[derive(Debug, Clone)]
+pub struct OpenCodeConfig {
+    /// Whether OpenCode workers are available.
+    pub enabled: bool,
+    /// Path to the OpenCode binary. Supports "env:VAR_NAME" references.
+    /// Falls back to "opencode" on PATH.
+    pub path: String,
+    /// Maximum concurrent OpenCode server processes.
+    pub max_servers: usize,
+    /// Timeout in seconds waiting for a server to become healthy.
+    pub server_startup_timeout_secs: u64,
+    /// Maximum restart attempts before giving up on a server.
+    pub max_restart_retries: u32,
+    /// Permission settings passed to OpenCode's config.
+    pub permissions: crate::opencode::OpenCodePermissions,
+}
+
+impl Default for OpenCodeConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            path: "opencode".to_string(),
+            max_servers: 5,
+            server_startup_timeout_secs: 30,
+            max_restart_retries: 5,
+            permissions: crate::opencode::OpenCodePermissions::default(),
+        }
+    }
+}
+
+/// Cortex configuration.
+#[derive(Debug, Clone, Copy)]
+pub struct CortexConfig {
+    pub tick_interval_secs: u64,
+    pub worker_timeout_secs: u64,
+    pub branch_timeout_secs: u64,
+    pub circuit_breaker_threshold: u8,
+    /// Interval in seconds between memory bulletin refreshes.
+    pub bulletin_interval_secs: u64,
+    /// Target word count for the memory bulletin.
+    pub bulletin_max_words: usize,
+    /// Max LLM turns for bulletin generation.
+    pub bulletin_max_turns: usize,
+    /// Interval in seconds between association passes.
+    pub association_interval_secs: u64,
+    /// Minimum cosine similarity to create a RelatedTo edge.
+    pub association_similarity_threshold: f32,
+    /// Minimum cosine similarity to create an Updates edge (near-duplicate).
+    pub association_updates_threshold: f32,
+    /// Max associations to create per pass (rate limit).
+    pub association_max_per_pass: usize,
+}
+
+impl Default for CortexConfig {
+    fn default() -> Self {
+        Self {
+            tick_interval_secs: 30,
+            worker_timeout_secs: 300,
+            branch_timeout_secs: 60,
+            circuit_breaker_threshold: 3,
+            bulletin_interval_secs: 3600,
+            bulletin_max_words: 1500,
+            bulletin_max_turns: 15,
+            association_interval_secs: 300,
+            association_similarity_threshold: 0.85,
+            association_updates_threshold: 0.95,
+            association_max_per_pass: 100,
+        }
+    }
+}
+
+/// Per-agent configuration (raw, before resolution with defaults).
+#[derive(Debug, Clone)]
+pub struct AgentConfig {
+    pub id: String,
+    pub default: bool,
+    /// What this agent handles, in a sentence or two. Intake classification
+    /// routes unbound messages by it; agents without one are never picked.
+    pub description: Option<String>,
+    /// Custom workspace path. If None, resolved to instance_dir/agents/{id}/workspace.
+    pub workspace: Option<PathBuf>,
+    /// Per-agent routing overrides. None inherits from defaults.
+    pub routing: Option<RoutingConfig>,
+    pub max_concurrent_branches: Option<usize>,
+    pub max_concurrent_workers: Option<usize>,
+    pub max_turns: Option<usize>,
+    pub branch_max_turns: Option<usize>,
+    pub context_window: Option<usize>,
+    pub compaction: Option<CompactionConfig>,
+    pub memory_persistence: Option<MemoryPersistenceConfig>,
+    pub coalesce: Option<CoalesceConfig>,
+    pub ingestion: Option<IngestionConfig>,
+    pub cortex: Option<CortexConfig>,
+    pub browser: Option<BrowserConfig>,
+    pub tool_filter: Option<ToolFilterConfig>,
+    pub compression: Option<CompressionConfig>,
+    pub preview: Option<PreviewConfig>,
+    pub tool_guard: Option<ToolGuardConfig>,
+    pub cost_gate: Option<CostGateConfig>,
+    pub computer_use: Option<ComputerUseConfig>,
+    pub ocr: Option<OcrConfig>,
+    pub image_generation: Option<ImageGenerationConfig>,
+    pub home_assistant: Option<HomeAssistantConfig>,
+    pub issues: Option<IssuesConfig>,
+    pub sql: Option<SqlConfig>,
+    pub http: Option<HttpConfig>,
+    pub kubernetes: Option<KubernetesConfig>,
+    pub observability: Option<ObservabilityConfig>,
+    pub incidents: Option<IncidentsConfig>,
+    pub storage: Option<StorageConfig>,
+    pub rate_limit: Option<RateLimitConfig>,
+    pub dedup: Option<DedupConfig>,
+    pub loop_detection: Option<LoopDetectionConfig>,
+    pub usage_anomalies: Option<UsageAnomalyConfig>,
+    pub budget: Option<BudgetConfig>,
+    pub fact_check: Option<FactCheckConfig>,
+    pub provenance: Option<ProvenanceConfig>,
+    pub escalation: Option<EscalationConfig>,
+    pub generation: Option<GenerationConfig>,
+    pub output_caps: Option<OutputCapsConfig>,
+    pub empty_response: Option<EmptyResponseConfig>,
+    pub self_report: Option<SelfReportConfig>,
+    pub latency_budget: Option<LatencyBudgetConfig>,
+    pub usage_reports: Option<UsageReportConfig>,
+    pub retention: Option<RetentionConfig>,
+    pub language: Option<LanguageConfig>,
+    pub network: Option<NetworkConfig>,
+    pub threads: Option<ThreadConfig>,
+    pub commands: Option<CommandsConfig>,
+    /// Per-agent admin users. None inherits from defaults.
+    pub admin_users: Option<Vec<String>>,
+    /// Per-agent Brave Search API key override. None inherits from defaults.
+    pub brave_search_key: Option<String>,
+    /// Per-agent sampling seed. None inherits from defaults.
+    pub seed: Option<u64>,
+    /// Cron job definitions for this agent.
+    pub cron: Vec<CronDef>,
+    /// Feed watchers for this agent.
+    pub feeds: Vec<FeedDef>,
+    /// Scheduled channel digests for this agent.
+    pub digests: Vec<DigestDef>,
+    /// Personas conversations can switch to with `/persona`.
+    pub personas: Vec<PersonaDef>,
+    /// Experiment flags, replacing the defaults' flags with the same name.
+    pub flags: Vec<FlagDef>,
+    /// Automatic retrieval before each turn. None disables it.
+    pub retrieval: Option<RetrievalConfig>,
+    /// Knowledge sources synced into this agent's memory.
+    pub knowledge: Vec<KnowledgeSourceDef>,
+}
+
+/// A cron job definition from config.
+#[derive(Debug, Clone)]
+pub struct CronDef {
+    pub id: String,
+    pub prompt: String,
+    pub interval_secs: u64,
+    /// Delivery target in "adapter:target" format (e.g. "discord:123456789").
+    pub delivery_target: String,
+    /// Optional active hours window (start_hour, end_hour) in 24h format.
+    pub active_hours: Option<(u8, u8)>,
+    pub enabled: bool,
+}
+
+/// A feed watcher from config. Its feeds are polled every `interval_secs`,
+/// and the entries not seen before run `prompt` as one batch.
+#[derive(Debug, Clone)]
+pub struct FeedDef {
+    pub id: String,
+    /// RSS, Atom or JSON Feed URLs. New entries from all of them are batched
+    /// together.
+    pub urls: Vec<String>,
+    pub interval_secs: u64,
+    /// What to do with the new entries, which are appended to it.
+    pub prompt: String,
+    /// Delivery target in "adapter:target" format (e.g. "discord:123456789").
+    pub delivery_target: String,
+    /// Most entries in one batch. The newest are kept.
+    pub max_items: usize,
+    /// Summarize the entries with a model call before the agent sees them.
+    pub summarize: bool,
+    pub enabled: bool,
+}
+
+/// A scheduled digest from config. Every `interval_secs`, the last `hours`
+/// of `channel` are fetched from the platform, summarized into decisions,
+/// action items and links, and posted to `delivery_target`.
+#[derive(Debug, Clone)]
+pub struct DigestDef {
+    pub id: String,
+    /// Channel to digest, in "adapter:target" format (e.g.
+    /// "slack:C0123456789").
+    pub channel: String,
+    /// Where the digest is posted, in the same format. Defaults to `channel`.
+    pub delivery_target: String,
+    /// How far back each digest looks.
+    pub hours: u64,
+    pub interval_secs: u64,
+    /// Optional active hours window (start_hour, end_hour) in 24h format.
+    pub active_hours: Option<(u8, u8)>,
+    /// Most messages fetched for one digest. The newest are kept.
+    pub max_messages: usize,
+    /// Prime the provider's prompt cache with the digest prompts shortly
+    /// before the digest runs, and send them marked cacheable.
+    pub prime_cache: bool,
+    pub enabled: bool,
+}
+
+/// A persona from config. A conversation switched to it with `/persona`
+/// gets its preamble in the channel prompt, only its tools, and its model.
+#[derive(Debug, Clone)]
+pub struct PersonaDef {
+    pub name: String,
+    /// One line shown when listing personas.
+    pub description: String,
+    /// Instructions added to the channel prompt.
+    pub preamble: String,
+    /// Channel tools the persona may use. `reply` is always available. None
+    /// allows them all.
+    pub tools: Option<Vec<String>>,
+    /// Model answering the persona's turns: a model name, or a routing tier
+    /// (`channel`, `branch`, `worker`, `compactor`, `cortex` or a task type
+    /// such as `coding`). None keeps the channel model.
+    pub model: Option<String>,
+    /// Only admins may switch to it.
+    pub admin_only: bool,
+}
+
+/// A knowledge source from config. Every `interval_secs` it's re-crawled,
+/// and its chunks are stored as memories tagged with `store`: new and
+/// changed chunks are embedded, and chunks that are gone are deleted.
+#[derive(Debug, Clone)]
+pub struct KnowledgeSourceDef {
+    pub id: String,
+    pub kind: KnowledgeSourceKind,
+    /// Git remote, Confluence site (e.g. `https://acme.atlassian.net/wiki`)
+    /// or sitemap URL. Unused for Notion, which syncs every page shared with
+    /// the integration.
+    pub url: String,
+    /// Git branch. None uses the remote's default branch.
+    pub branch: Option<String>,
+    /// Git only: path prefixes to include. Empty includes the whole repo.
+    pub paths: Vec<String>,
+    /// Notion integration token, Confluence API token, or a git token for
+    /// private HTTPS remotes.
+    pub token: Option<String>,
+    /// Confluence Cloud account email, sent with `token`. Without it the
+    /// token is sent as a Data Center personal access token.
+    pub email: Option<String>,
+    /// Confluence space key. None syncs every space the token can read.
+    pub space: Option<String>,
+    /// Memory source the chunks are saved under, which is what
+    /// `[agents.retrieval] store` selects.
+    pub store: String,
+    pub interval_secs: u64,
+    /// Characters per chunk.
+    pub chunk_size: usize,
+    /// Most documents taken from one crawl.
+    pub max_documents: usize,
+    pub enabled: bool,
+}
+
+/// Where a knowledge source's documents come from.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Deserialize, serde::Serialize, schemars::JsonSchema,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum KnowledgeSourceKind {
+    /// Text files in a git repository.
+    Git,
+    /// Pages shared with a Notion integration.
+    Notion,
+    /// Pages in a Confluence site, or one of its spaces.
+    Confluence,
+    /// Pages listed in a website's sitemap.
+    Sitemap,
+}
+
+impl KnowledgeSourceKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Git => "git",
+            Self::Notion => "notion",
+            Self::Confluence => "confluence",
+            Self::Sitemap => "sitemap",
+        }
+    }
+}
+
+/// Fully resolved agent config (merged with defaults, paths resolved).
+#[derive(Debug, Clone)]
+pub struct ResolvedAgentConfig {
+    pub id: String,
+    pub description: Option<String>,
+    pub workspace: PathBuf,
+    pub data_dir: PathBuf,
+    pub archives_dir: PathBuf,
+    pub routing: RoutingConfig,
+    pub max_concurrent_branches: usize,
+    pub max_concurrent_workers: usize,
+    pub max_turns: usize,
+    pub branch_max_turns: usize,
+    pub context_window: usize,
+    pub compaction: CompactionConfig,
+    pub memory_persistence: MemoryPersistenceConfig,
+    pub coalesce: CoalesceConfig,
+    pub ingestion: IngestionConfig,
+    pub cortex: CortexConfig,
+    pub browser: BrowserConfig,
+    pub tool_filter: ToolFilterConfig,
+    pub compression: CompressionConfig,
+    pub preview: PreviewConfig,
+    pub tool_guard: ToolGuardConfig,
+    pub cost_gate: CostGateConfig,
+    pub computer_use: ComputerUseConfig,
+    pub ocr: OcrConfig,
+    pub image_generation: ImageGenerationConfig,
+    pub home_assistant: HomeAssistantConfig,
+    pub issues: IssuesConfig,
+    pub sql: SqlConfig,
+    pub http: HttpConfig,
+    pub kubernetes: KubernetesConfig,
+    pub observability: ObservabilityConfig,
+    pub incidents: IncidentsConfig,
+    pub storage: StorageConfig,
+    pub rate_limit: RateLimitConfig,
+    pub dedup: DedupConfig,
+    pub flags: FlagSet,
+    pub loop_detection: LoopDetectionConfig,
+    pub usage_anomalies: UsageAnomalyConfig,
+    pub budget: BudgetConfig,
+    pub fact_check: FactCheckConfig,
+    pub provenance: ProvenanceConfig,
+    pub escalation: EscalationConfig,
+    pub generation: GenerationConfig,
+    pub output_caps: OutputCapsConfig,
+    pub empty_response: EmptyResponseConfig,
+    pub self_report: SelfReportConfig,
+    pub latency_budget: LatencyBudgetConfig,
+    pub usage_reports: UsageReportConfig,
+    pub retention: RetentionConfig,
+    pub language: LanguageConfig,
+    pub network: NetworkConfig,
+    pub threads: ThreadConfig,
+    pub commands: CommandsConfig,
+    pub admin_users: Vec<String>,
+    pub brave_search_key: Option<String>,
+    pub seed: Option<u64>,
+    /// Number of messages to fetch from the platform when a new channel is created.
+    pub history_backfill_count: usize,
+    pub cron: Vec<CronDef>,
+    pub feeds: Vec<FeedDef>,
+    pub digests: Vec<DigestDef>,
+    pub personas: Vec<PersonaDef>,
+    pub retrieval: Option<RetrievalConfig>,
+    pub knowledge: Vec<KnowledgeSourceDef>,
+}
+
+impl Default for DefaultsConfig {
+    fn default() -> Self {
+        Self {
+            routing: RoutingConfig::default(),
+            max_concurrent_branches: 5,
+            max_concurrent_workers: 5,
+            max_turns: 5,
+            branch_max_turns: 50,
+            context_window: 128_000,
+            compaction: CompactionConfig::default(),
+            memory_persistence: MemoryPersistenceConfig::default(),
+            coalesce: CoalesceConfig::default(),
+            ingestion: IngestionConfig::default(),
+            cortex: CortexConfig::default(),
+            browser: BrowserConfig::default(),
+            tool_filter: ToolFilterConfig::default(),
+            compression: CompressionConfig::default(),
+            preview: PreviewConfig::default(),
+            tool_guard: ToolGuardConfig::default(),
+            cost_gate: CostGateConfig::default(),
+            computer_use: ComputerUseConfig::default(),
+            ocr: OcrConfig::default(),
+            image_generation: ImageGenerationConfig::default(),
+            home_assistant: HomeAssistantConfig::default(),
+            issues: IssuesConfig::default(),
+            sql: SqlConfig::default(),
+            http: HttpConfig::default(),
+            kubernetes: KubernetesConfig::default(),
+            observability: ObservabilityConfig::default(),
+            incidents: IncidentsConfig::default(),
+            storage: StorageConfig::default(),
+            rate_limit: RateLimitConfig::default(),
+            dedup: DedupConfig::default(),
+            flags: Vec::new(),
+            loop_detection: LoopDetectionConfig::default(),
+            usage_anomalies: UsageAnomalyConfig::default(),
+            budget: BudgetConfig::default(),
+            fact_check: FactCheckConfig::default(),
+            provenance: ProvenanceConfig::default(),
+            escalation: EscalationConfig::default(),
+            generation: GenerationConfig::default(),
+            output_caps: OutputCapsConfig::default(),
+            empty_response: EmptyResponseConfig::default(),
+            self_report: SelfReportConfig::default(),
+            latency_budget: LatencyBudgetConfig::default(),
+            usage_reports: UsageReportConfig::default(),
+            retention: RetentionConfig::default(),
+            language: LanguageConfig::default(),
+            network: NetworkConfig::default(),
+            threads: ThreadConfig::default(),
+            commands: CommandsConfig::default(),
+            admin_users: Vec::new(),
+            brave_search_key: None,
+            seed: None,
+            history_backfill_count: 50,
+            cron: Vec::new(),
+            opencode: OpenCodeConfig::default(),
+            worker_log_mode: crate::settings::WorkerLogMode::default(),
+        }
+    }
+}
+
+impl AgentConfig {
+    /// Resolve this agent config against instance defaults and base paths.
+    pub fn resolve(&self, instance_dir: &Path, defaults: &DefaultsConfig) -> ResolvedAgentConfig {
+        let agent_root = instance_dir.join("agents").join(&self.id);
+
+        ResolvedAgentConfig {
+            id: self.id.clone(),
+            description: self.description.clone(),
+            workspace: self
+                .workspace
+                .clone()
+                .unwrap_or_else(|| agent_root.join("workspace")),
+            data_dir: agent_root.join("data"),
+            archives_dir: agent_root.join("archives"),
+            routing: self
+                .routing
+                .clone()
+                .unwrap_or_else(|| defaults.routing.clone()),
+            max_concurrent_branches: self
+                .max_concurrent_branches
+                .unwrap_or(defaults.max_concurrent_branches),
+            max_concurrent_workers: self
+                .max_concurrent_workers
+                .unwrap_or(defaults.max_concurrent_workers),
+            max_turns: self.max_turns.unwrap_or(defaults.max_turns),
+            branch_max_turns: self.branch_max_turns.unwrap_or(defaults.branch_max_turns),
+            context_window: self.context_window.unwrap_or(defaults.context_window),
+            compaction: self.compaction.unwrap_or(defaults.compaction),
+            memory_persistence: self
+                .memory_persistence
+                .unwrap_or(defaults.memory_persistence),
+            coalesce: self.coalesce.unwrap_or(defaults.coalesce),
+            ingestion: self.ingestion.unwrap_or(defaults.ingestion),
+            cortex: self.cortex.unwrap_or(defaults.cortex),
+            browser: self
+                .browser
+                .clone()
+                .unwrap_or_else(|| defaults.browser.clone()),
+            tool_filter: self
+                .tool_filter
+                .clone()
+                .unwrap_or_else(|| defaults.tool_filter.clone()),
+            compression: self.compression.unwrap_or(defaults.compression),
+            preview: self
+                .preview
+                .clone()
+                .unwrap_or_else(|| defaults.preview.clone()),
+            tool_guard: self
+                .tool_guard
+                .clone()
+                .unwrap_or_else(|| defaults.tool_guard.clone()),
+            cost_gate: self.cost_gate.unwrap_or(defaults.cost_gate),
+            computer_use: self
+                .computer_use
+                .clone()
+                .unwrap_or_else(|| defaults.computer_use.clone()),
+            ocr: self.ocr.clone().unwrap_or_else(|| defaults.ocr.clone()),
+            image_generation: self
+                .image_generation
+                .clone()
+                .unwrap_or_else(|| defaults.image_generation.clone()),
+            home_assistant: self
+                .home_assistant
+                .clone()
+                .unwrap_or_else(|| defaults.home_assistant.clone()),
+            issues: self
+                .issues
+                .clone()
+                .unwrap_or_else(|| defaults.issues.clone()),
+            sql: self.sql.clone().unwrap_or_else(|| defaults.sql.clone()),
+            http: self.http.clone().unwrap_or_else(|| defaults.http.clone()),
+            kubernetes: self
+                .kubernetes
+                .clone()
+                .unwrap_or_else(|| defaults.kubernetes.clone()),
+            observability: self
+                .observability
+                .clone()
+                .unwrap_or_else(|| defaults.observability.clone()),
+            incidents: self
+                .incidents
+                .clone()
+                .unwrap_or_else(|| defaults.incidents.clone()),
+            storage: self
+                .storage
+                .clone()
+                .unwrap_or_else(|| defaults.storage.clone()),
+            rate_limit: self.rate_limit.unwrap_or(defaults.rate_limit),
+            dedup: self.dedup.unwrap_or(defaults.dedup),
+            flags: FlagSet::new(&defaults.flags, &self.flags),
+            loop_detection: self.loop_detection.unwrap_or(defaults.loop_detection),
+            usage_anomalies: self
+                .usage_anomalies
+                .clone()
+                .unwrap_or_else(|| defaults.usage_anomalies.clone()),
+            budget: self
+                .budget
+                .clone()
+                .unwrap_or_else(|| defaults.budget.clone()),
+            fact_check: self
+                .fact_check
+                .clone()
+                .unwrap_or_else(|| defaults.fact_check.clone()),
+            provenance: self
+                .provenance
+                .clone()
+                .unwrap_or_else(|| defaults.provenance.clone()),
+            escalation: self
+                .escalation
+                .clone()
+                .unwrap_or_else(|| defaults.escalation.clone()),
+            generation: self
+                .generation
+                .clone()
+                .unwrap_or_else(|| defaults.generation.clone()),
+            output_caps: self
+                .output_caps
+                .clone()
+                .unwrap_or_else(|| defaults.output_caps.clone()),
+            empty_response: self
+                .empty_response
+                .clone()
+                .unwrap_or_else(|| defaults.empty_response.clone()),
+            self_report: self
+                .self_report
+                .clone()
+                .unwrap_or_else(|| defaults.self_report.clone()),
+            latency_budget: self
+                .latency_budget
+                .clone()
+                .unwrap_or_else(|| defaults.latency_budget.clone()),
+            usage_reports: self
+                .usage_reports
+                .clone()
+                .unwrap_or_else(|| defaults.usage_reports.clone()),
+            retention: self
+                .retention
+                .clone()
+                .unwrap_or_else(|| defaults.retention.clone()),
+            language: self
+                .language
+                .clone()
+                .unwrap_or_else(|| defaults.language.clone()),
+            network: self
+                .network
+                .clone()
+                .unwrap_or_else(|| defaults.network.clone()),
+            threads: self
+                .threads
+                .clone()
+                .unwrap_or_else(|| defaults.threads.clone()),
+            commands: self
+                .commands
+                .clone()
+                .unwrap_or_else(|| defaults.commands.clone()),
+            admin_users: self
+                .admin_users
+                .clone()
+                .unwrap_or_else(|| defaults.admin_users.clone()),
+            brave_search_key: self
+                .brave_search_key
+                .clone()
+                .or_else(|| defaults.brave_search_key.clone()),
+            seed: self.seed.or(defaults.seed),
+            history_backfill_count: defaults.history_backfill_count,
+            cron: self.cron.clone(),
+            feeds: self.feeds.clone(),
+            digests: self.digests.clone(),
+            personas: self.personas.clone(),
+            retrieval: self.retrieval.clone(),
+            knowledge: self.knowledge.clone(),
+        }
+    }
+}
+
+impl ResolvedAgentConfig {
+    pub fn sqlite_path(&self) -> PathBuf {
+        self.data_dir.join("spacebot.db")
+    }
+    pub fn lancedb_path(&self) -> PathBuf {
+        self.data_dir.join("lancedb")
+    }
+    pub fn redb_path(&self) -> PathBuf {
+        self.data_dir.join("config.redb")
+    }
+    pub fn history_backfill_count(&self) -> usize {
+        self.history_backfill_count
+    }
+    /// Resolved screenshot directory, falling back to data_dir/screenshots.
+    pub fn screenshot_dir(&self) -> PathBuf {
+        self.browser
+            .screenshot_dir
+            .clone()
+            .unwrap_or_else(|| self.data_dir.join("screenshots"))
+    }
+
+    /// Directory for worker execution logs written on failure.
+    pub fn logs_dir(&self) -> PathBuf {
+        self.data_dir.join("logs")
+    }
+
+    /// Path to agent workspace skills directory.
+    pub fn skills_dir(&self) -> PathBuf {
+        self.workspace.join("skills")
+    }
+
+    /// Path to agent workspace prompt library directory.
+    pub fn prompt_library_dir(&self) -> PathBuf {
+        self.workspace.join("prompts")
+    }
+
+    /// Path to the memory ingestion directory where users drop files.
+    pub fn ingest_dir(&self) -> PathBuf {
+        self.workspace.join("ingest")
+    }
+
+    /// Directory holding checkouts of git knowledge sources.
+    pub fn knowledge_dir(&self) -> PathBuf {
+        self.data_dir.join("knowledge")
+    }
+}
+
+/// Routes a messaging platform conversation to a specific agent.
+#[derive(Debug, Clone)]
+pub struct Binding {
+    pub agent_id: String,
+    pub channel: String,
+    pub guild_id: Option<String>,
+    pub workspace_id: Option<String>, // Slack workspace (team) ID
+    pub chat_id: Option<String>,
+    /// Channel IDs this binding applies to. If empty, all channels in the guild/workspace are allowed.
+    pub channel_ids: Vec<String>,
+    /// User IDs allowed to DM the bot through this binding.
+    pub dm_allowed_users: Vec<String>,
+    /// Rewrites applied, in order, to replies in matching conversations.
+    pub post_processors: Vec<PostProcessor>,
+    /// Footer added to replies in matching conversations.
+    pub disclosure: Option<Disclosure>,
+    /// Which messages in matching conversations the bot answers. None
+    /// answers every message.
+    pub activation: Option<ActivationPolicy>,
+}
+
+impl Binding {
+    /// Check if this binding matches an inbound message.
+    fn matches(&self, message: &crate::InboundMessage) -> bool {
+        if self.channel != message.source {
+            return false;
+        }
+
+        // DM messages have no guild_id — match if the sender is in dm_allowed_users
+        let is_dm =
+            message.metadata.get("discord_guild_id").is_none() && message.source == "discord";
+        if is_dm {
+            return !self.dm_allowed_users.is_empty()
+                && self.dm_allowed_users.contains(&message.sender_id);
+        }
+
+        if let Some(guild_id) = &self.guild_id {
+            let message_guild = message
+                .metadata
+                .get("discord_guild_id")
+                .and_then(|v| v.as_u64())
+                .map(|v| v.to_string());
+            if message_guild.as_deref() != Some(guild_id) {
+                return false;
+            }
+        }
+
+        if let Some(workspace_id) = &self.workspace_id {
+            let message_workspace = message
+                .metadata
+                .get("slack_workspace_id")
+                .and_then(|v| v.as_str());
+            if message_workspace != Some(workspace_id) {
+                return false;
+            }
+        }
+
+        if !self.channel_ids.is_empty() {
+            let message_channel = message
+                .metadata
+                .get("discord_channel_id")
+                .and_then(|v| v.as_u64())
+                .map(|v| v.to_string());
+            let parent_channel = message
+                .metadata
+                .get("discord_parent_channel_id")
+                .and_then(|v| v.as_u64())
+                .map(|v| v.to_string());
+
+            // Also check Slack channel IDs, IRC channel names and XMPP rooms
+            let slack_channel = message
+                .metadata
+                .get("slack_channel_id")
+                .and_then(|v| v.as_str());
+            let named_channel = message
+                .metadata
+                .get("irc_channel")
+                .or_else(|| message.metadata.get("xmpp_room"))
+                .and_then(|v| v.as_str());
+
+            let direct_match = message_channel
+                .as_ref()
+                .is_some_and(|id| self.channel_ids.contains(id))
+                || slack_channel.is_some_and(|id| self.channel_ids.contains(&id.to_string()))
+                || named_channel.is_some_and(|name| {
+                    self.channel_ids
+                        .iter()
+                        .any(|id| id.eq_ignore_ascii_case(name))
+                });
+            let parent_match = parent_channel
+                .as_ref()
+                .is_some_and(|id| self.channel_ids.contains(id));
+
+            if !direct_match && !parent_match {
+                return false;
+            }
+        }
+
+        if let Some(chat_id) = &self.chat_id {
+            let message_chat = message
+                .metadata
+                .get("telegram_chat_id")
+                .and_then(|v| v.as_str());
+            if message_chat != Some(chat_id) {
+                return false;
+            }
+        }
+
+        true
+    }
+}
+
+/// The first binding that matches an inbound message, if any.
+pub fn resolve_binding_for_message<'a>(
+    bindings: &'a [Binding],
+    message: &crate::InboundMessage,
+) -> Option<&'a Binding> {
+    bindings.iter().find(|binding| binding.matches(message))
+}
+
+/// Resolve which agent should handle an inbound message.
+///
+/// Checks bindings in order. First match wins. Falls back to the default
+/// agent if no binding matches.
+pub fn resolve_agent_for_message(
+    bindings: &[Binding],
+    message: &crate::InboundMessage,
+    default_agent_id: &str,
+) -> crate::AgentId {
+    match resolve_binding_for_message(bindings, message) {
+        Some(binding) => std::sync::Arc::from(binding.agent_id.as_str()),
+        None => std::sync::Arc::from(default_agent_id),
+    }
+}
+
+/// Messaging platform credentials (instance-level).
+#[derive(Debug, Clone, Default)]
+pub struct MessagingConfig {
+    pub discord: Option<DiscordConfig>,
+    pub slack: Option<SlackConfig>,
+    pub telegram: Option<TelegramConfig>,
+    pub irc: Option<IrcConfig>,
+    pub xmpp: Option<XmppConfig>,
+    pub notify: Option<NotifyConfig>,
+    pub twilio: Option<TwilioConfig>,
+    pub webhook: Option<WebhookConfig>,
+}
+
+#[derive(Debug, Clone)]
+pub struct DiscordConfig {
+    pub enabled: bool,
+    pub token: String,
+    /// User IDs allowed to DM the bot. If empty, DMs are ignored entirely.
+    pub dm_allowed_users: Vec<String>,
+    /// Whether to process messages from other bots (self-messages are always ignored).
+    pub allow_bot_messages: bool,
+    /// Voice channel support. `None` when voice is disabled.
+    pub voice: Option<DiscordVoiceConfig>,
+}
+
+/// Listening and speaking in Discord voice channels.
+#[derive(Debug, Clone)]
+pub struct DiscordVoiceConfig {
+    /// Voice channel IDs to join on startup.
+    pub channels: Vec<String>,
+    /// Phrases that address the bot. Only transcripts that start with one
+    /// are passed to the agent. If empty, everything said is passed on.
+    pub activation_keywords: Vec<String>,
+    /// Silence that ends an utterance, in milliseconds.
+    pub silence_ms: u64,
+    pub mode: VoiceMode,
+    pub live: LiveTranscriptionConfig,
+    pub speech: SpeechConfig,
+}
+
+/// How the bot takes part in a voice channel.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, serde::Serialize, schemars::JsonSchema,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum VoiceMode {
+    /// Answer out loud when addressed with an activation keyword.
+    #[default]
+    Conversation,
+    /// Keep a running transcript and wake the agent, in text, when a
+    /// trigger phrase comes up.
+    Live,
+}
+
+/// Live transcription: a rolling transcript with trigger phrases.
+#[derive(Debug, Clone)]
+pub struct LiveTranscriptionConfig {
+    /// Phrases that wake the agent wherever they're said.
+    pub triggers: Vec<String>,
+    /// How much of the transcript is kept and shown to the agent, in seconds.
+    pub window_secs: u64,
+    /// Minimum time between two wakes for the same trigger, in seconds.
+    pub cooldown_secs: u64,
+}
+
+/// An OpenAI-compatible speech API for speech-to-text and text-to-speech.
+#[derive(Debug, Clone)]
+pub struct SpeechConfig {
+    pub base_url: String,
+    pub api_key: String,
+    /// Transcription model, e.g. `whisper-1`.
+    pub stt_model: String,
+    /// Speech synthesis model, e.g. `tts-1`.
+    pub tts_model: String,
+    pub tts_voice: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct SlackConfig {
+    pub enabled: bool,
+    pub bot_token: String,
+    pub app_token: String,
+    /// User IDs allowed to DM the bot. If empty, DMs are ignored entirely.
+    pub dm_allowed_users: Vec<String>,
+}
+
+/// Hot-reloadable Discord permission filters.
+///
+/// Derived from bindings + discord config. Shared with the Discord adapter
+/// via `Arc<ArcSwap<..>>` so the file watcher can swap in new values without
+/// restarting the gateway connection.
+#[derive(Debug, Clone, Default)]
+pub struct DiscordPermissions {
+    pub guild_filter: Option<Vec<u64>>,
+    pub channel_filter: std::collections::HashMap<u64, Vec<u64>>,
+    pub dm_allowed_users: Vec<u64>,
+    pub allow_bot_messages: bool,
+}
+
+/// Hot-reloadable Slack permission filters.
+///
+/// Shared with the Slack adapter via `Arc<ArcSwap<..>>` for hot-reloading.
+#[derive(Debug, Clone, Default)]
+pub struct SlackPermissions {
+    pub workspace_filter: Option<Vec<String>>, // team IDs
+    pub channel_filter: std::collections::HashMap<String, Vec<String>>, // team_id -> allowed channel_ids
+    pub dm_allowed_users: Vec<String>,                                  // user IDs
+}
+
+impl SlackPermissions {
+    /// Build from the current config's slack settings and bindings.
+    pub fn from_config(slack: &SlackConfig, bindings: &[Binding]) -> Self {
+        let slack_bindings: Vec<&Binding> =
+            bindings.iter().filter(|b| b.channel == "slack").collect();
+
+        let workspace_filter = {
+            let workspace_ids: Vec<String> = slack_bindings
+                .iter()
+                .filter_map(|b| b.workspace_id.clone())
+                .collect();
+            if workspace_ids.is_empty() {
+                None
+            } else {
+                Some(workspace_ids)
+            }
+        };
+
+        let channel_filter = {
+            let mut filter: std::collections::HashMap<String, Vec<String>> =
+                std::collections::HashMap::new();
+            for binding in &slack_bindings {
+                if let Some(workspace_id) = &binding.workspace_id {
+                    if !binding.channel_ids.is_empty() {
+                        filter
+                            .entry(workspace_id.clone())
+                            .or_default()
+                            .extend(binding.channel_ids.clone());
+                    }
+                }
+            }
+            filter
+        };
+
+        let dm_allowed_users = slack.dm_allowed_users.clone();
+
+        Self {
+            workspace_filter,
+            channel_filter,
+            dm_allowed_users,
+        }
+    }
+}
+
+impl DiscordPermissions {
+    /// Build from the current config's discord settings and bindings.
+    pub fn from_config(discord: &DiscordConfig, bindings: &[Binding]) -> Self {
+        let discord_bindings: Vec<&Binding> =
+            bindings.iter().filter(|b| b.channel == "discord").collect();
+
+        let guild_filter = {
+            let guild_ids: Vec<u64> = discord_bindings
+                .iter()
+                .filter_map(|b| b.guild_id.as_ref()?.parse::<u64>().ok())
+                .collect();
+            if guild_ids.is_empty() {
+                None
+            } else {
+                Some(guild_ids)
+            }
+        };
+
+        let channel_filter = {
+            let mut filter: std::collections::HashMap<u64, Vec<u64>> =
+                std::collections::HashMap::new();
+            for binding in &discord_bindings {
+                if let Some(guild_id) = binding
+                    .guild_id
+                    .as_ref()
+                    .and_then(|g| g.parse::<u64>().ok())
+                {
+                    if !binding.channel_ids.is_empty() {
+                        let channel_ids: Vec<u64> = binding
+                            .channel_ids
+                            .iter()
+                            .filter_map(|id| id.parse::<u64>().ok())
+                            .collect();
+                        filter.entry(guild_id).or_default().extend(channel_ids);
+                    }
+                }
+            }
+            filter
+        };
+
+        let mut dm_allowed_users: Vec<u64> = discord
+            .dm_allowed_users
+            .iter()
+            .filter_map(|id| id.parse::<u64>().ok())
+            .collect();
+
+        // Also collect dm_allowed_users from bindings
+        for binding in &discord_bindings {
+            for id in &binding.dm_allowed_users {
+                if let Ok(uid) = id.parse::<u64>() {
+                    if !dm_allowed_users.contains(&uid) {
+                        dm_allowed_users.push(uid);
+                    }
+                }
+            }
+        }
+
+        Self {
+            guild_filter,
+            channel_filter,
+            dm_allowed_users,
+            allow_bot_messages: discord.allow_bot_messages,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TelegramConfig {
+    pub enabled: bool,
+    pub token: String,
+    /// User IDs allowed to DM the bot. If empty, DMs are ignored entirely.
+    pub dm_allowed_users: Vec<String>,
+}
+
+/// Hot-reloadable Telegram permission filters.
+///
+/// Shared with the Telegram adapter via `Arc<ArcSwap<..>>` for hot-reloading.
+#[derive(Debug, Clone, Default)]
+pub struct TelegramPermissions {
+    /// Allowed chat IDs (None = all chats accepted).
+    pub chat_filter: Option<Vec<i64>>,
+    /// User IDs allowed in private chats.
+    pub dm_allowed_users: Vec<i64>,
+}
+
+impl TelegramPermissions {
+    /// Build from the current config's telegram settings and bindings.
+    pub fn from_config(telegram: &TelegramConfig, bindings: &[Binding]) -> Self {
+        let telegram_bindings: Vec<&Binding> = bindings
+            .iter()
+            .filter(|b| b.channel == "telegram")
+            .collect();
+
+        let chat_filter = {
+            let chat_ids: Vec<i64> = telegram_bindings
+                .iter()
+                .filter_map(|b| b.chat_id.as_ref()?.parse::<i64>().ok())
+                .collect();
+            if chat_ids.is_empty() {
+                None
+            } else {
+                Some(chat_ids)
+            }
+        };
+
+        let mut dm_allowed_users: Vec<i64> = telegram
+            .dm_allowed_users
+            .iter()
+            .filter_map(|id| id.parse::<i64>().ok())
+            .collect();
+
+        for binding in &telegram_bindings {
+            for id in &binding.dm_allowed_users {
+                if let Ok(uid) = id.parse::<i64>() {
+                    if !dm_allowed_users.contains(&uid) {
+                        dm_allowed_users.push(uid);
+                    }
+                }
+            }
+        }
+
+        Self {
+            chat_filter,
+            dm_allowed_users,
+        }
+    }
+}
+
+/// A connection to one IRC network.
+#[derive(Debug, Clone)]
+pub struct IrcConfig {
+    pub enabled: bool,
+    pub server: String,
+    pub port: u16,
+    pub tls: bool,
+    pub nickname: String,
+    pub username: String,
+    pub realname: String,
+    /// Server password, sent with PASS.
+    pub password: Option<String>,
+    /// Account to log in to with SASL PLAIN. Defaults to the nickname.
+    pub sasl_username: Option<String>,
+    /// Password for SASL PLAIN. SASL is skipped when unset.
+    pub sasl_password: Option<String>,
+    /// Channels to join, e.g. "#ops".
+    pub channels: Vec<String>,
+    /// Nicks allowed to message the bot directly. If empty, queries are
+    /// ignored entirely.
+    pub dm_allowed_users: Vec<String>,
+    /// Lines that can be sent back to back before throttling kicks in.
+    pub flood_burst: u32,
+    /// Delay between lines once the burst is used up.
+    pub flood_interval_ms: u64,
+}
+
+/// An XMPP account, in rooms (MUC) and one-on-one chats.
+#[derive(Debug, Clone)]
+pub struct XmppConfig {
+    pub enabled: bool,
+    /// Bare JID of the bot's account, e.g. "spacebot@example.com".
+    pub jid: String,
+    pub password: String,
+    /// Nick to use in rooms.
+    pub nickname: String,
+    /// Bare JIDs of rooms to join, e.g. "ops@conference.example.com".
+    pub rooms: Vec<String>,
+    /// Bare JIDs allowed to chat with the bot one-on-one. An entry without
+    /// `@` allows everyone on that domain. If empty, one-on-one chats are
+    /// ignored.
+    pub dm_allowed_users: Vec<String>,
+}
+
+/// Local output for single-machine setups: desktop notifications and an
+/// inbox file. Delivery only, nothing is read back.
+#[derive(Debug, Clone)]
+pub struct NotifyConfig {
+    pub enabled: bool,
+    /// Show a desktop notification for each delivery. Needs the
+    /// `desktop-notifications` build feature.
+    pub desktop: bool,
+    /// Markdown file every delivery is appended to.
+    pub inbox: PathBuf,
+    /// Application name shown on notifications.
+    pub app_name: String,
+}
+
+/// SMS and WhatsApp through Twilio.
+///
+/// Twilio POSTs inbound messages to `{public_url}/sms`; replies go out
+/// through the Messages API. Each phone number (and each WhatsApp number)
+/// is its own conversation.
+#[derive(Debug, Clone)]
+pub struct TwilioConfig {
+    pub enabled: bool,
+    pub account_sid: String,
+    pub auth_token: String,
+    /// Number SMS replies are sent from, in E.164 format ("+15551234567").
+    pub sms_number: Option<String>,
+    /// WhatsApp sender number, in E.164 format.
+    pub whatsapp_number: Option<String>,
+    pub port: u16,
+    pub bind: String,
+    /// Public base URL of this server as Twilio reaches it. Request
+    /// signatures are checked against it, and outbound media is served
+    /// from it.
+    pub public_url: String,
+    /// Numbers allowed to message the agent. If empty, anyone can.
+    pub allowed_numbers: Vec<String>,
+    /// Most SMS segments one reply may use. Longer replies are cut short.
+    pub max_sms_segments: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct WebhookConfig {
+    /// Whether the inbound HTTP server runs.
+    pub enabled: bool,
+    pub port: u16,
+    pub bind: String,
+    /// Endpoints replies are POSTed to. Each one is a delivery target,
+    /// `webhook:{name}`, and works without the inbound server.
+    pub outbound: Vec<OutboundWebhookConfig>,
+}
+
+/// An endpoint that receives agent replies as JSON POST requests.
+#[derive(Debug, Clone)]
+pub struct OutboundWebhookConfig {
+    pub name: String,
+    pub url: String,
+    /// minijinja template for the request body, which must render to JSON.
+    /// Without one the body is the payload fields as a JSON object.
+    pub template: Option<String>,
+    /// Retries after a network error, a 429 or a 5xx response.
+    pub max_retries: u32,
+    /// Sign each request body with a shared secret.
+    pub signing: Option<HmacSigningConfig>,
+}
+
+// -- TOML deserialization types --
+
+#[derive(Deserialize, schemars::JsonSchema)]
+#[schemars(title = "Spacebot config.toml")]
+struct TomlConfig {
+    #[serde(default)]
+    llm: TomlLlmConfig,
+    #[serde(default)]
+    defaults: TomlDefaultsConfig,
+    #[serde(default)]
+    agents: Vec<TomlAgentConfig>,
+    #[serde(default)]
+    messaging: TomlMessagingConfig,
+    #[serde(default)]
+    bindings: Vec<TomlBinding>,
+    #[serde(default)]
+    api: TomlApiConfig,
+    #[serde(default)]
+    telemetry: TomlTelemetryConfig,
+    #[serde(default)]
+    intake: TomlIntakeConfig,
+    #[serde(default)]
+    topics: TomlTopicsConfig,
+    #[serde(default)]
+    scripting: TomlScriptingConfig,
+}
+
+#[derive(Deserialize, Default, schemars::JsonSchema)]
+struct TomlIntakeConfig {
+    #[serde(default)]
+    enabled: bool,
+    #[serde(default)]
+    method: IntakeMethod,
+    model: Option<String>,
+    min_confidence: Option<f32>,
+    default_agent: Option<String>,
+}
+
+#[derive(Deserialize, Default, schemars::JsonSchema)]
+struct TomlTopicsConfig {
+    #[serde(default)]
+    enabled: bool,
+    #[serde(default)]
+    method: TopicMethod,
+    model: Option<String>,
+    #[serde(default)]
+    categories: Vec<TopicCategory>,
+}
+
+#[derive(Deserialize, Default, schemars::JsonSchema)]
+struct TomlScriptingConfig {
+    #[serde(default)]
+    enabled: bool,
+    #[serde(default)]
+    hooks: Vec<String>,
+    #[serde(default)]
+    commands: HashMap<String, String>,
+    max_operations: Option<u64>,
+}
+
+#[derive(Deserialize, Default, schemars::JsonSchema)]
+struct TomlTelemetryConfig {
+    #[serde(default)]
+    crash_reporting: bool,
+    dsn: Option<String>,
+    environment: Option<String>,
+    snapshot_interval_secs: Option<u64>,
+}
+
+#[derive(Deserialize, schemars::JsonSchema)]
+struct TomlApiConfig {
+    #[serde(default = "default_api_enabled")]
+    enabled: bool,
+    #[serde(default = "default_api_port")]
+    port: u16,
+    #[serde(default = "default_api_bind")]
+    bind: String,
+}
+
+impl Default for TomlApiConfig {
+    fn default() -> Self {
+        Self {
+            enabled: default_api_enabled(),
+            port: default_api_port(),
+            bind: default_api_bind(),
+        }
+    }
+}
+
+fn default_api_enabled() -> bool {
+    true
+}
+fn default_api_port() -> u16 {
+    19898
+}
+fn default_api_bind() -> String {
+    "127.0.0.1".into()
+}
+
+#[derive(Deserialize, Default, schemars::JsonSchema)]
+struct TomlLlmConfig {
+    anthropic_key: Option<String>,
+    openai_key: Option<String>,
+    openrouter_key: Option<String>,
+    ollama_key: Option<String>,
+    zhipu_key: Option<String>,
+    groq_key: Option<String>,
+    together_key: Option<String>,
+    fireworks_key: Option<String>,
+    deepseek_key: Option<String>,
+    xai_key: Option<String>,
+    mistral_key: Option<String>,
+    opencode_zen_key: Option<String>,
+    openai_organization: Option<String>,
+    openai_project: Option<String>,
+    max_concurrent_requests: Option<usize>,
+    debug_recording: Option<bool>,
+    file_upload_threshold_kb: Option<usize>,
+    #[serde(default)]
+    secondary_keys: HashMap<String, String>,
+    #[serde(default)]
+    base_urls: HashMap<String, String>,
+    #[serde(default)]
+    regions: HashMap<String, TomlRegionalEndpointsConfig>,
+    health_check: Option<TomlHealthCheckConfig>,
+    outage: Option<TomlOutageConfig>,
+    key_check: Option<TomlKeyCheckConfig>,
+    #[serde(default)]
+    vllm_providers: Vec<String>,
+    #[serde(default)]
+    grammar_providers: Vec<String>,
+    #[serde(default)]
+    tag_metadata_providers: Vec<String>,
+    #[serde(default)]
+    request_signing: HashMap<String, HmacSigningConfig>,
+    #[serde(default)]
+    pricing: HashMap<String, ModelPricing>,
+    #[serde(default)]
+    tool_pricing: HashMap<String, f64>,
+    #[serde(default)]
+    faults: Vec<FaultRule>,
+    vault: Option<VaultConfig>,
+    aws_secrets: Option<AwsSecretsConfig>,
+}
+
+#[derive(Deserialize, schemars::JsonSchema)]
+struct TomlHealthCheckConfig {
+    interval_secs: Option<u64>,
+    max_queue_depth: Option<u64>,
+}
+
+#[derive(Deserialize, schemars::JsonSchema)]
+struct TomlRegionalEndpointsConfig {
+    #[serde(default)]
+    endpoints: Vec<String>,
+    stickiness_secs: Option<u64>,
+}
+
+#[derive(Deserialize, schemars::JsonSchema)]
+struct TomlKeyCheckConfig {
+    interval_secs: Option<u64>,
+    quota_warn_at: Option<f64>,
+    expiry_warn_days: Option<u32>,
+    /// RFC 3339 timestamps or `YYYY-MM-DD` dates, by provider id or
+    /// `<provider>:secondary`.
+    #[serde(default)]
+    expires: HashMap<String, String>,
+    #[serde(default)]
+    delivery_targets: Vec<String>,
+}
+
+#[derive(Deserialize, schemars::JsonSchema)]
+struct TomlOutageConfig {
+    window_secs: Option<u64>,
+    min_failures: Option<usize>,
+    error_rate: Option<f64>,
+    min_models: Option<usize>,
+    probe_interval_secs: Option<u64>,
+}
+
+#[derive(Deserialize, Default, schemars::JsonSchema)]
+struct TomlDefaultsConfig {
+    routing: Option<TomlRoutingConfig>,
+    max_concurrent_branches: Option<usize>,
+    max_concurrent_workers: Option<usize>,
+    max_turns: Option<usize>,
+    branch_max_turns: Option<usize>,
     context_window: Option<usize>,
     compaction: Option<TomlCompactionConfig>,
     memory_persistence: Option<TomlMemoryPersistenceConfig>,
@@ -896,78 +3393,1159 @@ struct TomlDefaultsConfig {
     ingestion: Option<TomlIngestionConfig>,
     cortex: Option<TomlCortexConfig>,
     browser: Option<TomlBrowserConfig>,
+    tool_filter: Option<TomlToolFilterConfig>,
+    compression: Option<TomlCompressionConfig>,
+    preview: Option<TomlPreviewConfig>,
+    tool_guard: Option<TomlToolGuardConfig>,
+    cost_gate: Option<TomlCostGateConfig>,
+    computer_use: Option<TomlComputerUseConfig>,
+    ocr: Option<TomlOcrConfig>,
+    image_generation: Option<TomlImageGenerationConfig>,
+    home_assistant: Option<TomlHomeAssistantConfig>,
+    issues: Option<TomlIssuesConfig>,
+    sql: Option<TomlSqlConfig>,
+    http: Option<TomlHttpConfig>,
+    kubernetes: Option<TomlKubernetesConfig>,
+    observability: Option<TomlObservabilityConfig>,
+    incidents: Option<TomlIncidentsConfig>,
+    storage: Option<TomlStorageConfig>,
+    rate_limit: Option<TomlRateLimitConfig>,
+    dedup: Option<TomlDedupConfig>,
+    #[serde(default)]
+    flags: Vec<FlagDef>,
+    loop_detection: Option<TomlLoopDetectionConfig>,
+    usage_anomalies: Option<TomlUsageAnomalyConfig>,
+    budget: Option<TomlBudgetConfig>,
+    fact_check: Option<TomlFactCheckConfig>,
+    provenance: Option<TomlProvenanceConfig>,
+    escalation: Option<TomlEscalationConfig>,
+    generation: Option<TomlGenerationConfig>,
+    output_caps: Option<TomlOutputCapsConfig>,
+    empty_response: Option<TomlEmptyResponseConfig>,
+    self_report: Option<TomlSelfReportConfig>,
+    latency_budget: Option<TomlLatencyBudgetConfig>,
+    usage_reports: Option<TomlUsageReportConfig>,
+    retention: Option<TomlRetentionConfig>,
+    language: Option<TomlLanguageConfig>,
+    network: Option<TomlNetworkConfig>,
+    threads: Option<TomlThreadConfig>,
+    commands: Option<TomlCommandsConfig>,
+    admin_users: Option<Vec<String>>,
     brave_search_key: Option<String>,
+    seed: Option<u64>,
     opencode: Option<TomlOpenCodeConfig>,
     worker_log_mode: Option<String>,
 }
 
-#[derive(Deserialize, Default)]
-struct TomlRoutingConfig {
-    channel: Option<String>,
-    branch: Option<String>,
-    worker: Option<String>,
-    compactor: Option<String>,
-    cortex: Option<String>,
-    rate_limit_cooldown_secs: Option<u64>,
+#[derive(Deserialize, Default, schemars::JsonSchema)]
+struct TomlRoutingConfig {
+    channel: Option<String>,
+    branch: Option<String>,
+    worker: Option<String>,
+    compactor: Option<String>,
+    cortex: Option<String>,
+    rate_limit_cooldown_secs: Option<u64>,
+    #[serde(default)]
+    task_overrides: HashMap<String, String>,
+    fallbacks: Option<HashMap<String, Vec<String>>>,
+    #[serde(default)]
+    vllm: HashMap<String, TomlVllmOptions>,
+    #[serde(default)]
+    anthropic_beta: HashMap<String, Vec<String>>,
+    #[serde(default)]
+    race: HashMap<String, TomlRaceConfig>,
+    #[serde(default)]
+    shadow: HashMap<String, TomlShadowConfig>,
+}
+
+#[derive(Deserialize, schemars::JsonSchema)]
+struct TomlRaceConfig {
+    #[serde(default)]
+    models: Vec<String>,
+    judge: Option<String>,
+}
+
+#[derive(Deserialize, schemars::JsonSchema)]
+struct TomlShadowConfig {
+    model: String,
+    sample_rate: Option<f64>,
+    judge: Option<String>,
+}
+
+#[derive(Deserialize, schemars::JsonSchema)]
+struct TomlVllmOptions {
+    /// A schema table, or a string holding a JSON schema.
+    #[schemars(with = "Option<serde_json::Value>")]
+    guided_json: Option<toml::Value>,
+    guided_regex: Option<String>,
+    lora_adapter: Option<String>,
+}
+
+#[derive(Deserialize, schemars::JsonSchema)]
+struct TomlMemoryPersistenceConfig {
+    enabled: Option<bool>,
+    message_interval: Option<usize>,
+}
+
+#[derive(Deserialize, schemars::JsonSchema)]
+struct TomlCoalesceConfig {
+    enabled: Option<bool>,
+    debounce_ms: Option<u64>,
+    max_wait_ms: Option<u64>,
+    min_messages: Option<usize>,
+    multi_user_only: Option<bool>,
+}
+
+#[derive(Deserialize, schemars::JsonSchema)]
+struct TomlIngestionConfig {
+    enabled: Option<bool>,
+    poll_interval_secs: Option<u64>,
+    chunk_size: Option<usize>,
+}
+
+#[derive(Deserialize, schemars::JsonSchema)]
+struct TomlCompactionConfig {
+    background_threshold: Option<f32>,
+    aggressive_threshold: Option<f32>,
+    emergency_threshold: Option<f32>,
+    eviction: Option<EvictionPolicy>,
+}
+
+#[derive(Deserialize, schemars::JsonSchema)]
+struct TomlCortexConfig {
+    tick_interval_secs: Option<u64>,
+    worker_timeout_secs: Option<u64>,
+    branch_timeout_secs: Option<u64>,
+    circuit_breaker_threshold: Option<u8>,
+    bulletin_interval_secs: Option<u64>,
+    bulletin_max_words: Option<usize>,
+    bulletin_max_turns: Option<usize>,
+    association_interval_secs: Option<u64>,
+    association_similarity_threshold: Option<f32>,
+    association_updates_threshold: Option<f32>,
+    association_max_per_pass: Option<usize>,
+}
+
+#[derive(Deserialize, schemars::JsonSchema)]
+struct TomlBrowserConfig {
+    enabled: Option<bool>,
+    headless: Option<bool>,
+    evaluate_enabled: Option<bool>,
+    executable_path: Option<String>,
+    screenshot_dir: Option<String>,
+}
+
+#[derive(Deserialize, schemars::JsonSchema)]
+struct TomlToolFilterConfig {
+    enabled: Option<bool>,
+    top_k: Option<usize>,
+    always_include: Option<Vec<String>>,
+}
+
+#[derive(Deserialize, schemars::JsonSchema)]
+struct TomlCompressionConfig {
+    enabled: Option<bool>,
+    ratio: Option<f32>,
+    min_chars: Option<usize>,
+}
+
+#[derive(Deserialize, schemars::JsonSchema)]
+struct TomlPreviewConfig {
+    enabled: Option<bool>,
+    tools: Option<Vec<String>>,
+    timeout_secs: Option<u64>,
+}
+
+#[derive(Deserialize, schemars::JsonSchema)]
+struct TomlToolGuardConfig {
+    enabled: Option<bool>,
+    tools: Option<Vec<String>>,
+    model: Option<String>,
+    block_below: Option<f32>,
+    confirm_below: Option<f32>,
+    timeout_secs: Option<u64>,
+}
+
+#[derive(Deserialize, schemars::JsonSchema)]
+struct TomlCostGateConfig {
+    enabled: Option<bool>,
+    threshold_usd: Option<f64>,
+    expected_completions: Option<u32>,
+    expected_output_tokens: Option<u64>,
+    timeout_secs: Option<u64>,
+}
+
+#[derive(Deserialize, schemars::JsonSchema)]
+struct TomlComputerUseConfig {
+    enabled: Option<bool>,
+    confirm: Option<ComputerConfirm>,
+    excluded_applications: Option<Vec<String>>,
+    display: Option<String>,
+}
+
+#[derive(Deserialize, schemars::JsonSchema)]
+struct TomlOcrConfig {
+    enabled: Option<bool>,
+    backend: Option<OcrBackend>,
+    fallback: Option<bool>,
+    model: Option<String>,
+    languages: Option<String>,
+}
+
+#[derive(Deserialize, schemars::JsonSchema)]
+struct TomlImageGenerationConfig {
+    enabled: Option<bool>,
+    provider: Option<String>,
+    model: Option<String>,
+    size: Option<String>,
+    max_images: Option<u32>,
+}
+
+impl TomlImageGenerationConfig {
+    fn resolve(self, base: &ImageGenerationConfig) -> ImageGenerationConfig {
+        ImageGenerationConfig {
+            enabled: self.enabled.unwrap_or(base.enabled),
+            provider: self.provider.unwrap_or_else(|| base.provider.clone()),
+            model: self.model.unwrap_or_else(|| base.model.clone()),
+            size: self.size.unwrap_or_else(|| base.size.clone()),
+            max_images: self.max_images.unwrap_or(base.max_images).max(1),
+        }
+    }
+}
+
+#[derive(Deserialize, schemars::JsonSchema)]
+struct TomlHomeAssistantConfig {
+    enabled: Option<bool>,
+    url: Option<String>,
+    token: Option<String>,
+    entities: Option<Vec<String>>,
+    triggers: Option<Vec<TomlHomeAssistantTrigger>>,
+}
+
+#[derive(Deserialize, schemars::JsonSchema)]
+struct TomlHomeAssistantTrigger {
+    id: String,
+    entities: Vec<String>,
+    from: Option<String>,
+    to: Option<String>,
+    prompt: String,
+    delivery_target: String,
+    cooldown_secs: Option<u64>,
+    #[serde(default = "default_enabled")]
+    enabled: bool,
+}
+
+fn resolve_home_assistant_triggers(
+    triggers: Vec<TomlHomeAssistantTrigger>,
+) -> Vec<HomeAssistantTrigger> {
+    triggers
+        .into_iter()
+        .map(|t| HomeAssistantTrigger {
+            id: t.id,
+            entities: t.entities,
+            from: t.from,
+            to: t.to,
+            prompt: t.prompt,
+            delivery_target: t.delivery_target,
+            cooldown_secs: t.cooldown_secs.unwrap_or(60),
+            enabled: t.enabled,
+        })
+        .collect()
+}
+
+#[derive(Deserialize, schemars::JsonSchema)]
+struct TomlIssuesConfig {
+    enabled: Option<bool>,
+    workspaces: Option<Vec<TomlIssueWorkspace>>,
+}
+
+#[derive(Deserialize, schemars::JsonSchema)]
+struct TomlIssueWorkspace {
+    name: String,
+    provider: IssueProvider,
+    url: Option<String>,
+    email: Option<String>,
+    token: String,
+    project: String,
+    #[serde(default)]
+    fields: serde_json::Map<String, serde_json::Value>,
+}
+
+fn resolve_issue_workspaces(workspaces: Vec<TomlIssueWorkspace>) -> Vec<IssueWorkspace> {
+    workspaces
+        .into_iter()
+        .map(|w| IssueWorkspace {
+            name: w.name,
+            provider: w.provider,
+            url: w.url.map(|url| url.trim_end_matches('/').to_string()),
+            email: w.email.as_deref().and_then(resolve_env_value),
+            token: resolve_env_value(&w.token).unwrap_or_default(),
+            project: w.project,
+            fields: w.fields,
+        })
+        .collect()
+}
+
+/// Reject issue workspaces the tool couldn't reach: Jira needs its site URL,
+/// and workspace names must be unique within a config section.
+fn validate_issue_workspaces(issues: &TomlIssuesConfig) -> Result<()> {
+    let mut names = std::collections::HashSet::new();
+    for workspace in issues.workspaces.iter().flatten() {
+        if !names.insert(workspace.name.as_str()) {
+            return Err(ConfigError::Invalid(format!(
+                "duplicate issue workspace '{}'",
+                workspace.name
+            ))
+            .into());
+        }
+        if workspace.provider == IssueProvider::Jira && workspace.url.is_none() {
+            return Err(ConfigError::Invalid(format!(
+                "issue workspace '{}' uses Jira and needs a url",
+                workspace.name
+            ))
+            .into());
+        }
+    }
+    Ok(())
+}
+
+/// Reject script commands that couldn't be typed as `!name` or that would
+/// shadow a built-in command.
+fn validate_scripting(scripting: &TomlScriptingConfig) -> Result<()> {
+    for name in scripting.commands.keys() {
+        if name.is_empty()
+            || !name
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit())
+        {
+            return Err(ConfigError::Invalid(format!(
+                "script command '{name}' must be lowercase letters and digits"
+            ))
+            .into());
+        }
+        if crate::agent::commands::is_builtin(name) {
+            return Err(ConfigError::Invalid(format!(
+                "script command '{name}' has the name of a built-in command"
+            ))
+            .into());
+        }
+    }
+    Ok(())
+}
+
+/// Reject topic categories the policy couldn't apply: names must be unique,
+/// routed categories need an agent, and every pattern has to compile.
+fn validate_topics(topics: &TomlTopicsConfig) -> Result<()> {
+    let mut names = std::collections::HashSet::new();
+    for category in &topics.categories {
+        if !names.insert(category.name.as_str()) {
+            return Err(ConfigError::Invalid(format!(
+                "duplicate topic category '{}'",
+                category.name
+            ))
+            .into());
+        }
+        if category.action == TopicAction::Route && category.agent.is_none() {
+            return Err(ConfigError::Invalid(format!(
+                "topic category '{}' routes conversations and needs an agent",
+                category.name
+            ))
+            .into());
+        }
+        for pattern in &category.patterns {
+            if let Err(error) = regex::RegexBuilder::new(pattern)
+                .case_insensitive(true)
+                .build()
+            {
+                return Err(ConfigError::Invalid(format!(
+                    "topic category '{}' has an invalid pattern '{pattern}': {error}",
+                    category.name
+                ))
+                .into());
+            }
+        }
+    }
+    Ok(())
+}
+
+#[derive(Deserialize, schemars::JsonSchema)]
+struct TomlSqlConfig {
+    enabled: Option<bool>,
+    max_rows: Option<usize>,
+    max_bytes: Option<usize>,
+    timeout_secs: Option<u64>,
+    databases: Option<Vec<TomlSqlDatabase>>,
+}
+
+#[derive(Deserialize, schemars::JsonSchema)]
+struct TomlSqlDatabase {
+    name: String,
+    url: String,
+    description: Option<String>,
+    statements: Option<Vec<String>>,
+}
+
+impl TomlSqlConfig {
+    fn resolve(self, base: &SqlConfig) -> SqlConfig {
+        SqlConfig {
+            enabled: self.enabled.unwrap_or(base.enabled),
+            max_rows: self.max_rows.unwrap_or(base.max_rows),
+            max_bytes: self.max_bytes.unwrap_or(base.max_bytes),
+            timeout_secs: self.timeout_secs.unwrap_or(base.timeout_secs),
+            databases: self
+                .databases
+                .map(|databases| {
+                    databases
+                        .into_iter()
+                        .map(|d| SqlDatabase {
+                            url: resolve_env_value(&d.url).unwrap_or_default(),
+                            statements: d
+                                .statements
+                                .map(|statements| {
+                                    statements.iter().map(|s| s.to_lowercase()).collect()
+                                })
+                                .unwrap_or_else(|| {
+                                    DEFAULT_SQL_STATEMENTS
+                                        .iter()
+                                        .map(|s| s.to_string())
+                                        .collect()
+                                }),
+                            name: d.name,
+                            description: d.description,
+                        })
+                        .collect()
+                })
+                .unwrap_or_else(|| base.databases.clone()),
+        }
+    }
+}
+
+/// Reject SQL databases the tool couldn't use: names must be unique within a
+/// config section, and URLs written out (not `env:` references) need a
+/// supported scheme.
+fn validate_sql_databases(sql: &TomlSqlConfig) -> Result<()> {
+    let mut names = std::collections::HashSet::new();
+    for database in sql.databases.iter().flatten() {
+        if !names.insert(database.name.as_str()) {
+            return Err(ConfigError::Invalid(format!(
+                "duplicate sql database '{}'",
+                database.name
+            ))
+            .into());
+        }
+        if !database.url.starts_with("env:") && SqlBackend::from_url(&database.url).is_none() {
+            return Err(ConfigError::Invalid(format!(
+                "sql database '{}' needs a postgres://, mysql:// or sqlite: url",
+                database.name
+            ))
+            .into());
+        }
+    }
+    Ok(())
+}
+
+#[derive(Deserialize, schemars::JsonSchema)]
+struct TomlHttpConfig {
+    enabled: Option<bool>,
+    allowed_domains: Option<Vec<String>>,
+    timeout_secs: Option<u64>,
+    max_response_bytes: Option<usize>,
+    apis: Option<Vec<TomlHttpApi>>,
+}
+
+#[derive(Deserialize, schemars::JsonSchema)]
+struct TomlHttpApi {
+    name: String,
+    spec: PathBuf,
+    base_url: Option<String>,
+    #[serde(default)]
+    allowed_domains: Vec<String>,
+    #[serde(default)]
+    operations: Vec<String>,
+    auth: Option<HttpAuth>,
+}
+
+impl TomlHttpConfig {
+    fn resolve(self, base: &HttpConfig) -> HttpConfig {
+        HttpConfig {
+            enabled: self.enabled.unwrap_or(base.enabled),
+            allowed_domains: self
+                .allowed_domains
+                .unwrap_or_else(|| base.allowed_domains.clone()),
+            timeout_secs: self.timeout_secs.unwrap_or(base.timeout_secs),
+            max_response_bytes: self.max_response_bytes.unwrap_or(base.max_response_bytes),
+            apis: self
+                .apis
+                .map(|apis| {
+                    apis.into_iter()
+                        .map(|api| HttpApi {
+                            name: api.name,
+                            spec: api.spec,
+                            base_url: api.base_url,
+                            allowed_domains: api.allowed_domains,
+                            operations: api.operations,
+                            auth: api.auth.map(HttpAuth::resolve_env),
+                        })
+                        .collect()
+                })
+                .unwrap_or_else(|| base.apis.clone()),
+        }
+    }
+}
+
+/// Reject APIs whose tool names wouldn't work: names must be unique within a
+/// config section and made of lowercase letters, digits and underscores.
+fn validate_http_apis(http: &TomlHttpConfig) -> Result<()> {
+    let mut names = std::collections::HashSet::new();
+    for api in http.apis.iter().flatten() {
+        let valid = !api.name.is_empty()
+            && api
+                .name
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_');
+        if !valid {
+            return Err(ConfigError::Invalid(format!(
+                "http api name '{}' may only hold lowercase letters, digits and underscores",
+                api.name
+            ))
+            .into());
+        }
+        if !names.insert(api.name.as_str()) {
+            return Err(ConfigError::Invalid(format!("duplicate http api '{}'", api.name)).into());
+        }
+    }
+    Ok(())
+}
+
+#[derive(Deserialize, schemars::JsonSchema)]
+struct TomlKubernetesConfig {
+    enabled: Option<bool>,
+    allow_writes: Option<bool>,
+    max_objects: Option<usize>,
+    max_log_lines: Option<usize>,
+    timeout_secs: Option<u64>,
+    clusters: Option<Vec<TomlKubernetesCluster>>,
+}
+
+#[derive(Deserialize, schemars::JsonSchema)]
+struct TomlKubernetesCluster {
+    name: String,
+    kubeconfig: Option<PathBuf>,
+    context: Option<String>,
+    #[serde(default)]
+    namespaces: Vec<String>,
+    description: Option<String>,
+}
+
+impl TomlKubernetesConfig {
+    fn resolve(self, base: &KubernetesConfig) -> KubernetesConfig {
+        KubernetesConfig {
+            enabled: self.enabled.unwrap_or(base.enabled),
+            allow_writes: self.allow_writes.unwrap_or(base.allow_writes),
+            max_objects: self.max_objects.unwrap_or(base.max_objects),
+            max_log_lines: self.max_log_lines.unwrap_or(base.max_log_lines),
+            timeout_secs: self.timeout_secs.unwrap_or(base.timeout_secs),
+            clusters: self
+                .clusters
+                .map(|clusters| {
+                    clusters
+                        .into_iter()
+                        .map(|c| KubernetesCluster {
+                            name: c.name,
+                            kubeconfig: c.kubeconfig,
+                            context: c.context,
+                            namespaces: c.namespaces,
+                            description: c.description,
+                        })
+                        .collect()
+                })
+                .unwrap_or_else(|| base.clusters.clone()),
+        }
+    }
+}
+
+/// Reject Kubernetes clusters the agent couldn't pick: names must be
+/// non-empty and unique within a config section.
+fn validate_kubernetes_clusters(kubernetes: &TomlKubernetesConfig) -> Result<()> {
+    let mut names = std::collections::HashSet::new();
+    for cluster in kubernetes.clusters.iter().flatten() {
+        if cluster.name.trim().is_empty() {
+            return Err(ConfigError::Invalid("kubernetes cluster needs a name".into()).into());
+        }
+        if !names.insert(cluster.name.as_str()) {
+            return Err(ConfigError::Invalid(format!(
+                "duplicate kubernetes cluster '{}'",
+                cluster.name
+            ))
+            .into());
+        }
+    }
+    Ok(())
+}
+
+#[derive(Deserialize, schemars::JsonSchema)]
+struct TomlObservabilityConfig {
+    enabled: Option<bool>,
+    max_range_hours: Option<u64>,
+    max_points: Option<usize>,
+    max_series: Option<usize>,
+    max_log_lines: Option<usize>,
+    timeout_secs: Option<u64>,
+    sources: Option<Vec<TomlObservabilitySource>>,
+}
+
+#[derive(Deserialize, schemars::JsonSchema)]
+struct TomlObservabilitySource {
+    name: String,
+    kind: ObservabilityBackend,
+    url: String,
+    description: Option<String>,
+    auth: Option<HttpAuth>,
+}
+
+impl TomlObservabilityConfig {
+    fn resolve(self, base: &ObservabilityConfig) -> ObservabilityConfig {
+        ObservabilityConfig {
+            enabled: self.enabled.unwrap_or(base.enabled),
+            max_range_hours: self.max_range_hours.unwrap_or(base.max_range_hours),
+            max_points: self.max_points.unwrap_or(base.max_points),
+            max_series: self.max_series.unwrap_or(base.max_series),
+            max_log_lines: self.max_log_lines.unwrap_or(base.max_log_lines),
+            timeout_secs: self.timeout_secs.unwrap_or(base.timeout_secs),
+            sources: self
+                .sources
+                .map(|sources| {
+                    sources
+                        .into_iter()
+                        .map(|s| ObservabilitySource {
+                            name: s.name,
+                            backend: s.kind,
+                            url: resolve_env_value(&s.url)
+                                .unwrap_or_default()
+                                .trim_end_matches('/')
+                                .to_string(),
+                            description: s.description,
+                            auth: s.auth.map(HttpAuth::resolve_env),
+                        })
+                        .collect()
+                })
+                .unwrap_or_else(|| base.sources.clone()),
+        }
+    }
+}
+
+/// Reject observability sources the agent couldn't pick: names must be
+/// unique within a config section.
+fn validate_observability_sources(observability: &TomlObservabilityConfig) -> Result<()> {
+    let mut names = std::collections::HashSet::new();
+    for source in observability.sources.iter().flatten() {
+        if !names.insert(source.name.as_str()) {
+            return Err(ConfigError::Invalid(format!(
+                "duplicate observability source '{}'",
+                source.name
+            ))
+            .into());
+        }
+    }
+    Ok(())
+}
+
+#[derive(Deserialize, schemars::JsonSchema)]
+struct TomlIncidentsConfig {
+    enabled: Option<bool>,
+    responders: Option<Vec<String>>,
+    accounts: Option<Vec<TomlIncidentAccount>>,
+    trigger: Option<TomlIncidentTrigger>,
+}
+
+#[derive(Deserialize, schemars::JsonSchema)]
+struct TomlIncidentAccount {
+    name: String,
+    provider: IncidentProvider,
+    token: String,
+    email: Option<String>,
+    url: Option<String>,
+    webhook_secret: Option<String>,
+}
+
+#[derive(Deserialize, schemars::JsonSchema)]
+struct TomlIncidentTrigger {
+    prompt: String,
+    delivery_target: String,
+    #[serde(default)]
+    services: Vec<String>,
+    #[serde(default = "default_enabled")]
+    enabled: bool,
+}
+
+impl TomlIncidentsConfig {
+    fn resolve(self, base: &IncidentsConfig) -> IncidentsConfig {
+        IncidentsConfig {
+            enabled: self.enabled.unwrap_or(base.enabled),
+            responders: self.responders.unwrap_or_else(|| base.responders.clone()),
+            accounts: self
+                .accounts
+                .map(|accounts| {
+                    accounts
+                        .into_iter()
+                        .map(|a| IncidentAccount {
+                            name: a.name,
+                            provider: a.provider,
+                            token: resolve_env_value(&a.token).unwrap_or_default(),
+                            email: a.email.as_deref().and_then(resolve_env_value),
+                            url: a.url.map(|url| url.trim_end_matches('/').to_string()),
+                            webhook_secret: a.webhook_secret.as_deref().and_then(resolve_env_value),
+                        })
+                        .collect()
+                })
+                .unwrap_or_else(|| base.accounts.clone()),
+            trigger: self
+                .trigger
+                .map(|t| IncidentTrigger {
+                    prompt: t.prompt,
+                    delivery_target: t.delivery_target,
+                    services: t.services,
+                    enabled: t.enabled,
+                })
+                .or_else(|| base.trigger.clone()),
+        }
+    }
+}
+
+/// Reject incident accounts the tool couldn't use: names must be unique
+/// within a config section and safe in a URL path, and PagerDuty needs the
+/// email changes are made as.
+fn validate_incident_accounts(incidents: &TomlIncidentsConfig) -> Result<()> {
+    let mut names = std::collections::HashSet::new();
+    for account in incidents.accounts.iter().flatten() {
+        let valid = !account.name.is_empty()
+            && account
+                .name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        if !valid {
+            return Err(ConfigError::Invalid(format!(
+                "incident account name '{}' may only use letters, digits, '-' and '_'",
+                account.name
+            ))
+            .into());
+        }
+        if !names.insert(account.name.as_str()) {
+            return Err(ConfigError::Invalid(format!(
+                "duplicate incident account '{}'",
+                account.name
+            ))
+            .into());
+        }
+        if account.provider == IncidentProvider::Pagerduty && account.email.is_none() {
+            return Err(ConfigError::Invalid(format!(
+                "incident account '{}' uses PagerDuty and needs an email",
+                account.name
+            ))
+            .into());
+        }
+    }
+    Ok(())
+}
+
+#[derive(Deserialize, schemars::JsonSchema)]
+struct TomlStorageConfig {
+    backend: Option<StorageBackend>,
+    endpoint: Option<String>,
+    bucket: Option<String>,
+    region: Option<String>,
+    access_key_id: Option<String>,
+    secret_access_key: Option<String>,
+    prefix: Option<String>,
+    public_url: Option<String>,
+}
+
+impl TomlStorageConfig {
+    fn resolve(self, base: &StorageConfig) -> StorageConfig {
+        StorageConfig {
+            backend: self.backend.unwrap_or(base.backend),
+            endpoint: self
+                .endpoint
+                .map(|url| url.trim_end_matches('/').to_string())
+                .or_else(|| base.endpoint.clone()),
+            bucket: self.bucket.or_else(|| base.bucket.clone()),
+            region: self.region.unwrap_or_else(|| base.region.clone()),
+            access_key_id: self
+                .access_key_id
+                .as_deref()
+                .and_then(resolve_env_value)
+                .or_else(|| base.access_key_id.clone()),
+            secret_access_key: self
+                .secret_access_key
+                .as_deref()
+                .and_then(resolve_env_value)
+                .or_else(|| base.secret_access_key.clone()),
+            prefix: self.prefix.unwrap_or_else(|| base.prefix.clone()),
+            public_url: self
+                .public_url
+                .map(|url| url.trim_end_matches('/').to_string())
+                .or_else(|| base.public_url.clone()),
+        }
+    }
+}
+
+/// Reject a generation profile that doesn't exist, so a typo doesn't quietly
+/// leave an agent on its providers' defaults.
+fn validate_generation(generation: &GenerationConfig, section: &str) -> Result<()> {
+    match generation.profile.as_deref() {
+        Some(profile) if generation.resolve(profile).is_none() => {
+            Err(ConfigError::Invalid(format!(
+                "{section}.generation.profile '{profile}' isn't a configured or built-in profile (available: {})",
+                generation.names().join(", ")
+            ))
+            .into())
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Reject an S3 storage backend missing what it needs to connect.
+fn validate_storage(storage: &StorageConfig, section: &str) -> Result<()> {
+    if storage.backend != StorageBackend::S3 {
+        return Ok(());
+    }
+    let missing = [
+        ("endpoint", storage.endpoint.is_none()),
+        ("bucket", storage.bucket.is_none()),
+        ("access_key_id", storage.access_key_id.is_none()),
+        ("secret_access_key", storage.secret_access_key.is_none()),
+    ];
+    for (key, is_missing) in missing {
+        if is_missing {
+            return Err(ConfigError::Invalid(format!(
+                "{section}.storage uses the s3 backend and needs {key}"
+            ))
+            .into());
+        }
+    }
+    Ok(())
+}
+
+#[derive(Deserialize, schemars::JsonSchema)]
+struct TomlRateLimitConfig {
+    enabled: Option<bool>,
+    user_per_minute: Option<u32>,
+    user_burst: Option<u32>,
+    user_per_hour: Option<u32>,
+    channel_per_minute: Option<u32>,
+    channel_burst: Option<u32>,
+}
+
+#[derive(Deserialize, schemars::JsonSchema)]
+struct TomlDedupConfig {
+    enabled: Option<bool>,
+    event_window_secs: Option<u64>,
+    content_window_secs: Option<u64>,
+}
+
+#[derive(Deserialize, schemars::JsonSchema)]
+struct TomlLoopDetectionConfig {
+    enabled: Option<bool>,
+    max_repeated_calls: Option<u32>,
+    max_stalled_rounds: Option<u32>,
+}
+
+#[derive(Deserialize, schemars::JsonSchema)]
+struct TomlUsageAnomalyConfig {
+    enabled: Option<bool>,
+    check_interval_secs: Option<u64>,
+    baseline_days: Option<u32>,
+    spend_factor: Option<f64>,
+    min_spend_usd: Option<f64>,
+    token_factor: Option<f64>,
+    fallback_factor: Option<f64>,
+    min_fallback_share: Option<f64>,
+    min_turns: Option<u64>,
+    cooldown_secs: Option<u64>,
+    delivery_targets: Option<Vec<String>>,
+}
+
+impl TomlUsageAnomalyConfig {
+    fn resolve(self, base: &UsageAnomalyConfig) -> UsageAnomalyConfig {
+        UsageAnomalyConfig {
+            enabled: self.enabled.unwrap_or(base.enabled),
+            check_interval_secs: self.check_interval_secs.unwrap_or(base.check_interval_secs),
+            baseline_days: self.baseline_days.unwrap_or(base.baseline_days),
+            spend_factor: self.spend_factor.unwrap_or(base.spend_factor),
+            min_spend_usd: self.min_spend_usd.unwrap_or(base.min_spend_usd),
+            token_factor: self.token_factor.unwrap_or(base.token_factor),
+            fallback_factor: self.fallback_factor.unwrap_or(base.fallback_factor),
+            min_fallback_share: self.min_fallback_share.unwrap_or(base.min_fallback_share),
+            min_turns: self.min_turns.unwrap_or(base.min_turns),
+            cooldown_secs: self.cooldown_secs.unwrap_or(base.cooldown_secs),
+            delivery_targets: self
+                .delivery_targets
+                .unwrap_or_else(|| base.delivery_targets.clone()),
+        }
+    }
+}
+
+#[derive(Deserialize, schemars::JsonSchema)]
+struct TomlBudgetConfig {
+    enabled: Option<bool>,
+    daily_usd: Option<f64>,
+    check_interval_secs: Option<u64>,
+    cheaper_model_at: Option<f64>,
+    cheaper_model: Option<String>,
+    restrict_tools_at: Option<f64>,
+    expensive_tools: Option<Vec<String>>,
+    over_budget_reply: Option<String>,
+    delivery_targets: Option<Vec<String>>,
+}
+
+impl TomlBudgetConfig {
+    fn resolve(self, base: &BudgetConfig) -> BudgetConfig {
+        BudgetConfig {
+            enabled: self.enabled.unwrap_or(base.enabled),
+            daily_usd: self.daily_usd.unwrap_or(base.daily_usd),
+            check_interval_secs: self.check_interval_secs.unwrap_or(base.check_interval_secs),
+            cheaper_model_at: self.cheaper_model_at.unwrap_or(base.cheaper_model_at),
+            cheaper_model: self
+                .cheaper_model
+                .unwrap_or_else(|| base.cheaper_model.clone()),
+            restrict_tools_at: self.restrict_tools_at.unwrap_or(base.restrict_tools_at),
+            expensive_tools: self
+                .expensive_tools
+                .unwrap_or_else(|| base.expensive_tools.clone()),
+            over_budget_reply: self
+                .over_budget_reply
+                .unwrap_or_else(|| base.over_budget_reply.clone()),
+            delivery_targets: self
+                .delivery_targets
+                .unwrap_or_else(|| base.delivery_targets.clone()),
+        }
+    }
+}
+
+#[derive(Deserialize, schemars::JsonSchema)]
+struct TomlFactCheckConfig {
+    enabled: Option<bool>,
+    channels: Option<Vec<String>>,
+    model: Option<String>,
+    sources: Option<Vec<String>>,
+    action: Option<FactCheckAction>,
+    max_claims: Option<usize>,
+    timeout_secs: Option<u64>,
+}
+
+impl TomlFactCheckConfig {
+    fn resolve(self, base: &FactCheckConfig) -> FactCheckConfig {
+        FactCheckConfig {
+            enabled: self.enabled.unwrap_or(base.enabled),
+            channels: self.channels.unwrap_or_else(|| base.channels.clone()),
+            model: self.model.unwrap_or_else(|| base.model.clone()),
+            sources: self.sources.unwrap_or_else(|| base.sources.clone()),
+            action: self.action.unwrap_or(base.action),
+            max_claims: self.max_claims.unwrap_or(base.max_claims),
+            timeout_secs: self.timeout_secs.unwrap_or(base.timeout_secs),
+        }
+    }
+}
+
+#[derive(Deserialize, schemars::JsonSchema)]
+struct TomlProvenanceConfig {
+    enabled: Option<bool>,
+    max_sources: Option<usize>,
+}
+
+impl TomlProvenanceConfig {
+    fn resolve(self, base: &ProvenanceConfig) -> ProvenanceConfig {
+        ProvenanceConfig {
+            enabled: self.enabled.unwrap_or(base.enabled),
+            max_sources: self.max_sources.unwrap_or(base.max_sources),
+        }
+    }
+}
+
+#[derive(Deserialize, schemars::JsonSchema)]
+struct TomlEscalationConfig {
+    enabled: Option<bool>,
+    delivery_targets: Option<Vec<String>>,
+    patterns: Option<Vec<String>>,
+    paused_reply: Option<String>,
+    dashboard_url: Option<String>,
+}
+
+impl TomlEscalationConfig {
+    fn resolve(self, base: &EscalationConfig) -> EscalationConfig {
+        EscalationConfig {
+            enabled: self.enabled.unwrap_or(base.enabled),
+            delivery_targets: self
+                .delivery_targets
+                .unwrap_or_else(|| base.delivery_targets.clone()),
+            patterns: self.patterns.unwrap_or_else(|| base.patterns.clone()),
+            paused_reply: self
+                .paused_reply
+                .unwrap_or_else(|| base.paused_reply.clone()),
+            dashboard_url: self.dashboard_url.or_else(|| base.dashboard_url.clone()),
+        }
+    }
+}
+
+#[derive(Deserialize, schemars::JsonSchema)]
+struct TomlGenerationConfig {
+    profile: Option<String>,
+    #[serde(default)]
+    profiles: HashMap<String, GenerationProfile>,
+}
+
+impl TomlGenerationConfig {
+    /// Profiles defined here are added to the base's, replacing ones with
+    /// the same name.
+    fn resolve(self, base: &GenerationConfig) -> GenerationConfig {
+        let mut profiles = base.profiles.clone();
+        profiles.extend(self.profiles);
+        GenerationConfig {
+            profile: self.profile.or_else(|| base.profile.clone()),
+            profiles,
+        }
+    }
+}
+
+#[derive(Deserialize, schemars::JsonSchema)]
+struct TomlOutputCapsConfig {
+    channel: Option<u64>,
+    branch: Option<u64>,
+    worker: Option<u64>,
+    compactor: Option<u64>,
+    cortex: Option<u64>,
     #[serde(default)]
-    task_overrides: HashMap<String, String>,
-    fallbacks: Option<HashMap<String, Vec<String>>>,
+    models: HashMap<String, u64>,
+    truncation: Option<Truncation>,
 }
 
-#[derive(Deserialize)]
-struct TomlMemoryPersistenceConfig {
+impl TomlOutputCapsConfig {
+    /// Model caps defined here are added to the base's, replacing ones for
+    /// the same model.
+    fn resolve(self, base: &OutputCapsConfig) -> OutputCapsConfig {
+        let mut models = base.models.clone();
+        models.extend(self.models);
+        OutputCapsConfig {
+            channel: self.channel.or(base.channel),
+            branch: self.branch.or(base.branch),
+            worker: self.worker.or(base.worker),
+            compactor: self.compactor.or(base.compactor),
+            cortex: self.cortex.or(base.cortex),
+            models,
+            truncation: self.truncation.unwrap_or(base.truncation),
+        }
+    }
+}
+
+#[derive(Deserialize, schemars::JsonSchema)]
+struct TomlEmptyResponseConfig {
+    retry: Option<bool>,
+    nudge: Option<String>,
+}
+
+impl TomlEmptyResponseConfig {
+    fn resolve(self, base: &EmptyResponseConfig) -> EmptyResponseConfig {
+        EmptyResponseConfig {
+            retry: self.retry.unwrap_or(base.retry),
+            nudge: self.nudge.unwrap_or_else(|| base.nudge.clone()),
+        }
+    }
+}
+
+#[derive(Deserialize, schemars::JsonSchema)]
+struct TomlSelfReportConfig {
     enabled: Option<bool>,
-    message_interval: Option<usize>,
+    min_turns: Option<usize>,
 }
 
-#[derive(Deserialize)]
-struct TomlCoalesceConfig {
+impl TomlSelfReportConfig {
+    fn resolve(self, base: &SelfReportConfig) -> SelfReportConfig {
+        SelfReportConfig {
+            enabled: self.enabled.unwrap_or(base.enabled),
+            min_turns: self.min_turns.unwrap_or(base.min_turns),
+        }
+    }
+}
+
+#[derive(Deserialize, schemars::JsonSchema)]
+struct TomlLatencyBudgetConfig {
     enabled: Option<bool>,
-    debounce_ms: Option<u64>,
-    max_wait_ms: Option<u64>,
-    min_messages: Option<usize>,
-    multi_user_only: Option<bool>,
+    target_secs: Option<u64>,
+    hurry_at: Option<f64>,
+    fast_model: Option<String>,
+    max_tool_calls: Option<usize>,
 }
 
-#[derive(Deserialize)]
-struct TomlIngestionConfig {
+impl TomlLatencyBudgetConfig {
+    fn resolve(self, base: &LatencyBudgetConfig) -> LatencyBudgetConfig {
+        LatencyBudgetConfig {
+            enabled: self.enabled.unwrap_or(base.enabled),
+            target_secs: self.target_secs.unwrap_or(base.target_secs).max(1),
+            hurry_at: self.hurry_at.unwrap_or(base.hurry_at).clamp(0.0, 1.0),
+            fast_model: self.fast_model.unwrap_or_else(|| base.fast_model.clone()),
+            max_tool_calls: self.max_tool_calls.unwrap_or(base.max_tool_calls),
+        }
+    }
+}
+
+#[derive(Deserialize, schemars::JsonSchema)]
+struct TomlUsageReportConfig {
+    min_group_size: Option<u64>,
+}
+
+impl TomlUsageReportConfig {
+    fn resolve(self, base: &UsageReportConfig) -> UsageReportConfig {
+        UsageReportConfig {
+            min_group_size: self.min_group_size.unwrap_or(base.min_group_size),
+        }
+    }
+}
+
+#[derive(Deserialize, schemars::JsonSchema)]
+struct TomlRetentionConfig {
     enabled: Option<bool>,
-    poll_interval_secs: Option<u64>,
-    chunk_size: Option<usize>,
+    idle_days: Option<u32>,
+    action: Option<RetentionAction>,
+    summarize: Option<bool>,
+    check_interval_secs: Option<u64>,
+    #[serde(default)]
+    channels: HashMap<String, TomlChannelRetentionConfig>,
 }
 
-#[derive(Deserialize)]
-struct TomlCompactionConfig {
-    background_threshold: Option<f32>,
-    aggressive_threshold: Option<f32>,
-    emergency_threshold: Option<f32>,
+#[derive(Deserialize, schemars::JsonSchema)]
+struct TomlLanguageConfig {
+    detect: Option<bool>,
+    #[serde(default)]
+    channels: HashMap<String, String>,
 }
 
-#[derive(Deserialize)]
-struct TomlCortexConfig {
-    tick_interval_secs: Option<u64>,
-    worker_timeout_secs: Option<u64>,
-    branch_timeout_secs: Option<u64>,
-    circuit_breaker_threshold: Option<u8>,
-    bulletin_interval_secs: Option<u64>,
-    bulletin_max_words: Option<usize>,
-    bulletin_max_turns: Option<usize>,
-    association_interval_secs: Option<u64>,
-    association_similarity_threshold: Option<f32>,
-    association_updates_threshold: Option<f32>,
-    association_max_per_pass: Option<usize>,
+#[derive(Deserialize, schemars::JsonSchema)]
+struct TomlNetworkConfig {
+    #[serde(default)]
+    tools: HashMap<String, ToolNetworkPolicy>,
 }
 
-#[derive(Deserialize)]
-struct TomlBrowserConfig {
+#[derive(Deserialize, schemars::JsonSchema)]
+struct TomlThreadConfig {
+    parent_context: Option<ThreadParentContext>,
+    parent_messages: Option<usize>,
+}
+
+#[derive(Deserialize, schemars::JsonSchema)]
+struct TomlCommandsConfig {
     enabled: Option<bool>,
-    headless: Option<bool>,
-    evaluate_enabled: Option<bool>,
-    executable_path: Option<String>,
-    screenshot_dir: Option<String>,
+    admin_only: Option<Vec<String>>,
+    disabled: Option<Vec<String>>,
+}
+
+#[derive(Deserialize, schemars::JsonSchema)]
+struct TomlChannelRetentionConfig {
+    idle_days: Option<u32>,
+    action: Option<RetentionAction>,
+    summarize: Option<bool>,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, schemars::JsonSchema)]
 struct TomlOpenCodeConfig {
     enabled: Option<bool>,
     path: Option<String>,
@@ -977,18 +4555,19 @@ struct TomlOpenCodeConfig {
     permissions: Option<TomlOpenCodePermissions>,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, schemars::JsonSchema)]
 struct TomlOpenCodePermissions {
     edit: Option<String>,
     bash: Option<String>,
     webfetch: Option<String>,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, schemars::JsonSchema)]
 struct TomlAgentConfig {
     id: String,
     #[serde(default)]
     default: bool,
+    description: Option<String>,
     workspace: Option<String>,
     routing: Option<TomlRoutingConfig>,
     max_concurrent_branches: Option<usize>,
@@ -1002,12 +4581,82 @@ struct TomlAgentConfig {
     ingestion: Option<TomlIngestionConfig>,
     cortex: Option<TomlCortexConfig>,
     browser: Option<TomlBrowserConfig>,
+    tool_filter: Option<TomlToolFilterConfig>,
+    compression: Option<TomlCompressionConfig>,
+    preview: Option<TomlPreviewConfig>,
+    tool_guard: Option<TomlToolGuardConfig>,
+    cost_gate: Option<TomlCostGateConfig>,
+    computer_use: Option<TomlComputerUseConfig>,
+    ocr: Option<TomlOcrConfig>,
+    image_generation: Option<TomlImageGenerationConfig>,
+    home_assistant: Option<TomlHomeAssistantConfig>,
+    issues: Option<TomlIssuesConfig>,
+    sql: Option<TomlSqlConfig>,
+    http: Option<TomlHttpConfig>,
+    kubernetes: Option<TomlKubernetesConfig>,
+    observability: Option<TomlObservabilityConfig>,
+    incidents: Option<TomlIncidentsConfig>,
+    storage: Option<TomlStorageConfig>,
+    rate_limit: Option<TomlRateLimitConfig>,
+    dedup: Option<TomlDedupConfig>,
+    #[serde(default)]
+    flags: Vec<FlagDef>,
+    loop_detection: Option<TomlLoopDetectionConfig>,
+    usage_anomalies: Option<TomlUsageAnomalyConfig>,
+    budget: Option<TomlBudgetConfig>,
+    fact_check: Option<TomlFactCheckConfig>,
+    provenance: Option<TomlProvenanceConfig>,
+    escalation: Option<TomlEscalationConfig>,
+    generation: Option<TomlGenerationConfig>,
+    output_caps: Option<TomlOutputCapsConfig>,
+    empty_response: Option<TomlEmptyResponseConfig>,
+    self_report: Option<TomlSelfReportConfig>,
+    latency_budget: Option<TomlLatencyBudgetConfig>,
+    usage_reports: Option<TomlUsageReportConfig>,
+    retention: Option<TomlRetentionConfig>,
+    language: Option<TomlLanguageConfig>,
+    network: Option<TomlNetworkConfig>,
+    threads: Option<TomlThreadConfig>,
+    commands: Option<TomlCommandsConfig>,
+    admin_users: Option<Vec<String>>,
     brave_search_key: Option<String>,
+    seed: Option<u64>,
     #[serde(default)]
     cron: Vec<TomlCronDef>,
+    #[serde(default)]
+    feeds: Vec<TomlFeedDef>,
+    #[serde(default)]
+    digests: Vec<TomlDigestDef>,
+    #[serde(default)]
+    personas: Vec<TomlPersonaDef>,
+    retrieval: Option<TomlRetrievalConfig>,
+    #[serde(default)]
+    knowledge: Vec<TomlKnowledgeSourceDef>,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, schemars::JsonSchema)]
+struct TomlRetrievalConfig {
+    #[serde(default = "default_retrieval_store")]
+    store: String,
+    #[serde(default = "default_retrieval_top_k")]
+    top_k: usize,
+    #[serde(default = "default_retrieval_min_score")]
+    min_score: f32,
+}
+
+fn default_retrieval_store() -> String {
+    "memory".into()
+}
+
+fn default_retrieval_top_k() -> usize {
+    5
+}
+
+fn default_retrieval_min_score() -> f32 {
+    0.6
+}
+
+#[derive(Deserialize, schemars::JsonSchema)]
 struct TomlCronDef {
     id: String,
     prompt: String,
@@ -1019,19 +4668,86 @@ struct TomlCronDef {
     enabled: bool,
 }
 
+#[derive(Deserialize, schemars::JsonSchema)]
+struct TomlFeedDef {
+    id: String,
+    urls: Vec<String>,
+    interval_secs: Option<u64>,
+    prompt: Option<String>,
+    delivery_target: String,
+    max_items: Option<usize>,
+    #[serde(default)]
+    summarize: bool,
+    #[serde(default = "default_enabled")]
+    enabled: bool,
+}
+
+#[derive(Deserialize, schemars::JsonSchema)]
+struct TomlDigestDef {
+    id: String,
+    channel: String,
+    delivery_target: Option<String>,
+    hours: Option<u64>,
+    interval_secs: Option<u64>,
+    active_start_hour: Option<u8>,
+    active_end_hour: Option<u8>,
+    max_messages: Option<usize>,
+    #[serde(default)]
+    prime_cache: bool,
+    #[serde(default = "default_enabled")]
+    enabled: bool,
+}
+
+#[derive(Deserialize, schemars::JsonSchema)]
+struct TomlPersonaDef {
+    name: String,
+    #[serde(default)]
+    description: String,
+    #[serde(default)]
+    preamble: String,
+    tools: Option<Vec<String>>,
+    model: Option<String>,
+    #[serde(default)]
+    admin_only: bool,
+}
+
+#[derive(Deserialize, schemars::JsonSchema)]
+struct TomlKnowledgeSourceDef {
+    id: String,
+    kind: KnowledgeSourceKind,
+    #[serde(default)]
+    url: String,
+    branch: Option<String>,
+    #[serde(default)]
+    paths: Vec<String>,
+    token: Option<String>,
+    email: Option<String>,
+    space: Option<String>,
+    store: Option<String>,
+    interval_secs: Option<u64>,
+    chunk_size: Option<usize>,
+    max_documents: Option<usize>,
+    #[serde(default = "default_enabled")]
+    enabled: bool,
+}
+
 fn default_enabled() -> bool {
     true
 }
 
-#[derive(Deserialize, Default)]
+#[derive(Deserialize, Default, schemars::JsonSchema)]
 struct TomlMessagingConfig {
     discord: Option<TomlDiscordConfig>,
     slack: Option<TomlSlackConfig>,
     telegram: Option<TomlTelegramConfig>,
+    irc: Option<TomlIrcConfig>,
+    xmpp: Option<TomlXmppConfig>,
+    notify: Option<TomlNotifyConfig>,
+    twilio: Option<TomlTwilioConfig>,
     webhook: Option<TomlWebhookConfig>,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, schemars::JsonSchema)]
 struct TomlDiscordConfig {
     #[serde(default)]
     enabled: bool,
@@ -1040,9 +4756,71 @@ struct TomlDiscordConfig {
     dm_allowed_users: Vec<String>,
     #[serde(default)]
     allow_bot_messages: bool,
+    voice: Option<TomlDiscordVoiceConfig>,
+}
+
+#[derive(Deserialize, schemars::JsonSchema)]
+struct TomlDiscordVoiceConfig {
+    #[serde(default)]
+    enabled: bool,
+    #[serde(default)]
+    channels: Vec<String>,
+    #[serde(default = "default_voice_activation_keywords")]
+    activation_keywords: Vec<String>,
+    #[serde(default = "default_voice_silence_ms")]
+    silence_ms: u64,
+    #[serde(default)]
+    mode: VoiceMode,
+    #[serde(default)]
+    triggers: Vec<String>,
+    #[serde(default = "default_live_window_secs")]
+    transcript_window_secs: u64,
+    #[serde(default = "default_live_cooldown_secs")]
+    trigger_cooldown_secs: u64,
+    #[serde(default = "default_speech_base_url")]
+    base_url: String,
+    api_key: Option<String>,
+    #[serde(default = "default_speech_stt_model")]
+    stt_model: String,
+    #[serde(default = "default_speech_tts_model")]
+    tts_model: String,
+    #[serde(default = "default_speech_tts_voice")]
+    tts_voice: String,
+}
+
+fn default_voice_activation_keywords() -> Vec<String> {
+    vec!["spacebot".into()]
+}
+
+fn default_voice_silence_ms() -> u64 {
+    800
+}
+
+fn default_live_window_secs() -> u64 {
+    300
+}
+
+fn default_live_cooldown_secs() -> u64 {
+    60
+}
+
+fn default_speech_base_url() -> String {
+    "https://api.openai.com/v1".into()
+}
+
+fn default_speech_stt_model() -> String {
+    "whisper-1".into()
+}
+
+fn default_speech_tts_model() -> String {
+    "tts-1".into()
+}
+
+fn default_speech_tts_voice() -> String {
+    "alloy".into()
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, schemars::JsonSchema)]
 struct TomlSlackConfig {
     #[serde(default)]
     enabled: bool,
@@ -1052,7 +4830,7 @@ struct TomlSlackConfig {
     dm_allowed_users: Vec<String>,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, schemars::JsonSchema)]
 struct TomlTelegramConfig {
     #[serde(default)]
     enabled: bool,
@@ -1061,7 +4839,112 @@ struct TomlTelegramConfig {
     dm_allowed_users: Vec<String>,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, schemars::JsonSchema)]
+struct TomlIrcConfig {
+    #[serde(default)]
+    enabled: bool,
+    server: String,
+    #[serde(default = "default_irc_port")]
+    port: u16,
+    #[serde(default = "default_irc_tls")]
+    tls: bool,
+    nickname: String,
+    username: Option<String>,
+    realname: Option<String>,
+    password: Option<String>,
+    sasl_username: Option<String>,
+    sasl_password: Option<String>,
+    #[serde(default)]
+    channels: Vec<String>,
+    #[serde(default)]
+    dm_allowed_users: Vec<String>,
+    #[serde(default = "default_irc_flood_burst")]
+    flood_burst: u32,
+    #[serde(default = "default_irc_flood_interval_ms")]
+    flood_interval_ms: u64,
+}
+
+fn default_irc_port() -> u16 {
+    6697
+}
+
+fn default_irc_tls() -> bool {
+    true
+}
+
+fn default_irc_flood_burst() -> u32 {
+    5
+}
+
+fn default_irc_flood_interval_ms() -> u64 {
+    2000
+}
+
+#[derive(Deserialize, schemars::JsonSchema)]
+struct TomlXmppConfig {
+    #[serde(default)]
+    enabled: bool,
+    jid: String,
+    password: Option<String>,
+    #[serde(default = "default_xmpp_nickname")]
+    nickname: String,
+    #[serde(default)]
+    rooms: Vec<String>,
+    #[serde(default)]
+    dm_allowed_users: Vec<String>,
+}
+
+fn default_xmpp_nickname() -> String {
+    "spacebot".into()
+}
+
+#[derive(Deserialize, schemars::JsonSchema)]
+struct TomlNotifyConfig {
+    #[serde(default)]
+    enabled: bool,
+    #[serde(default = "default_notify_desktop")]
+    desktop: bool,
+    inbox: Option<String>,
+    #[serde(default = "default_notify_app_name")]
+    app_name: String,
+}
+
+fn default_notify_desktop() -> bool {
+    true
+}
+
+fn default_notify_app_name() -> String {
+    "Spacebot".into()
+}
+
+#[derive(Deserialize, schemars::JsonSchema)]
+struct TomlTwilioConfig {
+    #[serde(default)]
+    enabled: bool,
+    account_sid: Option<String>,
+    auth_token: Option<String>,
+    sms_number: Option<String>,
+    whatsapp_number: Option<String>,
+    #[serde(default = "default_twilio_port")]
+    port: u16,
+    #[serde(default = "default_webhook_bind")]
+    bind: String,
+    public_url: String,
+    #[serde(default)]
+    allowed_numbers: Vec<String>,
+    #[serde(default = "default_twilio_max_sms_segments")]
+    max_sms_segments: usize,
+}
+
+fn default_twilio_port() -> u16 {
+    18790
+}
+
+fn default_twilio_max_sms_segments() -> usize {
+    3
+}
+
+#[derive(Deserialize, schemars::JsonSchema)]
 struct TomlWebhookConfig {
     #[serde(default)]
     enabled: bool,
@@ -1069,6 +4952,81 @@ struct TomlWebhookConfig {
     port: u16,
     #[serde(default = "default_webhook_bind")]
     bind: String,
+    #[serde(default)]
+    outbound: Vec<TomlOutboundWebhook>,
+}
+
+#[derive(Deserialize, schemars::JsonSchema)]
+struct TomlOutboundWebhook {
+    name: String,
+    url: String,
+    template: Option<String>,
+    #[serde(default = "default_webhook_max_retries")]
+    max_retries: u32,
+    signing: Option<HmacSigningConfig>,
+}
+
+fn default_webhook_max_retries() -> u32 {
+    3
+}
+
+/// Reject outbound webhooks with duplicate names or templates that don't
+/// parse.
+fn validate_outbound_webhooks(outbound: &[TomlOutboundWebhook]) -> Result<()> {
+    let mut names = std::collections::HashSet::new();
+    for webhook in outbound {
+        if !names.insert(webhook.name.as_str()) {
+            return Err(ConfigError::Invalid(format!(
+                "duplicate outbound webhook '{}'",
+                webhook.name
+            ))
+            .into());
+        }
+        if let Some(template) = &webhook.template {
+            if let Err(error) = minijinja::Environment::new().template_from_str(template) {
+                return Err(ConfigError::Invalid(format!(
+                    "outbound webhook '{}' has an invalid template: {error}",
+                    webhook.name
+                ))
+                .into());
+            }
+        }
+    }
+    Ok(())
+}
+
+fn resolve_key_check(key_check: Option<TomlKeyCheckConfig>) -> Result<KeyCheckConfig> {
+    let defaults = KeyCheckConfig::default();
+    let Some(key_check) = key_check else {
+        return Ok(defaults);
+    };
+    let mut expires = HashMap::new();
+    for (key, value) in key_check.expires {
+        let expires_at = chrono::DateTime::parse_from_rfc3339(&value)
+            .map(|expires_at| expires_at.to_utc())
+            .or_else(|_| {
+                chrono::NaiveDate::parse_from_str(&value, "%Y-%m-%d")
+                    .map(|date| date.and_time(chrono::NaiveTime::MIN).and_utc())
+            })
+            .map_err(|_| {
+                ConfigError::Invalid(format!(
+                    "llm.key_check.expires.{key} must be a date (YYYY-MM-DD) or an RFC 3339 timestamp, got '{value}'"
+                ))
+            })?;
+        expires.insert(key, expires_at);
+    }
+    Ok(KeyCheckConfig {
+        interval_secs: key_check.interval_secs.unwrap_or(defaults.interval_secs),
+        quota_warn_at: key_check
+            .quota_warn_at
+            .unwrap_or(defaults.quota_warn_at)
+            .clamp(0.0, 1.0),
+        expiry_warn_days: key_check
+            .expiry_warn_days
+            .unwrap_or(defaults.expiry_warn_days),
+        expires,
+        delivery_targets: key_check.delivery_targets,
+    })
 }
 
 fn default_webhook_port() -> u16 {
@@ -1078,7 +5036,7 @@ fn default_webhook_bind() -> String {
     "127.0.0.1".into()
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, schemars::JsonSchema)]
 struct TomlBinding {
     agent_id: String,
     channel: String,
@@ -1089,6 +5047,10 @@ struct TomlBinding {
     channel_ids: Vec<String>,
     #[serde(default)]
     dm_allowed_users: Vec<String>,
+    #[serde(default)]
+    post_processors: Vec<PostProcessor>,
+    disclosure: Option<Disclosure>,
+    activation: Option<ActivationPolicy>,
 }
 
 /// Resolve a value that might be an "env:VAR_NAME" reference.
@@ -1101,6 +5063,24 @@ fn resolve_env_value(value: &str) -> Option<String> {
 }
 
 /// Resolve a TomlRoutingConfig against a base RoutingConfig.
+/// Resolve retention overrides on top of the inherited ones. An override
+/// for the same channel or platform replaces the inherited one.
+fn channel_retention(
+    toml: HashMap<String, TomlChannelRetentionConfig>,
+    base: &HashMap<String, ChannelRetentionConfig>,
+) -> HashMap<String, ChannelRetentionConfig> {
+    let mut channels = base.clone();
+    channels.extend(toml.into_iter().map(|(key, c)| {
+        let overrides = ChannelRetentionConfig {
+            idle_days: c.idle_days,
+            action: c.action,
+            summarize: c.summarize,
+        };
+        (key, overrides)
+    }));
+    channels
+}
+
 fn resolve_routing(toml: Option<TomlRoutingConfig>, base: &RoutingConfig) -> RoutingConfig {
     let Some(t) = toml else { return base.clone() };
 
@@ -1112,6 +5092,42 @@ fn resolve_routing(toml: Option<TomlRoutingConfig>, base: &RoutingConfig) -> Rou
         None => base.fallbacks.clone(),
     };
 
+    let mut vllm = base.vllm.clone();
+    vllm.extend(t.vllm.into_iter().map(|(model, options)| {
+        let options = VllmOptions {
+            // vLLM takes the schema as an object or a JSON string, so a
+            // string is passed through untouched.
+            guided_json: options
+                .guided_json
+                .and_then(|schema| serde_json::to_value(schema).ok()),
+            guided_regex: options.guided_regex,
+            lora_adapter: options.lora_adapter,
+        };
+        (model, options)
+    }));
+
+    let mut anthropic_betas = base.anthropic_betas.clone();
+    anthropic_betas.extend(t.anthropic_beta);
+
+    let mut race = base.race.clone();
+    race.extend(t.race.into_iter().map(|(model, config)| {
+        let config = RaceConfig {
+            models: config.models,
+            judge: config.judge,
+        };
+        (model, config)
+    }));
+
+    let mut shadow = base.shadow.clone();
+    shadow.extend(t.shadow.into_iter().map(|(model, config)| {
+        let config = ShadowConfig {
+            model: config.model,
+            sample_rate: config.sample_rate.unwrap_or(0.1).clamp(0.0, 1.0),
+            judge: config.judge,
+        };
+        (model, config)
+    }));
+
     RoutingConfig {
         channel: t.channel.unwrap_or_else(|| base.channel.clone()),
         branch: t.branch.unwrap_or_else(|| base.branch.clone()),
@@ -1123,7 +5139,29 @@ fn resolve_routing(toml: Option<TomlRoutingConfig>, base: &RoutingConfig) -> Rou
         rate_limit_cooldown_secs: t
             .rate_limit_cooldown_secs
             .unwrap_or(base.rate_limit_cooldown_secs),
+        vllm,
+        anthropic_betas,
+        race,
+        shadow,
+    }
+}
+
+/// Reject `anthropic-beta` values Anthropic doesn't know, which it would
+/// otherwise fail every request over.
+fn validate_anthropic_betas(routing: &TomlRoutingConfig) -> Result<()> {
+    for (model, betas) in &routing.anthropic_beta {
+        if let Some(unknown) = betas
+            .iter()
+            .find(|beta| !KNOWN_ANTHROPIC_BETAS.contains(&beta.as_str()))
+        {
+            return Err(ConfigError::Invalid(format!(
+                "unknown anthropic_beta '{unknown}' for {model}, expected one of: {}",
+                KNOWN_ANTHROPIC_BETAS.join(", ")
+            ))
+            .into());
+        }
     }
+    Ok(())
 }
 
 impl Config {
@@ -1150,9 +5188,17 @@ impl Config {
             && std::env::var("OPENAI_API_KEY").is_err()
             && std::env::var("OPENROUTER_API_KEY").is_err()
             && std::env::var("OLLAMA_API_KEY").is_err()
+            && std::env::var("OLLAMA_BASE_URL").is_err()
             && std::env::var("OPENCODE_ZEN_API_KEY").is_err()
     }
 
+    /// JSON Schema for `config.toml`, for editor completion and validation.
+    /// Generated from the same types the file is parsed with, so it can't
+    /// drift from what's accepted.
+    pub fn json_schema() -> serde_json::Value {
+        schemars::schema_for!(TomlConfig).to_value()
+    }
+
     /// Load configuration from the default config file, falling back to env vars.
     pub fn load() -> Result<Self> {
         let instance_dir = Self::default_instance_dir();
@@ -1178,7 +5224,30 @@ impl Config {
         let toml_config: TomlConfig = toml::from_str(&content)
             .with_context(|| format!("failed to parse config from {}", path.display()))?;
 
-        Self::from_toml(toml_config, instance_dir)
+        let mut config = Self::from_toml(toml_config, instance_dir)?;
+        config.merge_managed_agents();
+        Ok(config)
+    }
+
+    /// Add the lightweight agents made with `/agent` to the personas of the
+    /// agents they were made in. A persona in config.toml wins over a
+    /// managed one with the same name, and an unreadable managed agents file
+    /// is skipped rather than failing the load.
+    fn merge_managed_agents(&mut self) {
+        let managed = match crate::agent::managed::ManagedAgents::load(&self.instance_dir) {
+            Ok(managed) => managed,
+            Err(error) => {
+                tracing::warn!(%error, "ignoring managed agents");
+                return;
+            }
+        };
+        for agent in &mut self.agents {
+            for persona in managed.personas(&agent.id) {
+                if crate::agent::persona::find(&agent.personas, &persona.name).is_none() {
+                    agent.personas.push(persona);
+                }
+            }
+        }
     }
 
     /// Load from environment variables only (no config file).
@@ -1196,6 +5265,29 @@ impl Config {
             xai_key: std::env::var("XAI_API_KEY").ok(),
             mistral_key: std::env::var("MISTRAL_API_KEY").ok(),
             opencode_zen_key: std::env::var("OPENCODE_ZEN_API_KEY").ok(),
+            openai_organization: std::env::var("OPENAI_ORG_ID").ok(),
+            openai_project: std::env::var("OPENAI_PROJECT_ID").ok(),
+            max_concurrent_requests: None,
+            debug_recording: false,
+            upload_threshold_bytes: None,
+            secondary_keys: HashMap::new(),
+            base_urls: std::env::var("OLLAMA_BASE_URL")
+                .ok()
+                .map(|base_url| HashMap::from([("ollama".to_string(), base_url)]))
+                .unwrap_or_default(),
+            regions: HashMap::new(),
+            health_check: HealthCheckConfig::default(),
+            outage: OutageConfig::default(),
+            key_check: KeyCheckConfig::default(),
+            vllm_providers: Vec::new(),
+            grammar_providers: Vec::new(),
+            tag_metadata_providers: Vec::new(),
+            request_signing: HashMap::new(),
+            pricing: HashMap::new(),
+            tool_pricing: HashMap::new(),
+            faults: Vec::new(),
+            vault: None,
+            aws_secrets: None,
         };
 
         // Note: We allow boot without provider keys now. System starts in setup mode.
@@ -1213,6 +5305,7 @@ impl Config {
         let agents = vec![AgentConfig {
             id: "main".into(),
             default: true,
+            description: None,
             workspace: None,
             routing: Some(routing),
             max_concurrent_branches: None,
@@ -1226,8 +5319,51 @@ impl Config {
             ingestion: None,
             cortex: None,
             browser: None,
+            tool_filter: None,
+            compression: None,
+            preview: None,
+            tool_guard: None,
+            cost_gate: None,
+            computer_use: None,
+            ocr: None,
+            image_generation: None,
+            home_assistant: None,
+            issues: None,
+            sql: None,
+            http: None,
+            kubernetes: None,
+            observability: None,
+            incidents: None,
+            storage: None,
+            rate_limit: None,
+            dedup: None,
+            flags: Vec::new(),
+            loop_detection: None,
+            usage_anomalies: None,
+            budget: None,
+            fact_check: None,
+            provenance: None,
+            escalation: None,
+            generation: None,
+            output_caps: None,
+            empty_response: None,
+            self_report: None,
+            latency_budget: None,
+            usage_reports: None,
+            retention: None,
+            language: None,
+            network: None,
+            threads: None,
+            commands: None,
+            admin_users: None,
             brave_search_key: None,
+            seed: None,
             cron: Vec::new(),
+            feeds: Vec::new(),
+            digests: Vec::new(),
+            personas: Vec::new(),
+            retrieval: None,
+            knowledge: Vec::new(),
         }];
 
         Ok(Self {
@@ -1238,6 +5374,10 @@ impl Config {
             messaging: MessagingConfig::default(),
             bindings: Vec::new(),
             api: ApiConfig::default(),
+            telemetry: TelemetryConfig::default(),
+            intake: IntakeConfig::default(),
+            topics: TopicsConfig::default(),
+            scripting: ScriptingConfig::default(),
         })
     }
 
@@ -1252,7 +5392,67 @@ impl Config {
         Ok(())
     }
 
-    fn from_toml(toml: TomlConfig, instance_dir: PathBuf) -> Result<Self> {
+    fn from_toml(toml: TomlConfig, instance_dir: PathBuf) -> Result<Self> {
+        let routings = toml.defaults.routing.iter().chain(
+            toml.agents
+                .iter()
+                .filter_map(|agent| agent.routing.as_ref()),
+        );
+        for routing in routings {
+            validate_anthropic_betas(routing)?;
+        }
+        let issues = toml
+            .defaults
+            .issues
+            .iter()
+            .chain(toml.agents.iter().filter_map(|agent| agent.issues.as_ref()));
+        for issues in issues {
+            validate_issue_workspaces(issues)?;
+        }
+        let sql = toml
+            .defaults
+            .sql
+            .iter()
+            .chain(toml.agents.iter().filter_map(|agent| agent.sql.as_ref()));
+        for sql in sql {
+            validate_sql_databases(sql)?;
+        }
+        let http = toml
+            .defaults
+            .http
+            .iter()
+            .chain(toml.agents.iter().filter_map(|agent| agent.http.as_ref()));
+        for http in http {
+            validate_http_apis(http)?;
+        }
+        let kubernetes = toml.defaults.kubernetes.iter().chain(
+            toml.agents
+                .iter()
+                .filter_map(|agent| agent.kubernetes.as_ref()),
+        );
+        for kubernetes in kubernetes {
+            validate_kubernetes_clusters(kubernetes)?;
+        }
+        let observability = toml.defaults.observability.iter().chain(
+            toml.agents
+                .iter()
+                .filter_map(|agent| agent.observability.as_ref()),
+        );
+        for observability in observability {
+            validate_observability_sources(observability)?;
+        }
+        let incidents = toml.defaults.incidents.iter().chain(
+            toml.agents
+                .iter()
+                .filter_map(|agent| agent.incidents.as_ref()),
+        );
+        for incidents in incidents {
+            validate_incident_accounts(incidents)?;
+        }
+        if let Some(webhook) = &toml.messaging.webhook {
+            validate_outbound_webhooks(&webhook.outbound)?;
+        }
+
         let llm = LlmConfig {
             anthropic_key: toml
                 .llm
@@ -1326,6 +5526,131 @@ impl Config {
                 .as_deref()
                 .and_then(resolve_env_value)
                 .or_else(|| std::env::var("OPENCODE_ZEN_API_KEY").ok()),
+            openai_organization: toml
+                .llm
+                .openai_organization
+                .as_deref()
+                .and_then(resolve_env_value)
+                .or_else(|| std::env::var("OPENAI_ORG_ID").ok()),
+            openai_project: toml
+                .llm
+                .openai_project
+                .as_deref()
+                .and_then(resolve_env_value)
+                .or_else(|| std::env::var("OPENAI_PROJECT_ID").ok()),
+            max_concurrent_requests: toml.llm.max_concurrent_requests,
+            debug_recording: toml.llm.debug_recording.unwrap_or(false),
+            upload_threshold_bytes: toml
+                .llm
+                .file_upload_threshold_kb
+                .map(|kilobytes| kilobytes * 1024),
+            secondary_keys: toml
+                .llm
+                .secondary_keys
+                .iter()
+                .filter_map(|(provider, key)| {
+                    resolve_env_value(key).map(|key| (provider.clone(), key))
+                })
+                .collect(),
+            base_urls: toml
+                .llm
+                .base_urls
+                .iter()
+                .filter_map(|(provider, base_url)| {
+                    resolve_env_value(base_url).map(|base_url| (provider.clone(), base_url))
+                })
+                .collect(),
+            regions: toml
+                .llm
+                .regions
+                .iter()
+                .map(|(provider, regions)| {
+                    let defaults = RegionalEndpointsConfig::default();
+                    let config = RegionalEndpointsConfig {
+                        endpoints: regions
+                            .endpoints
+                            .iter()
+                            .filter_map(|endpoint| resolve_env_value(endpoint))
+                            .collect(),
+                        stickiness_secs: regions
+                            .stickiness_secs
+                            .unwrap_or(defaults.stickiness_secs),
+                    };
+                    (provider.clone(), config)
+                })
+                .collect(),
+            health_check: toml
+                .llm
+                .health_check
+                .map(|health_check| {
+                    let defaults = HealthCheckConfig::default();
+                    HealthCheckConfig {
+                        interval_secs: health_check.interval_secs.unwrap_or(defaults.interval_secs),
+                        max_queue_depth: health_check.max_queue_depth,
+                    }
+                })
+                .unwrap_or_default(),
+            outage: toml
+                .llm
+                .outage
+                .map(|outage| {
+                    let defaults = OutageConfig::default();
+                    OutageConfig {
+                        window_secs: outage.window_secs.unwrap_or(defaults.window_secs),
+                        min_failures: outage.min_failures.unwrap_or(defaults.min_failures),
+                        error_rate: outage
+                            .error_rate
+                            .unwrap_or(defaults.error_rate)
+                            .clamp(0.0, 1.0),
+                        min_models: outage.min_models.unwrap_or(defaults.min_models),
+                        probe_interval_secs: outage
+                            .probe_interval_secs
+                            .unwrap_or(defaults.probe_interval_secs),
+                    }
+                })
+                .unwrap_or_default(),
+            key_check: resolve_key_check(toml.llm.key_check)?,
+            vllm_providers: toml.llm.vllm_providers,
+            grammar_providers: toml.llm.grammar_providers,
+            tag_metadata_providers: toml.llm.tag_metadata_providers,
+            request_signing: toml
+                .llm
+                .request_signing
+                .into_iter()
+                .filter_map(|(provider, mut signing)| {
+                    let Some(secret) = resolve_env_value(&signing.secret) else {
+                        tracing::warn!(
+                            provider,
+                            "request signing secret is unset, requests go out unsigned"
+                        );
+                        return None;
+                    };
+                    signing.secret = secret;
+                    Some((provider, signing))
+                })
+                .collect(),
+            pricing: toml.llm.pricing,
+            tool_pricing: toml.llm.tool_pricing,
+            faults: toml.llm.faults,
+            vault: toml.llm.vault.map(|mut vault| {
+                vault.auth = match vault.auth {
+                    VaultAuth::Token { token } => VaultAuth::Token {
+                        token: resolve_env_value(&token).unwrap_or_default(),
+                    },
+                    VaultAuth::AppRole { role_id, secret_id } => VaultAuth::AppRole {
+                        role_id: resolve_env_value(&role_id).unwrap_or_default(),
+                        secret_id: resolve_env_value(&secret_id).unwrap_or_default(),
+                    },
+                };
+                vault
+            }),
+            aws_secrets: toml.llm.aws_secrets.map(|mut aws| {
+                aws.access_key_id = aws.access_key_id.as_deref().and_then(resolve_env_value);
+                aws.secret_access_key =
+                    aws.secret_access_key.as_deref().and_then(resolve_env_value);
+                aws.session_token = aws.session_token.as_deref().and_then(resolve_env_value);
+                aws
+            }),
         };
 
         // Note: We allow boot without provider keys now. System starts in setup mode.
@@ -1364,6 +5689,7 @@ impl Config {
                     emergency_threshold: c
                         .emergency_threshold
                         .unwrap_or(base_defaults.compaction.emergency_threshold),
+                    eviction: c.eviction.unwrap_or(base_defaults.compaction.eviction),
                 })
                 .unwrap_or(base_defaults.compaction),
             memory_persistence: toml
@@ -1460,12 +5786,349 @@ impl Config {
                     }
                 })
                 .unwrap_or_else(|| base_defaults.browser.clone()),
+            tool_filter: toml
+                .defaults
+                .tool_filter
+                .map(|t| {
+                    let base = &base_defaults.tool_filter;
+                    ToolFilterConfig {
+                        enabled: t.enabled.unwrap_or(base.enabled),
+                        top_k: t.top_k.unwrap_or(base.top_k),
+                        always_include: t
+                            .always_include
+                            .unwrap_or_else(|| base.always_include.clone()),
+                    }
+                })
+                .unwrap_or_else(|| base_defaults.tool_filter.clone()),
+            compression: toml
+                .defaults
+                .compression
+                .map(|c| CompressionConfig {
+                    enabled: c.enabled.unwrap_or(base_defaults.compression.enabled),
+                    ratio: c.ratio.unwrap_or(base_defaults.compression.ratio),
+                    min_chars: c.min_chars.unwrap_or(base_defaults.compression.min_chars),
+                })
+                .unwrap_or(base_defaults.compression),
+            preview: toml
+                .defaults
+                .preview
+                .map(|p| {
+                    let base = &base_defaults.preview;
+                    PreviewConfig {
+                        enabled: p.enabled.unwrap_or(base.enabled),
+                        tools: p.tools.unwrap_or_else(|| base.tools.clone()),
+                        timeout_secs: p.timeout_secs.unwrap_or(base.timeout_secs),
+                    }
+                })
+                .unwrap_or_else(|| base_defaults.preview.clone()),
+            tool_guard: toml
+                .defaults
+                .tool_guard
+                .map(|g| {
+                    let base = &base_defaults.tool_guard;
+                    ToolGuardConfig {
+                        enabled: g.enabled.unwrap_or(base.enabled),
+                        tools: g.tools.unwrap_or_else(|| base.tools.clone()),
+                        model: g.model.unwrap_or_else(|| base.model.clone()),
+                        block_below: g.block_below.unwrap_or(base.block_below),
+                        confirm_below: g.confirm_below.unwrap_or(base.confirm_below),
+                        timeout_secs: g.timeout_secs.unwrap_or(base.timeout_secs),
+                    }
+                })
+                .unwrap_or_else(|| base_defaults.tool_guard.clone()),
+            cost_gate: toml
+                .defaults
+                .cost_gate
+                .map(|c| {
+                    let base = &base_defaults.cost_gate;
+                    CostGateConfig {
+                        enabled: c.enabled.unwrap_or(base.enabled),
+                        threshold_usd: c.threshold_usd.unwrap_or(base.threshold_usd),
+                        expected_completions: c
+                            .expected_completions
+                            .unwrap_or(base.expected_completions),
+                        expected_output_tokens: c
+                            .expected_output_tokens
+                            .unwrap_or(base.expected_output_tokens),
+                        timeout_secs: c.timeout_secs.unwrap_or(base.timeout_secs),
+                    }
+                })
+                .unwrap_or(base_defaults.cost_gate),
+            computer_use: toml
+                .defaults
+                .computer_use
+                .map(|c| {
+                    let base = &base_defaults.computer_use;
+                    ComputerUseConfig {
+                        enabled: c.enabled.unwrap_or(base.enabled),
+                        confirm: c.confirm.unwrap_or(base.confirm),
+                        excluded_applications: c
+                            .excluded_applications
+                            .unwrap_or_else(|| base.excluded_applications.clone()),
+                        display: c.display.or_else(|| base.display.clone()),
+                    }
+                })
+                .unwrap_or_else(|| base_defaults.computer_use.clone()),
+            ocr: toml
+                .defaults
+                .ocr
+                .map(|o| {
+                    let base = &base_defaults.ocr;
+                    OcrConfig {
+                        enabled: o.enabled.unwrap_or(base.enabled),
+                        backend: o.backend.unwrap_or(base.backend),
+                        fallback: o.fallback.unwrap_or(base.fallback),
+                        model: o.model.or_else(|| base.model.clone()),
+                        languages: o.languages.unwrap_or_else(|| base.languages.clone()),
+                    }
+                })
+                .unwrap_or_else(|| base_defaults.ocr.clone()),
+            image_generation: toml
+                .defaults
+                .image_generation
+                .map(|i| i.resolve(&base_defaults.image_generation))
+                .unwrap_or_else(|| base_defaults.image_generation.clone()),
+            home_assistant: toml
+                .defaults
+                .home_assistant
+                .map(|h| {
+                    let base = &base_defaults.home_assistant;
+                    HomeAssistantConfig {
+                        enabled: h.enabled.unwrap_or(base.enabled),
+                        url: h.url.unwrap_or_else(|| base.url.clone()),
+                        token: h
+                            .token
+                            .as_deref()
+                            .and_then(resolve_env_value)
+                            .or_else(|| base.token.clone()),
+                        entities: h.entities.unwrap_or_else(|| base.entities.clone()),
+                        triggers: h
+                            .triggers
+                            .map(resolve_home_assistant_triggers)
+                            .unwrap_or_else(|| base.triggers.clone()),
+                    }
+                })
+                .unwrap_or_else(|| base_defaults.home_assistant.clone()),
+            issues: toml
+                .defaults
+                .issues
+                .map(|i| {
+                    let base = &base_defaults.issues;
+                    IssuesConfig {
+                        enabled: i.enabled.unwrap_or(base.enabled),
+                        workspaces: i
+                            .workspaces
+                            .map(resolve_issue_workspaces)
+                            .unwrap_or_else(|| base.workspaces.clone()),
+                    }
+                })
+                .unwrap_or_else(|| base_defaults.issues.clone()),
+            sql: toml
+                .defaults
+                .sql
+                .map(|s| s.resolve(&base_defaults.sql))
+                .unwrap_or_else(|| base_defaults.sql.clone()),
+            http: toml
+                .defaults
+                .http
+                .map(|h| h.resolve(&base_defaults.http))
+                .unwrap_or_else(|| base_defaults.http.clone()),
+            kubernetes: toml
+                .defaults
+                .kubernetes
+                .map(|k| k.resolve(&base_defaults.kubernetes))
+                .unwrap_or_else(|| base_defaults.kubernetes.clone()),
+            observability: toml
+                .defaults
+                .observability
+                .map(|o| o.resolve(&base_defaults.observability))
+                .unwrap_or_else(|| base_defaults.observability.clone()),
+            incidents: toml
+                .defaults
+                .incidents
+                .map(|i| i.resolve(&base_defaults.incidents))
+                .unwrap_or_else(|| base_defaults.incidents.clone()),
+            storage: toml
+                .defaults
+                .storage
+                .map(|s| s.resolve(&base_defaults.storage))
+                .unwrap_or_else(|| base_defaults.storage.clone()),
+            rate_limit: toml
+                .defaults
+                .rate_limit
+                .map(|r| RateLimitConfig {
+                    enabled: r.enabled.unwrap_or(base_defaults.rate_limit.enabled),
+                    user_per_minute: r
+                        .user_per_minute
+                        .unwrap_or(base_defaults.rate_limit.user_per_minute),
+                    user_burst: r.user_burst.unwrap_or(base_defaults.rate_limit.user_burst),
+                    user_per_hour: r
+                        .user_per_hour
+                        .unwrap_or(base_defaults.rate_limit.user_per_hour),
+                    channel_per_minute: r
+                        .channel_per_minute
+                        .unwrap_or(base_defaults.rate_limit.channel_per_minute),
+                    channel_burst: r
+                        .channel_burst
+                        .unwrap_or(base_defaults.rate_limit.channel_burst),
+                })
+                .unwrap_or(base_defaults.rate_limit),
+            dedup: toml
+                .defaults
+                .dedup
+                .map(|d| DedupConfig {
+                    enabled: d.enabled.unwrap_or(base_defaults.dedup.enabled),
+                    event_window_secs: d
+                        .event_window_secs
+                        .unwrap_or(base_defaults.dedup.event_window_secs),
+                    content_window_secs: d
+                        .content_window_secs
+                        .unwrap_or(base_defaults.dedup.content_window_secs),
+                })
+                .unwrap_or(base_defaults.dedup),
+            flags: toml.defaults.flags,
+            loop_detection: toml
+                .defaults
+                .loop_detection
+                .map(|l| LoopDetectionConfig {
+                    enabled: l.enabled.unwrap_or(base_defaults.loop_detection.enabled),
+                    max_repeated_calls: l
+                        .max_repeated_calls
+                        .unwrap_or(base_defaults.loop_detection.max_repeated_calls),
+                    max_stalled_rounds: l
+                        .max_stalled_rounds
+                        .unwrap_or(base_defaults.loop_detection.max_stalled_rounds),
+                })
+                .unwrap_or(base_defaults.loop_detection),
+            usage_anomalies: toml
+                .defaults
+                .usage_anomalies
+                .map(|u| u.resolve(&base_defaults.usage_anomalies))
+                .unwrap_or_else(|| base_defaults.usage_anomalies.clone()),
+            budget: toml
+                .defaults
+                .budget
+                .map(|b| b.resolve(&base_defaults.budget))
+                .unwrap_or_else(|| base_defaults.budget.clone()),
+            fact_check: toml
+                .defaults
+                .fact_check
+                .map(|f| f.resolve(&base_defaults.fact_check))
+                .unwrap_or_else(|| base_defaults.fact_check.clone()),
+            provenance: toml
+                .defaults
+                .provenance
+                .map(|p| p.resolve(&base_defaults.provenance))
+                .unwrap_or_else(|| base_defaults.provenance.clone()),
+            escalation: toml
+                .defaults
+                .escalation
+                .map(|e| e.resolve(&base_defaults.escalation))
+                .unwrap_or_else(|| base_defaults.escalation.clone()),
+            generation: toml
+                .defaults
+                .generation
+                .map(|g| g.resolve(&base_defaults.generation))
+                .unwrap_or_else(|| base_defaults.generation.clone()),
+            output_caps: toml
+                .defaults
+                .output_caps
+                .map(|o| o.resolve(&base_defaults.output_caps))
+                .unwrap_or_else(|| base_defaults.output_caps.clone()),
+            empty_response: toml
+                .defaults
+                .empty_response
+                .map(|e| e.resolve(&base_defaults.empty_response))
+                .unwrap_or_else(|| base_defaults.empty_response.clone()),
+            self_report: toml
+                .defaults
+                .self_report
+                .map(|s| s.resolve(&base_defaults.self_report))
+                .unwrap_or_else(|| base_defaults.self_report.clone()),
+            latency_budget: toml
+                .defaults
+                .latency_budget
+                .map(|l| l.resolve(&base_defaults.latency_budget))
+                .unwrap_or_else(|| base_defaults.latency_budget.clone()),
+            usage_reports: toml
+                .defaults
+                .usage_reports
+                .map(|u| u.resolve(&base_defaults.usage_reports))
+                .unwrap_or_else(|| base_defaults.usage_reports.clone()),
+            retention: toml
+                .defaults
+                .retention
+                .map(|r| {
+                    let base = &base_defaults.retention;
+                    RetentionConfig {
+                        enabled: r.enabled.unwrap_or(base.enabled),
+                        idle_days: r.idle_days.unwrap_or(base.idle_days),
+                        action: r.action.unwrap_or(base.action),
+                        summarize: r.summarize.unwrap_or(base.summarize),
+                        check_interval_secs: r
+                            .check_interval_secs
+                            .unwrap_or(base.check_interval_secs),
+                        channels: channel_retention(r.channels, &base.channels),
+                    }
+                })
+                .unwrap_or_else(|| base_defaults.retention.clone()),
+            language: toml
+                .defaults
+                .language
+                .map(|l| {
+                    let mut channels = base_defaults.language.channels.clone();
+                    channels.extend(l.channels);
+                    LanguageConfig {
+                        detect: l.detect.unwrap_or(base_defaults.language.detect),
+                        channels,
+                    }
+                })
+                .unwrap_or_else(|| base_defaults.language.clone()),
+            network: toml
+                .defaults
+                .network
+                .map(|n| {
+                    let mut tools = base_defaults.network.tools.clone();
+                    tools.extend(n.tools);
+                    NetworkConfig { tools }
+                })
+                .unwrap_or_else(|| base_defaults.network.clone()),
+            threads: toml
+                .defaults
+                .threads
+                .map(|t| ThreadConfig {
+                    parent_context: t
+                        .parent_context
+                        .unwrap_or(base_defaults.threads.parent_context),
+                    parent_messages: t
+                        .parent_messages
+                        .unwrap_or(base_defaults.threads.parent_messages),
+                })
+                .unwrap_or_else(|| base_defaults.threads.clone()),
+            commands: toml
+                .defaults
+                .commands
+                .map(|c| CommandsConfig {
+                    enabled: c.enabled.unwrap_or(base_defaults.commands.enabled),
+                    admin_only: c
+                        .admin_only
+                        .unwrap_or_else(|| base_defaults.commands.admin_only.clone()),
+                    disabled: c
+                        .disabled
+                        .unwrap_or_else(|| base_defaults.commands.disabled.clone()),
+                })
+                .unwrap_or_else(|| base_defaults.commands.clone()),
+            admin_users: toml
+                .defaults
+                .admin_users
+                .unwrap_or_else(|| base_defaults.admin_users.clone()),
             brave_search_key: toml
                 .defaults
                 .brave_search_key
                 .as_deref()
                 .and_then(resolve_env_value)
                 .or_else(|| std::env::var("BRAVE_SEARCH_API_KEY").ok()),
+            seed: toml.defaults.seed.or(base_defaults.seed),
             history_backfill_count: base_defaults.history_backfill_count,
             cron: Vec::new(),
             opencode: toml
@@ -1506,6 +6169,8 @@ impl Config {
                 .and_then(|s| s.parse().ok())
                 .unwrap_or(base_defaults.worker_log_mode),
         };
+        validate_storage(&defaults.storage, "defaults")?;
+        validate_generation(&defaults.generation, "defaults")?;
 
         let mut agents: Vec<AgentConfig> = toml
             .agents
@@ -1532,9 +6197,79 @@ impl Config {
                     })
                     .collect();
 
+                let feeds = a
+                    .feeds
+                    .into_iter()
+                    .map(|f| FeedDef {
+                        id: f.id,
+                        urls: f.urls,
+                        interval_secs: f.interval_secs.unwrap_or(3600),
+                        prompt: f.prompt.unwrap_or_else(|| {
+                            "Write a short digest of these new feed entries.".into()
+                        }),
+                        delivery_target: f.delivery_target,
+                        max_items: f.max_items.unwrap_or(20),
+                        summarize: f.summarize,
+                        enabled: f.enabled,
+                    })
+                    .collect();
+
+                let digests = a
+                    .digests
+                    .into_iter()
+                    .map(|d| DigestDef {
+                        delivery_target: d.delivery_target.unwrap_or_else(|| d.channel.clone()),
+                        id: d.id,
+                        channel: d.channel,
+                        hours: d.hours.unwrap_or(24),
+                        interval_secs: d.interval_secs.unwrap_or(86400),
+                        active_hours: match (d.active_start_hour, d.active_end_hour) {
+                            (Some(s), Some(e)) => Some((s, e)),
+                            _ => None,
+                        },
+                        max_messages: d.max_messages.unwrap_or(1000),
+                        prime_cache: d.prime_cache,
+                        enabled: d.enabled,
+                    })
+                    .collect();
+
+                let personas = a
+                    .personas
+                    .into_iter()
+                    .map(|p| PersonaDef {
+                        name: p.name.to_ascii_lowercase(),
+                        description: p.description,
+                        preamble: p.preamble,
+                        tools: p.tools,
+                        model: p.model,
+                        admin_only: p.admin_only,
+                    })
+                    .collect();
+
+                let knowledge = a
+                    .knowledge
+                    .into_iter()
+                    .map(|k| KnowledgeSourceDef {
+                        id: k.id,
+                        kind: k.kind,
+                        url: k.url,
+                        branch: k.branch,
+                        paths: k.paths,
+                        token: k.token.as_deref().and_then(resolve_env_value),
+                        email: k.email,
+                        space: k.space,
+                        store: k.store.unwrap_or_else(|| "docs".into()),
+                        interval_secs: k.interval_secs.unwrap_or(86_400),
+                        chunk_size: k.chunk_size.unwrap_or(2_000),
+                        max_documents: k.max_documents.unwrap_or(1_000),
+                        enabled: k.enabled,
+                    })
+                    .collect();
+
                 AgentConfig {
                     id: a.id,
                     default: a.default,
+                    description: a.description,
                     workspace: a.workspace.map(PathBuf::from),
                     routing: agent_routing,
                     max_concurrent_branches: a.max_concurrent_branches,
@@ -1552,6 +6287,7 @@ impl Config {
                         emergency_threshold: c
                             .emergency_threshold
                             .unwrap_or(defaults.compaction.emergency_threshold),
+                        eviction: c.eviction.unwrap_or(defaults.compaction.eviction),
                     }),
                     memory_persistence: a.memory_persistence.map(|mp| MemoryPersistenceConfig {
                         enabled: mp.enabled.unwrap_or(defaults.memory_persistence.enabled),
@@ -1624,16 +6360,207 @@ impl Config {
                             .map(PathBuf::from)
                             .or_else(|| defaults.browser.screenshot_dir.clone()),
                     }),
+                    tool_filter: a.tool_filter.map(|t| ToolFilterConfig {
+                        enabled: t.enabled.unwrap_or(defaults.tool_filter.enabled),
+                        top_k: t.top_k.unwrap_or(defaults.tool_filter.top_k),
+                        always_include: t
+                            .always_include
+                            .unwrap_or_else(|| defaults.tool_filter.always_include.clone()),
+                    }),
+                    compression: a.compression.map(|c| CompressionConfig {
+                        enabled: c.enabled.unwrap_or(defaults.compression.enabled),
+                        ratio: c.ratio.unwrap_or(defaults.compression.ratio),
+                        min_chars: c.min_chars.unwrap_or(defaults.compression.min_chars),
+                    }),
+                    preview: a.preview.map(|p| PreviewConfig {
+                        enabled: p.enabled.unwrap_or(defaults.preview.enabled),
+                        tools: p.tools.unwrap_or_else(|| defaults.preview.tools.clone()),
+                        timeout_secs: p.timeout_secs.unwrap_or(defaults.preview.timeout_secs),
+                    }),
+                    tool_guard: a.tool_guard.map(|g| ToolGuardConfig {
+                        enabled: g.enabled.unwrap_or(defaults.tool_guard.enabled),
+                        tools: g.tools.unwrap_or_else(|| defaults.tool_guard.tools.clone()),
+                        model: g.model.unwrap_or_else(|| defaults.tool_guard.model.clone()),
+                        block_below: g.block_below.unwrap_or(defaults.tool_guard.block_below),
+                        confirm_below: g.confirm_below.unwrap_or(defaults.tool_guard.confirm_below),
+                        timeout_secs: g.timeout_secs.unwrap_or(defaults.tool_guard.timeout_secs),
+                    }),
+                    cost_gate: a.cost_gate.map(|c| CostGateConfig {
+                        enabled: c.enabled.unwrap_or(defaults.cost_gate.enabled),
+                        threshold_usd: c.threshold_usd.unwrap_or(defaults.cost_gate.threshold_usd),
+                        expected_completions: c
+                            .expected_completions
+                            .unwrap_or(defaults.cost_gate.expected_completions),
+                        expected_output_tokens: c
+                            .expected_output_tokens
+                            .unwrap_or(defaults.cost_gate.expected_output_tokens),
+                        timeout_secs: c.timeout_secs.unwrap_or(defaults.cost_gate.timeout_secs),
+                    }),
+                    computer_use: a.computer_use.map(|c| ComputerUseConfig {
+                        enabled: c.enabled.unwrap_or(defaults.computer_use.enabled),
+                        confirm: c.confirm.unwrap_or(defaults.computer_use.confirm),
+                        excluded_applications: c
+                            .excluded_applications
+                            .unwrap_or_else(|| defaults.computer_use.excluded_applications.clone()),
+                        display: c.display.or_else(|| defaults.computer_use.display.clone()),
+                    }),
+                    ocr: a.ocr.map(|o| OcrConfig {
+                        enabled: o.enabled.unwrap_or(defaults.ocr.enabled),
+                        backend: o.backend.unwrap_or(defaults.ocr.backend),
+                        fallback: o.fallback.unwrap_or(defaults.ocr.fallback),
+                        model: o.model.or_else(|| defaults.ocr.model.clone()),
+                        languages: o
+                            .languages
+                            .unwrap_or_else(|| defaults.ocr.languages.clone()),
+                    }),
+                    image_generation: a
+                        .image_generation
+                        .map(|i| i.resolve(&defaults.image_generation)),
+                    home_assistant: a.home_assistant.map(|h| HomeAssistantConfig {
+                        enabled: h.enabled.unwrap_or(defaults.home_assistant.enabled),
+                        url: h.url.unwrap_or_else(|| defaults.home_assistant.url.clone()),
+                        token: h
+                            .token
+                            .as_deref()
+                            .and_then(resolve_env_value)
+                            .or_else(|| defaults.home_assistant.token.clone()),
+                        entities: h
+                            .entities
+                            .unwrap_or_else(|| defaults.home_assistant.entities.clone()),
+                        triggers: h
+                            .triggers
+                            .map(resolve_home_assistant_triggers)
+                            .unwrap_or_else(|| defaults.home_assistant.triggers.clone()),
+                    }),
+                    issues: a.issues.map(|i| IssuesConfig {
+                        enabled: i.enabled.unwrap_or(defaults.issues.enabled),
+                        workspaces: i
+                            .workspaces
+                            .map(resolve_issue_workspaces)
+                            .unwrap_or_else(|| defaults.issues.workspaces.clone()),
+                    }),
+                    sql: a.sql.map(|s| s.resolve(&defaults.sql)),
+                    http: a.http.map(|h| h.resolve(&defaults.http)),
+                    kubernetes: a.kubernetes.map(|k| k.resolve(&defaults.kubernetes)),
+                    observability: a.observability.map(|o| o.resolve(&defaults.observability)),
+                    incidents: a.incidents.map(|i| i.resolve(&defaults.incidents)),
+                    storage: a.storage.map(|s| s.resolve(&defaults.storage)),
+                    rate_limit: a.rate_limit.map(|r| RateLimitConfig {
+                        enabled: r.enabled.unwrap_or(defaults.rate_limit.enabled),
+                        user_per_minute: r
+                            .user_per_minute
+                            .unwrap_or(defaults.rate_limit.user_per_minute),
+                        user_burst: r.user_burst.unwrap_or(defaults.rate_limit.user_burst),
+                        user_per_hour: r.user_per_hour.unwrap_or(defaults.rate_limit.user_per_hour),
+                        channel_per_minute: r
+                            .channel_per_minute
+                            .unwrap_or(defaults.rate_limit.channel_per_minute),
+                        channel_burst: r.channel_burst.unwrap_or(defaults.rate_limit.channel_burst),
+                    }),
+                    dedup: a.dedup.map(|d| DedupConfig {
+                        enabled: d.enabled.unwrap_or(defaults.dedup.enabled),
+                        event_window_secs: d
+                            .event_window_secs
+                            .unwrap_or(defaults.dedup.event_window_secs),
+                        content_window_secs: d
+                            .content_window_secs
+                            .unwrap_or(defaults.dedup.content_window_secs),
+                    }),
+                    loop_detection: a.loop_detection.map(|l| LoopDetectionConfig {
+                        enabled: l.enabled.unwrap_or(defaults.loop_detection.enabled),
+                        max_repeated_calls: l
+                            .max_repeated_calls
+                            .unwrap_or(defaults.loop_detection.max_repeated_calls),
+                        max_stalled_rounds: l
+                            .max_stalled_rounds
+                            .unwrap_or(defaults.loop_detection.max_stalled_rounds),
+                    }),
+                    usage_anomalies: a
+                        .usage_anomalies
+                        .map(|u| u.resolve(&defaults.usage_anomalies)),
+                    budget: a.budget.map(|b| b.resolve(&defaults.budget)),
+                    fact_check: a.fact_check.map(|f| f.resolve(&defaults.fact_check)),
+                    provenance: a.provenance.map(|p| p.resolve(&defaults.provenance)),
+                    escalation: a.escalation.map(|e| e.resolve(&defaults.escalation)),
+                    generation: a.generation.map(|g| g.resolve(&defaults.generation)),
+                    output_caps: a.output_caps.map(|o| o.resolve(&defaults.output_caps)),
+                    empty_response: a
+                        .empty_response
+                        .map(|e| e.resolve(&defaults.empty_response)),
+                    self_report: a.self_report.map(|s| s.resolve(&defaults.self_report)),
+                    latency_budget: a
+                        .latency_budget
+                        .map(|l| l.resolve(&defaults.latency_budget)),
+                    usage_reports: a.usage_reports.map(|u| u.resolve(&defaults.usage_reports)),
+                    retention: a.retention.map(|r| RetentionConfig {
+                        enabled: r.enabled.unwrap_or(defaults.retention.enabled),
+                        idle_days: r.idle_days.unwrap_or(defaults.retention.idle_days),
+                        action: r.action.unwrap_or(defaults.retention.action),
+                        summarize: r.summarize.unwrap_or(defaults.retention.summarize),
+                        check_interval_secs: r
+                            .check_interval_secs
+                            .unwrap_or(defaults.retention.check_interval_secs),
+                        channels: channel_retention(r.channels, &defaults.retention.channels),
+                    }),
+                    language: a.language.map(|l| {
+                        let mut channels = defaults.language.channels.clone();
+                        channels.extend(l.channels);
+                        LanguageConfig {
+                            detect: l.detect.unwrap_or(defaults.language.detect),
+                            channels,
+                        }
+                    }),
+                    network: a.network.map(|n| {
+                        let mut tools = defaults.network.tools.clone();
+                        tools.extend(n.tools);
+                        NetworkConfig { tools }
+                    }),
+                    threads: a.threads.map(|t| ThreadConfig {
+                        parent_context: t.parent_context.unwrap_or(defaults.threads.parent_context),
+                        parent_messages: t
+                            .parent_messages
+                            .unwrap_or(defaults.threads.parent_messages),
+                    }),
+                    commands: a.commands.map(|c| CommandsConfig {
+                        enabled: c.enabled.unwrap_or(defaults.commands.enabled),
+                        admin_only: c
+                            .admin_only
+                            .unwrap_or_else(|| defaults.commands.admin_only.clone()),
+                        disabled: c
+                            .disabled
+                            .unwrap_or_else(|| defaults.commands.disabled.clone()),
+                    }),
+                    admin_users: a.admin_users,
                     brave_search_key: a.brave_search_key.as_deref().and_then(resolve_env_value),
+                    seed: a.seed,
                     cron,
+                    feeds,
+                    digests,
+                    personas,
+                    flags: a.flags,
+                    retrieval: a.retrieval.map(|r| RetrievalConfig {
+                        store: r.store,
+                        top_k: r.top_k,
+                        min_score: r.min_score,
+                    }),
+                    knowledge,
                 }
             })
             .collect();
+        for agent in &agents {
+            if let Some(storage) = &agent.storage {
+                validate_storage(storage, &format!("agents.{}", agent.id))?;
+            }
+            if let Some(generation) = &agent.generation {
+                validate_generation(generation, &format!("agents.{}", agent.id))?;
+            }
+        }
 
         if agents.is_empty() {
             agents.push(AgentConfig {
                 id: "main".into(),
                 default: true,
+                description: None,
                 workspace: None,
                 routing: None,
                 max_concurrent_branches: None,
@@ -1647,8 +6574,51 @@ impl Config {
                 ingestion: None,
                 cortex: None,
                 browser: None,
+                tool_filter: None,
+                compression: None,
+                preview: None,
+                tool_guard: None,
+                cost_gate: None,
+                computer_use: None,
+                ocr: None,
+                image_generation: None,
+                home_assistant: None,
+                issues: None,
+                sql: None,
+                http: None,
+                kubernetes: None,
+                observability: None,
+                incidents: None,
+                storage: None,
+                rate_limit: None,
+                dedup: None,
+                flags: Vec::new(),
+                loop_detection: None,
+                usage_anomalies: None,
+                budget: None,
+                fact_check: None,
+                provenance: None,
+                escalation: None,
+                generation: None,
+                output_caps: None,
+                empty_response: None,
+                self_report: None,
+                latency_budget: None,
+                usage_reports: None,
+                retention: None,
+                language: None,
+                network: None,
+                threads: None,
+                commands: None,
+                admin_users: None,
                 brave_search_key: None,
+                seed: None,
                 cron: Vec::new(),
+                feeds: Vec::new(),
+                digests: Vec::new(),
+                personas: Vec::new(),
+                retrieval: None,
+                knowledge: Vec::new(),
             });
         }
 
@@ -1665,11 +6635,41 @@ impl Config {
                     .as_deref()
                     .and_then(resolve_env_value)
                     .or_else(|| std::env::var("DISCORD_BOT_TOKEN").ok())?;
+                let voice = d.voice.filter(|voice| voice.enabled).and_then(|voice| {
+                    let api_key = voice
+                        .api_key
+                        .as_deref()
+                        .and_then(resolve_env_value)
+                        .or_else(|| std::env::var("OPENAI_API_KEY").ok());
+                    let Some(api_key) = api_key else {
+                        tracing::warn!("discord voice needs a speech api_key, voice is disabled");
+                        return None;
+                    };
+                    Some(DiscordVoiceConfig {
+                        channels: voice.channels,
+                        activation_keywords: voice.activation_keywords,
+                        silence_ms: voice.silence_ms,
+                        mode: voice.mode,
+                        live: LiveTranscriptionConfig {
+                            triggers: voice.triggers,
+                            window_secs: voice.transcript_window_secs,
+                            cooldown_secs: voice.trigger_cooldown_secs,
+                        },
+                        speech: SpeechConfig {
+                            base_url: voice.base_url,
+                            api_key,
+                            stt_model: voice.stt_model,
+                            tts_model: voice.tts_model,
+                            tts_voice: voice.tts_voice,
+                        },
+                    })
+                });
                 Some(DiscordConfig {
                     enabled: d.enabled,
                     token,
                     dm_allowed_users: d.dm_allowed_users,
                     allow_bot_messages: d.allow_bot_messages,
+                    voice,
                 })
             }),
             slack: toml.messaging.slack.and_then(|s| {
@@ -1702,10 +6702,95 @@ impl Config {
                     dm_allowed_users: t.dm_allowed_users,
                 })
             }),
+            irc: toml.messaging.irc.map(|i| IrcConfig {
+                enabled: i.enabled,
+                server: i.server,
+                port: i.port,
+                tls: i.tls,
+                username: i.username.unwrap_or_else(|| i.nickname.clone()),
+                realname: i.realname.unwrap_or_else(|| "Spacebot".into()),
+                nickname: i.nickname,
+                password: i.password.as_deref().and_then(resolve_env_value),
+                sasl_username: i.sasl_username,
+                sasl_password: i.sasl_password.as_deref().and_then(resolve_env_value),
+                channels: i.channels,
+                dm_allowed_users: i.dm_allowed_users,
+                flood_burst: i.flood_burst.max(1),
+                flood_interval_ms: i.flood_interval_ms,
+            }),
+            xmpp: toml.messaging.xmpp.and_then(|x| {
+                let password = x
+                    .password
+                    .as_deref()
+                    .and_then(resolve_env_value)
+                    .or_else(|| std::env::var("XMPP_PASSWORD").ok())?;
+                Some(XmppConfig {
+                    enabled: x.enabled,
+                    jid: x.jid,
+                    password,
+                    nickname: x.nickname,
+                    rooms: x.rooms,
+                    dm_allowed_users: x.dm_allowed_users,
+                })
+            }),
+            notify: toml.messaging.notify.map(|n| NotifyConfig {
+                enabled: n.enabled,
+                desktop: n.desktop,
+                inbox: n
+                    .inbox
+                    .map(|path| instance_dir.join(path))
+                    .unwrap_or_else(|| instance_dir.join("inbox.md")),
+                app_name: n.app_name,
+            }),
+            twilio: toml.messaging.twilio.and_then(|t| {
+                let account_sid = t
+                    .account_sid
+                    .as_deref()
+                    .and_then(resolve_env_value)
+                    .or_else(|| std::env::var("TWILIO_ACCOUNT_SID").ok())?;
+                let auth_token = t
+                    .auth_token
+                    .as_deref()
+                    .and_then(resolve_env_value)
+                    .or_else(|| std::env::var("TWILIO_AUTH_TOKEN").ok())?;
+                Some(TwilioConfig {
+                    enabled: t.enabled,
+                    account_sid,
+                    auth_token,
+                    sms_number: t.sms_number,
+                    whatsapp_number: t.whatsapp_number,
+                    port: t.port,
+                    bind: t.bind,
+                    public_url: t.public_url.trim_end_matches('/').to_string(),
+                    allowed_numbers: t.allowed_numbers,
+                    max_sms_segments: t.max_sms_segments.max(1),
+                })
+            }),
             webhook: toml.messaging.webhook.map(|w| WebhookConfig {
                 enabled: w.enabled,
                 port: w.port,
                 bind: w.bind,
+                outbound: w
+                    .outbound
+                    .into_iter()
+                    .map(|o| OutboundWebhookConfig {
+                        signing: o.signing.and_then(|mut signing| {
+                            let Some(secret) = resolve_env_value(&signing.secret) else {
+                                tracing::warn!(
+                                    webhook = %o.name,
+                                    "webhook signing secret is unset, requests go out unsigned"
+                                );
+                                return None;
+                            };
+                            signing.secret = secret;
+                            Some(signing)
+                        }),
+                        name: o.name,
+                        url: o.url,
+                        template: o.template,
+                        max_retries: o.max_retries,
+                    })
+                    .collect(),
             }),
         };
 
@@ -1720,6 +6805,9 @@ impl Config {
                 chat_id: b.chat_id,
                 channel_ids: b.channel_ids,
                 dm_allowed_users: b.dm_allowed_users,
+                post_processors: b.post_processors,
+                disclosure: b.disclosure,
+                activation: b.activation,
             })
             .collect();
 
@@ -1729,6 +6817,56 @@ impl Config {
             bind: toml.api.bind,
         };
 
+        let telemetry = TelemetryConfig {
+            crash_reporting: toml.telemetry.crash_reporting,
+            dsn: toml.telemetry.dsn.as_deref().and_then(resolve_env_value),
+            environment: toml.telemetry.environment,
+            snapshot_interval_secs: toml
+                .telemetry
+                .snapshot_interval_secs
+                .unwrap_or(TelemetryConfig::default().snapshot_interval_secs),
+        };
+
+        let intake = IntakeConfig {
+            enabled: toml.intake.enabled,
+            method: toml.intake.method,
+            model: toml.intake.model,
+            min_confidence: toml
+                .intake
+                .min_confidence
+                .unwrap_or(toml.intake.method.default_min_confidence()),
+            default_agent: toml.intake.default_agent,
+        };
+
+        validate_topics(&toml.topics)?;
+        let topics = TopicsConfig {
+            enabled: toml.topics.enabled,
+            method: toml.topics.method,
+            model: toml.topics.model,
+            categories: toml.topics.categories,
+        };
+
+        validate_scripting(&toml.scripting)?;
+        let scripting = ScriptingConfig {
+            enabled: toml.scripting.enabled,
+            hooks: toml
+                .scripting
+                .hooks
+                .iter()
+                .map(|path| instance_dir.join(path))
+                .collect(),
+            commands: toml
+                .scripting
+                .commands
+                .into_iter()
+                .map(|(name, path)| (name, instance_dir.join(path)))
+                .collect(),
+            max_operations: toml
+                .scripting
+                .max_operations
+                .unwrap_or(ScriptingConfig::default().max_operations),
+        };
+
         Ok(Config {
             instance_dir,
             llm,
@@ -1737,6 +6875,10 @@ impl Config {
             messaging,
             bindings,
             api,
+            telemetry,
+            intake,
+            topics,
+            scripting,
         })
     }
 
@@ -1761,6 +6903,11 @@ impl Config {
     pub fn skills_dir(&self) -> PathBuf {
         self.instance_dir.join("skills")
     }
+
+    /// Path to instance-level prompt library directory.
+    pub fn prompt_library_dir(&self) -> PathBuf {
+        self.instance_dir.join("prompts")
+    }
 }
 
 /// Live configuration that can be hot-reloaded without restarting.
@@ -1784,8 +6931,51 @@ pub struct RuntimeConfig {
     pub max_concurrent_branches: ArcSwap<usize>,
     pub max_concurrent_workers: ArcSwap<usize>,
     pub browser_config: ArcSwap<BrowserConfig>,
+    pub tool_filter: ArcSwap<ToolFilterConfig>,
+    pub compression: ArcSwap<CompressionConfig>,
+    pub preview: ArcSwap<PreviewConfig>,
+    pub tool_guard: ArcSwap<ToolGuardConfig>,
+    pub cost_gate: ArcSwap<CostGateConfig>,
+    pub computer_use: ArcSwap<ComputerUseConfig>,
+    pub ocr: ArcSwap<OcrConfig>,
+    pub image_generation: ArcSwap<ImageGenerationConfig>,
+    pub home_assistant: ArcSwap<HomeAssistantConfig>,
+    pub issues: ArcSwap<IssuesConfig>,
+    pub sql: ArcSwap<SqlConfig>,
+    pub http: ArcSwap<HttpConfig>,
+    pub kubernetes: ArcSwap<KubernetesConfig>,
+    pub observability: ArcSwap<ObservabilityConfig>,
+    pub incidents: ArcSwap<IncidentsConfig>,
+    pub storage: ArcSwap<StorageConfig>,
+    pub feeds: ArcSwap<Vec<FeedDef>>,
+    pub digests: ArcSwap<Vec<DigestDef>>,
+    pub personas: ArcSwap<Vec<PersonaDef>>,
+    pub retrieval: ArcSwap<Option<RetrievalConfig>>,
+    pub knowledge: ArcSwap<Vec<KnowledgeSourceDef>>,
+    pub rate_limit: ArcSwap<RateLimitConfig>,
+    pub dedup: ArcSwap<DedupConfig>,
+    pub flags: ArcSwap<FlagSet>,
+    pub loop_detection: ArcSwap<LoopDetectionConfig>,
+    pub usage_anomalies: ArcSwap<UsageAnomalyConfig>,
+    pub budget: ArcSwap<BudgetConfig>,
+    pub fact_check: ArcSwap<FactCheckConfig>,
+    pub provenance: ArcSwap<ProvenanceConfig>,
+    pub escalation: ArcSwap<EscalationConfig>,
+    pub generation: ArcSwap<GenerationConfig>,
+    pub output_caps: ArcSwap<OutputCapsConfig>,
+    pub empty_response: ArcSwap<EmptyResponseConfig>,
+    pub self_report: ArcSwap<SelfReportConfig>,
+    pub latency_budget: ArcSwap<LatencyBudgetConfig>,
+    pub usage_reports: ArcSwap<UsageReportConfig>,
+    pub retention: ArcSwap<RetentionConfig>,
+    pub language: ArcSwap<LanguageConfig>,
+    pub network: ArcSwap<NetworkConfig>,
+    pub threads: ArcSwap<ThreadConfig>,
+    pub commands: ArcSwap<CommandsConfig>,
+    pub admin_users: ArcSwap<Vec<String>>,
     pub history_backfill_count: ArcSwap<usize>,
     pub brave_search_key: ArcSwap<Option<String>>,
+    pub seed: ArcSwap<Option<u64>>,
     pub cortex: ArcSwap<CortexConfig>,
     /// Cached memory bulletin generated by the cortex. Injected into every
     /// channel's system prompt. Empty string until the first cortex run.
@@ -1793,6 +6983,8 @@ pub struct RuntimeConfig {
     pub prompts: ArcSwap<crate::prompts::PromptEngine>,
     pub identity: ArcSwap<crate::identity::Identity>,
     pub skills: ArcSwap<crate::skills::SkillSet>,
+    /// Prompt snippets for `/prompt` and `{{prompt:name}}` references.
+    pub prompt_library: ArcSwap<crate::prompt_library::PromptLibrary>,
     pub opencode: ArcSwap<OpenCodeConfig>,
     /// Shared pool of OpenCode server processes. Lazily initialized on first use.
     pub opencode_server_pool: Arc<crate::opencode::OpenCodeServerPool>,
@@ -1835,13 +7027,57 @@ impl RuntimeConfig {
             max_concurrent_branches: ArcSwap::from_pointee(agent_config.max_concurrent_branches),
             max_concurrent_workers: ArcSwap::from_pointee(agent_config.max_concurrent_workers),
             browser_config: ArcSwap::from_pointee(agent_config.browser.clone()),
+            tool_filter: ArcSwap::from_pointee(agent_config.tool_filter.clone()),
+            compression: ArcSwap::from_pointee(agent_config.compression),
+            preview: ArcSwap::from_pointee(agent_config.preview.clone()),
+            tool_guard: ArcSwap::from_pointee(agent_config.tool_guard.clone()),
+            cost_gate: ArcSwap::from_pointee(agent_config.cost_gate),
+            computer_use: ArcSwap::from_pointee(agent_config.computer_use.clone()),
+            ocr: ArcSwap::from_pointee(agent_config.ocr.clone()),
+            image_generation: ArcSwap::from_pointee(agent_config.image_generation.clone()),
+            home_assistant: ArcSwap::from_pointee(agent_config.home_assistant.clone()),
+            issues: ArcSwap::from_pointee(agent_config.issues.clone()),
+            sql: ArcSwap::from_pointee(agent_config.sql.clone()),
+            http: ArcSwap::from_pointee(agent_config.http.clone()),
+            kubernetes: ArcSwap::from_pointee(agent_config.kubernetes.clone()),
+            observability: ArcSwap::from_pointee(agent_config.observability.clone()),
+            incidents: ArcSwap::from_pointee(agent_config.incidents.clone()),
+            storage: ArcSwap::from_pointee(agent_config.storage.clone()),
+            feeds: ArcSwap::from_pointee(agent_config.feeds.clone()),
+            digests: ArcSwap::from_pointee(agent_config.digests.clone()),
+            personas: ArcSwap::from_pointee(agent_config.personas.clone()),
+            retrieval: ArcSwap::from_pointee(agent_config.retrieval.clone()),
+            knowledge: ArcSwap::from_pointee(agent_config.knowledge.clone()),
+            rate_limit: ArcSwap::from_pointee(agent_config.rate_limit),
+            dedup: ArcSwap::from_pointee(agent_config.dedup),
+            flags: ArcSwap::from_pointee(agent_config.flags.clone()),
+            loop_detection: ArcSwap::from_pointee(agent_config.loop_detection),
+            usage_anomalies: ArcSwap::from_pointee(agent_config.usage_anomalies.clone()),
+            budget: ArcSwap::from_pointee(agent_config.budget.clone()),
+            fact_check: ArcSwap::from_pointee(agent_config.fact_check.clone()),
+            provenance: ArcSwap::from_pointee(agent_config.provenance.clone()),
+            escalation: ArcSwap::from_pointee(agent_config.escalation.clone()),
+            generation: ArcSwap::from_pointee(agent_config.generation.clone()),
+            output_caps: ArcSwap::from_pointee(agent_config.output_caps.clone()),
+            empty_response: ArcSwap::from_pointee(agent_config.empty_response.clone()),
+            self_report: ArcSwap::from_pointee(agent_config.self_report.clone()),
+            latency_budget: ArcSwap::from_pointee(agent_config.latency_budget.clone()),
+            usage_reports: ArcSwap::from_pointee(agent_config.usage_reports.clone()),
+            retention: ArcSwap::from_pointee(agent_config.retention.clone()),
+            language: ArcSwap::from_pointee(agent_config.language.clone()),
+            network: ArcSwap::from_pointee(agent_config.network.clone()),
+            threads: ArcSwap::from_pointee(agent_config.threads.clone()),
+            commands: ArcSwap::from_pointee(agent_config.commands.clone()),
+            admin_users: ArcSwap::from_pointee(agent_config.admin_users.clone()),
             history_backfill_count: ArcSwap::from_pointee(agent_config.history_backfill_count),
             brave_search_key: ArcSwap::from_pointee(agent_config.brave_search_key.clone()),
+            seed: ArcSwap::from_pointee(agent_config.seed),
             cortex: ArcSwap::from_pointee(agent_config.cortex),
             memory_bulletin: ArcSwap::from_pointee(String::new()),
             prompts: ArcSwap::from_pointee(prompts),
             identity: ArcSwap::from_pointee(identity),
             skills: ArcSwap::from_pointee(skills),
+            prompt_library: ArcSwap::from_pointee(Default::default()),
             opencode: ArcSwap::from_pointee(defaults.opencode.clone()),
             opencode_server_pool: Arc::new(server_pool),
             cron_store: ArcSwap::from_pointee(None),
@@ -1869,7 +7105,8 @@ impl RuntimeConfig {
     ///
     /// Finds the matching agent by ID, re-resolves it against defaults, and
     /// swaps all reloadable fields. Ignores values that require a restart
-    /// (API keys, DB paths, messaging adapters, agent topology).
+    /// (API keys, DB paths, messaging adapters, agent topology). Routing is
+    /// kept as is while another agent is the routing canary.
     pub fn reload_config(&self, config: &Config, agent_id: &str) {
         let agent = config.agents.iter().find(|a| a.id == agent_id);
         let Some(agent) = agent else {
@@ -1879,7 +7116,11 @@ impl RuntimeConfig {
 
         let resolved = agent.resolve(&config.instance_dir, &config.defaults);
 
-        self.routing.store(Arc::new(resolved.routing));
+        if rollout::routing_held(agent_id) {
+            tracing::info!(agent_id, "routing held until the canary is promoted");
+        } else {
+            self.routing.store(Arc::new(resolved.routing));
+        }
         self.compaction.store(Arc::new(resolved.compaction));
         self.memory_persistence
             .store(Arc::new(resolved.memory_persistence));
@@ -1894,10 +7135,55 @@ impl RuntimeConfig {
         self.max_concurrent_workers
             .store(Arc::new(resolved.max_concurrent_workers));
         self.browser_config.store(Arc::new(resolved.browser));
+        self.tool_filter.store(Arc::new(resolved.tool_filter));
+        self.compression.store(Arc::new(resolved.compression));
+        self.preview.store(Arc::new(resolved.preview));
+        self.tool_guard.store(Arc::new(resolved.tool_guard));
+        self.cost_gate.store(Arc::new(resolved.cost_gate));
+        self.computer_use.store(Arc::new(resolved.computer_use));
+        self.ocr.store(Arc::new(resolved.ocr));
+        self.image_generation
+            .store(Arc::new(resolved.image_generation));
+        self.home_assistant.store(Arc::new(resolved.home_assistant));
+        self.issues.store(Arc::new(resolved.issues));
+        self.sql.store(Arc::new(resolved.sql));
+        self.http.store(Arc::new(resolved.http));
+        self.kubernetes.store(Arc::new(resolved.kubernetes));
+        self.observability.store(Arc::new(resolved.observability));
+        self.incidents.store(Arc::new(resolved.incidents));
+        self.storage.store(Arc::new(resolved.storage));
+        self.feeds.store(Arc::new(resolved.feeds));
+        self.digests.store(Arc::new(resolved.digests));
+        self.personas.store(Arc::new(resolved.personas));
+        self.retrieval.store(Arc::new(resolved.retrieval));
+        self.knowledge.store(Arc::new(resolved.knowledge));
+        self.rate_limit.store(Arc::new(resolved.rate_limit));
+        self.dedup.store(Arc::new(resolved.dedup));
+        self.flags.store(Arc::new(resolved.flags));
+        self.loop_detection.store(Arc::new(resolved.loop_detection));
+        self.usage_anomalies
+            .store(Arc::new(resolved.usage_anomalies));
+        self.budget.store(Arc::new(resolved.budget));
+        self.fact_check.store(Arc::new(resolved.fact_check));
+        self.provenance.store(Arc::new(resolved.provenance));
+        self.escalation.store(Arc::new(resolved.escalation));
+        self.generation.store(Arc::new(resolved.generation));
+        self.output_caps.store(Arc::new(resolved.output_caps));
+        self.empty_response.store(Arc::new(resolved.empty_response));
+        self.self_report.store(Arc::new(resolved.self_report));
+        self.latency_budget.store(Arc::new(resolved.latency_budget));
+        self.usage_reports.store(Arc::new(resolved.usage_reports));
+        self.retention.store(Arc::new(resolved.retention));
+        self.language.store(Arc::new(resolved.language));
+        self.network.store(Arc::new(resolved.network));
+        self.threads.store(Arc::new(resolved.threads));
+        self.commands.store(Arc::new(resolved.commands));
+        self.admin_users.store(Arc::new(resolved.admin_users));
         self.history_backfill_count
             .store(Arc::new(resolved.history_backfill_count));
         self.brave_search_key
             .store(Arc::new(resolved.brave_search_key));
+        self.seed.store(Arc::new(resolved.seed));
         self.cortex.store(Arc::new(resolved.cortex));
 
         tracing::info!(agent_id, "runtime config reloaded");
@@ -1914,6 +7200,12 @@ impl RuntimeConfig {
         self.skills.store(Arc::new(skills));
         tracing::info!("skills reloaded");
     }
+
+    /// Reload the prompt library from disk.
+    pub fn reload_prompt_library(&self, library: crate::prompt_library::PromptLibrary) {
+        self.prompt_library.store(Arc::new(library));
+        tracing::info!("prompt library reloaded");
+    }
 }
 
 impl std::fmt::Debug for RuntimeConfig {
@@ -1976,17 +7268,25 @@ pub fn spawn_file_watcher(
             tracing::warn!(%error, path = %config_path.display(), "failed to watch config file");
         }
 
-        // Watch instance-level skills directory
-        let instance_skills_dir = instance_dir.join("skills");
-        if instance_skills_dir.is_dir() {
-            if let Err(error) = watcher.watch(&instance_skills_dir, RecursiveMode::Recursive) {
-                tracing::warn!(%error, path = %instance_skills_dir.display(), "failed to watch instance skills dir");
+        // Watch the instance dir itself for the managed agents file, which
+        // may not exist yet
+        if let Err(error) = watcher.watch(&instance_dir, RecursiveMode::NonRecursive) {
+            tracing::warn!(%error, path = %instance_dir.display(), "failed to watch instance dir");
+        }
+
+        // Watch instance-level skills and prompt library directories
+        for subdir in &["skills", "prompts"] {
+            let path = instance_dir.join(subdir);
+            if path.is_dir() {
+                if let Err(error) = watcher.watch(&path, RecursiveMode::Recursive) {
+                    tracing::warn!(%error, path = %path.display(), "failed to watch instance dir");
+                }
             }
         }
 
-        // Watch per-agent workspace directories (skills, identity)
+        // Watch per-agent workspace directories (skills, prompts, identity)
         for (_, workspace, _) in &agents {
-            for subdir in &["skills"] {
+            for subdir in &["skills", "prompts"] {
                 let path = workspace.join(subdir);
                 if path.is_dir() {
                     if let Err(error) = watcher.watch(&path, RecursiveMode::Recursive) {
@@ -2000,6 +7300,7 @@ pub fn spawn_file_watcher(
             }
         }
 
+        rollout::track(&config_path, &agents);
         tracing::info!("file watcher started");
 
         // Track config.toml content hash to skip no-op reloads
@@ -2030,6 +7331,9 @@ pub fn spawn_file_watcher(
 
             // Categorize what changed
             let mut config_changed = changed_paths.iter().any(|p| p.ends_with("config.toml"));
+            let agents_changed = changed_paths
+                .iter()
+                .any(|p| p.ends_with(crate::agent::managed::FILE_NAME));
             let identity_changed = changed_paths.iter().any(|p| {
                 let name = p.file_name().and_then(|n| n.to_str()).unwrap_or("");
                 matches!(name, "SOUL.md" | "IDENTITY.md" | "USER.md")
@@ -2037,9 +7341,17 @@ pub fn spawn_file_watcher(
             let skills_changed = changed_paths
                 .iter()
                 .any(|p| p.to_string_lossy().contains("skills"));
+            let prompts_changed = changed_paths
+                .iter()
+                .any(|p| p.parent().is_some_and(|dir| dir.ends_with("prompts")));
 
             // Skip entirely if nothing relevant changed
-            if !config_changed && !identity_changed && !skills_changed {
+            if !config_changed
+                && !agents_changed
+                && !identity_changed
+                && !skills_changed
+                && !prompts_changed
+            {
                 continue;
             }
 
@@ -2056,7 +7368,7 @@ pub fn spawn_file_watcher(
                 if current_hash == last_config_hash {
                     config_changed = false;
                     // If config was the only thing that "changed", skip entirely
-                    if !identity_changed && !skills_changed {
+                    if !agents_changed && !identity_changed && !skills_changed && !prompts_changed {
                         continue;
                     }
                 } else {
@@ -2066,8 +7378,10 @@ pub fn spawn_file_watcher(
 
             let changed_summary: Vec<&str> = [
                 config_changed.then_some("config"),
+                agents_changed.then_some("managed agents"),
                 identity_changed.then_some("identity"),
                 skills_changed.then_some("skills"),
+                prompts_changed.then_some("prompts"),
             ]
             .into_iter()
             .flatten()
@@ -2078,10 +7392,18 @@ pub fn spawn_file_watcher(
                 "file change detected, reloading"
             );
 
-            // Reload config.toml if it changed
-            let new_config = if config_changed {
+            // Reload config.toml if it or the managed agents changed
+            let reload = config_changed || agents_changed;
+            let new_config = if reload {
                 match Config::load_from_path(&config_path) {
-                    Ok(config) => Some(config),
+                    Ok(config) => {
+                        if config_changed {
+                            if let Ok(source) = std::fs::read_to_string(&config_path) {
+                                rollout::record_reload(&source);
+                            }
+                        }
+                        Some(config)
+                    }
                     Err(error) => {
                         tracing::error!(%error, "failed to reload config.toml, keeping previous values");
                         None
@@ -2091,6 +7413,22 @@ pub fn spawn_file_watcher(
                 None
             };
 
+            let detail = if reload && new_config.is_none() {
+                "config.toml failed to load, previous values kept".to_string()
+            } else {
+                format!("reloaded {}", changed_summary.join(", "))
+            };
+            let entry = crate::changelog::ChangelogEntry::new(
+                "config_reload",
+                changed_summary.join(", "),
+                detail,
+            );
+            if let Err(error) =
+                crate::changelog::append(&crate::changelog::path(&instance_dir), &entry)
+            {
+                tracing::warn!(%error, "failed to append config reload to the changelog");
+            }
+
             // Reload instance-level bindings and permissions
             if let Some(config) = &new_config {
                 bindings.store(Arc::new(config.bindings.clone()));
@@ -2143,10 +7481,13 @@ pub fn spawn_file_watcher(
                                         Arc::new(arc_swap::ArcSwap::from_pointee(perms))
                                     }
                                 };
-                                let adapter = crate::messaging::discord::DiscordAdapter::new(
+                                let mut adapter = crate::messaging::discord::DiscordAdapter::new(
                                     &discord_config.token,
                                     perms,
                                 );
+                                if let Some(voice_config) = &discord_config.voice {
+                                    adapter = adapter.with_voice(voice_config.clone());
+                                }
                                 if let Err(error) = manager.register_and_start(adapter).await {
                                     tracing::error!(%error, "failed to hot-start discord adapter from config change");
                                 }
@@ -2217,6 +7558,15 @@ pub fn spawn_file_watcher(
                     ));
                     runtime_config.reload_skills(skills);
                 }
+
+                if prompts_changed {
+                    let rt = tokio::runtime::Handle::current();
+                    let library = rt.block_on(crate::prompt_library::PromptLibrary::load(
+                        &instance_dir.join("prompts"),
+                        &workspace.join("prompts"),
+                    ));
+                    runtime_config.reload_prompt_library(library);
+                }
             }
         }
 
@@ -2224,13 +7574,28 @@ pub fn spawn_file_watcher(
     })
 }
 
+/// Providers offered during setup: display name, `[llm]` key, provider id.
+const SETUP_PROVIDERS: &[(&str, &str, &str)] = &[
+    ("Anthropic", "anthropic_key", "anthropic"),
+    ("OpenRouter", "openrouter_key", "openrouter"),
+    ("OpenAI", "openai_key", "openai"),
+    ("Ollama Cloud", "ollama_key", "ollama"),
+    ("Z.ai (GLM)", "zhipu_key", "zhipu"),
+    ("Groq", "groq_key", "groq"),
+    ("Together AI", "together_key", "together"),
+    ("Fireworks AI", "fireworks_key", "fireworks"),
+    ("DeepSeek", "deepseek_key", "deepseek"),
+    ("xAI (Grok)", "xai_key", "xai"),
+    ("Mistral AI", "mistral_key", "mistral"),
+    ("OpenCode Zen", "opencode_zen_key", "opencode-zen"),
+];
+
 /// Interactive first-run onboarding. Creates ~/.spacebot with a minimal config.
 ///
 /// Returns `Some(path)` if the CLI wizard created a config file, or `None` if
 /// the user chose to set up via the embedded UI (setup mode).
 pub fn run_onboarding() -> anyhow::Result<Option<PathBuf>> {
-    use dialoguer::{Input, Password, Select};
-    use std::io::Write;
+    use dialoguer::Select;
 
     println!();
     println!("  Welcome to Spacebot");
@@ -2255,55 +7620,121 @@ pub fn run_onboarding() -> anyhow::Result<Option<PathBuf>> {
     }
 
     println!();
+    let config_path = Config::default_instance_dir().join("config.toml");
+    run_setup_wizard(&config_path).map(Some)
+}
 
-    // 1. Pick a provider
-    let providers = &[
-        "Anthropic",
-        "OpenRouter",
-        "OpenAI",
-        "Ollama Cloud",
-        "Z.ai (GLM)",
-        "Groq",
-        "Together AI",
-        "Fireworks AI",
-        "DeepSeek",
-        "xAI (Grok)",
-        "Mistral AI",
-        "OpenCode Zen",
-    ];
-    let provider_idx = Select::new()
-        .with_prompt("Which LLM provider do you want to use?")
-        .items(providers)
-        .default(0)
-        .interact()?;
+/// `spacebot init`: walk through setup and write a starter config to
+/// `config_path` (default `<instance dir>/config.toml`), whether or not
+/// Spacebot has been set up before. An existing config is only replaced
+/// after confirmation, or with `force`.
+pub fn run_init(config_path: Option<PathBuf>, force: bool) -> anyhow::Result<PathBuf> {
+    let config_path =
+        config_path.unwrap_or_else(|| Config::default_instance_dir().join("config.toml"));
 
-    let (provider_key_name, toml_key, provider_id) = match provider_idx {
-        0 => ("Anthropic API key", "anthropic_key", "anthropic"),
-        1 => ("OpenRouter API key", "openrouter_key", "openrouter"),
-        2 => ("OpenAI API key", "openai_key", "openai"),
-        3 => ("Ollama Cloud API key", "ollama_key", "ollama"),
-        4 => ("Z.ai (GLM) API key", "zhipu_key", "zhipu"),
-        5 => ("Groq API key", "groq_key", "groq"),
-        6 => ("Together AI API key", "together_key", "together"),
-        7 => ("Fireworks AI API key", "fireworks_key", "fireworks"),
-        8 => ("DeepSeek API key", "deepseek_key", "deepseek"),
-        9 => ("xAI API key", "xai_key", "xai"),
-        10 => ("Mistral AI API key", "mistral_key", "mistral"),
-        11 => ("OpenCode Zen API key", "opencode_zen_key", "opencode-zen"),
-        _ => unreachable!(),
-    };
+    println!();
+    println!("  Spacebot setup");
+    println!("  --------------");
+    println!();
+
+    if config_path.exists() && !force {
+        let replace = dialoguer::Confirm::new()
+            .with_prompt(format!(
+                "{} already exists. Replace it?",
+                config_path.display()
+            ))
+            .default(false)
+            .interact()?;
+        if !replace {
+            anyhow::bail!("left {} unchanged", config_path.display());
+        }
+    }
+
+    run_setup_wizard(&config_path)
+}
+
+/// Ask for providers, models, an agent and optionally Discord, then write
+/// the config to `config_path`.
+fn run_setup_wizard(config_path: &Path) -> anyhow::Result<PathBuf> {
+    use dialoguer::{Input, MultiSelect, Password, Select};
+    use std::io::Write;
 
-    // 2. Get API key
-    let api_key: String = Password::new()
-        .with_prompt(format!("Enter your {provider_key_name}"))
+    // 1. Pick providers
+    let provider_names: Vec<&str> = SETUP_PROVIDERS.iter().map(|(name, ..)| *name).collect();
+    let chosen = MultiSelect::new()
+        .with_prompt("Which LLM providers do you want to use? (space to select, enter to confirm)")
+        .items(&provider_names)
         .interact()?;
+    if chosen.is_empty() {
+        anyhow::bail!("pick at least one provider");
+    }
+    let chosen: Vec<_> = chosen
+        .into_iter()
+        .map(|index| SETUP_PROVIDERS[index])
+        .collect();
 
-    let api_key = api_key.trim().to_string();
-    if api_key.is_empty() {
-        anyhow::bail!("API key cannot be empty");
+    // 2. Get API keys
+    println!();
+    println!("  Keys can be pasted, or given as a reference like env:ANTHROPIC_API_KEY");
+    println!("  so the secret stays out of the config file.");
+    println!();
+    let mut keys = Vec::new();
+    for (name, toml_key, _) in &chosen {
+        let api_key: String = Password::new()
+            .with_prompt(format!("{name} API key"))
+            .interact()?;
+        let api_key = api_key.trim().to_string();
+        if api_key.is_empty() {
+            anyhow::bail!("API key cannot be empty");
+        }
+        keys.push((*toml_key, api_key));
     }
 
-    // 3. Agent name
+    // 3. Pick default models
+    let primary = if chosen.len() == 1 {
+        0
+    } else {
+        let names: Vec<&str> = chosen.iter().map(|(name, ..)| *name).collect();
+        Select::new()
+            .with_prompt("Which provider should answer by default?")
+            .items(&names)
+            .default(0)
+            .interact()?
+    };
+    let routing = crate::llm::routing::defaults_for_provider(chosen[primary].2);
+    let conversation_model: String = Input::new()
+        .with_prompt("Model for conversations (channels and branches)")
+        .default(routing.channel.clone())
+        .interact_text()?;
+    let background_model: String = Input::new()
+        .with_prompt("Model for background work (workers, compaction, cortex)")
+        .default(routing.worker.clone())
+        .interact_text()?;
+    // Branches, compaction and cortex keep the provider's own defaults
+    // unless the model they'd share was changed.
+    let conversation_model = conversation_model.trim().to_string();
+    let background_model = background_model.trim().to_string();
+    let branch_model = if conversation_model == routing.channel {
+        routing.branch.clone()
+    } else {
+        conversation_model.clone()
+    };
+    let (compactor_model, cortex_model) = if background_model == routing.worker {
+        (routing.compactor.clone(), routing.cortex.clone())
+    } else {
+        (background_model.clone(), background_model.clone())
+    };
+    // The other providers back up the conversation model.
+    let fallbacks: Vec<String> = chosen
+        .iter()
+        .enumerate()
+        .filter(|(index, _)| *index != primary)
+        .map(|(_, (_, _, provider_id))| {
+            crate::llm::routing::defaults_for_provider(provider_id).channel
+        })
+        .collect();
+
+    // 4. Agent name
     let agent_id: String = Input::new()
         .with_prompt("Agent name")
         .default("main".to_string())
@@ -2311,20 +7742,13 @@ pub fn run_onboarding() -> anyhow::Result<Option<PathBuf>> {
 
     let agent_id = agent_id.trim().to_lowercase().replace(' ', "-");
 
-    // 4. Optional Discord setup
+    // 5. Optional Discord setup
     let setup_discord = Select::new()
         .with_prompt("Set up Discord integration?")
         .items(&["Not now", "Yes"])
         .default(0)
         .interact()?;
 
-    struct DiscordSetup {
-        token: String,
-        guild_id: Option<String>,
-        channel_ids: Vec<String>,
-        dm_user_ids: Vec<String>,
-    }
-
     let discord = if setup_discord == 1 {
         let token: String = Password::new()
             .with_prompt("Discord bot token")
@@ -2356,98 +7780,61 @@ pub fn run_onboarding() -> anyhow::Result<Option<PathBuf>> {
                 .allow_empty(true)
                 .default(String::new())
                 .interact_text()?;
-            let channel_ids: Vec<String> = channel_ids_raw
-                .split(',')
-                .map(|s| s.trim().to_string())
-                .filter(|s| !s.is_empty())
-                .collect();
-
             let dm_user_ids_raw: String = Input::new()
                 .with_prompt("User IDs allowed to DM the bot (comma-separated, or blank)")
                 .allow_empty(true)
                 .default(String::new())
                 .interact_text()?;
-            let dm_user_ids: Vec<String> = dm_user_ids_raw
-                .split(',')
-                .map(|s| s.trim().to_string())
-                .filter(|s| !s.is_empty())
-                .collect();
 
             Some(DiscordSetup {
                 token,
                 guild_id,
-                channel_ids,
-                dm_user_ids,
+                channel_ids: split_ids(&channel_ids_raw),
+                dm_user_ids: split_ids(&dm_user_ids_raw),
             })
         }
     } else {
         None
     };
 
-    // 5. Build config.toml
-    let instance_dir = Config::default_instance_dir();
-    let config_path = instance_dir.join("config.toml");
+    // 6. Write config.toml
+    let setup = StarterConfig {
+        keys,
+        routing: [
+            ("channel", conversation_model.clone()),
+            ("branch", branch_model),
+            ("worker", background_model),
+            ("compactor", compactor_model),
+            ("cortex", cortex_model),
+        ],
+        conversation_model,
+        fallbacks,
+        agent_id: agent_id.clone(),
+        discord,
+    };
 
-    // Create directory structure
+    let instance_dir = config_path
+        .parent()
+        .map(Path::to_path_buf)
+        .unwrap_or_else(|| PathBuf::from("."));
     std::fs::create_dir_all(&instance_dir)
         .with_context(|| format!("failed to create {}", instance_dir.display()))?;
 
-    let mut config_content = String::new();
-    config_content.push_str("[llm]\n");
-    config_content.push_str(&format!("{toml_key} = \"{api_key}\"\n"));
-    config_content.push('\n');
-
-    // Write routing defaults for the chosen provider
-    let routing = crate::llm::routing::defaults_for_provider(provider_id);
-    config_content.push_str("[defaults.routing]\n");
-    config_content.push_str(&format!("channel = \"{}\"\n", routing.channel));
-    config_content.push_str(&format!("branch = \"{}\"\n", routing.branch));
-    config_content.push_str(&format!("worker = \"{}\"\n", routing.worker));
-    config_content.push_str(&format!("compactor = \"{}\"\n", routing.compactor));
-    config_content.push_str(&format!("cortex = \"{}\"\n", routing.cortex));
-    config_content.push('\n');
-
-    config_content.push_str("[[agents]]\n");
-    config_content.push_str(&format!("id = \"{agent_id}\"\n"));
-    config_content.push_str("default = true\n");
-
-    if let Some(discord) = &discord {
-        config_content.push_str("\n[messaging.discord]\n");
-        config_content.push_str("enabled = true\n");
-        config_content.push_str(&format!("token = \"{}\"\n", discord.token));
-
-        // Write the binding
-        config_content.push_str("\n[[bindings]]\n");
-        config_content.push_str(&format!("agent_id = \"{agent_id}\"\n"));
-        config_content.push_str("channel = \"discord\"\n");
-        if let Some(guild_id) = &discord.guild_id {
-            config_content.push_str(&format!("guild_id = \"{guild_id}\"\n"));
-        }
-        if !discord.channel_ids.is_empty() {
-            let ids: Vec<String> = discord
-                .channel_ids
-                .iter()
-                .map(|id| format!("\"{id}\""))
-                .collect();
-            config_content.push_str(&format!("channel_ids = [{}]\n", ids.join(", ")));
-        }
-        if !discord.dm_user_ids.is_empty() {
-            let ids: Vec<String> = discord
-                .dm_user_ids
-                .iter()
-                .map(|id| format!("\"{id}\""))
-                .collect();
-            config_content.push_str(&format!("dm_allowed_users = [{}]\n", ids.join(", ")));
-        }
-    }
-
-    let mut file = std::fs::File::create(&config_path)
+    let mut file = std::fs::File::create(config_path)
         .with_context(|| format!("failed to create {}", config_path.display()))?;
-    file.write_all(config_content.as_bytes())?;
+    // The config holds the API keys; lock it down before writing it.
+    crate::permissions::restrict_to_owner(config_path)
+        .with_context(|| format!("failed to restrict access to {}", config_path.display()))?;
+    file.write_all(setup.render().as_bytes())?;
 
     println!();
     println!("  Config written to {}", config_path.display());
     println!("  Agent '{}' created.", agent_id);
+    // A key given as an unset env: reference is the usual reason.
+    if let Err(error) = Config::load_from_path(config_path) {
+        println!();
+        println!("  The config doesn't load yet: {error:#}");
+    }
     println!();
     println!("  You can customize identity files in:");
     println!(
@@ -2457,5 +7844,95 @@ pub fn run_onboarding() -> anyhow::Result<Option<PathBuf>> {
     );
     println!();
 
-    Ok(Some(config_path))
+    Ok(config_path.to_path_buf())
+}
+
+fn split_ids(raw: &str) -> Vec<String> {
+    raw.split(',')
+        .map(|id| id.trim().to_string())
+        .filter(|id| !id.is_empty())
+        .collect()
+}
+
+struct DiscordSetup {
+    token: String,
+    guild_id: Option<String>,
+    channel_ids: Vec<String>,
+    dm_user_ids: Vec<String>,
+}
+
+/// Answers from the setup wizard.
+struct StarterConfig {
+    /// `[llm]` key name and the key (or credential reference).
+    keys: Vec<(&'static str, String)>,
+    /// `[defaults.routing]` process type and model.
+    routing: [(&'static str, String); 5],
+    conversation_model: String,
+    /// Models tried when the conversation model fails.
+    fallbacks: Vec<String>,
+    agent_id: String,
+    discord: Option<DiscordSetup>,
+}
+
+impl StarterConfig {
+    fn render(&self) -> String {
+        use toml_edit::{Array, ArrayOfTables, DocumentMut, Item, Table, value};
+
+        let mut doc = DocumentMut::new();
+
+        let mut llm = Table::new();
+        for (toml_key, api_key) in &self.keys {
+            llm[toml_key] = value(api_key.as_str());
+        }
+        doc["llm"] = Item::Table(llm);
+
+        let mut routing = Table::new();
+        for (process_type, model) in &self.routing {
+            routing[process_type] = value(model);
+        }
+        if !self.fallbacks.is_empty() {
+            let mut fallbacks = Table::new();
+            fallbacks[&self.conversation_model] = value(self.fallbacks.iter().collect::<Array>());
+            routing["fallbacks"] = Item::Table(fallbacks);
+        }
+        let mut defaults = Table::new();
+        defaults.set_implicit(true);
+        defaults["routing"] = Item::Table(routing);
+        doc["defaults"] = Item::Table(defaults);
+
+        let mut agent = Table::new();
+        agent["id"] = value(&self.agent_id);
+        agent["default"] = value(true);
+        let mut agents = ArrayOfTables::new();
+        agents.push(agent);
+        doc["agents"] = Item::ArrayOfTables(agents);
+
+        if let Some(discord) = &self.discord {
+            let mut discord_table = Table::new();
+            discord_table["enabled"] = value(true);
+            discord_table["token"] = value(&discord.token);
+            let mut messaging = Table::new();
+            messaging.set_implicit(true);
+            messaging["discord"] = Item::Table(discord_table);
+            doc["messaging"] = Item::Table(messaging);
+
+            let mut binding = Table::new();
+            binding["agent_id"] = value(&self.agent_id);
+            binding["channel"] = value("discord");
+            if let Some(guild_id) = &discord.guild_id {
+                binding["guild_id"] = value(guild_id);
+            }
+            if !discord.channel_ids.is_empty() {
+                binding["channel_ids"] = value(discord.channel_ids.iter().collect::<Array>());
+            }
+            if !discord.dm_user_ids.is_empty() {
+                binding["dm_allowed_users"] = value(discord.dm_user_ids.iter().collect::<Array>());
+            }
+            let mut bindings = ArrayOfTables::new();
+            bindings.push(binding);
+            doc["bindings"] = Item::ArrayOfTables(bindings);
+        }
+
+        doc.to_string()
+    }
 }
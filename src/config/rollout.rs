@@ -0,0 +1,427 @@
+//! Previewing and staging config changes on a running daemon.
+//!
+//! The daemon keeps a snapshot of the config it's actually running: what it
+//! started with, plus whatever hot reloads have applied since. [`diff`]
+//! compares that against config.toml and says, for each changed setting,
+//! whether a reload applies it or it waits for a restart. A routing canary
+//! limits new routing to one agent, so a provider or model switch can be
+//! tried there before every agent gets it.
+
+use super::{Config, RuntimeConfig};
+
+use anyhow::Context as _;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use toml::{Table, Value};
+
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard, Weak};
+
+/// Sections read once at startup.
+const RESTART_SECTIONS: &[&str] = &["llm", "api", "telemetry", "intake", "topics"];
+
+/// Key suffixes whose values are credentials, shown only as a fingerprint.
+const SECRET_SUFFIXES: &[&str] = &["key", "token", "secret", "password", "dsn"];
+
+static ROLLOUT: Mutex<Rollout> = Mutex::new(Rollout {
+    config_path: None,
+    agents: Vec::new(),
+    running: None,
+    canary: None,
+});
+
+struct Rollout {
+    config_path: Option<PathBuf>,
+    agents: Vec<(String, Weak<RuntimeConfig>)>,
+    running: Option<Value>,
+    canary: Option<String>,
+}
+
+/// When a changed setting takes effect.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ApplyMode {
+    /// On the next reload.
+    Reload,
+    /// On the next reload, but only for the canary agent until promoted.
+    Canary,
+    /// Only after a restart.
+    Restart,
+}
+
+/// One setting that differs between two configs. Agents are keyed by ID,
+/// e.g. `agents.main.routing.channel`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ConfigChange {
+    pub path: String,
+    pub old: Option<String>,
+    pub new: Option<String>,
+    pub mode: ApplyMode,
+}
+
+/// The daemon's running config and canary, as read over IPC.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RolloutStatus {
+    /// The running config as TOML, with secrets fingerprinted. None before
+    /// the config has been loaded.
+    pub running: Option<String>,
+    pub canary: Option<String>,
+}
+
+/// Parse config.toml for diffing: agents keyed by ID and secrets replaced
+/// with a fingerprint, so a rotated key still shows up as changed.
+pub fn parse(source: &str) -> anyhow::Result<Value> {
+    let mut table: Table = toml::from_str(source)?;
+    if let Some(agents) = table.remove("agents") {
+        table.insert("agents".into(), agents_by_id(&agents));
+    }
+    let mut value = Value::Table(table);
+    mask_secrets(&mut value);
+    Ok(value)
+}
+
+/// Every setting that differs from `running` in `file`, in path order.
+pub fn diff(running: &Value, file: &Value, canary: Option<&str>) -> Vec<ConfigChange> {
+    let mut changes = Vec::new();
+    walk(&mut Vec::new(), Some(running), Some(file), &mut changes);
+    changes
+        .into_iter()
+        .map(|(path, old, new)| ConfigChange {
+            mode: apply_mode(&path, running, file, canary),
+            path: path.join("."),
+            old: old.map(|value| value.to_string()),
+            new: new.map(|value| value.to_string()),
+        })
+        .collect()
+}
+
+/// Whether `agent_id` keeps its current routing on reload because another
+/// agent is the canary.
+pub fn routing_held(agent_id: &str) -> bool {
+    lock()
+        .canary
+        .as_deref()
+        .is_some_and(|canary| canary != agent_id)
+}
+
+/// The running config and canary.
+pub fn status() -> RolloutStatus {
+    let state = lock();
+    RolloutStatus {
+        running: state
+            .running
+            .as_ref()
+            .and_then(|value| toml::to_string(value).ok()),
+        canary: state.canary.clone(),
+    }
+}
+
+/// Start tracking the config the daemon is running. Called when the file
+/// watcher starts, with the agents it reloads.
+pub(crate) fn track(config_path: &Path, agents: &[(String, PathBuf, Arc<RuntimeConfig>)]) {
+    let running = std::fs::read_to_string(config_path)
+        .ok()
+        .and_then(|source| parse(&source).ok());
+    let mut state = lock();
+    state.config_path = Some(config_path.to_path_buf());
+    state.agents = agents
+        .iter()
+        .map(|(agent_id, _, runtime_config)| (agent_id.clone(), Arc::downgrade(runtime_config)))
+        .collect();
+    state.running = running;
+    state.canary = None;
+}
+
+/// Fold a reloaded config.toml into the running snapshot. Only what the
+/// reload actually applied is taken.
+pub(crate) fn record_reload(source: &str) {
+    let Ok(file) = parse(source) else {
+        return;
+    };
+    let mut state = lock();
+    let running = match state.running.take() {
+        Some(running) => merge(running, &file, state.canary.as_deref()),
+        None => file,
+    };
+    state.running = Some(running);
+}
+
+/// Reload config.toml into every agent now. With a canary, new routing only
+/// reaches that agent; without one, any held routing goes to every agent.
+/// Returns the changes that were pending.
+pub fn apply(canary: Option<String>) -> anyhow::Result<Vec<ConfigChange>> {
+    let (config_path, agents) = {
+        let state = lock();
+        let config_path = state
+            .config_path
+            .clone()
+            .context("the config hasn't been loaded yet")?;
+        (config_path, state.agents.clone())
+    };
+    let unknown = canary
+        .as_ref()
+        .filter(|canary| !agents.iter().any(|(agent_id, _)| agent_id == *canary));
+    if let Some(canary) = unknown {
+        anyhow::bail!("no running agent named {canary}");
+    }
+
+    let source = std::fs::read_to_string(&config_path)
+        .with_context(|| format!("failed to read {}", config_path.display()))?;
+    let file = parse(&source)?;
+    let config = Config::load_from_path(&config_path)?;
+
+    let changes = {
+        let mut state = lock();
+        state.canary = canary;
+        let running = state.running.take().unwrap_or_else(|| file.clone());
+        let changes = diff(&running, &file, state.canary.as_deref());
+        state.running = Some(merge(running, &file, state.canary.as_deref()));
+        changes
+    };
+    for (agent_id, runtime_config) in agents {
+        if let Some(runtime_config) = runtime_config.upgrade() {
+            runtime_config.reload_config(&config, &agent_id);
+        }
+    }
+    Ok(changes)
+}
+
+fn lock() -> MutexGuard<'static, Rollout> {
+    ROLLOUT
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// `running` with every change a reload applies taken from `file`.
+fn merge(mut running: Value, file: &Value, canary: Option<&str>) -> Value {
+    let mut leaves = Vec::new();
+    walk(&mut Vec::new(), Some(&running), Some(file), &mut leaves);
+    for (path, _, new) in leaves {
+        if apply_mode(&path, &running, file, canary) == ApplyMode::Reload {
+            set_path(&mut running, &path, new);
+        }
+    }
+    running
+}
+
+type Leaf = (Vec<String>, Option<Value>, Option<Value>);
+
+/// Collect the leaves that differ, treating a missing table as empty.
+fn walk(path: &mut Vec<String>, old: Option<&Value>, new: Option<&Value>, leaves: &mut Vec<Leaf>) {
+    if old == new {
+        return;
+    }
+    let is_table = |value: Option<&Value>| value.is_none_or(Value::is_table);
+    if !is_table(old) || !is_table(new) {
+        leaves.push((path.clone(), old.cloned(), new.cloned()));
+        return;
+    }
+    let empty = Table::new();
+    let old = old.and_then(Value::as_table).unwrap_or(&empty);
+    let new = new.and_then(Value::as_table).unwrap_or(&empty);
+    let keys: BTreeSet<&String> = old.keys().chain(new.keys()).collect();
+    for key in keys {
+        path.push(key.clone());
+        walk(path, old.get(key), new.get(key), leaves);
+        path.pop();
+    }
+}
+
+fn apply_mode(path: &[String], running: &Value, file: &Value, canary: Option<&str>) -> ApplyMode {
+    let routing = || match canary {
+        Some(_) => ApplyMode::Canary,
+        None => ApplyMode::Reload,
+    };
+    let segment = |index: usize| path.get(index).map(String::as_str);
+    match segment(0) {
+        Some(section) if RESTART_SECTIONS.contains(&section) => ApplyMode::Restart,
+        Some("messaging") if path.last().is_some_and(|key| is_secret(key)) => ApplyMode::Restart,
+        Some("agents") => {
+            // Adding or removing an agent needs a restart.
+            let agent_id = segment(1).unwrap_or_default();
+            let in_both = [running, file].iter().all(|config| {
+                config
+                    .get("agents")
+                    .and_then(|agents| agents.get(agent_id))
+                    .is_some()
+            });
+            if !in_both {
+                ApplyMode::Restart
+            } else if segment(2) == Some("routing") {
+                routing()
+            } else {
+                ApplyMode::Reload
+            }
+        }
+        Some("defaults") if segment(1) == Some("routing") => routing(),
+        _ => ApplyMode::Reload,
+    }
+}
+
+fn agents_by_id(agents: &Value) -> Value {
+    let mut by_id = Table::new();
+    for (index, agent) in agents.as_array().into_iter().flatten().enumerate() {
+        let agent_id = agent
+            .get("id")
+            .and_then(Value::as_str)
+            .map(str::to_string)
+            .unwrap_or_else(|| index.to_string());
+        by_id.insert(agent_id, agent.clone());
+    }
+    Value::Table(by_id)
+}
+
+fn is_secret(key: &str) -> bool {
+    SECRET_SUFFIXES.iter().any(|suffix| key.ends_with(suffix))
+}
+
+fn mask_secrets(value: &mut Value) {
+    match value {
+        Value::Table(table) => {
+            for (key, value) in table.iter_mut() {
+                match value {
+                    Value::String(secret) if is_secret(key) => {
+                        let digest = format!("{:x}", Sha256::digest(secret.as_bytes()));
+                        *secret = format!("sha256:{}", &digest[..8]);
+                    }
+                    _ => mask_secrets(value),
+                }
+            }
+        }
+        Value::Array(values) => values.iter_mut().for_each(mask_secrets),
+        _ => {}
+    }
+}
+
+fn set_path(root: &mut Value, path: &[String], value: Option<Value>) {
+    let Some((last, parents)) = path.split_last() else {
+        return;
+    };
+    let Some(mut table) = root.as_table_mut() else {
+        return;
+    };
+    for key in parents {
+        let entry = table
+            .entry(key.clone())
+            .or_insert_with(|| Value::Table(Table::new()));
+        if !entry.is_table() {
+            *entry = Value::Table(Table::new());
+        }
+        let Some(next) = entry.as_table_mut() else {
+            return;
+        };
+        table = next;
+    }
+    match value {
+        Some(value) => table.insert(last.clone(), value),
+        None => table.remove(last),
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RUNNING: &str = r#"
+        [llm]
+        anthropic_key = "sk-old"
+
+        [defaults.routing]
+        channel = "anthropic/claude-sonnet-4"
+
+        [[agents]]
+        id = "main"
+        max_turns = 5
+
+        [[agents]]
+        id = "support"
+    "#;
+
+    const FILE: &str = r#"
+        [llm]
+        anthropic_key = "sk-new"
+
+        [defaults.routing]
+        channel = "openai/gpt-4.1"
+
+        [[agents]]
+        id = "main"
+        max_turns = 8
+
+        [[agents]]
+        id = "support"
+
+        [[agents]]
+        id = "research"
+    "#;
+
+    fn change<'a>(changes: &'a [ConfigChange], path: &str) -> &'a ConfigChange {
+        changes
+            .iter()
+            .find(|change| change.path == path)
+            .unwrap_or_else(|| panic!("no change at {path}"))
+    }
+
+    #[test]
+    fn test_diff_modes() {
+        let running = parse(RUNNING).unwrap();
+        let file = parse(FILE).unwrap();
+        let changes = diff(&running, &file, None);
+        assert_eq!(changes.len(), 4);
+
+        let key = change(&changes, "llm.anthropic_key");
+        assert_eq!(key.mode, ApplyMode::Restart);
+        assert!(key.old.as_deref().unwrap().starts_with("\"sha256:"));
+        assert_ne!(key.old, key.new);
+        assert!(!format!("{changes:?}").contains("sk-"));
+
+        let routing = change(&changes, "defaults.routing.channel");
+        assert_eq!(routing.mode, ApplyMode::Reload);
+        assert_eq!(routing.new.as_deref(), Some("\"openai/gpt-4.1\""));
+        assert_eq!(
+            change(&changes, "agents.main.max_turns").mode,
+            ApplyMode::Reload
+        );
+        assert_eq!(
+            change(&changes, "agents.research.id").mode,
+            ApplyMode::Restart
+        );
+
+        let changes = diff(&running, &file, Some("main"));
+        assert_eq!(
+            change(&changes, "defaults.routing.channel").mode,
+            ApplyMode::Canary
+        );
+    }
+
+    #[test]
+    fn test_merge_takes_only_reloaded_changes() {
+        let running = parse(RUNNING).unwrap();
+        let file = parse(FILE).unwrap();
+
+        let merged = merge(running.clone(), &file, Some("main"));
+        let pending: Vec<_> = diff(&merged, &file, Some("main"))
+            .into_iter()
+            .map(|change| change.path)
+            .collect();
+        assert_eq!(
+            pending,
+            [
+                "agents.research.id",
+                "defaults.routing.channel",
+                "llm.anthropic_key"
+            ]
+        );
+
+        // Promoting the canary applies the held routing.
+        let merged = merge(merged, &file, None);
+        let pending = diff(&merged, &file, None);
+        assert!(
+            pending
+                .iter()
+                .all(|change| change.mode == ApplyMode::Restart)
+        );
+        assert_eq!(pending.len(), 2);
+    }
+}
@@ -2,7 +2,18 @@
 
 pub mod channels;
 pub mod context;
+pub mod escalations;
+pub mod forks;
 pub mod history;
+pub mod links;
+pub mod scratchpad;
+pub mod shares;
+pub mod transcript;
 
-pub use channels::ChannelStore;
-pub use history::{ConversationLogger, ProcessRunLogger, TimelineItem};
+pub use channels::{ChannelPin, ChannelStore};
+pub use escalations::{Escalation, EscalationStore};
+pub use forks::{ConversationFork, ForkStore};
+pub use history::{ConversationLogger, ProcessRunLogger, ReplyAttribution, TimelineItem};
+pub use links::{ConversationLink, LinkStore, LinkedMessage};
+pub use scratchpad::{ScratchpadEntry, ScratchpadStore};
+pub use shares::{ShareLink, ShareLinkStore};
@@ -21,10 +21,24 @@ pub struct ChannelInfo {
     pub display_name: Option<String>,
     pub platform_meta: Option<serde_json::Value>,
     pub is_active: bool,
+    /// Model set with `/model set`, replacing the agent's channel model.
+    pub model_override: Option<String>,
+    /// Persona set with `/persona`, if any.
+    pub persona: Option<String>,
     pub created_at: chrono::DateTime<chrono::Utc>,
     pub last_activity_at: chrono::DateTime<chrono::Utc>,
 }
 
+/// A fact pinned to a conversation.
+#[derive(Debug, Clone)]
+pub struct ChannelPin {
+    pub id: String,
+    pub content: String,
+    /// The sender ID of whoever pinned it, or `agent` for the pin tool.
+    pub pinned_by: String,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
 impl ChannelStore {
     pub fn new(pool: SqlitePool) -> Self {
         Self { pool }
@@ -83,7 +97,7 @@ impl ChannelStore {
     /// List all active channels, most recently active first.
     pub async fn list_active(&self) -> crate::error::Result<Vec<ChannelInfo>> {
         let rows = sqlx::query(
-            "SELECT id, platform, display_name, platform_meta, is_active, created_at, last_activity_at \
+            "SELECT id, platform, display_name, platform_meta, is_active, model_override, persona, created_at, last_activity_at \
              FROM channels \
              WHERE is_active = 1 \
              ORDER BY last_activity_at DESC"
@@ -140,7 +154,7 @@ impl ChannelStore {
     /// Get a single channel by exact ID.
     pub async fn get(&self, channel_id: &str) -> crate::error::Result<Option<ChannelInfo>> {
         let row = sqlx::query(
-            "SELECT id, platform, display_name, platform_meta, is_active, created_at, last_activity_at \
+            "SELECT id, platform, display_name, platform_meta, is_active, model_override, persona, created_at, last_activity_at \
              FROM channels \
              WHERE id = ?"
         )
@@ -152,6 +166,138 @@ impl ChannelStore {
         Ok(row.map(row_to_channel_info))
     }
 
+    /// The channel's model override, if one is set.
+    pub async fn model_override(&self, channel_id: &str) -> crate::error::Result<Option<String>> {
+        let model: Option<Option<String>> =
+            sqlx::query_scalar("SELECT model_override FROM channels WHERE id = ?")
+                .bind(channel_id)
+                .fetch_optional(&self.pool)
+                .await
+                .map_err(|e| anyhow::anyhow!(e))?;
+        Ok(model.flatten())
+    }
+
+    /// Set or clear (with None) the channel's model override.
+    pub async fn set_model_override(
+        &self,
+        channel_id: &str,
+        model: Option<&str>,
+    ) -> crate::error::Result<()> {
+        sqlx::query(
+            "INSERT INTO channels (id, platform, model_override) VALUES (?, ?, ?) \
+             ON CONFLICT(id) DO UPDATE SET model_override = excluded.model_override",
+        )
+        .bind(channel_id)
+        .bind(extract_platform(channel_id))
+        .bind(model)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| anyhow::anyhow!(e))?;
+        Ok(())
+    }
+
+    /// The channel's persona, if one is set.
+    pub async fn persona(&self, channel_id: &str) -> crate::error::Result<Option<String>> {
+        let persona: Option<Option<String>> =
+            sqlx::query_scalar("SELECT persona FROM channels WHERE id = ?")
+                .bind(channel_id)
+                .fetch_optional(&self.pool)
+                .await
+                .map_err(|e| anyhow::anyhow!(e))?;
+        Ok(persona.flatten())
+    }
+
+    /// Set or clear (with None) the channel's persona.
+    pub async fn set_persona(
+        &self,
+        channel_id: &str,
+        persona: Option<&str>,
+    ) -> crate::error::Result<()> {
+        sqlx::query(
+            "INSERT INTO channels (id, platform, persona) VALUES (?, ?, ?) \
+             ON CONFLICT(id) DO UPDATE SET persona = excluded.persona",
+        )
+        .bind(channel_id)
+        .bind(extract_platform(channel_id))
+        .bind(persona)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| anyhow::anyhow!(e))?;
+        Ok(())
+    }
+
+    /// The channel's pinned facts, oldest first.
+    pub async fn pins(&self, channel_id: &str) -> crate::error::Result<Vec<ChannelPin>> {
+        let rows = sqlx::query(
+            "SELECT id, content, pinned_by, created_at FROM channel_pins \
+             WHERE channel_id = ? ORDER BY created_at, rowid",
+        )
+        .bind(channel_id)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| anyhow::anyhow!(e))?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| ChannelPin {
+                id: row.try_get("id").unwrap_or_default(),
+                content: row.try_get("content").unwrap_or_default(),
+                pinned_by: row.try_get("pinned_by").unwrap_or_default(),
+                created_at: row
+                    .try_get("created_at")
+                    .unwrap_or_else(|_| chrono::Utc::now()),
+            })
+            .collect())
+    }
+
+    /// Pin a fact to the channel.
+    pub async fn add_pin(
+        &self,
+        channel_id: &str,
+        content: &str,
+        pinned_by: &str,
+    ) -> crate::error::Result<()> {
+        sqlx::query("INSERT INTO channels (id, platform) VALUES (?, ?) ON CONFLICT(id) DO NOTHING")
+            .bind(channel_id)
+            .bind(extract_platform(channel_id))
+            .execute(&self.pool)
+            .await
+            .map_err(|e| anyhow::anyhow!(e))?;
+
+        sqlx::query(
+            "INSERT INTO channel_pins (id, channel_id, content, pinned_by) VALUES (?, ?, ?, ?)",
+        )
+        .bind(uuid::Uuid::new_v4().to_string())
+        .bind(channel_id)
+        .bind(content)
+        .bind(pinned_by)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| anyhow::anyhow!(e))?;
+        Ok(())
+    }
+
+    /// Unpin one fact by ID. Returns whether it was pinned.
+    pub async fn remove_pin(&self, channel_id: &str, pin_id: &str) -> crate::error::Result<bool> {
+        let result = sqlx::query("DELETE FROM channel_pins WHERE channel_id = ? AND id = ?")
+            .bind(channel_id)
+            .bind(pin_id)
+            .execute(&self.pool)
+            .await
+            .map_err(|e| anyhow::anyhow!(e))?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Unpin everything in the channel, returning how many pins there were.
+    pub async fn clear_pins(&self, channel_id: &str) -> crate::error::Result<u64> {
+        let result = sqlx::query("DELETE FROM channel_pins WHERE channel_id = ?")
+            .bind(channel_id)
+            .execute(&self.pool)
+            .await
+            .map_err(|e| anyhow::anyhow!(e))?;
+        Ok(result.rows_affected())
+    }
+
     /// Resolve a channel's display name by ID.
     pub async fn resolve_name(&self, channel_id: &str) -> Option<String> {
         self.get(channel_id)
@@ -172,6 +318,8 @@ fn row_to_channel_info(row: sqlx::sqlite::SqliteRow) -> ChannelInfo {
         display_name: row.try_get("display_name").ok().flatten(),
         platform_meta,
         is_active: row.try_get::<i32, _>("is_active").unwrap_or(1) == 1,
+        model_override: row.try_get("model_override").ok().flatten(),
+        persona: row.try_get("persona").ok().flatten(),
         created_at: row
             .try_get("created_at")
             .unwrap_or_else(|_| chrono::Utc::now()),
@@ -184,7 +332,7 @@ fn row_to_channel_info(row: sqlx::sqlite::SqliteRow) -> ChannelInfo {
 /// Extract the platform name from a channel ID.
 ///
 /// "discord:123:456" -> "discord", "slack:T01:C01" -> "slack", "cron:daily" -> "cron"
-fn extract_platform(channel_id: &str) -> String {
+pub(super) fn extract_platform(channel_id: &str) -> String {
     channel_id
         .split(':')
         .next()
@@ -0,0 +1,222 @@
+//! Conversations flagged for human review (SQLite).
+//!
+//! A conversation has at most one open escalation. While it's open the
+//! channel logs what's said but doesn't answer, until a moderator resolves
+//! it. Escalations are kept after they're resolved, with who resolved them.
+
+use crate::error::Result;
+
+use anyhow::Context as _;
+use serde::Serialize;
+use sqlx::{Row as _, SqlitePool};
+
+/// `flagged_by` for escalations the agent raised with its `escalate` tool.
+pub const FLAGGED_BY_AGENT: &str = "agent";
+
+/// `flagged_by` for escalations raised by an escalation rule.
+pub const FLAGGED_BY_RULE: &str = "rule";
+
+/// A conversation flagged for review.
+#[derive(Debug, Clone, Serialize)]
+pub struct Escalation {
+    pub id: String,
+    pub channel_id: String,
+    /// Why, as written by the agent or the rule that matched.
+    pub reason: String,
+    /// [`FLAGGED_BY_AGENT`] or [`FLAGGED_BY_RULE`].
+    pub flagged_by: String,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    /// When moderators were told. None until the watcher gets to it.
+    pub notified_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub resolved_at: Option<chrono::DateTime<chrono::Utc>>,
+    /// The moderator who resumed the conversation.
+    pub resolved_by: Option<String>,
+}
+
+/// Escalations in SQLite.
+#[derive(Debug, Clone)]
+pub struct EscalationStore {
+    pool: SqlitePool,
+}
+
+impl EscalationStore {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+
+    /// Flag `channel_id` for review. Returns None if it already has an open
+    /// escalation, which stays as it was.
+    pub async fn flag(
+        &self,
+        channel_id: &str,
+        reason: &str,
+        flagged_by: &str,
+    ) -> Result<Option<Escalation>> {
+        let id = uuid::Uuid::new_v4().to_string();
+        let mut transaction = self
+            .pool
+            .begin()
+            .await
+            .context("failed to start escalation")?;
+        sqlx::query("INSERT INTO channels (id, platform) VALUES (?, ?) ON CONFLICT(id) DO NOTHING")
+            .bind(channel_id)
+            .bind(super::channels::extract_platform(channel_id))
+            .execute(&mut *transaction)
+            .await
+            .context("failed to record channel")?;
+        let result = sqlx::query(
+            "INSERT OR IGNORE INTO conversation_escalations (id, channel_id, reason, flagged_by) \
+             VALUES (?, ?, ?, ?)",
+        )
+        .bind(&id)
+        .bind(channel_id)
+        .bind(reason)
+        .bind(flagged_by)
+        .execute(&mut *transaction)
+        .await
+        .context("failed to save escalation")?;
+        transaction
+            .commit()
+            .await
+            .context("failed to save escalation")?;
+
+        if result.rows_affected() == 0 {
+            return Ok(None);
+        }
+        self.open(channel_id).await
+    }
+
+    /// The conversation's open escalation, if it has one.
+    pub async fn open(&self, channel_id: &str) -> Result<Option<Escalation>> {
+        let row = sqlx::query(
+            "SELECT id, channel_id, reason, flagged_by, created_at, notified_at, resolved_at, resolved_by \
+             FROM conversation_escalations \
+             WHERE channel_id = ? AND resolved_at IS NULL",
+        )
+        .bind(channel_id)
+        .fetch_optional(&self.pool)
+        .await
+        .context("failed to load escalation")?;
+        Ok(row.map(row_to_escalation))
+    }
+
+    /// Every open escalation, oldest first.
+    pub async fn list_open(&self) -> Result<Vec<Escalation>> {
+        let rows = sqlx::query(
+            "SELECT id, channel_id, reason, flagged_by, created_at, notified_at, resolved_at, resolved_by \
+             FROM conversation_escalations WHERE resolved_at IS NULL \
+             ORDER BY created_at, rowid",
+        )
+        .fetch_all(&self.pool)
+        .await
+        .context("failed to list escalations")?;
+        Ok(rows.into_iter().map(row_to_escalation).collect())
+    }
+
+    /// Open escalations moderators haven't been told about, oldest first.
+    pub async fn unnotified(&self) -> Result<Vec<Escalation>> {
+        let rows = sqlx::query(
+            "SELECT id, channel_id, reason, flagged_by, created_at, notified_at, resolved_at, resolved_by \
+             FROM conversation_escalations \
+             WHERE resolved_at IS NULL AND notified_at IS NULL \
+             ORDER BY created_at, rowid",
+        )
+        .fetch_all(&self.pool)
+        .await
+        .context("failed to list unnotified escalations")?;
+        Ok(rows.into_iter().map(row_to_escalation).collect())
+    }
+
+    /// Record that moderators were told about an escalation.
+    pub async fn mark_notified(&self, id: &str) -> Result<()> {
+        sqlx::query(
+            "UPDATE conversation_escalations SET notified_at = CURRENT_TIMESTAMP WHERE id = ?",
+        )
+        .bind(id)
+        .execute(&self.pool)
+        .await
+        .context("failed to mark escalation notified")?;
+        Ok(())
+    }
+
+    /// Resolve the conversation's open escalation, resuming automated
+    /// replies. Returns whether it had one.
+    pub async fn resolve(&self, channel_id: &str, resolved_by: &str) -> Result<bool> {
+        let result = sqlx::query(
+            "UPDATE conversation_escalations \
+             SET resolved_at = CURRENT_TIMESTAMP, resolved_by = ? \
+             WHERE channel_id = ? AND resolved_at IS NULL",
+        )
+        .bind(resolved_by)
+        .bind(channel_id)
+        .execute(&self.pool)
+        .await
+        .context("failed to resolve escalation")?;
+        Ok(result.rows_affected() > 0)
+    }
+}
+
+fn row_to_escalation(row: sqlx::sqlite::SqliteRow) -> Escalation {
+    Escalation {
+        id: row.try_get("id").unwrap_or_default(),
+        channel_id: row.try_get("channel_id").unwrap_or_default(),
+        reason: row.try_get("reason").unwrap_or_default(),
+        flagged_by: row.try_get("flagged_by").unwrap_or_default(),
+        created_at: row
+            .try_get("created_at")
+            .unwrap_or_else(|_| chrono::Utc::now()),
+        notified_at: row.try_get("notified_at").ok().flatten(),
+        resolved_at: row.try_get("resolved_at").ok().flatten(),
+        resolved_by: row.try_get("resolved_by").ok().flatten(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn store() -> EscalationStore {
+        EscalationStore::new(crate::db::connect_in_memory().await)
+    }
+
+    #[tokio::test]
+    async fn test_one_open_escalation_per_conversation() {
+        let store = store().await;
+        let channel = "discord:1:2";
+        let escalation = store
+            .flag(channel, "threatening other users", FLAGGED_BY_AGENT)
+            .await
+            .unwrap()
+            .expect("first flag opens an escalation");
+        assert_eq!(escalation.flagged_by, FLAGGED_BY_AGENT);
+        assert!(
+            store
+                .flag(channel, "again", FLAGGED_BY_RULE)
+                .await
+                .unwrap()
+                .is_none()
+        );
+        assert_eq!(
+            store.open(channel).await.unwrap().unwrap().id,
+            escalation.id
+        );
+
+        assert_eq!(store.unnotified().await.unwrap().len(), 1);
+        store.mark_notified(&escalation.id).await.unwrap();
+        assert!(store.unnotified().await.unwrap().is_empty());
+
+        assert!(store.resolve(channel, "mod-7").await.unwrap());
+        assert!(!store.resolve(channel, "mod-7").await.unwrap());
+        assert!(store.open(channel).await.unwrap().is_none());
+        assert!(store.list_open().await.unwrap().is_empty());
+
+        // Once resolved, the conversation can be flagged again.
+        assert!(
+            store
+                .flag(channel, "again", FLAGGED_BY_RULE)
+                .await
+                .unwrap()
+                .is_some()
+        );
+    }
+}
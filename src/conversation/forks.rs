@@ -0,0 +1,301 @@
+//! Conversation forks for message editing (SQLite).
+//!
+//! Editing an earlier user message forks the conversation at that message:
+//! the new fork sees everything before the edited message and nothing after
+//! it, and becomes the channel's active fork so the re-sent message and the
+//! reply land on it. The original line is untouched and can be switched back
+//! to. Forks of forks form a tree; a fork only stores its own messages and
+//! reads the rest from its ancestors, cut off at each fork point.
+
+use crate::conversation::history::ConversationMessage;
+use crate::error::Result;
+
+use anyhow::Context as _;
+use serde::Serialize;
+use sqlx::{Row as _, SqlitePool};
+
+use std::collections::HashMap;
+
+/// One fork of a channel's conversation.
+#[derive(Debug, Clone, Serialize)]
+pub struct ConversationFork {
+    pub id: String,
+    pub channel_id: String,
+    /// The fork this one branched off, or None for the root line.
+    pub parent_fork_id: Option<String>,
+    /// The edited message. The fork holds everything before it.
+    pub fork_point_message_id: String,
+    #[serde(skip)]
+    fork_point_seq: i64,
+    pub created_at: String,
+}
+
+/// Fork persistence and fork-aware history loading.
+#[derive(Debug, Clone)]
+pub struct ForkStore {
+    pool: SqlitePool,
+}
+
+impl ForkStore {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+
+    /// Fork the conversation at `message_id` and make the fork active.
+    ///
+    /// Returns None if the message isn't a user message in this channel.
+    pub async fn fork_at(
+        &self,
+        channel_id: &str,
+        message_id: &str,
+    ) -> Result<Option<ConversationFork>> {
+        let message = sqlx::query(
+            "SELECT rowid AS seq, fork_id FROM conversation_messages \
+             WHERE id = ? AND channel_id = ? AND role = 'user'",
+        )
+        .bind(message_id)
+        .bind(channel_id)
+        .fetch_optional(&self.pool)
+        .await
+        .context("failed to find fork point")?;
+        let Some(message) = message else {
+            return Ok(None);
+        };
+
+        let fork_id = uuid::Uuid::new_v4().to_string();
+        let parent_fork_id: Option<String> = message.try_get("fork_id").ok().flatten();
+        let fork_point_seq: i64 = message.try_get("seq").context("fork point has no rowid")?;
+
+        let mut transaction = self.pool.begin().await.context("failed to begin fork")?;
+        sqlx::query(
+            "INSERT INTO conversation_forks \
+             (id, channel_id, parent_fork_id, fork_point_message_id, fork_point_seq) \
+             VALUES (?, ?, ?, ?, ?)",
+        )
+        .bind(&fork_id)
+        .bind(channel_id)
+        .bind(&parent_fork_id)
+        .bind(message_id)
+        .bind(fork_point_seq)
+        .execute(&mut *transaction)
+        .await
+        .context("failed to create fork")?;
+        set_active(&mut transaction, channel_id, Some(&fork_id)).await?;
+        transaction
+            .commit()
+            .await
+            .context("failed to commit fork")?;
+
+        Ok(self
+            .list(channel_id)
+            .await?
+            .into_iter()
+            .find(|fork| fork.id == fork_id))
+    }
+
+    /// Every fork in a channel, oldest first.
+    pub async fn list(&self, channel_id: &str) -> Result<Vec<ConversationFork>> {
+        let rows = sqlx::query(
+            "SELECT id, channel_id, parent_fork_id, fork_point_message_id, fork_point_seq, created_at \
+             FROM conversation_forks WHERE channel_id = ? ORDER BY created_at, rowid",
+        )
+        .bind(channel_id)
+        .fetch_all(&self.pool)
+        .await
+        .context("failed to list forks")?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| ConversationFork {
+                id: row.try_get("id").unwrap_or_default(),
+                channel_id: row.try_get("channel_id").unwrap_or_default(),
+                parent_fork_id: row.try_get("parent_fork_id").ok().flatten(),
+                fork_point_message_id: row.try_get("fork_point_message_id").unwrap_or_default(),
+                fork_point_seq: row.try_get("fork_point_seq").unwrap_or_default(),
+                created_at: row
+                    .try_get::<chrono::NaiveDateTime, _>("created_at")
+                    .map(|t| t.and_utc().to_rfc3339())
+                    .unwrap_or_default(),
+            })
+            .collect())
+    }
+
+    /// The channel's active fork, or None for the root line.
+    pub async fn active(&self, channel_id: &str) -> Result<Option<String>> {
+        let fork_id =
+            sqlx::query_scalar("SELECT fork_id FROM channel_active_forks WHERE channel_id = ?")
+                .bind(channel_id)
+                .fetch_optional(&self.pool)
+                .await
+                .context("failed to load active fork")?;
+        Ok(fork_id)
+    }
+
+    /// Make `fork_id` the channel's active fork, or the root line for None.
+    /// Returns false if the fork doesn't belong to the channel.
+    pub async fn switch(&self, channel_id: &str, fork_id: Option<&str>) -> Result<bool> {
+        if let Some(fork_id) = fork_id {
+            let exists: Option<i64> = sqlx::query_scalar(
+                "SELECT 1 FROM conversation_forks WHERE id = ? AND channel_id = ?",
+            )
+            .bind(fork_id)
+            .bind(channel_id)
+            .fetch_optional(&self.pool)
+            .await
+            .context("failed to look up fork")?;
+            if exists.is_none() {
+                return Ok(false);
+            }
+        }
+
+        let mut transaction = self.pool.begin().await.context("failed to begin switch")?;
+        set_active(&mut transaction, channel_id, fork_id).await?;
+        transaction
+            .commit()
+            .await
+            .context("failed to commit switch")?;
+        Ok(true)
+    }
+
+    /// The most recent `limit` messages as seen from `fork_id` (None for the
+    /// root line), oldest first.
+    pub async fn load_messages(
+        &self,
+        channel_id: &str,
+        fork_id: Option<&str>,
+        limit: usize,
+    ) -> Result<Vec<ConversationMessage>> {
+        let forks: HashMap<String, ConversationFork> = self
+            .list(channel_id)
+            .await?
+            .into_iter()
+            .map(|fork| (fork.id.clone(), fork))
+            .collect();
+
+        let mut messages = Vec::new();
+        for (segment_fork, before_seq) in lineage(&forks, fork_id) {
+            let rows = sqlx::query(
+                "SELECT rowid AS seq, id, channel_id, role, sender_name, sender_id, content, metadata, created_at \
+                 FROM conversation_messages \
+                 WHERE channel_id = ? AND fork_id IS ? AND (? IS NULL OR rowid < ?) \
+                 ORDER BY rowid DESC \
+                 LIMIT ?",
+            )
+            .bind(channel_id)
+            .bind(&segment_fork)
+            .bind(before_seq)
+            .bind(before_seq)
+            .bind(limit as i64)
+            .fetch_all(&self.pool)
+            .await
+            .context("failed to load fork messages")?;
+
+            messages.extend(rows.into_iter().map(|row| {
+                let seq: i64 = row.try_get("seq").unwrap_or_default();
+                let message = ConversationMessage {
+                    id: row.try_get("id").unwrap_or_default(),
+                    channel_id: row.try_get("channel_id").unwrap_or_default(),
+                    role: row.try_get("role").unwrap_or_default(),
+                    sender_name: row.try_get("sender_name").ok(),
+                    sender_id: row.try_get("sender_id").ok(),
+                    content: row.try_get("content").unwrap_or_default(),
+                    metadata: row.try_get("metadata").ok(),
+                    created_at: row
+                        .try_get("created_at")
+                        .unwrap_or_else(|_| chrono::Utc::now()),
+                };
+                (seq, message)
+            }));
+        }
+
+        // Ancestor messages always precede the fork point, so rowid order is
+        // conversation order across segments.
+        messages.sort_by_key(|(seq, _)| *seq);
+        let skip = messages.len().saturating_sub(limit);
+        Ok(messages
+            .into_iter()
+            .skip(skip)
+            .map(|(_, message)| message)
+            .collect())
+    }
+}
+
+async fn set_active(
+    transaction: &mut sqlx::Transaction<'_, sqlx::Sqlite>,
+    channel_id: &str,
+    fork_id: Option<&str>,
+) -> Result<()> {
+    match fork_id {
+        Some(fork_id) => sqlx::query(
+            "INSERT INTO channel_active_forks (channel_id, fork_id) VALUES (?, ?) \
+             ON CONFLICT(channel_id) DO UPDATE SET fork_id = excluded.fork_id",
+        )
+        .bind(channel_id)
+        .bind(fork_id),
+        None => {
+            sqlx::query("DELETE FROM channel_active_forks WHERE channel_id = ?").bind(channel_id)
+        }
+    }
+    .execute(&mut **transaction)
+    .await
+    .context("failed to set active fork")?;
+    Ok(())
+}
+
+/// The segments that make up a fork's view, starting with the fork itself:
+/// each ancestor's own messages (None for the root line) and the rowid they
+/// must precede. The cutoff only ever tightens going up, since an ancestor
+/// is seen as it was at the earliest fork point below it.
+fn lineage(
+    forks: &HashMap<String, ConversationFork>,
+    fork_id: Option<&str>,
+) -> Vec<(Option<String>, Option<i64>)> {
+    let mut segments = vec![(fork_id.map(str::to_string), None)];
+    let mut cutoff: Option<i64> = None;
+    let mut current = fork_id.and_then(|id| forks.get(id));
+    while let Some(fork) = current {
+        let seq = cutoff.map_or(fork.fork_point_seq, |c| c.min(fork.fork_point_seq));
+        cutoff = Some(seq);
+        segments.push((fork.parent_fork_id.clone(), cutoff));
+        // A corrupt tree with a cycle would otherwise loop forever.
+        if segments.len() > forks.len() + 1 {
+            break;
+        }
+        current = fork.parent_fork_id.as_deref().and_then(|id| forks.get(id));
+    }
+    segments
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fork(id: &str, parent: Option<&str>, seq: i64) -> (String, ConversationFork) {
+        let fork = ConversationFork {
+            id: id.into(),
+            channel_id: "c".into(),
+            parent_fork_id: parent.map(str::to_string),
+            fork_point_message_id: format!("m{seq}"),
+            fork_point_seq: seq,
+            created_at: String::new(),
+        };
+        (id.into(), fork)
+    }
+
+    #[test]
+    fn test_lineage_tightens_cutoff() {
+        let forks: HashMap<_, _> = [fork("a", None, 10), fork("b", Some("a"), 20)]
+            .into_iter()
+            .collect();
+
+        assert_eq!(lineage(&forks, None), vec![(None, None)]);
+        assert_eq!(
+            lineage(&forks, Some("b")),
+            vec![
+                (Some("b".into()), None),
+                (Some("a".into()), Some(20)),
+                (None, Some(10)),
+            ]
+        );
+    }
+}
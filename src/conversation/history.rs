@@ -1,22 +1,44 @@
 //! Conversation message persistence (SQLite).
 
-use crate::{BranchId, ChannelId, WorkerId};
+use crate::agent::self_report::SelfReport;
+use crate::agent::turn::TurnOutcome;
+use crate::citations::Citation;
+use crate::{BranchId, ChannelId, InboundMessage, MessageContent, WorkerId};
 
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use sqlx::{Row as _, SqlitePool};
 use std::collections::HashMap;
 
+/// Metadata key holding a logged user message's attachments.
+pub const ATTACHMENTS_METADATA_KEY: &str = "attachments";
+
+/// The metadata to log with a user message: its inbound metadata plus any
+/// attachments, so transcripts can show them.
+pub fn logged_metadata(message: &InboundMessage) -> HashMap<String, serde_json::Value> {
+    let attachments = match &message.content {
+        MessageContent::Media { attachments, .. } if !attachments.is_empty() => attachments,
+        _ => return message.metadata.clone(),
+    };
+    let mut metadata = message.metadata.clone();
+    metadata.insert(
+        ATTACHMENTS_METADATA_KEY.into(),
+        serde_json::json!(attachments),
+    );
+    metadata
+}
+
 /// Persists conversation messages (user and assistant) to SQLite.
 ///
 /// All write methods are fire-and-forget — they spawn a tokio task and return
-/// immediately so the caller never blocks on a DB write.
+/// immediately so the caller never blocks on a DB write. Messages are written
+/// to the channel's active fork (see [`ForkStore`](super::ForkStore)).
 #[derive(Debug, Clone)]
 pub struct ConversationLogger {
     pool: SqlitePool,
 }
 
 /// A persisted conversation message.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct ConversationMessage {
     pub id: String,
     pub channel_id: String,
@@ -28,6 +50,23 @@ pub struct ConversationMessage {
     pub created_at: chrono::DateTime<chrono::Utc>,
 }
 
+/// The completion behind an assistant message. Stored in the message's
+/// metadata so feedback on the reply can be tied back to what produced it.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ReplyAttribution {
+    pub request_id: Option<String>,
+    pub model: Option<String>,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    /// Estimated cost in USD, if the model has a price.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cost_usd: Option<f64>,
+    /// Sources the provider attached to the completion. Stored alongside
+    /// the attribution rather than inside it.
+    #[serde(skip)]
+    pub citations: Vec<Citation>,
+}
+
 impl ConversationLogger {
     pub fn new(pool: SqlitePool) -> Self {
         Self { pool }
@@ -52,8 +91,8 @@ impl ConversationLogger {
 
         tokio::spawn(async move {
             if let Err(error) = sqlx::query(
-                "INSERT INTO conversation_messages (id, channel_id, role, sender_name, sender_id, content, metadata) \
-                 VALUES (?, ?, 'user', ?, ?, ?, ?)"
+                "INSERT INTO conversation_messages (id, channel_id, role, sender_name, sender_id, content, metadata, fork_id) \
+                 VALUES (?, ?, 'user', ?, ?, ?, ?, (SELECT fork_id FROM channel_active_forks WHERE channel_id = ?))"
             )
             .bind(&id)
             .bind(&channel_id)
@@ -61,6 +100,7 @@ impl ConversationLogger {
             .bind(&sender_id)
             .bind(&content)
             .bind(&metadata_json)
+            .bind(&channel_id)
             .execute(&pool)
             .await
             {
@@ -69,21 +109,119 @@ impl ConversationLogger {
         });
     }
 
-    /// Log a bot (assistant) message. Fire-and-forget.
-    pub fn log_bot_message(&self, channel_id: &ChannelId, content: &str) {
+    /// Log the notice of a handoff between agents as an assistant message,
+    /// with who handed off to whom in its metadata. Fire-and-forget.
+    pub fn log_handoff(
+        &self,
+        channel_id: &ChannelId,
+        content: &str,
+        from: &str,
+        to: &str,
+        reason: &str,
+    ) {
+        let pool = self.pool.clone();
+        let id = uuid::Uuid::new_v4().to_string();
+        let channel_id = channel_id.to_string();
+        let content = content.to_string();
+        let metadata_json = serde_json::json!({
+            "handoff": { "from": from, "to": to, "reason": reason }
+        })
+        .to_string();
+
+        tokio::spawn(async move {
+            if let Err(error) = sqlx::query(
+                "INSERT INTO conversation_messages (id, channel_id, role, content, metadata, fork_id) \
+                 VALUES (?, ?, 'assistant', ?, ?, (SELECT fork_id FROM channel_active_forks WHERE channel_id = ?))",
+            )
+            .bind(&id)
+            .bind(&channel_id)
+            .bind(&content)
+            .bind(&metadata_json)
+            .bind(&channel_id)
+            .execute(&pool)
+            .await
+            {
+                tracing::warn!(%error, "failed to persist handoff");
+            }
+        });
+    }
+
+    /// Log the notice of linking or unlinking another conversation as an
+    /// assistant message, with the other conversation and who did it in its
+    /// metadata. Fire-and-forget.
+    pub fn log_link(
+        &self,
+        channel_id: &ChannelId,
+        content: &str,
+        other: &str,
+        linked_by: &str,
+        linked: bool,
+    ) {
+        let pool = self.pool.clone();
+        let id = uuid::Uuid::new_v4().to_string();
+        let channel_id = channel_id.to_string();
+        let content = content.to_string();
+        let metadata_json = serde_json::json!({
+            (super::links::LINK_METADATA_KEY): {
+                "channel_id": other,
+                "by": linked_by,
+                "action": if linked { "link" } else { "unlink" },
+            }
+        })
+        .to_string();
+
+        tokio::spawn(async move {
+            if let Err(error) = sqlx::query(
+                "INSERT INTO conversation_messages (id, channel_id, role, content, metadata, fork_id) \
+                 VALUES (?, ?, 'assistant', ?, ?, (SELECT fork_id FROM channel_active_forks WHERE channel_id = ?))",
+            )
+            .bind(&id)
+            .bind(&channel_id)
+            .bind(&content)
+            .bind(&metadata_json)
+            .bind(&channel_id)
+            .execute(&pool)
+            .await
+            {
+                tracing::warn!(%error, "failed to persist link notice");
+            }
+        });
+    }
+
+    /// Log a bot (assistant) message with the completion that produced it
+    /// and the sources it cites. Fire-and-forget.
+    pub fn log_bot_message(
+        &self,
+        channel_id: &ChannelId,
+        content: &str,
+        attribution: Option<&ReplyAttribution>,
+        citations: &[Citation],
+    ) {
         let pool = self.pool.clone();
         let id = uuid::Uuid::new_v4().to_string();
         let channel_id = channel_id.to_string();
         let content = content.to_string();
+        let mut metadata = serde_json::Map::new();
+        if let Some(attribution) = attribution {
+            metadata.insert("attribution".into(), serde_json::json!(attribution));
+        }
+        if !citations.is_empty() {
+            metadata.insert("citations".into(), serde_json::json!(citations));
+        }
+        let metadata_json = (!metadata.is_empty())
+            .then(|| serde_json::to_string(&metadata).ok())
+            .flatten();
 
         tokio::spawn(async move {
             if let Err(error) = sqlx::query(
-                "INSERT INTO conversation_messages (id, channel_id, role, content) \
-                 VALUES (?, ?, 'assistant', ?)",
+                "INSERT INTO conversation_messages (id, channel_id, role, content, metadata, fork_id) \
+                 VALUES (?, ?, 'assistant', ?, ?, (SELECT fork_id FROM channel_active_forks WHERE channel_id = ?))",
             )
             .bind(&id)
             .bind(&channel_id)
             .bind(&content)
+            .bind(&metadata_json)
+            .bind(&channel_id)
             .execute(&pool)
             .await
             {
@@ -173,6 +311,88 @@ impl ConversationLogger {
     }
 }
 
+/// What a channel's turns used, summed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct TurnUsageTotals {
+    pub turns: u64,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    /// None when no turn had a priced model.
+    pub cost_usd: Option<f64>,
+    /// Input tokens by part of the prompt. Turns recorded before the split
+    /// was tracked count in `input_tokens` only.
+    pub preamble_tokens: u64,
+    pub tool_tokens: u64,
+    pub history_tokens: u64,
+}
+
+impl TurnUsageTotals {
+    /// Shares of all tokens, input and output, taken by the preamble, tool
+    /// schemas, history and completions, as whole percentages. None when
+    /// no turn has the split.
+    pub fn shares(&self) -> Option<[(&'static str, u64); 4]> {
+        let parts = [
+            ("preamble", self.preamble_tokens),
+            ("tool schemas", self.tool_tokens),
+            ("history", self.history_tokens),
+            ("completion", self.output_tokens),
+        ];
+        let split = self.preamble_tokens + self.tool_tokens + self.history_tokens;
+        if split == 0 {
+            return None;
+        }
+        let total = split + self.output_tokens;
+        Some(parts.map(|(name, tokens)| (name, (tokens * 100 + total / 2) / total)))
+    }
+}
+
+/// Turns, tokens and cost summed over all of an agent's turns finished in
+/// the last `hours` hours.
+pub async fn agent_turn_usage(
+    pool: &SqlitePool,
+    hours: u32,
+) -> crate::error::Result<TurnUsageTotals> {
+    sum_turn_usage(pool, None, Some(hours)).await
+}
+
+/// Sum turn usage over one channel's turns, or all of them.
+async fn sum_turn_usage(
+    pool: &SqlitePool,
+    channel_id: Option<&str>,
+    hours: Option<u32>,
+) -> crate::error::Result<TurnUsageTotals> {
+    let window = hours.map(|hours| format!("-{hours} hours"));
+    let row = sqlx::query(
+        "SELECT COUNT(*) AS turns, \
+         COALESCE(SUM(json_extract(outcome, '$.usage.input_tokens')), 0) AS input_tokens, \
+         COALESCE(SUM(json_extract(outcome, '$.usage.output_tokens')), 0) AS output_tokens, \
+         SUM(json_extract(outcome, '$.cost_usd')) AS cost_usd, \
+         COALESCE(SUM(json_extract(outcome, '$.usage.preamble_tokens')), 0) AS preamble_tokens, \
+         COALESCE(SUM(json_extract(outcome, '$.usage.tool_tokens')), 0) AS tool_tokens, \
+         COALESCE(SUM(json_extract(outcome, '$.usage.history_tokens')), 0) AS history_tokens \
+         FROM turn_runs WHERE (? IS NULL OR channel_id = ?) \
+         AND (? IS NULL OR completed_at >= datetime('now', ?))",
+    )
+    .bind(channel_id)
+    .bind(channel_id)
+    .bind(&window)
+    .bind(&window)
+    .fetch_one(pool)
+    .await
+    .map_err(|e| anyhow::anyhow!(e))?;
+
+    let tokens = |column: &str| row.try_get::<i64, _>(column).unwrap_or_default() as u64;
+    Ok(TurnUsageTotals {
+        turns: tokens("turns"),
+        input_tokens: tokens("input_tokens"),
+        output_tokens: tokens("output_tokens"),
+        cost_usd: row.try_get("cost_usd").ok().flatten(),
+        preamble_tokens: tokens("preamble_tokens"),
+        tool_tokens: tokens("tool_tokens"),
+        history_tokens: tokens("history_tokens"),
+    })
+}
+
 /// A unified timeline item combining messages, branch runs, and worker runs.
 #[derive(Debug, Clone, Serialize)]
 #[serde(tag = "type", rename_all = "snake_case")]
@@ -196,6 +416,8 @@ pub enum TimelineItem {
         id: String,
         task: String,
         result: Option<String>,
+        /// The worker's end-of-run report, if it gave one.
+        report: Option<SelfReport>,
         status: String,
         started_at: String,
         completed_at: Option<String>,
@@ -346,6 +568,59 @@ impl ProcessRunLogger {
         });
     }
 
+    /// Record a worker's end-of-run report. Fire-and-forget.
+    pub fn log_worker_report(&self, worker_id: WorkerId, report: &SelfReport) {
+        let pool = self.pool.clone();
+        let id = worker_id.to_string();
+        let Ok(report) = serde_json::to_string(report) else {
+            return;
+        };
+
+        tokio::spawn(async move {
+            if let Err(error) = sqlx::query("UPDATE worker_runs SET report = ? WHERE id = ?")
+                .bind(&report)
+                .bind(&id)
+                .execute(&pool)
+                .await
+            {
+                tracing::warn!(%error, worker_id = %id, "failed to persist worker report");
+            }
+        });
+    }
+
+    /// Record a finished channel turn with its outcome. Fire-and-forget.
+    pub fn log_turn(&self, channel_id: &ChannelId, outcome: &TurnOutcome) {
+        let pool = self.pool.clone();
+        let id = uuid::Uuid::new_v4().to_string();
+        let channel_id = channel_id.to_string();
+        let Ok(outcome) = serde_json::to_string(outcome) else {
+            return;
+        };
+
+        tokio::spawn(async move {
+            if let Err(error) =
+                sqlx::query("INSERT INTO turn_runs (id, channel_id, outcome) VALUES (?, ?, ?)")
+                    .bind(&id)
+                    .bind(&channel_id)
+                    .bind(&outcome)
+                    .execute(&pool)
+                    .await
+            {
+                tracing::warn!(%error, %channel_id, "failed to persist turn");
+            }
+        });
+    }
+
+    /// Turns, tokens and cost summed over a channel's turns: all of them, or
+    /// those finished in the last `hours` hours.
+    pub async fn turn_usage(
+        &self,
+        channel_id: &ChannelId,
+        hours: Option<u32>,
+    ) -> crate::error::Result<TurnUsageTotals> {
+        sum_turn_usage(&self.pool, Some(channel_id.as_ref()), hours).await
+    }
+
     /// Load a unified timeline for a channel: messages, branch runs, and worker runs
     /// interleaved chronologically (oldest first).
     ///
@@ -366,17 +641,17 @@ impl ProcessRunLogger {
         let query_str = format!(
             "SELECT * FROM ( \
                 SELECT 'message' AS item_type, id, role, sender_name, sender_id, content, \
-                       NULL AS description, NULL AS conclusion, NULL AS task, NULL AS result, NULL AS status, \
+                       NULL AS description, NULL AS conclusion, NULL AS task, NULL AS result, NULL AS report, NULL AS status, \
                        created_at AS timestamp, NULL AS completed_at \
                 FROM conversation_messages WHERE channel_id = ?1 \
                 UNION ALL \
                 SELECT 'branch_run' AS item_type, id, NULL, NULL, NULL, NULL, \
-                       description, conclusion, NULL, NULL, NULL, \
+                       description, conclusion, NULL, NULL, NULL, NULL, \
                        started_at AS timestamp, completed_at \
                 FROM branch_runs WHERE channel_id = ?1 \
                 UNION ALL \
                 SELECT 'worker_run' AS item_type, id, NULL, NULL, NULL, NULL, \
-                       NULL, NULL, task, result, status, \
+                       NULL, NULL, task, result, report, status, \
                        started_at AS timestamp, completed_at \
                 FROM worker_runs WHERE channel_id = ?1 \
             ) WHERE 1=1 {before_clause} ORDER BY timestamp DESC LIMIT ?2"
@@ -426,6 +701,11 @@ impl ProcessRunLogger {
                         id: row.try_get("id").unwrap_or_default(),
                         task: row.try_get("task").unwrap_or_default(),
                         result: row.try_get("result").ok(),
+                        report: row
+                            .try_get::<Option<String>, _>("report")
+                            .ok()
+                            .flatten()
+                            .and_then(|report| serde_json::from_str(&report).ok()),
                         status: row.try_get("status").unwrap_or_default(),
                         started_at: row
                             .try_get::<chrono::DateTime<chrono::Utc>, _>("timestamp")
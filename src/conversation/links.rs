@@ -0,0 +1,297 @@
+//! Linked conversations: one user's context across surfaces (SQLite).
+//!
+//! `/link` joins two conversations, say a user's DM and a thread they
+//! started in a server, so what's said in one is read in the other. Nothing
+//! is copied: each transcript keeps its own messages, and a linked
+//! conversation's are read from it with their sequence numbers so a channel
+//! can take just the ones it hasn't seen. Linking and unlinking are logged in
+//! both transcripts under [`LINK_METADATA_KEY`], and those notices are left
+//! out of what the other side reads.
+
+use crate::conversation::history::ConversationMessage;
+use crate::error::Result;
+
+use anyhow::Context as _;
+use sqlx::{Row as _, SqlitePool};
+
+/// Most conversations one conversation can be linked with.
+pub const MAX_LINKS: usize = 5;
+
+/// Metadata key marking a logged link or unlink notice.
+pub const LINK_METADATA_KEY: &str = "link";
+
+/// A conversation linked with another.
+#[derive(Debug, Clone)]
+pub struct ConversationLink {
+    /// The other conversation.
+    pub channel_id: String,
+    /// The sender ID of whoever linked them.
+    pub linked_by: String,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// A message read from a linked conversation.
+#[derive(Debug, Clone)]
+pub struct LinkedMessage {
+    /// Where the message falls among all logged messages. Later messages
+    /// have higher numbers.
+    pub seq: i64,
+    pub message: ConversationMessage,
+}
+
+/// Conversation links in SQLite.
+#[derive(Debug, Clone)]
+pub struct LinkStore {
+    pool: SqlitePool,
+}
+
+impl LinkStore {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+
+    /// Link two conversations. Returns false if they already were. Fails
+    /// if they're the same conversation or either has [`MAX_LINKS`] already.
+    pub async fn link(&self, channel_id: &str, other: &str, linked_by: &str) -> Result<bool> {
+        if channel_id == other {
+            return Err(anyhow::anyhow!("a conversation can't be linked to itself").into());
+        }
+        let (first, second) = ordered(channel_id, other);
+
+        let mut transaction = self.pool.begin().await.context("failed to start link")?;
+        let existing: Option<i64> = sqlx::query_scalar(
+            "SELECT 1 FROM conversation_links WHERE channel_id = ? AND linked_channel_id = ?",
+        )
+        .bind(first)
+        .bind(second)
+        .fetch_optional(&mut *transaction)
+        .await
+        .context("failed to look up link")?;
+        if existing.is_some() {
+            return Ok(false);
+        }
+
+        for id in [channel_id, other] {
+            let links: i64 = sqlx::query_scalar(
+                "SELECT COUNT(*) FROM conversation_links \
+                 WHERE channel_id = ? OR linked_channel_id = ?",
+            )
+            .bind(id)
+            .bind(id)
+            .fetch_one(&mut *transaction)
+            .await
+            .context("failed to count links")?;
+            if links as usize >= MAX_LINKS {
+                return Err(anyhow::anyhow!(
+                    "{id} is already linked with {MAX_LINKS} conversations; unlink one first"
+                )
+                .into());
+            }
+            sqlx::query(
+                "INSERT INTO channels (id, platform) VALUES (?, ?) ON CONFLICT(id) DO NOTHING",
+            )
+            .bind(id)
+            .bind(super::channels::extract_platform(id))
+            .execute(&mut *transaction)
+            .await
+            .context("failed to record channel")?;
+        }
+
+        sqlx::query(
+            "INSERT INTO conversation_links (channel_id, linked_channel_id, linked_by) \
+             VALUES (?, ?, ?)",
+        )
+        .bind(first)
+        .bind(second)
+        .bind(linked_by)
+        .execute(&mut *transaction)
+        .await
+        .context("failed to save link")?;
+        transaction.commit().await.context("failed to save link")?;
+        Ok(true)
+    }
+
+    /// Unlink two conversations. Returns whether they were linked.
+    pub async fn unlink(&self, channel_id: &str, other: &str) -> Result<bool> {
+        let (first, second) = ordered(channel_id, other);
+        let result = sqlx::query(
+            "DELETE FROM conversation_links WHERE channel_id = ? AND linked_channel_id = ?",
+        )
+        .bind(first)
+        .bind(second)
+        .execute(&self.pool)
+        .await
+        .context("failed to delete link")?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// The conversations linked with `channel_id`, oldest link first.
+    pub async fn linked(&self, channel_id: &str) -> Result<Vec<ConversationLink>> {
+        let rows = sqlx::query(
+            "SELECT CASE WHEN channel_id = ? THEN linked_channel_id ELSE channel_id END AS other, \
+             linked_by, created_at \
+             FROM conversation_links WHERE channel_id = ? OR linked_channel_id = ? \
+             ORDER BY created_at, rowid",
+        )
+        .bind(channel_id)
+        .bind(channel_id)
+        .bind(channel_id)
+        .fetch_all(&self.pool)
+        .await
+        .context("failed to list links")?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| ConversationLink {
+                channel_id: row.try_get("other").unwrap_or_default(),
+                linked_by: row.try_get("linked_by").unwrap_or_default(),
+                created_at: row
+                    .try_get("created_at")
+                    .unwrap_or_else(|_| chrono::Utc::now()),
+            })
+            .collect())
+    }
+
+    /// Whether `sender_id` has written in `channel_id`. Only participants
+    /// may link a conversation, so nobody can read one they weren't in.
+    pub async fn has_spoken(&self, channel_id: &str, sender_id: &str) -> Result<bool> {
+        let spoken: Option<i64> = sqlx::query_scalar(
+            "SELECT 1 FROM conversation_messages \
+             WHERE channel_id = ? AND role = 'user' AND sender_id = ? LIMIT 1",
+        )
+        .bind(channel_id)
+        .bind(sender_id)
+        .fetch_optional(&self.pool)
+        .await
+        .context("failed to check conversation participants")?;
+        Ok(spoken.is_some())
+    }
+
+    /// The latest `limit` messages in `channel_id` after `after_seq`, oldest
+    /// first, without link notices.
+    pub async fn messages_after(
+        &self,
+        channel_id: &str,
+        after_seq: i64,
+        limit: usize,
+    ) -> Result<Vec<LinkedMessage>> {
+        let rows = sqlx::query(
+            "SELECT rowid AS seq, id, channel_id, role, sender_name, sender_id, content, metadata, created_at \
+             FROM conversation_messages \
+             WHERE channel_id = ? AND rowid > ? \
+             AND (metadata IS NULL OR json_extract(metadata, '$.link') IS NULL) \
+             ORDER BY rowid DESC \
+             LIMIT ?",
+        )
+        .bind(channel_id)
+        .bind(after_seq)
+        .bind(limit as i64)
+        .fetch_all(&self.pool)
+        .await
+        .context("failed to load linked messages")?;
+
+        let mut messages: Vec<LinkedMessage> = rows
+            .into_iter()
+            .map(|row| LinkedMessage {
+                seq: row.try_get("seq").unwrap_or_default(),
+                message: ConversationMessage {
+                    id: row.try_get("id").unwrap_or_default(),
+                    channel_id: row.try_get("channel_id").unwrap_or_default(),
+                    role: row.try_get("role").unwrap_or_default(),
+                    sender_name: row.try_get("sender_name").ok(),
+                    sender_id: row.try_get("sender_id").ok(),
+                    content: row.try_get("content").unwrap_or_default(),
+                    metadata: row.try_get("metadata").ok(),
+                    created_at: row
+                        .try_get("created_at")
+                        .unwrap_or_else(|_| chrono::Utc::now()),
+                },
+            })
+            .collect();
+        messages.reverse();
+        Ok(messages)
+    }
+}
+
+/// A link's two conversations in the order they're stored.
+fn ordered<'a>(channel_id: &'a str, other: &'a str) -> (&'a str, &'a str) {
+    if channel_id <= other {
+        (channel_id, other)
+    } else {
+        (other, channel_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn store() -> LinkStore {
+        LinkStore::new(crate::db::connect_in_memory().await)
+    }
+
+    async fn log(store: &LinkStore, channel_id: &str, content: &str, metadata: Option<&str>) {
+        sqlx::query(
+            "INSERT INTO conversation_messages (id, channel_id, role, sender_id, content, metadata) \
+             VALUES (?, ?, 'user', '42', ?, ?)",
+        )
+        .bind(uuid::Uuid::new_v4().to_string())
+        .bind(channel_id)
+        .bind(content)
+        .bind(metadata)
+        .execute(&store.pool)
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_links_go_both_ways() {
+        let store = store().await;
+        let dm = "discord:dm:42";
+        let thread = "discord:1:2";
+        assert!(store.link(thread, dm, "42").await.unwrap());
+        assert!(!store.link(dm, thread, "42").await.unwrap());
+        assert!(store.link(dm, dm, "42").await.is_err());
+
+        let linked = store.linked(dm).await.unwrap();
+        assert_eq!(linked.len(), 1);
+        assert_eq!(linked[0].channel_id, thread);
+        assert_eq!(store.linked(thread).await.unwrap()[0].channel_id, dm);
+
+        for index in 0..MAX_LINKS - 1 {
+            store
+                .link(dm, &format!("discord:1:{}", index + 10), "42")
+                .await
+                .unwrap();
+        }
+        assert!(store.link(dm, "discord:1:99", "42").await.is_err());
+
+        assert!(store.unlink(dm, thread).await.unwrap());
+        assert!(!store.unlink(thread, dm).await.unwrap());
+        assert!(store.linked(thread).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_messages_after_skips_seen_and_notices() {
+        let store = store().await;
+        let dm = "discord:dm:42";
+        log(&store, dm, "first", None).await;
+        log(&store, dm, "Linked with #deploys.", Some(r#"{"link":{}}"#)).await;
+        log(&store, dm, "second", Some("{}")).await;
+        assert!(store.has_spoken(dm, "42").await.unwrap());
+        assert!(!store.has_spoken(dm, "7").await.unwrap());
+
+        let messages = store.messages_after(dm, 0, 10).await.unwrap();
+        let contents: Vec<&str> = messages
+            .iter()
+            .map(|linked| linked.message.content.as_str())
+            .collect();
+        assert_eq!(contents, ["first", "second"]);
+
+        let seen = messages[0].seq;
+        let newer = store.messages_after(dm, seen, 10).await.unwrap();
+        assert_eq!(newer.len(), 1);
+        assert_eq!(newer[0].message.content, "second");
+        assert_eq!(store.messages_after(dm, 0, 1).await.unwrap().len(), 1);
+    }
+}
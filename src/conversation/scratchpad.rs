@@ -0,0 +1,196 @@
+//! Per-conversation scratchpad: working state the channel keeps across turns.
+//!
+//! The scratchpad tools read and write short values by key, so the channel
+//! can track a checklist or a half-finished plan without carrying it in the
+//! prompt. Entries are capped in number and size, and go away with the rest
+//! of the transcript when the conversation expires.
+
+use anyhow::Context as _;
+use sqlx::{Row as _, SqlitePool};
+
+/// Most entries one conversation can hold.
+pub const MAX_KEYS: usize = 50;
+
+/// Longest key, in bytes.
+pub const MAX_KEY_BYTES: usize = 64;
+
+/// Largest single value, in bytes.
+pub const MAX_VALUE_BYTES: usize = 4_000;
+
+/// Largest total of all of a conversation's values, in bytes.
+pub const MAX_TOTAL_BYTES: usize = 32_000;
+
+/// One scratchpad entry.
+#[derive(Debug, Clone)]
+pub struct ScratchpadEntry {
+    pub key: String,
+    pub value: String,
+    pub updated_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// Scratchpad entries in SQLite, keyed by channel.
+#[derive(Debug, Clone)]
+pub struct ScratchpadStore {
+    pool: SqlitePool,
+}
+
+impl ScratchpadStore {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+
+    /// Set `key` to `value`, replacing what was there. Fails without saving
+    /// anything if the key or value is too long or the scratchpad is full.
+    pub async fn set(&self, channel_id: &str, key: &str, value: &str) -> crate::error::Result<()> {
+        check_entry(key, value)?;
+
+        let mut transaction = self.pool.begin().await.context("failed to start write")?;
+        let row = sqlx::query(
+            "SELECT COUNT(*) AS keys, COALESCE(SUM(LENGTH(CAST(value AS BLOB))), 0) AS bytes \
+             FROM channel_scratchpad WHERE channel_id = ? AND key != ?",
+        )
+        .bind(channel_id)
+        .bind(key)
+        .fetch_one(&mut *transaction)
+        .await
+        .context("failed to measure scratchpad")?;
+        let other_keys: i64 = row.try_get("keys").unwrap_or_default();
+        let other_bytes: i64 = row.try_get("bytes").unwrap_or_default();
+        check_room(other_keys as usize, other_bytes as usize, value.len())?;
+
+        sqlx::query("INSERT INTO channels (id, platform) VALUES (?, ?) ON CONFLICT(id) DO NOTHING")
+            .bind(channel_id)
+            .bind(super::channels::extract_platform(channel_id))
+            .execute(&mut *transaction)
+            .await
+            .context("failed to record channel")?;
+        sqlx::query(
+            "INSERT INTO channel_scratchpad (channel_id, key, value) VALUES (?, ?, ?) \
+             ON CONFLICT(channel_id, key) DO UPDATE SET \
+             value = excluded.value, updated_at = CURRENT_TIMESTAMP",
+        )
+        .bind(channel_id)
+        .bind(key)
+        .bind(value)
+        .execute(&mut *transaction)
+        .await
+        .context("failed to save scratchpad entry")?;
+        transaction.commit().await.context("failed to save write")?;
+        Ok(())
+    }
+
+    /// The value under `key`, if any.
+    pub async fn get(&self, channel_id: &str, key: &str) -> crate::error::Result<Option<String>> {
+        let value = sqlx::query_scalar(
+            "SELECT value FROM channel_scratchpad WHERE channel_id = ? AND key = ?",
+        )
+        .bind(channel_id)
+        .bind(key)
+        .fetch_optional(&self.pool)
+        .await
+        .context("failed to read scratchpad entry")?;
+        Ok(value)
+    }
+
+    /// Delete `key`. Returns whether it was set.
+    pub async fn delete(&self, channel_id: &str, key: &str) -> crate::error::Result<bool> {
+        let result = sqlx::query("DELETE FROM channel_scratchpad WHERE channel_id = ? AND key = ?")
+            .bind(channel_id)
+            .bind(key)
+            .execute(&self.pool)
+            .await
+            .context("failed to delete scratchpad entry")?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Every entry in the conversation's scratchpad, by key.
+    pub async fn list(&self, channel_id: &str) -> crate::error::Result<Vec<ScratchpadEntry>> {
+        let rows = sqlx::query(
+            "SELECT key, value, updated_at FROM channel_scratchpad \
+             WHERE channel_id = ? ORDER BY key",
+        )
+        .bind(channel_id)
+        .fetch_all(&self.pool)
+        .await
+        .context("failed to list scratchpad")?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| ScratchpadEntry {
+                key: row.try_get("key").unwrap_or_default(),
+                value: row.try_get("value").unwrap_or_default(),
+                updated_at: row
+                    .try_get("updated_at")
+                    .unwrap_or_else(|_| chrono::Utc::now()),
+            })
+            .collect())
+    }
+}
+
+fn check_entry(key: &str, value: &str) -> anyhow::Result<()> {
+    anyhow::ensure!(!key.is_empty(), "the key is empty");
+    anyhow::ensure!(
+        key.len() <= MAX_KEY_BYTES,
+        "keys can be at most {MAX_KEY_BYTES} bytes"
+    );
+    anyhow::ensure!(
+        value.len() <= MAX_VALUE_BYTES,
+        "values can be at most {MAX_VALUE_BYTES} bytes, this one is {}",
+        value.len()
+    );
+    Ok(())
+}
+
+/// Whether a value of `value_bytes` fits next to the conversation's other
+/// entries.
+fn check_room(other_keys: usize, other_bytes: usize, value_bytes: usize) -> anyhow::Result<()> {
+    anyhow::ensure!(
+        other_keys < MAX_KEYS,
+        "the scratchpad is full ({MAX_KEYS} keys); delete keys you no longer need"
+    );
+    anyhow::ensure!(
+        other_bytes + value_bytes <= MAX_TOTAL_BYTES,
+        "the scratchpad is full ({MAX_TOTAL_BYTES} bytes); shorten or delete values"
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn store() -> ScratchpadStore {
+        ScratchpadStore::new(crate::db::connect_in_memory().await)
+    }
+
+    #[tokio::test]
+    async fn test_set_get_and_limits() {
+        let store = store().await;
+        let channel = "discord:1:2";
+        store.set(channel, "plan", "1. draft").await.unwrap();
+        store.set(channel, "plan", "2. review").await.unwrap();
+        store.set("discord:1:3", "plan", "elsewhere").await.unwrap();
+        assert_eq!(
+            store.get(channel, "plan").await.unwrap().as_deref(),
+            Some("2. review")
+        );
+        assert_eq!(store.get(channel, "missing").await.unwrap(), None);
+
+        assert!(
+            store
+                .set(channel, "big", &"x".repeat(MAX_VALUE_BYTES + 1))
+                .await
+                .is_err()
+        );
+        for index in 1..MAX_KEYS {
+            store.set(channel, &format!("k{index}"), "v").await.unwrap();
+        }
+        assert!(store.set(channel, "one_more", "v").await.is_err());
+        // Overwriting an existing key still works when full.
+        store.set(channel, "plan", "3. ship").await.unwrap();
+
+        assert!(store.delete(channel, "plan").await.unwrap());
+        assert!(!store.delete(channel, "plan").await.unwrap());
+        assert_eq!(store.list(channel).await.unwrap().len(), MAX_KEYS - 1);
+    }
+}
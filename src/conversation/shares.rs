@@ -0,0 +1,207 @@
+//! Read-only sharing links to conversations (SQLite).
+//!
+//! A link lets someone without access to the bot read one conversation's
+//! transcript, redacted with [`Transcript::shared`], until the link expires
+//! or is revoked. The link's token is only returned when it's created; the
+//! table keeps its SHA-256 hash, so a copy of the database can't be turned
+//! back into working links.
+//!
+//! [`Transcript::shared`]: crate::conversation::transcript::Transcript::shared
+
+use crate::error::Result;
+
+use anyhow::Context as _;
+use base64::Engine as _;
+use chrono::{DateTime, TimeDelta, Utc};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use sqlx::{Row as _, SqlitePool};
+
+/// How long a link lasts when its creator doesn't say.
+pub const DEFAULT_EXPIRY_DAYS: i64 = 7;
+
+/// The longest a link can last.
+pub const MAX_EXPIRY_DAYS: i64 = 30;
+
+/// A sharing link, without its token.
+#[derive(Debug, Clone, Serialize)]
+pub struct ShareLink {
+    pub id: String,
+    pub channel_id: String,
+    /// Who created the link, as given to the API.
+    pub created_by: String,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+/// Sharing links in SQLite.
+#[derive(Debug, Clone)]
+pub struct ShareLinkStore {
+    pool: SqlitePool,
+}
+
+impl ShareLinkStore {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+
+    /// Create a link to `channel_id` that lasts `expires_in`, at most
+    /// [`MAX_EXPIRY_DAYS`]. Returns the link and its token, or None if the
+    /// conversation doesn't exist.
+    pub async fn create(
+        &self,
+        channel_id: &str,
+        created_by: &str,
+        expires_in: TimeDelta,
+    ) -> Result<Option<(ShareLink, String)>> {
+        let id = uuid::Uuid::new_v4().to_string();
+        let token =
+            base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(rand::random::<[u8; 32]>());
+        let expires_in = expires_in.clamp(TimeDelta::minutes(1), TimeDelta::days(MAX_EXPIRY_DAYS));
+        let result = sqlx::query(
+            "INSERT INTO share_links (id, token_hash, channel_id, created_by, expires_at) \
+             SELECT ?, ?, id, ?, datetime('now', ?) FROM channels WHERE id = ?",
+        )
+        .bind(&id)
+        .bind(hash_token(&token))
+        .bind(created_by)
+        .bind(format!("+{} seconds", expires_in.num_seconds()))
+        .bind(channel_id)
+        .execute(&self.pool)
+        .await
+        .context("failed to save share link")?;
+        if result.rows_affected() == 0 {
+            return Ok(None);
+        }
+
+        let row = sqlx::query(
+            "SELECT id, channel_id, created_by, created_at, expires_at, revoked_at \
+             FROM share_links WHERE id = ?",
+        )
+        .bind(&id)
+        .fetch_one(&self.pool)
+        .await
+        .context("failed to load share link")?;
+        Ok(Some((row_to_link(row), token)))
+    }
+
+    /// The link `token` belongs to, if it exists and is neither expired nor
+    /// revoked.
+    pub async fn resolve(&self, token: &str) -> Result<Option<ShareLink>> {
+        let row = sqlx::query(
+            "SELECT id, channel_id, created_by, created_at, expires_at, revoked_at \
+             FROM share_links \
+             WHERE token_hash = ? AND revoked_at IS NULL AND expires_at > CURRENT_TIMESTAMP",
+        )
+        .bind(hash_token(token))
+        .fetch_optional(&self.pool)
+        .await
+        .context("failed to load share link")?;
+        Ok(row.map(row_to_link))
+    }
+
+    /// The conversation's links, newest first, including expired and
+    /// revoked ones.
+    pub async fn list(&self, channel_id: &str) -> Result<Vec<ShareLink>> {
+        let rows = sqlx::query(
+            "SELECT id, channel_id, created_by, created_at, expires_at, revoked_at \
+             FROM share_links WHERE channel_id = ? \
+             ORDER BY created_at DESC, rowid DESC",
+        )
+        .bind(channel_id)
+        .fetch_all(&self.pool)
+        .await
+        .context("failed to list share links")?;
+        Ok(rows.into_iter().map(row_to_link).collect())
+    }
+
+    /// Revoke a link. Returns whether it was still unrevoked.
+    pub async fn revoke(&self, id: &str) -> Result<bool> {
+        let result = sqlx::query(
+            "UPDATE share_links SET revoked_at = CURRENT_TIMESTAMP \
+             WHERE id = ? AND revoked_at IS NULL",
+        )
+        .bind(id)
+        .execute(&self.pool)
+        .await
+        .context("failed to revoke share link")?;
+        Ok(result.rows_affected() > 0)
+    }
+}
+
+fn hash_token(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+fn row_to_link(row: sqlx::sqlite::SqliteRow) -> ShareLink {
+    ShareLink {
+        id: row.try_get("id").unwrap_or_default(),
+        channel_id: row.try_get("channel_id").unwrap_or_default(),
+        created_by: row.try_get("created_by").unwrap_or_default(),
+        created_at: row.try_get("created_at").unwrap_or_else(|_| Utc::now()),
+        expires_at: row.try_get("expires_at").unwrap_or_else(|_| Utc::now()),
+        revoked_at: row.try_get("revoked_at").ok().flatten(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn store() -> ShareLinkStore {
+        let pool = crate::db::connect_in_memory().await;
+        sqlx::query("INSERT INTO channels (id, platform) VALUES ('discord:1:2', 'discord')")
+            .execute(&pool)
+            .await
+            .unwrap();
+        ShareLinkStore::new(pool)
+    }
+
+    #[tokio::test]
+    async fn test_links_resolve_until_revoked() {
+        let store = store().await;
+        assert!(
+            store
+                .create("discord:9:9", "ops", TimeDelta::days(1))
+                .await
+                .unwrap()
+                .is_none()
+        );
+
+        let (link, token) = store
+            .create("discord:1:2", "ops", TimeDelta::days(365))
+            .await
+            .unwrap()
+            .expect("the conversation exists");
+        assert!(link.expires_at <= Utc::now() + TimeDelta::days(MAX_EXPIRY_DAYS));
+        assert_eq!(
+            store.resolve(&token).await.unwrap().map(|link| link.id),
+            Some(link.id.clone())
+        );
+        assert!(store.resolve("not-a-token").await.unwrap().is_none());
+
+        assert!(store.revoke(&link.id).await.unwrap());
+        assert!(!store.revoke(&link.id).await.unwrap());
+        assert!(store.resolve(&token).await.unwrap().is_none());
+        assert_eq!(store.list("discord:1:2").await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_expired_links_dont_resolve() {
+        let store = store().await;
+        let (link, token) = store
+            .create("discord:1:2", "ops", TimeDelta::days(1))
+            .await
+            .unwrap()
+            .unwrap();
+        sqlx::query(
+            "UPDATE share_links SET expires_at = datetime('now', '-1 minute') WHERE id = ?",
+        )
+        .bind(&link.id)
+        .execute(&store.pool)
+        .await
+        .unwrap();
+        assert!(store.resolve(&token).await.unwrap().is_none());
+    }
+}
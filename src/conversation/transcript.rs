@@ -0,0 +1,901 @@
+//! Conversation transcripts for `spacebot transcript show` and `export`.
+//!
+//! A [`Transcript`] interleaves a channel's messages with the turns that
+//! produced the replies and the branches and workers those turns started.
+//! Tool calls are folded into one line per turn next to what the turn cost.
+//! Everything passes through [`redact_secrets`] as it's loaded, so a
+//! transcript can be pasted into a bug report. [`Transcript::export`] writes
+//! it as Markdown or a standalone HTML page for a wiki or an issue, with
+//! tool calls in collapsed sections and image attachments inline.
+
+use crate::Attachment;
+use crate::agent::turn::{StopReason, TurnOutcome};
+use crate::conversation::history::{ATTACHMENTS_METADATA_KEY, ReplyAttribution};
+use crate::error::Result;
+use crate::llm::recorder::{AttemptOutcome, RecordedAttempt};
+
+use anyhow::Context as _;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use spacebot_core::redact::{redact_pii, redact_secrets};
+use sqlx::{Row as _, SqlitePool};
+
+use std::collections::BTreeMap;
+
+/// One thing that happened in a conversation.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum TranscriptEntry {
+    Message {
+        role: String,
+        sender_name: Option<String>,
+        content: String,
+        /// The completion behind an assistant message, if it was stored.
+        attribution: Option<ReplyAttribution>,
+        /// Files sent with a user message.
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        attachments: Vec<Attachment>,
+        at: DateTime<Utc>,
+    },
+    Turn {
+        outcome: TurnOutcome,
+        at: DateTime<Utc>,
+    },
+    BranchRun {
+        description: String,
+        conclusion: Option<String>,
+        at: DateTime<Utc>,
+    },
+    WorkerRun {
+        task: String,
+        result: Option<String>,
+        status: String,
+        at: DateTime<Utc>,
+    },
+}
+
+impl TranscriptEntry {
+    fn at(&self) -> DateTime<Utc> {
+        match self {
+            Self::Message { at, .. }
+            | Self::Turn { at, .. }
+            | Self::BranchRun { at, .. }
+            | Self::WorkerRun { at, .. } => *at,
+        }
+    }
+}
+
+/// Format for [`Transcript::export`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Markdown,
+    Html,
+}
+
+impl std::str::FromStr for ExportFormat {
+    type Err = String;
+
+    fn from_str(value: &str) -> std::result::Result<Self, Self::Err> {
+        match value.to_ascii_lowercase().as_str() {
+            "md" | "markdown" => Ok(Self::Markdown),
+            "html" => Ok(Self::Html),
+            other => Err(format!("unknown format '{other}', expected md or html")),
+        }
+    }
+}
+
+/// A conversation, oldest entry first, with secrets redacted.
+#[derive(Debug, Clone, Serialize)]
+pub struct Transcript {
+    pub channel_id: String,
+    pub entries: Vec<TranscriptEntry>,
+}
+
+impl Transcript {
+    /// Load a channel's transcript. Empty if the channel has no history.
+    pub async fn load(pool: &SqlitePool, channel_id: &str) -> Result<Self> {
+        let mut entries = Vec::new();
+
+        let rows = sqlx::query(
+            "SELECT role, sender_name, content, metadata, created_at FROM conversation_messages \
+             WHERE channel_id = ? ORDER BY created_at, rowid",
+        )
+        .bind(channel_id)
+        .fetch_all(pool)
+        .await
+        .context("failed to load conversation messages")?;
+        for row in rows {
+            let metadata: Option<String> = row.try_get("metadata").unwrap_or_default();
+            let metadata = metadata
+                .and_then(|metadata| serde_json::from_str::<serde_json::Value>(&metadata).ok())
+                .unwrap_or_default();
+            let attribution = serde_json::from_value(metadata["attribution"].clone()).ok();
+            let mut attachments: Vec<Attachment> =
+                serde_json::from_value(metadata[ATTACHMENTS_METADATA_KEY].clone())
+                    .unwrap_or_default();
+            for attachment in &mut attachments {
+                attachment.url = redact_secrets(&attachment.url);
+            }
+            let content: String = row.try_get("content").unwrap_or_default();
+            entries.push(TranscriptEntry::Message {
+                role: row.try_get("role").unwrap_or_default(),
+                sender_name: row.try_get("sender_name").unwrap_or_default(),
+                content: redact_secrets(&content),
+                attribution,
+                attachments,
+                at: row.try_get("created_at").unwrap_or_default(),
+            });
+        }
+
+        let rows = sqlx::query(
+            "SELECT outcome, completed_at FROM turn_runs \
+             WHERE channel_id = ? ORDER BY completed_at, rowid",
+        )
+        .bind(channel_id)
+        .fetch_all(pool)
+        .await
+        .context("failed to load turns")?;
+        for row in rows {
+            let outcome: String = row.try_get("outcome").unwrap_or_default();
+            let Ok(mut outcome) = serde_json::from_str::<TurnOutcome>(&outcome) else {
+                continue;
+            };
+            outcome.text = redact_secrets(&outcome.text);
+            for entry in &mut outcome.tool_trace {
+                entry.args = redact_secrets(&entry.args);
+                entry.result = entry.result.as_deref().map(redact_secrets);
+            }
+            entries.push(TranscriptEntry::Turn {
+                outcome,
+                at: row.try_get("completed_at").unwrap_or_default(),
+            });
+        }
+
+        let rows = sqlx::query(
+            "SELECT description, conclusion, started_at FROM branch_runs \
+             WHERE channel_id = ? ORDER BY started_at, rowid",
+        )
+        .bind(channel_id)
+        .fetch_all(pool)
+        .await
+        .context("failed to load branch runs")?;
+        for row in rows {
+            let description: String = row.try_get("description").unwrap_or_default();
+            let conclusion: Option<String> = row.try_get("conclusion").unwrap_or_default();
+            entries.push(TranscriptEntry::BranchRun {
+                description: redact_secrets(&description),
+                conclusion: conclusion.as_deref().map(redact_secrets),
+                at: row.try_get("started_at").unwrap_or_default(),
+            });
+        }
+
+        let rows = sqlx::query(
+            "SELECT task, result, status, started_at FROM worker_runs \
+             WHERE channel_id = ? ORDER BY started_at, rowid",
+        )
+        .bind(channel_id)
+        .fetch_all(pool)
+        .await
+        .context("failed to load worker runs")?;
+        for row in rows {
+            let task: String = row.try_get("task").unwrap_or_default();
+            let result: Option<String> = row.try_get("result").unwrap_or_default();
+            entries.push(TranscriptEntry::WorkerRun {
+                task: redact_secrets(&task),
+                result: result.as_deref().map(redact_secrets),
+                status: row.try_get("status").unwrap_or_default(),
+                at: row.try_get("started_at").unwrap_or_default(),
+            });
+        }
+
+        // Stable, so messages keep their order within the same second and
+        // a turn lands after the reply it produced.
+        entries.sort_by_key(TranscriptEntry::at);
+
+        Ok(Self {
+            channel_id: channel_id.to_string(),
+            entries,
+        })
+    }
+
+    /// The transcript as it's shown through a sharing link, to people
+    /// outside the instance: personal data redacted from everything, users
+    /// named only by role, attachments and the requests behind each reply
+    /// left out, and the channel named by its platform.
+    pub fn shared(mut self) -> Self {
+        self.channel_id = super::channels::extract_platform(&self.channel_id);
+        for entry in &mut self.entries {
+            match entry {
+                TranscriptEntry::Message {
+                    sender_name,
+                    content,
+                    attribution,
+                    attachments,
+                    ..
+                } => {
+                    *sender_name = None;
+                    *content = redact_pii(content);
+                    *attribution = None;
+                    attachments.clear();
+                }
+                TranscriptEntry::Turn { outcome, .. } => {
+                    outcome.text = redact_pii(&outcome.text);
+                    outcome.impersonated_by = None;
+                    for entry in &mut outcome.tool_trace {
+                        entry.args = redact_pii(&entry.args);
+                        entry.result = entry.result.as_deref().map(redact_pii);
+                    }
+                    for decision in &mut outcome.routing {
+                        decision.request_id = None;
+                        decision.provider_request_id = None;
+                    }
+                }
+                TranscriptEntry::BranchRun {
+                    description,
+                    conclusion,
+                    ..
+                } => {
+                    *description = redact_pii(description);
+                    *conclusion = conclusion.as_deref().map(redact_pii);
+                }
+                TranscriptEntry::WorkerRun { task, result, .. } => {
+                    *task = redact_pii(task);
+                    *result = result.as_deref().map(redact_pii);
+                }
+            }
+        }
+        self
+    }
+
+    /// Every completion request the transcript knows about, in order, for
+    /// fetching raw exchanges.
+    pub fn request_ids(&self) -> Vec<String> {
+        let mut request_ids: Vec<String> = Vec::new();
+        for entry in &self.entries {
+            let ids: Vec<&String> = match entry {
+                TranscriptEntry::Turn { outcome, .. } => outcome
+                    .routing
+                    .iter()
+                    .filter_map(|decision| decision.request_id.as_ref())
+                    .collect(),
+                TranscriptEntry::Message {
+                    attribution: Some(attribution),
+                    ..
+                } => attribution.request_id.iter().collect(),
+                _ => Vec::new(),
+            };
+            for id in ids {
+                if !request_ids.contains(id) {
+                    request_ids.push(id.clone());
+                }
+            }
+        }
+        request_ids
+    }
+
+    /// Render for a terminal. With `raw`, each turn's tool calls are
+    /// unfolded and followed by the provider exchanges behind it.
+    pub fn render(&self, raw: Option<&BTreeMap<String, Vec<RecordedAttempt>>>) -> String {
+        // Conversations from before turns were recorded only have the
+        // completion behind each reply to go on.
+        let has_turns = self
+            .entries
+            .iter()
+            .any(|entry| matches!(entry, TranscriptEntry::Turn { .. }));
+
+        let mut out = String::new();
+        for entry in &self.entries {
+            match entry {
+                TranscriptEntry::Message {
+                    role,
+                    sender_name,
+                    content,
+                    attribution,
+                    attachments,
+                    at,
+                } => {
+                    out.push_str(&format!(
+                        "[{}] {}: ",
+                        timestamp(at),
+                        speaker(role, sender_name)
+                    ));
+                    out.push_str(&indent(content.trim(), "  "));
+                    out.push('\n');
+                    for attachment in attachments {
+                        out.push_str(&format!("  > attached {}\n", attachment.filename));
+                    }
+                    if let Some(attribution) = attribution.as_ref().filter(|_| !has_turns) {
+                        out.push_str(&format!("  > {}\n", completion_summary(attribution)));
+                    }
+                }
+                TranscriptEntry::Turn { outcome, .. } => {
+                    render_turn(&mut out, outcome, raw);
+                }
+                TranscriptEntry::BranchRun {
+                    description,
+                    conclusion,
+                    at,
+                } => {
+                    out.push_str(&format!(
+                        "[{}] branch: {}\n",
+                        timestamp(at),
+                        first_line(description)
+                    ));
+                    if let Some(conclusion) = conclusion {
+                        out.push_str(&format!("  > {}\n", first_line(conclusion)));
+                    }
+                }
+                TranscriptEntry::WorkerRun {
+                    task,
+                    result,
+                    status,
+                    at,
+                } => {
+                    out.push_str(&format!(
+                        "[{}] worker ({status}): {}\n",
+                        timestamp(at),
+                        first_line(task)
+                    ));
+                    if let Some(result) = result {
+                        out.push_str(&format!("  > {}\n", first_line(result)));
+                    }
+                }
+            }
+        }
+        out
+    }
+
+    /// Write the conversation for sharing. Messages are shown in full, with
+    /// image attachments inline; each turn's tool calls and each branch and
+    /// worker run go in a collapsed section.
+    pub fn export(&self, format: ExportFormat) -> String {
+        let mut out = match format {
+            ExportFormat::Markdown => format!("# Conversation `{}`\n\n", self.channel_id),
+            ExportFormat::Html => {
+                let title = escape_html(&format!("Conversation {}", self.channel_id));
+                format!(
+                    "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n\
+                     <title>{title}</title>\n<style>{HTML_STYLE}</style>\n</head>\n<body>\n\
+                     <h1>{title}</h1>\n"
+                )
+            }
+        };
+
+        for entry in &self.entries {
+            let section = match entry {
+                TranscriptEntry::Message {
+                    role,
+                    sender_name,
+                    content,
+                    attachments,
+                    at,
+                    ..
+                } => {
+                    let speaker = speaker(role, sender_name);
+                    match format {
+                        ExportFormat::Markdown => {
+                            let mut section = format!(
+                                "**{speaker}** · {}\n\n{}\n",
+                                timestamp(at),
+                                content.trim()
+                            );
+                            for attachment in attachments {
+                                section
+                                    .push_str(&format!("\n{}\n", markdown_attachment(attachment)));
+                            }
+                            section
+                        }
+                        ExportFormat::Html => {
+                            let mut section = format!(
+                                "<div class=\"message {}\">\n<div class=\"meta\"><strong>{}</strong> · {}</div>\n\
+                                 <div class=\"content\">{}</div>\n",
+                                escape_html(role),
+                                escape_html(speaker),
+                                timestamp(at),
+                                escape_html(content.trim())
+                            );
+                            for attachment in attachments {
+                                section.push_str(&html_attachment(attachment));
+                                section.push('\n');
+                            }
+                            section.push_str("</div>\n");
+                            section
+                        }
+                    }
+                }
+                TranscriptEntry::Turn { outcome, .. } => {
+                    let mut summary = turn_summary(outcome);
+                    if !outcome.tool_trace.is_empty() {
+                        summary.push_str(&format!(", {}", tool_calls_summary(outcome)));
+                    }
+                    let mut body = String::new();
+                    for entry in &outcome.tool_trace {
+                        let result = entry.result.as_deref().unwrap_or("(no result)");
+                        body.push_str(&match format {
+                            ExportFormat::Markdown => format!(
+                                "`{}`\n\n{}\n\n{}\n\n",
+                                entry.tool_name,
+                                fenced(&entry.args),
+                                fenced(result.trim())
+                            ),
+                            ExportFormat::Html => format!(
+                                "<p><code>{}</code></p>\n<pre>{}</pre>\n<pre>{}</pre>\n",
+                                escape_html(&entry.tool_name),
+                                escape_html(&entry.args),
+                                escape_html(result.trim())
+                            ),
+                        });
+                    }
+                    collapsed(format, &summary, &body)
+                }
+                TranscriptEntry::BranchRun {
+                    description,
+                    conclusion,
+                    at,
+                } => collapsed(
+                    format,
+                    &format!("branch at {}: {}", timestamp(at), first_line(description)),
+                    &run_details(format, description, conclusion.as_deref()),
+                ),
+                TranscriptEntry::WorkerRun {
+                    task,
+                    result,
+                    status,
+                    at,
+                } => collapsed(
+                    format,
+                    &format!(
+                        "worker ({status}) at {}: {}",
+                        timestamp(at),
+                        first_line(task)
+                    ),
+                    &run_details(format, task, result.as_deref()),
+                ),
+            };
+            out.push_str(&section);
+            if format == ExportFormat::Markdown {
+                out.push('\n');
+            }
+        }
+
+        if format == ExportFormat::Html {
+            out.push_str("</body>\n</html>\n");
+        }
+        out
+    }
+}
+
+const HTML_STYLE: &str = "body{font-family:system-ui,sans-serif;max-width:50rem;margin:2rem auto;padding:0 1rem;line-height:1.5}\
+.message{margin:1rem 0}.meta{color:#666;font-size:.9rem}.content{white-space:pre-wrap}\
+.message.assistant .content{border-left:3px solid #8ab;padding-left:.75rem}\
+details{margin:.5rem 0;color:#444;font-size:.9rem}summary{cursor:pointer}\
+pre{background:#f4f4f4;padding:.5rem;overflow-x:auto;white-space:pre-wrap}img{max-width:100%}";
+
+/// A collapsed section with `summary` as its title.
+fn collapsed(format: ExportFormat, summary: &str, body: &str) -> String {
+    let summary = escape_html(summary);
+    match format {
+        // Markdown needs blank lines around the body for it to render
+        // inside the HTML block.
+        ExportFormat::Markdown => {
+            format!("<details>\n<summary>{summary}</summary>\n\n{body}</details>\n")
+        }
+        ExportFormat::Html => {
+            format!("<details>\n<summary>{summary}</summary>\n{body}</details>\n")
+        }
+    }
+}
+
+/// A branch's or worker's full description and result, for its section.
+fn run_details(format: ExportFormat, description: &str, result: Option<&str>) -> String {
+    let result = result.unwrap_or("(no result yet)");
+    match format {
+        ExportFormat::Markdown => format!(
+            "{}\n\n{}\n\n",
+            fenced(description.trim()),
+            fenced(result.trim())
+        ),
+        ExportFormat::Html => format!(
+            "<pre>{}</pre>\n<pre>{}</pre>\n",
+            escape_html(description.trim()),
+            escape_html(result.trim())
+        ),
+    }
+}
+
+/// Only web links are shown as images or links; anything else, such as a
+/// local path, is named.
+fn is_web_url(url: &str) -> bool {
+    url.starts_with("https://") || url.starts_with("http://")
+}
+
+fn markdown_attachment(attachment: &Attachment) -> String {
+    let name = attachment.filename.replace(['[', ']'], "");
+    if !is_web_url(&attachment.url) {
+        return format!("_attached {name}_");
+    }
+    let url = attachment.url.replace(' ', "%20").replace(')', "%29");
+    if attachment.mime_type.starts_with("image/") {
+        format!("![{name}]({url})")
+    } else {
+        format!("[{name}]({url})")
+    }
+}
+
+fn html_attachment(attachment: &Attachment) -> String {
+    let name = escape_html(&attachment.filename);
+    if !is_web_url(&attachment.url) {
+        return format!("<p><em>attached {name}</em></p>");
+    }
+    let url = escape_html(&attachment.url);
+    if attachment.mime_type.starts_with("image/") {
+        format!("<img src=\"{url}\" alt=\"{name}\">")
+    } else {
+        format!("<p><a href=\"{url}\">{name}</a></p>")
+    }
+}
+
+/// `text` in a fenced code block, with a fence longer than any run of
+/// backticks inside it.
+fn fenced(text: &str) -> String {
+    let longest = text
+        .split(|c| c != '`')
+        .map(str::len)
+        .max()
+        .unwrap_or_default();
+    let fence = "`".repeat((longest + 1).max(3));
+    format!("{fence}\n{text}\n{fence}")
+}
+
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn render_turn(
+    out: &mut String,
+    outcome: &TurnOutcome,
+    raw: Option<&BTreeMap<String, Vec<RecordedAttempt>>>,
+) {
+    out.push_str(&format!("  > {}\n", turn_summary(outcome)));
+
+    if outcome.tool_trace.is_empty() {
+        return;
+    }
+    let Some(raw) = raw else {
+        out.push_str(&format!("  > {}\n", tool_calls_summary(outcome)));
+        return;
+    };
+
+    for entry in &outcome.tool_trace {
+        out.push_str(&format!("  > {}({})\n", entry.tool_name, entry.args));
+        match &entry.result {
+            Some(result) => out.push_str(&format!("    = {}\n", indent(result.trim(), "      "))),
+            None => out.push_str("    = (no result)\n"),
+        }
+    }
+    for request_id in outcome
+        .routing
+        .iter()
+        .filter_map(|decision| decision.request_id.as_ref())
+    {
+        let Some(attempts) = raw.get(request_id) else {
+            out.push_str(&format!("  > request {request_id}: not recorded\n"));
+            continue;
+        };
+        for attempt in attempts {
+            let result = match &attempt.outcome {
+                Some(AttemptOutcome::Parsed { .. }) => "parsed",
+                Some(AttemptOutcome::Failed { .. }) => "failed",
+                None => "unparsed",
+            };
+            out.push_str(&format!(
+                "  > request {request_id} attempt {} to {}: {} {result}",
+                attempt.attempt, attempt.model, attempt.status
+            ));
+            if let Some(provider_request_id) = &attempt.provider_request_id {
+                out.push_str(&format!(" (provider request id: {provider_request_id})"));
+            }
+            out.push('\n');
+            out.push_str(&format!("    request: {}\n", attempt.request_body));
+            out.push_str(&format!("    response: {}\n", attempt.response_body));
+        }
+    }
+}
+
+/// What a turn cost and how it ended, on one line.
+fn turn_summary(outcome: &TurnOutcome) -> String {
+    let mut summary = format!(
+        "turn: {} completion{}, {} in / {} out tokens",
+        outcome.usage.completions,
+        if outcome.usage.completions == 1 {
+            ""
+        } else {
+            "s"
+        },
+        outcome.usage.input_tokens,
+        outcome.usage.output_tokens,
+    );
+    if let Some(cost) = outcome.cost_usd {
+        summary.push_str(&format!(", ${cost:.4}"));
+    }
+    if let Some(estimate) = outcome.estimated_cost_usd {
+        summary.push_str(&format!(" (estimated ${estimate:.4})"));
+    }
+    let models: Vec<&str> = outcome
+        .routing
+        .iter()
+        .filter_map(|decision| decision.model.as_deref())
+        .fold(Vec::new(), |mut models, model| {
+            if !models.contains(&model) {
+                models.push(model);
+            }
+            models
+        });
+    if !models.is_empty() {
+        summary.push_str(&format!(" on {}", models.join(", ")));
+    }
+    if let Some(seed) = outcome.seed {
+        summary.push_str(&format!(", seed {seed}"));
+    }
+    match &outcome.stop_reason {
+        StopReason::Completed => {}
+        StopReason::Skipped => summary.push_str(", skipped"),
+        StopReason::MaxTurns => summary.push_str(", hit max turns"),
+        StopReason::Cancelled { reason } => summary.push_str(&format!(", cancelled: {reason}")),
+        StopReason::Failed { error, .. } => summary.push_str(&format!(", failed: {error}")),
+        StopReason::Panicked { message } => summary.push_str(&format!(", panicked: {message}")),
+        StopReason::Declined { code, detail } => {
+            summary.push_str(&format!(", declined ({code}): {detail}"))
+        }
+    }
+    summary
+}
+
+/// The tools a turn called, e.g. `2 tool calls: shell, reply`.
+fn tool_calls_summary(outcome: &TurnOutcome) -> String {
+    let names: Vec<&str> = outcome
+        .tool_trace
+        .iter()
+        .map(|entry| entry.tool_name.as_str())
+        .collect();
+    format!(
+        "{} tool call{}: {}",
+        names.len(),
+        if names.len() == 1 { "" } else { "s" },
+        names.join(", ")
+    )
+}
+
+fn completion_summary(attribution: &ReplyAttribution) -> String {
+    let mut summary = format!(
+        "{} in / {} out tokens",
+        attribution.input_tokens, attribution.output_tokens
+    );
+    if let Some(cost) = attribution.cost_usd {
+        summary.push_str(&format!(", ${cost:.4}"));
+    }
+    if let Some(model) = &attribution.model {
+        summary.push_str(&format!(" on {model}"));
+    }
+    summary
+}
+
+/// Who sent a message: the sender's name for users, the role otherwise.
+fn speaker<'a>(role: &'a str, sender_name: &'a Option<String>) -> &'a str {
+    match (role, sender_name) {
+        ("user", Some(name)) => name,
+        (role, _) => role,
+    }
+}
+
+fn timestamp(at: &DateTime<Utc>) -> String {
+    at.format("%Y-%m-%d %H:%M:%S").to_string()
+}
+
+/// Indent every line after the first.
+fn indent(text: &str, prefix: &str) -> String {
+    text.replace('\n', &format!("\n{prefix}"))
+}
+
+/// The first line of a run's description or result, which is all a folded
+/// transcript shows.
+fn first_line(text: &str) -> &str {
+    text.trim().lines().next().unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::turn::{RoutingDecision, ToolTraceEntry, TurnUsage};
+
+    fn at(second: u32) -> DateTime<Utc> {
+        DateTime::from_timestamp(1_790_000_000 + i64::from(second), 0).unwrap()
+    }
+
+    fn transcript() -> Transcript {
+        Transcript {
+            channel_id: "webhook:demo".into(),
+            entries: vec![
+                TranscriptEntry::Message {
+                    role: "user".into(),
+                    sender_name: Some("alice".into()),
+                    content: "what's in the repo?".into(),
+                    attribution: None,
+                    attachments: Vec::new(),
+                    at: at(0),
+                },
+                TranscriptEntry::Message {
+                    role: "assistant".into(),
+                    sender_name: None,
+                    content: "A Cargo.toml\nand src/.".into(),
+                    attribution: Some(ReplyAttribution {
+                        request_id: Some("req-2".into()),
+                        ..ReplyAttribution::default()
+                    }),
+                    attachments: Vec::new(),
+                    at: at(4),
+                },
+                TranscriptEntry::Turn {
+                    outcome: TurnOutcome {
+                        tool_trace: vec![
+                            ToolTraceEntry {
+                                tool_name: "shell".into(),
+                                args: r#"{"command":"ls"}"#.into(),
+                                result: Some("Cargo.toml\nsrc".into()),
+                            },
+                            ToolTraceEntry {
+                                tool_name: "reply".into(),
+                                args: "{}".into(),
+                                result: None,
+                            },
+                        ],
+                        usage: TurnUsage {
+                            completions: 2,
+                            input_tokens: 3000,
+                            output_tokens: 120,
+                            retrieval_tokens: 0,
+                            preamble_tokens: 0,
+                            tool_tokens: 0,
+                            history_tokens: 0,
+                        },
+                        cost_usd: Some(0.0108),
+                        routing: vec![
+                            RoutingDecision {
+                                request_id: Some("req-1".into()),
+                                model: Some("anthropic/x".into()),
+                                provider_request_id: None,
+                                seed: None,
+                                fallback: false,
+                            },
+                            RoutingDecision {
+                                request_id: Some("req-2".into()),
+                                model: Some("anthropic/x".into()),
+                                provider_request_id: None,
+                                seed: None,
+                                fallback: false,
+                            },
+                        ],
+                        ..TurnOutcome::default()
+                    },
+                    at: at(4),
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn test_render_folded() {
+        let transcript = transcript();
+        assert_eq!(transcript.request_ids(), vec!["req-2", "req-1"]);
+        assert_eq!(
+            transcript.render(None),
+            "[2026-09-21 14:13:20] alice: what's in the repo?\n\
+             [2026-09-21 14:13:24] assistant: A Cargo.toml\n  and src/.\n  \
+             > turn: 2 completions, 3000 in / 120 out tokens, $0.0108 on anthropic/x\n  \
+             > 2 tool calls: shell, reply\n"
+        );
+    }
+
+    #[test]
+    fn test_export() {
+        let mut transcript = transcript();
+        if let TranscriptEntry::Message { attachments, .. } = &mut transcript.entries[0] {
+            attachments.push(Attachment {
+                filename: "tree.png".into(),
+                mime_type: "image/png".into(),
+                url: "https://cdn.example.com/tree.png".into(),
+                size_bytes: None,
+            });
+        }
+
+        let markdown = transcript.export(ExportFormat::Markdown);
+        assert!(markdown.starts_with("# Conversation `webhook:demo`\n\n"));
+        assert!(markdown.contains(
+            "**alice** · 2026-09-21 14:13:20\n\nwhat's in the repo?\n\n\
+             ![tree.png](https://cdn.example.com/tree.png)\n"
+        ));
+        assert!(markdown.contains(
+            "<details>\n<summary>turn: 2 completions, 3000 in / 120 out tokens, $0.0108 on \
+             anthropic/x, 2 tool calls: shell, reply</summary>\n\n`shell`\n\n\
+             ```\n{\"command\":\"ls\"}\n```\n\n```\nCargo.toml\nsrc\n```\n\n"
+        ));
+
+        let html = transcript.export(ExportFormat::Html);
+        assert!(html.contains("<title>Conversation webhook:demo</title>"));
+        assert!(html.contains("<div class=\"content\">what&#39;s in the repo?</div>"));
+        assert!(html.contains("<img src=\"https://cdn.example.com/tree.png\" alt=\"tree.png\">"));
+        assert!(html.contains("<pre>{&quot;command&quot;:&quot;ls&quot;}</pre>"));
+        assert!(html.ends_with("</body>\n</html>\n"));
+    }
+
+    #[test]
+    fn test_shared_redacts() {
+        let mut transcript = transcript();
+        if let TranscriptEntry::Message { content, .. } = &mut transcript.entries[0] {
+            *content = "what's in the repo? mail me at alice@example.com".into();
+        }
+        let shared = transcript.shared();
+        assert_eq!(shared.channel_id, "webhook");
+        assert!(shared.request_ids().is_empty());
+        let TranscriptEntry::Message {
+            sender_name,
+            content,
+            ..
+        } = &shared.entries[0]
+        else {
+            panic!("expected a message");
+        };
+        assert_eq!(sender_name, &None);
+        assert!(!content.contains("alice@example.com"));
+        assert!(!shared.export(ExportFormat::Html).contains("alice"));
+    }
+
+    #[test]
+    fn test_fenced_outgrows_backticks() {
+        assert_eq!(fenced("a ``` b"), "````\na ``` b\n````");
+        assert_eq!(fenced("plain"), "```\nplain\n```");
+    }
+
+    #[test]
+    fn test_render_raw() {
+        let mut raw = BTreeMap::new();
+        raw.insert(
+            "req-1".to_string(),
+            vec![RecordedAttempt {
+                model: "anthropic/x".into(),
+                attempt: 1,
+                started_at: at(1),
+                request_body: r#"{"messages":[]}"#.into(),
+                status: 200,
+                provider_request_id: Some("req_011".into()),
+                response_body: r#"{"content":[]}"#.into(),
+                outcome: Some(AttemptOutcome::Parsed {
+                    summary: String::new(),
+                }),
+            }],
+        );
+
+        let rendered = transcript().render(Some(&raw));
+        assert!(
+            rendered.contains("  > shell({\"command\":\"ls\"})\n    = Cargo.toml\n      src\n")
+        );
+        assert!(rendered.contains("  > reply({})\n    = (no result)\n"));
+        assert!(rendered.contains(
+            "  > request req-1 attempt 1 to anthropic/x: 200 parsed \
+             (provider request id: req_011)\n    \
+             request: {\"messages\":[]}\n    response: {\"content\":[]}\n"
+        ));
+        assert!(rendered.contains("  > request req-2: not recorded\n"));
+    }
+}
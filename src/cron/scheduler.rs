@@ -117,6 +117,12 @@ impl Scheduler {
         }
     }
 
+    /// The context jobs run in, for other triggers that run prompts the same
+    /// way (e.g. incident webhooks).
+    pub fn context(&self) -> &CronContext {
+        &self.context
+    }
+
     /// Register and start a cron job from config.
     pub async fn register(&self, config: CronConfig) -> Result<()> {
         let delivery_target = DeliveryTarget::parse(&config.delivery_target).unwrap_or_else(|| {
@@ -243,6 +249,14 @@ impl Scheduler {
                                 cron_id = %job_id,
                                 "circuit breaker tripped after {MAX_CONSECUTIVE_FAILURES} consecutive failures, disabling"
                             );
+                            context.deps.llm_manager.events().publish(
+                                crate::events::Event::CircuitBreakerTripped {
+                                    scope: format!("cron:{job_id}"),
+                                    detail: format!(
+                                        "disabled after {MAX_CONSECUTIVE_FAILURES} consecutive failures"
+                                    ),
+                                },
+                            );
 
                             {
                                 let mut j = jobs.write().await;
@@ -366,9 +380,16 @@ impl Scheduler {
     }
 }
 
-/// Execute a single cron job: create a fresh channel, run the prompt, deliver the result.
-async fn run_cron_job(job: &CronJob, context: &CronContext) -> Result<()> {
-    let channel_id: crate::ChannelId = Arc::from(format!("cron:{}", job.id).as_str());
+/// Run `prompt` through a fresh, short-lived channel and return the text it
+/// replied with. Cron jobs and other background triggers (e.g. Home
+/// Assistant state changes) share this path.
+pub async fn run_prompt(
+    context: &CronContext,
+    source: &str,
+    conversation_id: &str,
+    prompt: String,
+) -> Result<String> {
+    let channel_id: crate::ChannelId = Arc::from(conversation_id);
 
     // Create the outbound response channel to collect whatever the channel produces
     let (response_tx, mut response_rx) = tokio::sync::mpsc::channel::<OutboundResponse>(32);
@@ -388,18 +409,18 @@ async fn run_cron_job(job: &CronJob, context: &CronContext) -> Result<()> {
     // Spawn the channel's event loop
     let channel_handle = tokio::spawn(async move {
         if let Err(error) = channel.run().await {
-            tracing::error!(%error, "cron channel failed");
+            tracing::error!(%error, "synthetic channel failed");
         }
     });
 
-    // Send the cron job prompt as a synthetic message
+    // Send the prompt as a synthetic message
     let message = InboundMessage {
         id: uuid::Uuid::new_v4().to_string(),
-        source: "cron".into(),
-        conversation_id: format!("cron:{}", job.id),
+        source: source.into(),
+        conversation_id: conversation_id.into(),
         sender_id: "system".into(),
         agent_id: Some(context.deps.agent_id.clone()),
-        content: MessageContent::Text(job.prompt.clone()),
+        content: MessageContent::Text(prompt),
         timestamp: chrono::Utc::now(),
         metadata: HashMap::new(),
     };
@@ -407,7 +428,7 @@ async fn run_cron_job(job: &CronJob, context: &CronContext) -> Result<()> {
     channel_tx
         .send(message)
         .await
-        .map_err(|error| anyhow::anyhow!("failed to send cron prompt to channel: {error}"))?;
+        .map_err(|error| anyhow::anyhow!("failed to send prompt to channel: {error}"))?;
 
     // Collect responses with a timeout. The channel may produce multiple messages
     // (e.g. status updates, then text). We only care about text responses.
@@ -425,14 +446,14 @@ async fn run_cron_job(job: &CronJob, context: &CronContext) -> Result<()> {
                 collected_text.push(text);
             }
             Ok(Some(_)) => {
-                // Status updates, stream chunks, etc. — ignore for cron jobs
+                // Status updates, stream chunks, etc. — only the text is delivered
             }
             Ok(None) => {
                 // Channel finished (response_tx dropped)
                 break;
             }
             Err(_) => {
-                tracing::warn!(cron_id = %job.id, "cron job timed out after {timeout:?}");
+                tracing::warn!(%conversation_id, "prompt timed out after {timeout:?}");
                 channel_handle.abort();
                 break;
             }
@@ -442,7 +463,23 @@ async fn run_cron_job(job: &CronJob, context: &CronContext) -> Result<()> {
     // Wait for the channel task to finish (it should already be done since we dropped channel_tx)
     let _ = channel_handle.await;
 
-    let result_text = collected_text.join("\n\n");
+    Ok(collected_text.join("\n\n"))
+}
+
+/// Execute a single cron job: create a fresh channel, run the prompt, deliver the result.
+async fn run_cron_job(job: &CronJob, context: &CronContext) -> Result<()> {
+    if context.deps.maintenance.is_enabled() {
+        tracing::info!(cron_id = %job.id, "skipping cron job during maintenance");
+        return Ok(());
+    }
+
+    let prompt = context
+        .deps
+        .runtime_config
+        .prompt_library
+        .load()
+        .expand(&job.prompt);
+    let result_text = run_prompt(context, "cron", &format!("cron:{}", job.id), prompt).await?;
     let has_result = !result_text.trim().is_empty();
 
     // Log execution
@@ -1,14 +1,25 @@
 //! Process daemonization and IPC for background operation.
 
 use crate::config::Config;
+use crate::config::rollout::{ConfigChange, RolloutStatus};
+use crate::llm::LlmManager;
+use crate::llm::recorder::RecordedAttempt;
+use crate::llm::replay::{ReplayOptions, ReplayOutcome};
+use crate::llm::simulate::RouteState;
 
 use anyhow::{Context as _, anyhow};
 use serde::{Deserialize, Serialize};
 use tokio::io::{AsyncBufReadExt, AsyncWriteExt};
 use tokio::net::{UnixListener, UnixStream};
 use tokio::sync::watch;
+use tracing_subscriber::filter::{EnvFilter, LevelFilter};
+use tracing_subscriber::layer::SubscriberExt as _;
+use tracing_subscriber::util::SubscriberInitExt as _;
+use tracing_subscriber::{Registry, reload};
 
+use std::collections::BTreeMap;
 use std::path::PathBuf;
+use std::sync::{Arc, Mutex, OnceLock, Weak};
 use std::time::Instant;
 
 /// Commands sent from CLI client to the running daemon.
@@ -17,6 +28,19 @@ use std::time::Instant;
 pub enum IpcCommand {
     Shutdown,
     Status,
+    LogLevel(LogLevelChange),
+    RouteState,
+    LlmExchanges {
+        request_ids: Vec<String>,
+    },
+    Replay {
+        request_id: String,
+        options: ReplayOptions,
+    },
+    ConfigRollout,
+    ApplyConfig {
+        canary: Option<String>,
+    },
 }
 
 /// Responses from the daemon back to the CLI client.
@@ -25,9 +49,132 @@ pub enum IpcCommand {
 pub enum IpcResponse {
     Ok,
     Status { pid: u32, uptime_seconds: u64 },
+    LogFilter { filter: String },
+    RouteState(RouteState),
+    LlmExchanges(BTreeMap<String, Vec<RecordedAttempt>>),
+    Replay(ReplayOutcome),
+    ConfigRollout(RolloutStatus),
+    ConfigApplied(Vec<ConfigChange>),
     Error { message: String },
 }
 
+/// A change to the running daemon's log filter. With no fields set it
+/// changes nothing, which is how the current filter is read.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LogLevelChange {
+    /// `error`, `warn`, `info`, `debug`, `trace` or `off`.
+    #[serde(default)]
+    pub level: Option<String>,
+    /// Apply `level` to this target only, e.g. `spacebot::llm`, instead of
+    /// everything.
+    #[serde(default)]
+    pub target: Option<String>,
+    /// Go back to the filter the daemon started with first.
+    #[serde(default)]
+    pub reset: bool,
+}
+
+/// A default level plus per-target overrides, rendered as `EnvFilter`
+/// directives.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct LogFilterState {
+    startup_level: String,
+    level: String,
+    targets: BTreeMap<String, String>,
+}
+
+impl LogFilterState {
+    fn new(level: &str) -> Self {
+        Self {
+            startup_level: level.to_string(),
+            level: level.to_string(),
+            targets: BTreeMap::new(),
+        }
+    }
+
+    fn apply(&mut self, change: &LogLevelChange) -> anyhow::Result<()> {
+        if change.reset {
+            self.level = self.startup_level.clone();
+            self.targets.clear();
+        }
+        let level = match (&change.level, &change.target) {
+            (Some(level), _) => level.trim().to_lowercase(),
+            (None, Some(_)) => anyhow::bail!("a level is needed with a target"),
+            (None, None) => return Ok(()),
+        };
+        level
+            .parse::<LevelFilter>()
+            .map_err(|_| anyhow!("unknown log level '{level}'"))?;
+        match change.target.as_deref().map(str::trim) {
+            Some(target) => {
+                if target.is_empty() || target.contains([',', '=', '[', ' ']) {
+                    anyhow::bail!("invalid log target '{target}'");
+                }
+                self.targets.insert(target.to_string(), level);
+            }
+            None => self.level = level,
+        }
+        Ok(())
+    }
+
+    fn directives(&self) -> String {
+        std::iter::once(self.level.clone())
+            .chain(
+                self.targets
+                    .iter()
+                    .map(|(target, level)| format!("{target}={level}")),
+            )
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+}
+
+struct LogFilter {
+    handle: reload::Handle<EnvFilter, Registry>,
+    state: Mutex<LogFilterState>,
+}
+
+/// Set once tracing is initialized, so the filter can be changed at runtime.
+static LOG_FILTER: OnceLock<LogFilter> = OnceLock::new();
+
+/// Change the log filter without restarting. Returns the filter now in
+/// effect, as `EnvFilter` directives.
+pub fn change_log_filter(change: &LogLevelChange) -> anyhow::Result<String> {
+    let log_filter = LOG_FILTER
+        .get()
+        .context("tracing was not initialized with a reloadable filter")?;
+    let mut state = log_filter
+        .state
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+    let mut next = state.clone();
+    next.apply(change)?;
+    let directives = next.directives();
+    if next != *state {
+        let filter = EnvFilter::try_new(&directives)
+            .with_context(|| format!("invalid log filter '{directives}'"))?;
+        log_filter
+            .handle
+            .reload(filter)
+            .context("failed to swap the log filter")?;
+        *state = next;
+        tracing::info!(filter = %directives, "log filter changed");
+    }
+    Ok(directives)
+}
+
+/// The startup log filter, wrapped so it can be changed at runtime.
+fn reloadable_filter(debug: bool) -> reload::Layer<EnvFilter, Registry> {
+    let level = if debug { "debug" } else { "info" };
+    let (layer, handle) = reload::Layer::new(EnvFilter::new(level));
+    let _ = LOG_FILTER.set(LogFilter {
+        handle,
+        state: Mutex::new(LogFilterState::new(level)),
+    });
+    layer
+}
+
 /// Paths for daemon runtime files, all derived from the instance directory.
 pub struct DaemonPaths {
     pub pid_file: PathBuf,
@@ -120,28 +267,41 @@ pub fn init_background_tracing(paths: &DaemonPaths, debug: bool) {
     // The process owns this — it's cleaned up on exit.
     std::mem::forget(_guard);
 
-    let filter = if debug {
-        tracing_subscriber::EnvFilter::new("debug")
-    } else {
-        tracing_subscriber::EnvFilter::new("info")
-    };
-
-    tracing_subscriber::fmt()
-        .with_env_filter(filter)
-        .with_writer(non_blocking)
-        .with_ansi(false)
+    tracing_subscriber::registry()
+        .with(reloadable_filter(debug))
+        .with(
+            tracing_subscriber::fmt::layer()
+                .with_writer(non_blocking)
+                .with_ansi(false),
+        )
         .init();
 }
 
 /// Initialize tracing for foreground mode (stdout).
 pub fn init_foreground_tracing(debug: bool) {
-    let filter = if debug {
-        tracing_subscriber::EnvFilter::new("debug")
-    } else {
-        tracing_subscriber::EnvFilter::new("info")
-    };
+    tracing_subscriber::registry()
+        .with(reloadable_filter(debug))
+        .with(tracing_subscriber::fmt::layer())
+        .init();
+}
+
+/// The daemon's current LLM manager, whose rate limit and health state
+/// `spacebot routing simulate` starts from. Replaced when the manager is
+/// rebuilt.
+static LLM_MANAGER: Mutex<Weak<LlmManager>> = Mutex::new(Weak::new());
+
+/// Make `manager`'s routing state available over IPC.
+pub fn set_llm_manager(manager: &Arc<LlmManager>) {
+    *LLM_MANAGER
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner()) = Arc::downgrade(manager);
+}
 
-    tracing_subscriber::fmt().with_env_filter(filter).init();
+fn llm_manager() -> Option<Arc<LlmManager>> {
+    LLM_MANAGER
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .upgrade()
 }
 
 /// Start the IPC server. Returns a shutdown receiver that the main event
@@ -219,6 +379,61 @@ async fn handle_ipc_connection(
             pid: std::process::id(),
             uptime_seconds: uptime.as_secs(),
         },
+        IpcCommand::LogLevel(change) => match change_log_filter(&change) {
+            Ok(filter) => IpcResponse::LogFilter { filter },
+            Err(error) => IpcResponse::Error {
+                message: format!("{error:#}"),
+            },
+        },
+        IpcCommand::RouteState => match llm_manager() {
+            Some(manager) => IpcResponse::RouteState(manager.route_state().await),
+            None => IpcResponse::Error {
+                message: "the LLM manager isn't running yet".into(),
+            },
+        },
+        // Request ids the recorder no longer has are left out.
+        IpcCommand::LlmExchanges { request_ids } => match llm_manager() {
+            Some(manager) if !manager.debug_recorder().is_enabled() => IpcResponse::Error {
+                message: "llm.debug_recording is off, so no exchanges were kept".into(),
+            },
+            Some(manager) => IpcResponse::LlmExchanges(
+                request_ids
+                    .into_iter()
+                    .filter_map(|request_id| {
+                        let attempts = manager.debug_recorder().get(&request_id)?;
+                        Some((request_id, attempts))
+                    })
+                    .collect(),
+            ),
+            None => IpcResponse::Error {
+                message: "the LLM manager isn't running yet".into(),
+            },
+        },
+        IpcCommand::Replay {
+            request_id,
+            options,
+        } => match llm_manager() {
+            Some(manager) => {
+                match crate::llm::replay::replay(&manager, &request_id, &options).await {
+                    Ok(outcome) => IpcResponse::Replay(outcome),
+                    Err(error) => IpcResponse::Error {
+                        message: error.to_string(),
+                    },
+                }
+            }
+            None => IpcResponse::Error {
+                message: "the LLM manager isn't running yet".into(),
+            },
+        },
+        IpcCommand::ConfigRollout => IpcResponse::ConfigRollout(crate::config::rollout::status()),
+        // Reload config.toml now, optionally with new routing limited to one
+        // agent.
+        IpcCommand::ApplyConfig { canary } => match crate::config::rollout::apply(canary) {
+            Ok(changes) => IpcResponse::ConfigApplied(changes),
+            Err(error) => IpcResponse::Error {
+                message: format!("{error:#}"),
+            },
+        },
     };
 
     let mut response_bytes = serde_json::to_vec(&response)?;
@@ -292,3 +507,49 @@ pub fn wait_for_exit(pid: u32) -> bool {
     }
     false
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn change(level: Option<&str>, target: Option<&str>, reset: bool) -> LogLevelChange {
+        LogLevelChange {
+            level: level.map(String::from),
+            target: target.map(String::from),
+            reset,
+        }
+    }
+
+    #[test]
+    fn test_log_filter_changes() {
+        let mut state = LogFilterState::new("info");
+        state
+            .apply(&change(Some("debug"), Some("spacebot::llm"), false))
+            .unwrap();
+        state
+            .apply(&change(Some("TRACE"), Some("spacebot::tools"), false))
+            .unwrap();
+        assert_eq!(
+            state.directives(),
+            "info,spacebot::llm=debug,spacebot::tools=trace"
+        );
+
+        state.apply(&change(Some("warn"), None, false)).unwrap();
+        assert_eq!(
+            state.directives(),
+            "warn,spacebot::llm=debug,spacebot::tools=trace"
+        );
+
+        state.apply(&change(None, None, true)).unwrap();
+        assert_eq!(state.directives(), "info");
+
+        assert!(state.apply(&change(Some("loud"), None, false)).is_err());
+        assert!(state.apply(&change(None, Some("spacebot"), false)).is_err());
+        assert!(
+            state
+                .apply(&change(Some("debug"), Some("a=b"), false))
+                .is_err()
+        );
+        assert_eq!(state.directives(), "info");
+    }
+}
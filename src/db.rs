@@ -66,3 +66,26 @@ impl Db {
         // LanceDB and redb close automatically when dropped
     }
 }
+
+/// Create a migrated in-memory SQLite pool for testing. Each call creates an
+/// isolated database so tests can run in parallel.
+#[cfg(test)]
+pub async fn connect_in_memory() -> SqlitePool {
+    use sqlx::sqlite::SqliteConnectOptions;
+
+    let options = SqliteConnectOptions::new()
+        .in_memory(true)
+        .create_if_missing(true);
+
+    // Single-connection pool: each pool gets its own private in-memory db.
+    let pool = sqlx::pool::PoolOptions::<sqlx::Sqlite>::new()
+        .max_connections(1)
+        .connect_with(options)
+        .await
+        .expect("in-memory SQLite");
+    sqlx::migrate!("./migrations")
+        .run(&pool)
+        .await
+        .expect("migrations");
+    pool
+}
@@ -0,0 +1,160 @@
+//! Messages the policy layers decline to answer.
+//!
+//! The budget, the cost gate, moderator escalation, the sensitive-topic
+//! policy, approval permissions, maintenance mode and rate limits can all
+//! stop a message before a model answers it. Each says so with a
+//! [`Declined`] carrying a [`DeclineReason`] rather than an error or a bare
+//! reply, so the user is told in one consistent way, a channel turn that was
+//! declined ends as [`StopReason::Declined`] instead of looking skipped or
+//! failed, and [`DeclineMetrics`] counts refusals apart from failures.
+//!
+//! [`StopReason::Declined`]: crate::agent::turn::StopReason::Declined
+
+use crate::OutboundResponse;
+
+use serde::{Deserialize, Serialize};
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+/// Which policy declined a message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeclineReason {
+    /// The agent has spent its daily budget.
+    OverBudget,
+    /// The user rejected the turn's cost estimate or didn't confirm it in
+    /// time.
+    CostNotConfirmed,
+    /// The conversation is waiting for a moderator.
+    Escalated,
+    /// The message is in a sensitive-topic category that's declined.
+    SensitiveTopic,
+    /// The sender isn't allowed to do what they asked, like deciding an
+    /// incident action.
+    NotPermitted,
+    /// The instance is in maintenance mode.
+    Maintenance,
+    /// The sender or conversation is over its message quota.
+    RateLimited,
+}
+
+impl DeclineReason {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::OverBudget => "over_budget",
+            Self::CostNotConfirmed => "cost_not_confirmed",
+            Self::Escalated => "escalated",
+            Self::SensitiveTopic => "sensitive_topic",
+            Self::NotPermitted => "not_permitted",
+            Self::Maintenance => "maintenance",
+            Self::RateLimited => "rate_limited",
+        }
+    }
+}
+
+impl std::fmt::Display for DeclineReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A message a policy won't let through, and what its sender is told.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Declined {
+    pub reason: DeclineReason,
+    /// For logs and the turn ledger, e.g. the topic category.
+    pub detail: String,
+    /// The reply to the sender. None declines silently, e.g. a rate limited
+    /// sender who was already told.
+    pub message: Option<String>,
+}
+
+impl Declined {
+    pub fn new(reason: DeclineReason, detail: impl Into<String>) -> Self {
+        Self {
+            reason,
+            detail: detail.into(),
+            message: None,
+        }
+    }
+
+    pub fn with_message(mut self, message: impl Into<String>) -> Self {
+        self.message = Some(message.into());
+        self
+    }
+
+    /// What to send the sender, if anything.
+    pub fn response(&self) -> Option<OutboundResponse> {
+        self.message.clone().map(OutboundResponse::Text)
+    }
+}
+
+/// Per-agent counts of declined messages by reason. Clones share state.
+#[derive(Debug, Clone, Default)]
+pub struct DeclineMetrics {
+    counts: Arc<Mutex<BTreeMap<DeclineReason, u64>>>,
+}
+
+impl DeclineMetrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&self, declined: &Declined) {
+        *self.lock().entry(declined.reason).or_default() += 1;
+    }
+
+    /// Declines since startup, by reason.
+    pub fn counts(&self) -> BTreeMap<DeclineReason, u64> {
+        self.lock().clone()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, BTreeMap<DeclineReason, u64>> {
+        self.counts
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::turn::StopReason;
+
+    #[test]
+    fn test_declines_are_counted_apart_from_failures() {
+        let metrics = DeclineMetrics::new();
+        let over_budget = Declined::new(DeclineReason::OverBudget, "daily budget spent")
+            .with_message("I'm out of budget for today.");
+        metrics.record(&over_budget);
+        metrics.record(&over_budget);
+        metrics.record(&Declined::new(
+            DeclineReason::RateLimited,
+            "UserMinute limit",
+        ));
+        assert_eq!(
+            serde_json::to_value(metrics.counts()).unwrap(),
+            serde_json::json!({"over_budget": 2, "rate_limited": 1})
+        );
+
+        assert!(matches!(
+            over_budget.response(),
+            Some(OutboundResponse::Text(text)) if text == "I'm out of budget for today."
+        ));
+        assert!(
+            Declined::new(DeclineReason::Maintenance, "maintenance mode is on")
+                .response()
+                .is_none()
+        );
+
+        assert_eq!(
+            serde_json::to_value(StopReason::declined(&over_budget)).unwrap(),
+            serde_json::json!({
+                "reason": "declined",
+                "code": "over_budget",
+                "detail": "daily budget spent",
+            })
+        );
+    }
+}
@@ -0,0 +1,8 @@
+//! Scheduled digests: summarize a channel's recent history from the platform
+//! into decisions, action items and links, and post it.
+
+pub mod store;
+pub mod watcher;
+
+pub use store::DigestStore;
+pub use watcher::spawn_digest_watcher;
@@ -0,0 +1,47 @@
+//! When each digest last ran (SQLite).
+
+use crate::error::Result;
+use anyhow::Context as _;
+use sqlx::{Row as _, SqlitePool};
+
+/// Run-time store for digest watchers.
+#[derive(Debug)]
+pub struct DigestStore {
+    pool: SqlitePool,
+}
+
+impl DigestStore {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+
+    /// When `digest_id` last ran, if it ever did.
+    pub async fn last_run_at(
+        &self,
+        digest_id: &str,
+    ) -> Result<Option<chrono::DateTime<chrono::Utc>>> {
+        let row = sqlx::query("SELECT last_run_at FROM digest_runs WHERE digest_id = ?")
+            .bind(digest_id)
+            .fetch_optional(&self.pool)
+            .await
+            .context("failed to load digest run time")?;
+
+        Ok(row
+            .and_then(|row| row.try_get::<chrono::NaiveDateTime, _>("last_run_at").ok())
+            .map(|timestamp| timestamp.and_utc()))
+    }
+
+    /// Record that `digest_id` ran just now.
+    pub async fn record_run(&self, digest_id: &str) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO digest_runs (digest_id, last_run_at) VALUES (?, CURRENT_TIMESTAMP) \
+             ON CONFLICT(digest_id) DO UPDATE SET last_run_at = CURRENT_TIMESTAMP",
+        )
+        .bind(digest_id)
+        .execute(&self.pool)
+        .await
+        .context("failed to record digest run")?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,417 @@
+//! Digest scheduling and chunked summarization.
+//!
+//! One watcher runs per agent. Each enabled digest in `runtime_config.digests`
+//! runs on its interval, inside its active hours: the last `hours` of its
+//! channel are fetched through the channel's adapter, summarized, and posted
+//! to its delivery target. A history too long for one model call is split
+//! into chunks, each condensed into notes, and the notes are merged into the
+//! digest.
+//!
+//! Digests with `prime_cache` have their prompts cached at the provider
+//! shortly before they run, so the chunk calls of every digest due together
+//! are billed at the cached-input rate.
+
+use crate::config::DigestDef;
+use crate::cron::CronContext;
+use crate::cron::scheduler::DeliveryTarget;
+use crate::digests::DigestStore;
+use crate::error::Result;
+use crate::llm::{Priority, SpacebotModel};
+use crate::messaging::traits::HistoryMessage;
+use crate::{AgentDeps, OutboundResponse, ProcessType};
+
+use anyhow::Context as _;
+use chrono::Timelike;
+use rig::agent::AgentBuilder;
+use rig::completion::Prompt as _;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// How often the watcher checks which digests are due.
+const TICK: Duration = Duration::from_secs(60);
+
+/// Digests never run more often than this, whatever their interval.
+const MIN_INTERVAL_SECS: u64 = 600;
+
+/// Characters of transcript or notes in one summarizer call.
+const MAX_CHUNK_CHARS: usize = 40_000;
+
+/// Characters kept of a single message.
+const MAX_MESSAGE_CHARS: usize = 2_000;
+
+/// Times chunk notes are condensed again before they're cut to fit.
+const MAX_REDUCE_ROUNDS: usize = 3;
+
+/// How long before a digest is due its prompts are cached. Two ticks, well
+/// inside Anthropic's five-minute cache lifetime.
+const PRIME_LEAD: Duration = Duration::from_secs(120);
+
+/// Prompts every digest run may send, in the order they're used.
+const DIGEST_PROMPTS: [&str; 2] = ["digest_chunk", "digest"];
+
+/// Spawn the digest watcher for an agent.
+///
+/// Reads `deps.runtime_config.digests` on every tick, so digests added or
+/// changed on config reload are picked up without a restart. A digest that
+/// comes due outside its active hours runs once they start; nothing runs
+/// during maintenance.
+pub fn spawn_digest_watcher(
+    context: CronContext,
+    store: Arc<DigestStore>,
+) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut next_run: HashMap<String, Instant> = HashMap::new();
+        // The due time each digest's prompts were last cached for.
+        let mut primed: HashMap<String, Instant> = HashMap::new();
+
+        loop {
+            let digests = context.deps.runtime_config.digests.load_full();
+            next_run.retain(|id, _| digests.iter().any(|digest| &digest.id == id));
+            primed.retain(|id, _| next_run.contains_key(id));
+
+            if !context.deps.maintenance.is_enabled() {
+                let hour = chrono::Local::now().hour() as u8;
+                let upcoming = due_for_priming(&digests, &next_run, &primed, hour, Instant::now());
+                if !upcoming.is_empty() {
+                    prime(&context.deps, upcoming.len()).await;
+                    primed.extend(upcoming);
+                }
+                for digest in digests.iter().filter(|digest| digest.enabled) {
+                    let interval = Duration::from_secs(digest.interval_secs.max(MIN_INTERVAL_SECS));
+                    let due = match next_run.get(&digest.id) {
+                        Some(due) => *due,
+                        None => first_due(&store, digest, interval).await,
+                    };
+                    if Instant::now() < due || !in_active_hours(digest.active_hours, hour) {
+                        next_run.insert(digest.id.clone(), due);
+                        continue;
+                    }
+                    next_run.insert(digest.id.clone(), Instant::now() + interval);
+
+                    if let Err(error) = run(&context, &store, digest).await {
+                        tracing::warn!(digest_id = %digest.id, %error, "digest failed");
+                    }
+                }
+            }
+
+            tokio::time::sleep(TICK).await;
+        }
+    })
+}
+
+/// When a digest not yet seen by this loop is due: one interval after its
+/// last run, or now if it never ran.
+async fn first_due(store: &DigestStore, digest: &DigestDef, interval: Duration) -> Instant {
+    let last_run_at = match store.last_run_at(&digest.id).await {
+        Ok(last_run_at) => last_run_at,
+        Err(error) => {
+            tracing::warn!(digest_id = %digest.id, %error, "failed to load digest run time");
+            None
+        }
+    };
+    let elapsed = last_run_at
+        .and_then(|last| (chrono::Utc::now() - last).to_std().ok())
+        .unwrap_or(interval);
+    Instant::now() + interval.saturating_sub(elapsed)
+}
+
+fn in_active_hours(active_hours: Option<(u8, u8)>, hour: u8) -> bool {
+    let Some((start, end)) = active_hours else {
+        return true;
+    };
+    if start <= end {
+        hour >= start && hour < end
+    } else {
+        // Wraps midnight (e.g. 22:00 - 06:00)
+        hour >= start || hour < end
+    }
+}
+
+/// Digests with `prime_cache` coming due within [`PRIME_LEAD`] whose prompts
+/// aren't cached for that run yet, with their due times.
+fn due_for_priming(
+    digests: &[DigestDef],
+    next_run: &HashMap<String, Instant>,
+    primed: &HashMap<String, Instant>,
+    hour: u8,
+    now: Instant,
+) -> Vec<(String, Instant)> {
+    digests
+        .iter()
+        .filter(|digest| digest.enabled && digest.prime_cache)
+        .filter(|digest| in_active_hours(digest.active_hours, hour))
+        .filter_map(|digest| {
+            let due = *next_run.get(&digest.id)?;
+            let fresh = primed.get(&digest.id) != Some(&due);
+            (fresh && due <= now + PRIME_LEAD).then(|| (digest.id.clone(), due))
+        })
+        .collect()
+}
+
+/// Cache the digest prompts at the provider. Every digest uses the same
+/// model and prompts, so one priming covers all the digests about to run.
+async fn prime(deps: &AgentDeps, digests: usize) {
+    let model = digest_model(deps, true);
+    for prompt in DIGEST_PROMPTS {
+        match model.prime_cache(crate::prompts::text::get(prompt)).await {
+            Ok(usage) => tracing::debug!(
+                prompt,
+                digests,
+                input_tokens = usage.input_tokens,
+                "primed prompt cache"
+            ),
+            Err(error) => tracing::warn!(prompt, %error, "failed to prime prompt cache"),
+        }
+    }
+}
+
+/// Fetch the digest's channel history, summarize it and post the digest.
+async fn run(context: &CronContext, store: &DigestStore, digest: &DigestDef) -> Result<()> {
+    let channel = DeliveryTarget::parse(&digest.channel)
+        .with_context(|| format!("invalid digest channel '{}'", digest.channel))?;
+    let target = DeliveryTarget::parse(&digest.delivery_target)
+        .with_context(|| format!("invalid delivery target '{}'", digest.delivery_target))?;
+
+    // Recorded before the run, so a failing digest waits for its next
+    // interval, across restarts too, instead of retrying on every tick.
+    store.record_run(&digest.id).await?;
+
+    let since = chrono::Utc::now() - chrono::Duration::hours(digest.hours as i64);
+    let history = context
+        .messaging_manager
+        .fetch_channel_history(
+            &channel.adapter,
+            &channel.target,
+            since,
+            digest.max_messages,
+        )
+        .await?;
+    let lines = transcript_lines(&history);
+    if lines.is_empty() {
+        tracing::info!(digest_id = %digest.id, "no messages in digest window, skipping");
+        return Ok(());
+    }
+
+    tracing::info!(digest_id = %digest.id, messages = lines.len(), "writing digest");
+    let text = summarize(&context.deps, digest, &lines)
+        .await
+        .context("failed to summarize channel history")?;
+    if text.trim().is_empty() {
+        tracing::debug!(digest_id = %digest.id, "digest came back empty, skipping delivery");
+        return Ok(());
+    }
+
+    context
+        .messaging_manager
+        .broadcast(
+            &target.adapter,
+            &target.target,
+            OutboundResponse::Text(text),
+        )
+        .await?;
+    tracing::info!(digest_id = %digest.id, %target, "digest delivered");
+    Ok(())
+}
+
+/// One `author: text` line per message, with long messages cut and empty
+/// ones dropped.
+fn transcript_lines(history: &[HistoryMessage]) -> Vec<String> {
+    history
+        .iter()
+        .filter_map(|message| {
+            let text = message
+                .content
+                .split_whitespace()
+                .collect::<Vec<_>>()
+                .join(" ");
+            if text.is_empty() {
+                return None;
+            }
+            let truncated = text.chars().count() > MAX_MESSAGE_CHARS;
+            let mut text: String = text.chars().take(MAX_MESSAGE_CHARS).collect();
+            if truncated {
+                text.push('…');
+            }
+            let author = if message.is_bot {
+                format!("{} (bot)", message.author)
+            } else {
+                message.author.clone()
+            };
+            Some(format!("{author}: {text}"))
+        })
+        .collect()
+}
+
+/// Pack `items` in order into chunks of at most `max_chars`, joined by
+/// `separator`. An item longer than `max_chars` is cut to fit its own chunk.
+fn chunk(items: &[String], separator: &str, max_chars: usize) -> Vec<String> {
+    let mut chunks = Vec::new();
+    let mut current = String::new();
+    for item in items {
+        let item: String = item.chars().take(max_chars).collect();
+        if !current.is_empty() && current.len() + separator.len() + item.len() > max_chars {
+            chunks.push(std::mem::take(&mut current));
+        }
+        if !current.is_empty() {
+            current.push_str(separator);
+        }
+        current.push_str(&item);
+    }
+    if !current.is_empty() {
+        chunks.push(current);
+    }
+    chunks
+}
+
+/// Write the digest of `lines`. Short histories go to the digest prompt
+/// whole; longer ones are condensed chunk by chunk first, repeatedly if the
+/// notes themselves don't fit in one call.
+async fn summarize(
+    deps: &AgentDeps,
+    digest: &DigestDef,
+    lines: &[String],
+) -> std::result::Result<String, rig::completion::PromptError> {
+    let header = format!(
+        "Channel `{}`, last {} hours, {} messages.",
+        digest.channel,
+        digest.hours,
+        lines.len()
+    );
+
+    let mut chunks = chunk(lines, "\n", MAX_CHUNK_CHARS);
+    let mut rounds = 0;
+    while chunks.len() > 1 {
+        if rounds == MAX_REDUCE_ROUNDS {
+            let notes = chunks.join("\n\n");
+            chunks = vec![notes.chars().take(MAX_CHUNK_CHARS).collect()];
+            break;
+        }
+        let mut notes = Vec::with_capacity(chunks.len());
+        for (index, part) in chunks.iter().enumerate() {
+            let input = format!(
+                "{header}\n\nPart {} of {}:\n\n{part}",
+                index + 1,
+                chunks.len()
+            );
+            let note = complete(deps, digest, "digest_chunk", input).await?;
+            notes.push(format!("Part {}:\n{}", index + 1, note.trim()));
+        }
+        tracing::debug!(digest_id = %digest.id, parts = notes.len(), "condensed digest chunks");
+        chunks = chunk(&notes, "\n\n", MAX_CHUNK_CHARS);
+        rounds += 1;
+    }
+
+    let body = chunks.pop().unwrap_or_default();
+    let input = if rounds == 0 {
+        format!("{header}\n\nTranscript:\n\n{body}")
+    } else {
+        format!("{header}\n\nNotes on consecutive parts of the transcript:\n\n{body}")
+    };
+    complete(deps, digest, "digest", input).await
+}
+
+/// The worker model digests are written with.
+fn digest_model(deps: &AgentDeps, prompt_cache: bool) -> SpacebotModel {
+    let routing = deps.runtime_config.routing.load_full();
+    let model_name = routing.resolve(ProcessType::Worker, None).to_string();
+    SpacebotModel::make(&deps.llm_manager, &model_name)
+        .with_routing((*routing).clone())
+        .with_priority(Priority::Background)
+        .with_prompt_cache(prompt_cache)
+}
+
+/// One background model call with the prompt `preamble` as its preamble.
+async fn complete(
+    deps: &AgentDeps,
+    digest: &DigestDef,
+    preamble: &str,
+    input: String,
+) -> std::result::Result<String, rig::completion::PromptError> {
+    let model = digest_model(deps, digest.prime_cache);
+    let agent = AgentBuilder::new(model)
+        .preamble(crate::prompts::text::get(preamble))
+        .build();
+
+    agent.prompt(input).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(author: &str, content: &str, is_bot: bool) -> HistoryMessage {
+        HistoryMessage {
+            author: author.into(),
+            content: content.into(),
+            is_bot,
+        }
+    }
+
+    #[test]
+    fn test_transcript_lines() {
+        let history = vec![
+            message("ana", "ship it\n  on friday", false),
+            message("ben", "   ", false),
+            message("spacebot", "noted", true),
+            message("cy", &"x".repeat(MAX_MESSAGE_CHARS + 10), false),
+        ];
+        let lines = transcript_lines(&history);
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0], "ana: ship it on friday");
+        assert_eq!(lines[1], "spacebot (bot): noted");
+        assert!(lines[2].ends_with('…'));
+        assert_eq!(
+            lines[2].chars().count(),
+            "cy: ".len() + MAX_MESSAGE_CHARS + 1
+        );
+    }
+
+    #[test]
+    fn test_due_for_priming() {
+        let digest = |id: &str, prime_cache: bool| DigestDef {
+            id: id.into(),
+            channel: "slack:C0123456789".into(),
+            delivery_target: "slack:C0123456789".into(),
+            hours: 24,
+            interval_secs: 86400,
+            active_hours: None,
+            max_messages: 1000,
+            prime_cache,
+            enabled: true,
+        };
+        let digests = [
+            digest("eng", true),
+            digest("ops", true),
+            digest("sales", false),
+            digest("later", true),
+        ];
+        let now = Instant::now();
+        let soon = now + Duration::from_secs(90);
+        let next_run = HashMap::from([
+            ("eng".to_string(), soon),
+            ("ops".to_string(), soon),
+            ("sales".to_string(), soon),
+            ("later".to_string(), now + Duration::from_secs(3600)),
+        ]);
+
+        let mut primed = HashMap::new();
+        let upcoming = due_for_priming(&digests, &next_run, &primed, 9, now);
+        let ids: Vec<&str> = upcoming.iter().map(|(id, _)| id.as_str()).collect();
+        assert_eq!(ids, ["eng", "ops"]);
+
+        // Primed once per run.
+        primed.extend(upcoming);
+        assert!(due_for_priming(&digests, &next_run, &primed, 9, now).is_empty());
+    }
+
+    #[test]
+    fn test_chunk_packs_in_order() {
+        let items: Vec<String> = ["aaaa", "bbbb", "cc", "dddddddddddd"]
+            .iter()
+            .map(|item| item.to_string())
+            .collect();
+        assert_eq!(chunk(&items, "\n", 10), ["aaaa\nbbbb", "cc", "dddddddddd"]);
+        assert!(chunk(&[], "\n", 10).is_empty());
+    }
+}
@@ -0,0 +1,486 @@
+//! Environment diagnostics for `spacebot doctor`.
+//!
+//! Each check inspects one thing that commonly breaks a deployment — file
+//! permissions, provider keys, network reachability, the system clock,
+//! model routing, listening ports — and pairs every problem with the change
+//! that fixes it.
+
+use crate::config::Config;
+use crate::llm::LlmManager;
+use crate::llm::routing::{RoutingConfig, provider_from_model};
+
+use serde::Serialize;
+
+use std::path::Path;
+use std::time::Duration;
+
+/// Clock skew above this is reported as a warning.
+const CLOCK_SKEW_WARN: Duration = Duration::from_secs(30);
+
+/// Clock skew above this breaks signed requests (AWS SigV4 allows five
+/// minutes) and is reported as a failure.
+const CLOCK_SKEW_FAIL: Duration = Duration::from_secs(300);
+
+/// Timeout for each provider probe.
+const PROBE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Endpoints that require a valid key and are cheap to call, with how the
+/// key is sent. Providers not listed are only checked for reachability.
+const KEY_PROBES: &[(&str, &str, KeyHeader)] = &[
+    (
+        "anthropic",
+        "https://api.anthropic.com/v1/models",
+        KeyHeader::Anthropic,
+    ),
+    (
+        "openai",
+        "https://api.openai.com/v1/models",
+        KeyHeader::Bearer,
+    ),
+    (
+        "openrouter",
+        "https://openrouter.ai/api/v1/key",
+        KeyHeader::Bearer,
+    ),
+    (
+        "groq",
+        "https://api.groq.com/openai/v1/models",
+        KeyHeader::Bearer,
+    ),
+    (
+        "together",
+        "https://api.together.xyz/v1/models",
+        KeyHeader::Bearer,
+    ),
+    (
+        "fireworks",
+        "https://api.fireworks.ai/inference/v1/models",
+        KeyHeader::Bearer,
+    ),
+    (
+        "deepseek",
+        "https://api.deepseek.com/models",
+        KeyHeader::Bearer,
+    ),
+    ("xai", "https://api.x.ai/v1/models", KeyHeader::Bearer),
+    (
+        "mistral",
+        "https://api.mistral.ai/v1/models",
+        KeyHeader::Bearer,
+    ),
+];
+
+#[derive(Debug, Clone, Copy)]
+enum KeyHeader {
+    Anthropic,
+    Bearer,
+}
+
+/// Outcome of a single check.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CheckStatus {
+    Ok,
+    Warn,
+    Fail,
+}
+
+/// One diagnostic result.
+#[derive(Debug, Clone, Serialize)]
+pub struct Check {
+    pub name: String,
+    pub status: CheckStatus,
+    pub detail: String,
+    /// What to do about it, for anything other than `Ok`.
+    pub fix: Option<String>,
+}
+
+impl Check {
+    fn ok(name: impl Into<String>, detail: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            status: CheckStatus::Ok,
+            detail: detail.into(),
+            fix: None,
+        }
+    }
+
+    fn warn(name: impl Into<String>, detail: impl Into<String>, fix: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            status: CheckStatus::Warn,
+            detail: detail.into(),
+            fix: Some(fix.into()),
+        }
+    }
+
+    fn fail(name: impl Into<String>, detail: impl Into<String>, fix: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            status: CheckStatus::Fail,
+            detail: detail.into(),
+            fix: Some(fix.into()),
+        }
+    }
+}
+
+/// Run every check against a loaded config.
+pub async fn run_checks(config: &Config) -> Vec<Check> {
+    let mut checks = check_instance_dir(&config.instance_dir);
+    checks.extend(check_routing(config));
+
+    match LlmManager::new(config.llm.clone()).await {
+        Ok(manager) => {
+            let (provider_checks, skews) = check_providers(&manager).await;
+            checks.extend(provider_checks);
+            checks.push(check_clock_skew(&skews));
+        }
+        Err(error) => checks.push(Check::fail(
+            "credentials",
+            format!("can't initialize the LLM manager: {error}"),
+            "fix the [llm] section of config.toml",
+        )),
+    }
+
+    checks.extend(check_ports(config).await);
+    checks
+}
+
+fn check_instance_dir(instance_dir: &Path) -> Vec<Check> {
+    let name = "instance directory";
+    let metadata = match std::fs::metadata(instance_dir) {
+        Ok(metadata) if metadata.is_dir() => metadata,
+        Ok(_) => {
+            return vec![Check::fail(
+                name,
+                format!("{} is not a directory", instance_dir.display()),
+                "move the file out of the way or set SPACEBOT_DIR to another path",
+            )];
+        }
+        Err(error) => {
+            return vec![Check::fail(
+                name,
+                format!("can't read {}: {error}", instance_dir.display()),
+                format!("mkdir -p {}", instance_dir.display()),
+            )];
+        }
+    };
+
+    let probe = instance_dir.join(".doctor-write-test");
+    let writable = std::fs::write(&probe, b"").is_ok();
+    std::fs::remove_file(&probe).ok();
+
+    let mut checks = vec![if metadata.permissions().readonly() || !writable {
+        Check::fail(
+            name,
+            format!("{} is not writable", instance_dir.display()),
+            format!(
+                "chown -R $(whoami) {0} && chmod u+rwx {0}",
+                instance_dir.display()
+            ),
+        )
+    } else {
+        Check::ok(name, format!("{} is writable", instance_dir.display()))
+    }];
+
+    checks.extend(check_config_file_mode(&instance_dir.join("config.toml")));
+    checks
+}
+
+/// config.toml may hold API keys, so it shouldn't be readable by others.
+fn check_config_file_mode(path: &Path) -> Option<Check> {
+    let name = "config file permissions";
+    let fix = if cfg!(windows) {
+        format!(
+            "icacls \"{}\" /inheritance:r /grant:r \"%USERNAME%:F\"",
+            path.display()
+        )
+    } else {
+        format!("chmod 600 {}", path.display())
+    };
+    match crate::permissions::accessible_by_others(path) {
+        Ok(true) => Some(Check::warn(
+            name,
+            format!("{} is accessible by other users", path.display()),
+            fix,
+        )),
+        Ok(false) => Some(Check::ok(name, "only the owner can read it")),
+        // A missing file is reported by config loading, not here.
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => None,
+        Err(error) => Some(Check::warn(
+            name,
+            format!("can't read permissions of {}: {error}", path.display()),
+            fix,
+        )),
+    }
+}
+
+/// Every model referenced by routing must belong to a provider with a key.
+fn check_routing(config: &Config) -> Vec<Check> {
+    let mut missing = Vec::new();
+    collect_unconfigured_models(&config.defaults.routing, config, "defaults", &mut missing);
+    for agent in config.resolve_agents() {
+        collect_unconfigured_models(
+            &agent.routing,
+            config,
+            &format!("agent {}", agent.id),
+            &mut missing,
+        );
+    }
+    missing.sort();
+    missing.dedup();
+
+    if missing.is_empty() {
+        return vec![Check::ok(
+            "model routing",
+            "every routed model has a configured provider",
+        )];
+    }
+    missing
+        .into_iter()
+        .map(|(provider, model, scope)| {
+            let section = if scope == "defaults" {
+                "[defaults.routing]"
+            } else {
+                "the agent's [agents.routing]"
+            };
+            Check::fail(
+                "model routing",
+                format!("{scope} routes to {model}, but {provider} has no API key"),
+                format!("add a {provider} key under [llm] or change the model in {section}"),
+            )
+        })
+        .collect()
+}
+
+fn collect_unconfigured_models(
+    routing: &RoutingConfig,
+    config: &Config,
+    scope: &str,
+    missing: &mut Vec<(String, String, String)>,
+) {
+    let models = [
+        &routing.channel,
+        &routing.branch,
+        &routing.worker,
+        &routing.compactor,
+        &routing.cortex,
+    ]
+    .into_iter()
+    .chain(routing.task_overrides.values())
+    .chain(
+        routing
+            .fallbacks
+            .iter()
+            .flat_map(|(model, chain)| std::iter::once(model).chain(chain.iter())),
+    );
+
+    for model in models {
+        let provider = provider_from_model(model);
+        if config.llm.key(provider).is_none() {
+            missing.push((provider.to_string(), model.clone(), scope.to_string()));
+        }
+    }
+}
+
+/// Resolve each provider's key, then call it to confirm it's reachable and
+/// the key is accepted. Also returns the clock offset reported by each
+/// provider's `Date` header.
+async fn check_providers(manager: &LlmManager) -> (Vec<Check>, Vec<i64>) {
+    let providers = manager.configured_providers();
+    if providers.is_empty() {
+        return (
+            vec![Check::fail(
+                "providers",
+                "no provider keys configured",
+                "set at least one key under [llm], e.g. anthropic_key = \"env:ANTHROPIC_API_KEY\"",
+            )],
+            Vec::new(),
+        );
+    }
+
+    let probes = providers
+        .into_iter()
+        .map(|provider| check_provider(manager, provider));
+    let results = futures::future::join_all(probes).await;
+
+    let mut checks = Vec::new();
+    let mut skews = Vec::new();
+    for (check, skew) in results {
+        checks.push(check);
+        skews.extend(skew);
+    }
+    (checks, skews)
+}
+
+async fn check_provider(manager: &LlmManager, provider: &'static str) -> (Check, Option<i64>) {
+    let name = format!("provider {provider}");
+    let source = manager.credentials().describe(provider).unwrap_or_default();
+
+    let key = match manager.get_api_key(provider).await {
+        Ok(key) => key,
+        Err(error) => {
+            return (
+                Check::fail(
+                    name,
+                    format!("can't read key from {source}: {error}"),
+                    "check the credential reference and that the secret still exists",
+                ),
+                None,
+            );
+        }
+    };
+
+    let probe = KEY_PROBES.iter().find(|(id, ..)| *id == provider);
+    let request = match probe {
+        Some((_, url, KeyHeader::Anthropic)) => manager
+            .http_client()
+            .get(*url)
+            .header("x-api-key", &key)
+            .header("anthropic-version", "2023-06-01"),
+        Some((_, url, KeyHeader::Bearer)) => manager.http_client().get(*url).bearer_auth(&key),
+        None => {
+            let origin = crate::llm::providers::provider_origin(provider).unwrap_or_default();
+            manager.http_client().head(origin)
+        }
+    };
+
+    let response = match manager.send(provider, request.timeout(PROBE_TIMEOUT)).await {
+        Ok(response) => response,
+        Err(error) => {
+            return (
+                Check::fail(
+                    name,
+                    format!("unreachable: {error}"),
+                    "check DNS, firewall and HTTPS_PROXY settings for outbound HTTPS",
+                ),
+                None,
+            );
+        }
+    };
+
+    let skew = response
+        .headers()
+        .get(reqwest::header::DATE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| chrono::DateTime::parse_from_rfc2822(value).ok())
+        .map(|server_time| (chrono::Utc::now() - server_time.to_utc()).num_seconds());
+
+    let status = response.status();
+    let check = if probe.is_none() {
+        Check::ok(name, format!("reachable; key from {source} not verified"))
+    } else if status.is_success() {
+        Check::ok(name, format!("key from {source} accepted"))
+    } else if status == reqwest::StatusCode::UNAUTHORIZED
+        || status == reqwest::StatusCode::FORBIDDEN
+    {
+        Check::fail(
+            name,
+            format!("key from {source} rejected ({status}); it may be expired or revoked"),
+            format!("issue a new {provider} key and update the reference in config.toml"),
+        )
+    } else {
+        Check::warn(
+            name,
+            format!("reachable, but the key check returned {status}"),
+            "retry later; the provider may be degraded",
+        )
+    };
+    (check, skew)
+}
+
+/// Judge clock skew from the largest offset any provider reported.
+fn check_clock_skew(skews: &[i64]) -> Check {
+    let name = "clock skew";
+    let Some(skew) = skews.iter().copied().max_by_key(|skew| skew.unsigned_abs()) else {
+        return Check::warn(
+            name,
+            "no provider responded, so skew couldn't be measured",
+            "fix provider reachability and run doctor again",
+        );
+    };
+
+    let magnitude = Duration::from_secs(skew.unsigned_abs());
+    let detail = format!("local clock is {skew:+}s off provider time");
+    let fix = "enable time sync, e.g. `timedatectl set-ntp true`";
+    if magnitude > CLOCK_SKEW_FAIL {
+        Check::fail(name, detail, fix)
+    } else if magnitude > CLOCK_SKEW_WARN {
+        Check::warn(name, detail, fix)
+    } else {
+        Check::ok(name, detail)
+    }
+}
+
+/// Ports spacebot will listen on must be free, unless spacebot itself holds
+/// them.
+async fn check_ports(config: &Config) -> Vec<Check> {
+    let daemon_pid =
+        crate::daemon::is_running(&crate::daemon::DaemonPaths::new(&config.instance_dir));
+
+    let mut listeners = Vec::new();
+    if config.api.enabled {
+        listeners.push(("api", "[api]", &config.api.bind, config.api.port));
+    }
+    if let Some(webhook) = config.messaging.webhook.as_ref().filter(|w| w.enabled) {
+        listeners.push((
+            "webhook",
+            "[messaging.webhook]",
+            &webhook.bind,
+            webhook.port,
+        ));
+    }
+
+    let mut checks = Vec::new();
+    for (label, section, bind, port) in listeners {
+        let name = format!("{label} port");
+        let address = format!("{bind}:{port}");
+        let check = match (tokio::net::TcpListener::bind(&address).await, daemon_pid) {
+            (Ok(_), _) => Check::ok(name, format!("{address} is free")),
+            (Err(_), Some(pid)) => Check::ok(
+                name,
+                format!("{address} is held by the running daemon (pid {pid})"),
+            ),
+            (Err(error), None) if error.kind() == std::io::ErrorKind::AddrInUse => Check::fail(
+                name,
+                format!("{address} is already in use"),
+                format!(
+                    "stop the process using it (`lsof -i :{port}`) or change `port` in {section}"
+                ),
+            ),
+            (Err(error), None) => Check::fail(
+                name,
+                format!("can't bind {address}: {error}"),
+                format!("check `bind` in {section}; ports below 1024 need extra privileges"),
+            ),
+        };
+        checks.push(check);
+    }
+    checks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clock_skew_thresholds() {
+        assert_eq!(check_clock_skew(&[2, -5]).status, CheckStatus::Ok);
+        assert_eq!(check_clock_skew(&[2, -90]).status, CheckStatus::Warn);
+        assert_eq!(check_clock_skew(&[400]).status, CheckStatus::Fail);
+        assert_eq!(check_clock_skew(&[]).status, CheckStatus::Warn);
+    }
+
+    #[test]
+    fn test_instance_dir_missing_fails() {
+        let path = std::env::temp_dir().join(format!("spacebot-doctor-{}", uuid::Uuid::new_v4()));
+        let checks = check_instance_dir(&path);
+        assert_eq!(checks[0].status, CheckStatus::Fail);
+        assert!(
+            checks[0]
+                .fix
+                .as_deref()
+                .is_some_and(|fix| fix.contains("mkdir"))
+        );
+    }
+}
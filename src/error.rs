@@ -1,5 +1,7 @@
 //! Top-level error types for Spacebot.
 
+pub use spacebot_core::error::{LlmError, ProviderApiError};
+
 use std::sync::Arc;
 
 /// Crate-wide result type alias.
@@ -83,31 +85,6 @@ pub enum DbError {
     Other(#[from] anyhow::Error),
 }
 
-/// LLM provider and model errors.
-#[derive(Debug, thiserror::Error)]
-pub enum LlmError {
-    #[error("unknown provider: {0}")]
-    UnknownProvider(String),
-
-    #[error("unknown model: {0}")]
-    UnknownModel(String),
-
-    #[error("provider request failed: {0}")]
-    ProviderRequest(String),
-
-    #[error("missing API key for provider: {0}")]
-    MissingProviderKey(String),
-
-    #[error("embedding generation failed: {0}")]
-    EmbeddingFailed(String),
-
-    #[error("completion failed: {0}")]
-    CompletionFailed(String),
-
-    #[error(transparent)]
-    Other(#[from] anyhow::Error),
-}
-
 /// Memory storage and retrieval errors.
 #[derive(Debug, thiserror::Error)]
 pub enum MemoryError {
@@ -0,0 +1,468 @@
+//! Feedback ledger: 👍/👎 reactions and `/feedback` commands on bot replies.
+//!
+//! Adapters that can see reactions on the bot's own messages tag an inbound
+//! message with [`reaction_metadata`]; everything else goes through the
+//! `/feedback up|down [comment]` command. The router turns either into a
+//! [`FeedbackSignal`] and records it instead of handing the message to a
+//! channel. Each row carries the request id, model and usage of the reply it
+//! rates, copied from the reply's attribution, so the ledger can be exported
+//! for fine-tuning and compared across models without further joins.
+
+use crate::conversation::ReplyAttribution;
+use crate::error::Result;
+use crate::{InboundMessage, MessageContent};
+
+use anyhow::Context as _;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::{Row as _, SqlitePool};
+
+use std::collections::HashMap;
+
+/// Metadata key carrying a reaction's rating (`"up"` or `"down"`).
+pub const FEEDBACK_RATING: &str = "feedback_rating";
+
+/// Metadata key carrying the platform ID of the reacted-to message.
+pub const FEEDBACK_TARGET_ID: &str = "feedback_target_message_id";
+
+/// Metadata key carrying when the reacted-to message was sent (RFC 3339).
+pub const FEEDBACK_TARGET_SENT_AT: &str = "feedback_target_sent_at";
+
+const COMMAND: &str = "/feedback";
+
+/// Platform and local clocks drift; a reply logged a moment after the
+/// platform stamped it should still match.
+const CLOCK_SKEW_SECS: i64 = 5;
+
+/// Thumbs up or thumbs down.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Rating {
+    Up,
+    Down,
+}
+
+impl Rating {
+    /// Map a reaction to a rating. Accepts unicode emoji with any skin tone
+    /// and Slack-style shortcodes.
+    pub fn from_emoji(emoji: &str) -> Option<Self> {
+        let base: String = emoji
+            .trim_matches(':')
+            .chars()
+            .filter(|c| !matches!(c, '\u{1F3FB}'..='\u{1F3FF}' | '\u{FE0F}'))
+            .collect();
+        match base.as_str() {
+            "👍" | "+1" | "thumbsup" => Some(Self::Up),
+            "👎" | "-1" | "thumbsdown" => Some(Self::Down),
+            _ => None,
+        }
+    }
+
+    fn from_word(word: &str) -> Option<Self> {
+        match word.to_ascii_lowercase().as_str() {
+            "up" | "good" | "yes" => Some(Self::Up),
+            "down" | "bad" | "no" => Some(Self::Down),
+            other => Self::from_emoji(other),
+        }
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Up => "up",
+            Self::Down => "down",
+        }
+    }
+
+    fn score(self) -> i64 {
+        match self {
+            Self::Up => 1,
+            Self::Down => -1,
+        }
+    }
+}
+
+impl std::str::FromStr for Rating {
+    type Err = String;
+
+    fn from_str(value: &str) -> std::result::Result<Self, Self::Err> {
+        Self::from_word(value)
+            .ok_or_else(|| format!("unknown rating '{value}', expected up or down"))
+    }
+}
+
+/// How the feedback was given.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FeedbackSource {
+    Reaction,
+    Command,
+}
+
+impl FeedbackSource {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Reaction => "reaction",
+            Self::Command => "command",
+        }
+    }
+}
+
+/// Feedback extracted from an inbound message.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FeedbackSignal {
+    pub rating: Rating,
+    pub source: FeedbackSource,
+    pub comment: Option<String>,
+    /// Platform ID of the rated message, for reactions.
+    pub target_message_id: Option<String>,
+    /// When the rated message was sent, if the platform reports it.
+    pub target_sent_at: Option<DateTime<Utc>>,
+}
+
+impl FeedbackSignal {
+    /// Read feedback from a tagged reaction or a `/feedback` command. Returns
+    /// `None` for ordinary messages, which should be routed as usual.
+    pub fn from_message(message: &InboundMessage) -> Option<Self> {
+        if let Some(rating) = message
+            .metadata
+            .get(FEEDBACK_RATING)
+            .and_then(|value| value.as_str())
+        {
+            return Some(Self {
+                rating: Rating::from_word(rating)?,
+                source: FeedbackSource::Reaction,
+                comment: None,
+                target_message_id: message
+                    .metadata
+                    .get(FEEDBACK_TARGET_ID)
+                    .and_then(|value| value.as_str())
+                    .map(String::from),
+                target_sent_at: message
+                    .metadata
+                    .get(FEEDBACK_TARGET_SENT_AT)
+                    .and_then(|value| value.as_str())
+                    .and_then(|value| DateTime::parse_from_rfc3339(value).ok())
+                    .map(|value| value.with_timezone(&Utc)),
+            });
+        }
+
+        let MessageContent::Text(text) = &message.content else {
+            return None;
+        };
+        let rest = text.trim().strip_prefix(COMMAND)?;
+        if !rest.is_empty() && !rest.starts_with(char::is_whitespace) {
+            return None;
+        }
+        let mut words = rest.trim_start().splitn(2, char::is_whitespace);
+        let rating = Rating::from_word(words.next()?)?;
+        let comment = words
+            .next()
+            .map(str::trim)
+            .filter(|comment| !comment.is_empty())
+            .map(String::from);
+
+        Some(Self {
+            rating,
+            source: FeedbackSource::Command,
+            comment,
+            target_message_id: None,
+            target_sent_at: None,
+        })
+    }
+}
+
+/// Metadata an adapter attaches to an inbound message that represents a
+/// reaction on one of the bot's messages.
+pub fn reaction_metadata(
+    rating: Rating,
+    target_message_id: impl Into<String>,
+    target_sent_at: Option<DateTime<Utc>>,
+) -> HashMap<String, serde_json::Value> {
+    let mut metadata = HashMap::new();
+    metadata.insert(FEEDBACK_RATING.into(), rating.as_str().into());
+    metadata.insert(FEEDBACK_TARGET_ID.into(), target_message_id.into().into());
+    if let Some(sent_at) = target_sent_at {
+        metadata.insert(FEEDBACK_TARGET_SENT_AT.into(), sent_at.to_rfc3339().into());
+    }
+    metadata
+}
+
+/// A recorded piece of feedback, with the reply it rates.
+#[derive(Debug, Clone, Serialize)]
+pub struct FeedbackEntry {
+    pub id: String,
+    pub channel_id: String,
+    pub message_id: Option<String>,
+    pub platform_message_id: Option<String>,
+    pub rating: Rating,
+    pub source: String,
+    pub user_id: String,
+    pub comment: Option<String>,
+    pub request_id: Option<String>,
+    pub model: Option<String>,
+    pub input_tokens: Option<i64>,
+    pub output_tokens: Option<i64>,
+    /// Text of the rated reply, when it could be identified.
+    pub reply: Option<String>,
+    pub created_at: String,
+}
+
+/// Per-model feedback totals for comparison reports.
+#[derive(Debug, Clone, Serialize)]
+pub struct ModelFeedback {
+    pub model: Option<String>,
+    pub total: i64,
+    pub up: i64,
+    pub down: i64,
+    /// Share of ratings that were positive, 0.0 to 1.0.
+    pub approval_rate: f64,
+    pub avg_input_tokens: Option<f64>,
+    pub avg_output_tokens: Option<f64>,
+}
+
+/// Feedback persistence (SQLite).
+#[derive(Debug, Clone)]
+pub struct FeedbackStore {
+    pool: SqlitePool,
+}
+
+impl FeedbackStore {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+
+    /// Record feedback from `user_id` in a conversation.
+    ///
+    /// The rated reply is the latest assistant message in the conversation
+    /// (or one of its threads) sent no later than the reacted-to message, or
+    /// simply the latest reply for commands. A user reacting again to the
+    /// same message replaces their earlier rating.
+    pub async fn record(
+        &self,
+        channel_id: &str,
+        user_id: &str,
+        signal: &FeedbackSignal,
+    ) -> Result<()> {
+        let sent_before = signal.target_sent_at.map(|sent_at| {
+            (sent_at + chrono::Duration::seconds(CLOCK_SKEW_SECS))
+                .format("%Y-%m-%d %H:%M:%S")
+                .to_string()
+        });
+
+        let reply = sqlx::query(
+            "SELECT id, metadata FROM conversation_messages \
+             WHERE (channel_id = ? OR channel_id LIKE ? || ':%') AND role = 'assistant' \
+             AND (? IS NULL OR created_at <= ?) \
+             ORDER BY created_at DESC LIMIT 1",
+        )
+        .bind(channel_id)
+        .bind(channel_id)
+        .bind(&sent_before)
+        .bind(&sent_before)
+        .fetch_optional(&self.pool)
+        .await
+        .context("failed to find rated reply")?;
+
+        let message_id: Option<String> = reply.as_ref().and_then(|row| row.try_get("id").ok());
+        let attribution = reply
+            .and_then(|row| row.try_get::<Option<String>, _>("metadata").ok().flatten())
+            .and_then(|metadata| serde_json::from_str::<serde_json::Value>(&metadata).ok())
+            .and_then(|mut metadata| metadata.get_mut("attribution").map(serde_json::Value::take))
+            .and_then(|attribution| serde_json::from_value::<ReplyAttribution>(attribution).ok());
+
+        sqlx::query(
+            r#"
+            INSERT INTO feedback (id, channel_id, message_id, platform_message_id, rating, source,
+                                  user_id, comment, request_id, model, input_tokens, output_tokens)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+            ON CONFLICT(channel_id, user_id, platform_message_id) DO UPDATE SET
+                rating = excluded.rating,
+                created_at = CURRENT_TIMESTAMP
+            "#,
+        )
+        .bind(uuid::Uuid::new_v4().to_string())
+        .bind(channel_id)
+        .bind(&message_id)
+        .bind(&signal.target_message_id)
+        .bind(signal.rating.score())
+        .bind(signal.source.as_str())
+        .bind(user_id)
+        .bind(&signal.comment)
+        .bind(attribution.as_ref().and_then(|a| a.request_id.clone()))
+        .bind(attribution.as_ref().and_then(|a| a.model.clone()))
+        .bind(attribution.as_ref().map(|a| a.input_tokens as i64))
+        .bind(attribution.as_ref().map(|a| a.output_tokens as i64))
+        .execute(&self.pool)
+        .await
+        .context("failed to record feedback")?;
+
+        Ok(())
+    }
+
+    /// Load feedback with the rated reply's text, newest first.
+    pub async fn load(&self, limit: i64, offset: i64) -> Result<Vec<FeedbackEntry>> {
+        let rows = sqlx::query(
+            r#"
+            SELECT f.id, f.channel_id, f.message_id, f.platform_message_id, f.rating, f.source,
+                   f.user_id, f.comment, f.request_id, f.model, f.input_tokens, f.output_tokens,
+                   m.content AS reply, f.created_at
+            FROM feedback f
+            LEFT JOIN conversation_messages m ON m.id = f.message_id
+            ORDER BY f.created_at DESC
+            LIMIT ? OFFSET ?
+            "#,
+        )
+        .bind(limit)
+        .bind(offset)
+        .fetch_all(&self.pool)
+        .await
+        .context("failed to load feedback")?;
+
+        let entries = rows
+            .into_iter()
+            .map(|row| FeedbackEntry {
+                id: row.try_get("id").unwrap_or_default(),
+                channel_id: row.try_get("channel_id").unwrap_or_default(),
+                message_id: row.try_get("message_id").ok().flatten(),
+                platform_message_id: row.try_get("platform_message_id").ok().flatten(),
+                rating: if row.try_get::<i64, _>("rating").unwrap_or_default() > 0 {
+                    Rating::Up
+                } else {
+                    Rating::Down
+                },
+                source: row.try_get("source").unwrap_or_default(),
+                user_id: row.try_get("user_id").unwrap_or_default(),
+                comment: row.try_get("comment").ok().flatten(),
+                request_id: row.try_get("request_id").ok().flatten(),
+                model: row.try_get("model").ok().flatten(),
+                input_tokens: row.try_get("input_tokens").ok().flatten(),
+                output_tokens: row.try_get("output_tokens").ok().flatten(),
+                reply: row.try_get("reply").ok().flatten(),
+                created_at: row
+                    .try_get::<chrono::NaiveDateTime, _>("created_at")
+                    .map(|timestamp| timestamp.and_utc().to_rfc3339())
+                    .unwrap_or_default(),
+            })
+            .collect();
+
+        Ok(entries)
+    }
+
+    /// Count recorded feedback.
+    pub async fn count(&self) -> Result<i64> {
+        let count: (i64,) = sqlx::query_as("SELECT COUNT(*) FROM feedback")
+            .fetch_one(&self.pool)
+            .await
+            .context("failed to count feedback")?;
+        Ok(count.0)
+    }
+
+    /// Ratings and average usage per model, most-rated first.
+    pub async fn model_summary(&self) -> Result<Vec<ModelFeedback>> {
+        let rows = sqlx::query(
+            r#"
+            SELECT model,
+                   COUNT(*) AS total,
+                   SUM(CASE WHEN rating > 0 THEN 1 ELSE 0 END) AS up,
+                   SUM(CASE WHEN rating < 0 THEN 1 ELSE 0 END) AS down,
+                   AVG(input_tokens) AS avg_input_tokens,
+                   AVG(output_tokens) AS avg_output_tokens
+            FROM feedback
+            GROUP BY model
+            ORDER BY total DESC
+            "#,
+        )
+        .fetch_all(&self.pool)
+        .await
+        .context("failed to summarize feedback")?;
+
+        let summary = rows
+            .into_iter()
+            .map(|row| {
+                let total: i64 = row.try_get("total").unwrap_or_default();
+                let up: i64 = row.try_get("up").unwrap_or_default();
+                ModelFeedback {
+                    model: row.try_get("model").ok().flatten(),
+                    total,
+                    up,
+                    down: row.try_get("down").unwrap_or_default(),
+                    approval_rate: if total > 0 {
+                        up as f64 / total as f64
+                    } else {
+                        0.0
+                    },
+                    avg_input_tokens: row.try_get("avg_input_tokens").ok().flatten(),
+                    avg_output_tokens: row.try_get("avg_output_tokens").ok().flatten(),
+                }
+            })
+            .collect();
+
+        Ok(summary)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(text: &str, metadata: HashMap<String, serde_json::Value>) -> InboundMessage {
+        InboundMessage {
+            id: "1".into(),
+            source: "discord".into(),
+            conversation_id: "discord:1:2".into(),
+            sender_id: "3".into(),
+            agent_id: None,
+            content: MessageContent::Text(text.into()),
+            timestamp: Utc::now(),
+            metadata,
+        }
+    }
+
+    #[test]
+    fn test_rating_from_emoji_ignores_skin_tone() {
+        assert_eq!(Rating::from_emoji("👍🏽"), Some(Rating::Up));
+        assert_eq!(Rating::from_emoji(":-1:"), Some(Rating::Down));
+        assert_eq!(Rating::from_emoji("🎉"), None);
+    }
+
+    #[test]
+    fn test_feedback_command() {
+        let signal = FeedbackSignal::from_message(&message(
+            "/feedback down  missed the second question",
+            HashMap::new(),
+        ))
+        .expect("command parses");
+        assert_eq!(signal.rating, Rating::Down);
+        assert_eq!(signal.source, FeedbackSource::Command);
+        assert_eq!(
+            signal.comment.as_deref(),
+            Some("missed the second question")
+        );
+
+        for text in [
+            "/feedback",
+            "/feedbackup",
+            "/feedback thanks",
+            "hi /feedback up",
+        ] {
+            assert_eq!(
+                FeedbackSignal::from_message(&message(text, HashMap::new())),
+                None,
+                "{text}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_reaction_metadata_round_trips() {
+        let sent_at = DateTime::parse_from_rfc3339("2026-02-17T10:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let metadata = reaction_metadata(Rating::Up, "42", Some(sent_at));
+        let signal =
+            FeedbackSignal::from_message(&message("👍", metadata)).expect("reaction parses");
+
+        assert_eq!(signal.source, FeedbackSource::Reaction);
+        assert_eq!(signal.target_message_id.as_deref(), Some("42"));
+        assert_eq!(signal.target_sent_at, Some(sent_at));
+    }
+}
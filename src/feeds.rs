@@ -0,0 +1,8 @@
+//! Feed watchers: poll RSS, Atom and JSON feeds and hand new entries to an
+//! agent as a digest.
+
+pub mod store;
+pub mod watcher;
+
+pub use store::FeedStore;
+pub use watcher::{FeedItem, spawn_feed_watcher};
@@ -0,0 +1,55 @@
+//! Which feed entries have already been picked up (SQLite).
+
+use crate::error::Result;
+use anyhow::Context as _;
+use sqlx::{Row as _, SqlitePool};
+use std::collections::HashSet;
+
+/// Seen-entry store for feed watchers.
+#[derive(Debug)]
+pub struct FeedStore {
+    pool: SqlitePool,
+}
+
+impl FeedStore {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+
+    /// IDs of every entry already picked up for `feed_id`.
+    pub async fn seen(&self, feed_id: &str) -> Result<HashSet<String>> {
+        let rows = sqlx::query("SELECT item_id FROM feed_items WHERE feed_id = ?")
+            .bind(feed_id)
+            .fetch_all(&self.pool)
+            .await
+            .context("failed to load seen feed items")?;
+
+        Ok(rows
+            .into_iter()
+            .filter_map(|row| row.try_get("item_id").ok())
+            .collect())
+    }
+
+    /// Record entries as picked up for `feed_id`.
+    pub async fn mark_seen(&self, feed_id: &str, item_ids: &[String]) -> Result<()> {
+        let mut transaction = self
+            .pool
+            .begin()
+            .await
+            .context("failed to start transaction")?;
+        for item_id in item_ids {
+            sqlx::query("INSERT OR IGNORE INTO feed_items (feed_id, item_id) VALUES (?, ?)")
+                .bind(feed_id)
+                .bind(item_id)
+                .execute(&mut *transaction)
+                .await
+                .context("failed to mark feed item seen")?;
+        }
+        transaction
+            .commit()
+            .await
+            .context("failed to commit seen feed items")?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,406 @@
+//! Feed polling and digest delivery.
+//!
+//! One watcher runs per agent. Each enabled feed in `runtime_config.feeds`
+//! is polled on its interval; entries not seen before are batched, optionally
+//! summarized, and run through a fresh channel the way a cron job runs, with
+//! the reply going to the feed's delivery target. The first poll of a feed
+//! only records its current entries, so adding a feed doesn't replay its
+//! whole history.
+
+use crate::config::FeedDef;
+use crate::cron::CronContext;
+use crate::cron::scheduler::{DeliveryTarget, run_prompt};
+use crate::error::Result;
+use crate::feeds::FeedStore;
+use crate::llm::{Priority, SpacebotModel};
+use crate::{AgentDeps, OutboundResponse, ProcessType};
+
+use anyhow::Context as _;
+use chrono::{DateTime, Utc};
+use regex::Regex;
+use rig::agent::AgentBuilder;
+use rig::completion::Prompt as _;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, LazyLock};
+use std::time::{Duration, Instant};
+
+/// How often the watcher checks which feeds are due.
+const TICK: Duration = Duration::from_secs(30);
+
+/// Feeds are never polled more often than this, whatever their interval.
+const MIN_INTERVAL_SECS: u64 = 60;
+
+const FETCH_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Characters of entry text the agent sees per entry.
+const MAX_ITEM_CHARS: usize = 500;
+
+/// Characters of entry text the summarizer sees per entry.
+const MAX_SUMMARY_INPUT_CHARS: usize = 4_000;
+
+static HTML_TAG: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"<[^>]*>").expect("valid regex"));
+
+/// One feed entry, flattened from RSS, Atom or JSON Feed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FeedItem {
+    pub id: String,
+    pub title: String,
+    pub link: Option<String>,
+    pub published: Option<DateTime<Utc>>,
+    /// Summary or content with HTML stripped.
+    pub text: String,
+    /// Title of the feed the entry came from.
+    pub source: String,
+}
+
+/// Spawn the feed watcher for an agent.
+///
+/// Reads `deps.runtime_config.feeds` on every tick, so feeds added or changed
+/// on config reload are picked up without a restart. Nothing is polled during
+/// maintenance; entries that arrive meanwhile are picked up afterwards.
+pub fn spawn_feed_watcher(
+    context: CronContext,
+    store: Arc<FeedStore>,
+) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let http = reqwest::Client::builder()
+            .timeout(FETCH_TIMEOUT)
+            .user_agent(concat!("spacebot/", env!("CARGO_PKG_VERSION")))
+            .build()
+            .expect("hardcoded reqwest client config");
+        let mut next_poll: HashMap<String, Instant> = HashMap::new();
+
+        loop {
+            let feeds = context.deps.runtime_config.feeds.load_full();
+            next_poll.retain(|id, _| feeds.iter().any(|feed| &feed.id == id));
+
+            if !context.deps.maintenance.is_enabled() {
+                for feed in feeds.iter().filter(|feed| feed.enabled) {
+                    if next_poll
+                        .get(&feed.id)
+                        .is_some_and(|due| Instant::now() < *due)
+                    {
+                        continue;
+                    }
+                    let interval = Duration::from_secs(feed.interval_secs.max(MIN_INTERVAL_SECS));
+                    next_poll.insert(feed.id.clone(), Instant::now() + interval);
+
+                    if let Err(error) = poll(&context, &store, &http, feed).await {
+                        tracing::warn!(feed_id = %feed.id, %error, "feed poll failed");
+                    }
+                }
+            }
+
+            tokio::time::sleep(TICK).await;
+        }
+    })
+}
+
+/// Fetch a feed's URLs and deliver a digest of the entries not seen before.
+async fn poll(
+    context: &CronContext,
+    store: &FeedStore,
+    http: &reqwest::Client,
+    feed: &FeedDef,
+) -> Result<()> {
+    let target = DeliveryTarget::parse(&feed.delivery_target)
+        .with_context(|| format!("invalid delivery target '{}'", feed.delivery_target))?;
+
+    let mut items = Vec::new();
+    for url in &feed.urls {
+        match fetch(http, url).await {
+            Ok(fetched) => items.extend(fetched),
+            Err(error) => tracing::warn!(feed_id = %feed.id, %url, %error, "failed to fetch feed"),
+        }
+    }
+
+    let seen = store.seen(&feed.id).await?;
+    let (batch, new_ids) = select_new(items, &seen, feed.max_items);
+    if new_ids.is_empty() {
+        return Ok(());
+    }
+    // Entries are marked before the agent runs, so a failed run drops the
+    // batch instead of retrying it on every poll.
+    store.mark_seen(&feed.id, &new_ids).await?;
+    if seen.is_empty() {
+        tracing::info!(
+            feed_id = %feed.id,
+            entries = new_ids.len(),
+            "first poll of feed, existing entries marked as seen"
+        );
+        return Ok(());
+    }
+
+    tracing::info!(feed_id = %feed.id, entries = batch.len(), "new feed entries");
+    deliver(context, feed, &target, &batch).await
+}
+
+async fn fetch(http: &reqwest::Client, url: &str) -> Result<Vec<FeedItem>> {
+    let bytes = http
+        .get(url)
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .context("request failed")?
+        .bytes()
+        .await
+        .context("failed to read response")?;
+    let feed = feed_rs::parser::parse(bytes.as_ref()).context("not an RSS, Atom or JSON feed")?;
+    Ok(items_from_feed(feed))
+}
+
+fn items_from_feed(feed: feed_rs::model::Feed) -> Vec<FeedItem> {
+    let source = feed
+        .title
+        .map(|title| strip_html(&title.content))
+        .unwrap_or_default();
+    feed.entries
+        .into_iter()
+        .map(|entry| {
+            let text = entry
+                .summary
+                .map(|summary| summary.content)
+                .or_else(|| entry.content.and_then(|content| content.body))
+                .map(|html| strip_html(&html))
+                .unwrap_or_default();
+            FeedItem {
+                id: entry.id,
+                title: entry
+                    .title
+                    .map(|title| strip_html(&title.content))
+                    .unwrap_or_else(|| "(untitled)".into()),
+                link: entry.links.into_iter().next().map(|link| link.href),
+                published: entry.published.or(entry.updated),
+                text,
+                source: source.clone(),
+            }
+        })
+        .collect()
+}
+
+/// Pick the entries of `items` not in `seen`, dropping repeats of the same
+/// ID or link. Returns at most `max_items` of the newest, oldest first, and
+/// the IDs of every new entry, including repeats and the ones cut from the
+/// batch, so none of them come back on the next poll.
+fn select_new(
+    items: Vec<FeedItem>,
+    seen: &HashSet<String>,
+    max_items: usize,
+) -> (Vec<FeedItem>, Vec<String>) {
+    let mut new_ids = Vec::new();
+    let mut ids = HashSet::new();
+    let mut links = HashSet::new();
+    let mut batch = Vec::new();
+    for item in items {
+        if seen.contains(&item.id) || !ids.insert(item.id.clone()) {
+            continue;
+        }
+        new_ids.push(item.id.clone());
+        if item
+            .link
+            .as_ref()
+            .is_some_and(|link| !links.insert(link.clone()))
+        {
+            continue;
+        }
+        batch.push(item);
+    }
+
+    // Newest first to apply the cap; undated entries sort last.
+    batch.sort_by_key(|item| std::cmp::Reverse(item.published));
+    batch.truncate(max_items);
+    batch.reverse();
+    (batch, new_ids)
+}
+
+async fn deliver(
+    context: &CronContext,
+    feed: &FeedDef,
+    target: &DeliveryTarget,
+    items: &[FeedItem],
+) -> Result<()> {
+    let entries = if feed.summarize {
+        match summarize(&context.deps, items).await {
+            Ok(summary) => summary,
+            Err(error) => {
+                tracing::warn!(feed_id = %feed.id, %error, "failed to summarize feed entries");
+                render_items(items, MAX_ITEM_CHARS)
+            }
+        }
+    } else {
+        render_items(items, MAX_ITEM_CHARS)
+    };
+    let prompt = format!(
+        "{}\n\n{} new entries from feed `{}`:\n\n{entries}",
+        feed.prompt,
+        items.len(),
+        feed.id
+    );
+
+    let text = run_prompt(context, "feed", &format!("feed:{}", feed.id), prompt).await?;
+    if text.trim().is_empty() {
+        tracing::debug!(feed_id = %feed.id, "feed digest produced no output, skipping delivery");
+        return Ok(());
+    }
+
+    context
+        .messaging_manager
+        .broadcast(
+            &target.adapter,
+            &target.target,
+            OutboundResponse::Text(text),
+        )
+        .await?;
+    tracing::info!(feed_id = %feed.id, %target, "feed digest delivered");
+    Ok(())
+}
+
+/// Condense the entries with one model call before the agent sees them.
+async fn summarize(
+    deps: &AgentDeps,
+    items: &[FeedItem],
+) -> std::result::Result<String, rig::completion::PromptError> {
+    let routing = deps.runtime_config.routing.load();
+    let model_name = routing.resolve(ProcessType::Worker, None).to_string();
+    let model = SpacebotModel::make(&deps.llm_manager, &model_name)
+        .with_routing((**routing).clone())
+        .with_priority(Priority::Background);
+    let agent = AgentBuilder::new(model)
+        .preamble(crate::prompts::text::get("feed_summary"))
+        .build();
+
+    agent
+        .prompt(render_items(items, MAX_SUMMARY_INPUT_CHARS))
+        .await
+}
+
+/// A numbered list of entries, each with its text cut to `max_chars`.
+fn render_items(items: &[FeedItem], max_chars: usize) -> String {
+    let mut rendered = String::new();
+    for (index, item) in items.iter().enumerate() {
+        rendered.push_str(&format!("{}. {}", index + 1, item.title));
+        if !item.source.is_empty() {
+            rendered.push_str(&format!(" — {}", item.source));
+        }
+        if let Some(published) = item.published {
+            rendered.push_str(&format!(" ({})", published.format("%Y-%m-%d")));
+        }
+        rendered.push('\n');
+        if let Some(link) = &item.link {
+            rendered.push_str(&format!("   {link}\n"));
+        }
+        if !item.text.is_empty() {
+            let mut text: String = item.text.chars().take(max_chars).collect();
+            if text.len() < item.text.len() {
+                text.push('…');
+            }
+            rendered.push_str(&format!("   {text}\n"));
+        }
+        rendered.push('\n');
+    }
+    rendered.trim_end().to_string()
+}
+
+/// Plain text from an HTML fragment: tags dropped, common entities decoded,
+/// whitespace collapsed.
+fn strip_html(html: &str) -> String {
+    let text = HTML_TAG
+        .replace_all(html, " ")
+        .replace("&nbsp;", " ")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&");
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(id: &str, link: &str, day: u32) -> FeedItem {
+        FeedItem {
+            id: id.into(),
+            title: format!("Entry {id}"),
+            link: Some(link.into()),
+            published: Some(
+                DateTime::parse_from_rfc3339(&format!("2026-10-{day:02}T08:00:00Z"))
+                    .unwrap()
+                    .with_timezone(&Utc),
+            ),
+            text: String::new(),
+            source: "Example".into(),
+        }
+    }
+
+    #[test]
+    fn test_items_from_rss_and_atom() {
+        let rss = r#"<?xml version="1.0"?>
+            <rss version="2.0"><channel><title>Example News</title>
+            <item>
+                <title>First</title>
+                <link>https://example.com/first</link>
+                <guid>first-1</guid>
+                <description>&lt;p&gt;Hello &amp;amp; &lt;b&gt;bye&lt;/b&gt;&lt;/p&gt;</description>
+                <pubDate>Wed, 14 Oct 2026 10:00:00 GMT</pubDate>
+            </item>
+            </channel></rss>"#;
+        let items = items_from_feed(feed_rs::parser::parse(rss.as_bytes()).unwrap());
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].id, "first-1");
+        assert_eq!(items[0].title, "First");
+        assert_eq!(items[0].link.as_deref(), Some("https://example.com/first"));
+        assert_eq!(items[0].text, "Hello & bye");
+        assert_eq!(items[0].source, "Example News");
+        assert!(items[0].published.is_some());
+
+        let atom = r#"<?xml version="1.0" encoding="utf-8"?>
+            <feed xmlns="http://www.w3.org/2005/Atom">
+            <title>Example Blog</title>
+            <id>urn:example:blog</id>
+            <updated>2026-10-15T08:00:00Z</updated>
+            <entry>
+                <title>Second</title>
+                <id>urn:example:second</id>
+                <link href="https://example.com/second"/>
+                <updated>2026-10-15T08:00:00Z</updated>
+                <summary>Short summary</summary>
+            </entry>
+            </feed>"#;
+        let items = items_from_feed(feed_rs::parser::parse(atom.as_bytes()).unwrap());
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].id, "urn:example:second");
+        assert_eq!(items[0].link.as_deref(), Some("https://example.com/second"));
+        assert_eq!(items[0].text, "Short summary");
+    }
+
+    #[test]
+    fn test_select_new_dedupes_and_caps() {
+        let items = vec![
+            item("a", "https://example.com/a", 10),
+            item("b", "https://example.com/b", 12),
+            item("c", "https://example.com/c", 11),
+            // The same article under another ID, e.g. from a second feed.
+            item("b-mirror", "https://example.com/b", 12),
+            item("b", "https://example.com/b", 12),
+            item("d", "https://example.com/d", 13),
+        ];
+        let seen = HashSet::from(["a".to_string()]);
+
+        let (batch, new_ids) = select_new(items, &seen, 2);
+
+        let batch_ids: Vec<&str> = batch.iter().map(|item| item.id.as_str()).collect();
+        assert_eq!(batch_ids, ["b", "d"]);
+        assert_eq!(new_ids, ["b", "c", "b-mirror", "d"]);
+    }
+
+    #[test]
+    fn test_strip_html() {
+        assert_eq!(
+            strip_html("<p>Rust&nbsp;1.90 is <em>out</em> &amp; &lt;fast&gt;</p>\n<br/>"),
+            "Rust 1.90 is out & <fast>"
+        );
+    }
+}
@@ -0,0 +1,286 @@
+//! Fine-tuning dataset export.
+//!
+//! Reads persisted conversations back out of an agent's database and writes
+//! them as provider-specific fine-tuning JSONL, one conversation per line.
+//! Every message passes through [`redact_pii`] on the way out. Replies keep
+//! the sources they cited, as markdown footnotes.
+
+use crate::citations::Citation;
+use crate::error::Result;
+use crate::feedback::Rating;
+use crate::messaging::format::render_citations;
+
+use anyhow::Context as _;
+use chrono::NaiveDate;
+use spacebot_core::redact::redact_pii;
+use sqlx::{Row as _, SqlitePool};
+
+use std::collections::HashSet;
+
+/// Target fine-tuning format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FinetuneFormat {
+    /// OpenAI chat format: `{"messages": [{"role": ..., "content": ...}]}`.
+    OpenAi,
+    /// Anthropic (Bedrock) format: `{"system": ..., "messages": [...]}`.
+    Anthropic,
+}
+
+impl std::str::FromStr for FinetuneFormat {
+    type Err = String;
+
+    fn from_str(value: &str) -> std::result::Result<Self, Self::Err> {
+        match value.to_ascii_lowercase().as_str() {
+            "openai" => Ok(Self::OpenAi),
+            "anthropic" => Ok(Self::Anthropic),
+            other => Err(format!(
+                "unknown format '{other}', expected openai or anthropic"
+            )),
+        }
+    }
+}
+
+/// Which conversations to export.
+#[derive(Debug, Clone, Default)]
+pub struct ExportFilter {
+    /// `Up` keeps conversations whose rated replies were all rated up;
+    /// `Down` keeps conversations with at least one reply rated down.
+    /// Unrated conversations are only exported without a rating filter.
+    pub rating: Option<Rating>,
+    /// Only messages sent on or after this day (UTC).
+    pub since: Option<NaiveDate>,
+    /// Only messages sent on or before this day (UTC).
+    pub until: Option<NaiveDate>,
+}
+
+/// One turn of an exported conversation.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Turn {
+    /// `"user"` or `"assistant"`.
+    pub role: String,
+    pub content: String,
+}
+
+/// A conversation ready to be written as a training example.
+#[derive(Debug, Clone)]
+pub struct ExportedConversation {
+    pub channel_id: String,
+    pub turns: Vec<Turn>,
+}
+
+impl ExportedConversation {
+    /// Render as one JSONL record in the given format.
+    pub fn to_record(&self, format: FinetuneFormat) -> serde_json::Value {
+        let messages: Vec<serde_json::Value> = self
+            .turns
+            .iter()
+            .map(|turn| serde_json::json!({ "role": turn.role, "content": turn.content }))
+            .collect();
+
+        match format {
+            FinetuneFormat::OpenAi => serde_json::json!({ "messages": messages }),
+            FinetuneFormat::Anthropic => serde_json::json!({ "system": "", "messages": messages }),
+        }
+    }
+}
+
+/// Reads conversations for export from an agent's SQLite database.
+#[derive(Debug, Clone)]
+pub struct FinetuneExporter {
+    pool: SqlitePool,
+}
+
+impl FinetuneExporter {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+
+    /// Load the conversations matching `filter`, redacted and shaped into
+    /// alternating user/assistant turns. Conversations without a reply are
+    /// dropped.
+    pub async fn conversations(&self, filter: &ExportFilter) -> Result<Vec<ExportedConversation>> {
+        let allowed = match filter.rating {
+            Some(rating) => Some(self.rated_channels(rating).await?),
+            None => None,
+        };
+
+        let since = filter.since.map(|day| day.to_string());
+        let until = filter.until.map(|day| day.to_string());
+        let rows = sqlx::query(
+            "SELECT channel_id, role, content, metadata FROM conversation_messages \
+             WHERE (? IS NULL OR date(created_at) >= ?) AND (? IS NULL OR date(created_at) <= ?) \
+             ORDER BY channel_id, created_at, rowid",
+        )
+        .bind(&since)
+        .bind(&since)
+        .bind(&until)
+        .bind(&until)
+        .fetch_all(&self.pool)
+        .await
+        .context("failed to load conversation messages")?;
+
+        let mut conversations = Vec::new();
+        let mut current: Option<(String, Vec<(String, String)>)> = None;
+        for row in rows {
+            let channel_id: String = row.try_get("channel_id").unwrap_or_default();
+            if allowed
+                .as_ref()
+                .is_some_and(|allowed| !allowed.contains(&channel_id))
+            {
+                continue;
+            }
+            let role: String = row.try_get("role").unwrap_or_default();
+            let content: String = row.try_get("content").unwrap_or_default();
+            let metadata: Option<String> = row.try_get("metadata").unwrap_or_default();
+            let content = match metadata.as_deref().map(stored_citations) {
+                Some(citations) if !citations.is_empty() => {
+                    render_citations(&content, &citations, None)
+                }
+                _ => content,
+            };
+
+            if current.as_ref().is_none_or(|(id, _)| *id != channel_id) {
+                conversations.extend(current.take().and_then(finish));
+                current = Some((channel_id, Vec::new()));
+            }
+            if let Some((_, messages)) = current.as_mut() {
+                messages.push((role, content));
+            }
+        }
+        conversations.extend(current.and_then(finish));
+
+        Ok(conversations)
+    }
+
+    /// Channels whose feedback matches `rating`.
+    async fn rated_channels(&self, rating: Rating) -> Result<HashSet<String>> {
+        let rows = sqlx::query(
+            "SELECT m.channel_id, MIN(f.rating) AS worst FROM feedback f \
+             JOIN conversation_messages m ON m.id = f.message_id \
+             GROUP BY m.channel_id",
+        )
+        .fetch_all(&self.pool)
+        .await
+        .context("failed to load feedback ratings")?;
+
+        Ok(rows
+            .into_iter()
+            .filter(|row| {
+                let worst: i64 = row.try_get("worst").unwrap_or_default();
+                match rating {
+                    Rating::Up => worst > 0,
+                    Rating::Down => worst < 0,
+                }
+            })
+            .filter_map(|row| row.try_get("channel_id").ok())
+            .collect())
+    }
+}
+
+/// The citations stored in a message's metadata.
+fn stored_citations(metadata: &str) -> Vec<Citation> {
+    serde_json::from_str::<serde_json::Value>(metadata)
+        .ok()
+        .and_then(|metadata| serde_json::from_value(metadata["citations"].clone()).ok())
+        .unwrap_or_default()
+}
+
+/// Shape a channel's raw messages into a training example: redact, merge
+/// consecutive messages from the same side, and trim so the conversation
+/// opens with the user and closes with the assistant.
+fn finish((channel_id, messages): (String, Vec<(String, String)>)) -> Option<ExportedConversation> {
+    let mut turns: Vec<Turn> = Vec::new();
+    for (role, content) in messages {
+        let role = match role.as_str() {
+            "user" | "assistant" => role,
+            _ => continue,
+        };
+        let content = redact_pii(content.trim());
+        if content.is_empty() {
+            continue;
+        }
+        match turns.last_mut() {
+            Some(last) if last.role == role => {
+                last.content.push_str("\n\n");
+                last.content.push_str(&content);
+            }
+            _ => turns.push(Turn { role, content }),
+        }
+    }
+
+    let start = turns.iter().position(|turn| turn.role == "user")?;
+    let end = turns.iter().rposition(|turn| turn.role == "assistant")?;
+    if end < start {
+        return None;
+    }
+    turns.truncate(end + 1);
+    turns.drain(..start);
+
+    Some(ExportedConversation { channel_id, turns })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn messages(pairs: &[(&str, &str)]) -> (String, Vec<(String, String)>) {
+        (
+            "discord:1:2".into(),
+            pairs
+                .iter()
+                .map(|(role, content)| (role.to_string(), content.to_string()))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn test_finish_alternates_and_trims() {
+        let conversation = finish(messages(&[
+            ("assistant", "scheduled reminder"),
+            ("user", "hi, I'm jane@example.com"),
+            ("user", "can you help?"),
+            ("assistant", "sure"),
+            ("user", "thanks"),
+        ]))
+        .expect("has a reply");
+
+        assert_eq!(
+            conversation.turns,
+            vec![
+                Turn {
+                    role: "user".into(),
+                    content: "hi, I'm [EMAIL]\n\ncan you help?".into(),
+                },
+                Turn {
+                    role: "assistant".into(),
+                    content: "sure".into(),
+                },
+            ]
+        );
+        assert!(finish(messages(&[("user", "anyone?")])).is_none());
+    }
+
+    #[test]
+    fn test_stored_citations() {
+        let metadata = r#"{"attribution":{"input_tokens":1,"output_tokens":2},"citations":[{"title":"Tokio","url":"https://tokio.rs","origin":"web_search"}]}"#;
+        let citations = stored_citations(metadata);
+        assert_eq!(citations.len(), 1);
+        assert_eq!(citations[0].url.as_deref(), Some("https://tokio.rs"));
+
+        assert!(stored_citations(r#"{"attribution":{}}"#).is_empty());
+        assert!(stored_citations("not json").is_empty());
+    }
+
+    #[test]
+    fn test_record_formats() {
+        let conversation = finish(messages(&[("user", "ping"), ("assistant", "pong")])).unwrap();
+
+        let openai = conversation.to_record(FinetuneFormat::OpenAi);
+        assert_eq!(openai["messages"][1]["content"], "pong");
+        assert!(openai.get("system").is_none());
+
+        let anthropic = conversation.to_record(FinetuneFormat::Anthropic);
+        assert_eq!(anthropic["messages"][0]["role"], "user");
+        assert_eq!(anthropic["system"], "");
+    }
+}
@@ -0,0 +1,286 @@
+//! Experiment flags: behavior changes rolled out to a share of users.
+//!
+//! A flag from `[[defaults.flags]]` or `[[agents.flags]]` is on for a user
+//! when they're listed in its `enabled_for`, off when they're listed in its
+//! `disabled_for`, and otherwise on for `rollout_percent` of users. Each user
+//! lands in a stable bucket per flag, so raising the percentage only adds
+//! users and the same user sees the same behavior every turn.
+//!
+//! While a flag is on for the user a channel turn answers, its `preamble`
+//! is added to the channel prompt, its `model` answers the turn and its
+//! `tools` limit the channel's tools. Templates can test `flags` for the
+//! names of the flags that are on, and other code can ask [`FlagSet::is_on`].
+//! Each turn records the flags it ran with, and [`flag_report`] compares
+//! turns with each flag on against the rest.
+
+use crate::agent::turn::TurnCompletion;
+use crate::error::Result;
+
+use anyhow::Context as _;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::{Row as _, SqlitePool};
+
+use std::collections::HashSet;
+
+/// An experiment flag from config.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, schemars::JsonSchema)]
+pub struct FlagDef {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    /// Share of users the flag is on for, 0 to 100.
+    #[serde(default)]
+    pub rollout_percent: u8,
+    /// Users the flag is always on for, as a platform ID or `platform:id`.
+    #[serde(default)]
+    pub enabled_for: Vec<String>,
+    /// Users the flag is never on for. Wins over `enabled_for`.
+    #[serde(default)]
+    pub disabled_for: Vec<String>,
+    /// Instructions added to the channel prompt while the flag is on.
+    pub preamble: Option<String>,
+    /// Model answering channel turns while the flag is on: a model name or a
+    /// routing tier, as for personas.
+    pub model: Option<String>,
+    /// Channel tools allowed while the flag is on. `reply` is always
+    /// available. None leaves the tools alone.
+    pub tools: Option<Vec<String>>,
+}
+
+impl FlagDef {
+    /// Whether the flag is on for `user`, given as `platform:id`.
+    pub fn is_on_for(&self, user: &str) -> bool {
+        let bare = user.split_once(':').map(|(_, id)| id);
+        let listed = |users: &[String]| {
+            users
+                .iter()
+                .any(|listed| listed == user || Some(listed.as_str()) == bare)
+        };
+        if listed(&self.disabled_for) {
+            return false;
+        }
+        if listed(&self.enabled_for) {
+            return true;
+        }
+        bucket(&self.name, user) < u32::from(self.rollout_percent.min(100))
+    }
+}
+
+/// `user`'s bucket for the flag `name`, 0 to 99.
+fn bucket(name: &str, user: &str) -> u32 {
+    let digest = Sha256::new()
+        .chain_update(name.as_bytes())
+        .chain_update(b":")
+        .chain_update(user.as_bytes())
+        .finalize();
+    u32::from_be_bytes([digest[0], digest[1], digest[2], digest[3]]) % 100
+}
+
+/// An agent's flags, agent definitions over the defaults with the same name.
+#[derive(Debug, Clone, Default)]
+pub struct FlagSet {
+    flags: Vec<FlagDef>,
+}
+
+impl FlagSet {
+    pub fn new(defaults: &[FlagDef], agent: &[FlagDef]) -> Self {
+        let overridden: HashSet<&str> = agent.iter().map(|flag| flag.name.as_str()).collect();
+        let flags = defaults
+            .iter()
+            .filter(|flag| !overridden.contains(flag.name.as_str()))
+            .chain(agent)
+            .cloned()
+            .collect();
+        Self { flags }
+    }
+
+    /// Whether the flag `name` is on for `user`. Unknown flags are off.
+    pub fn is_on(&self, name: &str, user: &str) -> bool {
+        self.flags
+            .iter()
+            .any(|flag| flag.name == name && flag.is_on_for(user))
+    }
+
+    /// The flags on for `user`, in config order.
+    pub fn active(&self, user: &str) -> Vec<&FlagDef> {
+        self.flags
+            .iter()
+            .filter(|flag| flag.is_on_for(user))
+            .collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.flags.is_empty()
+    }
+}
+
+/// Channel turns with one flag on, against the turns without it.
+#[derive(Debug, Clone, Serialize)]
+pub struct FlagStats {
+    pub flag: String,
+    /// Distinct conversations with turns the flag was on for.
+    pub conversations: u64,
+    pub on: FlagArm,
+    pub off: FlagArm,
+}
+
+/// Turns on one side of a flag.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct FlagArm {
+    #[serde(flatten)]
+    pub completion: TurnCompletion,
+    pub avg_input_tokens: Option<f64>,
+    pub avg_output_tokens: Option<f64>,
+    pub avg_cost_usd: Option<f64>,
+}
+
+/// Sums over a set of turns, for working out the turns without a flag.
+#[derive(Debug, Clone, Copy, Default)]
+struct Totals {
+    turns: i64,
+    unfinished: i64,
+    input_tokens: f64,
+    output_tokens: f64,
+    priced: i64,
+    cost_usd: f64,
+}
+
+impl Totals {
+    fn from_row(row: &sqlx::sqlite::SqliteRow) -> Self {
+        Self {
+            turns: row.try_get("turns").unwrap_or_default(),
+            unfinished: row.try_get("unfinished").unwrap_or_default(),
+            input_tokens: row.try_get("input_tokens").unwrap_or_default(),
+            output_tokens: row.try_get("output_tokens").unwrap_or_default(),
+            priced: row.try_get("priced").unwrap_or_default(),
+            cost_usd: row.try_get("cost_usd").unwrap_or_default(),
+        }
+    }
+
+    fn minus(self, other: Self) -> Self {
+        Self {
+            turns: self.turns - other.turns,
+            unfinished: self.unfinished - other.unfinished,
+            input_tokens: self.input_tokens - other.input_tokens,
+            output_tokens: self.output_tokens - other.output_tokens,
+            priced: self.priced - other.priced,
+            cost_usd: self.cost_usd - other.cost_usd,
+        }
+    }
+
+    fn arm(self) -> FlagArm {
+        let per_turn = |sum: f64| (self.turns > 0).then(|| sum / self.turns as f64);
+        FlagArm {
+            completion: TurnCompletion::new(self.turns as u64, self.unfinished as u64),
+            avg_input_tokens: per_turn(self.input_tokens),
+            avg_output_tokens: per_turn(self.output_tokens),
+            avg_cost_usd: (self.priced > 0).then(|| self.cost_usd / self.priced as f64),
+        }
+    }
+}
+
+const TOTALS: &str = "COUNT(*) AS turns, \
+     SUM(CASE WHEN json_extract(t.outcome, '$.stop_reason.reason') \
+         IN ('failed', 'panicked', 'cancelled', 'max_turns') THEN 1 ELSE 0 END) AS unfinished, \
+     CAST(COALESCE(SUM(json_extract(t.outcome, '$.usage.input_tokens')), 0) AS REAL) AS input_tokens, \
+     CAST(COALESCE(SUM(json_extract(t.outcome, '$.usage.output_tokens')), 0) AS REAL) AS output_tokens, \
+     COUNT(json_extract(t.outcome, '$.cost_usd')) AS priced, \
+     CAST(COALESCE(SUM(json_extract(t.outcome, '$.cost_usd')), 0) AS REAL) AS cost_usd";
+
+/// Each flag seen on an agent's turns over the last `days` days, with the
+/// turns it was on for against the rest, most-used first.
+pub async fn flag_report(pool: &SqlitePool, days: u32) -> Result<Vec<FlagStats>> {
+    let window = format!("-{days} days");
+    let all = sqlx::query(&format!(
+        "SELECT {TOTALS} FROM turn_runs t WHERE t.completed_at >= datetime('now', ?)"
+    ))
+    .bind(&window)
+    .fetch_one(pool)
+    .await
+    .context("failed to sum turns")?;
+    let all = Totals::from_row(&all);
+
+    let rows = sqlx::query(&format!(
+        "SELECT f.value AS flag, COUNT(DISTINCT t.channel_id) AS conversations, {TOTALS} \
+         FROM turn_runs t, json_each(t.outcome, '$.flags') f \
+         WHERE t.completed_at >= datetime('now', ?) \
+         GROUP BY flag ORDER BY turns DESC"
+    ))
+    .bind(&window)
+    .fetch_all(pool)
+    .await
+    .context("failed to sum turns by flag")?;
+
+    Ok(rows
+        .iter()
+        .map(|row| {
+            let on = Totals::from_row(row);
+            FlagStats {
+                flag: row.try_get("flag").unwrap_or_default(),
+                conversations: row.try_get::<i64, _>("conversations").unwrap_or_default() as u64,
+                on: on.arm(),
+                off: all.minus(on).arm(),
+            }
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn flag(name: &str, rollout_percent: u8) -> FlagDef {
+        FlagDef {
+            name: name.into(),
+            rollout_percent,
+            ..FlagDef::default()
+        }
+    }
+
+    #[test]
+    fn test_rollout_is_stable_and_grows() {
+        let users: Vec<String> = (0..1000).map(|i| format!("discord:{i}")).collect();
+        let on = |percent| {
+            let flag = flag("terse", percent);
+            users
+                .iter()
+                .filter(|user| flag.is_on_for(user))
+                .cloned()
+                .collect::<HashSet<_>>()
+        };
+
+        assert!(on(0).is_empty());
+        assert_eq!(on(100).len(), users.len());
+        let ten = on(10);
+        assert!((50..150).contains(&ten.len()), "{} users at 10%", ten.len());
+        assert!(ten.is_subset(&on(30)));
+        assert_eq!(ten, on(10));
+    }
+
+    #[test]
+    fn test_overrides_and_agent_flags() {
+        let mut beta = flag("beta", 0);
+        beta.enabled_for = vec!["42".into(), "slack:U7".into()];
+        beta.disabled_for = vec!["discord:43".into()];
+        assert!(beta.is_on_for("discord:42"));
+        assert!(beta.is_on_for("slack:U7"));
+        assert!(!beta.is_on_for("discord:U7"));
+
+        let mut everyone = flag("beta", 100);
+        everyone.disabled_for = vec!["43".into()];
+        assert!(!everyone.is_on_for("discord:43"));
+
+        let flags = FlagSet::new(&[beta, flag("terse", 100)], &[everyone]);
+        assert!(flags.is_on("beta", "discord:1"));
+        assert!(!flags.is_on("beta", "discord:43"));
+        assert!(flags.is_on("terse", "discord:43"));
+        assert!(!flags.is_on("missing", "discord:1"));
+        let active: Vec<&str> = flags
+            .active("discord:1")
+            .iter()
+            .map(|flag| flag.name.as_str())
+            .collect();
+        assert_eq!(active, ["terse", "beta"]);
+    }
+}
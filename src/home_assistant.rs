@@ -0,0 +1,537 @@
+//! Home Assistant integration.
+//!
+//! [`Client`] wraps the REST API behind the `home_assistant` tool. The trigger
+//! watcher keeps a websocket subscription to `state_changed` events and, when
+//! a watched entity changes state, runs the trigger's prompt through a fresh
+//! channel the same way a cron job runs, delivering the reply to the
+//! trigger's target.
+
+use crate::OutboundResponse;
+use crate::config::{HomeAssistantConfig, HomeAssistantTrigger};
+use crate::cron::CronContext;
+use crate::cron::scheduler::{DeliveryTarget, run_prompt};
+use crate::error::Result;
+
+use anyhow::Context as _;
+use futures::{SinkExt as _, StreamExt as _};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use tokio_tungstenite::tungstenite::Message;
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How often to re-check the config while there is nothing to watch.
+const IDLE_POLL_INTERVAL: Duration = Duration::from_secs(30);
+
+/// A quiet socket gets a ping after this long, and is dropped if the pong
+/// doesn't arrive within the same interval.
+const PING_INTERVAL: Duration = Duration::from_secs(60);
+
+const MIN_RECONNECT_DELAY: Duration = Duration::from_secs(5);
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(300);
+
+type Socket =
+    tokio_tungstenite::WebSocketStream<tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>>;
+
+/// An entity and its current state, as returned by the REST API.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EntityState {
+    pub entity_id: String,
+    pub state: String,
+    #[serde(default)]
+    pub attributes: serde_json::Map<String, serde_json::Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_changed: Option<String>,
+}
+
+impl EntityState {
+    pub fn friendly_name(&self) -> Option<&str> {
+        self.attributes
+            .get("friendly_name")
+            .and_then(|name| name.as_str())
+    }
+}
+
+/// Whether `entity_id` matches any of `patterns`. `*` matches any run of
+/// characters, so "light.*" covers every light and "*" everything.
+pub fn entity_allowed(patterns: &[String], entity_id: &str) -> bool {
+    patterns
+        .iter()
+        .any(|pattern| glob_match(pattern, entity_id))
+}
+
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let Some(mut rest) = text.strip_prefix(parts.next().unwrap_or_default()) else {
+        return false;
+    };
+    let parts: Vec<&str> = parts.collect();
+    let Some((last, middle)) = parts.split_last() else {
+        // No wildcard, so the prefix had to be the whole text.
+        return rest.is_empty();
+    };
+    for part in middle {
+        match rest.find(part) {
+            Some(index) => rest = &rest[index + part.len()..],
+            None => return false,
+        }
+    }
+    rest.ends_with(last)
+}
+
+/// REST client for one Home Assistant instance.
+#[derive(Debug, Clone)]
+pub struct Client {
+    http: reqwest::Client,
+    url: String,
+    token: String,
+}
+
+impl Client {
+    pub fn new(config: &HomeAssistantConfig) -> Result<Self> {
+        let token = config
+            .token
+            .clone()
+            .context("home_assistant.token is not set")?;
+        let http = reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .context("failed to build Home Assistant HTTP client")?;
+
+        Ok(Self {
+            http,
+            url: config.url.trim_end_matches('/').to_string(),
+            token,
+        })
+    }
+
+    pub async fn states(&self) -> Result<Vec<EntityState>> {
+        self.send(self.http.get(format!("{}/api/states", self.url)))
+            .await
+    }
+
+    pub async fn state(&self, entity_id: &str) -> Result<EntityState> {
+        self.send(
+            self.http
+                .get(format!("{}/api/states/{entity_id}", self.url)),
+        )
+        .await
+    }
+
+    /// Call `domain.service` with `data` and return the states it changed.
+    pub async fn call_service(
+        &self,
+        domain: &str,
+        service: &str,
+        data: &serde_json::Value,
+    ) -> Result<Vec<EntityState>> {
+        self.send(
+            self.http
+                .post(format!("{}/api/services/{domain}/{service}", self.url))
+                .json(data),
+        )
+        .await
+    }
+
+    async fn send<T: serde::de::DeserializeOwned>(
+        &self,
+        request: reqwest::RequestBuilder,
+    ) -> Result<T> {
+        let response = request
+            .bearer_auth(&self.token)
+            .send()
+            .await
+            .context("Home Assistant request failed")?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            return Err(anyhow::anyhow!("Home Assistant returned {status}: {body}").into());
+        }
+
+        Ok(response
+            .json()
+            .await
+            .context("invalid response from Home Assistant")?)
+    }
+
+    fn websocket_url(&self) -> String {
+        let url = if let Some(rest) = self.url.strip_prefix("https://") {
+            format!("wss://{rest}")
+        } else if let Some(rest) = self.url.strip_prefix("http://") {
+            format!("ws://{rest}")
+        } else {
+            self.url.clone()
+        };
+        format!("{url}/api/websocket")
+    }
+}
+
+/// Payload of a `state_changed` event. Either state is missing when the
+/// entity was just added or removed.
+#[derive(Debug, Clone, Deserialize)]
+struct StateChange {
+    entity_id: String,
+    old_state: Option<EntityState>,
+    new_state: Option<EntityState>,
+}
+
+impl StateChange {
+    fn previous(&self) -> Option<&str> {
+        self.old_state.as_ref().map(|state| state.state.as_str())
+    }
+
+    fn current(&self) -> Option<&str> {
+        self.new_state.as_ref().map(|state| state.state.as_str())
+    }
+}
+
+/// Whether `change` should fire `trigger`. Attribute-only updates never do,
+/// and neither do entities outside the agent's allowlist.
+fn trigger_matches(
+    trigger: &HomeAssistantTrigger,
+    allowlist: &[String],
+    change: &StateChange,
+) -> bool {
+    trigger.enabled
+        && change.previous() != change.current()
+        && entity_allowed(allowlist, &change.entity_id)
+        && entity_allowed(&trigger.entities, &change.entity_id)
+        && trigger
+            .from
+            .as_deref()
+            .is_none_or(|from| change.previous() == Some(from))
+        && trigger
+            .to
+            .as_deref()
+            .is_none_or(|to| change.current() == Some(to))
+}
+
+fn watching(config: &HomeAssistantConfig) -> bool {
+    config.enabled && config.triggers.iter().any(|trigger| trigger.enabled)
+}
+
+/// Spawn the trigger watcher for an agent.
+///
+/// Reads `deps.runtime_config.home_assistant` on every event so trigger edits
+/// apply without a restart, and reconnects when the URL or token change.
+/// Idles while the integration is disabled or has no enabled triggers.
+pub fn spawn_trigger_watcher(context: CronContext) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut delay = MIN_RECONNECT_DELAY;
+        loop {
+            let config = context.deps.runtime_config.home_assistant.load_full();
+            if !watching(&config) {
+                tokio::time::sleep(IDLE_POLL_INTERVAL).await;
+                continue;
+            }
+
+            if let Err(error) = watch(&context, &config, &mut delay).await {
+                tracing::warn!(
+                    agent_id = %context.deps.agent_id,
+                    %error,
+                    retry_in = ?delay,
+                    "Home Assistant subscription failed"
+                );
+                tokio::time::sleep(delay).await;
+                delay = (delay * 2).min(MAX_RECONNECT_DELAY);
+            }
+        }
+    })
+}
+
+/// Subscribe to state changes and fire triggers until the socket fails
+/// (an error) or the config no longer matches the connection (`Ok`).
+async fn watch(
+    context: &CronContext,
+    config: &HomeAssistantConfig,
+    delay: &mut Duration,
+) -> Result<()> {
+    let client = Client::new(config)?;
+    let (mut socket, _) = tokio_tungstenite::connect_async(client.websocket_url())
+        .await
+        .context("failed to connect to the Home Assistant websocket")?;
+
+    let hello = next_json(&mut socket).await?;
+    if hello["type"] != "auth_required" {
+        return Err(anyhow::anyhow!("unexpected Home Assistant greeting: {hello}").into());
+    }
+    send_json(
+        &mut socket,
+        serde_json::json!({"type": "auth", "access_token": client.token}),
+    )
+    .await?;
+    let auth = next_json(&mut socket).await?;
+    if auth["type"] != "auth_ok" {
+        return Err(
+            anyhow::anyhow!("Home Assistant rejected the token: {}", auth["message"]).into(),
+        );
+    }
+
+    send_json(
+        &mut socket,
+        serde_json::json!({"id": 1, "type": "subscribe_events", "event_type": "state_changed"}),
+    )
+    .await?;
+    let subscribed = next_json(&mut socket).await?;
+    if subscribed["success"] != true {
+        return Err(anyhow::anyhow!("failed to subscribe to state changes: {subscribed}").into());
+    }
+
+    *delay = MIN_RECONNECT_DELAY;
+    tracing::info!(agent_id = %context.deps.agent_id, "watching Home Assistant state changes");
+
+    let mut last_fired: HashMap<String, Instant> = HashMap::new();
+    let mut next_id = 2;
+    let mut awaiting_pong = false;
+    loop {
+        let message = match tokio::time::timeout(PING_INTERVAL, next_json(&mut socket)).await {
+            Ok(message) => message?,
+            Err(_) if awaiting_pong => {
+                return Err(anyhow::anyhow!("Home Assistant stopped responding").into());
+            }
+            Err(_) => {
+                send_json(
+                    &mut socket,
+                    serde_json::json!({"id": next_id, "type": "ping"}),
+                )
+                .await?;
+                next_id += 1;
+                awaiting_pong = true;
+                continue;
+            }
+        };
+        awaiting_pong = false;
+
+        if message["type"] != "event" {
+            continue;
+        }
+        let current = context.deps.runtime_config.home_assistant.load();
+        if !watching(&current) || current.url != config.url || current.token != config.token {
+            return Ok(());
+        }
+        let Ok(change) = serde_json::from_value::<StateChange>(message["event"]["data"].clone())
+        else {
+            continue;
+        };
+
+        for trigger in &current.triggers {
+            if !trigger_matches(trigger, &current.entities, &change) {
+                continue;
+            }
+            let cooldown = Duration::from_secs(trigger.cooldown_secs);
+            if last_fired
+                .get(&trigger.id)
+                .is_some_and(|fired| fired.elapsed() < cooldown)
+            {
+                tracing::debug!(trigger_id = %trigger.id, "Home Assistant trigger cooling down");
+                continue;
+            }
+            last_fired.insert(trigger.id.clone(), Instant::now());
+            tokio::spawn(fire(context.clone(), trigger.clone(), change.clone()));
+        }
+    }
+}
+
+/// Run a trigger's prompt and deliver the reply.
+async fn fire(context: CronContext, trigger: HomeAssistantTrigger, change: StateChange) {
+    if context.deps.maintenance.is_enabled() {
+        tracing::info!(trigger_id = %trigger.id, "skipping Home Assistant trigger during maintenance");
+        return;
+    }
+    let Some(target) = DeliveryTarget::parse(&trigger.delivery_target) else {
+        tracing::warn!(
+            trigger_id = %trigger.id,
+            target = %trigger.delivery_target,
+            "invalid Home Assistant trigger delivery target"
+        );
+        return;
+    };
+
+    tracing::info!(
+        trigger_id = %trigger.id,
+        entity_id = %change.entity_id,
+        "Home Assistant trigger fired"
+    );
+    let entity = match change
+        .new_state
+        .as_ref()
+        .and_then(EntityState::friendly_name)
+    {
+        Some(name) => format!("{name} ({})", change.entity_id),
+        None => change.entity_id.clone(),
+    };
+    let prompt = format!(
+        "{}\n\nHome Assistant trigger `{}`: {entity} changed from `{}` to `{}`.",
+        trigger.prompt,
+        trigger.id,
+        change.previous().unwrap_or("nothing"),
+        change.current().unwrap_or("removed"),
+    );
+
+    let text = match run_prompt(
+        &context,
+        "home_assistant",
+        &format!("home_assistant:{}", trigger.id),
+        prompt,
+    )
+    .await
+    {
+        Ok(text) => text,
+        Err(error) => {
+            tracing::error!(trigger_id = %trigger.id, %error, "Home Assistant trigger failed");
+            return;
+        }
+    };
+    if text.trim().is_empty() {
+        tracing::debug!(trigger_id = %trigger.id, "Home Assistant trigger produced no output");
+        return;
+    }
+
+    if let Err(error) = context
+        .messaging_manager
+        .broadcast(
+            &target.adapter,
+            &target.target,
+            OutboundResponse::Text(text),
+        )
+        .await
+    {
+        tracing::error!(
+            trigger_id = %trigger.id,
+            %target,
+            %error,
+            "failed to deliver Home Assistant trigger result"
+        );
+    }
+}
+
+async fn next_json(socket: &mut Socket) -> Result<serde_json::Value> {
+    loop {
+        let message = socket
+            .next()
+            .await
+            .context("Home Assistant closed the websocket")?
+            .context("Home Assistant websocket error")?;
+        match message {
+            Message::Text(text) => {
+                return Ok(serde_json::from_str(text.as_str())
+                    .context("invalid Home Assistant websocket message")?);
+            }
+            Message::Close(_) => {
+                return Err(anyhow::anyhow!("Home Assistant closed the websocket").into());
+            }
+            _ => {}
+        }
+    }
+}
+
+async fn send_json(socket: &mut Socket, value: serde_json::Value) -> Result<()> {
+    socket
+        .send(Message::Text(value.to_string().into()))
+        .await
+        .context("failed to write to the Home Assistant websocket")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state(entity_id: &str, state: &str) -> EntityState {
+        EntityState {
+            entity_id: entity_id.into(),
+            state: state.into(),
+            attributes: serde_json::Map::new(),
+            last_changed: None,
+        }
+    }
+
+    fn change(entity_id: &str, old: &str, new: &str) -> StateChange {
+        StateChange {
+            entity_id: entity_id.into(),
+            old_state: Some(state(entity_id, old)),
+            new_state: Some(state(entity_id, new)),
+        }
+    }
+
+    fn trigger(entities: &[&str], from: Option<&str>, to: Option<&str>) -> HomeAssistantTrigger {
+        HomeAssistantTrigger {
+            id: "front_door".into(),
+            entities: entities.iter().map(|entity| entity.to_string()).collect(),
+            from: from.map(String::from),
+            to: to.map(String::from),
+            prompt: "Someone opened the front door.".into(),
+            delivery_target: "discord:123".into(),
+            cooldown_secs: 60,
+            enabled: true,
+        }
+    }
+
+    #[test]
+    fn test_entity_allowed_globs() {
+        let patterns = vec!["light.*".to_string(), "sensor.*_temperature".to_string()];
+
+        assert!(entity_allowed(&patterns, "light.kitchen"));
+        assert!(entity_allowed(&patterns, "sensor.bedroom_temperature"));
+        assert!(!entity_allowed(&patterns, "sensor.bedroom_humidity"));
+        assert!(!entity_allowed(&patterns, "lock.front_door"));
+        assert!(!entity_allowed(&[], "light.kitchen"));
+        assert!(entity_allowed(&["*".to_string()], "lock.front_door"));
+        assert!(entity_allowed(
+            &["lock.front_door".to_string()],
+            "lock.front_door"
+        ));
+        assert!(!entity_allowed(
+            &["lock.front".to_string()],
+            "lock.front_door"
+        ));
+    }
+
+    #[test]
+    fn test_trigger_matches_state_transitions() {
+        let allowlist = vec!["binary_sensor.*".to_string()];
+        let opened = trigger(&["binary_sensor.front_door"], Some("off"), Some("on"));
+
+        assert!(trigger_matches(
+            &opened,
+            &allowlist,
+            &change("binary_sensor.front_door", "off", "on")
+        ));
+        assert!(!trigger_matches(
+            &opened,
+            &allowlist,
+            &change("binary_sensor.front_door", "on", "off")
+        ));
+        assert!(!trigger_matches(
+            &opened,
+            &allowlist,
+            &change("binary_sensor.back_door", "off", "on")
+        ));
+        // Attribute-only updates keep the same state.
+        assert!(!trigger_matches(
+            &trigger(&["binary_sensor.*"], None, None),
+            &allowlist,
+            &change("binary_sensor.front_door", "on", "on")
+        ));
+        // The trigger can't see past the agent's allowlist.
+        assert!(!trigger_matches(
+            &opened,
+            &["light.*".to_string()],
+            &change("binary_sensor.front_door", "off", "on")
+        ));
+    }
+
+    #[test]
+    fn test_websocket_url() {
+        let client = Client::new(&HomeAssistantConfig {
+            url: "https://ha.example.com/".into(),
+            token: Some("token".into()),
+            ..HomeAssistantConfig::default()
+        })
+        .unwrap();
+
+        assert_eq!(client.websocket_url(), "wss://ha.example.com/api/websocket");
+    }
+}
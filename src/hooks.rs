@@ -1,7 +1,11 @@
 //! Prompt hooks for observing and controlling agent behavior.
 
 pub mod cortex;
+pub mod loop_guard;
 pub mod spacebot;
+pub mod tool_guard;
 
 pub use cortex::CortexHook;
+pub use loop_guard::LoopGuard;
 pub use spacebot::SpacebotHook;
+pub use tool_guard::ToolGuard;
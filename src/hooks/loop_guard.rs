@@ -0,0 +1,297 @@
+//! Runaway loop detection for the agentic loop.
+//!
+//! A model stuck in a loop keeps spending tokens until it hits max turns,
+//! and workers continue across segments, so a loop can run for a long time.
+//! [`LoopGuard`] watches a process's completions and tool calls and trips
+//! when it sees one of three patterns:
+//!
+//! - the same tool called with the same arguments several times in a row,
+//! - several tool-calling rounds in a row that only got results the
+//!   process had already seen, i.e. no progress,
+//! - completions oscillating between two outputs.
+//!
+//! The hook turns a trip into a terminated turn with the detection as the
+//! reason.
+
+use crate::ProcessType;
+use crate::agent::cortex::CortexLogger;
+use crate::config::{LoopDetectionConfig, RuntimeConfig};
+
+use serde::Serialize;
+
+use std::collections::HashSet;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::sync::{Arc, Mutex};
+
+/// Which pattern tripped the guard.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LoopKind {
+    RepeatedToolCall,
+    NoProgress,
+    Oscillation,
+}
+
+impl LoopKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::RepeatedToolCall => "repeated_tool_call",
+            Self::NoProgress => "no_progress",
+            Self::Oscillation => "oscillation",
+        }
+    }
+}
+
+/// A detected loop.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Detection {
+    pub kind: LoopKind,
+    pub detail: String,
+}
+
+impl Detection {
+    /// The reason given when the turn is terminated.
+    pub fn reason(&self) -> String {
+        format!(
+            "Stopped a runaway loop: {}. Rethink the approach or report what is blocking you instead of repeating it.",
+            self.detail
+        )
+    }
+}
+
+#[derive(Debug, Default)]
+struct GuardState {
+    last_call: Option<u64>,
+    repeats: u32,
+    seen_results: HashSet<u64>,
+    round_has_results: bool,
+    round_made_progress: bool,
+    stalled_rounds: u32,
+    /// Fingerprints of the most recent completions, oldest first.
+    recent_completions: Vec<u64>,
+    tripped: Option<Detection>,
+}
+
+impl GuardState {
+    fn tool_call(
+        &mut self,
+        config: &LoopDetectionConfig,
+        tool_name: &str,
+        args: &str,
+    ) -> Option<Detection> {
+        let call = fingerprint(&(tool_name, args));
+        if self.last_call == Some(call) {
+            self.repeats += 1;
+        } else {
+            self.last_call = Some(call);
+            self.repeats = 1;
+        }
+        if config.max_repeated_calls == 0 || self.repeats < config.max_repeated_calls {
+            return None;
+        }
+        let detail = format!(
+            "{tool_name} was called {} times in a row with the same arguments",
+            self.repeats
+        );
+        Some(self.trip(LoopKind::RepeatedToolCall, detail))
+    }
+
+    fn tool_result(&mut self, tool_name: &str, args: &str, result: &str) {
+        self.round_has_results = true;
+        if self
+            .seen_results
+            .insert(fingerprint(&(tool_name, args, result)))
+        {
+            self.round_made_progress = true;
+        }
+    }
+
+    fn finish_round(&mut self, config: &LoopDetectionConfig) -> Option<Detection> {
+        if self.tripped.is_some() || !self.round_has_results {
+            return None;
+        }
+        if self.round_made_progress {
+            self.stalled_rounds = 0;
+        } else {
+            self.stalled_rounds += 1;
+        }
+        self.round_has_results = false;
+        self.round_made_progress = false;
+
+        if config.max_stalled_rounds == 0 || self.stalled_rounds < config.max_stalled_rounds {
+            return None;
+        }
+        let detail = format!(
+            "the last {} rounds of tool calls returned nothing new",
+            self.stalled_rounds
+        );
+        Some(self.trip(LoopKind::NoProgress, detail))
+    }
+
+    fn completion(&mut self, output_fingerprint: u64) -> Option<Detection> {
+        let recent = &mut self.recent_completions;
+        recent.push(output_fingerprint);
+        if recent.len() > 4 {
+            recent.remove(0);
+        }
+        let oscillating = matches!(recent.as_slice(), [a, b, c, d] if a == c && b == d && a != b);
+        if !oscillating {
+            return None;
+        }
+        let detail = "the model alternated between the same two responses".to_string();
+        Some(self.trip(LoopKind::Oscillation, detail))
+    }
+
+    fn trip(&mut self, kind: LoopKind, detail: String) -> Detection {
+        let detection = Detection { kind, detail };
+        self.tripped = Some(detection.clone());
+        detection
+    }
+}
+
+/// Per-process loop detector. Clones share state, so the copy handed to
+/// each prompt call sees the whole history.
+#[derive(Clone)]
+pub struct LoopGuard {
+    runtime_config: Arc<RuntimeConfig>,
+    audit: CortexLogger,
+    state: Arc<Mutex<GuardState>>,
+}
+
+impl LoopGuard {
+    pub fn new(runtime_config: Arc<RuntimeConfig>, audit: CortexLogger) -> Self {
+        Self {
+            runtime_config,
+            audit,
+            state: Arc::default(),
+        }
+    }
+
+    /// Forget everything seen so far, at the start of a new turn.
+    pub fn reset(&self) {
+        *self.lock() = GuardState::default();
+    }
+
+    /// The detection that tripped the guard, if any. Stays set until reset.
+    pub fn tripped(&self) -> Option<Detection> {
+        self.lock().tripped.clone()
+    }
+
+    /// Record a detection in the cortex event log, for auditing.
+    pub fn audit(&self, process_id: &str, process_type: ProcessType, detection: &Detection) {
+        self.audit.log(
+            "loop_detected",
+            &format!("Stopped {process_type} {process_id}: {}", detection.detail),
+            Some(serde_json::json!({
+                "process_id": process_id,
+                "process_type": process_type.to_string(),
+                "kind": detection.kind.as_str(),
+                "detail": detection.detail,
+            })),
+        );
+    }
+
+    /// Note a tool call. Trips on the configured number of identical calls
+    /// in a row.
+    pub fn observe_tool_call(&self, tool_name: &str, args: &str) -> Option<Detection> {
+        let config = self.config()?;
+        self.lock().tool_call(&config, tool_name, args)
+    }
+
+    /// Note a tool result. A round makes progress if any of its results is
+    /// new.
+    pub fn observe_tool_result(&self, tool_name: &str, args: &str, result: &str) {
+        if self.config().is_some() {
+            self.lock().tool_result(tool_name, args, result);
+        }
+    }
+
+    /// Close the current tool round before the next completion request.
+    /// Trips after the configured number of rounds in a row without a new
+    /// result.
+    pub fn finish_round(&self) -> Option<Detection> {
+        let config = self.config()?;
+        self.lock().finish_round(&config)
+    }
+
+    /// Note a completion by the fingerprint of its output. Trips when the
+    /// last four completions alternate between two outputs.
+    pub fn observe_completion(&self, output_fingerprint: u64) -> Option<Detection> {
+        self.config()?;
+        self.lock().completion(output_fingerprint)
+    }
+
+    fn config(&self) -> Option<LoopDetectionConfig> {
+        let config = **self.runtime_config.loop_detection.load();
+        config.enabled.then_some(config)
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, GuardState> {
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// Hash of anything hashable, for comparing calls, results and outputs
+/// without keeping them around.
+pub fn fingerprint(value: &impl Hash) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    value.hash(&mut hasher);
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: LoopDetectionConfig = LoopDetectionConfig {
+        enabled: true,
+        max_repeated_calls: 3,
+        max_stalled_rounds: 2,
+    };
+
+    #[test]
+    fn test_repeated_calls_trip_only_in_a_row() {
+        let mut state = GuardState::default();
+        assert!(state.tool_call(&CONFIG, "shell", "ls").is_none());
+        assert!(state.tool_call(&CONFIG, "shell", "ls").is_none());
+        assert!(state.tool_call(&CONFIG, "file", "a").is_none());
+        assert!(state.tool_call(&CONFIG, "shell", "ls").is_none());
+        assert!(state.tool_call(&CONFIG, "shell", "ls").is_none());
+        let detection = state.tool_call(&CONFIG, "shell", "ls").unwrap();
+        assert_eq!(detection.kind, LoopKind::RepeatedToolCall);
+        assert_eq!(state.tripped, Some(detection));
+    }
+
+    #[test]
+    fn test_rounds_without_new_results_trip() {
+        let mut state = GuardState::default();
+        state.tool_result("file", "a", "one");
+        assert!(state.finish_round(&CONFIG).is_none());
+        state.tool_result("file", "b", "two");
+        assert!(state.finish_round(&CONFIG).is_none());
+        state.tool_result("file", "a", "one");
+        assert!(state.finish_round(&CONFIG).is_none());
+        state.tool_result("file", "b", "two");
+        let detection = state.finish_round(&CONFIG).unwrap();
+        assert_eq!(detection.kind, LoopKind::NoProgress);
+    }
+
+    #[test]
+    fn test_oscillating_completions_trip() {
+        let mut state = GuardState::default();
+        assert!(state.completion(1).is_none());
+        assert!(state.completion(2).is_none());
+        assert!(state.completion(1).is_none());
+        assert_eq!(
+            state.completion(2).map(|detection| detection.kind),
+            Some(LoopKind::Oscillation)
+        );
+
+        let mut state = GuardState::default();
+        for _ in 0..4 {
+            assert!(state.completion(7).is_none());
+        }
+    }
+}
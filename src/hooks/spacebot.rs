@@ -1,9 +1,30 @@
 //! SpacebotHook: Prompt hook for channels, branches, and workers.
 
+use crate::agent::turn::TurnRecorder;
+use crate::approval::{Decision, PreviewGate};
+use crate::conversation::ReplyAttribution;
+use crate::hooks::loop_guard::{Detection, LoopGuard, fingerprint};
+use crate::hooks::tool_guard::{GuardAction, ToolGuard};
+use crate::messaging::impersonation;
 use crate::{AgentId, ChannelId, ProcessEvent, ProcessId, ProcessType};
 use rig::agent::{HookAction, PromptHook, ToolCallHookAction};
 use rig::completion::{CompletionModel, CompletionResponse, Message};
-use tokio::sync::broadcast;
+use rig::message::AssistantContent;
+use spacebot_core::events::{Event, EventBus};
+use spacebot_core::llm::latency_budget::LatencyBudget;
+use spacebot_core::llm::model::RawResponse;
+use spacebot_core::redact::LEAK_PATTERNS;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tokio::sync::{RwLock, broadcast};
+
+/// How Rig reports a tool that returned an error, in place of its output.
+const TOOL_ERROR_PREFIX: &str = "Toolset error:";
+
+/// Tools that answer the user, which a hurrying turn always lets through.
+const ANSWER_TOOLS: [&str; 3] = ["reply", "react", "skip"];
 
 /// Hook for observing agent behavior and sending events.
 #[derive(Clone)]
@@ -13,6 +34,22 @@ pub struct SpacebotHook {
     process_type: ProcessType,
     channel_id: Option<ChannelId>,
     event_tx: broadcast::Sender<ProcessEvent>,
+    /// Shared bus for tool events, when the process has one.
+    events: Option<EventBus>,
+    /// Where to record the latest completion, for attributing replies.
+    last_completion: Option<Arc<RwLock<Option<ReplyAttribution>>>>,
+    /// Collects completions and tool calls into a turn outcome.
+    turn: Option<TurnRecorder>,
+    /// Holds side-effecting tool calls until the user confirms a preview.
+    preview: Option<PreviewGate>,
+    /// Stops the turn when the model loops without making progress.
+    loop_guard: Option<LoopGuard>,
+    /// Checks high-risk tool calls against the process's task.
+    tool_guard: Option<ToolGuard>,
+    /// Limits tool fan-out once the turn is short on time.
+    latency_budget: Option<Arc<LatencyBudget>>,
+    /// When each running tool call started, by Rig's internal call id.
+    tool_started: Arc<Mutex<HashMap<String, Instant>>>,
 }
 
 impl SpacebotHook {
@@ -30,6 +67,64 @@ impl SpacebotHook {
             process_type,
             channel_id,
             event_tx,
+            events: None,
+            last_completion: None,
+            turn: None,
+            preview: None,
+            loop_guard: None,
+            tool_guard: None,
+            latency_budget: None,
+            tool_started: Arc::default(),
+        }
+    }
+
+    /// Also publish tool executions to the shared event bus.
+    pub fn with_events(mut self, events: EventBus) -> Self {
+        self.events = Some(events);
+        self
+    }
+
+    /// Record each completion's request id, model and usage into `slot`.
+    pub fn with_last_completion(mut self, slot: Arc<RwLock<Option<ReplyAttribution>>>) -> Self {
+        self.last_completion = Some(slot);
+        self
+    }
+
+    /// Record completions and tool calls into `recorder`.
+    pub fn with_turn_recorder(mut self, recorder: TurnRecorder) -> Self {
+        self.turn = Some(recorder);
+        self
+    }
+
+    /// Ask the user to confirm gated tool calls before they run.
+    pub fn with_preview_gate(mut self, gate: Option<PreviewGate>) -> Self {
+        self.preview = gate;
+        self
+    }
+
+    /// Stop turns that loop without making progress.
+    pub fn with_loop_guard(mut self, guard: LoopGuard) -> Self {
+        self.loop_guard = Some(guard);
+        self
+    }
+
+    /// Have a guard model check high-risk tool calls before they run.
+    pub fn with_tool_guard(mut self, guard: Option<ToolGuard>) -> Self {
+        self.tool_guard = guard;
+        self
+    }
+
+    /// Skip tool calls past the budget's limit per completion once the
+    /// turn is short on time.
+    pub fn with_latency_budget(mut self, budget: Option<Arc<LatencyBudget>>) -> Self {
+        self.latency_budget = budget;
+        self
+    }
+
+    /// Forget the loop guard's history, at the start of a new turn.
+    pub fn reset_loop_guard(&self) {
+        if let Some(guard) = &self.loop_guard {
+            guard.reset();
         }
     }
 
@@ -45,37 +140,6 @@ impl SpacebotHook {
 
     /// Scan content for potential secret leaks.
     fn scan_for_leaks(&self, content: &str) -> Option<String> {
-        use regex::Regex;
-        use std::sync::LazyLock;
-
-        static LEAK_PATTERNS: LazyLock<Vec<Regex>> = LazyLock::new(|| {
-            vec![
-                // OpenAI keys
-                Regex::new(r"sk-[a-zA-Z0-9]{20,}").expect("hardcoded regex"),
-                // Anthropic keys
-                Regex::new(r"sk-ant-[a-zA-Z0-9_-]{20,}").expect("hardcoded regex"),
-                // OpenRouter keys
-                Regex::new(r"sk-or-[a-zA-Z0-9_-]{20,}").expect("hardcoded regex"),
-                // PEM private keys
-                Regex::new(r"-----BEGIN.*PRIVATE KEY-----").expect("hardcoded regex"),
-                // GitHub personal access tokens
-                Regex::new(r"ghp_[a-zA-Z0-9]{36}").expect("hardcoded regex"),
-                // Google API keys
-                Regex::new(r"AIza[0-9A-Za-z_-]{35}").expect("hardcoded regex"),
-                // Discord bot tokens (base64 user ID . timestamp . HMAC)
-                Regex::new(r"[MN][A-Za-z0-9]{23,}\.[A-Za-z0-9_-]{6}\.[A-Za-z0-9_-]{27,}")
-                    .expect("hardcoded regex"),
-                // Slack bot tokens
-                Regex::new(r"xoxb-[0-9]{10,}-[0-9A-Za-z-]+").expect("hardcoded regex"),
-                // Slack app tokens
-                Regex::new(r"xapp-[0-9]-[A-Z0-9]+-[0-9]+-[a-f0-9]+").expect("hardcoded regex"),
-                // Telegram bot tokens
-                Regex::new(r"\d{8,}:[A-Za-z0-9_-]{35}").expect("hardcoded regex"),
-                // Brave Search API keys
-                Regex::new(r"BSA[a-zA-Z0-9]{20,}").expect("hardcoded regex"),
-            ]
-        });
-
         for pattern in LEAK_PATTERNS.iter() {
             if let Some(matched) = pattern.find(content) {
                 return Some(matched.as_str().to_string());
@@ -84,11 +148,112 @@ impl SpacebotHook {
 
         None
     }
+
+    fn lock_tool_started(&self) -> std::sync::MutexGuard<'_, HashMap<String, Instant>> {
+        self.tool_started
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Log, audit and publish a loop the guard just detected.
+    fn report_loop(&self, guard: &LoopGuard, detection: &Detection) {
+        tracing::warn!(
+            process_id = %self.process_id,
+            process_type = %self.process_type,
+            kind = detection.kind.as_str(),
+            detail = %detection.detail,
+            "runaway loop detected, stopping"
+        );
+        let process_id = self.process_id.to_string();
+        guard.audit(&process_id, self.process_type, detection);
+        if let Some(events) = &self.events {
+            events.publish(Event::LoopDetected {
+                agent_id: self.agent_id.to_string(),
+                process_id,
+                process_type: self.process_type,
+                kind: detection.kind.as_str().to_string(),
+                detail: detection.detail.clone(),
+            });
+        }
+    }
+
+    /// Post a preview of the call to the channel and wait for the user's
+    /// decision. Returns the reason to give the model when the call should
+    /// not run.
+    async fn await_confirmation(
+        &self,
+        gate: &PreviewGate,
+        tool_name: &str,
+        args: &str,
+    ) -> Option<String> {
+        let Some(channel_id) = &self.channel_id else {
+            return Some(format!(
+                "{tool_name} needs the user's confirmation, but this process has no channel to ask in."
+            ));
+        };
+
+        let preview = crate::approval::preview::render(tool_name, args, &gate.workspace).await;
+        let (action_id, decision) = gate.approvals.request(channel_id, tool_name);
+        let _ = self.event_tx.send(ProcessEvent::ActionPreview {
+            agent_id: self.agent_id.clone(),
+            process_id: self.process_id.clone(),
+            channel_id: channel_id.clone(),
+            action_id: action_id.clone(),
+            tool_name: tool_name.to_string(),
+            preview,
+        });
+        self.send_status(format!("waiting for confirmation of {tool_name}"));
+
+        let decision = tokio::time::timeout(gate.timeout, decision).await;
+        tracing::info!(
+            process_id = %self.process_id,
+            %tool_name,
+            %action_id,
+            ?decision,
+            "preview decided"
+        );
+        match decision {
+            Ok(Ok(Decision::Approved)) => None,
+            Ok(Ok(Decision::Rejected)) => Some(format!(
+                "The user rejected this {tool_name} call. Do not retry it unless they ask."
+            )),
+            Ok(Err(_)) | Err(_) => {
+                gate.approvals.forget(&action_id);
+                Some(format!(
+                    "The user did not confirm this {tool_name} call in time, so it was not run."
+                ))
+            }
+        }
+    }
+}
+
+/// A tool result with its image payload, if it is an image, replaced by its
+/// size. Screenshots are base64, where credential patterns turn up by
+/// chance, and the data is no use in traces or events.
+fn without_image_data(result: &str) -> Cow<'_, str> {
+    if !result.contains(r#""image""#) {
+        return Cow::Borrowed(result);
+    }
+    let Ok(mut value) = serde_json::from_str::<serde_json::Value>(result) else {
+        return Cow::Borrowed(result);
+    };
+    let Some(image) = value
+        .as_object_mut()
+        .filter(|object| object.get("type").and_then(|kind| kind.as_str()) == Some("image"))
+    else {
+        return Cow::Borrowed(result);
+    };
+    let bytes = image
+        .get("data")
+        .and_then(|data| data.as_str())
+        .map_or(0, str::len);
+    image.insert("data".into(), format!("[{bytes} bytes]").into());
+    Cow::Owned(value.to_string())
 }
 
 impl<M> PromptHook<M> for SpacebotHook
 where
-    M: CompletionModel,
+    M: CompletionModel<Response = RawResponse>,
 {
     async fn on_completion_call(&self, _prompt: &Message, _history: &[Message]) -> HookAction {
         // Log the completion call but don't block it
@@ -98,6 +263,21 @@ where
             "completion call started"
         );
 
+        if let Some(budget) = &self.latency_budget {
+            budget.start_round();
+        }
+
+        if let Some(guard) = &self.loop_guard {
+            if let Some(detection) = guard.finish_round() {
+                self.report_loop(guard, &detection);
+            }
+            if let Some(detection) = guard.tripped() {
+                return HookAction::Terminate {
+                    reason: detection.reason(),
+                };
+            }
+        }
+
         HookAction::Continue
     }
 
@@ -115,6 +295,57 @@ where
             "completion response received"
         );
 
+        if let Some(slot) = &self.last_completion {
+            *slot.write().await = Some(ReplyAttribution {
+                request_id: response.raw_response.request_id.clone(),
+                model: response.raw_response.model.clone(),
+                input_tokens: response.usage.input_tokens,
+                output_tokens: response.usage.output_tokens,
+                cost_usd: response.raw_response.cost_usd,
+                citations: crate::citations::from_provider_response(&response.raw_response.body),
+            });
+        }
+
+        if let Some(turn) = &self.turn {
+            turn.record_completion(
+                response.raw_response.request_id.clone(),
+                response.raw_response.model.clone(),
+                response.raw_response.provider_request_id.clone(),
+                response.raw_response.seed,
+                response.raw_response.fallback,
+                response.usage.input_tokens,
+                response.usage.output_tokens,
+                response.raw_response.cost_usd,
+            );
+            turn.record_prompt_tokens(
+                response.raw_response.prompt_tokens,
+                response.usage.input_tokens,
+            );
+        }
+
+        if let Some(guard) = &self.loop_guard {
+            // Tool call ids differ on every response, so only the text and
+            // the calls themselves count.
+            let output: Vec<(&str, String)> = response
+                .choice
+                .iter()
+                .filter_map(|content| match content {
+                    AssistantContent::Text(text) => Some(("text", text.text.clone())),
+                    AssistantContent::ToolCall(call) => Some((
+                        call.function.name.as_str(),
+                        call.function.arguments.to_string(),
+                    )),
+                    _ => None,
+                })
+                .collect();
+            if let Some(detection) = guard.observe_completion(fingerprint(&output)) {
+                self.report_loop(guard, &detection);
+                return HookAction::Terminate {
+                    reason: detection.reason(),
+                };
+            }
+        }
+
         HookAction::Continue
     }
 
@@ -122,7 +353,7 @@ where
         &self,
         tool_name: &str,
         _tool_call_id: Option<String>,
-        _internal_call_id: &str,
+        internal_call_id: &str,
         args: &str,
     ) -> ToolCallHookAction {
         // Scan tool arguments for secrets before execution
@@ -138,6 +369,100 @@ where
             };
         }
 
+        // A sandboxed impersonation must not reach anything real.
+        let sandboxed = self
+            .channel_id
+            .as_deref()
+            .is_some_and(impersonation::is_sandboxed);
+        if sandboxed && !impersonation::runs_in_sandbox(tool_name, args) {
+            tracing::info!(
+                process_id = %self.process_id,
+                %tool_name,
+                "skipping tool call in sandboxed conversation"
+            );
+            return ToolCallHookAction::Skip {
+                reason: "Skipped: this conversation is a sandbox, so tools that change anything outside it don't run.".into(),
+            };
+        }
+
+        if let Some(budget) = &self.latency_budget {
+            if !ANSWER_TOOLS.contains(&tool_name) && !budget.admit_tool_call() {
+                tracing::debug!(
+                    process_id = %self.process_id,
+                    %tool_name,
+                    "turn short on time, skipping tool call"
+                );
+                return ToolCallHookAction::Skip {
+                    reason: "Skipped: this turn is nearly out of time. Reply with what you have, or hand the rest to a worker.".into(),
+                };
+            }
+        }
+
+        // A call the user confirmed for the guard isn't previewed again.
+        let mut confirmed = false;
+        let tool_guard = self
+            .tool_guard
+            .as_ref()
+            .filter(|guard| guard.applies_to(tool_name, args));
+        if let Some(guard) = tool_guard {
+            let verdict = guard.check(tool_name, args).await;
+            tracing::info!(
+                process_id = %self.process_id,
+                %tool_name,
+                action = verdict.action.as_str(),
+                score = ?verdict.score,
+                reason = %verdict.reason,
+                "tool guard verdict"
+            );
+            match verdict.action {
+                GuardAction::Allow => {}
+                GuardAction::Block => {
+                    return ToolCallHookAction::Skip {
+                        reason: verdict.block_reason(tool_name),
+                    };
+                }
+                GuardAction::Confirm => {
+                    if let Some(reason) = self
+                        .await_confirmation(&guard.escalation, tool_name, args)
+                        .await
+                    {
+                        return ToolCallHookAction::Skip { reason };
+                    }
+                    confirmed = true;
+                }
+            }
+        }
+
+        let gate = self
+            .preview
+            .as_ref()
+            .filter(|gate| !confirmed && gate.requires_confirmation(tool_name, args));
+        let rejection = match gate {
+            Some(gate) => self.await_confirmation(gate, tool_name, args).await,
+            None => None,
+        };
+        if let Some(reason) = rejection {
+            return ToolCallHookAction::Skip { reason };
+        }
+
+        if let Some(guard) = &self.loop_guard {
+            if let Some(detection) = guard.observe_tool_call(tool_name, args) {
+                self.report_loop(guard, &detection);
+            }
+            if let Some(detection) = guard.tripped() {
+                return ToolCallHookAction::Skip {
+                    reason: detection.reason(),
+                };
+            }
+        }
+
+        if let Some(turn) = &self.turn {
+            turn.record_tool_call(tool_name, args);
+        }
+
+        self.lock_tool_started()
+            .insert(internal_call_id.to_string(), Instant::now());
+
         // Send event without blocking
         let event = ProcessEvent::ToolStarted {
             agent_id: self.agent_id.clone(),
@@ -160,15 +485,23 @@ where
         &self,
         tool_name: &str,
         _tool_call_id: Option<String>,
-        _internal_call_id: &str,
-        _args: &str,
+        internal_call_id: &str,
+        args: &str,
         result: &str,
     ) -> HookAction {
+        let text = without_image_data(result);
+        let duration_ms = self
+            .lock_tool_started()
+            .remove(internal_call_id)
+            .map(|started| started.elapsed().as_millis() as u64)
+            .unwrap_or_default();
+        let success = !result.starts_with(TOOL_ERROR_PREFIX);
+
         // Scan for potential leaks in tool output and terminate if found.
         // The result is already in Rig's history at this point, but terminating
         // prevents the agent from forwarding the leaked content to external
         // services via subsequent tool calls.
-        if let Some(leak) = self.scan_for_leaks(result) {
+        if let Some(leak) = self.scan_for_leaks(&text) {
             tracing::error!(
                 process_id = %self.process_id,
                 tool_name = %tool_name,
@@ -181,23 +514,47 @@ where
             };
         }
 
+        if let Some(turn) = &self.turn {
+            turn.record_tool_result(tool_name, &text);
+        }
+
+        if let Some(guard) = &self.loop_guard {
+            guard.observe_tool_result(tool_name, args, result);
+        }
+
         // Cap the result stored in the broadcast event to avoid blowing up
         // event subscribers with multi-MB tool results.
         let capped_result =
-            crate::tools::truncate_output(result, crate::tools::MAX_TOOL_OUTPUT_BYTES);
+            crate::tools::truncate_output(&text, crate::tools::MAX_TOOL_OUTPUT_BYTES);
         let event = ProcessEvent::ToolCompleted {
             agent_id: self.agent_id.clone(),
             process_id: self.process_id.clone(),
             channel_id: self.channel_id.clone(),
             tool_name: tool_name.to_string(),
+            source: crate::agent::provenance::label(tool_name, args),
             result: capped_result,
         };
         let _ = self.event_tx.send(event);
 
+        if let Some(events) = &self.events {
+            events.publish(Event::ToolExecuted {
+                agent_id: self.agent_id.to_string(),
+                process_id: self.process_id.to_string(),
+                process_type: self.process_type,
+                channel_id: self.channel_id.as_ref().map(|id| id.to_string()),
+                tool_name: tool_name.to_string(),
+                result_bytes: result.len(),
+                duration_ms,
+                success,
+            });
+        }
+
         tracing::debug!(
             process_id = %self.process_id,
             tool_name = %tool_name,
             result_bytes = result.len(),
+            duration_ms,
+            success,
             "tool call completed"
         );
 
@@ -0,0 +1,290 @@
+//! Guard model checks on high-risk tool calls.
+//!
+//! Before a worker runs a call to one of the tools listed in
+//! `[defaults.tool_guard]` that changes something, a cheap model reads the
+//! worker's task and the proposed call and scores from 0 to 1 how consistent
+//! the call is with what was asked. The hook allows calls that score well,
+//! asks the user to confirm middling ones as in preview mode, and refuses
+//! the rest with the guard's reason. A guard that fails or times out counts
+//! as a middling score, so a broken guard model never lets a call through
+//! unchecked. [`GuardMetrics`] counts the verdicts for the API.
+
+use crate::ProcessType;
+use crate::approval::PreviewGate;
+use crate::config::{RuntimeConfig, ToolGuardConfig};
+use crate::llm::{LlmManager, Priority, SpacebotModel};
+
+use anyhow::Context as _;
+use rig::agent::AgentBuilder;
+use rig::completion::Prompt as _;
+use serde::{Deserialize, Serialize};
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Characters of the task and of the call's arguments shown to the guard.
+const MAX_PROMPT_CHARS: usize = 4000;
+
+/// What happens to a checked call.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GuardAction {
+    Allow,
+    /// The user has to confirm the call first.
+    Confirm,
+    Block,
+}
+
+impl GuardAction {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Allow => "allow",
+            Self::Confirm => "confirm",
+            Self::Block => "block",
+        }
+    }
+}
+
+/// The guard's decision on one call.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GuardVerdict {
+    pub action: GuardAction,
+    /// None when the guard model failed or timed out.
+    pub score: Option<f32>,
+    pub reason: String,
+}
+
+impl GuardVerdict {
+    fn scored(config: &ToolGuardConfig, score: f32, reason: String) -> Self {
+        let action = if score < config.block_below {
+            GuardAction::Block
+        } else if score < config.confirm_below {
+            GuardAction::Confirm
+        } else {
+            GuardAction::Allow
+        };
+        Self {
+            action,
+            score: Some(score),
+            reason,
+        }
+    }
+
+    fn unscored(reason: impl Into<String>) -> Self {
+        Self {
+            action: GuardAction::Confirm,
+            score: None,
+            reason: reason.into(),
+        }
+    }
+
+    /// The reason given to the model when the call is refused.
+    pub fn block_reason(&self, tool_name: &str) -> String {
+        format!(
+            "A safety check refused this {tool_name} call as inconsistent with your task: {} Do not retry it; if the task needs it, explain why in your result.",
+            self.reason
+        )
+    }
+}
+
+/// Counts of guard verdicts since startup, for the API.
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct GuardStats {
+    pub allowed: u64,
+    pub confirmed: u64,
+    pub blocked: u64,
+    /// Checks where the guard model failed or timed out. These calls needed
+    /// confirmation and aren't counted in `confirmed`.
+    pub failed: u64,
+}
+
+/// Per-agent verdict counts. Clones share state.
+#[derive(Debug, Clone, Default)]
+pub struct GuardMetrics {
+    stats: Arc<Mutex<GuardStats>>,
+}
+
+impl GuardMetrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn record(&self, verdict: &GuardVerdict) {
+        let mut stats = self
+            .stats
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        match (verdict.score, verdict.action) {
+            (None, _) => stats.failed += 1,
+            (Some(_), GuardAction::Allow) => stats.allowed += 1,
+            (Some(_), GuardAction::Confirm) => stats.confirmed += 1,
+            (Some(_), GuardAction::Block) => stats.blocked += 1,
+        }
+    }
+
+    pub fn stats(&self) -> GuardStats {
+        *self
+            .stats
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// Checks one worker's high-risk tool calls against its task. Built per
+/// worker by [`crate::AgentDeps::tool_guard`].
+#[derive(Clone)]
+pub struct ToolGuard {
+    runtime_config: Arc<RuntimeConfig>,
+    llm_manager: Arc<LlmManager>,
+    metrics: GuardMetrics,
+    /// The task calls are judged against.
+    task: Arc<str>,
+    /// Where calls the guard is unsure about are confirmed.
+    pub escalation: PreviewGate,
+}
+
+impl ToolGuard {
+    pub fn new(
+        runtime_config: Arc<RuntimeConfig>,
+        llm_manager: Arc<LlmManager>,
+        metrics: GuardMetrics,
+        task: &str,
+        escalation: PreviewGate,
+    ) -> Self {
+        Self {
+            runtime_config,
+            llm_manager,
+            metrics,
+            task: Arc::from(task),
+            escalation,
+        }
+    }
+
+    /// Whether a call to `tool_name` with `args` is checked. Read-only calls
+    /// through listed tools, like `file` reads, aren't.
+    pub fn applies_to(&self, tool_name: &str, args: &str) -> bool {
+        let config = self.runtime_config.tool_guard.load();
+        config.enabled
+            && config.tools.iter().any(|tool| tool == tool_name)
+            && crate::approval::preview::has_side_effects(tool_name, args)
+    }
+
+    /// Score the call and decide what happens to it.
+    pub async fn check(&self, tool_name: &str, args: &str) -> GuardVerdict {
+        let config = self.runtime_config.tool_guard.load_full();
+        let timeout = Duration::from_secs(config.timeout_secs);
+        let verdict =
+            match tokio::time::timeout(timeout, self.score(&config, tool_name, args)).await {
+                Ok(Ok((score, reason))) => GuardVerdict::scored(&config, score, reason),
+                Ok(Err(error)) => {
+                    tracing::warn!(%error, %tool_name, "tool guard failed");
+                    GuardVerdict::unscored("The safety check failed.")
+                }
+                Err(_) => {
+                    tracing::warn!(%tool_name, "tool guard timed out");
+                    GuardVerdict::unscored("The safety check timed out.")
+                }
+            };
+        self.metrics.record(&verdict);
+        verdict
+    }
+
+    async fn score(
+        &self,
+        config: &ToolGuardConfig,
+        tool_name: &str,
+        args: &str,
+    ) -> anyhow::Result<(f32, String)> {
+        let routing = self.runtime_config.routing.load();
+        let model_name = crate::agent::persona::resolve_model(&config.model, &routing)
+            .with_context(|| format!("unknown tool guard model '{}'", config.model))?;
+        let model = SpacebotModel::make(&self.llm_manager, &model_name)
+            .with_routing((**routing).clone())
+            .with_priority(Priority::for_process(ProcessType::Worker));
+        let agent = AgentBuilder::new(model)
+            .preamble(crate::prompts::text::get("tool_guard"))
+            .build();
+
+        let response = agent
+            .prompt(render_prompt(&self.task, tool_name, args))
+            .await
+            .context("tool guard model call failed")?;
+        parse_score(&response)
+    }
+}
+
+impl std::fmt::Debug for ToolGuard {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ToolGuard")
+            .field("stats", &self.metrics.stats())
+            .finish()
+    }
+}
+
+fn render_prompt(task: &str, tool_name: &str, args: &str) -> String {
+    let clip = |text: &str| text.chars().take(MAX_PROMPT_CHARS).collect::<String>();
+    format!(
+        "Request:\n{}\n\nTool call: {tool_name}\nArguments:\n{}",
+        clip(task),
+        clip(args)
+    )
+}
+
+#[derive(Deserialize)]
+struct Assessment {
+    score: f32,
+    #[serde(default)]
+    reason: String,
+}
+
+fn parse_score(response: &str) -> anyhow::Result<(f32, String)> {
+    let cleaned = response
+        .trim()
+        .trim_start_matches("```json")
+        .trim_start_matches("```")
+        .trim_end_matches("```")
+        .trim();
+    let assessment: Assessment = serde_json::from_str(cleaned)
+        .with_context(|| format!("tool guard reply isn't the requested JSON: {cleaned}"))?;
+    anyhow::ensure!(
+        assessment.score.is_finite(),
+        "tool guard score isn't a number"
+    );
+    Ok((assessment.score.clamp(0.0, 1.0), assessment.reason))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_score() {
+        assert_eq!(
+            parse_score("```json\n{\"score\": 0.9, \"reason\": \"Runs the tests.\"}\n```").unwrap(),
+            (0.9, "Runs the tests.".to_string())
+        );
+        assert_eq!(parse_score(r#"{"score": 3}"#).unwrap().0, 1.0);
+        assert!(parse_score("Looks fine to me.").is_err());
+    }
+
+    #[test]
+    fn test_verdicts_follow_thresholds_and_count() {
+        let config = ToolGuardConfig::default();
+        let verdict = |score| GuardVerdict::scored(&config, score, String::new());
+        assert_eq!(verdict(0.1).action, GuardAction::Block);
+        assert_eq!(verdict(0.3).action, GuardAction::Confirm);
+        assert_eq!(verdict(0.69).action, GuardAction::Confirm);
+        assert_eq!(verdict(0.7).action, GuardAction::Allow);
+
+        let metrics = GuardMetrics::new();
+        for score in [0.1, 0.5, 0.9, 1.0] {
+            metrics.record(&verdict(score));
+        }
+        metrics.record(&GuardVerdict::unscored("timed out"));
+        let stats = metrics.stats();
+        assert_eq!(
+            (stats.allowed, stats.confirmed, stats.blocked, stats.failed),
+            (2, 1, 1, 1)
+        );
+    }
+}
@@ -0,0 +1,651 @@
+//! PagerDuty and Opsgenie integration.
+//!
+//! [`Client`] wraps each provider's REST API behind the `incident` tool. Each
+//! configured account also accepts webhooks at
+//! `/api/incidents/{agent_id}/{account}`. When one reports a new page, the
+//! trigger's prompt runs through a fresh channel the same way a cron job
+//! runs, and the reply is delivered to the trigger's target.
+
+use crate::OutboundResponse;
+use crate::config::{IncidentAccount, IncidentProvider, IncidentTrigger};
+use crate::cron::CronContext;
+use crate::cron::scheduler::{DeliveryTarget, run_prompt};
+use crate::error::Result;
+
+use anyhow::Context as _;
+use hmac::{Hmac, Mac};
+use serde::Serialize;
+use serde_json::{Value, json};
+use sha2::Sha256;
+use std::time::Duration;
+
+const PAGERDUTY_API_URL: &str = "https://api.pagerduty.com";
+const OPSGENIE_API_URL: &str = "https://api.opsgenie.com";
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// PagerDuty signs webhook bodies with the subscription's secret and sends
+/// one or more `v1=<hex>` signatures in this header.
+const PAGERDUTY_SIGNATURE_HEADER: &str = "x-pagerduty-signature";
+
+/// Opsgenie can't sign webhooks, so the integration is set up to send the
+/// shared secret in this custom header.
+const OPSGENIE_TOKEN_HEADER: &str = "x-spacebot-token";
+
+/// Sent as the `source` of Opsgenie changes, so they're attributed in its log.
+const OPSGENIE_SOURCE: &str = "spacebot";
+
+/// A PagerDuty incident or Opsgenie alert.
+#[derive(Debug, Clone, Serialize)]
+pub struct Incident {
+    pub id: String,
+    /// Short number people refer to it by: PagerDuty's incident number or
+    /// Opsgenie's tiny ID.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub number: Option<String>,
+    pub title: String,
+    pub status: String,
+    /// PagerDuty urgency or Opsgenie priority.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub urgency: Option<String>,
+    /// PagerDuty service or Opsgenie entity it was raised against.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub service: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub created_at: Option<String>,
+}
+
+/// One entry of an incident's timeline: a state change, a notification or a
+/// note.
+#[derive(Debug, Clone, Serialize)]
+pub struct TimelineEntry {
+    pub at: String,
+    /// "note" for notes, otherwise the provider's log entry type.
+    pub kind: String,
+    pub summary: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub by: Option<String>,
+}
+
+/// REST client for one incident account.
+#[derive(Debug, Clone)]
+pub struct Client {
+    http: reqwest::Client,
+    account: IncidentAccount,
+}
+
+impl Client {
+    pub fn new(account: IncidentAccount) -> Self {
+        let http = reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .expect("hardcoded reqwest client config");
+        Self { http, account }
+    }
+
+    pub fn account(&self) -> &IncidentAccount {
+        &self.account
+    }
+
+    /// Triggered and acknowledged incidents (PagerDuty) or open alerts
+    /// (Opsgenie), newest first.
+    pub async fn open_incidents(&self, limit: usize) -> Result<Vec<Incident>> {
+        let limit = limit.to_string();
+        let incidents = match self.account.provider {
+            IncidentProvider::Pagerduty => {
+                let response = self
+                    .send(self.request(reqwest::Method::GET, "/incidents").query(&[
+                        ("statuses[]", "triggered"),
+                        ("statuses[]", "acknowledged"),
+                        ("sort_by", "created_at:desc"),
+                        ("limit", limit.as_str()),
+                    ]))
+                    .await?;
+                items(&response["incidents"])
+                    .iter()
+                    .map(pagerduty_incident)
+                    .collect()
+            }
+            IncidentProvider::Opsgenie => {
+                let response = self
+                    .send(self.request(reqwest::Method::GET, "/v2/alerts").query(&[
+                        ("query", "status:open"),
+                        ("sort", "createdAt"),
+                        ("order", "desc"),
+                        ("limit", limit.as_str()),
+                    ]))
+                    .await?;
+                items(&response["data"])
+                    .iter()
+                    .map(opsgenie_alert)
+                    .collect()
+            }
+        };
+        Ok(incidents)
+    }
+
+    pub async fn incident(&self, id: &str) -> Result<Incident> {
+        Ok(match self.account.provider {
+            IncidentProvider::Pagerduty => {
+                let response = self
+                    .send(self.request(reqwest::Method::GET, &format!("/incidents/{id}")))
+                    .await?;
+                pagerduty_incident(&response["incident"])
+            }
+            IncidentProvider::Opsgenie => {
+                let response = self
+                    .send(self.request(reqwest::Method::GET, &format!("/v2/alerts/{id}")))
+                    .await?;
+                opsgenie_alert(&response["data"])
+            }
+        })
+    }
+
+    /// The latest `limit` log entries and notes of an incident, oldest first.
+    pub async fn timeline(&self, id: &str, limit: usize) -> Result<Vec<TimelineEntry>> {
+        let limit_param = limit.to_string();
+        let mut entries: Vec<TimelineEntry> = match self.account.provider {
+            IncidentProvider::Pagerduty => {
+                let log = self
+                    .send(
+                        self.request(
+                            reqwest::Method::GET,
+                            &format!("/incidents/{id}/log_entries"),
+                        )
+                        .query(&[("is_overview", "true"), ("limit", limit_param.as_str())]),
+                    )
+                    .await?;
+                let notes = self
+                    .send(self.request(reqwest::Method::GET, &format!("/incidents/{id}/notes")))
+                    .await?;
+                items(&log["log_entries"])
+                    .iter()
+                    .map(|entry| TimelineEntry {
+                        at: text(&entry["created_at"]).unwrap_or_default(),
+                        kind: text(&entry["type"])
+                            .map(|kind| kind.trim_end_matches("_log_entry").to_string())
+                            .unwrap_or_default(),
+                        summary: text(&entry["summary"]).unwrap_or_default(),
+                        by: text(&entry["agent"]["summary"]),
+                    })
+                    .chain(items(&notes["notes"]).iter().map(|note| TimelineEntry {
+                        at: text(&note["created_at"]).unwrap_or_default(),
+                        kind: "note".into(),
+                        summary: text(&note["content"]).unwrap_or_default(),
+                        by: text(&note["user"]["summary"]),
+                    }))
+                    .collect()
+            }
+            IncidentProvider::Opsgenie => {
+                let query = [("order", "desc"), ("limit", limit_param.as_str())];
+                let log = self
+                    .send(
+                        self.request(reqwest::Method::GET, &format!("/v2/alerts/{id}/logs"))
+                            .query(&query),
+                    )
+                    .await?;
+                let notes = self
+                    .send(
+                        self.request(reqwest::Method::GET, &format!("/v2/alerts/{id}/notes"))
+                            .query(&query),
+                    )
+                    .await?;
+                items(&log["data"])
+                    .iter()
+                    .map(|entry| TimelineEntry {
+                        at: opsgenie_time(&entry["createdAt"]).unwrap_or_default(),
+                        kind: text(&entry["type"]).unwrap_or_default(),
+                        summary: text(&entry["log"]).unwrap_or_default(),
+                        by: text(&entry["owner"]),
+                    })
+                    .chain(items(&notes["data"]).iter().map(|note| TimelineEntry {
+                        at: opsgenie_time(&note["createdAt"]).unwrap_or_default(),
+                        kind: "note".into(),
+                        summary: text(&note["note"]).unwrap_or_default(),
+                        by: text(&note["owner"]),
+                    }))
+                    .collect()
+            }
+        };
+
+        // RFC 3339 times in the same zone sort as strings.
+        entries.sort_by(|a, b| a.at.cmp(&b.at));
+        let skip = entries.len().saturating_sub(limit);
+        Ok(entries.split_off(skip))
+    }
+
+    pub async fn acknowledge(&self, id: &str) -> Result<()> {
+        self.change_status(id, "acknowledged", "acknowledge").await
+    }
+
+    pub async fn resolve(&self, id: &str) -> Result<()> {
+        self.change_status(id, "resolved", "close").await
+    }
+
+    pub async fn add_note(&self, id: &str, note: &str) -> Result<()> {
+        let request = match self.account.provider {
+            IncidentProvider::Pagerduty => self
+                .request(reqwest::Method::POST, &format!("/incidents/{id}/notes"))
+                .json(&json!({"note": {"content": note}})),
+            IncidentProvider::Opsgenie => self
+                .request(reqwest::Method::POST, &format!("/v2/alerts/{id}/notes"))
+                .json(&json!({"note": note, "source": OPSGENIE_SOURCE})),
+        };
+        self.send(request).await?;
+        Ok(())
+    }
+
+    /// Move an incident to `status` (PagerDuty) or run `alert_action` on the
+    /// alert (Opsgenie).
+    async fn change_status(&self, id: &str, status: &str, alert_action: &str) -> Result<()> {
+        let request = match self.account.provider {
+            IncidentProvider::Pagerduty => self
+                .request(reqwest::Method::PUT, &format!("/incidents/{id}"))
+                .json(&json!({"incident": {"type": "incident_reference", "status": status}})),
+            IncidentProvider::Opsgenie => self
+                .request(
+                    reqwest::Method::POST,
+                    &format!("/v2/alerts/{id}/{alert_action}"),
+                )
+                .json(&json!({"source": OPSGENIE_SOURCE})),
+        };
+        self.send(request).await?;
+        Ok(())
+    }
+
+    fn request(&self, method: reqwest::Method, path: &str) -> reqwest::RequestBuilder {
+        let base = self
+            .account
+            .url
+            .as_deref()
+            .unwrap_or(match self.account.provider {
+                IncidentProvider::Pagerduty => PAGERDUTY_API_URL,
+                IncidentProvider::Opsgenie => OPSGENIE_API_URL,
+            });
+        let request = self.http.request(method, format!("{base}{path}"));
+        match self.account.provider {
+            IncidentProvider::Pagerduty => {
+                let request = request
+                    .header(
+                        reqwest::header::AUTHORIZATION,
+                        format!("Token token={}", self.account.token),
+                    )
+                    .header(
+                        reqwest::header::ACCEPT,
+                        "application/vnd.pagerduty+json;version=2",
+                    );
+                match &self.account.email {
+                    Some(email) => request.header(reqwest::header::FROM, email),
+                    None => request,
+                }
+            }
+            IncidentProvider::Opsgenie => request.header(
+                reqwest::header::AUTHORIZATION,
+                format!("GenieKey {}", self.account.token),
+            ),
+        }
+    }
+
+    async fn send(&self, request: reqwest::RequestBuilder) -> Result<Value> {
+        let provider = self.account.provider.as_str();
+        let response = request
+            .send()
+            .await
+            .with_context(|| format!("{provider} request failed"))?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            return Err(anyhow::anyhow!("{provider} returned {status}: {body}").into());
+        }
+
+        let body = response
+            .text()
+            .await
+            .with_context(|| format!("failed to read {provider} response"))?;
+        if body.trim().is_empty() {
+            return Ok(Value::Null);
+        }
+        Ok(serde_json::from_str(&body).with_context(|| format!("invalid {provider} response"))?)
+    }
+}
+
+fn items(value: &Value) -> &[Value] {
+    value.as_array().map(Vec::as_slice).unwrap_or_default()
+}
+
+/// A string or number field as text.
+fn text(value: &Value) -> Option<String> {
+    match value {
+        Value::String(text) if !text.is_empty() => Some(text.clone()),
+        Value::Number(number) => Some(number.to_string()),
+        _ => None,
+    }
+}
+
+/// Opsgenie times are RFC 3339 in the REST API and epoch milliseconds in
+/// webhooks.
+fn opsgenie_time(value: &Value) -> Option<String> {
+    match value.as_i64() {
+        Some(millis) => chrono::DateTime::from_timestamp_millis(millis).map(|at| at.to_rfc3339()),
+        None => text(value),
+    }
+}
+
+fn pagerduty_incident(value: &Value) -> Incident {
+    Incident {
+        id: text(&value["id"]).unwrap_or_default(),
+        number: text(&value["incident_number"]).or_else(|| text(&value["number"])),
+        title: text(&value["title"]).unwrap_or_default(),
+        status: text(&value["status"]).unwrap_or_default(),
+        urgency: text(&value["urgency"]),
+        service: text(&value["service"]["summary"]),
+        url: text(&value["html_url"]),
+        created_at: text(&value["created_at"]),
+    }
+}
+
+fn opsgenie_alert(value: &Value) -> Incident {
+    let status = match (text(&value["status"]), value["acknowledged"].as_bool()) {
+        (Some(status), Some(true)) if status == "open" => "acknowledged".to_string(),
+        (Some(status), _) => status,
+        // Webhooks for new alerts don't carry a status.
+        (None, _) => "open".to_string(),
+    };
+    Incident {
+        id: text(&value["id"])
+            .or_else(|| text(&value["alertId"]))
+            .unwrap_or_default(),
+        number: text(&value["tinyId"]),
+        title: text(&value["message"]).unwrap_or_default(),
+        status,
+        urgency: text(&value["priority"]),
+        service: text(&value["entity"]),
+        url: None,
+        created_at: opsgenie_time(&value["createdAt"]),
+    }
+}
+
+/// Whether a webhook `body` with `headers` really comes from `account`'s
+/// provider. Accounts without a webhook secret accept nothing.
+pub fn webhook_authentic(
+    account: &IncidentAccount,
+    headers: &axum::http::HeaderMap,
+    body: &[u8],
+) -> bool {
+    let Some(secret) = account.webhook_secret.as_deref() else {
+        return false;
+    };
+    let header = |name: &str| headers.get(name).and_then(|value| value.to_str().ok());
+    match account.provider {
+        IncidentProvider::Pagerduty => header(PAGERDUTY_SIGNATURE_HEADER)
+            .is_some_and(|signatures| pagerduty_signature_valid(secret, body, signatures)),
+        IncidentProvider::Opsgenie => {
+            header(OPSGENIE_TOKEN_HEADER).is_some_and(|token| constant_time_eq(token, secret))
+        }
+    }
+}
+
+/// Check PagerDuty's comma-separated `v1=<hex HMAC-SHA256>` signatures. Any
+/// one matching is enough, since PagerDuty signs with both secrets while one
+/// is being rotated.
+fn pagerduty_signature_valid(secret: &str, body: &[u8], signatures: &str) -> bool {
+    signatures
+        .split(',')
+        .filter_map(|signature| signature.trim().strip_prefix("v1="))
+        .filter_map(decode_hex)
+        .any(|signature| {
+            let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())
+                .expect("HMAC accepts any key length");
+            mac.update(body);
+            mac.verify_slice(&signature).is_ok()
+        })
+}
+
+fn decode_hex(text: &str) -> Option<Vec<u8>> {
+    if text.len() % 2 != 0 {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|index| u8::from_str_radix(text.get(index..index + 2)?, 16).ok())
+        .collect()
+}
+
+fn constant_time_eq(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.bytes()
+            .zip(b.bytes())
+            .fold(0, |difference, (x, y)| difference | (x ^ y))
+            == 0
+}
+
+/// The incident a webhook reports as newly paged, or None for any other
+/// event (acknowledgements, resolutions, notes).
+pub fn parse_page(provider: IncidentProvider, body: &[u8]) -> Option<Incident> {
+    let payload: Value = serde_json::from_slice(body).ok()?;
+    let incident = match provider {
+        IncidentProvider::Pagerduty => {
+            let event = &payload["event"];
+            if event["event_type"] != "incident.triggered" {
+                return None;
+            }
+            pagerduty_incident(&event["data"])
+        }
+        IncidentProvider::Opsgenie => {
+            if payload["action"] != "Create" {
+                return None;
+            }
+            opsgenie_alert(&payload["alert"])
+        }
+    };
+    (!incident.id.is_empty()).then_some(incident)
+}
+
+/// Whether a new page should fire `trigger`.
+pub fn trigger_matches(trigger: &IncidentTrigger, incident: &Incident) -> bool {
+    trigger.enabled
+        && (trigger.services.is_empty()
+            || incident
+                .service
+                .as_ref()
+                .is_some_and(|service| trigger.services.contains(service)))
+}
+
+/// Run the trigger's prompt for a new page in `account` and deliver the
+/// reply.
+pub async fn fire(
+    context: CronContext,
+    account: String,
+    trigger: IncidentTrigger,
+    incident: Incident,
+) {
+    if context.deps.maintenance.is_enabled() {
+        tracing::info!(%account, incident_id = %incident.id, "skipping incident trigger during maintenance");
+        return;
+    }
+    let Some(target) = DeliveryTarget::parse(&trigger.delivery_target) else {
+        tracing::warn!(
+            %account,
+            target = %trigger.delivery_target,
+            "invalid incident trigger delivery target"
+        );
+        return;
+    };
+
+    tracing::info!(%account, incident_id = %incident.id, "incident trigger fired");
+    let prompt = format!(
+        "{}\n\nNew page in incident account `{account}`:\n{}",
+        trigger.prompt,
+        describe(&incident),
+    );
+
+    let text = match run_prompt(
+        &context,
+        "incident",
+        &format!("incident:{account}:{}", incident.id),
+        prompt,
+    )
+    .await
+    {
+        Ok(text) => text,
+        Err(error) => {
+            tracing::error!(%account, incident_id = %incident.id, %error, "incident trigger failed");
+            return;
+        }
+    };
+    if text.trim().is_empty() {
+        tracing::debug!(%account, incident_id = %incident.id, "incident trigger produced no output");
+        return;
+    }
+
+    if let Err(error) = context
+        .messaging_manager
+        .broadcast(
+            &target.adapter,
+            &target.target,
+            OutboundResponse::Text(text),
+        )
+        .await
+    {
+        tracing::error!(
+            %account,
+            incident_id = %incident.id,
+            %target,
+            %error,
+            "failed to deliver incident trigger result"
+        );
+    }
+}
+
+/// Markdown bullets describing a page, for the trigger prompt.
+fn describe(incident: &Incident) -> String {
+    let mut lines = vec![
+        format!("- title: {}", incident.title),
+        format!("- id: {}", incident.id),
+    ];
+    let optional = [
+        ("number", &incident.number),
+        ("urgency", &incident.urgency),
+        ("service", &incident.service),
+        ("url", &incident.url),
+        ("created at", &incident.created_at),
+    ];
+    for (label, value) in optional {
+        if let Some(value) = value {
+            lines.push(format!("- {label}: {value}"));
+        }
+    }
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn account(provider: IncidentProvider) -> IncidentAccount {
+        IncidentAccount {
+            name: "oncall".into(),
+            provider,
+            token: "token".into(),
+            email: Some("bot@example.com".into()),
+            url: None,
+            webhook_secret: Some("secret".into()),
+        }
+    }
+
+    #[test]
+    fn test_pagerduty_webhook_signature() {
+        let body = br#"{"event":{"event_type":"incident.triggered"}}"#;
+        let mut mac = Hmac::<Sha256>::new_from_slice(b"secret").unwrap();
+        mac.update(body);
+        let signature: String = mac
+            .finalize()
+            .into_bytes()
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect();
+
+        let mut headers = axum::http::HeaderMap::new();
+        headers.insert(
+            PAGERDUTY_SIGNATURE_HEADER,
+            format!("v1=00ff, v1={signature}").parse().unwrap(),
+        );
+        let pagerduty = account(IncidentProvider::Pagerduty);
+        assert!(webhook_authentic(&pagerduty, &headers, body));
+        assert!(!webhook_authentic(&pagerduty, &headers, b"{}"));
+
+        let unsigned = IncidentAccount {
+            webhook_secret: None,
+            ..pagerduty
+        };
+        assert!(!webhook_authentic(&unsigned, &headers, body));
+
+        headers.insert(OPSGENIE_TOKEN_HEADER, "secret".parse().unwrap());
+        assert!(webhook_authentic(
+            &account(IncidentProvider::Opsgenie),
+            &headers,
+            body
+        ));
+    }
+
+    #[test]
+    fn test_parse_page_only_for_new_pages() {
+        let triggered = json!({"event": {
+            "event_type": "incident.triggered",
+            "data": {
+                "id": "Q1P3AHC3KLGSBF",
+                "number": 42,
+                "title": "Checkout error rate above 5%",
+                "status": "triggered",
+                "urgency": "high",
+                "service": {"summary": "checkout"},
+                "html_url": "https://acme.pagerduty.com/incidents/Q1P3AHC3KLGSBF"
+            }
+        }});
+        let incident = parse_page(
+            IncidentProvider::Pagerduty,
+            triggered.to_string().as_bytes(),
+        )
+        .unwrap();
+        assert_eq!(incident.id, "Q1P3AHC3KLGSBF");
+        assert_eq!(incident.number.as_deref(), Some("42"));
+        assert_eq!(incident.service.as_deref(), Some("checkout"));
+
+        let acknowledged =
+            json!({"event": {"event_type": "incident.acknowledged", "data": {"id": "Q1"}}});
+        assert!(
+            parse_page(
+                IncidentProvider::Pagerduty,
+                acknowledged.to_string().as_bytes()
+            )
+            .is_none()
+        );
+
+        let created = json!({"action": "Create", "alert": {
+            "alertId": "70413a06-38d6-4c85-92b8-5ebc900d42e2",
+            "tinyId": "1791",
+            "message": "Disk full on db-1",
+            "priority": "P1",
+            "entity": "postgres",
+            "createdAt": 1_700_000_000_000_i64
+        }});
+        let alert = parse_page(IncidentProvider::Opsgenie, created.to_string().as_bytes()).unwrap();
+        assert_eq!(alert.status, "open");
+        assert_eq!(
+            alert.created_at.as_deref(),
+            Some("2023-11-14T22:13:20+00:00")
+        );
+
+        let trigger = IncidentTrigger {
+            prompt: "Investigate.".into(),
+            delivery_target: "slack:C123".into(),
+            services: vec!["checkout".into()],
+            enabled: true,
+        };
+        assert!(trigger_matches(&trigger, &incident));
+        assert!(!trigger_matches(&trigger, &alert));
+    }
+}
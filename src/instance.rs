@@ -0,0 +1,289 @@
+//! Versioned migrations for the instance directory.
+//!
+//! The layout of `~/.spacebot` (agent directories, archives, spools) changes
+//! between releases. The instance records the layout version it's at in a
+//! `schema_version` file, and [`migrate`] brings it up to date at startup by
+//! running every newer migration in order. Before the first migration runs
+//! the instance is copied to `backups/`, so a migration that goes wrong can
+//! be undone by hand.
+//!
+//! The per-agent SQLite schema is versioned separately by sqlx; migrations
+//! here are for files on disk.
+
+use anyhow::Context as _;
+
+use std::path::{Path, PathBuf};
+
+/// File in the instance directory holding the layout version.
+const VERSION_FILE: &str = "schema_version";
+
+/// Entries not copied into a pre-migration backup: earlier backups, caches
+/// that are rebuilt on demand, and the running daemon's files.
+const BACKUP_SKIP: &[&str] = &[
+    "backups",
+    "embedding_cache",
+    "logs",
+    "spacebot.pid",
+    "spacebot.sock",
+];
+
+/// One step from version `version - 1` to `version`.
+pub struct Migration {
+    pub version: u32,
+    pub description: &'static str,
+    pub apply: fn(&Path) -> anyhow::Result<()>,
+}
+
+/// Every migration, in order. Versions start at 1 and have no gaps; add new
+/// ones at the end and never change one that has shipped.
+pub const MIGRATIONS: &[Migration] = &[Migration {
+    version: 1,
+    description: "start versioning the instance directory",
+    apply: |_| Ok(()),
+}];
+
+/// What [`migrate`] did.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MigrationReport {
+    pub from: u32,
+    pub to: u32,
+    /// Where the instance was copied before migrating. None if nothing ran.
+    pub backup: Option<PathBuf>,
+}
+
+/// Bring the instance directory up to the current layout version.
+///
+/// A new or empty instance is stamped with the current version without
+/// migrating. An instance written by a newer release is an error, since
+/// running an older build on it could corrupt state it doesn't understand.
+pub fn migrate(instance_dir: &Path) -> anyhow::Result<MigrationReport> {
+    run_migrations(instance_dir, MIGRATIONS, chrono::Utc::now())
+}
+
+fn run_migrations(
+    instance_dir: &Path,
+    migrations: &[Migration],
+    now: chrono::DateTime<chrono::Utc>,
+) -> anyhow::Result<MigrationReport> {
+    let target = migrations.last().map_or(0, |migration| migration.version);
+    std::fs::create_dir_all(instance_dir)
+        .with_context(|| format!("failed to create {}", instance_dir.display()))?;
+
+    let from = match read_version(instance_dir)? {
+        Some(version) => version,
+        None if is_fresh(instance_dir)? => {
+            write_version(instance_dir, target)?;
+            return Ok(MigrationReport {
+                from: target,
+                to: target,
+                backup: None,
+            });
+        }
+        // Instances from before versioning have files but no version.
+        None => 0,
+    };
+
+    if from > target {
+        anyhow::bail!(
+            "{} is at layout version {from}, but this build of spacebot only knows up to \
+             version {target}. Upgrade spacebot, or restore a backup from {}",
+            instance_dir.display(),
+            instance_dir.join("backups").display()
+        );
+    }
+    let pending = migrations
+        .iter()
+        .filter(|migration| migration.version > from)
+        .collect::<Vec<_>>();
+    if pending.is_empty() {
+        return Ok(MigrationReport {
+            from,
+            to: from,
+            backup: None,
+        });
+    }
+
+    let backup = instance_dir.join("backups").join(format!(
+        "pre-migration-v{from}-{}",
+        now.format("%Y%m%dT%H%M%SZ")
+    ));
+    copy_dir(instance_dir, &backup, BACKUP_SKIP)
+        .with_context(|| format!("failed to back up instance to {}", backup.display()))?;
+    tracing::info!(backup = %backup.display(), "instance directory backed up before migrating");
+
+    for migration in pending {
+        tracing::info!(
+            version = migration.version,
+            description = migration.description,
+            "migrating instance directory"
+        );
+        (migration.apply)(instance_dir).with_context(|| {
+            format!(
+                "instance migration to version {} ({}) failed; the instance before migrating is \
+                 in {}",
+                migration.version,
+                migration.description,
+                backup.display()
+            )
+        })?;
+        // Record each step, so a failure later resumes from the right place.
+        write_version(instance_dir, migration.version)?;
+    }
+
+    Ok(MigrationReport {
+        from,
+        to: target,
+        backup: Some(backup),
+    })
+}
+
+fn read_version(instance_dir: &Path) -> anyhow::Result<Option<u32>> {
+    let path = instance_dir.join(VERSION_FILE);
+    match std::fs::read_to_string(&path) {
+        Ok(contents) => contents
+            .trim()
+            .parse()
+            .map(Some)
+            .with_context(|| format!("{} is not a version number", path.display())),
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(error) => Err(error).with_context(|| format!("failed to read {}", path.display())),
+    }
+}
+
+fn write_version(instance_dir: &Path, version: u32) -> anyhow::Result<()> {
+    let path = instance_dir.join(VERSION_FILE);
+    let temporary = path.with_extension("tmp");
+    std::fs::write(&temporary, format!("{version}\n"))
+        .and_then(|()| std::fs::rename(&temporary, &path))
+        .with_context(|| format!("failed to write {}", path.display()))
+}
+
+/// Nothing worth migrating yet: no agent state. Onboarding may already have
+/// written `config.toml`, so that doesn't count.
+fn is_fresh(instance_dir: &Path) -> anyhow::Result<bool> {
+    let agents_dir = instance_dir.join("agents");
+    match std::fs::read_dir(&agents_dir) {
+        Ok(mut entries) => Ok(entries.next().is_none()),
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => Ok(true),
+        Err(error) => {
+            Err(error).with_context(|| format!("failed to read {}", agents_dir.display()))
+        }
+    }
+}
+
+/// Copy a directory tree, leaving out the named top-level entries. Only
+/// regular files and directories are copied; sockets and symlinks aren't.
+fn copy_dir(from: &Path, to: &Path, skip: &[&str]) -> std::io::Result<()> {
+    std::fs::create_dir_all(to)?;
+    for entry in std::fs::read_dir(from)? {
+        let entry = entry?;
+        if skip.iter().any(|name| entry.file_name() == *name) {
+            continue;
+        }
+        let file_type = entry.file_type()?;
+        let target = to.join(entry.file_name());
+        if file_type.is_dir() {
+            copy_dir(&entry.path(), &target, &[])?;
+        } else if file_type.is_file() {
+            std::fs::copy(entry.path(), &target)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn add_agent_file(instance_dir: &Path) {
+        let data_dir = instance_dir.join("agents/main/data");
+        std::fs::create_dir_all(&data_dir).unwrap();
+        std::fs::write(data_dir.join("spacebot.db"), "db").unwrap();
+    }
+
+    fn rename_data_dir(instance_dir: &Path) -> anyhow::Result<()> {
+        let agent_dir = instance_dir.join("agents/main");
+        std::fs::rename(agent_dir.join("data"), agent_dir.join("state"))?;
+        Ok(())
+    }
+
+    fn always_fail(_: &Path) -> anyhow::Result<()> {
+        anyhow::bail!("disk on fire")
+    }
+
+    const TWO_STEPS: &[Migration] = &[
+        Migration {
+            version: 1,
+            description: "baseline",
+            apply: |_| Ok(()),
+        },
+        Migration {
+            version: 2,
+            description: "rename data dir",
+            apply: rename_data_dir,
+        },
+    ];
+
+    #[test]
+    fn test_fresh_instance_is_stamped() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("config.toml"), "").unwrap();
+
+        let report = run_migrations(dir.path(), TWO_STEPS, chrono::Utc::now()).unwrap();
+
+        assert_eq!(report.to, 2);
+        assert_eq!(report.backup, None);
+        assert_eq!(read_version(dir.path()).unwrap(), Some(2));
+    }
+
+    #[test]
+    fn test_unversioned_instance_is_backed_up_and_migrated() {
+        let dir = tempfile::tempdir().unwrap();
+        add_agent_file(dir.path());
+        std::fs::create_dir_all(dir.path().join("embedding_cache")).unwrap();
+
+        let report = run_migrations(dir.path(), TWO_STEPS, chrono::Utc::now()).unwrap();
+
+        assert_eq!((report.from, report.to), (0, 2));
+        assert!(dir.path().join("agents/main/state/spacebot.db").exists());
+        let backup = report.backup.unwrap();
+        assert!(backup.join("agents/main/data/spacebot.db").exists());
+        assert!(!backup.join("embedding_cache").exists());
+        assert_eq!(read_version(dir.path()).unwrap(), Some(2));
+
+        // Up to date: nothing runs.
+        let report = run_migrations(dir.path(), TWO_STEPS, chrono::Utc::now()).unwrap();
+        assert_eq!(report.backup, None);
+    }
+
+    #[test]
+    fn test_failed_migration_keeps_last_good_version() {
+        let dir = tempfile::tempdir().unwrap();
+        add_agent_file(dir.path());
+        let migrations = &[
+            Migration {
+                version: 1,
+                description: "baseline",
+                apply: |_| Ok(()),
+            },
+            Migration {
+                version: 2,
+                description: "broken",
+                apply: always_fail,
+            },
+        ];
+
+        let error = run_migrations(dir.path(), migrations, chrono::Utc::now()).unwrap_err();
+
+        assert!(format!("{error:#}").contains("disk on fire"));
+        assert_eq!(read_version(dir.path()).unwrap(), Some(1));
+    }
+
+    #[test]
+    fn test_newer_instance_is_refused() {
+        let dir = tempfile::tempdir().unwrap();
+        write_version(dir.path(), 5).unwrap();
+
+        assert!(run_migrations(dir.path(), TWO_STEPS, chrono::Utc::now()).is_err());
+    }
+}
@@ -0,0 +1,11 @@
+//! Knowledge sources: git repositories, Notion workspaces, Confluence sites
+//! and website sitemaps kept in sync with an agent's memory on a schedule.
+
+pub mod markdown;
+pub mod sources;
+pub mod store;
+pub mod sync;
+
+pub use sources::Document;
+pub use store::{KnowledgeStatus, KnowledgeStore, StoredChunk};
+pub use sync::spawn_knowledge_sync;
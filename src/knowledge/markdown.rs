@@ -0,0 +1,184 @@
+//! HTML to markdown, for web pages and Confluence's storage format.
+//!
+//! Regex passes rather than a parser: headings, emphasis, links, lists,
+//! tables and code blocks come out as markdown, and everything else is
+//! reduced to its text. Good enough to embed and to quote back, which is all
+//! knowledge chunks are used for.
+
+use regex::{Captures, Regex};
+use std::sync::LazyLock;
+
+static HIDDEN: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(concat!(
+        r"(?is)<head\b.*?</head>|<script\b.*?</script>|<style\b.*?</style>|",
+        r"<noscript\b.*?</noscript>|<!--.*?-->|<ac:parameter\b.*?</ac:parameter>"
+    ))
+    .expect("valid regex")
+});
+/// Body of a Confluence code macro.
+static CDATA_BODY: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?is)<ac:plain-text-body>\s*<!\[CDATA\[(.*?)\]\]>\s*</ac:plain-text-body>")
+        .expect("valid regex")
+});
+static PRE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?is)<pre\b[^>]*>(.*?)</pre>").expect("valid regex"));
+static HEADING: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?is)<h([1-6])\b[^>]*>(.*?)</h[1-6]>").expect("valid regex"));
+static LINK: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"(?is)<a\s[^>]*?href\s*=\s*["']([^"']*)["'][^>]*>(.*?)</a>"#).expect("valid regex")
+});
+static BOLD: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?is)<(?:strong|b)(?:\s[^>]*)?>(.*?)</(?:strong|b)>").expect("valid regex")
+});
+static ITALIC: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?is)<(?:em|i)(?:\s[^>]*)?>(.*?)</(?:em|i)>").expect("valid regex")
+});
+static CODE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?is)<code(?:\s[^>]*)?>(.*?)</code>").expect("valid regex"));
+static LIST_ITEM: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?i)<li\b[^>]*>").expect("valid regex"));
+static TABLE_ROW: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?is)<tr\b[^>]*>(.*?)</tr>").expect("valid regex"));
+static TABLE_CELL: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?is)<t[dh]\b[^>]*>(.*?)</t[dh]>").expect("valid regex"));
+static BLOCK: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(concat!(
+        r"(?i)</?(p|div|br|ul|ol|li|tr|table|thead|tbody|section|article|header|footer|",
+        r"blockquote|ac:layout|ac:layout-section|ac:layout-cell)\b[^>]*>"
+    ))
+    .expect("valid regex")
+});
+static TAG: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"<[^>]*>").expect("valid regex"));
+
+/// Markdown from an HTML page or fragment.
+pub fn html_to_markdown(html: &str) -> String {
+    let html = HIDDEN.replace_all(html, "");
+    // Code bodies are escaped so the tag passes below leave them alone; the
+    // entities are decoded with everything else at the end.
+    let html = CDATA_BODY.replace_all(&html, |captures: &Captures| fence(&escape(&captures[1])));
+    let html = PRE.replace_all(&html, |captures: &Captures| {
+        fence(&TAG.replace_all(&captures[1], ""))
+    });
+    let html = HEADING.replace_all(&html, |captures: &Captures| {
+        let level: usize = captures[1].parse().unwrap_or(1);
+        format!("\n{} {}\n", "#".repeat(level), inline(&captures[2]))
+    });
+    let html = LINK.replace_all(&html, |captures: &Captures| {
+        let text = inline(&captures[2]);
+        if text.is_empty() {
+            String::new()
+        } else {
+            format!("[{text}]({})", &captures[1])
+        }
+    });
+    let html = BOLD.replace_all(&html, "**$1**");
+    let html = ITALIC.replace_all(&html, "_${1}_");
+    let html = CODE.replace_all(&html, "`$1`");
+    let html = LIST_ITEM.replace_all(&html, "\n- ");
+    let html = TABLE_ROW.replace_all(&html, |captures: &Captures| {
+        let cells: Vec<String> = TABLE_CELL
+            .captures_iter(&captures[1])
+            .map(|cell| inline(&cell[1]))
+            .collect();
+        format!("\n| {} |\n", cells.join(" | "))
+    });
+    let html = BLOCK.replace_all(&html, "\n");
+    let text = decode_entities(&TAG.replace_all(&html, ""));
+    tidy(&text)
+}
+
+/// Decode the entities common in HTML text.
+pub fn decode_entities(text: &str) -> String {
+    text.replace("&nbsp;", " ")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+fn fence(code: &str) -> String {
+    format!("\n```\n{}\n```\n", code.trim_matches('\n'))
+}
+
+/// Inline content on one line, for headings and link text.
+fn inline(html: &str) -> String {
+    TAG.replace_all(html, "")
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Collapse whitespace and drop blank lines outside code blocks, and join
+/// list markers left on a line of their own to the item's text.
+fn tidy(text: &str) -> String {
+    let mut lines: Vec<String> = Vec::new();
+    let mut in_code = false;
+    let mut bullet = false;
+    for line in text.lines() {
+        if line.trim() == "```" {
+            in_code = !in_code;
+            lines.push("```".into());
+            continue;
+        }
+        if in_code {
+            lines.push(line.trim_end().to_string());
+            continue;
+        }
+        let line = line.split_whitespace().collect::<Vec<_>>().join(" ");
+        match line.as_str() {
+            "" => {}
+            "-" => bullet = true,
+            _ if bullet => {
+                bullet = false;
+                lines.push(format!("- {line}"));
+            }
+            _ => lines.push(line),
+        }
+    }
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_web_page() {
+        let html = "<html><head><title>Docs</title><style>p { color: red }</style></head>\
+            <body><script>track()</script><h1>Install</h1><p>Run <code>cargo   install</code> \
+            &amp; <a href=\"/faq\">see the <b>FAQ</b></a>.</p><ul><li>Linux</li><li>macOS</li></ul>\
+            <pre><code>let x = 1 &lt; 2;\n    done();</code></pre></body></html>";
+        assert_eq!(
+            html_to_markdown(html),
+            "# Install\nRun `cargo install` & [see the FAQ](/faq).\n- Linux\n- macOS\n\
+             ```\nlet x = 1 < 2;\n    done();\n```"
+        );
+    }
+
+    #[test]
+    fn test_confluence_storage_format() {
+        let storage = r#"<h2>Escalation</h2>
+            <p>Page the <strong>on-call lead</strong> first.</p>
+            <ul><li><p>Check <em>status</em></p></li></ul>
+            <table><tbody><tr><th>Severity</th><th>Response</th></tr>
+            <tr><td>SEV1</td><td>15 min</td></tr></tbody></table>
+            <ac:structured-macro ac:name="code"><ac:parameter ac:name="language">bash</ac:parameter>
+            <ac:plain-text-body><![CDATA[if [ $a -lt 2 ]; then
+  page <lead>
+fi]]></ac:plain-text-body></ac:structured-macro>"#;
+        assert_eq!(
+            html_to_markdown(storage),
+            "## Escalation\nPage the **on-call lead** first.\n- Check _status_\n\
+             | Severity | Response |\n| SEV1 | 15 min |\n\
+             ```\nif [ $a -lt 2 ]; then\n  page <lead>\nfi\n```"
+        );
+    }
+}
@@ -0,0 +1,713 @@
+//! Crawlers for each kind of knowledge source.
+//!
+//! Each crawl returns the source's current documents as markdown. Git
+//! repositories are cloned shallowly into the agent's data directory and
+//! fetched on later syncs; Notion pages are read block by block through the
+//! API; Confluence pages are read in storage format through the REST API;
+//! sitemap pages are fetched and converted.
+//!
+//! Notion and Confluence report when each page was last edited. Pages not
+//! edited since the last sync aren't fetched again, and come back without
+//! text so their chunks are kept as they are.
+
+use crate::config::{KnowledgeSourceDef, KnowledgeSourceKind};
+use crate::error::Result;
+use crate::knowledge::markdown::{decode_entities, html_to_markdown};
+
+use anyhow::Context as _;
+use base64::Engine as _;
+use regex::Regex;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::LazyLock;
+
+const NOTION_API: &str = "https://api.notion.com/v1";
+const NOTION_VERSION: &str = "2022-06-28";
+
+/// Nested Notion blocks are read this deep below the page.
+const NOTION_MAX_DEPTH: usize = 3;
+
+/// Pages per Confluence content listing request.
+const CONFLUENCE_PAGE_SIZE: usize = 100;
+
+/// Sitemap indexes are followed this deep.
+const SITEMAP_MAX_DEPTH: usize = 2;
+
+/// Files larger than this are skipped in git sources.
+const MAX_FILE_BYTES: u64 = 1024 * 1024;
+
+/// Extensions of the files read from git sources.
+const DOCUMENT_EXTENSIONS: &[&str] = &[
+    "md", "mdx", "markdown", "txt", "rst", "adoc", "org", "html", "htm",
+];
+
+static SITEMAP_LOC: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?is)<loc>\s*(.*?)\s*</loc>").expect("valid regex"));
+static HTML_TITLE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?is)<title[^>]*>(.*?)</title>").expect("valid regex"));
+/// One document from a knowledge source, as markdown.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Document {
+    /// Stable within the source: file path, page URL, or Notion or
+    /// Confluence page ID.
+    pub id: String,
+    pub title: String,
+    /// None when the document hasn't been edited since the last sync.
+    pub text: Option<String>,
+    /// When the document was last edited, for sources that say.
+    pub edited_at: Option<String>,
+}
+
+/// Crawl `source` and return its current documents, at most
+/// `source.max_documents` of them. Git sources are checked out under
+/// `checkout_dir`. `edited` holds the last-edited time of each document as
+/// of the last sync; documents edited at the same time are returned
+/// without text.
+pub async fn crawl(
+    http: &reqwest::Client,
+    source: &KnowledgeSourceDef,
+    checkout_dir: &Path,
+    edited: &HashMap<String, String>,
+) -> Result<Vec<Document>> {
+    let mut documents = match source.kind {
+        KnowledgeSourceKind::Git => crawl_git(source, &checkout_dir.join(&source.id)).await?,
+        KnowledgeSourceKind::Notion => crawl_notion(http, source, edited).await?,
+        KnowledgeSourceKind::Confluence => crawl_confluence(http, source, edited).await?,
+        KnowledgeSourceKind::Sitemap => crawl_sitemap(http, source).await?,
+    };
+    documents.retain(|document| {
+        document
+            .text
+            .as_ref()
+            .is_none_or(|text| !text.trim().is_empty())
+    });
+    documents.truncate(source.max_documents);
+    Ok(documents)
+}
+
+// -- Git --
+
+async fn crawl_git(source: &KnowledgeSourceDef, dir: &Path) -> Result<Vec<Document>> {
+    if dir.join(".git").exists() {
+        let refspec = source.branch.as_deref().unwrap_or("HEAD");
+        // The remote may have changed in config since the clone
+        git(
+            source,
+            Some(dir),
+            &["remote", "set-url", "origin", &source.url],
+        )
+        .await?;
+        git(
+            source,
+            Some(dir),
+            &["fetch", "--depth", "1", "origin", refspec],
+        )
+        .await?;
+        git(source, Some(dir), &["reset", "--hard", "FETCH_HEAD"]).await?;
+    } else {
+        // Left behind by a clone that failed partway
+        if dir.exists() {
+            tokio::fs::remove_dir_all(dir)
+                .await
+                .with_context(|| format!("failed to remove {}", dir.display()))?;
+        }
+        if let Some(parent) = dir.parent() {
+            tokio::fs::create_dir_all(parent)
+                .await
+                .with_context(|| format!("failed to create {}", parent.display()))?;
+        }
+        let target = dir.to_string_lossy().into_owned();
+        let mut args = vec!["clone", "--depth", "1"];
+        if let Some(branch) = &source.branch {
+            args.extend(["--branch", branch.as_str()]);
+        }
+        args.extend(["--", source.url.as_str(), target.as_str()]);
+        git(source, None, &args).await?;
+    }
+
+    let root = dir.to_path_buf();
+    let paths = source.paths.clone();
+    let files = tokio::task::spawn_blocking(move || {
+        let mut files = Vec::new();
+        walk(&root, &root, &mut files);
+        files.sort();
+        files
+    })
+    .await
+    .context("failed to list repository files")?;
+
+    let mut documents = Vec::new();
+    for (relative, path) in files {
+        if !paths.is_empty()
+            && !paths
+                .iter()
+                .any(|prefix| relative.starts_with(prefix.as_str()))
+        {
+            continue;
+        }
+        // Files that aren't UTF-8 are skipped
+        let Ok(text) = tokio::fs::read_to_string(&path).await else {
+            continue;
+        };
+        let text = if relative.ends_with(".html") || relative.ends_with(".htm") {
+            html_to_markdown(&text)
+        } else {
+            text
+        };
+        documents.push(Document {
+            id: relative.clone(),
+            title: relative,
+            text: Some(text),
+            edited_at: None,
+        });
+    }
+    Ok(documents)
+}
+
+/// Run git with the source's token, if any, sent as an HTTP header so it's
+/// never written to the checkout's config.
+async fn git(source: &KnowledgeSourceDef, dir: Option<&Path>, args: &[&str]) -> Result<()> {
+    let mut command = tokio::process::Command::new("git");
+    if let Some(token) = &source.token {
+        let credentials =
+            base64::engine::general_purpose::STANDARD.encode(format!("x-access-token:{token}"));
+        command.arg("-c").arg(format!(
+            "http.extraHeader=Authorization: Basic {credentials}"
+        ));
+    }
+    if let Some(dir) = dir {
+        command.arg("-C").arg(dir);
+    }
+    let output = command
+        .args(args)
+        .env("GIT_TERMINAL_PROMPT", "0")
+        .output()
+        .await
+        .context("failed to run git")?;
+    if !output.status.success() {
+        return Err(crate::error::Error::Other(anyhow::anyhow!(
+            "git {} failed: {}",
+            args.first().copied().unwrap_or_default(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(())
+}
+
+/// Collect document files under `dir` as (path relative to `root`, path).
+fn walk(root: &Path, dir: &Path, files: &mut Vec<(String, PathBuf)>) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let Ok(file_type) = entry.file_type() else {
+            continue;
+        };
+        if file_type.is_dir() {
+            if entry.file_name() != ".git" {
+                walk(root, &path, files);
+            }
+            continue;
+        }
+        let is_document = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .is_some_and(|extension| {
+                DOCUMENT_EXTENSIONS.contains(&extension.to_lowercase().as_str())
+            });
+        let small = entry
+            .metadata()
+            .is_ok_and(|metadata| metadata.len() <= MAX_FILE_BYTES);
+        if file_type.is_file() && is_document && small {
+            let relative = path
+                .strip_prefix(root)
+                .unwrap_or(&path)
+                .to_string_lossy()
+                .replace('\\', "/");
+            files.push((relative, path));
+        }
+    }
+}
+
+// -- Notion --
+
+async fn crawl_notion(
+    http: &reqwest::Client,
+    source: &KnowledgeSourceDef,
+    edited: &HashMap<String, String>,
+) -> Result<Vec<Document>> {
+    let token = source
+        .token
+        .as_deref()
+        .context("notion sources need a token")?;
+
+    let mut pages = Vec::new();
+    let mut cursor: Option<String> = None;
+    loop {
+        let mut body = serde_json::json!({
+            "filter": {"property": "object", "value": "page"},
+            "page_size": 100,
+        });
+        if let Some(cursor) = &cursor {
+            body["start_cursor"] = cursor.clone().into();
+        }
+        let response = notion_request(http.post(format!("{NOTION_API}/search")), token)
+            .json(&body)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .context("notion search failed")?
+            .json::<serde_json::Value>()
+            .await
+            .context("invalid notion search response")?;
+
+        for page in response["results"].as_array().into_iter().flatten() {
+            if let Some(id) = page["id"].as_str() {
+                let edited_at = page["last_edited_time"].as_str().map(ToOwned::to_owned);
+                pages.push((id.to_string(), notion_page_title(page), edited_at));
+            }
+        }
+        cursor = response["next_cursor"].as_str().map(ToOwned::to_owned);
+        if cursor.is_none() || pages.len() >= source.max_documents {
+            break;
+        }
+    }
+    pages.truncate(source.max_documents);
+
+    let mut documents = Vec::new();
+    for (id, title, edited_at) in pages {
+        let text = if is_unchanged(edited, &id, edited_at.as_deref()) {
+            None
+        } else {
+            let mut lines = Vec::new();
+            notion_block_lines(http, token, &id, 0, &mut lines).await?;
+            Some(lines.join("\n"))
+        };
+        documents.push(Document {
+            id,
+            title,
+            text,
+            edited_at,
+        });
+    }
+    Ok(documents)
+}
+
+fn notion_request(request: reqwest::RequestBuilder, token: &str) -> reqwest::RequestBuilder {
+    request
+        .bearer_auth(token)
+        .header("Notion-Version", NOTION_VERSION)
+}
+
+/// Append the markdown of `block_id`'s children, and theirs, to `lines`.
+/// Nested blocks are indented. Child pages are skipped: search returns them
+/// as pages of their own.
+async fn notion_block_lines(
+    http: &reqwest::Client,
+    token: &str,
+    block_id: &str,
+    depth: usize,
+    lines: &mut Vec<String>,
+) -> Result<()> {
+    let mut cursor: Option<String> = None;
+    loop {
+        let mut request = http
+            .get(format!("{NOTION_API}/blocks/{block_id}/children"))
+            .query(&[("page_size", "100")]);
+        if let Some(cursor) = &cursor {
+            request = request.query(&[("start_cursor", cursor)]);
+        }
+        let response = notion_request(request, token)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .context("failed to read notion blocks")?
+            .json::<serde_json::Value>()
+            .await
+            .context("invalid notion blocks response")?;
+
+        for block in response["results"].as_array().into_iter().flatten() {
+            if let Some(text) = notion_block_markdown(block) {
+                let indent = "  ".repeat(depth);
+                lines.extend(text.lines().map(|line| format!("{indent}{line}")));
+            }
+            let nested = block["has_children"].as_bool() == Some(true)
+                && block["type"].as_str() != Some("child_page")
+                && depth + 1 < NOTION_MAX_DEPTH;
+            if let (true, Some(id)) = (nested, block["id"].as_str()) {
+                Box::pin(notion_block_lines(http, token, id, depth + 1, lines)).await?;
+            }
+        }
+        cursor = response["next_cursor"].as_str().map(ToOwned::to_owned);
+        if cursor.is_none() {
+            return Ok(());
+        }
+    }
+}
+
+/// A page's title, from whichever property has type `title`.
+fn notion_page_title(page: &serde_json::Value) -> String {
+    page["properties"]
+        .as_object()
+        .into_iter()
+        .flat_map(|properties| properties.values())
+        .find(|property| property["type"].as_str() == Some("title"))
+        .map(|property| plain_text(&property["title"]))
+        .filter(|title| !title.is_empty())
+        .unwrap_or_else(|| "(untitled)".into())
+}
+
+/// A block as markdown, or None for blocks without text.
+fn notion_block_markdown(block: &serde_json::Value) -> Option<String> {
+    let kind = block["type"].as_str()?;
+    if kind == "divider" {
+        return Some("---".into());
+    }
+    let content = &block[kind];
+    if kind == "code" {
+        let language = content["language"].as_str().unwrap_or_default();
+        let code = plain_text(&content["rich_text"]);
+        return Some(format!("```{language}\n{code}\n```"));
+    }
+    let text = rich_text_markdown(&content["rich_text"]);
+    if text.is_empty() {
+        return None;
+    }
+    let markdown = match kind {
+        "heading_1" => format!("# {text}"),
+        "heading_2" => format!("## {text}"),
+        "heading_3" => format!("### {text}"),
+        "bulleted_list_item" | "toggle" => format!("- {text}"),
+        "numbered_list_item" => format!("1. {text}"),
+        "to_do" if content["checked"].as_bool() == Some(true) => format!("- [x] {text}"),
+        "to_do" => format!("- [ ] {text}"),
+        "quote" | "callout" => format!("> {text}"),
+        _ => text,
+    };
+    Some(markdown)
+}
+
+/// Rich text with its bold, italic, strikethrough, code and links kept.
+fn rich_text_markdown(rich_text: &serde_json::Value) -> String {
+    let mut markdown = String::new();
+    for span in rich_text.as_array().into_iter().flatten() {
+        let Some(text) = span["plain_text"].as_str() else {
+            continue;
+        };
+        if text.trim().is_empty() {
+            markdown.push_str(text);
+            continue;
+        }
+        // Markers go inside the span's own leading and trailing spaces
+        let inner = text.trim();
+        let leading = &text[..text.len() - text.trim_start().len()];
+        let trailing = &text[text.trim_end().len()..];
+        let annotations = &span["annotations"];
+        let mut styled = inner.to_string();
+        if annotations["code"].as_bool() == Some(true) {
+            styled = format!("`{styled}`");
+        }
+        if annotations["bold"].as_bool() == Some(true) {
+            styled = format!("**{styled}**");
+        }
+        if annotations["italic"].as_bool() == Some(true) {
+            styled = format!("_{styled}_");
+        }
+        if annotations["strikethrough"].as_bool() == Some(true) {
+            styled = format!("~~{styled}~~");
+        }
+        if let Some(href) = span["href"].as_str() {
+            styled = format!("[{styled}]({href})");
+        }
+        markdown.push_str(&format!("{leading}{styled}{trailing}"));
+    }
+    markdown
+}
+
+fn plain_text(rich_text: &serde_json::Value) -> String {
+    rich_text
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|span| span["plain_text"].as_str())
+        .collect()
+}
+
+// -- Confluence --
+
+async fn crawl_confluence(
+    http: &reqwest::Client,
+    source: &KnowledgeSourceDef,
+    edited: &HashMap<String, String>,
+) -> Result<Vec<Document>> {
+    let base = source.url.trim_end_matches('/');
+    if base.is_empty() {
+        return Err(crate::error::Error::Other(anyhow::anyhow!(
+            "confluence sources need the site's url"
+        )));
+    }
+
+    // Listing pages with their versions is cheap; bodies are only fetched
+    // for pages edited since the last sync.
+    let mut pages = Vec::new();
+    let mut start = 0;
+    loop {
+        let mut query = vec![
+            ("type", "page".to_string()),
+            ("expand", "version".to_string()),
+            ("limit", CONFLUENCE_PAGE_SIZE.to_string()),
+            ("start", start.to_string()),
+        ];
+        if let Some(space) = &source.space {
+            query.push(("spaceKey", space.clone()));
+        }
+        let request = http.get(format!("{base}/rest/api/content")).query(&query);
+        let response = confluence_request(request, source)?
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .context("confluence page listing failed")?
+            .json::<serde_json::Value>()
+            .await
+            .context("invalid confluence page listing")?;
+
+        let results = response["results"].as_array().cloned().unwrap_or_default();
+        for page in &results {
+            if let Some(id) = page["id"].as_str() {
+                let title = page["title"].as_str().unwrap_or("(untitled)").to_string();
+                let edited_at = page["version"]["when"].as_str().map(ToOwned::to_owned);
+                pages.push((id.to_string(), title, edited_at));
+            }
+        }
+        start += results.len();
+        let more = response["_links"]["next"].is_string();
+        if !more || results.is_empty() || pages.len() >= source.max_documents {
+            break;
+        }
+    }
+    pages.truncate(source.max_documents);
+
+    let mut documents = Vec::new();
+    for (id, title, edited_at) in pages {
+        let text = if is_unchanged(edited, &id, edited_at.as_deref()) {
+            None
+        } else {
+            let request = http
+                .get(format!("{base}/rest/api/content/{id}"))
+                .query(&[("expand", "body.storage")]);
+            let page = confluence_request(request, source)?
+                .send()
+                .await
+                .and_then(|response| response.error_for_status())
+                .with_context(|| format!("failed to read confluence page {id}"))?
+                .json::<serde_json::Value>()
+                .await
+                .with_context(|| format!("invalid confluence page {id}"))?;
+            let storage = page["body"]["storage"]["value"]
+                .as_str()
+                .unwrap_or_default();
+            Some(html_to_markdown(storage))
+        };
+        documents.push(Document {
+            id,
+            title,
+            text,
+            edited_at,
+        });
+    }
+    Ok(documents)
+}
+
+/// Confluence Cloud takes the account email and an API token as basic auth;
+/// Data Center takes a personal access token as a bearer token.
+fn confluence_request(
+    request: reqwest::RequestBuilder,
+    source: &KnowledgeSourceDef,
+) -> Result<reqwest::RequestBuilder> {
+    let token = source
+        .token
+        .as_deref()
+        .context("confluence sources need a token")?;
+    Ok(match &source.email {
+        Some(email) => request.basic_auth(email, Some(token)),
+        None => request.bearer_auth(token),
+    })
+}
+
+/// Whether a page was last edited when the last sync saw it.
+fn is_unchanged(edited: &HashMap<String, String>, id: &str, edited_at: Option<&str>) -> bool {
+    edited_at.is_some_and(|edited_at| edited.get(id).map(String::as_str) == Some(edited_at))
+}
+
+// -- Sitemap --
+
+async fn crawl_sitemap(
+    http: &reqwest::Client,
+    source: &KnowledgeSourceDef,
+) -> Result<Vec<Document>> {
+    let mut pages = Vec::new();
+    let mut sitemaps = vec![(source.url.clone(), 0)];
+    while let Some((url, depth)) = sitemaps.pop() {
+        let xml = fetch_text(http, &url).await?;
+        let locs = sitemap_locs(&xml);
+        if xml.contains("<sitemapindex") {
+            if depth < SITEMAP_MAX_DEPTH {
+                sitemaps.extend(locs.into_iter().rev().map(|loc| (loc, depth + 1)));
+            }
+        } else {
+            pages.extend(locs);
+        }
+        if pages.len() >= source.max_documents {
+            break;
+        }
+    }
+    pages.dedup();
+    pages.truncate(source.max_documents);
+
+    let mut documents = Vec::new();
+    for url in pages {
+        // One unreachable page shouldn't fail the sync, or every chunk of
+        // the site would be kept until it's back.
+        match fetch_text(http, &url).await {
+            Ok(html) => {
+                let title = HTML_TITLE
+                    .captures(&html)
+                    .map(|captures| decode_entities(captures[1].trim()))
+                    .filter(|title| !title.is_empty())
+                    .unwrap_or_else(|| url.clone());
+                documents.push(Document {
+                    id: url,
+                    title,
+                    text: Some(html_to_markdown(&html)),
+                    edited_at: None,
+                });
+            }
+            Err(error) => tracing::warn!(%url, %error, "failed to fetch sitemap page"),
+        }
+    }
+    Ok(documents)
+}
+
+async fn fetch_text(http: &reqwest::Client, url: &str) -> Result<String> {
+    Ok(http
+        .get(url)
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .with_context(|| format!("request to {url} failed"))?
+        .text()
+        .await
+        .with_context(|| format!("failed to read {url}"))?)
+}
+
+/// The `<loc>` URLs of a sitemap or sitemap index.
+fn sitemap_locs(xml: &str) -> Vec<String> {
+    SITEMAP_LOC
+        .captures_iter(xml)
+        .map(|captures| {
+            let loc = captures[1].trim();
+            let loc = loc
+                .strip_prefix("<![CDATA[")
+                .and_then(|loc| loc.strip_suffix("]]>"))
+                .unwrap_or(loc);
+            decode_entities(loc)
+        })
+        .filter(|loc| !loc.is_empty())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sitemap_locs() {
+        let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+            <urlset xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">
+              <url><loc>https://example.com/docs/</loc></url>
+              <url>
+                <loc>
+                  https://example.com/docs/search?q=a&amp;page=2
+                </loc>
+              </url>
+              <url><loc><![CDATA[https://example.com/docs/faq]]></loc></url>
+            </urlset>"#;
+        assert_eq!(
+            sitemap_locs(xml),
+            [
+                "https://example.com/docs/",
+                "https://example.com/docs/search?q=a&page=2",
+                "https://example.com/docs/faq",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_notion_blocks() {
+        let page = serde_json::json!({
+            "properties": {
+                "Tags": {"type": "multi_select", "multi_select": []},
+                "Name": {"type": "title", "title": [
+                    {"plain_text": "On-call "}, {"plain_text": "runbook"}
+                ]}
+            }
+        });
+        assert_eq!(notion_page_title(&page), "On-call runbook");
+
+        let heading = serde_json::json!({
+            "type": "heading_2",
+            "heading_2": {"rich_text": [{"plain_text": "Escalation"}]}
+        });
+        let todo = serde_json::json!({
+            "type": "to_do",
+            "to_do": {"rich_text": [{"plain_text": "Page the lead"}], "checked": true}
+        });
+        let empty = serde_json::json!({"type": "paragraph", "paragraph": {"rich_text": []}});
+        assert_eq!(
+            notion_block_markdown(&heading).as_deref(),
+            Some("## Escalation")
+        );
+        assert_eq!(
+            notion_block_markdown(&todo).as_deref(),
+            Some("- [x] Page the lead")
+        );
+        assert_eq!(notion_block_markdown(&empty), None);
+    }
+
+    #[test]
+    fn test_notion_rich_text_markdown() {
+        let rich_text = serde_json::json!([
+            {"plain_text": "Run ", "annotations": {}},
+            {"plain_text": "deploy.sh ", "annotations": {"code": true}},
+            {"plain_text": "only after the ", "annotations": {}},
+            {"plain_text": "freeze", "annotations": {"bold": true, "italic": true},
+             "href": "https://example.com/freeze"}
+        ]);
+        assert_eq!(
+            rich_text_markdown(&rich_text),
+            "Run `deploy.sh` only after the [_**freeze**_](https://example.com/freeze)"
+        );
+    }
+
+    #[test]
+    fn test_is_unchanged() {
+        let edited = HashMap::from([("page-1".to_string(), "2026-10-01T09:00:00Z".to_string())]);
+        assert!(is_unchanged(
+            &edited,
+            "page-1",
+            Some("2026-10-01T09:00:00Z")
+        ));
+        assert!(!is_unchanged(
+            &edited,
+            "page-1",
+            Some("2026-10-02T09:00:00Z")
+        ));
+        assert!(!is_unchanged(
+            &edited,
+            "page-2",
+            Some("2026-10-01T09:00:00Z")
+        ));
+        assert!(!is_unchanged(&edited, "page-1", None));
+    }
+}
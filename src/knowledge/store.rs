@@ -0,0 +1,285 @@
+//! Synced chunks and per-source sync status (SQLite).
+
+use crate::error::Result;
+use anyhow::Context as _;
+use serde::Serialize;
+use sqlx::{Row as _, SqlitePool};
+use std::collections::HashMap;
+
+/// Outcome of a knowledge source's latest sync.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct KnowledgeStatus {
+    pub source_id: String,
+    /// `syncing`, `ok` or `error`.
+    pub status: String,
+    pub last_sync_at: Option<String>,
+    pub last_success_at: Option<String>,
+    pub last_error: Option<String>,
+    pub documents: i64,
+    pub chunks: i64,
+    pub added: i64,
+    pub removed: i64,
+}
+
+/// A chunk stored by an earlier sync.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StoredChunk {
+    pub document: String,
+    pub memory_id: String,
+}
+
+/// Chunk and status store for knowledge sources.
+#[derive(Debug)]
+pub struct KnowledgeStore {
+    pool: SqlitePool,
+}
+
+impl KnowledgeStore {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+
+    /// The chunks stored for `source_id`, by chunk hash.
+    pub async fn chunks(&self, source_id: &str) -> Result<HashMap<String, StoredChunk>> {
+        let rows = sqlx::query(
+            "SELECT chunk_hash, document, memory_id FROM knowledge_chunks WHERE source_id = ?",
+        )
+        .bind(source_id)
+        .fetch_all(&self.pool)
+        .await
+        .context("failed to load knowledge chunks")?;
+
+        Ok(rows
+            .into_iter()
+            .filter_map(|row| {
+                let chunk = StoredChunk {
+                    document: row.try_get("document").ok()?,
+                    memory_id: row.try_get("memory_id").ok()?,
+                };
+                Some((row.try_get("chunk_hash").ok()?, chunk))
+            })
+            .collect())
+    }
+
+    /// Last-edited time of each document of `source_id` as of the last
+    /// successful sync, for sources that report one.
+    pub async fn edited(&self, source_id: &str) -> Result<HashMap<String, String>> {
+        let rows =
+            sqlx::query("SELECT document, edited_at FROM knowledge_documents WHERE source_id = ?")
+                .bind(source_id)
+                .fetch_all(&self.pool)
+                .await
+                .context("failed to load knowledge documents")?;
+
+        Ok(rows
+            .into_iter()
+            .filter_map(|row| {
+                Some((
+                    row.try_get("document").ok()?,
+                    row.try_get("edited_at").ok()?,
+                ))
+            })
+            .collect())
+    }
+
+    /// Replace the last-edited times recorded for `source_id`.
+    pub async fn set_edited(&self, source_id: &str, edited: &[(String, String)]) -> Result<()> {
+        let mut transaction = self
+            .pool
+            .begin()
+            .await
+            .context("failed to start transaction")?;
+        sqlx::query("DELETE FROM knowledge_documents WHERE source_id = ?")
+            .bind(source_id)
+            .execute(&mut *transaction)
+            .await
+            .context("failed to clear knowledge documents")?;
+        for (document, edited_at) in edited {
+            sqlx::query(
+                "INSERT INTO knowledge_documents (source_id, document, edited_at) VALUES (?, ?, ?)",
+            )
+            .bind(source_id)
+            .bind(document)
+            .bind(edited_at)
+            .execute(&mut *transaction)
+            .await
+            .context("failed to record knowledge document")?;
+        }
+        transaction
+            .commit()
+            .await
+            .context("failed to commit knowledge documents")?;
+
+        Ok(())
+    }
+
+    /// Record a chunk as stored in memory `memory_id`.
+    pub async fn add_chunk(
+        &self,
+        source_id: &str,
+        chunk_hash: &str,
+        document: &str,
+        memory_id: &str,
+    ) -> Result<()> {
+        sqlx::query(
+            "INSERT OR REPLACE INTO knowledge_chunks (source_id, chunk_hash, document, memory_id) \
+             VALUES (?, ?, ?, ?)",
+        )
+        .bind(source_id)
+        .bind(chunk_hash)
+        .bind(document)
+        .bind(memory_id)
+        .execute(&self.pool)
+        .await
+        .context("failed to record knowledge chunk")?;
+
+        Ok(())
+    }
+
+    pub async fn remove_chunk(&self, source_id: &str, chunk_hash: &str) -> Result<()> {
+        sqlx::query("DELETE FROM knowledge_chunks WHERE source_id = ? AND chunk_hash = ?")
+            .bind(source_id)
+            .bind(chunk_hash)
+            .execute(&self.pool)
+            .await
+            .context("failed to remove knowledge chunk")?;
+
+        Ok(())
+    }
+
+    /// IDs of every source with chunks or a status row, including sources
+    /// since removed from config.
+    pub async fn source_ids(&self) -> Result<Vec<String>> {
+        let rows = sqlx::query(
+            "SELECT source_id FROM knowledge_chunks \
+             UNION SELECT source_id FROM knowledge_sources",
+        )
+        .fetch_all(&self.pool)
+        .await
+        .context("failed to list knowledge sources")?;
+
+        Ok(rows
+            .into_iter()
+            .filter_map(|row| row.try_get("source_id").ok())
+            .collect())
+    }
+
+    /// Forget a source's status and document edit times. Its chunks are
+    /// removed one by one by the caller, alongside their memories.
+    pub async fn remove_status(&self, source_id: &str) -> Result<()> {
+        sqlx::query("DELETE FROM knowledge_sources WHERE source_id = ?")
+            .bind(source_id)
+            .execute(&self.pool)
+            .await
+            .context("failed to remove knowledge source status")?;
+        self.set_edited(source_id, &[]).await
+    }
+
+    /// Mark a sync of `source_id` as started.
+    pub async fn start_sync(&self, source_id: &str) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO knowledge_sources (source_id, status, last_sync_at) \
+             VALUES (?, 'syncing', CURRENT_TIMESTAMP) \
+             ON CONFLICT(source_id) DO UPDATE SET \
+             status = 'syncing', last_sync_at = CURRENT_TIMESTAMP",
+        )
+        .bind(source_id)
+        .execute(&self.pool)
+        .await
+        .context("failed to record knowledge sync start")?;
+
+        Ok(())
+    }
+
+    /// Record a successful sync and its counts.
+    pub async fn finish_sync(
+        &self,
+        source_id: &str,
+        documents: usize,
+        chunks: usize,
+        added: usize,
+        removed: usize,
+    ) -> Result<()> {
+        sqlx::query(
+            "UPDATE knowledge_sources SET status = 'ok', last_success_at = CURRENT_TIMESTAMP, \
+             last_error = NULL, documents = ?, chunks = ?, added = ?, removed = ? \
+             WHERE source_id = ?",
+        )
+        .bind(documents as i64)
+        .bind(chunks as i64)
+        .bind(added as i64)
+        .bind(removed as i64)
+        .bind(source_id)
+        .execute(&self.pool)
+        .await
+        .context("failed to record knowledge sync")?;
+
+        Ok(())
+    }
+
+    /// Record a failed sync. Chunks from earlier syncs stay in place.
+    pub async fn fail_sync(&self, source_id: &str, error: &str) -> Result<()> {
+        sqlx::query(
+            "UPDATE knowledge_sources SET status = 'error', last_error = ? WHERE source_id = ?",
+        )
+        .bind(error)
+        .bind(source_id)
+        .execute(&self.pool)
+        .await
+        .context("failed to record knowledge sync failure")?;
+
+        Ok(())
+    }
+
+    /// When the latest sync of `source_id` started, if it ever ran.
+    pub async fn last_sync_at(
+        &self,
+        source_id: &str,
+    ) -> Result<Option<chrono::DateTime<chrono::Utc>>> {
+        let row = sqlx::query("SELECT last_sync_at FROM knowledge_sources WHERE source_id = ?")
+            .bind(source_id)
+            .fetch_optional(&self.pool)
+            .await
+            .context("failed to load knowledge sync time")?;
+
+        Ok(row
+            .and_then(|row| {
+                row.try_get::<Option<chrono::NaiveDateTime>, _>("last_sync_at")
+                    .ok()
+            })
+            .flatten()
+            .map(|timestamp| timestamp.and_utc()))
+    }
+
+    /// Status of every source that has synced at least once.
+    pub async fn statuses(&self) -> Result<Vec<KnowledgeStatus>> {
+        let rows = sqlx::query(
+            "SELECT source_id, status, last_sync_at, last_success_at, last_error, \
+             documents, chunks, added, removed FROM knowledge_sources",
+        )
+        .fetch_all(&self.pool)
+        .await
+        .context("failed to load knowledge source statuses")?;
+
+        let timestamp = |row: &sqlx::sqlite::SqliteRow, column: &str| {
+            row.try_get::<Option<chrono::NaiveDateTime>, _>(column)
+                .ok()
+                .flatten()
+                .map(|timestamp| timestamp.and_utc().to_rfc3339())
+        };
+        Ok(rows
+            .iter()
+            .map(|row| KnowledgeStatus {
+                source_id: row.try_get("source_id").unwrap_or_default(),
+                status: row.try_get("status").unwrap_or_default(),
+                last_sync_at: timestamp(row, "last_sync_at"),
+                last_success_at: timestamp(row, "last_success_at"),
+                last_error: row.try_get("last_error").ok().flatten(),
+                documents: row.try_get("documents").unwrap_or_default(),
+                chunks: row.try_get("chunks").unwrap_or_default(),
+                added: row.try_get("added").unwrap_or_default(),
+                removed: row.try_get("removed").unwrap_or_default(),
+            })
+            .collect())
+    }
+}
@@ -0,0 +1,408 @@
+//! Scheduled knowledge source syncs.
+//!
+//! One sync loop runs per agent. Each enabled source in
+//! `runtime_config.knowledge` is re-crawled on its interval and its documents
+//! are chunked. A chunk is identified by a hash of its document and text, so
+//! a sync embeds only the chunks that are new or changed, and deletes the
+//! memories of chunks that are gone. Documents the source reports as not
+//! edited since the last sync keep their chunks without being fetched. A
+//! failed crawl leaves the previous chunks in place. Sources removed from
+//! config have their chunks deleted.
+
+use crate::AgentDeps;
+use crate::agent::ingestion::{chunk_text, content_hash};
+use crate::config::KnowledgeSourceDef;
+use crate::error::Result;
+use crate::knowledge::{Document, KnowledgeStore, StoredChunk, sources};
+use crate::memory::types::{Memory, MemoryType};
+
+use anyhow::Context as _;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// How often the sync loop checks which sources are due.
+const TICK: Duration = Duration::from_secs(30);
+
+/// Sources are never synced more often than this, whatever their interval.
+const MIN_INTERVAL_SECS: u64 = 300;
+
+const FETCH_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// One chunk of a document, as stored in memory.
+#[derive(Debug, Clone, PartialEq)]
+struct Chunk {
+    hash: String,
+    document: String,
+    content: String,
+}
+
+/// What a sync changes: chunks to embed, and the (hash, memory ID) of
+/// chunks to delete.
+#[derive(Debug, Default, PartialEq)]
+struct SyncPlan {
+    add: Vec<Chunk>,
+    remove: Vec<(String, String)>,
+}
+
+/// Spawn the knowledge sync loop for an agent.
+///
+/// Reads `deps.runtime_config.knowledge` on every tick, so sources added or
+/// changed on config reload are picked up without a restart. A source's
+/// first sync after startup is due one interval after its last sync, so
+/// restarts don't re-crawl everything. Nothing syncs during maintenance.
+pub fn spawn_knowledge_sync(
+    deps: AgentDeps,
+    store: Arc<KnowledgeStore>,
+    checkout_dir: PathBuf,
+) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let http = reqwest::Client::builder()
+            .timeout(FETCH_TIMEOUT)
+            .user_agent(concat!("spacebot/", env!("CARGO_PKG_VERSION")))
+            .build()
+            .expect("hardcoded reqwest client config");
+        let mut next_sync: HashMap<String, Instant> = HashMap::new();
+
+        loop {
+            let knowledge = deps.runtime_config.knowledge.load_full();
+            next_sync.retain(|id, _| knowledge.iter().any(|source| &source.id == id));
+
+            if !deps.maintenance.is_enabled() {
+                if let Err(error) = remove_stale_sources(&deps, &store, &knowledge).await {
+                    tracing::warn!(%error, "failed to remove stale knowledge sources");
+                }
+
+                for source in knowledge.iter().filter(|source| source.enabled) {
+                    let interval = Duration::from_secs(source.interval_secs.max(MIN_INTERVAL_SECS));
+                    let due = match next_sync.get(&source.id) {
+                        Some(due) => *due,
+                        None => first_due(&store, source, interval).await,
+                    };
+                    if Instant::now() < due {
+                        next_sync.insert(source.id.clone(), due);
+                        continue;
+                    }
+                    next_sync.insert(source.id.clone(), Instant::now() + interval);
+
+                    run_sync(&deps, &store, &http, &checkout_dir, source).await;
+                }
+            }
+
+            tokio::time::sleep(TICK).await;
+        }
+    })
+}
+
+/// When a source not yet seen by this loop is due: one interval after its
+/// last sync, or now if it never synced.
+async fn first_due(
+    store: &KnowledgeStore,
+    source: &KnowledgeSourceDef,
+    interval: Duration,
+) -> Instant {
+    let last_sync_at = match store.last_sync_at(&source.id).await {
+        Ok(last_sync_at) => last_sync_at,
+        Err(error) => {
+            tracing::warn!(source_id = %source.id, %error, "failed to load knowledge sync time");
+            None
+        }
+    };
+    let elapsed = last_sync_at
+        .and_then(|last| (chrono::Utc::now() - last).to_std().ok())
+        .unwrap_or(interval);
+    Instant::now() + interval.saturating_sub(elapsed)
+}
+
+/// Sync one source and record the outcome.
+async fn run_sync(
+    deps: &AgentDeps,
+    store: &KnowledgeStore,
+    http: &reqwest::Client,
+    checkout_dir: &Path,
+    source: &KnowledgeSourceDef,
+) {
+    if let Err(error) = store.start_sync(&source.id).await {
+        tracing::warn!(source_id = %source.id, %error, "failed to record knowledge sync start");
+    }
+    let Err(error) = sync(deps, store, http, checkout_dir, source).await else {
+        return;
+    };
+    tracing::warn!(source_id = %source.id, %error, "knowledge sync failed");
+    if let Err(error) = store.fail_sync(&source.id, &error.to_string()).await {
+        tracing::warn!(
+            source_id = %source.id,
+            %error,
+            "failed to record knowledge sync failure"
+        );
+    }
+}
+
+/// Re-crawl a source, embed its new chunks and delete the ones that are gone.
+async fn sync(
+    deps: &AgentDeps,
+    store: &KnowledgeStore,
+    http: &reqwest::Client,
+    checkout_dir: &Path,
+    source: &KnowledgeSourceDef,
+) -> Result<()> {
+    let edited = store.edited(&source.id).await?;
+    let documents = sources::crawl(http, source, checkout_dir, &edited).await?;
+    let existing = store.chunks(&source.id).await?;
+    if documents.is_empty() && !existing.is_empty() {
+        // More likely a broken crawl than a source that was emptied
+        return Err(crate::error::Error::Other(anyhow::anyhow!(
+            "crawl found no documents, keeping the {} chunks from the last sync",
+            existing.len()
+        )));
+    }
+
+    let unchanged: HashSet<&str> = documents
+        .iter()
+        .filter(|document| document.text.is_none())
+        .map(|document| document.id.as_str())
+        .collect();
+    let plan = plan_sync(
+        &existing,
+        document_chunks(&documents, source.chunk_size),
+        &unchanged,
+    );
+    tracing::info!(
+        source_id = %source.id,
+        documents = documents.len(),
+        unchanged = unchanged.len(),
+        added = plan.add.len(),
+        removed = plan.remove.len(),
+        "syncing knowledge source"
+    );
+
+    // New chunks go in before old ones come out, so retrieval never sees
+    // a document missing mid-sync.
+    for chunk in &plan.add {
+        let memory = Memory::new(&chunk.content, MemoryType::Fact).with_source(&source.store);
+        deps.memory_search
+            .store()
+            .save(&memory)
+            .await
+            .context("failed to save knowledge chunk")?;
+        let embedding = deps
+            .memory_search
+            .embedding_model_arc()
+            .embed_one(&chunk.content)
+            .await
+            .context("failed to embed knowledge chunk")?;
+        deps.memory_search
+            .embedding_table()
+            .store(&memory.id, &chunk.content, &embedding)
+            .await
+            .context("failed to store knowledge chunk embedding")?;
+        store
+            .add_chunk(&source.id, &chunk.hash, &chunk.document, &memory.id)
+            .await?;
+    }
+    let fts = if plan.add.is_empty() {
+        Ok(())
+    } else {
+        deps.memory_search
+            .embedding_table()
+            .ensure_fts_index()
+            .await
+    };
+    if let Err(error) = fts {
+        tracing::warn!(%error, "failed to ensure FTS index after knowledge sync");
+    }
+
+    for (hash, memory_id) in &plan.remove {
+        delete_chunk(deps, store, &source.id, hash, memory_id).await?;
+    }
+
+    let edited: Vec<(String, String)> = documents
+        .iter()
+        .filter_map(|document| Some((document.id.clone(), document.edited_at.clone()?)))
+        .collect();
+    store.set_edited(&source.id, &edited).await?;
+    store
+        .finish_sync(
+            &source.id,
+            documents.len(),
+            existing.len() + plan.add.len() - plan.remove.len(),
+            plan.add.len(),
+            plan.remove.len(),
+        )
+        .await
+}
+
+/// Delete the chunks and status of sources no longer in config.
+async fn remove_stale_sources(
+    deps: &AgentDeps,
+    store: &KnowledgeStore,
+    knowledge: &[KnowledgeSourceDef],
+) -> Result<()> {
+    for source_id in store.source_ids().await? {
+        if knowledge.iter().any(|source| source.id == source_id) {
+            continue;
+        }
+        let chunks = store.chunks(&source_id).await?;
+        tracing::info!(
+            %source_id,
+            chunks = chunks.len(),
+            "removing knowledge source no longer in config"
+        );
+        for (hash, chunk) in &chunks {
+            delete_chunk(deps, store, &source_id, hash, &chunk.memory_id).await?;
+        }
+        store.remove_status(&source_id).await?;
+    }
+    Ok(())
+}
+
+async fn delete_chunk(
+    deps: &AgentDeps,
+    store: &KnowledgeStore,
+    source_id: &str,
+    hash: &str,
+    memory_id: &str,
+) -> Result<()> {
+    deps.memory_search
+        .embedding_table()
+        .delete(memory_id)
+        .await
+        .context("failed to delete knowledge chunk embedding")?;
+    deps.memory_search
+        .store()
+        .delete(memory_id)
+        .await
+        .context("failed to delete knowledge chunk")?;
+    store.remove_chunk(source_id, hash).await
+}
+
+/// Split the fetched documents into chunks of about `chunk_size`
+/// characters, each headed by its document's title.
+fn document_chunks(documents: &[Document], chunk_size: usize) -> Vec<Chunk> {
+    documents
+        .iter()
+        .filter_map(|document| Some((document, document.text.as_deref()?)))
+        .flat_map(|(document, text)| {
+            chunk_text(text.trim(), chunk_size).into_iter().map(|text| {
+                let content = format!("{}\n\n{text}", document.title);
+                Chunk {
+                    hash: content_hash(&format!("{}\n{content}", document.id)),
+                    document: document.id.clone(),
+                    content,
+                }
+            })
+        })
+        .collect()
+}
+
+/// Compare the chunks stored by the last sync, by hash, with the current
+/// ones. Chunks of `unchanged` documents are kept. Repeated chunks are
+/// added once.
+fn plan_sync(
+    existing: &HashMap<String, StoredChunk>,
+    chunks: Vec<Chunk>,
+    unchanged: &HashSet<&str>,
+) -> SyncPlan {
+    let mut current = HashSet::new();
+    let mut add = Vec::new();
+    for chunk in chunks {
+        if !current.insert(chunk.hash.clone()) || existing.contains_key(&chunk.hash) {
+            continue;
+        }
+        add.push(chunk);
+    }
+    let mut remove: Vec<(String, String)> = existing
+        .iter()
+        .filter(|(hash, chunk)| {
+            !current.contains(*hash) && !unchanged.contains(chunk.document.as_str())
+        })
+        .map(|(hash, chunk)| (hash.clone(), chunk.memory_id.clone()))
+        .collect();
+    remove.sort();
+    SyncPlan { add, remove }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn document(id: &str, text: &str) -> Document {
+        Document {
+            id: id.into(),
+            title: id.into(),
+            text: Some(text.into()),
+            edited_at: None,
+        }
+    }
+
+    #[test]
+    fn test_document_chunks_are_stable() {
+        let documents = [
+            document("guide.md", "alpha\nbeta\ngamma"),
+            document("faq.md", "alpha"),
+        ];
+        let chunks = document_chunks(&documents, 12);
+        assert_eq!(chunks.len(), 3);
+        assert_eq!(chunks[0].content, "guide.md\n\nalpha\nbeta");
+        assert_eq!(chunks[0].document, "guide.md");
+        // The same text in another document is a different chunk
+        let faq = chunks
+            .iter()
+            .find(|chunk| chunk.document == "faq.md")
+            .unwrap();
+        assert_ne!(faq.hash, chunks[0].hash);
+        assert_eq!(document_chunks(&documents, 12), chunks);
+    }
+
+    #[test]
+    fn test_plan_sync() {
+        let old = document_chunks(
+            &[
+                document("a.md", "one\ntwo"),
+                document("c.md", "kept"),
+                document("d.md", "gone"),
+            ],
+            4,
+        );
+        let existing: HashMap<String, StoredChunk> = old
+            .iter()
+            .enumerate()
+            .map(|(index, chunk)| {
+                let stored = StoredChunk {
+                    document: chunk.document.clone(),
+                    memory_id: format!("memory-{index}"),
+                };
+                (chunk.hash.clone(), stored)
+            })
+            .collect();
+
+        // "two" was edited to "three", c.md wasn't edited so it wasn't
+        // fetched, d.md was deleted, and a document was added twice
+        let unchanged_doc = Document {
+            text: None,
+            ..document("c.md", "")
+        };
+        let documents = [
+            document("a.md", "one\nthree"),
+            unchanged_doc,
+            document("b.md", "new"),
+            document("b.md", "new"),
+        ];
+        let unchanged = HashSet::from(["c.md"]);
+        let plan = plan_sync(&existing, document_chunks(&documents, 4), &unchanged);
+
+        let added: Vec<&str> = plan
+            .add
+            .iter()
+            .map(|chunk| chunk.content.as_str())
+            .collect();
+        assert_eq!(added, ["a.md\n\nthree", "b.md\n\nnew"]);
+        let mut removed = vec![
+            (old[1].hash.clone(), "memory-1".to_string()),
+            (old[3].hash.clone(), "memory-3".to_string()),
+        ];
+        removed.sort();
+        assert_eq!(plan.remove, removed);
+    }
+}
@@ -0,0 +1,91 @@
+//! Language detection for inbound messages.
+//!
+//! Each message's language is detected locally, so the channel can ask the
+//! model to reply in kind, and each turn is labeled with it so operators can
+//! see which languages their users write in.
+
+use crate::error::Result;
+
+use anyhow::Context as _;
+use serde::Serialize;
+use sqlx::{Row as _, SqlitePool};
+
+/// A detected language.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Language {
+    /// ISO 639-3 code, e.g. "deu". What turns are labeled with.
+    pub code: &'static str,
+    /// English name, e.g. "German". What the model is asked to reply in.
+    pub name: &'static str,
+}
+
+/// The language `text` is written in, when detection is confident. It
+/// rarely is for short messages like "ok" or "thanks!".
+pub fn detect(text: &str) -> Option<Language> {
+    let info = whatlang::detect(text).filter(whatlang::Info::is_reliable)?;
+    Some(Language {
+        code: info.lang().code(),
+        name: info.lang().eng_name(),
+    })
+}
+
+/// Turns in one language.
+#[derive(Debug, Clone, Serialize)]
+pub struct LanguageCount {
+    /// ISO 639-3 code. None for turns whose language wasn't detected.
+    pub language: Option<String>,
+    /// English name of the language.
+    pub name: Option<String>,
+    pub turns: u64,
+    /// Distinct conversations the turns were in.
+    pub conversations: u64,
+}
+
+/// How many of an agent's turns in the last `days` days were in each
+/// language, most common first.
+pub async fn language_mix(pool: &SqlitePool, days: u32) -> Result<Vec<LanguageCount>> {
+    let rows = sqlx::query(
+        "SELECT json_extract(outcome, '$.language') AS language, COUNT(*) AS turns, \
+         COUNT(DISTINCT channel_id) AS conversations \
+         FROM turn_runs WHERE completed_at >= datetime('now', ?) \
+         GROUP BY language ORDER BY turns DESC",
+    )
+    .bind(format!("-{days} days"))
+    .fetch_all(pool)
+    .await
+    .context("failed to count turns by language")?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| {
+            let language: Option<String> = row.try_get("language").unwrap_or_default();
+            let name = language
+                .as_deref()
+                .and_then(whatlang::Lang::from_code)
+                .map(|lang| lang.eng_name().to_string());
+            LanguageCount {
+                language,
+                name,
+                turns: row.try_get::<i64, _>("turns").unwrap_or_default() as u64,
+                conversations: row.try_get::<i64, _>("conversations").unwrap_or_default() as u64,
+            }
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect() {
+        let german = detect("Kannst du mir bitte sagen, wann der nächste Zug nach Berlin fährt?");
+        assert_eq!(german.map(|language| language.code), Some("deu"));
+        assert_eq!(german.map(|language| language.name), Some("German"));
+
+        let spanish = detect("¿Puedes ayudarme a configurar el servidor de correo esta tarde?");
+        assert_eq!(spanish.map(|language| language.code), Some("spa"));
+
+        assert_eq!(detect("ok"), None);
+    }
+}
@@ -1,27 +1,58 @@
 //! Spacebot: A Rust agentic system where every LLM process has a dedicated role.
 
+pub mod activation;
 pub mod agent;
 pub mod api;
+pub mod approval;
+pub mod bench;
+pub mod budget;
+pub mod calc;
+pub mod changelog;
+pub mod citations;
 pub mod config;
 pub mod conversation;
+pub mod crash;
 pub mod cron;
 pub mod daemon;
 pub mod db;
+pub mod declined;
+pub mod digests;
+pub mod doctor;
 pub mod error;
+pub mod feedback;
+pub mod feeds;
+pub mod flags;
+pub mod finetune;
+pub mod home_assistant;
 pub mod hooks;
 pub mod identity;
-pub mod llm;
+pub mod incidents;
+pub mod instance;
+pub mod knowledge;
+pub mod language;
+pub mod maintenance;
 pub mod memory;
 pub mod messaging;
+pub mod observability;
 pub mod opencode;
+pub mod privacy;
+pub mod prompt_library;
 pub mod prompts;
 pub mod secrets;
+pub mod service;
 pub mod settings;
 pub mod skills;
+pub mod speech;
+pub mod storage;
 pub mod tools;
 pub mod update;
+pub mod usage_anomalies;
+pub mod usage_privacy;
+pub mod usage_snapshots;
+pub mod usage_tags;
 
 pub use error::{Error, Result};
+pub use spacebot_core::{ProcessType, events, llm, permissions};
 
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
@@ -64,29 +95,6 @@ impl std::fmt::Display for ProcessId {
     }
 }
 
-/// Process types in the system.
-#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
-#[serde(rename_all = "snake_case")]
-pub enum ProcessType {
-    Channel,
-    Branch,
-    Worker,
-    Compactor,
-    Cortex,
-}
-
-impl std::fmt::Display for ProcessType {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            ProcessType::Channel => write!(f, "channel"),
-            ProcessType::Branch => write!(f, "branch"),
-            ProcessType::Worker => write!(f, "worker"),
-            ProcessType::Compactor => write!(f, "compactor"),
-            ProcessType::Cortex => write!(f, "cortex"),
-        }
-    }
-}
-
 /// Events sent between processes.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type", rename_all = "snake_case")]
@@ -139,6 +147,9 @@ pub enum ProcessEvent {
         process_id: ProcessId,
         channel_id: Option<ChannelId>,
         tool_name: String,
+        /// What the result came from, for crediting replies to it. `None`
+        /// for tools that don't fetch information.
+        source: Option<String>,
         result: String,
     },
     MemorySaved {
@@ -164,6 +175,29 @@ pub enum ProcessEvent {
         description: String,
         patterns: Vec<String>,
     },
+    /// A worker wants to run a side-effecting tool and is waiting for the
+    /// user to confirm the rendered preview.
+    ActionPreview {
+        agent_id: AgentId,
+        process_id: ProcessId,
+        channel_id: ChannelId,
+        action_id: String,
+        tool_name: String,
+        preview: String,
+    },
+    /// An image, table or file from a tool, for the channel to deliver.
+    ToolContent {
+        agent_id: AgentId,
+        process_id: ProcessId,
+        channel_id: ChannelId,
+        content: tools::content::ToolContent,
+    },
+    /// A channel turn finished.
+    TurnCompleted {
+        agent_id: AgentId,
+        channel_id: ChannelId,
+        outcome: agent::turn::TurnOutcome,
+    },
     WorkerQuestion {
         agent_id: AgentId,
         worker_id: WorkerId,
@@ -183,6 +217,24 @@ pub struct AgentDeps {
     pub runtime_config: Arc<config::RuntimeConfig>,
     pub event_tx: tokio::sync::broadcast::Sender<ProcessEvent>,
     pub sqlite_pool: sqlx::SqlitePool,
+    /// Side-effecting actions waiting for a user's `/confirm` or `/reject`.
+    pub approvals: approval::ActionApprovals,
+    /// Inbound message quotas, checked by the router.
+    pub rate_limiter: messaging::rate_limit::RateLimiter,
+    /// Counts of the tool guard's verdicts, shared by the agent's workers.
+    pub guard_metrics: hooks::tool_guard::GuardMetrics,
+    /// Counts of messages the policy layers declined, by reason.
+    pub decline_metrics: declined::DeclineMetrics,
+    /// Where the agent is on its budget's degradation ladder.
+    pub budget: budget::BudgetTracker,
+    /// Instance-wide maintenance switch. No new turns start while it's on.
+    pub maintenance: maintenance::Maintenance,
+    /// Instance-wide handoff state, shared with the router.
+    pub handoffs: agent::handoff::Handoffs,
+    /// Proxies enforcing the agent's per-tool network policies.
+    pub network: tools::NetworkSandbox,
+    /// Instance-wide fetcher for inbound attachment URLs.
+    pub downloads: messaging::download::AttachmentDownloader,
 }
 
 impl AgentDeps {
@@ -197,6 +249,146 @@ impl AgentDeps {
     pub fn routing(&self) -> arc_swap::Guard<Arc<llm::RoutingConfig>> {
         self.runtime_config.routing.load()
     }
+
+    /// Build the per-turn tool filter for this agent, if enabled.
+    pub fn tool_filter(&self) -> Option<spacebot_core::llm::tool_filter::ToolFilter> {
+        let config = self.runtime_config.tool_filter.load();
+        if !config.enabled {
+            return None;
+        }
+        let ranker = tools::relevance::EmbeddingToolRanker::new(
+            self.memory_search.embedding_model_arc().clone(),
+        );
+        let always_include = config.always_include.iter().cloned().chain(
+            tools::relevance::ESSENTIAL_TOOLS
+                .iter()
+                .map(|name| name.to_string()),
+        );
+        Some(spacebot_core::llm::tool_filter::ToolFilter::new(
+            Arc::new(ranker),
+            config.top_k,
+            always_include,
+        ))
+    }
+
+    /// Build the prompt compressor for this agent, if enabled.
+    pub fn compressor(&self) -> Option<spacebot_core::llm::compress::PromptCompressor> {
+        let config = self.runtime_config.compression.load();
+        config.enabled.then(|| {
+            spacebot_core::llm::compress::PromptCompressor::new(config.ratio, config.min_chars)
+        })
+    }
+
+    /// The output token cap for a `process_type` completion on `model_name`,
+    /// if `[defaults.output_caps]` sets one.
+    pub fn output_cap(
+        &self,
+        process_type: ProcessType,
+        model_name: &str,
+    ) -> Option<spacebot_core::llm::output_cap::OutputCap> {
+        self.runtime_config
+            .output_caps
+            .load()
+            .resolve(process_type, model_name)
+    }
+
+    /// The nudge to retry empty completions with, if retrying is on.
+    pub fn empty_nudge(&self) -> Option<String> {
+        let config = self.runtime_config.empty_response.load();
+        config.retry.then(|| config.nudge.clone())
+    }
+
+    /// Build the preview gate for this agent's workers, if preview mode is on,
+    /// the computer tool needs confirmation or incidents can be changed.
+    /// Incident changes are confirmed even with preview mode off.
+    pub fn preview_gate(&self) -> Option<approval::PreviewGate> {
+        let config = self.runtime_config.preview.load();
+        let computer_use = self.runtime_config.computer_use.load();
+        let mut tools = if config.enabled {
+            config.tools.clone()
+        } else {
+            Vec::new()
+        };
+        let mut confirm_every_call = false;
+        if computer_use.available() {
+            match computer_use.confirm {
+                config::ComputerConfirm::Always => confirm_every_call = true,
+                config::ComputerConfirm::Actions => tools.push("computer".into()),
+                config::ComputerConfirm::Never => {}
+            }
+        }
+        if self.runtime_config.incidents.load().enabled {
+            tools.push("incident".into());
+        }
+
+        let mut gate = approval::PreviewGate::new(
+            self.approvals.clone(),
+            tools,
+            std::time::Duration::from_secs(config.timeout_secs),
+            self.runtime_config.workspace_dir.clone(),
+        );
+        if confirm_every_call {
+            gate = gate.with_every_call("computer");
+        }
+        (!gate.is_empty()).then_some(gate)
+    }
+
+    /// Build the tool guard for a worker working on `task`, if the guard is
+    /// enabled. Calls it's unsure about are confirmed like previews.
+    pub fn tool_guard(&self, task: &str) -> Option<hooks::ToolGuard> {
+        if !self.runtime_config.tool_guard.load().enabled {
+            return None;
+        }
+        let preview = self.runtime_config.preview.load();
+        let escalation = approval::PreviewGate::new(
+            self.approvals.clone(),
+            Vec::new(),
+            std::time::Duration::from_secs(preview.timeout_secs),
+            self.runtime_config.workspace_dir.clone(),
+        );
+        Some(hooks::ToolGuard::new(
+            self.runtime_config.clone(),
+            self.llm_manager.clone(),
+            self.guard_metrics.clone(),
+            task,
+            escalation,
+        ))
+    }
+
+    /// Build a loop guard for one of this agent's processes.
+    pub fn loop_guard(&self) -> hooks::LoopGuard {
+        hooks::LoopGuard::new(
+            self.runtime_config.clone(),
+            agent::cortex::CortexLogger::new(self.sqlite_pool.clone()),
+        )
+    }
+
+    /// Whether the sender of `message` may decide a pending `tool_name`
+    /// action. Incident changes are limited to the incident responders, or
+    /// the admin users when none are listed; anything else can be decided by
+    /// anyone in the channel.
+    pub fn may_decide(&self, tool_name: &str, message: &InboundMessage) -> bool {
+        if tool_name != "incident" {
+            return true;
+        }
+        let incidents = self.runtime_config.incidents.load();
+        let admin_users = self.runtime_config.admin_users.load();
+        let deciders = if incidents.responders.is_empty() {
+            &**admin_users
+        } else {
+            &incidents.responders
+        };
+        agent::model_override::is_admin(deciders, &message.source, &message.sender_id)
+    }
+
+    /// Whether the sender of `message` may run admin chat commands.
+    pub fn is_admin(&self, message: &InboundMessage) -> bool {
+        agent::model_override::is_admin(
+            &self.runtime_config.admin_users.load(),
+            &message.source,
+            &message.sender_id,
+        )
+    }
 }
 
 /// A running agent instance with all its isolated resources.
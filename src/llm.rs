@@ -1,10 +0,0 @@
-//! LLM provider management and routing.
-
-pub mod manager;
-pub mod model;
-pub mod providers;
-pub mod routing;
-
-pub use manager::LlmManager;
-pub use model::SpacebotModel;
-pub use routing::RoutingConfig;
@@ -23,6 +23,189 @@ struct Cli {
     /// Enable debug logging
     #[arg(short, long, global = true)]
     debug: bool,
+
+    /// Print machine-readable JSON instead of text (status, doctor)
+    #[arg(long, global = true)]
+    json: bool,
+}
+
+#[derive(Subcommand)]
+enum ServiceAction {
+    /// Write the unit file (Linux) or plist (macOS) and start the service
+    Install {
+        /// Install system-wide (still running as the current user) instead of as a
+        /// per-user service
+        #[arg(long)]
+        system: bool,
+        /// Environment file loaded by the service (default: <instance dir>/spacebot.env)
+        #[arg(long)]
+        env_file: Option<std::path::PathBuf>,
+        /// Write and enable the service without starting it
+        #[arg(long)]
+        no_start: bool,
+    },
+    /// Stop the service and remove its definition
+    Uninstall {
+        #[arg(long)]
+        system: bool,
+    },
+    /// Show whether the service is installed and running
+    Status {
+        #[arg(long)]
+        system: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum ExportTarget {
+    /// Write conversations as fine-tuning JSONL, with personal data redacted
+    Finetune {
+        /// Provider format: openai or anthropic
+        #[arg(long)]
+        format: spacebot::finetune::FinetuneFormat,
+        /// Only export this agent (default: all agents)
+        #[arg(long)]
+        agent: Option<String>,
+        /// `up`: conversations whose rated replies were all rated up;
+        /// `down`: conversations with any reply rated down
+        #[arg(long)]
+        rating: Option<spacebot::feedback::Rating>,
+        /// Only messages sent on or after this day (YYYY-MM-DD, UTC)
+        #[arg(long)]
+        since: Option<chrono::NaiveDate>,
+        /// Only messages sent on or before this day (YYYY-MM-DD, UTC)
+        #[arg(long)]
+        until: Option<chrono::NaiveDate>,
+        /// Write to this file instead of stdout
+        #[arg(short, long)]
+        output: Option<std::path::PathBuf>,
+    },
+}
+
+#[derive(Subcommand)]
+enum AgentsAction {
+    /// Show an agent's models, tools, memory, limits, schedules and bindings
+    /// as the daemon resolves them
+    Describe {
+        /// Agent ID
+        name: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum RoutingAction {
+    /// Show which models a failing request would try, in order, without
+    /// calling any provider
+    Simulate {
+        /// Model the request is routed to
+        #[arg(long)]
+        model: String,
+        /// Failure every tried model returns: rate_limit, overloaded,
+        /// server_error, timeout, auth, bad_request or context_overflow
+        #[arg(long, default_value = "rate_limit")]
+        error: spacebot::llm::simulate::SimulatedError,
+        /// Use this agent's routing instead of [defaults.routing]
+        #[arg(long)]
+        agent: Option<String>,
+        /// Ignore the running daemon's cooldown and health state
+        #[arg(long)]
+        fresh: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum ConfigAction {
+    /// Print a JSON Schema for config.toml, for editor completion and validation
+    Schema {
+        /// Write to this file instead of stdout
+        #[arg(short, long)]
+        output: Option<std::path::PathBuf>,
+    },
+    /// Show what reloading config.toml would change, and whether each change
+    /// applies on reload or needs a restart
+    Diff {
+        /// `running` for the daemon's live config, or a config file to compare
+        /// config.toml against
+        #[arg(long, default_value = "running")]
+        against: String,
+    },
+    /// Reload config.toml into the running daemon now
+    Apply {
+        /// Give only this agent the new routing until applied again without
+        /// --canary, e.g. `--canary agent=support`
+        #[arg(long, value_name = "agent=ID")]
+        canary: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum PrivacyAction {
+    /// Delete a user's messages, feedback and memories from every agent
+    Forget {
+        /// Platform user ID, as the adapters report it
+        #[arg(long)]
+        user: String,
+        /// Only forget the user on this platform, e.g. discord
+        #[arg(long)]
+        platform: Option<String>,
+        /// Only this agent (default: all agents)
+        #[arg(long)]
+        agent: Option<String>,
+        /// Report what would be deleted without deleting anything
+        #[arg(long)]
+        dry_run: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum TranscriptAction {
+    /// Print a conversation with tool calls folded, secrets redacted and
+    /// what each turn cost
+    Show {
+        /// Conversation (channel) ID, e.g. discord:123:456
+        conversation_id: String,
+        /// Agent that owns the conversation (default: whichever has it)
+        #[arg(long)]
+        agent: Option<String>,
+        /// Unfold tool calls and include the raw provider exchanges behind
+        /// each turn, from the running daemon's llm.debug_recording
+        #[arg(long)]
+        raw: bool,
+    },
+    /// Write a conversation as Markdown or HTML for a wiki or an issue, with
+    /// secrets redacted, tool calls collapsed and images inline
+    Export {
+        /// Conversation (channel) ID, e.g. discord:123:456
+        conversation_id: String,
+        /// Agent that owns the conversation (default: whichever has it)
+        #[arg(long)]
+        agent: Option<String>,
+        /// Output format: md or html
+        #[arg(long, default_value = "md")]
+        format: spacebot::conversation::transcript::ExportFormat,
+        /// Write to this file instead of stdout
+        #[arg(short, long)]
+        output: Option<std::path::PathBuf>,
+    },
+}
+
+/// `spacebot status --json` output.
+#[derive(serde::Serialize)]
+struct StatusOutput {
+    running: bool,
+    pid: Option<u32>,
+    uptime_seconds: Option<u64>,
+    /// Set when the daemon is running but couldn't be queried.
+    error: Option<String>,
+}
+
+/// `spacebot doctor --json` output.
+#[derive(serde::Serialize)]
+struct DoctorOutput {
+    /// False when any check failed.
+    ok: bool,
+    failures: usize,
+    checks: Vec<spacebot::doctor::Check>,
 }
 
 #[derive(Subcommand)]
@@ -32,6 +215,16 @@ enum Command {
         /// Run in the foreground instead of daemonizing
         #[arg(short, long)]
         foreground: bool,
+        /// Start in maintenance mode: serve the API and dashboard but refuse
+        /// new agent turns until it's turned off
+        #[arg(long)]
+        maintenance: bool,
+    },
+    /// Walk through setup and write a starter config
+    Init {
+        /// Replace an existing config without asking
+        #[arg(long)]
+        force: bool,
     },
     /// Stop the running daemon
     Stop,
@@ -40,14 +233,157 @@ enum Command {
         /// Run in the foreground instead of daemonizing
         #[arg(short, long)]
         foreground: bool,
+        /// Start in maintenance mode
+        #[arg(long)]
+        maintenance: bool,
     },
     /// Show status of the running daemon
     Status,
+    /// Check the environment for common problems and suggest fixes
+    Doctor,
+    /// Install or remove spacebot as a systemd/launchd service
+    Service {
+        #[command(subcommand)]
+        action: ServiceAction,
+    },
+    /// Export data from the agents' databases
+    Export {
+        #[command(subcommand)]
+        target: ExportTarget,
+    },
+    /// List a channel's conversation forks (switch them through the API)
+    Forks {
+        /// Agent that owns the channel
+        #[arg(long)]
+        agent: String,
+        /// Channel ID, e.g. webhook:my-conversation
+        #[arg(long)]
+        channel: String,
+    },
+    /// Read stored conversations
+    Transcript {
+        #[command(subcommand)]
+        action: TranscriptAction,
+    },
+    /// Send a recorded request to another model and diff the outputs. Needs
+    /// the daemon running with llm.debug_recording on
+    Replay {
+        /// Request ID, from a transcript's --raw output or a failed
+        /// completion's error
+        request_id: String,
+        /// Model to replay against, e.g. openrouter/deepseek/deepseek-chat
+        #[arg(long)]
+        model: String,
+        /// Sampling temperature (default: the original request's)
+        #[arg(long)]
+        temperature: Option<f64>,
+        /// Output token cap (default: the original request's)
+        #[arg(long)]
+        max_tokens: Option<u64>,
+        /// Sampling seed, for providers that take one
+        #[arg(long)]
+        seed: Option<u64>,
+    },
+    /// Report which conversations the retention policy would expire now,
+    /// without changing anything
+    Retention {
+        /// Only report this agent (default: all agents)
+        #[arg(long)]
+        agent: Option<String>,
+    },
+    /// Show or change the running daemon's log level without restarting
+    LogLevel {
+        /// error, warn, info, debug, trace or off. Omit to show the current filter
+        level: Option<String>,
+        /// Only change this target, e.g. spacebot::llm
+        #[arg(long)]
+        target: Option<String>,
+        /// Go back to the level the daemon started with
+        #[arg(long)]
+        reset: bool,
+    },
+    /// Show routing and model changes from the changelog: deprecations,
+    /// snapshot changes, outages, key failovers, circuit breakers and
+    /// config reloads
+    Events {
+        /// How far back to look, e.g. 90m, 24h or 7d
+        #[arg(long, default_value = "24h")]
+        since: String,
+        /// Only show this kind, e.g. model_deprecated or config_reload
+        #[arg(long)]
+        kind: Option<String>,
+    },
+    /// Show LLM requests, tokens, cost and errors over time, from the
+    /// snapshots in the default agent's database
+    Usage {
+        /// How far back to look, e.g. 90m, 24h or 7d
+        #[arg(long, default_value = "24h")]
+        since: String,
+        /// Width of each row, e.g. 15m, 1h or 1d
+        #[arg(long, default_value = "1h")]
+        bucket: String,
+        /// Only count this model, e.g. anthropic/claude-sonnet-4
+        #[arg(long)]
+        model: Option<String>,
+    },
+    /// Inspect configured agents
+    Agents {
+        #[command(subcommand)]
+        action: AgentsAction,
+    },
+    /// Inspect model routing
+    Routing {
+        #[command(subcommand)]
+        action: RoutingAction,
+    },
+    /// Drive synthetic conversations against a mock provider and report
+    /// throughput, latency and memory use
+    Bench {
+        /// Number of simulated agents, each with its own channel
+        #[arg(long, default_value_t = 10)]
+        agents: usize,
+        /// Turns per agent
+        #[arg(long, default_value_t = 20)]
+        turns: usize,
+        /// How long the mock provider takes to answer, in milliseconds
+        #[arg(long, default_value_t = 200)]
+        mock_latency_ms: u64,
+        /// Request limiter size (default: llm.max_concurrent_requests from
+        /// the config, if one exists)
+        #[arg(long)]
+        max_concurrent: Option<usize>,
+    },
+    /// Inspect the configuration format and stage config changes
+    Config {
+        #[command(subcommand)]
+        action: ConfigAction,
+    },
+    /// Handle data subject requests
+    Privacy {
+        #[command(subcommand)]
+        action: PrivacyAction,
+    },
+    /// Print a shell completion script
+    #[cfg(feature = "completions")]
+    Completions {
+        /// Shell to generate completions for
+        shell: clap_complete::Shell,
+    },
+    /// Generate man pages
+    #[cfg(feature = "man-pages")]
+    Man {
+        /// Write spacebot.1 and one page per subcommand into this directory
+        /// instead of printing spacebot.1 to stdout
+        #[arg(long)]
+        out_dir: Option<std::path::PathBuf>,
+    },
 }
 
 /// Tracks an active conversation channel and its message sender.
 struct ActiveChannel {
     message_tx: mpsc::Sender<spacebot::InboundMessage>,
+    /// The message that opened the channel, which replies are addressed to.
+    origin: spacebot::InboundMessage,
     /// Retained so the outbound routing task stays alive.
     _outbound_handle: tokio::task::JoinHandle<()>,
 }
@@ -57,173 +393,1546 @@ fn main() -> anyhow::Result<()> {
         .install_default()
         .expect("failed to install rustls crypto provider");
 
-    let cli = Cli::parse();
-    let command = cli.command.unwrap_or(Command::Start { foreground: false });
+    let cli = Cli::parse();
+    let command = cli.command.unwrap_or(Command::Start {
+        foreground: false,
+        maintenance: false,
+    });
+
+    match command {
+        Command::Start {
+            foreground,
+            maintenance,
+        } => cmd_start(cli.config, cli.debug, foreground, maintenance),
+        Command::Init { force } => cmd_init(cli.config, force),
+        Command::Stop => cmd_stop(),
+        Command::Restart {
+            foreground,
+            maintenance,
+        } => {
+            cmd_stop_if_running();
+            cmd_start(cli.config, cli.debug, foreground, maintenance)
+        }
+        Command::Status => cmd_status(cli.json),
+        Command::Doctor => cmd_doctor(cli.config, cli.json),
+        Command::Service { action } => cmd_service(action, cli.config, cli.json),
+        Command::Export { target } => cmd_export(target, cli.config),
+        Command::Forks { agent, channel } => cmd_forks(&agent, &channel, cli.config, cli.json),
+        Command::Transcript { action } => cmd_transcript(action, cli.config, cli.json),
+        Command::Replay {
+            request_id,
+            model,
+            temperature,
+            max_tokens,
+            seed,
+        } => cmd_replay(
+            request_id,
+            spacebot::llm::replay::ReplayOptions {
+                model,
+                temperature,
+                max_tokens,
+                seed,
+            },
+            cli.json,
+        ),
+        Command::Retention { agent } => cmd_retention(agent.as_deref(), cli.config, cli.json),
+        Command::LogLevel {
+            level,
+            target,
+            reset,
+        } => cmd_log_level(
+            spacebot::daemon::LogLevelChange {
+                level,
+                target,
+                reset,
+            },
+            cli.json,
+        ),
+        Command::Events { since, kind } => {
+            cmd_events(&since, kind.as_deref(), cli.config, cli.json)
+        }
+        Command::Usage {
+            since,
+            bucket,
+            model,
+        } => cmd_usage(&since, &bucket, model.as_deref(), cli.config, cli.json),
+        Command::Agents { action } => cmd_agents(action, cli.config, cli.json),
+        Command::Routing { action } => cmd_routing(action, cli.config, cli.json),
+        Command::Bench {
+            agents,
+            turns,
+            mock_latency_ms,
+            max_concurrent,
+        } => cmd_bench(
+            spacebot::bench::BenchOptions {
+                agents,
+                turns,
+                mock_latency: std::time::Duration::from_millis(mock_latency_ms),
+                max_concurrent_requests: max_concurrent,
+            },
+            cli.config,
+            cli.json,
+        ),
+        Command::Config { action } => cmd_config(action, cli.config, cli.json),
+        Command::Privacy { action } => cmd_privacy(action, cli.config, cli.json),
+        #[cfg(feature = "completions")]
+        Command::Completions { shell } => {
+            clap_complete::generate(
+                shell,
+                &mut <Cli as clap::CommandFactory>::command(),
+                "spacebot",
+                &mut std::io::stdout(),
+            );
+            Ok(())
+        }
+        #[cfg(feature = "man-pages")]
+        Command::Man { out_dir } => cmd_man(out_dir),
+    }
+}
+
+fn cmd_start(
+    config_path: Option<std::path::PathBuf>,
+    debug: bool,
+    foreground: bool,
+    maintenance: bool,
+) -> anyhow::Result<()> {
+    let paths = spacebot::daemon::DaemonPaths::from_default();
+
+    // Bail if already running
+    if let Some(pid) = spacebot::daemon::is_running(&paths) {
+        eprintln!("spacebot is already running (pid {pid})");
+        std::process::exit(1);
+    }
+
+    // Run onboarding interactively before daemonizing
+    let resolved_config_path = if config_path.is_some() {
+        config_path.clone()
+    } else if spacebot::config::Config::needs_onboarding() {
+        // Returns Some(path) if CLI wizard ran, None if user chose the UI.
+        spacebot::config::run_onboarding().with_context(|| "onboarding failed")?
+    } else {
+        None
+    };
+
+    // Validate config loads successfully before forking
+    let config = load_config(&resolved_config_path)?;
+
+    if foreground {
+        spacebot::daemon::init_foreground_tracing(debug);
+    } else {
+        // Derive paths from the loaded config's instance dir
+        let paths = spacebot::daemon::DaemonPaths::new(&config.instance_dir);
+        spacebot::daemon::daemonize(&paths)?;
+        spacebot::daemon::init_background_tracing(&paths, debug);
+    }
+
+    // Bring the instance directory up to this release's layout before
+    // anything reads it.
+    let migration = spacebot::instance::migrate(&config.instance_dir)
+        .context("failed to migrate instance directory")?;
+    if migration.from != migration.to {
+        tracing::info!(
+            from = migration.from,
+            to = migration.to,
+            "instance directory migrated"
+        );
+    }
+
+    // Build and run the tokio runtime for the async main
+    tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .context("failed to build tokio runtime")?
+        .block_on(run(config, foreground, maintenance))
+}
+
+fn cmd_stop() -> anyhow::Result<()> {
+    let paths = spacebot::daemon::DaemonPaths::from_default();
+
+    let Some(pid) = spacebot::daemon::is_running(&paths) else {
+        eprintln!("spacebot is not running");
+        std::process::exit(1);
+    };
+
+    // Use a small runtime for the IPC call
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .context("failed to build tokio runtime")?;
+
+    runtime.block_on(async {
+        match spacebot::daemon::send_command(&paths, spacebot::daemon::IpcCommand::Shutdown).await {
+            Ok(spacebot::daemon::IpcResponse::Ok) => {
+                eprintln!("stopping spacebot (pid {pid})...");
+            }
+            Ok(spacebot::daemon::IpcResponse::Error { message }) => {
+                eprintln!("shutdown failed: {message}");
+                std::process::exit(1);
+            }
+            Ok(_) => {
+                eprintln!("unexpected response from daemon");
+                std::process::exit(1);
+            }
+            Err(error) => {
+                eprintln!("failed to send shutdown command: {error}");
+                std::process::exit(1);
+            }
+        }
+    });
+
+    if spacebot::daemon::wait_for_exit(pid) {
+        eprintln!("spacebot stopped");
+    } else {
+        eprintln!("spacebot did not stop within 10 seconds (pid {pid})");
+        std::process::exit(1);
+    }
+
+    Ok(())
+}
+
+/// Stop if running, don't error if not.
+fn cmd_stop_if_running() {
+    let paths = spacebot::daemon::DaemonPaths::from_default();
+
+    let Some(pid) = spacebot::daemon::is_running(&paths) else {
+        return;
+    };
+
+    let Ok(runtime) = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+    else {
+        return;
+    };
+
+    runtime.block_on(async {
+        if let Ok(spacebot::daemon::IpcResponse::Ok) =
+            spacebot::daemon::send_command(&paths, spacebot::daemon::IpcCommand::Shutdown).await
+        {
+            eprintln!("stopping spacebot (pid {pid})...");
+            spacebot::daemon::wait_for_exit(pid);
+        }
+    });
+}
+
+fn cmd_status(json: bool) -> anyhow::Result<()> {
+    let paths = spacebot::daemon::DaemonPaths::from_default();
+
+    let status = if spacebot::daemon::is_running(&paths).is_none() {
+        StatusOutput {
+            running: false,
+            pid: None,
+            uptime_seconds: None,
+            error: None,
+        }
+    } else {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .context("failed to build tokio runtime")?;
+
+        runtime.block_on(async {
+            let (pid, uptime_seconds, error) =
+                match spacebot::daemon::send_command(&paths, spacebot::daemon::IpcCommand::Status)
+                    .await
+                {
+                    Ok(spacebot::daemon::IpcResponse::Status {
+                        pid,
+                        uptime_seconds,
+                    }) => (Some(pid), Some(uptime_seconds), None),
+                    Ok(spacebot::daemon::IpcResponse::Error { message }) => {
+                        (None, None, Some(format!("status query failed: {message}")))
+                    }
+                    Ok(_) => (None, None, Some("unexpected response from daemon".into())),
+                    Err(error) => (
+                        None,
+                        None,
+                        Some(format!("failed to query daemon status: {error}")),
+                    ),
+                };
+            StatusOutput {
+                running: true,
+                pid,
+                uptime_seconds,
+                error,
+            }
+        })
+    };
+
+    let healthy = status.running && status.error.is_none();
+    if json {
+        print_json(&status)?;
+    } else if let Some(error) = &status.error {
+        eprintln!("{error}");
+    } else if let (Some(pid), Some(uptime_seconds)) = (status.pid, status.uptime_seconds) {
+        let hours = uptime_seconds / 3600;
+        let minutes = (uptime_seconds % 3600) / 60;
+        let seconds = uptime_seconds % 60;
+        eprintln!("spacebot is running");
+        eprintln!("  pid:    {pid}");
+        eprintln!("  uptime: {hours}h {minutes}m {seconds}s");
+    } else {
+        eprintln!("spacebot is not running");
+    }
+
+    if !healthy {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+fn cmd_doctor(config_path: Option<std::path::PathBuf>, json: bool) -> anyhow::Result<()> {
+    use spacebot::doctor::{Check, CheckStatus};
+
+    let checks = match load_config(&config_path) {
+        Ok(config) => {
+            let runtime = tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .context("failed to build tokio runtime")?;
+            runtime.block_on(spacebot::doctor::run_checks(&config))
+        }
+        Err(error) => vec![Check {
+            name: "config".into(),
+            status: CheckStatus::Fail,
+            detail: format!("{error:#}"),
+            fix: Some("correct the file, or run `spacebot` once to go through onboarding".into()),
+        }],
+    };
+
+    let failures = checks
+        .iter()
+        .filter(|check| check.status == CheckStatus::Fail)
+        .count();
+
+    if json {
+        print_json(&DoctorOutput {
+            ok: failures == 0,
+            failures,
+            checks,
+        })?;
+    } else {
+        for check in &checks {
+            let marker = match check.status {
+                CheckStatus::Ok => "✓",
+                CheckStatus::Warn => "!",
+                CheckStatus::Fail => "✗",
+            };
+            println!("{marker} {}: {}", check.name, check.detail);
+            if let Some(fix) = &check.fix {
+                println!("    fix: {fix}");
+            }
+        }
+        if failures > 0 {
+            println!("\n{failures} problem(s) found");
+        }
+    }
+
+    if failures > 0 {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+#[cfg(feature = "man-pages")]
+fn cmd_man(out_dir: Option<std::path::PathBuf>) -> anyhow::Result<()> {
+    let command = <Cli as clap::CommandFactory>::command();
+    let Some(out_dir) = out_dir else {
+        return clap_mangen::Man::new(command)
+            .render(&mut std::io::stdout())
+            .context("failed to render man page");
+    };
+
+    std::fs::create_dir_all(&out_dir)
+        .with_context(|| format!("failed to create {}", out_dir.display()))?;
+
+    let mut pages = vec![("spacebot".to_string(), command.clone())];
+    for subcommand in command
+        .get_subcommands()
+        .filter(|sub| sub.get_name() != "help")
+    {
+        let name = format!("spacebot-{}", subcommand.get_name());
+        pages.push((name.clone(), subcommand.clone().name(name)));
+    }
+
+    for (name, page) in pages {
+        let path = out_dir.join(format!("{name}.1"));
+        let mut buffer = Vec::new();
+        clap_mangen::Man::new(page)
+            .render(&mut buffer)
+            .with_context(|| format!("failed to render {name}.1"))?;
+        std::fs::write(&path, buffer)
+            .with_context(|| format!("failed to write {}", path.display()))?;
+        eprintln!("wrote {}", path.display());
+    }
+    Ok(())
+}
+
+fn cmd_service(
+    action: ServiceAction,
+    config_path: Option<std::path::PathBuf>,
+    json: bool,
+) -> anyhow::Result<()> {
+    use spacebot::service::ServiceSpec;
+
+    let instance_dir = spacebot::config::Config::default_instance_dir();
+    let spec = |env_file, system| {
+        ServiceSpec::for_current_binary(&instance_dir, config_path.as_deref(), env_file, system)
+    };
+
+    match action {
+        ServiceAction::Install {
+            system,
+            env_file,
+            no_start,
+        } => {
+            let spec = spec(env_file, system)?;
+            let path = spacebot::service::install(&spec, !no_start)?;
+            eprintln!("installed {}", path.display());
+            eprintln!("environment file: {}", spec.env_file.display());
+        }
+        ServiceAction::Uninstall { system } => {
+            match spacebot::service::uninstall(&spec(None, system)?)? {
+                Some(path) => eprintln!("removed {}", path.display()),
+                None => eprintln!("service is not installed"),
+            }
+        }
+        ServiceAction::Status { system } => {
+            let status = spacebot::service::status(&spec(None, system)?)?;
+            if json {
+                print_json(&status)?;
+            } else if !status.installed {
+                eprintln!("service is not installed");
+            } else {
+                let state = if status.active { "running" } else { "stopped" };
+                eprintln!("service is installed ({state})");
+                eprintln!("  definition: {}", status.definition_path.display());
+            }
+            if !status.active {
+                std::process::exit(1);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn cmd_export(target: ExportTarget, config_path: Option<std::path::PathBuf>) -> anyhow::Result<()> {
+    use spacebot::finetune::{ExportFilter, FinetuneExporter};
+    use std::io::Write as _;
+
+    let ExportTarget::Finetune {
+        format,
+        agent,
+        rating,
+        since,
+        until,
+        output,
+    } = target;
+
+    let config = load_config(&config_path)?;
+    let agents: Vec<_> = config
+        .resolve_agents()
+        .into_iter()
+        .filter(|agent_config| agent.as_ref().is_none_or(|id| agent_config.id == *id))
+        .collect();
+    if let Some(id) = agent.as_ref().filter(|_| agents.is_empty()) {
+        anyhow::bail!("no agent named '{id}'");
+    }
+
+    let mut writer: Box<dyn std::io::Write> = match &output {
+        Some(path) => Box::new(std::io::BufWriter::new(
+            std::fs::File::create(path)
+                .with_context(|| format!("failed to create {}", path.display()))?,
+        )),
+        None => Box::new(std::io::stdout().lock()),
+    };
+    let filter = ExportFilter {
+        rating,
+        since,
+        until,
+    };
+
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .context("failed to build tokio runtime")?;
+
+    let mut exported = 0;
+    for agent_config in &agents {
+        let sqlite_path = agent_config.sqlite_path();
+        if !sqlite_path.exists() {
+            eprintln!("skipping {}: no database yet", agent_config.id);
+            continue;
+        }
+
+        // Read-only, so an export can't race the running daemon's migrations.
+        let conversations = runtime.block_on(async {
+            let pool =
+                sqlx::SqlitePool::connect(&format!("sqlite:{}?mode=ro", sqlite_path.display()))
+                    .await
+                    .with_context(|| format!("failed to open {}", sqlite_path.display()))?;
+            let conversations = FinetuneExporter::new(pool.clone())
+                .conversations(&filter)
+                .await;
+            pool.close().await;
+            anyhow::Ok(conversations?)
+        })?;
+
+        for conversation in &conversations {
+            let line = serde_json::to_string(&conversation.to_record(format))
+                .context("failed to serialize conversation")?;
+            writeln!(writer, "{line}").context("failed to write export")?;
+        }
+        eprintln!("{}: {} conversations", agent_config.id, conversations.len());
+        exported += conversations.len();
+    }
+    writer.flush().context("failed to write export")?;
+
+    match &output {
+        Some(path) => eprintln!("exported {exported} conversations to {}", path.display()),
+        None => eprintln!("exported {exported} conversations"),
+    }
+    Ok(())
+}
+
+fn cmd_forks(
+    agent: &str,
+    channel: &str,
+    config_path: Option<std::path::PathBuf>,
+    json: bool,
+) -> anyhow::Result<()> {
+    use spacebot::conversation::ForkStore;
+
+    let config = load_config(&config_path)?;
+    let agent_config = config
+        .resolve_agents()
+        .into_iter()
+        .find(|agent_config| agent_config.id == agent)
+        .with_context(|| format!("no agent named '{agent}'"))?;
+    let sqlite_path = agent_config.sqlite_path();
+    if !sqlite_path.exists() {
+        anyhow::bail!("{agent} has no database yet");
+    }
+
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .context("failed to build tokio runtime")?;
+    let (forks, active) = runtime.block_on(async {
+        let pool = sqlx::SqlitePool::connect(&format!("sqlite:{}?mode=ro", sqlite_path.display()))
+            .await
+            .with_context(|| format!("failed to open {}", sqlite_path.display()))?;
+        let store = ForkStore::new(pool.clone());
+        let forks = store.list(channel).await;
+        let active = store.active(channel).await;
+        pool.close().await;
+        anyhow::Ok((forks?, active?))
+    })?;
+
+    if json {
+        return print_json(&serde_json::json!({
+            "forks": forks,
+            "active_fork_id": active,
+        }));
+    }
+
+    let marker = |fork_id: Option<&str>| {
+        if active.as_deref() == fork_id {
+            "*"
+        } else {
+            " "
+        }
+    };
+    println!("{} root", marker(None));
+    for fork in &forks {
+        println!(
+            "{} {}  from {} at message {}  ({})",
+            marker(Some(&fork.id)),
+            fork.id,
+            fork.parent_fork_id.as_deref().unwrap_or("root"),
+            fork.fork_point_message_id,
+            fork.created_at,
+        );
+    }
+    Ok(())
+}
+
+fn cmd_transcript(
+    action: TranscriptAction,
+    config_path: Option<std::path::PathBuf>,
+    json: bool,
+) -> anyhow::Result<()> {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .context("failed to build tokio runtime")?;
+
+    let (conversation_id, agent, raw) = match action {
+        TranscriptAction::Show {
+            conversation_id,
+            agent,
+            raw,
+        } => (conversation_id, agent, raw),
+        TranscriptAction::Export {
+            conversation_id,
+            agent,
+            format,
+            output,
+        } => {
+            let transcript =
+                load_transcript(&runtime, &config_path, &conversation_id, agent.as_deref())?;
+            let exported = transcript.export(format);
+            return match output {
+                Some(path) => std::fs::write(&path, exported)
+                    .with_context(|| format!("failed to write {}", path.display())),
+                None => {
+                    print!("{exported}");
+                    Ok(())
+                }
+            };
+        }
+    };
+    let transcript = load_transcript(&runtime, &config_path, &conversation_id, agent.as_deref())?;
+
+    // Raw exchanges only live in the daemon's memory.
+    let exchanges = if raw {
+        let paths = spacebot::daemon::DaemonPaths::from_default();
+        if spacebot::daemon::is_running(&paths).is_none() {
+            anyhow::bail!("--raw reads exchanges from the running daemon, which isn't running");
+        }
+        let response = runtime.block_on(spacebot::daemon::send_command(
+            &paths,
+            spacebot::daemon::IpcCommand::LlmExchanges {
+                request_ids: transcript.request_ids(),
+            },
+        ))?;
+        match response {
+            spacebot::daemon::IpcResponse::LlmExchanges(exchanges) => Some(exchanges),
+            spacebot::daemon::IpcResponse::Error { message } => {
+                anyhow::bail!("failed to read exchanges: {message}")
+            }
+            _ => anyhow::bail!("unexpected response from daemon"),
+        }
+    } else {
+        None
+    };
+
+    if json {
+        return print_json(&serde_json::json!({
+            "channel_id": transcript.channel_id,
+            "entries": transcript.entries,
+            "exchanges": exchanges,
+        }));
+    }
+
+    print!("{}", transcript.render(exchanges.as_ref()));
+    Ok(())
+}
+
+/// Load a stored conversation from whichever agent's database holds it.
+fn load_transcript(
+    runtime: &tokio::runtime::Runtime,
+    config_path: &Option<std::path::PathBuf>,
+    conversation_id: &str,
+    agent: Option<&str>,
+) -> anyhow::Result<spacebot::conversation::transcript::Transcript> {
+    use spacebot::conversation::transcript::Transcript;
+
+    let config = load_config(config_path)?;
+    let agents: Vec<_> = config
+        .resolve_agents()
+        .into_iter()
+        .filter(|agent_config| agent.is_none_or(|id| agent_config.id == id))
+        .collect();
+    if let Some(id) = agent.filter(|_| agents.is_empty()) {
+        anyhow::bail!("no agent named '{id}'");
+    }
+
+    let mut found = Vec::new();
+    for agent_config in &agents {
+        let sqlite_path = agent_config.sqlite_path();
+        if !sqlite_path.exists() {
+            continue;
+        }
+        let transcript = runtime.block_on(async {
+            let pool =
+                sqlx::SqlitePool::connect(&format!("sqlite:{}?mode=ro", sqlite_path.display()))
+                    .await
+                    .with_context(|| format!("failed to open {}", sqlite_path.display()))?;
+            let transcript = Transcript::load(&pool, conversation_id).await;
+            pool.close().await;
+            anyhow::Ok(transcript?)
+        })?;
+        if !transcript.entries.is_empty() {
+            found.push((agent_config.id.clone(), transcript));
+        }
+    }
+    match found.len() {
+        0 => anyhow::bail!("no stored conversation '{conversation_id}'"),
+        1 => Ok(found.remove(0).1),
+        _ => {
+            let owners: Vec<String> = found.into_iter().map(|(id, _)| id).collect();
+            anyhow::bail!(
+                "'{conversation_id}' is stored by more than one agent ({}), pick one with --agent",
+                owners.join(", ")
+            )
+        }
+    }
+}
+
+fn cmd_replay(
+    request_id: String,
+    options: spacebot::llm::replay::ReplayOptions,
+    json: bool,
+) -> anyhow::Result<()> {
+    // Recorded requests only live in the daemon's memory.
+    let paths = spacebot::daemon::DaemonPaths::from_default();
+    if spacebot::daemon::is_running(&paths).is_none() {
+        anyhow::bail!("replay needs the running daemon, which isn't running");
+    }
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .context("failed to build tokio runtime")?;
+    let response = runtime.block_on(spacebot::daemon::send_command(
+        &paths,
+        spacebot::daemon::IpcCommand::Replay {
+            request_id,
+            options,
+        },
+    ))?;
+    let outcome = match response {
+        spacebot::daemon::IpcResponse::Replay(outcome) => outcome,
+        spacebot::daemon::IpcResponse::Error { message } => {
+            anyhow::bail!("replay failed: {message}")
+        }
+        _ => anyhow::bail!("unexpected response from daemon"),
+    };
+
+    if json {
+        return print_json(&outcome);
+    }
+
+    let cost = outcome
+        .cost_usd
+        .map(|cost| format!(", ${cost:.4}"))
+        .unwrap_or_default();
+    println!(
+        "replayed {} on {} as {}: {} in / {} out tokens{cost}",
+        outcome.request_id,
+        outcome.model,
+        outcome.replay_request_id.as_deref().unwrap_or("?"),
+        outcome.input_tokens,
+        outcome.output_tokens,
+    );
+    println!("--- {}", outcome.original_model);
+    println!("+++ {}", outcome.model);
+    match &outcome.original_output {
+        Some(original) => println!(
+            "{}",
+            spacebot::approval::preview::unified_diff(original, &outcome.output)
+        ),
+        None => {
+            println!("(the original request failed)");
+            println!("{}", outcome.output);
+        }
+    }
+    Ok(())
+}
+
+/// `spacebot retention --json` output, one per agent.
+#[derive(serde::Serialize)]
+struct RetentionReport {
+    agent_id: String,
+    /// Whether the daemon will actually expire these conversations.
+    enabled: bool,
+    expired: Vec<spacebot::agent::retention::ExpiredConversation>,
+}
+
+fn cmd_retention(
+    agent: Option<&str>,
+    config_path: Option<std::path::PathBuf>,
+    json: bool,
+) -> anyhow::Result<()> {
+    use spacebot::agent::retention::find_expired;
+
+    let config = load_config(&config_path)?;
+    let agents: Vec<_> = config
+        .resolve_agents()
+        .into_iter()
+        .filter(|agent_config| agent.is_none_or(|agent| agent_config.id == agent))
+        .collect();
+    if let Some(agent) = agent.filter(|_| agents.is_empty()) {
+        anyhow::bail!("no agent named '{agent}'");
+    }
+
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .context("failed to build tokio runtime")?;
+    let now = chrono::Utc::now();
+    let mut reports = Vec::new();
+    for agent_config in &agents {
+        let sqlite_path = agent_config.sqlite_path();
+        if !sqlite_path.exists() {
+            continue;
+        }
+        let expired = runtime.block_on(async {
+            let pool =
+                sqlx::SqlitePool::connect(&format!("sqlite:{}?mode=ro", sqlite_path.display()))
+                    .await
+                    .with_context(|| format!("failed to open {}", sqlite_path.display()))?;
+            let expired = find_expired(&pool, &agent_config.retention, now).await;
+            pool.close().await;
+            expired
+        })?;
+        reports.push(RetentionReport {
+            agent_id: agent_config.id.clone(),
+            enabled: agent_config.retention.enabled,
+            expired,
+        });
+    }
+
+    if json {
+        return print_json(&reports);
+    }
+
+    for report in &reports {
+        let status = if report.enabled {
+            ""
+        } else {
+            " (retention disabled)"
+        };
+        println!(
+            "{}: {} expired conversations{status}",
+            report.agent_id,
+            report.expired.len()
+        );
+        for conversation in &report.expired {
+            let summarize = if conversation.summarize {
+                "summarize, "
+            } else {
+                ""
+            };
+            println!(
+                "  {}  {} messages, idle {} days  ({summarize}{})",
+                conversation.channel_id,
+                conversation.message_count,
+                conversation.idle_days,
+                conversation.action.as_str(),
+            );
+        }
+    }
+    Ok(())
+}
+
+fn cmd_events(
+    since: &str,
+    kind: Option<&str>,
+    config_path: Option<std::path::PathBuf>,
+    json: bool,
+) -> anyhow::Result<()> {
+    let Some(window) = spacebot::observability::parse_duration(since) else {
+        anyhow::bail!("invalid duration '{since}', expected something like 90m, 24h or 7d");
+    };
+    let config = load_config(&config_path)?;
+    let entries: Vec<_> = spacebot::changelog::read_since(
+        &spacebot::changelog::path(&config.instance_dir),
+        chrono::Utc::now() - window,
+    )?
+    .into_iter()
+    .filter(|entry| kind.is_none_or(|kind| entry.kind == kind))
+    .collect();
+
+    if json {
+        return print_json(&entries);
+    }
+
+    if entries.is_empty() {
+        println!("no events in the last {since}");
+        return Ok(());
+    }
+    for entry in &entries {
+        println!(
+            "{}  {:<22} {}  {}",
+            entry.at.format("%Y-%m-%d %H:%M:%S"),
+            entry.kind,
+            entry.subject,
+            entry.detail
+        );
+    }
+    Ok(())
+}
+
+fn cmd_usage(
+    since: &str,
+    bucket: &str,
+    model: Option<&str>,
+    config_path: Option<std::path::PathBuf>,
+    json: bool,
+) -> anyhow::Result<()> {
+    let Some(window) = spacebot::observability::parse_duration(since) else {
+        anyhow::bail!("invalid duration '{since}', expected something like 90m, 24h or 7d");
+    };
+    let Some(bucket_width) = spacebot::observability::parse_duration(bucket) else {
+        anyhow::bail!("invalid bucket '{bucket}', expected something like 15m, 1h or 1d");
+    };
+    let config = load_config(&config_path)?;
+    let default_agent_id = config.default_agent_id();
+    let Some(agent_config) = config
+        .resolve_agents()
+        .into_iter()
+        .find(|agent_config| agent_config.id == default_agent_id)
+    else {
+        anyhow::bail!("no agent named '{default_agent_id}'");
+    };
+    let sqlite_path = agent_config.sqlite_path();
+
+    let points = if sqlite_path.exists() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .context("failed to build tokio runtime")?;
+        runtime.block_on(async {
+            let pool =
+                sqlx::SqlitePool::connect(&format!("sqlite:{}?mode=ro", sqlite_path.display()))
+                    .await
+                    .with_context(|| format!("failed to open {}", sqlite_path.display()))?;
+            let points =
+                spacebot::usage_snapshots::series(&pool, window, bucket_width, model).await;
+            pool.close().await;
+            anyhow::Ok(points?)
+        })?
+    } else {
+        Vec::new()
+    };
+
+    if json {
+        return print_json(&points);
+    }
+
+    if points.is_empty() {
+        println!("no usage snapshots in the last {since}");
+        return Ok(());
+    }
+    println!(
+        "{:<16}  {:>8}  {:>6}  {:>10}  {:>10}  {:>9}  errors",
+        "start", "requests", "failed", "input", "output", "cost"
+    );
+    for point in &points {
+        let errors = point
+            .errors
+            .iter()
+            .map(|(class, failed)| format!("{class}={failed}"))
+            .collect::<Vec<_>>()
+            .join(" ");
+        println!(
+            "{:<16}  {:>8}  {:>6}  {:>10}  {:>10}  {:>9}  {errors}",
+            point.start.format("%Y-%m-%d %H:%M"),
+            point.requests,
+            point.failed,
+            point.input_tokens,
+            point.output_tokens,
+            format!("${:.4}", point.cost_usd),
+        );
+    }
+    Ok(())
+}
+
+fn cmd_init(config_path: Option<std::path::PathBuf>, force: bool) -> anyhow::Result<()> {
+    let config_path = spacebot::config::run_init(config_path, force)?;
+    println!("  Run `spacebot start` to bring the bot up.");
+    if config_path != spacebot::config::Config::default_instance_dir().join("config.toml") {
+        println!("  (with --config {})", config_path.display());
+    }
+    println!();
+    Ok(())
+}
+
+fn cmd_agents(
+    action: AgentsAction,
+    config_path: Option<std::path::PathBuf>,
+    json: bool,
+) -> anyhow::Result<()> {
+    let AgentsAction::Describe { name } = action;
+    let config = load_config(&config_path)?;
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .context("failed to build tokio runtime")?;
+    let manifest = runtime.block_on(spacebot::agent::manifest::describe_agent(&config, &name))?;
+
+    if json {
+        return print_json(&manifest);
+    }
+
+    let on_off = |value: Option<String>| value.unwrap_or_else(|| "off".into());
+    println!(
+        "{}{}",
+        manifest.id,
+        if manifest.default { " (default)" } else { "" }
+    );
+    println!("  workspace  {}", manifest.workspace.display());
+
+    println!("\nModels");
+    for tier in &manifest.models {
+        let missing = if tier.provider_configured {
+            ""
+        } else {
+            "  [no provider key]"
+        };
+        println!("  {:<10} {}{missing}", tier.process, tier.model);
+        if !tier.fallbacks.is_empty() {
+            println!("               then {}", tier.fallbacks.join(", "));
+        }
+    }
+    for (task, model) in &manifest.task_overrides {
+        println!("  task {task:<5} {model}");
+    }
+
+    println!("\nTools");
+    for process in &manifest.tools {
+        println!("  {}", process.process);
+        for tool in &process.tools {
+            let note = match (&tool.reason, tool.requires_approval) {
+                (Some(reason), _) => format!("unavailable: {reason}"),
+                (None, true) => "needs /confirm".into(),
+                (None, false) => String::new(),
+            };
+            println!("    {:<16} {note}", tool.name);
+        }
+    }
+    if let Some(filter) = &manifest.tool_filter {
+        println!(
+            "  trimmed to the {} most relevant per turn, always including: {}",
+            filter.top_k,
+            filter.always_include.join(", ")
+        );
+    }
+
+    let memory = &manifest.memory;
+    println!("\nMemory");
+    println!("  sqlite     {}", memory.sqlite.display());
+    println!("  lancedb    {}", memory.lancedb.display());
+    println!(
+        "  persist    {}",
+        on_off(
+            memory
+                .persistence_interval
+                .map(|interval| format!("every {interval} messages"))
+        )
+    );
+    println!(
+        "  ingest     {}",
+        on_off(
+            memory
+                .ingest_dir
+                .as_ref()
+                .map(|dir| dir.display().to_string())
+        )
+    );
+    println!(
+        "  retrieve   {}",
+        on_off(memory.retrieval.as_ref().map(|retrieval| {
+            format!(
+                "up to {} from {} per turn, min score {}",
+                retrieval.top_k, retrieval.store, retrieval.min_score
+            )
+        }))
+    );
+
+    let limits = &manifest.limits;
+    println!("\nLimits");
+    println!(
+        "  turns      {} per channel turn, {} per branch",
+        limits.max_turns, limits.branch_max_turns
+    );
+    println!(
+        "  concurrent {} branches, {} workers",
+        limits.max_concurrent_branches, limits.max_concurrent_workers
+    );
+    println!("  context    {} tokens", limits.context_window);
+    println!(
+        "  rate limit {}",
+        on_off(
+            limits
+                .user_rate_limit
+                .map(|(minute, hour)| format!("{minute}/min, {hour}/hour per user"))
+        )
+    );
+
+    println!("\nSchedules (from {})", manifest.schedules_source);
+    if manifest.schedules.is_empty() {
+        println!("  none");
+    }
+    for schedule in &manifest.schedules {
+        let hours = schedule
+            .active_hours
+            .map(|(start, end)| format!(", {start:02}:00-{end:02}:00"))
+            .unwrap_or_default();
+        let disabled = if schedule.enabled { "" } else { " (disabled)" };
+        println!(
+            "  {}  every {}s{hours} -> {}{disabled}",
+            schedule.id, schedule.interval_secs, schedule.delivery_target
+        );
+    }
 
-    match command {
-        Command::Start { foreground } => cmd_start(cli.config, cli.debug, foreground),
-        Command::Stop => cmd_stop(),
-        Command::Restart { foreground } => {
-            cmd_stop_if_running();
-            cmd_start(cli.config, cli.debug, foreground)
+    println!("\nBindings");
+    if manifest.bindings.is_empty() {
+        println!("  none");
+    }
+    for binding in &manifest.bindings {
+        let mut line = format!("  {}", binding.platform);
+        if let Some(scope) = &binding.scope {
+            line.push_str(&format!(" {scope}"));
+        }
+        if !binding.channel_ids.is_empty() {
+            line.push_str(&format!(" channels {}", binding.channel_ids.join(", ")));
+        }
+        if !binding.dm_allowed_users.is_empty() {
+            line.push_str(&format!(
+                " DMs from {}",
+                binding.dm_allowed_users.join(", ")
+            ));
+        }
+        if !binding.platform_enabled {
+            line.push_str(&format!("  [{} is not enabled]", binding.platform));
         }
-        Command::Status => cmd_status(),
+        println!("{line}");
     }
+    if manifest.default {
+        println!("  plus every message no binding matches");
+    }
+    Ok(())
 }
 
-fn cmd_start(
+fn cmd_routing(
+    action: RoutingAction,
     config_path: Option<std::path::PathBuf>,
-    debug: bool,
-    foreground: bool,
+    json: bool,
 ) -> anyhow::Result<()> {
-    let paths = spacebot::daemon::DaemonPaths::from_default();
-
-    // Bail if already running
-    if let Some(pid) = spacebot::daemon::is_running(&paths) {
-        eprintln!("spacebot is already running (pid {pid})");
-        std::process::exit(1);
-    }
+    use spacebot::llm::simulate::{RouteState, RouteStep, simulate_route};
+
+    let RoutingAction::Simulate {
+        model,
+        error,
+        agent,
+        fresh,
+    } = action;
+    let config = load_config(&config_path)?;
+    let routing = match &agent {
+        Some(agent) => {
+            config
+                .resolve_agents()
+                .into_iter()
+                .find(|agent_config| &agent_config.id == agent)
+                .with_context(|| format!("no agent named '{agent}'"))?
+                .routing
+        }
+        None => config.defaults.routing.clone(),
+    };
 
-    // Run onboarding interactively before daemonizing
-    let resolved_config_path = if config_path.is_some() {
-        config_path.clone()
-    } else if spacebot::config::Config::needs_onboarding() {
-        // Returns Some(path) if CLI wizard ran, None if user chose the UI.
-        spacebot::config::run_onboarding().with_context(|| "onboarding failed")?
+    // Cooldowns and health only exist in the running daemon.
+    let paths = spacebot::daemon::DaemonPaths::from_default();
+    let (state, state_source) = if fresh || spacebot::daemon::is_running(&paths).is_none() {
+        (RouteState::default(), "none")
     } else {
-        None
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .context("failed to build tokio runtime")?;
+        let response = runtime.block_on(spacebot::daemon::send_command(
+            &paths,
+            spacebot::daemon::IpcCommand::RouteState,
+        ))?;
+        match response {
+            spacebot::daemon::IpcResponse::RouteState(state) => (state, "daemon"),
+            spacebot::daemon::IpcResponse::Error { message } => {
+                anyhow::bail!("failed to read routing state: {message}")
+            }
+            _ => anyhow::bail!("unexpected response from daemon"),
+        }
     };
 
-    // Validate config loads successfully before forking
-    let config = load_config(&resolved_config_path)?;
+    let steps = simulate_route(&routing, &model, error, &state);
 
-    if foreground {
-        spacebot::daemon::init_foreground_tracing(debug);
+    if json {
+        return print_json(&serde_json::json!({
+            "model": model,
+            "error": error,
+            "state_source": state_source,
+            "steps": steps,
+        }));
+    }
+
+    println!("{model}, every attempt failing with {}", error.name());
+    if state_source == "daemon" {
+        println!("(cooldowns and health from the running daemon)");
     } else {
-        // Derive paths from the loaded config's instance dir
-        let paths = spacebot::daemon::DaemonPaths::new(&config.instance_dir);
-        spacebot::daemon::daemonize(&paths)?;
-        spacebot::daemon::init_background_tracing(&paths, debug);
+        println!("(no cooldowns or unhealthy providers assumed)");
+    }
+    let mut tried = 0;
+    for step in &steps {
+        match step {
+            RouteStep::Tried {
+                model,
+                attempts,
+                backoff_ms,
+                enters_cooldown,
+            } => {
+                tried += 1;
+                let mut line = format!("  {tried}. {model}  {attempts} attempt(s)");
+                if *backoff_ms > 0 {
+                    line.push_str(&format!(", {:.1}s backoff", *backoff_ms as f64 / 1000.0));
+                }
+                if *enters_cooldown {
+                    line.push_str(&format!(
+                        ", then cools down for {}s",
+                        routing.rate_limit_cooldown_secs
+                    ));
+                }
+                println!("{line}");
+            }
+            RouteStep::Skipped { model, reason } => println!("  -  {model}  skipped: {reason}"),
+        }
     }
+    println!("  then the request fails");
+    Ok(())
+}
 
-    // Build and run the tokio runtime for the async main
-    tokio::runtime::Builder::new_multi_thread()
+fn cmd_bench(
+    mut options: spacebot::bench::BenchOptions,
+    config_path: Option<std::path::PathBuf>,
+    json: bool,
+) -> anyhow::Result<()> {
+    if options.agents == 0 || options.turns == 0 {
+        anyhow::bail!("--agents and --turns must be at least 1");
+    }
+    // Match the limiter the daemon would run with. The config is optional.
+    if options.max_concurrent_requests.is_none() {
+        options.max_concurrent_requests = load_config(&config_path)
+            .ok()
+            .and_then(|config| config.llm.max_concurrent_requests);
+    }
+
+    if !json {
+        eprintln!(
+            "Running {} agent(s) x {} turn(s) against a mock provider ({}ms per response)...",
+            options.agents,
+            options.turns,
+            options.mock_latency.as_millis()
+        );
+    }
+    let runtime = tokio::runtime::Builder::new_multi_thread()
         .enable_all()
         .build()
-        .context("failed to build tokio runtime")?
-        .block_on(run(config, foreground))
+        .context("failed to build tokio runtime")?;
+    let report = runtime.block_on(spacebot::bench::run(&options))?;
+
+    if json {
+        return print_json(&report);
+    }
+
+    let total_turns = report.agents * report.turns_per_agent;
+    println!(
+        "Turns:       {}/{} completed in {:.1}s ({:.1} turns/s)",
+        report.completed_turns,
+        total_turns,
+        report.elapsed_ms as f64 / 1000.0,
+        report.turns_per_second
+    );
+    if report.failed_turns > 0 {
+        println!("Failed:      {}", report.failed_turns);
+    }
+    let latency = &report.turn_latency;
+    println!(
+        "Latency:     p50 {}ms  p90 {}ms  p99 {}ms  max {}ms  mean {}ms",
+        latency.p50_ms, latency.p90_ms, latency.p99_ms, latency.max_ms, latency.mean_ms
+    );
+    println!(
+        "Queue wait:  mean {}ms  max {}ms",
+        report.queue_wait_mean_ms, report.queue_wait_max_ms
+    );
+    println!(
+        "Requests:    {} sent to the provider",
+        report.provider_requests
+    );
+    println!(
+        "Persisted:   {} messages, last one {}ms after the final turn",
+        report.persisted_messages, report.persistence_drain_ms
+    );
+    match report.peak_rss_bytes {
+        Some(bytes) => println!("Peak memory: {:.1} MiB", bytes as f64 / (1024.0 * 1024.0)),
+        None => println!("Peak memory: not reported on this platform"),
+    }
+    Ok(())
 }
 
-fn cmd_stop() -> anyhow::Result<()> {
-    let paths = spacebot::daemon::DaemonPaths::from_default();
+fn cmd_config(
+    action: ConfigAction,
+    config_path: Option<std::path::PathBuf>,
+    json: bool,
+) -> anyhow::Result<()> {
+    use spacebot::config::rollout;
+    use spacebot::daemon::{IpcCommand, IpcResponse};
 
-    let Some(pid) = spacebot::daemon::is_running(&paths) else {
-        eprintln!("spacebot is not running");
-        std::process::exit(1);
+    let send = |command: IpcCommand| -> anyhow::Result<IpcResponse> {
+        let paths = spacebot::daemon::DaemonPaths::from_default();
+        if spacebot::daemon::is_running(&paths).is_none() {
+            anyhow::bail!("spacebot isn't running");
+        }
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .context("failed to build tokio runtime")?;
+        match runtime.block_on(spacebot::daemon::send_command(&paths, command))? {
+            IpcResponse::Error { message } => anyhow::bail!("{message}"),
+            response => Ok(response),
+        }
     };
 
-    // Use a small runtime for the IPC call
-    let runtime = tokio::runtime::Builder::new_current_thread()
-        .enable_all()
-        .build()
-        .context("failed to build tokio runtime")?;
+    match action {
+        ConfigAction::Schema { output } => {
+            let schema = serde_json::to_string_pretty(&spacebot::config::Config::json_schema())?;
+            match output {
+                Some(path) => std::fs::write(&path, format!("{schema}\n"))
+                    .with_context(|| format!("failed to write {}", path.display())),
+                None => {
+                    println!("{schema}");
+                    Ok(())
+                }
+            }
+        }
+        ConfigAction::Diff { against } => {
+            let config_path = config_path.unwrap_or_else(|| {
+                spacebot::config::Config::default_instance_dir().join("config.toml")
+            });
+            let read = |path: &std::path::Path| -> anyhow::Result<toml::Value> {
+                let source = std::fs::read_to_string(path)
+                    .with_context(|| format!("failed to read {}", path.display()))?;
+                rollout::parse(&source)
+                    .with_context(|| format!("failed to parse {}", path.display()))
+            };
+            let file = read(&config_path)?;
+            let (old, canary) = if against == "running" {
+                let IpcResponse::ConfigRollout(status) = send(IpcCommand::ConfigRollout)? else {
+                    anyhow::bail!("unexpected response from daemon");
+                };
+                let running = status
+                    .running
+                    .context("the daemon hasn't loaded a config yet")?;
+                (rollout::parse(&running)?, status.canary)
+            } else {
+                (read(std::path::Path::new(&against))?, None)
+            };
+            let changes = rollout::diff(&old, &file, canary.as_deref());
 
-    runtime.block_on(async {
-        match spacebot::daemon::send_command(&paths, spacebot::daemon::IpcCommand::Shutdown).await {
-            Ok(spacebot::daemon::IpcResponse::Ok) => {
-                eprintln!("stopping spacebot (pid {pid})...");
+            if json {
+                return print_json(&changes);
             }
-            Ok(spacebot::daemon::IpcResponse::Error { message }) => {
-                eprintln!("shutdown failed: {message}");
-                std::process::exit(1);
+            println!("{against} -> {}", config_path.display());
+            if let Some(canary) = &canary {
+                println!("routing canary: {canary}");
             }
-            Ok(_) => {
-                eprintln!("unexpected response from daemon");
-                std::process::exit(1);
+            print_config_changes(&changes);
+            Ok(())
+        }
+        ConfigAction::Apply { canary } => {
+            let canary = canary
+                .map(|canary| match canary.strip_prefix("agent=") {
+                    Some(agent_id) if !agent_id.is_empty() => Ok(agent_id.to_string()),
+                    _ => Err(anyhow::anyhow!("--canary takes agent=<id>, got {canary}")),
+                })
+                .transpose()?;
+            let IpcResponse::ConfigApplied(changes) = send(IpcCommand::ApplyConfig {
+                canary: canary.clone(),
+            })?
+            else {
+                anyhow::bail!("unexpected response from daemon");
+            };
+
+            if json {
+                return print_json(&changes);
             }
-            Err(error) => {
-                eprintln!("failed to send shutdown command: {error}");
-                std::process::exit(1);
+            println!("config reloaded");
+            print_config_changes(&changes);
+            if let Some(agent_id) = canary {
+                println!(
+                    "new routing only reaches {agent_id}; run `spacebot config apply` to roll it out to every agent"
+                );
             }
+            Ok(())
         }
-    });
-
-    if spacebot::daemon::wait_for_exit(pid) {
-        eprintln!("spacebot stopped");
-    } else {
-        eprintln!("spacebot did not stop within 10 seconds (pid {pid})");
-        std::process::exit(1);
     }
+}
 
-    Ok(())
+fn print_config_changes(changes: &[spacebot::config::rollout::ConfigChange]) {
+    use spacebot::config::rollout::ApplyMode;
+
+    if changes.is_empty() {
+        println!("no changes");
+        return;
+    }
+    for change in changes {
+        let mode = match change.mode {
+            ApplyMode::Reload => "reload",
+            ApplyMode::Canary => "canary",
+            ApplyMode::Restart => "restart",
+        };
+        println!(
+            "  {mode:<8} {}: {} -> {}",
+            change.path,
+            change.old.as_deref().unwrap_or("(unset)"),
+            change.new.as_deref().unwrap_or("(unset)"),
+        );
+    }
 }
 
-/// Stop if running, don't error if not.
-fn cmd_stop_if_running() {
+fn cmd_privacy(
+    action: PrivacyAction,
+    config_path: Option<std::path::PathBuf>,
+    json: bool,
+) -> anyhow::Result<()> {
+    use spacebot::privacy::{DataSubject, UserEraser};
+
+    let PrivacyAction::Forget {
+        user,
+        platform,
+        agent,
+        dry_run,
+    } = action;
+
+    // Running channels keep their history in memory and would write it
+    // back, so a real deletion needs the daemon stopped.
     let paths = spacebot::daemon::DaemonPaths::from_default();
+    if let Some(pid) = spacebot::daemon::is_running(&paths).filter(|_| !dry_run) {
+        anyhow::bail!("spacebot is running (pid {pid}); stop it before deleting user data");
+    }
 
-    let Some(pid) = spacebot::daemon::is_running(&paths) else {
-        return;
+    let config = load_config(&config_path)?;
+    let agents: Vec<_> = config
+        .resolve_agents()
+        .into_iter()
+        .filter(|agent_config| agent.as_ref().is_none_or(|id| agent_config.id == *id))
+        .collect();
+    if let Some(id) = agent.as_ref().filter(|_| agents.is_empty()) {
+        anyhow::bail!("no agent named '{id}'");
+    }
+    let subject = DataSubject {
+        user_id: user,
+        platform,
     };
 
-    let Ok(runtime) = tokio::runtime::Builder::new_current_thread()
+    let runtime = tokio::runtime::Builder::new_current_thread()
         .enable_all()
         .build()
-    else {
-        return;
-    };
+        .context("failed to build tokio runtime")?;
+    let mut reports = Vec::new();
+    for agent_config in &agents {
+        let sqlite_path = agent_config.sqlite_path();
+        if !sqlite_path.exists() {
+            continue;
+        }
+        let report = runtime.block_on(async {
+            let mode = if dry_run { "ro" } else { "rw" };
+            let pool =
+                sqlx::SqlitePool::connect(&format!("sqlite:{}?mode={mode}", sqlite_path.display()))
+                    .await
+                    .with_context(|| format!("failed to open {}", sqlite_path.display()))?;
+            let embeddings = if dry_run {
+                None
+            } else {
+                let lance_path = agent_config.lancedb_path();
+                let connection = lancedb::connect(&lance_path.to_string_lossy())
+                    .execute()
+                    .await
+                    .with_context(|| format!("failed to open {}", lance_path.display()))?;
+                Some(spacebot::memory::EmbeddingTable::open_or_create(&connection).await?)
+            };
+            let eraser = UserEraser::new(
+                pool.clone(),
+                embeddings,
+                agent_config.archives_dir.clone(),
+                agent_config.workspace.clone(),
+            );
+            let report = eraser.forget(&agent_config.id, &subject, dry_run).await;
+            pool.close().await;
+            anyhow::Ok(report?)
+        })?;
+        reports.push(report);
+    }
 
-    runtime.block_on(async {
-        if let Ok(spacebot::daemon::IpcResponse::Ok) =
-            spacebot::daemon::send_command(&paths, spacebot::daemon::IpcCommand::Shutdown).await
-        {
-            eprintln!("stopping spacebot (pid {pid})...");
-            spacebot::daemon::wait_for_exit(pid);
+    if json {
+        return print_json(&reports);
+    }
+
+    let verb = if dry_run { "would delete" } else { "deleted" };
+    for report in &reports {
+        println!("{}:", report.agent_id);
+        println!(
+            "  {verb} {} messages in shared conversations",
+            report.messages
+        );
+        println!(
+            "  {verb} {} private conversations ({} messages, {} pins)",
+            report.private_conversations.len(),
+            report.private_messages,
+            report.private_pins
+        );
+        for channel_id in &report.private_conversations {
+            println!("    {channel_id}");
         }
-    });
+        println!("  {verb} {} feedback entries", report.feedback);
+        println!("  {verb} {} queued replies", report.queued_replies);
+        println!("  {verb} {} memories", report.memories.len());
+        println!(
+            "  {verb} {} archived messages ({} archives rewritten, {} removed)",
+            report.archived_messages,
+            report.archives_rewritten.len(),
+            report.archives_deleted.len()
+        );
+        for path in &report.needs_review {
+            println!("  mentions the user, review by hand: {}", path.display());
+        }
+    }
+    Ok(())
 }
 
-fn cmd_status() -> anyhow::Result<()> {
+fn cmd_log_level(change: spacebot::daemon::LogLevelChange, json: bool) -> anyhow::Result<()> {
     let paths = spacebot::daemon::DaemonPaths::from_default();
-
-    let Some(_pid) = spacebot::daemon::is_running(&paths) else {
+    if spacebot::daemon::is_running(&paths).is_none() {
         eprintln!("spacebot is not running");
         std::process::exit(1);
-    };
+    }
 
     let runtime = tokio::runtime::Builder::new_current_thread()
         .enable_all()
         .build()
         .context("failed to build tokio runtime")?;
-
-    runtime.block_on(async {
-        match spacebot::daemon::send_command(&paths, spacebot::daemon::IpcCommand::Status).await {
-            Ok(spacebot::daemon::IpcResponse::Status {
-                pid,
-                uptime_seconds,
-            }) => {
-                let hours = uptime_seconds / 3600;
-                let minutes = (uptime_seconds % 3600) / 60;
-                let seconds = uptime_seconds % 60;
-                eprintln!("spacebot is running");
-                eprintln!("  pid:    {pid}");
-                eprintln!("  uptime: {hours}h {minutes}m {seconds}s");
-            }
-            Ok(spacebot::daemon::IpcResponse::Error { message }) => {
-                eprintln!("status query failed: {message}");
-                std::process::exit(1);
-            }
-            Ok(_) => {
-                eprintln!("unexpected response from daemon");
-                std::process::exit(1);
-            }
-            Err(error) => {
-                eprintln!("failed to query daemon status: {error}");
-                std::process::exit(1);
+    let response = runtime.block_on(spacebot::daemon::send_command(
+        &paths,
+        spacebot::daemon::IpcCommand::LogLevel(change),
+    ))?;
+
+    match response {
+        spacebot::daemon::IpcResponse::LogFilter { filter } => {
+            if json {
+                print_json(&serde_json::json!({ "filter": filter }))?;
+            } else {
+                eprintln!("log filter: {filter}");
             }
+            Ok(())
         }
-    });
+        spacebot::daemon::IpcResponse::Error { message } => {
+            anyhow::bail!("failed to change log level: {message}")
+        }
+        _ => anyhow::bail!("unexpected response from daemon"),
+    }
+}
 
+fn print_json(value: &impl serde::Serialize) -> anyhow::Result<()> {
+    let json = serde_json::to_string_pretty(value).context("failed to serialize output")?;
+    println!("{json}");
     Ok(())
 }
 
@@ -238,12 +1947,38 @@ fn load_config(
     }
 }
 
-async fn run(config: spacebot::config::Config, foreground: bool) -> anyhow::Result<()> {
+async fn run(
+    config: spacebot::config::Config,
+    foreground: bool,
+    maintenance: bool,
+) -> anyhow::Result<()> {
     let paths = spacebot::daemon::DaemonPaths::new(&config.instance_dir);
 
     tracing::info!("starting spacebot");
     tracing::info!(instance_dir = %config.instance_dir.display(), "configuration loaded");
 
+    // Opt-in crash reporting. Reports spooled by a previous crash go out now.
+    if let Some(reporter) =
+        spacebot::crash::CrashReporter::from_config(&config.telemetry, &config.instance_dir)?
+    {
+        let adapters = [
+            config.messaging.discord.as_ref().map(|_| "discord"),
+            config.messaging.slack.as_ref().map(|_| "slack"),
+            config.messaging.telegram.as_ref().map(|_| "telegram"),
+            config.messaging.irc.as_ref().map(|_| "irc"),
+            config.messaging.xmpp.as_ref().map(|_| "xmpp"),
+            config.messaging.notify.as_ref().map(|_| "notify"),
+            config.messaging.twilio.as_ref().map(|_| "twilio"),
+            config.messaging.webhook.as_ref().map(|_| "webhook"),
+        ];
+        let adapters = adapters.into_iter().flatten().collect::<Vec<_>>().join(",");
+        spacebot::crash::set_tag("agents", config.agents.len().to_string());
+        spacebot::crash::set_tag("adapters", adapters);
+        reporter.install_panic_hook();
+        reporter.spawn_uploader();
+        tracing::info!("crash reporting enabled");
+    }
+
     // Start the IPC server for stop/status commands
     let (mut shutdown_rx, _ipc_handle) = spacebot::daemon::start_ipc_server(&paths)
         .await
@@ -257,6 +1992,11 @@ async fn run(config: spacebot::config::Config, foreground: bool) -> anyhow::Resu
         provider_tx,
     ));
 
+    if maintenance {
+        api_state.maintenance.enable(None);
+    }
+    api_state.start_event_journal(config.instance_dir.join("logs").join("events.jsonl"));
+
     // Start background update checker
     spacebot::update::spawn_update_checker(api_state.update_status.clone());
 
@@ -288,14 +2028,50 @@ async fn run(config: spacebot::config::Config, foreground: bool) -> anyhow::Resu
         }
     }
 
+    // Outlives manager rebuilds so subscribers keep receiving events
+    let events = spacebot::events::EventBus::default();
+    forward_events_to_api(&events, &api_state);
+    let logs_dir = config.instance_dir.join("logs");
+    log_event_lines(&events, logs_dir.join("races.jsonl"), |event| match event {
+        spacebot::events::Event::RaceFinished { race } => Some(race),
+        _ => None,
+    });
+    log_event_lines(
+        &events,
+        logs_dir.join("shadow.jsonl"),
+        |event| match event {
+            spacebot::events::Event::ShadowFinished { shadow } => Some(shadow),
+            _ => None,
+        },
+    );
+    log_event_lines(
+        &events,
+        spacebot::changelog::path(&config.instance_dir),
+        spacebot::changelog::from_event,
+    );
+    record_tool_usage(&events, &api_state, config.llm.tool_pricing.clone());
+    alert_key_health(
+        &events,
+        &api_state,
+        config.llm.key_check.delivery_targets.clone(),
+    );
+
     // Shared LLM manager (same API keys for all agents)
     // This works even without keys; it will fail later at call time if no keys exist
     let llm_manager = Arc::new(
-        spacebot::llm::LlmManager::new(config.llm.clone())
-            .await
+        build_llm_manager(&config.llm, &events)
             .with_context(|| "failed to initialize LLM manager")?,
     );
 
+    if has_providers {
+        llm_manager.warm_up().await;
+    }
+    llm_manager.spawn_health_checks();
+    llm_manager.spawn_outage_probes();
+    llm_manager.spawn_key_checks();
+    api_state.set_llm_manager(llm_manager.clone()).await;
+    spacebot::daemon::set_llm_manager(&llm_manager);
+
     // Shared embedding model (stateless, agent-agnostic)
     let embedding_cache_dir = config.instance_dir.join("embedding_cache");
     let embedding_model = Arc::new(
@@ -327,6 +2103,18 @@ async fn run(config: spacebot::config::Config, foreground: bool) -> anyhow::Resu
         Arc::new(ArcSwap::from_pointee(config.bindings.clone()));
     api_state.set_bindings(bindings.clone()).await;
     let default_agent_id = config.default_agent_id().to_string();
+    // Conversations agents hand to each other, and the queue of handoffs to carry out
+    let (handoffs, mut handoff_rx) = spacebot::agent::handoff::Handoffs::new();
+    // When each conversation last activated its agent, for activation cooldowns
+    let activations = spacebot::activation::Activations::new();
+    // Recently seen inbound messages, so redeliveries and double sends run once
+    let inbound_dedup = spacebot::messaging::dedup::InboundDedup::new();
+    // Classifies messages no binding matches; set once agents are initialized
+    let mut intake: Option<Arc<spacebot::messaging::intake::IntakeRouter>> = None;
+    // Routes or declines sensitive topics; set once agents are initialized
+    let mut topics: Option<Arc<spacebot::messaging::topics::TopicRouter>> = None;
+    // Operator hook scripts; loaded once, a restart picks up changes
+    let scripts = spacebot::messaging::scripts::ScriptHooks::from_config(&config.scripting);
 
     // Set the config path on the API state for config.toml writes
     let config_path = config.instance_dir.join("config.toml");
@@ -356,6 +2144,7 @@ async fn run(config: spacebot::config::Config, foreground: bool) -> anyhow::Resu
             &mut cron_schedulers_for_shutdown,
             &mut _ingestion_handles,
             &mut _cortex_handles,
+            &handoffs,
             &mut watcher_agents,
             &mut discord_permissions,
             &mut slack_permissions,
@@ -363,6 +2152,19 @@ async fn run(config: spacebot::config::Config, foreground: bool) -> anyhow::Resu
         )
         .await?;
         agents_initialized = true;
+        api_state.set_ready(true);
+        intake = spacebot::messaging::intake::IntakeRouter::from_agents(
+            &config.intake,
+            &agents,
+            &default_agent_id,
+            embedding_model.clone(),
+        );
+        api_state.set_intake(intake.clone()).await;
+        topics = spacebot::messaging::topics::TopicRouter::from_config(
+            &config.topics,
+            &agents,
+            &default_agent_id,
+        );
 
         // Start file watcher with populated agent data
         _file_watcher = spacebot::config::spawn_file_watcher(
@@ -398,28 +2200,300 @@ async fn run(config: spacebot::config::Config, foreground: bool) -> anyhow::Resu
         tracing::info!(pid = std::process::id(), "spacebot daemon started");
     }
 
-    // Active conversation channels: conversation_id -> ActiveChannel
-    let mut active_channels: HashMap<String, ActiveChannel> = HashMap::new();
+    // systemd and launchd stop services with SIGTERM
+    let mut terminate = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+        .context("failed to install SIGTERM handler")?;
+
+    // Active conversation channels: conversation_id -> ActiveChannel
+    let mut active_channels: HashMap<String, ActiveChannel> = HashMap::new();
+
+    // Main event loop: route inbound messages to agent channels
+    loop {
+        // Poll the inbound stream if it exists, otherwise yield a never-resolving future
+        let inbound_next = async {
+            match inbound_stream.as_mut() {
+                Some(stream) => stream.next().await,
+                None => std::future::pending().await,
+            }
+        };
+        tokio::select! {
+            Some(mut message) = inbound_next, if agents_initialized => {
+                // Hook scripts can rewrite or drop the message before it's routed.
+                let preprocessed = match &scripts {
+                    Some(scripts) => scripts.preprocess(&mut message),
+                    None => spacebot::messaging::scripts::Preprocessed::Pass,
+                };
+                if let spacebot::messaging::scripts::Preprocessed::Drop { script } = preprocessed {
+                    tracing::info!(
+                        conversation_id = %message.conversation_id,
+                        message_id = %message.id,
+                        %script,
+                        "inbound message dropped by script"
+                    );
+                    continue;
+                }
+
+                // Resolve which agent handles this message (bindings hot-reload on config change).
+                // Handed-off conversations stay with the agent they were handed to, and
+                // messages no binding matches are classified when intake is on.
+                let current_bindings = bindings.load();
+                let handed_to = handoffs.route(&message.conversation_id);
+                let handed_off = handed_to.is_some();
+                let bound =
+                    spacebot::config::resolve_binding_for_message(&current_bindings, &message)
+                        .map(|binding| spacebot::AgentId::from(binding.agent_id.as_str()));
+                let agent_id = match (handed_to, bound, &intake) {
+                    (Some(agent_id), _, _) | (None, Some(agent_id), _) => agent_id,
+                    (None, None, Some(intake)) => intake.route(&message).await,
+                    (None, None, None) => spacebot::AgentId::from(default_agent_id.as_str()),
+                };
+                message.agent_id = Some(agent_id.clone());
+
+                // Route hooks can pick another agent, except for handed-off
+                // conversations.
+                let routed = scripts
+                    .as_ref()
+                    .filter(|_| !handed_off)
+                    .and_then(|scripts| scripts.route(&message));
+                let agent_id = match routed {
+                    Some(routed) if agents.contains_key(&routed) => {
+                        message.agent_id = Some(routed.clone());
+                        routed
+                    }
+                    Some(routed) => {
+                        tracing::warn!(
+                            agent_id = %routed,
+                            "script routed a message to an unknown agent, ignoring it"
+                        );
+                        agent_id
+                    }
+                    None => agent_id,
+                };
+
+                // Redelivered webhooks and double-sent messages stop here,
+                // before they can record feedback or start a run twice.
+                let duplicate = agents.get(&agent_id).and_then(|agent| {
+                    inbound_dedup.check(&agent.deps.runtime_config.dedup.load(), &message)
+                });
+                if let Some(duplicate) = duplicate {
+                    tracing::info!(
+                        conversation_id = %message.conversation_id,
+                        message_id = %message.id,
+                        ?duplicate,
+                        "duplicate inbound message dropped"
+                    );
+                    continue;
+                }
+
+                // Reactions and /feedback commands go to the feedback ledger,
+                // not the conversation.
+                if let Some(signal) = spacebot::feedback::FeedbackSignal::from_message(&message) {
+                    if let Some(agent) = agents.get(&agent_id) {
+                        let store = spacebot::feedback::FeedbackStore::new(agent.db.sqlite.clone());
+                        tokio::spawn(async move {
+                            if let Err(error) = store
+                                .record(&message.conversation_id, &message.sender_id, &signal)
+                                .await
+                            {
+                                tracing::warn!(%error, "failed to record feedback");
+                            }
+                        });
+                    }
+                    continue;
+                }
+
+                // Sensitive topics go to their own agent, after its disclaimer,
+                // or are declined without reaching a model.
+                let topic = match &topics {
+                    Some(topics) => topics.check(&message).await,
+                    None => spacebot::messaging::topics::TopicDecision::Pass,
+                };
+                let agent_id = match topic {
+                    spacebot::messaging::topics::TopicDecision::Pass => agent_id,
+                    spacebot::messaging::topics::TopicDecision::Route {
+                        agent_id: routed,
+                        disclaimer,
+                        ..
+                    } => {
+                        if let Some(disclaimer) = disclaimer {
+                            let disclaimer = spacebot::OutboundResponse::Text(disclaimer);
+                            if let Err(error) =
+                                messaging_manager.respond(&message, disclaimer).await
+                            {
+                                tracing::warn!(%error, "failed to send topic disclaimer");
+                            }
+                        }
+                        message.agent_id = Some(routed.clone());
+                        routed
+                    }
+                    spacebot::messaging::topics::TopicDecision::Decline {
+                        category,
+                        message: text,
+                    } => {
+                        let declined = spacebot::declined::Declined::new(
+                            spacebot::declined::DeclineReason::SensitiveTopic,
+                            format!("topic category `{category}`"),
+                        )
+                        .with_message(text);
+                        send_decline(&messaging_manager, agents.get(&agent_id), &message, declined)
+                            .await;
+                        continue;
+                    }
+                };
+
+                // /confirm and /reject answer a worker's action preview or a
+                // channel's turn cost estimate. With nothing pending they're
+                // ordinary messages. Some actions only certain users may decide.
+                let decided = spacebot::approval::ApprovalCommand::from_message(&message)
+                    .and_then(|command| {
+                        let agent = agents.get(&agent_id)?;
+                        let action_id = command.action_id.as_deref();
+                        let tool_name = agent
+                            .deps
+                            .approvals
+                            .pending_tool(&message.conversation_id, action_id)?;
+                        if !agent.deps.may_decide(&tool_name, &message) {
+                            return Some(Err(tool_name));
+                        }
+                        agent
+                            .deps
+                            .approvals
+                            .resolve(&message.conversation_id, action_id, command.decision)
+                            .map(Ok)
+                    });
+                match decided {
+                    Some(Ok(tool_name)) => {
+                        tracing::info!(
+                            conversation_id = %message.conversation_id,
+                            %tool_name,
+                            "action preview decided by user"
+                        );
+                        continue;
+                    }
+                    Some(Err(tool_name)) => {
+                        let declined = spacebot::declined::Declined::new(
+                            spacebot::declined::DeclineReason::NotPermitted,
+                            format!("sender may not decide `{tool_name}` actions"),
+                        )
+                        .with_message(
+                            "Only incident responders can confirm or reject incident changes.",
+                        );
+                        send_decline(&messaging_manager, agents.get(&agent_id), &message, declined)
+                            .await;
+                        continue;
+                    }
+                    None => {}
+                }
+
+                // In maintenance mode nothing new starts. Each conversation
+                // is told once; feedback and approvals above still go through.
+                if let spacebot::maintenance::Admission::Refused { notice, notify } =
+                    api_state.maintenance.check(&message.conversation_id)
+                {
+                    let mut declined = spacebot::declined::Declined::new(
+                        spacebot::declined::DeclineReason::Maintenance,
+                        "maintenance mode is on",
+                    );
+                    if notify {
+                        declined = declined.with_message(notice);
+                    }
+                    send_decline(&messaging_manager, agents.get(&agent_id), &message, declined)
+                        .await;
+                    continue;
+                }
 
-    // Main event loop: route inbound messages to agent channels
-    loop {
-        // Poll the inbound stream if it exists, otherwise yield a never-resolving future
-        let inbound_next = async {
-            match inbound_stream.as_mut() {
-                Some(stream) => stream.next().await,
-                None => std::future::pending().await,
-            }
-        };
-        tokio::select! {
-            Some(mut message) = inbound_next, if agents_initialized => {
-                // Resolve which agent handles this message (bindings hot-reload on config change)
-                let current_bindings = bindings.load();
-                let agent_id = spacebot::config::resolve_agent_for_message(
+                // Messages the binding's activation rules don't let through go
+                // into the transcript for context and no further, so a busy
+                // channel doesn't cost a model call per line.
+                let activation = spacebot::config::resolve_binding_for_message(
                     &current_bindings,
                     &message,
-                    &default_agent_id,
-                );
-                message.agent_id = Some(agent_id.clone());
+                )
+                .and_then(|binding| binding.activation.as_ref());
+                let activated = match activation {
+                    Some(policy) => activations.admit(
+                        policy,
+                        &message,
+                        chrono::Timelike::hour(&chrono::Local::now()) as u8,
+                        std::time::Instant::now(),
+                    ),
+                    None => true,
+                };
+                if !activated {
+                    if let Some(agent) = agents.get(&agent_id) {
+                        let sender_name = message
+                            .metadata
+                            .get("sender_display_name")
+                            .and_then(|v| v.as_str())
+                            .unwrap_or(&message.sender_id);
+                        let channel_id: spacebot::ChannelId =
+                            Arc::from(message.conversation_id.as_str());
+                        spacebot::conversation::ConversationLogger::new(agent.db.sqlite.clone())
+                            .log_user_message(
+                                &channel_id,
+                                sender_name,
+                                &message.sender_id,
+                                &message.content.to_string(),
+                                &spacebot::conversation::history::logged_metadata(&message),
+                            );
+                    }
+                    tracing::debug!(
+                        conversation_id = %message.conversation_id,
+                        "inbound message didn't activate the agent"
+                    );
+                    continue;
+                }
+
+                // Drop messages over the agent's quotas. The first one over
+                // gets a polite refusal; the rest are dropped silently.
+                let limited = agents.get(&agent_id).and_then(|agent| {
+                    if agent.deps.is_admin(&message) {
+                        return None;
+                    }
+                    let user = format!("{}:{}", message.source, message.sender_id);
+                    match agent.deps.rate_limiter.check(
+                        &agent.deps.runtime_config.rate_limit.load(),
+                        &user,
+                        &message.conversation_id,
+                    ) {
+                        spacebot::messaging::rate_limit::Verdict::Allowed => None,
+                        spacebot::messaging::rate_limit::Verdict::Limited {
+                            scope,
+                            retry_after,
+                            notify,
+                        } => Some((scope, retry_after, notify)),
+                    }
+                });
+                if let Some((scope, retry_after, notify)) = limited {
+                    let mut declined = spacebot::declined::Declined::new(
+                        spacebot::declined::DeclineReason::RateLimited,
+                        format!("{scope:?} limit"),
+                    );
+                    if notify {
+                        declined = declined.with_message(scope.refusal(retry_after));
+                    }
+                    send_decline(&messaging_manager, agents.get(&agent_id), &message, declined)
+                        .await;
+                    continue;
+                }
+
+                // Custom `!` commands are answered by their script, without
+                // a channel.
+                let command = scripts.as_ref().and_then(|scripts| scripts.command(&message));
+                if let Some(command) = command {
+                    tracing::debug!(
+                        conversation_id = %message.conversation_id,
+                        command = %command.name,
+                        "script command answered"
+                    );
+                    if let Some(reply) = command.reply {
+                        let reply = spacebot::OutboundResponse::Text(reply);
+                        if let Err(error) = messaging_manager.respond(&message, reply).await {
+                            tracing::warn!(%error, "failed to send script command reply");
+                        }
+                    }
+                    continue;
+                }
 
                 let conversation_id = message.conversation_id.clone();
 
@@ -434,147 +2508,17 @@ async fn run(config: spacebot::config::Config, foreground: bool) -> anyhow::Resu
                         continue;
                     };
 
-                    // Create outbound response channel
-                    let (response_tx, mut response_rx) = mpsc::channel::<spacebot::OutboundResponse>(32);
-
-                    // Subscribe to the agent's event bus
-                    let event_rx = agent.deps.event_tx.subscribe();
-
-                    let channel_id: spacebot::ChannelId = Arc::from(conversation_id.as_str());
-
-                    let (channel, channel_tx) = spacebot::agent::channel::Channel::new(
-                        channel_id,
-                        agent.deps.clone(),
-                        response_tx,
-                        event_rx,
-                        agent.config.screenshot_dir(),
-                        agent.config.logs_dir(),
-                    );
-
-                    // Register the channel's status block with the API for snapshot queries
-                    api_state.register_channel_status(
-                        conversation_id.clone(),
-                        channel.state.status_block.clone(),
-                    ).await;
-
-                    // Register the channel state for API-driven cancellation
-                    api_state.register_channel_state(
-                        conversation_id.clone(),
-                        channel.state.clone(),
-                    ).await;
-
-                    // Backfill recent message history from the platform
-                    let backfill_count = agent.config.history_backfill_count();
-                    if backfill_count > 0 {
-                        match messaging_manager.fetch_history(&message, backfill_count).await {
-                            Ok(history_messages) if !history_messages.is_empty() => {
-                                let mut transcript = String::new();
-                                for entry in &history_messages {
-                                    let label = if entry.is_bot { "(you)" } else { &entry.author };
-                                    transcript.push_str(&format!("{}: {}\n", label, entry.content));
-                                }
-
-                                let prompt_engine = agent.deps.runtime_config.prompts.load();
-                                let backfill_text = prompt_engine
-                                    .render_system_history_backfill(transcript.trim_end())
-                                    .unwrap_or(transcript);
-
-                                let mut history = channel.state.history.write().await;
-                                history.push(rig::message::Message::from(backfill_text));
-                                drop(history);
-
-                                tracing::info!(
-                                    conversation_id = %conversation_id,
-                                    message_count = history_messages.len(),
-                                    "backfilled channel history"
-                                );
-                            }
-                            Err(error) => {
-                                tracing::warn!(%error, "failed to backfill channel history");
-                            }
-                            _ => {}
-                        }
-                    }
-
-                    // Spawn the channel's event loop
-                    tokio::spawn(async move {
-                        if let Err(error) = channel.run().await {
-                            tracing::error!(%error, "channel event loop failed");
-                        }
-                    });
-
-                    // Spawn outbound response routing: reads from response_rx,
-                    // sends to the messaging adapter and forwards to SSE
-                    let messaging_for_outbound = messaging_manager.clone();
-                    let outbound_message = message.clone();
-                    let outbound_conversation_id = conversation_id.clone();
-                    let api_event_tx = api_state.event_tx.clone();
-                    let sse_agent_id = agent_id.to_string();
-                    let sse_channel_id = conversation_id.clone();
-                    let outbound_handle = tokio::spawn(async move {
-                        while let Some(response) = response_rx.recv().await {
-                            // Forward relevant events to SSE clients
-                            match &response {
-                                spacebot::OutboundResponse::Text(text) => {
-                                    api_event_tx.send(spacebot::api::ApiEvent::OutboundMessage {
-                                        agent_id: sse_agent_id.clone(),
-                                        channel_id: sse_channel_id.clone(),
-                                        text: text.clone(),
-                                    }).ok();
-                                }
-                                spacebot::OutboundResponse::ThreadReply { text, .. } => {
-                                    api_event_tx.send(spacebot::api::ApiEvent::OutboundMessage {
-                                        agent_id: sse_agent_id.clone(),
-                                        channel_id: sse_channel_id.clone(),
-                                        text: text.clone(),
-                                    }).ok();
-                                }
-                                spacebot::OutboundResponse::Status(spacebot::StatusUpdate::Thinking) => {
-                                    api_event_tx.send(spacebot::api::ApiEvent::TypingState {
-                                        agent_id: sse_agent_id.clone(),
-                                        channel_id: sse_channel_id.clone(),
-                                        is_typing: true,
-                                    }).ok();
-                                }
-                                spacebot::OutboundResponse::Status(spacebot::StatusUpdate::StopTyping) => {
-                                    api_event_tx.send(spacebot::api::ApiEvent::TypingState {
-                                        agent_id: sse_agent_id.clone(),
-                                        channel_id: sse_channel_id.clone(),
-                                        is_typing: false,
-                                    }).ok();
-                                }
-                                _ => {}
-                            }
-
-                            match response {
-                                spacebot::OutboundResponse::Status(status) => {
-                                    if let Err(error) = messaging_for_outbound
-                                        .send_status(&outbound_message, status)
-                                        .await
-                                    {
-                                        tracing::warn!(%error, "failed to send status update");
-                                    }
-                                }
-                                response => {
-                                    tracing::info!(
-                                        conversation_id = %outbound_conversation_id,
-                                        "routing outbound response to messaging adapter"
-                                    );
-                                    if let Err(error) = messaging_for_outbound
-                                        .respond(&outbound_message, response)
-                                        .await
-                                    {
-                                        tracing::error!(%error, "failed to send outbound response");
-                                    }
-                                }
-                            }
-                        }
-                    });
-
-                    active_channels.insert(conversation_id.clone(), ActiveChannel {
-                        message_tx: channel_tx,
-                        _outbound_handle: outbound_handle,
-                    });
+                    let binding =
+                        spacebot::config::resolve_binding_for_message(&current_bindings, &message);
+                    let channel = spawn_channel(
+                        agent,
+                        &message,
+                        binding,
+                        &messaging_manager,
+                        &api_state,
+                    )
+                    .await;
+                    active_channels.insert(conversation_id.clone(), channel);
 
                     tracing::info!(
                         conversation_id = %conversation_id,
@@ -603,6 +2547,83 @@ async fn run(config: spacebot::config::Config, foreground: bool) -> anyhow::Resu
                     }
                 }
             }
+            Some(request) = handoff_rx.recv() => {
+                // Move the conversation to the agent it was handed to: close the old
+                // channel, open one for the new agent and start it on the summary.
+                let conversation_id = request.conversation_id.clone();
+                let Some(agent) = agents.get(&request.to) else {
+                    tracing::warn!(
+                        conversation_id = %conversation_id,
+                        agent_id = %request.to,
+                        "handoff to unknown agent, dropping"
+                    );
+                    continue;
+                };
+                let Some(previous) = active_channels.remove(&conversation_id) else {
+                    tracing::warn!(
+                        conversation_id = %conversation_id,
+                        "handoff for a conversation with no open channel, dropping"
+                    );
+                    continue;
+                };
+
+                let mut message = previous.origin;
+                message.agent_id = Some(request.to.clone());
+                let current_bindings = bindings.load();
+                let binding =
+                    spacebot::config::resolve_binding_for_message(&current_bindings, &message);
+                let channel = spawn_channel(
+                    agent,
+                    &message,
+                    binding,
+                    &messaging_manager,
+                    &api_state,
+                )
+                .await;
+                handoffs.assign(&conversation_id, request.to.clone());
+
+                let channel_id: spacebot::ChannelId = Arc::from(conversation_id.as_str());
+                let logger = spacebot::conversation::ConversationLogger::new(agent.db.sqlite.clone());
+                logger.log_handoff(
+                    &channel_id,
+                    &request.notice(),
+                    &request.from,
+                    &request.to,
+                    &request.reason,
+                );
+                let brief = agent
+                    .deps
+                    .runtime_config
+                    .prompts
+                    .load()
+                    .render_system_handoff(&request.from, &request.reason, &request.summary)
+                    .unwrap_or_else(|_| request.summary.clone());
+                let first_turn = spacebot::InboundMessage {
+                    id: uuid::Uuid::new_v4().to_string(),
+                    source: "system".into(),
+                    conversation_id: conversation_id.clone(),
+                    sender_id: "system".into(),
+                    agent_id: Some(request.to.clone()),
+                    content: spacebot::MessageContent::Text(brief),
+                    timestamp: chrono::Utc::now(),
+                    metadata: HashMap::new(),
+                };
+                if let Err(error) = channel.message_tx.send(first_turn).await {
+                    tracing::error!(
+                        conversation_id = %conversation_id,
+                        %error,
+                        "failed to start channel after handoff"
+                    );
+                }
+                active_channels.insert(conversation_id.clone(), channel);
+
+                tracing::info!(
+                    conversation_id = %conversation_id,
+                    from = %request.from,
+                    to = %request.to,
+                    "conversation handed off"
+                );
+            }
             Some(_event) = provider_rx.recv(), if !agents_initialized => {
                 tracing::info!("provider keys configured, initializing agents");
 
@@ -619,9 +2640,15 @@ async fn run(config: spacebot::config::Config, foreground: bool) -> anyhow::Resu
                 match new_config {
                     Ok(new_config) if new_config.llm.has_any_key() => {
                         // Rebuild LlmManager with the new keys
-                        match spacebot::llm::LlmManager::new(new_config.llm.clone()).await {
+                        match build_llm_manager(&new_config.llm, &events) {
                             Ok(new_llm) => {
                                 let new_llm_manager = Arc::new(new_llm);
+                                new_llm_manager.warm_up().await;
+                                new_llm_manager.spawn_health_checks();
+                                new_llm_manager.spawn_outage_probes();
+                                new_llm_manager.spawn_key_checks();
+                                api_state.set_llm_manager(new_llm_manager.clone()).await;
+                                spacebot::daemon::set_llm_manager(&new_llm_manager);
                                 let mut new_watcher_agents = Vec::new();
                                 let mut new_discord_permissions = None;
                                 let mut new_slack_permissions = None;
@@ -638,6 +2665,7 @@ async fn run(config: spacebot::config::Config, foreground: bool) -> anyhow::Resu
                                     &mut cron_schedulers_for_shutdown,
                                     &mut _ingestion_handles,
                                     &mut _cortex_handles,
+                                    &handoffs,
                                     &mut new_watcher_agents,
                                     &mut new_discord_permissions,
                                     &mut new_slack_permissions,
@@ -645,6 +2673,21 @@ async fn run(config: spacebot::config::Config, foreground: bool) -> anyhow::Resu
                                 ).await {
                                     Ok(()) => {
                                         agents_initialized = true;
+                                        api_state.set_ready(true);
+                                        intake =
+                                            spacebot::messaging::intake::IntakeRouter::from_agents(
+                                                &new_config.intake,
+                                                &agents,
+                                                new_config.default_agent_id(),
+                                                embedding_model.clone(),
+                                            );
+                                        api_state.set_intake(intake.clone()).await;
+                                        topics =
+                                            spacebot::messaging::topics::TopicRouter::from_config(
+                                                &new_config.topics,
+                                                &agents,
+                                                new_config.default_agent_id(),
+                                            );
                                         // Restart file watcher with the new agent data
                                         _file_watcher = spacebot::config::spawn_file_watcher(
                                             config_path.clone(),
@@ -684,6 +2727,10 @@ async fn run(config: spacebot::config::Config, foreground: bool) -> anyhow::Resu
                 tracing::info!("shutdown signal received");
                 break;
             }
+            _ = terminate.recv() => {
+                tracing::info!("SIGTERM received");
+                break;
+            }
         }
     }
 
@@ -711,6 +2758,509 @@ async fn run(config: spacebot::config::Config, foreground: bool) -> anyhow::Resu
     std::process::exit(0);
 }
 
+/// Build the shared LLM manager, surfacing key failovers to API clients.
+fn build_llm_manager(
+    llm: &spacebot::config::LlmConfig,
+    events: &spacebot::events::EventBus,
+) -> Result<spacebot::llm::LlmManager, spacebot::error::LlmError> {
+    spacebot::llm::LlmManager::builder()
+        .config(llm.clone())
+        .event_bus(events.clone())
+        .build()
+}
+
+/// Relay bus events the web UI cares about onto the API event stream.
+fn forward_events_to_api(
+    events: &spacebot::events::EventBus,
+    api_state: &Arc<spacebot::api::ApiState>,
+) {
+    use spacebot::events::Event;
+    use tokio::sync::broadcast::error::RecvError;
+
+    let mut receiver = events.subscribe();
+    let event_tx = api_state.event_tx.clone();
+    tokio::spawn(async move {
+        loop {
+            let event = match receiver.recv().await {
+                Ok(event) => event,
+                Err(RecvError::Lagged(skipped)) => {
+                    tracing::warn!(skipped, "API event forwarder fell behind");
+                    continue;
+                }
+                Err(RecvError::Closed) => break,
+            };
+            let api_event = match event {
+                Event::CredentialFailover { provider } => {
+                    spacebot::api::ApiEvent::CredentialFailover { provider }
+                }
+                Event::ProviderHealthChanged {
+                    provider,
+                    healthy,
+                    detail,
+                } => spacebot::api::ApiEvent::ProviderHealthChanged {
+                    provider,
+                    healthy,
+                    detail,
+                },
+                Event::ProviderOutage {
+                    provider,
+                    active,
+                    detail,
+                } => spacebot::api::ApiEvent::ProviderOutage {
+                    provider,
+                    active,
+                    detail,
+                },
+                Event::KeyHealthChanged {
+                    provider,
+                    slot,
+                    status,
+                    detail,
+                } => spacebot::api::ApiEvent::KeyHealthChanged {
+                    provider,
+                    slot: slot.as_str().to_string(),
+                    status: status.as_str().to_string(),
+                    detail,
+                },
+                Event::LoopDetected {
+                    agent_id,
+                    process_id,
+                    kind,
+                    detail,
+                    ..
+                } => spacebot::api::ApiEvent::LoopDetected {
+                    agent_id,
+                    process_id,
+                    kind,
+                    detail,
+                },
+                _ => continue,
+            };
+            event_tx.send(api_event).ok();
+        }
+    });
+}
+
+/// Append each event `select` picks (finished best-of-N races, shadow
+/// requests) to `path` as a JSON line, so candidates and their costs can be
+/// analyzed later.
+fn log_event_lines<T: serde::Serialize + Send + 'static>(
+    events: &spacebot::events::EventBus,
+    path: std::path::PathBuf,
+    select: fn(spacebot::events::Event) -> Option<T>,
+) {
+    use tokio::io::AsyncWriteExt as _;
+    use tokio::sync::broadcast::error::RecvError;
+
+    let mut receiver = events.subscribe();
+    tokio::spawn(async move {
+        loop {
+            let record = match receiver.recv().await {
+                Ok(event) => match select(event) {
+                    Some(record) => record,
+                    None => continue,
+                },
+                Err(RecvError::Lagged(skipped)) => {
+                    tracing::warn!(skipped, path = %path.display(), "event log fell behind");
+                    continue;
+                }
+                Err(RecvError::Closed) => break,
+            };
+            let Ok(mut line) = serde_json::to_string(&record) else {
+                continue;
+            };
+            line.push('\n');
+            let written = async {
+                if let Some(parent) = path.parent() {
+                    tokio::fs::create_dir_all(parent).await?;
+                }
+                tokio::fs::OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(&path)
+                    .await?
+                    .write_all(line.as_bytes())
+                    .await
+            };
+            if let Err(error) = written.await {
+                tracing::warn!(%error, path = %path.display(), "failed to append to event log");
+            }
+        }
+    });
+}
+
+/// Count each tool call in the API's tool metrics and write it to its
+/// agent's `tool_runs` ledger, priced from `[llm] tool_pricing`.
+fn record_tool_usage(
+    events: &spacebot::events::EventBus,
+    api_state: &Arc<spacebot::api::ApiState>,
+    pricing: std::collections::HashMap<String, f64>,
+) {
+    use spacebot::tools::usage::ToolRun;
+    use tokio::sync::broadcast::error::RecvError;
+
+    let mut receiver = events.subscribe();
+    let api_state = api_state.clone();
+    tokio::spawn(async move {
+        loop {
+            let run = match receiver.recv().await {
+                Ok(event) => match ToolRun::from_event(&event, &pricing) {
+                    Some(run) => run,
+                    None => continue,
+                },
+                Err(RecvError::Lagged(skipped)) => {
+                    tracing::warn!(skipped, "tool usage recorder fell behind");
+                    continue;
+                }
+                Err(RecvError::Closed) => break,
+            };
+            api_state.tool_metrics.observe(&run);
+            let Some(pool) = api_state.agent_pools.load().get(&run.agent_id).cloned() else {
+                continue;
+            };
+            if let Err(error) = run.log(&pool).await {
+                tracing::warn!(%error, tool_name = %run.tool_name, "failed to log tool run");
+            }
+        }
+    });
+}
+
+/// Tell the sender of `message` why it was declined, when the decline has
+/// something to say, and count it against the agent it was routed to.
+async fn send_decline(
+    messaging_manager: &spacebot::messaging::MessagingManager,
+    agent: Option<&spacebot::Agent>,
+    message: &spacebot::InboundMessage,
+    declined: spacebot::declined::Declined,
+) {
+    tracing::info!(
+        conversation_id = %message.conversation_id,
+        sender_id = %message.sender_id,
+        reason = %declined.reason,
+        detail = %declined.detail,
+        "inbound message declined"
+    );
+    if let Some(agent) = agent {
+        agent.deps.decline_metrics.record(&declined);
+    }
+    let Some(response) = declined.response() else {
+        return;
+    };
+    if let Err(error) = messaging_manager.respond(message, response).await {
+        tracing::warn!(%error, reason = %declined.reason, "failed to send decline");
+    }
+}
+
+/// Send each provider key status change to `[llm.key_check]
+/// delivery_targets`, through whichever messaging manager is current.
+fn alert_key_health(
+    events: &spacebot::events::EventBus,
+    api_state: &Arc<spacebot::api::ApiState>,
+    delivery_targets: Vec<String>,
+) {
+    use spacebot::cron::scheduler::DeliveryTarget;
+    use spacebot::events::Event;
+    use tokio::sync::broadcast::error::RecvError;
+
+    if delivery_targets.is_empty() {
+        return;
+    }
+    let mut receiver = events.subscribe();
+    let api_state = api_state.clone();
+    tokio::spawn(async move {
+        loop {
+            let text = match receiver.recv().await {
+                Ok(Event::KeyHealthChanged {
+                    provider,
+                    slot,
+                    status,
+                    detail,
+                }) => format!(
+                    "{provider} API key ({}) is now {}: {detail}",
+                    slot.as_str(),
+                    status.as_str()
+                ),
+                Ok(_) => continue,
+                Err(RecvError::Lagged(skipped)) => {
+                    tracing::warn!(skipped, "key health alerts fell behind");
+                    continue;
+                }
+                Err(RecvError::Closed) => break,
+            };
+            let Some(messaging_manager) = api_state.messaging_manager.read().await.clone() else {
+                tracing::warn!(%text, "no messaging manager to deliver key health alert");
+                continue;
+            };
+            for raw_target in &delivery_targets {
+                let Some(target) = DeliveryTarget::parse(raw_target) else {
+                    tracing::warn!(%raw_target, "invalid key health delivery target, expected 'adapter:target'");
+                    continue;
+                };
+                if let Err(error) = messaging_manager
+                    .broadcast(
+                        &target.adapter,
+                        &target.target,
+                        spacebot::OutboundResponse::Text(text.clone()),
+                    )
+                    .await
+                {
+                    tracing::warn!(%error, %target, "failed to deliver key health alert");
+                }
+            }
+        }
+    });
+}
+
+/// Open a channel for `agent` on `message`'s conversation. Replies go back
+/// through the adapter `message` came from, run through the output pipeline
+/// of the `binding` it matched.
+async fn spawn_channel(
+    agent: &spacebot::Agent,
+    message: &spacebot::InboundMessage,
+    binding: Option<&spacebot::config::Binding>,
+    messaging_manager: &Arc<spacebot::messaging::MessagingManager>,
+    api_state: &Arc<spacebot::api::ApiState>,
+) -> ActiveChannel {
+    let conversation_id = message.conversation_id.clone();
+
+    // Create outbound response channel
+    let (response_tx, mut response_rx) = mpsc::channel::<spacebot::OutboundResponse>(32);
+
+    // Subscribe to the agent's event bus
+    let event_rx = agent.deps.event_tx.subscribe();
+
+    let channel_id: spacebot::ChannelId = Arc::from(conversation_id.as_str());
+
+    let (channel, channel_tx) = spacebot::agent::channel::Channel::new(
+        channel_id,
+        agent.deps.clone(),
+        response_tx,
+        event_rx,
+        agent.config.screenshot_dir(),
+        agent.config.logs_dir(),
+    );
+
+    // Register the channel's status block with the API for snapshot queries
+    api_state
+        .register_channel_status(conversation_id.clone(), channel.state.status_block.clone())
+        .await;
+
+    // Register the channel state for API-driven cancellation
+    api_state
+        .register_channel_state(conversation_id.clone(), channel.state.clone())
+        .await;
+    let last_completion = channel.state.last_completion.clone();
+
+    // Backfill recent message history from the platform
+    let backfill_count = agent.config.history_backfill_count();
+    if backfill_count > 0 {
+        match messaging_manager
+            .fetch_history(message, backfill_count)
+            .await
+        {
+            Ok(history_messages) if !history_messages.is_empty() => {
+                let mut transcript = String::new();
+                for entry in &history_messages {
+                    let label = if entry.is_bot { "(you)" } else { &entry.author };
+                    transcript.push_str(&format!("{}: {}\n", label, entry.content));
+                }
+
+                let prompt_engine = agent.deps.runtime_config.prompts.load();
+                let backfill_text = prompt_engine
+                    .render_system_history_backfill(transcript.trim_end())
+                    .unwrap_or(transcript);
+
+                let mut history = channel.state.history.write().await;
+                history.push(rig::message::Message::from(backfill_text));
+                drop(history);
+
+                tracing::info!(
+                    conversation_id = %conversation_id,
+                    message_count = history_messages.len(),
+                    "backfilled channel history"
+                );
+            }
+            Err(error) => {
+                tracing::warn!(%error, "failed to backfill channel history");
+            }
+            _ => {}
+        }
+    }
+
+    // Spawn the channel's event loop. A thread's channel first takes in the
+    // conversation it was started from; messages queue up meanwhile.
+    let thread_deps = agent.deps.clone();
+    let thread_message = message.clone();
+    tokio::spawn(async move {
+        if let Some(context) =
+            spacebot::agent::thread::parent_context(&thread_deps, &thread_message).await
+        {
+            channel
+                .state
+                .history
+                .write()
+                .await
+                .push(rig::message::Message::from(context));
+            tracing::info!(
+                conversation_id = %thread_message.conversation_id,
+                "seeded thread with parent conversation context"
+            );
+        }
+        if let Err(error) = channel.run().await {
+            tracing::error!(%error, "channel event loop failed");
+        }
+    });
+
+    // Spawn outbound response routing: reads from response_rx,
+    // sends to the messaging adapter and forwards to SSE
+    let messaging_for_outbound = messaging_manager.clone();
+    let outbox = spacebot::messaging::outbox::Outbox::new(agent.deps.sqlite_pool.clone());
+    let output_pipeline = spacebot::messaging::postprocess::OutputPipeline::new(
+        message.source.clone(),
+        binding
+            .map(|binding| binding.post_processors.clone())
+            .unwrap_or_default(),
+    )
+    .with_disclosure(binding.and_then(|binding| binding.disclosure.clone()));
+    let mut outbound_message = message.clone();
+    output_pipeline.annotate(&mut outbound_message);
+    let outbound_conversation_id = conversation_id.clone();
+    let api_event_tx = api_state.event_tx.clone();
+    let sse_agent_id = agent.id.to_string();
+    let sse_channel_id = conversation_id.clone();
+    let mut coalescer = spacebot::messaging::stream::StreamCoalescer::new(
+        messaging_manager.stream_coalescing(&message.source).await,
+    );
+    let outbound_handle = tokio::spawn(async move {
+        loop {
+            // Wake up for the streamed reply's next edit when one is due.
+            let next = match coalescer.deadline() {
+                Some(deadline) => {
+                    tokio::time::timeout_at(deadline.into(), response_rx.recv()).await
+                }
+                None => Ok(response_rx.recv().await),
+            };
+            let responses = match next {
+                Ok(Some(response)) => coalescer.push(response, std::time::Instant::now()),
+                Ok(None) => break,
+                Err(_) => coalescer.flush(std::time::Instant::now()),
+            };
+            // The completion behind the reply, for the disclosure footer.
+            let origin = last_completion.read().await.clone();
+            for response in responses
+                .into_iter()
+                .flat_map(|response| output_pipeline.apply(response, origin.as_ref()))
+            {
+                // Forward relevant events to SSE clients
+                match &response {
+                    spacebot::OutboundResponse::Text(text) => {
+                        api_event_tx
+                            .send(spacebot::api::ApiEvent::OutboundMessage {
+                                agent_id: sse_agent_id.clone(),
+                                channel_id: sse_channel_id.clone(),
+                                text: text.clone(),
+                            })
+                            .ok();
+                    }
+                    spacebot::OutboundResponse::ThreadReply { text, .. } => {
+                        api_event_tx
+                            .send(spacebot::api::ApiEvent::OutboundMessage {
+                                agent_id: sse_agent_id.clone(),
+                                channel_id: sse_channel_id.clone(),
+                                text: text.clone(),
+                            })
+                            .ok();
+                    }
+                    spacebot::OutboundResponse::Status(spacebot::StatusUpdate::Thinking) => {
+                        api_event_tx
+                            .send(spacebot::api::ApiEvent::TypingState {
+                                agent_id: sse_agent_id.clone(),
+                                channel_id: sse_channel_id.clone(),
+                                is_typing: true,
+                            })
+                            .ok();
+                    }
+                    spacebot::OutboundResponse::Status(spacebot::StatusUpdate::StopTyping) => {
+                        api_event_tx
+                            .send(spacebot::api::ApiEvent::TypingState {
+                                agent_id: sse_agent_id.clone(),
+                                channel_id: sse_channel_id.clone(),
+                                is_typing: false,
+                            })
+                            .ok();
+                    }
+                    _ => {}
+                }
+
+                match response {
+                    spacebot::OutboundResponse::Status(status) => {
+                        if let Err(error) = messaging_for_outbound
+                            .send_status(&outbound_message, status)
+                            .await
+                        {
+                            tracing::warn!(%error, "failed to send status update");
+                        }
+                    }
+                    response => {
+                        let queueable = spacebot::messaging::outbox::Outbox::accepts(&response);
+                        // Replies waiting in the outbox go first, so this one queues behind them.
+                        if queueable
+                            && outbox
+                                .has_pending(&outbound_conversation_id)
+                                .await
+                                .unwrap_or(false)
+                        {
+                            if let Err(error) =
+                                outbox.enqueue(&outbound_message, &response, None).await
+                            {
+                                tracing::error!(%error, "failed to queue outbound response");
+                            }
+                            continue;
+                        }
+
+                        tracing::info!(
+                            conversation_id = %outbound_conversation_id,
+                            "routing outbound response to messaging adapter"
+                        );
+                        let retry = queueable.then(|| response.clone());
+                        let Err(error) = messaging_for_outbound
+                            .respond(&outbound_message, response)
+                            .await
+                        else {
+                            continue;
+                        };
+                        let Some(response) = retry else {
+                            tracing::error!(%error, "failed to send outbound response");
+                            continue;
+                        };
+                        match outbox
+                            .enqueue(&outbound_message, &response, Some(&error.to_string()))
+                            .await
+                        {
+                            Ok(outbox_id) => tracing::warn!(
+                                %error,
+                                %outbox_id,
+                                "failed to send outbound response, queued for retry"
+                            ),
+                            Err(queue_error) => tracing::error!(
+                                %error,
+                                %queue_error,
+                                "failed to send outbound response"
+                            ),
+                        }
+                    }
+                }
+            }
+        }
+    });
+
+    ActiveChannel {
+        message_tx: channel_tx,
+        origin: message.clone(),
+        _outbound_handle: outbound_handle,
+    }
+}
+
 /// Initialize agents, messaging adapters, cron, cortex, and ingestion.
 /// Extracted so it can be called either at startup or after provider keys are configured.
 #[allow(clippy::too_many_arguments)]
@@ -728,6 +3278,7 @@ async fn initialize_agents(
     cron_schedulers_for_shutdown: &mut Vec<Arc<spacebot::cron::Scheduler>>,
     ingestion_handles: &mut Vec<tokio::task::JoinHandle<()>>,
     cortex_handles: &mut Vec<tokio::task::JoinHandle<()>>,
+    handoffs: &spacebot::agent::handoff::Handoffs,
     watcher_agents: &mut Vec<(
         String,
         std::path::PathBuf,
@@ -738,6 +3289,8 @@ async fn initialize_agents(
     telegram_permissions: &mut Option<Arc<ArcSwap<spacebot::config::TelegramPermissions>>>,
 ) -> anyhow::Result<()> {
     let resolved_agents = config.resolve_agents();
+    let downloads =
+        spacebot::messaging::download::AttachmentDownloader::new(llm_manager.http_client().clone());
 
     for agent_config in &resolved_agents {
         tracing::info!(agent_id = %agent_config.id, "initializing agent");
@@ -845,6 +3398,14 @@ async fn initialize_agents(
             skills,
         ));
 
+        runtime_config.reload_prompt_library(
+            spacebot::prompt_library::PromptLibrary::load(
+                &config.prompt_library_dir(),
+                &agent_config.prompt_library_dir(),
+            )
+            .await,
+        );
+
         // Set the settings store in RuntimeConfig and apply config-driven defaults
         runtime_config.set_settings(settings_store.clone());
         if let Err(error) = settings_store.set_worker_log_mode(config.defaults.worker_log_mode) {
@@ -857,6 +3418,24 @@ async fn initialize_agents(
             runtime_config.clone(),
         ));
 
+        let network = spacebot::tools::NetworkSandbox::new(runtime_config.clone());
+
+        // Per-user quotas set from chat
+        let rate_limiter = spacebot::messaging::rate_limit::RateLimiter::new();
+        match spacebot::messaging::rate_limit::QuotaStore::new(db.sqlite.clone())
+            .load()
+            .await
+        {
+            Ok(quotas) => {
+                for (user, quota) in quotas {
+                    rate_limiter.set_quota(&user, Some(quota));
+                }
+            }
+            Err(error) => {
+                tracing::warn!(%error, agent = %agent_config.id, "failed to load user quotas");
+            }
+        }
+
         let deps = spacebot::AgentDeps {
             agent_id: agent_id.clone(),
             memory_search,
@@ -865,6 +3444,15 @@ async fn initialize_agents(
             runtime_config,
             event_tx,
             sqlite_pool: db.sqlite.clone(),
+            approvals: spacebot::approval::ActionApprovals::new(),
+            rate_limiter,
+            guard_metrics: spacebot::hooks::tool_guard::GuardMetrics::new(),
+            decline_metrics: spacebot::declined::DeclineMetrics::new(),
+            budget: spacebot::budget::BudgetTracker::new(),
+            maintenance: api_state.maintenance.clone(),
+            handoffs: handoffs.clone(),
+            network,
+            downloads: downloads.clone(),
         };
 
         let agent = spacebot::Agent {
@@ -880,6 +3468,16 @@ async fn initialize_agents(
 
     tracing::info!(agent_count = agents.len(), "all agents initialized");
 
+    handoffs.set_targets(
+        agents
+            .values()
+            .map(|agent| spacebot::agent::handoff::HandoffTarget {
+                agent_id: agent.id.clone(),
+                description: agent.config.description.clone(),
+            })
+            .collect(),
+    );
+
     // Wire agent event streams, DB pools, and config summaries into the API server
     {
         let mut agent_pools = std::collections::HashMap::new();
@@ -887,6 +3485,9 @@ async fn initialize_agents(
         let mut memory_searches = std::collections::HashMap::new();
         let mut agent_workspaces = std::collections::HashMap::new();
         let mut runtime_configs = std::collections::HashMap::new();
+        let mut rate_limiters = std::collections::HashMap::new();
+        let mut guard_metrics = std::collections::HashMap::new();
+        let mut decline_metrics = std::collections::HashMap::new();
         for (agent_id, agent) in agents.iter() {
             let event_rx = agent.deps.event_tx.subscribe();
             api_state.register_agent_events(agent_id.to_string(), event_rx);
@@ -894,6 +3495,9 @@ async fn initialize_agents(
             memory_searches.insert(agent_id.to_string(), agent.deps.memory_search.clone());
             agent_workspaces.insert(agent_id.to_string(), agent.config.workspace.clone());
             runtime_configs.insert(agent_id.to_string(), agent.deps.runtime_config.clone());
+            rate_limiters.insert(agent_id.to_string(), agent.deps.rate_limiter.clone());
+            guard_metrics.insert(agent_id.to_string(), agent.deps.guard_metrics.clone());
+            decline_metrics.insert(agent_id.to_string(), agent.deps.decline_metrics.clone());
             agent_configs.push(spacebot::api::AgentInfo {
                 id: agent.config.id.clone(),
                 workspace: agent.config.workspace.clone(),
@@ -907,6 +3511,9 @@ async fn initialize_agents(
         api_state.set_agent_configs(agent_configs);
         api_state.set_memory_searches(memory_searches);
         api_state.set_runtime_configs(runtime_configs);
+        api_state.set_rate_limiters(rate_limiters);
+        api_state.set_guard_metrics(guard_metrics);
+        api_state.set_decline_metrics(decline_metrics);
         api_state.set_agent_workspaces(agent_workspaces);
     }
 
@@ -925,12 +3532,15 @@ async fn initialize_agents(
 
     if let Some(discord_config) = &config.messaging.discord {
         if discord_config.enabled {
-            let adapter = spacebot::messaging::discord::DiscordAdapter::new(
+            let mut adapter = spacebot::messaging::discord::DiscordAdapter::new(
                 &discord_config.token,
                 discord_permissions
                     .clone()
                     .expect("discord permissions initialized when discord is enabled"),
             );
+            if let Some(voice_config) = &discord_config.voice {
+                adapter = adapter.with_voice(voice_config.clone());
+            }
             new_messaging_manager.register(adapter).await;
         }
     }
@@ -976,12 +3586,37 @@ async fn initialize_agents(
         }
     }
 
+    if let Some(irc_config) = &config.messaging.irc {
+        if irc_config.enabled {
+            let adapter = spacebot::messaging::irc::IrcAdapter::new(irc_config);
+            new_messaging_manager.register(adapter).await;
+        }
+    }
+
+    if let Some(xmpp_config) = &config.messaging.xmpp {
+        if xmpp_config.enabled {
+            let adapter = spacebot::messaging::xmpp::XmppAdapter::new(xmpp_config);
+            new_messaging_manager.register(adapter).await;
+        }
+    }
+
+    if let Some(notify_config) = &config.messaging.notify {
+        if notify_config.enabled {
+            let adapter = spacebot::messaging::notify::NotifyAdapter::new(notify_config);
+            new_messaging_manager.register(adapter).await;
+        }
+    }
+
+    if let Some(twilio_config) = &config.messaging.twilio {
+        if twilio_config.enabled {
+            let adapter = spacebot::messaging::twilio::TwilioAdapter::new(twilio_config);
+            new_messaging_manager.register(adapter).await;
+        }
+    }
+
     if let Some(webhook_config) = &config.messaging.webhook {
-        if webhook_config.enabled {
-            let adapter = spacebot::messaging::webhook::WebhookAdapter::new(
-                webhook_config.port,
-                &webhook_config.bind,
-            );
+        if webhook_config.enabled || !webhook_config.outbound.is_empty() {
+            let adapter = spacebot::messaging::webhook::WebhookAdapter::new(webhook_config);
             new_messaging_manager.register(adapter).await;
         }
     }
@@ -1036,6 +3671,28 @@ async fn initialize_agents(
             store: store.clone(),
         };
 
+        // Home Assistant triggers and feed digests run through the same
+        // short-lived channels as cron jobs
+        spacebot::home_assistant::spawn_trigger_watcher(cron_context.clone());
+        spacebot::feeds::spawn_feed_watcher(
+            cron_context.clone(),
+            Arc::new(spacebot::feeds::FeedStore::new(agent.db.sqlite.clone())),
+        );
+        spacebot::digests::spawn_digest_watcher(
+            cron_context.clone(),
+            Arc::new(spacebot::digests::DigestStore::new(agent.db.sqlite.clone())),
+        );
+        spacebot::usage_anomalies::spawn_anomaly_watcher(cron_context.clone());
+        spacebot::budget::spawn_budget_watcher(cron_context.clone());
+        spacebot::agent::escalation::spawn_escalation_watcher(cron_context.clone());
+        spacebot::knowledge::spawn_knowledge_sync(
+            agent.deps.clone(),
+            Arc::new(spacebot::knowledge::KnowledgeStore::new(
+                agent.db.sqlite.clone(),
+            )),
+            agent.config.knowledge_dir(),
+        );
+
         let scheduler = Arc::new(spacebot::cron::Scheduler::new(cron_context));
 
         // Make cron store and scheduler available via RuntimeConfig
@@ -1085,6 +3742,42 @@ async fn initialize_agents(
         }
     }
 
+    // Retry each agent's undelivered replies, including ones queued before a restart
+    for (agent_id, agent) in agents.iter() {
+        let handle = spacebot::messaging::outbox::spawn_outbox_loop(
+            spacebot::messaging::outbox::Outbox::new(agent.db.sqlite.clone()),
+            messaging_manager.clone(),
+        );
+        cortex_handles.push(handle);
+        tracing::debug!(agent_id = %agent_id, "outbox loop started");
+    }
+
+    // Start conversation retention loops for each agent. Each pass checks
+    // whether retention is enabled, so a config reload can turn it on.
+    for (agent_id, agent) in agents.iter() {
+        let handle = spacebot::agent::retention::spawn_retention_loop(
+            agent.config.archives_dir.clone(),
+            agent.deps.clone(),
+        );
+        cortex_handles.push(handle);
+        tracing::debug!(agent_id = %agent_id, "conversation retention loop started");
+    }
+
+    // Snapshot the instance-wide LLM counters into the default agent's ledger
+    let snapshot_interval_secs = config.telemetry.snapshot_interval_secs;
+    if snapshot_interval_secs > 0 {
+        let default_agent_id = config.default_agent_id();
+        if let Some(agent) = agents.get(default_agent_id) {
+            let handle = spacebot::usage_snapshots::spawn_snapshot_writer(
+                llm_manager.clone(),
+                agent.db.sqlite.clone(),
+                std::time::Duration::from_secs(snapshot_interval_secs),
+            );
+            cortex_handles.push(handle);
+            tracing::debug!(agent_id = %default_agent_id, "usage snapshot writer started");
+        }
+    }
+
     // Start cortex bulletin loops and association loops for each agent
     for (agent_id, agent) in agents.iter() {
         let cortex_logger = spacebot::agent::cortex::CortexLogger::new(agent.db.sqlite.clone());
@@ -1108,12 +3801,17 @@ async fn initialize_agents(
             let conversation_logger =
                 spacebot::conversation::history::ConversationLogger::new(agent.db.sqlite.clone());
             let channel_store = spacebot::conversation::ChannelStore::new(agent.db.sqlite.clone());
+            let screenshots = spacebot::storage::ArtifactStore::new(
+                &agent.deps.runtime_config.storage.load(),
+                agent.config.screenshot_dir(),
+                &format!("{agent_id}/screenshots"),
+            );
             let tool_server = spacebot::tools::create_cortex_chat_tool_server(
                 agent.deps.memory_search.clone(),
                 conversation_logger,
                 channel_store,
                 browser_config,
-                agent.config.screenshot_dir(),
+                screenshots,
                 brave_search_key,
                 agent.deps.runtime_config.workspace_dir.clone(),
                 agent.deps.runtime_config.instance_dir.clone(),
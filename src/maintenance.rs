@@ -0,0 +1,143 @@
+//! Maintenance mode: the daemon stays up but takes no new agent turns.
+//!
+//! Used during migrations and incident response. While it's on, health
+//! checks, the API and the dashboard keep working, but inbound messages are
+//! answered with a notice instead of reaching a channel, cron jobs are
+//! skipped and cortex chat is refused. Each conversation gets the notice
+//! once per maintenance window, so a busy channel isn't flooded with it.
+//! Turns already running are left to finish.
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+
+/// Sent when maintenance was turned on without a message of its own.
+pub const DEFAULT_NOTICE: &str =
+    "I'm down for maintenance right now and can't take new requests. Please try again later.";
+
+/// Whether a new turn may start.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Admission {
+    Open,
+    Refused {
+        notice: String,
+        /// True the first time this conversation is refused in the current
+        /// window; only that one gets the notice.
+        notify: bool,
+    },
+}
+
+/// Maintenance mode as reported by the API.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct MaintenanceStatus {
+    pub enabled: bool,
+    /// The notice sent to users, when enabled.
+    pub message: Option<String>,
+    /// When maintenance was turned on.
+    pub since: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Default)]
+struct MaintenanceState {
+    status: MaintenanceStatus,
+    /// Conversations already sent the notice in this window.
+    notified: HashSet<String>,
+}
+
+/// Instance-wide maintenance switch. Clones share state.
+#[derive(Debug, Clone, Default)]
+pub struct Maintenance {
+    state: Arc<Mutex<MaintenanceState>>,
+}
+
+impl Maintenance {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Turn maintenance on, with a custom notice or the default one.
+    /// Turning it on again only replaces the notice.
+    pub fn enable(&self, message: Option<String>) {
+        let mut state = self.lock();
+        let message = message
+            .filter(|message| !message.trim().is_empty())
+            .unwrap_or_else(|| DEFAULT_NOTICE.to_string());
+        if !state.status.enabled {
+            state.status.since = Some(Utc::now());
+            state.notified.clear();
+        }
+        state.status.enabled = true;
+        state.status.message = Some(message);
+        tracing::warn!("maintenance mode on, new agent turns are refused");
+    }
+
+    pub fn disable(&self) {
+        let mut state = self.lock();
+        if state.status.enabled {
+            tracing::info!("maintenance mode off");
+        }
+        *state = MaintenanceState::default();
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.lock().status.enabled
+    }
+
+    pub fn status(&self) -> MaintenanceStatus {
+        self.lock().status.clone()
+    }
+
+    /// Check a new turn in a conversation.
+    pub fn check(&self, conversation_id: &str) -> Admission {
+        let mut state = self.lock();
+        if !state.status.enabled {
+            return Admission::Open;
+        }
+        let notify = state.notified.insert(conversation_id.to_string());
+        Admission::Refused {
+            notice: state.status.message.clone().unwrap_or_default(),
+            notify,
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, MaintenanceState> {
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_notice_once_per_conversation_per_window() {
+        let maintenance = Maintenance::new();
+        assert_eq!(maintenance.check("discord:1"), Admission::Open);
+
+        maintenance.enable(None);
+        let refused = |notify| Admission::Refused {
+            notice: DEFAULT_NOTICE.to_string(),
+            notify,
+        };
+        assert_eq!(maintenance.check("discord:1"), refused(true));
+        assert_eq!(maintenance.check("discord:1"), refused(false));
+        assert_eq!(maintenance.check("discord:2"), refused(true));
+
+        maintenance.disable();
+        assert_eq!(maintenance.check("discord:1"), Admission::Open);
+
+        maintenance.enable(Some("Upgrading, back at 14:00.".into()));
+        assert_eq!(
+            maintenance.check("discord:1"),
+            Admission::Refused {
+                notice: "Upgrading, back at 14:00.".into(),
+                notify: true,
+            }
+        );
+        assert!(maintenance.status().since.is_some());
+    }
+}
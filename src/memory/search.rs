@@ -7,6 +7,9 @@ use crate::memory::{EmbeddingModel, EmbeddingTable, MemoryStore};
 use std::collections::HashMap;
 use std::sync::Arc;
 
+/// Nearest neighbours fetched per retrieval before filtering by source.
+const RETRIEVAL_CANDIDATES: usize = 50;
+
 /// Which search strategy to use.
 #[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
 pub enum SearchMode {
@@ -142,6 +145,52 @@ impl MemorySearch {
         Ok(results)
     }
 
+    /// Vector search for automatic retrieval: the `top_k` memories most
+    /// similar to `query` with a similarity of at least `min_score`,
+    /// optionally only those saved with the given `source`.
+    ///
+    /// Scores are one minus the vector distance, as for the vector results
+    /// of hybrid search. Unlike fused RRF scores they don't depend on the
+    /// other results, so a fixed threshold means the same thing from one
+    /// query to the next.
+    pub async fn retrieve(
+        &self,
+        query: &str,
+        source: Option<&str>,
+        top_k: usize,
+        min_score: f32,
+    ) -> Result<Vec<MemorySearchResult>> {
+        let query_embedding = self.embedding_model.embed_one(query).await?;
+        // Over-fetch so filtering by source still leaves enough to choose from.
+        let candidates = self
+            .embedding_table
+            .vector_search(&query_embedding, RETRIEVAL_CANDIDATES.max(top_k))
+            .await?;
+
+        let mut results = Vec::new();
+        for (memory_id, distance) in candidates {
+            let score = 1.0 - distance;
+            if score < min_score || results.len() >= top_k {
+                break;
+            }
+            let Some(memory) = self.store.load(&memory_id).await? else {
+                continue;
+            };
+            if memory.forgotten
+                || source.is_some_and(|source| memory.source.as_deref() != Some(source))
+            {
+                continue;
+            }
+            results.push(MemorySearchResult {
+                memory,
+                score,
+                rank: results.len() + 1,
+            });
+        }
+
+        Ok(results)
+    }
+
     /// Perform hybrid search across all memory sources.
     pub async fn hybrid_search(
         &self,
@@ -404,23 +404,9 @@ impl MemoryStore {
     /// database so tests can run in parallel without migration conflicts.
     #[cfg(test)]
     pub async fn connect_in_memory() -> Arc<Self> {
-        use sqlx::sqlite::SqliteConnectOptions;
-
-        let options = SqliteConnectOptions::new()
-            .in_memory(true)
-            .create_if_missing(true);
-
-        // Single-connection pool: each pool gets its own private in-memory db.
-        let pool = sqlx::pool::PoolOptions::<sqlx::Sqlite>::new()
-            .max_connections(1)
-            .connect_with(options)
-            .await
-            .expect("in-memory SQLite");
-        sqlx::migrate!("./migrations")
-            .run(&pool)
-            .await
-            .expect("migrations");
-        Arc::new(Self { pool })
+        Arc::new(Self {
+            pool: crate::db::connect_in_memory().await,
+        })
     }
 }
 
@@ -1,11 +1,27 @@
-//! Messaging adapters (Discord, Slack, Telegram, Webhook).
+//! Messaging adapters (Discord, Slack, Telegram, IRC, XMPP, Twilio, Webhook,
+//! desktop notifications).
 
 pub mod discord;
+pub mod dedup;
+pub mod download;
+pub mod format;
+pub mod impersonation;
+pub mod intake;
+pub mod irc;
 pub mod manager;
+pub mod notify;
+pub mod outbox;
+pub mod postprocess;
+pub mod rate_limit;
+pub mod scripts;
 pub mod slack;
+pub mod stream;
 pub mod telegram;
+pub mod topics;
 pub mod traits;
+pub mod twilio;
 pub mod webhook;
+pub mod xmpp;
 
 pub use manager::MessagingManager;
 pub use traits::Messaging;
@@ -0,0 +1,200 @@
+//! Inbound deduplication for redelivered and double-sent messages.
+//!
+//! Platforms redeliver webhooks they didn't see acknowledged in time, and
+//! users tap send twice. The router checks every inbound message here before
+//! anything else, so the same trigger doesn't start two agent runs. A message
+//! is a duplicate when its platform event id was already seen in the
+//! conversation within `event_window_secs`, or when the same sender sent the
+//! same content there within `content_window_secs`. A window of 0 turns that
+//! check off.
+
+use crate::config::DedupConfig;
+use crate::{InboundMessage, MessageContent};
+
+use std::collections::HashMap;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Past this many tracked keys, expired ones are dropped.
+const PRUNE_THRESHOLD: usize = 10_000;
+
+/// Why a message was taken for a duplicate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Duplicate {
+    /// Its event id was already seen.
+    EventId,
+    /// The sender just sent the same content.
+    Content,
+}
+
+#[derive(Debug, Default)]
+struct Seen {
+    events: HashMap<String, Instant>,
+    contents: HashMap<u64, Instant>,
+}
+
+/// Recently seen inbound messages. Clones share state.
+#[derive(Debug, Clone, Default)]
+pub struct InboundDedup {
+    seen: Arc<Mutex<Seen>>,
+}
+
+impl InboundDedup {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether `message` duplicates one seen recently, recording it if not.
+    pub fn check(&self, config: &DedupConfig, message: &InboundMessage) -> Option<Duplicate> {
+        self.check_at(config, message, Instant::now())
+    }
+
+    fn check_at(
+        &self,
+        config: &DedupConfig,
+        message: &InboundMessage,
+        now: Instant,
+    ) -> Option<Duplicate> {
+        if !config.enabled {
+            return None;
+        }
+        let event_window = Duration::from_secs(config.event_window_secs);
+        let content_window = Duration::from_secs(config.content_window_secs);
+        let event_key = (!event_window.is_zero() && !message.id.is_empty()).then(|| {
+            format!(
+                "{}:{}:{}",
+                message.source, message.conversation_id, message.id
+            )
+        });
+        let content_key = (!content_window.is_zero()).then(|| content_hash(message));
+
+        let mut seen = self
+            .seen
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let recent = |at: &Instant, window: Duration| now.duration_since(*at) < window;
+
+        if event_key
+            .as_ref()
+            .and_then(|key| seen.events.get(key))
+            .is_some_and(|at| recent(at, event_window))
+        {
+            return Some(Duplicate::EventId);
+        }
+        if content_key
+            .and_then(|key| seen.contents.get(&key))
+            .is_some_and(|at| recent(at, content_window))
+        {
+            return Some(Duplicate::Content);
+        }
+
+        if seen.events.len() + seen.contents.len() >= PRUNE_THRESHOLD {
+            seen.events.retain(|_, at| recent(at, event_window));
+            seen.contents.retain(|_, at| recent(at, content_window));
+        }
+        if let Some(key) = event_key {
+            seen.events.insert(key, now);
+        }
+        if let Some(key) = content_key {
+            seen.contents.insert(key, now);
+        }
+        None
+    }
+}
+
+/// Hash of who sent what where. Attachments count, so two different images
+/// without a caption aren't mistaken for each other.
+fn content_hash(message: &InboundMessage) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    message.source.hash(&mut hasher);
+    message.conversation_id.hash(&mut hasher);
+    message.sender_id.hash(&mut hasher);
+    match &message.content {
+        MessageContent::Text(text) => text.trim().hash(&mut hasher),
+        MessageContent::Media { text, attachments } => {
+            text.as_deref().map(str::trim).hash(&mut hasher);
+            for attachment in attachments {
+                attachment.url.hash(&mut hasher);
+            }
+        }
+    }
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: DedupConfig = DedupConfig {
+        enabled: true,
+        event_window_secs: 600,
+        content_window_secs: 10,
+    };
+
+    fn message(id: &str, sender: &str, text: &str) -> InboundMessage {
+        InboundMessage {
+            id: id.into(),
+            source: "slack".into(),
+            conversation_id: "slack:T1:C1".into(),
+            sender_id: sender.into(),
+            agent_id: None,
+            content: MessageContent::Text(text.into()),
+            timestamp: chrono::Utc::now(),
+            metadata: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn test_redelivered_event_is_a_duplicate() {
+        let dedup = InboundDedup::new();
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+
+        assert_eq!(
+            dedup.check_at(&CONFIG, &message("e1", "u", "hi"), at(0)),
+            None
+        );
+        assert_eq!(
+            dedup.check_at(&CONFIG, &message("e1", "u", "hi"), at(120)),
+            Some(Duplicate::EventId)
+        );
+        assert_eq!(
+            dedup.check_at(&CONFIG, &message("e1", "u", "hi"), at(601)),
+            None
+        );
+    }
+
+    #[test]
+    fn test_double_send_is_a_duplicate_within_the_window() {
+        let dedup = InboundDedup::new();
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+
+        assert_eq!(
+            dedup.check_at(&CONFIG, &message("a", "u", "go"), at(0)),
+            None
+        );
+        assert_eq!(
+            dedup.check_at(&CONFIG, &message("b", "u", "go "), at(2)),
+            Some(Duplicate::Content)
+        );
+        assert_eq!(
+            dedup.check_at(&CONFIG, &message("c", "v", "go"), at(3)),
+            None
+        );
+        assert_eq!(
+            dedup.check_at(&CONFIG, &message("d", "u", "go"), at(15)),
+            None
+        );
+
+        let disabled = DedupConfig {
+            enabled: false,
+            ..CONFIG
+        };
+        assert_eq!(
+            dedup.check_at(&disabled, &message("d", "u", "go"), at(16)),
+            None
+        );
+    }
+}
@@ -1,17 +1,26 @@
 //! Discord messaging adapter using serenity.
 
-use crate::config::DiscordPermissions;
+mod voice;
+
+use crate::activation::MENTIONS_BOT;
+use crate::agent::thread::PARENT_CONVERSATION_ID;
+use crate::config::{DiscordPermissions, DiscordVoiceConfig};
+use crate::feedback::{Rating, reaction_metadata};
+use crate::messaging::format::{DISCORD_MAX_LENGTH, Platform, format_message};
+use crate::messaging::stream::CoalesceConfig;
 use crate::messaging::traits::{HistoryMessage, InboundStream, Messaging};
 use crate::{InboundMessage, MessageContent, OutboundResponse, StatusUpdate};
 
 use anyhow::Context as _;
 use arc_swap::ArcSwap;
 use async_trait::async_trait;
+use chrono::{DateTime, Utc};
 use serenity::all::{
     ChannelId, ChannelType, Context, CreateAttachment, CreateMessage, CreateThread, EditMessage,
-    EventHandler, GatewayIntents, GetMessages, Http, Message, MessageId, ReactionType, Ready,
-    ShardManager, User, UserId,
+    EventHandler, GatewayIntents, GetMessages, Http, Message, MessageId, Reaction, ReactionType,
+    Ready, ShardManager, User, UserId,
 };
+use songbird::SerenityInit as _;
 use std::collections::HashMap;
 use std::sync::Arc;
 use tokio::sync::{RwLock, mpsc};
@@ -27,6 +36,7 @@ pub struct DiscordAdapter {
     /// Typing handles per message. Typing stops when the handle is dropped.
     typing_tasks: Arc<RwLock<HashMap<String, serenity::http::Typing>>>,
     shard_manager: Arc<RwLock<Option<Arc<ShardManager>>>>,
+    voice: Option<Arc<voice::Voice>>,
 }
 
 impl DiscordAdapter {
@@ -39,9 +49,17 @@ impl DiscordAdapter {
             active_messages: Arc::new(RwLock::new(HashMap::new())),
             typing_tasks: Arc::new(RwLock::new(HashMap::new())),
             shard_manager: Arc::new(RwLock::new(None)),
+            voice: None,
         }
     }
 
+    /// Join voice channels, pass what's said to the bot on as messages and
+    /// speak the replies.
+    pub fn with_voice(mut self, config: DiscordVoiceConfig) -> Self {
+        self.voice = Some(Arc::new(voice::Voice::new(config)));
+        self
+    }
+
     async fn get_http(&self) -> anyhow::Result<Arc<Http>> {
         self.http
             .read()
@@ -63,6 +81,32 @@ impl DiscordAdapter {
         // Typing stops when the handle is dropped
         self.typing_tasks.write().await.remove(message_id);
     }
+
+    /// Speak text replies to messages that came in by voice. Returns the
+    /// response if it still needs sending as text: it isn't a voice reply,
+    /// or speaking it failed.
+    async fn speak_reply(
+        &self,
+        message: &InboundMessage,
+        response: OutboundResponse,
+    ) -> Option<OutboundResponse> {
+        let (Some(voice), Some(guild_id)) = (&self.voice, voice::voice_guild_id(message)) else {
+            return Some(response);
+        };
+        let text = match &response {
+            OutboundResponse::Text(text) | OutboundResponse::ThreadReply { text, .. } => text,
+            _ => return Some(response),
+        };
+
+        self.stop_typing(&message.id).await;
+        match voice.speak(guild_id, text).await {
+            Ok(()) => None,
+            Err(error) => {
+                tracing::warn!(%error, "failed to speak discord voice reply, sending text");
+                Some(response)
+            }
+        }
+    }
 }
 
 impl Messaging for DiscordAdapter {
@@ -78,17 +122,24 @@ impl Messaging for DiscordAdapter {
             permissions: self.permissions.clone(),
             http_slot: self.http.clone(),
             bot_user_id_slot: self.bot_user_id.clone(),
+            voice: self.voice.clone(),
         };
 
-        let intents = GatewayIntents::GUILD_MESSAGES
+        let mut intents = GatewayIntents::GUILD_MESSAGES
             | GatewayIntents::DIRECT_MESSAGES
             | GatewayIntents::MESSAGE_CONTENT
-            | GatewayIntents::GUILDS;
+            | GatewayIntents::GUILDS
+            | GatewayIntents::GUILD_MESSAGE_REACTIONS
+            | GatewayIntents::DIRECT_MESSAGE_REACTIONS;
+        if self.voice.is_some() {
+            intents |= GatewayIntents::GUILD_VOICE_STATES;
+        }
 
-        let mut client = serenity::Client::builder(&self.token, intents)
-            .event_handler(handler)
-            .await
-            .context("failed to build discord client")?;
+        let mut builder = serenity::Client::builder(&self.token, intents).event_handler(handler);
+        if let Some(voice) = &self.voice {
+            builder = builder.register_songbird_with(voice.manager());
+        }
+        let mut client = builder.await.context("failed to build discord client")?;
 
         *self.http.write().await = Some(client.http.clone());
         *self.shard_manager.write().await = Some(client.shard_manager.clone());
@@ -108,6 +159,11 @@ impl Messaging for DiscordAdapter {
         message: &InboundMessage,
         response: OutboundResponse,
     ) -> crate::Result<()> {
+        let response = match self.speak_reply(message, response).await {
+            Some(response) => response,
+            None => return Ok(()),
+        };
+
         let http = self.get_http().await?;
         let channel_id = self.extract_channel_id(message)?;
 
@@ -115,7 +171,7 @@ impl Messaging for DiscordAdapter {
             OutboundResponse::Text(text) => {
                 self.stop_typing(&message.id).await;
 
-                for chunk in split_message(&text, 2000) {
+                for chunk in format_message(&text, Platform::Discord) {
                     channel_id
                         .say(&*http, &chunk)
                         .await
@@ -150,7 +206,7 @@ impl Messaging for DiscordAdapter {
 
                 match thread_result {
                     Ok(thread) => {
-                        for chunk in split_message(&text, 2000) {
+                        for chunk in format_message(&text, Platform::Discord) {
                             thread
                                 .id
                                 .say(&*http, &chunk)
@@ -166,7 +222,7 @@ impl Messaging for DiscordAdapter {
                             thread_name = %thread_name,
                             "failed to create thread, falling back to regular message"
                         );
-                        for chunk in split_message(&text, 2000) {
+                        for chunk in format_message(&text, Platform::Discord) {
                             channel_id
                                 .say(&*http, &chunk)
                                 .await
@@ -226,8 +282,8 @@ impl Messaging for DiscordAdapter {
             OutboundResponse::StreamChunk(text) => {
                 let active = self.active_messages.read().await;
                 if let Some(&message_id) = active.get(&message.id) {
-                    let display_text = if text.len() > 2000 {
-                        let end = text.floor_char_boundary(1997);
+                    let display_text = if text.len() > DISCORD_MAX_LENGTH {
+                        let end = text.floor_char_boundary(DISCORD_MAX_LENGTH - 3);
                         format!("{}...", &text[..end])
                     } else {
                         text
@@ -283,7 +339,7 @@ impl Messaging for DiscordAdapter {
         );
 
         if let OutboundResponse::Text(text) = response {
-            for chunk in split_message(&text, 2000) {
+            for chunk in format_message(&text, Platform::Discord) {
                 channel_id
                     .say(&*http, &chunk)
                     .await
@@ -299,6 +355,11 @@ impl Messaging for DiscordAdapter {
         message: &InboundMessage,
         limit: usize,
     ) -> crate::Result<Vec<HistoryMessage>> {
+        // Voice messages have no message to fetch the history before.
+        if voice::is_voice_message(message) {
+            return Ok(Vec::new());
+        }
+
         let http = self.get_http().await?;
         let channel_id = self.extract_channel_id(message)?;
 
@@ -325,37 +386,7 @@ impl Messaging for DiscordAdapter {
         let history: Vec<HistoryMessage> = messages
             .iter()
             .rev()
-            .map(|message| {
-                let is_bot = bot_user_id
-                    .map(|bot_id| message.author.id == bot_id)
-                    .unwrap_or(false);
-
-                let resolved_content = resolve_mentions(&message.content, &message.mentions);
-
-                let display_name = message
-                    .author
-                    .global_name
-                    .as_deref()
-                    .unwrap_or(&message.author.name);
-
-                // Include reply-to attribution if this message is a reply
-                let author = if let Some(referenced) = &message.referenced_message {
-                    let reply_author = referenced
-                        .author
-                        .global_name
-                        .as_deref()
-                        .unwrap_or(&referenced.author.name);
-                    format!("{display_name} (replying to {reply_author})")
-                } else {
-                    display_name.to_string()
-                };
-
-                HistoryMessage {
-                    author,
-                    content: resolved_content,
-                    is_bot,
-                }
-            })
+            .map(|message| history_message(message, *bot_user_id))
             .collect();
 
         tracing::info!(
@@ -367,6 +398,62 @@ impl Messaging for DiscordAdapter {
         Ok(history)
     }
 
+    async fn fetch_channel_history(
+        &self,
+        target: &str,
+        since: DateTime<Utc>,
+        limit: usize,
+    ) -> crate::Result<Vec<HistoryMessage>> {
+        let http = self.get_http().await?;
+        let channel_id = ChannelId::new(
+            target
+                .parse::<u64>()
+                .context("invalid discord channel id for history target")?,
+        );
+        let bot_user_id = *self.bot_user_id.read().await;
+
+        // Page back from the newest message, 100 at a time, until `since`
+        let mut history = Vec::new();
+        let mut before: Option<MessageId> = None;
+        'pages: while history.len() < limit {
+            let mut builder = GetMessages::new().limit(100);
+            if let Some(before) = before {
+                builder = builder.before(before);
+            }
+            let messages = channel_id
+                .messages(&*http, builder)
+                .await
+                .context("failed to fetch discord channel history")?;
+            let Some(oldest) = messages.last() else {
+                break;
+            };
+            before = Some(oldest.id);
+
+            let page_len = messages.len();
+            for message in &messages {
+                if message.timestamp.unix_timestamp() < since.timestamp() {
+                    break 'pages;
+                }
+                history.push(history_message(message, bot_user_id));
+                if history.len() == limit {
+                    break 'pages;
+                }
+            }
+            if page_len < 100 {
+                break;
+            }
+        }
+        history.reverse();
+
+        tracing::info!(
+            count = history.len(),
+            channel_id = %channel_id,
+            "fetched discord channel history"
+        );
+
+        Ok(history)
+    }
+
     async fn health_check(&self) -> crate::Result<()> {
         let http = self.get_http().await?;
         http.get_current_user()
@@ -375,9 +462,17 @@ impl Messaging for DiscordAdapter {
         Ok(())
     }
 
+    fn stream_coalescing(&self) -> Option<CoalesceConfig> {
+        Some(CoalesceConfig::DISCORD)
+    }
+
     async fn shutdown(&self) -> crate::Result<()> {
         self.typing_tasks.write().await.clear();
 
+        if let Some(voice) = &self.voice {
+            voice.leave_all().await;
+        }
+
         if let Some(shard_manager) = self.shard_manager.read().await.as_ref() {
             shard_manager.shutdown_all().await;
         }
@@ -394,6 +489,7 @@ struct Handler {
     permissions: Arc<ArcSwap<DiscordPermissions>>,
     http_slot: Arc<RwLock<Option<Arc<Http>>>>,
     bot_user_id_slot: Arc<RwLock<Option<UserId>>>,
+    voice: Option<Arc<voice::Voice>>,
 }
 
 #[async_trait]
@@ -404,6 +500,10 @@ impl EventHandler for Handler {
         *self.http_slot.write().await = Some(ctx.http.clone());
         *self.bot_user_id_slot.write().await = Some(ready.user.id);
         tracing::info!(guild_count = ready.guilds.len(), "discord guilds available");
+
+        if let Some(voice) = &self.voice {
+            voice.join_all(&ctx, self.inbound_tx.clone()).await;
+        }
     }
 
     async fn message(&self, ctx: Context, message: Message) {
@@ -443,7 +543,17 @@ impl EventHandler for Handler {
 
         let conversation_id = build_conversation_id(&message);
         let content = extract_content(&message);
-        let metadata = build_metadata(&ctx, &message).await;
+        let mut metadata = build_metadata(&ctx, &message).await;
+        let bot_user_id = *self.bot_user_id_slot.read().await;
+        let mentions_bot = message.guild_id.is_none()
+            || bot_user_id.is_some_and(|bot_id| {
+                message.mentions.iter().any(|user| user.id == bot_id)
+                    || message
+                        .referenced_message
+                        .as_ref()
+                        .is_some_and(|referenced| referenced.author.id == bot_id)
+            });
+        metadata.insert(MENTIONS_BOT.into(), mentions_bot.into());
 
         // Channel filter: allow if the channel ID or its parent (for threads) is in the allowlist
         if let Some(guild_id) = message.guild_id {
@@ -482,6 +592,88 @@ impl EventHandler for Handler {
             );
         }
     }
+
+    /// Thumbs up/down on one of our messages becomes feedback.
+    async fn reaction_add(&self, ctx: Context, reaction: Reaction) {
+        let ReactionType::Unicode(emoji) = &reaction.emoji else {
+            return;
+        };
+        let Some(rating) = Rating::from_emoji(emoji) else {
+            return;
+        };
+        let Some(user_id) = reaction.user_id else {
+            return;
+        };
+
+        let Some(bot_user_id) = *self.bot_user_id_slot.read().await else {
+            return;
+        };
+        if user_id == bot_user_id {
+            return;
+        }
+
+        let permissions = self.permissions.load();
+        match reaction.guild_id {
+            None => {
+                if !permissions.dm_allowed_users.contains(&user_id.get()) {
+                    return;
+                }
+            }
+            Some(guild_id) => {
+                if permissions
+                    .guild_filter
+                    .as_ref()
+                    .is_some_and(|filter| !filter.contains(&guild_id.get()))
+                {
+                    return;
+                }
+            }
+        }
+
+        let message = match reaction.message(&ctx.http).await {
+            Ok(message) => message,
+            Err(error) => {
+                tracing::debug!(%error, "failed to fetch reacted discord message");
+                return;
+            }
+        };
+        if message.author.id != bot_user_id {
+            return;
+        }
+
+        let conversation_id = match reaction.guild_id {
+            Some(guild_id) => format!("discord:{}:{}", guild_id, reaction.channel_id),
+            None => format!("discord:dm:{}", user_id),
+        };
+
+        let mut metadata =
+            reaction_metadata(rating, message.id.to_string(), Some(*message.timestamp));
+        metadata.insert(
+            "discord_channel_id".into(),
+            reaction.channel_id.get().into(),
+        );
+        if let Some(guild_id) = reaction.guild_id {
+            metadata.insert("discord_guild_id".into(), guild_id.get().into());
+        }
+
+        let inbound = InboundMessage {
+            id: format!("{}:{}", message.id, user_id),
+            source: "discord".into(),
+            conversation_id,
+            sender_id: user_id.to_string(),
+            agent_id: None,
+            content: MessageContent::Text(emoji.clone()),
+            timestamp: chrono::Utc::now(),
+            metadata,
+        };
+
+        if let Err(error) = self.inbound_tx.send(inbound).await {
+            tracing::warn!(
+                %error,
+                "failed to send discord reaction (receiver dropped)"
+            );
+        }
+    }
 }
 
 // -- Helper functions --
@@ -538,6 +730,39 @@ fn resolve_mentions(content: &str, mentions: &[User]) -> String {
     resolved
 }
 
+/// A fetched message as history, with mentions resolved to names.
+fn history_message(message: &Message, bot_user_id: Option<UserId>) -> HistoryMessage {
+    let is_bot = bot_user_id
+        .map(|bot_id| message.author.id == bot_id)
+        .unwrap_or(false);
+
+    let resolved_content = resolve_mentions(&message.content, &message.mentions);
+
+    let display_name = message
+        .author
+        .global_name
+        .as_deref()
+        .unwrap_or(&message.author.name);
+
+    // Include reply-to attribution if this message is a reply
+    let author = if let Some(referenced) = &message.referenced_message {
+        let reply_author = referenced
+            .author
+            .global_name
+            .as_deref()
+            .unwrap_or(&referenced.author.name);
+        format!("{display_name} (replying to {reply_author})")
+    } else {
+        display_name.to_string()
+    };
+
+    HistoryMessage {
+        author,
+        content: resolved_content,
+        is_bot,
+    }
+}
+
 async fn build_metadata(ctx: &Context, message: &Message) -> HashMap<String, serde_json::Value> {
     let mut metadata = HashMap::new();
     metadata.insert("discord_channel_id".into(), message.channel_id.get().into());
@@ -591,6 +816,12 @@ async fn build_metadata(ctx: &Context, message: &Message) -> HashMap<String, ser
                 metadata.insert("discord_is_thread".into(), true.into());
                 if let Some(parent_id) = guild_channel.parent_id {
                     metadata.insert("discord_parent_channel_id".into(), parent_id.get().into());
+                    if let Some(guild_id) = message.guild_id {
+                        metadata.insert(
+                            PARENT_CONVERSATION_ID.into(),
+                            format!("discord:{guild_id}:{parent_id}").into(),
+                        );
+                    }
                 }
             }
         }
@@ -618,31 +849,3 @@ async fn build_metadata(ctx: &Context, message: &Message) -> HashMap<String, ser
 
     metadata
 }
-
-/// Split a message into chunks that fit within Discord's 2000 char limit.
-/// Tries to split at newlines, then spaces, then hard-cuts.
-fn split_message(text: &str, max_len: usize) -> Vec<String> {
-    if text.len() <= max_len {
-        return vec![text.to_string()];
-    }
-
-    let mut chunks = Vec::new();
-    let mut remaining = text;
-
-    while !remaining.is_empty() {
-        if remaining.len() <= max_len {
-            chunks.push(remaining.to_string());
-            break;
-        }
-
-        let split_at = remaining[..max_len]
-            .rfind('\n')
-            .or_else(|| remaining[..max_len].rfind(' '))
-            .unwrap_or(max_len);
-
-        chunks.push(remaining[..split_at].to_string());
-        remaining = remaining[split_at..].trim_start();
-    }
-
-    chunks
-}
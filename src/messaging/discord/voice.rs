@@ -0,0 +1,616 @@
+//! Discord voice channels: listen, transcribe, and answer out loud.
+//!
+//! The bot joins the configured voice channels through songbird and decodes
+//! everyone's audio to 16 kHz mono. An utterance ends after `silence_ms`
+//! without speech; it's transcribed, and if the transcript starts with an
+//! activation keyword ("Spacebot, what's on the calendar?") the rest goes to
+//! the agent as a message from the speaker. Replies to those messages are
+//! synthesized and played in the channel.
+//!
+//! In live mode the bot stays quiet instead: every utterance goes into a
+//! rolling transcript (see [`crate::speech::live`]), and when a trigger
+//! phrase comes up the agent gets the recent transcript and answers in the
+//! voice channel's text chat.
+
+use crate::config::{DiscordVoiceConfig, VoiceMode};
+use crate::messaging::format::split_message;
+use crate::speech::live::{Diarizer, LivePipeline, Trigger, Utterance};
+use crate::speech::{SAMPLE_RATE, SpeechClient, encode_wav, normalize_word};
+use crate::{InboundMessage, MessageContent};
+
+use anyhow::Context as _;
+use async_trait::async_trait;
+use serenity::all::{ChannelId, Context, GuildId, Http, UserId};
+use songbird::driver::{Channels, DecodeMode, SampleRate};
+use songbird::input::Input;
+use songbird::{CoreEvent, Event, EventContext, Songbird};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, LazyLock};
+use tokio::sync::mpsc;
+
+/// Songbird delivers audio in 20 ms ticks.
+const TICK_MS: u64 = 20;
+
+/// Utterances shorter than this are coughs and clicks, not speech.
+const MIN_UTTERANCE_SAMPLES: usize = SAMPLE_RATE as usize * 2 / 5;
+
+/// Longer utterances are cut and transcribed in pieces.
+const MAX_UTTERANCE_SAMPLES: usize = SAMPLE_RATE as usize * 30;
+
+/// Speech APIs cap the input of one synthesis request.
+const MAX_SPEECH_LENGTH: usize = 4000;
+
+/// Words before the activation keyword that are still a match ("hey", "ok").
+const MAX_LEADING_WORDS: usize = 2;
+
+/// Voice support for the Discord adapter.
+pub(super) struct Voice {
+    config: DiscordVoiceConfig,
+    manager: Arc<Songbird>,
+    speech: Arc<SpeechClient>,
+    /// Guilds with a voice connection, to leave on shutdown.
+    joined: std::sync::Mutex<HashSet<GuildId>>,
+}
+
+impl Voice {
+    pub(super) fn new(config: DiscordVoiceConfig) -> Self {
+        let songbird_config = songbird::Config::default()
+            .decode_mode(DecodeMode::Decode)
+            .decode_channels(Channels::Mono)
+            .decode_sample_rate(SampleRate::Hz16000);
+        Self {
+            speech: Arc::new(SpeechClient::new(config.speech.clone())),
+            manager: Songbird::serenity_from_config(songbird_config),
+            joined: std::sync::Mutex::new(HashSet::new()),
+            config,
+        }
+    }
+
+    pub(super) fn manager(&self) -> Arc<Songbird> {
+        self.manager.clone()
+    }
+
+    /// Join the configured voice channels and start listening.
+    pub(super) async fn join_all(&self, ctx: &Context, inbound_tx: mpsc::Sender<InboundMessage>) {
+        for channel in &self.config.channels {
+            let Ok(channel_id) = channel.parse::<u64>().map(ChannelId::new) else {
+                tracing::warn!(channel, "invalid discord voice channel id");
+                continue;
+            };
+            if let Err(error) = self.join(ctx, channel_id, inbound_tx.clone()).await {
+                tracing::error!(%error, %channel_id, "failed to join discord voice channel");
+            }
+        }
+    }
+
+    async fn join(
+        &self,
+        ctx: &Context,
+        channel_id: ChannelId,
+        inbound_tx: mpsc::Sender<InboundMessage>,
+    ) -> anyhow::Result<()> {
+        let channel = channel_id
+            .to_channel(&ctx.http)
+            .await
+            .context("failed to fetch voice channel")?
+            .guild()
+            .context("not a server voice channel")?;
+        let guild_id = channel.guild_id;
+
+        let call = self
+            .manager
+            .join(guild_id, channel_id)
+            .await
+            .context("failed to connect")?;
+        self.joined
+            .lock()
+            .expect("voice guild lock poisoned")
+            .insert(guild_id);
+
+        let voice_channel = VoiceChannel {
+            http: ctx.http.clone(),
+            guild_id,
+            channel_id,
+            channel_name: channel.name.clone(),
+            inbound_tx,
+        };
+        let sink = match self.config.mode {
+            VoiceMode::Conversation => {
+                let (utterance_tx, utterance_rx) = mpsc::channel(16);
+                let transcriber = Transcriber {
+                    voice_channel,
+                    speech: self.speech.clone(),
+                    activation_keywords: self.config.activation_keywords.clone(),
+                };
+                tokio::spawn(transcriber.run(utterance_rx));
+                Sink::Conversation(utterance_tx)
+            }
+            VoiceMode::Live => {
+                let names = MemberNames {
+                    http: ctx.http.clone(),
+                    guild_id,
+                    cache: tokio::sync::Mutex::new(HashMap::new()),
+                };
+                let (pipeline, triggers) = LivePipeline::spawn(
+                    self.speech.clone(),
+                    &self.config.live,
+                    Some(Arc::new(names)),
+                );
+                tokio::spawn(voice_channel.relay_triggers(triggers));
+                Sink::Live(Arc::new(pipeline))
+            }
+        };
+
+        let listener = Listener {
+            state: Arc::new(std::sync::Mutex::new(ListenerState {
+                speakers: HashMap::new(),
+                segmenter: Segmenter::new(self.config.silence_ms),
+            })),
+            sink,
+        };
+        {
+            let mut call = call.lock().await;
+            // The gateway may hand us `ready` again after a reconnect.
+            call.remove_all_global_events();
+            call.add_global_event(
+                Event::Core(CoreEvent::SpeakingStateUpdate),
+                listener.clone(),
+            );
+            call.add_global_event(Event::Core(CoreEvent::VoiceTick), listener);
+        }
+
+        tracing::info!(
+            %guild_id,
+            %channel_id,
+            channel = %channel.name,
+            mode = ?self.config.mode,
+            "joined discord voice channel"
+        );
+        Ok(())
+    }
+
+    /// Speak `text` in the guild's voice channel. Replies are queued, so
+    /// they never talk over each other.
+    pub(super) async fn speak(&self, guild_id: GuildId, text: &str) -> anyhow::Result<()> {
+        let call = self
+            .manager
+            .get(guild_id)
+            .context("not connected to a voice channel in this server")?;
+
+        let text = spoken_text(text);
+        if text.is_empty() {
+            return Ok(());
+        }
+        for chunk in split_message(&text, MAX_SPEECH_LENGTH) {
+            let audio = self.speech.synthesize(&chunk).await?;
+            call.lock().await.enqueue_input(Input::from(audio)).await;
+        }
+        Ok(())
+    }
+
+    pub(super) async fn leave_all(&self) {
+        let guilds: Vec<GuildId> = self
+            .joined
+            .lock()
+            .expect("voice guild lock poisoned")
+            .drain()
+            .collect();
+        for guild_id in guilds {
+            if let Err(error) = self.manager.remove(guild_id).await {
+                tracing::warn!(%error, %guild_id, "failed to leave discord voice channel");
+            }
+        }
+    }
+}
+
+/// Whether a message came from a voice channel, spoken or transcribed.
+pub(super) fn is_voice_message(message: &InboundMessage) -> bool {
+    ["discord_voice", "discord_voice_trigger"]
+        .iter()
+        .any(|key| message.metadata.contains_key(*key))
+}
+
+/// The guild of a message that was spoken to the bot, if it was. Replies
+/// to these are spoken too.
+pub(super) fn voice_guild_id(message: &InboundMessage) -> Option<GuildId> {
+    if message
+        .metadata
+        .get("discord_voice")
+        .and_then(|v| v.as_bool())
+        != Some(true)
+    {
+        return None;
+    }
+    message
+        .metadata
+        .get("discord_guild_id")
+        .and_then(|v| v.as_u64())
+        .map(GuildId::new)
+}
+
+// -- Receiving --
+
+struct ListenerState {
+    /// Voice stream SSRC to the user speaking on it.
+    speakers: HashMap<u32, UserId>,
+    segmenter: Segmenter,
+}
+
+/// Where finished utterances go.
+#[derive(Clone)]
+enum Sink {
+    /// To the transcriber, which checks for activation keywords.
+    Conversation(mpsc::Sender<(UserId, Vec<i16>)>),
+    /// Into the rolling transcript.
+    Live(Arc<LivePipeline>),
+}
+
+/// Songbird event handler for one call. Runs on the voice driver, so it
+/// only buffers audio and hands finished utterances on.
+#[derive(Clone)]
+struct Listener {
+    state: Arc<std::sync::Mutex<ListenerState>>,
+    sink: Sink,
+}
+
+#[async_trait]
+impl songbird::EventHandler for Listener {
+    async fn act(&self, ctx: &EventContext<'_>) -> Option<Event> {
+        match ctx {
+            EventContext::SpeakingStateUpdate(speaking) => {
+                if let Some(user_id) = speaking.user_id {
+                    let mut state = self.state.lock().expect("voice listener lock poisoned");
+                    state
+                        .speakers
+                        .insert(speaking.ssrc, UserId::from(user_id.0));
+                }
+            }
+            EventContext::VoiceTick(tick) => {
+                let mut state = self.state.lock().expect("voice listener lock poisoned");
+                let audio = tick.speaking.iter().filter_map(|(ssrc, data)| {
+                    data.decoded_voice
+                        .as_deref()
+                        .map(|samples| (*ssrc, samples))
+                });
+                let finished = state.segmenter.tick(audio);
+                for (ssrc, samples) in finished {
+                    let Some(&user_id) = state.speakers.get(&ssrc) else {
+                        continue;
+                    };
+                    match &self.sink {
+                        Sink::Conversation(utterance_tx) => {
+                            if utterance_tx.try_send((user_id, samples)).is_err() {
+                                tracing::warn!(
+                                    "voice transcription is falling behind, dropping utterance"
+                                );
+                            }
+                        }
+                        Sink::Live(pipeline) => {
+                            let duration_ms = samples.len() as i64 * 1000 / SAMPLE_RATE as i64;
+                            pipeline.push(Utterance {
+                                speaker_id: Some(user_id.to_string()),
+                                samples,
+                                started_at: chrono::Utc::now()
+                                    - chrono::Duration::milliseconds(duration_ms),
+                            });
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+        None
+    }
+}
+
+/// Splits each speaker's audio into utterances at pauses.
+struct Segmenter {
+    silence_ticks: u32,
+    /// Audio so far and consecutive silent ticks, per SSRC.
+    open: HashMap<u32, (Vec<i16>, u32)>,
+}
+
+impl Segmenter {
+    fn new(silence_ms: u64) -> Self {
+        Self {
+            silence_ticks: (silence_ms / TICK_MS).max(1) as u32,
+            open: HashMap::new(),
+        }
+    }
+
+    /// Add one tick of audio and return the utterances it finished.
+    fn tick<'a>(&mut self, audio: impl Iterator<Item = (u32, &'a [i16])>) -> Vec<(u32, Vec<i16>)> {
+        let mut heard = Vec::new();
+        for (ssrc, samples) in audio {
+            let (buffer, silent) = self.open.entry(ssrc).or_default();
+            buffer.extend_from_slice(samples);
+            *silent = 0;
+            heard.push(ssrc);
+        }
+
+        let mut finished = Vec::new();
+        let silence_ticks = self.silence_ticks;
+        self.open.retain(|ssrc, (buffer, silent)| {
+            if !heard.contains(ssrc) {
+                *silent += 1;
+            }
+            if *silent < silence_ticks && buffer.len() < MAX_UTTERANCE_SAMPLES {
+                return true;
+            }
+            if buffer.len() >= MIN_UTTERANCE_SAMPLES {
+                finished.push((*ssrc, std::mem::take(buffer)));
+            }
+            false
+        });
+        finished
+    }
+}
+
+/// A joined voice channel, as a source of inbound messages.
+struct VoiceChannel {
+    http: Arc<Http>,
+    guild_id: GuildId,
+    channel_id: ChannelId,
+    channel_name: String,
+    inbound_tx: mpsc::Sender<InboundMessage>,
+}
+
+impl VoiceChannel {
+    /// Pass `text` to the agent as a message from `user_id`. Other bots in
+    /// the channel are ignored.
+    async fn send(
+        &self,
+        user_id: UserId,
+        text: String,
+        mut metadata: HashMap<String, serde_json::Value>,
+    ) -> anyhow::Result<()> {
+        let member = self
+            .guild_id
+            .member(&self.http, user_id)
+            .await
+            .context("failed to fetch speaking member")?;
+        if member.user.bot {
+            return Ok(());
+        }
+
+        metadata.insert("discord_guild_id".into(), self.guild_id.get().into());
+        metadata.insert("discord_channel_id".into(), self.channel_id.get().into());
+        metadata.insert(
+            "discord_channel_name".into(),
+            self.channel_name.clone().into(),
+        );
+        metadata.insert(
+            "discord_author_name".into(),
+            member.user.name.clone().into(),
+        );
+        metadata.insert(
+            "sender_display_name".into(),
+            member.display_name().to_string().into(),
+        );
+        metadata.insert("sender_id".into(), user_id.get().into());
+
+        let inbound = InboundMessage {
+            id: uuid::Uuid::new_v4().to_string(),
+            source: "discord".into(),
+            conversation_id: format!("discord:{}:{}", self.guild_id, self.channel_id),
+            sender_id: user_id.to_string(),
+            agent_id: None,
+            content: MessageContent::Text(text),
+            timestamp: chrono::Utc::now(),
+            metadata,
+        };
+
+        self.inbound_tx
+            .send(inbound)
+            .await
+            .context("inbound receiver dropped")?;
+        Ok(())
+    }
+
+    /// Wake the agent for each trigger from the live transcript. The reply
+    /// goes to the voice channel's text chat.
+    async fn relay_triggers(self, mut triggers: mpsc::Receiver<Trigger>) {
+        while let Some(trigger) = triggers.recv().await {
+            let Some(user_id) = trigger
+                .segment
+                .speaker_id
+                .as_deref()
+                .and_then(|id| id.parse::<u64>().ok())
+                .map(UserId::new)
+            else {
+                continue;
+            };
+            tracing::debug!(phrase = %trigger.phrase, %user_id, "live transcript trigger");
+
+            let text = format!(
+                "\"{}\" came up in the {} voice channel. Recent transcript:\n\n{}",
+                trigger.phrase, self.channel_name, trigger.transcript
+            );
+            let metadata = HashMap::from([("discord_voice_trigger".into(), trigger.phrase.into())]);
+            if let Err(error) = self.send(user_id, text, metadata).await {
+                tracing::warn!(
+                    %error,
+                    channel_id = %self.channel_id,
+                    "failed to relay live transcript trigger"
+                );
+            }
+        }
+    }
+}
+
+/// Transcribes utterances from one voice channel and passes the ones
+/// addressed to the bot on as messages.
+struct Transcriber {
+    voice_channel: VoiceChannel,
+    speech: Arc<SpeechClient>,
+    activation_keywords: Vec<String>,
+}
+
+impl Transcriber {
+    async fn run(self, mut utterance_rx: mpsc::Receiver<(UserId, Vec<i16>)>) {
+        while let Some((user_id, samples)) = utterance_rx.recv().await {
+            if let Err(error) = self.handle(user_id, samples).await {
+                tracing::warn!(
+                    %error,
+                    channel_id = %self.voice_channel.channel_id,
+                    "failed to handle voice utterance"
+                );
+            }
+        }
+    }
+
+    async fn handle(&self, user_id: UserId, samples: Vec<i16>) -> anyhow::Result<()> {
+        let transcript = self
+            .speech
+            .transcribe(encode_wav(&samples, SAMPLE_RATE))
+            .await?;
+        let Some(text) = activation(&transcript, &self.activation_keywords) else {
+            tracing::trace!(%user_id, transcript, "voice utterance not addressed to the bot");
+            return Ok(());
+        };
+
+        let metadata = HashMap::from([("discord_voice".into(), true.into())]);
+        self.voice_channel.send(user_id, text, metadata).await
+    }
+}
+
+/// Names live transcript speakers after their server display names.
+struct MemberNames {
+    http: Arc<Http>,
+    guild_id: GuildId,
+    cache: tokio::sync::Mutex<HashMap<String, String>>,
+}
+
+#[async_trait]
+impl Diarizer for MemberNames {
+    async fn speaker(&self, utterance: &Utterance) -> Option<String> {
+        let speaker_id = utterance.speaker_id.as_ref()?;
+        let mut cache = self.cache.lock().await;
+        if let Some(name) = cache.get(speaker_id) {
+            return Some(name.clone());
+        }
+
+        let user_id = UserId::new(speaker_id.parse().ok()?);
+        let member = self.guild_id.member(&self.http, user_id).await.ok()?;
+        let name = member.display_name().to_string();
+        cache.insert(speaker_id.clone(), name.clone());
+        Some(name)
+    }
+}
+
+/// The request in a transcript that starts with an activation keyword, with
+/// the keyword removed. A couple of words may come before the keyword
+/// ("hey spacebot"). With no keywords configured, every transcript counts.
+fn activation(transcript: &str, keywords: &[String]) -> Option<String> {
+    let words: Vec<&str> = transcript.split_whitespace().collect();
+    if keywords.is_empty() {
+        return (!words.is_empty()).then(|| words.join(" "));
+    }
+    let normalized: Vec<String> = words.iter().map(|word| normalize_word(word)).collect();
+
+    for keyword in keywords {
+        let keyword: Vec<String> = keyword
+            .split_whitespace()
+            .map(normalize_word)
+            .filter(|word| !word.is_empty())
+            .collect();
+        if keyword.is_empty() {
+            continue;
+        }
+        for start in 0..=MAX_LEADING_WORDS {
+            let end = start + keyword.len();
+            if end > normalized.len() {
+                break;
+            }
+            if normalized[start..end] != keyword[..] {
+                continue;
+            }
+            let request = words[end..].join(" ");
+            let request = request.trim_start_matches(|c: char| !c.is_alphanumeric());
+            return (!request.is_empty()).then(|| request.to_string());
+        }
+    }
+    None
+}
+
+static MD_CODE_BLOCK: LazyLock<regex::Regex> =
+    LazyLock::new(|| regex::Regex::new(r"(?s)```.*?(```|$)").expect("valid regex"));
+static MD_LINK: LazyLock<regex::Regex> =
+    LazyLock::new(|| regex::Regex::new(r"\[([^\]]+)\]\([^)]+\)").expect("valid regex"));
+
+/// Text for speech synthesis: code blocks and link targets don't read
+/// well out loud, and markup characters would be read literally.
+fn spoken_text(text: &str) -> String {
+    let text = MD_CODE_BLOCK.replace_all(text, "");
+    let text = MD_LINK.replace_all(&text, "$1");
+    text.chars()
+        .filter(|c| !matches!(c, '*' | '_' | '`' | '#' | '|' | '>'))
+        .collect::<String>()
+        .trim()
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_activation_keywords() {
+        let keywords = vec!["spacebot".to_string(), "hey computer".to_string()];
+
+        assert_eq!(
+            activation("Spacebot, what's on the calendar?", &keywords).as_deref(),
+            Some("what's on the calendar?")
+        );
+        assert_eq!(
+            activation("Hey computer. Turn off the lights.", &keywords).as_deref(),
+            Some("Turn off the lights.")
+        );
+        assert_eq!(
+            activation("OK so Spacebot, ping me at five", &keywords).as_deref(),
+            Some("ping me at five")
+        );
+        // Too far into the sentence to be addressing the bot
+        assert_eq!(
+            activation("I think that spacebot is great", &keywords),
+            None
+        );
+        assert_eq!(activation("Spacebot.", &keywords), None);
+        assert_eq!(
+            activation("anything at all", &[]).as_deref(),
+            Some("anything at all")
+        );
+    }
+
+    #[test]
+    fn test_segmenter_splits_on_silence() {
+        let mut segmenter = Segmenter::new(60);
+        let speech = vec![1i16; 320];
+
+        // A long enough utterance from SSRC 1, then three silent ticks
+        for _ in 0..25 {
+            assert!(segmenter.tick([(1, &speech[..])].into_iter()).is_empty());
+        }
+        assert!(segmenter.tick(std::iter::empty()).is_empty());
+        assert!(segmenter.tick(std::iter::empty()).is_empty());
+        let finished = segmenter.tick(std::iter::empty());
+        assert_eq!(finished.len(), 1);
+        assert_eq!(finished[0].0, 1);
+        assert_eq!(finished[0].1.len(), 25 * 320);
+
+        // A click is dropped
+        segmenter.tick([(2, &speech[..])].into_iter());
+        for _ in 0..3 {
+            assert!(segmenter.tick(std::iter::empty()).is_empty());
+        }
+        assert!(segmenter.open.is_empty());
+    }
+
+    #[test]
+    fn test_spoken_text() {
+        assert_eq!(
+            spoken_text("**Done.** See [the docs](https://example.com).\n```\nls\n```"),
+            "Done. See the docs."
+        );
+    }
+}
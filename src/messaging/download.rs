@@ -0,0 +1,311 @@
+//! Shared downloader for inbound attachment URLs.
+//!
+//! Adapters only put an attachment's URL on the inbound message; the channel
+//! fetches it before handing the model an image or inlined text. Every fetch
+//! goes through [`AttachmentDownloader`], which refuses files over
+//! [`MAX_ATTACHMENT_BYTES`] without reading them, retries transient failures,
+//! trusts the file's magic bytes over the MIME type the platform declared, and
+//! keeps recent downloads so a message that's replayed or retriggered isn't
+//! fetched again.
+
+use crate::Attachment;
+
+use reqwest::StatusCode;
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Largest attachment we download: 20 MB.
+pub const MAX_ATTACHMENT_BYTES: u64 = 20 * 1024 * 1024;
+
+/// Attempts per download, the first included.
+const MAX_ATTEMPTS: u32 = 3;
+
+/// Delay before the first retry, doubled for each one after.
+const RETRY_BASE_DELAY: Duration = Duration::from_millis(500);
+
+/// Bytes of downloads kept in memory across all URLs.
+const CACHE_BYTES: usize = 64 * 1024 * 1024;
+
+/// How long a download is reused for its URL.
+const CACHE_TTL: Duration = Duration::from_secs(10 * 60);
+
+/// A downloaded attachment.
+#[derive(Debug, Clone)]
+pub struct Download {
+    pub data: Arc<[u8]>,
+    /// The sniffed type where the bytes say, otherwise the declared one.
+    pub mime_type: String,
+}
+
+/// Why an attachment couldn't be downloaded.
+#[derive(Debug, Clone, thiserror::Error)]
+pub enum DownloadError {
+    #[error("file is too large ({size} bytes, max {MAX_ATTACHMENT_BYTES} bytes)")]
+    TooLarge { size: u64 },
+
+    #[error("HTTP {0}")]
+    Status(StatusCode),
+
+    #[error("request failed: {0}")]
+    Request(String),
+}
+
+impl DownloadError {
+    /// Whether trying again might succeed.
+    fn is_transient(&self) -> bool {
+        match self {
+            Self::TooLarge { .. } => false,
+            Self::Status(status) => {
+                status.is_server_error() || *status == StatusCode::TOO_MANY_REQUESTS
+            }
+            Self::Request(_) => true,
+        }
+    }
+}
+
+/// Fetches attachment URLs for every channel of an instance.
+#[derive(Clone)]
+pub struct AttachmentDownloader {
+    http: reqwest::Client,
+    cache: Arc<Mutex<DownloadCache>>,
+}
+
+impl AttachmentDownloader {
+    pub fn new(http: reqwest::Client) -> Self {
+        Self {
+            http,
+            cache: Arc::new(Mutex::new(DownloadCache::new(CACHE_BYTES))),
+        }
+    }
+
+    /// Download `attachment`, from the cache when it was fetched recently.
+    pub async fn fetch(&self, attachment: &Attachment) -> Result<Download, DownloadError> {
+        if let Some(size) = attachment
+            .size_bytes
+            .filter(|size| *size > MAX_ATTACHMENT_BYTES)
+        {
+            return Err(DownloadError::TooLarge { size });
+        }
+        if let Some(download) = self.lock().get(&attachment.url, Instant::now()) {
+            return Ok(download);
+        }
+
+        let mut attempt = 0;
+        let data = loop {
+            let error = match self.fetch_once(&attachment.url).await {
+                Ok(data) => break data,
+                Err(error) => error,
+            };
+            attempt += 1;
+            if !error.is_transient() || attempt >= MAX_ATTEMPTS {
+                return Err(error);
+            }
+            let delay = RETRY_BASE_DELAY * 2u32.pow(attempt - 1);
+            tracing::warn!(
+                %error,
+                filename = %attachment.filename,
+                ?delay,
+                "retrying attachment download"
+            );
+            tokio::time::sleep(delay).await;
+        };
+
+        let download = Download {
+            mime_type: resolve_mime(&attachment.mime_type, &data),
+            data: data.into(),
+        };
+        self.lock()
+            .insert(attachment.url.clone(), download.clone(), Instant::now());
+        Ok(download)
+    }
+
+    async fn fetch_once(&self, url: &str) -> Result<Vec<u8>, DownloadError> {
+        let request_error = |error: reqwest::Error| DownloadError::Request(error.to_string());
+
+        let mut response = self.http.get(url).send().await.map_err(request_error)?;
+        if !response.status().is_success() {
+            return Err(DownloadError::Status(response.status()));
+        }
+        if let Some(size) = response
+            .content_length()
+            .filter(|size| *size > MAX_ATTACHMENT_BYTES)
+        {
+            return Err(DownloadError::TooLarge { size });
+        }
+
+        // Content-Length can be missing or wrong, so count while reading too.
+        let mut data = Vec::new();
+        while let Some(chunk) = response.chunk().await.map_err(request_error)? {
+            data.extend_from_slice(&chunk);
+            if data.len() as u64 > MAX_ATTACHMENT_BYTES {
+                return Err(DownloadError::TooLarge {
+                    size: data.len() as u64,
+                });
+            }
+        }
+        Ok(data)
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, DownloadCache> {
+        self.cache
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// Recent downloads by URL, bounded by total size and age.
+struct DownloadCache {
+    entries: HashMap<String, (Download, Instant)>,
+    bytes: usize,
+    max_bytes: usize,
+}
+
+impl DownloadCache {
+    fn new(max_bytes: usize) -> Self {
+        Self {
+            entries: HashMap::new(),
+            bytes: 0,
+            max_bytes,
+        }
+    }
+
+    fn get(&mut self, url: &str, now: Instant) -> Option<Download> {
+        let (download, fetched_at) = self.entries.get(url)?;
+        if now.duration_since(*fetched_at) < CACHE_TTL {
+            return Some(download.clone());
+        }
+        self.remove(url);
+        None
+    }
+
+    fn insert(&mut self, url: String, download: Download, now: Instant) {
+        // One file shouldn't push out everything else.
+        if download.data.len() > self.max_bytes / 4 {
+            return;
+        }
+        self.remove(&url);
+        self.bytes += download.data.len();
+        self.entries.insert(url, (download, now));
+
+        while self.bytes > self.max_bytes {
+            let Some(oldest) = self
+                .entries
+                .iter()
+                .min_by_key(|(_, (_, fetched_at))| *fetched_at)
+                .map(|(url, _)| url.clone())
+            else {
+                break;
+            };
+            self.remove(&oldest);
+        }
+    }
+
+    fn remove(&mut self, url: &str) {
+        if let Some((download, _)) = self.entries.remove(url) {
+            self.bytes -= download.data.len();
+        }
+    }
+}
+
+/// The type of `data`: what its magic bytes say, then the declared type,
+/// then plain text for undeclared UTF-8.
+pub fn resolve_mime(declared: &str, data: &[u8]) -> String {
+    if let Some(sniffed) = sniff_mime(data) {
+        return sniffed.into();
+    }
+    let declared = declared.trim();
+    if !declared.is_empty() && declared != "application/octet-stream" {
+        return declared.into();
+    }
+    if !data.contains(&0) && std::str::from_utf8(data).is_ok() {
+        return "text/plain".into();
+    }
+    "application/octet-stream".into()
+}
+
+/// The type of the common binary formats, recognized by their signature.
+fn sniff_mime(data: &[u8]) -> Option<&'static str> {
+    let signatures: &[(&[u8], &str)] = &[
+        (b"\x89PNG\r\n\x1a\n", "image/png"),
+        (b"\xff\xd8\xff", "image/jpeg"),
+        (b"GIF87a", "image/gif"),
+        (b"GIF89a", "image/gif"),
+        (b"%PDF-", "application/pdf"),
+        (b"OggS", "audio/ogg"),
+        (b"ID3", "audio/mpeg"),
+        (b"fLaC", "audio/flac"),
+        (b"\x1a\x45\xdf\xa3", "video/webm"),
+    ];
+    if let Some((_, mime_type)) = signatures
+        .iter()
+        .find(|(signature, _)| data.starts_with(signature))
+    {
+        return Some(mime_type);
+    }
+    if data.len() >= 12 && data.starts_with(b"RIFF") {
+        match &data[8..12] {
+            b"WEBP" => return Some("image/webp"),
+            b"WAVE" => return Some("audio/wav"),
+            _ => {}
+        }
+    }
+    if data.len() >= 12 && &data[4..8] == b"ftyp" {
+        return Some(match &data[8..12] {
+            b"M4A " => "audio/mp4",
+            b"qt  " => "video/quicktime",
+            _ => "video/mp4",
+        });
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn download(size: usize) -> Download {
+        Download {
+            data: vec![0; size].into(),
+            mime_type: "application/octet-stream".into(),
+        }
+    }
+
+    #[test]
+    fn test_resolve_mime_prefers_magic_bytes() {
+        let png = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR";
+        assert_eq!(resolve_mime("image/jpeg", png), "image/png");
+        assert_eq!(resolve_mime("", b"RIFF\0\0\0\0WEBPVP8 "), "image/webp");
+        assert_eq!(resolve_mime("application/json", b"{}"), "application/json");
+        assert_eq!(
+            resolve_mime("application/octet-stream", b"hello"),
+            "text/plain"
+        );
+        assert_eq!(resolve_mime("", b"\0\x01\x02"), "application/octet-stream");
+    }
+
+    #[test]
+    fn test_cache_evicts_oldest_and_expired() {
+        let start = Instant::now();
+        let mut cache = DownloadCache::new(100);
+        cache.insert("a".into(), download(20), start);
+        cache.insert("b".into(), download(20), start + Duration::from_secs(1));
+        cache.insert("huge".into(), download(30), start);
+        assert!(cache.get("huge", start).is_none());
+
+        for (index, url) in ["c", "d", "e", "f"].into_iter().enumerate() {
+            cache.insert(
+                url.into(),
+                download(20),
+                start + Duration::from_secs(2 + index as u64),
+            );
+        }
+        assert_eq!(cache.bytes, 100);
+        assert!(cache.get("a", start).is_none());
+        assert!(cache.get("b", start).is_some());
+
+        assert!(cache.get("b", start + CACHE_TTL * 2).is_none());
+        assert_eq!(cache.bytes, 80);
+    }
+}
@@ -0,0 +1,585 @@
+//! Platform formatting: message limits, chunking, markdown dialects and
+//! table rendering.
+//!
+//! Adapters run every outbound text through [`format_message`] (or
+//! [`slack_messages`]) before sending. Chunks never break a fenced code
+//! block: a block that spans chunks is closed at the end of one and
+//! reopened, with its language, at the start of the next. Lengths are
+//! measured in bytes, which never undercounts the characters or UTF-16
+//! units the platforms actually limit on.
+
+use crate::citations::{Citation, CitationOrigin};
+
+use regex::Regex;
+
+use std::sync::LazyLock;
+
+/// Discord's per-message character limit.
+pub const DISCORD_MAX_LENGTH: usize = 2000;
+
+/// Telegram's per-message character limit.
+pub const TELEGRAM_MAX_LENGTH: usize = 4096;
+
+/// Text per IRC line. Lines are capped at 512 bytes, which also holds the
+/// command, the target and the prefix the server adds when relaying.
+pub const IRC_MAX_LENGTH: usize = 400;
+
+/// Longest XMPP message we send. The protocol has no limit of its own, but
+/// servers cap stanza sizes and long messages are hard to read in clients.
+pub const XMPP_MAX_LENGTH: usize = 4000;
+
+/// Twilio's limit on the body of one SMS or WhatsApp message.
+pub const SMS_MAX_LENGTH: usize = 1600;
+
+/// Slack's limit on the text of a single section block.
+pub const SLACK_SECTION_MAX_LENGTH: usize = 3000;
+
+/// Section blocks per Slack message. Keeps the notification fallback text,
+/// which repeats the blocks, well under Slack's 40k character cap.
+pub const SLACK_MAX_BLOCKS: usize = 10;
+
+const FENCE: &str = "```";
+
+/// A messaging platform with its own limits and markdown dialect.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Platform {
+    Discord,
+    Slack,
+    Telegram,
+    Irc,
+    Xmpp,
+    /// SMS and WhatsApp through Twilio.
+    Sms,
+}
+
+impl Platform {
+    /// Look up a platform by adapter name. Returns `None` for adapters
+    /// (like the webhook) that take text as-is.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "discord" => Some(Self::Discord),
+            "slack" => Some(Self::Slack),
+            "telegram" => Some(Self::Telegram),
+            "irc" => Some(Self::Irc),
+            "xmpp" => Some(Self::Xmpp),
+            "twilio" => Some(Self::Sms),
+            _ => None,
+        }
+    }
+
+    /// The platform a conversation is on, from its `platform:` prefix.
+    pub fn from_conversation_id(conversation_id: &str) -> Option<Self> {
+        conversation_id.split(':').next().and_then(Self::from_name)
+    }
+
+    /// The longest chunk that can be sent as one message (or, on Slack, one
+    /// section block).
+    pub fn max_length(self) -> usize {
+        match self {
+            Self::Discord => DISCORD_MAX_LENGTH,
+            Self::Slack => SLACK_SECTION_MAX_LENGTH,
+            Self::Telegram => TELEGRAM_MAX_LENGTH,
+            Self::Irc => IRC_MAX_LENGTH,
+            Self::Xmpp => XMPP_MAX_LENGTH,
+            Self::Sms => SMS_MAX_LENGTH,
+        }
+    }
+}
+
+/// Render tables for the platform and split the result into sendable chunks.
+pub fn format_message(text: &str, platform: Platform) -> Vec<String> {
+    split_message(&render_tables(text, platform), platform.max_length())
+}
+
+/// Format text for Slack: one inner `Vec` per message, one string per
+/// section block.
+pub fn slack_messages(text: &str) -> Vec<Vec<String>> {
+    format_message(text, Platform::Slack)
+        .chunks(SLACK_MAX_BLOCKS)
+        .map(<[String]>::to_vec)
+        .collect()
+}
+
+/// Split text into chunks of at most `max_len` bytes.
+///
+/// Prefers paragraph breaks, then line breaks, then sentence ends, then
+/// spaces, and only hard-cuts a word when nothing else fits.
+pub fn split_message(text: &str, max_len: usize) -> Vec<String> {
+    if text.len() <= max_len {
+        return vec![text.to_string()];
+    }
+
+    let mut chunks = Vec::new();
+    let mut remaining = text;
+    // Opening line of the fence the previous chunk ended inside, if any.
+    let mut open_fence: Option<String> = None;
+
+    while !remaining.is_empty() {
+        let prefix = open_fence
+            .as_ref()
+            .map(|fence| format!("{fence}\n"))
+            .unwrap_or_default();
+
+        if prefix.len() + remaining.len() <= max_len {
+            chunks.push(prefix + remaining);
+            break;
+        }
+
+        // Leave room to close a fence the chunk might end inside.
+        let reserve = if open_fence.is_some() || remaining.contains(FENCE) {
+            FENCE.len() + 1
+        } else {
+            0
+        };
+        let budget = max_len.saturating_sub(prefix.len() + reserve);
+        let window = &remaining[..remaining.floor_char_boundary(budget)];
+        // A hard cut always takes at least one character so the loop
+        // makes progress.
+        let split_at = find_split(window, open_fence.is_some())
+            .unwrap_or_else(|| remaining.ceil_char_boundary(window.len().max(1)));
+
+        let body = &remaining[..split_at];
+        open_fence = fence_state_after(body, open_fence);
+
+        let mut chunk = prefix + body;
+        if open_fence.is_some() {
+            if !chunk.ends_with('\n') {
+                chunk.push('\n');
+            }
+            chunk.push_str(FENCE);
+        }
+        chunks.push(chunk);
+
+        remaining = &remaining[split_at..];
+        // Indentation inside a code block is significant; only drop the
+        // line break the split landed on.
+        remaining = match open_fence {
+            Some(_) => remaining.strip_prefix('\n').unwrap_or(remaining),
+            None => remaining.trim_start(),
+        };
+    }
+
+    chunks
+}
+
+/// Pick where to end a chunk within `window`. Natural breaks are only taken
+/// from the back half of the window so chunks don't come out tiny.
+fn find_split(window: &str, in_code: bool) -> Option<usize> {
+    let min = window.len() / 2;
+    let usable = |index: &usize| *index > min;
+
+    let paragraph = || window.rfind("\n\n").filter(usable);
+    let line = || window.rfind('\n').filter(usable);
+    let sentence = || last_sentence_end(window).filter(usable);
+
+    let natural = if in_code {
+        line()
+    } else {
+        paragraph().or_else(line).or_else(sentence)
+    };
+    natural.or_else(|| {
+        window
+            .rfind('\n')
+            .or_else(|| window.rfind(' '))
+            .filter(|index| *index > 0)
+    })
+}
+
+static SENTENCE_END: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"[.!?][)\]\x22']?[ \t\n]").expect("valid regex"));
+
+/// Byte offset just past the last sentence-ending punctuation in `text`.
+fn last_sentence_end(text: &str) -> Option<usize> {
+    SENTENCE_END
+        .find_iter(text)
+        .last()
+        .map(|found| found.end() - 1)
+}
+
+/// Whether `text` leaves a fence open, given the fence open before it.
+fn fence_state_after(text: &str, mut open_fence: Option<String>) -> Option<String> {
+    for line in text.lines() {
+        let line = line.trim_start();
+        if !line.starts_with(FENCE) {
+            continue;
+        }
+        open_fence = match open_fence {
+            Some(_) => None,
+            None => Some(line.trim_end().to_string()),
+        };
+    }
+    open_fence
+}
+
+/// Apply `rewrite` to everything outside fenced code blocks and inline code.
+pub(crate) fn map_prose(text: &str, rewrite: impl Fn(&str) -> String) -> String {
+    let mut output = String::with_capacity(text.len());
+    let mut prose = String::new();
+    let mut in_fence = false;
+
+    let flush = |prose: &mut String, output: &mut String| {
+        for (index, piece) in prose.split('`').enumerate() {
+            if index > 0 {
+                output.push('`');
+            }
+            if index % 2 == 0 {
+                output.push_str(&rewrite(piece));
+            } else {
+                output.push_str(piece);
+            }
+        }
+        prose.clear();
+    };
+
+    for line in text.split_inclusive('\n') {
+        let is_fence = line.trim_start().starts_with(FENCE);
+        if in_fence {
+            output.push_str(line);
+            in_fence = !is_fence;
+        } else if is_fence {
+            flush(&mut prose, &mut output);
+            output.push_str(line);
+            in_fence = true;
+        } else {
+            prose.push_str(line);
+        }
+    }
+    flush(&mut prose, &mut output);
+    output
+}
+
+/// Rewrite standard markdown into the platform's dialect: mrkdwn on Slack,
+/// plain text on Telegram, IRC, XMPP and SMS. Discord renders markdown
+/// natively. Code is left untouched, and XMPP clients that support message
+/// styling render its fences.
+pub fn convert_markdown(text: &str, platform: Platform) -> String {
+    match platform {
+        Platform::Discord => text.to_string(),
+        Platform::Slack => map_prose(text, to_slack_mrkdwn),
+        Platform::Telegram | Platform::Irc | Platform::Xmpp | Platform::Sms => {
+            map_prose(text, to_plain_text)
+        }
+    }
+}
+
+static MD_LINK: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\[([^\]]+)\]\((https?://[^)\s]+)\)").expect("valid regex"));
+static MD_BOLD: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\*\*(.+?)\*\*|__(.+?)__").expect("valid regex"));
+static MD_STRIKE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"~~(.+?)~~").expect("valid regex"));
+static MD_HEADING: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?m)^#{1,6}\s+(.+?)\s*#*$").expect("valid regex"));
+static MD_BULLET: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?m)^(\s*)[-*]\s+").expect("valid regex"));
+
+/// Slack mrkdwn: single-asterisk bold, single-tilde strike, `<url|text>`
+/// links, no headings.
+fn to_slack_mrkdwn(text: &str) -> String {
+    let text = MD_LINK.replace_all(text, "<$2|$1>");
+    let text = MD_HEADING.replace_all(&text, "**$1**");
+    let text = MD_BULLET.replace_all(&text, "$1• ");
+    let text = MD_BOLD.replace_all(&text, "*$1$2*");
+    MD_STRIKE.replace_all(&text, "~$1~").into_owned()
+}
+
+/// Telegram messages are sent without a parse mode, so markup would show
+/// up literally. Keep the words and the link targets.
+fn to_plain_text(text: &str) -> String {
+    let text = MD_LINK.replace_all(text, "$1 ($2)");
+    let text = MD_HEADING.replace_all(&text, "$1");
+    let text = MD_BULLET.replace_all(&text, "$1• ");
+    let text = MD_BOLD.replace_all(&text, "$1$2");
+    MD_STRIKE.replace_all(&text, "$1").into_owned()
+}
+
+/// Append a reply's sources as numbered footnotes in the platform's own
+/// markup: masked links on Discord (in angle brackets, so they don't unfurl
+/// into embeds), a `<url|title>` sources block on Slack, and `title: url`
+/// lines on plain text platforms. Other adapters get standard markdown.
+/// Memories are listed by title alone. Tool citations go on a last "via"
+/// line instead, in italics where the platform has them.
+pub fn render_citations(text: &str, citations: &[Citation], platform: Option<Platform>) -> String {
+    let (tools, citations): (Vec<&Citation>, Vec<&Citation>) = citations
+        .iter()
+        .partition(|citation| citation.origin == CitationOrigin::Tool);
+    let mut output = render_sources(text, &citations, platform);
+    if !tools.is_empty() {
+        let labels: Vec<&str> = tools.iter().map(|tool| tool.title.as_str()).collect();
+        let via = format!("via {}", labels.join(", "));
+        let via = match platform {
+            Some(Platform::Discord | Platform::Slack) | None => format!("_{via}_"),
+            Some(Platform::Telegram | Platform::Irc | Platform::Xmpp | Platform::Sms) => via,
+        };
+        output = format!("{}\n\n{via}", output.trim_end());
+    }
+    output
+}
+
+fn render_sources(text: &str, citations: &[&Citation], platform: Option<Platform>) -> String {
+    if citations.is_empty() {
+        return text.to_string();
+    }
+
+    let heading = match platform {
+        Some(Platform::Discord) | None => "**Sources**",
+        Some(Platform::Slack) => "*Sources*",
+        Some(Platform::Telegram | Platform::Irc | Platform::Xmpp | Platform::Sms) => "Sources:",
+    };
+    let mut output = format!("{}\n\n{heading}", text.trim_end());
+    for (index, citation) in citations.iter().enumerate() {
+        let number = index + 1;
+        let title = &citation.title;
+        let line = match (&citation.url, platform) {
+            (None, _) => format!("[{number}] {title}"),
+            (Some(url), Some(Platform::Discord)) => format!("[{number}] [{title}](<{url}>)"),
+            (Some(url), Some(Platform::Slack)) => {
+                format!("[{number}] <{url}|{}>", title.replace(['<', '>', '|'], ""))
+            }
+            (Some(url), Some(_)) => format!("[{number}] {title}: {url}"),
+            (Some(url), None) => format!("[{number}] [{title}]({url})"),
+        };
+        output.push('\n');
+        output.push_str(&line);
+    }
+    output
+}
+
+static TABLE_DELIMITER: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^\s*\|?\s*:?-+:?\s*(\|\s*:?-+:?\s*)*\|?\s*$").expect("valid regex")
+});
+
+/// None of the platforms render markdown tables. Discord, Slack and XMPP get
+/// an aligned monospace block; Telegram, IRC and SMS, which show fences
+/// literally, get one line per row.
+pub fn render_tables(text: &str, platform: Platform) -> String {
+    let lines: Vec<&str> = text.split_inclusive('\n').collect();
+    let mut output = String::with_capacity(text.len());
+    let mut in_fence = false;
+    let mut index = 0;
+
+    while index < lines.len() {
+        let line = lines[index];
+        if line.trim_start().starts_with(FENCE) {
+            in_fence = !in_fence;
+        }
+
+        let is_table_start = !in_fence
+            && line.contains('|')
+            && lines
+                .get(index + 1)
+                .is_some_and(|next| TABLE_DELIMITER.is_match(next.trim_end()));
+        if !is_table_start {
+            output.push_str(line);
+            index += 1;
+            continue;
+        }
+
+        let header = table_cells(line);
+        let mut rows = Vec::new();
+        index += 2;
+        while let Some(row) = lines.get(index).filter(|row| row.contains('|')) {
+            rows.push(table_cells(row));
+            index += 1;
+        }
+
+        match platform {
+            Platform::Discord | Platform::Slack | Platform::Xmpp => {
+                render_monospace(&header, &rows, &mut output)
+            }
+            Platform::Telegram | Platform::Irc | Platform::Sms => {
+                render_rows(&header, &rows, &mut output)
+            }
+        }
+    }
+
+    output
+}
+
+fn table_cells(line: &str) -> Vec<String> {
+    let line = line.trim();
+    let line = line.strip_prefix('|').unwrap_or(line);
+    let line = line.strip_suffix('|').unwrap_or(line);
+    line.split('|')
+        .map(|cell| cell.trim().replace("**", "").replace('`', ""))
+        .collect()
+}
+
+fn render_monospace(header: &[String], rows: &[Vec<String>], output: &mut String) {
+    let columns = rows.iter().map(Vec::len).fold(header.len(), usize::max);
+    let mut widths = vec![0; columns];
+    for row in std::iter::once(header).chain(rows.iter().map(Vec::as_slice)) {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let render_row = |row: &[String], output: &mut String| {
+        let cells: Vec<String> = widths
+            .iter()
+            .enumerate()
+            .map(|(column, width)| {
+                let cell = row.get(column).map(String::as_str).unwrap_or("");
+                format!("{cell:<width$}")
+            })
+            .collect();
+        output.push_str(cells.join(" | ").trim_end());
+        output.push('\n');
+    };
+
+    output.push_str("```\n");
+    render_row(header, output);
+    let rule: Vec<String> = widths.iter().map(|width| "-".repeat(*width)).collect();
+    output.push_str(&rule.join("-+-"));
+    output.push('\n');
+    for row in rows {
+        render_row(row, output);
+    }
+    output.push_str("```\n");
+}
+
+fn render_rows(header: &[String], rows: &[Vec<String>], output: &mut String) {
+    for row in rows {
+        let fields: Vec<String> = row
+            .iter()
+            .enumerate()
+            .filter(|(_, cell)| !cell.is_empty())
+            .map(|(column, cell)| match header.get(column) {
+                Some(name) if !name.is_empty() => format!("{name}: {cell}"),
+                _ => cell.clone(),
+            })
+            .collect();
+        output.push_str("• ");
+        output.push_str(&fields.join(", "));
+        output.push('\n');
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_short_message_is_one_chunk() {
+        assert_eq!(split_message("hello", 2000), ["hello"]);
+    }
+
+    #[test]
+    fn test_split_prefers_sentence_boundaries() {
+        let text = "First sentence here. Second sentence is a bit longer. Third.";
+        let chunks = split_message(text, 36);
+        assert_eq!(
+            chunks,
+            [
+                "First sentence here.",
+                "Second sentence is a bit longer.",
+                "Third."
+            ]
+        );
+    }
+
+    #[test]
+    fn test_split_reopens_code_fences() {
+        let code: String = (0..30).map(|i| format!("let x{i} = {i};\n")).collect();
+        let text = format!("Here:\n```rust\n{code}```\nDone.");
+        let chunks = split_message(&text, 200);
+
+        assert!(chunks.len() > 1);
+        for chunk in &chunks {
+            assert!(chunk.len() <= 200, "chunk too long: {}", chunk.len());
+            assert_eq!(chunk.matches(FENCE).count() % 2, 0, "unbalanced: {chunk}");
+        }
+        assert!(chunks[1].starts_with("```rust\n"));
+        assert!(chunks.last().unwrap().ends_with("Done."));
+
+        let rejoined: String = chunks.concat();
+        assert_eq!(rejoined.matches("let x").count(), 30);
+    }
+
+    #[test]
+    fn test_split_hard_cuts_on_char_boundaries() {
+        let text = "é".repeat(50);
+        let chunks = split_message(&text, 15);
+        assert!(chunks.iter().all(|chunk| chunk.len() <= 15));
+        assert_eq!(chunks.concat(), text);
+    }
+
+    #[test]
+    fn test_tables_render_per_platform() {
+        let text = "Scores:\n| Name | **Score** |\n|---|--:|\n| ada | 10 |\n| grace | 7 |\nend\n";
+
+        assert_eq!(
+            render_tables(text, Platform::Discord),
+            "Scores:\n```\nName  | Score\n------+------\nada   | 10\ngrace | 7\n```\nend\n"
+        );
+        assert_eq!(
+            render_tables(text, Platform::Telegram),
+            "Scores:\n• Name: ada, Score: 10\n• Name: grace, Score: 7\nend\n"
+        );
+    }
+
+    #[test]
+    fn test_citations_render_per_platform() {
+        let citations = [
+            Citation::web(
+                Some("Tokio docs"),
+                "https://docs.rs/tokio",
+                crate::citations::CitationOrigin::WebSearch,
+            ),
+            Citation::memory("4f2a", "Ana prefers async Rust"),
+        ];
+
+        assert_eq!(
+            render_citations("Answer.", &[], Some(Platform::Discord)),
+            "Answer."
+        );
+        assert_eq!(
+            render_citations("Answer.\n", &citations, Some(Platform::Discord)),
+            "Answer.\n\n**Sources**\n[1] [Tokio docs](<https://docs.rs/tokio>)\n[2] Ana prefers async Rust"
+        );
+        assert_eq!(
+            render_citations("Answer.", &citations[..1], Some(Platform::Slack)),
+            "Answer.\n\n*Sources*\n[1] <https://docs.rs/tokio|Tokio docs>"
+        );
+        assert_eq!(
+            render_citations("Answer.", &citations[..1], Some(Platform::Irc)),
+            "Answer.\n\nSources:\n[1] Tokio docs: https://docs.rs/tokio"
+        );
+    }
+
+    #[test]
+    fn test_tool_citations_render_as_via_line() {
+        let citations = [
+            Citation::tool("web_search".into()),
+            Citation::memory("4f2a", "Ana prefers async Rust"),
+            Citation::tool("file:docs/deploy.md".into()),
+        ];
+
+        assert_eq!(
+            render_citations("Answer.", &citations, Some(Platform::Discord)),
+            "Answer.\n\n**Sources**\n[1] Ana prefers async Rust\n\n_via web_search, file:docs/deploy.md_"
+        );
+        assert_eq!(
+            render_citations("Answer.", &citations[..1], Some(Platform::Irc)),
+            "Answer.\n\nvia web_search"
+        );
+    }
+
+    #[test]
+    fn test_tables_inside_code_are_left_alone() {
+        let text = "```\n| a | b |\n|---|---|\n```\n";
+        assert_eq!(render_tables(text, Platform::Slack), text);
+    }
+
+    #[test]
+    fn test_slack_messages_group_blocks() {
+        let text = "word ".repeat(SLACK_SECTION_MAX_LENGTH * SLACK_MAX_BLOCKS / 4);
+        let messages = slack_messages(&text);
+        assert!(messages.len() > 1);
+        assert!(
+            messages
+                .iter()
+                .all(|blocks| blocks.len() <= SLACK_MAX_BLOCKS)
+        );
+    }
+}
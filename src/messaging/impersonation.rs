@@ -0,0 +1,188 @@
+//! Messages sent through the management API as if a user had sent them.
+//!
+//! To reproduce a report exactly, an operator can post a message as a given
+//! sender in a given conversation. It joins the inbound stream like any
+//! adapter's message, so it's routed, rate limited and permission-checked
+//! the way the real sender's would be. By default it runs in a sandbox copy
+//! of the conversation: a separate channel that starts with the real one's
+//! persona and model override, and whose replies reach the API's event
+//! stream and transcript but never the platform. Nothing else leaves it
+//! either: its tool calls that would change anything outside the
+//! conversation are skipped, escalation rules aren't checked and broadcasts
+//! to it are dropped. With `deliver` set it runs in the real conversation
+//! and replies go out for real. Either way the message is marked synthetic
+//! and so is the turn that answers it.
+
+use crate::{InboundMessage, MessageContent};
+
+use serde::Deserialize;
+
+use std::collections::HashMap;
+
+/// Conversation ID prefix of sandboxed conversations.
+pub const SANDBOX_PREFIX: &str = "impersonation:";
+
+/// Tools a sandboxed turn runs whatever their arguments, since they only
+/// answer in, or work on, the sandboxed conversation itself.
+const SANDBOX_TOOLS: &[&str] = &[
+    "reply",
+    "react",
+    "skip",
+    "set_status",
+    "send_file",
+    "branch",
+    "spawn_worker",
+    "route",
+    "cancel",
+    "pin",
+    "scratchpad_get",
+    "scratchpad_list",
+    "scratchpad_set",
+    "channel_recall",
+    "memory_recall",
+    "calc",
+    "ocr",
+    "web_search",
+];
+
+/// Metadata key holding who sent a synthetic message.
+pub const IMPERSONATED_BY_KEY: &str = "impersonated_by";
+
+/// A message to send as someone else.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ImpersonationRequest {
+    /// Adapter the message claims to come from, e.g. "discord".
+    pub source: String,
+    /// Conversation to reproduce, e.g. "discord:123:456".
+    pub conversation_id: String,
+    /// Platform user ID of the sender.
+    pub sender_id: String,
+    #[serde(default)]
+    pub sender_name: Option<String>,
+    pub text: String,
+    /// Adapter metadata the message carries, so bindings match it the same
+    /// way, e.g. `discord_guild_id` and `discord_channel_id`.
+    #[serde(default)]
+    pub metadata: HashMap<String, serde_json::Value>,
+    /// Run in the real conversation and deliver the replies.
+    #[serde(default)]
+    pub deliver: bool,
+    /// Who is impersonating, for the turn ledger and logs.
+    #[serde(default = "default_operator")]
+    pub operator: String,
+}
+
+fn default_operator() -> String {
+    "api".into()
+}
+
+impl ImpersonationRequest {
+    /// The inbound message this request sends.
+    pub fn into_message(self) -> InboundMessage {
+        let conversation_id = if self.deliver {
+            self.conversation_id
+        } else {
+            format!("{SANDBOX_PREFIX}{}", self.conversation_id)
+        };
+        let mut metadata = self.metadata;
+        if let Some(name) = self.sender_name {
+            metadata.insert("sender_display_name".into(), serde_json::json!(name));
+        }
+        metadata.insert(IMPERSONATED_BY_KEY.into(), serde_json::json!(self.operator));
+        InboundMessage {
+            id: uuid::Uuid::new_v4().to_string(),
+            source: self.source,
+            conversation_id,
+            sender_id: self.sender_id,
+            agent_id: None,
+            content: MessageContent::Text(self.text),
+            timestamp: chrono::Utc::now(),
+            metadata,
+        }
+    }
+}
+
+/// Whether replies to `conversation_id` stay off the platform.
+pub fn is_sandboxed(conversation_id: &str) -> bool {
+    conversation_id.starts_with(SANDBOX_PREFIX)
+}
+
+/// Whether a sandboxed turn may run this call: the tool stays inside the
+/// conversation, or the call only reads.
+pub fn runs_in_sandbox(tool_name: &str, args: &str) -> bool {
+    SANDBOX_TOOLS.contains(&tool_name)
+        || !crate::approval::preview::has_side_effects(tool_name, args)
+}
+
+/// The conversation a sandbox copies, or `conversation_id` itself.
+pub fn real_conversation_id(conversation_id: &str) -> &str {
+    conversation_id
+        .strip_prefix(SANDBOX_PREFIX)
+        .unwrap_or(conversation_id)
+}
+
+/// Who sent `message` through the API, if it's synthetic.
+pub fn impersonated_by(message: &InboundMessage) -> Option<String> {
+    message
+        .metadata
+        .get(IMPERSONATED_BY_KEY)
+        .and_then(serde_json::Value::as_str)
+        .map(str::to_string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(deliver: bool) -> ImpersonationRequest {
+        serde_json::from_value(serde_json::json!({
+            "source": "discord",
+            "conversation_id": "discord:1:2",
+            "sender_id": "42",
+            "sender_name": "Ada",
+            "text": "why did my export fail?",
+            "metadata": {"discord_channel_id": 2},
+            "deliver": deliver,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_sandboxed_message() {
+        let message = request(false).into_message();
+        assert_eq!(message.conversation_id, "impersonation:discord:1:2");
+        assert!(is_sandboxed(&message.conversation_id));
+        assert_eq!(
+            real_conversation_id(&message.conversation_id),
+            "discord:1:2"
+        );
+        assert_eq!(message.sender_id, "42");
+        assert_eq!(impersonated_by(&message).as_deref(), Some("api"));
+        assert_eq!(
+            message.metadata["sender_display_name"],
+            serde_json::json!("Ada")
+        );
+        assert_eq!(message.metadata["discord_channel_id"], serde_json::json!(2));
+    }
+
+    #[test]
+    fn test_delivered_message_uses_real_conversation() {
+        let message = request(true).into_message();
+        assert_eq!(message.conversation_id, "discord:1:2");
+        assert!(!is_sandboxed(&message.conversation_id));
+        assert_eq!(real_conversation_id("discord:1:2"), "discord:1:2");
+        assert!(impersonated_by(&message).is_some());
+    }
+
+    #[test]
+    fn test_sandbox_only_runs_contained_or_read_only_calls() {
+        assert!(runs_in_sandbox("reply", r#"{"content": "hi"}"#));
+        assert!(runs_in_sandbox("spawn_worker", r#"{"task": "look"}"#));
+        assert!(runs_in_sandbox("file", r#"{"operation": "read"}"#));
+        assert!(runs_in_sandbox("http_request", r#"{"method": "GET"}"#));
+        assert!(!runs_in_sandbox("file", r#"{"operation": "write"}"#));
+        assert!(!runs_in_sandbox("shell", r#"{"command": "ls"}"#));
+        assert!(!runs_in_sandbox("escalate", r#"{"reason": "help"}"#));
+        assert!(!runs_in_sandbox("memory_save", r#"{"content": "x"}"#));
+    }
+}
@@ -0,0 +1,525 @@
+//! Intake classification for messages no binding matches.
+//!
+//! With `[intake]` enabled, the first message of an unbound conversation is
+//! scored against each agent's description, by embedding similarity or by
+//! one model call, and the conversation is pinned to the best match so its
+//! later messages go to the same agent. A best match below the confidence
+//! threshold, or a classification that fails or runs too long, sends the
+//! conversation to the default agent. Decisions are counted for the API.
+
+use crate::config::{IntakeConfig, IntakeMethod, RuntimeConfig};
+use crate::llm::{LlmManager, Priority, SpacebotModel};
+use crate::memory::EmbeddingModel;
+use crate::{Agent, AgentId, InboundMessage, MessageContent, ProcessType};
+
+use anyhow::Context as _;
+use rig::agent::AgentBuilder;
+use rig::completion::Prompt as _;
+use serde::{Deserialize, Serialize};
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Classification taking longer than this is abandoned for the default agent.
+const CLASSIFY_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Only this much of a message is classified.
+const MAX_MESSAGE_CHARS: usize = 2000;
+
+/// Past this many pinned conversations, ones idle for `PIN_TTL` are dropped.
+const PRUNE_THRESHOLD: usize = 10_000;
+const PIN_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Confidence histogram buckets, each 0.1 wide.
+const HISTOGRAM_BUCKETS: usize = 10;
+
+/// An agent classification can pick.
+#[derive(Debug, Clone)]
+pub struct Candidate {
+    pub agent_id: AgentId,
+    pub description: String,
+}
+
+/// Why a conversation went to the agent it did.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RouteReason {
+    /// The best match cleared the confidence threshold.
+    Classified,
+    /// The best match was below the threshold.
+    LowConfidence,
+    /// The message couldn't be classified.
+    Failed,
+}
+
+impl RouteReason {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Classified => "classified",
+            Self::LowConfidence => "low_confidence",
+            Self::Failed => "failed",
+        }
+    }
+}
+
+/// Where a conversation went, and why.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IntakeDecision {
+    pub agent_id: AgentId,
+    /// Score of the best match. None when nothing was scored.
+    pub confidence: Option<f32>,
+    pub reason: RouteReason,
+}
+
+/// Counts of routing decisions since startup, for the API.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct IntakeStats {
+    pub classified: u64,
+    pub low_confidence: u64,
+    pub failed: u64,
+    /// Messages that followed their conversation's earlier decision.
+    pub pinned: u64,
+    /// Classified conversations by the agent they went to.
+    pub by_agent: BTreeMap<String, u64>,
+    /// Best-match scores in buckets of 0.1, the first holding [0, 0.1).
+    /// Useful for picking `min_confidence`.
+    pub confidence_histogram: [u64; HISTOGRAM_BUCKETS],
+}
+
+#[derive(Default)]
+struct IntakeState {
+    pinned: HashMap<String, (AgentId, Instant)>,
+    stats: IntakeStats,
+}
+
+/// Picks an agent for conversations no binding matches.
+pub struct IntakeRouter {
+    config: IntakeConfig,
+    candidates: Vec<Candidate>,
+    default_agent: AgentId,
+    embedding_model: Arc<EmbeddingModel>,
+    llm_manager: Arc<LlmManager>,
+    /// The default agent's config, for model routing.
+    runtime_config: Arc<RuntimeConfig>,
+    /// Description embeddings, computed on first use.
+    description_embeddings: tokio::sync::OnceCell<Vec<Vec<f32>>>,
+    state: Mutex<IntakeState>,
+}
+
+impl IntakeRouter {
+    /// The router for `config`, or None when intake is off or no agent has a
+    /// description to classify against.
+    pub fn from_agents(
+        config: &IntakeConfig,
+        agents: &HashMap<AgentId, Agent>,
+        default_agent_id: &str,
+        embedding_model: Arc<EmbeddingModel>,
+    ) -> Option<Arc<Self>> {
+        if !config.enabled {
+            return None;
+        }
+
+        let default_agent_id = match config.default_agent.as_deref() {
+            Some(agent_id) if agents.contains_key(agent_id) => agent_id,
+            Some(agent_id) => {
+                tracing::warn!(
+                    agent_id,
+                    "intake.default_agent is not a configured agent, using the default agent"
+                );
+                default_agent_id
+            }
+            None => default_agent_id,
+        };
+        let default_agent = agents.get(default_agent_id)?;
+
+        let mut candidates: Vec<Candidate> = agents
+            .values()
+            .filter_map(|agent| {
+                let description = agent.config.description.as_deref()?.trim();
+                (!description.is_empty()).then(|| Candidate {
+                    agent_id: agent.id.clone(),
+                    description: description.to_string(),
+                })
+            })
+            .collect();
+        if candidates.is_empty() {
+            tracing::warn!("intake is enabled but no agent has a description, disabling it");
+            return None;
+        }
+        candidates.sort_by(|a, b| a.agent_id.cmp(&b.agent_id));
+
+        tracing::info!(
+            method = config.method.as_str(),
+            candidates = candidates.len(),
+            default_agent = %default_agent.id,
+            "intake classification enabled"
+        );
+        Some(Arc::new(Self {
+            config: config.clone(),
+            candidates,
+            default_agent: default_agent.id.clone(),
+            embedding_model,
+            llm_manager: default_agent.deps.llm_manager.clone(),
+            runtime_config: default_agent.deps.runtime_config.clone(),
+            description_embeddings: tokio::sync::OnceCell::new(),
+            state: Mutex::new(IntakeState::default()),
+        }))
+    }
+
+    pub fn config(&self) -> &IntakeConfig {
+        &self.config
+    }
+
+    pub fn default_agent(&self) -> &AgentId {
+        &self.default_agent
+    }
+
+    /// The agent for a message no binding matched: its conversation's earlier
+    /// decision if there is one, otherwise a fresh classification.
+    pub async fn route(&self, message: &InboundMessage) -> AgentId {
+        if let Some(agent_id) = self.pinned(&message.conversation_id) {
+            return agent_id;
+        }
+
+        let text = match &message.content {
+            MessageContent::Text(text) => text.as_str(),
+            MessageContent::Media { text, .. } => text.as_deref().unwrap_or_default(),
+        };
+        let text: String = text.trim().chars().take(MAX_MESSAGE_CHARS).collect();
+
+        let decision = if text.is_empty() {
+            tracing::debug!(
+                conversation_id = %message.conversation_id,
+                "message has no text to classify"
+            );
+            self.failed()
+        } else {
+            match tokio::time::timeout(CLASSIFY_TIMEOUT, self.classify(&text)).await {
+                Ok(Ok(scores)) => decide(&scores, self.config.min_confidence, &self.default_agent),
+                Ok(Err(error)) => {
+                    tracing::warn!(
+                        %error,
+                        conversation_id = %message.conversation_id,
+                        "intake classification failed"
+                    );
+                    self.failed()
+                }
+                Err(_) => {
+                    tracing::warn!(
+                        conversation_id = %message.conversation_id,
+                        "intake classification timed out"
+                    );
+                    self.failed()
+                }
+            }
+        };
+
+        tracing::info!(
+            conversation_id = %message.conversation_id,
+            agent_id = %decision.agent_id,
+            confidence = ?decision.confidence,
+            reason = decision.reason.as_str(),
+            "intake routed conversation"
+        );
+        self.record(&message.conversation_id, &decision);
+        decision.agent_id
+    }
+
+    pub fn stats(&self) -> IntakeStats {
+        self.lock().stats.clone()
+    }
+
+    fn failed(&self) -> IntakeDecision {
+        IntakeDecision {
+            agent_id: self.default_agent.clone(),
+            confidence: None,
+            reason: RouteReason::Failed,
+        }
+    }
+
+    fn pinned(&self, conversation_id: &str) -> Option<AgentId> {
+        let mut state = self.lock();
+        let (agent_id, last_seen) = state.pinned.get_mut(conversation_id)?;
+        *last_seen = Instant::now();
+        let agent_id = agent_id.clone();
+        state.stats.pinned += 1;
+        Some(agent_id)
+    }
+
+    fn record(&self, conversation_id: &str, decision: &IntakeDecision) {
+        let now = Instant::now();
+        let mut state = self.lock();
+        if state.pinned.len() >= PRUNE_THRESHOLD {
+            state
+                .pinned
+                .retain(|_, (_, last_seen)| now.duration_since(*last_seen) < PIN_TTL);
+        }
+        state.pinned.insert(
+            conversation_id.to_string(),
+            (decision.agent_id.clone(), now),
+        );
+
+        let stats = &mut state.stats;
+        match decision.reason {
+            RouteReason::Classified => {
+                stats.classified += 1;
+                *stats
+                    .by_agent
+                    .entry(decision.agent_id.to_string())
+                    .or_default() += 1;
+            }
+            RouteReason::LowConfidence => stats.low_confidence += 1,
+            RouteReason::Failed => stats.failed += 1,
+        }
+        if let Some(confidence) = decision.confidence {
+            stats.confidence_histogram[histogram_bucket(confidence)] += 1;
+        }
+    }
+
+    /// A score per candidate, or just for the model's pick.
+    async fn classify(&self, text: &str) -> anyhow::Result<Vec<(AgentId, f32)>> {
+        match self.config.method {
+            IntakeMethod::Embedding => self.classify_by_embedding(text).await,
+            IntakeMethod::Llm => self.classify_by_llm(text).await,
+        }
+    }
+
+    async fn classify_by_embedding(&self, text: &str) -> anyhow::Result<Vec<(AgentId, f32)>> {
+        let descriptions = self
+            .description_embeddings
+            .get_or_try_init(|| async {
+                let mut embeddings = Vec::with_capacity(self.candidates.len());
+                for candidate in &self.candidates {
+                    embeddings.push(
+                        self.embedding_model
+                            .embed_one(&candidate.description)
+                            .await
+                            .with_context(|| {
+                                format!("failed to embed description of {}", candidate.agent_id)
+                            })?,
+                    );
+                }
+                anyhow::Ok(embeddings)
+            })
+            .await?;
+        let message = self
+            .embedding_model
+            .embed_one(text)
+            .await
+            .context("failed to embed message")?;
+
+        Ok(self
+            .candidates
+            .iter()
+            .zip(descriptions)
+            .map(|(candidate, description)| {
+                (
+                    candidate.agent_id.clone(),
+                    cosine_similarity(&message, description),
+                )
+            })
+            .collect())
+    }
+
+    async fn classify_by_llm(&self, text: &str) -> anyhow::Result<Vec<(AgentId, f32)>> {
+        let routing = self.runtime_config.routing.load();
+        let model_name = self
+            .config
+            .model
+            .clone()
+            .unwrap_or_else(|| routing.resolve(ProcessType::Worker, None).to_string());
+        let model = SpacebotModel::make(&self.llm_manager, &model_name)
+            .with_routing((**routing).clone())
+            .with_priority(Priority::Interactive);
+        let agent = AgentBuilder::new(model)
+            .preamble(crate::prompts::text::get("intake"))
+            .build();
+
+        let response = agent
+            .prompt(render_prompt(&self.candidates, text))
+            .await
+            .context("intake model call failed")?;
+        parse_choice(&response, &self.candidates)
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, IntakeState> {
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl std::fmt::Debug for IntakeRouter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("IntakeRouter")
+            .field("method", &self.config.method)
+            .field("candidates", &self.candidates.len())
+            .field("stats", &self.stats())
+            .finish()
+    }
+}
+
+/// The best-scoring agent if it clears `min_confidence`, otherwise the
+/// default. Ties go to the earlier candidate.
+fn decide(
+    scores: &[(AgentId, f32)],
+    min_confidence: f32,
+    default_agent: &AgentId,
+) -> IntakeDecision {
+    let best = scores
+        .iter()
+        .fold(None, |best: Option<&(AgentId, f32)>, score| match best {
+            Some(best) if best.1 >= score.1 => Some(best),
+            _ => Some(score),
+        });
+    match best {
+        Some((agent_id, confidence)) if *confidence >= min_confidence => IntakeDecision {
+            agent_id: agent_id.clone(),
+            confidence: Some(*confidence),
+            reason: RouteReason::Classified,
+        },
+        _ => IntakeDecision {
+            agent_id: default_agent.clone(),
+            confidence: best.map(|(_, confidence)| *confidence),
+            reason: RouteReason::LowConfidence,
+        },
+    }
+}
+
+fn histogram_bucket(confidence: f32) -> usize {
+    ((confidence.max(0.0) * HISTOGRAM_BUCKETS as f32) as usize).min(HISTOGRAM_BUCKETS - 1)
+}
+
+fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm_a = a.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norm_b = b.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm_a == 0.0 || norm_b == 0.0 {
+        0.0
+    } else {
+        dot / (norm_a * norm_b)
+    }
+}
+
+fn render_prompt(candidates: &[Candidate], text: &str) -> String {
+    let mut prompt = String::from("Agents:\n");
+    for candidate in candidates {
+        let description = candidate.description.split_whitespace().collect::<Vec<_>>();
+        prompt.push_str(&format!(
+            "- {}: {}\n",
+            candidate.agent_id,
+            description.join(" ")
+        ));
+    }
+    prompt.push_str(&format!("\nMessage:\n{text}"));
+    prompt
+}
+
+#[derive(Deserialize)]
+struct Choice {
+    agent: Option<String>,
+    #[serde(default)]
+    confidence: f32,
+}
+
+/// The model's pick as a single score. A reply of no agent is an empty set
+/// of scores, which routes to the default.
+fn parse_choice(response: &str, candidates: &[Candidate]) -> anyhow::Result<Vec<(AgentId, f32)>> {
+    let cleaned = response
+        .trim()
+        .trim_start_matches("```json")
+        .trim_start_matches("```")
+        .trim_end_matches("```")
+        .trim();
+    let choice: Choice = serde_json::from_str(cleaned)
+        .with_context(|| format!("intake model reply isn't the requested JSON: {cleaned}"))?;
+    let Some(agent) = choice.agent else {
+        return Ok(Vec::new());
+    };
+    let candidate = candidates
+        .iter()
+        .find(|candidate| *candidate.agent_id == *agent)
+        .with_context(|| format!("intake model picked unknown agent '{agent}'"))?;
+    Ok(vec![(
+        candidate.agent_id.clone(),
+        choice.confidence.clamp(0.0, 1.0),
+    )])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candidates() -> Vec<Candidate> {
+        vec![
+            Candidate {
+                agent_id: "billing".into(),
+                description: "Invoices, refunds and\n  plan changes.".into(),
+            },
+            Candidate {
+                agent_id: "support".into(),
+                description: "Troubleshooting the product.".into(),
+            },
+        ]
+    }
+
+    #[test]
+    fn test_decide() {
+        let default: AgentId = "main".into();
+        let scores: Vec<(AgentId, f32)> = vec![
+            ("billing".into(), 0.42),
+            ("support".into(), 0.61),
+            ("sales".into(), 0.61),
+        ];
+
+        let decision = decide(&scores, 0.5, &default);
+        assert_eq!(&*decision.agent_id, "support");
+        assert_eq!(decision.confidence, Some(0.61));
+        assert_eq!(decision.reason, RouteReason::Classified);
+
+        let decision = decide(&scores, 0.7, &default);
+        assert_eq!(&*decision.agent_id, "main");
+        assert_eq!(decision.confidence, Some(0.61));
+        assert_eq!(decision.reason, RouteReason::LowConfidence);
+
+        let decision = decide(&[], 0.5, &default);
+        assert_eq!(&*decision.agent_id, "main");
+        assert_eq!(decision.confidence, None);
+        assert_eq!(decision.reason, RouteReason::LowConfidence);
+    }
+
+    #[test]
+    fn test_parse_choice() {
+        let candidates = candidates();
+        let scores = parse_choice(
+            "```json\n{\"agent\": \"billing\", \"confidence\": 0.9}\n```",
+            &candidates,
+        )
+        .unwrap();
+        assert_eq!(scores, vec![(AgentId::from("billing"), 0.9)]);
+
+        let scores = parse_choice("{\"agent\": null, \"confidence\": 0.2}", &candidates).unwrap();
+        assert!(scores.is_empty());
+
+        assert!(parse_choice("{\"agent\": \"sales\", \"confidence\": 0.9}", &candidates).is_err());
+        assert!(parse_choice("billing, probably", &candidates).is_err());
+    }
+
+    #[test]
+    fn test_render_prompt() {
+        assert_eq!(
+            render_prompt(&candidates(), "I was charged twice"),
+            "Agents:\n- billing: Invoices, refunds and plan changes.\n\
+             - support: Troubleshooting the product.\n\nMessage:\nI was charged twice"
+        );
+    }
+
+    #[test]
+    fn test_histogram_bucket() {
+        assert_eq!(histogram_bucket(-0.2), 0);
+        assert_eq!(histogram_bucket(0.05), 0);
+        assert_eq!(histogram_bucket(0.35), 3);
+        assert_eq!(histogram_bucket(1.0), 9);
+    }
+}
@@ -0,0 +1,717 @@
+//! IRC messaging adapter.
+//!
+//! Speaks the client protocol directly over TCP, optionally with TLS, and
+//! logs in with SASL PLAIN when a password is configured. Each joined
+//! channel is a conversation (`irc:#ops`), and so is each nick allowed to
+//! query the bot (`irc:alice`). Replies are sent one line per PRIVMSG
+//! through a token bucket, so a long answer trickles out instead of getting
+//! the bot kicked for flooding. Dropped connections are retried with
+//! backoff.
+
+use crate::activation::MENTIONS_BOT;
+use crate::config::IrcConfig;
+use crate::messaging::format::{IRC_MAX_LENGTH, Platform, render_tables, split_message};
+use crate::messaging::traits::{InboundStream, Messaging};
+use crate::{InboundMessage, MessageContent, OutboundResponse};
+
+use anyhow::Context as _;
+use base64::Engine as _;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader, WriteHalf};
+use tokio::net::TcpStream;
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, watch};
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Idle time before we PING the server. Twice this without any traffic
+/// and the connection is considered dead.
+const PING_INTERVAL: Duration = Duration::from_secs(120);
+
+const RECONNECT_MIN_DELAY: Duration = Duration::from_secs(5);
+const RECONNECT_MAX_DELAY: Duration = Duration::from_secs(300);
+
+/// Lines waiting to go out. Each reply line takes one slot.
+const OUTGOING_CAPACITY: usize = 1024;
+
+/// IRC adapter state.
+pub struct IrcAdapter {
+    config: Arc<IrcConfig>,
+    outgoing_tx: mpsc::Sender<String>,
+    /// Taken by the connection task on start.
+    outgoing_rx: std::sync::Mutex<Option<mpsc::Receiver<String>>>,
+    connected: Arc<AtomicBool>,
+    shutdown_tx: watch::Sender<bool>,
+}
+
+trait Connection: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> Connection for T {}
+
+impl IrcAdapter {
+    pub fn new(config: &IrcConfig) -> Self {
+        let (outgoing_tx, outgoing_rx) = mpsc::channel(OUTGOING_CAPACITY);
+        Self {
+            config: Arc::new(config.clone()),
+            outgoing_tx,
+            outgoing_rx: std::sync::Mutex::new(Some(outgoing_rx)),
+            connected: Arc::new(AtomicBool::new(false)),
+            shutdown_tx: watch::channel(false).0,
+        }
+    }
+
+    /// Queue `response` for `target`, a channel or a nick.
+    async fn send(&self, target: &str, response: OutboundResponse) -> anyhow::Result<()> {
+        if target.is_empty() || target.contains([' ', '\r', '\n']) {
+            anyhow::bail!("'{target}' is not an IRC channel or nick");
+        }
+        let lines = match response {
+            OutboundResponse::Text(text) | OutboundResponse::ThreadReply { text, .. } => {
+                irc_lines(&text)
+            }
+            OutboundResponse::File {
+                filename, caption, ..
+            } => {
+                let mut lines = caption.as_deref().map(irc_lines).unwrap_or_default();
+                lines.push(format!("({filename} not sent, IRC can't carry files)"));
+                lines
+            }
+            // No reactions, typing indicators or message edits on IRC.
+            OutboundResponse::Reaction(_)
+            | OutboundResponse::Status(_)
+            | OutboundResponse::StreamStart
+            | OutboundResponse::StreamChunk(_)
+            | OutboundResponse::StreamEnd => return Ok(()),
+        };
+
+        for line in lines {
+            self.outgoing_tx
+                .send(format!("PRIVMSG {target} :{line}"))
+                .await
+                .context("irc connection task has stopped")?;
+        }
+        Ok(())
+    }
+}
+
+/// Split a reply into lines that fit in a PRIVMSG. Code fences and blank
+/// lines are dropped, since IRC shows both literally.
+fn irc_lines(text: &str) -> Vec<String> {
+    render_tables(text, Platform::Irc)
+        .lines()
+        .map(|line| line.replace('\r', ""))
+        .filter(|line| !line.trim().is_empty() && !line.trim_start().starts_with("```"))
+        .flat_map(|line| split_message(&line, IRC_MAX_LENGTH))
+        .collect()
+}
+
+impl Messaging for IrcAdapter {
+    fn name(&self) -> &str {
+        "irc"
+    }
+
+    async fn start(&self) -> crate::Result<InboundStream> {
+        let outgoing_rx = self
+            .outgoing_rx
+            .lock()
+            .expect("irc outgoing queue lock poisoned")
+            .take()
+            .context("irc adapter already started")?;
+        let (inbound_tx, inbound_rx) = mpsc::channel(256);
+
+        let config = self.config.clone();
+        let connected = self.connected.clone();
+        let shutdown_rx = self.shutdown_tx.subscribe();
+        tokio::spawn(async move {
+            run(config, inbound_tx, outgoing_rx, connected, shutdown_rx).await;
+        });
+
+        let stream = tokio_stream::wrappers::ReceiverStream::new(inbound_rx);
+        Ok(Box::pin(stream))
+    }
+
+    async fn respond(
+        &self,
+        message: &InboundMessage,
+        response: OutboundResponse,
+    ) -> crate::Result<()> {
+        let target = message
+            .metadata
+            .get("irc_target")
+            .and_then(|value| value.as_str())
+            .context("missing irc_target in metadata")?;
+        self.send(target, response).await?;
+        Ok(())
+    }
+
+    async fn broadcast(&self, target: &str, response: OutboundResponse) -> crate::Result<()> {
+        self.send(target, response).await?;
+        Ok(())
+    }
+
+    async fn health_check(&self) -> crate::Result<()> {
+        if !self.connected.load(Ordering::Relaxed) {
+            return Err(anyhow::anyhow!("not connected to {}", self.config.server).into());
+        }
+        Ok(())
+    }
+
+    fn restart_on_disconnect(&self) -> bool {
+        // The connection loop reconnects by itself, and `start` can only run once.
+        false
+    }
+
+    async fn shutdown(&self) -> crate::Result<()> {
+        self.shutdown_tx.send_replace(true);
+        tracing::info!("irc adapter shut down");
+        Ok(())
+    }
+}
+
+/// Keep a connection up until shutdown, reconnecting with backoff.
+async fn run(
+    config: Arc<IrcConfig>,
+    inbound_tx: mpsc::Sender<InboundMessage>,
+    mut outgoing_rx: mpsc::Receiver<String>,
+    connected: Arc<AtomicBool>,
+    mut shutdown_rx: watch::Receiver<bool>,
+) {
+    let mut delay = RECONNECT_MIN_DELAY;
+    loop {
+        let started = Instant::now();
+        let result = session(
+            &config,
+            &inbound_tx,
+            &mut outgoing_rx,
+            &connected,
+            &mut shutdown_rx,
+        )
+        .await;
+        connected.store(false, Ordering::Relaxed);
+
+        if *shutdown_rx.borrow() || inbound_tx.is_closed() {
+            return;
+        }
+        match result {
+            Ok(()) => tracing::warn!(server = %config.server, "irc connection closed"),
+            Err(error) => tracing::warn!(server = %config.server, %error, "irc connection failed"),
+        }
+
+        // A connection that stayed up a while starts the backoff over.
+        if started.elapsed() > RECONNECT_MAX_DELAY {
+            delay = RECONNECT_MIN_DELAY;
+        }
+        tokio::select! {
+            _ = tokio::time::sleep(delay) => {}
+            _ = shutdown_rx.changed() => return,
+        }
+        delay = (delay * 2).min(RECONNECT_MAX_DELAY);
+    }
+}
+
+async fn connect(config: &IrcConfig) -> anyhow::Result<Box<dyn Connection>> {
+    let address = format!("{}:{}", config.server, config.port);
+    let tcp = tokio::time::timeout(CONNECT_TIMEOUT, TcpStream::connect(&address))
+        .await
+        .with_context(|| format!("timed out connecting to {address}"))?
+        .with_context(|| format!("failed to connect to {address}"))?;
+    if !config.tls {
+        return Ok(Box::new(tcp));
+    }
+
+    let mut roots = rustls::RootCertStore::empty();
+    roots.add_parsable_certificates(rustls_native_certs::load_native_certs().certs);
+    let tls_config = rustls::ClientConfig::builder()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    let server_name = rustls::pki_types::ServerName::try_from(config.server.clone())
+        .with_context(|| format!("'{}' is not a valid server name", config.server))?;
+    let stream = tokio_rustls::TlsConnector::from(Arc::new(tls_config))
+        .connect(server_name, tcp)
+        .await
+        .with_context(|| format!("TLS handshake with {address} failed"))?;
+    Ok(Box::new(stream))
+}
+
+/// Where a connection is in registration.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Registration {
+    /// Waiting for the server's capability list.
+    Capabilities,
+    /// SASL requested or in progress.
+    Sasl,
+    /// Waiting for the welcome reply.
+    Welcome,
+    Registered,
+}
+
+/// One connection, from registration until it drops or shutdown.
+async fn session(
+    config: &IrcConfig,
+    inbound_tx: &mpsc::Sender<InboundMessage>,
+    outgoing_rx: &mut mpsc::Receiver<String>,
+    connected: &AtomicBool,
+    shutdown_rx: &mut watch::Receiver<bool>,
+) -> anyhow::Result<()> {
+    let stream = connect(config).await?;
+    let (reader, writer) = tokio::io::split(stream);
+
+    // Reading happens in its own task so the select below only waits on
+    // channels, which are safe to cancel.
+    let (line_tx, mut line_rx) = mpsc::channel::<String>(256);
+    let reader_task = tokio::spawn(async move {
+        let mut reader = BufReader::new(reader);
+        let mut buffer = Vec::new();
+        loop {
+            buffer.clear();
+            match reader.read_until(b'\n', &mut buffer).await {
+                Ok(0) | Err(_) => return,
+                Ok(_) => {
+                    let line = String::from_utf8_lossy(&buffer).into_owned();
+                    if line_tx.send(line).await.is_err() {
+                        return;
+                    }
+                }
+            }
+        }
+    });
+    let _reader_guard = AbortOnDrop(reader_task);
+
+    let mut state = SessionState {
+        config,
+        writer,
+        nickname: config.nickname.clone(),
+        registration: Registration::Welcome,
+        capabilities: String::new(),
+    };
+    state.register().await?;
+
+    let mut flood = FloodControl::new(
+        config.flood_burst,
+        Duration::from_millis(config.flood_interval_ms),
+    );
+    let mut keepalive = tokio::time::interval(PING_INTERVAL);
+    let mut last_activity = Instant::now();
+
+    loop {
+        tokio::select! {
+            line = line_rx.recv() => {
+                let Some(line) = line else {
+                    return Ok(());
+                };
+                last_activity = Instant::now();
+                let Some(message) = parse_line(&line) else {
+                    continue;
+                };
+                let inbound = state.handle(&message).await?;
+                if state.registration == Registration::Registered {
+                    connected.store(true, Ordering::Relaxed);
+                }
+                let Some(inbound) = inbound else {
+                    continue;
+                };
+                if inbound_tx.send(inbound).await.is_err() {
+                    return Ok(());
+                }
+            }
+            line = outgoing_rx.recv(), if state.registration == Registration::Registered => {
+                let Some(line) = line else {
+                    return Ok(());
+                };
+                let wait = flood.reserve(Instant::now());
+                if !wait.is_zero() {
+                    tokio::time::sleep(wait).await;
+                }
+                state.write(&line).await?;
+            }
+            _ = keepalive.tick() => {
+                let idle = last_activity.elapsed();
+                if idle >= PING_INTERVAL * 2 {
+                    anyhow::bail!("no traffic from {} for {}s", config.server, idle.as_secs());
+                }
+                if idle >= PING_INTERVAL {
+                    state.write(&format!("PING :{}", config.server)).await?;
+                }
+            }
+            _ = shutdown_rx.changed() => {
+                state.write("QUIT :Shutting down").await.ok();
+                return Ok(());
+            }
+        }
+    }
+}
+
+/// Protocol state of one connection.
+struct SessionState<'a> {
+    config: &'a IrcConfig,
+    writer: WriteHalf<Box<dyn Connection>>,
+    /// Our nick as the server knows it.
+    nickname: String,
+    registration: Registration,
+    /// Capabilities listed so far by `CAP LS`.
+    capabilities: String,
+}
+
+impl SessionState<'_> {
+    async fn write(&mut self, line: &str) -> anyhow::Result<()> {
+        self.writer.write_all(line.as_bytes()).await?;
+        self.writer.write_all(b"\r\n").await?;
+        self.writer.flush().await?;
+        Ok(())
+    }
+
+    /// Start registration. With SASL, it's held open with `CAP LS` until
+    /// the login succeeds.
+    async fn register(&mut self) -> anyhow::Result<()> {
+        let config = self.config;
+        if config.sasl_password.is_some() {
+            self.write("CAP LS 302").await?;
+            self.registration = Registration::Capabilities;
+        }
+        if let Some(password) = &config.password {
+            self.write(&format!("PASS {password}")).await?;
+        }
+        self.write(&format!("NICK {}", self.nickname)).await?;
+        self.write(&format!(
+            "USER {} 0 * :{}",
+            config.username, config.realname
+        ))
+        .await
+    }
+
+    /// React to one line from the server. Returns the inbound message for
+    /// a PRIVMSG we should pass on.
+    async fn handle(&mut self, message: &Message<'_>) -> anyhow::Result<Option<InboundMessage>> {
+        let config = self.config;
+        let param = |index: usize| message.params.get(index).copied().unwrap_or_default();
+        let last = message.params.last().copied().unwrap_or_default();
+
+        match message.command {
+            "PING" => self.write(&format!("PONG :{}", param(0))).await?,
+            "ERROR" => anyhow::bail!("server closed the connection: {}", param(0)),
+            "CAP" if self.registration != Registration::Registered => match param(1) {
+                "LS" => {
+                    self.capabilities.push(' ');
+                    self.capabilities.push_str(last);
+                    // `CAP * LS * :...` means more lines follow.
+                    if message.params.len() > 3 && param(2) == "*" {
+                        return Ok(None);
+                    }
+                    let offers_sasl = self
+                        .capabilities
+                        .split_whitespace()
+                        .any(|cap| cap == "sasl" || cap.starts_with("sasl="));
+                    if !offers_sasl {
+                        anyhow::bail!("{} doesn't support SASL", config.server);
+                    }
+                    self.write("CAP REQ :sasl").await?;
+                    self.registration = Registration::Sasl;
+                }
+                "ACK" => self.write("AUTHENTICATE PLAIN").await?,
+                "NAK" => anyhow::bail!("{} refused SASL", config.server),
+                _ => {}
+            },
+            "AUTHENTICATE" if param(0) == "+" => {
+                let account = config.sasl_username.as_deref().unwrap_or(&config.nickname);
+                let password = config.sasl_password.as_deref().unwrap_or_default();
+                let payload = base64::engine::general_purpose::STANDARD
+                    .encode(format!("{account}\0{account}\0{password}"));
+                self.write(&format!("AUTHENTICATE {payload}")).await?;
+            }
+            // RPL_SASLSUCCESS
+            "903" => {
+                self.write("CAP END").await?;
+                self.registration = Registration::Welcome;
+            }
+            // ERR_NICKLOCKED, ERR_SASLFAIL, ERR_SASLTOOLONG, ERR_SASLABORTED,
+            // ERR_SASLALREADY
+            "902" | "904" | "905" | "906" | "907" => {
+                anyhow::bail!("SASL login failed: {last}")
+            }
+            // RPL_WELCOME
+            "001" => {
+                if !param(0).is_empty() {
+                    self.nickname = param(0).to_string();
+                }
+                self.registration = Registration::Registered;
+                tracing::info!(server = %config.server, nickname = %self.nickname, "irc connected");
+                for channel in &config.channels {
+                    self.write(&format!("JOIN {channel}")).await?;
+                }
+            }
+            // ERR_NICKNAMEINUSE
+            "433" if self.registration != Registration::Registered => {
+                self.nickname.push('_');
+                self.write(&format!("NICK {}", self.nickname)).await?;
+            }
+            "NICK" if message.nick() == Some(self.nickname.as_str()) => {
+                self.nickname = param(0).to_string();
+            }
+            "PRIVMSG" => return Ok(inbound_message(config, &self.nickname, message)),
+            _ => {}
+        }
+        Ok(None)
+    }
+}
+
+struct AbortOnDrop(tokio::task::JoinHandle<()>);
+
+impl Drop for AbortOnDrop {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+/// The inbound message for a PRIVMSG, or None when it should be ignored:
+/// our own lines, CTCP requests, and queries from nicks not allowed to DM.
+fn inbound_message(
+    config: &IrcConfig,
+    nickname: &str,
+    message: &Message<'_>,
+) -> Option<InboundMessage> {
+    let sender = message.nick()?;
+    let (target, text) = match message.params.as_slice() {
+        [target, text, ..] => (*target, *text),
+        _ => return None,
+    };
+    if sender.eq_ignore_ascii_case(nickname) {
+        return None;
+    }
+
+    // CTCP ACTION ("/me waves") is a message; other CTCP is not.
+    let text = match text.strip_prefix('\u{1}') {
+        Some(ctcp) => {
+            let action = ctcp.trim_end_matches('\u{1}').strip_prefix("ACTION ")?;
+            format!("* {sender} {action}")
+        }
+        None => text.to_string(),
+    };
+
+    let is_channel = target.starts_with(['#', '&', '+', '!']);
+    let mut metadata = HashMap::new();
+    let conversation_id = if is_channel {
+        metadata.insert("irc_target".into(), serde_json::Value::from(target));
+        metadata.insert("irc_channel".into(), serde_json::Value::from(target));
+        format!("irc:{}", target.to_ascii_lowercase())
+    } else {
+        let allowed = config
+            .dm_allowed_users
+            .iter()
+            .any(|user| user.eq_ignore_ascii_case(sender));
+        if !allowed {
+            return None;
+        }
+        metadata.insert("irc_target".into(), serde_json::Value::from(sender));
+        format!("irc:{}", sender.to_ascii_lowercase())
+    };
+    let mentions_bot = !is_channel || mentions_nick(&text, nickname);
+    metadata.insert(MENTIONS_BOT.into(), serde_json::Value::from(mentions_bot));
+    metadata.insert("irc_nick".into(), serde_json::Value::from(sender));
+    metadata.insert("display_name".into(), serde_json::Value::from(sender));
+    if let Some((_, user)) = message.prefix.and_then(|prefix| prefix.split_once('!')) {
+        metadata.insert("irc_user".into(), serde_json::Value::from(user));
+    }
+
+    Some(InboundMessage {
+        id: uuid::Uuid::new_v4().to_string(),
+        source: "irc".into(),
+        conversation_id,
+        sender_id: sender.to_string(),
+        agent_id: None,
+        content: MessageContent::Text(text),
+        timestamp: chrono::Utc::now(),
+        metadata,
+    })
+}
+
+/// Whether `text` addresses `nickname`, as a word anywhere in the line.
+fn mentions_nick(text: &str, nickname: &str) -> bool {
+    text.split(|c: char| !(c.is_alphanumeric() || "-_[]\\`^{}|".contains(c)))
+        .any(|word| word.eq_ignore_ascii_case(nickname))
+}
+
+/// One protocol line, split into its parts. Message tags are skipped.
+#[derive(Debug, PartialEq, Eq)]
+struct Message<'a> {
+    prefix: Option<&'a str>,
+    command: &'a str,
+    /// Middle parameters followed by the trailing one, if any.
+    params: Vec<&'a str>,
+}
+
+impl Message<'_> {
+    /// The nick from a `nick!user@host` prefix.
+    fn nick(&self) -> Option<&str> {
+        let prefix = self.prefix?;
+        Some(prefix.split_once('!').map_or(prefix, |(nick, _)| nick))
+    }
+}
+
+fn parse_line(line: &str) -> Option<Message<'_>> {
+    let mut rest = line.trim_end_matches(['\r', '\n']);
+    if let Some(tagged) = rest.strip_prefix('@') {
+        rest = tagged.split_once(' ')?.1;
+    }
+    let prefix = match rest.strip_prefix(':') {
+        Some(prefixed) => {
+            let (prefix, remainder) = prefixed.split_once(' ')?;
+            rest = remainder;
+            Some(prefix)
+        }
+        None => None,
+    };
+    let (middle, trailing) = match rest.split_once(" :") {
+        Some((middle, trailing)) => (middle, Some(trailing)),
+        None => (rest, None),
+    };
+
+    let mut words = middle.split(' ').filter(|word| !word.is_empty());
+    let command = words.next()?;
+    let mut params: Vec<&str> = words.collect();
+    params.extend(trailing);
+    Some(Message {
+        prefix,
+        command,
+        params,
+    })
+}
+
+/// Token bucket for outgoing lines: `burst` lines go out back to back, then
+/// one per `interval`.
+#[derive(Debug)]
+struct FloodControl {
+    burst: f64,
+    interval: Duration,
+    /// Lines that may go out now. Negative while lines are waiting.
+    tokens: f64,
+    updated: Instant,
+}
+
+impl FloodControl {
+    fn new(burst: u32, interval: Duration) -> Self {
+        let burst = f64::from(burst.max(1));
+        Self {
+            burst,
+            interval,
+            tokens: burst,
+            updated: Instant::now(),
+        }
+    }
+
+    /// Take a slot for one line and return how long to wait before sending
+    /// it.
+    fn reserve(&mut self, now: Instant) -> Duration {
+        if self.interval.is_zero() {
+            return Duration::ZERO;
+        }
+        let refilled =
+            now.saturating_duration_since(self.updated).as_secs_f64() / self.interval.as_secs_f64();
+        self.tokens = (self.tokens + refilled).min(self.burst) - 1.0;
+        self.updated = now;
+        if self.tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            self.interval.mul_f64(-self.tokens)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_line() {
+        let message =
+            parse_line("@time=2024-01-01T00:00:00Z :alice!~a@host PRIVMSG #ops :deploy: done\r\n")
+                .unwrap();
+        assert_eq!(
+            message,
+            Message {
+                prefix: Some("alice!~a@host"),
+                command: "PRIVMSG",
+                params: vec!["#ops", "deploy: done"],
+            }
+        );
+        assert_eq!(message.nick(), Some("alice"));
+
+        let message = parse_line("PING :irc.example.net").unwrap();
+        assert_eq!(message.command, "PING");
+        assert_eq!(message.params, ["irc.example.net"]);
+
+        let message = parse_line(":server 001 spacebot :Welcome").unwrap();
+        assert_eq!(message.params, ["spacebot", "Welcome"]);
+    }
+
+    #[test]
+    fn test_flood_control_throttles_after_burst() {
+        let start = Instant::now();
+        let mut flood = FloodControl::new(3, Duration::from_secs(2));
+
+        for _ in 0..3 {
+            assert_eq!(flood.reserve(start), Duration::ZERO);
+        }
+        assert_eq!(flood.reserve(start), Duration::from_secs(2));
+        assert_eq!(flood.reserve(start), Duration::from_secs(4));
+
+        // Idle long enough to refill the whole burst.
+        let later = start + Duration::from_secs(30);
+        assert_eq!(flood.reserve(later), Duration::ZERO);
+    }
+
+    #[test]
+    fn test_irc_lines_fit_and_drop_fences() {
+        let long = "word ".repeat(200);
+        let text = format!("Here:\n\n```\nls -la\n```\n{long}");
+
+        let lines = irc_lines(&text);
+
+        assert_eq!(lines[0], "Here:");
+        assert_eq!(lines[1], "ls -la");
+        assert!(lines.len() > 3);
+        assert!(lines.iter().all(|line| line.len() <= IRC_MAX_LENGTH));
+    }
+
+    #[test]
+    fn test_queries_need_allowed_nick() {
+        let config = IrcConfig {
+            enabled: true,
+            server: "irc.example.net".into(),
+            port: 6697,
+            tls: true,
+            nickname: "spacebot".into(),
+            username: "spacebot".into(),
+            realname: "Spacebot".into(),
+            password: None,
+            sasl_username: None,
+            sasl_password: None,
+            channels: vec!["#ops".into()],
+            dm_allowed_users: vec!["Alice".into()],
+            flood_burst: 5,
+            flood_interval_ms: 2000,
+        };
+        let query = |nick: &str| {
+            let line = format!(":{nick}!~u@host PRIVMSG spacebot :hi");
+            inbound_message(&config, "spacebot", &parse_line(&line).unwrap())
+        };
+
+        let inbound = query("alice").unwrap();
+        assert_eq!(inbound.conversation_id, "irc:alice");
+        assert_eq!(inbound.metadata["irc_target"], "alice");
+        assert!(query("mallory").is_none());
+
+        let channel = parse_line(":mallory!~m@host PRIVMSG #Ops :hello").unwrap();
+        let inbound = inbound_message(&config, "spacebot", &channel).unwrap();
+        assert_eq!(inbound.conversation_id, "irc:#ops");
+        assert_eq!(inbound.metadata["irc_target"], "#Ops");
+        assert_eq!(inbound.metadata[MENTIONS_BOT], false);
+        assert_eq!(query("alice").unwrap().metadata[MENTIONS_BOT], true);
+
+        let addressed = parse_line(":mallory!~m@host PRIVMSG #ops :SpaceBot: ping").unwrap();
+        let inbound = inbound_message(&config, "spacebot", &addressed).unwrap();
+        assert_eq!(inbound.metadata[MENTIONS_BOT], true);
+    }
+}
@@ -1,33 +1,150 @@
 //! MessagingManager: Fan-in and routing for all adapters.
+//!
+//! Each adapter's inbound stream is supervised. When it ends while the
+//! adapter is still registered (a dropped gateway or socket connection), the
+//! adapter is started again with exponential backoff instead of going quiet
+//! until the process restarts. Adapters that reconnect on their own opt out
+//! with [`Messaging::restart_on_disconnect`].
 
+use crate::messaging::impersonation;
+use crate::messaging::stream::CoalesceConfig;
 use crate::messaging::traits::{HistoryMessage, InboundStream, Messaging, MessagingDyn};
 use crate::{InboundMessage, OutboundResponse, StatusUpdate};
 
 use anyhow::Context as _;
+use chrono::{DateTime, Utc};
 use futures::StreamExt as _;
+use serde::Serialize;
 use std::collections::HashMap;
-use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 use tokio::sync::{RwLock, mpsc};
 
+/// Delay before the first restart of a disconnected adapter, doubled for
+/// each failed attempt after it.
+const RESTART_BASE_DELAY: Duration = Duration::from_secs(1);
+
+/// Longest wait between restart attempts.
+const RESTART_MAX_DELAY: Duration = Duration::from_secs(5 * 60);
+
+/// How long an adapter's health check may take before it counts as failed.
+const HEALTH_CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+
+type Adapters = Arc<RwLock<HashMap<String, Arc<dyn MessagingDyn>>>>;
+
+/// Where an adapter's connection stands.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AdapterState {
+    /// Registered, not started yet.
+    Starting,
+    Connected,
+    /// Its stream ended and it's waiting to be started again.
+    Reconnecting,
+    /// Running, but its health check failed.
+    Unhealthy,
+}
+
+/// An adapter's health, as reported by `/api/healthz`.
+#[derive(Debug, Clone, Serialize)]
+pub struct AdapterHealth {
+    pub adapter: String,
+    pub state: AdapterState,
+    /// When the adapter entered `state`.
+    pub since: DateTime<Utc>,
+    /// Times the adapter was started again after disconnecting.
+    pub restarts: u64,
+    /// The last disconnect, failed restart or failed health check.
+    pub last_error: Option<String>,
+}
+
+impl AdapterHealth {
+    fn new(adapter: &str) -> Self {
+        Self {
+            adapter: adapter.to_string(),
+            state: AdapterState::Starting,
+            since: Utc::now(),
+            restarts: 0,
+            last_error: None,
+        }
+    }
+
+    fn set_state(&mut self, state: AdapterState) {
+        if self.state != state {
+            self.state = state;
+            self.since = Utc::now();
+        }
+    }
+}
+
+/// Supervision state shared with each adapter's forwarding task.
+#[derive(Clone, Default)]
+struct Supervision {
+    health: Arc<Mutex<HashMap<String, AdapterHealth>>>,
+    shutting_down: Arc<AtomicBool>,
+}
+
+impl Supervision {
+    fn update(&self, name: &str, update: impl FnOnce(&mut AdapterHealth)) {
+        let mut health = self
+            .health
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        update(
+            health
+                .entry(name.to_string())
+                .or_insert_with(|| AdapterHealth::new(name)),
+        );
+    }
+
+    /// Whether `adapter` should still run: it's the one registered as
+    /// `name` and the manager isn't shutting down.
+    async fn is_current(
+        &self,
+        adapters: &Adapters,
+        name: &str,
+        adapter: &Arc<dyn MessagingDyn>,
+    ) -> bool {
+        !self.shutting_down.load(Ordering::Relaxed)
+            && adapters
+                .read()
+                .await
+                .get(name)
+                .is_some_and(|current| Arc::ptr_eq(current, adapter))
+    }
+
+    fn get(&self, name: &str) -> AdapterHealth {
+        self.health
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .get(name)
+            .cloned()
+            .unwrap_or_else(|| AdapterHealth::new(name))
+    }
+}
+
 /// Manages all messaging adapters with support for runtime addition.
 ///
 /// Adapters forward messages into a shared mpsc channel, so new adapters
 /// can be registered after `start()` without replacing the inbound stream.
 pub struct MessagingManager {
-    adapters: RwLock<HashMap<String, Arc<dyn MessagingDyn>>>,
+    adapters: Adapters,
     /// Sender side of the fan-in channel. Cloned for each adapter's forwarding task.
     fan_in_tx: mpsc::Sender<InboundMessage>,
     /// Receiver side, taken once by `start()`.
     fan_in_rx: RwLock<Option<mpsc::Receiver<InboundMessage>>>,
+    supervision: Supervision,
 }
 
 impl MessagingManager {
     pub fn new() -> Self {
         let (fan_in_tx, fan_in_rx) = mpsc::channel(512);
         Self {
-            adapters: RwLock::new(HashMap::new()),
+            adapters: Arc::new(RwLock::new(HashMap::new())),
             fan_in_tx,
             fan_in_rx: RwLock::new(Some(fan_in_rx)),
+            supervision: Supervision::default(),
         }
     }
 
@@ -35,6 +152,8 @@ impl MessagingManager {
     pub async fn register(&self, adapter: impl Messaging) {
         let name = adapter.name().to_string();
         tracing::info!(adapter = %name, "registered messaging adapter");
+        self.supervision
+            .update(&name, |health| *health = AdapterHealth::new(&name));
         self.adapters.write().await.insert(name, Arc::new(adapter));
     }
 
@@ -49,7 +168,7 @@ impl MessagingManager {
                 .start()
                 .await
                 .with_context(|| format!("failed to start adapter '{name}'"))?;
-            Self::spawn_forwarder(name.clone(), stream, self.fan_in_tx.clone());
+            self.spawn_forwarder(name.clone(), adapter.clone(), stream);
         }
         drop(adapters);
 
@@ -90,9 +209,15 @@ impl MessagingManager {
             .start()
             .await
             .with_context(|| format!("failed to start adapter '{name}'"))?;
-        Self::spawn_forwarder(name.clone(), stream, self.fan_in_tx.clone());
 
-        self.adapters.write().await.insert(name.clone(), adapter);
+        // Registered before forwarding, so the forwarder sees the adapter as current.
+        self.supervision
+            .update(&name, |health| *health = AdapterHealth::new(&name));
+        self.adapters
+            .write()
+            .await
+            .insert(name.clone(), adapter.clone());
+        self.spawn_forwarder(name.clone(), adapter, stream);
 
         tracing::info!(adapter = %name, "adapter registered and started at runtime");
         Ok(())
@@ -103,29 +228,115 @@ impl MessagingManager {
         self.adapters.read().await.contains_key(name)
     }
 
-    /// Spawn a task that forwards messages from an adapter stream into the fan-in channel.
-    fn spawn_forwarder(
-        name: String,
-        mut stream: InboundStream,
-        fan_in_tx: mpsc::Sender<InboundMessage>,
-    ) {
+    /// How the named adapter wants streamed replies coalesced. None when it
+    /// can't edit messages or isn't registered.
+    pub async fn stream_coalescing(&self, name: &str) -> Option<CoalesceConfig> {
+        self.adapters.read().await.get(name)?.stream_coalescing()
+    }
+
+    /// Every registered adapter's health, by name. Connected adapters are
+    /// health-checked on the spot.
+    pub async fn health(&self) -> Vec<AdapterHealth> {
+        let adapters: Vec<(String, Arc<dyn MessagingDyn>)> = self
+            .adapters
+            .read()
+            .await
+            .iter()
+            .map(|(name, adapter)| (name.clone(), adapter.clone()))
+            .collect();
+
+        let checks = adapters.into_iter().map(|(name, adapter)| async move {
+            let mut health = self.supervision.get(&name);
+            if health.state != AdapterState::Connected {
+                return health;
+            }
+            let failure =
+                match tokio::time::timeout(HEALTH_CHECK_TIMEOUT, adapter.health_check()).await {
+                    Ok(Ok(())) => return health,
+                    Ok(Err(error)) => error.to_string(),
+                    Err(_) => format!("health check timed out after {HEALTH_CHECK_TIMEOUT:?}"),
+                };
+            health.state = AdapterState::Unhealthy;
+            health.last_error = Some(failure);
+            health
+        });
+        let mut health = futures::future::join_all(checks).await;
+        health.sort_by(|a, b| a.adapter.cmp(&b.adapter));
+        health
+    }
+
+    /// Spawn a task that forwards messages from an adapter stream into the
+    /// fan-in channel, restarting the adapter when the stream ends while it's
+    /// still registered.
+    fn spawn_forwarder(&self, name: String, adapter: Arc<dyn MessagingDyn>, stream: InboundStream) {
+        let adapters = self.adapters.clone();
+        let fan_in_tx = self.fan_in_tx.clone();
+        let supervision = self.supervision.clone();
+
         tokio::spawn(async move {
-            while let Some(message) = stream.next().await {
-                if fan_in_tx.send(message).await.is_err() {
-                    tracing::warn!(adapter = %name, "fan-in channel closed, stopping forwarder");
-                    break;
+            let mut stream = stream;
+            loop {
+                supervision.update(&name, |health| health.set_state(AdapterState::Connected));
+                while let Some(message) = stream.next().await {
+                    if fan_in_tx.send(message).await.is_err() {
+                        tracing::warn!(adapter = %name, "fan-in channel closed, stopping forwarder");
+                        return;
+                    }
                 }
+                tracing::info!(adapter = %name, "adapter stream ended");
+
+                if !adapter.restart_on_disconnect()
+                    || !supervision.is_current(&adapters, &name, &adapter).await
+                {
+                    return;
+                }
+
+                let mut error = "inbound stream ended".to_string();
+                let mut attempt = 0;
+                stream = loop {
+                    let delay = restart_delay(attempt);
+                    supervision.update(&name, |health| {
+                        health.set_state(AdapterState::Reconnecting);
+                        health.last_error = Some(error.clone());
+                    });
+                    tracing::warn!(adapter = %name, %error, ?delay, "restarting disconnected adapter");
+                    tokio::time::sleep(delay).await;
+                    if !supervision.is_current(&adapters, &name, &adapter).await {
+                        return;
+                    }
+                    match adapter.start().await {
+                        Ok(stream) => break stream,
+                        Err(start_error) => error = start_error.to_string(),
+                    }
+                    attempt += 1;
+                };
+                supervision.update(&name, |health| health.restarts += 1);
+                tracing::info!(adapter = %name, "adapter restarted");
             }
-            tracing::info!(adapter = %name, "adapter stream ended");
         });
     }
 
+    /// Feed a message into the inbound stream as if an adapter had received
+    /// it, e.g. one an operator sends through the API.
+    pub async fn inject(&self, message: InboundMessage) -> crate::Result<()> {
+        self.fan_in_tx
+            .send(message)
+            .await
+            .context("inbound stream is closed")?;
+        Ok(())
+    }
+
     /// Route a response back to the correct adapter based on message source.
+    /// Responses in sandboxed conversations go nowhere.
     pub async fn respond(
         &self,
         message: &InboundMessage,
         response: OutboundResponse,
     ) -> crate::Result<()> {
+        if impersonation::is_sandboxed(&message.conversation_id) {
+            tracing::debug!(conversation_id = %message.conversation_id, "response kept in sandbox");
+            return Ok(());
+        }
         let adapters = self.adapters.read().await;
         let adapter = adapters
             .get(&message.source)
@@ -139,6 +350,9 @@ impl MessagingManager {
         message: &InboundMessage,
         status: StatusUpdate,
     ) -> crate::Result<()> {
+        if impersonation::is_sandboxed(&message.conversation_id) {
+            return Ok(());
+        }
         let adapters = self.adapters.read().await;
         let adapter = adapters
             .get(&message.source)
@@ -146,13 +360,18 @@ impl MessagingManager {
         adapter.send_status(message, status).await
     }
 
-    /// Send a proactive message through a specific adapter.
+    /// Send a proactive message through a specific adapter. Messages to a
+    /// sandboxed conversation go nowhere.
     pub async fn broadcast(
         &self,
         adapter_name: &str,
         target: &str,
         response: OutboundResponse,
     ) -> crate::Result<()> {
+        if impersonation::is_sandboxed(&format!("{adapter_name}:{target}")) {
+            tracing::debug!(%adapter_name, %target, "broadcast kept in sandbox");
+            return Ok(());
+        }
         let adapters = self.adapters.read().await;
         let adapter = adapters
             .get(adapter_name)
@@ -173,8 +392,27 @@ impl MessagingManager {
         adapter.fetch_history(message, limit).await
     }
 
+    /// Fetch what was posted to a broadcast target since `since`, oldest
+    /// first, for digests.
+    pub async fn fetch_channel_history(
+        &self,
+        adapter_name: &str,
+        target: &str,
+        since: chrono::DateTime<chrono::Utc>,
+        limit: usize,
+    ) -> crate::Result<Vec<HistoryMessage>> {
+        let adapters = self.adapters.read().await;
+        let adapter = adapters
+            .get(adapter_name)
+            .with_context(|| format!("no messaging adapter named '{adapter_name}'"))?;
+        adapter.fetch_channel_history(target, since, limit).await
+    }
+
     /// Shut down all adapters gracefully.
     pub async fn shutdown(&self) {
+        self.supervision
+            .shutting_down
+            .store(true, Ordering::Relaxed);
         let adapters = self.adapters.read().await;
         for (name, adapter) in adapters.iter() {
             if let Err(error) = adapter.shutdown().await {
@@ -189,3 +427,87 @@ impl Default for MessagingManager {
         Self::new()
     }
 }
+
+/// How long to wait before restart attempt `attempt` (counting from 0).
+fn restart_delay(attempt: u32) -> Duration {
+    RESTART_BASE_DELAY
+        .saturating_mul(2u32.saturating_pow(attempt))
+        .min(RESTART_MAX_DELAY)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MessageContent;
+
+    /// An adapter whose every start yields one message and then disconnects.
+    struct FlakyAdapter {
+        starts: Arc<std::sync::atomic::AtomicUsize>,
+    }
+
+    impl Messaging for FlakyAdapter {
+        fn name(&self) -> &str {
+            "flaky"
+        }
+
+        async fn start(&self) -> crate::Result<InboundStream> {
+            let start = self.starts.fetch_add(1, Ordering::Relaxed);
+            let message = InboundMessage {
+                id: start.to_string(),
+                source: "flaky".into(),
+                conversation_id: "flaky:1".into(),
+                sender_id: "42".into(),
+                agent_id: None,
+                content: MessageContent::Text(format!("start {start}")),
+                timestamp: Utc::now(),
+                metadata: Default::default(),
+            };
+            Ok(Box::pin(futures::stream::iter([message])))
+        }
+
+        async fn respond(&self, _: &InboundMessage, _: OutboundResponse) -> crate::Result<()> {
+            Ok(())
+        }
+
+        async fn health_check(&self) -> crate::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_restart_delay_backs_off_to_a_cap() {
+        assert_eq!(restart_delay(0), Duration::from_secs(1));
+        assert_eq!(restart_delay(3), Duration::from_secs(8));
+        assert_eq!(restart_delay(40), RESTART_MAX_DELAY);
+    }
+
+    #[tokio::test]
+    async fn test_disconnected_adapter_is_restarted() {
+        let starts = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let manager = MessagingManager::new();
+        manager
+            .register(FlakyAdapter {
+                starts: starts.clone(),
+            })
+            .await;
+        let mut inbound = manager.start().await.unwrap();
+
+        for expected in ["start 0", "start 1"] {
+            let message = inbound.next().await.unwrap();
+            let MessageContent::Text(text) = message.content else {
+                panic!("expected text");
+            };
+            assert_eq!(text, expected);
+        }
+
+        let health = manager.health().await;
+        assert_eq!(health.len(), 1);
+        assert!(health[0].restarts >= 1);
+        assert_eq!(
+            health[0].last_error.as_deref(),
+            Some("inbound stream ended")
+        );
+
+        manager.shutdown().await;
+    }
+}
@@ -0,0 +1,216 @@
+//! Local notification sink for single-machine deployments.
+//!
+//! A delivery-only adapter: cron jobs, feed watchers and other deliveries
+//! addressed to `notify:{title}` are appended to a markdown inbox file and,
+//! with the `desktop-notifications` feature, shown as desktop
+//! notifications. There's nothing to reply to, so it produces no inbound
+//! messages.
+
+use crate::config::NotifyConfig;
+use crate::messaging::traits::{InboundStream, Messaging};
+use crate::{InboundMessage, OutboundResponse};
+
+use anyhow::Context as _;
+use tokio::io::AsyncWriteExt as _;
+
+use std::path::PathBuf;
+use tokio::sync::Mutex;
+
+/// Characters of the text shown in a desktop notification.
+const PREVIEW_LENGTH: usize = 200;
+
+/// Notify adapter state.
+pub struct NotifyAdapter {
+    desktop: bool,
+    app_name: String,
+    inbox: PathBuf,
+    /// Serializes appends so entries never interleave.
+    write_lock: Mutex<()>,
+}
+
+impl NotifyAdapter {
+    pub fn new(config: &NotifyConfig) -> Self {
+        if config.desktop && !cfg!(feature = "desktop-notifications") {
+            tracing::warn!(
+                "built without the desktop-notifications feature, deliveries only go to the inbox"
+            );
+        }
+        Self {
+            desktop: config.desktop,
+            app_name: config.app_name.clone(),
+            inbox: config.inbox.clone(),
+            write_lock: Mutex::new(()),
+        }
+    }
+
+    async fn deliver(&self, title: &str, response: OutboundResponse) -> anyhow::Result<()> {
+        let text = match response {
+            OutboundResponse::Text(text) | OutboundResponse::ThreadReply { text, .. } => text,
+            OutboundResponse::File {
+                filename,
+                data,
+                caption,
+                ..
+            } => {
+                let path = self.save_file(&filename, &data).await?;
+                let link = format!("[{filename}]({})", path.display());
+                match caption {
+                    Some(caption) => format!("{caption}\n\n{link}"),
+                    None => link,
+                }
+            }
+            OutboundResponse::Reaction(_)
+            | OutboundResponse::Status(_)
+            | OutboundResponse::StreamStart
+            | OutboundResponse::StreamChunk(_)
+            | OutboundResponse::StreamEnd => return Ok(()),
+        };
+
+        self.append(&inbox_entry(title, &text, chrono::Local::now()))
+            .await?;
+        if self.desktop {
+            show_notification(&self.app_name, title, &preview(&text)).await;
+        }
+        Ok(())
+    }
+
+    async fn append(&self, entry: &str) -> anyhow::Result<()> {
+        let _guard = self.write_lock.lock().await;
+        if let Some(parent) = self.inbox.parent() {
+            tokio::fs::create_dir_all(parent)
+                .await
+                .with_context(|| format!("failed to create {}", parent.display()))?;
+        }
+        let mut file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.inbox)
+            .await
+            .with_context(|| format!("failed to open {}", self.inbox.display()))?;
+        file.write_all(entry.as_bytes())
+            .await
+            .with_context(|| format!("failed to write {}", self.inbox.display()))?;
+        Ok(())
+    }
+
+    /// Save a delivered file next to the inbox and return its path.
+    async fn save_file(&self, filename: &str, data: &[u8]) -> anyhow::Result<PathBuf> {
+        let directory = self.inbox.with_extension("files");
+        tokio::fs::create_dir_all(&directory)
+            .await
+            .with_context(|| format!("failed to create {}", directory.display()))?;
+        // Keep only the last component so a filename can't escape the
+        // directory.
+        let name = std::path::Path::new(filename)
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "attachment".into());
+        let path = directory.join(format!(
+            "{}-{name}",
+            chrono::Local::now().format("%Y%m%d-%H%M%S")
+        ));
+        tokio::fs::write(&path, data)
+            .await
+            .with_context(|| format!("failed to write {}", path.display()))?;
+        Ok(path)
+    }
+}
+
+/// One inbox entry: a dated heading with the title, then the text.
+fn inbox_entry(title: &str, text: &str, at: chrono::DateTime<chrono::Local>) -> String {
+    format!(
+        "## {} · {title}\n\n{}\n\n",
+        at.format("%Y-%m-%d %H:%M"),
+        text.trim()
+    )
+}
+
+/// The start of `text` on one line, for the notification body.
+fn preview(text: &str) -> String {
+    let flat = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    match flat.char_indices().nth(PREVIEW_LENGTH) {
+        Some((end, _)) => format!("{}…", flat[..end].trim_end()),
+        None => flat,
+    }
+}
+
+#[cfg(feature = "desktop-notifications")]
+async fn show_notification(app_name: &str, title: &str, body: &str) {
+    let mut notification = notify_rust::Notification::new();
+    notification.appname(app_name).summary(title).body(body);
+    // Talking to the notification daemon blocks.
+    let result = tokio::task::spawn_blocking(move || notification.show().map(|_| ())).await;
+    match result {
+        Ok(Ok(())) => {}
+        Ok(Err(error)) => tracing::warn!(%error, "failed to show desktop notification"),
+        Err(error) => tracing::warn!(%error, "desktop notification task failed"),
+    }
+}
+
+#[cfg(not(feature = "desktop-notifications"))]
+async fn show_notification(_app_name: &str, _title: &str, _body: &str) {}
+
+impl Messaging for NotifyAdapter {
+    fn name(&self) -> &str {
+        "notify"
+    }
+
+    async fn start(&self) -> crate::Result<InboundStream> {
+        tracing::info!(inbox = %self.inbox.display(), "notify adapter ready");
+        Ok(Box::pin(futures::stream::empty()))
+    }
+
+    async fn respond(
+        &self,
+        message: &InboundMessage,
+        response: OutboundResponse,
+    ) -> crate::Result<()> {
+        // Nothing arrives through this adapter, but reply to whatever was
+        // routed here as a delivery titled after its conversation.
+        self.deliver(&message.conversation_id, response).await?;
+        Ok(())
+    }
+
+    async fn broadcast(&self, target: &str, response: OutboundResponse) -> crate::Result<()> {
+        self.deliver(target, response).await?;
+        Ok(())
+    }
+
+    async fn health_check(&self) -> crate::Result<()> {
+        Ok(())
+    }
+
+    fn restart_on_disconnect(&self) -> bool {
+        // Nothing is ever received, so the stream ends right away.
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::TimeZone as _;
+
+    #[test]
+    fn test_inbox_entry_format() {
+        let at = chrono::Local
+            .with_ymd_and_hms(2025, 3, 14, 9, 30, 0)
+            .unwrap();
+
+        assert_eq!(
+            inbox_entry("Morning briefing", "\nThree new issues.\n", at),
+            "## 2025-03-14 09:30 · Morning briefing\n\nThree new issues.\n\n"
+        );
+    }
+
+    #[test]
+    fn test_preview_flattens_and_truncates() {
+        assert_eq!(preview("Line one.\n\nLine  two."), "Line one. Line two.");
+
+        let long = "é".repeat(300);
+        let preview = preview(&long);
+        assert_eq!(preview.chars().count(), PREVIEW_LENGTH + 1);
+        assert!(preview.ends_with('…'));
+    }
+}
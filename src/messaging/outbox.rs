@@ -0,0 +1,324 @@
+//! Persistent outbox for replies a messaging adapter failed to deliver.
+//!
+//! When sending a reply fails (a platform outage, a rate limit, an adapter
+//! that's reconnecting), the response is written to the agent's `outbox`
+//! table under a dedup key instead of being dropped. A per-agent loop retries
+//! due entries with exponential backoff, oldest first, and deletes each one
+//! as soon as the adapter accepts it. While a conversation has entries
+//! waiting, its new replies queue behind them, so the user still reads them
+//! in order. Entries survive restarts; one that was sent just before a crash
+//! but not yet deleted is sent again, so delivery is at least once.
+
+use crate::error::Result;
+use crate::messaging::MessagingManager;
+use crate::{InboundMessage, OutboundResponse};
+
+use anyhow::Context as _;
+use sqlx::{Row as _, SqlitePool};
+
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Delivery attempts per entry, the failed send that queued it included.
+const MAX_ATTEMPTS: i64 = 12;
+
+/// Wait before the first retry, doubled for each one after.
+const RETRY_BASE_DELAY_SECS: i64 = 5;
+
+/// Longest wait between retries.
+const RETRY_MAX_DELAY_SECS: i64 = 30 * 60;
+
+/// How often the loop looks for due entries.
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// A queued reply.
+#[derive(Debug, Clone)]
+struct OutboxEntry {
+    id: String,
+    channel_id: String,
+    message: InboundMessage,
+    response: OutboundResponse,
+    attempts: i64,
+    due: bool,
+}
+
+/// One agent's queue of undelivered replies.
+#[derive(Debug, Clone)]
+pub struct Outbox {
+    pool: SqlitePool,
+}
+
+impl Outbox {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+
+    /// Whether `response` can be queued. Streaming and status updates only
+    /// make sense in the moment and are never retried.
+    pub fn accepts(response: &OutboundResponse) -> bool {
+        matches!(
+            response,
+            OutboundResponse::Text(_)
+                | OutboundResponse::ThreadReply { .. }
+                | OutboundResponse::File { .. }
+                | OutboundResponse::Reaction(_)
+        )
+    }
+
+    /// Queue `response` to `message`'s conversation. `error` is why sending
+    /// it failed, or None when it's queued behind earlier entries without
+    /// being tried. Returns the entry's dedup key.
+    pub async fn enqueue(
+        &self,
+        message: &InboundMessage,
+        response: &OutboundResponse,
+        error: Option<&str>,
+    ) -> Result<String> {
+        let id = uuid::Uuid::new_v4().to_string();
+        let attempts = i64::from(error.is_some());
+        sqlx::query(
+            "INSERT OR IGNORE INTO outbox \
+             (id, channel_id, message, response, attempts, last_error, next_attempt_at) \
+             VALUES (?, ?, ?, ?, ?, ?, datetime('now', ?))",
+        )
+        .bind(&id)
+        .bind(&message.conversation_id)
+        .bind(serde_json::to_string(message).context("failed to serialize message")?)
+        .bind(serde_json::to_string(response).context("failed to serialize response")?)
+        .bind(attempts)
+        .bind(error)
+        .bind(format!("+{} seconds", retry_delay_secs(attempts)))
+        .execute(&self.pool)
+        .await
+        .context("failed to queue outbound response")?;
+        Ok(id)
+    }
+
+    /// Whether `channel_id` has replies waiting, which new ones must queue behind.
+    pub async fn has_pending(&self, channel_id: &str) -> Result<bool> {
+        let pending: i64 = sqlx::query_scalar(
+            "SELECT EXISTS(SELECT 1 FROM outbox WHERE status = 'pending' AND channel_id = ?)",
+        )
+        .bind(channel_id)
+        .fetch_one(&self.pool)
+        .await
+        .context("failed to check outbox")?;
+        Ok(pending != 0)
+    }
+
+    /// Try each conversation's oldest waiting entry, and the ones after it
+    /// while they go through. Returns how many were delivered.
+    pub async fn drain(&self, messaging: &MessagingManager) -> Result<usize> {
+        let mut blocked = HashSet::new();
+        let mut delivered = 0;
+
+        for entry in self.pending().await? {
+            if blocked.contains(&entry.channel_id) {
+                continue;
+            }
+            if !entry.due {
+                blocked.insert(entry.channel_id);
+                continue;
+            }
+
+            match messaging
+                .respond(&entry.message, entry.response.clone())
+                .await
+            {
+                Ok(()) => {
+                    self.delete(&entry.id).await?;
+                    delivered += 1;
+                }
+                Err(error) => {
+                    self.record_failure(&entry, &error.to_string()).await?;
+                    blocked.insert(entry.channel_id);
+                }
+            }
+        }
+
+        Ok(delivered)
+    }
+
+    /// Waiting entries in the order they were queued.
+    async fn pending(&self) -> Result<Vec<OutboxEntry>> {
+        let rows = sqlx::query(
+            "SELECT id, channel_id, message, response, attempts, \
+             next_attempt_at <= datetime('now') AS due \
+             FROM outbox WHERE status = 'pending' ORDER BY rowid",
+        )
+        .fetch_all(&self.pool)
+        .await
+        .context("failed to load outbox")?;
+
+        let mut entries = Vec::with_capacity(rows.len());
+        for row in rows {
+            let id: String = row.try_get("id").unwrap_or_default();
+            let message: String = row.try_get("message").unwrap_or_default();
+            let response: String = row.try_get("response").unwrap_or_default();
+            let message = serde_json::from_str::<InboundMessage>(&message);
+            let response = serde_json::from_str::<OutboundResponse>(&response);
+            let (Ok(message), Ok(response)) = (message, response) else {
+                // Written by a version with a different message format.
+                tracing::warn!(outbox_id = %id, "dropping unreadable outbox entry");
+                self.delete(&id).await?;
+                continue;
+            };
+            entries.push(OutboxEntry {
+                id,
+                channel_id: row.try_get("channel_id").unwrap_or_default(),
+                message,
+                response,
+                attempts: row.try_get("attempts").unwrap_or_default(),
+                due: row.try_get("due").unwrap_or_default(),
+            });
+        }
+        Ok(entries)
+    }
+
+    async fn delete(&self, id: &str) -> Result<()> {
+        sqlx::query("DELETE FROM outbox WHERE id = ?")
+            .bind(id)
+            .execute(&self.pool)
+            .await
+            .context("failed to delete outbox entry")?;
+        Ok(())
+    }
+
+    /// Schedule the entry's next attempt, or give up on it after the last.
+    async fn record_failure(&self, entry: &OutboxEntry, error: &str) -> Result<()> {
+        let attempts = entry.attempts + 1;
+        let status = if attempts >= MAX_ATTEMPTS {
+            tracing::error!(
+                outbox_id = %entry.id,
+                channel_id = %entry.channel_id,
+                attempts,
+                %error,
+                "giving up on outbound response"
+            );
+            "failed"
+        } else {
+            tracing::warn!(
+                outbox_id = %entry.id,
+                channel_id = %entry.channel_id,
+                attempts,
+                %error,
+                "outbound response retry failed"
+            );
+            "pending"
+        };
+
+        sqlx::query(
+            "UPDATE outbox SET status = ?, attempts = ?, last_error = ?, \
+             next_attempt_at = datetime('now', ?) WHERE id = ?",
+        )
+        .bind(status)
+        .bind(attempts)
+        .bind(error)
+        .bind(format!("+{} seconds", retry_delay_secs(attempts)))
+        .bind(&entry.id)
+        .execute(&self.pool)
+        .await
+        .context("failed to update outbox entry")?;
+        Ok(())
+    }
+}
+
+/// Spawn the loop that retries an agent's queued replies.
+///
+/// Runs until the returned JoinHandle is aborted.
+pub fn spawn_outbox_loop(
+    outbox: Outbox,
+    messaging: Arc<MessagingManager>,
+) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        loop {
+            match outbox.drain(&messaging).await {
+                Ok(0) => {}
+                Ok(count) => tracing::info!(count, "delivered queued outbound responses"),
+                Err(error) => tracing::error!(%error, "outbox pass failed"),
+            }
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+    })
+}
+
+/// Seconds to wait after `attempts` failed attempts.
+fn retry_delay_secs(attempts: i64) -> i64 {
+    if attempts == 0 {
+        return 0;
+    }
+    let doublings = (attempts - 1).min(20) as u32;
+    (RETRY_BASE_DELAY_SECS << doublings).min(RETRY_MAX_DELAY_SECS)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MessageContent;
+
+    async fn outbox() -> Outbox {
+        Outbox::new(crate::db::connect_in_memory().await)
+    }
+
+    fn message(conversation_id: &str) -> InboundMessage {
+        InboundMessage {
+            id: "1".into(),
+            source: "discord".into(),
+            conversation_id: conversation_id.into(),
+            sender_id: "42".into(),
+            agent_id: None,
+            content: MessageContent::Text("hi".into()),
+            timestamp: chrono::Utc::now(),
+            metadata: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_retry_delay_backs_off_to_a_cap() {
+        assert_eq!(retry_delay_secs(0), 0);
+        assert_eq!(retry_delay_secs(1), 5);
+        assert_eq!(retry_delay_secs(4), 40);
+        assert_eq!(retry_delay_secs(MAX_ATTEMPTS), RETRY_MAX_DELAY_SECS);
+    }
+
+    #[test]
+    fn test_only_final_responses_are_queued() {
+        assert!(Outbox::accepts(&OutboundResponse::Text("hi".into())));
+        assert!(!Outbox::accepts(&OutboundResponse::StreamChunk("h".into())));
+        assert!(!Outbox::accepts(&OutboundResponse::Status(
+            crate::StatusUpdate::Thinking
+        )));
+    }
+
+    #[tokio::test]
+    async fn test_failed_entries_wait_and_block_their_channel() {
+        let outbox = outbox().await;
+        let failed = OutboundResponse::Text("first".into());
+        outbox
+            .enqueue(&message("discord:1:2"), &failed, Some("HTTP 503"))
+            .await
+            .unwrap();
+        outbox
+            .enqueue(
+                &message("discord:1:2"),
+                &OutboundResponse::Text("second".into()),
+                None,
+            )
+            .await
+            .unwrap();
+        assert!(outbox.has_pending("discord:1:2").await.unwrap());
+        assert!(!outbox.has_pending("discord:1:3").await.unwrap());
+
+        let pending = outbox.pending().await.unwrap();
+        assert_eq!(pending.len(), 2);
+        assert!(!pending[0].due, "a failed send waits before its retry");
+        assert_eq!(pending[0].attempts, 1);
+        assert!(pending[1].due);
+
+        // The waiting first entry holds back the second, so nothing is sent.
+        let messaging = MessagingManager::new();
+        assert_eq!(outbox.drain(&messaging).await.unwrap(), 0);
+        assert_eq!(outbox.pending().await.unwrap().len(), 2);
+    }
+}
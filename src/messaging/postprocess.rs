@@ -0,0 +1,415 @@
+//! Output post-processing: per-channel rewrites of final assistant text.
+//!
+//! Bindings list the processors to run on a channel's outbound text, in
+//! order. Only `Text` and `ThreadReply` responses are rewritten; streaming
+//! chunks, files, reactions and status updates pass through unchanged.
+//! Code (fenced blocks and inline spans) is never rewritten, except by
+//! `extract_code`, which moves it out of the message entirely. A binding's
+//! [`Disclosure`] adds a footer after the processors have run.
+
+use crate::conversation::ReplyAttribution;
+use crate::messaging::format::{Platform, convert_markdown, map_prose};
+use crate::{InboundMessage, OutboundResponse};
+
+use regex::Regex;
+use serde::Deserialize;
+
+use std::sync::LazyLock;
+
+/// Metadata flag adapters check before sending, for platforms where link
+/// previews are an API option rather than something the text can express.
+pub const SUPPRESS_LINK_PREVIEWS: &str = "suppress_link_previews";
+
+/// Fenced blocks with at least this many lines are moved to attachments.
+const EXTRACT_CODE_MIN_LINES: usize = 20;
+
+/// One step of a channel's output pipeline.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, schemars::JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum PostProcessor {
+    /// Rewrite markdown into the platform's dialect: mrkdwn on Slack, plain
+    /// text on Telegram. Discord renders markdown natively.
+    Markdown,
+    /// Send long fenced code blocks as file attachments instead of inline.
+    ExtractCode,
+    /// Stop the platform from expanding links into previews.
+    SuppressUnfurls,
+    /// Mask common profanity with asterisks.
+    MaskProfanity,
+}
+
+/// An AI-disclosure footer for a channel's replies, for platforms and
+/// communities that require AI-generated content to be labelled.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, schemars::JsonSchema)]
+pub struct Disclosure {
+    /// Fixed text, e.g. "Written by an AI assistant."
+    #[serde(default)]
+    pub text: Option<String>,
+    /// Name the model that wrote the reply.
+    #[serde(default)]
+    pub model: bool,
+    /// Show the estimated cost of the completion that wrote the reply, for
+    /// models with a price.
+    #[serde(default)]
+    pub cost: bool,
+}
+
+impl Disclosure {
+    /// The footer for a reply written by `origin`, or None if there's
+    /// nothing to add.
+    pub fn footer(&self, origin: Option<&ReplyAttribution>) -> Option<String> {
+        let text = self
+            .text
+            .as_deref()
+            .map(str::trim)
+            .filter(|text| !text.is_empty())
+            .map(String::from);
+        let model = origin
+            .and_then(|origin| origin.model.clone())
+            .filter(|_| self.model);
+        let cost = origin
+            .and_then(|origin| origin.cost_usd)
+            .filter(|_| self.cost)
+            .map(|cost| format!("${cost:.4}"));
+        let parts: Vec<String> = [text, model, cost].into_iter().flatten().collect();
+        (!parts.is_empty()).then(|| parts.join(" · "))
+    }
+}
+
+/// The processors configured for one conversation.
+#[derive(Debug, Clone)]
+pub struct OutputPipeline {
+    platform: String,
+    processors: Vec<PostProcessor>,
+    disclosure: Option<Disclosure>,
+}
+
+impl OutputPipeline {
+    pub fn new(platform: impl Into<String>, processors: Vec<PostProcessor>) -> Self {
+        Self {
+            platform: platform.into(),
+            processors,
+            disclosure: None,
+        }
+    }
+
+    /// Append a disclosure footer to every reply.
+    pub fn with_disclosure(mut self, disclosure: Option<Disclosure>) -> Self {
+        self.disclosure = disclosure;
+        self
+    }
+
+    /// Mark the message adapters reply to with any hints they need to honor
+    /// this pipeline when sending.
+    pub fn annotate(&self, message: &mut InboundMessage) {
+        if self.processors.contains(&PostProcessor::SuppressUnfurls) {
+            message
+                .metadata
+                .insert(SUPPRESS_LINK_PREVIEWS.into(), serde_json::Value::Bool(true));
+        }
+    }
+
+    /// Run the pipeline over a response. `origin` is the completion behind
+    /// it, for the disclosure footer. Extracted code blocks follow the
+    /// rewritten text as `File` responses.
+    pub fn apply(
+        &self,
+        response: OutboundResponse,
+        origin: Option<&ReplyAttribution>,
+    ) -> Vec<OutboundResponse> {
+        if self.processors.is_empty() && self.disclosure.is_none() {
+            return vec![response];
+        }
+
+        let footer = self
+            .disclosure
+            .as_ref()
+            .and_then(|disclosure| disclosure.footer(origin));
+        let mut attachments = Vec::new();
+        let mut finish = |text| {
+            let text = self.process_text(text, &mut attachments);
+            match &footer {
+                Some(footer) => self.append_footer(text, footer),
+                None => text,
+            }
+        };
+        let response = match response {
+            OutboundResponse::Text(text) => OutboundResponse::Text(finish(text)),
+            OutboundResponse::ThreadReply { thread_name, text } => OutboundResponse::ThreadReply {
+                thread_name,
+                text: finish(text),
+            },
+            other => return vec![other],
+        };
+
+        let mut responses = vec![response];
+        responses.extend(attachments);
+        responses
+    }
+
+    fn process_text(&self, mut text: String, attachments: &mut Vec<OutboundResponse>) -> String {
+        for processor in &self.processors {
+            text = match processor {
+                PostProcessor::Markdown => match Platform::from_name(&self.platform) {
+                    Some(platform) => convert_markdown(&text, platform),
+                    None => text,
+                },
+                PostProcessor::ExtractCode => extract_code_blocks(&text, attachments),
+                // Discord drops the embed for links wrapped in angle brackets.
+                // Elsewhere the adapter disables previews via `annotate`.
+                PostProcessor::SuppressUnfurls if self.platform == "discord" => {
+                    map_prose(&text, suppress_discord_embeds)
+                }
+                PostProcessor::SuppressUnfurls => text,
+                PostProcessor::MaskProfanity => map_prose(&text, mask_profanity),
+            };
+        }
+        text
+    }
+
+    /// Discord shows the footer as small subtext. Elsewhere it's a plain
+    /// last line.
+    fn append_footer(&self, text: String, footer: &str) -> String {
+        let text = text.trim_end();
+        if self.platform == "discord" {
+            format!("{text}\n-# {footer}")
+        } else {
+            format!("{text}\n\n{footer}")
+        }
+    }
+}
+
+/// Whether replies to this message should be sent without link previews.
+pub fn link_previews_suppressed(message: &InboundMessage) -> bool {
+    message
+        .metadata
+        .get(SUPPRESS_LINK_PREVIEWS)
+        .and_then(|value| value.as_bool())
+        .unwrap_or(false)
+}
+
+static MD_LINK_TARGET: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\]\((https?://[^)\s]+)\)").expect("valid regex"));
+static BARE_URL: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(^|[\s(])(https?://[^\s<>()]+)").expect("valid regex"));
+
+fn suppress_discord_embeds(text: &str) -> String {
+    let text = MD_LINK_TARGET.replace_all(text, "](<$1>)");
+    BARE_URL.replace_all(&text, "$1<$2>").into_owned()
+}
+
+static PROFANITY: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"(?i)\b(?:(?:mother)?fuck\w*|shit\w*|cunt\w*|bitch(?:es|y)?|bastards?|assholes?|dick(?:s|head)?)\b",
+    )
+    .expect("valid regex")
+});
+
+/// Keep the first letter so the text stays readable.
+fn mask_profanity(text: &str) -> String {
+    PROFANITY
+        .replace_all(text, |captures: &regex::Captures| {
+            let word = &captures[0];
+            let mut chars = word.chars();
+            let first = chars.next().map(String::from).unwrap_or_default();
+            first + &"*".repeat(chars.count())
+        })
+        .into_owned()
+}
+
+/// Move long fenced blocks into attachments, leaving a pointer in the text.
+fn extract_code_blocks(text: &str, attachments: &mut Vec<OutboundResponse>) -> String {
+    let mut output = String::with_capacity(text.len());
+    let mut block: Option<(String, Vec<&str>)> = None;
+    let mut opening = "";
+
+    for line in text.split_inclusive('\n') {
+        let fence = line.trim_start().strip_prefix("```");
+        match (&mut block, fence) {
+            (None, Some(language)) => {
+                block = Some((language.trim().to_string(), Vec::new()));
+                opening = line;
+            }
+            (None, None) => output.push_str(line),
+            (Some(_), Some(_)) => {
+                let Some((language, lines)) = block.take() else {
+                    continue;
+                };
+                if lines.len() < EXTRACT_CODE_MIN_LINES {
+                    output.push_str(opening);
+                    output.extend(lines);
+                    output.push_str(line);
+                    continue;
+                }
+                let filename = format!(
+                    "snippet-{}.{}",
+                    attachments.len() + 1,
+                    file_extension(&language)
+                );
+                output.push_str(&format!("(attached {filename})\n"));
+                attachments.push(OutboundResponse::File {
+                    filename,
+                    data: lines.concat().into_bytes(),
+                    mime_type: "text/plain".into(),
+                    caption: None,
+                });
+            }
+            (Some((_, lines)), None) => lines.push(line),
+        }
+    }
+
+    // An unclosed fence is left inline as written.
+    if let Some((_, lines)) = block {
+        output.push_str(opening);
+        output.extend(lines);
+    }
+    output
+}
+
+fn file_extension(language: &str) -> &'static str {
+    match language.to_ascii_lowercase().as_str() {
+        "rust" | "rs" => "rs",
+        "python" | "py" => "py",
+        "javascript" | "js" => "js",
+        "typescript" | "ts" => "ts",
+        "tsx" => "tsx",
+        "jsx" => "jsx",
+        "go" | "golang" => "go",
+        "java" => "java",
+        "kotlin" | "kt" => "kt",
+        "swift" => "swift",
+        "c" => "c",
+        "cpp" | "c++" => "cpp",
+        "csharp" | "cs" => "cs",
+        "ruby" | "rb" => "rb",
+        "php" => "php",
+        "bash" | "sh" | "shell" | "zsh" => "sh",
+        "sql" => "sql",
+        "html" => "html",
+        "css" => "css",
+        "json" => "json",
+        "yaml" | "yml" => "yaml",
+        "toml" => "toml",
+        "xml" => "xml",
+        "markdown" | "md" => "md",
+        "diff" | "patch" => "diff",
+        _ => "txt",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn apply_text(platform: &str, processors: Vec<PostProcessor>, text: &str) -> Vec<String> {
+        OutputPipeline::new(platform, processors)
+            .apply(OutboundResponse::Text(text.into()), None)
+            .into_iter()
+            .map(|response| match response {
+                OutboundResponse::Text(text) => text,
+                OutboundResponse::File { filename, data, .. } => {
+                    format!("{filename}:{}", String::from_utf8_lossy(&data))
+                }
+                other => panic!("unexpected response: {other:?}"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_markdown_to_slack_leaves_code_alone() {
+        let output = apply_text(
+            "slack",
+            vec![PostProcessor::Markdown],
+            "## Result\n**done**, see [docs](https://example.com) and `**raw**`\n```\n**kept**\n```\n",
+        );
+        assert_eq!(
+            output,
+            [
+                "*Result*\n*done*, see <https://example.com|docs> and `**raw**`\n```\n**kept**\n```\n"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_extract_code_moves_long_blocks_only() {
+        let long: String = (0..EXTRACT_CODE_MIN_LINES)
+            .map(|i| format!("line {i}\n"))
+            .collect();
+        let text = format!("short:\n```sh\nls\n```\nlong:\n```rust\n{long}```\ndone");
+        let output = apply_text("discord", vec![PostProcessor::ExtractCode], &text);
+
+        assert_eq!(
+            output[0],
+            "short:\n```sh\nls\n```\nlong:\n(attached snippet-1.rs)\ndone"
+        );
+        assert_eq!(output[1], format!("snippet-1.rs:{long}"));
+    }
+
+    #[test]
+    fn test_suppress_unfurls_on_discord() {
+        let output = apply_text(
+            "discord",
+            vec![PostProcessor::SuppressUnfurls],
+            "see https://example.com/a and [b](https://example.com/b) (https://example.com/c)",
+        );
+        assert_eq!(
+            output,
+            [
+                "see <https://example.com/a> and [b](<https://example.com/b>) (<https://example.com/c>)"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_mask_profanity() {
+        let output = apply_text(
+            "telegram",
+            vec![PostProcessor::MaskProfanity],
+            "Well, Shit. That's a dickens of a bitchy `shit` problem",
+        );
+        assert_eq!(
+            output,
+            ["Well, S***. That's a dickens of a b***** `shit` problem"]
+        );
+    }
+
+    #[test]
+    fn test_disclosure_footer() {
+        let disclosure = Disclosure {
+            text: Some("Written by an AI assistant.".into()),
+            model: true,
+            cost: true,
+        };
+        let origin = ReplyAttribution {
+            model: Some("anthropic/claude-sonnet-4".into()),
+            cost_usd: Some(0.01234),
+            ..ReplyAttribution::default()
+        };
+
+        let pipeline = OutputPipeline::new("slack", Vec::new()).with_disclosure(Some(disclosure));
+        let output = pipeline.apply(OutboundResponse::Text("Done.\n".into()), Some(&origin));
+        let [OutboundResponse::Text(text)] = output.as_slice() else {
+            panic!("unexpected responses: {output:?}");
+        };
+        assert_eq!(
+            text,
+            "Done.\n\nWritten by an AI assistant. · anthropic/claude-sonnet-4 · $0.0123"
+        );
+
+        // Without a known origin only the fixed text is left.
+        let pipeline =
+            OutputPipeline::new("discord", Vec::new()).with_disclosure(Some(Disclosure {
+                text: Some("AI".into()),
+                model: true,
+                cost: false,
+            }));
+        let output = pipeline.apply(OutboundResponse::Text("Hi".into()), None);
+        assert!(matches!(output.as_slice(), [OutboundResponse::Text(text)] if text == "Hi\n-# AI"));
+
+        let silent = Disclosure {
+            cost: true,
+            ..Disclosure::default()
+        };
+        assert_eq!(silent.footer(None), None);
+    }
+}
@@ -0,0 +1,738 @@
+//! Per-user quotas and per-channel flood protection for inbound messages.
+//!
+//! The router checks every inbound message against token buckets before it
+//! reaches a channel: one per user for the per-minute rate (sized by the
+//! burst allowance) and the hourly quota, and one per conversation for the
+//! channel-wide rate. A limited message is dropped and the sender gets one
+//! polite notice until they're let through again, so the bot can't be
+//! goaded into flooding the channel with refusals.
+//!
+//! Admins can give a user their own limits from chat with `!quota set`
+//! ([`QuotaCommand`]); the overrides are kept in a [`QuotaStore`] and loaded
+//! into the limiter at startup. `!quota` shows a user what they have left.
+
+use crate::config::RateLimitConfig;
+use crate::error::Result;
+
+use anyhow::Context as _;
+use serde::Serialize;
+use sqlx::{Row as _, SqlitePool};
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Past this many tracked users or channels, idle entries are dropped.
+const PRUNE_THRESHOLD: usize = 10_000;
+
+/// Which limit a message ran into.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LimitScope {
+    /// The sender's per-minute rate or burst allowance.
+    UserMinute,
+    /// The sender's hourly quota.
+    UserHour,
+    /// The conversation's combined rate.
+    Channel,
+}
+
+impl LimitScope {
+    /// The reply sent for a message that ran into this limit.
+    pub fn refusal(self, retry_after: Duration) -> String {
+        let wait = format_wait(retry_after);
+        match self {
+            Self::UserMinute => format!(
+                "You're sending messages faster than I can keep up. Please try again in {wait}."
+            ),
+            Self::UserHour => {
+                format!("You've reached the hourly message limit. Please try again in {wait}.")
+            }
+            Self::Channel => {
+                format!("This conversation is moving too fast for me. I'll be back in {wait}.")
+            }
+        }
+    }
+}
+
+/// Whether a message may go through.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    Allowed,
+    Limited {
+        scope: LimitScope,
+        /// How long until the sender can try again.
+        retry_after: Duration,
+        /// True for the first limited message since the last allowed one;
+        /// only that one gets a refusal reply.
+        notify: bool,
+    },
+}
+
+/// An admin's override of one user's limits. Unset limits follow
+/// `[defaults.rate_limit]`; 0 turns a limit off.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct UserQuota {
+    pub per_minute: Option<u32>,
+    pub burst: Option<u32>,
+    pub per_hour: Option<u32>,
+}
+
+impl UserQuota {
+    /// These limits, with the unset ones filled in from `config`.
+    pub fn resolve(&self, config: &RateLimitConfig) -> Self {
+        Self {
+            per_minute: Some(self.per_minute.unwrap_or(config.user_per_minute)),
+            burst: Some(self.burst.unwrap_or(config.user_burst)),
+            per_hour: Some(self.per_hour.unwrap_or(config.user_per_hour)),
+        }
+    }
+}
+
+impl std::fmt::Display for UserQuota {
+    /// The limits as `!quota set` takes them, e.g. `minute=10 hour=100`.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let limits = [
+            ("minute", self.per_minute),
+            ("burst", self.burst),
+            ("hour", self.per_hour),
+        ];
+        let mut first = true;
+        for (name, limit) in limits {
+            let Some(limit) = limit else { continue };
+            if !first {
+                f.write_str(" ")?;
+            }
+            write!(f, "{name}={limit}")?;
+            first = false;
+        }
+        Ok(())
+    }
+}
+
+/// What a user has left of their quota.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QuotaStatus {
+    /// The user's limits, all set.
+    pub limits: UserQuota,
+    /// Messages they can send right now before the per-minute rate kicks
+    /// in. None when that limit is off.
+    pub minute_left: Option<u32>,
+    /// Messages left this hour. None when the hourly quota is off.
+    pub hour_left: Option<u32>,
+    /// Whether an admin override applies.
+    pub custom: bool,
+}
+
+/// Counts of checked messages, for the API.
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct RateLimitStats {
+    pub allowed: u64,
+    pub limited_user_minute: u64,
+    pub limited_user_hour: u64,
+    pub limited_channel: u64,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    fn full(capacity: f64, now: Instant) -> Self {
+        Self {
+            tokens: capacity,
+            updated: now,
+        }
+    }
+
+    /// Refill for the time since the last update. Returns how long until a
+    /// token is available, or None if one is available now.
+    fn wait(&mut self, capacity: f64, per_sec: f64, now: Instant) -> Option<Duration> {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * per_sec).min(capacity);
+        self.updated = now;
+        (self.tokens < 1.0).then(|| Duration::from_secs_f64((1.0 - self.tokens) / per_sec))
+    }
+
+    fn is_full(&self, capacity: f64, per_sec: f64, now: Instant) -> bool {
+        self.level(capacity, per_sec, now) >= capacity
+    }
+
+    /// Tokens available at `now`, without updating the bucket.
+    fn level(&self, capacity: f64, per_sec: f64, now: Instant) -> f64 {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        (self.tokens + elapsed * per_sec).min(capacity)
+    }
+}
+
+/// A limit as bucket capacity and refill rate. None when the limit is off.
+#[derive(Debug, Clone, Copy)]
+struct Limit {
+    capacity: f64,
+    per_sec: f64,
+}
+
+impl Limit {
+    fn new(per_window: u32, window: Duration, capacity: u32) -> Option<Self> {
+        (per_window > 0).then(|| Self {
+            capacity: capacity.max(1) as f64,
+            per_sec: per_window as f64 / window.as_secs_f64(),
+        })
+    }
+}
+
+struct Tracked {
+    buckets: Vec<Bucket>,
+    notified: bool,
+}
+
+#[derive(Default)]
+struct LimiterState {
+    users: HashMap<String, Tracked>,
+    channels: HashMap<String, Tracked>,
+    /// Admin overrides by user.
+    quotas: HashMap<String, UserQuota>,
+    stats: RateLimitStats,
+}
+
+/// Inbound message limiter for one agent. Clones share state.
+#[derive(Clone, Default)]
+pub struct RateLimiter {
+    state: Arc<Mutex<LimiterState>>,
+}
+
+impl RateLimiter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Check a message from `user` in `channel`, taking a token from every
+    /// bucket if it's allowed.
+    pub fn check(&self, config: &RateLimitConfig, user: &str, channel: &str) -> Verdict {
+        self.check_at(config, user, channel, Instant::now())
+    }
+
+    fn check_at(
+        &self,
+        config: &RateLimitConfig,
+        user: &str,
+        channel: &str,
+        now: Instant,
+    ) -> Verdict {
+        if !config.enabled {
+            return Verdict::Allowed;
+        }
+        let channel_limits = [(
+            LimitScope::Channel,
+            Limit::new(
+                config.channel_per_minute,
+                Duration::from_secs(60),
+                config.channel_burst,
+            ),
+        )];
+
+        let mut guard = self.lock();
+        let state = &mut *guard;
+        let limits = user_limits(config, state.quotas.get(user));
+        let user_entry = tracked(&mut state.users, user, &limits, now);
+        let user_wait = first_wait(user_entry, &limits, now);
+        let channel_entry = tracked(&mut state.channels, channel, &channel_limits, now);
+        let channel_wait = first_wait(channel_entry, &channel_limits, now);
+
+        let verdict = match user_wait.or(channel_wait) {
+            None => {
+                take(state.users.get_mut(user), &limits);
+                take(state.channels.get_mut(channel), &channel_limits);
+                state.stats.allowed += 1;
+                Verdict::Allowed
+            }
+            Some((scope, retry_after)) => {
+                let entry = match scope {
+                    LimitScope::Channel => state.channels.get_mut(channel),
+                    _ => state.users.get_mut(user),
+                };
+                let notify =
+                    entry.is_some_and(|entry| !std::mem::replace(&mut entry.notified, true));
+                match scope {
+                    LimitScope::UserMinute => state.stats.limited_user_minute += 1,
+                    LimitScope::UserHour => state.stats.limited_user_hour += 1,
+                    LimitScope::Channel => state.stats.limited_channel += 1,
+                }
+                Verdict::Limited {
+                    scope,
+                    retry_after,
+                    notify,
+                }
+            }
+        };
+
+        // Users with their own limits are never pruned, so their buckets
+        // aren't judged by the configured ones.
+        let quotas = &state.quotas;
+        prune(
+            &mut state.users,
+            &user_limits(config, None),
+            |user| quotas.contains_key(user),
+            now,
+        );
+        prune(&mut state.channels, &channel_limits, |_| false, now);
+        verdict
+    }
+
+    /// Give `user` their own limits, or with None, put them back on the
+    /// configured ones. Either way they start again with full buckets.
+    pub fn set_quota(&self, user: &str, quota: Option<UserQuota>) {
+        let mut state = self.lock();
+        state.users.remove(user);
+        match quota {
+            Some(quota) => {
+                state.quotas.insert(user.to_string(), quota);
+            }
+            None => {
+                state.quotas.remove(user);
+            }
+        }
+    }
+
+    /// `user`'s override, if an admin gave them one.
+    pub fn quota(&self, user: &str) -> Option<UserQuota> {
+        self.lock().quotas.get(user).copied()
+    }
+
+    /// What `user` has left, without using any of it.
+    pub fn status(&self, config: &RateLimitConfig, user: &str) -> QuotaStatus {
+        self.status_at(config, user, Instant::now())
+    }
+
+    fn status_at(&self, config: &RateLimitConfig, user: &str, now: Instant) -> QuotaStatus {
+        let state = self.lock();
+        let quota = state.quotas.get(user);
+        let limits = user_limits(config, quota);
+        let entry = state.users.get(user);
+        let left = |index: usize| {
+            let limit = limits[index].1?;
+            let level = entry.map_or(limit.capacity, |entry| {
+                entry.buckets[index].level(limit.capacity, limit.per_sec, now)
+            });
+            Some(level.max(0.0).floor() as u32)
+        };
+        QuotaStatus {
+            limits: quota.copied().unwrap_or_default().resolve(config),
+            minute_left: left(0),
+            hour_left: left(1),
+            custom: quota.is_some(),
+        }
+    }
+
+    pub fn stats(&self) -> RateLimitStats {
+        self.lock().stats
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, LimiterState> {
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl std::fmt::Debug for RateLimiter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RateLimiter")
+            .field("stats", &self.stats())
+            .finish()
+    }
+}
+
+/// A user's per-minute and hourly limits, after any override.
+fn user_limits(
+    config: &RateLimitConfig,
+    quota: Option<&UserQuota>,
+) -> [(LimitScope, Option<Limit>); 2] {
+    let limits = quota.copied().unwrap_or_default().resolve(config);
+    let per_minute = limits.per_minute.unwrap_or_default();
+    let burst = limits.burst.unwrap_or_default();
+    let per_hour = limits.per_hour.unwrap_or_default();
+    [
+        (
+            LimitScope::UserMinute,
+            Limit::new(per_minute, Duration::from_secs(60), burst),
+        ),
+        (
+            LimitScope::UserHour,
+            Limit::new(per_hour, Duration::from_secs(3600), per_hour),
+        ),
+    ]
+}
+
+fn tracked<'a>(
+    entries: &'a mut HashMap<String, Tracked>,
+    key: &str,
+    limits: &[(LimitScope, Option<Limit>)],
+    now: Instant,
+) -> &'a mut Tracked {
+    entries.entry(key.to_string()).or_insert_with(|| Tracked {
+        buckets: limits
+            .iter()
+            .map(|(_, limit)| Bucket::full(limit.map_or(0.0, |limit| limit.capacity), now))
+            .collect(),
+        notified: false,
+    })
+}
+
+/// Refill an entry's buckets and return the first limit it's over.
+fn first_wait(
+    entry: &mut Tracked,
+    limits: &[(LimitScope, Option<Limit>)],
+    now: Instant,
+) -> Option<(LimitScope, Duration)> {
+    let mut over = None;
+    for (bucket, (scope, limit)) in entry.buckets.iter_mut().zip(limits) {
+        let Some(limit) = limit else { continue };
+        // Refill every bucket, even after finding one that's over.
+        if let Some(wait) = bucket.wait(limit.capacity, limit.per_sec, now) {
+            over = over.or(Some((*scope, wait)));
+        }
+    }
+    over
+}
+
+fn take(entry: Option<&mut Tracked>, limits: &[(LimitScope, Option<Limit>)]) {
+    let Some(entry) = entry else { return };
+    entry.notified = false;
+    for (bucket, (_, limit)) in entry.buckets.iter_mut().zip(limits) {
+        if limit.is_some() {
+            bucket.tokens -= 1.0;
+        }
+    }
+}
+
+/// Drop entries whose buckets have all refilled, once the map gets large.
+fn prune(
+    entries: &mut HashMap<String, Tracked>,
+    limits: &[(LimitScope, Option<Limit>)],
+    keep: impl Fn(&str) -> bool,
+    now: Instant,
+) {
+    if entries.len() <= PRUNE_THRESHOLD {
+        return;
+    }
+    entries.retain(|key, entry| {
+        keep(key)
+            || entry
+                .buckets
+                .iter()
+                .zip(limits)
+                .any(|(bucket, (_, limit))| {
+                    limit.is_some_and(|limit| !bucket.is_full(limit.capacity, limit.per_sec, now))
+                })
+    });
+}
+
+/// Admin quota overrides (SQLite), keyed like the limiter's users.
+#[derive(Debug)]
+pub struct QuotaStore {
+    pool: SqlitePool,
+}
+
+impl QuotaStore {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+
+    /// Every stored override.
+    pub async fn load(&self) -> Result<Vec<(String, UserQuota)>> {
+        let rows = sqlx::query("SELECT user_key, per_minute, burst, per_hour FROM user_quotas")
+            .fetch_all(&self.pool)
+            .await
+            .context("failed to load user quotas")?;
+
+        let limit = |row: &sqlx::sqlite::SqliteRow, column: &str| {
+            row.try_get::<Option<i64>, _>(column)
+                .ok()
+                .flatten()
+                .map(|limit| limit as u32)
+        };
+        Ok(rows
+            .iter()
+            .filter_map(|row| {
+                let user: String = row.try_get("user_key").ok()?;
+                let quota = UserQuota {
+                    per_minute: limit(row, "per_minute"),
+                    burst: limit(row, "burst"),
+                    per_hour: limit(row, "per_hour"),
+                };
+                Some((user, quota))
+            })
+            .collect())
+    }
+
+    /// Store `user`'s override, or delete it with None.
+    pub async fn save(
+        &self,
+        user: &str,
+        quota: Option<&UserQuota>,
+        updated_by: &str,
+    ) -> Result<()> {
+        match quota {
+            Some(quota) => sqlx::query(
+                "INSERT INTO user_quotas (user_key, per_minute, burst, per_hour, updated_by) \
+                 VALUES (?, ?, ?, ?, ?) \
+                 ON CONFLICT(user_key) DO UPDATE SET per_minute = excluded.per_minute, \
+                 burst = excluded.burst, per_hour = excluded.per_hour, \
+                 updated_by = excluded.updated_by, updated_at = CURRENT_TIMESTAMP",
+            )
+            .bind(user)
+            .bind(quota.per_minute.map(i64::from))
+            .bind(quota.burst.map(i64::from))
+            .bind(quota.per_hour.map(i64::from))
+            .bind(updated_by)
+            .execute(&self.pool)
+            .await
+            .context("failed to save user quota")?,
+            None => sqlx::query("DELETE FROM user_quotas WHERE user_key = ?")
+                .bind(user)
+                .execute(&self.pool)
+                .await
+                .context("failed to delete user quota")?,
+        };
+
+        Ok(())
+    }
+}
+
+/// A parsed `!quota` command.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum QuotaCommand {
+    /// The sender's quota, or an admin's look at another user's.
+    Show(Option<String>),
+    /// Give a user their own limits, merged into any they already have.
+    Set(String, UserQuota),
+    /// Put a user back on the configured limits.
+    Reset(String),
+    Invalid,
+}
+
+impl QuotaCommand {
+    /// Parse the arguments following `!quota`: nothing, a user, `set <user>
+    /// minute=N burst=N hour=N` with any of the limits, or `reset <user>`.
+    pub fn parse(args: &str) -> Self {
+        let words: Vec<&str> = args.split_whitespace().collect();
+        match words.as_slice() {
+            [] => Self::Show(None),
+            ["reset", user] => Self::Reset(user.to_string()),
+            ["set", user, limits @ ..] if !limits.is_empty() => {
+                let mut quota = UserQuota::default();
+                for limit in limits {
+                    let Some((name, value)) = limit.split_once('=') else {
+                        return Self::Invalid;
+                    };
+                    let Ok(value) = value.parse() else {
+                        return Self::Invalid;
+                    };
+                    let field = match name {
+                        "minute" => &mut quota.per_minute,
+                        "burst" => &mut quota.burst,
+                        "hour" => &mut quota.per_hour,
+                        _ => return Self::Invalid,
+                    };
+                    *field = Some(value);
+                }
+                Self::Set(user.to_string(), quota)
+            }
+            [user] if *user != "set" && *user != "reset" => Self::Show(Some(user.to_string())),
+            _ => Self::Invalid,
+        }
+    }
+
+    /// Whether running this command needs admin rights.
+    pub fn is_privileged(&self) -> bool {
+        !matches!(self, Self::Show(None) | Self::Invalid)
+    }
+}
+
+/// The limiter's key for a user named in a command on `source`: a bare ID,
+/// a platform mention like `<@123>`, or a `platform:id` key as is.
+pub fn user_key(source: &str, user: &str) -> String {
+    let user = user
+        .strip_prefix("<@")
+        .and_then(|user| user.strip_suffix('>'))
+        .map(|user| user.trim_start_matches('!'))
+        .unwrap_or(user);
+    if user.contains(':') {
+        user.to_string()
+    } else {
+        format!("{source}:{user}")
+    }
+}
+
+fn format_wait(wait: Duration) -> String {
+    let secs = wait.as_secs().max(1);
+    match secs {
+        1 => "a second".into(),
+        2..60 => format!("{secs} seconds"),
+        60..120 => "a minute".into(),
+        _ => format!("{} minutes", secs.div_ceil(60)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> RateLimitConfig {
+        RateLimitConfig {
+            enabled: true,
+            user_per_minute: 6,
+            user_burst: 2,
+            user_per_hour: 0,
+            channel_per_minute: 0,
+            channel_burst: 0,
+        }
+    }
+
+    #[test]
+    fn test_burst_then_refill() {
+        let limiter = RateLimiter::new();
+        let config = config();
+        let start = Instant::now();
+
+        assert_eq!(limiter.check_at(&config, "u", "c", start), Verdict::Allowed);
+        assert_eq!(limiter.check_at(&config, "u", "c", start), Verdict::Allowed);
+        let Verdict::Limited {
+            scope,
+            retry_after,
+            notify,
+        } = limiter.check_at(&config, "u", "c", start)
+        else {
+            panic!("third message in a burst of two should be limited");
+        };
+        assert_eq!(scope, LimitScope::UserMinute);
+        assert_eq!(retry_after, Duration::from_secs(10));
+        assert!(notify);
+        // Only the first limited message gets a reply.
+        assert!(matches!(
+            limiter.check_at(&config, "u", "c", start),
+            Verdict::Limited { notify: false, .. }
+        ));
+
+        // Other users have their own quota.
+        assert_eq!(limiter.check_at(&config, "v", "c", start), Verdict::Allowed);
+
+        let later = start + Duration::from_secs(10);
+        assert_eq!(limiter.check_at(&config, "u", "c", later), Verdict::Allowed);
+
+        let stats = limiter.stats();
+        assert_eq!(stats.allowed, 4);
+        assert_eq!(stats.limited_user_minute, 2);
+    }
+
+    #[test]
+    fn test_channel_limit_spans_users() {
+        let limiter = RateLimiter::new();
+        let config = RateLimitConfig {
+            user_per_minute: 0,
+            channel_per_minute: 1,
+            channel_burst: 2,
+            ..config()
+        };
+        let now = Instant::now();
+
+        assert_eq!(limiter.check_at(&config, "a", "c", now), Verdict::Allowed);
+        assert_eq!(limiter.check_at(&config, "b", "c", now), Verdict::Allowed);
+        assert!(matches!(
+            limiter.check_at(&config, "d", "c", now),
+            Verdict::Limited {
+                scope: LimitScope::Channel,
+                ..
+            }
+        ));
+        assert_eq!(
+            limiter.check_at(&config, "d", "other", now),
+            Verdict::Allowed
+        );
+    }
+
+    #[test]
+    fn test_user_quota_override_and_status() {
+        let limiter = RateLimiter::new();
+        let config = config();
+        let now = Instant::now();
+
+        let status = limiter.status_at(&config, "u", now);
+        assert_eq!(status.minute_left, Some(2));
+        assert_eq!(status.hour_left, None);
+        assert!(!status.custom);
+
+        limiter.set_quota(
+            "u",
+            Some(UserQuota {
+                burst: Some(3),
+                per_hour: Some(10),
+                ..Default::default()
+            }),
+        );
+        for _ in 0..3 {
+            assert_eq!(limiter.check_at(&config, "u", "c", now), Verdict::Allowed);
+        }
+        assert!(matches!(
+            limiter.check_at(&config, "u", "c", now),
+            Verdict::Limited { .. }
+        ));
+        let status = limiter.status_at(&config, "u", now);
+        assert_eq!(status.minute_left, Some(0));
+        assert_eq!(status.hour_left, Some(7));
+        assert_eq!(status.limits.per_minute, Some(6));
+        assert!(status.custom);
+
+        // Back on the configured limits, starting over.
+        limiter.set_quota("u", None);
+        let status = limiter.status_at(&config, "u", now);
+        assert_eq!(status.minute_left, Some(2));
+        assert_eq!(status.hour_left, None);
+    }
+
+    #[test]
+    fn test_parse_quota_command() {
+        assert_eq!(QuotaCommand::parse(""), QuotaCommand::Show(None));
+        assert_eq!(
+            QuotaCommand::parse("<@42>"),
+            QuotaCommand::Show(Some("<@42>".into()))
+        );
+        assert_eq!(
+            QuotaCommand::parse("set 42 minute=10 hour=0"),
+            QuotaCommand::Set(
+                "42".into(),
+                UserQuota {
+                    per_minute: Some(10),
+                    burst: None,
+                    per_hour: Some(0),
+                }
+            )
+        );
+        assert_eq!(
+            QuotaCommand::parse("reset 42"),
+            QuotaCommand::Reset("42".into())
+        );
+        assert_eq!(QuotaCommand::parse("set 42"), QuotaCommand::Invalid);
+        assert_eq!(QuotaCommand::parse("set 42 day=5"), QuotaCommand::Invalid);
+        assert_eq!(
+            QuotaCommand::parse("set 42 minute=-1"),
+            QuotaCommand::Invalid
+        );
+        assert!(!QuotaCommand::parse("").is_privileged());
+        assert!(QuotaCommand::parse("42").is_privileged());
+
+        let quota = UserQuota {
+            per_minute: Some(10),
+            per_hour: Some(0),
+            ..Default::default()
+        };
+        assert_eq!(quota.to_string(), "minute=10 hour=0");
+
+        assert_eq!(user_key("discord", "<@!42>"), "discord:42");
+        assert_eq!(user_key("slack", "U42"), "slack:U42");
+        assert_eq!(user_key("discord", "telegram:7"), "telegram:7");
+    }
+}
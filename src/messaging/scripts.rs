@@ -0,0 +1,364 @@
+//! Operator-written Rhai hooks for inbound messages.
+//!
+//! With `[scripting]` enabled, the router runs small scripts from the
+//! instance directory, for customizations too small for a plugin:
+//!
+//! - `on_message(msg)` runs before routing. Returning a string replaces the
+//!   message text, `false` drops the message and `()` leaves it alone.
+//! - `route(msg)` runs after bindings and intake. Returning an agent ID sends
+//!   the message to that agent; `()` keeps the one chosen. Handed-off
+//!   conversations and the sensitive-topic policy still take precedence.
+//! - `run(args, msg)` in a `[scripting.commands]` script answers `!name`.
+//!   Returning a string replies with it; `()` replies with nothing.
+//!
+//! `msg` is a map of `id`, `source`, `conversation_id`, `sender_id`,
+//! `sender_name`, `agent_id` and `text`. Every call is capped at
+//! `max_operations`, so a runaway loop is stopped rather than stalling the
+//! router. A script that fails to compile is skipped at startup, and a hook
+//! that errors leaves the message as it was.
+
+use crate::agent::commands::PREFIX;
+use crate::config::ScriptingConfig;
+use crate::{AgentId, InboundMessage, MessageContent};
+
+use rhai::{AST, Dynamic, Engine, FuncArgs, Scope};
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Longest string a script may build.
+const MAX_STRING_SIZE: usize = 64 * 1024;
+
+/// Deepest function call nesting a script may reach.
+const MAX_CALL_LEVELS: usize = 32;
+
+/// Sent when a command script fails.
+const COMMAND_FAILED: &str = "Sorry, that command failed.";
+
+/// What the `on_message` hooks did with a message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Preprocessed {
+    /// Route it as usual, with any rewrites applied.
+    Pass,
+    /// A hook dropped it.
+    Drop { script: String },
+}
+
+/// A custom `!` command answered by a script.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommandReply {
+    pub name: String,
+    /// None sends nothing back.
+    pub reply: Option<String>,
+}
+
+struct Script {
+    path: PathBuf,
+    ast: AST,
+}
+
+impl Script {
+    fn defines(&self, function: &str) -> bool {
+        self.ast.iter_functions().any(|f| f.name == function)
+    }
+
+    fn name(&self) -> String {
+        self.path.display().to_string()
+    }
+}
+
+/// The compiled hook and command scripts.
+pub struct ScriptHooks {
+    engine: Engine,
+    hooks: Vec<Script>,
+    commands: HashMap<String, Script>,
+}
+
+impl ScriptHooks {
+    /// The hooks for `config`, or None when scripting is off or no script
+    /// compiled.
+    pub fn from_config(config: &ScriptingConfig) -> Option<Arc<Self>> {
+        if !config.enabled {
+            return None;
+        }
+        let engine = engine(config.max_operations);
+        let hooks: Vec<Script> = config
+            .hooks
+            .iter()
+            .filter_map(|path| compile(&engine, path))
+            .collect();
+        let commands: HashMap<String, Script> = config
+            .commands
+            .iter()
+            .filter_map(|(name, path)| Some((name.clone(), compile(&engine, path)?)))
+            .collect();
+        if hooks.is_empty() && commands.is_empty() {
+            tracing::warn!("scripting is enabled but no scripts loaded, disabling it");
+            return None;
+        }
+        tracing::info!(
+            hooks = hooks.len(),
+            commands = commands.len(),
+            "script hooks loaded"
+        );
+        Some(Arc::new(Self {
+            engine,
+            hooks,
+            commands,
+        }))
+    }
+
+    /// Run every `on_message` hook in order, rewriting the message text as
+    /// they say. Stops at the first hook that drops it.
+    pub fn preprocess(&self, message: &mut InboundMessage) -> Preprocessed {
+        for script in self.hooks.iter().filter(|s| s.defines("on_message")) {
+            let Some(result) = self.call(script, "on_message", (message_map(message),)) else {
+                continue;
+            };
+            if result.as_bool() == Ok(false) {
+                return Preprocessed::Drop {
+                    script: script.name(),
+                };
+            }
+            if result.is_unit() || result.as_bool() == Ok(true) {
+                continue;
+            }
+            match result.into_string() {
+                Ok(text) => set_text(message, text),
+                Err(kind) => tracing::warn!(
+                    script = %script.name(),
+                    kind,
+                    "on_message returned something other than a string, false or ()"
+                ),
+            }
+        }
+        Preprocessed::Pass
+    }
+
+    /// The agent the first `route` hook with an answer picks. None keeps
+    /// the routing as it is.
+    pub fn route(&self, message: &InboundMessage) -> Option<AgentId> {
+        self.hooks
+            .iter()
+            .filter(|s| s.defines("route"))
+            .find_map(|script| {
+                let result = self.call(script, "route", (message_map(message),))?;
+                if result.is_unit() {
+                    return None;
+                }
+                match result.into_string() {
+                    Ok(agent_id) if !agent_id.trim().is_empty() => {
+                        Some(AgentId::from(agent_id.trim()))
+                    }
+                    Ok(_) => None,
+                    Err(kind) => {
+                        tracing::warn!(
+                            script = %script.name(),
+                            kind,
+                            "route returned something other than an agent ID or ()"
+                        );
+                        None
+                    }
+                }
+            })
+    }
+
+    /// Answer a `!name` message when a script defines the command. None for
+    /// every other message.
+    pub fn command(&self, message: &InboundMessage) -> Option<CommandReply> {
+        let MessageContent::Text(text) = &message.content else {
+            return None;
+        };
+        let rest = text.trim().strip_prefix(PREFIX)?;
+        let (name, args) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+        let name = name.to_ascii_lowercase();
+        let script = self.commands.get(&name)?;
+
+        let reply = match self.call(
+            script,
+            "run",
+            (args.trim().to_string(), message_map(message)),
+        ) {
+            None => Some(COMMAND_FAILED.to_string()),
+            Some(result) if result.is_unit() => None,
+            Some(result) => Some(result.to_string()),
+        };
+        Some(CommandReply { name, reply })
+    }
+
+    /// Call a script function. Errors, including a call running out of
+    /// operations, are logged and give None.
+    fn call(&self, script: &Script, function: &str, args: impl FuncArgs) -> Option<Dynamic> {
+        match self
+            .engine
+            .call_fn::<Dynamic>(&mut Scope::new(), &script.ast, function, args)
+        {
+            Ok(result) => Some(result),
+            Err(error) => {
+                tracing::warn!(script = %script.name(), function, %error, "script hook failed");
+                None
+            }
+        }
+    }
+}
+
+/// An engine with the limits every hook runs under. `print` and `debug` go to
+/// the log.
+fn engine(max_operations: u64) -> Engine {
+    let mut engine = Engine::new();
+    engine.set_max_operations(max_operations);
+    engine.set_max_string_size(MAX_STRING_SIZE);
+    engine.set_max_call_levels(MAX_CALL_LEVELS);
+    engine.on_print(|text| tracing::info!(target: "spacebot::scripts", "{text}"));
+    engine.on_debug(|text, source, position| {
+        tracing::debug!(target: "spacebot::scripts", source, %position, "{text}")
+    });
+    engine
+}
+
+fn compile(engine: &Engine, path: &Path) -> Option<Script> {
+    let source = match std::fs::read_to_string(path) {
+        Ok(source) => source,
+        Err(error) => {
+            tracing::warn!(path = %path.display(), %error, "failed to read script, skipping it");
+            return None;
+        }
+    };
+    match engine.compile(&source) {
+        Ok(ast) => Some(Script {
+            path: path.to_path_buf(),
+            ast,
+        }),
+        Err(error) => {
+            tracing::warn!(path = %path.display(), %error, "failed to compile script, skipping it");
+            None
+        }
+    }
+}
+
+/// The message as scripts see it.
+fn message_map(message: &InboundMessage) -> rhai::Map {
+    let sender_name = message
+        .metadata
+        .get("sender_display_name")
+        .and_then(|v| v.as_str())
+        .unwrap_or(&message.sender_id);
+    let text = match &message.content {
+        MessageContent::Text(text) => text.as_str(),
+        MessageContent::Media { text, .. } => text.as_deref().unwrap_or_default(),
+    };
+    let agent_id = message
+        .agent_id
+        .as_ref()
+        .map_or(Dynamic::UNIT, |agent_id| agent_id.to_string().into());
+
+    let mut map = rhai::Map::new();
+    map.insert("id".into(), message.id.clone().into());
+    map.insert("source".into(), message.source.clone().into());
+    map.insert(
+        "conversation_id".into(),
+        message.conversation_id.clone().into(),
+    );
+    map.insert("sender_id".into(), message.sender_id.clone().into());
+    map.insert("sender_name".into(), sender_name.to_string().into());
+    map.insert("agent_id".into(), agent_id);
+    map.insert("text".into(), text.to_string().into());
+    map
+}
+
+fn set_text(message: &mut InboundMessage, new_text: String) {
+    match &mut message.content {
+        MessageContent::Text(text) => *text = new_text,
+        MessageContent::Media { text, .. } => *text = Some(new_text),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(text: &str) -> InboundMessage {
+        InboundMessage {
+            id: "1".into(),
+            source: "discord".into(),
+            conversation_id: "discord:1:2".into(),
+            sender_id: "42".into(),
+            agent_id: Some(AgentId::from("main")),
+            content: MessageContent::Text(text.into()),
+            timestamp: chrono::Utc::now(),
+            metadata: Default::default(),
+        }
+    }
+
+    fn hooks(dir: &Path, hooks: &[&str], commands: &[(&str, &str)]) -> Arc<ScriptHooks> {
+        let mut config = ScriptingConfig {
+            enabled: true,
+            max_operations: 10_000,
+            ..Default::default()
+        };
+        for (index, source) in hooks.iter().enumerate() {
+            let path = dir.join(format!("hook{index}.rhai"));
+            std::fs::write(&path, source).unwrap();
+            config.hooks.push(path);
+        }
+        for (name, source) in commands {
+            let path = dir.join(format!("{name}.rhai"));
+            std::fs::write(&path, source).unwrap();
+            config.commands.insert(name.to_string(), path);
+        }
+        ScriptHooks::from_config(&config).unwrap()
+    }
+
+    #[test]
+    fn test_hooks_rewrite_route_and_answer_commands() {
+        let dir = tempfile::tempdir().unwrap();
+        let scripts = hooks(
+            dir.path(),
+            &[
+                r#"
+                fn on_message(msg) {
+                    if msg.text.starts_with("+1") { return false; }
+                    let text = msg.text;
+                    text.replace("colour", "color");
+                    text
+                }
+                fn route(msg) {
+                    if msg.text.contains("invoice") { "billing" }
+                }
+                "#,
+                "fn on_message(msg) { while true {} }",
+                "fn oops( {",
+            ],
+            &[(
+                "roll",
+                r#"fn run(args, msg) { `${msg.sender_name} rolled ${args}` }"#,
+            )],
+        );
+        assert_eq!(scripts.hooks.len(), 2);
+
+        let mut rewritten = message("what colour is it?");
+        assert_eq!(scripts.preprocess(&mut rewritten), Preprocessed::Pass);
+        assert_eq!(rewritten.content.to_string(), "what color is it?");
+        assert!(matches!(
+            scripts.preprocess(&mut message("+1 agreed")),
+            Preprocessed::Drop { .. }
+        ));
+
+        assert_eq!(
+            scripts.route(&message("where's my invoice?")),
+            Some(AgentId::from("billing"))
+        );
+        assert_eq!(scripts.route(&message("hello")), None);
+
+        assert_eq!(
+            scripts.command(&message("!Roll 2d6")),
+            Some(CommandReply {
+                name: "roll".into(),
+                reply: Some("42 rolled 2d6".into()),
+            })
+        );
+        assert_eq!(scripts.command(&message("!usage")), None);
+        assert_eq!(scripts.command(&message("roll 2d6")), None);
+    }
+}
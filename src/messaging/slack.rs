@@ -1,11 +1,17 @@
 //! Slack messaging adapter using slack-morphism.
 
+use crate::activation::MENTIONS_BOT;
+use crate::agent::thread::PARENT_CONVERSATION_ID;
 use crate::config::SlackPermissions;
+use crate::messaging::format::slack_messages;
+use crate::messaging::postprocess::link_previews_suppressed;
+use crate::messaging::stream::CoalesceConfig;
 use crate::messaging::traits::{HistoryMessage, InboundStream, Messaging};
 use crate::{InboundMessage, MessageContent, OutboundResponse, StatusUpdate};
 
 use anyhow::Context as _;
 use arc_swap::ArcSwap;
+use chrono::{DateTime, Utc};
 use slack_morphism::prelude::*;
 use std::collections::HashMap;
 use std::sync::Arc;
@@ -16,6 +22,8 @@ struct SlackAdapterState {
     inbound_tx: mpsc::Sender<InboundMessage>,
     permissions: Arc<ArcSwap<SlackPermissions>>,
     bot_token: String,
+    /// Our own user ID, for recognizing mentions. None if `auth.test` failed.
+    bot_user_id: Option<String>,
 }
 
 /// Slack adapter state.
@@ -154,6 +162,26 @@ async fn handle_push_event(
 
     // Build metadata
     let mut metadata = HashMap::new();
+    let text = msg_event
+        .content
+        .as_ref()
+        .and_then(|content| content.text.as_deref())
+        .unwrap_or_default();
+    let mentioned = adapter_state
+        .bot_user_id
+        .as_ref()
+        .is_some_and(|bot_id| text.contains(&format!("<@{bot_id}>")));
+    metadata.insert(
+        MENTIONS_BOT.into(),
+        serde_json::Value::Bool(channel_id.starts_with('D') || mentioned),
+    );
+    // Threads are their own conversations; note the channel they came from.
+    if msg_event.origin.thread_ts.is_some() {
+        metadata.insert(
+            PARENT_CONVERSATION_ID.into(),
+            serde_json::Value::String(format!("slack:{team_id}:{channel_id}")),
+        );
+    }
     metadata.insert(
         "slack_workspace_id".into(),
         serde_json::Value::String(team_id),
@@ -238,10 +266,20 @@ impl Messaging for SlackAdapter {
             SlackClientHyperConnector::new().context("failed to create slack connector")?,
         ));
 
+        let (session_client, token) = self.create_session()?;
+        let bot_user_id = match session_client.open_session(&token).auth_test().await {
+            Ok(response) => Some(response.user_id.0),
+            Err(error) => {
+                tracing::warn!(%error, "slack auth.test failed, mentions won't be recognized");
+                None
+            }
+        };
+
         let adapter_state = Arc::new(SlackAdapterState {
             inbound_tx,
             permissions: self.permissions.clone(),
             bot_token: self.bot_token.clone(),
+            bot_user_id,
         });
 
         let callbacks = SlackSocketModeListenerCallbacks::new().with_push_events(handle_push_event);
@@ -292,17 +330,21 @@ impl Messaging for SlackAdapter {
         let session = client.open_session(&token);
 
         let channel_id = extract_channel_id(message)?;
+        let suppress_previews = link_previews_suppressed(message);
 
         match response {
             OutboundResponse::Text(text) => {
                 let thread_ts = extract_thread_ts(message);
 
-                for chunk in split_message(&text, 4000) {
+                for blocks in slack_messages(&text) {
                     let mut req = SlackApiChatPostMessageRequest::new(
                         channel_id.clone(),
-                        SlackMessageContent::new().with_text(chunk),
+                        section_content(blocks),
                     );
-                    req = req.opt_thread_ts(thread_ts.clone());
+                    req = req
+                        .opt_thread_ts(thread_ts.clone())
+                        .opt_unfurl_links(suppress_previews.then_some(false))
+                        .opt_unfurl_media(suppress_previews.then_some(false));
 
                     session
                         .chat_post_message(&req)
@@ -317,12 +359,15 @@ impl Messaging for SlackAdapter {
                 // Use existing thread_ts, or create a thread from the source message
                 let thread_ts = extract_thread_ts(message).or_else(|| extract_message_ts(message));
 
-                for chunk in split_message(&text, 4000) {
+                for blocks in slack_messages(&text) {
                     let mut req = SlackApiChatPostMessageRequest::new(
                         channel_id.clone(),
-                        SlackMessageContent::new().with_text(chunk),
+                        section_content(blocks),
                     );
-                    req = req.opt_thread_ts(thread_ts.clone());
+                    req = req
+                        .opt_thread_ts(thread_ts.clone())
+                        .opt_unfurl_links(suppress_previews.then_some(false))
+                        .opt_unfurl_media(suppress_previews.then_some(false));
 
                     session
                         .chat_post_message(&req)
@@ -480,10 +525,10 @@ impl Messaging for SlackAdapter {
         let channel_id = SlackChannelId(target.to_string());
 
         if let OutboundResponse::Text(text) = response {
-            for chunk in split_message(&text, 4000) {
+            for blocks in slack_messages(&text) {
                 let req = SlackApiChatPostMessageRequest::new(
                     channel_id.clone(),
-                    SlackMessageContent::new().with_text(chunk),
+                    section_content(blocks),
                 );
 
                 session
@@ -554,6 +599,68 @@ impl Messaging for SlackAdapter {
         Ok(result)
     }
 
+    async fn fetch_channel_history(
+        &self,
+        target: &str,
+        since: DateTime<Utc>,
+        limit: usize,
+    ) -> crate::Result<Vec<HistoryMessage>> {
+        let (client, token) = self.create_session()?;
+        let session = client.open_session(&token);
+        let channel_id = SlackChannelId(target.to_string());
+        let oldest = SlackTs(format!("{}.000000", since.timestamp()));
+
+        // Slack pages newest-first; follow the cursor until `limit`
+        let mut messages = Vec::new();
+        let mut cursor: Option<SlackCursorId> = None;
+        while messages.len() < limit {
+            let mut req = SlackApiConversationsHistoryRequest::new()
+                .with_channel(channel_id.clone())
+                .with_oldest(oldest.clone())
+                .with_limit(200);
+            if let Some(cursor) = cursor.take() {
+                req = req.with_cursor(cursor);
+            }
+            let response = session
+                .conversations_history(&req)
+                .await
+                .context("failed to fetch slack channel history")?;
+            messages.extend(response.messages);
+            cursor = response
+                .response_metadata
+                .and_then(|metadata| metadata.next_cursor)
+                .filter(|cursor| !cursor.0.is_empty());
+            if cursor.is_none() {
+                break;
+            }
+        }
+        messages.truncate(limit);
+
+        let result: Vec<HistoryMessage> = messages
+            .into_iter()
+            .rev()
+            .map(|msg| {
+                let user_id = msg.sender.user.as_ref().map(|u| u.0.clone());
+                let is_bot = user_id.is_none() || msg.sender.bot_id.is_some();
+                let author = user_id.unwrap_or_else(|| "bot".into());
+
+                HistoryMessage {
+                    author,
+                    content: msg.content.text.clone().unwrap_or_default(),
+                    is_bot,
+                }
+            })
+            .collect();
+
+        tracing::info!(
+            count = result.len(),
+            channel_id = %channel_id.0,
+            "fetched slack channel history"
+        );
+
+        Ok(result)
+    }
+
     async fn health_check(&self) -> crate::Result<()> {
         let (client, token) = self.create_session()?;
         let session = client.open_session(&token);
@@ -566,6 +673,10 @@ impl Messaging for SlackAdapter {
         Ok(())
     }
 
+    fn stream_coalescing(&self) -> Option<CoalesceConfig> {
+        Some(CoalesceConfig::SLACK)
+    }
+
     async fn shutdown(&self) -> crate::Result<()> {
         self.active_messages.write().await.clear();
 
@@ -605,32 +716,22 @@ fn extract_thread_ts(message: &InboundMessage) -> Option<SlackTs> {
         .map(|s| SlackTs(s.to_string()))
 }
 
-/// Split a message into chunks that fit within Slack's character limit.
-/// Tries to split at newlines, then spaces, then hard-cuts.
-fn split_message(text: &str, max_len: usize) -> Vec<String> {
-    if text.len() <= max_len {
-        return vec![text.to_string()];
-    }
-
-    let mut chunks = Vec::new();
-    let mut remaining = text;
-
-    while !remaining.is_empty() {
-        if remaining.len() <= max_len {
-            chunks.push(remaining.to_string());
-            break;
-        }
-
-        let split_at = remaining[..max_len]
-            .rfind('\n')
-            .or_else(|| remaining[..max_len].rfind(' '))
-            .unwrap_or(max_len);
-
-        chunks.push(remaining[..split_at].to_string());
-        remaining = remaining[split_at..].trim_start();
-    }
-
-    chunks
+/// Build message content from mrkdwn section blocks, with the joined text
+/// as the notification fallback.
+fn section_content(blocks: Vec<String>) -> SlackMessageContent {
+    let fallback = blocks.join("\n");
+    let blocks = blocks
+        .into_iter()
+        .map(|text| {
+            SlackBlock::Section(
+                SlackSectionBlock::new()
+                    .with_text(SlackBlockText::MarkDown(SlackBlockMarkDownText::new(text))),
+            )
+        })
+        .collect();
+    SlackMessageContent::new()
+        .with_text(fallback)
+        .with_blocks(blocks)
 }
 
 /// Sanitize an emoji string for Slack reactions (remove colons, lowercase).
@@ -0,0 +1,280 @@
+//! Coalescing of streamed replies into live message edits.
+//!
+//! A streamed reply arrives as `StreamStart`, one `StreamChunk` per text
+//! delta, then `StreamEnd`. Forwarding every delta as an edit would hit every
+//! platform's rate limit, so each conversation's outbound task runs the
+//! deltas through a [`StreamCoalescer`] configured by the adapter's
+//! [`Messaging::stream_coalescing`](crate::messaging::Messaging::stream_coalescing).
+//! The coalescer emits `StreamChunk`s carrying the whole text so far, which
+//! adapters render by editing their placeholder message. Adapters that can't
+//! edit messages get the finished text as a single `Text` instead.
+
+use crate::OutboundResponse;
+
+use std::time::{Duration, Instant};
+
+/// When a streamed reply's accumulated text is flushed as an edit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CoalesceConfig {
+    /// Minimum new chars accumulated before flushing an edit.
+    pub min_chars: usize,
+    /// New chars that force a flush, however recent the last edit.
+    pub max_chars: usize,
+    /// Flush after this long without a new chunk (the model is pausing).
+    pub idle_timeout: Duration,
+    /// Shortest time between two edits of the same message.
+    pub min_interval: Duration,
+}
+
+impl CoalesceConfig {
+    pub const DISCORD: Self = Self {
+        min_chars: 200,
+        max_chars: 1500,
+        idle_timeout: Duration::from_millis(500),
+        min_interval: Duration::from_millis(500),
+    };
+
+    pub const SLACK: Self = Self {
+        min_chars: 200,
+        max_chars: 1500,
+        idle_timeout: Duration::from_millis(500),
+        min_interval: Duration::from_millis(1000),
+    };
+
+    pub const TELEGRAM: Self = Self {
+        min_chars: 300,
+        max_chars: 2000,
+        idle_timeout: Duration::from_millis(1000),
+        min_interval: Duration::from_millis(1000),
+    };
+}
+
+/// One conversation's in-flight streamed reply.
+#[derive(Debug)]
+pub struct StreamCoalescer {
+    /// None when the adapter can't edit messages.
+    config: Option<CoalesceConfig>,
+    streaming: bool,
+    /// Everything streamed so far.
+    text: String,
+    /// How much of `text` the last edit showed.
+    flushed_len: usize,
+    last_chunk: Option<Instant>,
+    last_edit: Option<Instant>,
+}
+
+impl StreamCoalescer {
+    pub fn new(config: Option<CoalesceConfig>) -> Self {
+        Self {
+            config,
+            streaming: false,
+            text: String::new(),
+            flushed_len: 0,
+            last_chunk: None,
+            last_edit: None,
+        }
+    }
+
+    /// Feed one response from the channel, returning what to send now.
+    /// Anything that isn't part of a stream passes straight through.
+    pub fn push(&mut self, response: OutboundResponse, now: Instant) -> Vec<OutboundResponse> {
+        match response {
+            OutboundResponse::StreamStart => {
+                let mut out = self.finish();
+                out.extend(self.start());
+                out
+            }
+            OutboundResponse::StreamChunk(delta) => {
+                // A chunk without a start opens the stream itself.
+                let mut out = if self.streaming {
+                    Vec::new()
+                } else {
+                    self.start()
+                };
+                self.text.push_str(&delta);
+                self.last_chunk = Some(now);
+                if self.should_flush(now) {
+                    out.extend(self.edit(now));
+                }
+                out
+            }
+            OutboundResponse::StreamEnd => self.finish(),
+            other => vec![other],
+        }
+    }
+
+    /// When [`flush`](Self::flush) should next be called, if the stream
+    /// holds text no edit has shown yet.
+    pub fn deadline(&self) -> Option<Instant> {
+        let config = self.config?;
+        if !self.streaming || self.pending() == 0 {
+            return None;
+        }
+        let idle = self.last_chunk? + config.idle_timeout;
+        Some(match self.last_edit {
+            Some(last_edit) => idle.max(last_edit + config.min_interval),
+            None => idle,
+        })
+    }
+
+    /// Flush the pending text if the deadline has passed.
+    pub fn flush(&mut self, now: Instant) -> Vec<OutboundResponse> {
+        match self.deadline() {
+            Some(deadline) if deadline <= now => self.edit(now).into_iter().collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    fn start(&mut self) -> Vec<OutboundResponse> {
+        self.streaming = true;
+        self.text.clear();
+        self.flushed_len = 0;
+        self.last_chunk = None;
+        self.last_edit = None;
+        match self.config {
+            Some(_) => vec![OutboundResponse::StreamStart],
+            None => Vec::new(),
+        }
+    }
+
+    /// End the stream, flushing whatever the last edit didn't show.
+    fn finish(&mut self) -> Vec<OutboundResponse> {
+        if !self.streaming {
+            return Vec::new();
+        }
+        self.streaming = false;
+        let text = std::mem::take(&mut self.text);
+        if self.config.is_none() {
+            return if text.is_empty() {
+                Vec::new()
+            } else {
+                vec![OutboundResponse::Text(text)]
+            };
+        }
+
+        let mut out = Vec::new();
+        if text.len() > self.flushed_len {
+            out.push(OutboundResponse::StreamChunk(text));
+        }
+        out.push(OutboundResponse::StreamEnd);
+        out
+    }
+
+    fn pending(&self) -> usize {
+        self.text.len() - self.flushed_len
+    }
+
+    fn should_flush(&self, now: Instant) -> bool {
+        let Some(config) = self.config else {
+            return false;
+        };
+        let pending = self.pending();
+        if pending >= config.max_chars {
+            return true;
+        }
+        let interval_passed = self
+            .last_edit
+            .is_none_or(|last_edit| now.duration_since(last_edit) >= config.min_interval);
+        pending >= config.min_chars && interval_passed
+    }
+
+    fn edit(&mut self, now: Instant) -> Option<OutboundResponse> {
+        if self.config.is_none() || self.pending() == 0 {
+            return None;
+        }
+        self.flushed_len = self.text.len();
+        self.last_edit = Some(now);
+        Some(OutboundResponse::StreamChunk(self.text.clone()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> CoalesceConfig {
+        CoalesceConfig {
+            min_chars: 5,
+            max_chars: 20,
+            idle_timeout: Duration::from_millis(100),
+            min_interval: Duration::from_millis(300),
+        }
+    }
+
+    fn chunk(text: &str) -> OutboundResponse {
+        OutboundResponse::StreamChunk(text.into())
+    }
+
+    fn texts(responses: &[OutboundResponse]) -> Vec<String> {
+        responses
+            .iter()
+            .map(|response| match response {
+                OutboundResponse::StreamStart => "<start>".into(),
+                OutboundResponse::StreamChunk(text) => format!("edit:{text}"),
+                OutboundResponse::StreamEnd => "<end>".into(),
+                OutboundResponse::Text(text) => format!("text:{text}"),
+                other => format!("{other:?}"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_coalescer_batches_deltas_into_cumulative_edits() {
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        let mut coalescer = StreamCoalescer::new(Some(config()));
+
+        assert_eq!(
+            texts(&coalescer.push(OutboundResponse::StreamStart, at(0))),
+            ["<start>"]
+        );
+        assert!(coalescer.push(chunk("he"), at(0)).is_empty());
+        assert_eq!(texts(&coalescer.push(chunk("llo"), at(10))), ["edit:hello"]);
+
+        // Enough new text, but too soon after the last edit.
+        assert!(coalescer.push(chunk(" world"), at(50)).is_empty());
+        assert_eq!(coalescer.deadline(), Some(at(310)));
+        assert!(coalescer.flush(at(200)).is_empty());
+        assert_eq!(texts(&coalescer.flush(at(310))), ["edit:hello world"]);
+        assert_eq!(coalescer.deadline(), None);
+
+        // A large burst is flushed regardless of the interval.
+        assert_eq!(
+            texts(&coalescer.push(chunk(&"x".repeat(20)), at(320))),
+            [format!("edit:hello world{}", "x".repeat(20))]
+        );
+
+        assert!(coalescer.push(chunk("!"), at(330)).is_empty());
+        assert_eq!(
+            texts(&coalescer.push(OutboundResponse::StreamEnd, at(340))),
+            [
+                format!("edit:hello world{}!", "x".repeat(20)),
+                "<end>".into()
+            ]
+        );
+    }
+
+    #[test]
+    fn test_coalescer_without_editing_sends_the_final_text() {
+        let now = Instant::now();
+        let mut coalescer = StreamCoalescer::new(None);
+        assert!(
+            coalescer
+                .push(OutboundResponse::StreamStart, now)
+                .is_empty()
+        );
+        assert!(coalescer.push(chunk("hello "), now).is_empty());
+        assert!(coalescer.push(chunk("world"), now).is_empty());
+        assert_eq!(coalescer.deadline(), None);
+        assert_eq!(
+            texts(&coalescer.push(OutboundResponse::StreamEnd, now)),
+            ["text:hello world"]
+        );
+
+        // Other responses pass through untouched.
+        assert_eq!(
+            texts(&coalescer.push(OutboundResponse::Text("hi".into()), now)),
+            ["text:hi"]
+        );
+    }
+}
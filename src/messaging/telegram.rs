@@ -1,6 +1,10 @@
 //! Telegram messaging adapter using teloxide.
 
+use crate::activation::MENTIONS_BOT;
 use crate::config::TelegramPermissions;
+use crate::messaging::format::{Platform, TELEGRAM_MAX_LENGTH, format_message};
+use crate::messaging::postprocess::link_previews_suppressed;
+use crate::messaging::stream::CoalesceConfig;
 use crate::messaging::traits::{InboundStream, Messaging};
 use crate::{Attachment, InboundMessage, MessageContent, OutboundResponse, StatusUpdate};
 
@@ -10,13 +14,12 @@ use teloxide::Bot;
 use teloxide::payloads::setters::*;
 use teloxide::requests::{Request, Requester};
 use teloxide::types::{
-    ChatAction, ChatId, InputFile, MediaKind, MessageId, MessageKind, ReactionType,
-    ReplyParameters, UpdateKind, UserId,
+    ChatAction, ChatId, InputFile, LinkPreviewOptions, MediaKind, MessageId, MessageKind,
+    ReactionType, ReplyParameters, UpdateKind, UserId,
 };
 
 use std::collections::HashMap;
 use std::sync::Arc;
-use std::time::Instant;
 use tokio::sync::{RwLock, mpsc};
 use tokio::task::JoinHandle;
 
@@ -38,15 +41,8 @@ pub struct TelegramAdapter {
 struct ActiveStream {
     chat_id: ChatId,
     message_id: MessageId,
-    last_edit: Instant,
 }
 
-/// Telegram's per-message character limit.
-const MAX_MESSAGE_LENGTH: usize = 4096;
-
-/// Minimum interval between streaming edits to avoid rate limits.
-const STREAM_EDIT_INTERVAL: std::time::Duration = std::time::Duration::from_millis(1000);
-
 impl TelegramAdapter {
     pub fn new(token: impl Into<String>, permissions: Arc<ArcSwap<TelegramPermissions>>) -> Self {
         let token = token.into();
@@ -233,14 +229,18 @@ impl Messaging for TelegramAdapter {
         response: OutboundResponse,
     ) -> crate::Result<()> {
         let chat_id = self.extract_chat_id(message)?;
+        let suppress_previews = link_previews_suppressed(message);
 
         match response {
             OutboundResponse::Text(text) => {
                 self.stop_typing(&message.conversation_id).await;
 
-                for chunk in split_message(&text, MAX_MESSAGE_LENGTH) {
-                    self.bot
-                        .send_message(chat_id, &chunk)
+                for chunk in format_message(&text, Platform::Telegram) {
+                    let mut request = self.bot.send_message(chat_id, &chunk);
+                    if suppress_previews {
+                        request = request.link_preview_options(disabled_link_preview());
+                    }
+                    request
                         .send()
                         .await
                         .context("failed to send telegram message")?;
@@ -255,8 +255,11 @@ impl Messaging for TelegramAdapter {
                 // Telegram doesn't have named threads. Reply to the source message instead.
                 let reply_to = self.extract_message_id(message).ok();
 
-                for chunk in split_message(&text, MAX_MESSAGE_LENGTH) {
+                for chunk in format_message(&text, Platform::Telegram) {
                     let mut request = self.bot.send_message(chat_id, &chunk);
+                    if suppress_previews {
+                        request = request.link_preview_options(disabled_link_preview());
+                    }
                     if let Some(reply_id) = reply_to {
                         request = request.reply_parameters(ReplyParameters::new(reply_id));
                     }
@@ -321,20 +324,16 @@ impl Messaging for TelegramAdapter {
                     ActiveStream {
                         chat_id,
                         message_id: placeholder.id,
-                        last_edit: Instant::now(),
                     },
                 );
             }
             OutboundResponse::StreamChunk(text) => {
-                let mut active = self.active_messages.write().await;
-                if let Some(stream) = active.get_mut(&message.conversation_id) {
-                    // Rate-limit edits to avoid Telegram API throttling
-                    if stream.last_edit.elapsed() < STREAM_EDIT_INTERVAL {
-                        return Ok(());
-                    }
-
-                    let display_text = if text.len() > MAX_MESSAGE_LENGTH {
-                        let end = text.floor_char_boundary(MAX_MESSAGE_LENGTH - 3);
+                // Edits arrive already spaced out for Telegram's rate limit,
+                // see `stream_coalescing`.
+                let active = self.active_messages.read().await;
+                if let Some(stream) = active.get(&message.conversation_id) {
+                    let display_text = if text.len() > TELEGRAM_MAX_LENGTH {
+                        let end = text.floor_char_boundary(TELEGRAM_MAX_LENGTH - 3);
                         format!("{}...", &text[..end])
                     } else {
                         text
@@ -348,7 +347,6 @@ impl Messaging for TelegramAdapter {
                     {
                         tracing::debug!(%error, "failed to edit streaming message");
                     }
-                    stream.last_edit = Instant::now();
                 }
             }
             OutboundResponse::StreamEnd => {
@@ -414,6 +412,10 @@ impl Messaging for TelegramAdapter {
         Ok(())
     }
 
+    fn stream_coalescing(&self) -> Option<CoalesceConfig> {
+        Some(CoalesceConfig::TELEGRAM)
+    }
+
     async fn shutdown(&self) -> crate::Result<()> {
         // Cancel all typing indicator tasks
         let mut tasks = self.typing_tasks.write().await;
@@ -619,6 +621,22 @@ fn build_metadata(
         metadata.insert("telegram_bot_username".into(), bot_username.clone().into());
     }
 
+    // Addressed to us: a private chat, an @mention, or a reply to one of ours.
+    let mentioned = bot_username.as_ref().is_some_and(|username| {
+        extract_text(message).is_some_and(|text| {
+            text.to_lowercase()
+                .contains(&format!("@{}", username.to_lowercase()))
+        })
+    });
+    let replied_to = message
+        .reply_to_message()
+        .and_then(|reply| reply.from.as_ref())
+        .is_some_and(|from| from.is_bot && from.username == *bot_username);
+    metadata.insert(
+        MENTIONS_BOT.into(),
+        (message.chat.is_private() || mentioned || replied_to).into(),
+    );
+
     // Reply-to context for threading
     if let Some(reply) = message.reply_to_message() {
         metadata.insert(
@@ -650,30 +668,12 @@ fn build_display_name(user: &teloxide::types::User) -> String {
     }
 }
 
-/// Split a message into chunks that fit within Telegram's character limit.
-/// Tries to split at newlines, then spaces, then hard-cuts.
-fn split_message(text: &str, max_len: usize) -> Vec<String> {
-    if text.len() <= max_len {
-        return vec![text.to_string()];
-    }
-
-    let mut chunks = Vec::new();
-    let mut remaining = text;
-
-    while !remaining.is_empty() {
-        if remaining.len() <= max_len {
-            chunks.push(remaining.to_string());
-            break;
-        }
-
-        let split_at = remaining[..max_len]
-            .rfind('\n')
-            .or_else(|| remaining[..max_len].rfind(' '))
-            .unwrap_or(max_len);
-
-        chunks.push(remaining[..split_at].to_string());
-        remaining = remaining[split_at..].trim_start();
+fn disabled_link_preview() -> LinkPreviewOptions {
+    LinkPreviewOptions {
+        is_disabled: true,
+        url: None,
+        prefer_small_media: false,
+        prefer_large_media: false,
+        show_above_text: false,
     }
-
-    chunks
 }
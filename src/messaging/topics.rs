@@ -0,0 +1,433 @@
+//! Sensitive-topic policy for inbound messages.
+//!
+//! With `[topics]` enabled, every message is checked against the operator's
+//! categories before it reaches an agent: first by each category's regex
+//! patterns, then, with the `llm` method, by one cheap model call against the
+//! categories' descriptions. A conversation that hits a category is pinned to
+//! it. Routed categories send the conversation to their own agent, with the
+//! category's disclaimer posted once; declined ones answer every message with
+//! the decline message and never reach a model. A classification that fails
+//! or runs too long lets the message through as usual.
+
+use crate::config::{RuntimeConfig, TopicAction, TopicCategory, TopicMethod, TopicsConfig};
+use crate::llm::{LlmManager, Priority, SpacebotModel};
+use crate::{Agent, AgentId, InboundMessage, MessageContent, ProcessType};
+
+use anyhow::Context as _;
+use regex::{Regex, RegexBuilder};
+use rig::agent::AgentBuilder;
+use rig::completion::Prompt as _;
+use serde::Deserialize;
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Classification taking longer than this is abandoned and the message passes.
+const CLASSIFY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Only this much of a message is classified.
+const MAX_MESSAGE_CHARS: usize = 2000;
+
+/// Past this many pinned conversations, ones idle for `PIN_TTL` are dropped.
+const PRUNE_THRESHOLD: usize = 10_000;
+const PIN_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Reply to declined messages when the category doesn't set one.
+const DEFAULT_DECLINE_MESSAGE: &str =
+    "Sorry, I can't help with this topic here. Please reach out to a qualified professional.";
+
+/// What to do with a message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TopicDecision {
+    /// Not in a sensitive category; route it as usual.
+    Pass,
+    /// Hand it to the category's agent, posting the disclaimer first if set.
+    Route {
+        category: String,
+        agent_id: AgentId,
+        disclaimer: Option<String>,
+    },
+    /// Answer with `message` and go no further.
+    Decline { category: String, message: String },
+}
+
+/// A category with its patterns compiled and its agent checked.
+struct Policy {
+    category: TopicCategory,
+    patterns: Vec<Regex>,
+    /// The agent for a routed category. None declines, including routed
+    /// categories whose agent isn't configured.
+    agent_id: Option<AgentId>,
+}
+
+impl Policy {
+    /// The decision for a message in this category. The disclaimer only goes
+    /// with the first one.
+    fn decision(&self, first: bool) -> TopicDecision {
+        match &self.agent_id {
+            Some(agent_id) => TopicDecision::Route {
+                category: self.category.name.clone(),
+                agent_id: agent_id.clone(),
+                disclaimer: self.category.disclaimer.clone().filter(|_| first),
+            },
+            None => TopicDecision::Decline {
+                category: self.category.name.clone(),
+                message: self
+                    .category
+                    .decline_message
+                    .clone()
+                    .unwrap_or_else(|| DEFAULT_DECLINE_MESSAGE.to_string()),
+            },
+        }
+    }
+}
+
+/// Applies the sensitive-topic policy to inbound messages.
+pub struct TopicRouter {
+    method: TopicMethod,
+    model: Option<String>,
+    policies: Vec<Policy>,
+    llm_manager: Arc<LlmManager>,
+    /// The default agent's config, for model routing.
+    runtime_config: Arc<RuntimeConfig>,
+    /// Conversation ID to the index of its category, and when it was last seen.
+    pinned: Mutex<HashMap<String, (usize, Instant)>>,
+}
+
+impl TopicRouter {
+    /// The router for `config`, or None when the policy is off or has no
+    /// categories.
+    pub fn from_config(
+        config: &TopicsConfig,
+        agents: &HashMap<AgentId, Agent>,
+        default_agent_id: &str,
+    ) -> Option<Arc<Self>> {
+        if !config.enabled {
+            return None;
+        }
+        if config.categories.is_empty() {
+            tracing::warn!("topic policy is enabled but has no categories, disabling it");
+            return None;
+        }
+        let default_agent = agents.get(default_agent_id)?;
+
+        let policies = config
+            .categories
+            .iter()
+            .map(|category| {
+                let agent_id = match (category.action, category.agent.as_deref()) {
+                    (TopicAction::Route, Some(agent_id)) if agents.contains_key(agent_id) => {
+                        Some(AgentId::from(agent_id))
+                    }
+                    (TopicAction::Route, agent_id) => {
+                        tracing::warn!(
+                            category = %category.name,
+                            agent_id,
+                            "topic category routes to an agent that isn't configured, declining it instead"
+                        );
+                        None
+                    }
+                    (TopicAction::Decline, _) => None,
+                };
+                Policy {
+                    category: category.clone(),
+                    patterns: compile_patterns(&category.patterns),
+                    agent_id,
+                }
+            })
+            .collect::<Vec<_>>();
+
+        tracing::info!(
+            method = ?config.method,
+            categories = policies.len(),
+            "topic policy enabled"
+        );
+        Some(Arc::new(Self {
+            method: config.method,
+            model: config.model.clone(),
+            policies,
+            llm_manager: default_agent.deps.llm_manager.clone(),
+            runtime_config: default_agent.deps.runtime_config.clone(),
+            pinned: Mutex::new(HashMap::new()),
+        }))
+    }
+
+    /// What to do with `message`: its conversation's category if it has one,
+    /// otherwise a fresh classification.
+    pub async fn check(&self, message: &InboundMessage) -> TopicDecision {
+        if let Some(index) = self.pinned(&message.conversation_id) {
+            return self.policies[index].decision(false);
+        }
+
+        let text = match &message.content {
+            MessageContent::Text(text) => text.as_str(),
+            MessageContent::Media { text, .. } => text.as_deref().unwrap_or_default(),
+        };
+        let text: String = text.trim().chars().take(MAX_MESSAGE_CHARS).collect();
+        if text.is_empty() {
+            return TopicDecision::Pass;
+        }
+
+        let index = match match_patterns(&self.policies, &text) {
+            Some(index) => Some(index),
+            None if self.method == TopicMethod::Llm => {
+                match tokio::time::timeout(CLASSIFY_TIMEOUT, self.classify(&text)).await {
+                    Ok(Ok(index)) => index,
+                    Ok(Err(error)) => {
+                        tracing::warn!(
+                            %error,
+                            conversation_id = %message.conversation_id,
+                            "topic classification failed, message passes"
+                        );
+                        None
+                    }
+                    Err(_) => {
+                        tracing::warn!(
+                            conversation_id = %message.conversation_id,
+                            "topic classification timed out, message passes"
+                        );
+                        None
+                    }
+                }
+            }
+            None => None,
+        };
+        let Some(index) = index else {
+            return TopicDecision::Pass;
+        };
+
+        let decision = self.policies[index].decision(true);
+        tracing::info!(
+            conversation_id = %message.conversation_id,
+            category = %self.policies[index].category.name,
+            declined = matches!(decision, TopicDecision::Decline { .. }),
+            "sensitive topic detected"
+        );
+        self.pin(&message.conversation_id, index);
+        decision
+    }
+
+    fn pinned(&self, conversation_id: &str) -> Option<usize> {
+        let mut pinned = self.lock();
+        let (index, last_seen) = pinned.get_mut(conversation_id)?;
+        *last_seen = Instant::now();
+        Some(*index)
+    }
+
+    fn pin(&self, conversation_id: &str, index: usize) {
+        let now = Instant::now();
+        let mut pinned = self.lock();
+        if pinned.len() >= PRUNE_THRESHOLD {
+            pinned.retain(|_, (_, last_seen)| now.duration_since(*last_seen) < PIN_TTL);
+        }
+        pinned.insert(conversation_id.to_string(), (index, now));
+    }
+
+    /// The category the model puts `text` in, if any.
+    async fn classify(&self, text: &str) -> anyhow::Result<Option<usize>> {
+        if !self
+            .policies
+            .iter()
+            .any(|policy| policy.category.description.is_some())
+        {
+            return Ok(None);
+        }
+
+        let routing = self.runtime_config.routing.load();
+        let model_name = self
+            .model
+            .clone()
+            .unwrap_or_else(|| routing.resolve(ProcessType::Worker, None).to_string());
+        let model = SpacebotModel::make(&self.llm_manager, &model_name)
+            .with_routing((**routing).clone())
+            .with_priority(Priority::Interactive);
+        let agent = AgentBuilder::new(model)
+            .preamble(crate::prompts::text::get("topics"))
+            .build();
+
+        let response = agent
+            .prompt(render_prompt(&self.policies, text))
+            .await
+            .context("topic model call failed")?;
+        parse_choice(&response, &self.policies)
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, (usize, Instant)>> {
+        self.pinned
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl std::fmt::Debug for TopicRouter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TopicRouter")
+            .field("method", &self.method)
+            .field("categories", &self.policies.len())
+            .finish()
+    }
+}
+
+/// Compile a category's patterns case-insensitively. Config validation
+/// rejects bad patterns, so one that fails here is only logged and skipped.
+fn compile_patterns(patterns: &[String]) -> Vec<Regex> {
+    patterns
+        .iter()
+        .filter_map(|pattern| {
+            RegexBuilder::new(pattern)
+                .case_insensitive(true)
+                .build()
+                .inspect_err(|error| tracing::warn!(%error, pattern, "invalid topic pattern"))
+                .ok()
+        })
+        .collect()
+}
+
+/// The first category with a pattern matching `text`.
+fn match_patterns(policies: &[Policy], text: &str) -> Option<usize> {
+    policies
+        .iter()
+        .position(|policy| policy.patterns.iter().any(|pattern| pattern.is_match(text)))
+}
+
+fn render_prompt(policies: &[Policy], text: &str) -> String {
+    let mut prompt = String::from("Categories:\n");
+    for policy in policies {
+        let Some(description) = &policy.category.description else {
+            continue;
+        };
+        let description = description.split_whitespace().collect::<Vec<_>>();
+        prompt.push_str(&format!(
+            "- {}: {}\n",
+            policy.category.name,
+            description.join(" ")
+        ));
+    }
+    prompt.push_str(&format!("\nMessage:\n{text}"));
+    prompt
+}
+
+#[derive(Deserialize)]
+struct Choice {
+    category: Option<String>,
+}
+
+/// The index of the model's pick. A reply of no category is None.
+fn parse_choice(response: &str, policies: &[Policy]) -> anyhow::Result<Option<usize>> {
+    let cleaned = response
+        .trim()
+        .trim_start_matches("```json")
+        .trim_start_matches("```")
+        .trim_end_matches("```")
+        .trim();
+    let choice: Choice = serde_json::from_str(cleaned)
+        .with_context(|| format!("topic model reply isn't the requested JSON: {cleaned}"))?;
+    let Some(category) = choice.category else {
+        return Ok(None);
+    };
+    policies
+        .iter()
+        .position(|policy| {
+            policy.category.description.is_some() && policy.category.name == category
+        })
+        .map(Some)
+        .with_context(|| format!("topic model picked unknown category '{category}'"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn category(name: &str, patterns: &[&str], action: TopicAction) -> TopicCategory {
+        TopicCategory {
+            name: name.into(),
+            description: Some(format!("Questions about {name} matters.")),
+            patterns: patterns.iter().map(|pattern| pattern.to_string()).collect(),
+            action,
+            agent: None,
+            disclaimer: None,
+            decline_message: None,
+        }
+    }
+
+    fn policies() -> Vec<Policy> {
+        let mut medical = category(
+            "medical",
+            &[r"\bdiagnos", r"\bdosage\b"],
+            TopicAction::Route,
+        );
+        medical.disclaimer = Some("I'm not a doctor.".into());
+        let mut self_harm = category("self_harm", &[r"\bhurt myself\b"], TopicAction::Decline);
+        self_harm.decline_message = Some("Please call a crisis line.".into());
+        vec![
+            Policy {
+                patterns: compile_patterns(&medical.patterns),
+                category: medical,
+                agent_id: Some("careful".into()),
+            },
+            Policy {
+                patterns: compile_patterns(&self_harm.patterns),
+                category: self_harm,
+                agent_id: None,
+            },
+        ]
+    }
+
+    #[test]
+    fn test_match_patterns() {
+        let policies = policies();
+        assert_eq!(
+            match_patterns(&policies, "What DOSAGE of ibuprofen is safe?"),
+            Some(0)
+        );
+        assert_eq!(
+            match_patterns(&policies, "sometimes I want to hurt myself"),
+            Some(1)
+        );
+        assert_eq!(match_patterns(&policies, "What's the weather?"), None);
+    }
+
+    #[test]
+    fn test_decision_sends_disclaimer_once() {
+        let policies = policies();
+        assert_eq!(
+            policies[0].decision(true),
+            TopicDecision::Route {
+                category: "medical".into(),
+                agent_id: "careful".into(),
+                disclaimer: Some("I'm not a doctor.".into()),
+            }
+        );
+        assert_eq!(
+            policies[0].decision(false),
+            TopicDecision::Route {
+                category: "medical".into(),
+                agent_id: "careful".into(),
+                disclaimer: None,
+            }
+        );
+        assert_eq!(
+            policies[1].decision(false),
+            TopicDecision::Decline {
+                category: "self_harm".into(),
+                message: "Please call a crisis line.".into(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_choice() {
+        let policies = policies();
+        assert_eq!(
+            parse_choice("```json\n{\"category\": \"self_harm\"}\n```", &policies).unwrap(),
+            Some(1)
+        );
+        assert_eq!(
+            parse_choice("{\"category\": null}", &policies).unwrap(),
+            None
+        );
+        assert!(parse_choice("{\"category\": \"legal\"}", &policies).is_err());
+        assert!(parse_choice("medical, probably", &policies).is_err());
+    }
+}
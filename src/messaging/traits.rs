@@ -1,7 +1,9 @@
 //! Messaging trait and dynamic dispatch companion.
 
 use crate::error::Result;
+use crate::messaging::stream::CoalesceConfig;
 use crate::{InboundMessage, OutboundResponse, StatusUpdate};
+use chrono::{DateTime, Utc};
 use futures::Stream;
 use std::pin::Pin;
 
@@ -62,9 +64,37 @@ pub trait Messaging: Send + Sync + 'static {
         async { Ok(Vec::new()) }
     }
 
+    /// Fetch the messages posted to a broadcast target since `since`, for
+    /// digests. Returns at most the newest `limit`, oldest first. Adapters
+    /// without channel history return an error.
+    fn fetch_channel_history(
+        &self,
+        target: &str,
+        since: DateTime<Utc>,
+        limit: usize,
+    ) -> impl std::future::Future<Output = Result<Vec<HistoryMessage>>> + Send {
+        let _ = (target, since, limit);
+        let adapter = self.name().to_string();
+        async move { Err(anyhow::anyhow!("{adapter} can't fetch channel history").into()) }
+    }
+
     /// Health check.
     fn health_check(&self) -> impl std::future::Future<Output = Result<()>> + Send;
 
+    /// Whether the manager should call `start` again when the inbound stream
+    /// ends. Adapters that reconnect by themselves, or whose stream ends
+    /// without anything being wrong, return false.
+    fn restart_on_disconnect(&self) -> bool {
+        true
+    }
+
+    /// How streamed replies are coalesced into edits of one message. None
+    /// for adapters that can't edit what they've sent; they get the finished
+    /// reply as a single `Text`.
+    fn stream_coalescing(&self) -> Option<CoalesceConfig> {
+        None
+    }
+
     /// Graceful shutdown.
     fn shutdown(&self) -> impl std::future::Future<Output = Result<()>> + Send {
         async { Ok(()) }
@@ -104,10 +134,21 @@ pub trait MessagingDyn: Send + Sync + 'static {
         limit: usize,
     ) -> Pin<Box<dyn std::future::Future<Output = Result<Vec<HistoryMessage>>> + Send + 'a>>;
 
+    fn fetch_channel_history<'a>(
+        &'a self,
+        target: &'a str,
+        since: DateTime<Utc>,
+        limit: usize,
+    ) -> Pin<Box<dyn std::future::Future<Output = Result<Vec<HistoryMessage>>> + Send + 'a>>;
+
     fn health_check<'a>(
         &'a self,
     ) -> Pin<Box<dyn std::future::Future<Output = Result<()>> + Send + 'a>>;
 
+    fn restart_on_disconnect(&self) -> bool;
+
+    fn stream_coalescing(&self) -> Option<CoalesceConfig>;
+
     fn shutdown<'a>(&'a self)
     -> Pin<Box<dyn std::future::Future<Output = Result<()>> + Send + 'a>>;
 }
@@ -156,12 +197,29 @@ impl<T: Messaging> MessagingDyn for T {
         Box::pin(Messaging::fetch_history(self, message, limit))
     }
 
+    fn fetch_channel_history<'a>(
+        &'a self,
+        target: &'a str,
+        since: DateTime<Utc>,
+        limit: usize,
+    ) -> Pin<Box<dyn std::future::Future<Output = Result<Vec<HistoryMessage>>> + Send + 'a>> {
+        Box::pin(Messaging::fetch_channel_history(self, target, since, limit))
+    }
+
     fn health_check<'a>(
         &'a self,
     ) -> Pin<Box<dyn std::future::Future<Output = Result<()>> + Send + 'a>> {
         Box::pin(Messaging::health_check(self))
     }
 
+    fn restart_on_disconnect(&self) -> bool {
+        Messaging::restart_on_disconnect(self)
+    }
+
+    fn stream_coalescing(&self) -> Option<CoalesceConfig> {
+        Messaging::stream_coalescing(self)
+    }
+
     fn shutdown<'a>(
         &'a self,
     ) -> Pin<Box<dyn std::future::Future<Output = Result<()>> + Send + 'a>> {
@@ -0,0 +1,590 @@
+//! SMS and WhatsApp messaging adapter using Twilio.
+//!
+//! Twilio POSTs each inbound message to `/sms` as a form. Requests are
+//! checked against the `X-Twilio-Signature` header, and the reply goes out
+//! through the Messages API rather than in the webhook response, so slow
+//! turns don't hit Twilio's timeout. Each phone number is its own
+//! conversation: `twilio:+15551234567` for SMS and
+//! `twilio:whatsapp:+15551234567` for WhatsApp.
+//!
+//! SMS is billed per segment, so SMS replies are cut to the configured
+//! number of segments. WhatsApp replies are split into several messages
+//! instead. Outbound files are served from `/media/{id}` for a while so
+//! Twilio can fetch them.
+
+use crate::activation::MENTIONS_BOT;
+use crate::config::TwilioConfig;
+use crate::messaging::format::{Platform, format_message, render_tables};
+use crate::messaging::traits::{InboundStream, Messaging};
+use crate::{Attachment, InboundMessage, MessageContent, OutboundResponse};
+
+use anyhow::Context as _;
+use axum::Router;
+use axum::extract::{Form, Path, State};
+use axum::http::{HeaderMap, StatusCode, header};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use base64::Engine as _;
+use hmac::{Hmac, Mac};
+use sha1::Sha1;
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::{RwLock, mpsc};
+
+const API_BASE: &str = "https://api.twilio.com/2010-04-01";
+
+const API_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How long outbound media stays available for Twilio to fetch.
+const MEDIA_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// Prefix Twilio puts on WhatsApp addresses.
+const WHATSAPP_PREFIX: &str = "whatsapp:";
+
+/// Empty TwiML: acknowledge the message without replying inline.
+const EMPTY_TWIML: &str = r#"<?xml version="1.0" encoding="UTF-8"?><Response></Response>"#;
+
+/// Twilio adapter state.
+pub struct TwilioAdapter {
+    config: Arc<TwilioConfig>,
+    http: reqwest::Client,
+    inbound_tx: Arc<RwLock<Option<mpsc::Sender<InboundMessage>>>>,
+    /// Outbound files waiting to be fetched by Twilio, by ID.
+    media: Arc<RwLock<HashMap<String, StoredMedia>>>,
+    shutdown_tx: Arc<RwLock<Option<mpsc::Sender<()>>>>,
+}
+
+/// Shared state for axum handlers.
+#[derive(Clone)]
+struct AppState {
+    config: Arc<TwilioConfig>,
+    inbound_tx: Arc<RwLock<Option<mpsc::Sender<InboundMessage>>>>,
+    media: Arc<RwLock<HashMap<String, StoredMedia>>>,
+}
+
+struct StoredMedia {
+    data: Vec<u8>,
+    mime_type: String,
+    expires_at: Instant,
+}
+
+impl TwilioAdapter {
+    pub fn new(config: &TwilioConfig) -> Self {
+        let http = reqwest::Client::builder()
+            .timeout(API_TIMEOUT)
+            .build()
+            .expect("hardcoded reqwest client config");
+        Self {
+            config: Arc::new(config.clone()),
+            http,
+            inbound_tx: Arc::new(RwLock::new(None)),
+            media: Arc::new(RwLock::new(HashMap::new())),
+            shutdown_tx: Arc::new(RwLock::new(None)),
+        }
+    }
+
+    /// Our number to send to `to` from: the WhatsApp sender for WhatsApp
+    /// addresses, the SMS number otherwise.
+    fn sender_for(&self, to: &str) -> anyhow::Result<String> {
+        if to.starts_with(WHATSAPP_PREFIX) {
+            let number = self
+                .config
+                .whatsapp_number
+                .as_deref()
+                .context("twilio whatsapp_number is not configured")?;
+            Ok(format!("{WHATSAPP_PREFIX}{number}"))
+        } else {
+            self.config
+                .sms_number
+                .clone()
+                .context("twilio sms_number is not configured")
+        }
+    }
+
+    /// Send `response` from `from` to `to`.
+    async fn deliver(
+        &self,
+        from: &str,
+        to: &str,
+        response: OutboundResponse,
+    ) -> anyhow::Result<()> {
+        match response {
+            OutboundResponse::Text(text) | OutboundResponse::ThreadReply { text, .. } => {
+                if to.starts_with(WHATSAPP_PREFIX) {
+                    for chunk in format_message(&text, Platform::Sms) {
+                        self.send_message(from, to, &chunk, None).await?;
+                    }
+                } else {
+                    let text = render_tables(&text, Platform::Sms);
+                    let body = fit_sms(&text, self.config.max_sms_segments);
+                    self.send_message(from, to, &body, None).await?;
+                }
+            }
+            OutboundResponse::File {
+                filename,
+                data,
+                mime_type,
+                caption,
+            } => {
+                let url = self.host_media(&filename, data, mime_type).await;
+                let caption = caption.unwrap_or_default();
+                let body = if to.starts_with(WHATSAPP_PREFIX) {
+                    caption
+                } else {
+                    fit_sms(&caption, self.config.max_sms_segments)
+                };
+                self.send_message(from, to, &body, Some(&url)).await?;
+            }
+            // SMS has no reactions, typing indicators or message edits, and
+            // each extra message costs money.
+            OutboundResponse::Reaction(_)
+            | OutboundResponse::Status(_)
+            | OutboundResponse::StreamStart
+            | OutboundResponse::StreamChunk(_)
+            | OutboundResponse::StreamEnd => {}
+        }
+        Ok(())
+    }
+
+    /// Keep a file in memory and return the public URL Twilio fetches it
+    /// from. Expired files are dropped on the way.
+    async fn host_media(&self, filename: &str, data: Vec<u8>, mime_type: String) -> String {
+        let id = uuid::Uuid::new_v4().to_string();
+        let now = Instant::now();
+        let mut media = self.media.write().await;
+        media.retain(|_, stored| stored.expires_at > now);
+        media.insert(
+            id.clone(),
+            StoredMedia {
+                data,
+                mime_type,
+                expires_at: now + MEDIA_TTL,
+            },
+        );
+        tracing::debug!(%filename, %id, "hosting outbound twilio media");
+        format!("{}/media/{id}", self.config.public_url)
+    }
+
+    async fn send_message(
+        &self,
+        from: &str,
+        to: &str,
+        body: &str,
+        media_url: Option<&str>,
+    ) -> anyhow::Result<()> {
+        let mut form = vec![("To", to), ("From", from)];
+        if !body.is_empty() {
+            form.push(("Body", body));
+        }
+        if let Some(media_url) = media_url {
+            form.push(("MediaUrl", media_url));
+        }
+
+        let url = format!(
+            "{API_BASE}/Accounts/{}/Messages.json",
+            self.config.account_sid
+        );
+        let response = self
+            .http
+            .post(&url)
+            .basic_auth(&self.config.account_sid, Some(&self.config.auth_token))
+            .form(&form)
+            .send()
+            .await
+            .context("failed to reach Twilio")?;
+        let status = response.status();
+        if !status.is_success() {
+            let body: serde_json::Value = response.json().await.unwrap_or_default();
+            let message = body["message"].as_str().unwrap_or("no error message");
+            anyhow::bail!("Twilio rejected the message with HTTP {status}: {message}");
+        }
+        Ok(())
+    }
+}
+
+impl Messaging for TwilioAdapter {
+    fn name(&self) -> &str {
+        "twilio"
+    }
+
+    async fn start(&self) -> crate::Result<InboundStream> {
+        let (inbound_tx, inbound_rx) = mpsc::channel(256);
+        let (shutdown_tx, mut shutdown_rx) = mpsc::channel::<()>(1);
+
+        *self.inbound_tx.write().await = Some(inbound_tx);
+        *self.shutdown_tx.write().await = Some(shutdown_tx);
+
+        let state = AppState {
+            config: self.config.clone(),
+            inbound_tx: self.inbound_tx.clone(),
+            media: self.media.clone(),
+        };
+
+        let app = Router::new()
+            .route("/sms", post(handle_sms))
+            .route("/media/{id}", get(handle_media))
+            .with_state(state);
+
+        let bind = format!("{}:{}", self.config.bind, self.config.port);
+        let listener = tokio::net::TcpListener::bind(&bind)
+            .await
+            .with_context(|| format!("failed to bind twilio server to {bind}"))?;
+        tracing::info!(%bind, "twilio server listening");
+
+        tokio::spawn(async move {
+            if let Err(error) = axum::serve(listener, app)
+                .with_graceful_shutdown(async move {
+                    let _ = shutdown_rx.recv().await;
+                })
+                .await
+            {
+                tracing::error!(%error, "twilio server exited with error");
+            }
+        });
+
+        let stream = tokio_stream::wrappers::ReceiverStream::new(inbound_rx);
+        Ok(Box::pin(stream))
+    }
+
+    async fn respond(
+        &self,
+        message: &InboundMessage,
+        response: OutboundResponse,
+    ) -> crate::Result<()> {
+        let to = message
+            .metadata
+            .get("twilio_from")
+            .and_then(|value| value.as_str())
+            .context("missing twilio_from in metadata")?;
+        // Reply from the number they wrote to.
+        let from = match message
+            .metadata
+            .get("twilio_to")
+            .and_then(|value| value.as_str())
+        {
+            Some(from) => from.to_string(),
+            None => self.sender_for(to)?,
+        };
+        self.deliver(&from, to, response).await?;
+        Ok(())
+    }
+
+    async fn broadcast(&self, target: &str, response: OutboundResponse) -> crate::Result<()> {
+        let from = self.sender_for(target)?;
+        self.deliver(&from, target, response).await?;
+        Ok(())
+    }
+
+    async fn health_check(&self) -> crate::Result<()> {
+        Ok(())
+    }
+
+    async fn shutdown(&self) -> crate::Result<()> {
+        if let Some(tx) = self.shutdown_tx.read().await.as_ref() {
+            tx.send(()).await.ok();
+        }
+        tracing::info!("twilio adapter shut down");
+        Ok(())
+    }
+}
+
+// -- Axum handlers --
+
+async fn handle_sms(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Form(params): Form<Vec<(String, String)>>,
+) -> Response {
+    let signature = headers
+        .get("x-twilio-signature")
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+    let url = format!("{}/sms", state.config.public_url);
+    if !signature_valid(&state.config.auth_token, &url, &params, signature) {
+        tracing::warn!("rejected twilio request with an invalid signature");
+        return StatusCode::FORBIDDEN.into_response();
+    }
+
+    let param = |name: &str| {
+        params
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    };
+    let (Some(from), Some(to)) = (param("From"), param("To")) else {
+        return (StatusCode::BAD_REQUEST, "missing From or To").into_response();
+    };
+
+    let number = from.strip_prefix(WHATSAPP_PREFIX).unwrap_or(from);
+    if !state.config.allowed_numbers.is_empty()
+        && !state.config.allowed_numbers.iter().any(|n| n == number)
+    {
+        tracing::debug!(%from, "ignoring twilio message from a number not in allowed_numbers");
+        return twiml();
+    }
+
+    let text = param("Body").unwrap_or_default().to_string();
+    let attachments = media_attachments(&param);
+    if text.is_empty() && attachments.is_empty() {
+        return twiml();
+    }
+    let content = if attachments.is_empty() {
+        MessageContent::Text(text)
+    } else {
+        MessageContent::Media {
+            text: Some(text).filter(|text| !text.is_empty()),
+            attachments,
+        }
+    };
+
+    let mut metadata = HashMap::new();
+    metadata.insert("twilio_from".into(), serde_json::Value::from(from));
+    // Every text is one-to-one with the bot's number.
+    metadata.insert(MENTIONS_BOT.into(), serde_json::Value::Bool(true));
+    metadata.insert("twilio_to".into(), serde_json::Value::from(to));
+    let display_name = param("ProfileName").unwrap_or(number);
+    metadata.insert("display_name".into(), serde_json::Value::from(display_name));
+
+    let inbound = InboundMessage {
+        id: param("MessageSid")
+            .map(String::from)
+            .unwrap_or_else(|| uuid::Uuid::new_v4().to_string()),
+        source: "twilio".into(),
+        conversation_id: format!("twilio:{from}"),
+        sender_id: number.to_string(),
+        agent_id: None,
+        content,
+        timestamp: chrono::Utc::now(),
+        metadata,
+    };
+
+    let tx = state.inbound_tx.read().await;
+    let Some(tx) = tx.as_ref() else {
+        return (StatusCode::SERVICE_UNAVAILABLE, "twilio not initialized").into_response();
+    };
+    if tx.send(inbound).await.is_err() {
+        return (StatusCode::INTERNAL_SERVER_ERROR, "channel closed").into_response();
+    }
+
+    twiml()
+}
+
+async fn handle_media(State(state): State<AppState>, Path(id): Path<String>) -> Response {
+    let media = state.media.read().await;
+    match media.get(&id) {
+        Some(stored) if stored.expires_at > Instant::now() => (
+            [(header::CONTENT_TYPE, stored.mime_type.clone())],
+            stored.data.clone(),
+        )
+            .into_response(),
+        _ => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+fn twiml() -> Response {
+    ([(header::CONTENT_TYPE, "text/xml")], EMPTY_TWIML).into_response()
+}
+
+/// Attachments from the `MediaUrl{N}` and `MediaContentType{N}` parameters.
+fn media_attachments<'a>(param: &impl Fn(&str) -> Option<&'a str>) -> Vec<Attachment> {
+    let count: usize = param("NumMedia")
+        .and_then(|count| count.parse().ok())
+        .unwrap_or(0);
+    (0..count)
+        .filter_map(|index| {
+            let url = param(&format!("MediaUrl{index}"))?;
+            let mime_type =
+                param(&format!("MediaContentType{index}")).unwrap_or("application/octet-stream");
+            let name = url.rsplit('/').next().unwrap_or("media");
+            let extension = mime_type
+                .split('/')
+                .nth(1)
+                .and_then(|subtype| subtype.split([';', '+']).next())
+                .unwrap_or("bin");
+            Some(Attachment {
+                filename: format!("{name}.{extension}"),
+                mime_type: mime_type.to_string(),
+                url: url.to_string(),
+                size_bytes: None,
+            })
+        })
+        .collect()
+}
+
+/// Check Twilio's request signature: base64 HMAC-SHA1, keyed with the auth
+/// token, over the URL followed by each POST parameter's name and value in
+/// name order.
+fn signature_valid(
+    auth_token: &str,
+    url: &str,
+    params: &[(String, String)],
+    signature: &str,
+) -> bool {
+    let Ok(signature) = base64::engine::general_purpose::STANDARD.decode(signature) else {
+        return false;
+    };
+    let mut sorted: Vec<&(String, String)> = params.iter().collect();
+    sorted.sort();
+
+    let mut mac =
+        Hmac::<Sha1>::new_from_slice(auth_token.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(url.as_bytes());
+    for (key, value) in sorted {
+        mac.update(key.as_bytes());
+        mac.update(value.as_bytes());
+    }
+    mac.verify_slice(&signature).is_ok()
+}
+
+// -- SMS segments --
+
+/// Characters of the GSM 03.38 basic set, which cost one septet each.
+const GSM_BASIC: &str = "@£$¥èéùìòÇ\nØø\rÅåΔ_ΦΓΛΩΠΨΣΘΞÆæßÉ !\"#¤%&'()*+,-./0123456789:;<=>?\
+    ¡ABCDEFGHIJKLMNOPQRSTUVWXYZÄÖÑÜ§¿abcdefghijklmnopqrstuvwxyzäöñüà";
+
+/// Characters of the GSM extension table, which cost an escape plus a
+/// septet.
+const GSM_EXTENSION: &str = "^{}\\[~]|€\u{c}";
+
+/// How an SMS body is encoded, which decides how much fits in a segment.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SmsEncoding {
+    Gsm7,
+    Ucs2,
+}
+
+impl SmsEncoding {
+    fn of(text: &str) -> Self {
+        if text
+            .chars()
+            .all(|c| GSM_BASIC.contains(c) || GSM_EXTENSION.contains(c))
+        {
+            Self::Gsm7
+        } else {
+            Self::Ucs2
+        }
+    }
+
+    /// Units a character takes: septets for GSM-7, UTF-16 code units for
+    /// UCS-2.
+    fn units(self, c: char) -> usize {
+        match self {
+            Self::Gsm7 if GSM_EXTENSION.contains(c) => 2,
+            Self::Gsm7 => 1,
+            Self::Ucs2 => c.len_utf16(),
+        }
+    }
+
+    /// Units in a message that fits in one segment.
+    fn single_segment(self) -> usize {
+        match self {
+            Self::Gsm7 => 160,
+            Self::Ucs2 => 70,
+        }
+    }
+
+    /// Units per segment of a concatenated message, after the header.
+    fn multi_segment(self) -> usize {
+        match self {
+            Self::Gsm7 => 153,
+            Self::Ucs2 => 67,
+        }
+    }
+}
+
+/// Number of segments Twilio bills for sending `text` as one SMS.
+fn sms_segments(text: &str) -> usize {
+    let encoding = SmsEncoding::of(text);
+    let units: usize = text.chars().map(|c| encoding.units(c)).sum();
+    if units <= encoding.single_segment() {
+        1
+    } else {
+        units.div_ceil(encoding.multi_segment())
+    }
+}
+
+/// Cut `text` so it's billed as at most `max_segments` segments, marking
+/// the cut with "...".
+fn fit_sms(text: &str, max_segments: usize) -> String {
+    let text = text.trim();
+    if sms_segments(text) <= max_segments {
+        return text.to_string();
+    }
+
+    let encoding = SmsEncoding::of(text);
+    let budget = match max_segments {
+        0 | 1 => encoding.single_segment(),
+        segments => segments * encoding.multi_segment(),
+    };
+    let budget = budget - "...".len();
+
+    let mut used = 0;
+    let mut end = 0;
+    for (index, c) in text.char_indices() {
+        used += encoding.units(c);
+        if used > budget {
+            break;
+        }
+        end = index + c.len_utf8();
+    }
+    format!("{}...", text[..end].trim_end())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sms_segments_by_encoding() {
+        assert_eq!(sms_segments(&"a".repeat(160)), 1);
+        assert_eq!(sms_segments(&"a".repeat(161)), 2);
+        assert_eq!(sms_segments(&"a".repeat(306)), 2);
+        // Extension characters take two septets.
+        assert_eq!(sms_segments(&"€".repeat(80)), 1);
+        assert_eq!(sms_segments(&"€".repeat(81)), 2);
+        // One character outside GSM-7 switches the whole message to UCS-2.
+        assert_eq!(sms_segments(&format!("{}✓", "a".repeat(69))), 1);
+        assert_eq!(sms_segments(&format!("{}✓", "a".repeat(70))), 2);
+    }
+
+    #[test]
+    fn test_fit_sms_truncates_to_segment_budget() {
+        let short = "See you at 5.";
+        assert_eq!(fit_sms(short, 1), short);
+
+        let long = "word ".repeat(100);
+        let fitted = fit_sms(&long, 2);
+        assert!(fitted.ends_with("..."));
+        assert_eq!(sms_segments(&fitted), 2);
+
+        let emoji = "🚀".repeat(100);
+        let fitted = fit_sms(&emoji, 1);
+        assert_eq!(sms_segments(&fitted), 1);
+    }
+
+    #[test]
+    fn test_signature_matches_twilio_example() {
+        // From Twilio's webhook security documentation.
+        let params: Vec<(String, String)> = [
+            ("CallSid", "CA1234567890ABCDE"),
+            ("Caller", "+12349013030"),
+            ("Digits", "1234"),
+            ("From", "+12349013030"),
+            ("To", "+18005551212"),
+        ]
+        .into_iter()
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect();
+        let url = "https://mycompany.com/myapp.php?foo=1&bar=2";
+        let signature = "0/KCTR6DLpKmkAf8muzZqo1nDgQ=";
+
+        assert!(signature_valid("12345", url, &params, signature));
+
+        let mut tampered = params.clone();
+        tampered[2].1 = "9999".into();
+        assert!(!signature_valid("12345", url, &tampered, signature));
+        assert!(!signature_valid("12345", url, &params, "not base64"));
+    }
+}
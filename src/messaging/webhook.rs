@@ -4,7 +4,13 @@
 //! delivers responses via a per-conversation polling endpoint. This is
 //! the integration point for scripts, CI pipelines, and other programs
 //! that need to interact with Spacebot programmatically.
+//!
+//! Replies can also be pushed: each configured outbound endpoint is a
+//! delivery target (`webhook:{name}`) that receives replies as JSON POSTs,
+//! optionally HMAC-signed, with retries. An inbound request can name one in
+//! `notify` to get its conversation's replies pushed instead of polling.
 
+use crate::config::{OutboundWebhookConfig, WebhookConfig};
 use crate::messaging::traits::{InboundStream, Messaging};
 use crate::{InboundMessage, MessageContent, OutboundResponse};
 
@@ -14,19 +20,31 @@ use axum::extract::{Json, State};
 use axum::http::StatusCode;
 use axum::routing::{get, post};
 use serde::{Deserialize, Serialize};
+use spacebot_core::llm::signing::{self, HmacSigner, RequestSigner};
+use spacebot_core::llm::tags::RequestTags;
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::sync::{RwLock, mpsc};
 
+/// Delay before the first retry of an outbound POST, doubled for each one
+/// after it.
+const RETRY_BASE_DELAY: Duration = Duration::from_secs(1);
+
+const OUTBOUND_TIMEOUT: Duration = Duration::from_secs(30);
+
 /// Webhook adapter state.
 pub struct WebhookAdapter {
+    /// Whether to run the inbound HTTP server.
+    serve: bool,
     port: u16,
     bind: String,
     inbound_tx: Arc<RwLock<Option<mpsc::Sender<InboundMessage>>>>,
     /// Buffered responses per conversation_id, waiting to be polled.
     response_buffers: Arc<RwLock<HashMap<String, Vec<WebhookResponse>>>>,
     shutdown_tx: Arc<RwLock<Option<mpsc::Sender<()>>>>,
+    outbound: Arc<Outbound>,
 }
 
 /// Shared state for axum handlers.
@@ -34,6 +52,34 @@ pub struct WebhookAdapter {
 struct AppState {
     inbound_tx: Arc<RwLock<Option<mpsc::Sender<InboundMessage>>>>,
     response_buffers: Arc<RwLock<HashMap<String, Vec<WebhookResponse>>>>,
+    outbound: Arc<Outbound>,
+}
+
+/// The configured outbound endpoints, by name.
+struct Outbound {
+    http: reqwest::Client,
+    endpoints: HashMap<String, OutboundEndpoint>,
+}
+
+struct OutboundEndpoint {
+    config: OutboundWebhookConfig,
+    signer: Option<HmacSigner>,
+}
+
+/// What an outbound endpoint receives for one reply. Also the variables of
+/// the endpoint's body template.
+#[derive(Debug, Clone, Serialize)]
+struct OutboundPayload {
+    /// Name of the endpoint.
+    endpoint: String,
+    /// Conversation the reply belongs to. None for broadcasts.
+    conversation_id: Option<String>,
+    /// "text" or "file".
+    kind: &'static str,
+    /// The reply text, or the file's caption.
+    text: String,
+    filename: Option<String>,
+    sent_at: String,
 }
 
 /// Inbound webhook request body.
@@ -48,6 +94,18 @@ struct WebhookRequest {
     content: String,
     /// Optional agent to route to (overrides binding resolution).
     agent_id: Option<String>,
+    /// Outbound endpoint to push this conversation's replies to.
+    notify: Option<String>,
+    /// Caller's id for this event. A retry sent with the same id is dropped
+    /// as a duplicate instead of starting a second run.
+    event_id: Option<String>,
+    /// Sampling seed for the turn answering this message, for providers
+    /// that take one. Overrides the agent's configured `seed`.
+    seed: Option<u64>,
+    /// Business dimensions to count the turn's requests and cost under,
+    /// like `{"campaign": "spring-promo"}`.
+    #[serde(default)]
+    tags: BTreeMap<String, String>,
 }
 
 fn default_sender() -> String {
@@ -72,23 +130,149 @@ struct PollResponse {
 }
 
 impl WebhookAdapter {
-    pub fn new(port: u16, bind: impl Into<String>) -> Self {
+    pub fn new(config: &WebhookConfig) -> Self {
+        let endpoints = config
+            .outbound
+            .iter()
+            .map(|endpoint| {
+                let outbound = OutboundEndpoint {
+                    config: endpoint.clone(),
+                    signer: endpoint.signing.clone().map(HmacSigner::new),
+                };
+                (endpoint.name.clone(), outbound)
+            })
+            .collect();
+        let http = reqwest::Client::builder()
+            .timeout(OUTBOUND_TIMEOUT)
+            .build()
+            .expect("hardcoded reqwest client config");
+
         Self {
-            port,
-            bind: bind.into(),
+            serve: config.enabled,
+            port: config.port,
+            bind: config.bind.clone(),
             inbound_tx: Arc::new(RwLock::new(None)),
             response_buffers: Arc::new(RwLock::new(HashMap::new())),
             shutdown_tx: Arc::new(RwLock::new(None)),
+            outbound: Arc::new(Outbound { http, endpoints }),
         }
     }
 }
 
+impl Outbound {
+    /// Render the payload for `endpoint` and POST it, retrying transient
+    /// failures with exponential backoff.
+    async fn deliver(&self, payload: &OutboundPayload) -> anyhow::Result<()> {
+        let endpoint = self
+            .endpoints
+            .get(&payload.endpoint)
+            .with_context(|| format!("no outbound webhook named '{}'", payload.endpoint))?;
+        let body = render_body(endpoint.config.template.as_deref(), payload)?;
+
+        let mut attempt = 0;
+        loop {
+            let request = self
+                .http
+                .post(&endpoint.config.url)
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .body(body.clone());
+            let signer = endpoint
+                .signer
+                .as_ref()
+                .map(|signer| signer as &dyn RequestSigner);
+            let failure = match signing::send(request, signer).await {
+                Ok(response) if response.status().is_success() => return Ok(()),
+                Ok(response) => {
+                    let status = response.status();
+                    if !(status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS) {
+                        let body = response.text().await.unwrap_or_default();
+                        anyhow::bail!(
+                            "webhook '{}' rejected the request with HTTP {status}: {body}",
+                            payload.endpoint
+                        );
+                    }
+                    format!("HTTP {status}")
+                }
+                Err(error) => error.to_string(),
+            };
+
+            if attempt >= endpoint.config.max_retries {
+                anyhow::bail!(
+                    "webhook '{}' failed after {} attempts: {failure}",
+                    payload.endpoint,
+                    attempt + 1
+                );
+            }
+            let delay = RETRY_BASE_DELAY * 2u32.saturating_pow(attempt);
+            tracing::warn!(webhook = %payload.endpoint, %failure, ?delay, "retrying webhook");
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        }
+    }
+}
+
+/// The request body for `payload`: the rendered template, or the payload
+/// itself as JSON.
+fn render_body(template: Option<&str>, payload: &OutboundPayload) -> anyhow::Result<Vec<u8>> {
+    let Some(template) = template else {
+        return Ok(serde_json::to_vec(payload)?);
+    };
+
+    let mut env = minijinja::Environment::new();
+    env.add_filter("tojson", |value: minijinja::Value| {
+        serde_json::to_string(&value).map_err(|error| {
+            minijinja::Error::new(minijinja::ErrorKind::InvalidOperation, error.to_string())
+        })
+    });
+    let rendered = env
+        .render_str(template, payload)
+        .context("failed to render webhook template")?;
+    serde_json::from_str::<serde_json::Value>(&rendered)
+        .context("webhook template didn't render valid JSON")?;
+    Ok(rendered.into_bytes())
+}
+
+/// The outbound payload for a reply, or None for responses that carry no
+/// content (streaming, reactions, status).
+fn outbound_payload(
+    endpoint: &str,
+    conversation_id: Option<&str>,
+    response: &OutboundResponse,
+) -> Option<OutboundPayload> {
+    let (kind, text, filename) = match response {
+        OutboundResponse::Text(text) | OutboundResponse::ThreadReply { text, .. } => {
+            ("text", text.clone(), None)
+        }
+        OutboundResponse::File {
+            filename, caption, ..
+        } => (
+            "file",
+            caption.clone().unwrap_or_default(),
+            Some(filename.clone()),
+        ),
+        _ => return None,
+    };
+    Some(OutboundPayload {
+        endpoint: endpoint.to_string(),
+        conversation_id: conversation_id.map(String::from),
+        kind,
+        text,
+        filename,
+        sent_at: chrono::Utc::now().to_rfc3339(),
+    })
+}
+
 impl Messaging for WebhookAdapter {
     fn name(&self) -> &str {
         "webhook"
     }
 
     async fn start(&self) -> crate::Result<InboundStream> {
+        if !self.serve {
+            // Outbound endpoints only.
+            return Ok(Box::pin(futures::stream::empty()));
+        }
+
         let (inbound_tx, inbound_rx) = mpsc::channel(256);
         let (shutdown_tx, mut shutdown_rx) = mpsc::channel::<()>(1);
 
@@ -98,6 +282,7 @@ impl Messaging for WebhookAdapter {
         let state = AppState {
             inbound_tx: self.inbound_tx.clone(),
             response_buffers: self.response_buffers.clone(),
+            outbound: self.outbound.clone(),
         };
 
         let app = Router::new()
@@ -132,6 +317,23 @@ impl Messaging for WebhookAdapter {
         message: &InboundMessage,
         response: OutboundResponse,
     ) -> crate::Result<()> {
+        let notify = message
+            .metadata
+            .get("webhook_notify")
+            .and_then(|value| value.as_str())
+            .and_then(|endpoint| {
+                outbound_payload(endpoint, Some(&message.conversation_id), &response)
+            });
+        if let Some(payload) = notify {
+            // Pushed in the background so retries don't hold up the turn.
+            let outbound = self.outbound.clone();
+            tokio::spawn(async move {
+                if let Err(error) = outbound.deliver(&payload).await {
+                    tracing::error!(%error, "failed to push webhook reply");
+                }
+            });
+        }
+
         let webhook_response = match response {
             OutboundResponse::Text(text) => WebhookResponse {
                 response_type: "text".into(),
@@ -185,10 +387,22 @@ impl Messaging for WebhookAdapter {
         Ok(())
     }
 
+    async fn broadcast(&self, target: &str, response: OutboundResponse) -> crate::Result<()> {
+        if let Some(payload) = outbound_payload(target, None, &response) {
+            self.outbound.deliver(&payload).await?;
+        }
+        Ok(())
+    }
+
     async fn health_check(&self) -> crate::Result<()> {
         Ok(())
     }
 
+    fn restart_on_disconnect(&self) -> bool {
+        // Without the server, the stream is empty and ends right away.
+        false
+    }
+
     async fn shutdown(&self) -> crate::Result<()> {
         if let Some(tx) = self.shutdown_tx.read().await.as_ref() {
             tx.send(()).await.ok();
@@ -212,7 +426,28 @@ async fn handle_send(
         ));
     };
 
+    if let Some(notify) = &request.notify {
+        if !state.outbound.endpoints.contains_key(notify) {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                format!("no outbound webhook named '{notify}'"),
+            ));
+        }
+    }
+
+    let tags =
+        RequestTags::parse(request.tags).map_err(|error| (StatusCode::BAD_REQUEST, error))?;
+
     let mut metadata = HashMap::new();
+    if !tags.is_empty() {
+        metadata.insert("tags".into(), tags.to_json());
+    }
+    if let Some(notify) = request.notify {
+        metadata.insert("webhook_notify".into(), serde_json::Value::String(notify));
+    }
+    if let Some(seed) = request.seed {
+        metadata.insert("seed".into(), serde_json::Value::from(seed));
+    }
     metadata.insert(
         "webhook_conversation_id".into(),
         serde_json::Value::String(request.conversation_id.clone()),
@@ -225,7 +460,9 @@ async fn handle_send(
     let conversation_id = format!("webhook:{}", request.conversation_id);
 
     let inbound = InboundMessage {
-        id: uuid::Uuid::new_v4().to_string(),
+        id: request
+            .event_id
+            .unwrap_or_else(|| uuid::Uuid::new_v4().to_string()),
         source: "webhook".into(),
         conversation_id,
         sender_id: request.sender_id,
@@ -260,3 +497,46 @@ async fn handle_poll(
 async fn handle_health() -> StatusCode {
     StatusCode::OK
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn payload() -> OutboundPayload {
+        outbound_payload(
+            "alerts",
+            Some("webhook:ci"),
+            &OutboundResponse::Text("Build \"42\" failed".into()),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_render_body_defaults_to_payload() {
+        let body: serde_json::Value =
+            serde_json::from_slice(&render_body(None, &payload()).unwrap()).unwrap();
+
+        assert_eq!(body["endpoint"], "alerts");
+        assert_eq!(body["conversation_id"], "webhook:ci");
+        assert_eq!(body["kind"], "text");
+        assert_eq!(body["text"], "Build \"42\" failed");
+    }
+
+    #[test]
+    fn test_render_body_uses_template() {
+        let template = r#"{"content": {{ text | tojson }}, "source": "spacebot"}"#;
+
+        let body: serde_json::Value =
+            serde_json::from_slice(&render_body(Some(template), &payload()).unwrap()).unwrap();
+
+        assert_eq!(
+            body,
+            serde_json::json!({"content": "Build \"42\" failed", "source": "spacebot"})
+        );
+    }
+
+    #[test]
+    fn test_render_body_rejects_invalid_json() {
+        assert!(render_body(Some("text: {{ text }}"), &payload()).is_err());
+    }
+}
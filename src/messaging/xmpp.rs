@@ -0,0 +1,452 @@
+//! XMPP messaging adapter using tokio-xmpp.
+//!
+//! Logs in to the configured account, joins the configured rooms (MUC) and
+//! accepts one-on-one chats from allowed JIDs. Each room is a conversation
+//! (`xmpp:ops@conference.example.com`), and so is each chat partner's bare
+//! JID. Room history replayed on join is skipped.
+//!
+//! Messages are plain text, protected by the client-to-server TLS
+//! connection. OMEMO end-to-end encryption isn't supported: an encrypted
+//! message gets a plain-text reply asking for an unencrypted one.
+
+use crate::activation::MENTIONS_BOT;
+use crate::config::XmppConfig;
+use crate::messaging::format::{Platform, format_message};
+use crate::messaging::traits::{InboundStream, Messaging};
+use crate::{Attachment, InboundMessage, MessageContent, OutboundResponse};
+
+use anyhow::Context as _;
+use futures::StreamExt as _;
+use tokio_xmpp::parsers::Element;
+use tokio_xmpp::parsers::jid::{BareJid, Jid};
+use tokio_xmpp::parsers::message::{Body, Message, MessageType};
+use tokio_xmpp::parsers::muc::Muc;
+use tokio_xmpp::parsers::muc::muc::History;
+use tokio_xmpp::parsers::presence::{Presence, Type as PresenceType};
+use tokio_xmpp::{Client, Event};
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use tokio::sync::{mpsc, watch};
+
+const NS_DELAY: &str = "urn:xmpp:delay";
+const NS_OOB: &str = "jabber:x:oob";
+const NS_OMEMO_LEGACY: &str = "eu.siacs.conversations.axolotl";
+const NS_OMEMO: &str = "urn:xmpp:omemo:2";
+
+const ENCRYPTED_REPLY: &str =
+    "I can't read end-to-end encrypted messages. Please turn off OMEMO for this chat and resend.";
+
+/// XMPP adapter state.
+pub struct XmppAdapter {
+    config: Arc<XmppConfig>,
+    outgoing_tx: mpsc::Sender<Element>,
+    /// Taken by the connection task on start.
+    outgoing_rx: std::sync::Mutex<Option<mpsc::Receiver<Element>>>,
+    connected: Arc<AtomicBool>,
+    shutdown_tx: watch::Sender<bool>,
+}
+
+impl XmppAdapter {
+    pub fn new(config: &XmppConfig) -> Self {
+        let (outgoing_tx, outgoing_rx) = mpsc::channel(256);
+        Self {
+            config: Arc::new(config.clone()),
+            outgoing_tx,
+            outgoing_rx: std::sync::Mutex::new(Some(outgoing_rx)),
+            connected: Arc::new(AtomicBool::new(false)),
+            shutdown_tx: watch::channel(false).0,
+        }
+    }
+
+    /// Queue `response` for `target`: a room when `groupchat` is set,
+    /// otherwise a user.
+    async fn send(
+        &self,
+        target: &str,
+        groupchat: bool,
+        response: OutboundResponse,
+    ) -> anyhow::Result<()> {
+        let texts = match response {
+            OutboundResponse::Text(text) | OutboundResponse::ThreadReply { text, .. } => {
+                format_message(&text, Platform::Xmpp)
+            }
+            OutboundResponse::File {
+                filename, caption, ..
+            } => {
+                let mut texts = caption
+                    .map(|caption| format_message(&caption, Platform::Xmpp))
+                    .unwrap_or_default();
+                texts.push(format!(
+                    "({filename} not sent, file transfer isn't supported)"
+                ));
+                texts
+            }
+            // Chat state notifications and corrections aren't used.
+            OutboundResponse::Reaction(_)
+            | OutboundResponse::Status(_)
+            | OutboundResponse::StreamStart
+            | OutboundResponse::StreamChunk(_)
+            | OutboundResponse::StreamEnd => return Ok(()),
+        };
+
+        let to = Jid::new(target).with_context(|| format!("'{target}' is not a JID"))?;
+        for text in texts {
+            let stanza = chat_message(to.clone(), groupchat, text);
+            self.outgoing_tx
+                .send(stanza)
+                .await
+                .context("xmpp connection task has stopped")?;
+        }
+        Ok(())
+    }
+}
+
+fn chat_message(to: Jid, groupchat: bool, text: String) -> Element {
+    let mut message = Message::new(Some(to));
+    message.type_ = if groupchat {
+        MessageType::Groupchat
+    } else {
+        MessageType::Chat
+    };
+    message.bodies.insert(String::new(), Body(text));
+    message.into()
+}
+
+impl Messaging for XmppAdapter {
+    fn name(&self) -> &str {
+        "xmpp"
+    }
+
+    async fn start(&self) -> crate::Result<InboundStream> {
+        let outgoing_rx = self
+            .outgoing_rx
+            .lock()
+            .expect("xmpp outgoing queue lock poisoned")
+            .take()
+            .context("xmpp adapter already started")?;
+        let jid = BareJid::new(&self.config.jid)
+            .with_context(|| format!("'{}' is not a valid JID", self.config.jid))?;
+        let mut client = Client::new(jid, self.config.password.clone());
+        client.set_reconnect(true);
+
+        let (inbound_tx, inbound_rx) = mpsc::channel(256);
+        let config = self.config.clone();
+        let connected = self.connected.clone();
+        let shutdown_rx = self.shutdown_tx.subscribe();
+        tokio::spawn(async move {
+            run(
+                client,
+                config,
+                inbound_tx,
+                outgoing_rx,
+                connected,
+                shutdown_rx,
+            )
+            .await;
+        });
+
+        let stream = tokio_stream::wrappers::ReceiverStream::new(inbound_rx);
+        Ok(Box::pin(stream))
+    }
+
+    async fn respond(
+        &self,
+        message: &InboundMessage,
+        response: OutboundResponse,
+    ) -> crate::Result<()> {
+        let (target, groupchat) = match message.metadata.get("xmpp_room") {
+            Some(room) => (room.as_str(), true),
+            None => (
+                message.metadata.get("xmpp_from").and_then(|v| v.as_str()),
+                false,
+            ),
+        };
+        let target = target.context("missing xmpp_from in metadata")?;
+        self.send(target, groupchat, response).await?;
+        Ok(())
+    }
+
+    async fn broadcast(&self, target: &str, response: OutboundResponse) -> crate::Result<()> {
+        let groupchat = self
+            .config
+            .rooms
+            .iter()
+            .any(|room| room.eq_ignore_ascii_case(target));
+        self.send(target, groupchat, response).await?;
+        Ok(())
+    }
+
+    async fn health_check(&self) -> crate::Result<()> {
+        if !self.connected.load(Ordering::Relaxed) {
+            return Err(anyhow::anyhow!("not connected as {}", self.config.jid).into());
+        }
+        Ok(())
+    }
+
+    fn restart_on_disconnect(&self) -> bool {
+        // The client reconnects by itself, and `start` can only run once.
+        false
+    }
+
+    async fn shutdown(&self) -> crate::Result<()> {
+        self.shutdown_tx.send_replace(true);
+        tracing::info!("xmpp adapter shut down");
+        Ok(())
+    }
+}
+
+/// Drive the client until shutdown. tokio-xmpp reconnects on its own, so
+/// each `Online` event means a fresh session that needs presence and room
+/// joins again.
+async fn run(
+    mut client: Client,
+    config: Arc<XmppConfig>,
+    inbound_tx: mpsc::Sender<InboundMessage>,
+    mut outgoing_rx: mpsc::Receiver<Element>,
+    connected: Arc<AtomicBool>,
+    mut shutdown_rx: watch::Receiver<bool>,
+) {
+    loop {
+        tokio::select! {
+            event = client.next() => {
+                let Some(event) = event else {
+                    break;
+                };
+                match event {
+                    Event::Online { .. } => {
+                        connected.store(true, Ordering::Relaxed);
+                        tracing::info!(jid = %config.jid, "xmpp connected");
+                        for stanza in session_start(&config) {
+                            if let Err(error) = client.send_stanza(stanza).await {
+                                tracing::warn!(%error, "failed to send xmpp presence");
+                            }
+                        }
+                    }
+                    Event::Disconnected(error) => {
+                        connected.store(false, Ordering::Relaxed);
+                        tracing::warn!(jid = %config.jid, %error, "xmpp disconnected");
+                    }
+                    Event::Stanza(stanza) => {
+                        let Ok(message) = Message::try_from(stanza) else {
+                            continue;
+                        };
+                        match classify(&config, &message) {
+                            Incoming::Message(inbound) => {
+                                if inbound_tx.send(*inbound).await.is_err() {
+                                    break;
+                                }
+                            }
+                            Incoming::Encrypted(from) => {
+                                let reply = chat_message(from, false, ENCRYPTED_REPLY.into());
+                                client.send_stanza(reply).await.ok();
+                            }
+                            Incoming::Ignored => {}
+                        }
+                    }
+                }
+            }
+            stanza = outgoing_rx.recv() => {
+                let Some(stanza) = stanza else {
+                    break;
+                };
+                if let Err(error) = client.send_stanza(stanza).await {
+                    tracing::warn!(%error, "failed to send xmpp message");
+                }
+            }
+            _ = shutdown_rx.changed() => break,
+        }
+    }
+
+    connected.store(false, Ordering::Relaxed);
+    client.send_end().await.ok();
+}
+
+/// Initial presence, then a join for each room. History is turned off so
+/// the bot doesn't answer old messages on every reconnect.
+fn session_start(config: &XmppConfig) -> Vec<Element> {
+    let mut stanzas = vec![Presence::new(PresenceType::None).into()];
+    for room in &config.rooms {
+        let Ok(occupant) = Jid::new(&format!("{room}/{}", config.nickname)) else {
+            tracing::warn!(%room, "skipping xmpp room with an invalid JID");
+            continue;
+        };
+        let mut presence = Presence::new(PresenceType::None);
+        presence.to = Some(occupant);
+        let muc = Muc::new().with_history(History::new().with_maxstanzas(0));
+        presence.payloads.push(muc.into());
+        stanzas.push(presence.into());
+    }
+    stanzas
+}
+
+/// What to do with a received message.
+enum Incoming {
+    Message(Box<InboundMessage>),
+    /// OMEMO-encrypted one-on-one message from this JID.
+    Encrypted(Jid),
+    Ignored,
+}
+
+/// Sort a received message: our own room echoes, replayed history, errors,
+/// empty messages and chats from JIDs not allowed to DM are ignored.
+fn classify(config: &XmppConfig, message: &Message) -> Incoming {
+    let Some(from) = &message.from else {
+        return Incoming::Ignored;
+    };
+    let from = from.to_string();
+    let (bare, resource) = match from.split_once('/') {
+        Some((bare, resource)) => (bare, Some(resource)),
+        None => (from.as_str(), None),
+    };
+    let has_payload = |name: &str, ns: &str| message.payloads.iter().any(|p| p.is(name, ns));
+
+    let room = match message.type_ {
+        MessageType::Groupchat => {
+            let Some(nick) = resource else {
+                // Room subject changes and server notices.
+                return Incoming::Ignored;
+            };
+            if nick == config.nickname || has_payload("delay", NS_DELAY) {
+                return Incoming::Ignored;
+            }
+            Some(bare)
+        }
+        MessageType::Chat | MessageType::Normal => {
+            if !dm_allowed(&config.dm_allowed_users, bare) {
+                return Incoming::Ignored;
+            }
+            if has_payload("encrypted", NS_OMEMO_LEGACY) || has_payload("encrypted", NS_OMEMO) {
+                return match Jid::new(bare) {
+                    Ok(jid) => Incoming::Encrypted(jid),
+                    Err(_) => Incoming::Ignored,
+                };
+            }
+            None
+        }
+        MessageType::Error | MessageType::Headline => return Incoming::Ignored,
+    };
+
+    let text = message
+        .bodies
+        .get("")
+        .or_else(|| message.bodies.values().next())
+        .map(|body| body.0.clone())
+        .unwrap_or_default();
+    let attachments: Vec<Attachment> = message
+        .payloads
+        .iter()
+        .filter(|payload| payload.is("x", NS_OOB))
+        .filter_map(|payload| payload.get_child("url", NS_OOB))
+        .map(|url| oob_attachment(url.text()))
+        .collect();
+    if text.trim().is_empty() && attachments.is_empty() {
+        return Incoming::Ignored;
+    }
+    // Clients put the link in the body too; drop it when it's all there is.
+    let text = if attachments.iter().any(|a| a.url == text.trim()) {
+        String::new()
+    } else {
+        text
+    };
+    let content = if attachments.is_empty() {
+        MessageContent::Text(text)
+    } else {
+        MessageContent::Media {
+            text: Some(text).filter(|text| !text.is_empty()),
+            attachments,
+        }
+    };
+
+    let sender = match room {
+        Some(_) => resource.unwrap_or(bare),
+        None => bare,
+    };
+    let mut metadata = HashMap::new();
+    metadata.insert("xmpp_from".into(), serde_json::Value::from(bare));
+    // Direct chats are addressed to us; room messages may not be.
+    metadata.insert(MENTIONS_BOT.into(), serde_json::Value::from(room.is_none()));
+    metadata.insert("display_name".into(), serde_json::Value::from(sender));
+    if let Some(room) = room {
+        metadata.insert("xmpp_room".into(), serde_json::Value::from(room));
+    }
+
+    Incoming::Message(Box::new(InboundMessage {
+        id: uuid::Uuid::new_v4().to_string(),
+        source: "xmpp".into(),
+        conversation_id: format!("xmpp:{}", room.unwrap_or(bare).to_lowercase()),
+        sender_id: match room {
+            Some(room) => format!("{room}/{sender}"),
+            None => bare.to_string(),
+        },
+        agent_id: None,
+        content,
+        timestamp: chrono::Utc::now(),
+        metadata,
+    }))
+}
+
+/// Whether `jid` may chat one-on-one: listed itself, or its domain is.
+fn dm_allowed(allowed: &[String], jid: &str) -> bool {
+    let domain = jid.rsplit_once('@').map_or(jid, |(_, domain)| domain);
+    allowed.iter().any(|entry| {
+        if entry.contains('@') {
+            entry.eq_ignore_ascii_case(jid)
+        } else {
+            entry.eq_ignore_ascii_case(domain)
+        }
+    })
+}
+
+/// An attachment for an out-of-band (XEP-0066) link, usually an HTTP
+/// upload.
+fn oob_attachment(url: String) -> Attachment {
+    let filename = url
+        .rsplit('/')
+        .next()
+        .filter(|name| !name.is_empty())
+        .unwrap_or("attachment")
+        .to_string();
+    let mime_type = match filename
+        .rsplit_once('.')
+        .map(|(_, ext)| ext.to_ascii_lowercase())
+    {
+        Some(ext) if ext == "png" => "image/png",
+        Some(ext) if ext == "jpg" || ext == "jpeg" => "image/jpeg",
+        Some(ext) if ext == "gif" => "image/gif",
+        Some(ext) if ext == "webp" => "image/webp",
+        Some(ext) if ext == "pdf" => "application/pdf",
+        Some(ext) if ext == "txt" => "text/plain",
+        _ => "application/octet-stream",
+    };
+    Attachment {
+        filename,
+        mime_type: mime_type.into(),
+        url,
+        size_bytes: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dm_allowed_by_jid_or_domain() {
+        let allowed = vec!["alice@example.com".to_string(), "corp.example".to_string()];
+
+        assert!(dm_allowed(&allowed, "alice@example.com"));
+        assert!(dm_allowed(&allowed, "bob@corp.example"));
+        assert!(!dm_allowed(&allowed, "mallory@example.com"));
+        assert!(!dm_allowed(&[], "alice@example.com"));
+    }
+
+    #[test]
+    fn test_oob_attachment_guesses_type() {
+        let attachment = oob_attachment("https://upload.example.com/abc/photo.JPG".into());
+
+        assert_eq!(attachment.filename, "photo.JPG");
+        assert_eq!(attachment.mime_type, "image/jpeg");
+    }
+}
@@ -0,0 +1,495 @@
+//! Prometheus and Loki queries.
+//!
+//! [`Client`] wraps the HTTP query APIs behind the `promql_query` and
+//! `logql_query` tools. Every query covers a [`TimeRange`] no longer than
+//! the configured limit, with a step picked so a series never has more than
+//! `max_points` points; series that come back longer anyway are downsampled
+//! by averaging neighbouring points.
+
+use crate::config::{HttpAuth, ObservabilityConfig, ObservabilitySource};
+use crate::error::Result;
+
+use anyhow::Context as _;
+use chrono::{DateTime, TimeDelta, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+/// Lookback when a query gives no range.
+const DEFAULT_RANGE: TimeDelta = TimeDelta::hours(1);
+
+/// Characters of a log line returned to the agent.
+const MAX_LINE_CHARS: usize = 1_000;
+
+/// The time span a query covers, and the resolution of range queries.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeRange {
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    pub step: TimeDelta,
+}
+
+impl TimeRange {
+    /// The range `range` back from `end` (now when not given), with a step
+    /// no finer than `step` and coarse enough to stay within `max_points`.
+    /// Ranges longer than the configured limit are refused.
+    pub fn new(
+        range: Option<&str>,
+        end: Option<&str>,
+        step: Option<&str>,
+        config: &ObservabilityConfig,
+        now: DateTime<Utc>,
+    ) -> std::result::Result<Self, String> {
+        let length = match range {
+            Some(text) => parse_duration(text)
+                .ok_or_else(|| format!("'{text}' is not a duration, expected e.g. \"30m\""))?,
+            None => DEFAULT_RANGE,
+        };
+        let max_length = TimeDelta::hours(config.max_range_hours as i64);
+        if length > max_length {
+            return Err(format!(
+                "queries may cover at most {}h; narrow the range",
+                config.max_range_hours
+            ));
+        }
+        let end = match end {
+            Some(text) => DateTime::parse_from_rfc3339(text)
+                .map(|end| end.with_timezone(&Utc))
+                .map_err(|_| format!("'{text}' is not an RFC 3339 time"))?,
+            None => now,
+        };
+        if end > now + TimeDelta::minutes(5) {
+            return Err("the range can't end in the future".into());
+        }
+
+        let points = config.max_points.max(1) as i64;
+        let coarsest = TimeDelta::seconds((length.num_seconds() + points - 1) / points);
+        let requested = match step {
+            Some(text) => parse_duration(text)
+                .ok_or_else(|| format!("'{text}' is not a duration, expected e.g. \"1m\""))?,
+            None => TimeDelta::zero(),
+        };
+        Ok(Self {
+            start: end - length,
+            end,
+            step: requested.max(coarsest).max(TimeDelta::seconds(1)),
+        })
+    }
+}
+
+/// A duration such as "90s", "15m", "1h30m", "2d" or "1w".
+pub fn parse_duration(text: &str) -> Option<TimeDelta> {
+    let text = text.trim();
+    if text.is_empty() {
+        return None;
+    }
+    let mut total = TimeDelta::zero();
+    let mut number = String::new();
+    for c in text.chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+        let amount: i64 = number.parse().ok()?;
+        number.clear();
+        total += match c {
+            's' => TimeDelta::try_seconds(amount)?,
+            'm' => TimeDelta::try_minutes(amount)?,
+            'h' => TimeDelta::try_hours(amount)?,
+            'd' => TimeDelta::try_days(amount)?,
+            'w' => TimeDelta::try_weeks(amount)?,
+            _ => return None,
+        };
+    }
+    // A trailing number without a unit isn't a duration.
+    (number.is_empty() && total > TimeDelta::zero()).then_some(total)
+}
+
+/// One labelled series of a metrics query.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Series {
+    pub labels: BTreeMap<String, String>,
+    /// `[unix seconds, value]` pairs, oldest first.
+    pub points: Vec<(i64, f64)>,
+    pub min: f64,
+    pub max: f64,
+    pub avg: f64,
+    pub last: f64,
+}
+
+impl Series {
+    fn new(labels: BTreeMap<String, String>, points: Vec<(i64, f64)>) -> Option<Self> {
+        let values = || points.iter().map(|(_, value)| *value);
+        let last = values().last()?;
+        Some(Self {
+            min: values().fold(f64::INFINITY, f64::min),
+            max: values().fold(f64::NEG_INFINITY, f64::max),
+            avg: values().sum::<f64>() / points.len() as f64,
+            last,
+            labels,
+            points,
+        })
+    }
+}
+
+/// `series` with the highest peaks first, cut to `max_series`, with every
+/// series cut to `max_points`. Also whether any series was left out.
+pub fn shape(mut series: Vec<Series>, max_series: usize, max_points: usize) -> (Vec<Series>, bool) {
+    series.sort_by(|a, b| b.max.total_cmp(&a.max));
+    let truncated = series.len() > max_series;
+    series.truncate(max_series);
+    for series in &mut series {
+        series.points = downsample(&series.points, max_points);
+    }
+    (series, truncated)
+}
+
+/// `points` averaged in runs of neighbours, down to at most `max` points.
+/// Each average is stamped with the time of its run's last point.
+pub fn downsample(points: &[(i64, f64)], max: usize) -> Vec<(i64, f64)> {
+    if points.len() <= max || max == 0 {
+        return points.to_vec();
+    }
+    let run = points.len().div_ceil(max);
+    points
+        .chunks(run)
+        .map(|chunk| {
+            let sum: f64 = chunk.iter().map(|(_, value)| value).sum();
+            let time = chunk.last().map_or(0, |(time, _)| *time);
+            (time, sum / chunk.len() as f64)
+        })
+        .collect()
+}
+
+/// One log line of a LogQL query.
+#[derive(Debug, Clone, Serialize)]
+pub struct LogLine {
+    pub time: String,
+    pub labels: BTreeMap<String, String>,
+    pub line: String,
+}
+
+/// What a LogQL query returned: log lines for log queries, series for
+/// metric queries such as `rate(...)`.
+#[derive(Debug, Default)]
+pub struct LokiResult {
+    pub lines: Vec<LogLine>,
+    pub series: Vec<Series>,
+}
+
+#[derive(Deserialize)]
+struct ApiResponse {
+    status: String,
+    #[serde(default)]
+    data: Option<QueryData>,
+    #[serde(default)]
+    error: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct QueryData {
+    result_type: String,
+    result: Value,
+}
+
+/// Client for one Prometheus-compatible or Loki endpoint.
+#[derive(Debug, Clone)]
+pub struct Client {
+    http: reqwest::Client,
+    url: String,
+    auth: Option<HttpAuth>,
+}
+
+impl Client {
+    /// Requests time out after `timeout`, and go through `proxy` when there
+    /// is one.
+    pub fn new(
+        source: &ObservabilitySource,
+        timeout: Duration,
+        proxy: Option<String>,
+    ) -> Result<Self> {
+        let mut builder = reqwest::Client::builder().timeout(timeout);
+        if let Some(proxy) = proxy {
+            builder = builder.proxy(reqwest::Proxy::all(proxy).context("invalid proxy url")?);
+        }
+        let http = builder
+            .build()
+            .context("failed to build observability HTTP client")?;
+        Ok(Self {
+            http,
+            url: source.url.clone(),
+            auth: source.auth.clone(),
+        })
+    }
+
+    /// A PromQL range query over `range`.
+    pub async fn prometheus_range(&self, query: &str, range: &TimeRange) -> Result<Vec<Series>> {
+        let data = self
+            .query(
+                "/api/v1/query_range",
+                &[
+                    ("query", query.to_string()),
+                    ("start", range.start.timestamp().to_string()),
+                    ("end", range.end.timestamp().to_string()),
+                    ("step", range.step.num_seconds().to_string()),
+                ],
+            )
+            .await?;
+        Ok(parse_series(&data))
+    }
+
+    /// A PromQL instant query at `time`.
+    pub async fn prometheus_instant(
+        &self,
+        query: &str,
+        time: DateTime<Utc>,
+    ) -> Result<Vec<Series>> {
+        let data = self
+            .query(
+                "/api/v1/query",
+                &[
+                    ("query", query.to_string()),
+                    ("time", time.timestamp().to_string()),
+                ],
+            )
+            .await?;
+        Ok(parse_series(&data))
+    }
+
+    /// A LogQL query over `range`, newest lines first, at most `limit` of
+    /// them.
+    pub async fn loki_range(
+        &self,
+        query: &str,
+        range: &TimeRange,
+        limit: usize,
+    ) -> Result<LokiResult> {
+        let nanos =
+            |time: DateTime<Utc>| time.timestamp_nanos_opt().unwrap_or_default().to_string();
+        let data = self
+            .query(
+                "/loki/api/v1/query_range",
+                &[
+                    ("query", query.to_string()),
+                    ("start", nanos(range.start)),
+                    ("end", nanos(range.end)),
+                    ("step", range.step.num_seconds().to_string()),
+                    ("limit", limit.to_string()),
+                    ("direction", "backward".to_string()),
+                ],
+            )
+            .await?;
+        if data.result_type == "streams" {
+            Ok(LokiResult {
+                lines: parse_streams(&data.result, limit),
+                series: Vec::new(),
+            })
+        } else {
+            Ok(LokiResult {
+                lines: Vec::new(),
+                series: parse_series(&data),
+            })
+        }
+    }
+
+    async fn query(&self, path: &str, params: &[(&str, String)]) -> Result<QueryData> {
+        let mut request = self.http.get(format!("{}{path}", self.url)).query(params);
+        request = match &self.auth {
+            Some(HttpAuth::Bearer { token }) => request.bearer_auth(token),
+            Some(HttpAuth::Basic { username, password }) => {
+                request.basic_auth(username, Some(password))
+            }
+            Some(HttpAuth::Header { name, value }) => request.header(name, value),
+            Some(HttpAuth::Query { name, value }) => request.query(&[(name, value)]),
+            None => request,
+        };
+        let response = request.send().await.context("query request failed")?;
+        let status = response.status();
+        let body = response
+            .text()
+            .await
+            .context("failed to read query response")?;
+        // Errors come back as JSON with a message worth passing on, e.g. a
+        // PromQL parse error.
+        let parsed: Option<ApiResponse> = serde_json::from_str(&body).ok();
+        match parsed {
+            Some(ApiResponse {
+                status,
+                data: Some(data),
+                ..
+            }) if status == "success" => Ok(data),
+            Some(ApiResponse {
+                error: Some(error), ..
+            }) => Err(anyhow::anyhow!("{error}").into()),
+            _ => {
+                let body: String = body.chars().take(500).collect();
+                Err(anyhow::anyhow!("query endpoint returned {status}: {body}").into())
+            }
+        }
+    }
+}
+
+/// Series from a `matrix`, `vector` or `scalar` result. Values that aren't
+/// finite numbers (NaN, +Inf) are dropped.
+fn parse_series(data: &QueryData) -> Vec<Series> {
+    let point = |pair: &Value| -> Option<(i64, f64)> {
+        let time = pair.get(0)?.as_f64()? as i64;
+        let value: f64 = pair.get(1)?.as_str()?.parse().ok()?;
+        value.is_finite().then_some((time, value))
+    };
+    match data.result_type.as_str() {
+        "matrix" | "vector" => data
+            .result
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|item| {
+                let points: Vec<(i64, f64)> = match item.get("values") {
+                    Some(values) => values
+                        .as_array()
+                        .into_iter()
+                        .flatten()
+                        .filter_map(point)
+                        .collect(),
+                    None => item.get("value").and_then(point).into_iter().collect(),
+                };
+                Series::new(labels(item.get("metric")), points)
+            })
+            .collect(),
+        "scalar" => point(&data.result)
+            .and_then(|point| Series::new(BTreeMap::new(), vec![point]))
+            .into_iter()
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Log lines from a `streams` result, newest first.
+fn parse_streams(result: &Value, limit: usize) -> Vec<LogLine> {
+    let mut lines: Vec<(i64, LogLine)> = Vec::new();
+    for stream in result.as_array().into_iter().flatten() {
+        let labels = labels(stream.get("stream"));
+        for entry in stream
+            .get("values")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+        {
+            let (Some(nanos), Some(line)) = (
+                entry
+                    .get(0)
+                    .and_then(Value::as_str)
+                    .and_then(|n| n.parse::<i64>().ok()),
+                entry.get(1).and_then(Value::as_str),
+            ) else {
+                continue;
+            };
+            let mut text: String = line.chars().take(MAX_LINE_CHARS).collect();
+            if line.chars().count() > MAX_LINE_CHARS {
+                text.push('…');
+            }
+            lines.push((
+                nanos,
+                LogLine {
+                    time: DateTime::from_timestamp_nanos(nanos).to_rfc3339(),
+                    labels: labels.clone(),
+                    line: text,
+                },
+            ));
+        }
+    }
+    lines.sort_by_key(|(nanos, _)| std::cmp::Reverse(*nanos));
+    lines.truncate(limit);
+    lines.into_iter().map(|(_, line)| line).collect()
+}
+
+fn labels(value: Option<&Value>) -> BTreeMap<String, String> {
+    value
+        .and_then(Value::as_object)
+        .into_iter()
+        .flatten()
+        .map(|(key, value)| {
+            let value = value
+                .as_str()
+                .map_or_else(|| value.to_string(), str::to_string);
+            (key.clone(), value)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn now() -> DateTime<Utc> {
+        "2026-03-02T12:00:00Z".parse().unwrap()
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("90s"), Some(TimeDelta::seconds(90)));
+        assert_eq!(parse_duration("1h30m"), Some(TimeDelta::minutes(90)));
+        assert_eq!(parse_duration("2d"), Some(TimeDelta::days(2)));
+        assert_eq!(parse_duration("15"), None);
+        assert_eq!(parse_duration("5x"), None);
+        assert_eq!(parse_duration("0m"), None);
+    }
+
+    #[test]
+    fn test_time_range_guards() {
+        let config = ObservabilityConfig::default();
+
+        let range = TimeRange::new(Some("6h"), None, None, &config, now()).unwrap();
+        assert_eq!(range.end, now());
+        assert_eq!(range.start, now() - TimeDelta::hours(6));
+        // 6h in at most 120 points.
+        assert_eq!(range.step, TimeDelta::minutes(3));
+
+        let fine = TimeRange::new(Some("1h"), None, Some("10m"), &config, now()).unwrap();
+        assert_eq!(fine.step, TimeDelta::minutes(10));
+
+        assert!(TimeRange::new(Some("3d"), None, None, &config, now()).is_err());
+        assert!(TimeRange::new(None, Some("2026-03-03T00:00:00Z"), None, &config, now()).is_err());
+    }
+
+    #[test]
+    fn test_shape_sorts_cuts_and_downsamples() {
+        let data = QueryData {
+            result_type: "matrix".into(),
+            result: json!([
+                {"metric": {"route": "/cart"}, "values": [[1, "0.1"], [2, "0.2"], [3, "NaN"]]},
+                {"metric": {"route": "/checkout"}, "values": [[1, "0.5"], [2, "1"], [3, "0.75"], [4, "0.25"]]},
+                {"metric": {"route": "/health"}, "values": [[1, "0.01"]]}
+            ]),
+        };
+        let (series, truncated) = shape(parse_series(&data), 2, 2);
+
+        assert!(truncated);
+        assert_eq!(series.len(), 2);
+        assert_eq!(series[0].labels["route"], "/checkout");
+        assert_eq!(series[0].points, vec![(2, 0.75), (4, 0.5)]);
+        assert_eq!(series[0].max, 1.0);
+        assert_eq!(series[1].labels["route"], "/cart");
+        assert_eq!(series[1].points, vec![(1, 0.1), (2, 0.2)]);
+    }
+
+    #[test]
+    fn test_parse_streams_newest_first() {
+        let result = json!([
+            {"stream": {"app": "api"}, "values": [
+                ["1772452800000000000", "GET /cart 200"],
+                ["1772452860000000000", "GET /checkout 500"]
+            ]},
+            {"stream": {"app": "worker"}, "values": [["1772452830000000000", "job failed"]]}
+        ]);
+        let lines = parse_streams(&result, 2);
+
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0].line, "GET /checkout 500");
+        assert_eq!(lines[1].labels["app"], "worker");
+        assert_eq!(lines[1].time, "2026-03-02T12:00:30+00:00");
+    }
+}
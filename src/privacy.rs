@@ -0,0 +1,448 @@
+//! Data subject deletion: forget everything an agent holds about one user.
+//!
+//! [`UserEraser`] removes, for one agent:
+//!
+//! - every message the user sent,
+//! - conversations only the user took part in (DMs), whole, including the
+//!   agent's replies, pinned facts, forks, and branch and worker runs,
+//! - the user's feedback, and all feedback in those conversations,
+//! - undelivered replies to the user's messages waiting in the outbox,
+//! - memories saved in those conversations, and memories that mention the
+//!   user's ID or any name they've used,
+//! - the user's messages in archived conversations, removing archives left
+//!   with no other user's messages.
+//!
+//! Memories carry no user ID, so matching them by mention is a best effort;
+//! a dry run lists what would go. `USER.md` is free-form text written by the
+//! operator, so it's only flagged for review when it mentions the user.
+
+use crate::error::Result;
+use crate::memory::EmbeddingTable;
+
+use anyhow::Context as _;
+use serde::Serialize;
+use sqlx::{Row as _, SqlitePool};
+
+use std::collections::{BTreeMap, HashSet};
+use std::io::{BufRead as _, Write as _};
+use std::path::{Path, PathBuf};
+
+/// Names shorter than this are too likely to match unrelated words to be
+/// used for finding memories about the user.
+const MIN_NAME_CHARS: usize = 3;
+
+/// The user to forget.
+#[derive(Debug, Clone)]
+pub struct DataSubject {
+    /// Platform user ID, as stored in `sender_id`.
+    pub user_id: String,
+    /// Only forget the user on this platform (e.g. "discord").
+    pub platform: Option<String>,
+}
+
+impl DataSubject {
+    fn in_scope(&self, channel_id: &str) -> bool {
+        self.platform.as_deref().is_none_or(|platform| {
+            channel_id
+                .strip_prefix(platform)
+                .is_some_and(|rest| rest.starts_with(':'))
+        })
+    }
+}
+
+/// What was (or, in a dry run, would be) deleted for one agent.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct DeletionReport {
+    pub agent_id: String,
+    pub dry_run: bool,
+    /// Messages the user sent in shared conversations.
+    pub messages: u64,
+    /// Conversations only the user took part in, deleted whole.
+    pub private_conversations: Vec<String>,
+    /// All messages in those conversations, the user's and the agent's.
+    pub private_messages: u64,
+    /// Facts pinned in those conversations.
+    pub private_pins: u64,
+    pub feedback: u64,
+    /// Undelivered replies to the user's messages in shared conversations.
+    /// Those in private conversations go with the conversation.
+    pub queued_replies: u64,
+    pub memories: Vec<String>,
+    /// Archive files the user's messages were removed from.
+    pub archives_rewritten: Vec<PathBuf>,
+    /// Archive files deleted because only the user's messages and replies
+    /// to them were left.
+    pub archives_deleted: Vec<PathBuf>,
+    pub archived_messages: u64,
+    /// Files that mention the user and have to be checked by hand.
+    pub needs_review: Vec<PathBuf>,
+}
+
+/// Deletes one user's data from one agent's stores.
+pub struct UserEraser {
+    pool: SqlitePool,
+    /// None in a dry run, which doesn't touch vector storage.
+    embeddings: Option<EmbeddingTable>,
+    archives_dir: PathBuf,
+    workspace: PathBuf,
+}
+
+impl UserEraser {
+    pub fn new(
+        pool: SqlitePool,
+        embeddings: Option<EmbeddingTable>,
+        archives_dir: PathBuf,
+        workspace: PathBuf,
+    ) -> Self {
+        Self {
+            pool,
+            embeddings,
+            archives_dir,
+            workspace,
+        }
+    }
+
+    /// Find everything held about `subject` and, unless `dry_run` is set,
+    /// delete it.
+    pub async fn forget(
+        &self,
+        agent_id: &str,
+        subject: &DataSubject,
+        dry_run: bool,
+    ) -> Result<DeletionReport> {
+        let mut report = DeletionReport {
+            agent_id: agent_id.to_string(),
+            dry_run,
+            ..Default::default()
+        };
+
+        let rows = sqlx::query(
+            "SELECT channel_id, sender_name, COUNT(*) AS message_count \
+             FROM conversation_messages \
+             WHERE sender_id = ? \
+             GROUP BY channel_id, sender_name",
+        )
+        .bind(&subject.user_id)
+        .fetch_all(&self.pool)
+        .await
+        .context("failed to find the user's messages")?;
+
+        let mut channels = BTreeMap::new();
+        let mut names = HashSet::new();
+        for row in rows {
+            let channel_id: String = row.try_get("channel_id").unwrap_or_default();
+            if !subject.in_scope(&channel_id) {
+                continue;
+            }
+            if let Ok(Some(name)) = row.try_get::<Option<String>, _>("sender_name") {
+                names.insert(name);
+            }
+            let count = row.try_get::<i64, _>("message_count").unwrap_or_default() as u64;
+            *channels.entry(channel_id).or_insert(0) += count;
+        }
+
+        for (channel_id, sent) in &channels {
+            let others: i64 = sqlx::query_scalar(
+                "SELECT COUNT(*) FROM conversation_messages \
+                 WHERE channel_id = ? AND role = 'user' AND (sender_id IS NULL OR sender_id != ?)",
+            )
+            .bind(channel_id)
+            .bind(&subject.user_id)
+            .fetch_one(&self.pool)
+            .await
+            .context("failed to check conversation participants")?;
+            if others == 0 {
+                report.private_conversations.push(channel_id.clone());
+            } else {
+                report.messages += sent;
+            }
+        }
+
+        for channel_id in &report.private_conversations {
+            let count: i64 = sqlx::query_scalar(
+                "SELECT COUNT(*) FROM conversation_messages WHERE channel_id = ?",
+            )
+            .bind(channel_id)
+            .fetch_one(&self.pool)
+            .await
+            .context("failed to count conversation messages")?;
+            report.private_messages += count as u64;
+
+            let pins: i64 =
+                sqlx::query_scalar("SELECT COUNT(*) FROM channel_pins WHERE channel_id = ?")
+                    .bind(channel_id)
+                    .fetch_one(&self.pool)
+                    .await
+                    .context("failed to count pinned facts")?;
+            report.private_pins += pins as u64;
+        }
+
+        let feedback_rows = sqlx::query("SELECT id, channel_id, user_id FROM feedback")
+            .fetch_all(&self.pool)
+            .await
+            .context("failed to load feedback")?;
+        let feedback_ids: Vec<String> = feedback_rows
+            .into_iter()
+            .filter(|row| {
+                let channel_id: String = row.try_get("channel_id").unwrap_or_default();
+                let user_id: String = row.try_get("user_id").unwrap_or_default();
+                (user_id == subject.user_id && subject.in_scope(&channel_id))
+                    || report.private_conversations.contains(&channel_id)
+            })
+            .filter_map(|row| row.try_get("id").ok())
+            .collect();
+        report.feedback = feedback_ids.len() as u64;
+
+        // Each queued reply keeps a copy of the message it answers.
+        let outbox_rows = sqlx::query(
+            "SELECT id, channel_id FROM outbox WHERE json_extract(message, '$.sender_id') = ?",
+        )
+        .bind(&subject.user_id)
+        .fetch_all(&self.pool)
+        .await
+        .context("failed to load queued replies")?;
+        let outbox_ids: Vec<String> = outbox_rows
+            .into_iter()
+            .filter(|row| {
+                let channel_id: String = row.try_get("channel_id").unwrap_or_default();
+                subject.in_scope(&channel_id) && !report.private_conversations.contains(&channel_id)
+            })
+            .filter_map(|row| row.try_get("id").ok())
+            .collect();
+        report.queued_replies = outbox_ids.len() as u64;
+
+        let mut terms: Vec<&str> = names.iter().map(String::as_str).collect();
+        terms.push(&subject.user_id);
+        let mention = mention_pattern(&terms);
+        let memory_rows = sqlx::query("SELECT id, content, channel_id FROM memories")
+            .fetch_all(&self.pool)
+            .await
+            .context("failed to load memories")?;
+        report.memories = memory_rows
+            .into_iter()
+            .filter(|row| {
+                let channel_id: Option<String> = row.try_get("channel_id").ok().flatten();
+                let content: String = row.try_get("content").unwrap_or_default();
+                channel_id.is_some_and(|id| report.private_conversations.contains(&id))
+                    || mention
+                        .as_ref()
+                        .is_some_and(|mention| mention.is_match(&content))
+            })
+            .filter_map(|row| row.try_get("id").ok())
+            .collect();
+
+        let user_md = self.workspace.join("USER.md");
+        let mentioned = mention.as_ref().is_some_and(|mention| {
+            std::fs::read_to_string(&user_md).is_ok_and(|content| mention.is_match(&content))
+        });
+        if mentioned {
+            report.needs_review.push(user_md);
+        }
+
+        self.scrub_archives(subject, dry_run, &mut report)?;
+
+        if dry_run {
+            return Ok(report);
+        }
+
+        let mut transaction = self
+            .pool
+            .begin()
+            .await
+            .context("failed to start deletion")?;
+        for channel_id in channels.keys() {
+            if report.private_conversations.contains(channel_id) {
+                crate::agent::retention::delete_transcript(&mut transaction, channel_id)
+                    .await
+                    .context("failed to delete conversation")?;
+                sqlx::query("DELETE FROM channels WHERE id = ?")
+                    .bind(channel_id)
+                    .execute(&mut *transaction)
+                    .await
+                    .context("failed to delete channel")?;
+            } else {
+                sqlx::query(
+                    "DELETE FROM conversation_messages WHERE channel_id = ? AND sender_id = ?",
+                )
+                .bind(channel_id)
+                .bind(&subject.user_id)
+                .execute(&mut *transaction)
+                .await
+                .context("failed to delete messages")?;
+            }
+        }
+        for id in &feedback_ids {
+            sqlx::query("DELETE FROM feedback WHERE id = ?")
+                .bind(id)
+                .execute(&mut *transaction)
+                .await
+                .context("failed to delete feedback")?;
+        }
+        for id in &outbox_ids {
+            sqlx::query("DELETE FROM outbox WHERE id = ?")
+                .bind(id)
+                .execute(&mut *transaction)
+                .await
+                .context("failed to delete queued reply")?;
+        }
+        for id in &report.memories {
+            sqlx::query("DELETE FROM associations WHERE source_id = ? OR target_id = ?")
+                .bind(id)
+                .bind(id)
+                .execute(&mut *transaction)
+                .await
+                .context("failed to delete memory associations")?;
+            sqlx::query("DELETE FROM memories WHERE id = ?")
+                .bind(id)
+                .execute(&mut *transaction)
+                .await
+                .context("failed to delete memory")?;
+        }
+        transaction
+            .commit()
+            .await
+            .context("failed to commit deletion")?;
+
+        if let Some(embeddings) = &self.embeddings {
+            for id in &report.memories {
+                embeddings.delete(id).await?;
+            }
+        }
+
+        Ok(report)
+    }
+
+    /// Remove the user's messages from archived conversations. Archives with
+    /// no other user's messages left are deleted.
+    fn scrub_archives(
+        &self,
+        subject: &DataSubject,
+        dry_run: bool,
+        report: &mut DeletionReport,
+    ) -> Result<()> {
+        let dir = self.archives_dir.join("conversations");
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            return Ok(());
+        };
+
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let Some(file_name) = path.file_name().and_then(|name| name.to_str()) else {
+                continue;
+            };
+            if !file_name.ends_with(".jsonl.gz") {
+                continue;
+            }
+            // Archive names start with the channel ID, `:` replaced by `_`.
+            let other_platform = subject
+                .platform
+                .as_ref()
+                .is_some_and(|platform| !file_name.starts_with(&format!("{platform}_")));
+            if other_platform {
+                continue;
+            }
+
+            let file = std::fs::File::open(&path)
+                .with_context(|| format!("failed to open {}", path.display()))?;
+            let reader = std::io::BufReader::new(flate2::read::GzDecoder::new(file));
+            let mut kept = Vec::new();
+            let mut removed = 0u64;
+            let mut others = false;
+            for line in reader.lines() {
+                let line = line.with_context(|| format!("failed to read {}", path.display()))?;
+                let message: serde_json::Value = serde_json::from_str(&line)
+                    .with_context(|| format!("malformed archive {}", path.display()))?;
+                if message["sender_id"].as_str() == Some(subject.user_id.as_str()) {
+                    removed += 1;
+                    continue;
+                }
+                others |= message["role"].as_str() == Some("user");
+                kept.push(line);
+            }
+            if removed == 0 {
+                continue;
+            }
+
+            report.archived_messages += removed;
+            if !others {
+                if !dry_run {
+                    std::fs::remove_file(&path)
+                        .with_context(|| format!("failed to delete {}", path.display()))?;
+                }
+                report.archives_deleted.push(path);
+                continue;
+            }
+            if !dry_run {
+                rewrite_archive(&path, &kept)?;
+            }
+            report.archives_rewritten.push(path);
+        }
+        Ok(())
+    }
+}
+
+/// Replace an archive's contents, through a temporary file so a failure
+/// can't leave it half written.
+fn rewrite_archive(path: &Path, lines: &[String]) -> Result<()> {
+    let temporary = path.with_extension("gz.tmp");
+    let file = std::fs::File::create(&temporary)
+        .with_context(|| format!("failed to create {}", temporary.display()))?;
+    let mut encoder = flate2::write::GzEncoder::new(file, flate2::Compression::default());
+    for line in lines {
+        writeln!(encoder, "{line}")
+            .with_context(|| format!("failed to write {}", temporary.display()))?;
+    }
+    encoder
+        .finish()
+        .and_then(|file| file.sync_all())
+        .with_context(|| format!("failed to write {}", temporary.display()))?;
+    std::fs::rename(&temporary, path)
+        .with_context(|| format!("failed to replace {}", path.display()))?;
+    Ok(())
+}
+
+/// A case-insensitive whole-word pattern matching any of `terms`. Terms
+/// shorter than [`MIN_NAME_CHARS`] are skipped; None if none are left.
+fn mention_pattern(terms: &[&str]) -> Option<regex::Regex> {
+    let alternatives: Vec<String> = terms
+        .iter()
+        .map(|term| term.trim())
+        .filter(|term| term.chars().count() >= MIN_NAME_CHARS)
+        .map(regex::escape)
+        .collect();
+    if alternatives.is_empty() {
+        return None;
+    }
+    regex::Regex::new(&format!(r"(?i)\b(?:{})\b", alternatives.join("|"))).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mention_pattern() {
+        let mention = mention_pattern(&["Ada Lovelace", "Al", "123456"]).unwrap();
+        assert!(mention.is_match("ada lovelace prefers tea"));
+        assert!(mention.is_match("User 123456 asked about billing"));
+        assert!(!mention.is_match("Al prefers coffee"));
+        assert!(!mention.is_match("order 1234567"));
+        assert!(mention_pattern(&["Al", " "]).is_none());
+    }
+
+    #[test]
+    fn test_platform_scope() {
+        let subject = DataSubject {
+            user_id: "42".into(),
+            platform: Some("discord".into()),
+        };
+        assert!(subject.in_scope("discord:dm:42"));
+        assert!(!subject.in_scope("discordx:1"));
+        assert!(!subject.in_scope("slack:T1:C1"));
+        let everywhere = DataSubject {
+            platform: None,
+            ..subject
+        };
+        assert!(everywhere.in_scope("slack:T1:C1"));
+    }
+}
@@ -0,0 +1,459 @@
+//! Prompt library: vetted, reusable prompt snippets with variable slots.
+//!
+//! A snippet is a markdown file in a `prompts/` directory, with optional
+//! frontmatter (name, description) and a body that may contain variable
+//! slots like `{{service}}`. Snippets are loaded from two sources (later wins
+//! on name conflicts):
+//! 1. Instance-level: `{instance_dir}/prompts/`
+//! 2. Agent workspace: `{workspace}/prompts/`
+//!
+//! In chat, `/prompt use triage` runs a snippet as if the user had sent it,
+//! asking for each slot that wasn't given inline as `name=value`. Persona
+//! preambles and cron job prompts can pull a snippet in with
+//! `{{prompt:triage}}`.
+
+use crate::{InboundMessage, MessageContent};
+
+use anyhow::Context as _;
+use regex::Regex;
+use std::collections::{HashMap, VecDeque};
+use std::path::{Path, PathBuf};
+use std::sync::LazyLock;
+
+const COMMAND: &str = "/prompt";
+
+/// A variable slot: `{{name}}`, spaces inside the braces allowed.
+static SLOT: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\{\{\s*([A-Za-z_][A-Za-z0-9_]*)\s*\}\}").unwrap());
+
+/// A snippet reference in a template: `{{prompt:name}}`.
+static REFERENCE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\{\{\s*prompt:([A-Za-z0-9_-]+)\s*\}\}").unwrap());
+
+/// A loaded prompt snippet.
+#[derive(Debug, Clone)]
+pub struct Snippet {
+    /// Name from frontmatter, or the file stem.
+    pub name: String,
+    /// Short description from frontmatter.
+    pub description: String,
+    /// Body with frontmatter stripped, slots left in place.
+    pub body: String,
+    /// Slot names in order of first appearance.
+    pub variables: Vec<String>,
+    /// Absolute path to the snippet file.
+    pub file_path: PathBuf,
+}
+
+impl Snippet {
+    fn new(name: String, description: String, body: String, file_path: PathBuf) -> Self {
+        let mut variables: Vec<String> = Vec::new();
+        for captures in SLOT.captures_iter(&body) {
+            let variable = &captures[1];
+            if !variables.iter().any(|v| v == variable) {
+                variables.push(variable.to_string());
+            }
+        }
+        Self {
+            name,
+            description,
+            body,
+            variables,
+            file_path,
+        }
+    }
+
+    /// The body with each slot that has a value filled in. Slots without a
+    /// value are left as they are.
+    pub fn fill(&self, values: &HashMap<String, String>) -> String {
+        SLOT.replace_all(&self.body, |captures: &regex::Captures| {
+            values
+                .get(&captures[1])
+                .cloned()
+                .unwrap_or_else(|| captures[0].to_string())
+        })
+        .into_owned()
+    }
+}
+
+/// All prompt snippets loaded for an agent.
+#[derive(Debug, Clone, Default)]
+pub struct PromptLibrary {
+    /// Snippets keyed by name (lowercase). Later sources override earlier ones.
+    snippets: HashMap<String, Snippet>,
+}
+
+impl PromptLibrary {
+    /// Load snippets from instance and workspace directories.
+    ///
+    /// Workspace snippets override instance snippets with the same name.
+    pub async fn load(instance_prompts_dir: &Path, workspace_prompts_dir: &Path) -> Self {
+        let mut library = Self::default();
+
+        for dir in [instance_prompts_dir, workspace_prompts_dir] {
+            if !dir.is_dir() {
+                continue;
+            }
+            match load_snippets_from_dir(dir).await {
+                Ok(snippets) => {
+                    for snippet in snippets {
+                        library
+                            .snippets
+                            .insert(snippet.name.to_lowercase(), snippet);
+                    }
+                }
+                Err(error) => {
+                    tracing::warn!(%error, path = %dir.display(), "failed to load prompt snippets");
+                }
+            }
+        }
+
+        if !library.snippets.is_empty() {
+            tracing::info!(
+                count = library.snippets.len(),
+                names = %library.snippets.keys().cloned().collect::<Vec<_>>().join(", "),
+                "prompt snippets loaded"
+            );
+        }
+
+        library
+    }
+
+    /// Get a snippet by name (case-insensitive).
+    pub fn get(&self, name: &str) -> Option<&Snippet> {
+        self.snippets.get(&name.to_lowercase())
+    }
+
+    /// All snippets, sorted by name.
+    pub fn sorted(&self) -> Vec<&Snippet> {
+        let mut snippets: Vec<&Snippet> = self.snippets.values().collect();
+        snippets.sort_by(|a, b| a.name.cmp(&b.name));
+        snippets
+    }
+
+    /// Whether any snippets are loaded.
+    pub fn is_empty(&self) -> bool {
+        self.snippets.is_empty()
+    }
+
+    /// Replace each `{{prompt:name}}` in `text` with that snippet's body.
+    /// References to unknown snippets are left as they are. Snippets are not
+    /// expanded inside other snippets.
+    pub fn expand(&self, text: &str) -> String {
+        if !text.contains("prompt:") {
+            return text.to_string();
+        }
+        REFERENCE
+            .replace_all(text, |captures: &regex::Captures| {
+                match self.get(&captures[1]) {
+                    Some(snippet) => snippet.body.trim().to_string(),
+                    None => {
+                        tracing::warn!(
+                            name = &captures[1],
+                            "template references an unknown prompt snippet"
+                        );
+                        captures[0].to_string()
+                    }
+                }
+            })
+            .into_owned()
+    }
+}
+
+/// Load all snippets from a directory: every `.md` file directly inside it.
+async fn load_snippets_from_dir(dir: &Path) -> anyhow::Result<Vec<Snippet>> {
+    let mut snippets = Vec::new();
+
+    let mut entries = tokio::fs::read_dir(dir)
+        .await
+        .with_context(|| format!("failed to read prompts directory: {}", dir.display()))?;
+
+    while let Some(entry) = entries.next_entry().await? {
+        let path = entry.path();
+        if path.extension().and_then(|e| e.to_str()) != Some("md") {
+            continue;
+        }
+        match load_snippet(&path).await {
+            Ok(snippet) => {
+                tracing::debug!(name = %snippet.name, path = %path.display(), "loaded prompt snippet");
+                snippets.push(snippet);
+            }
+            Err(error) => {
+                tracing::warn!(path = %path.display(), %error, "failed to load prompt snippet, skipping");
+            }
+        }
+    }
+
+    Ok(snippets)
+}
+
+async fn load_snippet(file_path: &Path) -> anyhow::Result<Snippet> {
+    let raw = tokio::fs::read_to_string(file_path)
+        .await
+        .with_context(|| format!("failed to read {}", file_path.display()))?;
+    let (frontmatter, body) = crate::skills::parse_frontmatter(&raw)?;
+
+    let name = frontmatter.get("name").cloned().unwrap_or_else(|| {
+        file_path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .unwrap_or("unknown")
+            .to_string()
+    });
+    let description = frontmatter.get("description").cloned().unwrap_or_default();
+
+    Ok(Snippet::new(
+        name,
+        description,
+        body.trim().to_string(),
+        file_path.to_path_buf(),
+    ))
+}
+
+/// A parsed `/prompt` command.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PromptCommand {
+    /// List the snippets.
+    List,
+    /// Show a snippet's text and slots.
+    Show(String),
+    /// Run a snippet, with the slot values given inline.
+    Use {
+        name: String,
+        values: Vec<(String, String)>,
+    },
+    /// Stop filling in a snippet's slots.
+    Cancel,
+    /// A `/prompt` command with arguments we don't understand.
+    Invalid,
+}
+
+impl PromptCommand {
+    /// Parse a `/prompt` command. Returns `None` for ordinary messages.
+    pub fn from_message(message: &InboundMessage) -> Option<Self> {
+        let MessageContent::Text(text) = &message.content else {
+            return None;
+        };
+        let rest = text.trim().strip_prefix(COMMAND)?;
+        if !rest.is_empty() && !rest.starts_with(char::is_whitespace) {
+            return None;
+        }
+        Some(Self::parse(rest))
+    }
+
+    /// Parse the arguments following the command name.
+    pub fn parse(args: &str) -> Self {
+        let args = args.trim();
+        let (verb, rest) = args.split_once(char::is_whitespace).unwrap_or((args, ""));
+        let rest = rest.trim();
+        match (verb.to_ascii_lowercase().as_str(), rest) {
+            ("" | "list", "") => Self::List,
+            ("cancel", "") => Self::Cancel,
+            ("show", name) if !name.is_empty() && !name.contains(char::is_whitespace) => {
+                Self::Show(name.to_ascii_lowercase())
+            }
+            ("use", rest) if !rest.is_empty() => {
+                let (name, assignments) =
+                    rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+                match parse_assignments(assignments) {
+                    Some(values) => Self::Use {
+                        name: name.to_ascii_lowercase(),
+                        values,
+                    },
+                    None => Self::Invalid,
+                }
+            }
+            _ => Self::Invalid,
+        }
+    }
+}
+
+/// Parse `name=value` pairs. Values with spaces go in double quotes:
+/// `service=checkout summary="slow since 9am"`.
+pub(crate) fn parse_assignments(text: &str) -> Option<Vec<(String, String)>> {
+    let mut values = Vec::new();
+    let mut rest = text.trim_start();
+    while !rest.is_empty() {
+        let (name, after) = rest.split_once('=')?;
+        if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            return None;
+        }
+        let (value, after) = match after.strip_prefix('"') {
+            Some(quoted) => {
+                let end = quoted.find('"')?;
+                (&quoted[..end], &quoted[end + 1..])
+            }
+            None => after.split_once(char::is_whitespace).unwrap_or((after, "")),
+        };
+        values.push((name.to_string(), value.to_string()));
+        rest = after.trim_start();
+    }
+    Some(values)
+}
+
+/// A snippet waiting for its remaining slots to be filled in, one message
+/// from the user who ran it per slot.
+#[derive(Debug, Clone)]
+pub struct PendingPrompt {
+    pub snippet: Snippet,
+    /// Sender who ran `/prompt use`; only their messages fill slots.
+    pub sender_id: String,
+    pub values: HashMap<String, String>,
+    /// Slots still to ask for, in order.
+    pub remaining: VecDeque<String>,
+}
+
+impl PendingPrompt {
+    /// Start filling in `snippet` with the values given inline. Values for
+    /// names that aren't slots are ignored.
+    pub fn new(snippet: Snippet, sender_id: String, values: Vec<(String, String)>) -> Self {
+        let values: HashMap<String, String> = values
+            .into_iter()
+            .filter(|(name, _)| snippet.variables.contains(name))
+            .collect();
+        let remaining = snippet
+            .variables
+            .iter()
+            .filter(|variable| !values.contains_key(*variable))
+            .cloned()
+            .collect();
+        Self {
+            snippet,
+            sender_id,
+            values,
+            remaining,
+        }
+    }
+
+    /// The question for the next slot, or None when every slot is filled.
+    pub fn question(&self) -> Option<String> {
+        let variable = self.remaining.front()?;
+        Some(format!(
+            "`{}` needs `{variable}` ({} of {}). Reply with a value, or `{COMMAND} cancel`.",
+            self.snippet.name,
+            self.snippet.variables.len() - self.remaining.len() + 1,
+            self.snippet.variables.len()
+        ))
+    }
+
+    /// Fill the next slot with `answer`.
+    pub fn answer(&mut self, answer: &str) {
+        if let Some(variable) = self.remaining.pop_front() {
+            self.values.insert(variable, answer.trim().to_string());
+        }
+    }
+
+    /// The filled-in prompt, once every slot has a value.
+    pub fn finished(&self) -> Option<String> {
+        self.remaining
+            .is_empty()
+            .then(|| self.snippet.fill(&self.values))
+    }
+}
+
+/// `/prompt` text: the snippets available.
+pub fn describe(library: &PromptLibrary) -> String {
+    if library.is_empty() {
+        return "No prompts are in the library. Add markdown files to the agent's `prompts/` directory."
+            .to_string();
+    }
+    let lines: Vec<String> = library
+        .sorted()
+        .into_iter()
+        .map(|snippet| {
+            let mut line = format!("`{}`", snippet.name);
+            if !snippet.variables.is_empty() {
+                line.push_str(&format!(" ({})", snippet.variables.join(", ")));
+            }
+            if !snippet.description.is_empty() {
+                line.push_str(&format!(" — {}", snippet.description));
+            }
+            line
+        })
+        .collect();
+    format!(
+        "Run one with `{COMMAND} use <name>`, or see it with `{COMMAND} show <name>`:\n{}",
+        lines.join("\n")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snippet(body: &str) -> Snippet {
+        Snippet::new(
+            "triage".into(),
+            "Triage an incident".into(),
+            body.into(),
+            PathBuf::from("/prompts/triage.md"),
+        )
+    }
+
+    #[test]
+    fn test_parse_prompt_commands() {
+        assert_eq!(PromptCommand::parse(""), PromptCommand::List);
+        assert_eq!(PromptCommand::parse(" cancel "), PromptCommand::Cancel);
+        assert_eq!(
+            PromptCommand::parse("show Triage"),
+            PromptCommand::Show("triage".into())
+        );
+        assert_eq!(
+            PromptCommand::parse(r#"use triage service=checkout summary="slow since 9am""#),
+            PromptCommand::Use {
+                name: "triage".into(),
+                values: vec![
+                    ("service".into(), "checkout".into()),
+                    ("summary".into(), "slow since 9am".into()),
+                ],
+            }
+        );
+        assert_eq!(PromptCommand::parse("use"), PromptCommand::Invalid);
+        assert_eq!(
+            PromptCommand::parse(r#"use triage summary="unclosed"#),
+            PromptCommand::Invalid
+        );
+        assert_eq!(
+            PromptCommand::parse("delete triage"),
+            PromptCommand::Invalid
+        );
+    }
+
+    #[test]
+    fn test_pending_prompt_asks_for_missing_slots() {
+        let snippet = snippet("Triage {{service}}: {{ summary }}. Check {{service}} dashboards.");
+        assert_eq!(snippet.variables, vec!["service", "summary"]);
+
+        let mut pending = PendingPrompt::new(
+            snippet,
+            "42".into(),
+            vec![
+                ("service".into(), "checkout".into()),
+                ("unused".into(), "x".into()),
+            ],
+        );
+        assert!(pending.question().unwrap().contains("`summary` (2 of 2)"));
+        assert_eq!(pending.finished(), None);
+
+        pending.answer(" p99 latency doubled ");
+        assert_eq!(pending.question(), None);
+        assert_eq!(
+            pending.finished().unwrap(),
+            "Triage checkout: p99 latency doubled. Check checkout dashboards."
+        );
+    }
+
+    #[test]
+    fn test_expand_references() {
+        let mut library = PromptLibrary::default();
+        library
+            .snippets
+            .insert("triage".into(), snippet("Ask for the {{service}} first."));
+
+        assert_eq!(
+            library.expand("You are on call. {{ prompt:Triage }}\n{{prompt:missing}}"),
+            "You are on call. Ask for the {{service}} first.\n{{prompt:missing}}"
+        );
+        assert_eq!(library.expand("No references."), "No references.");
+    }
+}
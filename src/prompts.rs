@@ -1,5 +1,6 @@
 pub mod engine;
 pub mod text;
+pub mod version;
 
-pub use engine::{PromptEngine, SkillInfo};
+pub use engine::{PromptEngine, RetrievedChunk, SkillInfo};
 pub use text::{get as get_text, init as init_language};
@@ -78,6 +78,10 @@ impl PromptEngine {
             "fragments/system/retrigger",
             crate::prompts::text::get("fragments/system/retrigger"),
         )?;
+        env.add_template(
+            "fragments/system/handoff",
+            crate::prompts::text::get("fragments/system/handoff"),
+        )?;
         env.add_template(
             "fragments/system/truncation",
             crate::prompts::text::get("fragments/system/truncation"),
@@ -110,10 +114,42 @@ impl PromptEngine {
             "fragments/system/history_backfill",
             crate::prompts::text::get("fragments/system/history_backfill"),
         )?;
+        env.add_template(
+            "fragments/system/thread_parent",
+            crate::prompts::text::get("fragments/system/thread_parent"),
+        )?;
+        env.add_template(
+            "fragments/system/linked_conversation",
+            crate::prompts::text::get("fragments/system/linked_conversation"),
+        )?;
+        env.add_template(
+            "fragments/system/persona_switch",
+            crate::prompts::text::get("fragments/system/persona_switch"),
+        )?;
+        env.add_template(
+            "fragments/system/self_report",
+            crate::prompts::text::get("fragments/system/self_report"),
+        )?;
         env.add_template(
             "fragments/coalesce_hint",
             crate::prompts::text::get("fragments/coalesce_hint"),
         )?;
+        env.add_template(
+            "fragments/reply_language",
+            crate::prompts::text::get("fragments/reply_language"),
+        )?;
+        env.add_template(
+            "fragments/latency_budget",
+            crate::prompts::text::get("fragments/latency_budget"),
+        )?;
+        env.add_template(
+            "fragments/persona",
+            crate::prompts::text::get("fragments/persona"),
+        )?;
+        env.add_template(
+            "fragments/retrieved_context",
+            crate::prompts::text::get("fragments/retrieved_context"),
+        )?;
 
         Ok(Self {
             env: Arc::new(env),
@@ -159,9 +195,14 @@ impl PromptEngine {
     }
 
     /// Convenience method for rendering worker capabilities fragment.
+    #[allow(clippy::too_many_arguments)]
     pub fn render_worker_capabilities(
         &self,
         browser_enabled: bool,
+        computer_use_enabled: bool,
+        ocr_enabled: bool,
+        home_assistant_enabled: bool,
+        issues_enabled: bool,
         web_search_enabled: bool,
         opencode_enabled: bool,
     ) -> Result<String> {
@@ -169,6 +210,10 @@ impl PromptEngine {
             "fragments/worker_capabilities",
             context! {
                 browser_enabled => browser_enabled,
+                computer_use_enabled => computer_use_enabled,
+                ocr_enabled => ocr_enabled,
+                home_assistant_enabled => home_assistant_enabled,
+                issues_enabled => issues_enabled,
                 web_search_enabled => web_search_enabled,
                 opencode_enabled => opencode_enabled,
             },
@@ -240,6 +285,23 @@ impl PromptEngine {
         self.render_static("fragments/system/retrigger")
     }
 
+    /// Render the first message of a channel that was handed a conversation.
+    pub fn render_system_handoff(
+        &self,
+        from_agent: &str,
+        reason: &str,
+        summary: &str,
+    ) -> Result<String> {
+        self.render(
+            "fragments/system/handoff",
+            context! {
+                from_agent => from_agent,
+                reason => reason,
+                summary => summary,
+            },
+        )
+    }
+
     /// Convenience method for rendering truncation marker.
     pub fn render_system_truncation(&self, remove_count: usize) -> Result<String> {
         self.render(
@@ -266,6 +328,16 @@ impl PromptEngine {
         )
     }
 
+    /// Render the request for a worker's end-of-run report.
+    pub fn render_system_self_report(&self, schema: &str) -> Result<String> {
+        self.render(
+            "fragments/system/self_report",
+            context! {
+                schema => schema,
+            },
+        )
+    }
+
     /// Convenience method for rendering memory persistence prompt.
     pub fn render_system_memory_persistence(&self) -> Result<String> {
         self.render_static("fragments/system/memory_persistence")
@@ -330,6 +402,41 @@ impl PromptEngine {
         )
     }
 
+    /// Render what a new thread's channel is told about the conversation it
+    /// branched from. `summarized` says whether `context` is a summary or a
+    /// transcript.
+    pub fn render_system_thread_parent(
+        &self,
+        parent: &str,
+        context: &str,
+        summarized: bool,
+    ) -> Result<String> {
+        self.render(
+            "fragments/system/thread_parent",
+            context! {
+                parent => parent,
+                context => context,
+                summarized => summarized,
+            },
+        )
+    }
+
+    /// Render what a channel is told about new messages in a conversation
+    /// linked with it.
+    pub fn render_system_linked_conversation(
+        &self,
+        name: &str,
+        transcript: &str,
+    ) -> Result<String> {
+        self.render(
+            "fragments/system/linked_conversation",
+            context! {
+                name => name,
+                transcript => transcript,
+            },
+        )
+    }
+
     /// Render the coalesce hint fragment for batched messages.
     pub fn render_coalesce_hint(
         &self,
@@ -347,27 +454,94 @@ impl PromptEngine {
         )
     }
 
+    /// Render the marker left in history when a conversation switches to
+    /// the persona `name`, or back to the agent's own with None.
+    pub fn render_system_persona_switch(&self, name: Option<&str>) -> Result<String> {
+        self.render(
+            "fragments/system/persona_switch",
+            context! {
+                name => name,
+            },
+        )
+    }
+
+    /// Render the channel prompt section for the persona `name`.
+    pub fn render_persona(&self, name: &str, preamble: &str) -> Result<String> {
+        self.render(
+            "fragments/persona",
+            context! {
+                name => name,
+                preamble => preamble,
+            },
+        )
+    }
+
+    /// Render the instruction to reply in `language`.
+    pub fn render_reply_language(&self, language: &str) -> Result<String> {
+        self.render(
+            "fragments/reply_language",
+            context! {
+                language => language,
+            },
+        )
+    }
+
+    /// Render the turn's latency budget, appended to the channel prompt.
+    pub fn render_latency_budget(&self, target_secs: u64) -> Result<String> {
+        self.render(
+            "fragments/latency_budget",
+            context! {
+                target_secs => target_secs,
+            },
+        )
+    }
+
+    /// Render the passages retrieved for a turn, appended to the channel
+    /// prompt.
+    pub fn render_retrieved_context(
+        &self,
+        store: &str,
+        chunks: Vec<RetrievedChunk>,
+    ) -> Result<String> {
+        self.render(
+            "fragments/retrieved_context",
+            context! {
+                store => store,
+                chunks => chunks,
+            },
+        )
+    }
+
     /// Render the complete channel system prompt with all dynamic components.
+    #[allow(clippy::too_many_arguments)]
     pub fn render_channel_prompt(
         &self,
         identity_context: Option<String>,
+        persona: Option<String>,
         memory_bulletin: Option<String>,
         skills_prompt: Option<String>,
         worker_capabilities: String,
         conversation_context: Option<String>,
         status_text: Option<String>,
         coalesce_hint: Option<String>,
+        reply_language: Option<String>,
+        flags: Vec<String>,
+        experiments: Vec<String>,
     ) -> Result<String> {
         self.render(
             "channel",
             context! {
                 identity_context => identity_context,
+                persona => persona,
                 memory_bulletin => memory_bulletin,
                 skills_prompt => skills_prompt,
                 worker_capabilities => worker_capabilities,
                 conversation_context => conversation_context,
                 status_text => status_text,
                 coalesce_hint => coalesce_hint,
+                reply_language => reply_language,
+                flags => flags,
+                experiments => experiments,
             },
         )
     }
@@ -405,5 +579,14 @@ pub struct SkillInfo {
     pub location: String,
 }
 
+/// A passage retrieved for a turn, for template rendering.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct RetrievedChunk {
+    pub memory_id: String,
+    pub content: String,
+    /// Similarity to the query, rounded to two decimals.
+    pub score: f32,
+}
+
 // All templates are now loaded from the centralized text registry (src/prompts/text.rs)
 // to support multiple languages at compile time.
@@ -64,6 +64,16 @@ fn lookup(lang: &str, key: &str) -> &'static str {
         ("en", "memory_persistence") => include_str!("../../prompts/en/memory_persistence.md.j2"),
         ("en", "ingestion") => include_str!("../../prompts/en/ingestion.md.j2"),
         ("en", "cortex_chat") => include_str!("../../prompts/en/cortex_chat.md.j2"),
+        ("en", "ocr") => include_str!("../../prompts/en/ocr.md.j2"),
+        ("en", "feed_summary") => include_str!("../../prompts/en/feed_summary.md.j2"),
+        ("en", "digest") => include_str!("../../prompts/en/digest.md.j2"),
+        ("en", "digest_chunk") => include_str!("../../prompts/en/digest_chunk.md.j2"),
+        ("en", "intake") => include_str!("../../prompts/en/intake.md.j2"),
+        ("en", "topics") => include_str!("../../prompts/en/topics.md.j2"),
+        ("en", "tool_guard") => include_str!("../../prompts/en/tool_guard.md.j2"),
+        ("en", "fact_check_claims") => include_str!("../../prompts/en/fact_check_claims.md.j2"),
+        ("en", "fact_check") => include_str!("../../prompts/en/fact_check.md.j2"),
+        ("en", "fact_check_soften") => include_str!("../../prompts/en/fact_check_soften.md.j2"),
 
         // Fragment Templates
         ("en", "fragments/worker_capabilities") => {
@@ -83,6 +93,9 @@ fn lookup(lang: &str, key: &str) -> &'static str {
         ("en", "fragments/system/retrigger") => {
             include_str!("../../prompts/en/fragments/system/retrigger.md.j2")
         }
+        ("en", "fragments/system/handoff") => {
+            include_str!("../../prompts/en/fragments/system/handoff.md.j2")
+        }
         ("en", "fragments/system/truncation") => {
             include_str!("../../prompts/en/fragments/system/truncation.md.j2")
         }
@@ -107,11 +120,35 @@ fn lookup(lang: &str, key: &str) -> &'static str {
         ("en", "fragments/system/history_backfill") => {
             include_str!("../../prompts/en/fragments/system/history_backfill.md.j2")
         }
+        ("en", "fragments/system/thread_parent") => {
+            include_str!("../../prompts/en/fragments/system/thread_parent.md.j2")
+        }
+        ("en", "fragments/system/linked_conversation") => {
+            include_str!("../../prompts/en/fragments/system/linked_conversation.md.j2")
+        }
+        ("en", "fragments/system/persona_switch") => {
+            include_str!("../../prompts/en/fragments/system/persona_switch.md.j2")
+        }
+        ("en", "fragments/system/self_report") => {
+            include_str!("../../prompts/en/fragments/system/self_report.md.j2")
+        }
+
+        // Retrieved Context
+        ("en", "fragments/retrieved_context") => {
+            include_str!("../../prompts/en/fragments/retrieved_context.md.j2")
+        }
 
         // Coalesce Hint
         ("en", "fragments/coalesce_hint") => {
             include_str!("../../prompts/en/fragments/coalesce_hint.md.j2")
         }
+        ("en", "fragments/reply_language") => {
+            include_str!("../../prompts/en/fragments/reply_language.md.j2")
+        }
+        ("en", "fragments/latency_budget") => {
+            include_str!("../../prompts/en/fragments/latency_budget.md.j2")
+        }
+        ("en", "fragments/persona") => include_str!("../../prompts/en/fragments/persona.md.j2"),
 
         // Tool Descriptions
         ("en", "tools/reply") => include_str!("../../prompts/en/tools/reply_description.md.j2"),
@@ -119,17 +156,67 @@ fn lookup(lang: &str, key: &str) -> &'static str {
         ("en", "tools/spawn_worker") => {
             include_str!("../../prompts/en/tools/spawn_worker_description.md.j2")
         }
+        ("en", "tools/start_task") => {
+            include_str!("../../prompts/en/tools/start_task_description.md.j2")
+        }
         ("en", "tools/route") => include_str!("../../prompts/en/tools/route_description.md.j2"),
         ("en", "tools/cancel") => include_str!("../../prompts/en/tools/cancel_description.md.j2"),
         ("en", "tools/skip") => include_str!("../../prompts/en/tools/skip_description.md.j2"),
         ("en", "tools/react") => include_str!("../../prompts/en/tools/react_description.md.j2"),
+        ("en", "tools/pin") => include_str!("../../prompts/en/tools/pin_description.md.j2"),
+        ("en", "tools/scratchpad_set") => {
+            include_str!("../../prompts/en/tools/scratchpad_set_description.md.j2")
+        }
+        ("en", "tools/scratchpad_get") => {
+            include_str!("../../prompts/en/tools/scratchpad_get_description.md.j2")
+        }
+        ("en", "tools/scratchpad_list") => {
+            include_str!("../../prompts/en/tools/scratchpad_list_description.md.j2")
+        }
+        ("en", "tools/kubernetes") => {
+            include_str!("../../prompts/en/tools/kubernetes_description.md.j2")
+        }
+        ("en", "tools/promql_query") => {
+            include_str!("../../prompts/en/tools/promql_query_description.md.j2")
+        }
+        ("en", "tools/logql_query") => {
+            include_str!("../../prompts/en/tools/logql_query_description.md.j2")
+        }
+        ("en", "tools/incident") => {
+            include_str!("../../prompts/en/tools/incident_description.md.j2")
+        }
+        ("en", "tools/sql_query") => {
+            include_str!("../../prompts/en/tools/sql_query_description.md.j2")
+        }
+        ("en", "tools/http_request") => {
+            include_str!("../../prompts/en/tools/http_request_description.md.j2")
+        }
+        ("en", "tools/calc") => include_str!("../../prompts/en/tools/calc_description.md.j2"),
+        ("en", "tools/escalate") => {
+            include_str!("../../prompts/en/tools/escalate_description.md.j2")
+        }
+        ("en", "tools/handoff") => {
+            include_str!("../../prompts/en/tools/handoff_description.md.j2")
+        }
         ("en", "tools/set_status") => {
             include_str!("../../prompts/en/tools/set_status_description.md.j2")
         }
+        ("en", "tools/share") => include_str!("../../prompts/en/tools/share_description.md.j2"),
         ("en", "tools/shell") => include_str!("../../prompts/en/tools/shell_description.md.j2"),
         ("en", "tools/file") => include_str!("../../prompts/en/tools/file_description.md.j2"),
         ("en", "tools/exec") => include_str!("../../prompts/en/tools/exec_description.md.j2"),
         ("en", "tools/browser") => include_str!("../../prompts/en/tools/browser_description.md.j2"),
+        ("en", "tools/computer") => {
+            include_str!("../../prompts/en/tools/computer_description.md.j2")
+        }
+        ("en", "tools/ocr") => include_str!("../../prompts/en/tools/ocr_description.md.j2"),
+        ("en", "tools/generate_image") => {
+            include_str!("../../prompts/en/tools/generate_image_description.md.j2")
+        }
+        ("en", "tools/home_assistant") => {
+            include_str!("../../prompts/en/tools/home_assistant_description.md.j2")
+        }
+        ("en", "tools/issues") => include_str!("../../prompts/en/tools/issues_description.md.j2"),
         ("en", "tools/web_search") => {
             include_str!("../../prompts/en/tools/web_search_description.md.j2")
         }
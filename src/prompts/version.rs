@@ -0,0 +1,176 @@
+//! Prompt versions: content hashes of what a channel's system prompt is
+//! built from.
+//!
+//! Each channel turn is labeled with the version of its prompt, a hash of
+//! the channel templates, the agent's identity files and the preambles of
+//! the active persona and experiment flags. The parts that change every turn (memory bulletin,
+//! status, retrieved context) are left out, so a version changes only when
+//! someone edits the prompt. [`version_report`] compares versions by cost,
+//! how often turns finished, and how users rated the replies, so a prompt
+//! change can be judged like a deploy.
+
+use crate::agent::turn::TurnCompletion;
+use crate::error::Result;
+
+use anyhow::Context as _;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use sqlx::{Row as _, SqlitePool};
+
+use std::collections::HashMap;
+
+/// Hex characters kept of a version's hash.
+const VERSION_LEN: usize = 12;
+
+/// Templates the channel prompt is rendered from.
+const CHANNEL_TEMPLATES: &[&str] = &[
+    "channel",
+    "fragments/persona",
+    "fragments/worker_capabilities",
+    "fragments/skills_channel",
+    "fragments/conversation_context",
+    "fragments/coalesce_hint",
+    "fragments/reply_language",
+    "fragments/latency_budget",
+];
+
+/// Version of a prompt made of `parts`, in order.
+pub fn version<'a>(parts: impl IntoIterator<Item = &'a str>) -> String {
+    let mut hasher = Sha256::new();
+    for part in parts {
+        hasher.update((part.len() as u64).to_le_bytes());
+        hasher.update(part.as_bytes());
+    }
+    let digest = format!("{:x}", hasher.finalize());
+    digest[..VERSION_LEN].to_string()
+}
+
+/// Version of the channel prompt for an agent with `identity_context`, with
+/// the `preambles` of the active persona and experiment flags.
+pub fn channel_version<'a>(
+    identity_context: &'a str,
+    preambles: impl IntoIterator<Item = &'a str>,
+) -> String {
+    let templates = CHANNEL_TEMPLATES
+        .iter()
+        .map(|name| crate::prompts::text::get(name));
+    version(
+        templates
+            .chain(std::iter::once(identity_context))
+            .chain(preambles),
+    )
+}
+
+/// Turns, cost and ratings for one prompt version.
+#[derive(Debug, Clone, Serialize)]
+pub struct PromptVersionStats {
+    /// None for turns recorded before prompts were versioned.
+    pub prompt_version: Option<String>,
+    pub first_seen: String,
+    pub last_seen: String,
+    #[serde(flatten)]
+    pub completion: TurnCompletion,
+    /// Distinct conversations the turns were in.
+    pub conversations: u64,
+    pub avg_input_tokens: Option<f64>,
+    pub avg_output_tokens: Option<f64>,
+    pub avg_cost_usd: Option<f64>,
+    pub cost_usd: Option<f64>,
+    /// Ratings of replies from this version's turns.
+    pub up: i64,
+    pub down: i64,
+    /// Share of ratings that were positive. None without ratings.
+    pub approval_rate: Option<f64>,
+}
+
+/// An agent's turns over the last `days` days grouped by prompt version,
+/// newest version first.
+pub async fn version_report(pool: &SqlitePool, days: u32) -> Result<Vec<PromptVersionStats>> {
+    let window = format!("-{days} days");
+    let rows = sqlx::query(
+        "SELECT json_extract(outcome, '$.prompt_version') AS prompt_version, \
+         MIN(completed_at) AS first_seen, MAX(completed_at) AS last_seen, \
+         COUNT(*) AS turns, COUNT(DISTINCT channel_id) AS conversations, \
+         SUM(CASE WHEN json_extract(outcome, '$.stop_reason.reason') \
+             IN ('failed', 'panicked', 'cancelled', 'max_turns') THEN 1 ELSE 0 END) AS unfinished, \
+         AVG(json_extract(outcome, '$.usage.input_tokens')) AS avg_input_tokens, \
+         AVG(json_extract(outcome, '$.usage.output_tokens')) AS avg_output_tokens, \
+         AVG(json_extract(outcome, '$.cost_usd')) AS avg_cost_usd, \
+         SUM(json_extract(outcome, '$.cost_usd')) AS cost_usd \
+         FROM turn_runs WHERE completed_at >= datetime('now', ?) \
+         GROUP BY prompt_version ORDER BY last_seen DESC",
+    )
+    .bind(&window)
+    .fetch_all(pool)
+    .await
+    .context("failed to group turns by prompt version")?;
+
+    // A rating belongs to the turn whose completions include the rated
+    // reply's request.
+    let ratings = sqlx::query(
+        "SELECT json_extract(t.outcome, '$.prompt_version') AS prompt_version, \
+         SUM(CASE WHEN f.rating > 0 THEN 1 ELSE 0 END) AS up, \
+         SUM(CASE WHEN f.rating < 0 THEN 1 ELSE 0 END) AS down \
+         FROM feedback f JOIN turn_runs t \
+         ON (t.channel_id = f.channel_id OR t.channel_id LIKE f.channel_id || ':%') \
+         AND EXISTS (SELECT 1 FROM json_each(t.outcome, '$.routing') r \
+                     WHERE json_extract(r.value, '$.request_id') = f.request_id) \
+         WHERE f.request_id IS NOT NULL AND t.completed_at >= datetime('now', ?) \
+         GROUP BY prompt_version",
+    )
+    .bind(&window)
+    .fetch_all(pool)
+    .await
+    .context("failed to group feedback by prompt version")?;
+
+    let mut ratings: HashMap<Option<String>, (i64, i64)> = ratings
+        .into_iter()
+        .map(|row| {
+            (
+                row.try_get("prompt_version").unwrap_or_default(),
+                (
+                    row.try_get("up").unwrap_or_default(),
+                    row.try_get("down").unwrap_or_default(),
+                ),
+            )
+        })
+        .collect();
+
+    Ok(rows
+        .into_iter()
+        .map(|row| {
+            let prompt_version: Option<String> = row.try_get("prompt_version").unwrap_or_default();
+            let (up, down) = ratings.remove(&prompt_version).unwrap_or_default();
+            PromptVersionStats {
+                prompt_version,
+                first_seen: row.try_get("first_seen").unwrap_or_default(),
+                last_seen: row.try_get("last_seen").unwrap_or_default(),
+                completion: TurnCompletion::from_row(&row),
+                conversations: row.try_get::<i64, _>("conversations").unwrap_or_default() as u64,
+                avg_input_tokens: row.try_get("avg_input_tokens").ok().flatten(),
+                avg_output_tokens: row.try_get("avg_output_tokens").ok().flatten(),
+                avg_cost_usd: row.try_get("avg_cost_usd").ok().flatten(),
+                cost_usd: row.try_get("cost_usd").ok().flatten(),
+                up,
+                down,
+                approval_rate: (up + down > 0).then(|| up as f64 / (up + down) as f64),
+            }
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_channel_version_follows_identity_and_persona() {
+        let base = channel_version("You are Spacebot.", []);
+        assert_eq!(base.len(), VERSION_LEN);
+        assert_eq!(base, channel_version("You are Spacebot.", []));
+        assert_ne!(base, channel_version("You are Spacebot!", []));
+        assert_ne!(base, channel_version("You are Spacebot.", ["Be terse."]));
+        // Part boundaries count: moving text between parts is a new version.
+        assert_ne!(version(["ab", "c"]), version(["a", "bc"]));
+    }
+}
@@ -0,0 +1,486 @@
+//! Install spacebot as a systemd or launchd service.
+//!
+//! The service runs `spacebot start --foreground` from the current binary,
+//! so the service manager owns restarts and logging instead of spacebot's
+//! own daemonization. Secrets go in an environment file next to the instance
+//! rather than in the unit itself.
+
+use anyhow::Context as _;
+use serde::Serialize;
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// systemd unit name and launchd label.
+const SYSTEMD_UNIT: &str = "spacebot.service";
+const LAUNCHD_LABEL: &str = "sh.spacebot.daemon";
+
+/// Which init system manages the service.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ServiceManager {
+    Systemd,
+    Launchd,
+}
+
+impl ServiceManager {
+    /// The service manager for this platform.
+    pub fn detect() -> anyhow::Result<Self> {
+        if cfg!(target_os = "macos") {
+            Ok(Self::Launchd)
+        } else if cfg!(target_os = "linux") {
+            Ok(Self::Systemd)
+        } else {
+            anyhow::bail!("service management is only supported on Linux (systemd) and macOS")
+        }
+    }
+}
+
+/// Everything needed to render and install the service definition.
+#[derive(Debug, Clone)]
+pub struct ServiceSpec {
+    pub manager: ServiceManager,
+    /// Install system-wide (root) instead of for the current user.
+    pub system: bool,
+    pub binary: PathBuf,
+    pub instance_dir: PathBuf,
+    pub config_path: Option<PathBuf>,
+    /// `KEY=value` lines loaded into the service environment.
+    pub env_file: PathBuf,
+    /// Account to run as for system-wide installs.
+    pub user: Option<String>,
+}
+
+impl ServiceSpec {
+    /// Describe a service for the running binary and the given instance.
+    pub fn for_current_binary(
+        instance_dir: &Path,
+        config_path: Option<&Path>,
+        env_file: Option<PathBuf>,
+        system: bool,
+    ) -> anyhow::Result<Self> {
+        let binary = std::env::current_exe()
+            .and_then(|path| path.canonicalize())
+            .context("can't locate the spacebot binary")?;
+        let instance_dir = absolute(instance_dir)?;
+        let config_path = config_path.map(absolute).transpose()?;
+        let env_file = match env_file {
+            Some(path) => absolute(&path)?,
+            None => instance_dir.join("spacebot.env"),
+        };
+        Ok(Self {
+            manager: ServiceManager::detect()?,
+            system,
+            binary,
+            instance_dir,
+            config_path,
+            env_file,
+            user: system.then(|| std::env::var("USER").ok()).flatten(),
+        })
+    }
+
+    /// Where the unit file or plist lives.
+    pub fn definition_path(&self) -> anyhow::Result<PathBuf> {
+        let home = || dirs::home_dir().context("can't determine the home directory");
+        Ok(match (self.manager, self.system) {
+            (ServiceManager::Systemd, true) => Path::new("/etc/systemd/system").join(SYSTEMD_UNIT),
+            (ServiceManager::Systemd, false) => dirs::config_dir()
+                .context("can't determine the config directory")?
+                .join("systemd/user")
+                .join(SYSTEMD_UNIT),
+            (ServiceManager::Launchd, true) => {
+                Path::new("/Library/LaunchDaemons").join(format!("{LAUNCHD_LABEL}.plist"))
+            }
+            (ServiceManager::Launchd, false) => home()?
+                .join("Library/LaunchAgents")
+                .join(format!("{LAUNCHD_LABEL}.plist")),
+        })
+    }
+
+    /// Arguments passed to the binary.
+    fn arguments(&self) -> Vec<String> {
+        let mut arguments = Vec::new();
+        if let Some(config_path) = &self.config_path {
+            arguments.push("--config".to_string());
+            arguments.push(config_path.display().to_string());
+        }
+        arguments.push("start".to_string());
+        arguments.push("--foreground".to_string());
+        arguments
+    }
+
+    /// The unit file or plist contents.
+    pub fn render(&self) -> String {
+        match self.manager {
+            ServiceManager::Systemd => self.render_systemd_unit(),
+            ServiceManager::Launchd => self.render_launchd_plist(),
+        }
+    }
+
+    fn render_systemd_unit(&self) -> String {
+        let exec = std::iter::once(self.binary.display().to_string())
+            .chain(self.arguments())
+            .map(|argument| systemd_quote(&argument.replace('$', "$$")))
+            .collect::<Vec<_>>()
+            .join(" ");
+        let instance_dir = systemd_quote(&self.instance_dir.display().to_string());
+        let environment = systemd_quote(&format!("SPACEBOT_DIR={}", self.instance_dir.display()));
+
+        let mut unit = format!(
+            "[Unit]\n\
+             Description=Spacebot agent daemon\n\
+             Documentation=https://spacebot.sh\n\
+             After=network-online.target\n\
+             Wants=network-online.target\n\
+             \n\
+             [Service]\n\
+             Type=simple\n\
+             ExecStart={exec}\n\
+             Environment={environment}\n\
+             EnvironmentFile=-{env_file}\n\
+             Restart=on-failure\n\
+             RestartSec=5\n\
+             TimeoutStopSec=30\n",
+            env_file = self.env_file.display(),
+        );
+        if let Some(user) = &self.user {
+            unit.push_str(&format!("User={user}\n"));
+        }
+
+        // These work without privileges, so they apply to user units too.
+        unit.push_str(
+            "NoNewPrivileges=yes\n\
+             LockPersonality=yes\n\
+             RestrictRealtime=yes\n\
+             RestrictSUIDSGID=yes\n",
+        );
+        // Filesystem sandboxing needs mount namespaces, which user managers
+        // can't always create. Browser automation needs its own namespaces,
+        // so RestrictNamespaces is deliberately left off.
+        if self.system {
+            unit.push_str(&format!(
+                "ProtectSystem=strict\n\
+                 ProtectHome=read-only\n\
+                 ReadWritePaths={instance_dir}\n\
+                 PrivateTmp=yes\n\
+                 PrivateDevices=yes\n\
+                 ProtectKernelTunables=yes\n\
+                 ProtectKernelModules=yes\n\
+                 ProtectControlGroups=yes\n"
+            ));
+        }
+
+        let target = if self.system {
+            "multi-user.target"
+        } else {
+            "default.target"
+        };
+        unit.push_str(&format!("\n[Install]\nWantedBy={target}\n"));
+        unit
+    }
+
+    fn render_launchd_plist(&self) -> String {
+        // launchd has no environment file support, so source it from a shell
+        // before exec'ing the binary.
+        let command = std::iter::once(self.binary.display().to_string())
+            .chain(self.arguments())
+            .map(|argument| shell_quote(&argument))
+            .collect::<Vec<_>>()
+            .join(" ");
+        let env_file = shell_quote(&self.env_file.display().to_string());
+        let script =
+            format!("if [ -f {env_file} ]; then set -a; . {env_file}; set +a; fi; exec {command}");
+        let log_path = self.instance_dir.join("logs").join("service.log");
+
+        let mut plist = format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+    <key>Label</key>
+    <string>{LAUNCHD_LABEL}</string>
+    <key>ProgramArguments</key>
+    <array>
+        <string>/bin/sh</string>
+        <string>-c</string>
+        <string>{script}</string>
+    </array>
+    <key>EnvironmentVariables</key>
+    <dict>
+        <key>SPACEBOT_DIR</key>
+        <string>{instance_dir}</string>
+    </dict>
+    <key>RunAtLoad</key>
+    <true/>
+    <key>KeepAlive</key>
+    <dict>
+        <key>SuccessfulExit</key>
+        <false/>
+    </dict>
+    <key>ThrottleInterval</key>
+    <integer>5</integer>
+    <key>ExitTimeOut</key>
+    <integer>30</integer>
+    <key>ProcessType</key>
+    <string>Background</string>
+    <key>StandardOutPath</key>
+    <string>{log_path}</string>
+    <key>StandardErrorPath</key>
+    <string>{log_path}</string>
+"#,
+            script = xml_escape(&script),
+            instance_dir = xml_escape(&self.instance_dir.display().to_string()),
+            log_path = xml_escape(&log_path.display().to_string()),
+        );
+        if let Some(user) = &self.user {
+            plist.push_str(&format!(
+                "    <key>UserName</key>\n    <string>{}</string>\n",
+                xml_escape(user)
+            ));
+        }
+        plist.push_str("</dict>\n</plist>\n");
+        plist
+    }
+}
+
+/// Write the service definition, then enable and start it unless
+/// `start` is false.
+pub fn install(spec: &ServiceSpec, start: bool) -> anyhow::Result<PathBuf> {
+    let path = spec.definition_path()?;
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .with_context(|| format!("failed to create {}", parent.display()))?;
+    }
+    std::fs::create_dir_all(spec.instance_dir.join("logs"))
+        .with_context(|| format!("failed to create {}", spec.instance_dir.display()))?;
+    ensure_env_file(&spec.env_file)?;
+
+    std::fs::write(&path, spec.render())
+        .with_context(|| format!("failed to write {}", path.display()))?;
+
+    match spec.manager {
+        ServiceManager::Systemd => {
+            run(systemctl(spec.system).arg("daemon-reload"))?;
+            let mut enable = systemctl(spec.system);
+            enable.arg("enable");
+            if start {
+                enable.arg("--now");
+            }
+            run(enable.arg(SYSTEMD_UNIT))?;
+        }
+        ServiceManager::Launchd if start => {
+            // Reinstalling over a loaded service fails to bootstrap otherwise.
+            Command::new("launchctl")
+                .args(["bootout", &launchd_target(spec.system)])
+                .output()
+                .ok();
+            run(Command::new("launchctl")
+                .arg("bootstrap")
+                .arg(launchd_domain(spec.system))
+                .arg(&path))?;
+        }
+        ServiceManager::Launchd => {}
+    }
+    Ok(path)
+}
+
+/// Stop and disable the service and remove its definition. The environment
+/// file is left in place since it holds secrets the user wrote.
+pub fn uninstall(spec: &ServiceSpec) -> anyhow::Result<Option<PathBuf>> {
+    let path = spec.definition_path()?;
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    match spec.manager {
+        ServiceManager::Systemd => {
+            run(systemctl(spec.system)
+                .args(["disable", "--now"])
+                .arg(SYSTEMD_UNIT))?;
+        }
+        ServiceManager::Launchd => {
+            // Fails when the service isn't loaded, which is fine here.
+            Command::new("launchctl")
+                .args(["bootout", &launchd_target(spec.system)])
+                .output()
+                .ok();
+        }
+    }
+
+    std::fs::remove_file(&path).with_context(|| format!("failed to remove {}", path.display()))?;
+    if spec.manager == ServiceManager::Systemd {
+        run(systemctl(spec.system).arg("daemon-reload"))?;
+    }
+    Ok(Some(path))
+}
+
+/// Whether the service is installed and running.
+#[derive(Debug, Clone, Serialize)]
+pub struct ServiceStatus {
+    pub manager: ServiceManager,
+    pub definition_path: PathBuf,
+    pub installed: bool,
+    pub active: bool,
+}
+
+pub fn status(spec: &ServiceSpec) -> anyhow::Result<ServiceStatus> {
+    let definition_path = spec.definition_path()?;
+    let installed = definition_path.exists();
+    let active = installed
+        && match spec.manager {
+            ServiceManager::Systemd => systemctl(spec.system)
+                .args(["is-active", "--quiet", SYSTEMD_UNIT])
+                .status()
+                .is_ok_and(|status| status.success()),
+            ServiceManager::Launchd => Command::new("launchctl")
+                .args(["print", &launchd_target(spec.system)])
+                .output()
+                .is_ok_and(|output| {
+                    output.status.success()
+                        && String::from_utf8_lossy(&output.stdout).contains("state = running")
+                }),
+        };
+    Ok(ServiceStatus {
+        manager: spec.manager,
+        definition_path,
+        installed,
+        active,
+    })
+}
+
+/// Create the environment file with owner-only permissions if it's missing.
+fn ensure_env_file(path: &Path) -> anyhow::Result<()> {
+    if path.exists() {
+        return Ok(());
+    }
+    std::fs::write(
+        path,
+        "# Environment for the spacebot service, one KEY=value per line.\n\
+         # Referenced from config.toml as env:KEY, e.g.\n\
+         # ANTHROPIC_API_KEY=sk-ant-...\n",
+    )
+    .with_context(|| format!("failed to create {}", path.display()))?;
+    crate::permissions::restrict_to_owner(path)
+        .with_context(|| format!("failed to restrict {}", path.display()))
+}
+
+fn systemctl(system: bool) -> Command {
+    let mut command = Command::new("systemctl");
+    if !system {
+        command.arg("--user");
+    }
+    command
+}
+
+fn launchd_domain(system: bool) -> String {
+    if system {
+        "system".into()
+    } else {
+        // SAFETY: getuid has no preconditions and can't fail.
+        format!("gui/{}", unsafe { libc::getuid() })
+    }
+}
+
+fn launchd_target(system: bool) -> String {
+    format!("{}/{LAUNCHD_LABEL}", launchd_domain(system))
+}
+
+fn run(command: &mut Command) -> anyhow::Result<()> {
+    let output = command
+        .output()
+        .with_context(|| format!("failed to run {:?}", command.get_program()))?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "{:?} failed: {}",
+            command,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(())
+}
+
+fn absolute(path: &Path) -> anyhow::Result<PathBuf> {
+    std::path::absolute(path).with_context(|| format!("can't resolve {}", path.display()))
+}
+
+/// Quote a value for a unit file setting, escaping `%` specifiers.
+/// `ExecStart=` additionally needs `$` doubled by the caller.
+fn systemd_quote(value: &str) -> String {
+    let escaped = value.replace('%', "%%");
+    if value
+        .chars()
+        .any(|c| c.is_whitespace() || matches!(c, '"' | '\'' | '\\'))
+    {
+        let escaped = escaped.replace('\\', "\\\\").replace('"', "\\\"");
+        format!("\"{escaped}\"")
+    } else {
+        escaped
+    }
+}
+
+/// Single-quote an argument for `/bin/sh`.
+fn shell_quote(argument: &str) -> String {
+    format!("'{}'", argument.replace('\'', r"'\''"))
+}
+
+fn xml_escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spec(manager: ServiceManager, system: bool) -> ServiceSpec {
+        ServiceSpec {
+            manager,
+            system,
+            binary: PathBuf::from("/opt/spacebot/bin/spacebot"),
+            instance_dir: PathBuf::from("/home/bot/My Bots/.spacebot"),
+            config_path: None,
+            env_file: PathBuf::from("/home/bot/My Bots/.spacebot/spacebot.env"),
+            user: system.then(|| "bot".to_string()),
+        }
+    }
+
+    #[test]
+    fn test_systemd_unit_sandboxing_depends_on_scope() {
+        let user_unit = spec(ServiceManager::Systemd, false).render();
+        assert!(user_unit.contains("ExecStart=/opt/spacebot/bin/spacebot start --foreground\n"));
+        assert!(user_unit.contains("EnvironmentFile=-/home/bot/My Bots/.spacebot/spacebot.env\n"));
+        assert!(user_unit.contains("Environment=\"SPACEBOT_DIR=/home/bot/My Bots/.spacebot\"\n"));
+        assert!(user_unit.contains("NoNewPrivileges=yes\n"));
+        assert!(!user_unit.contains("ProtectSystem"));
+        assert!(user_unit.contains("WantedBy=default.target\n"));
+
+        let system_unit = spec(ServiceManager::Systemd, true).render();
+        assert!(system_unit.contains("User=bot\n"));
+        assert!(system_unit.contains("ProtectSystem=strict\n"));
+        assert!(system_unit.contains("ReadWritePaths=\"/home/bot/My Bots/.spacebot\"\n"));
+        assert!(system_unit.contains("WantedBy=multi-user.target\n"));
+    }
+
+    #[test]
+    fn test_launchd_plist_sources_env_file() {
+        let plist = spec(ServiceManager::Launchd, false).render();
+        assert!(plist.contains("<string>sh.spacebot.daemon</string>"));
+        assert!(plist.contains(
+            "if [ -f '/home/bot/My Bots/.spacebot/spacebot.env' ]; then set -a; \
+             . '/home/bot/My Bots/.spacebot/spacebot.env'; set +a; fi; \
+             exec '/opt/spacebot/bin/spacebot' 'start' '--foreground'"
+        ));
+        assert!(!plist.contains("UserName"));
+    }
+
+    #[test]
+    fn test_systemd_quote() {
+        assert_eq!(systemd_quote("/usr/bin/spacebot"), "/usr/bin/spacebot");
+        assert_eq!(systemd_quote("/a b/c"), "\"/a b/c\"");
+        assert_eq!(systemd_quote("100%"), "100%%");
+        assert_eq!(systemd_quote("say \"hi\""), "\"say \\\"hi\\\"\"");
+    }
+}
@@ -238,7 +238,9 @@ async fn load_skill(
 ///
 /// Expects `---` delimiters. Returns the frontmatter key-value pairs and
 /// the remaining body. Compatible with OpenClaw's frontmatter format.
-fn parse_frontmatter(content: &str) -> anyhow::Result<(HashMap<String, String>, String)> {
+pub(crate) fn parse_frontmatter(
+    content: &str,
+) -> anyhow::Result<(HashMap<String, String>, String)> {
     let trimmed = content.trim_start();
 
     if !trimmed.starts_with("---") {
@@ -0,0 +1,149 @@
+//! Speech-to-text and text-to-speech.
+//!
+//! Talks to an OpenAI-compatible audio API: `/audio/transcriptions` for
+//! speech-to-text and `/audio/speech` for text-to-speech. Besides OpenAI
+//! itself, that covers self-hosted servers like faster-whisper-server,
+//! LocalAI and Kokoro-FastAPI.
+
+pub mod live;
+
+use crate::config::SpeechConfig;
+
+use anyhow::Context as _;
+
+/// Sample rate of recorded audio, what speech-to-text models expect.
+pub const SAMPLE_RATE: u32 = 16_000;
+
+/// Client for the configured speech API.
+pub struct SpeechClient {
+    http: reqwest::Client,
+    config: SpeechConfig,
+}
+
+impl SpeechClient {
+    pub fn new(config: SpeechConfig) -> Self {
+        Self {
+            http: reqwest::Client::new(),
+            config,
+        }
+    }
+
+    fn endpoint(&self, path: &str) -> String {
+        format!("{}/{path}", self.config.base_url.trim_end_matches('/'))
+    }
+
+    /// Transcribe a WAV recording to text.
+    pub async fn transcribe(&self, wav: Vec<u8>) -> anyhow::Result<String> {
+        #[derive(serde::Deserialize)]
+        struct Transcription {
+            text: String,
+        }
+
+        let file = reqwest::multipart::Part::bytes(wav)
+            .file_name("speech.wav")
+            .mime_str("audio/wav")?;
+        let form = reqwest::multipart::Form::new()
+            .text("model", self.config.stt_model.clone())
+            .text("response_format", "json")
+            .part("file", file);
+
+        let transcription: Transcription = self
+            .http
+            .post(self.endpoint("audio/transcriptions"))
+            .bearer_auth(&self.config.api_key)
+            .multipart(form)
+            .send()
+            .await
+            .context("transcription request failed")?
+            .error_for_status()
+            .context("transcription request rejected")?
+            .json()
+            .await
+            .context("invalid transcription response")?;
+
+        Ok(transcription.text.trim().to_string())
+    }
+
+    /// Synthesize speech for `text`, returned as a WAV file.
+    pub async fn synthesize(&self, text: &str) -> anyhow::Result<Vec<u8>> {
+        let body = serde_json::json!({
+            "model": self.config.tts_model,
+            "voice": self.config.tts_voice,
+            "input": text,
+            "response_format": "wav",
+        });
+
+        let audio = self
+            .http
+            .post(self.endpoint("audio/speech"))
+            .bearer_auth(&self.config.api_key)
+            .json(&body)
+            .send()
+            .await
+            .context("speech request failed")?
+            .error_for_status()
+            .context("speech request rejected")?
+            .bytes()
+            .await
+            .context("failed to read synthesized speech")?;
+
+        Ok(audio.to_vec())
+    }
+}
+
+/// Wrap 16-bit mono PCM samples in a WAV container.
+pub fn encode_wav(samples: &[i16], sample_rate: u32) -> Vec<u8> {
+    let data_length = (samples.len() * 2) as u32;
+    let mut wav = Vec::with_capacity(44 + samples.len() * 2);
+
+    wav.extend_from_slice(b"RIFF");
+    wav.extend_from_slice(&(36 + data_length).to_le_bytes());
+    wav.extend_from_slice(b"WAVE");
+
+    wav.extend_from_slice(b"fmt ");
+    wav.extend_from_slice(&16u32.to_le_bytes());
+    // PCM, one channel
+    wav.extend_from_slice(&1u16.to_le_bytes());
+    wav.extend_from_slice(&1u16.to_le_bytes());
+    wav.extend_from_slice(&sample_rate.to_le_bytes());
+    // Byte rate and block alignment for 16-bit mono
+    wav.extend_from_slice(&(sample_rate * 2).to_le_bytes());
+    wav.extend_from_slice(&2u16.to_le_bytes());
+    wav.extend_from_slice(&16u16.to_le_bytes());
+
+    wav.extend_from_slice(b"data");
+    wav.extend_from_slice(&data_length.to_le_bytes());
+    for sample in samples {
+        wav.extend_from_slice(&sample.to_le_bytes());
+    }
+
+    wav
+}
+
+/// Normalize a transcribed word for matching: lowercase, no punctuation.
+pub fn normalize_word(word: &str) -> String {
+    word.chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_wav_header() {
+        let wav = encode_wav(&[0, 1, -1], 16_000);
+
+        assert_eq!(wav.len(), 44 + 6);
+        assert_eq!(&wav[0..4], b"RIFF");
+        assert_eq!(u32::from_le_bytes(wav[4..8].try_into().unwrap()), 36 + 6);
+        assert_eq!(&wav[8..16], b"WAVEfmt ");
+        assert_eq!(u32::from_le_bytes(wav[24..28].try_into().unwrap()), 16_000);
+        assert_eq!(u32::from_le_bytes(wav[28..32].try_into().unwrap()), 32_000);
+        assert_eq!(&wav[36..40], b"data");
+        assert_eq!(u32::from_le_bytes(wav[40..44].try_into().unwrap()), 6);
+        assert_eq!(&wav[44..], &[0, 0, 1, 0, 0xff, 0xff]);
+    }
+}
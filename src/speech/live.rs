@@ -0,0 +1,334 @@
+//! Live transcription: a rolling transcript of everything said, and a
+//! wake-up when something relevant comes up.
+//!
+//! An audio source (a Discord voice channel, say) pushes utterances into a
+//! [`LivePipeline`]. A single worker transcribes them in order, names the
+//! speaker through an optional [`Diarizer`], and appends the text to a
+//! [`TranscriptBuffer`] holding the last few minutes. When a segment
+//! contains a trigger phrase, a [`Trigger`] with the recent transcript is
+//! handed back to the source, which wakes the agent with it.
+//!
+//! The input queue is bounded. When transcription falls behind, new
+//! utterances are dropped and counted instead of piling up, and a slow
+//! consumer of triggers holds up the worker, which fills the queue in turn.
+
+use crate::config::LiveTranscriptionConfig;
+use crate::speech::{SAMPLE_RATE, SpeechClient, encode_wav, normalize_word};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use tokio::sync::mpsc;
+
+/// Utterances waiting for transcription before new ones are dropped.
+const QUEUE_SIZE: usize = 32;
+
+/// Speaker name used when neither the source nor the diarizer knows it.
+const UNKNOWN_SPEAKER: &str = "Unknown speaker";
+
+/// A stretch of speech from the audio source.
+pub struct Utterance {
+    /// The source's ID for the speaker, when it has one stream per speaker.
+    pub speaker_id: Option<String>,
+    /// 16-bit mono PCM at [`SAMPLE_RATE`].
+    pub samples: Vec<i16>,
+    pub started_at: DateTime<Utc>,
+}
+
+/// Names the speaker of an utterance.
+///
+/// Sources with one stream per speaker map the stream to a display name.
+/// Sources that hear a whole room on one stream can plug in a speaker
+/// diarization model here.
+#[async_trait]
+pub trait Diarizer: Send + Sync + 'static {
+    async fn speaker(&self, utterance: &Utterance) -> Option<String>;
+}
+
+/// One transcribed utterance.
+#[derive(Debug, Clone)]
+pub struct Segment {
+    pub speaker_id: Option<String>,
+    pub speaker: String,
+    pub text: String,
+    pub at: DateTime<Utc>,
+}
+
+/// The transcript of the last `window` of speech.
+pub struct TranscriptBuffer {
+    segments: VecDeque<Segment>,
+    window: chrono::Duration,
+}
+
+impl TranscriptBuffer {
+    pub fn new(window_secs: u64) -> Self {
+        Self {
+            segments: VecDeque::new(),
+            window: chrono::Duration::seconds(window_secs as i64),
+        }
+    }
+
+    /// Append a segment and drop the ones that fell out of the window.
+    pub fn push(&mut self, segment: Segment) {
+        let cutoff = segment.at - self.window;
+        self.segments.push_back(segment);
+        while self
+            .segments
+            .front()
+            .is_some_and(|segment| segment.at < cutoff)
+        {
+            self.segments.pop_front();
+        }
+    }
+
+    /// One line per segment: `[HH:MM:SS] speaker: text`.
+    pub fn render(&self) -> String {
+        self.segments
+            .iter()
+            .map(|segment| {
+                format!(
+                    "[{}] {}: {}\n",
+                    segment.at.format("%H:%M:%S"),
+                    segment.speaker,
+                    segment.text
+                )
+            })
+            .collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.segments.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.segments.is_empty()
+    }
+}
+
+/// Trigger phrases, matched as whole words anywhere in a segment.
+pub struct Triggers {
+    phrases: Vec<(String, Vec<String>)>,
+    cooldown: chrono::Duration,
+    last_fired: HashMap<String, DateTime<Utc>>,
+}
+
+impl Triggers {
+    pub fn new(phrases: &[String], cooldown_secs: u64) -> Self {
+        let phrases = phrases
+            .iter()
+            .map(|phrase| {
+                let words = phrase
+                    .split_whitespace()
+                    .map(normalize_word)
+                    .filter(|word| !word.is_empty())
+                    .collect::<Vec<_>>();
+                (phrase.clone(), words)
+            })
+            .filter(|(_, words)| !words.is_empty())
+            .collect();
+        Self {
+            phrases,
+            cooldown: chrono::Duration::seconds(cooldown_secs as i64),
+            last_fired: HashMap::new(),
+        }
+    }
+
+    /// The first phrase in `text` that isn't cooling down from an earlier
+    /// match.
+    pub fn check(&mut self, text: &str, at: DateTime<Utc>) -> Option<String> {
+        let words: Vec<String> = text.split_whitespace().map(normalize_word).collect();
+
+        for (phrase, phrase_words) in &self.phrases {
+            if !words
+                .windows(phrase_words.len())
+                .any(|window| window == &phrase_words[..])
+            {
+                continue;
+            }
+            if self
+                .last_fired
+                .get(phrase)
+                .is_some_and(|fired| at - *fired < self.cooldown)
+            {
+                continue;
+            }
+            self.last_fired.insert(phrase.clone(), at);
+            return Some(phrase.clone());
+        }
+        None
+    }
+}
+
+/// A trigger phrase was said.
+#[derive(Debug, Clone)]
+pub struct Trigger {
+    pub phrase: String,
+    /// The segment the phrase was said in.
+    pub segment: Segment,
+    /// The transcript so far, including that segment.
+    pub transcript: String,
+}
+
+/// Handle for feeding audio into a live transcription worker. Dropping it
+/// stops the worker once the queue is drained.
+pub struct LivePipeline {
+    input: mpsc::Sender<Utterance>,
+    dropped: Arc<AtomicU64>,
+}
+
+impl LivePipeline {
+    /// Start a worker. Triggers come out of the returned receiver.
+    pub fn spawn(
+        speech: Arc<SpeechClient>,
+        config: &LiveTranscriptionConfig,
+        diarizer: Option<Arc<dyn Diarizer>>,
+    ) -> (Self, mpsc::Receiver<Trigger>) {
+        let (input, input_rx) = mpsc::channel(QUEUE_SIZE);
+        let (trigger_tx, trigger_rx) = mpsc::channel(QUEUE_SIZE);
+        let worker = Worker {
+            speech,
+            diarizer,
+            buffer: TranscriptBuffer::new(config.window_secs),
+            triggers: Triggers::new(&config.triggers, config.cooldown_secs),
+            trigger_tx,
+        };
+        tokio::spawn(worker.run(input_rx));
+
+        let pipeline = Self {
+            input,
+            dropped: Arc::new(AtomicU64::new(0)),
+        };
+        (pipeline, trigger_rx)
+    }
+
+    /// Queue an utterance for transcription. Never blocks, so it's safe to
+    /// call from an audio callback; drops the utterance if the queue is full.
+    pub fn push(&self, utterance: Utterance) {
+        if let Err(mpsc::error::TrySendError::Full(_)) = self.input.try_send(utterance) {
+            let dropped = self.dropped.fetch_add(1, Ordering::Relaxed) + 1;
+            // Log at 1, 2, 4, 8... so a long stall doesn't flood the logs.
+            if dropped.is_power_of_two() {
+                tracing::warn!(
+                    dropped,
+                    "live transcription is falling behind, dropping audio"
+                );
+            }
+        }
+    }
+
+    /// Utterances dropped because the queue was full.
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+struct Worker {
+    speech: Arc<SpeechClient>,
+    diarizer: Option<Arc<dyn Diarizer>>,
+    buffer: TranscriptBuffer,
+    triggers: Triggers,
+    trigger_tx: mpsc::Sender<Trigger>,
+}
+
+impl Worker {
+    async fn run(mut self, mut input_rx: mpsc::Receiver<Utterance>) {
+        while let Some(utterance) = input_rx.recv().await {
+            let Some(trigger) = self.process(utterance).await else {
+                continue;
+            };
+            if self.trigger_tx.send(trigger).await.is_err() {
+                break;
+            }
+        }
+        tracing::debug!("live transcription stopped");
+    }
+
+    async fn process(&mut self, utterance: Utterance) -> Option<Trigger> {
+        let text = match self
+            .speech
+            .transcribe(encode_wav(&utterance.samples, SAMPLE_RATE))
+            .await
+        {
+            Ok(text) if !text.is_empty() => text,
+            Ok(_) => return None,
+            Err(error) => {
+                tracing::warn!(%error, "live transcription failed");
+                return None;
+            }
+        };
+
+        let speaker = match &self.diarizer {
+            Some(diarizer) => diarizer.speaker(&utterance).await,
+            None => None,
+        };
+        let segment = Segment {
+            speaker: speaker
+                .or_else(|| utterance.speaker_id.clone())
+                .unwrap_or_else(|| UNKNOWN_SPEAKER.into()),
+            speaker_id: utterance.speaker_id,
+            text,
+            at: utterance.started_at,
+        };
+
+        let phrase = self.triggers.check(&segment.text, segment.at);
+        self.buffer.push(segment.clone());
+        phrase.map(|phrase| Trigger {
+            phrase,
+            segment,
+            transcript: self.buffer.render(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn segment(seconds: i64, text: &str) -> Segment {
+        Segment {
+            speaker_id: None,
+            speaker: "Ana".into(),
+            text: text.into(),
+            at: DateTime::from_timestamp(1_700_000_000 + seconds, 0).unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_transcript_buffer_keeps_window() {
+        let mut buffer = TranscriptBuffer::new(60);
+        buffer.push(segment(0, "First."));
+        buffer.push(segment(30, "Second."));
+        assert_eq!(buffer.len(), 2);
+
+        buffer.push(segment(90, "Third."));
+        assert_eq!(buffer.len(), 2);
+        assert_eq!(
+            buffer.render(),
+            "[22:13:50] Ana: Second.\n[22:14:50] Ana: Third.\n"
+        );
+    }
+
+    #[test]
+    fn test_triggers_match_words_and_cool_down() {
+        let mut triggers = Triggers::new(&["action item".into(), "deadline".into()], 60);
+        let at = |seconds: i64| DateTime::from_timestamp(1_700_000_000 + seconds, 0).unwrap();
+
+        assert_eq!(
+            triggers.check("OK, action item: Ana sends the draft.", at(0)),
+            Some("action item".into())
+        );
+        // Still cooling down
+        assert_eq!(triggers.check("Another action item.", at(30)), None);
+        assert_eq!(
+            triggers.check("The Deadline is Friday.", at(30)),
+            Some("deadline".into())
+        );
+        assert_eq!(triggers.check("Deadlines are hard.", at(200)), None);
+        assert_eq!(
+            triggers.check("One more action item.", at(61)),
+            Some("action item".into())
+        );
+    }
+}
@@ -0,0 +1,284 @@
+//! Artifact storage: a local working copy, optionally mirrored to an
+//! S3-compatible bucket.
+//!
+//! Tools keep writing artifacts to the agent's data directory, since other
+//! tools in the same run read them from there. With the S3 backend each
+//! artifact is also uploaded, and the bucket holds the copy that outlives the
+//! node. Uploads are signed with AWS Signature Version 4, which MinIO, R2 and
+//! the other S3-compatible services accept.
+
+use crate::config::{StorageBackend, StorageConfig};
+
+use anyhow::Context as _;
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+
+use std::path::PathBuf;
+use std::time::Duration;
+
+const UPLOAD_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Where one kind of artifact is saved for an agent.
+#[derive(Debug, Clone)]
+pub struct ArtifactStore {
+    local_dir: PathBuf,
+    remote: Option<S3Store>,
+    /// Key prefix within the bucket, ending in `/`.
+    key_prefix: String,
+}
+
+/// An artifact after [`ArtifactStore::save`].
+#[derive(Debug, Clone)]
+pub struct SavedArtifact {
+    pub path: PathBuf,
+    /// Link to the uploaded copy, when the S3 backend is on.
+    pub url: Option<String>,
+}
+
+impl ArtifactStore {
+    /// Store artifacts in `local_dir`, and under `{prefix}{namespace}/` in
+    /// the bucket when `config` selects the S3 backend.
+    pub fn new(config: &StorageConfig, local_dir: PathBuf, namespace: &str) -> Self {
+        let remote = match config.backend {
+            StorageBackend::Local => None,
+            StorageBackend::S3 => S3Store::from_config(config),
+        };
+        Self {
+            local_dir,
+            remote,
+            key_prefix: format!("{}{}/", config.prefix, namespace.trim_matches('/')),
+        }
+    }
+
+    /// Write `data` to `name` in the local directory, then upload it.
+    pub async fn save(
+        &self,
+        name: &str,
+        data: &[u8],
+        content_type: &str,
+    ) -> anyhow::Result<SavedArtifact> {
+        tokio::fs::create_dir_all(&self.local_dir)
+            .await
+            .with_context(|| format!("failed to create {}", self.local_dir.display()))?;
+        let path = self.local_dir.join(name);
+        tokio::fs::write(&path, data)
+            .await
+            .with_context(|| format!("failed to write {}", path.display()))?;
+
+        let url = match &self.remote {
+            Some(remote) => {
+                let key = format!("{}{name}", self.key_prefix);
+                Some(remote.put(&key, data.to_vec(), content_type).await?)
+            }
+            None => None,
+        };
+        Ok(SavedArtifact { path, url })
+    }
+}
+
+/// Client for one bucket of an S3-compatible service.
+#[derive(Clone)]
+pub struct S3Store {
+    http: reqwest::Client,
+    endpoint: reqwest::Url,
+    bucket: String,
+    region: String,
+    access_key_id: String,
+    secret_access_key: String,
+    public_url: Option<String>,
+}
+
+impl std::fmt::Debug for S3Store {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("S3Store")
+            .field("endpoint", &self.endpoint.as_str())
+            .field("bucket", &self.bucket)
+            .field("region", &self.region)
+            .finish()
+    }
+}
+
+impl S3Store {
+    /// Build a client from `config`, or None when it's missing a required
+    /// setting. Config validation rejects that case, so this only logs.
+    pub fn from_config(config: &StorageConfig) -> Option<Self> {
+        let endpoint = config
+            .endpoint
+            .as_deref()
+            .and_then(|endpoint| reqwest::Url::parse(endpoint).ok());
+        let (Some(endpoint), Some(bucket), Some(access_key_id), Some(secret_access_key)) = (
+            endpoint,
+            config.bucket.clone(),
+            config.access_key_id.clone(),
+            config.secret_access_key.clone(),
+        ) else {
+            tracing::warn!("s3 storage is missing its endpoint, bucket or keys, storing locally");
+            return None;
+        };
+        let http = reqwest::Client::builder()
+            .timeout(UPLOAD_TIMEOUT)
+            .build()
+            .expect("hardcoded reqwest client config");
+        Some(Self {
+            http,
+            endpoint,
+            bucket,
+            region: config.region.clone(),
+            access_key_id,
+            secret_access_key,
+            public_url: config.public_url.clone(),
+        })
+    }
+
+    /// Upload an object and return a link to it.
+    pub async fn put(
+        &self,
+        key: &str,
+        data: Vec<u8>,
+        content_type: &str,
+    ) -> anyhow::Result<String> {
+        let path = format!("/{}/{}", uri_encode(&self.bucket), encode_key(key));
+        let host = match self.endpoint.port() {
+            Some(port) => format!("{}:{port}", self.endpoint.host_str().unwrap_or_default()),
+            None => self.endpoint.host_str().unwrap_or_default().to_string(),
+        };
+        let payload_hash = hex(&Sha256::digest(&data));
+        let now = chrono::Utc::now();
+        let authorization = self.authorization("PUT", &path, &host, &payload_hash, now);
+
+        let url = format!("{}://{host}{path}", self.endpoint.scheme());
+        let response = self
+            .http
+            .put(&url)
+            .header("Authorization", authorization)
+            .header("x-amz-content-sha256", &payload_hash)
+            .header("x-amz-date", now.format("%Y%m%dT%H%M%SZ").to_string())
+            .header("Content-Type", content_type)
+            .body(data)
+            .send()
+            .await
+            .with_context(|| format!("failed to upload {key}"))?;
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            anyhow::bail!("upload of {key} failed with HTTP {status}: {body}");
+        }
+
+        Ok(match &self.public_url {
+            Some(base) => format!("{base}/{}", encode_key(key)),
+            None => format!("s3://{}/{key}", self.bucket),
+        })
+    }
+
+    /// The SigV4 `Authorization` header for a request with no query string
+    /// that signs `host`, `x-amz-content-sha256` and `x-amz-date`.
+    fn authorization(
+        &self,
+        method: &str,
+        path: &str,
+        host: &str,
+        payload_hash: &str,
+        now: chrono::DateTime<chrono::Utc>,
+    ) -> String {
+        let date = now.format("%Y%m%d").to_string();
+        let timestamp = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let signed_headers = "host;x-amz-content-sha256;x-amz-date";
+        let canonical_request = format!(
+            "{method}\n{path}\n\nhost:{host}\nx-amz-content-sha256:{payload_hash}\n\
+             x-amz-date:{timestamp}\n\n{signed_headers}\n{payload_hash}"
+        );
+        let scope = format!("{date}/{}/s3/aws4_request", self.region);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{timestamp}\n{scope}\n{}",
+            hex(&Sha256::digest(canonical_request.as_bytes()))
+        );
+        let key = signing_key(&self.secret_access_key, &date, &self.region, "s3");
+        let signature = hex(&hmac_sha256(&key, string_to_sign.as_bytes()));
+        format!(
+            "AWS4-HMAC-SHA256 Credential={}/{scope}, SignedHeaders={signed_headers}, \
+             Signature={signature}",
+            self.access_key_id
+        )
+    }
+}
+
+fn signing_key(secret: &str, date: &str, region: &str, service: &str) -> Vec<u8> {
+    let key = hmac_sha256(format!("AWS4{secret}").as_bytes(), date.as_bytes());
+    let key = hmac_sha256(&key, region.as_bytes());
+    let key = hmac_sha256(&key, service.as_bytes());
+    hmac_sha256(&key, b"aws4_request")
+}
+
+fn hmac_sha256(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts any key length");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+/// Percent-encode an object key, keeping the `/` between segments.
+fn encode_key(key: &str) -> String {
+    key.split('/').map(uri_encode).collect::<Vec<_>>().join("/")
+}
+
+/// Percent-encode everything but the unreserved characters, as SigV4 wants.
+fn uri_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{byte:02X}"),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_signing_key_matches_aws_example() {
+        // From the AWS Signature Version 4 documentation.
+        let key = signing_key(
+            "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY",
+            "20120215",
+            "us-east-1",
+            "iam",
+        );
+        assert_eq!(
+            hex(&key),
+            "f4780e2d9f65fa895f9c67b32ce1baf0b0d8a43505a000a1a9e090d414db404d"
+        );
+    }
+
+    #[test]
+    fn test_encode_key_keeps_separators() {
+        assert_eq!(
+            encode_key("prod/main/screenshots/shot 1+2.png"),
+            "prod/main/screenshots/shot%201%2B2.png"
+        );
+    }
+
+    #[test]
+    fn test_store_keys_are_namespaced() {
+        let config = StorageConfig {
+            backend: StorageBackend::S3,
+            endpoint: Some("http://minio:9000".into()),
+            bucket: Some("spacebot".into()),
+            access_key_id: Some("key".into()),
+            secret_access_key: Some("secret".into()),
+            prefix: "prod/".into(),
+            ..StorageConfig::default()
+        };
+
+        let store = ArtifactStore::new(&config, PathBuf::from("/tmp"), "main/screenshots");
+
+        assert!(store.remote.is_some());
+        assert_eq!(store.key_prefix, "prod/main/screenshots/");
+    }
+}
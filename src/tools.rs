@@ -6,53 +6,118 @@
 //! ## ToolServer Topology
 //!
 //! **Channel ToolServer** (one per channel):
-//! - `reply`, `branch`, `spawn_worker`, `route`, `cancel`, `skip`, `react` — added
+//! - `reply`, `branch`, `spawn_worker`, `start_task`, `route`, `cancel`, `skip`,
+//!   `react`, `pin`, `scratchpad_set`, `scratchpad_get`, `scratchpad_list` — added
 //!   dynamically per conversation turn via `add_channel_tools()` /
 //!   `remove_channel_tools()` because they hold per-channel state.
+//! - `handoff` — added the same way when there are other agents to hand to.
+//! - `escalate` — added the same way when moderator escalation is enabled.
+//! - `calc` — stateless, added with the per-turn tools.
 //! - No memory tools — the channel delegates memory work to branches.
 //!
 //! **Branch ToolServer** (one per branch, isolated):
 //! - `memory_save` + `memory_recall` + `memory_delete` — registered at creation
+//! - `calc` — stateless, registered at creation
 //!
 //! **Worker ToolServer** (one per worker, created at spawn time):
-//! - `shell`, `file`, `exec` — stateless, registered at creation
+//! - `shell`, `file`, `exec`, `calc` — stateless, registered at creation
 //! - `set_status` — per-worker instance, registered at creation
+//! - `share` — per-worker instance, registered at creation when the worker
+//!   belongs to a channel
+//! - `generate_image` — registered at creation when image generation is on;
+//!   uploads its images to the worker's channel, if it has one
+//! - `sql_query` — registered at creation when databases are configured
+//! - `http_request` — registered at creation when domains are allowlisted
+//! - one tool per operation of each configured OpenAPI spec — registered at
+//!   creation, named after the API and operation
+//! - `kubernetes` — registered at creation when clusters are configured
+//! - `promql_query`, `logql_query` — registered at creation when Prometheus
+//!   or Loki sources are configured
+//! - `incident` — registered at creation when incident accounts are configured
 //!
 //! **Cortex ToolServer** (one per agent):
 //! - `memory_save` — registered at startup
 
+pub mod api_operation;
 pub mod branch_tool;
 pub mod browser;
+pub mod calc;
 pub mod cancel;
 pub mod channel_recall;
+#[cfg(feature = "computer-use")]
+pub mod computer;
+pub mod content;
 pub mod cron;
+pub mod escalate;
 pub mod exec;
 pub mod file;
+pub mod handoff;
+pub mod home_assistant;
+pub mod http_request;
+pub mod image_generation;
+pub mod incident;
+pub mod issues;
+pub mod kubernetes;
+pub mod logql_query;
 pub mod memory_delete;
 pub mod memory_recall;
 pub mod memory_save;
+pub mod network;
+pub mod ocr;
+pub mod pin;
+pub mod promql_query;
 pub mod react;
+pub mod relevance;
 pub mod reply;
 pub mod route;
+pub mod scratchpad;
 pub mod send_file;
 pub mod set_status;
+pub mod share;
 pub mod shell;
 pub mod skip;
 pub mod spawn_worker;
+pub mod sql_query;
+pub mod start_task;
+pub mod usage;
 pub mod web_search;
 
+pub use api_operation::ApiOperationTool;
 pub use branch_tool::{BranchArgs, BranchError, BranchOutput, BranchTool};
 pub use browser::{
     ActKind, BrowserAction, BrowserArgs, BrowserError, BrowserOutput, BrowserTool, ElementSummary,
     TabInfo,
 };
+pub use calc::{CalcArgs, CalcError, CalcOutput, CalcTool};
 pub use cancel::{CancelArgs, CancelError, CancelOutput, CancelTool};
 pub use channel_recall::{
     ChannelRecallArgs, ChannelRecallError, ChannelRecallOutput, ChannelRecallTool,
 };
+#[cfg(feature = "computer-use")]
+pub use computer::{
+    ComputerAction, ComputerArgs, ComputerError, ComputerOutput, ComputerTool, ScrollDirection,
+};
+pub use content::{ContentSink, ToolContent};
 pub use cron::{CronArgs, CronError, CronOutput, CronTool};
+pub use escalate::{EscalateArgs, EscalateError, EscalateOutput, EscalateTool};
 pub use exec::{EnvVar, ExecArgs, ExecError, ExecOutput, ExecResult, ExecTool};
 pub use file::{FileArgs, FileEntry, FileEntryOutput, FileError, FileOutput, FileTool, FileType};
+pub use handoff::{HandoffArgs, HandoffError, HandoffOutput, HandoffTool};
+pub use home_assistant::{
+    HomeAssistantAction, HomeAssistantArgs, HomeAssistantError, HomeAssistantOutput,
+    HomeAssistantTool,
+};
+pub use http_request::{HttpRequestArgs, HttpRequestError, HttpRequestTool, HttpResponseOutput};
+pub use image_generation::{
+    GenerateImageArgs, GenerateImageError, GenerateImageOutput, GenerateImageTool, GeneratedImage,
+};
+pub use incident::{IncidentAction, IncidentArgs, IncidentError, IncidentOutput, IncidentTool};
+pub use issues::{Issue, IssuesAction, IssuesArgs, IssuesError, IssuesOutput, IssuesTool};
+pub use kubernetes::{
+    KubernetesAction, KubernetesArgs, KubernetesError, KubernetesKind, KubernetesOutput,
+    KubernetesTool,
+};
+pub use logql_query::{LogqlQueryArgs, LogqlQueryError, LogqlQueryOutput, LogqlQueryTool};
 pub use memory_delete::{
     MemoryDeleteArgs, MemoryDeleteError, MemoryDeleteOutput, MemoryDeleteTool,
 };
@@ -62,20 +127,35 @@ pub use memory_recall::{
 pub use memory_save::{
     AssociationInput, MemorySaveArgs, MemorySaveError, MemorySaveOutput, MemorySaveTool,
 };
+pub use network::NetworkSandbox;
+pub use ocr::{BoundingBox, OcrArgs, OcrError, OcrLine, OcrOutput, OcrTool};
+pub use pin::{PinArgs, PinError, PinOutput, PinTool};
+pub use promql_query::{PromqlQueryArgs, PromqlQueryError, PromqlQueryOutput, PromqlQueryTool};
 pub use react::{ReactArgs, ReactError, ReactOutput, ReactTool};
 pub use reply::{ReplyArgs, ReplyError, ReplyOutput, ReplyTool};
 pub use route::{RouteArgs, RouteError, RouteOutput, RouteTool};
+pub use scratchpad::{
+    ScratchpadError, ScratchpadGetArgs, ScratchpadGetOutput, ScratchpadGetTool, ScratchpadListArgs,
+    ScratchpadListEntry, ScratchpadListOutput, ScratchpadListTool, ScratchpadSetArgs,
+    ScratchpadSetOutput, ScratchpadSetTool,
+};
 pub use send_file::{SendFileArgs, SendFileError, SendFileOutput, SendFileTool};
 pub use set_status::{SetStatusArgs, SetStatusError, SetStatusOutput, SetStatusTool};
+pub use share::{ShareArgs, ShareError, ShareKind, ShareOutput, ShareTool};
 pub use shell::{ShellArgs, ShellError, ShellOutput, ShellResult, ShellTool};
 pub use skip::{SkipArgs, SkipError, SkipFlag, SkipOutput, SkipTool, new_skip_flag};
 pub use spawn_worker::{SpawnWorkerArgs, SpawnWorkerError, SpawnWorkerOutput, SpawnWorkerTool};
+pub use sql_query::{SqlQueryArgs, SqlQueryError, SqlQueryOutput, SqlQueryTool};
+pub use start_task::{StartTaskArgs, StartTaskError, StartTaskOutput, StartTaskTool};
 pub use web_search::{SearchResult, WebSearchArgs, WebSearchError, WebSearchOutput, WebSearchTool};
 
 use crate::agent::channel::ChannelState;
-use crate::config::BrowserConfig;
+use crate::agent::fact_check::FactChecker;
+use crate::agent::provenance::Provenance;
+use crate::config::{BrowserConfig, ComputerUseConfig};
 use crate::memory::MemorySearch;
-use crate::{AgentId, ChannelId, OutboundResponse, ProcessEvent, WorkerId};
+use crate::storage::ArtifactStore;
+use crate::{AgentId, ChannelId, OutboundResponse, ProcessEvent, ProcessId, WorkerId};
 use rig::tool::Tool as _;
 use rig::tool::server::{ToolServer, ToolServerHandle};
 use std::path::PathBuf;
@@ -127,18 +207,49 @@ pub async fn add_channel_tools(
     skip_flag: SkipFlag,
     cron_tool: Option<CronTool>,
 ) -> Result<(), rig::tool::server::ToolServerError> {
+    let conversation_id: String = conversation_id.into();
+    if !state
+        .deps
+        .handoffs
+        .targets_for(&state.deps.agent_id)
+        .is_empty()
+    {
+        handle
+            .add_tool(HandoffTool::new(
+                state.clone(),
+                response_tx.clone(),
+                conversation_id.clone(),
+                skip_flag.clone(),
+            ))
+            .await?;
+    }
+    if state.deps.runtime_config.escalation.load().enabled {
+        handle
+            .add_tool(EscalateTool::new(
+                state.clone(),
+                response_tx.clone(),
+                skip_flag.clone(),
+            ))
+            .await?;
+    }
     handle
-        .add_tool(ReplyTool::new(
-            response_tx.clone(),
-            conversation_id,
-            state.conversation_logger.clone(),
-            state.channel_id.clone(),
-        ))
+        .add_tool(
+            ReplyTool::new(
+                response_tx.clone(),
+                conversation_id,
+                state.conversation_logger.clone(),
+                state.channel_id.clone(),
+                state.last_completion.clone(),
+            )
+            .with_fact_check(FactChecker::for_channel(&state.deps, &state.channel_id))
+            .with_provenance(Provenance::for_channel(&state)),
+        )
         .await?;
     handle.add_tool(BranchTool::new(state.clone())).await?;
     handle.add_tool(SpawnWorkerTool::new(state.clone())).await?;
+    handle.add_tool(StartTaskTool::new(state.clone())).await?;
     handle.add_tool(RouteTool::new(state.clone())).await?;
-    handle.add_tool(CancelTool::new(state)).await?;
+    handle.add_tool(CancelTool::new(state.clone())).await?;
     handle
         .add_tool(SkipTool::new(skip_flag, response_tx.clone()))
         .await?;
@@ -146,6 +257,29 @@ pub async fn add_channel_tools(
         .add_tool(SendFileTool::new(response_tx.clone()))
         .await?;
     handle.add_tool(ReactTool::new(response_tx)).await?;
+    handle.add_tool(CalcTool::new()).await?;
+    let scratchpad = crate::conversation::ScratchpadStore::new(state.deps.sqlite_pool.clone());
+    handle
+        .add_tool(ScratchpadSetTool::new(
+            scratchpad.clone(),
+            state.channel_id.clone(),
+        ))
+        .await?;
+    handle
+        .add_tool(ScratchpadGetTool::new(
+            scratchpad.clone(),
+            state.channel_id.clone(),
+        ))
+        .await?;
+    handle
+        .add_tool(ScratchpadListTool::new(
+            scratchpad,
+            state.channel_id.clone(),
+        ))
+        .await?;
+    handle
+        .add_tool(PinTool::new(state.channel_store, state.channel_id))
+        .await?;
     if let Some(cron) = cron_tool {
         handle.add_tool(cron).await?;
     }
@@ -162,13 +296,21 @@ pub async fn remove_channel_tools(
     handle.remove_tool(ReplyTool::NAME).await?;
     handle.remove_tool(BranchTool::NAME).await?;
     handle.remove_tool(SpawnWorkerTool::NAME).await?;
+    handle.remove_tool(StartTaskTool::NAME).await?;
     handle.remove_tool(RouteTool::NAME).await?;
     handle.remove_tool(CancelTool::NAME).await?;
     handle.remove_tool(SkipTool::NAME).await?;
     handle.remove_tool(SendFileTool::NAME).await?;
     handle.remove_tool(ReactTool::NAME).await?;
-    // Cron tool removal is best-effort since not all channels have it
+    handle.remove_tool(PinTool::NAME).await?;
+    handle.remove_tool(CalcTool::NAME).await?;
+    handle.remove_tool(ScratchpadSetTool::NAME).await?;
+    handle.remove_tool(ScratchpadGetTool::NAME).await?;
+    handle.remove_tool(ScratchpadListTool::NAME).await?;
+    // Cron, handoff and escalate tool removal is best-effort since not all channels have them
     let _ = handle.remove_tool(CronTool::NAME).await;
+    let _ = handle.remove_tool(HandoffTool::NAME).await;
+    let _ = handle.remove_tool(EscalateTool::NAME).await;
     Ok(())
 }
 
@@ -187,42 +329,138 @@ pub fn create_branch_tool_server(
         .tool(MemoryRecallTool::new(memory_search.clone()))
         .tool(MemoryDeleteTool::new(memory_search))
         .tool(ChannelRecallTool::new(conversation_logger, channel_store))
+        .tool(CalcTool::new())
         .run()
 }
 
 /// Create a per-worker ToolServer with task-appropriate tools.
 ///
 /// Each worker gets its own isolated ToolServer. The `set_status` tool is bound to
-/// the specific worker's ID so status updates route correctly. The browser tool
-/// is included when browser automation is enabled in the agent config.
+/// the specific worker's ID so status updates route correctly, and `share` to its
+/// channel, when it has one, for delivering images, tables and files. The browser tool
+/// is included when browser automation is enabled in the agent config, the
+/// computer tool when computer use is built in and enabled, and `ocr_tool`,
+/// `home_assistant_tool`, `issues_tool`, `sql_tool`, `http_tool`, `api_tools`,
+/// `kubernetes_tool`, `promql_tool`, `logql_tool` and `incident_tool` when
+/// those integrations are enabled.
 ///
 /// File operations are restricted to `workspace`. Shell and exec commands are
-/// blocked from accessing sensitive files in `instance_dir`.
+/// blocked from accessing sensitive files in `instance_dir`. Shell, exec,
+/// browser, web search, `http_request`, `promql_query` and `logql_query`
+/// traffic goes through `network`'s
+/// proxy for any tool with a network policy. `api_tools` come with the proxy
+/// already set.
 pub fn create_worker_tool_server(
     agent_id: AgentId,
     worker_id: WorkerId,
     channel_id: Option<ChannelId>,
     event_tx: broadcast::Sender<ProcessEvent>,
     browser_config: BrowserConfig,
-    screenshot_dir: PathBuf,
+    computer_use: ComputerUseConfig,
+    ocr_tool: Option<OcrTool>,
+    image_tool: Option<GenerateImageTool>,
+    home_assistant_tool: Option<HomeAssistantTool>,
+    issues_tool: Option<IssuesTool>,
+    sql_tool: Option<SqlQueryTool>,
+    http_tool: Option<HttpRequestTool>,
+    api_tools: Vec<ApiOperationTool>,
+    kubernetes_tool: Option<KubernetesTool>,
+    promql_tool: Option<PromqlQueryTool>,
+    logql_tool: Option<LogqlQueryTool>,
+    incident_tool: Option<IncidentTool>,
+    screenshots: ArtifactStore,
     brave_search_key: Option<String>,
+    network: NetworkSandbox,
     workspace: PathBuf,
     instance_dir: PathBuf,
 ) -> ToolServerHandle {
+    let sink = ContentSink::new(
+        agent_id.clone(),
+        ProcessId::Worker(worker_id),
+        channel_id.clone(),
+        event_tx.clone(),
+    );
     let mut server = ToolServer::new()
-        .tool(ShellTool::new(instance_dir.clone(), workspace.clone()))
+        .tool(
+            ShellTool::new(instance_dir.clone(), workspace.clone())
+                .with_proxy(network.proxy_for(ShellTool::NAME)),
+        )
         .tool(FileTool::new(workspace.clone()))
-        .tool(ExecTool::new(instance_dir, workspace))
+        .tool(
+            ExecTool::new(instance_dir, workspace.clone())
+                .with_proxy(network.proxy_for(ExecTool::NAME)),
+        )
+        .tool(CalcTool::new())
         .tool(SetStatusTool::new(
             agent_id, worker_id, channel_id, event_tx,
         ));
 
+    if sink.has_channel() {
+        server = server.tool(ShareTool::new(workspace, sink.clone()));
+    }
+
+    if let Some(image) = image_tool {
+        server = server.tool(image.with_sink(sink.clone()));
+    }
+
     if browser_config.enabled {
-        server = server.tool(BrowserTool::new(browser_config, screenshot_dir));
+        server = server.tool(
+            BrowserTool::new(browser_config, screenshots)
+                .with_sink(sink)
+                .with_proxy(network.proxy_for(BrowserTool::NAME)),
+        );
+    }
+
+    #[cfg(feature = "computer-use")]
+    if computer_use.enabled {
+        server = server.tool(ComputerTool::new(computer_use));
+    }
+    #[cfg(not(feature = "computer-use"))]
+    let _ = computer_use;
+
+    if let Some(ocr) = ocr_tool {
+        server = server.tool(ocr);
+    }
+
+    if let Some(home_assistant) = home_assistant_tool {
+        server = server.tool(home_assistant);
+    }
+
+    if let Some(issues) = issues_tool {
+        server = server.tool(issues);
+    }
+
+    if let Some(sql) = sql_tool {
+        server = server.tool(sql);
+    }
+
+    if let Some(http) = http_tool {
+        server = server.tool(http.with_proxy(network.proxy_for(HttpRequestTool::NAME)));
+    }
+
+    for api_tool in api_tools {
+        server = server.tool(api_tool);
+    }
+
+    if let Some(kubernetes) = kubernetes_tool {
+        server = server.tool(kubernetes);
+    }
+
+    if let Some(promql) = promql_tool {
+        server = server.tool(promql.with_proxy(network.proxy_for(PromqlQueryTool::NAME)));
+    }
+
+    if let Some(logql) = logql_tool {
+        server = server.tool(logql.with_proxy(network.proxy_for(LogqlQueryTool::NAME)));
+    }
+
+    if let Some(incident) = incident_tool {
+        server = server.tool(incident);
     }
 
     if let Some(key) = brave_search_key {
-        server = server.tool(WebSearchTool::new(key));
+        server =
+            server.tool(WebSearchTool::new(key).with_proxy(network.proxy_for(WebSearchTool::NAME)));
     }
 
     server.run()
@@ -248,7 +486,7 @@ pub fn create_cortex_chat_tool_server(
     conversation_logger: crate::conversation::history::ConversationLogger,
     channel_store: crate::conversation::ChannelStore,
     browser_config: BrowserConfig,
-    screenshot_dir: PathBuf,
+    screenshots: ArtifactStore,
     brave_search_key: Option<String>,
     workspace: PathBuf,
     instance_dir: PathBuf,
@@ -263,7 +501,7 @@ pub fn create_cortex_chat_tool_server(
         .tool(ExecTool::new(instance_dir, workspace));
 
     if browser_config.enabled {
-        server = server.tool(BrowserTool::new(browser_config, screenshot_dir));
+        server = server.tool(BrowserTool::new(browser_config, screenshots));
     }
 
     if let Some(key) = brave_search_key {
@@ -0,0 +1,697 @@
+//! Tools generated from OpenAPI specs (task workers only).
+//!
+//! Each operation of an API in `[defaults.http]` becomes a tool of its own,
+//! named `{api}_{operationId}` in snake case. Its parameters are the
+//! operation's path, query and header parameters plus `body` for a JSON
+//! request body, with schemas taken from the spec and local `$ref`s inlined.
+//! Requests go to the API's base URL with its credentials attached, and only
+//! to domains on the API's allowlist; redirects elsewhere aren't followed.
+
+use super::http_request::{HttpRequestError, HttpResponseOutput, client, read_response};
+use crate::config::{HttpApi, HttpAuth, HttpConfig, domain_allowed};
+
+use anyhow::Context as _;
+use rig::completion::ToolDefinition;
+use rig::tool::Tool;
+use serde_json::{Map, Value, json};
+use std::collections::HashSet;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Operations one API may expose. Larger APIs need an `operations` list.
+const MAX_OPERATIONS: usize = 100;
+
+/// `$ref`s followed inside one schema. Deeper (or recursive) schemas accept
+/// any value from there on.
+const MAX_REF_DEPTH: usize = 5;
+
+/// Longest tool name most model providers accept.
+const MAX_NAME_CHARS: usize = 64;
+
+/// Characters of an operation's summary and description kept in its tool
+/// description.
+const MAX_DESCRIPTION_CHARS: usize = 1_000;
+
+/// Path item keys that are operations.
+const METHODS: &[&str] = &["get", "put", "post", "delete", "options", "head", "patch"];
+
+/// Where an operation parameter goes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Location {
+    Path,
+    Query,
+    Header,
+}
+
+/// One parameter of an operation.
+#[derive(Debug, Clone)]
+pub struct Parameter {
+    pub name: String,
+    pub location: Location,
+    pub required: bool,
+    /// JSON schema for the argument, description included.
+    pub schema: Value,
+}
+
+/// One operation of an OpenAPI spec.
+#[derive(Debug, Clone)]
+pub struct Operation {
+    /// The tool's name.
+    pub name: String,
+    pub method: reqwest::Method,
+    /// Path template, e.g. `/users/{id}`.
+    pub path: String,
+    pub description: String,
+    pub parameters: Vec<Parameter>,
+    /// JSON schema of the request body, when the operation takes JSON.
+    pub body: Option<Value>,
+    pub body_required: bool,
+}
+
+impl Operation {
+    /// JSON schema of the tool's arguments.
+    fn schema(&self) -> Value {
+        let mut properties = Map::new();
+        let mut required = Vec::new();
+        for parameter in &self.parameters {
+            properties.insert(parameter.name.clone(), parameter.schema.clone());
+            if parameter.required {
+                required.push(parameter.name.clone());
+            }
+        }
+        if let Some(body) = &self.body {
+            properties.insert("body".into(), body.clone());
+            if self.body_required {
+                required.push("body".into());
+            }
+        }
+        json!({
+            "type": "object",
+            "properties": properties,
+            "required": required,
+        })
+    }
+}
+
+/// Settings shared by an API's tools.
+#[derive(Debug)]
+struct Api {
+    client: reqwest::Client,
+    base_url: String,
+    allowed_domains: Vec<String>,
+    auth: Option<HttpAuth>,
+    timeout: Duration,
+    max_response_bytes: usize,
+}
+
+/// Tool for calling one operation of a configured API.
+#[derive(Debug, Clone)]
+pub struct ApiOperationTool {
+    api: Arc<Api>,
+    operation: Operation,
+}
+
+/// The tools for the operations of `config`'s APIs, sending requests
+/// through `proxy`. An API whose spec can't be used is left out with a
+/// warning.
+pub async fn load(
+    config: &HttpConfig,
+    instance_dir: &Path,
+    proxy: Option<String>,
+) -> Vec<ApiOperationTool> {
+    let mut tools = Vec::new();
+    let mut names = HashSet::new();
+    for api in &config.apis {
+        let loaded = match load_api(api, config, instance_dir, proxy.clone()).await {
+            Ok(loaded) => loaded,
+            Err(error) => {
+                tracing::warn!(api = %api.name, %error, "http api unavailable");
+                continue;
+            }
+        };
+        for tool in loaded {
+            if names.insert(tool.operation.name.clone()) {
+                tools.push(tool);
+            } else {
+                tracing::warn!(
+                    tool = %tool.operation.name,
+                    "duplicate api operation tool skipped"
+                );
+            }
+        }
+    }
+    tools
+}
+
+async fn load_api(
+    api: &HttpApi,
+    config: &HttpConfig,
+    instance_dir: &Path,
+    proxy: Option<String>,
+) -> anyhow::Result<Vec<ApiOperationTool>> {
+    let path = instance_dir.join(&api.spec);
+    let text = tokio::fs::read_to_string(&path)
+        .await
+        .with_context(|| format!("failed to read {}", path.display()))?;
+    let spec = parse_spec(&text)?;
+
+    let base_url = match &api.base_url {
+        Some(base_url) => base_url.clone(),
+        None => spec_server(&spec).context("the spec has no server; set base_url")?,
+    };
+    let host = reqwest::Url::parse(&base_url)
+        .with_context(|| format!("invalid base url {base_url}"))?
+        .host_str()
+        .unwrap_or_default()
+        .to_string();
+    let allowed_domains = if api.allowed_domains.is_empty() {
+        vec![host.clone()]
+    } else {
+        api.allowed_domains.clone()
+    };
+    anyhow::ensure!(
+        domain_allowed(&host, &allowed_domains),
+        "base url host {host} isn't in allowed_domains"
+    );
+
+    let operations = operations(&spec, &api.name, &api.operations);
+    anyhow::ensure!(
+        operations.len() <= MAX_OPERATIONS,
+        "the spec has {} operations; list at most {MAX_OPERATIONS} in `operations`",
+        operations.len()
+    );
+    let shared = Arc::new(Api {
+        client: client(Arc::new(allowed_domains.clone()), proxy),
+        base_url: base_url.trim_end_matches('/').to_string(),
+        allowed_domains,
+        auth: api.auth.clone(),
+        timeout: Duration::from_secs(config.timeout_secs),
+        max_response_bytes: config.max_response_bytes,
+    });
+    Ok(operations
+        .into_iter()
+        .map(|operation| ApiOperationTool {
+            api: shared.clone(),
+            operation,
+        })
+        .collect())
+}
+
+/// A spec, from JSON or YAML.
+pub fn parse_spec(source: &str) -> anyhow::Result<Value> {
+    let spec: Value = match serde_json::from_str(source) {
+        Ok(spec) => spec,
+        // Through YAML's own value, since YAML keys (such as response codes)
+        // needn't be strings.
+        Err(_) => {
+            let yaml: serde_yaml::Value =
+                serde_yaml::from_str(source).context("the spec is neither JSON nor YAML")?;
+            serde_json::to_value(yaml).context("the spec doesn't convert to JSON")?
+        }
+    };
+    anyhow::ensure!(
+        spec.get("openapi")
+            .is_some_and(|version| text(version).starts_with('3')),
+        "only OpenAPI 3 specs are supported"
+    );
+    Ok(spec)
+}
+
+/// The URL of the spec's first server, with its variables at their defaults.
+fn spec_server(spec: &Value) -> Option<String> {
+    let server = spec.get("servers")?.get(0)?;
+    let mut url = server.get("url")?.as_str()?.to_string();
+    if let Some(variables) = server.get("variables").and_then(Value::as_object) {
+        for (name, variable) in variables {
+            let default = variable
+                .get("default")
+                .and_then(Value::as_str)
+                .unwrap_or_default();
+            url = url.replace(&format!("{{{name}}}"), default);
+        }
+    }
+    Some(url)
+}
+
+/// The operations of `spec`, as tools prefixed with `api_name`. When `only`
+/// isn't empty, just the operations with those operationIds.
+pub fn operations(spec: &Value, api_name: &str, only: &[String]) -> Vec<Operation> {
+    let Some(paths) = spec.get("paths").and_then(Value::as_object) else {
+        return Vec::new();
+    };
+    let mut operations = Vec::new();
+    for (path, item) in paths {
+        let item = resolve(spec, item);
+        let shared_parameters = item.get("parameters").and_then(Value::as_array);
+        for method in METHODS {
+            let Some(operation) = item.get(*method) else {
+                continue;
+            };
+            let id = operation
+                .get("operationId")
+                .and_then(Value::as_str)
+                .map(str::to_string)
+                .unwrap_or_else(|| format!("{method}_{path}"));
+            if !only.is_empty() && !only.contains(&id) {
+                continue;
+            }
+
+            let mut parameters: Vec<Parameter> = Vec::new();
+            let own_parameters = operation.get("parameters").and_then(Value::as_array);
+            // Operation parameters come first so they override the path's.
+            for parameter in own_parameters
+                .into_iter()
+                .chain(shared_parameters)
+                .flatten()
+            {
+                let Some(parameter) = parameter_from(spec, parameter) else {
+                    continue;
+                };
+                if parameter.name != "body" && !parameters.iter().any(|p| p.name == parameter.name)
+                {
+                    parameters.push(parameter);
+                }
+            }
+
+            let request_body = operation.get("requestBody").map(|body| resolve(spec, body));
+            let body = request_body
+                .and_then(|body| body.get("content")?.as_object())
+                .and_then(|content| {
+                    content
+                        .iter()
+                        .find(|(media_type, _)| media_type.contains("json"))
+                })
+                .map(|(_, media)| {
+                    media
+                        .get("schema")
+                        .map(|schema| inline_refs(spec, schema, 0))
+                        .unwrap_or_else(|| json!({}))
+                });
+
+            operations.push(Operation {
+                name: tool_name(api_name, &id),
+                method: reqwest::Method::from_bytes(method.to_ascii_uppercase().as_bytes())
+                    .expect("valid method"),
+                path: path.clone(),
+                description: describe(operation, method, path),
+                parameters,
+                body_required: request_body
+                    .and_then(|body| body.get("required"))
+                    .and_then(Value::as_bool)
+                    .unwrap_or(false),
+                body,
+            });
+        }
+    }
+    operations
+}
+
+fn parameter_from(spec: &Value, parameter: &Value) -> Option<Parameter> {
+    let parameter = resolve(spec, parameter);
+    let location = match parameter.get("in")?.as_str()? {
+        "path" => Location::Path,
+        "query" => Location::Query,
+        "header" => Location::Header,
+        _ => return None,
+    };
+    let mut schema = parameter
+        .get("schema")
+        .map(|schema| inline_refs(spec, schema, 0))
+        .unwrap_or_else(|| json!({ "type": "string" }));
+    if let (Some(description), Some(schema)) = (
+        parameter.get("description").and_then(Value::as_str),
+        schema.as_object_mut(),
+    ) {
+        schema.insert("description".into(), description.into());
+    }
+    Some(Parameter {
+        name: parameter.get("name")?.as_str()?.to_string(),
+        required: location == Location::Path
+            || parameter
+                .get("required")
+                .and_then(Value::as_bool)
+                .unwrap_or(false),
+        location,
+        schema,
+    })
+}
+
+/// `value`, or what it points to when it's a local `$ref`.
+fn resolve<'a>(spec: &'a Value, value: &'a Value) -> &'a Value {
+    value
+        .get("$ref")
+        .and_then(Value::as_str)
+        .and_then(|reference| reference.strip_prefix('#'))
+        .and_then(|pointer| spec.pointer(pointer))
+        .unwrap_or(value)
+}
+
+/// `value` with local `$ref`s replaced by what they point to.
+fn inline_refs(spec: &Value, value: &Value, depth: usize) -> Value {
+    match value {
+        Value::Object(object) => {
+            if object.contains_key("$ref") {
+                let target = resolve(spec, value);
+                if depth >= MAX_REF_DEPTH || std::ptr::eq(target, value) {
+                    return json!({});
+                }
+                return inline_refs(spec, target, depth + 1);
+            }
+            Value::Object(
+                object
+                    .iter()
+                    .map(|(key, value)| (key.clone(), inline_refs(spec, value, depth)))
+                    .collect(),
+            )
+        }
+        Value::Array(values) => Value::Array(
+            values
+                .iter()
+                .map(|value| inline_refs(spec, value, depth))
+                .collect(),
+        ),
+        other => other.clone(),
+    }
+}
+
+/// `{api}_{operation_id}` in snake case, cut to the length providers accept.
+fn tool_name(api_name: &str, operation_id: &str) -> String {
+    let mut name = format!("{api_name}_");
+    let mut previous = '_';
+    for character in operation_id.chars() {
+        if character.is_ascii_uppercase()
+            && previous.is_ascii_alphanumeric()
+            && !previous.is_ascii_uppercase()
+        {
+            name.push('_');
+        }
+        if character.is_ascii_alphanumeric() {
+            name.push(character.to_ascii_lowercase());
+        } else if !name.ends_with('_') {
+            name.push('_');
+        }
+        previous = character;
+    }
+    let name = name.trim_end_matches('_');
+    name.chars().take(MAX_NAME_CHARS).collect()
+}
+
+fn describe(operation: &Value, method: &str, path: &str) -> String {
+    let text: Vec<&str> = ["summary", "description"]
+        .iter()
+        .filter_map(|key| operation.get(*key).and_then(Value::as_str))
+        .map(str::trim)
+        .filter(|text| !text.is_empty())
+        .collect();
+    let text: String = text
+        .join("\n\n")
+        .chars()
+        .take(MAX_DESCRIPTION_CHARS)
+        .collect();
+    format!("`{} {path}`. {text}", method.to_ascii_uppercase())
+        .trim_end()
+        .to_string()
+}
+
+/// An argument as it goes into a URL or header.
+fn text(value: &Value) -> String {
+    match value {
+        Value::String(text) => text.clone(),
+        other => other.to_string(),
+    }
+}
+
+/// `segment` percent-encoded, so it can't add to or climb out of the path.
+fn encode_segment(segment: &str) -> Result<String, HttpRequestError> {
+    if segment.is_empty() || segment == "." || segment == ".." {
+        return Err(HttpRequestError(format!(
+            "invalid path parameter '{segment}'"
+        )));
+    }
+    let mut encoded = String::new();
+    for byte in segment.bytes() {
+        if byte.is_ascii_alphanumeric() || b"-._~".contains(&byte) {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{byte:02X}"));
+        }
+    }
+    Ok(encoded)
+}
+
+impl ApiOperationTool {
+    /// The request URL for `args`, credentials in the query included.
+    fn url(&self, args: &Map<String, Value>) -> Result<reqwest::Url, HttpRequestError> {
+        let mut path = self.operation.path.clone();
+        let mut query = Vec::new();
+        for parameter in &self.operation.parameters {
+            let value = match args.get(&parameter.name) {
+                Some(Value::Null) | None if parameter.required => {
+                    return Err(HttpRequestError(format!(
+                        "missing required argument '{}'",
+                        parameter.name
+                    )));
+                }
+                Some(Value::Null) | None => continue,
+                Some(value) => value,
+            };
+            match parameter.location {
+                Location::Path => {
+                    let placeholder = format!("{{{}}}", parameter.name);
+                    path = path.replace(&placeholder, &encode_segment(&text(value))?);
+                }
+                Location::Query => match value {
+                    Value::Array(values) => {
+                        query.extend(values.iter().map(|v| (parameter.name.clone(), text(v))));
+                    }
+                    value => query.push((parameter.name.clone(), text(value))),
+                },
+                Location::Header => {}
+            }
+        }
+        if let Some(HttpAuth::Query { name, value }) = &self.api.auth {
+            query.push((name.clone(), value.clone()));
+        }
+
+        let mut url = reqwest::Url::parse(&format!("{}{path}", self.api.base_url))
+            .map_err(|error| HttpRequestError(format!("invalid url: {error}")))?;
+        if !query.is_empty() {
+            url.query_pairs_mut().extend_pairs(query);
+        }
+        Ok(url)
+    }
+}
+
+impl Tool for ApiOperationTool {
+    const NAME: &'static str = "api_operation";
+
+    type Error = HttpRequestError;
+    type Args = Map<String, Value>;
+    type Output = HttpResponseOutput;
+
+    fn name(&self) -> String {
+        self.operation.name.clone()
+    }
+
+    async fn definition(&self, _prompt: String) -> ToolDefinition {
+        ToolDefinition {
+            name: self.operation.name.clone(),
+            description: self.operation.description.clone(),
+            parameters: self.operation.schema(),
+        }
+    }
+
+    async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
+        let url = self.url(&args)?;
+        let host = url.host_str().unwrap_or_default();
+        if !domain_allowed(host, &self.api.allowed_domains) {
+            return Err(HttpRequestError(format!(
+                "{host} isn't allowed for this API"
+            )));
+        }
+        tracing::debug!(
+            tool = %self.operation.name,
+            method = %self.operation.method,
+            path = %url.path(),
+            "api operation tool called"
+        );
+
+        let mut request = self
+            .api
+            .client
+            .request(self.operation.method.clone(), url)
+            .timeout(self.api.timeout);
+        for parameter in &self.operation.parameters {
+            match args.get(&parameter.name).filter(|value| !value.is_null()) {
+                Some(value) if parameter.location == Location::Header => {
+                    request = request.header(&parameter.name, text(value));
+                }
+                _ => {}
+            }
+        }
+        match args.get("body").filter(|body| !body.is_null()) {
+            Some(body) if self.operation.body.is_some() => request = request.json(body),
+            None if self.operation.body_required => {
+                return Err(HttpRequestError("missing required argument 'body'".into()));
+            }
+            _ => {}
+        }
+        request = match &self.api.auth {
+            Some(HttpAuth::Bearer { token }) => request.bearer_auth(token),
+            Some(HttpAuth::Basic { username, password }) => {
+                request.basic_auth(username, Some(password))
+            }
+            Some(HttpAuth::Header { name, value }) => request.header(name, value),
+            Some(HttpAuth::Query { .. }) | None => request,
+        };
+
+        let response = request.send().await?;
+        read_response(response, self.api.max_response_bytes).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SPEC: &str = r##"
+openapi: 3.0.3
+servers:
+  - url: https://{region}.api.example.com/v1
+    variables:
+      region:
+        default: eu
+paths:
+  /users/{userId}:
+    parameters:
+      - $ref: "#/components/parameters/UserId"
+    get:
+      operationId: getUser
+      summary: Fetch a user.
+      parameters:
+        - name: fields
+          in: query
+          schema: { type: array, items: { type: string } }
+      responses:
+        200:
+          description: The user.
+    patch:
+      operationId: updateUser
+      requestBody:
+        required: true
+        content:
+          application/json:
+            schema:
+              $ref: "#/components/schemas/User"
+      responses:
+        200:
+          description: The user.
+components:
+  parameters:
+    UserId:
+      name: userId
+      in: path
+      description: The user's id.
+      schema: { type: string }
+  schemas:
+    User:
+      type: object
+      properties:
+        name: { type: string }
+        manager:
+          $ref: "#/components/schemas/User"
+"##;
+
+    fn tool(operation: Operation) -> ApiOperationTool {
+        ApiOperationTool {
+            api: Arc::new(Api {
+                client: reqwest::Client::new(),
+                base_url: "https://eu.api.example.com/v1".into(),
+                allowed_domains: vec!["eu.api.example.com".into()],
+                auth: Some(HttpAuth::Query {
+                    name: "key".into(),
+                    value: "secret".into(),
+                }),
+                timeout: Duration::from_secs(1),
+                max_response_bytes: 1_000,
+            }),
+            operation,
+        }
+    }
+
+    #[test]
+    fn test_operations_from_spec() {
+        let spec = parse_spec(SPEC).unwrap();
+        assert_eq!(
+            spec_server(&spec).as_deref(),
+            Some("https://eu.api.example.com/v1")
+        );
+
+        let operations = operations(&spec, "crm", &[]);
+        let names: Vec<&str> = operations.iter().map(|o| o.name.as_str()).collect();
+        assert_eq!(names, ["crm_get_user", "crm_update_user"]);
+
+        let get = &operations[0];
+        assert_eq!(get.method, reqwest::Method::GET);
+        assert!(
+            get.description
+                .starts_with("`GET /users/{userId}`. Fetch a user.")
+        );
+        let schema = get.schema();
+        assert_eq!(schema["required"], json!(["userId"]));
+        assert_eq!(
+            schema["properties"]["userId"]["description"],
+            "The user's id."
+        );
+        assert_eq!(schema["properties"]["fields"]["type"], "array");
+
+        let update = &operations[1];
+        let schema = update.schema();
+        assert_eq!(schema["required"], json!(["userId", "body"]));
+        let body = &schema["properties"]["body"];
+        assert_eq!(body["properties"]["name"]["type"], "string");
+        // The recursive reference is cut off instead of expanding forever.
+        assert!(body["properties"]["manager"]["properties"].is_object());
+
+        let only = operations_named(&spec, &["updateUser".to_string()]);
+        assert_eq!(only, ["crm_update_user"]);
+    }
+
+    fn operations_named(spec: &Value, only: &[String]) -> Vec<String> {
+        operations(spec, "crm", only)
+            .into_iter()
+            .map(|o| o.name)
+            .collect()
+    }
+
+    #[test]
+    fn test_url_encodes_arguments() {
+        let spec = parse_spec(SPEC).unwrap();
+        let get = tool(operations(&spec, "crm", &[]).remove(0));
+
+        let args = json!({ "userId": "a b/c", "fields": ["name", "email"] });
+        let url = get.url(args.as_object().unwrap()).unwrap();
+        assert_eq!(
+            url.as_str(),
+            "https://eu.api.example.com/v1/users/a%20b%2Fc?fields=name&fields=email&key=secret"
+        );
+
+        for bad in [
+            json!({}),
+            json!({ "userId": ".." }),
+            json!({ "userId": "" }),
+        ] {
+            assert!(get.url(bad.as_object().unwrap()).is_err());
+        }
+    }
+
+    #[test]
+    fn test_tool_names() {
+        assert_eq!(tool_name("gh", "listRepoIssues"), "gh_list_repo_issues");
+        assert_eq!(tool_name("gh", "repos/get-content"), "gh_repos_get_content");
+        assert_eq!(tool_name("gh", "getHTTPStatus"), "gh_get_httpstatus");
+        assert_eq!(tool_name("gh", "get_/users/{id}"), "gh_get_users_id");
+        assert_eq!(tool_name("gh", &"a".repeat(100)).len(), MAX_NAME_CHARS);
+    }
+}
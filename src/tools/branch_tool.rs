@@ -2,6 +2,7 @@
 
 use crate::BranchId;
 use crate::agent::channel::{ChannelState, spawn_branch_from_state};
+use crate::llm::sampling::{MAX_SAMPLES, SamplingConfig};
 use rig::completion::ToolDefinition;
 use rig::tool::Tool;
 use schemars::JsonSchema;
@@ -30,6 +31,9 @@ pub struct BranchError(String);
 pub struct BranchArgs {
     /// Description of what the branch should think about or investigate.
     pub description: String,
+    /// Answers drawn per step, keeping the consensus. Unset or 1 draws one.
+    #[serde(default)]
+    pub samples: Option<usize>,
 }
 
 /// Output from branch tool.
@@ -60,6 +64,12 @@ impl Tool for BranchTool {
                     "description": {
                         "type": "string",
                         "description": "What the branch should investigate or think about. Be specific about what conclusion you want."
+                    },
+                    "samples": {
+                        "type": "integer",
+                        "minimum": 1,
+                        "maximum": MAX_SAMPLES,
+                        "description": "Have the branch answer each step this many times and keep the consensus. Only for questions where being right matters more than speed or cost: every sample is paid for."
                     }
                 },
                 "required": ["description"]
@@ -68,7 +78,11 @@ impl Tool for BranchTool {
     }
 
     async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
-        let branch_id = spawn_branch_from_state(&self.state, &args.description)
+        let sampling = args
+            .samples
+            .filter(|samples| *samples > 1)
+            .map(SamplingConfig::with_samples);
+        let branch_id = spawn_branch_from_state(&self.state, &args.description, sampling)
             .await
             .map_err(|e| BranchError(format!("{e}")))?;
 
@@ -5,6 +5,8 @@
 //! ref system for LLM-friendly element addressing.
 
 use crate::config::BrowserConfig;
+use crate::storage::ArtifactStore;
+use crate::tools::content::{ContentSink, ToolContent};
 
 use chromiumoxide::browser::{Browser, BrowserConfig as ChromeConfig};
 use chromiumoxide::page::ScreenshotParams;
@@ -21,7 +23,6 @@ use rig::tool::Tool;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use std::path::PathBuf;
 use std::sync::Arc;
 use tokio::sync::Mutex;
 use tokio::task::JoinHandle;
@@ -31,7 +32,11 @@ use tokio::task::JoinHandle;
 pub struct BrowserTool {
     state: Arc<Mutex<BrowserState>>,
     config: BrowserConfig,
-    screenshot_dir: PathBuf,
+    screenshots: ArtifactStore,
+    /// Where shared screenshots go. Unset outside workers.
+    sink: Option<ContentSink>,
+    /// Proxy Chrome browses through, when the tool has a network policy.
+    proxy: Option<String>,
 }
 
 /// Internal browser state managed across tool invocations within a single worker.
@@ -73,7 +78,7 @@ struct ElementRef {
 }
 
 impl BrowserTool {
-    pub fn new(config: BrowserConfig, screenshot_dir: PathBuf) -> Self {
+    pub fn new(config: BrowserConfig, screenshots: ArtifactStore) -> Self {
         Self {
             state: Arc::new(Mutex::new(BrowserState {
                 browser: None,
@@ -84,9 +89,23 @@ impl BrowserTool {
                 next_ref: 0,
             })),
             config,
-            screenshot_dir,
+            screenshots,
+            sink: None,
+            proxy: None,
         }
     }
+
+    /// Let screenshots be shared with the worker's channel.
+    pub fn with_sink(mut self, sink: ContentSink) -> Self {
+        self.sink = Some(sink);
+        self
+    }
+
+    /// Browse through `proxy`, loopback addresses included.
+    pub fn with_proxy(mut self, proxy: Option<String>) -> Self {
+        self.proxy = proxy;
+        self
+    }
 }
 
 /// Error type for browser tool operations.
@@ -172,6 +191,9 @@ pub struct BrowserArgs {
     /// Whether to take a full-page screenshot.
     #[serde(default)]
     pub full_page: bool,
+    /// Whether to also send the screenshot to the user.
+    #[serde(default)]
+    pub share: bool,
     /// JavaScript expression to evaluate.
     pub script: Option<String>,
 }
@@ -198,6 +220,9 @@ pub struct BrowserOutput {
     /// Path to saved screenshot file.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub screenshot_path: Option<String>,
+    /// Link to the screenshot's copy in object storage, when configured.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub screenshot_url: Option<String>,
     /// JavaScript evaluation result.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub eval_result: Option<serde_json::Value>,
@@ -216,6 +241,7 @@ impl BrowserOutput {
             elements: None,
             tabs: None,
             screenshot_path: None,
+            screenshot_url: None,
             eval_result: None,
             content: None,
         }
@@ -332,6 +358,11 @@ impl Tool for BrowserTool {
                         "default": false,
                         "description": "Take full-page screenshot instead of viewport only"
                     },
+                    "share": {
+                        "type": "boolean",
+                        "default": false,
+                        "description": "Also send the screenshot to the user in the conversation"
+                    },
                     "script": {
                         "type": "string",
                         "description": "JavaScript expression to evaluate (requires evaluate_enabled in config)"
@@ -356,7 +387,7 @@ impl Tool for BrowserTool {
                     .await
             }
             BrowserAction::Screenshot => {
-                self.handle_screenshot(args.element_ref, args.full_page)
+                self.handle_screenshot(args.element_ref, args.full_page, args.share)
                     .await
             }
             BrowserAction::Evaluate => self.handle_evaluate(args.script).await,
@@ -384,6 +415,12 @@ impl BrowserTool {
             builder = builder.chrome_executable(path);
         }
 
+        if let Some(proxy) = &self.proxy {
+            builder = builder
+                .arg(format!("--proxy-server={proxy}"))
+                .arg("--proxy-bypass-list=<-loopback>");
+        }
+
         let chrome_config = builder.build().map_err(|error| {
             BrowserError::new(format!("failed to build browser config: {error}"))
         })?;
@@ -461,6 +498,7 @@ impl BrowserTool {
             tabs: None,
             elements: None,
             screenshot_path: None,
+            screenshot_url: None,
             eval_result: None,
             content: None,
             success: true,
@@ -496,6 +534,7 @@ impl BrowserTool {
             elements: None,
             tabs: Some(tabs),
             screenshot_path: None,
+            screenshot_url: None,
             eval_result: None,
             content: None,
         })
@@ -635,6 +674,7 @@ impl BrowserTool {
             elements: Some(elements),
             tabs: None,
             screenshot_path: None,
+            screenshot_url: None,
             eval_result: None,
             content: None,
         })
@@ -726,7 +766,18 @@ impl BrowserTool {
         &self,
         element_ref: Option<String>,
         full_page: bool,
+        share: bool,
     ) -> Result<BrowserOutput, BrowserError> {
+        let sink = match (share, &self.sink) {
+            (false, _) => None,
+            (true, Some(sink)) => Some(sink),
+            (true, None) => {
+                return Err(BrowserError::new(
+                    "screenshots can only be shared by workers in a conversation",
+                ));
+            }
+        };
+
         let state = self.state.lock().await;
         let page = self.require_active_page(&state)?;
 
@@ -746,36 +797,41 @@ impl BrowserTool {
                 .map_err(|error| BrowserError::new(format!("screenshot failed: {error}")))?
         };
 
-        // Save to disk
         let filename = format!(
             "screenshot_{}.png",
             chrono::Utc::now().format("%Y%m%d_%H%M%S_%3f")
         );
-        let filepath = self.screenshot_dir.join(&filename);
-
-        tokio::fs::create_dir_all(&self.screenshot_dir)
-            .await
-            .map_err(|error| {
-                BrowserError::new(format!("failed to create screenshot dir: {error}"))
-            })?;
-
-        tokio::fs::write(&filepath, &screenshot_data)
+        let saved = self
+            .screenshots
+            .save(&filename, &screenshot_data, "image/png")
             .await
-            .map_err(|error| BrowserError::new(format!("failed to save screenshot: {error}")))?;
+            .map_err(|error| BrowserError::new(format!("failed to save screenshot: {error:#}")))?;
 
-        let path_str = filepath.to_string_lossy().to_string();
+        let path_str = saved.path.to_string_lossy().to_string();
         let size_kb = screenshot_data.len() / 1024;
 
-        tracing::debug!(path = %path_str, size_kb, "screenshot saved");
+        tracing::debug!(path = %path_str, url = ?saved.url, size_kb, "screenshot saved");
+
+        let mut message = format!("Screenshot saved ({size_kb}KB)");
+        if let Some(sink) = sink {
+            let shared = sink.deliver(ToolContent::Image {
+                filename,
+                mime_type: "image/png".into(),
+                data: screenshot_data,
+                caption: None,
+            });
+            message = format!("{message}. {shared}");
+        }
 
         Ok(BrowserOutput {
             success: true,
-            message: format!("Screenshot saved ({size_kb}KB)"),
+            message,
             title: None,
             url: None,
             elements: None,
             tabs: None,
             screenshot_path: Some(path_str),
+            screenshot_url: saved.url,
             eval_result: None,
             content: None,
         })
@@ -810,6 +866,7 @@ impl BrowserTool {
             elements: None,
             tabs: None,
             screenshot_path: None,
+            screenshot_url: None,
             eval_result: value,
             content: None,
         })
@@ -846,6 +903,7 @@ impl BrowserTool {
             elements: None,
             tabs: None,
             screenshot_path: None,
+            screenshot_url: None,
             eval_result: None,
             content: Some(truncated),
         })
@@ -0,0 +1,96 @@
+//! Calc tool for exact arithmetic, unit conversion and date math.
+//!
+//! Stateless wrapper around [`crate::calc`], so agents work numbers out
+//! instead of estimating them in their reply.
+
+use crate::calc::Answer;
+use rig::completion::ToolDefinition;
+use rig::tool::Tool;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// Longest expression the tool evaluates, in characters.
+const MAX_EXPRESSION_CHARS: usize = 1000;
+
+/// Tool for evaluating calculator expressions.
+#[derive(Debug, Clone, Default)]
+pub struct CalcTool;
+
+impl CalcTool {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+/// Error type for calc tool.
+#[derive(Debug, thiserror::Error)]
+#[error("Calc failed: {0}")]
+pub struct CalcError(String);
+
+/// Arguments for calc tool.
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct CalcArgs {
+    /// The expression to evaluate, e.g. "5 mi to km" or "today + 90 days".
+    pub expression: String,
+}
+
+/// Output from calc tool.
+#[derive(Debug, Serialize)]
+pub struct CalcOutput {
+    pub expression: String,
+    /// The result as it should be quoted, with its unit or weekday.
+    pub result: String,
+    /// The number or `YYYY-MM-DD` date on its own.
+    pub value: String,
+    /// The result's unit, if it has one.
+    pub unit: Option<String>,
+}
+
+impl Tool for CalcTool {
+    const NAME: &'static str = "calc";
+
+    type Error = CalcError;
+    type Args = CalcArgs;
+    type Output = CalcOutput;
+
+    async fn definition(&self, _prompt: String) -> ToolDefinition {
+        ToolDefinition {
+            name: Self::NAME.to_string(),
+            description: crate::prompts::text::get("tools/calc").to_string(),
+            parameters: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "expression": {
+                        "type": "string",
+                        "description": "The expression, e.g. \"1299 * 0.85\", \"20% of 340\", \"5 mi to km\", \"60 km/h * 45 min\", \"2026-12-25 - today\"."
+                    }
+                },
+                "required": ["expression"]
+            }),
+        }
+    }
+
+    async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
+        let expression = args.expression.trim().to_string();
+        if expression.chars().count() > MAX_EXPRESSION_CHARS {
+            return Err(CalcError(format!(
+                "expressions can be at most {MAX_EXPRESSION_CHARS} characters"
+            )));
+        }
+
+        let answer =
+            crate::calc::evaluate(&expression).map_err(|error| CalcError(error.to_string()))?;
+        tracing::debug!(%expression, %answer, "calc tool called");
+
+        let (value, unit) = match &answer {
+            Answer::Number { value, unit } => (value.to_string(), unit.clone()),
+            Answer::Date(date) => (date.format("%Y-%m-%d").to_string(), None),
+        };
+        Ok(CalcOutput {
+            expression,
+            result: answer.to_string(),
+            value,
+            unit,
+        })
+    }
+}
@@ -0,0 +1,551 @@
+//! Computer tool for driving an X11 desktop (task workers only).
+//!
+//! Implements Anthropic's computer-use action set — screenshots, mouse and
+//! keyboard — as an ordinary tool, so any model can use it. Input goes
+//! through `xdotool` and screenshots through ImageMagick's `import`.
+//! Screenshots are scaled down to at most 1280x800 and the model's
+//! coordinates are mapped back to the display.
+//!
+//! Confirmation happens in the worker's hook like any other gated tool (see
+//! [`crate::approval`]). The tool itself refuses to touch excluded
+//! applications: no screenshot while one is on screen, no input while one is
+//! focused or under the pointer.
+
+use crate::config::ComputerUseConfig;
+
+use base64::Engine as _;
+use rig::completion::ToolDefinition;
+use rig::tool::Tool;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::process::Command;
+
+/// Largest screenshot sent to the model. Bigger displays are scaled down.
+const MAX_SCREENSHOT_WIDTH: u32 = 1280;
+const MAX_SCREENSHOT_HEIGHT: u32 = 800;
+
+/// Longest `wait` the model can ask for.
+const MAX_WAIT_SECS: f64 = 60.0;
+
+/// How long a single `xdotool` or `import` run may take.
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Tool for seeing and controlling the desktop.
+#[derive(Debug, Clone)]
+pub struct ComputerTool {
+    config: ComputerUseConfig,
+}
+
+impl ComputerTool {
+    pub fn new(config: ComputerUseConfig) -> Self {
+        Self { config }
+    }
+
+    /// Run `program` against the configured display and return its stdout.
+    async fn run(&self, program: &str, args: &[&str]) -> Result<Vec<u8>, ComputerError> {
+        let mut command = Command::new(program);
+        command
+            .args(args)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+        if let Some(display) = &self.config.display {
+            command.env("DISPLAY", display);
+        }
+
+        let output = tokio::time::timeout(COMMAND_TIMEOUT, command.output())
+            .await
+            .map_err(|_| ComputerError::new(format!("{program} timed out")))?
+            .map_err(|error| ComputerError::new(format!("failed to run {program}: {error}")))?;
+        if !output.status.success() {
+            return Err(ComputerError::new(format!(
+                "{program} {} failed: {}",
+                args.first().unwrap_or(&""),
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+        Ok(output.stdout)
+    }
+
+    async fn xdotool(&self, args: &[&str]) -> Result<String, ComputerError> {
+        let stdout = self.run("xdotool", args).await?;
+        Ok(String::from_utf8_lossy(&stdout).trim().to_string())
+    }
+
+    async fn scale(&self) -> Result<Scale, ComputerError> {
+        let geometry = self.xdotool(&["getdisplaygeometry"]).await?;
+        let mut dimensions = geometry.split_whitespace().map(str::parse::<u32>);
+        match (dimensions.next(), dimensions.next()) {
+            (Some(Ok(width)), Some(Ok(height))) if width > 0 && height > 0 => {
+                Ok(Scale::for_display(width, height))
+            }
+            _ => Err(ComputerError::new(format!(
+                "unexpected display geometry '{geometry}'"
+            ))),
+        }
+    }
+
+    /// Fail if `window` belongs to an excluded application.
+    async fn check_window(&self, window: &str) -> Result<(), ComputerError> {
+        // Either lookup fails for windows without the property; that's fine.
+        let class = self
+            .xdotool(&["getwindowclassname", window])
+            .await
+            .unwrap_or_default();
+        let title = self
+            .xdotool(&["getwindowname", window])
+            .await
+            .unwrap_or_default();
+        match excluded_match(&self.config.excluded_applications, &class, &title) {
+            Some(application) => Err(ComputerError::new(format!(
+                "refusing to act on a window of an excluded application ({application})"
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    /// Fail if the focused window belongs to an excluded application.
+    async fn check_active_window(&self) -> Result<(), ComputerError> {
+        // No window has focus on an empty desktop.
+        match self.xdotool(&["getactivewindow"]).await {
+            Ok(window) => self.check_window(&window).await,
+            Err(_) => Ok(()),
+        }
+    }
+
+    /// Move the pointer to `coordinate` (model space), then fail if the
+    /// window under it or the focused one is excluded.
+    async fn move_to(&self, scale: Scale, coordinate: [u32; 2]) -> Result<(), ComputerError> {
+        let (x, y) = scale.to_display(coordinate)?;
+        self.xdotool(&["mousemove", "--sync", &x.to_string(), &y.to_string()])
+            .await?;
+        self.check_pointer_target().await
+    }
+
+    async fn check_pointer_target(&self) -> Result<(), ComputerError> {
+        let location = self.xdotool(&["getmouselocation", "--shell"]).await?;
+        if let Some(window) = location
+            .lines()
+            .find_map(|line| line.strip_prefix("WINDOW="))
+        {
+            self.check_window(window).await?;
+        }
+        self.check_active_window().await
+    }
+
+    /// Fail if any visible window belongs to an excluded application.
+    async fn check_screen(&self) -> Result<(), ComputerError> {
+        let excluded: Vec<String> = self
+            .config
+            .excluded_applications
+            .iter()
+            .filter(|application| !application.is_empty())
+            .map(|application| regex::escape(application))
+            .collect();
+        if excluded.is_empty() {
+            return Ok(());
+        }
+        let pattern = format!("({})", excluded.join("|"));
+        // `search` exits non-zero when nothing matches.
+        let Ok(windows) = self
+            .xdotool(&[
+                "search",
+                "--onlyvisible",
+                "--class",
+                "--classname",
+                "--name",
+                &pattern,
+            ])
+            .await
+        else {
+            return Ok(());
+        };
+        if windows.is_empty() {
+            return Ok(());
+        }
+        Err(ComputerError::new(
+            "refusing to take a screenshot while an excluded application is on screen",
+        ))
+    }
+
+    async fn screenshot(&self, scale: Scale) -> Result<ComputerOutput, ComputerError> {
+        self.check_screen().await?;
+        let (width, height) = scale.screenshot_size();
+        let resize = format!("{width}x{height}!");
+        let png = self
+            .run("import", &["-window", "root", "-resize", &resize, "png:-"])
+            .await?;
+        Ok(ComputerOutput::Image {
+            kind: "image",
+            data: base64::engine::general_purpose::STANDARD.encode(png),
+            mime_type: "image/png",
+        })
+    }
+
+    async fn cursor_position(&self, scale: Scale) -> Result<[u32; 2], ComputerError> {
+        let location = self.xdotool(&["getmouselocation", "--shell"]).await?;
+        let field = |name: &str| {
+            location
+                .lines()
+                .find_map(|line| line.strip_prefix(name)?.strip_prefix('='))
+                .and_then(|value| value.parse::<u32>().ok())
+        };
+        match (field("X"), field("Y")) {
+            (Some(x), Some(y)) => Ok(scale.to_model(x, y)),
+            _ => Err(ComputerError::new("couldn't read the cursor position")),
+        }
+    }
+
+    async fn perform(&self, args: ComputerArgs) -> Result<ComputerOutput, ComputerError> {
+        let scale = self.scale().await?;
+        let action = args.action;
+
+        match action {
+            ComputerAction::Screenshot => return self.screenshot(scale).await,
+            ComputerAction::CursorPosition => {}
+            ComputerAction::Wait => {
+                let seconds = args.duration.unwrap_or(1.0).clamp(0.0, MAX_WAIT_SECS);
+                tokio::time::sleep(Duration::from_secs_f64(seconds)).await;
+            }
+            ComputerAction::MouseMove => {
+                self.move_to(scale, require(args.coordinate, "coordinate", action)?)
+                    .await?;
+            }
+            ComputerAction::LeftClick
+            | ComputerAction::RightClick
+            | ComputerAction::MiddleClick
+            | ComputerAction::DoubleClick
+            | ComputerAction::TripleClick => {
+                match args.coordinate {
+                    Some(coordinate) => self.move_to(scale, coordinate).await?,
+                    None => self.check_pointer_target().await?,
+                }
+                let (button, repeat) = match action {
+                    ComputerAction::RightClick => ("3", "1"),
+                    ComputerAction::MiddleClick => ("2", "1"),
+                    ComputerAction::DoubleClick => ("1", "2"),
+                    ComputerAction::TripleClick => ("1", "3"),
+                    _ => ("1", "1"),
+                };
+                self.xdotool(&["click", "--repeat", repeat, button]).await?;
+            }
+            ComputerAction::LeftClickDrag => {
+                let start = require(args.start_coordinate, "start_coordinate", action)?;
+                let end = require(args.coordinate, "coordinate", action)?;
+                self.move_to(scale, start).await?;
+                self.xdotool(&["mousedown", "1"]).await?;
+                let dragged = self.move_to(scale, end).await;
+                // Always let go, even when the drop target is refused.
+                self.xdotool(&["mouseup", "1"]).await?;
+                dragged?;
+            }
+            ComputerAction::Type => {
+                let text = require(args.text, "text", action)?;
+                self.check_active_window().await?;
+                self.xdotool(&["type", "--delay", "12", "--", &text])
+                    .await?;
+            }
+            ComputerAction::Key => {
+                let text = require(args.text, "text", action)?;
+                self.check_active_window().await?;
+                let mut keys = vec!["key", "--"];
+                keys.extend(text.split_whitespace());
+                self.xdotool(&keys).await?;
+            }
+            ComputerAction::Scroll => {
+                let direction = require(args.scroll_direction, "scroll_direction", action)?;
+                match args.coordinate {
+                    Some(coordinate) => self.move_to(scale, coordinate).await?,
+                    None => self.check_pointer_target().await?,
+                }
+                let amount = args.scroll_amount.unwrap_or(3).max(1).to_string();
+                self.xdotool(&["click", "--repeat", &amount, direction.button()])
+                    .await?;
+            }
+        }
+
+        Ok(ComputerOutput::Done {
+            action: action.as_str().to_string(),
+            cursor: self.cursor_position(scale).await?,
+        })
+    }
+}
+
+fn require<T>(value: Option<T>, field: &str, action: ComputerAction) -> Result<T, ComputerError> {
+    value
+        .ok_or_else(|| ComputerError::new(format!("'{field}' is required for {}", action.as_str())))
+}
+
+/// The entry of `excluded` found in a window's class or title, ignoring case.
+fn excluded_match<'a>(excluded: &'a [String], class: &str, title: &str) -> Option<&'a str> {
+    let class = class.to_lowercase();
+    let title = title.to_lowercase();
+    excluded
+        .iter()
+        .find(|application| {
+            let application = application.to_lowercase();
+            !application.is_empty()
+                && (class.contains(&application) || title.contains(&application))
+        })
+        .map(String::as_str)
+}
+
+/// Maps between display pixels and the scaled screenshot the model sees.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Scale {
+    width: u32,
+    height: u32,
+    factor: f64,
+}
+
+impl Scale {
+    fn for_display(width: u32, height: u32) -> Self {
+        let factor = (f64::from(MAX_SCREENSHOT_WIDTH) / f64::from(width))
+            .min(f64::from(MAX_SCREENSHOT_HEIGHT) / f64::from(height))
+            .min(1.0);
+        Self {
+            width,
+            height,
+            factor,
+        }
+    }
+
+    fn screenshot_size(self) -> (u32, u32) {
+        (
+            (f64::from(self.width) * self.factor).round() as u32,
+            (f64::from(self.height) * self.factor).round() as u32,
+        )
+    }
+
+    fn to_display(self, [x, y]: [u32; 2]) -> Result<(u32, u32), ComputerError> {
+        let (width, height) = self.screenshot_size();
+        if x >= width || y >= height {
+            return Err(ComputerError::new(format!(
+                "({x}, {y}) is outside the {width}x{height} screen"
+            )));
+        }
+        let display = |value: u32, limit: u32| {
+            ((f64::from(value) / self.factor).round() as u32).min(limit - 1)
+        };
+        Ok((display(x, self.width), display(y, self.height)))
+    }
+
+    fn to_model(self, x: u32, y: u32) -> [u32; 2] {
+        [
+            (f64::from(x) * self.factor).round() as u32,
+            (f64::from(y) * self.factor).round() as u32,
+        ]
+    }
+}
+
+/// Error type for computer tool.
+#[derive(Debug, thiserror::Error)]
+#[error("Computer action failed: {message}")]
+pub struct ComputerError {
+    message: String,
+}
+
+impl ComputerError {
+    fn new(message: impl Into<String>) -> Self {
+        Self {
+            message: message.into(),
+        }
+    }
+}
+
+/// The action to perform.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ComputerAction {
+    Screenshot,
+    LeftClick,
+    RightClick,
+    MiddleClick,
+    DoubleClick,
+    TripleClick,
+    MouseMove,
+    LeftClickDrag,
+    Type,
+    Key,
+    Scroll,
+    CursorPosition,
+    Wait,
+}
+
+impl ComputerAction {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Screenshot => "screenshot",
+            Self::LeftClick => "left_click",
+            Self::RightClick => "right_click",
+            Self::MiddleClick => "middle_click",
+            Self::DoubleClick => "double_click",
+            Self::TripleClick => "triple_click",
+            Self::MouseMove => "mouse_move",
+            Self::LeftClickDrag => "left_click_drag",
+            Self::Type => "type",
+            Self::Key => "key",
+            Self::Scroll => "scroll",
+            Self::CursorPosition => "cursor_position",
+            Self::Wait => "wait",
+        }
+    }
+}
+
+/// Scroll direction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ScrollDirection {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+impl ScrollDirection {
+    /// The X pointer button that scrolls this way.
+    fn button(self) -> &'static str {
+        match self {
+            Self::Up => "4",
+            Self::Down => "5",
+            Self::Left => "6",
+            Self::Right => "7",
+        }
+    }
+}
+
+/// Arguments for computer tool.
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct ComputerArgs {
+    /// The action to perform.
+    pub action: ComputerAction,
+    /// Target `[x, y]` in screenshot pixels.
+    pub coordinate: Option<[u32; 2]>,
+    /// Where a `left_click_drag` starts.
+    pub start_coordinate: Option<[u32; 2]>,
+    /// Text to type, or keys to press for `key` (xdotool syntax, e.g. "ctrl+s").
+    pub text: Option<String>,
+    pub scroll_direction: Option<ScrollDirection>,
+    /// Scroll steps (default: 3).
+    pub scroll_amount: Option<u32>,
+    /// Seconds to `wait` (default: 1).
+    pub duration: Option<f64>,
+}
+
+/// Output from computer tool.
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+pub enum ComputerOutput {
+    /// A screenshot, shaped so Rig hands it to the model as an image.
+    Image {
+        #[serde(rename = "type")]
+        kind: &'static str,
+        data: String,
+        #[serde(rename = "mimeType")]
+        mime_type: &'static str,
+    },
+    /// An action that ran, and where the cursor ended up.
+    Done { action: String, cursor: [u32; 2] },
+}
+
+impl Tool for ComputerTool {
+    const NAME: &'static str = "computer";
+
+    type Error = ComputerError;
+    type Args = ComputerArgs;
+    type Output = ComputerOutput;
+
+    async fn definition(&self, _prompt: String) -> ToolDefinition {
+        ToolDefinition {
+            name: Self::NAME.to_string(),
+            description: crate::prompts::text::get("tools/computer").to_string(),
+            parameters: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "action": {
+                        "type": "string",
+                        "enum": ["screenshot", "left_click", "right_click", "middle_click",
+                                 "double_click", "triple_click", "mouse_move", "left_click_drag",
+                                 "type", "key", "scroll", "cursor_position", "wait"],
+                        "description": "The action to perform"
+                    },
+                    "coordinate": {
+                        "type": "array",
+                        "items": { "type": "integer", "minimum": 0 },
+                        "minItems": 2,
+                        "maxItems": 2,
+                        "description": "[x, y] in screenshot pixels. Required for mouse_move and left_click_drag (the end point); optional for clicks and scroll, which otherwise use the current position"
+                    },
+                    "start_coordinate": {
+                        "type": "array",
+                        "items": { "type": "integer", "minimum": 0 },
+                        "minItems": 2,
+                        "maxItems": 2,
+                        "description": "[x, y] where left_click_drag starts"
+                    },
+                    "text": {
+                        "type": "string",
+                        "description": "Text for type, or space-separated keys for key in xdotool syntax (e.g. \"ctrl+s\", \"Return\")"
+                    },
+                    "scroll_direction": {
+                        "type": "string",
+                        "enum": ["up", "down", "left", "right"],
+                        "description": "Direction for scroll"
+                    },
+                    "scroll_amount": {
+                        "type": "integer",
+                        "minimum": 1,
+                        "default": 3,
+                        "description": "Number of scroll steps"
+                    },
+                    "duration": {
+                        "type": "number",
+                        "minimum": 0,
+                        "maximum": MAX_WAIT_SECS,
+                        "default": 1,
+                        "description": "Seconds to wait"
+                    }
+                },
+                "required": ["action"]
+            }),
+        }
+    }
+
+    async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
+        self.perform(args).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scale_maps_model_coordinates_to_display() {
+        let scale = Scale::for_display(2560, 1440);
+        assert_eq!(scale.screenshot_size(), (1280, 720));
+        assert_eq!(scale.to_display([640, 360]).unwrap(), (1280, 720));
+        assert_eq!(scale.to_model(1280, 720), [640, 360]);
+        assert!(scale.to_display([1280, 0]).is_err());
+
+        let small = Scale::for_display(1024, 768);
+        assert_eq!(small.screenshot_size(), (1024, 768));
+        assert_eq!(small.to_display([1023, 767]).unwrap(), (1023, 767));
+    }
+
+    #[test]
+    fn test_excluded_match_ignores_case() {
+        let excluded = vec!["KeePassXC".to_string(), String::new()];
+        assert_eq!(
+            excluded_match(&excluded, "keepassxc", "Passwords.kdbx"),
+            Some("KeePassXC")
+        );
+        assert_eq!(
+            excluded_match(&excluded, "firefox", "Mozilla Firefox"),
+            None
+        );
+    }
+}
@@ -0,0 +1,323 @@
+//! Typed tool results: images, tables and files that reach the user as well
+//! as the model.
+//!
+//! A tool holding rich output returns a [`ToolContent`] through a
+//! [`ContentSink`] instead of flattening it into its result string. The
+//! channel delivers the content in the form its platform takes (an image
+//! upload, a CSV attachment), and the tool hands the model
+//! [`ToolContent::model_text`] in its place: a description of what the user
+//! got, with tables rendered as markdown so the model can still reason about
+//! the data.
+
+use crate::{AgentId, ChannelId, OutboundResponse, ProcessEvent, ProcessId};
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+
+/// Largest attachment we send: 25 MB, Discord's limit for non-boosted servers.
+pub const MAX_CONTENT_BYTES: u64 = 25 * 1024 * 1024;
+
+/// Table rows the model reads before the rest are summarized as a count.
+const MODEL_TABLE_ROWS: usize = 50;
+
+/// Table rows previewed in the attachment's caption.
+const PREVIEW_TABLE_ROWS: usize = 5;
+
+/// Rich output from a tool.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ToolContent {
+    Image {
+        filename: String,
+        mime_type: String,
+        #[serde(with = "crate::base64_bytes")]
+        data: Vec<u8>,
+        caption: Option<String>,
+    },
+    /// Rows of cells under named columns, sent to the user as CSV.
+    Table {
+        title: Option<String>,
+        columns: Vec<String>,
+        rows: Vec<Vec<String>>,
+    },
+    File {
+        filename: String,
+        mime_type: String,
+        #[serde(with = "crate::base64_bytes")]
+        data: Vec<u8>,
+        caption: Option<String>,
+    },
+}
+
+impl ToolContent {
+    /// A file, or an image when `mime_type` is one.
+    pub fn from_file(
+        filename: String,
+        mime_type: String,
+        data: Vec<u8>,
+        caption: Option<String>,
+    ) -> Self {
+        if mime_type.starts_with("image/") {
+            Self::Image {
+                filename,
+                mime_type,
+                data,
+                caption,
+            }
+        } else {
+            Self::File {
+                filename,
+                mime_type,
+                data,
+                caption,
+            }
+        }
+    }
+
+    /// What the model reads in place of the content.
+    pub fn model_text(&self) -> String {
+        match self {
+            Self::Image { filename, data, .. } => format!(
+                "The image {filename} ({}) was sent to the user.",
+                format_size(data.len())
+            ),
+            Self::File { filename, data, .. } => format!(
+                "The file {filename} ({}) was sent to the user.",
+                format_size(data.len())
+            ),
+            Self::Table {
+                title,
+                columns,
+                rows,
+            } => {
+                let name = title
+                    .as_deref()
+                    .map(|title| format!(" \"{title}\""))
+                    .unwrap_or_default();
+                format!(
+                    "The table{name} was sent to the user as a CSV attachment:\n\n{}",
+                    markdown_table(columns, rows, MODEL_TABLE_ROWS)
+                )
+            }
+        }
+    }
+
+    /// The message that delivers the content to a channel.
+    pub fn into_response(self) -> OutboundResponse {
+        match self {
+            Self::Image {
+                filename,
+                mime_type,
+                data,
+                caption,
+            }
+            | Self::File {
+                filename,
+                mime_type,
+                data,
+                caption,
+            } => OutboundResponse::File {
+                filename,
+                data,
+                mime_type,
+                caption,
+            },
+            Self::Table {
+                title,
+                columns,
+                rows,
+            } => {
+                let preview = markdown_table(&columns, &rows, PREVIEW_TABLE_ROWS);
+                let caption = match &title {
+                    Some(title) => format!("**{title}**\n{preview}"),
+                    None => preview,
+                };
+                OutboundResponse::File {
+                    filename: format!("{}.csv", table_filename(title.as_deref())),
+                    data: csv(&columns, &rows).into_bytes(),
+                    mime_type: "text/csv".into(),
+                    caption: Some(caption),
+                }
+            }
+        }
+    }
+}
+
+/// Sends a process's [`ToolContent`] to the channel it works for.
+#[derive(Debug, Clone)]
+pub struct ContentSink {
+    agent_id: AgentId,
+    process_id: ProcessId,
+    channel_id: Option<ChannelId>,
+    event_tx: broadcast::Sender<ProcessEvent>,
+}
+
+impl ContentSink {
+    pub fn new(
+        agent_id: AgentId,
+        process_id: ProcessId,
+        channel_id: Option<ChannelId>,
+        event_tx: broadcast::Sender<ProcessEvent>,
+    ) -> Self {
+        Self {
+            agent_id,
+            process_id,
+            channel_id,
+            event_tx,
+        }
+    }
+
+    /// Whether there's a channel to deliver to.
+    pub fn has_channel(&self) -> bool {
+        self.channel_id.is_some()
+    }
+
+    /// Deliver `content` to the channel and return the text the tool should
+    /// give the model instead.
+    pub fn deliver(&self, content: ToolContent) -> String {
+        let Some(channel_id) = &self.channel_id else {
+            return "There is no conversation to send this to, so the user didn't get it.".into();
+        };
+        let text = content.model_text();
+        self.event_tx
+            .send(ProcessEvent::ToolContent {
+                agent_id: self.agent_id.clone(),
+                process_id: self.process_id.clone(),
+                channel_id: channel_id.clone(),
+                content,
+            })
+            .ok();
+        text
+    }
+}
+
+fn format_size(bytes: usize) -> String {
+    if bytes < 1024 {
+        format!("{bytes} bytes")
+    } else if bytes < 1024 * 1024 {
+        format!("{}KB", bytes / 1024)
+    } else {
+        format!("{:.1}MB", bytes as f64 / (1024.0 * 1024.0))
+    }
+}
+
+/// Render up to `max_rows` rows as a markdown table, noting any left out.
+fn markdown_table(columns: &[String], rows: &[Vec<String>], max_rows: usize) -> String {
+    let cell = |text: &str| text.replace('|', "\\|").replace('\n', " ");
+    let line = |cells: &[String]| {
+        let cells: Vec<String> = cells.iter().map(|text| cell(text)).collect();
+        format!("| {} |", cells.join(" | "))
+    };
+
+    let mut lines = vec![
+        line(columns),
+        format!("|{}", " --- |".repeat(columns.len().max(1))),
+    ];
+    lines.extend(rows.iter().take(max_rows).map(|row| line(row)));
+    if rows.len() > max_rows {
+        lines.push(format!("({} more rows)", rows.len() - max_rows));
+    }
+    lines.join("\n")
+}
+
+fn csv(columns: &[String], rows: &[Vec<String>]) -> String {
+    let field = |text: &String| {
+        if text.contains([',', '"', '\n', '\r']) {
+            format!("\"{}\"", text.replace('"', "\"\""))
+        } else {
+            text.clone()
+        }
+    };
+    std::iter::once(columns)
+        .chain(rows.iter().map(Vec::as_slice))
+        .map(|cells| cells.iter().map(field).collect::<Vec<_>>().join(",") + "\r\n")
+        .collect()
+}
+
+/// A filename stem for a table, from its title when it has one.
+fn table_filename(title: Option<&str>) -> String {
+    let stem = title
+        .unwrap_or_default()
+        .to_lowercase()
+        .split(|character: char| !character.is_alphanumeric())
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("-");
+    if stem.is_empty() {
+        "table".into()
+    } else {
+        stem
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(cells: &[&str]) -> Vec<String> {
+        cells.iter().map(|cell| cell.to_string()).collect()
+    }
+
+    #[test]
+    fn test_table_becomes_csv_attachment() {
+        let content = ToolContent::Table {
+            title: Some("Q3 Revenue".into()),
+            columns: strings(&["region", "note"]),
+            rows: vec![strings(&["EU", "up 4%, mostly \"DACH\""])],
+        };
+
+        let text = content.model_text();
+        assert!(text.contains("| region | note |"));
+        assert!(text.contains("| EU | up 4%, mostly \"DACH\" |"));
+
+        let OutboundResponse::File {
+            filename,
+            data,
+            mime_type,
+            caption,
+        } = content.into_response()
+        else {
+            panic!("expected a file");
+        };
+        assert_eq!(filename, "q3-revenue.csv");
+        assert_eq!(mime_type, "text/csv");
+        assert_eq!(
+            String::from_utf8(data).unwrap(),
+            "region,note\r\nEU,\"up 4%, mostly \"\"DACH\"\"\"\r\n"
+        );
+        assert!(
+            caption
+                .unwrap()
+                .starts_with("**Q3 Revenue**\n| region | note |")
+        );
+    }
+
+    #[test]
+    fn test_model_text_caps_table_rows() {
+        let rows: Vec<Vec<String>> = (0..MODEL_TABLE_ROWS + 3)
+            .map(|index| vec![index.to_string()])
+            .collect();
+        let content = ToolContent::Table {
+            title: None,
+            columns: strings(&["n"]),
+            rows,
+        };
+
+        let text = content.model_text();
+        assert!(text.contains(&format!("| {} |", MODEL_TABLE_ROWS - 1)));
+        assert!(!text.contains(&format!("| {} |", MODEL_TABLE_ROWS)));
+        assert!(text.ends_with("(3 more rows)"));
+    }
+
+    #[test]
+    fn test_from_file_detects_images() {
+        let image = ToolContent::from_file("a.png".into(), "image/png".into(), vec![0; 2048], None);
+        assert!(matches!(image, ToolContent::Image { .. }));
+        assert_eq!(
+            image.model_text(),
+            "The image a.png (2KB) was sent to the user."
+        );
+
+        let file = ToolContent::from_file("a.pdf".into(), "application/pdf".into(), vec![], None);
+        assert!(matches!(file, ToolContent::File { .. }));
+    }
+}
@@ -0,0 +1,122 @@
+//! Escalate tool for flagging a conversation for human review (channel only).
+
+use crate::OutboundResponse;
+use crate::agent::channel::ChannelState;
+use crate::conversation::escalations::{EscalationStore, FLAGGED_BY_AGENT};
+use crate::tools::SkipFlag;
+use rig::completion::ToolDefinition;
+use rig::tool::Tool;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::Ordering;
+use tokio::sync::mpsc;
+
+/// Tool for flagging the conversation for a moderator.
+///
+/// Stores the escalation, which pauses the channel's replies until a
+/// moderator resumes it, and tells the user a moderator will pick it up.
+/// Sets the skip flag so the turn ends without more output.
+#[derive(Debug, Clone)]
+pub struct EscalateTool {
+    state: ChannelState,
+    response_tx: mpsc::Sender<OutboundResponse>,
+    skip_flag: SkipFlag,
+}
+
+impl EscalateTool {
+    pub fn new(
+        state: ChannelState,
+        response_tx: mpsc::Sender<OutboundResponse>,
+        skip_flag: SkipFlag,
+    ) -> Self {
+        Self {
+            state,
+            response_tx,
+            skip_flag,
+        }
+    }
+}
+
+/// Error type for escalate tool.
+#[derive(Debug, thiserror::Error)]
+#[error("Escalation failed: {0}")]
+pub struct EscalateError(String);
+
+/// Arguments for escalate tool.
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct EscalateArgs {
+    /// Why a moderator needs to look, for the moderator.
+    pub reason: String,
+}
+
+/// Output from escalate tool.
+#[derive(Debug, Serialize)]
+pub struct EscalateOutput {
+    pub escalated: bool,
+}
+
+impl Tool for EscalateTool {
+    const NAME: &'static str = "escalate";
+
+    type Error = EscalateError;
+    type Args = EscalateArgs;
+    type Output = EscalateOutput;
+
+    async fn definition(&self, _prompt: String) -> ToolDefinition {
+        ToolDefinition {
+            name: Self::NAME.to_string(),
+            description: crate::prompts::text::get("tools/escalate").to_string(),
+            parameters: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "reason": {
+                        "type": "string",
+                        "description": "What a moderator needs to know, in a sentence or two: what happened and why it needs a person. Moderators see this, the user doesn't."
+                    }
+                },
+                "required": ["reason"]
+            }),
+        }
+    }
+
+    async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
+        let reason = args.reason.trim();
+        if reason.is_empty() {
+            return Err(EscalateError("the reason is empty".into()));
+        }
+
+        let store = EscalationStore::new(self.state.deps.sqlite_pool.clone());
+        let escalation = store
+            .flag(&self.state.channel_id, reason, FLAGGED_BY_AGENT)
+            .await
+            .map_err(|error| EscalateError(error.to_string()))?;
+        tracing::info!(
+            channel_id = %self.state.channel_id,
+            reason,
+            already_open = escalation.is_none(),
+            "escalate tool called"
+        );
+
+        let paused_reply = self
+            .state
+            .deps
+            .runtime_config
+            .escalation
+            .load()
+            .paused_reply
+            .clone();
+        self.state.conversation_logger.log_bot_message(
+            &self.state.channel_id,
+            &paused_reply,
+            None,
+            &[],
+        );
+        self.response_tx
+            .send(OutboundResponse::Text(paused_reply))
+            .await
+            .map_err(|error| EscalateError(format!("failed to send notice: {error}")))?;
+        self.skip_flag.store(true, Ordering::Relaxed);
+
+        Ok(EscalateOutput { escalated: true })
+    }
+}
@@ -14,6 +14,8 @@ use tokio::process::Command;
 pub struct ExecTool {
     instance_dir: PathBuf,
     workspace: PathBuf,
+    /// Proxy programs' HTTP traffic goes through, when the tool has a network policy.
+    proxy: Option<String>,
 }
 
 impl ExecTool {
@@ -22,9 +24,16 @@ impl ExecTool {
         Self {
             instance_dir,
             workspace,
+            proxy: None,
         }
     }
 
+    /// Point programs' proxy variables at `proxy`.
+    pub fn with_proxy(mut self, proxy: Option<String>) -> Self {
+        self.proxy = proxy;
+        self
+    }
+
     /// Check if program arguments reference sensitive instance paths.
     fn check_args(&self, program: &str, args: &[String]) -> Result<(), ExecError> {
         let instance_str = self.instance_dir.to_string_lossy();
@@ -226,6 +235,11 @@ impl Tool for ExecTool {
             cmd.env(env_var.key, env_var.value);
         }
 
+        // After the caller's variables, so they can't point around the proxy.
+        if let Some(proxy) = &self.proxy {
+            cmd.envs(super::network::proxy_env(proxy));
+        }
+
         cmd.stdout(Stdio::piped()).stderr(Stdio::piped());
 
         let timeout = tokio::time::Duration::from_secs(args.timeout_seconds);
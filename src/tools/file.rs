@@ -23,7 +23,7 @@ impl FileTool {
     /// Relative paths are resolved against the workspace root. Absolute paths are
     /// accepted only if they fall within the workspace. Symlink traversal and `..`
     /// components are handled via canonicalization.
-    fn resolve_path(&self, raw: &str) -> Result<PathBuf, FileError> {
+    pub(crate) fn resolve_path(&self, raw: &str) -> Result<PathBuf, FileError> {
         let path = Path::new(raw);
         let resolved = if path.is_absolute() {
             path.to_path_buf()
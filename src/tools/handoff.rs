@@ -0,0 +1,162 @@
+//! Handoff tool for passing a conversation to another agent (channel only).
+
+use crate::OutboundResponse;
+use crate::agent::channel::ChannelState;
+use crate::agent::handoff::HandoffRequest;
+use crate::tools::SkipFlag;
+use rig::completion::ToolDefinition;
+use rig::tool::Tool;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::Ordering;
+use tokio::sync::mpsc;
+
+/// Tool for handing the conversation to another agent.
+///
+/// Tells the user who is taking over, records the handoff in this agent's
+/// transcript and queues it for the router, which moves the conversation to
+/// the new agent. Sets the skip flag so the turn ends without more output.
+#[derive(Debug, Clone)]
+pub struct HandoffTool {
+    state: ChannelState,
+    response_tx: mpsc::Sender<OutboundResponse>,
+    conversation_id: String,
+    skip_flag: SkipFlag,
+}
+
+impl HandoffTool {
+    pub fn new(
+        state: ChannelState,
+        response_tx: mpsc::Sender<OutboundResponse>,
+        conversation_id: impl Into<String>,
+        skip_flag: SkipFlag,
+    ) -> Self {
+        Self {
+            state,
+            response_tx,
+            conversation_id: conversation_id.into(),
+            skip_flag,
+        }
+    }
+}
+
+/// Error type for handoff tool.
+#[derive(Debug, thiserror::Error)]
+#[error("Handoff failed: {0}")]
+pub struct HandoffError(String);
+
+/// Arguments for handoff tool.
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct HandoffArgs {
+    /// ID of the agent to hand the conversation to.
+    pub agent: String,
+    /// Why, in a short sentence the user will see.
+    pub reason: String,
+    /// What the new agent needs to know to carry on.
+    pub summary: String,
+}
+
+/// Output from handoff tool.
+#[derive(Debug, Serialize)]
+pub struct HandoffOutput {
+    pub handed_off: bool,
+    pub agent: String,
+}
+
+impl Tool for HandoffTool {
+    const NAME: &'static str = "handoff";
+
+    type Error = HandoffError;
+    type Args = HandoffArgs;
+    type Output = HandoffOutput;
+
+    async fn definition(&self, _prompt: String) -> ToolDefinition {
+        let mut description = crate::prompts::text::get("tools/handoff").to_string();
+        description.push_str("\n\nAgents you can hand off to:");
+        let targets = self
+            .state
+            .deps
+            .handoffs
+            .targets_for(&self.state.deps.agent_id);
+        for target in targets {
+            match &target.description {
+                Some(about) => description.push_str(&format!("\n- {}: {about}", target.agent_id)),
+                None => description.push_str(&format!("\n- {}", target.agent_id)),
+            }
+        }
+
+        ToolDefinition {
+            name: Self::NAME.to_string(),
+            description,
+            parameters: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "agent": {
+                        "type": "string",
+                        "description": "ID of the agent to hand the conversation to, from the list above."
+                    },
+                    "reason": {
+                        "type": "string",
+                        "description": "Why you're handing off, in one short sentence. The user sees this."
+                    },
+                    "summary": {
+                        "type": "string",
+                        "description": "Everything the new agent needs to carry on without asking again: who the user is, what they want, what's been tried or decided, and what's still open."
+                    }
+                },
+                "required": ["agent", "reason", "summary"]
+            }),
+        }
+    }
+
+    async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
+        let from = self.state.deps.agent_id.clone();
+        let request = HandoffRequest {
+            conversation_id: self.conversation_id.clone(),
+            from: from.clone(),
+            to: args.agent.as_str().into(),
+            reason: args.reason.trim().to_string(),
+            summary: args.summary,
+        };
+        self.state
+            .deps
+            .handoffs
+            .check(&request)
+            .map_err(HandoffError)?;
+
+        tracing::info!(
+            conversation_id = %self.conversation_id,
+            from = %from,
+            to = %request.to,
+            reason = %request.reason,
+            "handoff tool called"
+        );
+
+        // Tell the user before queueing, so the notice goes out ahead of the
+        // new agent's greeting.
+        let notice = request.notice();
+        self.state.conversation_logger.log_handoff(
+            &self.state.channel_id,
+            &notice,
+            &from,
+            &request.to,
+            &request.reason,
+        );
+        self.response_tx
+            .send(OutboundResponse::Text(notice))
+            .await
+            .map_err(|error| HandoffError(format!("failed to send notice: {error}")))?;
+
+        self.state
+            .deps
+            .handoffs
+            .submit(request)
+            .map_err(HandoffError)?;
+        self.skip_flag.store(true, Ordering::Relaxed);
+
+        Ok(HandoffOutput {
+            handed_off: true,
+            agent: args.agent,
+        })
+    }
+}
@@ -0,0 +1,274 @@
+//! Home Assistant tool: read entity states and call services (task workers only).
+//!
+//! Every action is limited to the entities in the agent's
+//! `home_assistant.entities` allowlist. Service calls must name one allowed
+//! entity; targets that could widen the call (areas, devices, labels) are
+//! refused.
+
+use crate::home_assistant::{Client, EntityState, entity_allowed};
+
+use rig::completion::ToolDefinition;
+use rig::tool::Tool;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// Service data keys that pick targets on their own.
+const TARGET_KEYS: &[&str] = &["entity_id", "device_id", "area_id", "floor_id", "label_id"];
+
+/// Attributes kept when listing states. The rest are fetched per entity.
+const SUMMARY_ATTRIBUTES: &[&str] = &["friendly_name", "device_class", "unit_of_measurement"];
+
+/// Tool for reading and controlling allowlisted Home Assistant entities.
+#[derive(Debug, Clone)]
+pub struct HomeAssistantTool {
+    client: Client,
+    entities: Vec<String>,
+}
+
+impl HomeAssistantTool {
+    pub fn new(client: Client, entities: Vec<String>) -> Self {
+        Self { client, entities }
+    }
+
+    fn check_entity(&self, entity_id: &str) -> Result<(), HomeAssistantError> {
+        let valid = entity_id
+            .split_once('.')
+            .is_some_and(|(domain, object)| is_slug(domain) && is_slug(object));
+        if !valid {
+            return Err(HomeAssistantError::new(format!(
+                "'{entity_id}' is not an entity ID, expected e.g. \"light.kitchen\""
+            )));
+        }
+        if !entity_allowed(&self.entities, entity_id) {
+            return Err(HomeAssistantError::new(format!(
+                "{entity_id} is not in this agent's Home Assistant allowlist"
+            )));
+        }
+        Ok(())
+    }
+}
+
+fn is_slug(value: &str) -> bool {
+    !value.is_empty()
+        && value
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+}
+
+/// Error type for Home Assistant tool.
+#[derive(Debug, thiserror::Error)]
+#[error("Home Assistant action failed: {message}")]
+pub struct HomeAssistantError {
+    message: String,
+}
+
+impl HomeAssistantError {
+    fn new(message: impl Into<String>) -> Self {
+        Self {
+            message: message.into(),
+        }
+    }
+}
+
+/// The action to perform.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum HomeAssistantAction {
+    ListStates,
+    GetState,
+    CallService,
+}
+
+/// Arguments for Home Assistant tool.
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct HomeAssistantArgs {
+    pub action: HomeAssistantAction,
+    /// Entity to read or target (e.g. "light.kitchen").
+    pub entity_id: Option<String>,
+    /// Only list entities in this domain (e.g. "light"), or the service's
+    /// domain for `call_service`.
+    pub domain: Option<String>,
+    /// Service to call (e.g. "turn_on").
+    pub service: Option<String>,
+    /// Extra service data (e.g. `{"brightness_pct": 40}`).
+    pub data: Option<serde_json::Map<String, serde_json::Value>>,
+}
+
+/// Output from Home Assistant tool.
+#[derive(Debug, Serialize)]
+pub struct HomeAssistantOutput {
+    pub summary: String,
+    /// Entities read, or the ones the service call changed.
+    pub states: Vec<EntityState>,
+}
+
+impl Tool for HomeAssistantTool {
+    const NAME: &'static str = "home_assistant";
+
+    type Error = HomeAssistantError;
+    type Args = HomeAssistantArgs;
+    type Output = HomeAssistantOutput;
+
+    async fn definition(&self, _prompt: String) -> ToolDefinition {
+        ToolDefinition {
+            name: Self::NAME.to_string(),
+            description: crate::prompts::text::get("tools/home_assistant").to_string(),
+            parameters: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "action": {
+                        "type": "string",
+                        "enum": ["list_states", "get_state", "call_service"],
+                        "description": "list_states: entities you may use and their states. get_state: one entity with all attributes. call_service: control an entity."
+                    },
+                    "entity_id": {
+                        "type": "string",
+                        "description": "Entity for get_state and call_service (e.g. \"light.kitchen\")"
+                    },
+                    "domain": {
+                        "type": "string",
+                        "description": "For list_states, only this domain (e.g. \"light\"). For call_service, the service domain (e.g. \"light\", \"climate\")"
+                    },
+                    "service": {
+                        "type": "string",
+                        "description": "Service for call_service (e.g. \"turn_on\", \"set_temperature\")"
+                    },
+                    "data": {
+                        "type": "object",
+                        "description": "Extra service data for call_service (e.g. {\"brightness_pct\": 40}). Don't put targets here; use entity_id."
+                    }
+                },
+                "required": ["action"]
+            }),
+        }
+    }
+
+    async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
+        match args.action {
+            HomeAssistantAction::ListStates => {
+                let states = self
+                    .client
+                    .states()
+                    .await
+                    .map_err(|error| HomeAssistantError::new(error.to_string()))?;
+                let states: Vec<EntityState> = states
+                    .into_iter()
+                    .filter(|state| entity_allowed(&self.entities, &state.entity_id))
+                    .filter(|state| {
+                        args.domain.as_deref().is_none_or(|domain| {
+                            state.entity_id.split_once('.').map(|(d, _)| d) == Some(domain)
+                        })
+                    })
+                    .map(summarize)
+                    .collect();
+                Ok(HomeAssistantOutput {
+                    summary: format!("{} entities", states.len()),
+                    states,
+                })
+            }
+            HomeAssistantAction::GetState => {
+                let entity_id = required(args.entity_id, "entity_id", "get_state")?;
+                self.check_entity(&entity_id)?;
+                let state = self
+                    .client
+                    .state(&entity_id)
+                    .await
+                    .map_err(|error| HomeAssistantError::new(error.to_string()))?;
+                Ok(HomeAssistantOutput {
+                    summary: format!("{entity_id} is {}", state.state),
+                    states: vec![state],
+                })
+            }
+            HomeAssistantAction::CallService => {
+                let entity_id = required(args.entity_id, "entity_id", "call_service")?;
+                let domain = required(args.domain, "domain", "call_service")?;
+                let service = required(args.service, "service", "call_service")?;
+                self.check_entity(&entity_id)?;
+                if !is_slug(&domain) || !is_slug(&service) {
+                    return Err(HomeAssistantError::new(format!(
+                        "'{domain}.{service}' is not a service name"
+                    )));
+                }
+
+                let mut data = args.data.unwrap_or_default();
+                if let Some(key) = TARGET_KEYS.iter().find(|key| data.contains_key(**key)) {
+                    return Err(HomeAssistantError::new(format!(
+                        "'{key}' can't be set in data; target a single entity with entity_id"
+                    )));
+                }
+                data.insert("entity_id".into(), entity_id.clone().into());
+
+                let changed = self
+                    .client
+                    .call_service(&domain, &service, &data.into())
+                    .await
+                    .map_err(|error| HomeAssistantError::new(error.to_string()))?;
+                let states: Vec<EntityState> = changed
+                    .into_iter()
+                    .filter(|state| entity_allowed(&self.entities, &state.entity_id))
+                    .collect();
+                Ok(HomeAssistantOutput {
+                    summary: format!("called {domain}.{service} on {entity_id}"),
+                    states,
+                })
+            }
+        }
+    }
+}
+
+fn required(value: Option<String>, name: &str, action: &str) -> Result<String, HomeAssistantError> {
+    value.ok_or_else(|| HomeAssistantError::new(format!("{action} needs {name}")))
+}
+
+fn summarize(mut state: EntityState) -> EntityState {
+    state
+        .attributes
+        .retain(|key, _| SUMMARY_ATTRIBUTES.contains(&key.as_str()));
+    state.last_changed = None;
+    state
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::HomeAssistantConfig;
+
+    fn tool() -> HomeAssistantTool {
+        let client = Client::new(&HomeAssistantConfig {
+            token: Some("token".into()),
+            ..HomeAssistantConfig::default()
+        })
+        .unwrap();
+        HomeAssistantTool::new(client, vec!["light.*".into(), "climate.living_room".into()])
+    }
+
+    #[test]
+    fn test_check_entity_enforces_allowlist() {
+        let tool = tool();
+
+        assert!(tool.check_entity("light.kitchen").is_ok());
+        assert!(tool.check_entity("climate.living_room").is_ok());
+        assert!(tool.check_entity("lock.front_door").is_err());
+        assert!(tool.check_entity("light.kitchen/../../config").is_err());
+        assert!(tool.check_entity("kitchen").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_call_service_refuses_wider_targets() {
+        let mut data = serde_json::Map::new();
+        data.insert("area_id".into(), "downstairs".into());
+
+        let error = tool()
+            .call(HomeAssistantArgs {
+                action: HomeAssistantAction::CallService,
+                entity_id: Some("light.kitchen".into()),
+                domain: Some("light".into()),
+                service: Some("turn_off".into()),
+                data: Some(data),
+            })
+            .await
+            .unwrap_err();
+
+        assert!(error.to_string().contains("area_id"));
+    }
+}
@@ -0,0 +1,274 @@
+//! HTTP request tool: requests to allowlisted domains (task workers only).
+//!
+//! `http_request` sends one request to a host on `[defaults.http]`'s
+//! allowlist and follows redirects only while they stay on it. It never
+//! carries the credentials of configured APIs; those are only sent by the
+//! API's own operation tools (see [`super::api_operation`]). Response bodies
+//! are cut off at `max_response_bytes`.
+
+use crate::config::{HttpConfig, domain_allowed};
+use crate::tools::network;
+
+use rig::completion::ToolDefinition;
+use rig::tool::Tool;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Redirects followed before a request fails.
+const MAX_REDIRECTS: usize = 5;
+
+/// Methods `http_request` sends.
+const METHODS: &[&str] = &["GET", "HEAD", "OPTIONS", "POST", "PUT", "PATCH", "DELETE"];
+
+/// Error type for `http_request` and the API operation tools.
+#[derive(Debug, thiserror::Error)]
+#[error("HTTP request failed: {0}")]
+pub struct HttpRequestError(pub(crate) String);
+
+impl From<reqwest::Error> for HttpRequestError {
+    fn from(error: reqwest::Error) -> Self {
+        Self(error.to_string())
+    }
+}
+
+/// Tool for sending HTTP requests to allowlisted domains.
+#[derive(Debug, Clone)]
+pub struct HttpRequestTool {
+    client: reqwest::Client,
+    allowed_domains: Arc<Vec<String>>,
+    timeout: Duration,
+    max_response_bytes: usize,
+}
+
+impl HttpRequestTool {
+    pub fn new(config: &HttpConfig) -> Self {
+        let allowed_domains = Arc::new(config.allowed_domains.clone());
+        Self {
+            client: client(allowed_domains.clone(), None),
+            allowed_domains,
+            timeout: Duration::from_secs(config.timeout_secs),
+            max_response_bytes: config.max_response_bytes,
+        }
+    }
+
+    /// Send requests through `proxy`.
+    pub fn with_proxy(mut self, proxy: Option<String>) -> Self {
+        if proxy.is_some() {
+            self.client = client(self.allowed_domains.clone(), proxy);
+        }
+        self
+    }
+}
+
+/// A client that only follows redirects to `allowed_domains`, going through
+/// `proxy` when there is one.
+pub(crate) fn client(allowed_domains: Arc<Vec<String>>, proxy: Option<String>) -> reqwest::Client {
+    let redirect = reqwest::redirect::Policy::custom(move |attempt| {
+        let allowed = attempt
+            .url()
+            .host_str()
+            .is_some_and(|host| domain_allowed(host, &allowed_domains));
+        if attempt.previous().len() >= MAX_REDIRECTS {
+            attempt.error("too many redirects")
+        } else if allowed {
+            attempt.follow()
+        } else {
+            attempt.stop()
+        }
+    });
+    let mut builder = reqwest::Client::builder().gzip(true).redirect(redirect);
+    if let Some(proxy) = proxy {
+        builder = builder.proxy(network::reqwest_proxy(&proxy));
+    }
+    builder.build().expect("hardcoded reqwest client config")
+}
+
+/// Arguments for http_request.
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct HttpRequestArgs {
+    #[serde(default = "default_method")]
+    pub method: String,
+    pub url: String,
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    /// Sent as is when a string, as JSON otherwise.
+    pub body: Option<Value>,
+}
+
+fn default_method() -> String {
+    "GET".into()
+}
+
+/// A response, from http_request or an API operation tool.
+#[derive(Debug, Serialize)]
+pub struct HttpResponseOutput {
+    pub status: u16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content_type: Option<String>,
+    /// Where a redirect that wasn't followed pointed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub location: Option<String>,
+    /// Parsed JSON when the response is JSON and complete, text otherwise.
+    pub body: Value,
+    /// Whether the body was cut off at the byte limit.
+    pub truncated: bool,
+}
+
+/// Read `response`, keeping at most `max_bytes` of its body.
+pub(crate) async fn read_response(
+    mut response: reqwest::Response,
+    max_bytes: usize,
+) -> Result<HttpResponseOutput, HttpRequestError> {
+    let header = |name: reqwest::header::HeaderName| {
+        response
+            .headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string)
+    };
+    let content_type = header(reqwest::header::CONTENT_TYPE);
+    let location = header(reqwest::header::LOCATION);
+    let status = response.status().as_u16();
+
+    let mut bytes = Vec::new();
+    let mut truncated = false;
+    while let Some(chunk) = response.chunk().await? {
+        let room = max_bytes - bytes.len();
+        if chunk.len() > room {
+            bytes.extend_from_slice(&chunk[..room]);
+            truncated = true;
+            break;
+        }
+        bytes.extend_from_slice(&chunk);
+    }
+
+    let is_json = content_type
+        .as_deref()
+        .is_some_and(|content_type| content_type.contains("json"));
+    let body = match serde_json::from_slice(&bytes) {
+        Ok(json) if is_json && !truncated => json,
+        _ => Value::String(String::from_utf8_lossy(&bytes).into_owned()),
+    };
+    Ok(HttpResponseOutput {
+        status,
+        content_type,
+        location,
+        body,
+        truncated,
+    })
+}
+
+/// The URL and method of a request, if `http_request` may send it.
+fn check_request(
+    method: &str,
+    url: &str,
+    allowed_domains: &[String],
+) -> Result<(reqwest::Method, reqwest::Url), HttpRequestError> {
+    let method = method.trim().to_ascii_uppercase();
+    if !METHODS.contains(&method.as_str()) {
+        return Err(HttpRequestError(format!(
+            "unsupported method {method}, use one of {}",
+            METHODS.join(", ")
+        )));
+    }
+    let url = reqwest::Url::parse(url.trim())
+        .map_err(|error| HttpRequestError(format!("invalid url: {error}")))?;
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(HttpRequestError("only http and https urls work".into()));
+    }
+    let host = url.host_str().unwrap_or_default();
+    if !domain_allowed(host, allowed_domains) {
+        return Err(HttpRequestError(format!(
+            "{host} isn't allowed; allowed domains: {}",
+            allowed_domains.join(", ")
+        )));
+    }
+    let method = reqwest::Method::from_bytes(method.as_bytes())
+        .map_err(|error| HttpRequestError(error.to_string()))?;
+    Ok((method, url))
+}
+
+impl Tool for HttpRequestTool {
+    const NAME: &'static str = "http_request";
+
+    type Error = HttpRequestError;
+    type Args = HttpRequestArgs;
+    type Output = HttpResponseOutput;
+
+    async fn definition(&self, _prompt: String) -> ToolDefinition {
+        let description = format!(
+            "{}\n\nAllowed domains (subdomains included): {}",
+            crate::prompts::text::get("tools/http_request"),
+            self.allowed_domains.join(", ")
+        );
+        ToolDefinition {
+            name: Self::NAME.to_string(),
+            description,
+            parameters: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "method": {
+                        "type": "string",
+                        "enum": METHODS,
+                        "default": "GET"
+                    },
+                    "url": {
+                        "type": "string",
+                        "description": "Full http or https URL, query string included."
+                    },
+                    "headers": {
+                        "type": "object",
+                        "additionalProperties": { "type": "string" },
+                        "description": "Request headers, e.g. {\"Accept\": \"application/json\"}."
+                    },
+                    "body": {
+                        "description": "Request body. A string is sent as is; anything else is sent as JSON."
+                    }
+                },
+                "required": ["url"]
+            }),
+        }
+    }
+
+    async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
+        let (method, url) = check_request(&args.method, &args.url, &self.allowed_domains)?;
+        tracing::debug!(%method, %url, "http_request tool called");
+
+        let mut request = self.client.request(method, url).timeout(self.timeout);
+        for (name, value) in &args.headers {
+            request = request.header(name, value);
+        }
+        request = match args.body {
+            Some(Value::String(text)) => request.body(text),
+            Some(Value::Null) | None => request,
+            Some(json) => request.json(&json),
+        };
+        let response = request.send().await?;
+        read_response(response, self.max_response_bytes).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_request() {
+        let allowed = vec!["example.com".to_string(), "*.api.test".to_string()];
+        let (method, url) =
+            check_request("post", "https://www.example.com/a?b=c", &allowed).unwrap();
+        assert_eq!(method, reqwest::Method::POST);
+        assert_eq!(url.path(), "/a");
+        assert!(check_request("GET", "https://v1.api.test/", &allowed).is_ok());
+
+        assert!(check_request("GET", "https://example.com.evil.net/", &allowed).is_err());
+        assert!(check_request("GET", "https://evil.net/?to=example.com", &allowed).is_err());
+        assert!(check_request("GET", "ftp://example.com/", &allowed).is_err());
+        assert!(check_request("TRACE", "https://example.com/", &allowed).is_err());
+        assert!(check_request("GET", "not a url", &allowed).is_err());
+    }
+}
@@ -0,0 +1,364 @@
+//! Image generation tool (task workers only).
+//!
+//! Images are drawn by the configured provider's OpenAI-compatible images
+//! endpoint. Every result is saved as an artifact, and a worker that belongs
+//! to a conversation uploads it there with a caption, so the user sees the
+//! image itself instead of a link in the worker's result.
+
+use crate::config::ImageGenerationConfig;
+use crate::llm::LlmManager;
+use crate::storage::ArtifactStore;
+use crate::tools::content::{ContentSink, MAX_CONTENT_BYTES, ToolContent};
+
+use base64::Engine as _;
+use rig::completion::ToolDefinition;
+use rig::tool::Tool;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::Duration;
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(180);
+
+/// Longest caption taken from the prompt when none is given.
+const MAX_PROMPT_CAPTION_CHARS: usize = 200;
+
+/// Tool for generating images from a text prompt.
+#[derive(Clone)]
+pub struct GenerateImageTool {
+    config: ImageGenerationConfig,
+    llm_manager: Arc<LlmManager>,
+    images: ArtifactStore,
+    sink: Option<ContentSink>,
+}
+
+impl GenerateImageTool {
+    pub fn new(
+        config: ImageGenerationConfig,
+        llm_manager: Arc<LlmManager>,
+        images: ArtifactStore,
+    ) -> Self {
+        Self {
+            config,
+            llm_manager,
+            images,
+            sink: None,
+        }
+    }
+
+    /// Upload generated images to the conversation the worker works for.
+    pub fn with_sink(mut self, sink: ContentSink) -> Self {
+        self.sink = Some(sink);
+        self
+    }
+
+    /// Ask the provider for `count` images, returning each one's bytes and
+    /// the prompt the provider actually drew, if it rewrote it.
+    async fn generate(
+        &self,
+        prompt: &str,
+        count: u32,
+    ) -> Result<Vec<(Vec<u8>, Option<String>)>, GenerateImageError> {
+        let provider = &self.config.provider;
+        let api_key = self
+            .llm_manager
+            .get_api_key(provider)
+            .await
+            .map_err(|error| GenerateImageError(format!("no key for {provider}: {error}")))?;
+        let root = self
+            .llm_manager
+            .base_url(provider)
+            .or_else(|| crate::llm::providers::provider_origin(provider))
+            .ok_or_else(|| GenerateImageError(format!("unknown provider '{provider}'")))?;
+
+        let request = self
+            .llm_manager
+            .http_client()
+            .post(format!(
+                "{}/v1/images/generations",
+                root.trim_end_matches('/')
+            ))
+            .header("authorization", format!("Bearer {api_key}"))
+            .timeout(REQUEST_TIMEOUT)
+            .json(&serde_json::json!({
+                "model": self.config.model,
+                "prompt": prompt,
+                "n": count,
+                "size": self.config.size,
+            }));
+        let response = self
+            .llm_manager
+            .send(provider, request)
+            .await
+            .map_err(|error| GenerateImageError(format!("request failed: {error}")))?;
+        let status = response.status();
+        let body: serde_json::Value = response
+            .json()
+            .await
+            .map_err(|error| GenerateImageError(format!("unreadable response: {error}")))?;
+        if !status.is_success() {
+            let message = body["error"]["message"]
+                .as_str()
+                .unwrap_or("no error message");
+            return Err(GenerateImageError(format!("{status}: {message}")));
+        }
+
+        let mut images = Vec::new();
+        for (source, revised_prompt) in parse_images(&body)? {
+            let data = match source {
+                ImageSource::Data(data) => data,
+                ImageSource::Url(url) => self.download(&url).await?,
+            };
+            images.push((data, revised_prompt));
+        }
+        Ok(images)
+    }
+
+    async fn download(&self, url: &str) -> Result<Vec<u8>, GenerateImageError> {
+        let response = self
+            .llm_manager
+            .http_client()
+            .get(url)
+            .timeout(REQUEST_TIMEOUT)
+            .send()
+            .await
+            .and_then(reqwest::Response::error_for_status)
+            .map_err(|error| GenerateImageError(format!("failed to download image: {error}")))?;
+        let data = response
+            .bytes()
+            .await
+            .map_err(|error| GenerateImageError(format!("failed to download image: {error}")))?;
+        if data.len() as u64 > MAX_CONTENT_BYTES {
+            return Err(GenerateImageError(format!(
+                "image is too large ({} bytes)",
+                data.len()
+            )));
+        }
+        Ok(data.to_vec())
+    }
+}
+
+/// Error type for generate_image tool.
+#[derive(Debug, thiserror::Error)]
+#[error("Image generation failed: {0}")]
+pub struct GenerateImageError(String);
+
+/// Arguments for generate_image tool.
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct GenerateImageArgs {
+    /// What to draw.
+    pub prompt: String,
+    /// Caption shown with the image. Defaults to the prompt.
+    #[serde(default)]
+    pub caption: Option<String>,
+    /// How many images to draw.
+    #[serde(default)]
+    pub count: Option<u32>,
+}
+
+/// One generated image.
+#[derive(Debug, Serialize)]
+pub struct GeneratedImage {
+    /// Where the image was saved in the workspace.
+    pub path: String,
+    /// Link to the stored copy, when artifacts go to a bucket.
+    pub url: Option<String>,
+    /// The prompt as the provider rewrote it, if it did.
+    pub revised_prompt: Option<String>,
+}
+
+/// Output from generate_image tool.
+#[derive(Debug, Serialize)]
+pub struct GenerateImageOutput {
+    pub success: bool,
+    pub images: Vec<GeneratedImage>,
+    /// What the user was sent.
+    pub message: String,
+}
+
+impl Tool for GenerateImageTool {
+    const NAME: &'static str = "generate_image";
+
+    type Error = GenerateImageError;
+    type Args = GenerateImageArgs;
+    type Output = GenerateImageOutput;
+
+    async fn definition(&self, _prompt: String) -> ToolDefinition {
+        ToolDefinition {
+            name: Self::NAME.to_string(),
+            description: crate::prompts::text::get("tools/generate_image").to_string(),
+            parameters: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "prompt": {
+                        "type": "string",
+                        "description": "A detailed description of the image to draw."
+                    },
+                    "caption": {
+                        "type": "string",
+                        "description": "Caption shown with the image. Defaults to the prompt."
+                    },
+                    "count": {
+                        "type": "integer",
+                        "minimum": 1,
+                        "maximum": self.config.max_images,
+                        "description": "How many images to draw (default 1)."
+                    }
+                },
+                "required": ["prompt"]
+            }),
+        }
+    }
+
+    async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
+        let prompt = args.prompt.trim();
+        if prompt.is_empty() {
+            return Err(GenerateImageError("prompt is empty".into()));
+        }
+        let count = args.count.unwrap_or(1).clamp(1, self.config.max_images);
+        let generated = self.generate(prompt, count).await?;
+
+        let caption = args
+            .caption
+            .filter(|caption| !caption.trim().is_empty())
+            .unwrap_or_else(|| prompt_caption(prompt));
+        let stamp = chrono::Utc::now().format("%Y%m%d_%H%M%S_%3f");
+        let mut images = Vec::new();
+        let mut shared = Vec::new();
+        for (index, (data, revised_prompt)) in generated.into_iter().enumerate() {
+            let mime_type = image_mime_type(&data);
+            let extension = mime_type.trim_start_matches("image/");
+            let filename = format!("image_{stamp}_{}.{extension}", index + 1);
+            let saved = self
+                .images
+                .save(&filename, &data, mime_type)
+                .await
+                .map_err(|error| GenerateImageError(format!("failed to save image: {error:#}")))?;
+            tracing::debug!(path = %saved.path.display(), url = ?saved.url, "generated image saved");
+
+            if let Some(sink) = self.sink.as_ref().filter(|sink| sink.has_channel()) {
+                shared.push(sink.deliver(ToolContent::Image {
+                    filename,
+                    mime_type: mime_type.to_string(),
+                    data,
+                    caption: Some(caption.clone()),
+                }));
+            }
+            images.push(GeneratedImage {
+                path: saved.path.to_string_lossy().into_owned(),
+                url: saved.url,
+                revised_prompt,
+            });
+        }
+
+        let message = if shared.is_empty() {
+            format!(
+                "Generated {} image(s). There is no conversation to send them to; they're saved at the paths listed.",
+                images.len()
+            )
+        } else {
+            shared.join(" ")
+        };
+        Ok(GenerateImageOutput {
+            success: true,
+            images,
+            message,
+        })
+    }
+}
+
+/// Where an image in the provider's response is.
+#[derive(Debug, PartialEq)]
+enum ImageSource {
+    Data(Vec<u8>),
+    Url(String),
+}
+
+/// The images in an images API response, with each one's revised prompt.
+fn parse_images(
+    body: &serde_json::Value,
+) -> Result<Vec<(ImageSource, Option<String>)>, GenerateImageError> {
+    let entries = body["data"]
+        .as_array()
+        .filter(|entries| !entries.is_empty())
+        .ok_or_else(|| GenerateImageError("the response had no images".into()))?;
+    entries
+        .iter()
+        .map(|entry| {
+            let revised_prompt = entry["revised_prompt"].as_str().map(str::to_string);
+            let source = if let Some(encoded) = entry["b64_json"].as_str() {
+                let data = base64::engine::general_purpose::STANDARD
+                    .decode(encoded)
+                    .map_err(|error| GenerateImageError(format!("invalid image data: {error}")))?;
+                ImageSource::Data(data)
+            } else if let Some(url) = entry["url"].as_str() {
+                ImageSource::Url(url.to_string())
+            } else {
+                return Err(GenerateImageError(
+                    "an image had neither data nor a URL".into(),
+                ));
+            };
+            Ok((source, revised_prompt))
+        })
+        .collect()
+}
+
+/// The image type, from its leading bytes. PNG unless it's clearly another.
+fn image_mime_type(data: &[u8]) -> &'static str {
+    if data.starts_with(&[0xff, 0xd8, 0xff]) {
+        "image/jpeg"
+    } else if data.len() >= 12 && &data[..4] == b"RIFF" && &data[8..12] == b"WEBP" {
+        "image/webp"
+    } else {
+        "image/png"
+    }
+}
+
+/// A caption from the prompt, cut at a word boundary if it's long.
+fn prompt_caption(prompt: &str) -> String {
+    if prompt.chars().count() <= MAX_PROMPT_CAPTION_CHARS {
+        return prompt.to_string();
+    }
+    let cut: String = prompt.chars().take(MAX_PROMPT_CAPTION_CHARS).collect();
+    let cut = cut.rsplit_once(' ').map_or(cut.as_str(), |(head, _)| head);
+    format!("{}…", cut.trim_end())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_images() {
+        let body = serde_json::json!({
+            "data": [
+                {"b64_json": "iVBORw0K", "revised_prompt": "a red fox in snow"},
+                {"url": "https://example.com/image.png"}
+            ]
+        });
+        let images = parse_images(&body).unwrap();
+        assert_eq!(images.len(), 2);
+        let (ImageSource::Data(data), Some(revised)) = &images[0] else {
+            panic!("expected inline data with a revised prompt");
+        };
+        assert_eq!(image_mime_type(data), "image/png");
+        assert_eq!(revised, "a red fox in snow");
+        assert_eq!(
+            images[1],
+            (
+                ImageSource::Url("https://example.com/image.png".into()),
+                None
+            )
+        );
+        assert!(parse_images(&serde_json::json!({"data": []})).is_err());
+    }
+
+    #[test]
+    fn test_prompt_caption() {
+        assert_eq!(prompt_caption("a red fox"), "a red fox");
+        let long = "word ".repeat(100);
+        let caption = prompt_caption(&long);
+        assert!(caption.chars().count() <= MAX_PROMPT_CAPTION_CHARS + 1);
+        assert!(caption.ends_with("word…"));
+    }
+}
@@ -0,0 +1,282 @@
+//! Incident tool: list, inspect and work PagerDuty incidents and Opsgenie
+//! alerts (task workers only).
+//!
+//! Acknowledging, resolving and adding notes always go through the preview
+//! gate, and only the agent's incident responders can confirm them.
+
+use crate::config::IncidentAccount;
+use crate::incidents::{Client, Incident, TimelineEntry};
+
+use rig::completion::ToolDefinition;
+use rig::tool::Tool;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// Most incidents a `list` call returns.
+const MAX_INCIDENTS: usize = 25;
+
+/// Most timeline entries a `timeline` call returns.
+const MAX_TIMELINE_ENTRIES: usize = 50;
+
+/// Tool for working incidents in PagerDuty and Opsgenie.
+#[derive(Debug, Clone)]
+pub struct IncidentTool {
+    clients: Vec<Client>,
+}
+
+impl IncidentTool {
+    pub fn new(accounts: Vec<IncidentAccount>) -> Self {
+        Self {
+            clients: accounts.into_iter().map(Client::new).collect(),
+        }
+    }
+
+    fn client(&self, name: Option<&str>) -> Result<&Client, IncidentError> {
+        let found = match name {
+            Some(name) => self.clients.iter().find(|c| c.account().name == name),
+            None if self.clients.len() == 1 => self.clients.first(),
+            None => None,
+        };
+        found.ok_or_else(|| {
+            let names: Vec<&str> = self
+                .clients
+                .iter()
+                .map(|c| c.account().name.as_str())
+                .collect();
+            IncidentError::new(format!("pick an account, one of: {}", names.join(", ")))
+        })
+    }
+}
+
+/// Error type for incident tool.
+#[derive(Debug, thiserror::Error)]
+#[error("Incident action failed: {message}")]
+pub struct IncidentError {
+    message: String,
+}
+
+impl IncidentError {
+    fn new(message: impl Into<String>) -> Self {
+        Self {
+            message: message.into(),
+        }
+    }
+}
+
+/// The action to perform.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum IncidentAction {
+    List,
+    Get,
+    Timeline,
+    Acknowledge,
+    Resolve,
+    Note,
+}
+
+/// Arguments for incident tool.
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct IncidentArgs {
+    pub action: IncidentAction,
+    /// Account name. Can be left out when only one is configured.
+    pub account: Option<String>,
+    /// Incident or alert ID, for everything but `list`.
+    pub incident_id: Option<String>,
+    /// Note text for `note`.
+    pub note: Option<String>,
+    /// Most incidents or timeline entries to return.
+    pub limit: Option<usize>,
+}
+
+/// Output from incident tool.
+#[derive(Debug, Serialize)]
+pub struct IncidentOutput {
+    pub account: String,
+    pub summary: String,
+    pub incidents: Vec<Incident>,
+    /// Timeline entries, oldest first.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub timeline: Vec<TimelineEntry>,
+}
+
+impl Tool for IncidentTool {
+    const NAME: &'static str = "incident";
+
+    type Error = IncidentError;
+    type Args = IncidentArgs;
+    type Output = IncidentOutput;
+
+    async fn definition(&self, _prompt: String) -> ToolDefinition {
+        let mut description = crate::prompts::text::get("tools/incident").to_string();
+        description.push_str("\n\nAccounts:");
+        for client in &self.clients {
+            let account = client.account();
+            description.push_str(&format!(
+                "\n- {} ({})",
+                account.name,
+                account.provider.as_str()
+            ));
+        }
+
+        ToolDefinition {
+            name: Self::NAME.to_string(),
+            description,
+            parameters: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "action": {
+                        "type": "string",
+                        "enum": ["list", "get", "timeline", "acknowledge", "resolve", "note"],
+                        "description": "list: open incidents. get: one incident. timeline: what happened to an incident, with notes. acknowledge, resolve, note: change an incident; needs a responder's confirmation."
+                    },
+                    "account": {
+                        "type": "string",
+                        "description": "Account name. Can be left out when only one is configured."
+                    },
+                    "incident_id": {
+                        "type": "string",
+                        "description": "Incident or alert ID (not the short number), for everything but list"
+                    },
+                    "note": {
+                        "type": "string",
+                        "description": "Note text for note"
+                    },
+                    "limit": {
+                        "type": "integer",
+                        "minimum": 1,
+                        "maximum": MAX_TIMELINE_ENTRIES,
+                        "description": "Most incidents or timeline entries to return"
+                    }
+                },
+                "required": ["action"]
+            }),
+        }
+    }
+
+    async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
+        let client = self.client(args.account.as_deref())?;
+        let account = client.account().name.clone();
+        let failed = |error: crate::error::Error| IncidentError::new(error.to_string());
+
+        if args.action == IncidentAction::List {
+            let limit = args.limit.unwrap_or(MAX_INCIDENTS).clamp(1, MAX_INCIDENTS);
+            let incidents = client.open_incidents(limit).await.map_err(failed)?;
+            return Ok(IncidentOutput {
+                account,
+                summary: format!("{} open incidents", incidents.len()),
+                incidents,
+                timeline: Vec::new(),
+            });
+        }
+
+        let id = args
+            .incident_id
+            .ok_or_else(|| IncidentError::new("this action needs incident_id"))?;
+        check_id(&id)?;
+        tracing::info!(%account, incident_id = %id, action = ?args.action, "incident tool called");
+
+        let (summary, timeline) = match args.action {
+            IncidentAction::List | IncidentAction::Get => (String::new(), Vec::new()),
+            IncidentAction::Timeline => {
+                let limit = args
+                    .limit
+                    .unwrap_or(MAX_TIMELINE_ENTRIES)
+                    .clamp(1, MAX_TIMELINE_ENTRIES);
+                let timeline = client.timeline(&id, limit).await.map_err(failed)?;
+                (format!("{} timeline entries", timeline.len()), timeline)
+            }
+            IncidentAction::Acknowledge => {
+                client.acknowledge(&id).await.map_err(failed)?;
+                (format!("acknowledged {id}"), Vec::new())
+            }
+            IncidentAction::Resolve => {
+                client.resolve(&id).await.map_err(failed)?;
+                (format!("resolved {id}"), Vec::new())
+            }
+            IncidentAction::Note => {
+                let note = args
+                    .note
+                    .filter(|note| !note.trim().is_empty())
+                    .ok_or_else(|| IncidentError::new("note needs note text"))?;
+                client.add_note(&id, &note).await.map_err(failed)?;
+                (format!("added a note to {id}"), Vec::new())
+            }
+        };
+
+        let incident = client.incident(&id).await.map_err(failed)?;
+        Ok(IncidentOutput {
+            account,
+            summary: if summary.is_empty() {
+                format!("{} is {}", incident.title, incident.status)
+            } else {
+                summary
+            },
+            incidents: vec![incident],
+            timeline,
+        })
+    }
+}
+
+/// IDs go into request paths, so only the characters PagerDuty and Opsgenie
+/// IDs use are accepted.
+fn check_id(id: &str) -> Result<(), IncidentError> {
+    let valid = !id.is_empty()
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if valid {
+        Ok(())
+    } else {
+        Err(IncidentError::new(format!("'{id}' is not an incident ID")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::IncidentProvider;
+
+    fn account(name: &str, provider: IncidentProvider) -> IncidentAccount {
+        IncidentAccount {
+            name: name.into(),
+            provider,
+            token: "token".into(),
+            email: Some("bot@example.com".into()),
+            url: None,
+            webhook_secret: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_refuses_unsafe_ids_and_unknown_accounts() {
+        let tool = IncidentTool::new(vec![
+            account("pagerduty", IncidentProvider::Pagerduty),
+            account("opsgenie", IncidentProvider::Opsgenie),
+        ]);
+
+        let error = tool
+            .call(IncidentArgs {
+                action: IncidentAction::Get,
+                account: None,
+                incident_id: Some("Q1P3AHC3KLGSBF".into()),
+                note: None,
+                limit: None,
+            })
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("pagerduty, opsgenie"));
+
+        let error = tool
+            .call(IncidentArgs {
+                action: IncidentAction::Resolve,
+                account: Some("pagerduty".into()),
+                incident_id: Some("../users/PXPGF42".into()),
+                note: None,
+                limit: None,
+            })
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("not an incident ID"));
+    }
+}
@@ -0,0 +1,637 @@
+//! Issue tracker tool: file, search and comment on Jira and Linear issues
+//! (task workers only).
+//!
+//! Each configured workspace is one Jira project or Linear team. Searches and
+//! comments are limited to it, and created issues get the workspace's
+//! templated `fields` on top of the title and description.
+
+use crate::config::{IssueProvider, IssueWorkspace};
+
+use rig::completion::ToolDefinition;
+use rig::tool::Tool;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value, json};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::time::Duration;
+
+const LINEAR_API_URL: &str = "https://api.linear.app/graphql";
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Template variables every workspace gets without the agent passing them.
+const BUILTIN_VARIABLES: &[&str] = &["title", "description"];
+
+/// Tool for filing and finding issues in Jira and Linear.
+#[derive(Debug, Clone)]
+pub struct IssuesTool {
+    http: reqwest::Client,
+    workspaces: Vec<IssueWorkspace>,
+}
+
+impl IssuesTool {
+    pub fn new(workspaces: Vec<IssueWorkspace>) -> Self {
+        let http = reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .expect("hardcoded reqwest client config");
+        Self { http, workspaces }
+    }
+
+    fn workspace(&self, name: Option<&str>) -> Result<&IssueWorkspace, IssuesError> {
+        let found = match name {
+            Some(name) => self.workspaces.iter().find(|w| w.name == name),
+            None if self.workspaces.len() == 1 => self.workspaces.first(),
+            None => None,
+        };
+        found.ok_or_else(|| {
+            let names: Vec<&str> = self.workspaces.iter().map(|w| w.name.as_str()).collect();
+            IssuesError::new(format!("pick a workspace, one of: {}", names.join(", ")))
+        })
+    }
+
+    async fn create(
+        &self,
+        workspace: &IssueWorkspace,
+        title: String,
+        description: String,
+        variables: HashMap<String, String>,
+    ) -> Result<IssuesOutput, IssuesError> {
+        let mut context: BTreeMap<String, String> = variables.into_iter().collect();
+        context.insert("title".into(), title.clone());
+        context.insert("description".into(), description.clone());
+        let mut fields = render_fields(&workspace.fields, &context)?;
+
+        let issue = match workspace.provider {
+            IssueProvider::Jira => {
+                fields.insert("project".into(), json!({"key": workspace.project}));
+                fields.insert("summary".into(), title.clone().into());
+                fields.insert("description".into(), description.into());
+                fields
+                    .entry("issuetype")
+                    .or_insert_with(|| json!({"name": "Task"}));
+
+                let response = self
+                    .send(
+                        self.jira(workspace, reqwest::Method::POST, "/rest/api/2/issue")
+                            .json(&json!({"fields": fields})),
+                    )
+                    .await?;
+                let key = response["key"].as_str().unwrap_or_default().to_string();
+                Issue {
+                    url: jira_browse_url(workspace, &key),
+                    key,
+                    title,
+                    status: None,
+                }
+            }
+            IssueProvider::Linear => {
+                fields.insert("teamId".into(), workspace.project.clone().into());
+                fields.insert("title".into(), title.into());
+                fields.insert("description".into(), description.into());
+
+                let data = self
+                    .linear(
+                        workspace,
+                        "mutation IssueCreate($input: IssueCreateInput!) { \
+                         issueCreate(input: $input) { \
+                         issue { identifier title url state { name } } } }",
+                        json!({"input": fields}),
+                    )
+                    .await?;
+                linear_issue(&data["issueCreate"]["issue"])
+            }
+        };
+
+        Ok(IssuesOutput {
+            workspace: workspace.name.clone(),
+            issues: vec![issue],
+            comment_url: None,
+        })
+    }
+
+    async fn search(
+        &self,
+        workspace: &IssueWorkspace,
+        query: &str,
+        limit: u32,
+    ) -> Result<IssuesOutput, IssuesError> {
+        let issues = match workspace.provider {
+            IssueProvider::Jira => {
+                let limit = limit.to_string();
+                let jql = format!(
+                    "project = \"{}\" AND text ~ \"{}\" ORDER BY updated DESC",
+                    jql_escape(&workspace.project),
+                    jql_escape(query)
+                );
+                let response = self
+                    .send(
+                        self.jira(workspace, reqwest::Method::GET, "/rest/api/2/search/jql")
+                            .query(&[
+                                ("jql", jql.as_str()),
+                                ("maxResults", limit.as_str()),
+                                ("fields", "summary,status"),
+                            ]),
+                    )
+                    .await?;
+                response["issues"]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .map(|issue| {
+                        let key = issue["key"].as_str().unwrap_or_default().to_string();
+                        Issue {
+                            url: jira_browse_url(workspace, &key),
+                            key,
+                            title: string(&issue["fields"]["summary"]),
+                            status: issue["fields"]["status"]["name"].as_str().map(String::from),
+                        }
+                    })
+                    .collect()
+            }
+            IssueProvider::Linear => {
+                let data = self
+                    .linear(
+                        workspace,
+                        "query Search($term: String!, $first: Int, $team: ID!) { \
+                         searchIssues(term: $term, first: $first, \
+                         filter: { team: { id: { eq: $team } } }) { \
+                         nodes { identifier title url state { name } } } }",
+                        json!({"term": query, "first": limit, "team": workspace.project}),
+                    )
+                    .await?;
+                data["searchIssues"]["nodes"]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .map(linear_issue)
+                    .collect()
+            }
+        };
+
+        Ok(IssuesOutput {
+            workspace: workspace.name.clone(),
+            issues,
+            comment_url: None,
+        })
+    }
+
+    async fn comment(
+        &self,
+        workspace: &IssueWorkspace,
+        issue: &str,
+        body: &str,
+    ) -> Result<IssuesOutput, IssuesError> {
+        let comment_url = match workspace.provider {
+            IssueProvider::Jira => {
+                if !in_jira_project(issue, &workspace.project) {
+                    return Err(IssuesError::new(format!(
+                        "{issue} is not an issue in project {}",
+                        workspace.project
+                    )));
+                }
+                let response = self
+                    .send(
+                        self.jira(
+                            workspace,
+                            reqwest::Method::POST,
+                            &format!("/rest/api/2/issue/{issue}/comment"),
+                        )
+                        .json(&json!({"body": body})),
+                    )
+                    .await?;
+                format!(
+                    "{}?focusedCommentId={}",
+                    jira_browse_url(workspace, issue),
+                    string(&response["id"])
+                )
+            }
+            IssueProvider::Linear => {
+                let data = self
+                    .linear(
+                        workspace,
+                        "query Issue($id: String!) { issue(id: $id) { id team { id } } }",
+                        json!({"id": issue}),
+                    )
+                    .await?;
+                if data["issue"]["team"]["id"] != workspace.project.as_str() {
+                    return Err(IssuesError::new(format!(
+                        "{issue} is not an issue in this workspace's team"
+                    )));
+                }
+                let data = self
+                    .linear(
+                        workspace,
+                        "mutation Comment($input: CommentCreateInput!) { \
+                         commentCreate(input: $input) { comment { url } } }",
+                        json!({"input": {"issueId": data["issue"]["id"], "body": body}}),
+                    )
+                    .await?;
+                string(&data["commentCreate"]["comment"]["url"])
+            }
+        };
+
+        Ok(IssuesOutput {
+            workspace: workspace.name.clone(),
+            issues: Vec::new(),
+            comment_url: Some(comment_url),
+        })
+    }
+
+    fn jira(
+        &self,
+        workspace: &IssueWorkspace,
+        method: reqwest::Method,
+        path: &str,
+    ) -> reqwest::RequestBuilder {
+        let url = format!("{}{path}", workspace.url.as_deref().unwrap_or_default());
+        let request = self
+            .http
+            .request(method, url)
+            .header("Accept", "application/json");
+        match &workspace.email {
+            Some(email) => request.basic_auth(email, Some(&workspace.token)),
+            None => request.bearer_auth(&workspace.token),
+        }
+    }
+
+    /// Run a Linear GraphQL operation and return its `data`.
+    async fn linear(
+        &self,
+        workspace: &IssueWorkspace,
+        query: &str,
+        variables: Value,
+    ) -> Result<Value, IssuesError> {
+        let mut response = self
+            .send(
+                self.http
+                    .post(LINEAR_API_URL)
+                    .header("Authorization", &workspace.token)
+                    .json(&json!({"query": query, "variables": variables})),
+            )
+            .await?;
+        if let Some(errors) = response["errors"].as_array().filter(|e| !e.is_empty()) {
+            let messages: Vec<String> = errors.iter().map(|e| string(&e["message"])).collect();
+            return Err(IssuesError::new(format!(
+                "Linear returned errors: {}",
+                messages.join("; ")
+            )));
+        }
+        Ok(response["data"].take())
+    }
+
+    async fn send(&self, request: reqwest::RequestBuilder) -> Result<Value, IssuesError> {
+        let response = request
+            .send()
+            .await
+            .map_err(|error| IssuesError::new(format!("request failed: {error}")))?;
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            return Err(IssuesError::new(format!("HTTP {status}: {body}")));
+        }
+        response
+            .json()
+            .await
+            .map_err(|error| IssuesError::new(format!("invalid response: {error}")))
+    }
+}
+
+/// Render the string leaves of a workspace's `fields` as templates. Unknown
+/// variables are an error, so the agent learns which ones it has to pass.
+fn render_fields(
+    fields: &Map<String, Value>,
+    context: &BTreeMap<String, String>,
+) -> Result<Map<String, Value>, IssuesError> {
+    let mut env = minijinja::Environment::new();
+    env.set_undefined_behavior(minijinja::UndefinedBehavior::Strict);
+    let context = minijinja::Value::from_serialize(context);
+
+    fields
+        .iter()
+        .map(|(name, value)| {
+            render_value(&env, value, &context)
+                .map(|rendered| (name.clone(), rendered))
+                .map_err(|error| IssuesError::new(format!("can't fill in field '{name}': {error}")))
+        })
+        .collect()
+}
+
+fn render_value(
+    env: &minijinja::Environment<'_>,
+    value: &Value,
+    context: &minijinja::Value,
+) -> Result<Value, minijinja::Error> {
+    Ok(match value {
+        Value::String(template) => Value::String(env.render_str(template, context)?),
+        Value::Array(items) => Value::Array(
+            items
+                .iter()
+                .map(|item| render_value(env, item, context))
+                .collect::<Result<_, _>>()?,
+        ),
+        Value::Object(object) => Value::Object(
+            object
+                .iter()
+                .map(|(key, item)| Ok((key.clone(), render_value(env, item, context)?)))
+                .collect::<Result<_, minijinja::Error>>()?,
+        ),
+        other => other.clone(),
+    })
+}
+
+/// Variables the agent has to pass for a workspace's field templates.
+fn template_variables(fields: &Map<String, Value>) -> BTreeSet<String> {
+    fn collect(env: &minijinja::Environment<'_>, value: &Value, names: &mut BTreeSet<String>) {
+        match value {
+            Value::String(source) => {
+                if let Ok(template) = env.template_from_str(source) {
+                    names.extend(template.undeclared_variables(false));
+                }
+            }
+            Value::Array(items) => items.iter().for_each(|item| collect(env, item, names)),
+            Value::Object(object) => object.values().for_each(|item| collect(env, item, names)),
+            _ => {}
+        }
+    }
+
+    let env = minijinja::Environment::new();
+    let mut names = BTreeSet::new();
+    fields
+        .values()
+        .for_each(|value| collect(&env, value, &mut names));
+    names.retain(|name| !BUILTIN_VARIABLES.contains(&name.as_str()));
+    names
+}
+
+fn jira_browse_url(workspace: &IssueWorkspace, key: &str) -> String {
+    format!(
+        "{}/browse/{key}",
+        workspace.url.as_deref().unwrap_or_default()
+    )
+}
+
+/// Whether `key` looks like an issue key of `project` (e.g. "SUP-123").
+fn in_jira_project(key: &str, project: &str) -> bool {
+    key.strip_prefix(project)
+        .and_then(|rest| rest.strip_prefix('-'))
+        .is_some_and(|number| !number.is_empty() && number.chars().all(|c| c.is_ascii_digit()))
+}
+
+fn jql_escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+fn linear_issue(issue: &Value) -> Issue {
+    Issue {
+        key: string(&issue["identifier"]),
+        title: string(&issue["title"]),
+        url: string(&issue["url"]),
+        status: issue["state"]["name"].as_str().map(String::from),
+    }
+}
+
+fn string(value: &Value) -> String {
+    match value {
+        Value::String(value) => value.clone(),
+        Value::Null => String::new(),
+        other => other.to_string(),
+    }
+}
+
+/// Error type for issues tool.
+#[derive(Debug, thiserror::Error)]
+#[error("Issue tracker action failed: {message}")]
+pub struct IssuesError {
+    message: String,
+}
+
+impl IssuesError {
+    fn new(message: impl Into<String>) -> Self {
+        Self {
+            message: message.into(),
+        }
+    }
+}
+
+/// The action to perform.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum IssuesAction {
+    Create,
+    Search,
+    Comment,
+}
+
+/// Arguments for issues tool.
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct IssuesArgs {
+    pub action: IssuesAction,
+    /// Workspace name. Can be left out when only one is configured.
+    pub workspace: Option<String>,
+    /// Title of the issue to `create`.
+    pub title: Option<String>,
+    /// Description of the issue to `create`.
+    pub description: Option<String>,
+    /// Values for the workspace's field templates (e.g. `{"customer": "Acme"}`).
+    #[serde(default)]
+    pub fields: HashMap<String, String>,
+    /// Text to `search` for.
+    pub query: Option<String>,
+    /// Issue key to `comment` on (e.g. "SUP-123").
+    pub issue: Option<String>,
+    /// Text of the comment.
+    pub body: Option<String>,
+    /// Most results for `search` (1-50, default 10).
+    #[serde(default = "default_limit")]
+    pub limit: u32,
+}
+
+fn default_limit() -> u32 {
+    10
+}
+
+/// Output from issues tool.
+#[derive(Debug, Serialize)]
+pub struct IssuesOutput {
+    pub workspace: String,
+    /// The created issue, or the search results.
+    pub issues: Vec<Issue>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub comment_url: Option<String>,
+}
+
+/// An issue as returned to the agent.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Issue {
+    /// Jira key or Linear identifier (e.g. "SUP-123").
+    pub key: String,
+    pub title: String,
+    pub url: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<String>,
+}
+
+impl Tool for IssuesTool {
+    const NAME: &'static str = "issues";
+
+    type Error = IssuesError;
+    type Args = IssuesArgs;
+    type Output = IssuesOutput;
+
+    async fn definition(&self, _prompt: String) -> ToolDefinition {
+        let mut description = crate::prompts::text::get("tools/issues").to_string();
+        description.push_str("\n\nWorkspaces:");
+        for workspace in &self.workspaces {
+            description.push_str(&format!(
+                "\n- {} ({}, project {})",
+                workspace.name,
+                workspace.provider.as_str(),
+                workspace.project
+            ));
+            let variables = template_variables(&workspace.fields);
+            if !variables.is_empty() {
+                let variables: Vec<String> = variables.into_iter().collect();
+                description.push_str(&format!(
+                    "; `create` needs fields: {}",
+                    variables.join(", ")
+                ));
+            }
+        }
+
+        ToolDefinition {
+            name: Self::NAME.to_string(),
+            description,
+            parameters: json!({
+                "type": "object",
+                "properties": {
+                    "action": {
+                        "type": "string",
+                        "enum": ["create", "search", "comment"],
+                        "description": "create: file a new issue. search: find existing issues. comment: add a comment to an issue."
+                    },
+                    "workspace": {
+                        "type": "string",
+                        "description": "Workspace name. Can be left out when only one is configured."
+                    },
+                    "title": {
+                        "type": "string",
+                        "description": "Issue title, for create"
+                    },
+                    "description": {
+                        "type": "string",
+                        "description": "Issue description, for create"
+                    },
+                    "fields": {
+                        "type": "object",
+                        "additionalProperties": {"type": "string"},
+                        "description": "Values for the fields the workspace asks for, for create (e.g. {\"customer\": \"Acme\"})"
+                    },
+                    "query": {
+                        "type": "string",
+                        "description": "Text to search for, for search"
+                    },
+                    "issue": {
+                        "type": "string",
+                        "description": "Issue key to comment on (e.g. \"SUP-123\")"
+                    },
+                    "body": {
+                        "type": "string",
+                        "description": "Comment text, for comment"
+                    },
+                    "limit": {
+                        "type": "integer",
+                        "minimum": 1,
+                        "maximum": 50,
+                        "default": 10,
+                        "description": "Most results for search"
+                    }
+                },
+                "required": ["action"]
+            }),
+        }
+    }
+
+    async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
+        let workspace = self.workspace(args.workspace.as_deref())?;
+        let missing = |name: &str| IssuesError::new(format!("{name} is required"));
+
+        match args.action {
+            IssuesAction::Create => {
+                let title = args.title.ok_or_else(|| missing("title"))?;
+                let description = args.description.unwrap_or_default();
+                self.create(workspace, title, description, args.fields)
+                    .await
+            }
+            IssuesAction::Search => {
+                let query = args.query.ok_or_else(|| missing("query"))?;
+                self.search(workspace, &query, args.limit.clamp(1, 50))
+                    .await
+            }
+            IssuesAction::Comment => {
+                let issue = args.issue.ok_or_else(|| missing("issue"))?;
+                let body = args.body.ok_or_else(|| missing("body"))?;
+                self.comment(workspace, &issue, &body).await
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fields() -> Map<String, Value> {
+        serde_json::from_value(json!({
+            "issuetype": {"name": "Bug"},
+            "labels": ["support", "{{ severity }}"],
+            "customfield_10010": "{{ customer }}: {{ title }}",
+            "story_points": 3
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_render_fields_fills_templates() {
+        let context = BTreeMap::from([
+            ("title".to_string(), "Login fails".to_string()),
+            ("customer".to_string(), "Acme".to_string()),
+            ("severity".to_string(), "high".to_string()),
+        ]);
+
+        let rendered = render_fields(&fields(), &context).unwrap();
+
+        assert_eq!(
+            Value::Object(rendered),
+            json!({
+                "issuetype": {"name": "Bug"},
+                "labels": ["support", "high"],
+                "customfield_10010": "Acme: Login fails",
+                "story_points": 3
+            })
+        );
+    }
+
+    #[test]
+    fn test_render_fields_rejects_missing_variables() {
+        let context = BTreeMap::from([("title".to_string(), "Login fails".to_string())]);
+
+        let error = render_fields(&fields(), &context).unwrap_err();
+
+        assert!(error.to_string().contains("can't fill in field"));
+    }
+
+    #[test]
+    fn test_template_variables_skip_builtins() {
+        let variables: Vec<String> = template_variables(&fields()).into_iter().collect();
+        assert_eq!(variables, ["customer", "severity"]);
+    }
+
+    #[test]
+    fn test_in_jira_project() {
+        assert!(in_jira_project("SUP-123", "SUP"));
+        assert!(!in_jira_project("SUPPORT-123", "SUP"));
+        assert!(!in_jira_project("SUP-", "SUP"));
+        assert!(!in_jira_project("SUP-1/../../admin", "SUP"));
+    }
+}
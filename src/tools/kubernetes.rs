@@ -0,0 +1,1059 @@
+//! Kubernetes tool: list and describe resources, read pod logs and events
+//! in configured clusters (task workers only).
+//!
+//! Every call is limited to the cluster's namespace allowlist. Secrets and
+//! config maps are never read. Restarting and scaling workloads and deleting
+//! pods are refused unless the agent's `kubernetes.allow_writes` is on.
+
+use crate::config::{KubernetesCluster, KubernetesConfig};
+
+use chrono::{DateTime, Utc};
+use k8s_openapi::api::apps::v1 as apps;
+use k8s_openapi::api::autoscaling::v2 as autoscaling;
+use k8s_openapi::api::batch::v1 as batch;
+use k8s_openapi::api::core::v1 as corev1;
+use k8s_openapi::api::networking::v1 as networking;
+use kube::api::{
+    Api, ApiResource, DeleteParams, DynamicObject, ListParams, LogParams, Patch, PatchParams,
+};
+use kube::config::{KubeConfigOptions, Kubeconfig};
+use rig::completion::ToolDefinition;
+use rig::tool::Tool;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use std::path::Path;
+use std::time::Duration;
+
+/// Most bytes of log output returned by one `logs` call.
+const MAX_LOG_BYTES: i64 = 50_000;
+
+/// Events shown with a `describe`.
+const DESCRIBE_EVENTS: usize = 20;
+
+/// Annotation `kubectl rollout restart` sets to roll a workload's pods.
+const RESTARTED_AT_ANNOTATION: &str = "kubectl.kubernetes.io/restartedAt";
+
+/// Tool for inspecting, and when allowed changing, Kubernetes clusters.
+#[derive(Debug, Clone)]
+pub struct KubernetesTool {
+    config: KubernetesConfig,
+}
+
+impl KubernetesTool {
+    /// Relative kubeconfig paths are resolved against `instance_dir`.
+    pub fn new(mut config: KubernetesConfig, instance_dir: &Path) -> Self {
+        for cluster in &mut config.clusters {
+            if let Some(path) = &mut cluster.kubeconfig
+                && path.is_relative()
+            {
+                *path = instance_dir.join(&*path);
+            }
+        }
+        Self { config }
+    }
+
+    fn cluster(&self, name: Option<&str>) -> Result<&KubernetesCluster, KubernetesError> {
+        let found = match name {
+            Some(name) => self.config.clusters.iter().find(|c| c.name == name),
+            None if self.config.clusters.len() == 1 => self.config.clusters.first(),
+            None => None,
+        };
+        found.ok_or_else(|| {
+            let names: Vec<&str> = self
+                .config
+                .clusters
+                .iter()
+                .map(|c| c.name.as_str())
+                .collect();
+            KubernetesError(format!("pick a cluster, one of: {}", names.join(", ")))
+        })
+    }
+
+    async fn run(
+        &self,
+        cluster: &KubernetesCluster,
+        args: KubernetesArgs,
+    ) -> Result<KubernetesOutput, KubernetesError> {
+        let client = connect(cluster, Duration::from_secs(self.config.timeout_secs)).await?;
+        let namespace = pick_namespace(cluster, args.namespace.as_deref(), &client)?;
+        let kind = args.kind.unwrap_or(KubernetesKind::Pods);
+        let api = |kind: KubernetesKind| -> Api<DynamicObject> {
+            if kind.namespaced() {
+                Api::namespaced_with(client.clone(), &namespace, &kind.resource())
+            } else {
+                Api::all_with(client.clone(), &kind.resource())
+            }
+        };
+
+        match args.action {
+            KubernetesAction::Get => {
+                let mut params = ListParams::default().limit(self.config.max_objects as u32);
+                if let Some(selector) = &args.selector {
+                    params = params.labels(selector);
+                }
+                let list = api(kind).list(&params).await.map_err(api_error)?;
+                let truncated = list.metadata.continue_.is_some_and(|c| !c.is_empty());
+                let now = Utc::now();
+                let objects: Vec<Value> = list
+                    .items
+                    .into_iter()
+                    .map(|object| to_json(&object))
+                    .filter(|object| {
+                        kind != KubernetesKind::Namespaces
+                            || cluster.namespace_allowed(field_str(object, "/metadata/name"))
+                    })
+                    .map(|object| summarize(kind, &object, now))
+                    .collect();
+                let scope = if kind.namespaced() {
+                    format!(" in {namespace}")
+                } else {
+                    String::new()
+                };
+                Ok(KubernetesOutput {
+                    summary: format!(
+                        "{} {}{scope}{}",
+                        objects.len(),
+                        kind.as_str(),
+                        if truncated { " (more not shown)" } else { "" }
+                    ),
+                    objects,
+                    events: Vec::new(),
+                    logs: None,
+                })
+            }
+            KubernetesAction::Describe => {
+                let name = required(args.name, "name", "describe")?;
+                if kind == KubernetesKind::Namespaces && !cluster.namespace_allowed(&name) {
+                    return Err(KubernetesError(format!(
+                        "namespace {name} isn't allowed on cluster {}",
+                        cluster.name
+                    )));
+                }
+                let object = api(kind).get(&name).await.map_err(api_error)?;
+                let events = if kind.namespaced() {
+                    let selector = format!(
+                        "involvedObject.kind={},involvedObject.name={name}",
+                        kind.resource().kind
+                    );
+                    self.events(api(KubernetesKind::Events), &selector, DESCRIBE_EVENTS)
+                        .await?
+                } else {
+                    Vec::new()
+                };
+                Ok(KubernetesOutput {
+                    summary: format!("{} {name}", kind.singular()),
+                    objects: vec![strip_noise(to_json(&object))],
+                    events,
+                    logs: None,
+                })
+            }
+            KubernetesAction::Events => {
+                let mut selectors = Vec::new();
+                if let Some(name) = &args.name {
+                    selectors.push(format!("involvedObject.name={name}"));
+                }
+                if args.warnings_only.unwrap_or(false) {
+                    selectors.push("type=Warning".to_string());
+                }
+                let events = self
+                    .events(
+                        api(KubernetesKind::Events),
+                        &selectors.join(","),
+                        self.config.max_objects,
+                    )
+                    .await?;
+                Ok(KubernetesOutput {
+                    summary: format!("{} events in {namespace}", events.len()),
+                    objects: Vec::new(),
+                    events,
+                    logs: None,
+                })
+            }
+            KubernetesAction::Logs => {
+                let name = required(args.name, "name", "logs")?;
+                let max_lines = self.config.max_log_lines.max(1);
+                let params = LogParams {
+                    container: args.container,
+                    previous: args.previous.unwrap_or(false),
+                    since_seconds: args.since_seconds,
+                    tail_lines: Some(
+                        args.tail_lines.unwrap_or(max_lines).clamp(1, max_lines) as i64
+                    ),
+                    limit_bytes: Some(MAX_LOG_BYTES),
+                    timestamps: true,
+                    ..LogParams::default()
+                };
+                let logs = Api::<corev1::Pod>::namespaced(client.clone(), &namespace)
+                    .logs(&name, &params)
+                    .await
+                    .map_err(api_error)?;
+                Ok(KubernetesOutput {
+                    summary: format!("{} log lines from {name}", logs.lines().count()),
+                    objects: Vec::new(),
+                    events: Vec::new(),
+                    logs: Some(logs),
+                })
+            }
+            KubernetesAction::Restart => {
+                let name = required(args.name, "name", "restart")?;
+                if !kind.restartable() {
+                    return Err(KubernetesError(
+                        "restart works on deployments, statefulsets and daemonsets".into(),
+                    ));
+                }
+                let patch = json!({
+                    "spec": {"template": {"metadata": {"annotations": {
+                        RESTARTED_AT_ANNOTATION: Utc::now().to_rfc3339()
+                    }}}}
+                });
+                api(kind)
+                    .patch(&name, &PatchParams::default(), &Patch::Merge(&patch))
+                    .await
+                    .map_err(api_error)?;
+                Ok(KubernetesOutput::done(format!(
+                    "restarting {} {name} in {namespace}",
+                    kind.singular()
+                )))
+            }
+            KubernetesAction::Scale => {
+                let name = required(args.name, "name", "scale")?;
+                let replicas = args
+                    .replicas
+                    .ok_or_else(|| KubernetesError("scale needs replicas".into()))?;
+                if !kind.scalable() {
+                    return Err(KubernetesError(
+                        "scale works on deployments and statefulsets".into(),
+                    ));
+                }
+                let patch = json!({"spec": {"replicas": replicas}});
+                api(kind)
+                    .patch_scale(&name, &PatchParams::default(), &Patch::Merge(&patch))
+                    .await
+                    .map_err(api_error)?;
+                Ok(KubernetesOutput::done(format!(
+                    "scaled {} {name} in {namespace} to {replicas} replicas",
+                    kind.singular()
+                )))
+            }
+            KubernetesAction::DeletePod => {
+                let name = required(args.name, "name", "delete_pod")?;
+                api(KubernetesKind::Pods)
+                    .delete(&name, &DeleteParams::default())
+                    .await
+                    .map_err(api_error)?;
+                Ok(KubernetesOutput::done(format!(
+                    "deleted pod {name} in {namespace}"
+                )))
+            }
+        }
+    }
+
+    /// Events matching `field_selector`, newest first.
+    async fn events(
+        &self,
+        api: Api<DynamicObject>,
+        field_selector: &str,
+        limit: usize,
+    ) -> Result<Vec<Value>, KubernetesError> {
+        let mut params = ListParams::default();
+        if !field_selector.is_empty() {
+            params = params.fields(field_selector);
+        }
+        let list = api.list(&params).await.map_err(api_error)?;
+        let now = Utc::now();
+        let mut events: Vec<Value> = list.items.iter().map(to_json).collect();
+        events.sort_by_key(|event| std::cmp::Reverse(event_time(event)));
+        events.truncate(limit);
+        Ok(events
+            .iter()
+            .map(|event| summarize(KubernetesKind::Events, event, now))
+            .collect())
+    }
+}
+
+/// Error type for kubernetes tool.
+#[derive(Debug, thiserror::Error)]
+#[error("Kubernetes call failed: {0}")]
+pub struct KubernetesError(String);
+
+/// The action to perform.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum KubernetesAction {
+    Get,
+    Describe,
+    Logs,
+    Events,
+    Restart,
+    Scale,
+    DeletePod,
+}
+
+impl KubernetesAction {
+    fn writes(self) -> bool {
+        matches!(self, Self::Restart | Self::Scale | Self::DeletePod)
+    }
+}
+
+/// Resource types the tool reads. Secrets and config maps are left out on
+/// purpose.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum KubernetesKind {
+    Pods,
+    Deployments,
+    StatefulSets,
+    DaemonSets,
+    ReplicaSets,
+    Jobs,
+    CronJobs,
+    Services,
+    Ingresses,
+    PersistentVolumeClaims,
+    HorizontalPodAutoscalers,
+    Events,
+    Nodes,
+    Namespaces,
+}
+
+impl KubernetesKind {
+    const ALL: &[Self] = &[
+        Self::Pods,
+        Self::Deployments,
+        Self::StatefulSets,
+        Self::DaemonSets,
+        Self::ReplicaSets,
+        Self::Jobs,
+        Self::CronJobs,
+        Self::Services,
+        Self::Ingresses,
+        Self::PersistentVolumeClaims,
+        Self::HorizontalPodAutoscalers,
+        Self::Events,
+        Self::Nodes,
+        Self::Namespaces,
+    ];
+
+    fn resource(self) -> ApiResource {
+        match self {
+            Self::Pods => ApiResource::erase::<corev1::Pod>(&()),
+            Self::Deployments => ApiResource::erase::<apps::Deployment>(&()),
+            Self::StatefulSets => ApiResource::erase::<apps::StatefulSet>(&()),
+            Self::DaemonSets => ApiResource::erase::<apps::DaemonSet>(&()),
+            Self::ReplicaSets => ApiResource::erase::<apps::ReplicaSet>(&()),
+            Self::Jobs => ApiResource::erase::<batch::Job>(&()),
+            Self::CronJobs => ApiResource::erase::<batch::CronJob>(&()),
+            Self::Services => ApiResource::erase::<corev1::Service>(&()),
+            Self::Ingresses => ApiResource::erase::<networking::Ingress>(&()),
+            Self::PersistentVolumeClaims => {
+                ApiResource::erase::<corev1::PersistentVolumeClaim>(&())
+            }
+            Self::HorizontalPodAutoscalers => {
+                ApiResource::erase::<autoscaling::HorizontalPodAutoscaler>(&())
+            }
+            Self::Events => ApiResource::erase::<corev1::Event>(&()),
+            Self::Nodes => ApiResource::erase::<corev1::Node>(&()),
+            Self::Namespaces => ApiResource::erase::<corev1::Namespace>(&()),
+        }
+    }
+
+    fn namespaced(self) -> bool {
+        !matches!(self, Self::Nodes | Self::Namespaces)
+    }
+
+    fn restartable(self) -> bool {
+        matches!(
+            self,
+            Self::Deployments | Self::StatefulSets | Self::DaemonSets
+        )
+    }
+
+    fn scalable(self) -> bool {
+        matches!(self, Self::Deployments | Self::StatefulSets)
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Pods => "pods",
+            Self::Deployments => "deployments",
+            Self::StatefulSets => "statefulsets",
+            Self::DaemonSets => "daemonsets",
+            Self::ReplicaSets => "replicasets",
+            Self::Jobs => "jobs",
+            Self::CronJobs => "cronjobs",
+            Self::Services => "services",
+            Self::Ingresses => "ingresses",
+            Self::PersistentVolumeClaims => "persistentvolumeclaims",
+            Self::HorizontalPodAutoscalers => "horizontalpodautoscalers",
+            Self::Events => "events",
+            Self::Nodes => "nodes",
+            Self::Namespaces => "namespaces",
+        }
+    }
+
+    fn singular(self) -> String {
+        self.resource().kind.to_lowercase()
+    }
+}
+
+/// Arguments for kubernetes tool.
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct KubernetesArgs {
+    pub action: KubernetesAction,
+    /// Cluster name. Can be left out when only one is configured.
+    pub cluster: Option<String>,
+    /// Namespace. Defaults to the cluster's only allowed namespace, or the
+    /// kubeconfig's default.
+    pub namespace: Option<String>,
+    /// Resource type for `get`, `describe`, `restart` and `scale`. Defaults
+    /// to pods.
+    pub kind: Option<KubernetesKind>,
+    /// Object name. For `events`, only events about this object.
+    pub name: Option<String>,
+    /// Label selector for `get` (e.g. "app=checkout").
+    pub selector: Option<String>,
+    /// Container for `logs`, when the pod has more than one.
+    pub container: Option<String>,
+    /// Last lines of logs to return, up to the configured limit.
+    pub tail_lines: Option<usize>,
+    /// Only logs from the last this many seconds.
+    pub since_seconds: Option<i64>,
+    /// Logs of the previous, crashed container instead of the running one.
+    pub previous: Option<bool>,
+    /// For `events`, only warnings.
+    pub warnings_only: Option<bool>,
+    /// Replica count for `scale`.
+    pub replicas: Option<i32>,
+}
+
+/// Output from kubernetes tool.
+#[derive(Debug, Serialize)]
+pub struct KubernetesOutput {
+    pub summary: String,
+    /// One summary row per object for `get`, or the whole object for
+    /// `describe`.
+    pub objects: Vec<Value>,
+    /// Recent events, newest first.
+    pub events: Vec<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub logs: Option<String>,
+}
+
+impl KubernetesOutput {
+    fn done(summary: String) -> Self {
+        Self {
+            summary,
+            objects: Vec::new(),
+            events: Vec::new(),
+            logs: None,
+        }
+    }
+}
+
+impl Tool for KubernetesTool {
+    const NAME: &'static str = "kubernetes";
+
+    type Error = KubernetesError;
+    type Args = KubernetesArgs;
+    type Output = KubernetesOutput;
+
+    async fn definition(&self, _prompt: String) -> ToolDefinition {
+        let mut description = crate::prompts::text::get("tools/kubernetes").to_string();
+        description.push_str("\n\nClusters:");
+        for cluster in &self.config.clusters {
+            let namespaces = if cluster.namespaces.is_empty() {
+                "all namespaces".to_string()
+            } else {
+                format!("namespaces {}", cluster.namespaces.join(", "))
+            };
+            description.push_str(&format!("\n- {} ({namespaces})", cluster.name));
+            if let Some(about) = &cluster.description {
+                description.push_str(&format!(": {about}"));
+            }
+        }
+
+        let mut actions = vec!["get", "describe", "logs", "events"];
+        let mut action_help = "get: list objects of a kind. describe: one object in full, with its recent events. logs: a pod's logs. events: recent events in the namespace.".to_string();
+        if self.config.allow_writes {
+            actions.extend(["restart", "scale", "delete_pod"]);
+            action_help.push_str(" restart: roll a deployment, statefulset or daemonset's pods. scale: set a deployment or statefulset's replicas. delete_pod: delete a pod so its controller replaces it.");
+        }
+        let kinds: Vec<&str> = KubernetesKind::ALL
+            .iter()
+            .map(|kind| kind.as_str())
+            .collect();
+
+        ToolDefinition {
+            name: Self::NAME.to_string(),
+            description,
+            parameters: json!({
+                "type": "object",
+                "properties": {
+                    "action": {
+                        "type": "string",
+                        "enum": actions,
+                        "description": action_help
+                    },
+                    "cluster": {
+                        "type": "string",
+                        "description": "Cluster name. Can be left out when only one is configured."
+                    },
+                    "namespace": {
+                        "type": "string",
+                        "description": "Namespace. Defaults to the cluster's only allowed namespace, or its default one."
+                    },
+                    "kind": {
+                        "type": "string",
+                        "enum": kinds,
+                        "description": "Resource type for get, describe, restart and scale. Defaults to pods."
+                    },
+                    "name": {
+                        "type": "string",
+                        "description": "Object name for describe, logs, restart, scale and delete_pod. For events, only events about this object."
+                    },
+                    "selector": {
+                        "type": "string",
+                        "description": "Label selector for get (e.g. \"app=checkout,tier!=canary\")"
+                    },
+                    "container": {
+                        "type": "string",
+                        "description": "Container for logs, when the pod has more than one"
+                    },
+                    "tail_lines": {
+                        "type": "integer",
+                        "minimum": 1,
+                        "maximum": self.config.max_log_lines,
+                        "description": "Last lines of logs to return"
+                    },
+                    "since_seconds": {
+                        "type": "integer",
+                        "minimum": 1,
+                        "description": "Only logs from the last this many seconds"
+                    },
+                    "previous": {
+                        "type": "boolean",
+                        "description": "Logs of the previous container, e.g. the one that crashed before a restart"
+                    },
+                    "warnings_only": {
+                        "type": "boolean",
+                        "description": "For events, only warnings"
+                    },
+                    "replicas": {
+                        "type": "integer",
+                        "minimum": 0,
+                        "description": "Replica count for scale"
+                    }
+                },
+                "required": ["action"]
+            }),
+        }
+    }
+
+    async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
+        if args.action.writes() && !self.config.allow_writes {
+            return Err(KubernetesError(
+                "this agent's Kubernetes access is read-only".into(),
+            ));
+        }
+        let cluster = self.cluster(args.cluster.as_deref())?;
+        tracing::info!(
+            cluster = %cluster.name,
+            action = ?args.action,
+            kind = ?args.kind,
+            name = ?args.name,
+            "kubernetes tool called"
+        );
+
+        let timeout = Duration::from_secs(self.config.timeout_secs);
+        match tokio::time::timeout(timeout, self.run(cluster, args)).await {
+            Ok(result) => result,
+            Err(_) => Err(KubernetesError(format!(
+                "the cluster didn't answer within {}s",
+                self.config.timeout_secs
+            ))),
+        }
+    }
+}
+
+/// A client for `cluster`, from its kubeconfig and context, or from the
+/// environment when it names neither.
+async fn connect(
+    cluster: &KubernetesCluster,
+    timeout: Duration,
+) -> Result<kube::Client, KubernetesError> {
+    let options = KubeConfigOptions {
+        context: cluster.context.clone(),
+        ..KubeConfigOptions::default()
+    };
+    let mut config = match (&cluster.kubeconfig, &cluster.context) {
+        (Some(path), _) => {
+            let kubeconfig = Kubeconfig::read_from(path).map_err(|error| {
+                KubernetesError(format!("can't read {}: {error}", path.display()))
+            })?;
+            kube::Config::from_custom_kubeconfig(kubeconfig, &options)
+                .await
+                .map_err(|error| KubernetesError(error.to_string()))?
+        }
+        (None, Some(_)) => kube::Config::from_kubeconfig(&options)
+            .await
+            .map_err(|error| KubernetesError(error.to_string()))?,
+        (None, None) => kube::Config::infer()
+            .await
+            .map_err(|error| KubernetesError(error.to_string()))?,
+    };
+    config.connect_timeout = Some(timeout);
+    config.read_timeout = Some(timeout);
+    kube::Client::try_from(config).map_err(|error| KubernetesError(error.to_string()))
+}
+
+/// The namespace a call works in, if the cluster allows it.
+fn pick_namespace(
+    cluster: &KubernetesCluster,
+    requested: Option<&str>,
+    client: &kube::Client,
+) -> Result<String, KubernetesError> {
+    let namespace = match (requested, cluster.namespaces.as_slice()) {
+        (Some(namespace), _) => namespace,
+        (None, [only]) => only.as_str(),
+        (None, []) => client.default_namespace(),
+        (None, namespaces) => {
+            return Err(KubernetesError(format!(
+                "pick a namespace, one of: {}",
+                namespaces.join(", ")
+            )));
+        }
+    };
+    if !cluster.namespace_allowed(namespace) {
+        return Err(KubernetesError(format!(
+            "namespace {namespace} isn't allowed on cluster {}; allowed: {}",
+            cluster.name,
+            cluster.namespaces.join(", ")
+        )));
+    }
+    Ok(namespace.to_string())
+}
+
+fn required(value: Option<String>, name: &str, action: &str) -> Result<String, KubernetesError> {
+    value.ok_or_else(|| KubernetesError(format!("{action} needs {name}")))
+}
+
+fn api_error(error: kube::Error) -> KubernetesError {
+    KubernetesError(error.to_string())
+}
+
+fn to_json(object: &DynamicObject) -> Value {
+    serde_json::to_value(object).unwrap_or_default()
+}
+
+fn field_str<'a>(object: &'a Value, pointer: &str) -> &'a str {
+    object
+        .pointer(pointer)
+        .and_then(Value::as_str)
+        .unwrap_or_default()
+}
+
+fn field_i64(object: &Value, pointer: &str) -> i64 {
+    object
+        .pointer(pointer)
+        .and_then(Value::as_i64)
+        .unwrap_or_default()
+}
+
+/// `object` without the bookkeeping that makes a describe long and says
+/// nothing about its state.
+fn strip_noise(mut object: Value) -> Value {
+    if let Some(metadata) = object.get_mut("metadata").and_then(Value::as_object_mut) {
+        metadata.remove("managedFields");
+        if let Some(annotations) = metadata
+            .get_mut("annotations")
+            .and_then(Value::as_object_mut)
+        {
+            annotations.remove("kubectl.kubernetes.io/last-applied-configuration");
+        }
+    }
+    object
+}
+
+/// When an event last happened, as RFC 3339 text that sorts by time.
+fn event_time(event: &Value) -> String {
+    [
+        "/lastTimestamp",
+        "/eventTime",
+        "/firstTimestamp",
+        "/metadata/creationTimestamp",
+    ]
+    .iter()
+    .map(|pointer| field_str(event, pointer))
+    .find(|time| !time.is_empty())
+    .unwrap_or_default()
+    .to_string()
+}
+
+/// How long ago `timestamp` was, the way kubectl shows it ("3d4h", "12m").
+fn age(timestamp: &str, now: DateTime<Utc>) -> String {
+    let Ok(then) = DateTime::parse_from_rfc3339(timestamp) else {
+        return String::new();
+    };
+    let seconds = (now - then.with_timezone(&Utc)).num_seconds().max(0);
+    let (days, hours, minutes) = (seconds / 86_400, seconds / 3_600 % 24, seconds / 60 % 60);
+    match (days, hours, minutes) {
+        (0, 0, 0) => format!("{seconds}s"),
+        (0, 0, m) => format!("{m}m"),
+        (0, h, m) => format!("{h}h{m}m"),
+        (d, h, _) if d < 7 => format!("{d}d{h}h"),
+        (d, _, _) => format!("{d}d"),
+    }
+}
+
+/// One compact row for `object`, with the columns `kubectl get` would show
+/// for its kind.
+fn summarize(kind: KubernetesKind, object: &Value, now: DateTime<Utc>) -> Value {
+    let mut row = serde_json::Map::new();
+    row.insert("name".into(), field_str(object, "/metadata/name").into());
+    let created = field_str(object, "/metadata/creationTimestamp");
+    let replicas = || {
+        format!(
+            "{}/{}",
+            field_i64(object, "/status/readyReplicas"),
+            field_i64(object, "/spec/replicas")
+        )
+    };
+
+    match kind {
+        KubernetesKind::Pods => {
+            let statuses = object
+                .pointer("/status/containerStatuses")
+                .and_then(Value::as_array)
+                .map(Vec::as_slice)
+                .unwrap_or_default();
+            let ready = statuses
+                .iter()
+                .filter(|status| status.get("ready") == Some(&Value::Bool(true)))
+                .count();
+            let restarts: i64 = statuses
+                .iter()
+                .map(|status| field_i64(status, "/restartCount"))
+                .sum();
+            // A waiting or terminated container explains more than the phase,
+            // e.g. CrashLoopBackOff or OOMKilled.
+            let reason = statuses.iter().find_map(|status| {
+                ["/state/waiting/reason", "/state/terminated/reason"]
+                    .iter()
+                    .map(|pointer| field_str(status, pointer))
+                    .find(|reason| !reason.is_empty())
+            });
+            let status = if object.pointer("/metadata/deletionTimestamp").is_some() {
+                "Terminating"
+            } else {
+                reason.unwrap_or_else(|| field_str(object, "/status/phase"))
+            };
+            row.insert("ready".into(), format!("{ready}/{}", statuses.len()).into());
+            row.insert("status".into(), status.into());
+            row.insert("restarts".into(), restarts.into());
+            row.insert("node".into(), field_str(object, "/spec/nodeName").into());
+        }
+        KubernetesKind::Deployments => {
+            row.insert("ready".into(), replicas().into());
+            row.insert(
+                "up_to_date".into(),
+                field_i64(object, "/status/updatedReplicas").into(),
+            );
+            row.insert(
+                "available".into(),
+                field_i64(object, "/status/availableReplicas").into(),
+            );
+        }
+        KubernetesKind::StatefulSets | KubernetesKind::ReplicaSets => {
+            row.insert("ready".into(), replicas().into());
+        }
+        KubernetesKind::DaemonSets => {
+            row.insert(
+                "desired".into(),
+                field_i64(object, "/status/desiredNumberScheduled").into(),
+            );
+            row.insert(
+                "ready".into(),
+                field_i64(object, "/status/numberReady").into(),
+            );
+            row.insert(
+                "available".into(),
+                field_i64(object, "/status/numberAvailable").into(),
+            );
+        }
+        KubernetesKind::Jobs => {
+            row.insert(
+                "completions".into(),
+                format!(
+                    "{}/{}",
+                    field_i64(object, "/status/succeeded"),
+                    object
+                        .pointer("/spec/completions")
+                        .and_then(Value::as_i64)
+                        .unwrap_or(1)
+                )
+                .into(),
+            );
+            row.insert("failed".into(), field_i64(object, "/status/failed").into());
+        }
+        KubernetesKind::CronJobs => {
+            row.insert(
+                "schedule".into(),
+                field_str(object, "/spec/schedule").into(),
+            );
+            row.insert(
+                "suspended".into(),
+                object
+                    .pointer("/spec/suspend")
+                    .and_then(Value::as_bool)
+                    .unwrap_or(false)
+                    .into(),
+            );
+            row.insert(
+                "last_schedule".into(),
+                age(field_str(object, "/status/lastScheduleTime"), now).into(),
+            );
+        }
+        KubernetesKind::Services => {
+            let ports: Vec<String> = object
+                .pointer("/spec/ports")
+                .and_then(Value::as_array)
+                .into_iter()
+                .flatten()
+                .map(|port| {
+                    format!(
+                        "{}/{}",
+                        field_i64(port, "/port"),
+                        field_str(port, "/protocol")
+                    )
+                })
+                .collect();
+            row.insert("type".into(), field_str(object, "/spec/type").into());
+            row.insert(
+                "cluster_ip".into(),
+                field_str(object, "/spec/clusterIP").into(),
+            );
+            row.insert("ports".into(), ports.join(",").into());
+        }
+        KubernetesKind::Ingresses => {
+            let hosts: Vec<&str> = object
+                .pointer("/spec/rules")
+                .and_then(Value::as_array)
+                .into_iter()
+                .flatten()
+                .map(|rule| field_str(rule, "/host"))
+                .filter(|host| !host.is_empty())
+                .collect();
+            row.insert("hosts".into(), hosts.join(",").into());
+        }
+        KubernetesKind::PersistentVolumeClaims => {
+            row.insert("status".into(), field_str(object, "/status/phase").into());
+            row.insert(
+                "capacity".into(),
+                field_str(object, "/status/capacity/storage").into(),
+            );
+            row.insert(
+                "volume".into(),
+                field_str(object, "/spec/volumeName").into(),
+            );
+        }
+        KubernetesKind::HorizontalPodAutoscalers => {
+            row.insert(
+                "target".into(),
+                field_str(object, "/spec/scaleTargetRef/name").into(),
+            );
+            row.insert(
+                "replicas".into(),
+                format!(
+                    "{} ({}-{})",
+                    field_i64(object, "/status/currentReplicas"),
+                    object
+                        .pointer("/spec/minReplicas")
+                        .and_then(Value::as_i64)
+                        .unwrap_or(1),
+                    field_i64(object, "/spec/maxReplicas")
+                )
+                .into(),
+            );
+        }
+        KubernetesKind::Events => {
+            row.insert("type".into(), field_str(object, "/type").into());
+            row.insert("reason".into(), field_str(object, "/reason").into());
+            row.insert(
+                "object".into(),
+                format!(
+                    "{}/{}",
+                    field_str(object, "/involvedObject/kind").to_lowercase(),
+                    field_str(object, "/involvedObject/name")
+                )
+                .into(),
+            );
+            row.insert("message".into(), field_str(object, "/message").into());
+            row.insert(
+                "count".into(),
+                object
+                    .pointer("/count")
+                    .and_then(Value::as_i64)
+                    .unwrap_or(1)
+                    .into(),
+            );
+            row.insert("last_seen".into(), age(&event_time(object), now).into());
+            return Value::Object(row);
+        }
+        KubernetesKind::Nodes => {
+            let ready = object
+                .pointer("/status/conditions")
+                .and_then(Value::as_array)
+                .into_iter()
+                .flatten()
+                .find(|condition| field_str(condition, "/type") == "Ready")
+                .map(|condition| field_str(condition, "/status") == "True");
+            let mut status = match ready {
+                Some(true) => "Ready",
+                Some(false) => "NotReady",
+                None => "Unknown",
+            }
+            .to_string();
+            if object.pointer("/spec/unschedulable") == Some(&Value::Bool(true)) {
+                status.push_str(",SchedulingDisabled");
+            }
+            let roles: Vec<&str> = object
+                .pointer("/metadata/labels")
+                .and_then(Value::as_object)
+                .into_iter()
+                .flat_map(|labels| labels.keys())
+                .filter_map(|label| label.strip_prefix("node-role.kubernetes.io/"))
+                .collect();
+            row.insert("status".into(), status.into());
+            row.insert("roles".into(), roles.join(",").into());
+            row.insert(
+                "version".into(),
+                field_str(object, "/status/nodeInfo/kubeletVersion").into(),
+            );
+        }
+        KubernetesKind::Namespaces => {
+            row.insert("status".into(), field_str(object, "/status/phase").into());
+        }
+    }
+
+    row.insert("age".into(), age(created, now).into());
+    Value::Object(row)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn now() -> DateTime<Utc> {
+        "2026-03-02T12:00:00Z".parse().unwrap()
+    }
+
+    fn cluster(namespaces: &[&str]) -> KubernetesCluster {
+        KubernetesCluster {
+            name: "prod".into(),
+            kubeconfig: None,
+            context: None,
+            namespaces: namespaces.iter().map(|n| n.to_string()).collect(),
+            description: None,
+        }
+    }
+
+    #[test]
+    fn test_summarize_pod_shows_crash_reason() {
+        let pod = json!({
+            "metadata": {"name": "checkout-7d9f", "creationTimestamp": "2026-02-28T10:00:00Z"},
+            "spec": {"nodeName": "node-a"},
+            "status": {
+                "phase": "Running",
+                "containerStatuses": [
+                    {"ready": true, "restartCount": 0, "state": {"running": {}}},
+                    {"ready": false, "restartCount": 7, "state": {"waiting": {"reason": "CrashLoopBackOff"}}}
+                ]
+            }
+        });
+
+        assert_eq!(
+            summarize(KubernetesKind::Pods, &pod, now()),
+            json!({
+                "name": "checkout-7d9f",
+                "ready": "1/2",
+                "status": "CrashLoopBackOff",
+                "restarts": 7,
+                "node": "node-a",
+                "age": "2d2h"
+            })
+        );
+    }
+
+    #[test]
+    fn test_summarize_node_and_event() {
+        let node = json!({
+            "metadata": {
+                "name": "node-a",
+                "creationTimestamp": "2026-01-01T00:00:00Z",
+                "labels": {"node-role.kubernetes.io/control-plane": ""}
+            },
+            "spec": {"unschedulable": true},
+            "status": {
+                "conditions": [{"type": "Ready", "status": "False"}],
+                "nodeInfo": {"kubeletVersion": "v1.33.1"}
+            }
+        });
+        let row = summarize(KubernetesKind::Nodes, &node, now());
+        assert_eq!(row["status"], "NotReady,SchedulingDisabled");
+        assert_eq!(row["roles"], "control-plane");
+        assert_eq!(row["age"], "60d");
+
+        let event = json!({
+            "metadata": {"name": "checkout.1", "creationTimestamp": "2026-03-02T11:00:00Z"},
+            "type": "Warning",
+            "reason": "BackOff",
+            "message": "Back-off restarting failed container",
+            "involvedObject": {"kind": "Pod", "name": "checkout-7d9f"},
+            "count": 12,
+            "lastTimestamp": "2026-03-02T11:58:30Z"
+        });
+        let row = summarize(KubernetesKind::Events, &event, now());
+        assert_eq!(row["object"], "pod/checkout-7d9f");
+        assert_eq!(row["last_seen"], "1m");
+        assert_eq!(row["count"], 12);
+    }
+
+    #[test]
+    fn test_namespace_allowlist() {
+        let cluster = cluster(&["payments", "checkout"]);
+        assert!(cluster.namespace_allowed("payments"));
+        assert!(!cluster.namespace_allowed("kube-system"));
+        assert!(self::cluster(&[]).namespace_allowed("kube-system"));
+    }
+
+    #[tokio::test]
+    async fn test_writes_refused_when_read_only() {
+        let tool = KubernetesTool::new(
+            KubernetesConfig {
+                enabled: true,
+                clusters: vec![cluster(&["payments"])],
+                ..KubernetesConfig::default()
+            },
+            Path::new("/tmp"),
+        );
+        let error = tool
+            .call(KubernetesArgs {
+                action: KubernetesAction::DeletePod,
+                cluster: None,
+                namespace: None,
+                kind: None,
+                name: Some("checkout-7d9f".into()),
+                selector: None,
+                container: None,
+                tail_lines: None,
+                since_seconds: None,
+                previous: None,
+                warnings_only: None,
+                replicas: None,
+            })
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("read-only"));
+    }
+}
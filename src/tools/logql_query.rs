@@ -0,0 +1,171 @@
+//! LogQL query tool: logs and log metrics from configured Loki endpoints
+//! (task workers only).
+//!
+//! Queries share `promql_query`'s time range guards. Log queries return the
+//! newest `max_log_lines` lines; metric queries such as `rate(...)` return
+//! series shaped like `promql_query`'s.
+
+use crate::config::{ObservabilityBackend, ObservabilityConfig};
+use crate::observability::{Client, LogLine, Series, TimeRange, shape};
+use crate::tools::promql_query::{describe_sources, pick_source};
+
+use chrono::Utc;
+use rig::completion::ToolDefinition;
+use rig::tool::Tool;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::time::Duration;
+
+/// Tool for querying logs with LogQL.
+#[derive(Debug, Clone)]
+pub struct LogqlQueryTool {
+    config: ObservabilityConfig,
+    proxy: Option<String>,
+}
+
+impl LogqlQueryTool {
+    pub fn new(config: ObservabilityConfig) -> Self {
+        Self {
+            config,
+            proxy: None,
+        }
+    }
+
+    /// Send queries through `proxy`.
+    pub fn with_proxy(mut self, proxy: Option<String>) -> Self {
+        self.proxy = proxy;
+        self
+    }
+}
+
+/// Error type for logql_query tool.
+#[derive(Debug, thiserror::Error)]
+#[error("LogQL query failed: {0}")]
+pub struct LogqlQueryError(String);
+
+/// Arguments for logql_query tool.
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct LogqlQueryArgs {
+    /// Source name. Can be left out when only one is configured.
+    pub source: Option<String>,
+    /// LogQL expression.
+    pub query: String,
+    /// How far back to look, e.g. "1h" or "30m". Defaults to 1h.
+    pub range: Option<String>,
+    /// End of the range as an RFC 3339 time. Defaults to now.
+    pub end: Option<String>,
+    /// Resolution of metric queries, e.g. "1m".
+    pub step: Option<String>,
+    /// Most log lines to return, up to the configured limit.
+    pub limit: Option<usize>,
+}
+
+/// Output from logql_query tool.
+#[derive(Debug, Serialize)]
+pub struct LogqlQueryOutput {
+    pub source: String,
+    pub start: String,
+    pub end: String,
+    /// Log lines, newest first. Empty for metric queries.
+    pub lines: Vec<LogLine>,
+    /// Series of a metric query, highest peaks first.
+    pub series: Vec<Series>,
+    /// Whether lines or series were left out to stay within the limits.
+    pub truncated: bool,
+}
+
+impl Tool for LogqlQueryTool {
+    const NAME: &'static str = "logql_query";
+
+    type Error = LogqlQueryError;
+    type Args = LogqlQueryArgs;
+    type Output = LogqlQueryOutput;
+
+    async fn definition(&self, _prompt: String) -> ToolDefinition {
+        let mut description = crate::prompts::text::get("tools/logql_query").to_string();
+        description.push_str(&describe_sources(&self.config, ObservabilityBackend::Loki));
+
+        ToolDefinition {
+            name: Self::NAME.to_string(),
+            description,
+            parameters: json!({
+                "type": "object",
+                "properties": {
+                    "source": {
+                        "type": "string",
+                        "description": "Source name. Can be left out when only one is configured."
+                    },
+                    "query": {
+                        "type": "string",
+                        "description": "LogQL expression, e.g. {app=\"checkout\"} |= \"error\" or sum by (app) (rate({env=\"prod\"} |= \"timeout\" [5m]))"
+                    },
+                    "range": {
+                        "type": "string",
+                        "description": format!("How far back to look, e.g. \"1h\" or \"30m\". Defaults to 1h, at most {}h.", self.config.max_range_hours)
+                    },
+                    "end": {
+                        "type": "string",
+                        "description": "End of the range as an RFC 3339 time. Defaults to now."
+                    },
+                    "step": {
+                        "type": "string",
+                        "description": "Resolution of metric queries, e.g. \"1m\""
+                    },
+                    "limit": {
+                        "type": "integer",
+                        "minimum": 1,
+                        "maximum": self.config.max_log_lines,
+                        "description": "Most log lines to return"
+                    }
+                },
+                "required": ["query"]
+            }),
+        }
+    }
+
+    async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
+        let source = pick_source(
+            &self.config,
+            ObservabilityBackend::Loki,
+            args.source.as_deref(),
+        )
+        .map_err(LogqlQueryError)?;
+        let range = TimeRange::new(
+            args.range.as_deref(),
+            args.end.as_deref(),
+            args.step.as_deref(),
+            &self.config,
+            Utc::now(),
+        )
+        .map_err(LogqlQueryError)?;
+        let max_lines = self.config.max_log_lines.max(1);
+        let limit = args.limit.unwrap_or(max_lines).clamp(1, max_lines);
+        tracing::info!(source = %source.name, query = %args.query, "logql_query tool called");
+
+        let client = Client::new(
+            source,
+            Duration::from_secs(self.config.timeout_secs),
+            self.proxy.clone(),
+        )
+        .map_err(|error| LogqlQueryError(error.to_string()))?;
+        let result = client
+            .loki_range(&args.query, &range, limit)
+            .await
+            .map_err(|error| LogqlQueryError(error.to_string()))?;
+        let (series, series_truncated) = shape(
+            result.series,
+            self.config.max_series,
+            self.config.max_points,
+        );
+
+        Ok(LogqlQueryOutput {
+            source: source.name.clone(),
+            start: range.start.to_rfc3339(),
+            end: range.end.to_rfc3339(),
+            truncated: series_truncated || result.lines.len() >= limit,
+            lines: result.lines,
+            series,
+        })
+    }
+}
@@ -0,0 +1,378 @@
+//! Network policy enforcement for worker tools.
+//!
+//! A tool with a [`ToolNetworkPolicy`] gets a forward proxy of its own on a
+//! loopback port, and its traffic is pointed there: reqwest clients through
+//! their proxy setting, Chrome with `--proxy-server`, and shell and exec
+//! commands through the `HTTP_PROXY` family of variables. The proxy checks
+//! each connection's host against the policy and counts the bytes sent
+//! upstream, so a prompt-injected tool can't reach hosts outside its
+//! allowlist or upload more than its limit.
+//!
+//! Policies are read on every connection, so edits apply without
+//! restarting. Only the proxy's port is fixed per tool. Commands that ignore
+//! the proxy variables aren't covered; deny their network at the OS level.
+
+use crate::config::{RuntimeConfig, ToolNetworkPolicy};
+
+use anyhow::{Context as _, bail};
+use tokio::io::{AsyncRead, AsyncReadExt as _, AsyncWrite, AsyncWriteExt as _};
+use tokio::net::{TcpListener, TcpStream};
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+
+/// Longest request head the proxy reads before giving up.
+const MAX_HEAD_BYTES: usize = 16 * 1024;
+
+/// Where a tool's traffic goes when its proxy couldn't start or is invalid:
+/// the discard port, so the tool fails closed instead of going out unchecked.
+const UNREACHABLE_PROXY: &str = "http://127.0.0.1:9";
+
+/// The per-tool proxies of one agent.
+#[derive(Clone)]
+pub struct NetworkSandbox {
+    runtime_config: Arc<RuntimeConfig>,
+    proxies: Arc<Mutex<HashMap<String, SocketAddr>>>,
+}
+
+impl NetworkSandbox {
+    pub fn new(runtime_config: Arc<RuntimeConfig>) -> Self {
+        Self {
+            runtime_config,
+            proxies: Arc::default(),
+        }
+    }
+
+    /// The proxy URL `tool_name`'s traffic must go through, starting its
+    /// proxy on first use. None when the tool has no policy.
+    pub fn proxy_for(&self, tool_name: &str) -> Option<String> {
+        if !self
+            .runtime_config
+            .network
+            .load()
+            .tools
+            .contains_key(tool_name)
+        {
+            return None;
+        }
+        if let Some(address) = self.lock().get(tool_name) {
+            return Some(format!("http://{address}"));
+        }
+
+        let bound = std::net::TcpListener::bind(("127.0.0.1", 0)).and_then(|listener| {
+            listener.set_nonblocking(true)?;
+            let address = listener.local_addr()?;
+            Ok((TcpListener::from_std(listener)?, address))
+        });
+        let (listener, address) = match bound {
+            Ok(bound) => bound,
+            Err(error) => {
+                tracing::error!(%error, tool_name, "failed to start tool network proxy");
+                return Some(UNREACHABLE_PROXY.into());
+            }
+        };
+        let address = *self.lock().entry(tool_name.to_string()).or_insert_with(|| {
+            tokio::spawn(serve(
+                listener,
+                tool_name.to_string(),
+                self.runtime_config.clone(),
+            ));
+            address
+        });
+        tracing::debug!(tool_name, %address, "tool network proxy started");
+        Some(format!("http://{address}"))
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, SocketAddr>> {
+        self.proxies
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// A reqwest proxy sending a tool's requests to `proxy`, or to
+/// [`UNREACHABLE_PROXY`] when `proxy` is invalid.
+pub fn reqwest_proxy(proxy: &str) -> reqwest::Proxy {
+    reqwest::Proxy::all(proxy).unwrap_or_else(|error| {
+        tracing::error!(%error, "invalid tool network proxy");
+        reqwest::Proxy::all(UNREACHABLE_PROXY).expect("hardcoded proxy url")
+    })
+}
+
+/// Environment variables that send a command's HTTP traffic to `proxy`.
+pub fn proxy_env(proxy: &str) -> Vec<(&'static str, String)> {
+    [
+        "HTTP_PROXY",
+        "HTTPS_PROXY",
+        "ALL_PROXY",
+        "http_proxy",
+        "https_proxy",
+        "all_proxy",
+    ]
+    .into_iter()
+    .map(|key| (key, proxy.to_string()))
+    .chain([("NO_PROXY", String::new()), ("no_proxy", String::new())])
+    .collect()
+}
+
+async fn serve(listener: TcpListener, tool_name: String, runtime_config: Arc<RuntimeConfig>) {
+    loop {
+        let client = match listener.accept().await {
+            Ok((client, _)) => client,
+            Err(error) => {
+                tracing::warn!(%error, %tool_name, "tool network proxy failed to accept");
+                continue;
+            }
+        };
+        let policy = runtime_config
+            .network
+            .load()
+            .tools
+            .get(&tool_name)
+            .cloned()
+            .unwrap_or_default();
+        let tool_name = tool_name.clone();
+        tokio::spawn(async move {
+            if let Err(error) = handle(client, &policy).await {
+                tracing::warn!(%error, %tool_name, "tool network request blocked or failed");
+            }
+        });
+    }
+}
+
+/// A request's destination, parsed from its head.
+#[derive(Debug)]
+struct Target {
+    host: String,
+    port: u16,
+    /// A `CONNECT` tunnel rather than a plain HTTP request.
+    tunnel: bool,
+    /// For plain requests, the head to send upstream, in origin form.
+    head: Vec<u8>,
+    content_length: Option<u64>,
+}
+
+async fn handle(mut client: TcpStream, policy: &ToolNetworkPolicy) -> anyhow::Result<()> {
+    let (head, rest) = read_head(&mut client).await?;
+    let target = match parse_head(&head) {
+        Ok(target) => target,
+        Err(error) => {
+            respond(&mut client, "400 Bad Request").await;
+            return Err(error);
+        }
+    };
+
+    if !policy.allows_host(&target.host) {
+        respond(&mut client, "403 Forbidden").await;
+        bail!("{} isn't allowed by the tool's network policy", target.host);
+    }
+    let mut budget = policy.max_request_bytes;
+    let upfront = target.head.len() as u64 + target.content_length.unwrap_or(0);
+    if budget.is_some_and(|limit| upfront > limit) {
+        respond(&mut client, "413 Payload Too Large").await;
+        bail!("request to {} is over max_request_bytes", target.host);
+    }
+
+    let mut upstream = match TcpStream::connect((target.host.as_str(), target.port)).await {
+        Ok(upstream) => upstream,
+        Err(error) => {
+            respond(&mut client, "502 Bad Gateway").await;
+            return Err(error).with_context(|| format!("failed to connect to {}", target.host));
+        }
+    };
+
+    if target.tunnel {
+        client
+            .write_all(b"HTTP/1.1 200 Connection Established\r\n\r\n")
+            .await?;
+    } else {
+        upstream.write_all(&target.head).await?;
+        budget = budget.map(|limit| limit - target.head.len() as u64);
+    }
+    if !rest.is_empty() {
+        if budget.is_some_and(|limit| rest.len() as u64 > limit) {
+            bail!("request to {} is over max_request_bytes", target.host);
+        }
+        upstream.write_all(&rest).await?;
+        budget = budget.map(|limit| limit - rest.len() as u64);
+    }
+
+    let (mut client_read, mut client_write) = client.into_split();
+    let (mut upstream_read, mut upstream_write) = upstream.into_split();
+    let host = target.host;
+    tokio::select! {
+        sent = copy_limited(&mut client_read, &mut upstream_write, budget) => {
+            sent.with_context(|| format!("upload to {host} cut off"))
+        }
+        received = tokio::io::copy(&mut upstream_read, &mut client_write) => {
+            received.map(|_| ()).map_err(Into::into)
+        }
+    }
+}
+
+/// Read up to the end of the request head. Returns the head and whatever
+/// was read past it.
+async fn read_head(client: &mut TcpStream) -> anyhow::Result<(Vec<u8>, Vec<u8>)> {
+    let mut buffer = Vec::new();
+    let mut chunk = [0; 4096];
+    loop {
+        let read = client.read(&mut chunk).await?;
+        if read == 0 {
+            bail!("connection closed before the request head ended");
+        }
+        buffer.extend_from_slice(&chunk[..read]);
+        if let Some(end) = buffer.windows(4).position(|window| window == b"\r\n\r\n") {
+            let rest = buffer.split_off(end + 4);
+            return Ok((buffer, rest));
+        }
+        if buffer.len() > MAX_HEAD_BYTES {
+            bail!("request head is too long");
+        }
+    }
+}
+
+fn parse_head(head: &[u8]) -> anyhow::Result<Target> {
+    let text = std::str::from_utf8(head).context("request head isn't UTF-8")?;
+    let mut lines = text.split("\r\n");
+    let request_line = lines.next().unwrap_or_default();
+    let mut parts = request_line.split(' ');
+    let (Some(method), Some(target), Some(version)) = (parts.next(), parts.next(), parts.next())
+    else {
+        bail!("malformed request line");
+    };
+
+    let content_length = text.split("\r\n").skip(1).find_map(|line| {
+        let (name, value) = line.split_once(':')?;
+        name.trim()
+            .eq_ignore_ascii_case("content-length")
+            .then(|| value.trim().parse().ok())
+            .flatten()
+    });
+
+    if method.eq_ignore_ascii_case("CONNECT") {
+        let (host, port) = target
+            .rsplit_once(':')
+            .context("CONNECT target has no port")?;
+        return Ok(Target {
+            host: host.trim_matches(['[', ']']).to_string(),
+            port: port.parse().context("invalid CONNECT port")?,
+            tunnel: true,
+            head: Vec::new(),
+            content_length: None,
+        });
+    }
+
+    let url = reqwest::Url::parse(target).context("proxy requests need an absolute URL")?;
+    if url.scheme() != "http" {
+        bail!("only http:// requests can be proxied without CONNECT");
+    }
+    let host = url.host_str().context("URL has no host")?.to_string();
+    let port = url.port_or_known_default().unwrap_or(80);
+    let path = match url.query() {
+        Some(query) => format!("{}?{query}", url.path()),
+        None => url.path().to_string(),
+    };
+
+    let mut rewritten = format!("{method} {path} {version}\r\n");
+    for line in lines.filter(|line| !line.is_empty()) {
+        let name = line.split(':').next().unwrap_or_default().trim();
+        if name.eq_ignore_ascii_case("proxy-connection")
+            || name.eq_ignore_ascii_case("proxy-authorization")
+        {
+            continue;
+        }
+        rewritten.push_str(line);
+        rewritten.push_str("\r\n");
+    }
+    rewritten.push_str("\r\n");
+
+    Ok(Target {
+        host: host.trim_matches(['[', ']']).to_string(),
+        port,
+        tunnel: false,
+        head: rewritten.into_bytes(),
+        content_length,
+    })
+}
+
+/// Copy `reader` to `writer`, failing once more than `limit` bytes were read.
+async fn copy_limited<R, W>(
+    reader: &mut R,
+    writer: &mut W,
+    limit: Option<u64>,
+) -> anyhow::Result<()>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let mut sent = 0u64;
+    let mut chunk = [0; 8192];
+    loop {
+        let read = reader.read(&mut chunk).await?;
+        if read == 0 {
+            writer.shutdown().await.ok();
+            return Ok(());
+        }
+        sent += read as u64;
+        if limit.is_some_and(|limit| sent > limit) {
+            bail!("over max_request_bytes");
+        }
+        writer.write_all(&chunk[..read]).await?;
+    }
+}
+
+async fn respond(client: &mut TcpStream, status: &str) {
+    let response = format!("HTTP/1.1 {status}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n");
+    client.write_all(response.as_bytes()).await.ok();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::NetworkAccess;
+
+    #[test]
+    fn test_allowlist_matches_subdomains_only() {
+        let policy = ToolNetworkPolicy {
+            access: NetworkAccess::Allowlist,
+            allow: vec!["brave.com".into(), "*.github.io".into()],
+            max_request_bytes: None,
+        };
+        assert!(policy.allows_host("brave.com"));
+        assert!(policy.allows_host("API.search.brave.com."));
+        assert!(policy.allows_host("docs.github.io"));
+        assert!(!policy.allows_host("evilbrave.com"));
+        assert!(!policy.allows_host("brave.com.evil.net"));
+        assert!(!policy.allows_host("203.0.113.7"));
+
+        let deny = ToolNetworkPolicy {
+            access: NetworkAccess::None,
+            ..policy
+        };
+        assert!(!deny.allows_host("brave.com"));
+    }
+
+    #[test]
+    fn test_parse_connect_and_absolute_requests() {
+        let connect =
+            parse_head(b"CONNECT api.search.brave.com:443 HTTP/1.1\r\nHost: x\r\n\r\n").unwrap();
+        assert_eq!(connect.host, "api.search.brave.com");
+        assert_eq!(connect.port, 443);
+        assert!(connect.tunnel);
+
+        let plain = parse_head(
+            b"POST http://example.com:8080/upload?x=1 HTTP/1.1\r\nHost: example.com\r\n\
+              Proxy-Connection: keep-alive\r\nContent-Length: 12\r\n\r\n",
+        )
+        .unwrap();
+        assert_eq!((plain.host.as_str(), plain.port), ("example.com", 8080));
+        assert_eq!(plain.content_length, Some(12));
+        assert_eq!(
+            String::from_utf8(plain.head).unwrap(),
+            "POST /upload?x=1 HTTP/1.1\r\nHost: example.com\r\nContent-Length: 12\r\n\r\n"
+        );
+
+        assert!(parse_head(b"GET /relative HTTP/1.1\r\n\r\n").is_err());
+    }
+}
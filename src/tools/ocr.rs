@@ -0,0 +1,475 @@
+//! OCR tool for reading text out of images (task workers only).
+//!
+//! Text comes back line by line with bounding boxes, so a worker can find a
+//! label in a screenshot without spending a vision turn of its own. The
+//! vision backend asks a model for a transcription; the tesseract backend
+//! runs locally and is built with the `ocr-tesseract` feature. When the
+//! configured backend fails, the other one is tried if `fallback` is set.
+
+use crate::ProcessType;
+use crate::config::{OcrBackend, OcrConfig};
+use crate::llm::{LlmManager, Priority, RoutingConfig, SpacebotModel};
+
+use base64::Engine as _;
+use rig::agent::AgentBuilder;
+use rig::completion::{Prompt as _, ToolDefinition};
+use rig::message::{ImageMediaType, Message, UserContent};
+use rig::one_or_many::OneOrMany;
+use rig::tool::Tool;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Largest image the tool reads.
+const MAX_IMAGE_BYTES: u64 = 20 * 1024 * 1024;
+
+/// Image types the tool accepts, by extension.
+const IMAGE_TYPES: &[(&str, &str)] = &[
+    ("png", "image/png"),
+    ("jpg", "image/jpeg"),
+    ("jpeg", "image/jpeg"),
+    ("gif", "image/gif"),
+    ("webp", "image/webp"),
+    ("bmp", "image/bmp"),
+    ("tif", "image/tiff"),
+    ("tiff", "image/tiff"),
+];
+
+/// Image types vision models read.
+const VISION_TYPES: &[&str] = &["image/png", "image/jpeg", "image/gif", "image/webp"];
+
+/// Tool for extracting text from images.
+#[derive(Clone)]
+pub struct OcrTool {
+    config: OcrConfig,
+    llm_manager: Arc<LlmManager>,
+    routing: RoutingConfig,
+    workspace: PathBuf,
+}
+
+impl OcrTool {
+    pub fn new(
+        config: OcrConfig,
+        llm_manager: Arc<LlmManager>,
+        routing: RoutingConfig,
+        workspace: PathBuf,
+    ) -> Self {
+        Self {
+            config,
+            llm_manager,
+            routing,
+            workspace,
+        }
+    }
+
+    async fn recognize(
+        &self,
+        backend: OcrBackend,
+        path: &Path,
+        mime_type: &str,
+    ) -> Result<Vec<OcrLine>, OcrError> {
+        match backend {
+            OcrBackend::Vision => self.vision(path, mime_type).await,
+            OcrBackend::Tesseract => self.tesseract(path).await,
+        }
+    }
+
+    async fn vision(&self, path: &Path, mime_type: &str) -> Result<Vec<OcrLine>, OcrError> {
+        if !VISION_TYPES.contains(&mime_type) {
+            return Err(OcrError::new(
+                "vision models only read PNG, JPEG, GIF and WebP images",
+            ));
+        }
+        let bytes = tokio::fs::read(path)
+            .await
+            .map_err(|error| OcrError::new(format!("failed to read image: {error}")))?;
+
+        let model_name = self
+            .config
+            .model
+            .clone()
+            .unwrap_or_else(|| self.routing.resolve(ProcessType::Worker, None).to_string());
+        let model = SpacebotModel::make(&self.llm_manager, &model_name)
+            .with_routing(self.routing.clone())
+            .with_priority(Priority::for_process(ProcessType::Worker));
+        let agent = AgentBuilder::new(model)
+            .preamble(crate::prompts::text::get("ocr"))
+            .build();
+
+        let mut history = vec![Message::User {
+            content: OneOrMany::one(UserContent::image_base64(
+                base64::engine::general_purpose::STANDARD.encode(&bytes),
+                ImageMediaType::from_mime_type(mime_type),
+                None,
+            )),
+        }];
+        let response = agent
+            .prompt("Transcribe the text in this image.")
+            .with_history(&mut history)
+            .await
+            .map_err(|error| OcrError::new(format!("vision model call failed: {error}")))?;
+        Ok(parse_vision_response(&response))
+    }
+
+    #[cfg(feature = "ocr-tesseract")]
+    async fn tesseract(&self, path: &Path) -> Result<Vec<OcrLine>, OcrError> {
+        let output = tokio::time::timeout(
+            std::time::Duration::from_secs(60),
+            tokio::process::Command::new("tesseract")
+                .arg(path)
+                .args(["stdout", "-l", &self.config.languages, "tsv"])
+                .stdin(std::process::Stdio::null())
+                .kill_on_drop(true)
+                .output(),
+        )
+        .await
+        .map_err(|_| OcrError::new("tesseract timed out"))?
+        .map_err(|error| OcrError::new(format!("failed to run tesseract: {error}")))?;
+        if !output.status.success() {
+            return Err(OcrError::new(format!(
+                "tesseract failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+        Ok(parse_tesseract_tsv(&String::from_utf8_lossy(
+            &output.stdout,
+        )))
+    }
+
+    #[cfg(not(feature = "ocr-tesseract"))]
+    async fn tesseract(&self, _path: &Path) -> Result<Vec<OcrLine>, OcrError> {
+        Err(OcrError::new(
+            "spacebot was built without the ocr-tesseract feature",
+        ))
+    }
+}
+
+/// Lines from a vision model's reply. Replies that aren't the requested
+/// JSON are kept as plain lines without boxes.
+fn parse_vision_response(response: &str) -> Vec<OcrLine> {
+    #[derive(Deserialize)]
+    struct Transcript {
+        lines: Vec<TranscriptLine>,
+    }
+
+    #[derive(Deserialize)]
+    struct TranscriptLine {
+        text: String,
+        #[serde(default)]
+        bbox: Option<[u32; 4]>,
+    }
+
+    let json = match (response.find('{'), response.rfind('}')) {
+        (Some(start), Some(end)) if start < end => &response[start..=end],
+        _ => "",
+    };
+    match serde_json::from_str::<Transcript>(json) {
+        Ok(transcript) => transcript
+            .lines
+            .into_iter()
+            .map(|line| OcrLine {
+                text: line.text,
+                bbox: line.bbox.map(|[x, y, width, height]| BoundingBox {
+                    x,
+                    y,
+                    width,
+                    height,
+                }),
+                confidence: None,
+            })
+            .collect(),
+        Err(_) => response
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with("```"))
+            .map(|line| OcrLine {
+                text: line.to_string(),
+                bbox: None,
+                confidence: None,
+            })
+            .collect(),
+    }
+}
+
+/// Group the words in tesseract's TSV output into lines.
+#[cfg(feature = "ocr-tesseract")]
+fn parse_tesseract_tsv(tsv: &str) -> Vec<OcrLine> {
+    // Columns: level page_num block_num par_num line_num word_num left top
+    // width height conf text. Level 5 rows are words.
+    struct Line<'a> {
+        /// Page, block, paragraph and line number.
+        key: (u32, u32, u32, u32),
+        words: Vec<&'a str>,
+        bbox: BoundingBox,
+        confidence_total: f32,
+    }
+
+    let mut lines: Vec<Line> = Vec::new();
+    for row in tsv.lines().skip(1) {
+        let columns: Vec<&str> = row.split('\t').collect();
+        let [
+            level,
+            page,
+            block,
+            paragraph,
+            line,
+            _,
+            left,
+            top,
+            width,
+            height,
+            confidence,
+            text,
+        ] = columns[..]
+        else {
+            continue;
+        };
+        let text = text.trim();
+        if level != "5" || text.is_empty() {
+            continue;
+        }
+        let number = |value: &str| value.parse::<u32>().unwrap_or_default();
+        let key = (number(page), number(block), number(paragraph), number(line));
+        let word = BoundingBox {
+            x: number(left),
+            y: number(top),
+            width: number(width),
+            height: number(height),
+        };
+        let confidence = confidence.parse::<f32>().unwrap_or_default().max(0.0);
+
+        match lines.last_mut() {
+            Some(last) if last.key == key => {
+                last.words.push(text);
+                last.bbox = last.bbox.union(&word);
+                last.confidence_total += confidence;
+            }
+            _ => lines.push(Line {
+                key,
+                words: vec![text],
+                bbox: word,
+                confidence_total: confidence,
+            }),
+        }
+    }
+
+    lines
+        .into_iter()
+        .map(|line| OcrLine {
+            text: line.words.join(" "),
+            bbox: Some(line.bbox),
+            confidence: Some(line.confidence_total / line.words.len() as f32 / 100.0),
+        })
+        .collect()
+}
+
+/// Error type for OCR tool.
+#[derive(Debug, thiserror::Error)]
+#[error("OCR failed: {message}")]
+pub struct OcrError {
+    message: String,
+}
+
+impl OcrError {
+    fn new(message: impl Into<String>) -> Self {
+        Self {
+            message: message.into(),
+        }
+    }
+}
+
+/// Arguments for OCR tool.
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct OcrArgs {
+    /// Path to the image. Relative paths resolve against the workspace.
+    pub path: String,
+}
+
+/// Output from OCR tool.
+#[derive(Debug, Serialize)]
+pub struct OcrOutput {
+    /// The backend that read the image.
+    pub backend: &'static str,
+    /// All recognized text, one line per line.
+    pub text: String,
+    /// Each line with its position.
+    pub lines: Vec<OcrLine>,
+}
+
+/// One line of recognized text.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct OcrLine {
+    pub text: String,
+    /// Position in image pixels. Approximate for the vision backend.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bbox: Option<BoundingBox>,
+    /// Recognition confidence from 0 to 1, when the backend reports one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub confidence: Option<f32>,
+}
+
+/// A rectangle in image pixels, from the top left corner.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct BoundingBox {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl BoundingBox {
+    /// The smallest box containing both.
+    pub fn union(&self, other: &Self) -> Self {
+        let x = self.x.min(other.x);
+        let y = self.y.min(other.y);
+        let right = (self.x + self.width).max(other.x + other.width);
+        let bottom = (self.y + self.height).max(other.y + other.height);
+        Self {
+            x,
+            y,
+            width: right - x,
+            height: bottom - y,
+        }
+    }
+}
+
+impl Tool for OcrTool {
+    const NAME: &'static str = "ocr";
+
+    type Error = OcrError;
+    type Args = OcrArgs;
+    type Output = OcrOutput;
+
+    async fn definition(&self, _prompt: String) -> ToolDefinition {
+        ToolDefinition {
+            name: Self::NAME.to_string(),
+            description: crate::prompts::text::get("tools/ocr").to_string(),
+            parameters: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "path": {
+                        "type": "string",
+                        "description": "Path to a PNG, JPEG, GIF, WebP, BMP or TIFF image, such as a browser screenshot"
+                    }
+                },
+                "required": ["path"]
+            }),
+        }
+    }
+
+    async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
+        let path = self.workspace.join(&args.path);
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        let Some((_, mime_type)) = IMAGE_TYPES.iter().find(|(known, _)| *known == extension) else {
+            return Err(OcrError::new(format!(
+                "{} is not a supported image type",
+                args.path
+            )));
+        };
+        let metadata = tokio::fs::metadata(&path)
+            .await
+            .map_err(|error| OcrError::new(format!("can't read {}: {error}", args.path)))?;
+        if metadata.len() > MAX_IMAGE_BYTES {
+            return Err(OcrError::new(format!(
+                "{} is larger than {} MB",
+                args.path,
+                MAX_IMAGE_BYTES / 1024 / 1024
+            )));
+        }
+
+        let first = self.config.backend;
+        let mut backends = vec![first];
+        if self.config.fallback {
+            backends.push(match first {
+                OcrBackend::Vision => OcrBackend::Tesseract,
+                OcrBackend::Tesseract => OcrBackend::Vision,
+            });
+        }
+
+        let mut failures = Vec::new();
+        for backend in backends {
+            match self.recognize(backend, &path, mime_type).await {
+                Ok(lines) => {
+                    let text = lines
+                        .iter()
+                        .map(|line| line.text.as_str())
+                        .collect::<Vec<_>>()
+                        .join("\n");
+                    return Ok(OcrOutput {
+                        backend: backend.as_str(),
+                        text,
+                        lines,
+                    });
+                }
+                Err(error) => {
+                    tracing::warn!(backend = backend.as_str(), %error, "OCR backend failed");
+                    failures.push(format!("{}: {}", backend.as_str(), error.message));
+                }
+            }
+        }
+        Err(OcrError::new(failures.join("; ")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_vision_response() {
+        let response = "```json\n{\"lines\": [{\"text\": \"Sign in\", \"bbox\": [10, 20, 80, 16]}, {\"text\": \"Forgot password?\"}]}\n```";
+        assert_eq!(
+            parse_vision_response(response),
+            vec![
+                OcrLine {
+                    text: "Sign in".into(),
+                    bbox: Some(BoundingBox {
+                        x: 10,
+                        y: 20,
+                        width: 80,
+                        height: 16
+                    }),
+                    confidence: None,
+                },
+                OcrLine {
+                    text: "Forgot password?".into(),
+                    bbox: None,
+                    confidence: None,
+                },
+            ]
+        );
+
+        let lines = parse_vision_response("Sign in\n\nForgot password?");
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[1].text, "Forgot password?");
+    }
+
+    #[cfg(feature = "ocr-tesseract")]
+    #[test]
+    fn test_parse_tesseract_tsv_groups_words_into_lines() {
+        let tsv = "level\tpage_num\tblock_num\tpar_num\tline_num\tword_num\tleft\ttop\twidth\theight\tconf\ttext\n\
+                   4\t1\t1\t1\t1\t0\t10\t20\t100\t15\t-1\t\n\
+                   5\t1\t1\t1\t1\t1\t10\t20\t40\t15\t90\tSign\n\
+                   5\t1\t1\t1\t1\t2\t55\t22\t20\t12\t80\tin\n\
+                   5\t1\t1\t1\t2\t1\t10\t50\t60\t15\t70\tCancel\n";
+        let lines = parse_tesseract_tsv(tsv);
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0].text, "Sign in");
+        assert_eq!(
+            lines[0].bbox,
+            Some(BoundingBox {
+                x: 10,
+                y: 20,
+                width: 65,
+                height: 15
+            })
+        );
+        assert_eq!(lines[0].confidence, Some(0.85));
+        assert_eq!(lines[1].text, "Cancel");
+    }
+}
@@ -0,0 +1,92 @@
+//! Pin tool for keeping a fact in the conversation's context (channel only).
+//!
+//! Pins are stored with the channel, so they outlive eviction, compaction and
+//! restarts. The channel mirrors them into its history before each turn.
+
+use crate::ChannelId;
+use crate::conversation::ChannelStore;
+use rig::completion::ToolDefinition;
+use rig::tool::Tool;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// What `pinned_by` says for pins the agent made.
+const PINNED_BY_AGENT: &str = "agent";
+
+/// Tool for pinning a fact to the conversation.
+#[derive(Debug, Clone)]
+pub struct PinTool {
+    channel_store: ChannelStore,
+    channel_id: ChannelId,
+}
+
+impl PinTool {
+    pub fn new(channel_store: ChannelStore, channel_id: ChannelId) -> Self {
+        Self {
+            channel_store,
+            channel_id,
+        }
+    }
+}
+
+/// Error type for pin tool.
+#[derive(Debug, thiserror::Error)]
+#[error("Pin failed: {0}")]
+pub struct PinError(String);
+
+/// Arguments for pin tool.
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct PinArgs {
+    /// The fact to keep in context, stated on its own.
+    pub content: String,
+}
+
+/// Output from pin tool.
+#[derive(Debug, Serialize)]
+pub struct PinOutput {
+    pub success: bool,
+    pub content: String,
+}
+
+impl Tool for PinTool {
+    const NAME: &'static str = "pin";
+
+    type Error = PinError;
+    type Args = PinArgs;
+    type Output = PinOutput;
+
+    async fn definition(&self, _prompt: String) -> ToolDefinition {
+        ToolDefinition {
+            name: Self::NAME.to_string(),
+            description: crate::prompts::text::get("tools/pin").to_string(),
+            parameters: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "content": {
+                        "type": "string",
+                        "description": "The fact to keep in context, stated so it makes sense on its own (e.g. \"The deploy freeze ends Friday 2026-10-23.\")."
+                    }
+                },
+                "required": ["content"]
+            }),
+        }
+    }
+
+    async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
+        let content = args.content.trim().to_string();
+        if content.is_empty() {
+            return Err(PinError("nothing to pin".into()));
+        }
+        tracing::info!(channel_id = %self.channel_id, "pin tool called");
+
+        self.channel_store
+            .add_pin(&self.channel_id, &content, PINNED_BY_AGENT)
+            .await
+            .map_err(|error| PinError(error.to_string()))?;
+
+        Ok(PinOutput {
+            success: true,
+            content,
+        })
+    }
+}
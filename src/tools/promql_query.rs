@@ -0,0 +1,244 @@
+//! PromQL query tool: metrics from configured Prometheus-compatible
+//! endpoints (task workers only).
+//!
+//! Range queries are limited to `max_range_hours` and get a step coarse
+//! enough for `max_points` points per series. Results come back with the
+//! highest-peaking series first, each with min, max, average and last value.
+
+use crate::config::{ObservabilityBackend, ObservabilityConfig, ObservabilitySource};
+use crate::observability::{Client, Series, TimeRange, shape};
+
+use chrono::Utc;
+use rig::completion::ToolDefinition;
+use rig::tool::Tool;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::time::Duration;
+
+/// Tool for querying metrics with PromQL.
+#[derive(Debug, Clone)]
+pub struct PromqlQueryTool {
+    config: ObservabilityConfig,
+    proxy: Option<String>,
+}
+
+impl PromqlQueryTool {
+    pub fn new(config: ObservabilityConfig) -> Self {
+        Self {
+            config,
+            proxy: None,
+        }
+    }
+
+    /// Send queries through `proxy`.
+    pub fn with_proxy(mut self, proxy: Option<String>) -> Self {
+        self.proxy = proxy;
+        self
+    }
+}
+
+/// The `backend` source named `name`, or the only one when no name is given.
+pub(crate) fn pick_source<'a>(
+    config: &'a ObservabilityConfig,
+    backend: ObservabilityBackend,
+    name: Option<&str>,
+) -> Result<&'a ObservabilitySource, String> {
+    let mut sources = config.sources.iter().filter(|s| s.backend == backend);
+    let found = match name {
+        Some(name) => sources.clone().find(|s| s.name == name),
+        None => sources.next().filter(|_| sources.next().is_none()),
+    };
+    found.ok_or_else(|| {
+        let names: Vec<&str> = config
+            .sources
+            .iter()
+            .filter(|s| s.backend == backend)
+            .map(|s| s.name.as_str())
+            .collect();
+        format!("pick a source, one of: {}", names.join(", "))
+    })
+}
+
+/// Lines listing the `backend` sources, for tool descriptions.
+pub(crate) fn describe_sources(
+    config: &ObservabilityConfig,
+    backend: ObservabilityBackend,
+) -> String {
+    let mut text = String::from("\n\nSources:");
+    for source in config.sources.iter().filter(|s| s.backend == backend) {
+        text.push_str(&format!("\n- {}", source.name));
+        if let Some(about) = &source.description {
+            text.push_str(&format!(": {about}"));
+        }
+    }
+    text
+}
+
+/// Error type for promql_query tool.
+#[derive(Debug, thiserror::Error)]
+#[error("PromQL query failed: {0}")]
+pub struct PromqlQueryError(String);
+
+/// Arguments for promql_query tool.
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct PromqlQueryArgs {
+    /// Source name. Can be left out when only one is configured.
+    pub source: Option<String>,
+    /// PromQL expression.
+    pub query: String,
+    /// How far back to look, e.g. "1h" or "30m". Defaults to 1h.
+    pub range: Option<String>,
+    /// End of the range as an RFC 3339 time. Defaults to now.
+    pub end: Option<String>,
+    /// Resolution, e.g. "1m". Coarsened when the range would have too many
+    /// points.
+    pub step: Option<String>,
+    /// Evaluate once at `end` instead of over the range.
+    pub instant: Option<bool>,
+}
+
+/// Output from promql_query tool.
+#[derive(Debug, Serialize)]
+pub struct PromqlQueryOutput {
+    pub source: String,
+    pub start: String,
+    pub end: String,
+    pub step_secs: i64,
+    /// Series with the highest peaks first.
+    pub series: Vec<Series>,
+    /// Whether series were left out to stay within the limit.
+    pub truncated: bool,
+}
+
+impl Tool for PromqlQueryTool {
+    const NAME: &'static str = "promql_query";
+
+    type Error = PromqlQueryError;
+    type Args = PromqlQueryArgs;
+    type Output = PromqlQueryOutput;
+
+    async fn definition(&self, _prompt: String) -> ToolDefinition {
+        let mut description = crate::prompts::text::get("tools/promql_query").to_string();
+        description.push_str(&describe_sources(
+            &self.config,
+            ObservabilityBackend::Prometheus,
+        ));
+
+        ToolDefinition {
+            name: Self::NAME.to_string(),
+            description,
+            parameters: json!({
+                "type": "object",
+                "properties": {
+                    "source": {
+                        "type": "string",
+                        "description": "Source name. Can be left out when only one is configured."
+                    },
+                    "query": {
+                        "type": "string",
+                        "description": "PromQL expression, e.g. histogram_quantile(0.99, sum by (le, route) (rate(http_request_duration_seconds_bucket[5m])))"
+                    },
+                    "range": {
+                        "type": "string",
+                        "description": format!("How far back to look, e.g. \"1h\" or \"30m\". Defaults to 1h, at most {}h.", self.config.max_range_hours)
+                    },
+                    "end": {
+                        "type": "string",
+                        "description": "End of the range as an RFC 3339 time. Defaults to now."
+                    },
+                    "step": {
+                        "type": "string",
+                        "description": "Resolution, e.g. \"1m\". Coarsened to keep series short."
+                    },
+                    "instant": {
+                        "type": "boolean",
+                        "description": "Evaluate once at the end time instead of over the range"
+                    }
+                },
+                "required": ["query"]
+            }),
+        }
+    }
+
+    async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
+        let source = pick_source(
+            &self.config,
+            ObservabilityBackend::Prometheus,
+            args.source.as_deref(),
+        )
+        .map_err(PromqlQueryError)?;
+        let range = TimeRange::new(
+            args.range.as_deref(),
+            args.end.as_deref(),
+            args.step.as_deref(),
+            &self.config,
+            Utc::now(),
+        )
+        .map_err(PromqlQueryError)?;
+        tracing::info!(source = %source.name, query = %args.query, "promql_query tool called");
+
+        let client = Client::new(
+            source,
+            Duration::from_secs(self.config.timeout_secs),
+            self.proxy.clone(),
+        )
+        .map_err(|error| PromqlQueryError(error.to_string()))?;
+        let series = if args.instant.unwrap_or(false) {
+            client.prometheus_instant(&args.query, range.end).await
+        } else {
+            client.prometheus_range(&args.query, &range).await
+        }
+        .map_err(|error| PromqlQueryError(error.to_string()))?;
+        let (series, truncated) = shape(series, self.config.max_series, self.config.max_points);
+
+        Ok(PromqlQueryOutput {
+            source: source.name.clone(),
+            start: range.start.to_rfc3339(),
+            end: range.end.to_rfc3339(),
+            step_secs: range.step.num_seconds(),
+            series,
+            truncated,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn source(name: &str, backend: ObservabilityBackend) -> ObservabilitySource {
+        ObservabilitySource {
+            name: name.into(),
+            backend,
+            url: "http://localhost:9090".into(),
+            description: None,
+            auth: None,
+        }
+    }
+
+    #[test]
+    fn test_pick_source_by_backend() {
+        let config = ObservabilityConfig {
+            sources: vec![
+                source("prod", ObservabilityBackend::Prometheus),
+                source("logs", ObservabilityBackend::Loki),
+            ],
+            ..ObservabilityConfig::default()
+        };
+
+        let only = pick_source(&config, ObservabilityBackend::Prometheus, None).unwrap();
+        assert_eq!(only.name, "prod");
+        assert!(pick_source(&config, ObservabilityBackend::Prometheus, Some("logs")).is_err());
+
+        let config = ObservabilityConfig {
+            sources: vec![
+                source("prod", ObservabilityBackend::Prometheus),
+                source("staging", ObservabilityBackend::Prometheus),
+            ],
+            ..config
+        };
+        let error = pick_source(&config, ObservabilityBackend::Prometheus, None).unwrap_err();
+        assert_eq!(error, "pick a source, one of: prod, staging");
+    }
+}
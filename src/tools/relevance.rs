@@ -0,0 +1,88 @@
+//! Embedding-based tool ranking for per-turn tool trimming.
+
+use crate::memory::EmbeddingModel;
+
+use rig::completion::ToolDefinition;
+use spacebot_core::error::LlmError;
+use spacebot_core::llm::tool_filter::ToolRanker;
+
+use std::collections::HashMap;
+use std::sync::{Arc, LazyLock, Mutex};
+
+/// Tools every filtered request keeps: the channel's control flow and the
+/// worker's status reporting stop working without them.
+pub const ESSENTIAL_TOOLS: &[&str] = &["reply", "skip", "branch", "spawn_worker", "set_status"];
+
+/// Tool description embeddings, keyed by name and description. Tool sets are
+/// fixed per process, so these are computed once and shared by every agent.
+static DESCRIPTION_EMBEDDINGS: LazyLock<Mutex<HashMap<String, Vec<f32>>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// Ranks tools by cosine similarity between the user turn and each tool's
+/// name and description, using the local memory embedding model.
+pub struct EmbeddingToolRanker {
+    model: Arc<EmbeddingModel>,
+}
+
+impl EmbeddingToolRanker {
+    pub fn new(model: Arc<EmbeddingModel>) -> Self {
+        Self { model }
+    }
+}
+
+impl ToolRanker for EmbeddingToolRanker {
+    async fn score(
+        &self,
+        query: &str,
+        tools: &[ToolDefinition],
+    ) -> spacebot_core::error::Result<Vec<f32>> {
+        let keys: Vec<String> = tools
+            .iter()
+            .map(|tool| format!("{}\n{}", tool.name, tool.description))
+            .collect();
+        let missing: Vec<String> = {
+            let cache = DESCRIPTION_EMBEDDINGS
+                .lock()
+                .expect("embedding cache poisoned");
+            keys.iter()
+                .filter(|key| !cache.contains_key(*key))
+                .cloned()
+                .collect()
+        };
+
+        let mut texts = missing.clone();
+        texts.push(query.to_string());
+        let model = self.model.clone();
+        let mut embeddings = tokio::task::spawn_blocking(move || model.embed(texts))
+            .await
+            .map_err(|error| LlmError::EmbeddingFailed(error.to_string()))?
+            .map_err(|error| LlmError::EmbeddingFailed(error.to_string()))?;
+        let query_embedding = embeddings
+            .pop()
+            .ok_or_else(|| LlmError::EmbeddingFailed("no embedding for query".into()))?;
+
+        let mut cache = DESCRIPTION_EMBEDDINGS
+            .lock()
+            .expect("embedding cache poisoned");
+        cache.extend(missing.into_iter().zip(embeddings));
+        Ok(keys
+            .iter()
+            .map(|key| {
+                cache.get(key).map_or(0.0, |embedding| {
+                    cosine_similarity(&query_embedding, embedding)
+                })
+            })
+            .collect())
+    }
+}
+
+fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm_a = a.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norm_b = b.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm_a == 0.0 || norm_b == 0.0 {
+        0.0
+    } else {
+        dot / (norm_a * norm_b)
+    }
+}
@@ -1,12 +1,17 @@
 //! Reply tool for sending messages to users (channel only).
 
-use crate::conversation::ConversationLogger;
+use crate::agent::fact_check::FactChecker;
+use crate::agent::provenance::Provenance;
+use crate::citations::{self, Citation, CitationOrigin};
+use crate::conversation::{ConversationLogger, ReplyAttribution};
+use crate::messaging::format::{Platform, render_citations};
 use crate::{ChannelId, OutboundResponse};
 use rig::completion::ToolDefinition;
 use rig::tool::Tool;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use tokio::sync::mpsc;
+use std::sync::Arc;
+use tokio::sync::{RwLock, mpsc};
 
 /// Tool for replying to users.
 ///
@@ -20,6 +25,14 @@ pub struct ReplyTool {
     conversation_id: String,
     conversation_logger: ConversationLogger,
     channel_id: ChannelId,
+    /// The completion that called this tool, for attributing the reply.
+    last_completion: Arc<RwLock<Option<ReplyAttribution>>>,
+    /// Checks each reply's claims before it's sent, when the channel has
+    /// fact-checking on.
+    fact_check: Option<FactChecker>,
+    /// Credits each reply to the tool results it draws on, when the channel
+    /// has provenance on.
+    provenance: Option<Provenance>,
 }
 
 impl ReplyTool {
@@ -29,14 +42,30 @@ impl ReplyTool {
         conversation_id: impl Into<String>,
         conversation_logger: ConversationLogger,
         channel_id: ChannelId,
+        last_completion: Arc<RwLock<Option<ReplyAttribution>>>,
     ) -> Self {
         Self {
             response_tx,
             conversation_id: conversation_id.into(),
             conversation_logger,
             channel_id,
+            last_completion,
+            fact_check: None,
+            provenance: None,
         }
     }
+
+    /// Fact-check replies with `checker` before sending them.
+    pub fn with_fact_check(mut self, checker: Option<FactChecker>) -> Self {
+        self.fact_check = checker;
+        self
+    }
+
+    /// Add a "via" footer naming the tools each reply drew on.
+    pub fn with_provenance(mut self, provenance: Option<Provenance>) -> Self {
+        self.provenance = provenance;
+        self
+    }
 }
 
 /// Error type for reply tool.
@@ -54,6 +83,38 @@ pub struct ReplyArgs {
     /// reply is posted there. Thread names are capped at 100 characters.
     #[serde(default)]
     pub thread_name: Option<String>,
+    /// Optional: web pages and memories the reply relies on, shown as
+    /// numbered footnotes.
+    #[serde(default)]
+    pub sources: Vec<ReplySource>,
+    /// Optional: tools or files the reply relies on, e.g. `web_search` or
+    /// `file:docs/deploy.md`, shown in a "via" footer.
+    #[serde(default)]
+    pub via: Vec<String>,
+}
+
+/// A source cited by a reply: a web page by URL or a memory by ID.
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct ReplySource {
+    pub title: String,
+    #[serde(default)]
+    pub url: Option<String>,
+    #[serde(default)]
+    pub memory_id: Option<String>,
+}
+
+impl ReplySource {
+    fn into_citation(self) -> Option<Citation> {
+        match (self.memory_id, self.url) {
+            (Some(memory_id), _) => Some(Citation::memory(&memory_id, &self.title)),
+            (None, Some(url)) => Some(Citation::web(
+                Some(&self.title),
+                &url,
+                CitationOrigin::WebSearch,
+            )),
+            (None, None) => None,
+        }
+    }
 }
 
 /// Output from reply tool.
@@ -72,23 +133,44 @@ impl Tool for ReplyTool {
     type Output = ReplyOutput;
 
     async fn definition(&self, _prompt: String) -> ToolDefinition {
+        let mut parameters = serde_json::json!({
+            "type": "object",
+            "properties": {
+                "content": {
+                    "type": "string",
+                    "description": "The content to send to the user. Can be markdown formatted."
+                },
+                "thread_name": {
+                    "type": "string",
+                    "description": "If provided, creates a new public thread with this name and posts the reply inside it. Max 100 characters."
+                },
+                "sources": {
+                    "type": "array",
+                    "description": "Web pages and memories the reply relies on. Each needs a title and either the page's url or the memory's memory_id.",
+                    "items": {
+                        "type": "object",
+                        "properties": {
+                            "title": { "type": "string" },
+                            "url": { "type": "string" },
+                            "memory_id": { "type": "string" }
+                        },
+                        "required": ["title"]
+                    }
+                }
+            },
+            "required": ["content"]
+        });
+        if self.provenance.is_some() {
+            parameters["properties"]["via"] = serde_json::json!({
+                "type": "array",
+                "description": "Tools or files whose results the reply relies on, e.g. web_search or file:docs/deploy.md.",
+                "items": { "type": "string" }
+            });
+        }
         ToolDefinition {
             name: Self::NAME.to_string(),
             description: crate::prompts::text::get("tools/reply").to_string(),
-            parameters: serde_json::json!({
-                "type": "object",
-                "properties": {
-                    "content": {
-                        "type": "string",
-                        "description": "The content to send to the user. Can be markdown formatted."
-                    },
-                    "thread_name": {
-                        "type": "string",
-                        "description": "If provided, creates a new public thread with this name and posts the reply inside it. Max 100 characters."
-                    }
-                },
-                "required": ["content"]
-            }),
+            parameters,
         }
     }
 
@@ -97,11 +179,43 @@ impl Tool for ReplyTool {
             conversation_id = %self.conversation_id,
             content_len = args.content.len(),
             thread_name = args.thread_name.as_deref(),
+            sources = args.sources.len(),
             "reply tool called"
         );
 
-        self.conversation_logger
-            .log_bot_message(&self.channel_id, &args.content);
+        let content = match &self.fact_check {
+            Some(checker) => checker.check(&args.content).await,
+            None => args.content,
+        };
+
+        let attribution = self.last_completion.read().await.clone();
+        let provider_citations = attribution
+            .iter()
+            .flat_map(|attribution| attribution.citations.iter().cloned());
+        let citations = citations::dedup(
+            args.sources
+                .into_iter()
+                .filter_map(ReplySource::into_citation)
+                .chain(provider_citations)
+                .chain(
+                    self.provenance
+                        .iter()
+                        .flat_map(|provenance| provenance.cite(&content, &args.via)),
+                )
+                .collect(),
+        );
+        self.conversation_logger.log_bot_message(
+            &self.channel_id,
+            &content,
+            attribution.as_ref(),
+            &citations,
+        );
+
+        let text = render_citations(
+            &content,
+            &citations,
+            Platform::from_conversation_id(&self.conversation_id),
+        );
 
         let response = match args.thread_name {
             Some(ref name) => {
@@ -111,12 +225,9 @@ impl Tool for ReplyTool {
                 } else {
                     name.clone()
                 };
-                OutboundResponse::ThreadReply {
-                    thread_name,
-                    text: args.content.clone(),
-                }
+                OutboundResponse::ThreadReply { thread_name, text }
             }
-            None => OutboundResponse::Text(args.content.clone()),
+            None => OutboundResponse::Text(text),
         };
 
         self.response_tx
@@ -129,7 +240,7 @@ impl Tool for ReplyTool {
         Ok(ReplyOutput {
             success: true,
             conversation_id: self.conversation_id.clone(),
-            content: args.content,
+            content,
         })
     }
 }
@@ -0,0 +1,232 @@
+//! Scratchpad tools for keeping working state across turns (channel only).
+//!
+//! `scratchpad_set`, `scratchpad_get` and `scratchpad_list` share the
+//! conversation's [`ScratchpadStore`]. Nothing in the scratchpad is added to
+//! the prompt; the channel reads back what it needs.
+
+use crate::ChannelId;
+use crate::conversation::ScratchpadStore;
+use rig::completion::ToolDefinition;
+use rig::tool::Tool;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// Characters of each value shown by `scratchpad_list`.
+const LIST_PREVIEW_CHARS: usize = 80;
+
+/// Error type for the scratchpad tools.
+#[derive(Debug, thiserror::Error)]
+#[error("Scratchpad failed: {0}")]
+pub struct ScratchpadError(String);
+
+impl From<crate::error::Error> for ScratchpadError {
+    fn from(error: crate::error::Error) -> Self {
+        Self(error.to_string())
+    }
+}
+
+/// Tool for setting or deleting a scratchpad entry.
+#[derive(Debug, Clone)]
+pub struct ScratchpadSetTool {
+    store: ScratchpadStore,
+    channel_id: ChannelId,
+}
+
+impl ScratchpadSetTool {
+    pub fn new(store: ScratchpadStore, channel_id: ChannelId) -> Self {
+        Self { store, channel_id }
+    }
+}
+
+/// Arguments for scratchpad_set.
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct ScratchpadSetArgs {
+    pub key: String,
+    /// The new value. Empty deletes the key.
+    #[serde(default)]
+    pub value: String,
+}
+
+/// Output from scratchpad_set.
+#[derive(Debug, Serialize)]
+pub struct ScratchpadSetOutput {
+    pub key: String,
+    /// Whether the key was deleted rather than set.
+    pub deleted: bool,
+}
+
+impl Tool for ScratchpadSetTool {
+    const NAME: &'static str = "scratchpad_set";
+
+    type Error = ScratchpadError;
+    type Args = ScratchpadSetArgs;
+    type Output = ScratchpadSetOutput;
+
+    async fn definition(&self, _prompt: String) -> ToolDefinition {
+        ToolDefinition {
+            name: Self::NAME.to_string(),
+            description: crate::prompts::text::get("tools/scratchpad_set").to_string(),
+            parameters: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "key": {
+                        "type": "string",
+                        "description": "Short name for the entry (e.g. \"plan\", \"open_questions\")."
+                    },
+                    "value": {
+                        "type": "string",
+                        "description": "The new value, replacing the old one. Leave empty to delete the key."
+                    }
+                },
+                "required": ["key", "value"]
+            }),
+        }
+    }
+
+    async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
+        let key = args.key.trim().to_string();
+        tracing::debug!(channel_id = %self.channel_id, %key, "scratchpad_set tool called");
+
+        let deleted = args.value.trim().is_empty();
+        if deleted {
+            self.store.delete(&self.channel_id, &key).await?;
+        } else {
+            self.store.set(&self.channel_id, &key, &args.value).await?;
+        }
+        Ok(ScratchpadSetOutput { key, deleted })
+    }
+}
+
+/// Tool for reading one scratchpad entry.
+#[derive(Debug, Clone)]
+pub struct ScratchpadGetTool {
+    store: ScratchpadStore,
+    channel_id: ChannelId,
+}
+
+impl ScratchpadGetTool {
+    pub fn new(store: ScratchpadStore, channel_id: ChannelId) -> Self {
+        Self { store, channel_id }
+    }
+}
+
+/// Arguments for scratchpad_get.
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct ScratchpadGetArgs {
+    pub key: String,
+}
+
+/// Output from scratchpad_get.
+#[derive(Debug, Serialize)]
+pub struct ScratchpadGetOutput {
+    pub key: String,
+    /// None when the key isn't set.
+    pub value: Option<String>,
+}
+
+impl Tool for ScratchpadGetTool {
+    const NAME: &'static str = "scratchpad_get";
+
+    type Error = ScratchpadError;
+    type Args = ScratchpadGetArgs;
+    type Output = ScratchpadGetOutput;
+
+    async fn definition(&self, _prompt: String) -> ToolDefinition {
+        ToolDefinition {
+            name: Self::NAME.to_string(),
+            description: crate::prompts::text::get("tools/scratchpad_get").to_string(),
+            parameters: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "key": {
+                        "type": "string",
+                        "description": "The key to read, as listed by scratchpad_list."
+                    }
+                },
+                "required": ["key"]
+            }),
+        }
+    }
+
+    async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
+        let key = args.key.trim().to_string();
+        let value = self.store.get(&self.channel_id, &key).await?;
+        Ok(ScratchpadGetOutput { key, value })
+    }
+}
+
+/// Tool for listing the scratchpad's keys.
+#[derive(Debug, Clone)]
+pub struct ScratchpadListTool {
+    store: ScratchpadStore,
+    channel_id: ChannelId,
+}
+
+impl ScratchpadListTool {
+    pub fn new(store: ScratchpadStore, channel_id: ChannelId) -> Self {
+        Self { store, channel_id }
+    }
+}
+
+/// Arguments for scratchpad_list.
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct ScratchpadListArgs {}
+
+/// One entry in scratchpad_list's output.
+#[derive(Debug, Serialize)]
+pub struct ScratchpadListEntry {
+    pub key: String,
+    /// The start of the value.
+    pub preview: String,
+    pub bytes: usize,
+    pub updated_at: String,
+}
+
+/// Output from scratchpad_list.
+#[derive(Debug, Serialize)]
+pub struct ScratchpadListOutput {
+    pub entries: Vec<ScratchpadListEntry>,
+    pub max_keys: usize,
+    pub total_bytes: usize,
+    pub max_total_bytes: usize,
+}
+
+impl Tool for ScratchpadListTool {
+    const NAME: &'static str = "scratchpad_list";
+
+    type Error = ScratchpadError;
+    type Args = ScratchpadListArgs;
+    type Output = ScratchpadListOutput;
+
+    async fn definition(&self, _prompt: String) -> ToolDefinition {
+        ToolDefinition {
+            name: Self::NAME.to_string(),
+            description: crate::prompts::text::get("tools/scratchpad_list").to_string(),
+            parameters: serde_json::json!({
+                "type": "object",
+                "properties": {}
+            }),
+        }
+    }
+
+    async fn call(&self, _args: Self::Args) -> Result<Self::Output, Self::Error> {
+        let entries: Vec<ScratchpadListEntry> = self
+            .store
+            .list(&self.channel_id)
+            .await?
+            .into_iter()
+            .map(|entry| ScratchpadListEntry {
+                preview: entry.value.chars().take(LIST_PREVIEW_CHARS).collect(),
+                bytes: entry.value.len(),
+                updated_at: entry.updated_at.to_rfc3339(),
+                key: entry.key,
+            })
+            .collect();
+        Ok(ScratchpadListOutput {
+            total_bytes: entries.iter().map(|entry| entry.bytes).sum(),
+            entries,
+            max_keys: crate::conversation::scratchpad::MAX_KEYS,
+            max_total_bytes: crate::conversation::scratchpad::MAX_TOTAL_BYTES,
+        })
+    }
+}
@@ -1,6 +1,7 @@
 //! Send file tool for delivering file attachments to users (channel only).
 
 use crate::OutboundResponse;
+use crate::tools::content::{MAX_CONTENT_BYTES, ToolContent};
 use rig::completion::ToolDefinition;
 use rig::tool::Tool;
 use schemars::JsonSchema;
@@ -47,9 +48,6 @@ pub struct SendFileOutput {
     pub size_bytes: u64,
 }
 
-/// Maximum file size: 25 MB (Discord's limit for non-boosted servers).
-const MAX_FILE_SIZE_BYTES: u64 = 25 * 1024 * 1024;
-
 impl Tool for SendFileTool {
     const NAME: &'static str = "send_file";
 
@@ -93,11 +91,11 @@ impl Tool for SendFileTool {
             return Err(SendFileError(format!("'{}' is not a file", path.display())));
         }
 
-        if metadata.len() > MAX_FILE_SIZE_BYTES {
+        if metadata.len() > MAX_CONTENT_BYTES {
             return Err(SendFileError(format!(
                 "file is too large ({} bytes, max {} bytes)",
                 metadata.len(),
-                MAX_FILE_SIZE_BYTES,
+                MAX_CONTENT_BYTES,
             )));
         }
 
@@ -124,15 +122,10 @@ impl Tool for SendFileTool {
             "send_file tool called"
         );
 
-        let response = OutboundResponse::File {
-            filename: filename.clone(),
-            data,
-            mime_type,
-            caption: args.caption,
-        };
+        let content = ToolContent::from_file(filename.clone(), mime_type, data, args.caption);
 
         self.response_tx
-            .send(response)
+            .send(content.into_response())
             .await
             .map_err(|error| SendFileError(format!("failed to send file: {error}")))?;
 
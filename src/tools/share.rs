@@ -0,0 +1,250 @@
+//! Share tool for sending images, tables and files to the user (workers only).
+
+use crate::tools::content::{ContentSink, MAX_CONTENT_BYTES, ToolContent};
+use crate::tools::file::FileTool;
+use rig::completion::ToolDefinition;
+use rig::tool::Tool;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+/// Tool for sending a worker's output to the conversation it works for.
+///
+/// Files are read from the worker's workspace, under the same boundary as
+/// the file tool. The model gets a description of what was sent back, and
+/// for tables the data itself, rendered as markdown.
+#[derive(Debug, Clone)]
+pub struct ShareTool {
+    files: FileTool,
+    sink: ContentSink,
+}
+
+impl ShareTool {
+    pub fn new(workspace: PathBuf, sink: ContentSink) -> Self {
+        Self {
+            files: FileTool::new(workspace),
+            sink,
+        }
+    }
+}
+
+/// Error type for share tool.
+#[derive(Debug, thiserror::Error)]
+#[error("Share failed: {0}")]
+pub struct ShareError(String);
+
+/// What to share.
+#[derive(Debug, Clone, Copy, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ShareKind {
+    /// An image file, shown inline where the platform can.
+    Image,
+    /// Any other file, as an attachment.
+    File,
+    /// Rows of data, sent as a CSV attachment.
+    Table,
+}
+
+/// Arguments for share tool.
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct ShareArgs {
+    pub kind: ShareKind,
+    /// Path of the image or file, relative to the workspace or absolute within it.
+    pub path: Option<String>,
+    /// A caption for an image or file, or a table's title.
+    pub title: Option<String>,
+    /// Column names of a table.
+    #[serde(default)]
+    pub columns: Vec<String>,
+    /// Rows of a table, one value per column.
+    #[serde(default)]
+    pub rows: Vec<Vec<serde_json::Value>>,
+}
+
+/// Output from share tool.
+#[derive(Debug, Serialize)]
+pub struct ShareOutput {
+    pub success: bool,
+    /// What the user was sent.
+    pub message: String,
+}
+
+impl Tool for ShareTool {
+    const NAME: &'static str = "share";
+
+    type Error = ShareError;
+    type Args = ShareArgs;
+    type Output = ShareOutput;
+
+    async fn definition(&self, _prompt: String) -> ToolDefinition {
+        ToolDefinition {
+            name: Self::NAME.to_string(),
+            description: crate::prompts::text::get("tools/share").to_string(),
+            parameters: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "kind": {
+                        "type": "string",
+                        "enum": ["image", "file", "table"],
+                        "description": "image and file send a file from the workspace, table sends columns and rows as a CSV attachment"
+                    },
+                    "path": {
+                        "type": "string",
+                        "description": "Path of the image or file, relative to the workspace or absolute within it"
+                    },
+                    "title": {
+                        "type": "string",
+                        "description": "Caption for an image or file, or the table's title"
+                    },
+                    "columns": {
+                        "type": "array",
+                        "items": { "type": "string" },
+                        "description": "Column names, for a table"
+                    },
+                    "rows": {
+                        "type": "array",
+                        "items": { "type": "array" },
+                        "description": "Rows of values in column order, for a table"
+                    }
+                },
+                "required": ["kind"]
+            }),
+        }
+    }
+
+    async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
+        let content = match args.kind {
+            ShareKind::Table => table(args.title, args.columns, args.rows)?,
+            ShareKind::Image | ShareKind::File => {
+                let raw = args
+                    .path
+                    .ok_or_else(|| ShareError("path is required for images and files".into()))?;
+                self.read_file(&raw, args.kind, args.title).await?
+            }
+        };
+
+        Ok(ShareOutput {
+            success: true,
+            message: self.sink.deliver(content),
+        })
+    }
+}
+
+impl ShareTool {
+    async fn read_file(
+        &self,
+        raw: &str,
+        kind: ShareKind,
+        caption: Option<String>,
+    ) -> Result<ToolContent, ShareError> {
+        let path = self
+            .files
+            .resolve_path(raw)
+            .map_err(|error| ShareError(error.to_string()))?;
+
+        let metadata = tokio::fs::metadata(&path)
+            .await
+            .map_err(|error| ShareError(format!("can't read '{}': {error}", path.display())))?;
+        if !metadata.is_file() {
+            return Err(ShareError(format!("'{}' is not a file", path.display())));
+        }
+        if metadata.len() > MAX_CONTENT_BYTES {
+            return Err(ShareError(format!(
+                "file is too large ({} bytes, max {MAX_CONTENT_BYTES} bytes)",
+                metadata.len(),
+            )));
+        }
+
+        let mime_type = mime_guess::from_path(&path)
+            .first_or_octet_stream()
+            .to_string();
+        if matches!(kind, ShareKind::Image) && !mime_type.starts_with("image/") {
+            return Err(ShareError(format!(
+                "'{}' is not an image ({mime_type}), share it as a file",
+                path.display()
+            )));
+        }
+
+        let data = tokio::fs::read(&path)
+            .await
+            .map_err(|error| ShareError(format!("failed to read '{}': {error}", path.display())))?;
+        let filename = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "file".into());
+
+        tracing::info!(
+            path = %path.display(),
+            %mime_type,
+            size_bytes = data.len(),
+            "share tool called"
+        );
+
+        Ok(ToolContent::from_file(filename, mime_type, data, caption))
+    }
+}
+
+/// Build a table, rejecting rows that don't fit the columns.
+fn table(
+    title: Option<String>,
+    columns: Vec<String>,
+    rows: Vec<Vec<serde_json::Value>>,
+) -> Result<ToolContent, ShareError> {
+    if columns.is_empty() {
+        return Err(ShareError("a table needs columns".into()));
+    }
+    let rows = rows
+        .into_iter()
+        .enumerate()
+        .map(|(index, row)| {
+            if row.len() != columns.len() {
+                return Err(ShareError(format!(
+                    "row {} has {} values but there are {} columns",
+                    index + 1,
+                    row.len(),
+                    columns.len()
+                )));
+            }
+            Ok(row.into_iter().map(cell).collect())
+        })
+        .collect::<Result<_, _>>()?;
+
+    Ok(ToolContent::Table {
+        title,
+        columns,
+        rows,
+    })
+}
+
+/// A table value as text: strings as they are, nulls empty, anything else as JSON.
+fn cell(value: serde_json::Value) -> String {
+    match value {
+        serde_json::Value::String(text) => text,
+        serde_json::Value::Null => String::new(),
+        other => other.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_table_cells_and_row_widths() {
+        let columns = vec!["name".to_string(), "stars".to_string(), "note".to_string()];
+        let content = table(
+            None,
+            columns.clone(),
+            vec![vec![json!("spacebot"), json!(42), json!(null)]],
+        )
+        .unwrap();
+        let ToolContent::Table { rows, .. } = content else {
+            panic!("expected a table");
+        };
+        assert_eq!(rows, [["spacebot", "42", ""]]);
+
+        assert!(table(None, columns, vec![vec![json!("short")]]).is_err());
+        assert!(table(None, Vec::new(), Vec::new()).is_err());
+    }
+}
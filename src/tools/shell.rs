@@ -36,6 +36,8 @@ pub const SECRET_ENV_VARS: &[&str] = &[
 pub struct ShellTool {
     instance_dir: PathBuf,
     workspace: PathBuf,
+    /// Proxy commands' HTTP traffic goes through, when the tool has a network policy.
+    proxy: Option<String>,
 }
 
 impl ShellTool {
@@ -44,9 +46,16 @@ impl ShellTool {
         Self {
             instance_dir,
             workspace,
+            proxy: None,
         }
     }
 
+    /// Point commands' proxy variables at `proxy`.
+    pub fn with_proxy(mut self, proxy: Option<String>) -> Self {
+        self.proxy = proxy;
+        self
+    }
+
     /// Check if a command references sensitive instance paths or secret env vars.
     fn check_command(&self, command: &str) -> Result<(), ShellError> {
         let instance_str = self.instance_dir.to_string_lossy();
@@ -255,6 +264,10 @@ impl Tool for ShellTool {
             cmd.current_dir(&self.workspace);
         }
 
+        if let Some(proxy) = &self.proxy {
+            cmd.envs(super::network::proxy_env(proxy));
+        }
+
         cmd.stdout(Stdio::piped()).stderr(Stdio::piped());
 
         // Set timeout
@@ -0,0 +1,533 @@
+//! SQL query tool: read-only queries against configured Postgres, MySQL and
+//! SQLite databases (task workers only).
+//!
+//! Every query is checked twice. The tool only runs single statements whose
+//! leading keyword is on the database's allowlist (`select`, `with`,
+//! `explain`, ... by default), and the connection itself is read-only:
+//! SQLite files are opened read-only, Postgres sessions default to read-only
+//! transactions and MySQL sessions are set read-only. Results are capped by
+//! rows, bytes and time, and come back as JSON rows plus a markdown table.
+
+use crate::config::{SqlBackend, SqlConfig, SqlDatabase};
+
+use futures::TryStreamExt as _;
+use rig::completion::ToolDefinition;
+use rig::tool::Tool;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use sqlx::{Column as _, ConnectOptions as _, Connection as _, Row};
+use std::str::FromStr as _;
+use std::time::Duration;
+
+/// Characters of a cell shown in the markdown table. The JSON rows hold the
+/// full value.
+const MAX_TABLE_CELL_CHARS: usize = 200;
+
+/// Tool for running read-only SQL queries.
+#[derive(Debug, Clone)]
+pub struct SqlQueryTool {
+    config: SqlConfig,
+}
+
+impl SqlQueryTool {
+    pub fn new(config: SqlConfig) -> Self {
+        Self { config }
+    }
+
+    fn database(&self, name: Option<&str>) -> Result<&SqlDatabase, SqlQueryError> {
+        let found = match name {
+            Some(name) => self.config.databases.iter().find(|d| d.name == name),
+            None if self.config.databases.len() == 1 => self.config.databases.first(),
+            None => None,
+        };
+        found.ok_or_else(|| {
+            let names: Vec<&str> = self
+                .config
+                .databases
+                .iter()
+                .map(|d| d.name.as_str())
+                .collect();
+            SqlQueryError(format!("pick a database, one of: {}", names.join(", ")))
+        })
+    }
+
+    async fn run(
+        &self,
+        database: &SqlDatabase,
+        statement: &str,
+        limits: Limits,
+    ) -> Result<Table, sqlx::Error> {
+        let timeout_ms = self.config.timeout_secs.saturating_mul(1000).to_string();
+        match SqlBackend::from_url(&database.url) {
+            Some(SqlBackend::Postgres) => {
+                let mut connection = sqlx::postgres::PgConnectOptions::from_str(&database.url)?
+                    .options([
+                        ("default_transaction_read_only", "on"),
+                        ("statement_timeout", timeout_ms.as_str()),
+                    ])
+                    .connect()
+                    .await?;
+                let table = read_rows(
+                    sqlx::raw_sql(statement).fetch(&mut connection),
+                    limits,
+                    postgres_cell,
+                )
+                .await;
+                let _ = connection.close().await;
+                table
+            }
+            Some(SqlBackend::Mysql) => {
+                let mut connection = sqlx::mysql::MySqlConnectOptions::from_str(&database.url)?
+                    .connect()
+                    .await?;
+                sqlx::raw_sql("SET SESSION TRANSACTION READ ONLY")
+                    .execute(&mut connection)
+                    .await?;
+                // MariaDB has no MAX_EXECUTION_TIME; the tool's own timeout
+                // still applies there.
+                let _ = sqlx::raw_sql(&format!("SET SESSION MAX_EXECUTION_TIME = {timeout_ms}"))
+                    .execute(&mut connection)
+                    .await;
+                let table = read_rows(
+                    sqlx::raw_sql(statement).fetch(&mut connection),
+                    limits,
+                    mysql_cell,
+                )
+                .await;
+                let _ = connection.close().await;
+                table
+            }
+            Some(SqlBackend::Sqlite) => {
+                let mut connection = sqlx::sqlite::SqliteConnectOptions::from_str(&database.url)?
+                    .read_only(true)
+                    .connect()
+                    .await?;
+                let table = read_rows(
+                    sqlx::raw_sql(statement).fetch(&mut connection),
+                    limits,
+                    sqlite_cell,
+                )
+                .await;
+                let _ = connection.close().await;
+                table
+            }
+            None => Err(sqlx::Error::Configuration(
+                format!(
+                    "database '{}' has no postgres://, mysql:// or sqlite: url",
+                    database.name
+                )
+                .into(),
+            )),
+        }
+    }
+}
+
+/// Error type for sql_query tool.
+#[derive(Debug, thiserror::Error)]
+#[error("SQL query failed: {0}")]
+pub struct SqlQueryError(String);
+
+/// Arguments for sql_query tool.
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct SqlQueryArgs {
+    /// Database name. Can be left out when only one is configured.
+    pub database: Option<String>,
+    /// One read-only statement.
+    pub query: String,
+    /// Most rows to return, up to the configured limit.
+    pub max_rows: Option<usize>,
+}
+
+/// Output from sql_query tool.
+#[derive(Debug, Serialize)]
+pub struct SqlQueryOutput {
+    pub database: String,
+    pub columns: Vec<String>,
+    /// One array of values per row, in column order.
+    pub rows: Vec<Vec<Value>>,
+    /// Whether rows were left out to stay within the row or byte limit.
+    pub truncated: bool,
+    /// The same rows as a markdown table, for showing to the user.
+    pub table: String,
+}
+
+impl Tool for SqlQueryTool {
+    const NAME: &'static str = "sql_query";
+
+    type Error = SqlQueryError;
+    type Args = SqlQueryArgs;
+    type Output = SqlQueryOutput;
+
+    async fn definition(&self, _prompt: String) -> ToolDefinition {
+        let mut description = crate::prompts::text::get("tools/sql_query").to_string();
+        description.push_str("\n\nDatabases:");
+        for database in &self.config.databases {
+            let backend = SqlBackend::from_url(&database.url).map_or("unknown", SqlBackend::as_str);
+            description.push_str(&format!(
+                "\n- {} ({backend}; allows {})",
+                database.name,
+                database.statements.join(", ")
+            ));
+            if let Some(about) = &database.description {
+                description.push_str(&format!(": {about}"));
+            }
+        }
+
+        ToolDefinition {
+            name: Self::NAME.to_string(),
+            description,
+            parameters: json!({
+                "type": "object",
+                "properties": {
+                    "database": {
+                        "type": "string",
+                        "description": "Database name. Can be left out when only one is configured."
+                    },
+                    "query": {
+                        "type": "string",
+                        "description": "One read-only SQL statement in the database's dialect. Use LIMIT and aggregate where you can."
+                    },
+                    "max_rows": {
+                        "type": "integer",
+                        "minimum": 1,
+                        "maximum": self.config.max_rows,
+                        "description": "Most rows to return"
+                    }
+                },
+                "required": ["query"]
+            }),
+        }
+    }
+
+    async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
+        let database = self.database(args.database.as_deref())?;
+        let statement =
+            check_statement(&args.query, &database.statements).map_err(SqlQueryError)?;
+        let limits = Limits {
+            rows: args
+                .max_rows
+                .unwrap_or(self.config.max_rows)
+                .clamp(1, self.config.max_rows.max(1)),
+            bytes: self.config.max_bytes,
+        };
+        tracing::info!(database = %database.name, %statement, "sql_query tool called");
+
+        let timeout = Duration::from_secs(self.config.timeout_secs);
+        let table = match tokio::time::timeout(timeout, self.run(database, statement, limits)).await
+        {
+            Ok(Ok(table)) => table,
+            Ok(Err(error)) => return Err(SqlQueryError(error.to_string())),
+            Err(_) => {
+                return Err(SqlQueryError(format!(
+                    "the query ran longer than {}s; narrow it down or aggregate",
+                    self.config.timeout_secs
+                )));
+            }
+        };
+
+        Ok(SqlQueryOutput {
+            database: database.name.clone(),
+            table: markdown_table(&table.columns, &table.rows),
+            columns: table.columns,
+            rows: table.rows,
+            truncated: table.truncated,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Limits {
+    rows: usize,
+    bytes: usize,
+}
+
+#[derive(Debug, Default)]
+struct Table {
+    columns: Vec<String>,
+    rows: Vec<Vec<Value>>,
+    truncated: bool,
+}
+
+/// `sql` without trailing semicolons, if it's one statement starting with an
+/// allowed keyword.
+fn check_statement<'a>(sql: &'a str, allowed: &[String]) -> Result<&'a str, String> {
+    let statement = sql.trim().trim_end_matches([';', ' ', '\t', '\n', '\r']);
+    if statement.is_empty() {
+        return Err("the query is empty".into());
+    }
+    if statement.contains(';') {
+        return Err("run one statement per call, without semicolons inside it".into());
+    }
+    let keyword = leading_keyword(statement);
+    if !allowed.iter().any(|allowed| *allowed == keyword) {
+        return Err(format!(
+            "this database only allows {} statements, not '{keyword}'",
+            allowed.join(", ")
+        ));
+    }
+    Ok(statement)
+}
+
+/// The first keyword of `sql`, lowercase, after comments and opening
+/// parentheses.
+fn leading_keyword(sql: &str) -> String {
+    let mut rest = sql;
+    loop {
+        rest = rest.trim_start();
+        if let Some(comment) = rest.strip_prefix("--") {
+            rest = comment.split_once('\n').map_or("", |(_, after)| after);
+        } else if let Some(comment) = rest.strip_prefix("/*") {
+            rest = comment.split_once("*/").map_or("", |(_, after)| after);
+        } else if let Some(after) = rest.strip_prefix('(') {
+            rest = after;
+        } else {
+            break;
+        }
+    }
+    rest.chars()
+        .take_while(char::is_ascii_alphabetic)
+        .collect::<String>()
+        .to_lowercase()
+}
+
+/// Collect rows until the row or byte limit. Rows past either limit are
+/// left unread.
+async fn read_rows<R: Row>(
+    mut rows: impl futures::Stream<Item = Result<R, sqlx::Error>> + Unpin,
+    limits: Limits,
+    cell: fn(&R, usize) -> Value,
+) -> Result<Table, sqlx::Error> {
+    let mut table = Table::default();
+    let mut bytes = 0;
+    while let Some(row) = rows.try_next().await? {
+        if table.columns.is_empty() {
+            table.columns = row
+                .columns()
+                .iter()
+                .map(|column| column.name().to_string())
+                .collect();
+        }
+        if table.rows.len() >= limits.rows {
+            table.truncated = true;
+            break;
+        }
+        let values: Vec<Value> = (0..row.len()).map(|index| cell(&row, index)).collect();
+        bytes += serde_json::to_string(&values).map_or(0, |text| text.len());
+        if bytes > limits.bytes {
+            table.truncated = true;
+            break;
+        }
+        table.rows.push(values);
+    }
+    Ok(table)
+}
+
+/// The value at `index` decoded as `T`, if the column's type allows it.
+fn cell<'r, R, T>(row: &'r R, index: usize, to_json: fn(T) -> Value) -> Option<Value>
+where
+    R: Row,
+    usize: sqlx::ColumnIndex<R>,
+    T: sqlx::Decode<'r, R::Database> + sqlx::Type<R::Database>,
+{
+    row.try_get::<Option<T>, _>(index)
+        .ok()
+        .map(|value| value.map_or(Value::Null, to_json))
+}
+
+/// A value with no JSON counterpart, as text.
+fn text<T: ToString>(value: T) -> Value {
+    Value::String(value.to_string())
+}
+
+/// Binary data as `0x`-prefixed hex.
+fn bytes(value: Vec<u8>) -> Value {
+    let hex: String = value.iter().map(|byte| format!("{byte:02x}")).collect();
+    Value::String(format!("0x{hex}"))
+}
+
+/// A value of a type the tool doesn't know, read as text or described.
+fn fallback<R>(row: &R, index: usize) -> Value
+where
+    R: Row,
+    usize: sqlx::ColumnIndex<R>,
+{
+    match row.try_get_unchecked::<Option<String>, _>(index) {
+        Ok(value) => value.map_or(Value::Null, Value::String),
+        Err(_) => Value::String(format!(
+            "<{}>",
+            sqlx::TypeInfo::name(row.column(index).type_info())
+        )),
+    }
+}
+
+fn postgres_cell(row: &sqlx::postgres::PgRow, index: usize) -> Value {
+    cell::<_, bool>(row, index, Value::from)
+        .or_else(|| cell::<_, i16>(row, index, Value::from))
+        .or_else(|| cell::<_, i32>(row, index, Value::from))
+        .or_else(|| cell::<_, i64>(row, index, Value::from))
+        .or_else(|| cell::<_, f32>(row, index, Value::from))
+        .or_else(|| cell::<_, f64>(row, index, Value::from))
+        .or_else(|| cell::<_, rust_decimal::Decimal>(row, index, text))
+        .or_else(|| cell::<_, String>(row, index, Value::from))
+        .or_else(|| cell::<_, chrono::DateTime<chrono::Utc>>(row, index, text))
+        .or_else(|| cell::<_, chrono::NaiveDateTime>(row, index, text))
+        .or_else(|| cell::<_, chrono::NaiveDate>(row, index, text))
+        .or_else(|| cell::<_, chrono::NaiveTime>(row, index, text))
+        .or_else(|| cell::<_, uuid::Uuid>(row, index, text))
+        .or_else(|| cell::<_, Value>(row, index, |value| value))
+        .or_else(|| cell::<_, Vec<u8>>(row, index, bytes))
+        .unwrap_or_else(|| fallback(row, index))
+}
+
+fn mysql_cell(row: &sqlx::mysql::MySqlRow, index: usize) -> Value {
+    cell::<_, i64>(row, index, Value::from)
+        .or_else(|| cell::<_, u64>(row, index, Value::from))
+        .or_else(|| cell::<_, f64>(row, index, Value::from))
+        .or_else(|| cell::<_, rust_decimal::Decimal>(row, index, text))
+        .or_else(|| cell::<_, String>(row, index, Value::from))
+        .or_else(|| cell::<_, chrono::DateTime<chrono::Utc>>(row, index, text))
+        .or_else(|| cell::<_, chrono::NaiveDateTime>(row, index, text))
+        .or_else(|| cell::<_, chrono::NaiveDate>(row, index, text))
+        .or_else(|| cell::<_, chrono::NaiveTime>(row, index, text))
+        .or_else(|| cell::<_, Value>(row, index, |value| value))
+        .or_else(|| cell::<_, Vec<u8>>(row, index, bytes))
+        .unwrap_or_else(|| fallback(row, index))
+}
+
+fn sqlite_cell(row: &sqlx::sqlite::SqliteRow, index: usize) -> Value {
+    cell::<_, i64>(row, index, Value::from)
+        .or_else(|| cell::<_, f64>(row, index, Value::from))
+        .or_else(|| cell::<_, String>(row, index, Value::from))
+        .or_else(|| cell::<_, Vec<u8>>(row, index, bytes))
+        .unwrap_or_else(|| fallback(row, index))
+}
+
+/// `rows` as a markdown table, with long cells shortened.
+fn markdown_table(columns: &[String], rows: &[Vec<Value>]) -> String {
+    if columns.is_empty() {
+        return "(no rows)".to_string();
+    }
+    let escape = |text: &str| {
+        let mut cell: String = text
+            .replace('|', "\\|")
+            .replace(['\r', '\n'], " ")
+            .chars()
+            .take(MAX_TABLE_CELL_CHARS)
+            .collect();
+        if text.chars().count() > MAX_TABLE_CELL_CHARS {
+            cell.push('…');
+        }
+        cell
+    };
+    let line = |cells: Vec<String>| format!("| {} |", cells.join(" | "));
+
+    let mut lines = vec![
+        line(columns.iter().map(|column| escape(column)).collect()),
+        line(columns.iter().map(|_| "---".to_string()).collect()),
+    ];
+    for row in rows {
+        lines.push(line(
+            row.iter()
+                .map(|value| match value {
+                    Value::Null => "NULL".to_string(),
+                    Value::String(text) => escape(text),
+                    other => escape(&other.to_string()),
+                })
+                .collect(),
+        ));
+    }
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn allowed() -> Vec<String> {
+        crate::config::DEFAULT_SQL_STATEMENTS
+            .iter()
+            .map(|s| s.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_check_statement() {
+        let allowed = allowed();
+        assert_eq!(check_statement("  SELECT 1;\n", &allowed), Ok("SELECT 1"));
+        assert!(check_statement("-- totals\n/* q3 */ (select 1)", &allowed).is_ok());
+        assert!(check_statement("WITH t AS (SELECT 1) SELECT * FROM t", &allowed).is_ok());
+        assert!(check_statement("DELETE FROM users", &allowed).is_err());
+        assert!(check_statement("SELECT 1; DROP TABLE users", &allowed).is_err());
+        assert!(check_statement("/* select */ update users set x = 1", &allowed).is_err());
+        assert!(check_statement(" ; ", &allowed).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_sqlite_query_is_read_only_and_limited() {
+        let dir = tempfile::tempdir().unwrap();
+        let url = format!("sqlite:{}", dir.path().join("shop.db").display());
+        let mut setup = sqlx::sqlite::SqliteConnectOptions::from_str(&url)
+            .unwrap()
+            .create_if_missing(true)
+            .connect()
+            .await
+            .unwrap();
+        sqlx::raw_sql(
+            "CREATE TABLE orders (id INTEGER, customer TEXT, total REAL, note TEXT); \
+             INSERT INTO orders VALUES (1, 'Acme', 12.5, 'rush | gift'), (2, 'Initech', 3.0, NULL), \
+             (3, 'Globex', 7.25, 'x');",
+        )
+        .execute(&mut setup)
+        .await
+        .unwrap();
+
+        let tool = SqlQueryTool::new(SqlConfig {
+            enabled: true,
+            max_rows: 2,
+            databases: vec![SqlDatabase {
+                name: "shop".into(),
+                url,
+                description: None,
+                statements: allowed(),
+            }],
+            ..SqlConfig::default()
+        });
+        let output = tool
+            .call(SqlQueryArgs {
+                database: None,
+                query: "SELECT id, customer, total, note FROM orders ORDER BY id".into(),
+                max_rows: None,
+            })
+            .await
+            .unwrap();
+        assert_eq!(output.columns, ["id", "customer", "total", "note"]);
+        assert_eq!(
+            output.rows,
+            vec![
+                vec![json!(1), json!("Acme"), json!(12.5), json!("rush | gift")],
+                vec![json!(2), json!("Initech"), json!(3.0), Value::Null],
+            ]
+        );
+        assert!(output.truncated);
+        assert_eq!(
+            output.table.lines().nth(2),
+            Some("| 1 | Acme | 12.5 | rush \\| gift |")
+        );
+
+        // Past the allowlist, the read-only connection still refuses writes.
+        let write = tool
+            .call(SqlQueryArgs {
+                database: Some("shop".into()),
+                query: "WITH gone AS (SELECT 1) DELETE FROM orders".into(),
+                max_rows: None,
+            })
+            .await;
+        assert!(write.is_err());
+        let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM orders")
+            .fetch_one(&mut setup)
+            .await
+            .unwrap();
+        assert_eq!(count, 3);
+    }
+}
@@ -0,0 +1,100 @@
+//! Start task tool for long-running background work (channel only).
+
+use crate::WorkerId;
+use crate::agent::channel::{ChannelState, spawn_worker_from_state};
+use rig::completion::ToolDefinition;
+use rig::tool::Tool;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// Tool for starting a background task.
+///
+/// Spawns a fire-and-forget worker and returns its ID straight away. The
+/// channel posts the worker's progress and final result to the conversation
+/// itself, so the turn doesn't wait on the job.
+#[derive(Debug, Clone)]
+pub struct StartTaskTool {
+    state: ChannelState,
+}
+
+impl StartTaskTool {
+    /// Create a new start task tool with access to channel state.
+    pub fn new(state: ChannelState) -> Self {
+        Self { state }
+    }
+}
+
+/// Error type for start task tool.
+#[derive(Debug, thiserror::Error)]
+#[error("Task start failed: {0}")]
+pub struct StartTaskError(String);
+
+/// Arguments for start task tool.
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct StartTaskArgs {
+    /// The task description for the worker.
+    pub task: String,
+    /// Optional skill name to load into the worker's context.
+    #[serde(default)]
+    pub skill: Option<String>,
+}
+
+/// Output from start task tool.
+#[derive(Debug, Serialize)]
+pub struct StartTaskOutput {
+    /// The task's ID, which is the ID of the worker running it.
+    pub task_id: WorkerId,
+    pub started: bool,
+    /// Status message.
+    pub message: String,
+}
+
+impl Tool for StartTaskTool {
+    const NAME: &'static str = "start_task";
+
+    type Error = StartTaskError;
+    type Args = StartTaskArgs;
+    type Output = StartTaskOutput;
+
+    async fn definition(&self, _prompt: String) -> ToolDefinition {
+        ToolDefinition {
+            name: Self::NAME.to_string(),
+            description: crate::prompts::text::get("tools/start_task").to_string(),
+            parameters: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "task": {
+                        "type": "string",
+                        "description": "Clear, specific description of the job, including everything needed since the worker can't see your conversation. Say what the finished result should look like — it's posted to the user as-is."
+                    },
+                    "skill": {
+                        "type": "string",
+                        "description": "Name of a skill to load into the worker. Only use skill names from <available_skills>."
+                    }
+                },
+                "required": ["task"]
+            }),
+        }
+    }
+
+    async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
+        let task_id =
+            spawn_worker_from_state(&self.state, &args.task, false, args.skill.as_deref())
+                .await
+                .map_err(|e| StartTaskError(format!("{e}")))?;
+        self.state
+            .tasks
+            .register(task_id, std::time::Instant::now());
+
+        tracing::info!(task_id = %task_id, task = %args.task, "background task started");
+
+        Ok(StartTaskOutput {
+            task_id,
+            started: true,
+            message: format!(
+                "Task {task_id} started for: {}. Progress and the result will be posted to the user directly; you don't need to relay them.",
+                args.task
+            ),
+        })
+    }
+}
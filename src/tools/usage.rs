@@ -0,0 +1,240 @@
+//! Per-tool cost and latency accounting.
+//!
+//! Every returned tool call is published as an [`Event::ToolExecuted`] with
+//! its duration and whether it failed. A [`ToolRun`] prices the call from
+//! `[llm] tool_pricing` (USD per call) and is counted in [`ToolMetrics`], the
+//! in-process totals per agent and tool, and written to the agent's
+//! `tool_runs` table, the ledger [`tool_spend`] reports from, so expensive
+//! tools show up next to model spend.
+
+use crate::error::Result;
+
+use anyhow::Context as _;
+use serde::Serialize;
+use spacebot_core::events::Event;
+use sqlx::{Row as _, SqlitePool};
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// One finished tool call.
+#[derive(Debug, Clone)]
+pub struct ToolRun {
+    pub agent_id: String,
+    pub tool_name: String,
+    pub process_id: String,
+    pub process_type: String,
+    /// The conversation the calling process worked for, if any.
+    pub channel_id: Option<String>,
+    pub duration_ms: u64,
+    pub success: bool,
+    /// The tool's price per call. None for tools without one.
+    pub cost_usd: Option<f64>,
+}
+
+impl ToolRun {
+    /// The run an event reports, priced with `pricing`. None for events
+    /// other than [`Event::ToolExecuted`].
+    pub fn from_event(event: &Event, pricing: &HashMap<String, f64>) -> Option<Self> {
+        let Event::ToolExecuted {
+            agent_id,
+            process_id,
+            process_type,
+            channel_id,
+            tool_name,
+            duration_ms,
+            success,
+            ..
+        } = event
+        else {
+            return None;
+        };
+        Some(Self {
+            agent_id: agent_id.clone(),
+            tool_name: tool_name.clone(),
+            process_id: process_id.clone(),
+            process_type: process_type.to_string(),
+            channel_id: channel_id.clone(),
+            duration_ms: *duration_ms,
+            success: *success,
+            cost_usd: pricing.get(tool_name).copied(),
+        })
+    }
+
+    /// Write the run to the agent's ledger.
+    pub async fn log(&self, pool: &SqlitePool) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO tool_runs \
+             (id, tool_name, process_id, process_type, channel_id, duration_ms, success, cost_usd) \
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+        )
+        .bind(uuid::Uuid::new_v4().to_string())
+        .bind(&self.tool_name)
+        .bind(&self.process_id)
+        .bind(&self.process_type)
+        .bind(&self.channel_id)
+        .bind(self.duration_ms as i64)
+        .bind(self.success)
+        .bind(self.cost_usd)
+        .execute(pool)
+        .await
+        .context("failed to log tool run")?;
+        Ok(())
+    }
+}
+
+/// Running totals for one agent's calls of one tool.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ToolStats {
+    pub agent_id: String,
+    pub tool_name: String,
+    pub calls: u64,
+    pub failures: u64,
+    pub total_ms: u64,
+    pub max_ms: u64,
+    /// Summed over priced calls. None for tools without a price.
+    pub cost_usd: Option<f64>,
+}
+
+/// Tool call totals since startup, per agent and tool.
+#[derive(Debug, Default)]
+pub struct ToolMetrics {
+    stats: Mutex<HashMap<(String, String), ToolStats>>,
+}
+
+impl ToolMetrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn observe(&self, run: &ToolRun) {
+        let mut stats = self
+            .stats
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let entry = stats
+            .entry((run.agent_id.clone(), run.tool_name.clone()))
+            .or_insert_with(|| ToolStats {
+                agent_id: run.agent_id.clone(),
+                tool_name: run.tool_name.clone(),
+                ..Default::default()
+            });
+        entry.calls += 1;
+        if !run.success {
+            entry.failures += 1;
+        }
+        entry.total_ms += run.duration_ms;
+        entry.max_ms = entry.max_ms.max(run.duration_ms);
+        if let Some(cost) = run.cost_usd {
+            *entry.cost_usd.get_or_insert(0.0) += cost;
+        }
+    }
+
+    /// Totals for `agent_id`'s tools, by tool name.
+    pub fn snapshot(&self, agent_id: &str) -> Vec<ToolStats> {
+        let mut snapshot: Vec<ToolStats> = self
+            .stats
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .values()
+            .filter(|stats| stats.agent_id == agent_id)
+            .cloned()
+            .collect();
+        snapshot.sort_by(|a, b| a.tool_name.cmp(&b.tool_name));
+        snapshot
+    }
+}
+
+/// One tool's calls in one conversation over a period.
+#[derive(Debug, Clone, Serialize)]
+pub struct ToolSpend {
+    pub tool_name: String,
+    /// None for calls made outside any conversation, e.g. by the cortex,
+    /// and for a tool's calls summed over conversations.
+    pub channel_id: Option<String>,
+    /// Distinct conversations the calls were made in.
+    pub conversations: u64,
+    pub calls: u64,
+    pub failures: u64,
+    pub avg_ms: u64,
+    pub max_ms: u64,
+    /// None for tools without a price.
+    pub cost_usd: Option<f64>,
+}
+
+/// An agent's tool calls in the last `days` days by tool and conversation,
+/// most expensive first, then most called.
+pub async fn tool_spend(pool: &SqlitePool, days: u32) -> Result<Vec<ToolSpend>> {
+    let rows = sqlx::query(
+        "SELECT tool_name, channel_id, COUNT(DISTINCT channel_id) AS conversations, \
+         COUNT(*) AS calls, \
+         SUM(CASE WHEN success THEN 0 ELSE 1 END) AS failures, \
+         CAST(AVG(duration_ms) AS INTEGER) AS avg_ms, MAX(duration_ms) AS max_ms, \
+         SUM(cost_usd) AS cost_usd \
+         FROM tool_runs WHERE created_at >= datetime('now', ?) \
+         GROUP BY tool_name, channel_id \
+         ORDER BY COALESCE(SUM(cost_usd), 0) DESC, calls DESC",
+    )
+    .bind(format!("-{days} days"))
+    .fetch_all(pool)
+    .await
+    .context("failed to sum tool runs")?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| ToolSpend {
+            tool_name: row.try_get("tool_name").unwrap_or_default(),
+            channel_id: row.try_get("channel_id").unwrap_or_default(),
+            conversations: row.try_get::<i64, _>("conversations").unwrap_or_default() as u64,
+            calls: row.try_get::<i64, _>("calls").unwrap_or_default() as u64,
+            failures: row.try_get::<i64, _>("failures").unwrap_or_default() as u64,
+            avg_ms: row.try_get::<i64, _>("avg_ms").unwrap_or_default() as u64,
+            max_ms: row.try_get::<i64, _>("max_ms").unwrap_or_default() as u64,
+            cost_usd: row.try_get("cost_usd").unwrap_or_default(),
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ProcessType;
+
+    fn executed(tool_name: &str, duration_ms: u64, success: bool) -> Event {
+        Event::ToolExecuted {
+            agent_id: "main".into(),
+            process_id: "worker-1".into(),
+            process_type: ProcessType::Worker,
+            channel_id: Some("discord:1:2".into()),
+            tool_name: tool_name.into(),
+            result_bytes: 10,
+            duration_ms,
+            success,
+        }
+    }
+
+    #[test]
+    fn test_metrics_total_runs() {
+        let pricing = HashMap::from([("web_search".to_string(), 0.005)]);
+        let metrics = ToolMetrics::new();
+        for event in [
+            executed("web_search", 300, true),
+            executed("web_search", 900, false),
+            executed("shell", 40, true),
+        ] {
+            metrics.observe(&ToolRun::from_event(&event, &pricing).unwrap());
+        }
+
+        let snapshot = metrics.snapshot("main");
+        assert_eq!(snapshot.len(), 2);
+        assert_eq!(snapshot[0].tool_name, "shell");
+        assert_eq!(snapshot[0].cost_usd, None);
+
+        let search = &snapshot[1];
+        assert_eq!((search.calls, search.failures), (2, 1));
+        assert_eq!((search.total_ms, search.max_ms), (1200, 900));
+        assert_eq!(search.cost_usd, Some(0.01));
+
+        assert!(metrics.snapshot("other").is_empty());
+    }
+}
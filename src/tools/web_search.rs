@@ -1,5 +1,7 @@
 //! Web search tool using the Brave Search API (task workers only).
 
+use crate::tools::network;
+
 use rig::completion::ToolDefinition;
 use rig::tool::Tool;
 use schemars::JsonSchema;
@@ -26,6 +28,19 @@ impl WebSearchTool {
             api_key: api_key.into(),
         }
     }
+
+    /// Send searches through `proxy`.
+    pub fn with_proxy(mut self, proxy: Option<String>) -> Self {
+        let Some(proxy) = proxy else {
+            return self;
+        };
+        self.client = reqwest::Client::builder()
+            .gzip(true)
+            .proxy(network::reqwest_proxy(&proxy))
+            .build()
+            .expect("hardcoded reqwest client config");
+        self
+    }
 }
 
 /// Error type for web search tool.
@@ -0,0 +1,326 @@
+//! Usage anomaly detection over the turn ledger.
+//!
+//! One watcher runs per agent. Every `check_interval_secs` it sums the
+//! agent's `turn_runs` for the last hour and for the `baseline_days` before
+//! it, and flags:
+//! - spend: the last hour cost `spend_factor` times the baseline hourly spend,
+//! - tokens per turn: turns got `token_factor` times longer than the baseline,
+//! - fallbacks: fallback models answered `fallback_factor` times the baseline
+//!   share of completions.
+//!
+//! Each anomaly is delivered to the configured targets, the same way cron
+//! results are, so a runaway prompt or a failing provider shows up before
+//! the monthly bill does.
+
+use crate::OutboundResponse;
+use crate::config::UsageAnomalyConfig;
+use crate::cron::CronContext;
+use crate::cron::scheduler::DeliveryTarget;
+
+use anyhow::Context as _;
+use sqlx::{Row as _, SqlitePool};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// Checks never run more often than this, whatever the interval.
+const MIN_INTERVAL_SECS: u64 = 60;
+
+/// What kind of usage went off its baseline.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AnomalyKind {
+    Spend,
+    TokensPerTurn,
+    Fallbacks,
+}
+
+/// Usage that went off its baseline.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Anomaly {
+    pub kind: AnomalyKind,
+    /// The last hour's value: USD, tokens per turn, or fallback share.
+    pub current: f64,
+    /// The same measure over the baseline.
+    pub baseline: f64,
+}
+
+impl Anomaly {
+    /// The alert text for `agent_id`.
+    pub fn describe(&self, agent_id: &str, baseline_days: u32) -> String {
+        let ratio = if self.baseline > 0.0 {
+            format!("{:.1}x", self.current / self.baseline)
+        } else {
+            "up from none".to_string()
+        };
+        let what = match self.kind {
+            AnomalyKind::Spend => format!(
+                "spent ${:.2} in the last hour, {ratio} its hourly baseline of ${:.2}",
+                self.current, self.baseline
+            ),
+            AnomalyKind::TokensPerTurn => format!(
+                "used {:.0} tokens per turn in the last hour, {ratio} its baseline of {:.0}",
+                self.current, self.baseline
+            ),
+            AnomalyKind::Fallbacks => format!(
+                "had {:.0}% of completions answered by fallback models in the last hour, {ratio} its baseline of {:.0}%",
+                self.current * 100.0,
+                self.baseline * 100.0
+            ),
+        };
+        format!("Usage anomaly: agent `{agent_id}` {what} (last {baseline_days} days).")
+    }
+}
+
+/// Turn totals over a time window.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct UsageWindow {
+    pub turns: u64,
+    pub cost_usd: f64,
+    pub tokens: f64,
+    pub completions: u64,
+    pub fallbacks: u64,
+    /// Hours the window covers.
+    pub hours: f64,
+}
+
+impl UsageWindow {
+    fn tokens_per_turn(&self) -> f64 {
+        if self.turns == 0 {
+            0.0
+        } else {
+            self.tokens / self.turns as f64
+        }
+    }
+
+    fn hourly_spend(&self) -> f64 {
+        self.cost_usd / self.hours.max(1.0)
+    }
+
+    fn fallback_share(&self) -> f64 {
+        if self.completions == 0 {
+            0.0
+        } else {
+            self.fallbacks as f64 / self.completions as f64
+        }
+    }
+}
+
+/// Compare the last hour against the baseline. Nothing is flagged until
+/// both have `min_turns` turns.
+pub fn detect(
+    config: &UsageAnomalyConfig,
+    recent: &UsageWindow,
+    baseline: &UsageWindow,
+) -> Vec<Anomaly> {
+    let mut anomalies = Vec::new();
+    if recent.turns < config.min_turns || baseline.turns < config.min_turns {
+        return anomalies;
+    }
+
+    let spend = recent.hourly_spend();
+    let baseline_spend = baseline.hourly_spend();
+    if spend >= config.min_spend_usd && spend >= baseline_spend * config.spend_factor {
+        anomalies.push(Anomaly {
+            kind: AnomalyKind::Spend,
+            current: spend,
+            baseline: baseline_spend,
+        });
+    }
+
+    let tokens = recent.tokens_per_turn();
+    let baseline_tokens = baseline.tokens_per_turn();
+    if baseline_tokens > 0.0 && tokens >= baseline_tokens * config.token_factor {
+        anomalies.push(Anomaly {
+            kind: AnomalyKind::TokensPerTurn,
+            current: tokens,
+            baseline: baseline_tokens,
+        });
+    }
+
+    let share = recent.fallback_share();
+    let baseline_share = baseline.fallback_share();
+    if share >= config.min_fallback_share && share >= baseline_share * config.fallback_factor {
+        anomalies.push(Anomaly {
+            kind: AnomalyKind::Fallbacks,
+            current: share,
+            baseline: baseline_share,
+        });
+    }
+
+    anomalies
+}
+
+/// Sum the turns completed between `from_hours` and `to_hours` ago.
+async fn usage_window(
+    pool: &SqlitePool,
+    from_hours: u64,
+    to_hours: u64,
+) -> anyhow::Result<UsageWindow> {
+    let from = format!("-{from_hours} hours");
+    let to = format!("-{to_hours} hours");
+    let row = sqlx::query(
+        "SELECT COUNT(*) AS turns, \
+         CAST(COALESCE(SUM(json_extract(outcome, '$.cost_usd')), 0) AS REAL) AS cost_usd, \
+         CAST(COALESCE(SUM(json_extract(outcome, '$.usage.input_tokens') \
+             + json_extract(outcome, '$.usage.output_tokens')), 0) AS REAL) AS tokens, \
+         COALESCE(SUM(json_extract(outcome, '$.usage.completions')), 0) AS completions, \
+         CAST(COALESCE((julianday('now', ?) - julianday(MIN(completed_at))) * 24, 0) AS REAL) AS hours \
+         FROM turn_runs WHERE completed_at >= datetime('now', ?) AND completed_at < datetime('now', ?)",
+    )
+    .bind(&to)
+    .bind(&from)
+    .bind(&to)
+    .fetch_one(pool)
+    .await
+    .context("failed to sum turns")?;
+
+    let fallbacks: i64 = sqlx::query_scalar(
+        "SELECT COUNT(*) FROM turn_runs t, json_each(t.outcome, '$.routing') r \
+         WHERE json_extract(r.value, '$.fallback') = 1 \
+         AND t.completed_at >= datetime('now', ?) AND t.completed_at < datetime('now', ?)",
+    )
+    .bind(&from)
+    .bind(&to)
+    .fetch_one(pool)
+    .await
+    .context("failed to count fallback completions")?;
+
+    Ok(UsageWindow {
+        turns: row.try_get::<i64, _>("turns").unwrap_or(0) as u64,
+        cost_usd: row.try_get("cost_usd").unwrap_or(0.0),
+        tokens: row.try_get("tokens").unwrap_or(0.0),
+        completions: row.try_get::<i64, _>("completions").unwrap_or(0) as u64,
+        fallbacks: fallbacks as u64,
+        hours: row.try_get("hours").unwrap_or(0.0),
+    })
+}
+
+/// Spawn the usage anomaly watcher for an agent.
+///
+/// Reads `deps.runtime_config.usage_anomalies` on every check, so changes
+/// on config reload are picked up without a restart.
+pub fn spawn_anomaly_watcher(context: CronContext) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut last_alerted: HashMap<AnomalyKind, Instant> = HashMap::new();
+
+        loop {
+            let config = context.deps.runtime_config.usage_anomalies.load_full();
+            if config.enabled {
+                if let Err(error) = check(&context, &config, &mut last_alerted).await {
+                    tracing::warn!(%error, "usage anomaly check failed");
+                }
+            }
+
+            let interval = config.check_interval_secs.max(MIN_INTERVAL_SECS);
+            tokio::time::sleep(Duration::from_secs(interval)).await;
+        }
+    })
+}
+
+async fn check(
+    context: &CronContext,
+    config: &UsageAnomalyConfig,
+    last_alerted: &mut HashMap<AnomalyKind, Instant>,
+) -> anyhow::Result<()> {
+    let pool = &context.deps.sqlite_pool;
+    let recent = UsageWindow {
+        hours: 1.0,
+        ..usage_window(pool, 1, 0).await?
+    };
+    let baseline = usage_window(pool, u64::from(config.baseline_days) * 24 + 1, 1).await?;
+
+    let cooldown = Duration::from_secs(config.cooldown_secs);
+    for anomaly in detect(config, &recent, &baseline) {
+        if last_alerted
+            .get(&anomaly.kind)
+            .is_some_and(|at| at.elapsed() < cooldown)
+        {
+            continue;
+        }
+        last_alerted.insert(anomaly.kind, Instant::now());
+
+        let text = anomaly.describe(&context.deps.agent_id, config.baseline_days);
+        tracing::warn!(agent_id = %context.deps.agent_id, kind = ?anomaly.kind, current = anomaly.current, baseline = anomaly.baseline, "usage anomaly");
+        for raw_target in &config.delivery_targets {
+            let Some(target) = DeliveryTarget::parse(raw_target) else {
+                tracing::warn!(%raw_target, "invalid usage anomaly delivery target, expected 'adapter:target'");
+                continue;
+            };
+            if let Err(error) = context
+                .messaging_manager
+                .broadcast(
+                    &target.adapter,
+                    &target.target,
+                    OutboundResponse::Text(text.clone()),
+                )
+                .await
+            {
+                tracing::warn!(%error, %target, "failed to deliver usage anomaly alert");
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn window(
+        turns: u64,
+        cost_usd: f64,
+        tokens: f64,
+        completions: u64,
+        fallbacks: u64,
+        hours: f64,
+    ) -> UsageWindow {
+        UsageWindow {
+            turns,
+            cost_usd,
+            tokens,
+            completions,
+            fallbacks,
+            hours,
+        }
+    }
+
+    #[test]
+    fn test_detect_flags_each_kind() {
+        let config = UsageAnomalyConfig::default();
+        // A week at $0.50 an hour, 2k tokens per turn, 5% fallbacks.
+        let baseline = window(1680, 84.0, 3_360_000.0, 3360, 168, 168.0);
+
+        let normal = window(12, 0.6, 24_000.0, 24, 1, 1.0);
+        assert!(detect(&config, &normal, &baseline).is_empty());
+
+        let runaway = window(12, 3.0, 120_000.0, 24, 12, 1.0);
+        let kinds: Vec<AnomalyKind> = detect(&config, &runaway, &baseline)
+            .into_iter()
+            .map(|anomaly| anomaly.kind)
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![
+                AnomalyKind::Spend,
+                AnomalyKind::TokensPerTurn,
+                AnomalyKind::Fallbacks
+            ]
+        );
+
+        // Too few turns in the last hour to say anything.
+        let quiet = window(3, 3.0, 120_000.0, 6, 6, 1.0);
+        assert!(detect(&config, &quiet, &baseline).is_empty());
+    }
+
+    #[test]
+    fn test_describe_spend() {
+        let anomaly = Anomaly {
+            kind: AnomalyKind::Spend,
+            current: 3.0,
+            baseline: 0.5,
+        };
+        assert_eq!(
+            anomaly.describe("main", 7),
+            "Usage anomaly: agent `main` spent $3.00 in the last hour, 6.0x its hourly baseline of $0.50 (last 7 days)."
+        );
+    }
+}
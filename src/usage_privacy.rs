@@ -0,0 +1,147 @@
+//! Minimum group sizes for aggregate usage reports.
+//!
+//! The API's breakdowns of an agent's turns (by language, flag, prompt
+//! version and request tag) and tool spend are meant for operators, but
+//! dashboards built on them get shared further. A row drawn from one or two
+//! conversations gives away what those users asked about and what they
+//! cost, so with `min_group_size` set in `[defaults.usage_reports]`, rows
+//! drawn from fewer conversations than that are left out and only counted.
+//! Conversations stand in for users: a DM is one user, and a group chat is
+//! at least one.
+
+use crate::flags::FlagStats;
+use crate::language::LanguageCount;
+use crate::prompts::version::PromptVersionStats;
+use crate::tools::usage::ToolSpend;
+use crate::usage_tags::TagStats;
+
+use std::collections::BTreeMap;
+
+/// A report row summed over some conversations.
+pub trait Grouped {
+    /// How many distinct conversations the row was drawn from. 0 for rows
+    /// that don't come from any conversation, like the cortex's tool calls.
+    fn conversations(&self) -> u64;
+}
+
+impl Grouped for LanguageCount {
+    fn conversations(&self) -> u64 {
+        self.conversations
+    }
+}
+
+impl Grouped for FlagStats {
+    fn conversations(&self) -> u64 {
+        self.conversations
+    }
+}
+
+impl Grouped for PromptVersionStats {
+    fn conversations(&self) -> u64 {
+        self.conversations
+    }
+}
+
+impl Grouped for ToolSpend {
+    fn conversations(&self) -> u64 {
+        self.conversations
+    }
+}
+
+impl Grouped for TagStats {
+    fn conversations(&self) -> u64 {
+        self.conversations
+    }
+}
+
+/// Drop the rows drawn from fewer than `min_group_size` conversations.
+/// Returns how many were dropped. Rows from no conversation are kept, and
+/// a `min_group_size` of 0 or 1 keeps everything.
+pub fn suppress_small_groups<T: Grouped>(rows: &mut Vec<T>, min_group_size: u64) -> usize {
+    let before = rows.len();
+    rows.retain(|row| {
+        let conversations = row.conversations();
+        conversations == 0 || conversations >= min_group_size
+    });
+    before - rows.len()
+}
+
+/// Sum tool spend over conversations, one row per tool, so that
+/// [`suppress_small_groups`] can keep tools used widely enough. Most
+/// expensive first, then most called.
+pub fn spend_per_tool(spend: Vec<ToolSpend>) -> Vec<ToolSpend> {
+    let mut tools: BTreeMap<String, ToolSpend> = BTreeMap::new();
+    for row in spend {
+        let Some(total) = tools.get_mut(&row.tool_name) else {
+            tools.insert(
+                row.tool_name.clone(),
+                ToolSpend {
+                    channel_id: None,
+                    ..row
+                },
+            );
+            continue;
+        };
+        let calls = total.calls + row.calls;
+        if calls > 0 {
+            total.avg_ms = (total.avg_ms * total.calls + row.avg_ms * row.calls) / calls;
+        }
+        total.calls = calls;
+        total.failures += row.failures;
+        total.max_ms = total.max_ms.max(row.max_ms);
+        total.conversations += row.conversations;
+        total.cost_usd = match (total.cost_usd, row.cost_usd) {
+            (Some(a), Some(b)) => Some(a + b),
+            (a, b) => a.or(b),
+        };
+    }
+
+    let mut spend: Vec<ToolSpend> = tools.into_values().collect();
+    spend.sort_by(|a, b| {
+        b.cost_usd
+            .unwrap_or_default()
+            .total_cmp(&a.cost_usd.unwrap_or_default())
+            .then(b.calls.cmp(&a.calls))
+    });
+    spend
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spend(tool_name: &str, channel_id: Option<&str>, calls: u64, cost_usd: f64) -> ToolSpend {
+        ToolSpend {
+            tool_name: tool_name.into(),
+            channel_id: channel_id.map(Into::into),
+            conversations: u64::from(channel_id.is_some()),
+            calls,
+            failures: 0,
+            avg_ms: 100 * calls,
+            max_ms: 100 * calls,
+            cost_usd: Some(cost_usd),
+        }
+    }
+
+    #[test]
+    fn test_small_groups_are_dropped() {
+        let mut rows = spend_per_tool(vec![
+            spend("web_search", Some("discord:1:2"), 3, 0.03),
+            spend("web_search", Some("discord:1:3"), 1, 0.01),
+            spend("web_search", Some("slack:T1:C1"), 2, 0.02),
+            spend("browser", Some("discord:1:2"), 5, 0.5),
+            spend("memory_save", None, 4, 0.0),
+        ]);
+        assert_eq!(rows[0].tool_name, "browser");
+        let search = &rows[1];
+        assert_eq!(search.channel_id, None);
+        assert_eq!((search.conversations, search.calls), (3, 6));
+        assert_eq!(search.avg_ms, (300 * 3 + 100 + 200 * 2) / 6);
+        assert_eq!(search.max_ms, 300);
+
+        assert_eq!(suppress_small_groups(&mut rows, 1), 0);
+        assert_eq!(suppress_small_groups(&mut rows, 3), 1);
+        let tools: Vec<_> = rows.iter().map(|row| row.tool_name.as_str()).collect();
+        assert_eq!(tools, ["web_search", "memory_save"]);
+    }
+}
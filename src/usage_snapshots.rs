@@ -0,0 +1,264 @@
+//! Snapshots of the LLM request counters in the SQLite ledger.
+//!
+//! [`LlmMetrics`](crate::llm::metrics::LlmMetrics) counts requests, tokens,
+//! cost and failures by error class per model, but only in memory and only
+//! since startup. Installs that don't scrape `GET /api/llm/metrics` into
+//! Prometheus still want history, so every `[telemetry]
+//! snapshot_interval_secs` what each model's counters grew by since the last
+//! snapshot is written to the `usage_snapshots` table. Every agent shares
+//! one LLM manager, so the counters are instance-wide and go to the default
+//! agent's database. `spacebot usage` and `GET /api/usage/series` sum the
+//! snapshots into time buckets.
+
+use crate::error::Result;
+use crate::llm::LlmManager;
+use crate::llm::metrics::UsageCount;
+
+use anyhow::Context as _;
+use chrono::{DateTime, TimeDelta, Utc};
+use serde::Serialize;
+use sqlx::{Row as _, SqlitePool};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Snapshots are never taken more often than this, whatever the interval.
+const MIN_INTERVAL_SECS: u64 = 10;
+
+/// Requests, tokens, cost and failures summed over one time bucket.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct UsagePoint {
+    /// Start of the bucket.
+    pub start: DateTime<Utc>,
+    pub requests: u64,
+    /// Failed requests, all classes.
+    pub failed: u64,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    pub cost_usd: f64,
+    /// Failed requests by error class.
+    pub errors: BTreeMap<String, u64>,
+}
+
+/// What each model's counters grew by from `previous` to `current`. Models
+/// with no new requests are left out.
+pub fn delta(previous: &HashMap<String, UsageCount>, current: &[UsageCount]) -> Vec<UsageCount> {
+    current
+        .iter()
+        .filter_map(|count| {
+            let Some(before) = previous.get(&count.model) else {
+                return (count.requests > 0).then(|| count.clone());
+            };
+            let requests = count.requests.saturating_sub(before.requests);
+            if requests == 0 {
+                return None;
+            }
+            let errors = count
+                .errors
+                .iter()
+                .filter_map(|(class, failed)| {
+                    let before = before.errors.get(class).copied().unwrap_or_default();
+                    let failed = failed.saturating_sub(before);
+                    (failed > 0).then(|| (class.clone(), failed))
+                })
+                .collect();
+            Some(UsageCount {
+                model: count.model.clone(),
+                requests,
+                input_tokens: count.input_tokens.saturating_sub(before.input_tokens),
+                output_tokens: count.output_tokens.saturating_sub(before.output_tokens),
+                cost_usd: (count.cost_usd - before.cost_usd).max(0.0),
+                errors,
+            })
+        })
+        .collect()
+}
+
+/// Write the counters' growth to `pool` every `interval`, for as long as
+/// the instance runs. Growth since the last snapshot is lost on shutdown.
+pub fn spawn_snapshot_writer(
+    llm_manager: Arc<LlmManager>,
+    pool: SqlitePool,
+    interval: Duration,
+) -> tokio::task::JoinHandle<()> {
+    let interval = interval.max(Duration::from_secs(MIN_INTERVAL_SECS));
+    tokio::spawn(async move {
+        let mut previous = HashMap::new();
+        loop {
+            tokio::time::sleep(interval).await;
+            let current = llm_manager.metrics().usage_counts();
+            let changes = delta(&previous, &current);
+            if let Err(error) = write_snapshot(&pool, &changes).await {
+                // Keep the last written counters, so the next snapshot
+                // includes this one's growth.
+                tracing::warn!(%error, "failed to write usage snapshot");
+                continue;
+            }
+            previous = current
+                .into_iter()
+                .map(|count| (count.model.clone(), count))
+                .collect();
+        }
+    })
+}
+
+/// Write one snapshot: a row per model that had requests.
+pub async fn write_snapshot(pool: &SqlitePool, changes: &[UsageCount]) -> Result<()> {
+    if changes.is_empty() {
+        return Ok(());
+    }
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("failed to start usage snapshot")?;
+    for change in changes {
+        let failed: u64 = change.errors.values().sum();
+        sqlx::query(
+            "INSERT INTO usage_snapshots \
+             (id, model, requests, failed, input_tokens, output_tokens, cost_usd, errors) \
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+        )
+        .bind(uuid::Uuid::new_v4().to_string())
+        .bind(&change.model)
+        .bind(change.requests as i64)
+        .bind(failed as i64)
+        .bind(change.input_tokens as i64)
+        .bind(change.output_tokens as i64)
+        .bind(change.cost_usd)
+        .bind(serde_json::to_string(&change.errors).unwrap_or_else(|_| "{}".into()))
+        .execute(&mut *transaction)
+        .await
+        .context("failed to save usage snapshot")?;
+    }
+    transaction
+        .commit()
+        .await
+        .context("failed to save usage snapshot")?;
+    Ok(())
+}
+
+/// Snapshots from the last `window`, summed into buckets of `bucket`
+/// aligned to the Unix epoch, oldest first. Buckets without snapshots are
+/// left out. `model` limits the sums to one model.
+pub async fn series(
+    pool: &SqlitePool,
+    window: TimeDelta,
+    bucket: TimeDelta,
+    model: Option<&str>,
+) -> Result<Vec<UsagePoint>> {
+    let bucket_secs = bucket.num_seconds().max(1);
+    let rows = sqlx::query(
+        "SELECT CAST(strftime('%s', taken_at) AS INTEGER) / ?1 * ?1 AS bucket, \
+         requests, failed, input_tokens, output_tokens, cost_usd, errors \
+         FROM usage_snapshots \
+         WHERE taken_at >= datetime('now', ?2) AND (?3 IS NULL OR model = ?3) \
+         ORDER BY taken_at",
+    )
+    .bind(bucket_secs)
+    .bind(format!("-{} seconds", window.num_seconds()))
+    .bind(model)
+    .fetch_all(pool)
+    .await
+    .context("failed to load usage snapshots")?;
+
+    let mut points: BTreeMap<i64, UsagePoint> = BTreeMap::new();
+    for row in rows {
+        let bucket: i64 = row.try_get("bucket").unwrap_or_default();
+        let point = points.entry(bucket).or_insert_with(|| UsagePoint {
+            start: DateTime::from_timestamp(bucket, 0).unwrap_or_default(),
+            ..Default::default()
+        });
+        point.requests += row.try_get::<i64, _>("requests").unwrap_or_default() as u64;
+        point.failed += row.try_get::<i64, _>("failed").unwrap_or_default() as u64;
+        point.input_tokens += row.try_get::<i64, _>("input_tokens").unwrap_or_default() as u64;
+        point.output_tokens += row.try_get::<i64, _>("output_tokens").unwrap_or_default() as u64;
+        point.cost_usd += row.try_get::<f64, _>("cost_usd").unwrap_or_default();
+        let errors: BTreeMap<String, u64> = row
+            .try_get::<String, _>("errors")
+            .ok()
+            .and_then(|errors| serde_json::from_str(&errors).ok())
+            .unwrap_or_default();
+        for (class, failed) in errors {
+            *point.errors.entry(class).or_default() += failed;
+        }
+    }
+    Ok(points.into_values().collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn count(model: &str, requests: u64, cost_usd: f64, errors: &[(&str, u64)]) -> UsageCount {
+        UsageCount {
+            model: model.into(),
+            requests,
+            input_tokens: requests * 100,
+            output_tokens: requests * 10,
+            cost_usd,
+            errors: errors
+                .iter()
+                .map(|(class, failed)| (class.to_string(), *failed))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_delta_keeps_only_growth() {
+        let previous = HashMap::from([
+            (
+                "a/x".to_string(),
+                count("a/x", 10, 1.0, &[("rate_limit", 2)]),
+            ),
+            ("a/y".to_string(), count("a/y", 3, 0.0, &[])),
+        ]);
+        let current = [
+            count("a/x", 15, 1.5, &[("rate_limit", 2), ("server", 1)]),
+            count("a/y", 3, 0.0, &[]),
+            count("b/z", 1, 0.25, &[]),
+        ];
+
+        let changes = delta(&previous, &current);
+
+        assert_eq!(changes.len(), 2);
+        assert_eq!(changes[0].model, "a/x");
+        assert_eq!(changes[0].requests, 5);
+        assert_eq!(changes[0].input_tokens, 500);
+        assert_eq!(changes[0].cost_usd, 0.5);
+        assert_eq!(
+            changes[0].errors,
+            BTreeMap::from([("server".to_string(), 1)])
+        );
+        assert_eq!(changes[1], count("b/z", 1, 0.25, &[]));
+    }
+
+    #[tokio::test]
+    async fn test_series_sums_buckets() {
+        let pool = crate::db::connect_in_memory().await;
+
+        write_snapshot(
+            &pool,
+            &[
+                count("a/x", 4, 0.5, &[("timeout", 1)]),
+                count("b/z", 2, 0.25, &[("timeout", 1), ("auth", 1)]),
+            ],
+        )
+        .await
+        .unwrap();
+
+        let points = series(&pool, TimeDelta::hours(1), TimeDelta::days(1), None)
+            .await
+            .unwrap();
+        assert_eq!(points.len(), 1);
+        assert_eq!(points[0].requests, 6);
+        assert_eq!(points[0].failed, 3);
+        assert_eq!(points[0].cost_usd, 0.75);
+        assert_eq!(points[0].errors.get("timeout"), Some(&2));
+        assert_eq!(points[0].start.timestamp() % 86_400, 0);
+
+        let points = series(&pool, TimeDelta::hours(1), TimeDelta::days(1), Some("b/z"))
+            .await
+            .unwrap();
+        assert_eq!(points[0].requests, 2);
+    }
+}
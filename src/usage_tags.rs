@@ -0,0 +1,125 @@
+//! Turn cost and completion by request tag.
+//!
+//! A message can carry [`RequestTags`] naming the business dimension it
+//! belongs to (a campaign, a channel of acquisition, an experiment). The
+//! turn answering it keeps them in its ledger outcome, so its cost and
+//! whether it finished can be summed per tag value here.
+//!
+//! [`RequestTags`]: crate::llm::tags::RequestTags
+
+use crate::agent::turn::TurnCompletion;
+use crate::error::Result;
+
+use anyhow::Context as _;
+use serde::Serialize;
+use sqlx::{Row as _, SqlitePool};
+
+/// Turns carrying one tag value.
+#[derive(Debug, Clone, Serialize)]
+pub struct TagStats {
+    pub key: String,
+    pub value: String,
+    /// Distinct conversations the turns were in.
+    pub conversations: u64,
+    #[serde(flatten)]
+    pub completion: TurnCompletion,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    /// Summed over turns whose models have a price. None if none did.
+    pub cost_usd: Option<f64>,
+}
+
+/// Each tag value on an agent's turns over the last `days` days, only for
+/// `key` if given, most expensive first.
+pub async fn tag_report(pool: &SqlitePool, key: Option<&str>, days: u32) -> Result<Vec<TagStats>> {
+    let rows = sqlx::query(
+        "SELECT tag.key AS key, tag.value AS value, \
+         COUNT(DISTINCT t.channel_id) AS conversations, COUNT(*) AS turns, \
+         SUM(CASE WHEN json_extract(t.outcome, '$.stop_reason.reason') \
+             IN ('failed', 'panicked', 'cancelled', 'max_turns') THEN 1 ELSE 0 END) AS unfinished, \
+         COALESCE(SUM(json_extract(t.outcome, '$.usage.input_tokens')), 0) AS input_tokens, \
+         COALESCE(SUM(json_extract(t.outcome, '$.usage.output_tokens')), 0) AS output_tokens, \
+         CAST(SUM(json_extract(t.outcome, '$.cost_usd')) AS REAL) AS cost_usd \
+         FROM turn_runs t, json_each(t.outcome, '$.tags') tag \
+         WHERE t.completed_at >= datetime('now', ?) AND (? IS NULL OR tag.key = ?) \
+         GROUP BY tag.key, tag.value \
+         ORDER BY COALESCE(cost_usd, 0) DESC, turns DESC",
+    )
+    .bind(format!("-{days} days"))
+    .bind(key)
+    .bind(key)
+    .fetch_all(pool)
+    .await
+    .context("failed to sum turns by tag")?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| TagStats {
+            key: row.try_get("key").unwrap_or_default(),
+            value: row.try_get("value").unwrap_or_default(),
+            conversations: row.try_get::<i64, _>("conversations").unwrap_or_default() as u64,
+            completion: TurnCompletion::from_row(&row),
+            input_tokens: row.try_get::<i64, _>("input_tokens").unwrap_or_default() as u64,
+            output_tokens: row.try_get::<i64, _>("output_tokens").unwrap_or_default() as u64,
+            cost_usd: row.try_get("cost_usd").ok().flatten(),
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_turns_are_summed_per_tag_value() {
+        let pool = crate::db::connect_in_memory().await;
+        sqlx::query(
+            "INSERT INTO channels (id, platform) VALUES \
+             ('webhook:a', 'webhook'), ('webhook:b', 'webhook')",
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        let turns = [
+            (
+                "webhook:a",
+                r#"{"campaign": "spring", "team": "growth"}"#,
+                0.02,
+                "completed",
+            ),
+            ("webhook:b", r#"{"campaign": "spring"}"#, 0.01, "failed"),
+            ("webhook:b", r#"{"campaign": "autumn"}"#, 0.005, "completed"),
+            ("webhook:a", "{}", 0.5, "completed"),
+        ];
+        for (index, (channel_id, tags, cost_usd, reason)) in turns.into_iter().enumerate() {
+            let outcome = format!(
+                r#"{{"usage": {{"input_tokens": 100, "output_tokens": 10}}, "cost_usd": {cost_usd}, "stop_reason": {{"reason": "{reason}"}}, "tags": {tags}}}"#
+            );
+            sqlx::query("INSERT INTO turn_runs (id, channel_id, outcome) VALUES (?, ?, ?)")
+                .bind(index.to_string())
+                .bind(channel_id)
+                .bind(outcome)
+                .execute(&pool)
+                .await
+                .unwrap();
+        }
+
+        let campaigns = tag_report(&pool, Some("campaign"), 30).await.unwrap();
+        let values: Vec<_> = campaigns.iter().map(|stats| stats.value.as_str()).collect();
+        assert_eq!(values, ["spring", "autumn"]);
+        let spring = &campaigns[0];
+        assert_eq!(
+            (
+                spring.conversations,
+                spring.completion.turns,
+                spring.completion.unfinished
+            ),
+            (2, 2, 1)
+        );
+        assert_eq!(spring.input_tokens, 200);
+        assert!((spring.cost_usd.unwrap() - 0.03).abs() < 1e-9);
+
+        assert_eq!(tag_report(&pool, None, 30).await.unwrap().len(), 3);
+    }
+}
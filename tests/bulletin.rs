@@ -68,6 +68,7 @@ async fn bootstrap_deps() -> anyhow::Result<spacebot::AgentDeps> {
     let (event_tx, _) = tokio::sync::broadcast::channel(16);
 
     let agent_id: spacebot::AgentId = Arc::from(agent_config.id.as_str());
+    let network = spacebot::tools::NetworkSandbox::new(runtime_config.clone());
 
     Ok(spacebot::AgentDeps {
         agent_id,
@@ -77,6 +78,15 @@ async fn bootstrap_deps() -> anyhow::Result<spacebot::AgentDeps> {
         runtime_config,
         event_tx,
         sqlite_pool: db.sqlite.clone(),
+        approvals: spacebot::approval::ActionApprovals::new(),
+        rate_limiter: spacebot::messaging::rate_limit::RateLimiter::new(),
+        guard_metrics: spacebot::hooks::tool_guard::GuardMetrics::new(),
+        decline_metrics: spacebot::declined::DeclineMetrics::new(),
+        budget: spacebot::budget::BudgetTracker::new(),
+        maintenance: spacebot::maintenance::Maintenance::new(),
+        handoffs: spacebot::agent::handoff::Handoffs::new().0,
+        network,
+        downloads: spacebot::messaging::download::AttachmentDownloader::new(reqwest::Client::new()),
     })
 }
 
@@ -68,6 +68,7 @@ async fn bootstrap_deps() -> anyhow::Result<(spacebot::AgentDeps, spacebot::conf
     let (event_tx, _) = tokio::sync::broadcast::channel(16);
 
     let agent_id: spacebot::AgentId = Arc::from(agent_config.id.as_str());
+    let network = spacebot::tools::NetworkSandbox::new(runtime_config.clone());
 
     let deps = spacebot::AgentDeps {
         agent_id,
@@ -77,6 +78,15 @@ async fn bootstrap_deps() -> anyhow::Result<(spacebot::AgentDeps, spacebot::conf
         runtime_config,
         event_tx,
         sqlite_pool: db.sqlite.clone(),
+        approvals: spacebot::approval::ActionApprovals::new(),
+        rate_limiter: spacebot::messaging::rate_limit::RateLimiter::new(),
+        guard_metrics: spacebot::hooks::tool_guard::GuardMetrics::new(),
+        decline_metrics: spacebot::declined::DeclineMetrics::new(),
+        budget: spacebot::budget::BudgetTracker::new(),
+        maintenance: spacebot::maintenance::Maintenance::new(),
+        handoffs: spacebot::agent::handoff::Handoffs::new().0,
+        network,
+        downloads: spacebot::messaging::download::AttachmentDownloader::new(reqwest::Client::new()),
     };
 
     Ok((deps, config))
@@ -140,6 +150,7 @@ fn build_channel_system_prompt(rc: &spacebot::config::RuntimeConfig) -> String {
     prompt_engine
         .render_channel_prompt(
             empty_to_none(identity_context),
+            None,
             empty_to_none(memory_bulletin.to_string()),
             empty_to_none(skills_prompt),
             worker_capabilities,
@@ -176,7 +187,10 @@ async fn dump_channel_context() {
         active_branches: Arc::new(tokio::sync::RwLock::new(std::collections::HashMap::new())),
         active_workers: Arc::new(tokio::sync::RwLock::new(std::collections::HashMap::new())),
         worker_inputs: Arc::new(tokio::sync::RwLock::new(std::collections::HashMap::new())),
+        tasks: spacebot::agent::task::BackgroundTasks::default(),
         status_block,
+        last_completion: Arc::new(tokio::sync::RwLock::new(None)),
+        tool_sources: spacebot::agent::provenance::ToolSources::default(),
         deps: deps.clone(),
         conversation_logger,
         channel_store,
@@ -376,9 +390,12 @@ async fn dump_all_contexts() {
         active_branches: Arc::new(tokio::sync::RwLock::new(std::collections::HashMap::new())),
         active_workers: Arc::new(tokio::sync::RwLock::new(std::collections::HashMap::new())),
         worker_inputs: Arc::new(tokio::sync::RwLock::new(std::collections::HashMap::new())),
+        tasks: spacebot::agent::task::BackgroundTasks::default(),
         status_block: Arc::new(tokio::sync::RwLock::new(
             spacebot::agent::status::StatusBlock::new(),
         )),
+        last_completion: Arc::new(tokio::sync::RwLock::new(None)),
+        tool_sources: spacebot::agent::provenance::ToolSources::default(),
         deps: deps.clone(),
         conversation_logger: conversation_logger.clone(),
         channel_store: channel_store.clone(),
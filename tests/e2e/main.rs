@@ -0,0 +1,137 @@
+//! End-to-end tests of whole message flows against a running `spacebot`.
+//!
+//! Each test starts the real binary in a scratch instance directory, with a
+//! mock provider and a fake chat platform running in the test process and
+//! Postgres from `tests/e2e/docker-compose.yml`. A message goes in through
+//! the webhook adapter and is checked on its way through routing, tool calls,
+//! the conversation log and the reply pushed back out, so refactors of the
+//! LLM manager and routing layers can be checked without real providers.
+//!
+//! Only built with the `e2e` feature. Run with `scripts/e2e.sh`, which starts
+//! the services and tears them down again, or with them already up with
+//! `cargo test --features e2e --test e2e -- --test-threads=1`.
+
+mod harness;
+
+use harness::{FakePlatform, Instance, MOCK_MODEL, MockProvider};
+
+use std::time::Duration;
+
+/// A reply can take a worker run and a second channel turn.
+const REPLY_TIMEOUT: Duration = Duration::from_secs(60);
+
+#[tokio::test]
+async fn test_question_answered_from_postgres() -> anyhow::Result<()> {
+    let provider = MockProvider::start().await?;
+    let platform = FakePlatform::start().await?;
+    let extra = format!(
+        r#"
+[defaults.sql]
+enabled = true
+
+[[defaults.sql.databases]]
+name = "shop"
+url = "{postgres}"
+description = "Orders placed in the shop."
+
+[[agents]]
+id = "main"
+default = true
+
+[[agents]]
+id = "shop"
+
+[[bindings]]
+agent_id = "shop"
+channel = "webhook"
+"#,
+        postgres = harness::postgres_url(),
+    );
+    let instance = Instance::start(&provider, &platform, &extra).await?;
+
+    instance
+        .send("orders", "How many orders do we have?")
+        .await?;
+
+    // Seeded with three orders; the count comes from the worker's
+    // sql_query call.
+    let reply = platform
+        .wait_for_reply("orders", "There are 3 orders.", REPLY_TIMEOUT)
+        .await
+        .map_err(|error| error.context(instance.log_tail()))?;
+    assert_eq!(reply, "There are 3 orders.");
+
+    // The binding sent the conversation to the shop agent...
+    let channels = instance.api_get("/channels").await?;
+    let channel = channels["channels"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .find(|channel| channel["id"] == "webhook:orders")
+        .cloned();
+    assert_eq!(
+        channel.as_ref().map(|channel| channel["agent_id"].clone()),
+        Some("shop".into()),
+        "channels: {channels}"
+    );
+
+    // ...and both sides of it were logged.
+    let instance = &instance;
+    let timeline = harness::wait_for(
+        REPLY_TIMEOUT,
+        "the reply to be logged",
+        move || async move {
+            let timeline = instance
+                .api_get("/channels/messages?channel_id=webhook:orders")
+                .await
+                .ok()?;
+            let logged = timeline.to_string();
+            (logged.contains("How many orders do we have?")
+                && logged.contains("There are 3 orders."))
+            .then_some(timeline)
+        },
+    )
+    .await?;
+    assert_eq!(timeline["has_more"], false);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_failed_model_falls_back() -> anyhow::Result<()> {
+    let provider = MockProvider::start().await?;
+    let platform = FakePlatform::start().await?;
+    let extra = format!(
+        r#"
+[[llm.faults]]
+model = "openai/e2e-primary"
+kind = "rate_limit"
+
+[[agents]]
+id = "main"
+default = true
+
+[agents.routing]
+channel = "openai/e2e-primary"
+
+[agents.routing.fallbacks]
+"openai/e2e-primary" = ["{MOCK_MODEL}"]
+"#
+    );
+    let instance = Instance::start(&provider, &platform, &extra).await?;
+
+    instance.send("fallback", "Hello?").await?;
+
+    platform
+        .wait_for_reply("fallback", "Hello from the mock provider.", REPLY_TIMEOUT)
+        .await
+        .map_err(|error| error.context(instance.log_tail()))?;
+    // The injected fault took the primary's place, so only the fallback
+    // reached the provider.
+    let models = provider.models();
+    assert!(models.iter().any(|model| model == "e2e-mock"), "{models:?}");
+    assert!(
+        !models.iter().any(|model| model == "e2e-primary"),
+        "{models:?}"
+    );
+    Ok(())
+}
@@ -0,0 +1,13 @@
+-- Data the e2e tests query through the sql_query tool. The tests expect
+-- exactly three orders.
+CREATE TABLE orders (
+    id SERIAL PRIMARY KEY,
+    customer TEXT NOT NULL,
+    total_cents INTEGER NOT NULL,
+    placed_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+INSERT INTO orders (customer, total_cents) VALUES
+    ('ada', 1250),
+    ('grace', 4800),
+    ('linus', 999);